@@ -0,0 +1,557 @@
+//! Asynchronous variant of the session API. An [`AsyncSession`] shares the connection of a
+//! connected [`Session`] but its functions return futures instead of blocking the calling thread
+//! while waiting for a response. This makes it suitable for use from inside tokio applications.
+//!
+//! Note that publish responses are still processed by the `Session`, so the session must be
+//! running, e.g. through `Session::run_async`, for subscription callbacks to be called.
+//!
+//! [`AsyncSession`]: ./struct.AsyncSession.html
+//! [`Session`]: ../session/struct.Session.html
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use futures::{
+    future,
+    Future,
+    Poll,
+    sync::mpsc::UnboundedSender,
+};
+use tokio_timer::Timeout;
+
+use opcua_types::{
+    *,
+    service_types::*,
+    status_code::StatusCode,
+};
+
+use crate::{
+    callbacks::{OnDataChange, SubscriptionCallbacks, DataChangeOnly},
+    message_queue::MessageQueue,
+    session_state::SessionState,
+    subscription::{self, Subscription},
+    subscription_state::SubscriptionState,
+    subscription_timer::SubscriptionTimerCommand,
+};
+
+/// The future returned by the functions of an `AsyncSession`. It knows the request handle of its
+/// request, so an application with several requests outstanding can tell which of them failed,
+/// e.g. from a request failure callback, or cancel one of them.
+pub struct SessionFuture<T> {
+    /// Request handle of the request, 0 if no request was sent
+    request_handle: u32,
+    /// Future that resolves to the processed response
+    inner: Box<dyn Future<Item=T, Error=StatusCode> + Send>,
+}
+
+impl<T> SessionFuture<T> {
+    /// Returns a future that fails with the status code without sending a request
+    fn err(status_code: StatusCode) -> SessionFuture<T> where T: Send + 'static {
+        SessionFuture {
+            request_handle: 0,
+            inner: Box::new(future::err(status_code)),
+        }
+    }
+
+    /// Returns the request handle of the request that the future is waiting on, or 0 if no request
+    /// was sent, e.g. because the arguments were invalid.
+    pub fn request_handle(&self) -> u32 {
+        self.request_handle
+    }
+}
+
+impl<T> Future for SessionFuture<T> {
+    type Item = T;
+    type Error = StatusCode;
+
+    fn poll(&mut self) -> Poll<T, StatusCode> {
+        self.inner.poll()
+    }
+}
+
+/// An asynchronous handle onto a session, obtained from `Session::async_session()`. The handle
+/// is cheap to clone and may be moved into tasks. Each function sends its request immediately
+/// and returns a future that resolves when the response arrives or the request times out.
+///
+/// Validation of arguments and the handling of responses is the same as the equivalently named
+/// function on `Session`.
+#[derive(Clone)]
+pub struct AsyncSession {
+    /// Runtime state of the session
+    session_state: Arc<RwLock<SessionState>>,
+    /// Subscriptions state
+    subscription_state: Arc<RwLock<SubscriptionState>>,
+    /// Message queue
+    message_queue: Arc<RwLock<MessageQueue>>,
+    /// Subscription timer command
+    timer_command_queue: UnboundedSender<SubscriptionTimerCommand>,
+    /// Request timeout in milliseconds of this handle, the session's request timeout if not set
+    request_timeout: Option<u32>,
+}
+
+impl AsyncSession {
+    pub(crate) fn new(session_state: Arc<RwLock<SessionState>>, subscription_state: Arc<RwLock<SubscriptionState>>,
+                      message_queue: Arc<RwLock<MessageQueue>>, timer_command_queue: UnboundedSender<SubscriptionTimerCommand>) -> AsyncSession {
+        AsyncSession {
+            session_state,
+            subscription_state,
+            message_queue,
+            timer_command_queue,
+            request_timeout: None,
+        }
+    }
+
+    /// Returns a handle whose requests time out after the given number of milliseconds instead of
+    /// the session's request timeout, e.g. for a call which is expected to be slow. The timeout is
+    /// sent to the server as the timeout hint of each request.
+    pub fn with_request_timeout(&self, request_timeout: u32) -> AsyncSession {
+        let mut async_session = self.clone();
+        async_session.request_timeout = Some(request_timeout);
+        async_session
+    }
+
+    /// Cancels outstanding requests by sending a [`CancelRequest`] to the server. See
+    /// `Session::cancel`.
+    ///
+    /// [`CancelRequest`]: ./struct.CancelRequest.html
+    ///
+    pub fn cancel(&self, request_handle: IntegerId) -> SessionFuture<u32> {
+        let request = CancelRequest {
+            request_header: self.make_request_header(),
+            request_handle,
+        };
+        self.send_request(request, |response| {
+            if let SupportedMessage::CancelResponse(response) = response {
+                crate::process_service_result(&response.response_header)?;
+                Ok(response.cancel_count)
+            } else {
+                Err(crate::process_unexpected_response(response))
+            }
+        })
+    }
+
+    /// Reads the value of nodes by sending a [`ReadRequest`] to the server. See `Session::read`.
+    ///
+    /// [`ReadRequest`]: ./struct.ReadRequest.html
+    ///
+    pub fn read(&self, nodes_to_read: &[ReadValueId]) -> SessionFuture<Option<Vec<DataValue>>> {
+        if nodes_to_read.is_empty() {
+            error!("read_nodes, was not supplied with any nodes to read");
+            return SessionFuture::err(StatusCode::BadNothingToDo);
+        }
+        let request = ReadRequest {
+            request_header: self.make_request_header(),
+            max_age: 1f64,
+            timestamps_to_return: TimestampsToReturn::Server,
+            nodes_to_read: Some(nodes_to_read.to_vec()),
+        };
+        self.send_request(request, |response| {
+            if let SupportedMessage::ReadResponse(response) = response {
+                crate::process_service_result(&response.response_header)?;
+                Ok(response.results)
+            } else {
+                Err(crate::process_unexpected_response(response))
+            }
+        })
+    }
+
+    /// Writes values to nodes by sending a [`WriteRequest`] to the server. See `Session::write`.
+    ///
+    /// [`WriteRequest`]: ./struct.WriteRequest.html
+    ///
+    pub fn write(&self, nodes_to_write: &[WriteValue]) -> SessionFuture<Option<Vec<StatusCode>>> {
+        if nodes_to_write.is_empty() {
+            error!("write_value() was not supplied with any nodes to write");
+            return SessionFuture::err(StatusCode::BadNothingToDo);
+        }
+        let request = WriteRequest {
+            request_header: self.make_request_header(),
+            nodes_to_write: Some(nodes_to_write.to_vec()),
+        };
+        self.send_request(request, |response| {
+            if let SupportedMessage::WriteResponse(response) = response {
+                crate::process_service_result(&response.response_header)?;
+                Ok(response.results)
+            } else {
+                Err(crate::process_unexpected_response(response))
+            }
+        })
+    }
+
+    /// Discovers the references of nodes by sending a [`BrowseRequest`] to the server. See `Session::browse`.
+    ///
+    /// [`BrowseRequest`]: ./struct.BrowseRequest.html
+    ///
+    pub fn browse(&self, nodes_to_browse: &[BrowseDescription]) -> SessionFuture<Option<Vec<BrowseResult>>> {
+        if nodes_to_browse.is_empty() {
+            error!("browse, was not supplied with any nodes to browse");
+            return SessionFuture::err(StatusCode::BadNothingToDo);
+        }
+        let request = BrowseRequest {
+            request_header: self.make_request_header(),
+            view: ViewDescription {
+                view_id: NodeId::null(),
+                timestamp: DateTime::now(),
+                view_version: 0,
+            },
+            requested_max_references_per_node: 1000,
+            nodes_to_browse: Some(nodes_to_browse.to_vec()),
+        };
+        self.send_request(request, |response| {
+            if let SupportedMessage::BrowseResponse(response) = response {
+                crate::process_service_result(&response.response_header)?;
+                Ok(response.results)
+            } else {
+                Err(crate::process_unexpected_response(response))
+            }
+        })
+    }
+
+    /// Continues a browse by sending a [`BrowseNextRequest`] to the server. See `Session::browse_next`.
+    ///
+    /// [`BrowseNextRequest`]: ./struct.BrowseNextRequest.html
+    ///
+    pub fn browse_next(&self, release_continuation_points: bool, continuation_points: &[ByteString]) -> SessionFuture<Option<Vec<BrowseResult>>> {
+        if continuation_points.is_empty() {
+            return SessionFuture::err(StatusCode::BadNothingToDo);
+        }
+        let request = BrowseNextRequest {
+            request_header: self.make_request_header(),
+            continuation_points: Some(continuation_points.to_vec()),
+            release_continuation_points,
+        };
+        self.send_request(request, |response| {
+            if let SupportedMessage::BrowseNextResponse(response) = response {
+                crate::process_service_result(&response.response_header)?;
+                Ok(response.results)
+            } else {
+                Err(crate::process_unexpected_response(response))
+            }
+        })
+    }
+
+    /// Calls a single method on an object on the server by sending a [`CallRequest`] to the
+    /// server. See `Session::call`.
+    ///
+    /// [`CallRequest`]: ./struct.CallRequest.html
+    ///
+    pub fn call<T>(&self, method: T) -> SessionFuture<CallMethodResult> where T: Into<CallMethodRequest> {
+        let request = CallRequest {
+            request_header: self.make_request_header(),
+            methods_to_call: Some(vec![method.into()]),
+        };
+        self.send_request(request, |response| {
+            if let SupportedMessage::CallResponse(response) = response {
+                crate::process_service_result(&response.response_header)?;
+                match response.results {
+                    Some(mut results) => {
+                        if results.len() != 1 {
+                            error!("call, expecting a result from the call to the server, got {} results", results.len());
+                            Err(StatusCode::BadUnexpectedError)
+                        } else {
+                            Ok(results.remove(0))
+                        }
+                    }
+                    None => {
+                        error!("call, expecting a result from the call to the server, got nothing");
+                        Err(StatusCode::BadUnexpectedError)
+                    }
+                }
+            } else {
+                Err(crate::process_unexpected_response(response))
+            }
+        })
+    }
+
+    /// Creates a subscription by sending a [`CreateSubscriptionRequest`] to the server. The future
+    /// resolves to the identifier of the new subscription. See `Session::create_subscription`.
+    ///
+    /// [`CreateSubscriptionRequest`]: ./struct.CreateSubscriptionRequest.html
+    ///
+    pub fn create_subscription<CB>(&self, publishing_interval: f64, lifetime_count: u32, max_keep_alive_count: u32, max_notifications_per_publish: u32, priority: u8, publishing_enabled: bool, callback: CB)
+                                   -> SessionFuture<u32>
+        where CB: OnDataChange + Send + Sync + 'static {
+        let callback: Arc<Mutex<dyn SubscriptionCallbacks + Send + Sync + 'static>> = Arc::new(Mutex::new(DataChangeOnly(callback)));
+        let request = CreateSubscriptionRequest {
+            request_header: self.make_request_header(),
+            requested_publishing_interval: publishing_interval,
+            requested_lifetime_count: lifetime_count,
+            requested_max_keep_alive_count: max_keep_alive_count,
+            max_notifications_per_publish,
+            publishing_enabled,
+            priority,
+        };
+        let subscription_state = self.subscription_state.clone();
+        let timer_command_queue = self.timer_command_queue.clone();
+        self.send_request(request, move |response| {
+            if let SupportedMessage::CreateSubscriptionResponse(response) = response {
+                crate::process_service_result(&response.response_header)?;
+                let subscription = Subscription::new(response.subscription_id, response.revised_publishing_interval,
+                                                     response.revised_lifetime_count,
+                                                     response.revised_max_keep_alive_count,
+                                                     max_notifications_per_publish,
+                                                     publishing_enabled,
+                                                     priority,
+                                                     callback);
+                {
+                    let mut subscription_state = trace_write_lock_unwrap!(subscription_state);
+                    subscription_state.add_subscription(subscription);
+                }
+                let _ = timer_command_queue.unbounded_send(SubscriptionTimerCommand::CreateTimer(response.subscription_id));
+                debug!("create_subscription, created a subscription with id {}", response.subscription_id);
+                Ok(response.subscription_id)
+            } else {
+                Err(crate::process_unexpected_response(response))
+            }
+        })
+    }
+
+    /// Modifies a subscription by sending a [`ModifySubscriptionRequest`] to the server. See
+    /// `Session::modify_subscription`.
+    ///
+    /// [`ModifySubscriptionRequest`]: ./struct.ModifySubscriptionRequest.html
+    ///
+    pub fn modify_subscription(&self, subscription_id: u32, publishing_interval: f64, lifetime_count: u32, max_keep_alive_count: u32, max_notifications_per_publish: u32, priority: u8) -> SessionFuture<()> {
+        if subscription_id == 0 || !self.subscription_exists(subscription_id) {
+            error!("modify_subscription, subscription id {} is invalid", subscription_id);
+            return SessionFuture::err(StatusCode::BadInvalidArgument);
+        }
+        let request = ModifySubscriptionRequest {
+            request_header: self.make_request_header(),
+            subscription_id,
+            requested_publishing_interval: publishing_interval,
+            requested_lifetime_count: lifetime_count,
+            requested_max_keep_alive_count: max_keep_alive_count,
+            max_notifications_per_publish,
+            priority,
+        };
+        let subscription_state = self.subscription_state.clone();
+        self.send_request(request, move |response| {
+            if let SupportedMessage::ModifySubscriptionResponse(response) = response {
+                crate::process_service_result(&response.response_header)?;
+                let mut subscription_state = trace_write_lock_unwrap!(subscription_state);
+                subscription_state.modify_subscription(subscription_id,
+                                                       response.revised_publishing_interval,
+                                                       response.revised_lifetime_count,
+                                                       response.revised_max_keep_alive_count,
+                                                       max_notifications_per_publish,
+                                                       priority);
+                Ok(())
+            } else {
+                Err(crate::process_unexpected_response(response))
+            }
+        })
+    }
+
+    /// Changes the publishing mode of subscriptions by sending a [`SetPublishingModeRequest`] to
+    /// the server. See `Session::set_publishing_mode`.
+    ///
+    /// [`SetPublishingModeRequest`]: ./struct.SetPublishingModeRequest.html
+    ///
+    pub fn set_publishing_mode(&self, subscription_ids: &[u32], publishing_enabled: bool) -> SessionFuture<Vec<StatusCode>> {
+        if subscription_ids.is_empty() {
+            error!("set_publishing_mode, no subscription ids were provided");
+            return SessionFuture::err(StatusCode::BadNothingToDo);
+        }
+        let request = SetPublishingModeRequest {
+            request_header: self.make_request_header(),
+            publishing_enabled,
+            subscription_ids: Some(subscription_ids.to_vec()),
+        };
+        let subscription_ids = subscription_ids.to_vec();
+        let subscription_state = self.subscription_state.clone();
+        self.send_request(request, move |response| {
+            if let SupportedMessage::SetPublishingModeResponse(response) = response {
+                crate::process_service_result(&response.response_header)?;
+                let results = response.results.unwrap_or_default();
+                {
+                    let mut subscription_state = trace_write_lock_unwrap!(subscription_state);
+                    subscription_state.set_publishing_mode(&subscription_ids, &results, publishing_enabled);
+                }
+                Ok(results)
+            } else {
+                Err(crate::process_unexpected_response(response))
+            }
+        })
+    }
+
+    /// Deletes subscriptions by sending a [`DeleteSubscriptionsRequest`] to the server. See
+    /// `Session::delete_subscriptions`.
+    ///
+    /// [`DeleteSubscriptionsRequest`]: ./struct.DeleteSubscriptionsRequest.html
+    ///
+    pub fn delete_subscriptions(&self, subscription_ids: &[u32]) -> SessionFuture<Vec<StatusCode>> {
+        if subscription_ids.is_empty() {
+            trace!("delete_subscriptions with no subscriptions");
+            return SessionFuture::err(StatusCode::BadNothingToDo);
+        }
+        let request = DeleteSubscriptionsRequest {
+            request_header: self.make_request_header(),
+            subscription_ids: Some(subscription_ids.to_vec()),
+        };
+        let subscription_ids = subscription_ids.to_vec();
+        let subscription_state = self.subscription_state.clone();
+        self.send_request(request, move |response| {
+            if let SupportedMessage::DeleteSubscriptionsResponse(response) = response {
+                crate::process_service_result(&response.response_header)?;
+                let results = response.results.unwrap_or_default();
+                {
+                    // Clear out the subscriptions that were deleted
+                    let mut subscription_state = trace_write_lock_unwrap!(subscription_state);
+                    subscription_state.delete_subscriptions(&subscription_ids, &results);
+                }
+                Ok(results)
+            } else {
+                Err(crate::process_unexpected_response(response))
+            }
+        })
+    }
+
+    /// Creates monitored items on a subscription by sending a [`CreateMonitoredItemsRequest`] to
+    /// the server. See `Session::create_monitored_items`.
+    ///
+    /// [`CreateMonitoredItemsRequest`]: ./struct.CreateMonitoredItemsRequest.html
+    ///
+    pub fn create_monitored_items(&self, subscription_id: u32, timestamps_to_return: TimestampsToReturn, items_to_create: &[MonitoredItemCreateRequest]) -> SessionFuture<Vec<MonitoredItemCreateResult>> {
+        if subscription_id == 0 || !self.subscription_exists(subscription_id) {
+            error!("create_monitored_items, subscription id {} is invalid", subscription_id);
+            return SessionFuture::err(StatusCode::BadInvalidArgument);
+        } else if items_to_create.is_empty() {
+            error!("create_monitored_items, called with no items to create");
+            return SessionFuture::err(StatusCode::BadNothingToDo);
+        }
+
+        // Assign each item a unique client handle
+        let mut items_to_create = items_to_create.to_vec();
+        {
+            let mut session_state = trace_write_lock_unwrap!(self.session_state);
+            items_to_create.iter_mut().for_each(|i| {
+                i.requested_parameters.client_handle = session_state.next_monitored_item_handle();
+            });
+        }
+
+        let request = CreateMonitoredItemsRequest {
+            request_header: self.make_request_header(),
+            subscription_id,
+            timestamps_to_return,
+            items_to_create: Some(items_to_create.clone()),
+        };
+        let subscription_state = self.subscription_state.clone();
+        self.send_request(request, move |response| {
+            if let SupportedMessage::CreateMonitoredItemsResponse(response) = response {
+                crate::process_service_result(&response.response_header)?;
+                let results = response.results.unwrap_or_default();
+                let items_to_create = items_to_create.iter()
+                    .zip(results.iter())
+                    .map(|(i, r)| {
+                        subscription::CreateMonitoredItem {
+                            id: r.monitored_item_id,
+                            client_handle: i.requested_parameters.client_handle,
+                            discard_oldest: i.requested_parameters.discard_oldest,
+                            item_to_monitor: i.item_to_monitor.clone(),
+                            monitoring_mode: i.monitoring_mode,
+                            queue_size: r.revised_queue_size,
+                            sampling_interval: r.revised_sampling_interval,
+                            filter: i.requested_parameters.filter.clone(),
+                        }
+                    })
+                    .collect::<Vec<subscription::CreateMonitoredItem>>();
+                {
+                    let mut subscription_state = trace_write_lock_unwrap!(subscription_state);
+                    subscription_state.insert_monitored_items(subscription_id, &items_to_create);
+                }
+                Ok(results)
+            } else {
+                Err(crate::process_unexpected_response(response))
+            }
+        })
+    }
+
+    /// Deletes monitored items from a subscription by sending a [`DeleteMonitoredItemsRequest`]
+    /// to the server. See `Session::delete_monitored_items`.
+    ///
+    /// [`DeleteMonitoredItemsRequest`]: ./struct.DeleteMonitoredItemsRequest.html
+    ///
+    pub fn delete_monitored_items(&self, subscription_id: u32, items_to_delete: &[u32]) -> SessionFuture<Vec<StatusCode>> {
+        if subscription_id == 0 || !self.subscription_exists(subscription_id) {
+            error!("delete_monitored_items, subscription id {} is invalid", subscription_id);
+            return SessionFuture::err(StatusCode::BadInvalidArgument);
+        } else if items_to_delete.is_empty() {
+            error!("delete_monitored_items, called with no items to delete");
+            return SessionFuture::err(StatusCode::BadNothingToDo);
+        }
+        let request = DeleteMonitoredItemsRequest {
+            request_header: self.make_request_header(),
+            subscription_id,
+            monitored_item_ids: Some(items_to_delete.to_vec()),
+        };
+        let items_to_delete = items_to_delete.to_vec();
+        let subscription_state = self.subscription_state.clone();
+        self.send_request(request, move |response| {
+            if let SupportedMessage::DeleteMonitoredItemsResponse(response) = response {
+                crate::process_service_result(&response.response_header)?;
+                {
+                    let mut subscription_state = trace_write_lock_unwrap!(subscription_state);
+                    subscription_state.delete_monitored_items(subscription_id, &items_to_delete);
+                }
+                Ok(response.results.unwrap_or_default())
+            } else {
+                Err(crate::process_unexpected_response(response))
+            }
+        })
+    }
+
+    // Test if the subscription by id exists
+    fn subscription_exists(&self, subscription_id: u32) -> bool {
+        let subscription_state = trace_read_lock_unwrap!(self.subscription_state);
+        subscription_state.subscription_exists(subscription_id)
+    }
+
+    /// Construct a request header for the session. The timeout hint is the request timeout of this
+    /// handle.
+    fn make_request_header(&self) -> RequestHeader {
+        let mut session_state = trace_write_lock_unwrap!(self.session_state);
+        let mut request_header = session_state.make_request_header();
+        if let Some(request_timeout) = self.request_timeout {
+            request_header.timeout_hint = request_timeout;
+        }
+        request_header
+    }
+
+    /// Sends the request and returns a future that resolves to the response, processed by the
+    /// supplied function. The future fails with `BadRequestTimeout` if the response does not arrive
+    /// within the request timeout of this handle.
+    fn send_request<T, R, F>(&self, request: T, process_response: F) -> SessionFuture<R>
+        where T: Into<SupportedMessage>,
+              R: Send + 'static,
+              F: FnOnce(SupportedMessage) -> Result<R, StatusCode> + Send + 'static {
+        let (result, request_timeout) = {
+            let mut session_state = trace_write_lock_unwrap!(self.session_state);
+            (session_state.send_request_with_response_channel(request), self.request_timeout.unwrap_or_else(|| session_state.request_timeout()))
+        };
+        match result {
+            Ok((request_handle, response)) => {
+                let message_queue = self.message_queue.clone();
+                let response = Timeout::new(response, Duration::from_millis(request_timeout as u64))
+                    .map_err(move |err| {
+                        if err.is_elapsed() {
+                            info!("Timeout waiting for response from server");
+                            let mut message_queue = trace_write_lock_unwrap!(message_queue);
+                            message_queue.request_has_timed_out(request_handle);
+                            StatusCode::BadRequestTimeout
+                        } else {
+                            // The sender was dropped, i.e. the connection was reset
+                            StatusCode::BadConnectionClosed
+                        }
+                    })
+                    .and_then(process_response);
+                SessionFuture {
+                    request_handle,
+                    inner: Box::new(response),
+                }
+            }
+            Err(err) => SessionFuture::err(err)
+        }
+    }
+}
@@ -178,11 +178,42 @@ impl ClientBuilder {
         self
     }
 
+    /// Sets the maximum interval that the session retry interval backs off to
+    pub fn session_retry_max_interval(mut self, session_retry_max_interval: u32) -> Self {
+        self.config.session_retry_max_interval = session_retry_max_interval;
+        self
+    }
+
     /// Sets the session timeout period
     pub fn session_timeout(mut self, session_timeout: u32) -> Self {
         self.config.session_timeout = session_timeout;
         self
     }
+
+    /// Sets the idle time in milliseconds before TCP keep-alive probes are sent, 0 to disable them
+    pub fn tcp_keep_alive(mut self, tcp_keep_alive: u32) -> Self {
+        self.config.tcp_keep_alive = tcp_keep_alive;
+        self
+    }
+
+    /// Sets the number of subscription keep-alive periods without a publish response after which
+    /// the connection is reconnected, 0 to disable the check
+    pub fn publish_keep_alive_limit(mut self, publish_keep_alive_limit: u32) -> Self {
+        self.config.publish_keep_alive_limit = publish_keep_alive_limit;
+        self
+    }
+
+    /// Sets the maximum number of publish requests kept outstanding with the server
+    pub fn max_publish_requests(mut self, max_publish_requests: u32) -> Self {
+        self.config.max_publish_requests = max_publish_requests;
+        self
+    }
+
+    /// Sets the time in milliseconds to wait for the response to a request
+    pub fn request_timeout(mut self, request_timeout: u32) -> Self {
+        self.config.request_timeout = request_timeout;
+        self
+    }
 }
 
 #[test]
@@ -201,6 +232,7 @@ fn client_builder() {
         .default_endpoint("http://default")
         .session_retry_interval(1234)
         .session_retry_limit(999)
+        .session_retry_max_interval(60000)
         .session_timeout(777)
         // TODO user tokens, endpoints
         ;
@@ -217,5 +249,6 @@ fn client_builder() {
     assert_eq!(c.default_endpoint, "http://default");
     assert_eq!(c.session_retry_interval, 1234);
     assert_eq!(c.session_retry_limit, 999);
+    assert_eq!(c.session_retry_max_interval, 60000);
     assert_eq!(c.session_timeout, 777);
 }
\ No newline at end of file
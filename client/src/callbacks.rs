@@ -3,10 +3,12 @@
 //!
 //! For example, the client must supply an [`OnDataChange`] implementation when it calls `Session::create_subscription`.
 //! It could implement this trait for itself, or it can use the convenience implementation called `DataChangeCallback`
-//! that calls a client supplied function when it triggers.
+//! that calls a client supplied function when it triggers. A client that also wants to know about events, keep-alives
+//! and status changes of the subscription implements [`SubscriptionCallbacks`] and calls
+//! `Session::create_subscription_with_callbacks` instead.
 use std::fmt;
 
-use opcua_types::status_code::StatusCode;
+use opcua_types::{DateTime, DiagnosticInfo, Variant, status_code::StatusCode, service_types::ServerState};
 
 use crate::subscription::MonitoredItem;
 
@@ -15,6 +17,41 @@ pub trait OnDataChange {
     fn data_change(&mut self, data_change_items: Vec<&MonitoredItem>);
 }
 
+/// This trait is implemented by something that wishes to receive all the notifications of a subscription.
+/// Every function has an implementation which does nothing so only the ones of interest need to be
+/// implemented.
+pub trait SubscriptionCallbacks {
+    /// Called with the monitored items whose values changed in a notification message
+    fn on_data_change(&mut self, _data_change_items: Vec<&MonitoredItem>) {}
+
+    /// Called for each event of an event monitored item which has no callback of its own, with the
+    /// fields selected by the select clauses of the item's filter
+    fn on_event(&mut self, _monitored_item: &MonitoredItem, _event_fields: &[Variant]) {}
+
+    /// Called when the server reports a change to the status of the subscription. A bad status, e.g.
+    /// `BadTimeout`, means that the server has deleted the subscription.
+    fn on_status_change(&mut self, _status: StatusCode, _diagnostic_info: &DiagnosticInfo) {}
+
+    /// Called when the server sends a keep-alive because it had no notifications to publish
+    fn on_keep_alive(&mut self, _publish_time: &DateTime) {}
+}
+
+/// Adapts an [`OnDataChange`] implementation into [`SubscriptionCallbacks`] which only receive data changes
+pub(crate) struct DataChangeOnly<T>(pub T) where T: OnDataChange;
+
+impl<T> SubscriptionCallbacks for DataChangeOnly<T> where T: OnDataChange {
+    fn on_data_change(&mut self, data_change_items: Vec<&MonitoredItem>) {
+        self.0.data_change(data_change_items);
+    }
+}
+
+/// This trait is implemented by something that wishes to receive the events of an event monitored item.
+pub trait OnEvent {
+    /// Called for each event with the fields selected by the select clauses of the item's filter,
+    /// in the same order as the select clauses
+    fn event(&mut self, monitored_item: &MonitoredItem, event_fields: &[Variant]);
+}
+
 /// This trait is implemented by something that wishes to receive connection status change notifications.
 pub trait OnConnectionStatusChange {
     /// Called when the connection status changes from connected to disconnected or vice versa
@@ -32,6 +69,25 @@ pub trait OnSessionClosed {
     fn session_closed(&mut self, status_code: StatusCode);
 }
 
+/// This trait is implemented by something that wishes to know the state of the server, as read by
+/// the session's keep-alive.
+pub trait OnServerStateChange {
+    /// Called when the state of the server is first read and whenever it changes afterwards,
+    /// e.g. to `Shutdown` when the server is about to stop.
+    fn server_state_change(&mut self, server_state: ServerState);
+}
+
+/// This trait is implemented by something that wishes to know which requests of the session have
+/// failed, e.g. to tell which of several overlapping asynchronous requests failed from the request
+/// handles of their futures.
+pub trait OnRequestFailure {
+    /// Called with the request handle of a request which the server answered with a service fault,
+    /// which timed out, or which was lost because the connection closed, and the status code that
+    /// the request failed with. The callback is called while the session is handling the failure
+    /// so it must not call functions of the session.
+    fn request_failure(&mut self, request_handle: u32, status_code: StatusCode);
+}
+
 /// This is a concrete implementation of [`OnDataChange`] that calls a function.
 pub struct DataChangeCallback {
     /// The actual call back
@@ -44,6 +100,12 @@ impl OnDataChange for DataChangeCallback {
     }
 }
 
+impl SubscriptionCallbacks for DataChangeCallback {
+    fn on_data_change(&mut self, data_change_items: Vec<&MonitoredItem>) {
+        (self.cb)(data_change_items);
+    }
+}
+
 impl DataChangeCallback {
     /// Constructs a callback from the supplied function
     pub fn new<CB>(cb: CB) -> Self where CB: Fn(Vec<&MonitoredItem>) + Send + Sync + 'static {
@@ -53,6 +115,27 @@ impl DataChangeCallback {
     }
 }
 
+/// This is a concrete implementation of [`OnEvent`] that calls a function.
+pub struct EventCallback {
+    /// The actual call back
+    cb: Box<dyn Fn(&MonitoredItem, &[Variant]) + Send + Sync + 'static>
+}
+
+impl OnEvent for EventCallback {
+    fn event(&mut self, monitored_item: &MonitoredItem, event_fields: &[Variant]) {
+        (self.cb)(monitored_item, event_fields);
+    }
+}
+
+impl EventCallback {
+    /// Constructs a callback from the supplied function
+    pub fn new<CB>(cb: CB) -> Self where CB: Fn(&MonitoredItem, &[Variant]) + Send + Sync + 'static {
+        Self {
+            cb: Box::new(cb)
+        }
+    }
+}
+
 /// This is a concrete implementation of [`OnConnectionStatusChange`] that calls the supplied function.
 pub struct ConnectionStatusCallback {
     cb: Box<dyn FnMut(bool) + Send + Sync + 'static>,
@@ -102,3 +185,43 @@ impl SessionClosedCallback {
         }
     }
 }
+
+/// This is a concrete implementation of [`OnServerStateChange`] that calls the supplied function.
+pub struct ServerStateCallback {
+    cb: Box<dyn FnMut(ServerState) + Send + Sync + 'static>,
+}
+
+impl OnServerStateChange for ServerStateCallback {
+    fn server_state_change(&mut self, server_state: ServerState) {
+        (self.cb)(server_state);
+    }
+}
+
+impl ServerStateCallback {
+    // Constructor
+    pub fn new<CB>(cb: CB) -> Self where CB: FnMut(ServerState) + Send + Sync + 'static {
+        Self {
+            cb: Box::new(cb)
+        }
+    }
+}
+
+/// This is a concrete implementation of [`OnRequestFailure`] that calls the supplied function.
+pub struct RequestFailureCallback {
+    cb: Box<dyn FnMut(u32, StatusCode) + Send + Sync + 'static>,
+}
+
+impl OnRequestFailure for RequestFailureCallback {
+    fn request_failure(&mut self, request_handle: u32, status_code: StatusCode) {
+        (self.cb)(request_handle, status_code);
+    }
+}
+
+impl RequestFailureCallback {
+    // Constructor
+    pub fn new<CB>(cb: CB) -> Self where CB: FnMut(u32, StatusCode) + Send + Sync + 'static {
+        Self {
+            cb: Box::new(cb)
+        }
+    }
+}
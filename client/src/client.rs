@@ -5,13 +5,13 @@ use std::sync::{Arc, RwLock};
 use std::path::PathBuf;
 
 use opcua_types::{
-    MessageSecurityMode,
+    MessageSecurityMode, ExtensionObject, UAString, DateTime,
     service_types::{
-        ApplicationDescription, EndpointDescription, RegisteredServer,
+        ApplicationDescription, EndpointDescription, RegisteredServer, ServerOnNetwork,
     },
     status_code::StatusCode,
     url::{
-        is_valid_opc_ua_url, is_opc_ua_binary_url, server_url_from_endpoint_url, url_matches, url_matches_except_host,
+        is_valid_opc_ua_url, is_opc_ua_binary_url, is_opc_ua_https_url, server_url_from_endpoint_url, url_matches, url_matches_except_host,
         hostname_from_url, url_with_replaced_hostname,
     },
 };
@@ -24,17 +24,20 @@ use opcua_core::{
 use crate::{
     config::{ANONYMOUS_USER_TOKEN_ID, ClientConfig, ClientEndpoint},
     session::{Session, SessionInfo},
+    session_pool::SessionPool,
     session_retry::SessionRetryPolicy,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum IdentityToken {
     /// Anonymous identity token
     Anonymous,
     /// User name and a password
     UserName(String, String),
-    /// X5090 cert - a path to the cert.der, and private.pem
+    /// X509 user certificate - a path to the cert.der and the private.pem key used to sign it
     X509(PathBuf, PathBuf),
+    /// Issued token - the raw token data obtained from an authorization service, e.g. a JWT
+    IssuedToken(Vec<u8>),
 }
 
 /// The `Client` defines a connection that can be used to to get end points or establish
@@ -119,12 +122,13 @@ impl Client {
         if config.trust_server_certs {
             certificate_store.trust_unknown_certs = true;
         }
+        certificate_store.validation = config.certificate_validation.clone();
 
         let session_timeout = config.session_timeout as f64;
 
         // The session retry policy dictates how many times to retry if connection to the server goes down
         // and on what interval
-        let session_retry_policy = match config.session_retry_limit {
+        let mut session_retry_policy = match config.session_retry_limit {
             // Try forever
             -1 => SessionRetryPolicy::infinity(session_timeout, config.session_retry_interval),
             // Never try
@@ -132,6 +136,8 @@ impl Client {
             // Try this many times
             session_retry_limit => SessionRetryPolicy::new(session_timeout, session_retry_limit as u32, config.session_retry_interval)
         };
+        // Back off between retries up to the maximum interval
+        session_retry_policy.set_max_retry_interval(config.session_retry_max_interval);
 
         Client {
             config,
@@ -223,6 +229,43 @@ impl Client {
         Ok(session)
     }
 
+    /// Connects a pool of sessions to an ad-hoc server endpoint description, each of which is
+    /// created and activated like the session of `connect_to_endpoint`. Calls made through the
+    /// pool are spread across its sessions.
+    ///
+    /// Returns with the pool or the error of the first session which cannot be established, in
+    /// which case the sessions that were established are disconnected.
+    ///
+    /// [`SessionPool`]: ../session_pool/struct.SessionPool.html
+    ///
+    pub fn connect_session_pool<T>(&mut self, endpoint: T, user_identity_token: IdentityToken, size: usize) -> Result<SessionPool, StatusCode> where T: Into<EndpointDescription> {
+        let endpoint = endpoint.into();
+        let mut sessions = Vec::with_capacity(size);
+        for _ in 0..size {
+            let session = self.new_session_from_info((endpoint.clone(), user_identity_token.clone()))
+                .map_err(|err| {
+                    error!("Cannot create a session for the session pool - {}", err);
+                    StatusCode::BadUnexpectedError
+                })
+                .and_then(|session| {
+                    {
+                        let mut session = session.write().unwrap();
+                        session.connect_and_activate()?;
+                    }
+                    Ok(session)
+                });
+            match session {
+                Ok(session) => sessions.push(session),
+                Err(status_code) => {
+                    error!("Got an error while connecting the session pool - {}", status_code);
+                    SessionPool::new(sessions).disconnect();
+                    return Err(status_code);
+                }
+            }
+        }
+        Ok(SessionPool::new(sessions))
+    }
+
     /// Gets the [`ClientEndpoint`] information for the default endpoint, as defined
     /// by the configuration. If there is no default endpoint, this function will return an error.
     ///
@@ -288,11 +331,16 @@ impl Client {
     ///
     pub fn new_session_from_info<T>(&mut self, session_info: T) -> Result<Arc<RwLock<Session>>, String> where T: Into<SessionInfo> {
         let session_info = session_info.into();
-        if !is_opc_ua_binary_url(session_info.endpoint.endpoint_url.as_ref()) {
+        let endpoint_url = session_info.endpoint.endpoint_url.as_ref();
+        if !is_opc_ua_binary_url(endpoint_url) && !is_opc_ua_https_url(endpoint_url) {
             Err(format!("Endpoint url {}, is not a valid / supported url", session_info.endpoint.endpoint_url))
         } else {
-            let session = Arc::new(RwLock::new(Session::new(self.application_description(), self.certificate_store.clone(), session_info, self.session_retry_policy.clone())));
-            Ok(session)
+            let mut session = Session::new(self.application_description(), self.certificate_store.clone(), session_info, self.session_retry_policy.clone());
+            session.set_tcp_keep_alive(self.config.tcp_keep_alive);
+            session.set_publish_keep_alive_limit(self.config.publish_keep_alive_limit);
+            session.set_max_publish_requests(self.config.max_publish_requests as usize);
+            session.set_request_timeout(self.config.request_timeout);
+            Ok(Arc::new(RwLock::new(session)))
         }
     }
 
@@ -380,6 +428,31 @@ impl Client {
     {
         let discovery_endpoint_url = discovery_endpoint_url.into();
         debug!("find_servers, {}", discovery_endpoint_url);
+        let endpoint_url = discovery_endpoint_url.clone();
+        self.call_discovery_endpoint(discovery_endpoint_url, move |session| session.find_servers(endpoint_url))
+    }
+
+    /// Connects to a discovery server and asks the server for a list of servers it has seen on
+    /// the network, returning the time that record ids were last reset and the [`ServerOnNetwork`]
+    /// records. Results can be paged by supplying the greatest record id seen so far as the
+    /// `starting_record_id` and filtered by server capabilities, e.g. "DA".
+    ///
+    /// [`ServerOnNetwork`]: ../../opcua_types/service_types/server_on_network/struct.ServerOnNetwork.html
+    ///
+    pub fn find_servers_on_network<T>(&mut self, discovery_endpoint_url: T, starting_record_id: u32,
+                                      max_records_to_return: u32, server_capability_filter: Option<Vec<UAString>>) -> Result<(DateTime, Vec<ServerOnNetwork>), StatusCode>
+        where T: Into<String>
+    {
+        let discovery_endpoint_url = discovery_endpoint_url.into();
+        debug!("find_servers_on_network, {}", discovery_endpoint_url);
+        self.call_discovery_endpoint(discovery_endpoint_url, move |session| session.find_servers_on_network(starting_record_id, max_records_to_return, server_capability_filter))
+    }
+
+    /// Connects to a discovery endpoint without security, calls the supplied function with the
+    /// session and then disconnects.
+    fn call_discovery_endpoint<F, R>(&mut self, discovery_endpoint_url: String, f: F) -> Result<R, StatusCode>
+        where F: FnOnce(&mut Session) -> Result<R, StatusCode>
+    {
         let endpoint = EndpointDescription::from(discovery_endpoint_url.as_ref());
         let session = self.new_session_from_info(endpoint);
         if let Ok(session) = session {
@@ -387,8 +460,8 @@ impl Client {
             // Connect & activate the session.
             let connected = session.connect();
             if connected.is_ok() {
-                // Find me some some servers
-                let result = session.find_servers(discovery_endpoint_url.clone())
+                // Call the discovery service
+                let result = f(&mut session)
                     .map_err(|err| {
                         error!("Cannot find servers on discovery server {} - check this error - {:?}", discovery_endpoint_url, err);
                         err
@@ -424,12 +497,39 @@ impl Client {
                               server: RegisteredServer) -> Result<(), StatusCode>
         where T: Into<String> {
         let discovery_endpoint_url = discovery_endpoint_url.into();
+        debug!("register_server({}, {:?}", discovery_endpoint_url, server);
+        self.call_discovery_server(discovery_endpoint_url, move |session| session.register_server(server))
+    }
+
+    /// Called by servers that wish to register themselves with a discovery server using the
+    /// `RegisterServer2` service, supplying additional discovery configuration such as an
+    /// `MdnsDiscoveryConfiguration` with the server's capabilities.
+    ///
+    /// The discovery server is connected to in the same way as [`register_server`]. Discovery
+    /// servers that do not implement `RegisterServer2` return `BadServiceUnsupported`, in which case
+    /// the caller may fall back to [`register_server`].
+    ///
+    /// On success, the results for each discovery configuration are returned.
+    ///
+    /// [`register_server`]: #method.register_server
+    pub fn register_server2<T>(&mut self, discovery_endpoint_url: T,
+                               server: RegisteredServer,
+                               discovery_configuration: Option<Vec<ExtensionObject>>) -> Result<Vec<StatusCode>, StatusCode>
+        where T: Into<String> {
+        let discovery_endpoint_url = discovery_endpoint_url.into();
+        debug!("register_server2({}, {:?}", discovery_endpoint_url, server);
+        self.call_discovery_server(discovery_endpoint_url, move |session| session.register_server2(server, discovery_configuration))
+    }
+
+    /// Connects to the strongest endpoint of a discovery server, calls the supplied function
+    /// with the session and then disconnects.
+    fn call_discovery_server<F, R>(&mut self, discovery_endpoint_url: String, f: F) -> Result<R, StatusCode>
+        where F: FnOnce(&mut Session) -> Result<R, StatusCode> {
         if !is_valid_opc_ua_url(&discovery_endpoint_url) {
             error!("Discovery endpoint url \"{}\" is not a valid OPC UA url", discovery_endpoint_url);
             Err(StatusCode::BadTcpEndpointUrlInvalid)
         } else {
             // Get a list of endpoints from the discovery server
-            debug!("register_server({}, {:?}", discovery_endpoint_url, server);
             let endpoints = self.get_server_endpoints_from_url(discovery_endpoint_url.clone())?;
             if endpoints.is_empty() {
                 Err(StatusCode::BadUnexpectedError)
@@ -445,7 +545,7 @@ impl Client {
                         match session.connect() {
                             Ok(_) => {
                                 // Register with the server
-                                let result = session.register_server(server);
+                                let result = f(&mut session);
                                 session.disconnect();
                                 result
                             }
@@ -0,0 +1,150 @@
+//! The experimental opc.https client transport. Each request is sent as the binary encoded body of
+//! an HTTPS POST and the response is decoded from the body of the HTTP response, so the connection
+//! can pass through proxies which only allow HTTP. TLS secures the connection instead of a secure
+//! channel.
+
+use std::result::Result;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use futures::{Future, Stream, future};
+use hyper::{self, Body, Client, Request, Uri, header};
+use hyper_rustls::HttpsConnector;
+use tokio;
+use tokio_timer::Interval;
+
+use opcua_types::{
+    url::{OPC_HTTPS_SCHEME, is_opc_ua_https_url},
+    status_code::StatusCode,
+};
+
+use opcua_core::{
+    prelude::*,
+    comms::https_codec::{CONTENT_TYPE_UA_BINARY, SECURITY_POLICY_HEADER, encode_https_body, decode_https_body},
+};
+
+use crate::{
+    session_state::ConnectionState,
+    message_queue::MessageQueue,
+};
+
+macro_rules! connection_state {( $s:expr ) => { *trace_read_lock_unwrap!($s) } }
+macro_rules! set_connection_state {( $s:expr, $v:expr ) => { *trace_write_lock_unwrap!($s) = $v } }
+
+/// Interval in millis at which the task checks whether the connection has finished
+const FINISHED_POLLING_INTERVAL: u64 = 200;
+
+/// Something for the connection task to do, either a request to send or a check of whether the
+/// connection has finished
+enum Action {
+    Send(SupportedMessage),
+    CheckFinished,
+}
+
+/// Turns an opc.https endpoint url into the https url which requests are posted to
+pub(crate) fn https_uri_from_endpoint_url(endpoint_url: &str) -> Result<Uri, StatusCode> {
+    if !is_opc_ua_https_url(endpoint_url) {
+        error!("Endpoint url {} is not an opc.https url", endpoint_url);
+        return Err(StatusCode::BadTcpEndpointUrlInvalid);
+    }
+    // Only the scheme differs
+    let https_url = format!("https{}", &endpoint_url[OPC_HTTPS_SCHEME.len()..]);
+    https_url.parse::<Uri>().map_err(|err| {
+        error!("Endpoint url {} cannot be turned into an https url, err = {:?}", endpoint_url, err);
+        StatusCode::BadTcpEndpointUrlInvalid
+    })
+}
+
+/// Makes the task which posts the requests of the session to the server's opc.https endpoint and
+/// stores the responses, until the connection is finished.
+pub(crate) fn connection_task(endpoint_url: &str, connection_state: Arc<RwLock<ConnectionState>>, secure_channel: Arc<RwLock<SecureChannel>>, message_queue: Arc<RwLock<MessageQueue>>) -> Result<impl Future<Item=(), Error=()>, StatusCode> {
+    let uri = https_uri_from_endpoint_url(endpoint_url)?;
+    debug!("Creating an opc.https connection task which posts requests to {}", uri);
+
+    let (security_policy_uri, decoding_limits) = {
+        let secure_channel = trace_read_lock_unwrap!(secure_channel);
+        (secure_channel.security_policy().to_uri(), secure_channel.decoding_limits())
+    };
+    let receiver = {
+        let mut message_queue = trace_write_lock_unwrap!(message_queue);
+        message_queue.make_request_channel()
+    };
+
+    // There is no handshake to wait for, connections are made by the client as requests need them
+    set_connection_state!(connection_state, ConnectionState::Processing);
+
+    let connection_state_for_take_while = connection_state.clone();
+    let check_finished = Interval::new(Instant::now(), Duration::from_millis(FINISHED_POLLING_INTERVAL))
+        .map(|_| Action::CheckFinished)
+        .map_err(|err| {
+            error!("Finished polling timer error {:?}", err);
+        });
+
+    Ok(future::lazy(move || {
+        let client = Client::builder().build::<_, Body>(HttpsConnector::new(1));
+        receiver.map(Action::Send)
+            .select(check_finished)
+            .take_while(move |_| {
+                let finished = if let ConnectionState::Finished(_) = connection_state!(connection_state_for_take_while) { true } else { false };
+                future::ok(!finished)
+            })
+            .for_each(move |action| {
+                if let Action::Send(request) = action {
+                    if let SupportedMessage::CloseSecureChannelRequest(_) = request {
+                        // There is no secure channel to close, so the connection just finishes
+                        info!("opc.https connection is closing");
+                        set_connection_state!(connection_state, ConnectionState::Finished(StatusCode::Good));
+                    } else {
+                        match encode_https_body(&request) {
+                            Ok(body) => {
+                                let http_request = Request::post(uri.clone())
+                                    .header(header::CONTENT_TYPE, CONTENT_TYPE_UA_BINARY)
+                                    .header(SECURITY_POLICY_HEADER, security_policy_uri)
+                                    .body(Body::from(body))
+                                    .unwrap();
+                                tokio::spawn(post_request_task(&client, http_request, decoding_limits.clone(), connection_state.clone(), message_queue.clone()));
+                            }
+                            Err(status_code) => {
+                                error!("Request {} cannot be encoded, status code = {}", request.request_handle(), status_code);
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            })
+            .map(|_| {
+                info!("opc.https connection task is finished");
+            })
+    }))
+}
+
+/// Posts the request and stores the response that the server returns. A failure to reach the
+/// server finishes the connection so the session can reconnect.
+fn post_request_task(client: &Client<HttpsConnector<hyper::client::HttpConnector>, Body>, http_request: Request<Body>, decoding_limits: DecodingLimits, connection_state: Arc<RwLock<ConnectionState>>, message_queue: Arc<RwLock<MessageQueue>>) -> impl Future<Item=(), Error=()> {
+    let connection_state_for_error = connection_state.clone();
+    client.request(http_request)
+        .and_then(|response| {
+            let status = response.status();
+            response.into_body().concat2().map(move |body| (status, body))
+        })
+        .map(move |(status, body)| {
+            if !status.is_success() {
+                error!("opc.https request was rejected with HTTP status {}", status);
+                set_connection_state!(connection_state, ConnectionState::Finished(StatusCode::BadCommunicationError));
+            } else {
+                match decode_https_body(&body, &decoding_limits) {
+                    Ok(response) => {
+                        let mut message_queue = trace_write_lock_unwrap!(message_queue);
+                        message_queue.store_response(response);
+                    }
+                    Err(status_code) => {
+                        error!("opc.https response cannot be decoded, status code = {}", status_code);
+                    }
+                }
+            }
+        })
+        .map_err(move |err| {
+            error!("opc.https request failed, err = {:?}", err);
+            set_connection_state!(connection_state_for_error, ConnectionState::Finished(StatusCode::BadCommunicationError));
+        })
+}
@@ -3,3 +3,5 @@
 mod transport;
 
 pub(crate) mod tcp_transport;
+#[cfg(feature = "https")]
+pub(crate) mod https_transport;
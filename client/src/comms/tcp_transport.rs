@@ -8,21 +8,22 @@ use std::time::{Duration, Instant};
 use std::result::Result;
 use std::sync::{Arc, RwLock, Mutex};
 use std::net::{SocketAddr, ToSocketAddrs};
+use std::io::Cursor;
 
 use futures::{Future, Stream};
 use futures::future::{self};
 use futures::sync::mpsc::UnboundedReceiver;
 use tokio;
-use tokio::net::TcpStream;
+use tokio::net::{TcpListener, TcpStream};
 use tokio_io::AsyncRead;
 use tokio_io::io::{self, ReadHalf, WriteHalf};
 use tokio_codec::FramedRead;
-use tokio_timer::Interval;
+use tokio_timer::{Interval, Timeout};
 
 use opcua_types::{
     url::OPC_TCP_SCHEME,
     status_code::StatusCode,
-    tcp_types::HelloMessage,
+    tcp_types::{HelloMessage, ReverseHelloMessage, MessageHeader, MessageType, MESSAGE_HEADER_LEN},
 };
 
 use opcua_core::{
@@ -39,6 +40,8 @@ use crate::{
     callbacks::OnSessionClosed,
     comms::transport::Transport,
 };
+#[cfg(feature = "https")]
+use crate::comms::https_transport;
 
 macro_rules! connection_state {( $s:expr ) => { *trace_read_lock_unwrap!($s) } }
 macro_rules! set_connection_state {( $s:expr, $v:expr ) => { *trace_write_lock_unwrap!($s) = $v } }
@@ -49,6 +52,8 @@ struct ReadState {
     pub message_queue: Arc<RwLock<MessageQueue>>,
     /// Last decoded sequence number
     last_received_sequence_number: u32,
+    /// A response may consist of one or more chunks which are stored here until complete.
+    pending_chunks: Vec<MessageChunk>,
 }
 
 impl Drop for ReadState {
@@ -70,27 +75,27 @@ impl ReadState {
         // trace!("Got a chunk {:?}", chunk);
         let (chunk, decoding_limits) = {
             let mut secure_channel = trace_write_lock_unwrap!(self.secure_channel);
-            (secure_channel.verify_and_remove_security(&chunk.data)?, secure_channel.decoding_limits())
+            (secure_channel.verify_and_remove_security(chunk.data)?, secure_channel.decoding_limits())
         };
         let message_header = chunk.message_header(&decoding_limits)?;
         match message_header.is_final {
             MessageIsFinalType::Intermediate => {
-                panic!("We don't support intermediate chunks yet");
+                // Wait for the rest of the message
+                self.pending_chunks.push(chunk);
+                Ok(None)
             }
             MessageIsFinalType::FinalError => {
-                info!("Discarding chunk marked in as final error");
-                return Ok(None);
+                info!("Discarding chunks as after receiving one marked as final error");
+                self.pending_chunks.clear();
+                Ok(None)
             }
-            _ => {
-                // Drop through
+            MessageIsFinalType::Final => {
+                self.pending_chunks.push(chunk);
+                let in_chunks: Vec<MessageChunk> = self.pending_chunks.drain(..).collect();
+                let message = self.turn_received_chunks_into_message(&in_chunks)?;
+                Ok(Some(message))
             }
         }
-
-        // TODO test chunk message type and either push to queue, turn to message or clear
-        let in_chunks = vec![chunk];
-        let message = self.turn_received_chunks_into_message(&in_chunks)?;
-
-        Ok(Some(message))
     }
 }
 
@@ -154,6 +159,10 @@ impl Transport for TcpTransport {}
 
 impl TcpTransport {
     const WAIT_POLLING_TIMEOUT: u64 = 100;
+    /// Time in millis to wait for a server to make a reverse connection
+    const REVERSE_CONNECT_TIMEOUT_MS: u64 = 60000;
+    /// Largest ReverseHello accepted, which is the header and two strings of at most 4096 bytes
+    const MAX_REVERSE_HELLO_SIZE: u32 = 8 + 2 * (4 + 4096);
 
     /// Create a new TCP transport layer for the session
     pub fn new(secure_channel: Arc<RwLock<SecureChannel>>, session_state: Arc<RwLock<SessionState>>, message_queue: Arc<RwLock<MessageQueue>>) -> TcpTransport {
@@ -209,13 +218,48 @@ impl TcpTransport {
         };
         assert_eq!(addr.port(), port);
 
+        let connection_task = Self::connection_task(addr, self.connection_state.clone(), endpoint_url.to_string(),
+                                                    self.session_state.clone(), self.secure_channel.clone(), self.message_queue.clone());
+        self.run_connection(connection_task)
+    }
+
+    /// Connects the stream to a server which makes a reverse connection to this client. The client
+    /// listens on the supplied address for the server to connect and send a ReverseHello, then
+    /// proceeds the same as if it had connected to the endpoint. If a server uri is supplied, the
+    /// ReverseHello must come from that server.
+    pub fn connect_reverse(&mut self, listen_address: SocketAddr, endpoint_url: &str, server_uri: &str) -> Result<(), StatusCode> {
+        if self.is_connected() {
+            panic!("Should not try to connect when already connected");
+        }
+        debug!("Waiting on {} for a reverse connection from server {}", listen_address, server_uri);
+        let connection_task = Self::reverse_connection_task(listen_address, self.connection_state.clone(), endpoint_url.to_string(), server_uri.to_string(),
+                                                            self.session_state.clone(), self.secure_channel.clone(), self.message_queue.clone());
+        self.run_connection(connection_task)
+    }
+
+    /// Connects to the specified endpoint of the experimental opc.https transport. Requests are
+    /// posted to the server rather than sent over a socket, and there is no secure channel to open.
+    #[cfg(feature = "https")]
+    pub fn connect_https(&mut self, endpoint_url: &str) -> Result<(), StatusCode> {
+        if self.is_connected() {
+            panic!("Should not try to connect when already connected");
+        }
+        let connection_task = https_transport::connection_task(endpoint_url, self.connection_state.clone(), self.secure_channel.clone(), self.message_queue.clone())?;
+        self.run_connection(connection_task)
+    }
+
+    #[cfg(not(feature = "https"))]
+    pub fn connect_https(&mut self, endpoint_url: &str) -> Result<(), StatusCode> {
+        error!("Cannot connect to {} because the client was built without the https feature", endpoint_url);
+        Err(StatusCode::BadTcpEndpointUrlInvalid)
+    }
+
+    /// Runs the connection task and waits for it to connect or fail to connect.
+    fn run_connection<F>(&mut self, connection_task: F) -> Result<(), StatusCode> where F: Future<Item=(), Error=()> + Send + 'static {
         // The connection will be serviced on its own thread. When the thread terminates, the connection
         // has also terminated.
 
         {
-            let connection_task = Self::connection_task(addr, self.connection_state.clone(), endpoint_url.to_string(),
-                                                        self.session_state.clone(), self.secure_channel.clone(), self.message_queue.clone());
-
             let connection_state = self.connection_state.clone();
             let session_state = self.session_state.clone();
 
@@ -273,6 +317,14 @@ impl TcpTransport {
         }
     }
 
+    /// Finishes the connection with the supplied status, e.g. because the server has stopped
+    /// responding, so its tasks terminate and the session can reconnect.
+    pub fn abort(&mut self, status_code: StatusCode) {
+        if self.is_connected() {
+            set_connection_state!(self.connection_state, ConnectionState::Finished(status_code));
+        }
+    }
+
     /// Tests if the transport is connected
     pub fn is_connected(&self) -> bool {
         match connection_state!(self.connection_state) {
@@ -287,35 +339,119 @@ impl TcpTransport {
         debug!("Creating a connection task to connect to {} with url {}", addr, endpoint_url);
 
         let connection_state_for_error = connection_state.clone();
-        let connection_state_for_error2 = connection_state.clone();
-
-        let hello = {
-            let session_state = trace_read_lock_unwrap!(session_state);
-            HelloMessage::new(&endpoint_url,
-                              session_state.send_buffer_size(),
-                              session_state.receive_buffer_size(),
-                              session_state.max_message_size())
-        };
 
         set_connection_state!(connection_state, ConnectionState::Connecting);
         TcpStream::connect(&addr).map_err(move |err| {
             error!("Could not connect to host {}, {:?}", addr, err);
             set_connection_state!(connection_state_for_error, ConnectionState::Finished(StatusCode::BadCommunicationError));
         }).and_then(move |socket| {
-            set_connection_state!(connection_state, ConnectionState::Connected);
-            let (reader, writer) = socket.split();
-            Ok((connection_state, reader, writer))
-        }).and_then(move |(connection_state, reader, writer)| {
-            debug! {"Sending HELLO"};
-            io::write_all(writer, hello.to_vec()).map_err(move |err| {
-                error!("Cannot send hello to server, err = {:?}", err);
-                set_connection_state!(connection_state_for_error2, ConnectionState::Finished(StatusCode::BadCommunicationError));
-            }).map(move |(writer, _)| {
-                (reader, writer)
-            }).and_then(|(reader, writer)| {
-                Self::spawn_looping_tasks(reader, writer, connection_state, session_state, secure_channel, message_queue);
-                Ok(())
+            Self::hello_task(socket, connection_state, endpoint_url, session_state, secure_channel, message_queue)
+        })
+    }
+
+    /// This is the connection task for a reverse connection. It waits for the server to connect
+    /// and send a ReverseHello, after which it proceeds as a normal connection.
+    fn reverse_connection_task(listen_address: SocketAddr, connection_state: Arc<RwLock<ConnectionState>>, endpoint_url: String, server_uri: String, session_state: Arc<RwLock<SessionState>>, secure_channel: Arc<RwLock<SecureChannel>>, message_queue: Arc<RwLock<MessageQueue>>) -> impl Future<Item=(), Error=()> {
+        debug!("Creating a reverse connection task listening on {} with url {}", listen_address, endpoint_url);
+
+        let connection_state_for_error = connection_state.clone();
+        let decoding_limits = {
+            let secure_channel = trace_read_lock_unwrap!(secure_channel);
+            secure_channel.decoding_limits()
+        };
+
+        set_connection_state!(connection_state, ConnectionState::Connecting);
+        let accept = future::result(TcpListener::bind(&listen_address))
+            .map_err(|err| {
+                error!("Cannot listen for a reverse connection, {:?}", err);
+                StatusCode::BadCommunicationError
+            })
+            .and_then(|listener| {
+                // Only the first connection is accepted, the listener is dropped after that
+                listener.incoming().into_future().map_err(|(err, _)| {
+                    error!("Cannot accept a reverse connection, {:?}", err);
+                    StatusCode::BadCommunicationError
+                })
+            })
+            .and_then(|(socket, _)| socket.ok_or(StatusCode::BadCommunicationError));
+        let accept = Timeout::new(accept, Duration::from_millis(Self::REVERSE_CONNECT_TIMEOUT_MS))
+            .map_err(|err| err.into_inner().unwrap_or_else(|| {
+                error!("Timed out waiting for a reverse connection");
+                StatusCode::BadTimeout
+            }));
+
+        accept.and_then(|socket| {
+            // The server sends the ReverseHello straight after connecting
+            io::read_exact(socket, [0u8; MESSAGE_HEADER_LEN]).map_err(|err| {
+                error!("Cannot read reverse hello from server, {:?}", err);
+                StatusCode::BadCommunicationError
+            })
+        }).and_then(move |(socket, header)| {
+            let message_size = MessageHeader::decode(&mut Cursor::new(&header[..]), &decoding_limits)
+                .ok()
+                .filter(|h| h.message_type == MessageType::ReverseHello && h.message_size as usize > MESSAGE_HEADER_LEN && h.message_size <= Self::MAX_REVERSE_HELLO_SIZE)
+                .map(|h| h.message_size as usize);
+            future::result(message_size.ok_or_else(|| {
+                error!("Expected a reverse hello from server");
+                StatusCode::BadTcpMessageTypeInvalid
+            })).and_then(move |message_size| {
+                io::read_exact(socket, vec![0u8; message_size - MESSAGE_HEADER_LEN]).map_err(|err| {
+                    error!("Cannot read reverse hello from server, {:?}", err);
+                    StatusCode::BadCommunicationError
+                })
+            }).and_then(move |(socket, body)| {
+                let mut stream = Cursor::new(header.iter().chain(body.iter()).cloned().collect::<Vec<u8>>());
+                let reverse_hello = ReverseHelloMessage::decode(&mut stream, &decoding_limits)
+                    .map_err(|_| StatusCode::BadTcpMessageTypeInvalid)?;
+                debug!("Received reverse hello {:?}", reverse_hello);
+                if !reverse_hello.is_valid() {
+                    error!("Reverse hello from server is invalid");
+                    Err(StatusCode::BadTcpEndpointUrlInvalid)
+                } else if !server_uri.is_empty() && reverse_hello.server_uri.as_ref() != server_uri {
+                    error!("Reverse hello is from server {} but expected {}", reverse_hello.server_uri, server_uri);
+                    Err(StatusCode::BadTcpEndpointUrlInvalid)
+                } else {
+                    Ok(socket)
+                }
             })
+        }).map_err(move |status_code| {
+            set_connection_state!(connection_state_for_error, ConnectionState::Finished(status_code));
+        }).and_then(move |socket| {
+            Self::hello_task(socket, connection_state, endpoint_url, session_state, secure_channel, message_queue)
+        })
+    }
+
+    /// Sends HEL on the connected socket and spawns the tasks that service the connection
+    fn hello_task(socket: TcpStream, connection_state: Arc<RwLock<ConnectionState>>, endpoint_url: String, session_state: Arc<RwLock<SessionState>>, secure_channel: Arc<RwLock<SecureChannel>>, message_queue: Arc<RwLock<MessageQueue>>) -> impl Future<Item=(), Error=()> {
+        let connection_state_for_error = connection_state.clone();
+
+        let (hello, tcp_keep_alive) = {
+            let session_state = trace_read_lock_unwrap!(session_state);
+            (HelloMessage::new(&endpoint_url,
+                               session_state.send_buffer_size(),
+                               session_state.receive_buffer_size(),
+                               session_state.max_message_size()),
+             session_state.tcp_keep_alive())
+        };
+
+        // Keep-alive probes make the socket fail if the server silently disappears
+        if tcp_keep_alive > 0 {
+            if let Err(err) = socket.set_keepalive(Some(Duration::from_millis(tcp_keep_alive as u64))) {
+                warn!("Cannot set TCP keep-alive on the connection, err = {:?}", err);
+            }
+        }
+
+        set_connection_state!(connection_state, ConnectionState::Connected);
+        let (reader, writer) = socket.split();
+        debug! {"Sending HELLO"};
+        io::write_all(writer, hello.to_vec()).map_err(move |err| {
+            error!("Cannot send hello to server, err = {:?}", err);
+            set_connection_state!(connection_state_for_error, ConnectionState::Finished(StatusCode::BadCommunicationError));
+        }).map(move |(writer, _)| {
+            (reader, writer)
+        }).and_then(|(reader, writer)| {
+            Self::spawn_looping_tasks(reader, writer, connection_state, session_state, secure_channel, message_queue);
+            Ok(())
         })
     }
 
@@ -328,11 +464,12 @@ impl TcpTransport {
         };
         io::write_all(writer, bytes_to_write).map_err(move |err| {
             error!("Write IO error {:?}", err);
-        }).map(move |(writer, _)| {
+        }).map(move |(writer, bytes_written)| {
             trace!("Write bytes task finished");
             // Reinstate writer
             let mut connection = trace_lock_unwrap!(connection);
             connection.writer = Some(writer);
+            connection.send_buffer.give_back(bytes_written);
         }).map_err(|_| {
             error!("Write bytes task error");
         })
@@ -380,6 +517,16 @@ impl TcpTransport {
         let connection_for_error = connection.clone();
         let connection_for_terminate = connection.clone();
 
+        // Stops reading once the connection has finished. The codec only sees the finished flag
+        // when data arrives, which it never may if the server has gone away.
+        let finished_flag_for_stop = finished_flag.clone();
+        let stop_task = Interval::new(Instant::now(), Duration::from_millis(200))
+            .take_while(move |_| future::ok(!*trace_read_lock_unwrap!(finished_flag_for_stop)))
+            .for_each(|_| Ok(()))
+            .map_err(|err| {
+                error!("Timer for stopping the read loop finished with an error {:?}", err);
+            });
+
         // The reader reads frames from the codec, which are messages
         let framed_reader = FramedRead::new(reader, TcpCodec::new(finished_flag, decoding_limits));
         let looping_task = framed_reader.for_each(move |message| {
@@ -446,7 +593,7 @@ impl TcpTransport {
             info!("Read loop finished");
         }).map_err(|_| {
             error!("Read loop ended with an error");
-        });
+        }).select(stop_task).map(|_| ()).map_err(|_| ());
         tokio::spawn(looping_task);
     }
 
@@ -519,9 +666,9 @@ impl TcpTransport {
     /// This is the main processing loop for the connection. It writes requests and reads responses
     /// over the socket to the server.
     fn spawn_looping_tasks(reader: ReadHalf<TcpStream>, writer: WriteHalf<TcpStream>, connection_state: Arc<RwLock<ConnectionState>>, session_state: Arc<RwLock<SessionState>>, secure_channel: Arc<RwLock<SecureChannel>>, message_queue: Arc<RwLock<MessageQueue>>) { //-> impl Future<Item=Connection, Error=StatusCode> {
-        let (receive_buffer_size, send_buffer_size) = {
+        let (receive_buffer_size, send_buffer_size, buffer_pool) = {
             let session_state = trace_read_lock_unwrap!(session_state);
-            (session_state.receive_buffer_size(), session_state.send_buffer_size())
+            (session_state.receive_buffer_size(), session_state.send_buffer_size(), session_state.buffer_pool())
         };
 
         // Create the message receiver that will drive writes
@@ -543,6 +690,7 @@ impl TcpTransport {
                 secure_channel: secure_channel.clone(),
                 state: connection_state.clone(),
                 last_received_sequence_number: 0,
+                pending_chunks: Vec::new(),
                 message_queue: message_queue.clone(),
             };
             Self::spawn_reading_task(reader, finished_flag, receive_buffer_size, read_connection);
@@ -553,7 +701,7 @@ impl TcpTransport {
             let write_connection = WriteState {
                 secure_channel,
                 state: connection_state,
-                send_buffer: MessageWriter::new(send_buffer_size),
+                send_buffer: MessageWriter::new(send_buffer_size, buffer_pool),
                 writer: Some(writer),
                 message_queue: message_queue.clone(),
             };
@@ -7,7 +7,7 @@ use std::str::FromStr;
 
 use opcua_types::{MessageSecurityMode, UAString};
 use opcua_core::config::Config;
-use opcua_core::crypto::SecurityPolicy;
+use opcua_core::crypto::{SecurityPolicy, CertificateValidation};
 
 use crate::session_retry::SessionRetryPolicy;
 
@@ -20,8 +20,10 @@ pub struct ClientUserToken {
     /// Password
     #[serde(skip_serializing_if = "Option::is_none")]
     pub password: Option<String>,
+    /// Path to the user's X509 certificate in .der format
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cert_path: Option<String>,
+    /// Path to the private key in .pem format that signs with the user's X509 certificate
     #[serde(skip_serializing_if = "Option::is_none")]
     pub private_key_path: Option<String>,
 }
@@ -35,6 +37,15 @@ impl ClientUserToken {
             private_key_path: None,
         }
     }
+
+    pub fn new_x509<S, T>(user: S, cert_path: T, private_key_path: T) -> Self where S: Into<String>, T: Into<String> {
+        ClientUserToken {
+            user: user.into(),
+            password: None,
+            cert_path: Some(cert_path.into()),
+            private_key_path: Some(private_key_path.into()),
+        }
+    }
 }
 
 /// Describes an endpoint, it's url security policy, mode and user token
@@ -85,6 +96,9 @@ pub struct ClientConfig {
     /// Auto trusts server certificates. For testing/samples only unless you're sure what you're
     /// doing.
     pub trust_server_certs: bool,
+    /// The checks made on server certificates, each of which can be relaxed
+    #[serde(default)]
+    pub certificate_validation: CertificateValidation,
     /// Product uri
     pub product_uri: String,
     /// pki folder, either absolute or relative to executable
@@ -101,8 +115,31 @@ pub struct ClientConfig {
     pub session_retry_limit: i32,
     /// Retry interval in milliseconds
     pub session_retry_interval: u32,
+    /// Maximum retry interval in milliseconds. The retry interval doubles after each failed
+    /// attempt up to this value.
+    #[serde(default = "ClientConfig::default_session_retry_max_interval")]
+    pub session_retry_max_interval: u32,
     /// Session timeout period in milliseconds
     pub session_timeout: u32,
+    /// Idle time in milliseconds before the operating system sends TCP keep-alive probes on the
+    /// connection, or 0 to disable them. Keep-alive probes detect a connection that was dropped
+    /// without being closed, e.g. by a network failure.
+    #[serde(default = "ClientConfig::default_tcp_keep_alive")]
+    pub tcp_keep_alive: u32,
+    /// Number of subscription keep-alive periods, i.e. the publishing interval multiplied by the
+    /// max keep-alive count, that may pass without any publish response before the connection is
+    /// treated as dead and reconnected. 0 disables the check.
+    #[serde(default = "ClientConfig::default_publish_keep_alive_limit")]
+    pub publish_keep_alive_limit: u32,
+    /// Maximum number of publish requests kept outstanding with the server. More outstanding
+    /// requests let a server with many subscriptions, or on a slow network, publish without
+    /// waiting for the client.
+    #[serde(default = "ClientConfig::default_max_publish_requests")]
+    pub max_publish_requests: u32,
+    /// Time in milliseconds to wait for the response to a request before it fails with
+    /// `BadRequestTimeout`. It is also the timeout hint that the server is given for the request.
+    #[serde(default = "ClientConfig::default_request_timeout")]
+    pub request_timeout: u32,
 }
 
 impl Config for ClientConfig {
@@ -140,7 +177,11 @@ impl Config for ClientConfig {
             }
             // Check for invalid security policy and modes in endpoints
             for (id, e) in &self.endpoints {
-                if SecurityPolicy::from_str(&e.security_policy).unwrap() != SecurityPolicy::Unknown {
+                let security_policy = SecurityPolicy::from_str(&e.security_policy).unwrap();
+                if cfg!(not(feature = "crypto")) && security_policy != SecurityPolicy::None {
+                    error!("Endpoint {} security policy {} requires the client to be built with the crypto feature", id, e.security_policy);
+                    valid = false;
+                } else if security_policy != SecurityPolicy::Unknown {
                     if MessageSecurityMode::Invalid == MessageSecurityMode::from(e.security_mode.as_ref()) {
                         error!("Endpoint {} security mode {} is invalid", id, e.security_mode);
                         valid = false;
@@ -151,6 +192,14 @@ impl Config for ClientConfig {
                 }
             }
         }
+        if self.max_publish_requests == 0 {
+            error!("Max publish requests must be at least 1");
+            valid = false;
+        }
+        if self.request_timeout == 0 {
+            error!("Request timeout must be greater than 0");
+            valid = false;
+        }
         if self.session_retry_limit < 0 && self.session_retry_limit != -1 {
             error!("Session retry limit of {} is invalid - must be -1 (infinite), 0 (never) or a positive value", self.session_retry_limit);
             valid = false;
@@ -174,6 +223,16 @@ impl Default for ClientConfig {
 impl ClientConfig {
     pub const PKI_DIR: &'static str = "pki";
 
+    fn default_session_retry_max_interval() -> u32 { SessionRetryPolicy::DEFAULT_MAX_RETRY_INTERVAL_MS }
+
+    fn default_tcp_keep_alive() -> u32 { 30000 }
+
+    fn default_publish_keep_alive_limit() -> u32 { 3 }
+
+    fn default_max_publish_requests() -> u32 { 2 }
+
+    fn default_request_timeout() -> u32 { 10000 }
+
     pub fn new<T>(application_name: T, application_uri: T) -> Self where T: Into<String> {
         let mut pki_dir = std::env::current_dir().unwrap();
         pki_dir.push(Self::PKI_DIR);
@@ -182,6 +241,7 @@ impl ClientConfig {
             application_uri: application_uri.into(),
             create_sample_keypair: false,
             trust_server_certs: false,
+            certificate_validation: CertificateValidation::default(),
             product_uri: String::new(),
             pki_dir,
             preferred_locales: Vec::new(),
@@ -190,7 +250,12 @@ impl ClientConfig {
             endpoints: BTreeMap::new(),
             session_retry_limit: SessionRetryPolicy::DEFAULT_RETRY_LIMIT as i32,
             session_retry_interval: SessionRetryPolicy::DEFAULT_RETRY_INTERVAL_MS,
+            session_retry_max_interval: Self::default_session_retry_max_interval(),
             session_timeout: 0,
+            tcp_keep_alive: Self::default_tcp_keep_alive(),
+            publish_keep_alive_limit: Self::default_publish_keep_alive_limit(),
+            max_publish_requests: Self::default_max_publish_requests(),
+            request_timeout: Self::default_request_timeout(),
         }
     }
 }
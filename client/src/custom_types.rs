@@ -0,0 +1,482 @@
+//! Decodes vendor specific structures whose types are not known to the client. The server describes
+//! the binary encoding of such types in an OPC binary schema dictionary (Part 3 Annex C), which is read
+//! into a `TypeDictionary` and used to decode the body of an extension object into a `StructureValue`,
+//! a generic map of field names to values.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use xml::{
+    namespace::Namespace,
+    reader::{EventReader, XmlEvent},
+};
+
+use opcua_types::{
+    *,
+    status_code::StatusCode,
+};
+
+/// Namespace of the types defined by the OPC binary schema itself
+pub const OPC_BINARY_SCHEMA_NAMESPACE: &str = "http://opcfoundation.org/BinarySchema/";
+/// Namespace of the built-in types defined by OPC UA
+pub const OPC_UA_NAMESPACE: &str = "http://opcfoundation.org/UA/";
+
+/// Structures may contain structures, but not without limit
+const MAX_NESTING_DEPTH: usize = 32;
+
+/// The value of a field of a decoded structure
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    /// A value of a built-in or enumerated type
+    Value(Variant),
+    /// An array of values
+    Array(Vec<FieldValue>),
+    /// A nested structure
+    Structure(Box<StructureValue>),
+    /// An optional field that is not present in the encoded structure
+    Absent,
+}
+
+impl FieldValue {
+    /// Returns the variant if the field is a value of a built-in or enumerated type
+    pub fn as_variant(&self) -> Option<&Variant> {
+        if let FieldValue::Value(ref value) = self { Some(value) } else { None }
+    }
+
+    /// Returns the structure if the field is a nested structure
+    pub fn as_structure(&self) -> Option<&StructureValue> {
+        if let FieldValue::Structure(ref value) = self { Some(value) } else { None }
+    }
+
+    /// Returns the integer value of a field, used for length and switch fields
+    fn as_i64(&self) -> Option<i64> {
+        match self {
+            FieldValue::Value(Variant::Boolean(v)) => Some(if *v { 1 } else { 0 }),
+            FieldValue::Value(Variant::SByte(v)) => Some(*v as i64),
+            FieldValue::Value(Variant::Byte(v)) => Some(*v as i64),
+            FieldValue::Value(Variant::Int16(v)) => Some(*v as i64),
+            FieldValue::Value(Variant::UInt16(v)) => Some(*v as i64),
+            FieldValue::Value(Variant::Int32(v)) => Some(*v as i64),
+            FieldValue::Value(Variant::UInt32(v)) => Some(*v as i64),
+            FieldValue::Value(Variant::Int64(v)) => Some(*v),
+            FieldValue::Value(Variant::UInt64(v)) => Some(*v as i64),
+            _ => None
+        }
+    }
+}
+
+/// A structure decoded with a type dictionary. The fields are in the order they are encoded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructureValue {
+    /// The name of the structured type in its dictionary
+    pub type_name: String,
+    /// The names and values of the fields
+    pub fields: Vec<(String, FieldValue)>,
+}
+
+impl StructureValue {
+    /// Returns the value of the named field
+    pub fn get(&self, name: &str) -> Option<&FieldValue> {
+        self.fields.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+}
+
+/// A type referenced by the `TypeName` of a field
+#[derive(Debug, Clone, PartialEq)]
+enum TypeName {
+    /// A type of the OPC binary schema or a built-in type of OPC UA
+    BuiltIn(String),
+    /// A type defined in a dictionary
+    Dictionary(String),
+}
+
+/// How the value of the switch field of an optional field is compared to its switch value
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SwitchOperand {
+    Equals,
+    GreaterThan,
+    LessThan,
+    GreaterThanOrEqual,
+    LessThanOrEqual,
+    NotEqual,
+}
+
+impl SwitchOperand {
+    fn from_str(s: &str) -> Option<SwitchOperand> {
+        match s {
+            "Equals" => Some(SwitchOperand::Equals),
+            "GreaterThan" => Some(SwitchOperand::GreaterThan),
+            "LessThan" => Some(SwitchOperand::LessThan),
+            "GreaterThanOrEqual" => Some(SwitchOperand::GreaterThanOrEqual),
+            "LessThanOrEqual" => Some(SwitchOperand::LessThanOrEqual),
+            "NotEqual" => Some(SwitchOperand::NotEqual),
+            _ => None
+        }
+    }
+
+    fn compare(self, value: i64, switch_value: i64) -> bool {
+        match self {
+            SwitchOperand::Equals => value == switch_value,
+            SwitchOperand::GreaterThan => value > switch_value,
+            SwitchOperand::LessThan => value < switch_value,
+            SwitchOperand::GreaterThanOrEqual => value >= switch_value,
+            SwitchOperand::LessThanOrEqual => value <= switch_value,
+            SwitchOperand::NotEqual => value != switch_value,
+        }
+    }
+}
+
+/// A field of a structured type
+#[derive(Debug, Clone, PartialEq)]
+struct FieldDescription {
+    name: String,
+    type_name: TypeName,
+    /// The number of bits of a bit field, or the fixed number of elements of an array
+    length: Option<u32>,
+    /// The field holding the number of elements of an array
+    length_field: Option<String>,
+    /// The field deciding if an optional field is present
+    switch_field: Option<String>,
+    switch_value: Option<i64>,
+    switch_operand: SwitchOperand,
+}
+
+impl FieldDescription {
+    fn is_bit(&self) -> bool {
+        self.type_name == TypeName::BuiltIn("Bit".to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TypeDescription {
+    Structured(Vec<FieldDescription>),
+    Enumerated { length_in_bits: u32 },
+}
+
+/// The types of an OPC binary schema dictionary, as held in the value of a `DataTypeDictionaryType`
+/// variable on the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeDictionary {
+    target_namespace: String,
+    types: HashMap<String, TypeDescription>,
+}
+
+impl TypeDictionary {
+    /// Parses the XML of a binary schema dictionary. Only the structured and enumerated types are
+    /// kept, opaque types cannot be decoded.
+    pub fn parse(xml: &[u8]) -> Result<TypeDictionary, StatusCode> {
+        let mut target_namespace = String::new();
+        let mut types = HashMap::new();
+        // The structured type currently being parsed, and its fields
+        let mut structured_type: Option<(String, Vec<FieldDescription>)> = None;
+
+        for event in EventReader::new(xml) {
+            let event = event.map_err(|err| {
+                error!("Type dictionary is not valid XML, {}", err);
+                StatusCode::BadDecodingError
+            })?;
+            match event {
+                XmlEvent::StartElement { name, attributes, namespace } => {
+                    if name.namespace.as_ref().map(|n| n.as_str()) != Some(OPC_BINARY_SCHEMA_NAMESPACE) {
+                        continue;
+                    }
+                    let attribute = |attribute_name: &str| {
+                        attributes.iter().find(|a| a.name.local_name == attribute_name).map(|a| a.value.clone())
+                    };
+                    match name.local_name.as_str() {
+                        "TypeDictionary" => {
+                            target_namespace = attribute("TargetNamespace").unwrap_or_default();
+                        }
+                        "StructuredType" => {
+                            let type_name = attribute("Name").ok_or(StatusCode::BadDecodingError)?;
+                            structured_type = Some((type_name, Vec::new()));
+                        }
+                        "Field" => {
+                            if let Some((_, ref mut fields)) = structured_type {
+                                fields.push(Self::parse_field(&namespace, &attribute)?);
+                            }
+                        }
+                        "EnumeratedType" => {
+                            let type_name = attribute("Name").ok_or(StatusCode::BadDecodingError)?;
+                            let length_in_bits = Self::parse_number(attribute("LengthInBits"))?.unwrap_or(32) as u32;
+                            types.insert(type_name, TypeDescription::Enumerated { length_in_bits });
+                        }
+                        _ => {}
+                    }
+                }
+                XmlEvent::EndElement { name } => {
+                    if name.local_name == "StructuredType" {
+                        if let Some((type_name, fields)) = structured_type.take() {
+                            types.insert(type_name, TypeDescription::Structured(fields));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if target_namespace.is_empty() {
+            error!("Type dictionary has no target namespace");
+            Err(StatusCode::BadDecodingError)
+        } else {
+            Ok(TypeDictionary {
+                target_namespace,
+                types,
+            })
+        }
+    }
+
+    fn parse_field<F>(namespace: &Namespace, attribute: &F) -> Result<FieldDescription, StatusCode> where F: Fn(&str) -> Option<String> {
+        let name = attribute("Name").ok_or(StatusCode::BadDecodingError)?;
+        let type_name = attribute("TypeName").ok_or(StatusCode::BadDecodingError)?;
+        // The type name is qualified by the prefix of its namespace, e.g. "opc:Int32" or "tns:MyType"
+        let (prefix, local_name) = if let Some(idx) = type_name.find(':') {
+            (Some(&type_name[..idx]), &type_name[idx + 1..])
+        } else {
+            (None, type_name.as_str())
+        };
+        let type_namespace = namespace.get(prefix.unwrap_or(""));
+        let type_name = match type_namespace {
+            Some(OPC_BINARY_SCHEMA_NAMESPACE) | Some(OPC_UA_NAMESPACE) => TypeName::BuiltIn(local_name.to_string()),
+            _ => TypeName::Dictionary(local_name.to_string()),
+        };
+        let switch_operand = if let Some(switch_operand) = attribute("SwitchOperand") {
+            SwitchOperand::from_str(&switch_operand).ok_or_else(|| {
+                error!("Field {} has an invalid switch operand {}", name, switch_operand);
+                StatusCode::BadDecodingError
+            })?
+        } else {
+            SwitchOperand::Equals
+        };
+        Ok(FieldDescription {
+            name,
+            type_name,
+            length: Self::parse_number(attribute("Length"))?.map(|v| v as u32),
+            length_field: attribute("LengthField"),
+            switch_field: attribute("SwitchField"),
+            switch_value: Self::parse_number(attribute("SwitchValue"))?,
+            switch_operand,
+        })
+    }
+
+    fn parse_number(value: Option<String>) -> Result<Option<i64>, StatusCode> {
+        if let Some(value) = value {
+            value.parse::<i64>().map(Some).map_err(|_| {
+                error!("Type dictionary has an invalid number {}", value);
+                StatusCode::BadDecodingError
+            })
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The namespace uri of the types of the dictionary
+    pub fn target_namespace(&self) -> &str {
+        &self.target_namespace
+    }
+
+    /// Tests if the dictionary contains a structured type with the name
+    pub fn contains_structure(&self, type_name: &str) -> bool {
+        if let Some(TypeDescription::Structured(_)) = self.types.get(type_name) { true } else { false }
+    }
+
+    /// Decodes the binary encoded body of a structure of the named type
+    pub fn decode(&self, type_name: &str, data: &[u8], decoding_limits: &DecodingLimits) -> Result<StructureValue, StatusCode> {
+        let mut decoder = Decoder {
+            stream: Cursor::new(data),
+            decoding_limits,
+            bits: 0,
+            bits_remaining: 0,
+        };
+        self.decode_structure(type_name, &mut decoder, 0)
+    }
+
+    fn decode_structure(&self, type_name: &str, decoder: &mut Decoder, depth: usize) -> Result<StructureValue, StatusCode> {
+        if depth > MAX_NESTING_DEPTH {
+            error!("Structure {} is nested too deeply to decode", type_name);
+            return Err(StatusCode::BadDecodingError);
+        }
+        let fields = if let Some(TypeDescription::Structured(ref fields)) = self.types.get(type_name) {
+            fields
+        } else {
+            error!("Type dictionary {} has no structured type {}", self.target_namespace, type_name);
+            return Err(StatusCode::BadDataTypeIdUnknown);
+        };
+
+        let mut structure = StructureValue {
+            type_name: type_name.to_string(),
+            fields: Vec::with_capacity(fields.len()),
+        };
+        for field in fields {
+            let value = if !Self::is_present(field, &structure)? {
+                FieldValue::Absent
+            } else if field.is_bit() {
+                let length = field.length.unwrap_or(1);
+                let value = decoder.read_bits(length)?;
+                if length == 1 {
+                    FieldValue::Value(Variant::Boolean(value != 0))
+                } else {
+                    FieldValue::Value(Variant::UInt32(value))
+                }
+            } else {
+                // Anything other than bits starts on a byte boundary
+                decoder.align();
+                let length = if let Some(ref length_field) = field.length_field {
+                    let length = structure.get(length_field).and_then(|v| v.as_i64()).ok_or_else(|| {
+                        error!("Field {} of {} has no length field {}", field.name, type_name, length_field);
+                        StatusCode::BadDecodingError
+                    })?;
+                    Some(length)
+                } else {
+                    field.length.map(|v| v as i64)
+                };
+                if let Some(length) = length {
+                    if length < 0 {
+                        // A null array
+                        FieldValue::Array(Vec::new())
+                    } else if length as usize > decoder.decoding_limits.max_array_length {
+                        error!("Field {} of {} has an array length {} exceeding the decoding limit", field.name, type_name, length);
+                        return Err(StatusCode::BadEncodingLimitsExceeded);
+                    } else {
+                        let mut values = Vec::with_capacity(length as usize);
+                        for _ in 0..length {
+                            values.push(self.decode_field(&field.type_name, decoder, depth)?);
+                        }
+                        FieldValue::Array(values)
+                    }
+                } else {
+                    self.decode_field(&field.type_name, decoder, depth)?
+                }
+            };
+            structure.fields.push((field.name.clone(), value));
+        }
+        decoder.align();
+        Ok(structure)
+    }
+
+    /// Tests if an optional field is present from the value of its switch field
+    fn is_present(field: &FieldDescription, structure: &StructureValue) -> Result<bool, StatusCode> {
+        if let Some(ref switch_field) = field.switch_field {
+            let value = structure.get(switch_field).and_then(|v| v.as_i64()).ok_or_else(|| {
+                error!("Field {} of {} has no switch field {}", field.name, structure.type_name, switch_field);
+                StatusCode::BadDecodingError
+            })?;
+            Ok(if let Some(switch_value) = field.switch_value {
+                field.switch_operand.compare(value, switch_value)
+            } else {
+                value != 0
+            })
+        } else {
+            Ok(true)
+        }
+    }
+
+    fn decode_field(&self, type_name: &TypeName, decoder: &mut Decoder, depth: usize) -> Result<FieldValue, StatusCode> {
+        match type_name {
+            TypeName::BuiltIn(type_name) => decoder.read_built_in(type_name).map(FieldValue::Value),
+            TypeName::Dictionary(type_name) => {
+                match self.types.get(type_name) {
+                    Some(TypeDescription::Structured(_)) => {
+                        Ok(FieldValue::Structure(Box::new(self.decode_structure(type_name, decoder, depth + 1)?)))
+                    }
+                    Some(TypeDescription::Enumerated { length_in_bits }) => {
+                        let value = match length_in_bits {
+                            8 => decoder.read_built_in("SByte")?,
+                            16 => decoder.read_built_in("Int16")?,
+                            32 => decoder.read_built_in("Int32")?,
+                            64 => decoder.read_built_in("Int64")?,
+                            _ => {
+                                error!("Enumerated type {} has an unsupported length of {} bits", type_name, length_in_bits);
+                                return Err(StatusCode::BadDecodingError);
+                            }
+                        };
+                        Ok(FieldValue::Value(value))
+                    }
+                    None => {
+                        error!("Type dictionary {} has no type {}", self.target_namespace, type_name);
+                        Err(StatusCode::BadDataTypeIdUnknown)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Reads bit fields and built-in types from the encoded body of a structure
+struct Decoder<'a> {
+    stream: Cursor<&'a [u8]>,
+    decoding_limits: &'a DecodingLimits,
+    /// The byte that bit fields are being read from, least significant bit first
+    bits: u8,
+    bits_remaining: u32,
+}
+
+impl<'a> Decoder<'a> {
+    fn read_bits(&mut self, length: u32) -> Result<u32, StatusCode> {
+        if length > 32 {
+            error!("Bit field of {} bits is too long", length);
+            return Err(StatusCode::BadDecodingError);
+        }
+        let mut value = 0u32;
+        for i in 0..length {
+            if self.bits_remaining == 0 {
+                self.bits = read_u8(&mut self.stream)?;
+                self.bits_remaining = 8;
+            }
+            value |= ((self.bits & 1) as u32) << i;
+            self.bits >>= 1;
+            self.bits_remaining -= 1;
+        }
+        Ok(value)
+    }
+
+    /// Discards the unread bits of the current byte
+    fn align(&mut self) {
+        self.bits_remaining = 0;
+    }
+
+    fn read_built_in(&mut self, type_name: &str) -> Result<Variant, StatusCode> {
+        let stream = &mut self.stream;
+        let decoding_limits = self.decoding_limits;
+        let value = match type_name {
+            "Boolean" => Variant::Boolean(bool::decode(stream, decoding_limits)?),
+            "SByte" | "Char" => Variant::SByte(i8::decode(stream, decoding_limits)?),
+            "Byte" => Variant::Byte(u8::decode(stream, decoding_limits)?),
+            "Int16" => Variant::Int16(i16::decode(stream, decoding_limits)?),
+            "UInt16" | "WideChar" => Variant::UInt16(u16::decode(stream, decoding_limits)?),
+            "Int32" => Variant::Int32(i32::decode(stream, decoding_limits)?),
+            "UInt32" => Variant::UInt32(u32::decode(stream, decoding_limits)?),
+            "Int64" => Variant::Int64(i64::decode(stream, decoding_limits)?),
+            "UInt64" => Variant::UInt64(u64::decode(stream, decoding_limits)?),
+            "Float" => Variant::Float(f32::decode(stream, decoding_limits)?),
+            "Double" => Variant::Double(f64::decode(stream, decoding_limits)?),
+            "String" | "CharArray" => Variant::String(UAString::decode(stream, decoding_limits)?),
+            "DateTime" => Variant::DateTime(Box::new(DateTime::decode(stream, decoding_limits)?)),
+            "Guid" => Variant::Guid(Box::new(Guid::decode(stream, decoding_limits)?)),
+            "ByteString" => Variant::ByteString(ByteString::decode(stream, decoding_limits)?),
+            "XmlElement" => Variant::XmlElement(XmlElement::decode(stream, decoding_limits)?),
+            "StatusCode" => Variant::StatusCode(StatusCode::decode(stream, decoding_limits)?),
+            "NodeId" => Variant::NodeId(Box::new(NodeId::decode(stream, decoding_limits)?)),
+            "ExpandedNodeId" => Variant::ExpandedNodeId(Box::new(ExpandedNodeId::decode(stream, decoding_limits)?)),
+            "QualifiedName" => Variant::QualifiedName(Box::new(QualifiedName::decode(stream, decoding_limits)?)),
+            "LocalizedText" => Variant::LocalizedText(Box::new(LocalizedText::decode(stream, decoding_limits)?)),
+            "ExtensionObject" => Variant::ExtensionObject(Box::new(ExtensionObject::decode(stream, decoding_limits)?)),
+            "DataValue" => Variant::DataValue(Box::new(DataValue::decode(stream, decoding_limits)?)),
+            "Variant" => Variant::decode(stream, decoding_limits)?,
+            _ => {
+                error!("Built-in type {} cannot be decoded", type_name);
+                return Err(StatusCode::BadDataTypeIdUnknown);
+            }
+        };
+        Ok(value)
+    }
+}
+
+/// Returns the body of a binary encoded extension object
+pub(crate) fn extension_object_body(extension_object: &ExtensionObject) -> Result<&[u8], StatusCode> {
+    match extension_object.body {
+        ExtensionObjectEncoding::ByteString(ByteString { value: Some(ref value) }) => Ok(value.as_slice()),
+        ExtensionObjectEncoding::XmlElement(_) => Err(StatusCode::BadDataEncodingUnsupported),
+        _ => Err(StatusCode::BadDecodingError),
+    }
+}
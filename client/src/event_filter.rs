@@ -0,0 +1,143 @@
+//! Provides a builder for the event filters of event monitored items.
+
+use opcua_types::{
+    *,
+    node_ids::{ObjectId, ObjectTypeId},
+    service_types::{ContentFilter, ContentFilterElement, ElementOperand, EventFilter, FilterOperator, LiteralOperand, SimpleAttributeOperand},
+};
+
+/// Builds an `EventFilter` for an event monitored item. Select clauses are the fields of the
+/// event that are delivered with each notification, in the order they are selected. Where clauses
+/// compare a field of the event with a value and an event is only delivered if it satisfies all
+/// of them.
+///
+/// Fields are identified by the browse path from the event type to the field with each browse name
+/// separated by `/`, e.g. `"Message"` or `"EnabledState/Id"`.
+///
+/// ```no_run
+/// use opcua_client::prelude::*;
+///
+/// let filter = EventFilterBuilder::new()
+///     .select("EventType")
+///     .select("Message")
+///     .select("Severity")
+///     .where_clause("Severity", FilterOperator::GreaterThanOrEqual, 500u16)
+///     .build();
+/// ```
+pub struct EventFilterBuilder {
+    /// The type that the fields of the select and where clauses belong to
+    event_type_id: NodeId,
+    select_clauses: Vec<SimpleAttributeOperand>,
+    where_clauses: Vec<ContentFilterElement>,
+}
+
+impl Default for EventFilterBuilder {
+    fn default() -> Self {
+        EventFilterBuilder::new()
+    }
+}
+
+impl EventFilterBuilder {
+    /// Creates a builder for fields of `BaseEventType`
+    pub fn new() -> EventFilterBuilder {
+        Self::new_for_type(ObjectTypeId::BaseEventType)
+    }
+
+    /// Creates a builder for fields of the supplied event type. Fields of the type are null for
+    /// events which are not of the type or one of its subtypes.
+    pub fn new_for_type<T>(event_type_id: T) -> EventFilterBuilder where T: Into<NodeId> {
+        EventFilterBuilder {
+            event_type_id: event_type_id.into(),
+            select_clauses: Vec::new(),
+            where_clauses: Vec::new(),
+        }
+    }
+
+    /// Selects the value of a field by its browse path
+    pub fn select(mut self, browse_path: &str) -> Self {
+        let operand = self.field_operand(browse_path);
+        self.select_clauses.push(operand);
+        self
+    }
+
+    /// Adds a where clause that compares the field with the value. The filter operator must be one
+    /// that compares two operands, i.e. `Equals`, `GreaterThan`, `LessThan`, `GreaterThanOrEqual`,
+    /// `LessThanOrEqual` or `Like`.
+    pub fn where_clause<V>(mut self, browse_path: &str, filter_operator: FilterOperator, value: V) -> Self where V: Into<Variant> {
+        let field = self.field_operand(browse_path);
+        self.where_clauses.push(ContentFilterElement {
+            filter_operator,
+            filter_operands: Some(vec![
+                ExtensionObject::from_encodable(ObjectId::SimpleAttributeOperand_Encoding_DefaultBinary, &field),
+                ExtensionObject::from_encodable(ObjectId::LiteralOperand_Encoding_DefaultBinary, &LiteralOperand { value: value.into() }),
+            ]),
+        });
+        self
+    }
+
+    /// Adds a where clause that the event is of the type or one of its subtypes
+    pub fn where_of_type<T>(mut self, event_type_id: T) -> Self where T: Into<NodeId> {
+        let event_type_id: NodeId = event_type_id.into();
+        self.where_clauses.push(ContentFilterElement {
+            filter_operator: FilterOperator::OfType,
+            filter_operands: Some(vec![
+                ExtensionObject::from_encodable(ObjectId::LiteralOperand_Encoding_DefaultBinary, &LiteralOperand { value: event_type_id.into() }),
+            ]),
+        });
+        self
+    }
+
+    /// Builds the event filter. More than one where clause are joined together with `And`.
+    pub fn build(self) -> EventFilter {
+        EventFilter {
+            select_clauses: if self.select_clauses.is_empty() { None } else { Some(self.select_clauses) },
+            where_clause: ContentFilter {
+                elements: Self::join_where_clauses(self.where_clauses),
+            },
+        }
+    }
+
+    /// Builds the event filter into an extension object for the `filter` of `MonitoringParameters`
+    pub fn build_extension_object(self) -> ExtensionObject {
+        ExtensionObject::from_encodable(ObjectId::EventFilter_Encoding_DefaultBinary, &self.build())
+    }
+
+    fn field_operand(&self, browse_path: &str) -> SimpleAttributeOperand {
+        let browse_path = browse_path.split('/')
+            .filter(|name| !name.is_empty())
+            .map(|name| QualifiedName::new(0, name))
+            .collect::<Vec<QualifiedName>>();
+        SimpleAttributeOperand {
+            type_definition_id: self.event_type_id.clone(),
+            browse_path: if browse_path.is_empty() { None } else { Some(browse_path) },
+            attribute_id: AttributeId::Value as u32,
+            index_range: UAString::null(),
+        }
+    }
+
+    /// Joins the clauses with `And` elements. An element may only refer to elements after it so the
+    /// `And` elements come first, each joining a clause to the next `And` and the last joining the
+    /// final two clauses.
+    fn join_where_clauses(where_clauses: Vec<ContentFilterElement>) -> Option<Vec<ContentFilterElement>> {
+        let count = where_clauses.len();
+        match count {
+            0 => None,
+            1 => Some(where_clauses),
+            _ => {
+                let element_operand = |index: usize| {
+                    ExtensionObject::from_encodable(ObjectId::ElementOperand_Encoding_DefaultBinary, &ElementOperand { index: index as u32 })
+                };
+                // The ands are at 0..count - 1 and the clauses at count - 1..2 * count - 1
+                let mut elements = (0..count - 1).map(|i| {
+                    let next = if i < count - 2 { i + 1 } else { 2 * count - 2 };
+                    ContentFilterElement {
+                        filter_operator: FilterOperator::And,
+                        filter_operands: Some(vec![element_operand(count - 1 + i), element_operand(next)]),
+                    }
+                }).collect::<Vec<ContentFilterElement>>();
+                elements.extend(where_clauses);
+                Some(elements)
+            }
+        }
+    }
+}
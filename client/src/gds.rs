@@ -0,0 +1,289 @@
+//! Pull certificate management through a global discovery server (GDS), OPC UA Part 12. The client
+//! registers its application with the GDS, requests a certificate for a signing request and fetches
+//! the trust list of its certificate group, and then installs the results into its PKI directory.
+//!
+//! The GDS nodes are in the GDS namespace of the server, whose index is looked up when a
+//! `GdsClient` is created.
+
+use std::io::{Cursor, Read, Write};
+
+use opcua_types::{
+    *,
+    node_ids::{MethodId, VariableId},
+    status_code::StatusCode,
+    service_types::{ApplicationType, ReadValueId, TrustListDataType},
+};
+
+use opcua_core::crypto::{CertificateStore, PrivateKey, X509};
+
+use crate::session::{MethodCallResult, Session};
+
+/// The namespace of the GDS information model
+pub const GDS_NAMESPACE: &str = "http://opcfoundation.org/UA/GDS/";
+
+// Numeric ids of the nodes in the GDS namespace
+const APPLICATION_RECORD_DATA_TYPE_ENCODING_DEFAULT_BINARY: u32 = 134;
+const DIRECTORY: u32 = 141;
+const DIRECTORY_REGISTER_APPLICATION: u32 = 146;
+const DIRECTORY_START_SIGNING_REQUEST: u32 = 157;
+const DIRECTORY_FINISH_REQUEST: u32 = 163;
+const DIRECTORY_GET_TRUST_LIST: u32 = 204;
+
+/// The mode for opening a file for reading, Part 5 C.2.1
+const FILE_OPEN_MODE_READ: u8 = 1;
+/// The number of bytes requested by each read of a trust list file
+const TRUST_LIST_READ_SIZE: i32 = 64 * 1024;
+
+/// The record of an application in the GDS, the ApplicationRecordDataType of Part 12
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApplicationRecord {
+    /// The id assigned by the GDS, null when registering an application
+    pub application_id: NodeId,
+    pub application_uri: UAString,
+    pub application_type: ApplicationType,
+    pub application_names: Option<Vec<LocalizedText>>,
+    pub product_uri: UAString,
+    pub discovery_urls: Option<Vec<UAString>>,
+    pub server_capabilities: Option<Vec<UAString>>,
+}
+
+impl BinaryEncoder<ApplicationRecord> for ApplicationRecord {
+    fn byte_len(&self) -> usize {
+        let mut size = 0;
+        size += self.application_id.byte_len();
+        size += self.application_uri.byte_len();
+        size += self.application_type.byte_len();
+        size += byte_len_array(&self.application_names);
+        size += self.product_uri.byte_len();
+        size += byte_len_array(&self.discovery_urls);
+        size += byte_len_array(&self.server_capabilities);
+        size
+    }
+
+    fn encode<S: Write>(&self, stream: &mut S) -> EncodingResult<usize> {
+        let mut size = 0;
+        size += self.application_id.encode(stream)?;
+        size += self.application_uri.encode(stream)?;
+        size += self.application_type.encode(stream)?;
+        size += write_array(stream, &self.application_names)?;
+        size += self.product_uri.encode(stream)?;
+        size += write_array(stream, &self.discovery_urls)?;
+        size += write_array(stream, &self.server_capabilities)?;
+        Ok(size)
+    }
+
+    fn decode<S: Read>(stream: &mut S, decoding_limits: &DecodingLimits) -> EncodingResult<Self> {
+        let application_id = NodeId::decode(stream, decoding_limits)?;
+        let application_uri = UAString::decode(stream, decoding_limits)?;
+        let application_type = ApplicationType::decode(stream, decoding_limits)?;
+        let application_names: Option<Vec<LocalizedText>> = read_array(stream, decoding_limits)?;
+        let product_uri = UAString::decode(stream, decoding_limits)?;
+        let discovery_urls: Option<Vec<UAString>> = read_array(stream, decoding_limits)?;
+        let server_capabilities: Option<Vec<UAString>> = read_array(stream, decoding_limits)?;
+        Ok(ApplicationRecord {
+            application_id,
+            application_uri,
+            application_type,
+            application_names,
+            product_uri,
+            discovery_urls,
+            server_capabilities,
+        })
+    }
+}
+
+/// A certificate issued by the GDS in answer to a signing request
+#[derive(Debug, Clone, PartialEq)]
+pub struct IssuedCertificate {
+    /// The DER encoded certificate
+    pub certificate: ByteString,
+    /// The private key, only returned when the GDS generated the key pair
+    pub private_key: ByteString,
+    /// The DER encoded certificates of the issuers of the certificate
+    pub issuer_certificates: Vec<ByteString>,
+}
+
+/// Calls the methods of the Directory object of a GDS over a session which has been connected
+/// and activated, normally with a user that the GDS allows to manage certificates.
+pub struct GdsClient<'a> {
+    session: &'a mut Session,
+    /// The index of the GDS namespace on the server
+    namespace: u16,
+}
+
+impl<'a> GdsClient<'a> {
+    /// Creates a client for the GDS on the other end of the session. Fails with `BadNotSupported`
+    /// if the server does not have the GDS namespace.
+    pub fn new(session: &'a mut Session) -> Result<GdsClient<'a>, StatusCode> {
+        let namespaces = session.read(&[ReadValueId::from(NodeId::from(&VariableId::Server_NamespaceArray))])?
+            .and_then(|results| results.into_iter().next())
+            .and_then(|data_value| data_value.value)
+            .ok_or(StatusCode::BadUnexpectedError)?;
+        let namespace = match namespaces {
+            Variant::Array(ref namespaces) => namespaces.iter().position(|namespace| {
+                if let Variant::String(ref namespace) = namespace { namespace.as_ref() == GDS_NAMESPACE } else { false }
+            }),
+            _ => None
+        };
+        if let Some(namespace) = namespace {
+            Ok(GdsClient { session, namespace: namespace as u16 })
+        } else {
+            error!("Server does not have the GDS namespace {}", GDS_NAMESPACE);
+            Err(StatusCode::BadNotSupported)
+        }
+    }
+
+    fn gds_node_id(&self, id: u32) -> NodeId {
+        NodeId::new(self.namespace, id)
+    }
+
+    /// Calls a method of the Directory object, turning a bad status of the call into an error
+    fn call_directory_method(&mut self, method_id: u32, input_arguments: Vec<Variant>) -> Result<MethodCallResult, StatusCode> {
+        let (object_id, method_id) = (self.gds_node_id(DIRECTORY), self.gds_node_id(method_id));
+        Self::good_result(self.session.call_method(object_id, method_id, input_arguments)?)
+    }
+
+    fn good_result(result: MethodCallResult) -> Result<MethodCallResult, StatusCode> {
+        if result.status_code.is_bad() {
+            Err(result.status_code)
+        } else {
+            Ok(result)
+        }
+    }
+
+    fn byte_string_array_output(result: &MethodCallResult, index: usize) -> Result<Vec<ByteString>, StatusCode> {
+        match result.output_arguments.get(index) {
+            Some(Variant::Array(values)) => values.iter().map(|value| {
+                if let Variant::ByteString(value) = value { Ok(value.clone()) } else { Err(StatusCode::BadTypeMismatch) }
+            }).collect(),
+            Some(Variant::Empty) | None => Ok(Vec::new()),
+            _ => Err(StatusCode::BadTypeMismatch)
+        }
+    }
+
+    /// Registers the application with the GDS, returning the application id that the GDS
+    /// assigned to it.
+    pub fn register_application(&mut self, application: &ApplicationRecord) -> Result<NodeId, StatusCode> {
+        let encoding_id = self.gds_node_id(APPLICATION_RECORD_DATA_TYPE_ENCODING_DEFAULT_BINARY);
+        let application = ExtensionObject::from_encodable(encoding_id, application);
+        let result = self.call_directory_method(DIRECTORY_REGISTER_APPLICATION, vec![application.into()])?;
+        result.output(0)
+    }
+
+    /// Asks the GDS to issue a certificate for a PKCS #10 signing request, returning the id of
+    /// the request to pass to `finish_request`. A null certificate group and type select the
+    /// defaults of the GDS.
+    pub fn start_signing_request(&mut self, application_id: &NodeId, certificate_group_id: &NodeId, certificate_type_id: &NodeId, certificate_request: &ByteString) -> Result<NodeId, StatusCode> {
+        let result = self.call_directory_method(DIRECTORY_START_SIGNING_REQUEST, vec![
+            application_id.clone().into(),
+            certificate_group_id.clone().into(),
+            certificate_type_id.clone().into(),
+            certificate_request.clone().into(),
+        ])?;
+        result.output(0)
+    }
+
+    /// Collects the certificate of a request. Returns `None` if the GDS has not issued the
+    /// certificate yet, in which case the call should be repeated later.
+    pub fn finish_request(&mut self, application_id: &NodeId, request_id: &NodeId) -> Result<Option<IssuedCertificate>, StatusCode> {
+        let result = match self.call_directory_method(DIRECTORY_FINISH_REQUEST, vec![application_id.clone().into(), request_id.clone().into()]) {
+            Ok(result) => result,
+            Err(StatusCode::BadNothingToDo) => return Ok(None),
+            Err(status_code) => return Err(status_code)
+        };
+        Ok(Some(IssuedCertificate {
+            certificate: result.output(0)?,
+            private_key: result.output::<ByteString>(1).unwrap_or_else(|_| ByteString::null()),
+            issuer_certificates: Self::byte_string_array_output(&result, 2)?,
+        }))
+    }
+
+    /// Fetches the trust list of the application's certificate group. A null certificate group
+    /// selects the default group.
+    pub fn get_trust_list(&mut self, application_id: &NodeId, certificate_group_id: &NodeId) -> Result<TrustListDataType, StatusCode> {
+        let result = self.call_directory_method(DIRECTORY_GET_TRUST_LIST, vec![application_id.clone().into(), certificate_group_id.clone().into()])?;
+        let trust_list_id: NodeId = result.output(0)?;
+        self.read_trust_list(&trust_list_id)
+    }
+
+    /// Reads the contents of a TrustList object through its file methods
+    pub fn read_trust_list(&mut self, trust_list_id: &NodeId) -> Result<TrustListDataType, StatusCode> {
+        let result = Self::good_result(self.session.call_method(trust_list_id.clone(), MethodId::FileType_Open, vec![Variant::from(FILE_OPEN_MODE_READ)])?)?;
+        let file_handle: u32 = result.output(0)?;
+
+        let contents = self.read_file(trust_list_id, file_handle);
+        let closed = self.session.call_method(trust_list_id.clone(), MethodId::FileType_Close, vec![Variant::from(file_handle)]);
+        let contents = contents?;
+        if let Err(status_code) = closed.and_then(Self::good_result) {
+            warn!("Trust list file could not be closed, {}", status_code);
+        }
+
+        TrustListDataType::decode(&mut Cursor::new(contents), &DecodingLimits::default()).map_err(|_| {
+            error!("Trust list cannot be decoded");
+            StatusCode::BadDecodingError
+        })
+    }
+
+    fn read_file(&mut self, file_id: &NodeId, file_handle: u32) -> Result<Vec<u8>, StatusCode> {
+        let mut contents = Vec::new();
+        loop {
+            let result = Self::good_result(self.session.call_method(file_id.clone(), MethodId::FileType_Read, vec![Variant::from(file_handle), Variant::from(TRUST_LIST_READ_SIZE)])?)?;
+            let data: ByteString = result.output(0)?;
+            let data = data.value.unwrap_or_default();
+            contents.extend_from_slice(&data);
+            if data.len() < TRUST_LIST_READ_SIZE as usize {
+                break;
+            }
+        }
+        Ok(contents)
+    }
+}
+
+/// Installs an issued certificate as the application's own certificate in the PKI directory,
+/// with the private key that was used for the signing request unless the GDS returned a PEM
+/// encoded key. The certificates of the issuers go into the issuers directory. The client must
+/// connect again to use the new certificate.
+pub fn install_certificate(certificate_store: &CertificateStore, issued_certificate: &IssuedCertificate, private_key: Option<&PrivateKey>) -> Result<(), StatusCode> {
+    let certificate = X509::from_byte_string(&issued_certificate.certificate)?;
+    let issued_private_key;
+    let private_key = if !issued_certificate.private_key.is_null_or_empty() {
+        issued_private_key = PrivateKey::from_pem(issued_certificate.private_key.as_ref()).map_err(|_| {
+            error!("Private key issued by the GDS is not in PEM format");
+            StatusCode::BadNotSupported
+        })?;
+        &issued_private_key
+    } else {
+        private_key.ok_or(StatusCode::BadInvalidArgument)?
+    };
+    for issuer_certificate in &issued_certificate.issuer_certificates {
+        let issuer_certificate = X509::from_byte_string(issuer_certificate)?;
+        certificate_store.store_issuer_cert(&issuer_certificate).map_err(|err| {
+            error!("{}", err);
+            StatusCode::BadInternalError
+        })?;
+    }
+    certificate_store.store_own_cert_and_pkey(&certificate, private_key, true).map_err(|err| {
+        error!("{}", err);
+        StatusCode::BadInternalError
+    })
+}
+
+/// Installs the contents of a trust list into the PKI directory. Trusted certificates go into
+/// the trusted directory, issuer certificates into the issuers directory and revocation lists
+/// into the crl directory. Certificates already in the directories are kept.
+pub fn install_trust_list(certificate_store: &CertificateStore, trust_list: &TrustListDataType) -> Result<(), StatusCode> {
+    let to_status_code = |err: String| {
+        error!("{}", err);
+        StatusCode::BadInternalError
+    };
+    for certificate in trust_list.trusted_certificates.iter().flatten() {
+        certificate_store.store_trusted_cert(&X509::from_byte_string(certificate)?).map_err(to_status_code)?;
+    }
+    for certificate in trust_list.issuer_certificates.iter().flatten() {
+        certificate_store.store_issuer_cert(&X509::from_byte_string(certificate)?).map_err(to_status_code)?;
+    }
+    for crl in trust_list.trusted_crls.iter().flatten().chain(trust_list.issuer_crls.iter().flatten()) {
+        certificate_store.store_crl(crl.as_ref()).map_err(to_status_code)?;
+    }
+    Ok(())
+}
@@ -36,9 +36,14 @@ mod message_queue;
 mod config;
 mod client;
 mod session;
+mod async_session;
 mod callbacks;
 mod builder;
 mod session_retry;
+mod session_pool;
+mod custom_types;
+mod event_filter;
+mod gds;
 
 use opcua_types::{SupportedMessage, service_types::ResponseHeader, status_code::StatusCode};
 
@@ -46,7 +51,7 @@ use opcua_types::{SupportedMessage, service_types::ResponseHeader, status_code::
 /// contains a failure status code.
 pub(crate) fn process_service_result(response_header: &ResponseHeader) -> Result<(), StatusCode> {
     if response_header.service_result.is_bad() {
-        info!("Received a bad service result {} from the request", response_header.service_result);
+        info!("Received a bad service result {} from request {}", response_header.service_result, response_header.request_handle);
         Err(response_header.service_result)
     } else {
         Ok(())
@@ -56,11 +61,11 @@ pub(crate) fn process_service_result(response_header: &ResponseHeader) -> Result
 pub(crate) fn process_unexpected_response(response: SupportedMessage) -> StatusCode {
     match response {
         SupportedMessage::ServiceFault(service_fault) => {
-            error!("Received a service fault of {} for the request", service_fault.response_header.service_result);
+            error!("Received a service fault of {} for request {}", service_fault.response_header.service_result, service_fault.response_header.request_handle);
             service_fault.response_header.service_result
         }
         _ => {
-            error!("Received an unexpected response to the request");
+            error!("Received an unexpected response to request {}", response.request_handle());
             StatusCode::BadUnknownResponse
         }
     }
@@ -74,8 +79,13 @@ pub mod prelude {
         builder::*,
         config::*,
         session::*,
+        async_session::*,
+        session_pool::SessionPool,
         subscription::MonitoredItem,
         callbacks::*,
+        event_filter::EventFilterBuilder,
+        custom_types::{FieldValue, StructureValue, TypeDictionary},
+        gds::{ApplicationRecord, GdsClient, IssuedCertificate, install_certificate, install_trust_list},
     };
 }
 
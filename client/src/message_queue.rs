@@ -1,8 +1,14 @@
 use std::collections::{HashSet, HashMap};
 
-use futures::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
 
 use opcua_types::*;
+use opcua_types::status_code::StatusCode;
+
+use crate::callbacks::OnRequestFailure;
 
 pub(crate) struct MessageQueue {
     /// The requests that are in-flight, defined by their request handle and an async flag. Basically,
@@ -11,8 +17,13 @@ pub(crate) struct MessageQueue {
     inflight_requests: HashSet<(u32, bool)>,
     /// A map of incoming responses waiting to be processed
     responses: HashMap<u32, (SupportedMessage, bool)>,
+    /// Requests made through the async api are completed by sending their response down a channel
+    /// instead of being stored in `responses`
+    response_channels: HashMap<u32, oneshot::Sender<SupportedMessage>>,
     /// This is the queue that messages will be sent onto the transport for sending
     sender: Option<UnboundedSender<SupportedMessage>>,
+    /// Request failure callback
+    request_failure_callback: Option<Box<dyn OnRequestFailure + Send + Sync + 'static>>,
 }
 
 impl MessageQueue {
@@ -20,13 +31,25 @@ impl MessageQueue {
         MessageQueue {
             inflight_requests: HashSet::new(),
             responses: HashMap::new(),
+            response_channels: HashMap::new(),
             sender: None,
+            request_failure_callback: None,
         }
     }
 
+    pub(crate) fn set_request_failure_callback<CB>(&mut self, request_failure_callback: CB) where CB: OnRequestFailure + Send + Sync + 'static {
+        self.request_failure_callback = Some(Box::new(request_failure_callback));
+    }
+
     pub(crate) fn clear(&mut self) {
+        // Requests that are still in-flight will never get a response
+        let mut request_handles = self.inflight_requests.iter().map(|r| r.0).collect::<Vec<_>>();
+        request_handles.sort();
+        request_handles.iter().for_each(|request_handle| self.request_failed(*request_handle, StatusCode::BadConnectionClosed));
         self.inflight_requests.clear();
         self.responses.clear();
+        // Dropping the senders cancels anything waiting on them
+        self.response_channels.clear();
     }
 
     // Creates the transmission queue that outgoing requests will be sent over
@@ -48,12 +71,29 @@ impl MessageQueue {
         let _ = self.sender.as_ref().unwrap().unbounded_send(request);
     }
 
+    /// Called by the session to add a request to be sent. The response will be sent to the
+    /// supplied channel rather than stored.
+    pub(crate) fn add_request_with_response_channel(&mut self, request: SupportedMessage, response_channel: oneshot::Sender<SupportedMessage>) {
+        let request_handle = request.request_handle();
+        self.response_channels.insert(request_handle, response_channel);
+        self.add_request(request, false);
+    }
+
     /// Called when a session's request times out. This call allows the session state to remove
     /// the request as pending and ignore any response that arrives for it.
     pub(crate) fn request_has_timed_out(&mut self, request_handle: u32) {
         info!("Request {} has timed out and any response will be ignored", request_handle);
         let _ = self.inflight_requests.remove(&(request_handle, false));
         let _ = self.inflight_requests.remove(&(request_handle, true));
+        let _ = self.response_channels.remove(&request_handle);
+        self.request_failed(request_handle, StatusCode::BadRequestTimeout);
+    }
+
+    /// Tells the request failure callback, if there is one, that the request has failed
+    fn request_failed(&mut self, request_handle: u32, status_code: StatusCode) {
+        if let Some(ref mut request_failure_callback) = self.request_failure_callback {
+            request_failure_callback.request_failure(request_handle, status_code);
+        }
     }
 
     /// Called by the connection to store a response for the consumption of the session.
@@ -61,14 +101,27 @@ impl MessageQueue {
         // Remove corresponding request handle from inflight queue, add to responses
         let request_handle = response.request_handle();
         debug!("Response to Request {} has been stored", request_handle);
+        let service_fault = if let SupportedMessage::ServiceFault(ref service_fault) = response {
+            Some(service_fault.response_header.service_result)
+        } else {
+            None
+        };
         // Remove the inflight request
         // This true / false is slightly clunky.
-        if let Some(request) = self.inflight_requests.take(&(request_handle, true)) {
+        if let Some(response_channel) = self.response_channels.remove(&request_handle) {
+            let _ = self.inflight_requests.remove(&(request_handle, false));
+            // The receiver may have gone away, in which case the response is dropped
+            let _ = response_channel.send(response);
+        } else if let Some(request) = self.inflight_requests.take(&(request_handle, true)) {
             self.responses.insert(request_handle, (response, request.1));
         } else if let Some(request) = self.inflight_requests.take(&(request_handle, false)) {
             self.responses.insert(request_handle, (response, request.1));
         } else {
             error!("A response with request handle {} doesn't belong to any request and will be ignored, inflight requests = {:?}", request_handle, self.inflight_requests);
+            return;
+        }
+        if let Some(service_result) = service_fault {
+            self.request_failed(request_handle, service_result);
         }
     }
 
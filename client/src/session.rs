@@ -7,7 +7,9 @@ use std::{
     cmp, thread,
     convert::TryFrom,
     result::Result,
-    collections::HashSet,
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    path::Path,
     str::FromStr,
     sync::{Arc, Mutex, RwLock, mpsc},
     time::{Instant, Duration},
@@ -23,24 +25,26 @@ use tokio_timer::Interval;
 
 use opcua_core::{
     comms::secure_channel::{Role, SecureChannel},
-    crypto::{self, CertificateStore, PrivateKey, SecurityPolicy, X509, user_identity::make_user_name_identity_token},
+    crypto::{self, CertificateStore, PrivateKey, SecurityPolicy, X509, user_identity::{make_user_name_identity_token, make_x509_identity_token, make_issued_identity_token}},
 };
 
 use opcua_types::{
     *,
-    node_ids::{ObjectId, MethodId},
+    node_ids::{ObjectId, MethodId, ReferenceTypeId, VariableId},
     service_types::*,
     status_code::StatusCode,
 };
 
 use crate::{
-    callbacks::{OnDataChange, OnConnectionStatusChange, OnSessionClosed},
+    async_session::AsyncSession,
+    callbacks::{OnDataChange, OnEvent, OnConnectionStatusChange, OnServerStateChange, OnSessionClosed, OnRequestFailure, SubscriptionCallbacks, DataChangeOnly},
     client,
     comms::tcp_transport::TcpTransport,
+    custom_types::{self, StructureValue, TypeDictionary},
     message_queue::MessageQueue,
     session_retry::{SessionRetryPolicy, Answer},
     session_state::{SessionState, ConnectionState},
-    subscription::{self, Subscription},
+    subscription::{self, MonitoredItem, Subscription},
     subscription_state::SubscriptionState,
     subscription_timer::{SubscriptionTimer, SubscriptionTimerCommand},
 };
@@ -79,6 +83,197 @@ impl Into<SessionInfo> for (EndpointDescription, client::IdentityToken) {
     }
 }
 
+/// The historical values of a node returned by one of the history read functions of the `Session`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryReadValues {
+    /// The status of the read of this node. If the status is bad, the values hold whatever was
+    /// read before the error occurred.
+    pub status_code: StatusCode,
+    /// The values read from the node
+    pub values: Vec<DataValue>,
+}
+
+/// The result of calling a method through `Session::call_method`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodCallResult {
+    /// The status of the method call
+    pub status_code: StatusCode,
+    /// The status of each input argument. Normally empty unless the status code is
+    /// `BadInvalidArgument`, in which case it indicates which arguments were rejected.
+    pub input_argument_results: Vec<StatusCode>,
+    /// The output arguments returned by the method
+    pub output_arguments: Vec<Variant>,
+}
+
+impl From<CallMethodResult> for MethodCallResult {
+    fn from(result: CallMethodResult) -> Self {
+        MethodCallResult {
+            status_code: result.status_code,
+            input_argument_results: result.input_argument_results.unwrap_or_default(),
+            output_arguments: result.output_arguments.unwrap_or_default(),
+        }
+    }
+}
+
+impl MethodCallResult {
+    /// Returns the output argument at `index` converted to the type `T`. The argument must hold
+    /// exactly that type, e.g. a `u32` can only be obtained from a `Variant::UInt32`.
+    ///
+    /// Returns `BadNoData` if there is no output argument at `index`, or `BadTypeMismatch` if the
+    /// argument is of a different type.
+    pub fn output<T>(&self, index: usize) -> Result<T, StatusCode> where T: for<'a> TryFrom<&'a Variant> {
+        let value = self.output_arguments.get(index).ok_or(StatusCode::BadNoData)?;
+        T::try_from(value).map_err(|_| StatusCode::BadTypeMismatch)
+    }
+}
+
+/// The limits that a server advertises on the number of operations in a single service call, read
+/// from the `OperationLimits` object of its `ServerCapabilities`. A limit of 0 means there is no limit.
+///
+/// The `Session` splits a read, write, browse or create monitored items call with more operations
+/// than the limit into several calls so the server does not reject it with `BadTooManyOperations`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct OperationLimits {
+    /// Maximum number of nodes in a read
+    pub max_nodes_per_read: u32,
+    /// Maximum number of nodes in a write
+    pub max_nodes_per_write: u32,
+    /// Maximum number of nodes in a browse
+    pub max_nodes_per_browse: u32,
+    /// Maximum number of monitored items in a create monitored items call
+    pub max_monitored_items_per_call: u32,
+}
+
+impl OperationLimits {
+    /// The variables holding the limits, in the order expected by `from_values()`
+    pub(crate) const VARIABLES: [VariableId; 4] = [
+        VariableId::Server_ServerCapabilities_OperationLimits_MaxNodesPerRead,
+        VariableId::Server_ServerCapabilities_OperationLimits_MaxNodesPerWrite,
+        VariableId::Server_ServerCapabilities_OperationLimits_MaxNodesPerBrowse,
+        VariableId::Server_ServerCapabilities_OperationLimits_MaxMonitoredItemsPerCall,
+    ];
+
+    /// Makes the limits from the values read from the `VARIABLES`. A limit whose value is missing
+    /// or is not a `UInt32`, e.g. because the server does not advertise it, is 0.
+    pub(crate) fn from_values(values: &[DataValue]) -> OperationLimits {
+        let limit = |index: usize| match values.get(index).and_then(|v| v.value.as_ref()) {
+            Some(Variant::UInt32(limit)) => *limit,
+            _ => 0
+        };
+        OperationLimits {
+            max_nodes_per_read: limit(0),
+            max_nodes_per_write: limit(1),
+            max_nodes_per_browse: limit(2),
+            max_monitored_items_per_call: limit(3),
+        }
+    }
+}
+
+/// Splits a number of operations into the start and end of each batch of operations that are sent
+/// in one call, where a batch holds at most `limit` operations, or all of them if the limit is 0.
+pub(crate) fn batches(operations: usize, limit: u32) -> Vec<(usize, usize)> {
+    let limit = limit as usize;
+    if limit == 0 || operations <= limit {
+        vec![(0, operations)]
+    } else {
+        (0..operations).step_by(limit).map(|start| (start, operations.min(start + limit))).collect()
+    }
+}
+
+/// The result of an operation in a call that is split into batches, which can stand in for the
+/// result of an operation that the server did not apply.
+pub(crate) trait OperationResult {
+    /// Makes the result of an operation that was not applied, holding the reason why
+    fn not_applied(status: StatusCode) -> Self;
+}
+
+impl OperationResult for DataValue {
+    fn not_applied(status: StatusCode) -> Self {
+        let mut value = DataValue::null();
+        value.status = Some(status.bits());
+        value
+    }
+}
+
+impl OperationResult for StatusCode {
+    fn not_applied(status: StatusCode) -> Self {
+        status
+    }
+}
+
+impl OperationResult for BrowseResult {
+    fn not_applied(status: StatusCode) -> Self {
+        BrowseResult {
+            status_code: status,
+            continuation_point: ByteString::null(),
+            references: None,
+        }
+    }
+}
+
+impl OperationResult for MonitoredItemCreateResult {
+    fn not_applied(status: StatusCode) -> Self {
+        MonitoredItemCreateResult {
+            status_code: status,
+            monitored_item_id: 0,
+            revised_sampling_interval: 0f64,
+            revised_queue_size: 0,
+            filter_result: ExtensionObject::null(),
+        }
+    }
+}
+
+/// Makes a call for each batch of the operations that is within the limit and returns the results
+/// of all the batches in the same order as the operations.
+///
+/// A failed call fails the whole thing if no earlier call succeeded, since nothing was applied. Once
+/// a call has succeeded, the results are returned with the operations of the failed call holding its
+/// status and those of the calls that were not made holding `BadRequestNotAllowed`, so the caller can
+/// tell which operations were applied. The operations of a call that returns a different number of
+/// results hold `BadUnexpectedError`. A call that returns no results, like a single call can, only
+/// returns `None` if none of the other calls returned results either.
+pub(crate) fn in_batches<T, R, F>(operations: &[T], limit: u32, mut f: F) -> Result<Option<Vec<R>>, StatusCode>
+    where R: OperationResult, F: FnMut(&[T]) -> Result<Option<Vec<R>>, StatusCode>
+{
+    let batches = batches(operations.len(), limit);
+    if batches.len() == 1 {
+        return f(operations);
+    }
+    debug!("Splitting {} operations into {} calls of at most {}", operations.len(), batches.len(), limit);
+    let not_applied = |results: &mut Vec<R>, count: usize, status: StatusCode| {
+        results.extend((0..count).map(|_| R::not_applied(status)));
+    };
+    let mut results = Vec::with_capacity(operations.len());
+    let mut has_results = false;
+    for (start, end) in batches {
+        match f(&operations[start..end]) {
+            Ok(Some(batch_results)) if batch_results.len() == end - start => {
+                has_results = true;
+                results.extend(batch_results);
+            }
+            Ok(Some(ref batch_results)) if !batch_results.is_empty() => {
+                error!("Server returned {} results for {} operations", batch_results.len(), end - start);
+                has_results = true;
+                not_applied(&mut results, end - start, StatusCode::BadUnexpectedError);
+            }
+            Ok(_) => {
+                debug!("Server returned no results for {} operations", end - start);
+                not_applied(&mut results, end - start, StatusCode::BadUnexpectedError);
+            }
+            Err(status) if start == 0 => {
+                return Err(status);
+            }
+            Err(status) => {
+                error!("Call for operations {} to {} failed, {} operations were not sent, status = {}", start, end, operations.len() - end, status);
+                not_applied(&mut results, end - start, status);
+                not_applied(&mut results, operations.len() - end, StatusCode::BadRequestNotAllowed);
+                return Ok(Some(results));
+            }
+        }
+    }
+    Ok(if has_results { Some(results) } else { None })
+}
+
 /// A `Session` runs in a loop, which can be terminated by sending it a `SessionCommand`.
 pub enum SessionCommand {
     /// Stop running as soon as possible
@@ -114,8 +309,24 @@ pub struct Session {
     message_queue: Arc<RwLock<MessageQueue>>,
     /// Connection status callback (TODO move to session state)
     connection_status_callback: Option<Box<dyn OnConnectionStatusChange + Send + Sync + 'static>>,
+    /// The connection status last reported to the connection status callback
+    connected: bool,
     /// Session retry policy
     session_retry_policy: SessionRetryPolicy,
+    /// Address to listen on for the server to make a reverse connection, if the server connects
+    /// to the client rather than the other way around
+    reverse_connect_address: Option<SocketAddr>,
+    /// Data type dictionaries read from the server, by the node id of their variable
+    type_dictionaries: HashMap<NodeId, Arc<TypeDictionary>>,
+    /// The dictionary and type name of custom structures, by their binary encoding id
+    custom_structure_types: HashMap<NodeId, (NodeId, String)>,
+    /// Number of subscription keep-alive periods without a publish response after which the
+    /// connection is treated as dead, or 0 to never do so
+    publish_keep_alive_limit: u32,
+    /// When the last publish response arrived, or when one was first expected
+    last_publish_response: Instant,
+    /// Operation limits of the server, read when they are first needed
+    operation_limits: Option<OperationLimits>,
 }
 
 impl Drop for Session {
@@ -162,7 +373,14 @@ impl Session {
             secure_channel,
             message_queue,
             connection_status_callback: None,
+            connected: false,
             session_retry_policy,
+            reverse_connect_address: None,
+            type_dictionaries: HashMap::new(),
+            custom_structure_types: HashMap::new(),
+            publish_keep_alive_limit: Self::DEFAULT_PUBLISH_KEEP_ALIVE_LIMIT,
+            last_publish_response: Instant::now(),
+            operation_limits: None,
         }
     }
 
@@ -194,6 +412,97 @@ impl Session {
         self.session_retry_policy = session_retry_policy;
     }
 
+    /// Sets the address to listen on for the server to connect to the client with reverse connect.
+    /// When set, connecting waits for the server to connect and send a ReverseHello instead of
+    /// connecting to the endpoint url, which is useful when the server is behind a firewall
+    /// that blocks incoming connections. Set to `None` to connect to the server as normal.
+    ///
+    /// # Arguments
+    ///
+    /// * `reverse_connect_address` - the address to listen on, e.g. `0.0.0.0:4844`
+    ///
+    pub fn set_reverse_connect_address(&mut self, reverse_connect_address: Option<SocketAddr>) {
+        self.reverse_connect_address = reverse_connect_address;
+    }
+
+    /// Sets the idle time before the operating system sends TCP keep-alive probes on the
+    /// connection. The probes detect a connection that was dropped without being closed, after
+    /// which the session reconnects. The value takes effect the next time the session connects.
+    ///
+    /// # Arguments
+    ///
+    /// * `tcp_keep_alive` - idle time in milliseconds, or 0 to disable keep-alive probes
+    ///
+    pub fn set_tcp_keep_alive(&mut self, tcp_keep_alive: u32) {
+        let mut session_state = trace_write_lock_unwrap!(self.session_state);
+        session_state.set_tcp_keep_alive(tcp_keep_alive);
+    }
+
+    /// Sets how long the session waits for the response to a request before the call fails with
+    /// `BadRequestTimeout`. The timeout is also sent to the server as the timeout hint of each
+    /// request so the server can abandon work on a request that the client has given up on.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_timeout` - timeout in milliseconds, 10 seconds by default
+    ///
+    pub fn set_request_timeout(&mut self, request_timeout: u32) {
+        let mut session_state = trace_write_lock_unwrap!(self.session_state);
+        session_state.set_request_timeout(request_timeout);
+    }
+
+    /// Calls the function with a different request timeout for the calls it makes on the session,
+    /// e.g. to give a slow history read longer than other calls, and restores the session's
+    /// request timeout afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_timeout` - timeout in milliseconds of the requests sent by the function
+    /// * `f` - function which calls the services of the session
+    ///
+    /// # Returns
+    ///
+    /// * The result of the function
+    ///
+    pub fn with_request_timeout<F, R>(&mut self, request_timeout: u32, f: F) -> R where F: FnOnce(&mut Session) -> R {
+        let session_request_timeout = {
+            let mut session_state = trace_write_lock_unwrap!(self.session_state);
+            let session_request_timeout = session_state.request_timeout();
+            session_state.set_request_timeout(request_timeout);
+            session_request_timeout
+        };
+        let result = f(self);
+        self.set_request_timeout(session_request_timeout);
+        result
+    }
+
+    /// Sets how many subscription keep-alive periods may pass without a publish response before
+    /// the connection is treated as dead and the session reconnects. A subscription's keep-alive
+    /// period is its publishing interval multiplied by its max keep-alive count, and the server
+    /// must respond at least that often while the subscription exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `publish_keep_alive_limit` - number of keep-alive periods, or 0 to disable the check
+    ///
+    pub fn set_publish_keep_alive_limit(&mut self, publish_keep_alive_limit: u32) {
+        self.publish_keep_alive_limit = publish_keep_alive_limit;
+    }
+
+    /// Sets the maximum number of publish requests that the session keeps outstanding with the
+    /// server. The server can only send a notification message or keep-alive when it holds a
+    /// publish request, so more outstanding requests let it publish for many subscriptions, or
+    /// over a slow network, without waiting for the client to send another.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_publish_requests` - maximum number of outstanding publish requests, at least 1
+    ///
+    pub fn set_max_publish_requests(&mut self, max_publish_requests: usize) {
+        let mut session_state = trace_write_lock_unwrap!(self.session_state);
+        session_state.set_max_publish_requests(max_publish_requests);
+    }
+
     /// Register a callback to be notified when the session has been closed.
     ///
     /// # Arguments
@@ -205,6 +514,34 @@ impl Session {
         session_state.set_session_closed_callback(session_closed_callback);
     }
 
+    /// Registers a callback to be notified of the state of the server, e.g. when it is shutting
+    /// down. The state is read by the session's keep-alive, which only runs when no request has
+    /// been sent for 3/4 of the session timeout, so the callback is not called while the session
+    /// is kept busy by other requests or subscriptions.
+    ///
+    /// # Arguments
+    ///
+    /// * `server_state_callback` - the server state callback
+    ///
+    pub fn set_server_state_callback<CB>(&mut self, server_state_callback: CB) where CB: OnServerStateChange + Send + Sync + 'static {
+        let mut session_state = trace_write_lock_unwrap!(self.session_state);
+        session_state.set_server_state_callback(server_state_callback);
+    }
+
+    /// Registers a callback to be notified of the requests which fail, identified by their request
+    /// handles. A request fails when the server answers it with a service fault, when it times out
+    /// or when the connection closes before it is answered. The request handle of an asynchronous
+    /// request is `SessionFuture::request_handle()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_failure_callback` - the request failure callback
+    ///
+    pub fn set_request_failure_callback<CB>(&mut self, request_failure_callback: CB) where CB: OnRequestFailure + Send + Sync + 'static {
+        let mut message_queue = trace_write_lock_unwrap!(self.message_queue);
+        message_queue.set_request_failure_callback(request_failure_callback);
+    }
+
     /// Registers a callback to be notified when the session connection status has changed.
     /// This will be called if connection status changes from connected to disconnected or vice versa.
     ///
@@ -246,6 +583,12 @@ impl Session {
                 subscription_state.cancel_subscription_timers();
             }
 
+            // Publish requests sent on the old connection will never be answered
+            {
+                let mut session_state = trace_write_lock_unwrap!(self.session_state);
+                session_state.clear_publish_requests();
+            }
+
             // Connect to server (again)
             self.connect_no_retry()?;
 
@@ -296,6 +639,10 @@ impl Session {
                     if r.status_code.is_good() {
                         // Subscription was transferred so it does not need to be recreated
                         subscription_ids_to_recreate.remove(&subscription_ids[i]);
+                        // Recover notifications sent while the connection was down
+                        if let Some(ref available_sequence_numbers) = r.available_sequence_numbers {
+                            self.recover_notification_messages(subscription_ids[i], available_sequence_numbers);
+                        }
                     }
                 });
             }
@@ -323,32 +670,62 @@ impl Session {
                         subscription.max_notifications_per_publish(),
                         subscription.priority(),
                         subscription.publishing_enabled(),
-                        subscription.data_change_callback()) {
+                        subscription.callbacks()) {
                         info!("New subscription created with id {}", subscription_id);
 
                         // For each monitored item
-                        let items_to_create = subscription.monitored_items().iter().map(|(_, item)| {
+                        let monitored_items = subscription.monitored_items().values().collect::<Vec<&MonitoredItem>>();
+                        let items_to_create = monitored_items.iter().map(|item| {
                             MonitoredItemCreateRequest {
                                 item_to_monitor: item.item_to_monitor().clone(),
                                 monitoring_mode: item.monitoring_mode(),
                                 requested_parameters: MonitoringParameters {
                                     client_handle: item.client_handle(),
                                     sampling_interval: item.sampling_interval(),
-                                    filter: ExtensionObject::null(),
+                                    filter: item.filter().clone(),
                                     queue_size: item.queue_size(),
-                                    discard_oldest: true,
+                                    discard_oldest: item.discard_oldest(),
                                 },
                             }
                         }).collect::<Vec<MonitoredItemCreateRequest>>();
-                        let _ = self.create_monitored_items(subscription_id, TimestampsToReturn::Both, &items_to_create);
+                        if items_to_create.is_empty() {
+                            return;
+                        }
+
+                        // The server assigns new ids to the monitored items so map the old ids onto the new ones
+                        let monitored_item_ids = match self.create_monitored_items(subscription_id, TimestampsToReturn::Both, &items_to_create) {
+                            Ok(results) => {
+                                monitored_items.iter().zip(results.iter())
+                                    .filter(|(_, r)| r.status_code.is_good())
+                                    .map(|(item, r)| (item.id(), r.monitored_item_id))
+                                    .collect::<HashMap<u32, u32>>()
+                            }
+                            Err(status_code) => {
+                                warn!("Could not recreate monitored items for subscription {}, error = {}", subscription_id, status_code);
+                                return;
+                            }
+                        };
+
+                        // Move the callbacks of event monitored items onto the new ids
+                        {
+                            let mut subscription_state = trace_write_lock_unwrap!(subscription_state);
+                            monitored_items.iter().for_each(|item| {
+                                if let (Some(monitored_item_id), Some(event_callback)) = (monitored_item_ids.get(&item.id()), subscription.event_callback(item.id())) {
+                                    subscription_state.set_event_callback(subscription_id, *monitored_item_id, event_callback);
+                                }
+                            });
+                        }
 
-                        // Recreate any triggers for the monitored item. This code assumes monitored item
-                        // ids are the same value as they were in the previous subscription.
-                        subscription.monitored_items().iter().for_each(|(_, item)| {
+                        // Recreate any triggers for the monitored items using the new ids
+                        monitored_items.iter().for_each(|item| {
                             let triggered_items = item.triggered_items();
-                            if !triggered_items.is_empty() {
-                                let links_to_add = triggered_items.iter().map(|i| *i).collect::<Vec<u32>>();
-                                let _ = self.set_triggering(subscription_id, item.id(), links_to_add.as_slice(), &[]);
+                            if let Some(triggering_item_id) = monitored_item_ids.get(&item.id()) {
+                                let links_to_add = triggered_items.iter()
+                                    .filter_map(|i| monitored_item_ids.get(i).map(|i| *i))
+                                    .collect::<Vec<u32>>();
+                                if !links_to_add.is_empty() {
+                                    let _ = self.set_triggering(subscription_id, *triggering_item_id, links_to_add.as_slice(), &[]);
+                                }
                             }
                         });
                     } else {
@@ -433,16 +810,45 @@ impl Session {
                 info!("Security policy = {:?}", security_policy);
                 info!("Security mode = {:?}", self.session_info.endpoint.security_mode);
             }
-            self.transport.connect(endpoint_url.as_ref())?;
-            self.open_secure_channel()?;
-
-            if let Some(ref mut connection_status) = self.connection_status_callback {
-                connection_status.connection_status_change(true);
+            if is_opc_ua_https_url(endpoint_url.as_ref()) {
+                // TLS secures the connection so there is no secure channel to open or renew
+                self.transport.connect_https(endpoint_url.as_ref())?;
+            } else {
+                if let Some(reverse_connect_address) = self.reverse_connect_address {
+                    let server_uri = self.session_info.endpoint.server.application_uri.clone();
+                    self.transport.connect_reverse(reverse_connect_address, endpoint_url.as_ref(), server_uri.as_ref())?;
+                } else {
+                    self.transport.connect(endpoint_url.as_ref())?;
+                }
+                self.open_secure_channel()?;
+                self.spawn_secure_channel_renewal_task();
             }
+            self.set_connection_status(true);
             Ok(())
         }
     }
 
+    /// Connects to the server without creating or activating a session so that one-shot reads,
+    /// writes and method calls can be made session-less. The access token, e.g. a JWT obtained
+    /// from an authorization service, is sent as the authentication token of each request and the
+    /// endpoint must accept issued tokens for the server to validate it.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - the access token identifying the caller
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - connection has happened
+    /// * `Err(StatusCode)` - reason for failure
+    ///
+    pub fn connect_sessionless(&mut self, access_token: ByteString) -> Result<(), StatusCode> {
+        self.connect()?;
+        let mut session_state = trace_write_lock_unwrap!(self.session_state);
+        session_state.set_authentication_token(NodeId::new(0, access_token));
+        Ok(())
+    }
+
     /// Disconnect from the server. Disconnect is an explicit command to drop the socket and throw
     /// away all state information. If you disconnect you cannot reconnect to your existing session
     /// or retrieve any existing subscriptions.
@@ -450,8 +856,17 @@ impl Session {
         let _ = self.delete_all_subscriptions();
         let _ = self.close_secure_channel();
         self.transport.wait_for_disconnect();
-        if let Some(ref mut connection_status) = self.connection_status_callback {
-            connection_status.connection_status_change(false);
+        self.set_connection_status(false);
+    }
+
+    /// Notifies the connection status callback if the connection status has changed since it was
+    /// last notified.
+    fn set_connection_status(&mut self, connected: bool) {
+        if self.connected != connected {
+            self.connected = connected;
+            if let Some(ref mut connection_status) = self.connection_status_callback {
+                connection_status.connection_status_change(connected);
+            }
         }
     }
 
@@ -469,6 +884,10 @@ impl Session {
     /// Internal constant for the sleep interval used during polling
     const POLL_SLEEP_INTERVAL: u64 = 50;
 
+    /// Default number of subscription keep-alive periods without a publish response before the
+    /// connection is treated as dead
+    const DEFAULT_PUBLISH_KEEP_ALIVE_LIMIT: u32 = 3;
+
     /// Synchronously runs a polling loop over the supplied session. The run command performs
     /// periodic actions such as receiving messages, processing subscriptions, and recovering from
     /// connection errors. The run command will break if the session is disconnected
@@ -553,9 +972,19 @@ impl Session {
     ///
     pub fn poll(&mut self, sleep_for: u64) -> Result<bool, ()> {
         let did_something = if self.is_connected() {
-            self.handle_publish_responses()
+            if self.publish_responses_stopped() {
+                // The link has probably dropped without the socket noticing, so drop the
+                // connection and let the next poll reconnect.
+                error!("No publish responses have been received for {} keep-alive periods, so the connection is assumed to be dead", self.publish_keep_alive_limit);
+                self.transport.abort(StatusCode::BadNoCommunication);
+                true
+            } else {
+                self.handle_publish_responses()
+            }
         } else {
             use chrono::Utc;
+            // Tell the application the connection has dropped before trying to reconnect
+            self.set_connection_status(false);
             match self.session_retry_policy.should_retry_connect(Utc::now()) {
                 Answer::GiveUp => {
                     error!("Session has given up trying to reconnect to the server after {} retries", self.session_retry_policy.retry_count());
@@ -567,6 +996,7 @@ impl Session {
                     if self.reconnect_and_activate().is_ok() {
                         info!("Retry to connect was successful");
                         self.session_retry_policy.reset_retry_count();
+                        self.last_publish_response = Instant::now();
                     } else {
                         self.session_retry_policy.increment_retry_count();
                         warn!("Reconnect was unsuccessful, retries = {}", self.session_retry_policy.retry_count());
@@ -628,6 +1058,47 @@ impl Session {
         }
     }
 
+    /// Sends a [`FindServersOnNetworkRequest`] to a discovery server to find the servers it has
+    /// seen on the network, e.g. through mDNS.
+    ///
+    /// See OPC UA Part 4 - Services 5.4.3 for complete description of the service and error responses.
+    ///
+    /// # Arguments
+    ///
+    /// * `starting_record_id` - Only records with an identifier greater than this are returned.
+    /// * `max_records_to_return` - The maximum number of records to return, or 0 for no limit.
+    /// * `server_capability_filter` - Only servers with all of these capabilities are returned, e.g. "DA".
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((DateTime, Vec<ServerOnNetwork>))` - The time the record ids were last reset and a list
+    ///   of [`ServerOnNetwork`] that meet criteria specified in the request.
+    /// * `Err(StatusCode)` - Request failed, status code is the reason for failure
+    ///
+    /// [`FindServersOnNetworkRequest`]: ./struct.FindServersOnNetworkRequest.html
+    /// [`ServerOnNetwork`]: ./struct.ServerOnNetwork.html
+    ///
+    pub fn find_servers_on_network(&mut self, starting_record_id: u32, max_records_to_return: u32, server_capability_filter: Option<Vec<UAString>>) -> Result<(DateTime, Vec<ServerOnNetwork>), StatusCode> {
+        let request = FindServersOnNetworkRequest {
+            request_header: self.make_request_header(),
+            starting_record_id,
+            max_records_to_return,
+            server_capability_filter,
+        };
+        let response = self.send_request(request)?;
+        if let SupportedMessage::FindServersOnNetworkResponse(response) = response {
+            crate::process_service_result(&response.response_header)?;
+            let servers = if let Some(servers) = response.servers {
+                servers
+            } else {
+                Vec::new()
+            };
+            Ok((response.last_counter_reset_time, servers))
+        } else {
+            Err(crate::process_unexpected_response(response))
+        }
+    }
+
     /// Obtain the list of endpoints supported by the server by sending it a [`GetEndpointsRequest`].
     ///
     /// See OPC UA Part 4 - Services 5.4.4 for complete description of the service and error responses.
@@ -697,6 +1168,43 @@ impl Session {
         }
     }
 
+    /// This function is used by servers that wish to register themselves with a discovery server
+    /// and supply discovery configuration, such as the server capabilities used for mDNS. The
+    /// server sends a [`RegisterServer2Request`] to the discovery server to register itself.
+    /// Discovery servers which do not support this service return `BadServiceUnsupported`, in which
+    /// case the caller should fall back to [`register_server`].
+    ///
+    /// See OPC UA Part 4 - Services 5.4.6 for complete description of the service and error responses.
+    ///
+    /// # Arguments
+    ///
+    /// * `server` - The server to register
+    /// * `discovery_configuration` - Additional discovery configuration, e.g. `MdnsDiscoveryConfiguration`
+    ///   encoded as an `ExtensionObject`
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<StatusCode>)` - Success, list of results for each discovery configuration
+    /// * `Err(StatusCode)` - Request failed, status code is the reason for failure
+    ///
+    /// [`RegisterServer2Request`]: ./struct.RegisterServer2Request.html
+    /// [`register_server`]: #method.register_server
+    ///
+    pub fn register_server2(&mut self, server: RegisteredServer, discovery_configuration: Option<Vec<ExtensionObject>>) -> Result<Vec<StatusCode>, StatusCode> {
+        let request = RegisterServer2Request {
+            request_header: self.make_request_header(),
+            server,
+            discovery_configuration,
+        };
+        let response = self.send_request(request)?;
+        if let SupportedMessage::RegisterServer2Response(response) = response {
+            crate::process_service_result(&response.response_header)?;
+            Ok(response.configuration_results.unwrap_or_default())
+        } else {
+            Err(crate::process_unexpected_response(response))
+        }
+    }
+
     ////////////////////////////////////////////////////////////////////////////////////////////////
     // SecureChannel Service set
     ////////////////////////////////////////////////////////////////////////////////////////////////
@@ -808,18 +1316,21 @@ impl Session {
                 session_state.session_id()
             };
 
+            // The session may be on a different server, or a restarted one, with different limits
+            self.operation_limits = None;
+
             // debug!("Server nonce is {:?}", response.server_nonce);
 
             // The server certificate is validated if the policy requires it
             let security_policy = self.security_policy();
             let cert_status_code = if security_policy != SecurityPolicy::None {
-                if let Ok(server_certificate) = crypto::X509::from_byte_string(&response.server_certificate) {
+                if let Ok(chain) = crypto::X509::chain_from_byte_string(&response.server_certificate) {
                     // Validate server certificate against hostname and application_uri
                     let hostname = hostname_from_url(self.session_info.endpoint.endpoint_url.as_ref()).map_err(|_| StatusCode::BadUnexpectedError)?;
                     let application_uri = self.session_info.endpoint.server.application_uri.as_ref();
 
                     let certificate_store = trace_write_lock_unwrap!(self.certificate_store);
-                    let result = certificate_store.validate_or_reject_application_instance_cert(&server_certificate, Some(&hostname), Some(application_uri));
+                    let result = certificate_store.validate_or_reject_application_instance_cert_chain(&chain[0], &chain[1..], Some(&hostname), Some(application_uri));
                     if result.is_bad() {
                         result
                     } else {
@@ -851,8 +1362,9 @@ impl Session {
         }
     }
 
-    /// Start a task that will periodically "ping" the server to keep the session alive. The ping rate
-    /// will be 3/4 the session timeout rate.
+    /// Start a task that will periodically "ping" the server to keep the session alive. The ping
+    /// reads the state of the server when no request has been sent for 3/4 of the session timeout,
+    /// so a session whose subscriptions are publishing is not pinged.
     ///
     /// NOTE: This code assumes that the session_timeout period never changes, e.g. if you
     /// connected to a server, negotiate a timeout period and then for whatever reason need to
@@ -875,10 +1387,9 @@ impl Session {
         let session_activity = cmp::max((session_timeout as u64 * 3) / 4, MIN_SESSION_ACTIVITY_MS);
         debug!("session timeout is {}, activity timer is {}", session_timeout, session_activity);
 
-        let last_timeout = Arc::new(Mutex::new(Instant::now()));
-
         // The timer runs at a higher frequency take_while() to terminate as soon after the session
-        // state has terminated. Each time it runs it will test if the interval has elapsed or not.
+        // state has terminated. Each time it runs it will test if the session has been idle for
+        // the activity interval or not.
 
         let session_activity_interval = Duration::from_millis(session_activity);
         let task = Interval::new(Instant::now(), Duration::from_millis(MIN_SESSION_ACTIVITY_MS))
@@ -891,36 +1402,22 @@ impl Session {
                 future::ok(!terminated)
             })
             .for_each(move |_| {
-                // Get the time now
-                let now = Instant::now();
-                let mut last_timeout = last_timeout.lock().unwrap();
-
-                // Calculate to interval since last check
-                let interval = now - *last_timeout;
-                if interval > session_activity_interval {
-                    let connection_state = {
-                        let connection_state = trace_read_lock_unwrap!(connection_state_for_each);
-                        *connection_state
-                    };
-                    match connection_state {
-                        ConnectionState::Processing => {
+                let connection_state = {
+                    let connection_state = trace_read_lock_unwrap!(connection_state_for_each);
+                    *connection_state
+                };
+                match connection_state {
+                    ConnectionState::Processing => {
+                        let mut session_state = trace_write_lock_unwrap!(session_state);
+                        if session_state.idle_time() > session_activity_interval {
                             info!("Session activity keep-alive request");
-                            let mut session_state = trace_write_lock_unwrap!(session_state);
-                            let request_header = session_state.make_request_header();
-                            let request = ReadRequest {
-                                request_header,
-                                max_age: 1f64,
-                                timestamps_to_return: TimestampsToReturn::Server,
-                                nodes_to_read: Some(vec![]),
-                            };
-                            let _ = session_state.async_send_request(request, true);
+                            let _ = session_state.async_keep_alive();
                         }
-                        connection_state => {
-                            info!("Session activity keep-alive is doing nothing - connection state = {:?}", connection_state);
-                        }
-                    };
-                    *last_timeout = now;
-                }
+                    }
+                    connection_state => {
+                        debug!("Session activity keep-alive is doing nothing - connection state = {:?}", connection_state);
+                    }
+                };
                 Ok(())
             })
             .map(|_| {
@@ -935,6 +1432,66 @@ impl Session {
         });
     }
 
+    /// Start a task that renews the secure channel's security token when 75% of its revised
+    /// lifetime has elapsed, so the token does not expire on a connection which is quiet or only
+    /// waiting on publish responses. A token is also renewed if necessary before a request is sent.
+    fn spawn_secure_channel_renewal_task(&mut self) {
+        debug!("spawn_secure_channel_renewal_task");
+
+        let connection_state = {
+            let session_state = trace_read_lock_unwrap!(self.session_state);
+            session_state.connection_state()
+        };
+
+        let session_state = self.session_state.clone();
+        let secure_channel = self.secure_channel.clone();
+        let connection_state_take_while = connection_state.clone();
+        let connection_state_for_each = connection_state.clone();
+
+        // Interval between checks on the age of the token
+        const SECURE_CHANNEL_RENEWAL_POLL_MS: u64 = 1000;
+
+        let task = Interval::new(Instant::now(), Duration::from_millis(SECURE_CHANNEL_RENEWAL_POLL_MS))
+            .take_while(move |_| {
+                let connection_state = trace_read_lock_unwrap!(connection_state_take_while);
+                let terminated = match *connection_state {
+                    ConnectionState::Finished(_) => true,
+                    _ => false
+                };
+                future::ok(!terminated)
+            })
+            .for_each(move |_| {
+                let connection_state = {
+                    let connection_state = trace_read_lock_unwrap!(connection_state_for_each);
+                    *connection_state
+                };
+                if connection_state == ConnectionState::Processing {
+                    let should_renew_security_token = {
+                        let secure_channel = trace_read_lock_unwrap!(secure_channel);
+                        secure_channel.should_renew_security_token()
+                    };
+                    if should_renew_security_token {
+                        info!("Secure channel token is due for renewal");
+                        let mut session_state = trace_write_lock_unwrap!(session_state);
+                        if let Err(err) = session_state.ensure_secure_channel_token() {
+                            error!("Secure channel token could not be renewed, err = {}", err);
+                        }
+                    }
+                }
+                Ok(())
+            })
+            .map(|_| {
+                info!("Secure channel renewal task is finished");
+            })
+            .map_err(|err| {
+                error!("Secure channel renewal task error = {:?}", err);
+            });
+
+        let _ = thread::spawn(move || {
+            tokio::run(task);
+        });
+    }
+
     /// Sends an [`ActivateSessionRequest`] to the server to activate this session
     ///
     /// See OPC UA Part 4 - Services 5.6.3 for complete description of the service and error responses.
@@ -947,7 +1504,7 @@ impl Session {
     /// [`ActivateSessionRequest`]: ./struct.ActivateSessionRequest.html
     ///
     pub fn activate_session(&mut self) -> Result<(), StatusCode> {
-        let user_identity_token = self.user_identity_token()?;
+        let (user_identity_token, user_token_signature) = self.user_identity_token()?;
         let locale_ids = if self.session_info.preferred_locales.is_empty() {
             None
         } else {
@@ -981,7 +1538,6 @@ impl Session {
         };
 
         let client_software_certificates = None;
-        let user_token_signature = SignatureData::null();
 
         let request = ActivateSessionRequest {
             request_header: self.make_request_header(),
@@ -998,13 +1554,16 @@ impl Session {
         if let SupportedMessage::ActivateSessionResponse(response) = response {
             // trace!("ActivateSessionResponse = {:#?}", response);
             crate::process_service_result(&response.response_header)?;
+            // The limits are read again in case the server changed them, e.g. on reconnecting
+            self.operation_limits = None;
             Ok(())
         } else {
             Err(crate::process_unexpected_response(response))
         }
     }
 
-    /// Cancels an outstanding service request by sending a [`CancelRequest`] to the server.
+    /// Cancels an outstanding service request by sending a [`CancelRequest`] to the server. The
+    /// server answers each cancelled request with `BadRequestCancelledByClient`.
     ///
     /// See OPC UA Part 4 - Services 5.6.5 for complete description of the service and error responses.
     ///
@@ -1179,6 +1738,8 @@ impl Session {
     ////////////////////////////////////////////////////////////////////////////////////////////////
 
     /// Discover the references to the specified nodes by sending a [`BrowseRequest`] to the server.
+    /// If there are more nodes than the server's `MaxNodesPerBrowse` operation limit, they are browsed
+    /// in several requests.
     ///
     /// See OPC UA Part 4 - Services 5.8.2 for complete description of the service and error responses.
     ///
@@ -1201,25 +1762,31 @@ impl Session {
             error!("browse, was not supplied with any nodes to browse");
             Err(StatusCode::BadNothingToDo)
         } else {
-            let request = BrowseRequest {
-                request_header: self.make_request_header(),
-                view: ViewDescription {
-                    view_id: NodeId::null(),
-                    timestamp: DateTime::now(),
-                    view_version: 0,
-                },
-                requested_max_references_per_node: 1000,
-                nodes_to_browse: Some(nodes_to_browse.to_vec()),
-            };
-            let response = self.send_request(request)?;
-            if let SupportedMessage::BrowseResponse(response) = response {
-                debug!("browse, success");
-                crate::process_service_result(&response.response_header)?;
-                Ok(response.results)
-            } else {
-                error!("browse failed {:?}", response);
-                Err(crate::process_unexpected_response(response))
-            }
+            let limit = self.operation_limits().max_nodes_per_browse;
+            in_batches(nodes_to_browse, limit, |nodes_to_browse| self.browse_batch(nodes_to_browse))
+        }
+    }
+
+    /// Browses a batch of nodes that is within the server's operation limits
+    fn browse_batch(&mut self, nodes_to_browse: &[BrowseDescription]) -> Result<Option<Vec<BrowseResult>>, StatusCode> {
+        let request = BrowseRequest {
+            request_header: self.make_request_header(),
+            view: ViewDescription {
+                view_id: NodeId::null(),
+                timestamp: DateTime::now(),
+                view_version: 0,
+            },
+            requested_max_references_per_node: 1000,
+            nodes_to_browse: Some(nodes_to_browse.to_vec()),
+        };
+        let response = self.send_request(request)?;
+        if let SupportedMessage::BrowseResponse(response) = response {
+            debug!("browse, success");
+            crate::process_service_result(&response.response_header)?;
+            Ok(response.results)
+        } else {
+            error!("browse failed {:?}", response);
+            Err(crate::process_unexpected_response(response))
         }
     }
 
@@ -1265,8 +1832,106 @@ impl Session {
         }
     }
 
-    /// Register nodes on the server by sending a [`RegisterNodesRequest`]. The purpose of this
-    /// call is server-dependent but allows a client to ask a server to create nodes which are
+    /// Translates browse paths to node ids by sending a [`TranslateBrowsePathsToNodeIdsRequest`] to
+    /// the server.
+    ///
+    /// See OPC UA Part 4 - Services 5.8.4 for complete description of the service and error responses.
+    ///
+    /// # Arguments
+    ///
+    /// * `browse_paths` - A list of [`BrowsePath`] each made of a starting node and a relative path
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<BrowsePathResult>)` - A list of [`BrowsePathResult`] corresponding to each browse path
+    /// * `Err(StatusCode)` - Request failed, status code is the reason for failure
+    ///
+    /// [`TranslateBrowsePathsToNodeIdsRequest`]: ./struct.TranslateBrowsePathsToNodeIdsRequest.html
+    /// [`BrowsePath`]: ./struct.BrowsePath.html
+    /// [`BrowsePathResult`]: ./struct.BrowsePathResult.html
+    ///
+    pub fn translate_browse_paths(&mut self, browse_paths: &[BrowsePath]) -> Result<Vec<BrowsePathResult>, StatusCode> {
+        if browse_paths.is_empty() {
+            error!("translate_browse_paths, was not supplied with any browse paths");
+            Err(StatusCode::BadNothingToDo)
+        } else {
+            let request = TranslateBrowsePathsToNodeIdsRequest {
+                request_header: self.make_request_header(),
+                browse_paths: Some(browse_paths.to_vec()),
+            };
+            let response = self.send_request(request)?;
+            if let SupportedMessage::TranslateBrowsePathsToNodeIdsResponse(response) = response {
+                debug!("translate_browse_paths, success");
+                crate::process_service_result(&response.response_header)?;
+                Ok(response.results.unwrap_or_default())
+            } else {
+                error!("translate_browse_paths failed {:?}", response);
+                Err(crate::process_unexpected_response(response))
+            }
+        }
+    }
+
+    /// Resolves a path of browse names, starting from the Root folder, to the node id of its
+    /// target, e.g. `"Objects/2:Device/2:Temperature"`. See `resolve_path_from()` for the path syntax.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(NodeId)` - The node id of the first target of the path
+    /// * `Err(StatusCode)` - The path is invalid, could not be resolved or the request failed
+    ///
+    pub fn resolve_path(&mut self, path: &str) -> Result<NodeId, StatusCode> {
+        self.resolve_path_from(ObjectId::RootFolder, path)
+    }
+
+    /// Resolves a path of browse names, starting from the supplied node, to the node id of its
+    /// target.
+    ///
+    /// The path is a relative path in the string format of OPC UA Part 4, Appendix A, where each
+    /// element is a browse name with an optional namespace index prefix. If the path does not begin
+    /// with a reference type, the first element follows hierarchical references, e.g. `"2:Device/2:Temperature"`
+    /// is the same as `"/2:Device/2:Temperature"`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(NodeId)` - The node id of the first target of the path
+    /// * `Err(StatusCode)` - The path is invalid, could not be resolved or the request failed
+    ///
+    pub fn resolve_path_from<T>(&mut self, starting_node: T, path: &str) -> Result<NodeId, StatusCode> where T: Into<NodeId> {
+        let browse_path = Self::make_browse_path(starting_node.into(), path)?;
+        let results = self.translate_browse_paths(&[browse_path])?;
+        let result = results.into_iter().next().ok_or(StatusCode::BadUnexpectedError)?;
+        if result.status_code.is_bad() {
+            debug!("resolve_path_from, path {} could not be resolved, status {}", path, result.status_code);
+            Err(result.status_code)
+        } else {
+            // Only a fully resolved target identifies a node on this server
+            result.targets.unwrap_or_default().into_iter()
+                .find(|target| target.remaining_path_index == u32::MAX && target.target_id.server_index == 0)
+                .map(|target| target.target_id.node_id)
+                .ok_or(StatusCode::BadNoMatch)
+        }
+    }
+
+    /// Makes a browse path from a starting node and a relative path string
+    pub(crate) fn make_browse_path(starting_node: NodeId, path: &str) -> Result<BrowsePath, StatusCode> {
+        let path = if path.starts_with(|c: char| c == '/' || c == '.' || c == '<') {
+            path.to_string()
+        } else {
+            format!("/{}", path)
+        };
+        let relative_path = RelativePath::from_str(&path, &RelativePathElement::default_node_resolver)
+            .map_err(|_| {
+                error!("Path {} is not a valid relative path", path);
+                StatusCode::BadBrowseNameInvalid
+            })?;
+        Ok(BrowsePath {
+            starting_node,
+            relative_path,
+        })
+    }
+
+    /// Register nodes on the server by sending a [`RegisterNodesRequest`]. The purpose of this
+    /// call is server-dependent but allows a client to ask a server to create nodes which are
     /// otherwise expensive to set up or maintain, e.g. nodes attached to hardware.
     ///
     /// See OPC UA Part 4 - Services 5.8.5 for complete description of the service and error responses.
@@ -1343,11 +2008,45 @@ impl Session {
         }
     }
 
+    /// Returns the limits that the server advertises on the number of operations in a single call.
+    /// The limits are read from the server the first time they are needed and kept until the session
+    /// is activated again. A server which does not advertise its limits, or whose limits cannot be
+    /// read, has no limits.
+    ///
+    /// # Returns
+    ///
+    /// * `OperationLimits` - The operation limits of the server
+    ///
+    pub fn operation_limits(&mut self) -> OperationLimits {
+        if let Some(operation_limits) = self.operation_limits {
+            return operation_limits;
+        }
+        let nodes_to_read = OperationLimits::VARIABLES.iter()
+            .map(|v| ReadValueId::from(NodeId::from(v)))
+            .collect::<Vec<ReadValueId>>();
+        match self.read_batch(&nodes_to_read) {
+            Ok(values) => {
+                let operation_limits = OperationLimits::from_values(&values.unwrap_or_default());
+                debug!("Server operation limits are {:?}", operation_limits);
+                self.operation_limits = Some(operation_limits);
+                operation_limits
+            }
+            Err(err) => {
+                // Kept so every call does not wait on a read that fails
+                warn!("Cannot read the server's operation limits, error = {}", err);
+                let operation_limits = OperationLimits::default();
+                self.operation_limits = Some(operation_limits);
+                operation_limits
+            }
+        }
+    }
+
     ////////////////////////////////////////////////////////////////////////////////////////////////
     // Attribute Service set
     ////////////////////////////////////////////////////////////////////////////////////////////////
 
-    /// Reads the value of nodes by sending a [`ReadRequest`] to the server.
+    /// Reads the value of nodes by sending a [`ReadRequest`] to the server. If there are more nodes
+    /// than the server's `MaxNodesPerRead` operation limit, they are read in several requests.
     ///
     /// See OPC UA Part 4 - Services 5.10.2 for complete description of the service and error responses.
     ///
@@ -1370,26 +2069,155 @@ impl Session {
             error!("read_nodes, was not supplied with any nodes to read");
             Err(StatusCode::BadNothingToDo)
         } else {
-            debug!("read_nodes requested to read nodes {:?}", nodes_to_read);
-            let request = ReadRequest {
-                request_header: self.make_request_header(),
-                max_age: 1f64,
-                timestamps_to_return: TimestampsToReturn::Server,
-                nodes_to_read: Some(nodes_to_read.to_vec()),
-            };
-            let response = self.send_request(request)?;
-            if let SupportedMessage::ReadResponse(response) = response {
-                debug!("read_nodes, success");
-                crate::process_service_result(&response.response_header)?;
-                Ok(response.results)
-            } else {
-                error!("write_value failed {:?}", response);
-                Err(crate::process_unexpected_response(response))
+            let limit = self.operation_limits().max_nodes_per_read;
+            in_batches(nodes_to_read, limit, |nodes_to_read| self.read_batch(nodes_to_read))
+        }
+    }
+
+    /// Reads a batch of nodes that is within the server's operation limits
+    fn read_batch(&mut self, nodes_to_read: &[ReadValueId]) -> Result<Option<Vec<DataValue>>, StatusCode> {
+        debug!("read_nodes requested to read nodes {:?}", nodes_to_read);
+        let request = ReadRequest {
+            request_header: self.make_request_header(),
+            max_age: 1f64,
+            timestamps_to_return: TimestampsToReturn::Server,
+            nodes_to_read: Some(nodes_to_read.to_vec()),
+        };
+        let response = self.send_request(request)?;
+        if let SupportedMessage::ReadResponse(response) = response {
+            debug!("read_nodes, success");
+            crate::process_service_result(&response.response_header)?;
+            Ok(response.results)
+        } else {
+            error!("write_value failed {:?}", response);
+            Err(crate::process_unexpected_response(response))
+        }
+    }
+
+    /// Decodes an extension object holding a structure whose type is unknown to the client, e.g. a
+    /// vendor specific structure, into a [`StructureValue`] of named fields.
+    ///
+    /// The type is found from the binary encoding id of the extension object, which has a `HasDescription`
+    /// reference to the `DataTypeDescription` variable of the type in a data type dictionary. The dictionary
+    /// is read from the server the first time one of its types is decoded and cached for the session.
+    ///
+    /// # Arguments
+    ///
+    /// * `extension_object` - An extension object with a binary encoded body
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(StructureValue)` - The fields of the structure
+    /// * `Err(StatusCode)` - The type could not be found on the server or the body could not be decoded
+    ///
+    /// [`StructureValue`]: ./struct.StructureValue.html
+    ///
+    pub fn decode_custom_structure(&mut self, extension_object: &ExtensionObject) -> Result<StructureValue, StatusCode> {
+        let (type_dictionary, type_name) = self.custom_structure_type(&extension_object.node_id)?;
+        let body = custom_types::extension_object_body(extension_object)?;
+        let decoding_limits = {
+            let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
+            secure_channel.decoding_limits()
+        };
+        type_dictionary.decode(&type_name, body, &decoding_limits)
+    }
+
+    /// Reads the binary schema held in the value of a data type dictionary variable and parses it
+    /// into a [`TypeDictionary`]. Dictionaries are cached for the session.
+    ///
+    /// # Arguments
+    ///
+    /// * `dictionary_id` - The node id of a variable of type `DataTypeDictionaryType`
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Arc<TypeDictionary>)` - The types of the dictionary
+    /// * `Err(StatusCode)` - The dictionary could not be read or is invalid
+    ///
+    /// [`TypeDictionary`]: ./struct.TypeDictionary.html
+    ///
+    pub fn read_type_dictionary(&mut self, dictionary_id: &NodeId) -> Result<Arc<TypeDictionary>, StatusCode> {
+        if let Some(type_dictionary) = self.type_dictionaries.get(dictionary_id) {
+            return Ok(type_dictionary.clone());
+        }
+        let type_dictionary = match self.read_variable_value(dictionary_id)? {
+            Variant::ByteString(ByteString { value: Some(xml) }) => Arc::new(TypeDictionary::parse(&xml)?),
+            value => {
+                error!("Data type dictionary {:?} has a value that is not a binary schema {:?}", dictionary_id, value);
+                return Err(StatusCode::BadTypeMismatch);
             }
+        };
+        self.type_dictionaries.insert(dictionary_id.clone(), type_dictionary.clone());
+        Ok(type_dictionary)
+    }
+
+    /// Finds the data type dictionary and the name of the type with the binary encoding id
+    fn custom_structure_type(&mut self, encoding_id: &NodeId) -> Result<(Arc<TypeDictionary>, String), StatusCode> {
+        let (dictionary_id, type_name) = if let Some(custom_structure_type) = self.custom_structure_types.get(encoding_id) {
+            custom_structure_type.clone()
+        } else {
+            let description_id = self.browse_single_target(encoding_id, BrowseDirection::Forward, ReferenceTypeId::HasDescription)?;
+            let type_name = match self.read_variable_value(&description_id)? {
+                Variant::String(ref type_name) if !type_name.is_null() => type_name.as_ref().to_string(),
+                value => {
+                    error!("Data type description {:?} has a value that is not a type name {:?}", description_id, value);
+                    return Err(StatusCode::BadTypeMismatch);
+                }
+            };
+            let dictionary_id = self.browse_single_target(&description_id, BrowseDirection::Inverse, ReferenceTypeId::HasComponent)?;
+            self.custom_structure_types.insert(encoding_id.clone(), (dictionary_id.clone(), type_name.clone()));
+            (dictionary_id, type_name)
+        };
+        let type_dictionary = self.read_type_dictionary(&dictionary_id)?;
+        if type_dictionary.contains_structure(&type_name) {
+            Ok((type_dictionary, type_name))
+        } else {
+            error!("Data type dictionary {:?} has no structured type {}", dictionary_id, type_name);
+            Err(StatusCode::BadDataTypeIdUnknown)
+        }
+    }
+
+    /// Browses the node for the target of the first reference of the reference type
+    fn browse_single_target(&mut self, node_id: &NodeId, browse_direction: BrowseDirection, reference_type_id: ReferenceTypeId) -> Result<NodeId, StatusCode> {
+        let browse_description = BrowseDescription {
+            node_id: node_id.clone(),
+            browse_direction,
+            reference_type_id: reference_type_id.into(),
+            include_subtypes: false,
+            node_class_mask: 0,
+            result_mask: 0,
+        };
+        let result = self.browse(&[browse_description])?
+            .and_then(|results| results.into_iter().next())
+            .ok_or(StatusCode::BadUnexpectedError)?;
+        if result.status_code.is_bad() {
+            Err(result.status_code)
+        } else {
+            result.references.unwrap_or_default().into_iter()
+                .find(|reference| reference.node_id.server_index == 0)
+                .map(|reference| reference.node_id.node_id)
+                .ok_or_else(|| {
+                    error!("Node {:?} has no {:?} reference", node_id, reference_type_id);
+                    StatusCode::BadNodeIdUnknown
+                })
+        }
+    }
+
+    /// Reads the value attribute of a variable
+    fn read_variable_value(&mut self, node_id: &NodeId) -> Result<Variant, StatusCode> {
+        let data_value = self.read(&[ReadValueId::from(node_id)])?
+            .and_then(|results| results.into_iter().next())
+            .ok_or(StatusCode::BadUnexpectedError)?;
+        let status_code = StatusCode::from_bits_truncate(data_value.status());
+        if status_code.is_bad() {
+            Err(status_code)
+        } else {
+            Ok(data_value.value.unwrap_or(Variant::Empty))
         }
     }
 
-    /// Writes values to nodes by sending a [`WriteRequest`] to the server.
+    /// Writes values to nodes by sending a [`WriteRequest`] to the server. If there are more nodes
+    /// than the server's `MaxNodesPerWrite` operation limit, they are written in several requests.
     ///
     /// See OPC UA Part 4 - Services 5.10.4 for complete description of the service and error responses.
     ///
@@ -1411,22 +2239,411 @@ impl Session {
             error!("write_value() was not supplied with any nodes to write");
             Err(StatusCode::BadNothingToDo)
         } else {
-            let request = WriteRequest {
+            let limit = self.operation_limits().max_nodes_per_write;
+            in_batches(nodes_to_write, limit, |nodes_to_write| self.write_batch(nodes_to_write))
+        }
+    }
+
+    /// Writes a batch of nodes that is within the server's operation limits
+    fn write_batch(&mut self, nodes_to_write: &[WriteValue]) -> Result<Option<Vec<StatusCode>>, StatusCode> {
+        let request = WriteRequest {
+            request_header: self.make_request_header(),
+            nodes_to_write: Some(nodes_to_write.to_vec()),
+        };
+        let response = self.send_request(request)?;
+        if let SupportedMessage::WriteResponse(response) = response {
+            debug!("write_value, success");
+            crate::process_service_result(&response.response_header)?;
+            Ok(response.results)
+        } else {
+            error!("write_value failed {:?}", response);
+            Err(crate::process_unexpected_response(response))
+        }
+    }
+
+    /// Reads a single attribute of a node and converts its value to the type `T`. The value must hold
+    /// exactly that type, e.g. a `LocalizedText` for the `DisplayName` attribute or a `u8` for the
+    /// `AccessLevel` attribute.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The node to read
+    /// * `attribute_id` - The attribute of the node to read
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(T)` - The value of the attribute
+    /// * `Err(StatusCode)` - The status of the attribute if it could not be read, `BadTypeMismatch` if
+    ///   the value is not of type `T`, or the reason the request failed
+    ///
+    pub fn read_attribute<N, T>(&mut self, node_id: N, attribute_id: AttributeId) -> Result<T, StatusCode>
+        where N: Into<NodeId>, T: for<'a> TryFrom<&'a Variant>
+    {
+        let value = self.read_attribute_variant(node_id.into(), attribute_id)?;
+        T::try_from(&value).map_err(|_| {
+            error!("read_attribute, attribute {:?} has an unexpected value {:?}", attribute_id, value);
+            StatusCode::BadTypeMismatch
+        })
+    }
+
+    /// Reads a single attribute of a node, returning its value or its status if it could not be read
+    fn read_attribute_variant(&mut self, node_id: NodeId, attribute_id: AttributeId) -> Result<Variant, StatusCode> {
+        let node_to_read = ReadValueId {
+            node_id,
+            attribute_id: attribute_id as u32,
+            index_range: UAString::null(),
+            data_encoding: QualifiedName::null(),
+        };
+        let data_value = self.read(&[node_to_read])?
+            .and_then(|results| results.into_iter().next())
+            .ok_or(StatusCode::BadUnexpectedError)?;
+        let status_code = StatusCode::from_bits_truncate(data_value.status());
+        if status_code.is_bad() {
+            debug!("read_attribute, attribute {:?} could not be read, status {}", attribute_id, status_code);
+            Err(status_code)
+        } else {
+            Ok(data_value.value.unwrap_or(Variant::Empty))
+        }
+    }
+
+    /// Writes a single attribute of a node. The value must be of the data type of the attribute.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The node to write
+    /// * `attribute_id` - The attribute of the node to write
+    /// * `value` - The new value of the attribute
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - The attribute was written
+    /// * `Err(StatusCode)` - The status of the write if it failed, or the reason the request failed
+    ///
+    pub fn write_attribute<N, V>(&mut self, node_id: N, attribute_id: AttributeId, value: V) -> Result<(), StatusCode>
+        where N: Into<NodeId>, V: Into<Variant>
+    {
+        let node_to_write = WriteValue {
+            node_id: node_id.into(),
+            attribute_id: attribute_id as u32,
+            index_range: UAString::null(),
+            value: DataValue::value_only(value.into()),
+        };
+        let status_code = self.write(&[node_to_write])?
+            .and_then(|results| results.into_iter().next())
+            .ok_or(StatusCode::BadUnexpectedError)?;
+        if status_code.is_bad() {
+            debug!("write_attribute, attribute {:?} could not be written, status {}", attribute_id, status_code);
+            Err(status_code)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reads the value of a variable and converts it to the type `T`. Unlike `read_attribute()`, numbers
+    /// are converted between numeric types as long as the value fits, e.g. a `Float` or an `Int32`
+    /// value is read as an `f64`, or an `Int64` value as a `u8` if it is between 0 and 255. See
+    /// [`FromVariant`] for the conversions.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The variable to read
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(T)` - The value of the variable
+    /// * `Err(StatusCode)` - The status of the value if it could not be read, `BadTypeMismatch` if
+    ///   the value cannot be converted to `T`, `BadOutOfRange` if it is a number that does not fit in `T`,
+    ///   or the reason the request failed
+    ///
+    /// [`FromVariant`]: ./trait.FromVariant.html
+    ///
+    pub fn read_value<N, T>(&mut self, node_id: N) -> Result<T, StatusCode>
+        where N: Into<NodeId>, T: FromVariant
+    {
+        let value = self.read_attribute_variant(node_id.into(), AttributeId::Value)?;
+        T::from_variant(&value).map_err(|status_code| {
+            error!("read_value, value {:?} cannot be converted, status {}", value, status_code);
+            status_code
+        })
+    }
+
+    /// Writes the value of a variable, making the variant from a Rust value, e.g. `42.5f64` is written
+    /// as a `Double`. The value must be of the data type of the variable.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The variable to write
+    /// * `value` - The new value of the variable
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - The value was written
+    /// * `Err(StatusCode)` - The status of the write if it failed, or the reason the request failed
+    ///
+    pub fn write_value<N, V>(&mut self, node_id: N, value: V) -> Result<(), StatusCode>
+        where N: Into<NodeId>, V: Into<Variant>
+    {
+        self.write_attribute(node_id, AttributeId::Value, value)
+    }
+
+    /// Reads the `DisplayName` attribute of a node
+    pub fn read_display_name<N>(&mut self, node_id: N) -> Result<LocalizedText, StatusCode> where N: Into<NodeId> {
+        self.read_attribute(node_id, AttributeId::DisplayName)
+    }
+
+    /// Writes the `DisplayName` attribute of a node
+    pub fn write_display_name<N, T>(&mut self, node_id: N, display_name: T) -> Result<(), StatusCode>
+        where N: Into<NodeId>, T: Into<LocalizedText>
+    {
+        self.write_attribute(node_id, AttributeId::DisplayName, display_name.into())
+    }
+
+    /// Reads the `Description` attribute of a node
+    pub fn read_description<N>(&mut self, node_id: N) -> Result<LocalizedText, StatusCode> where N: Into<NodeId> {
+        self.read_attribute(node_id, AttributeId::Description)
+    }
+
+    /// Writes the `Description` attribute of a node
+    pub fn write_description<N, T>(&mut self, node_id: N, description: T) -> Result<(), StatusCode>
+        where N: Into<NodeId>, T: Into<LocalizedText>
+    {
+        self.write_attribute(node_id, AttributeId::Description, description.into())
+    }
+
+    /// Reads the `BrowseName` attribute of a node
+    pub fn read_browse_name<N>(&mut self, node_id: N) -> Result<QualifiedName, StatusCode> where N: Into<NodeId> {
+        self.read_attribute(node_id, AttributeId::BrowseName)
+    }
+
+    /// Reads the `AccessLevel` attribute of a variable. The bits of the access level are defined in
+    /// OPC UA Part 3, e.g. 1 is `CurrentRead` and 2 is `CurrentWrite`.
+    pub fn read_access_level<N>(&mut self, node_id: N) -> Result<u8, StatusCode> where N: Into<NodeId> {
+        self.read_attribute(node_id, AttributeId::AccessLevel)
+    }
+
+    /// Writes the `AccessLevel` attribute of a variable
+    pub fn write_access_level<N>(&mut self, node_id: N, access_level: u8) -> Result<(), StatusCode> where N: Into<NodeId> {
+        self.write_attribute(node_id, AttributeId::AccessLevel, access_level)
+    }
+
+    /// Reads the `UserAccessLevel` attribute of a variable, i.e. the access level of the session's user
+    pub fn read_user_access_level<N>(&mut self, node_id: N) -> Result<u8, StatusCode> where N: Into<NodeId> {
+        self.read_attribute(node_id, AttributeId::UserAccessLevel)
+    }
+
+    /// Reads the `Historizing` attribute of a variable
+    pub fn read_historizing<N>(&mut self, node_id: N) -> Result<bool, StatusCode> where N: Into<NodeId> {
+        self.read_attribute(node_id, AttributeId::Historizing)
+    }
+
+    /// Writes the `Historizing` attribute of a variable
+    pub fn write_historizing<N>(&mut self, node_id: N, historizing: bool) -> Result<(), StatusCode> where N: Into<NodeId> {
+        self.write_attribute(node_id, AttributeId::Historizing, historizing)
+    }
+
+    /// Reads the `EURange` property of an analog item variable, i.e. the range of values the variable
+    /// is expected to hold in normal operation.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Range)` - The engineering units range
+    /// * `Err(StatusCode)` - The variable has no `EURange` property, its value is not a `Range`, or the
+    ///   request failed
+    ///
+    pub fn read_eu_range<N>(&mut self, node_id: N) -> Result<Range, StatusCode> where N: Into<NodeId> {
+        let eu_range_id = self.resolve_path_from(node_id, "0:EURange")?;
+        let eu_range: ExtensionObject = self.read_attribute(eu_range_id, AttributeId::Value)?;
+        let range_encoding_id: NodeId = ObjectId::Range_Encoding_DefaultBinary.into();
+        if eu_range.node_id != range_encoding_id {
+            error!("read_eu_range, EURange holds {:?} instead of a range", eu_range.node_id);
+            return Err(StatusCode::BadTypeMismatch);
+        }
+        let decoding_limits = {
+            let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
+            secure_channel.decoding_limits()
+        };
+        eu_range.decode_inner::<Range>(&decoding_limits)
+    }
+
+    /// Reads the history of nodes by sending a [`HistoryReadRequest`] to the server. This is the
+    /// raw service call - the results contain extension objects and continuation points that the
+    /// caller must deal with. The `history_read_raw_modified`, `history_read_processed` and
+    /// `history_read_at_time` functions are easier to use.
+    ///
+    /// See OPC UA Part 4 - Services 5.10.3 for complete description of the service and error responses.
+    ///
+    /// # Arguments
+    ///
+    /// * `history_read_details` - An extension object holding one of the history read details types.
+    /// * `timestamps_to_return` - The timestamps to return with each value.
+    /// * `release_continuation_points` - Release the continuation points in `nodes_to_read` instead
+    ///   of reading more values.
+    /// * `nodes_to_read` - A list of [`HistoryReadValueId`] to be read by the server.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<HistoryReadResult>)` - A list of [`HistoryReadResult`] corresponding to each node.
+    /// * `Err(StatusCode)` - Status code reason for failure.
+    ///
+    /// [`HistoryReadRequest`]: ./struct.HistoryReadRequest.html
+    /// [`HistoryReadValueId`]: ./struct.HistoryReadValueId.html
+    /// [`HistoryReadResult`]: ./struct.HistoryReadResult.html
+    ///
+    pub fn history_read(&mut self, history_read_details: ExtensionObject, timestamps_to_return: TimestampsToReturn, release_continuation_points: bool, nodes_to_read: &[HistoryReadValueId]) -> Result<Option<Vec<HistoryReadResult>>, StatusCode> {
+        if nodes_to_read.is_empty() {
+            error!("history_read, was not supplied with any nodes to read");
+            Err(StatusCode::BadNothingToDo)
+        } else {
+            let request = HistoryReadRequest {
                 request_header: self.make_request_header(),
-                nodes_to_write: Some(nodes_to_write.to_vec()),
+                history_read_details,
+                timestamps_to_return,
+                release_continuation_points,
+                nodes_to_read: Some(nodes_to_read.to_vec()),
             };
             let response = self.send_request(request)?;
-            if let SupportedMessage::WriteResponse(response) = response {
-                debug!("write_value, success");
+            if let SupportedMessage::HistoryReadResponse(response) = response {
+                debug!("history_read, success");
                 crate::process_service_result(&response.response_header)?;
                 Ok(response.results)
             } else {
-                error!("write_value failed {:?}", response);
+                error!("history_read failed {:?}", response);
                 Err(crate::process_unexpected_response(response))
             }
         }
     }
 
+    /// Reads raw or modified historical values of nodes. Continuation points are followed until
+    /// the server has returned every value.
+    ///
+    /// # Arguments
+    ///
+    /// * `details` - The time range and limits of the read.
+    /// * `timestamps_to_return` - The timestamps to return with each value.
+    /// * `nodes_to_read` - The nodes to read the history of.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<HistoryReadValues>)` - The values of each node, in the same order as `nodes_to_read`.
+    /// * `Err(StatusCode)` - Status code reason for failure.
+    ///
+    pub fn history_read_raw_modified(&mut self, details: &ReadRawModifiedDetails, timestamps_to_return: TimestampsToReturn, nodes_to_read: &[NodeId]) -> Result<Vec<HistoryReadValues>, StatusCode> {
+        let history_read_details = ExtensionObject::from_encodable(ObjectId::ReadRawModifiedDetails_Encoding_DefaultBinary, details);
+        self.history_read_values(history_read_details, timestamps_to_return, nodes_to_read)
+    }
+
+    /// Reads processed historical values of nodes, i.e. aggregates computed by the server over
+    /// each processing interval. The `aggregate_type` of the details must hold an aggregate for
+    /// each node in `nodes_to_read`. Continuation points are followed until the server has
+    /// returned every value.
+    ///
+    /// # Arguments
+    ///
+    /// * `details` - The time range, processing interval and aggregates of the read.
+    /// * `timestamps_to_return` - The timestamps to return with each value.
+    /// * `nodes_to_read` - The nodes to read the history of.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<HistoryReadValues>)` - The values of each node, in the same order as `nodes_to_read`.
+    /// * `Err(StatusCode)` - Status code reason for failure.
+    ///
+    pub fn history_read_processed(&mut self, details: &ReadProcessedDetails, timestamps_to_return: TimestampsToReturn, nodes_to_read: &[NodeId]) -> Result<Vec<HistoryReadValues>, StatusCode> {
+        let aggregate_count = details.aggregate_type.as_ref().map(|a| a.len()).unwrap_or(0);
+        if aggregate_count != nodes_to_read.len() {
+            error!("history_read_processed, was supplied {} aggregates for {} nodes", aggregate_count, nodes_to_read.len());
+            Err(StatusCode::BadAggregateListMismatch)
+        } else {
+            let history_read_details = ExtensionObject::from_encodable(ObjectId::ReadProcessedDetails_Encoding_DefaultBinary, details);
+            self.history_read_values(history_read_details, timestamps_to_return, nodes_to_read)
+        }
+    }
+
+    /// Reads the historical values of nodes at specific times. Continuation points are followed
+    /// until the server has returned every value.
+    ///
+    /// # Arguments
+    ///
+    /// * `details` - The times to read values at.
+    /// * `timestamps_to_return` - The timestamps to return with each value.
+    /// * `nodes_to_read` - The nodes to read the history of.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<HistoryReadValues>)` - The values of each node, in the same order as `nodes_to_read`.
+    /// * `Err(StatusCode)` - Status code reason for failure.
+    ///
+    pub fn history_read_at_time(&mut self, details: &ReadAtTimeDetails, timestamps_to_return: TimestampsToReturn, nodes_to_read: &[NodeId]) -> Result<Vec<HistoryReadValues>, StatusCode> {
+        let history_read_details = ExtensionObject::from_encodable(ObjectId::ReadAtTimeDetails_Encoding_DefaultBinary, details);
+        self.history_read_values(history_read_details, timestamps_to_return, nodes_to_read)
+    }
+
+    /// Calls HistoryRead repeatedly, following the continuation point of each node until the
+    /// server has no more values to return for it.
+    fn history_read_values(&mut self, history_read_details: ExtensionObject, timestamps_to_return: TimestampsToReturn, nodes_to_read: &[NodeId]) -> Result<Vec<HistoryReadValues>, StatusCode> {
+        let decoding_limits = {
+            let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
+            secure_channel.decoding_limits()
+        };
+
+        let mut history_read_values = nodes_to_read.iter().map(|_| HistoryReadValues {
+            status_code: StatusCode::Good,
+            values: Vec::new(),
+        }).collect::<Vec<HistoryReadValues>>();
+
+        // The index of each node that is still being read along with its continuation point
+        let mut pending = (0..nodes_to_read.len())
+            .map(|idx| (idx, ByteString::null()))
+            .collect::<Vec<(usize, ByteString)>>();
+
+        while !pending.is_empty() {
+            let history_read_value_ids = pending.iter().map(|(idx, continuation_point)| {
+                HistoryReadValueId {
+                    node_id: nodes_to_read[*idx].clone(),
+                    index_range: UAString::null(),
+                    data_encoding: QualifiedName::null(),
+                    continuation_point: continuation_point.clone(),
+                }
+            }).collect::<Vec<HistoryReadValueId>>();
+
+            let results = self.history_read(history_read_details.clone(), timestamps_to_return, false, &history_read_value_ids)?
+                .unwrap_or_default();
+            if results.len() != pending.len() {
+                error!("history_read, server returned {} results for {} nodes", results.len(), pending.len());
+                return Err(StatusCode::BadUnexpectedError);
+            }
+
+            pending = pending.into_iter().zip(results).filter_map(|((idx, _), result)| {
+                let node_values = &mut history_read_values[idx];
+                node_values.status_code = result.status_code;
+                if result.status_code.is_bad() {
+                    return None;
+                }
+                if !result.history_data.is_empty() {
+                    match result.history_data.decode_inner::<HistoryData>(&decoding_limits) {
+                        Ok(history_data) => {
+                            if let Some(data_values) = history_data.data_values {
+                                node_values.values.extend(data_values);
+                            }
+                        }
+                        Err(err) => {
+                            error!("history_read, cannot decode history data, error = {}", err);
+                            node_values.status_code = StatusCode::BadDecodingError;
+                            return None;
+                        }
+                    }
+                }
+                if result.continuation_point.is_null() {
+                    None
+                } else {
+                    Some((idx, result.continuation_point))
+                }
+            }).collect();
+        }
+
+        Ok(history_read_values)
+    }
+
     ////////////////////////////////////////////////////////////////////////////////////////////////
     // Method Service set
     ////////////////////////////////////////////////////////////////////////////////////////////////
@@ -1476,6 +2693,40 @@ impl Session {
         }
     }
 
+    /// Calls a single method on an object on the server. This is a convenience wrapper around
+    /// [`call`] which converts the input arguments into variants and unpacks the result.
+    ///
+    /// Note that a method call that the server rejects, e.g. because of a bad argument, is
+    /// still an `Ok` result. Check the `status_code` of the returned [`MethodCallResult`] and its
+    /// `input_argument_results` to see why.
+    ///
+    /// # Arguments
+    ///
+    /// * `object_id` - The id of the object or object type that the method belongs to.
+    /// * `method_id` - The id of the method to call.
+    /// * `input_arguments` - The input arguments, anything that can be turned into a `Variant`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(MethodCallResult)` - The status, argument results and output arguments of the call.
+    /// * `Err(StatusCode)` - Status code reason for failure of the request.
+    ///
+    /// [`call`]: #method.call
+    /// [`MethodCallResult`]: ./struct.MethodCallResult.html
+    ///
+    pub fn call_method<O, M, I>(&mut self, object_id: O, method_id: M, input_arguments: I) -> Result<MethodCallResult, StatusCode>
+        where O: Into<NodeId>, M: Into<NodeId>, I: IntoIterator, I::Item: Into<Variant>
+    {
+        let input_arguments = input_arguments.into_iter().map(|v| v.into()).collect::<Vec<Variant>>();
+        let input_arguments = if input_arguments.is_empty() { None } else { Some(input_arguments) };
+        let request = CallMethodRequest {
+            object_id: object_id.into(),
+            method_id: method_id.into(),
+            input_arguments,
+        };
+        self.call(request).map(MethodCallResult::from)
+    }
+
     /// Calls GetMonitoredItems via call_method(), putting a sane interface on the input / output.
     ///
     /// # Arguments
@@ -1513,6 +2764,8 @@ impl Session {
     ////////////////////////////////////////////////////////////////////////////////////////////////
 
     /// Creates monitored items on a subscription by sending a [`CreateMonitoredItemsRequest`] to the server.
+    /// If there are more items than the server's `MaxMonitoredItemsPerCall` operation limit, they are
+    /// created in several requests.
     ///
     /// See OPC UA Part 4 - Services 5.12.2 for complete description of the service and error responses.
     ///
@@ -1544,53 +2797,116 @@ impl Session {
             error!("create_monitored_items, called with no items to create");
             Err(StatusCode::BadNothingToDo)
         } else {
-            // Assign each item a unique client handle
-            let mut items_to_create = items_to_create.to_vec();
-            {
-                let mut session_state = trace_write_lock_unwrap!(self.session_state);
-                items_to_create.iter_mut().for_each(|i| {
-                    i.requested_parameters.client_handle = session_state.next_monitored_item_handle();
-                });
-            }
+            let limit = self.operation_limits().max_monitored_items_per_call;
+            let results = in_batches(items_to_create, limit, |items_to_create| {
+                self.create_monitored_items_batch(subscription_id, timestamps_to_return, items_to_create).map(Some)
+            })?;
+            Ok(results.unwrap_or_default())
+        }
+    }
 
-            let request = CreateMonitoredItemsRequest {
-                request_header: self.make_request_header(),
-                subscription_id,
-                timestamps_to_return,
-                items_to_create: Some(items_to_create.clone()),
-            };
-            let response = self.send_request(request)?;
-            if let SupportedMessage::CreateMonitoredItemsResponse(response) = response {
-                crate::process_service_result(&response.response_header)?;
-                if let Some(ref results) = response.results {
-                    debug!("create_monitored_items, {} items created", items_to_create.len());
-                    // Set the items in our internal state
-                    let items_to_create = items_to_create.iter()
-                        .zip(results)
-                        .map(|(i, r)| {
-                            subscription::CreateMonitoredItem {
-                                id: r.monitored_item_id,
-                                client_handle: i.requested_parameters.client_handle,
-                                discard_oldest: i.requested_parameters.discard_oldest,
-                                item_to_monitor: i.item_to_monitor.clone(),
-                                monitoring_mode: i.monitoring_mode,
-                                queue_size: r.revised_queue_size,
-                                sampling_interval: r.revised_sampling_interval,
-                            }
-                        })
-                        .collect::<Vec<subscription::CreateMonitoredItem>>();
-                    {
-                        let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
-                        subscription_state.insert_monitored_items(subscription_id, &items_to_create);
-                    }
-                } else {
-                    debug!("create_monitored_items, success but no monitored items were created");
+    /// Creates a batch of monitored items that is within the server's operation limits
+    fn create_monitored_items_batch(&mut self, subscription_id: u32, timestamps_to_return: TimestampsToReturn, items_to_create: &[MonitoredItemCreateRequest]) -> Result<Vec<MonitoredItemCreateResult>, StatusCode> {
+        // Assign each item a unique client handle
+        let mut items_to_create = items_to_create.to_vec();
+        {
+            let mut session_state = trace_write_lock_unwrap!(self.session_state);
+            items_to_create.iter_mut().for_each(|i| {
+                i.requested_parameters.client_handle = session_state.next_monitored_item_handle();
+            });
+        }
+
+        let request = CreateMonitoredItemsRequest {
+            request_header: self.make_request_header(),
+            subscription_id,
+            timestamps_to_return,
+            items_to_create: Some(items_to_create.clone()),
+        };
+        let response = self.send_request(request)?;
+        if let SupportedMessage::CreateMonitoredItemsResponse(response) = response {
+            crate::process_service_result(&response.response_header)?;
+            if let Some(ref results) = response.results {
+                debug!("create_monitored_items, {} items created", items_to_create.len());
+                // Set the items in our internal state
+                let items_to_create = items_to_create.iter()
+                    .zip(results)
+                    .map(|(i, r)| {
+                        subscription::CreateMonitoredItem {
+                            id: r.monitored_item_id,
+                            client_handle: i.requested_parameters.client_handle,
+                            discard_oldest: i.requested_parameters.discard_oldest,
+                            item_to_monitor: i.item_to_monitor.clone(),
+                            monitoring_mode: i.monitoring_mode,
+                            queue_size: r.revised_queue_size,
+                            sampling_interval: r.revised_sampling_interval,
+                            filter: i.requested_parameters.filter.clone(),
+                        }
+                    })
+                    .collect::<Vec<subscription::CreateMonitoredItem>>();
+                {
+                    let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
+                    subscription_state.insert_monitored_items(subscription_id, &items_to_create);
                 }
-                Ok(response.results.unwrap())
             } else {
-                error!("create_monitored_items failed {:?}", response);
-                Err(crate::process_unexpected_response(response))
+                debug!("create_monitored_items, success but no monitored items were created");
             }
+            Ok(response.results.unwrap_or_default())
+        } else {
+            error!("create_monitored_items failed {:?}", response);
+            Err(crate::process_unexpected_response(response))
+        }
+    }
+
+    /// Creates a monitored item for the events of a node, typically the `Server` object, and
+    /// registers a callback that receives the fields of each event it delivers.
+    ///
+    /// See OPC UA Part 4 - Services 5.12.2 and 7.17.3 for complete description of the service and the event filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscription_id` - The Server-assigned identifier for the Subscription that will report the events.
+    /// * `node_id` - The node whose `EventNotifier` attribute is monitored.
+    /// * `filter` - The [`EventFilter`] which selects the fields of each event and filters the events, e.g.
+    ///   made by an `EventFilterBuilder`.
+    /// * `callback` - Receives the monitored item and the selected fields of each event, in the order of the
+    ///   select clauses.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u32)` - The identifier of the monitored item.
+    /// * `Err(StatusCode)` - Status code reason for failure, including the status of the item if the server
+    ///   could not create it, e.g. `BadMonitoredItemFilterInvalid`.
+    ///
+    /// [`EventFilter`]: ./struct.EventFilter.html
+    ///
+    pub fn create_event_monitored_item<N, CB>(&mut self, subscription_id: u32, node_id: N, filter: EventFilter, callback: CB) -> Result<u32, StatusCode>
+        where N: Into<NodeId>, CB: OnEvent + Send + Sync + 'static {
+        let item_to_create = MonitoredItemCreateRequest {
+            item_to_monitor: ReadValueId {
+                node_id: node_id.into(),
+                attribute_id: AttributeId::EventNotifier as u32,
+                index_range: UAString::null(),
+                data_encoding: QualifiedName::null(),
+            },
+            monitoring_mode: MonitoringMode::Reporting,
+            requested_parameters: MonitoringParameters {
+                client_handle: 0,
+                sampling_interval: 0f64,
+                filter: ExtensionObject::from_encodable(ObjectId::EventFilter_Encoding_DefaultBinary, &filter),
+                // The server chooses the size of the event queue
+                queue_size: 0,
+                discard_oldest: true,
+            },
+        };
+        let results = self.create_monitored_items(subscription_id, TimestampsToReturn::Both, &[item_to_create])?;
+        let result = results.first().ok_or(StatusCode::BadUnexpectedError)?;
+        if result.status_code.is_bad() {
+            error!("create_event_monitored_item, the server could not create the item, error = {}", result.status_code);
+            Err(result.status_code)
+        } else {
+            let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
+            subscription_state.set_event_callback(subscription_id, result.monitored_item_id, Arc::new(Mutex::new(callback)));
+            Ok(result.monitored_item_id)
         }
     }
 
@@ -1627,8 +2943,8 @@ impl Session {
             Err(StatusCode::BadNothingToDo)
         } else {
             let monitored_item_ids = items_to_modify.iter()
-                .map(|i| i.monitored_item_id)
-                .collect::<Vec<u32>>();
+                .map(|i| (i.monitored_item_id, i.requested_parameters.filter.clone()))
+                .collect::<Vec<(u32, ExtensionObject)>>();
             let request = ModifyMonitoredItemsRequest {
                 request_header: self.make_request_header(),
                 subscription_id,
@@ -1642,11 +2958,12 @@ impl Session {
                     // Set the items in our internal state
                     let items_to_modify = monitored_item_ids.iter()
                         .zip(results.iter())
-                        .map(|(id, r)| {
+                        .map(|((id, filter), r)| {
                             subscription::ModifyMonitoredItem {
                                 id: *id,
                                 queue_size: r.revised_queue_size,
                                 sampling_interval: r.revised_sampling_interval,
+                                filter: filter.clone(),
                             }
                         })
                         .collect::<Vec<subscription::ModifyMonitoredItem>>();
@@ -1855,13 +3172,25 @@ impl Session {
     pub fn create_subscription<CB>(&mut self, publishing_interval: f64, lifetime_count: u32, max_keep_alive_count: u32, max_notifications_per_publish: u32, priority: u8, publishing_enabled: bool, callback: CB)
                                    -> Result<u32, StatusCode>
         where CB: OnDataChange + Send + Sync + 'static {
-        self.create_subscription_inner(publishing_interval, lifetime_count, max_keep_alive_count, max_notifications_per_publish, priority, publishing_enabled, Arc::new(Mutex::new(callback)))
+        self.create_subscription_inner(publishing_interval, lifetime_count, max_keep_alive_count, max_notifications_per_publish, priority, publishing_enabled, Arc::new(Mutex::new(DataChangeOnly(callback))))
     }
 
-    /// This is the internal handler for create subscription that receives the callback wrapped up and reference counted.
+    /// Creates a subscription in the same way as `create_subscription` but with [`SubscriptionCallbacks`]
+    /// which receive the events, keep-alives and status changes of the subscription as well as its
+    /// data changes.
+    ///
+    /// [`SubscriptionCallbacks`]: ./trait.SubscriptionCallbacks.html
+    ///
+    pub fn create_subscription_with_callbacks<CB>(&mut self, publishing_interval: f64, lifetime_count: u32, max_keep_alive_count: u32, max_notifications_per_publish: u32, priority: u8, publishing_enabled: bool, callbacks: CB)
+                                                  -> Result<u32, StatusCode>
+        where CB: SubscriptionCallbacks + Send + Sync + 'static {
+        self.create_subscription_inner(publishing_interval, lifetime_count, max_keep_alive_count, max_notifications_per_publish, priority, publishing_enabled, Arc::new(Mutex::new(callbacks)))
+    }
+
+    /// This is the internal handler for create subscription that receives the callbacks wrapped up and reference counted.
     fn create_subscription_inner(&mut self, publishing_interval: f64, lifetime_count: u32, max_keep_alive_count: u32, max_notifications_per_publish: u32,
                                  priority: u8, publishing_enabled: bool,
-                                 callback: Arc<Mutex<dyn OnDataChange + Send + Sync + 'static>>)
+                                 callback: Arc<Mutex<dyn SubscriptionCallbacks + Send + Sync + 'static>>)
                                  -> Result<u32, StatusCode>
     {
         let request = CreateSubscriptionRequest {
@@ -1986,13 +3315,14 @@ impl Session {
             let response = self.send_request(request)?;
             if let SupportedMessage::SetPublishingModeResponse(response) = response {
                 crate::process_service_result(&response.response_header)?;
+                let results = response.results.unwrap_or_default();
                 {
-                    // Clear out all subscriptions, assuming the delete worked
+                    // Update the subscriptions whose mode was set
                     let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
-                    subscription_state.set_publishing_mode(subscription_ids, publishing_enabled);
+                    subscription_state.set_publishing_mode(subscription_ids, &results, publishing_enabled);
                 }
                 debug!("set_publishing_mode success");
-                Ok(response.results.unwrap())
+                Ok(results)
             } else {
                 error!("set_publishing_mode failed {:?}", response);
                 Err(crate::process_unexpected_response(response))
@@ -2000,6 +3330,44 @@ impl Session {
         }
     }
 
+    /// Asks the server to resend a notification message that was sent but not received by sending
+    /// a [`RepublishRequest`] to the server. The server holds sent notifications until they are
+    /// acknowledged, or until it runs out of room for them.
+    ///
+    /// See OPC UA Part 4 - Services 5.13.6 for complete description of the service and error responses.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscription_id` - subscription identifier of the notification message.
+    /// * `retransmit_sequence_number` - the sequence number of the notification message to resend.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(NotificationMessage)` - The requested [`NotificationMessage`].
+    /// * `Err(StatusCode)` - Status code reason for failure, e.g. `BadMessageNotAvailable` if the
+    ///   server no longer holds the notification message.
+    ///
+    /// [`RepublishRequest`]: ./struct.RepublishRequest.html
+    /// [`NotificationMessage`]: ./struct.NotificationMessage.html
+    ///
+    pub fn republish(&mut self, subscription_id: u32, retransmit_sequence_number: u32) -> Result<NotificationMessage, StatusCode> {
+        debug!("republish, subscription {}, sequence number {}", subscription_id, retransmit_sequence_number);
+        let request = RepublishRequest {
+            request_header: self.make_request_header(),
+            subscription_id,
+            retransmit_sequence_number,
+        };
+        let response = self.send_request(request)?;
+        if let SupportedMessage::RepublishResponse(response) = response {
+            crate::process_service_result(&response.response_header)?;
+            debug!("republish success");
+            Ok(response.notification_message)
+        } else {
+            error!("republish failed {:?}", response);
+            Err(crate::process_unexpected_response(response))
+        }
+    }
+
     /// Transfers Subscriptions and their MonitoredItems from one Session to another. For example,
     /// a Client may need to reopen a Session and then transfer its Subscriptions to that Session.
     /// It may also be used by one Client to take over a Subscription from another Client by
@@ -2103,15 +3471,14 @@ impl Session {
             let response = self.send_request(request)?;
             if let SupportedMessage::DeleteSubscriptionsResponse(response) = response {
                 crate::process_service_result(&response.response_header)?;
+                let results = response.results.unwrap_or_default();
                 {
-                    // Clear out deleted subscriptions, assuming the delete worked
+                    // Clear out the subscriptions that were deleted
                     let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
-                    subscription_ids.iter().for_each(|id| {
-                        let _ = subscription_state.delete_subscription(*id);
-                    });
+                    subscription_state.delete_subscriptions(subscription_ids, &results);
                 }
                 debug!("delete_subscriptions success");
-                Ok(response.results.unwrap())
+                Ok(results)
             } else {
                 error!("delete_subscriptions failed {:?}", response);
                 Err(crate::process_unexpected_response(response))
@@ -2145,6 +3512,17 @@ impl Session {
         }
     }
 
+    /// Returns an [`AsyncSession`] that shares this session's connection. Its functions return
+    /// futures instead of blocking while they wait for a response. This session must still be
+    /// connected and running for the async session to be of any use.
+    ///
+    /// [`AsyncSession`]: ../async_session/struct.AsyncSession.html
+    ///
+    pub fn async_session(&self) -> AsyncSession {
+        AsyncSession::new(self.session_state.clone(), self.subscription_state.clone(),
+                          self.message_queue.clone(), self.timer_command_queue.clone())
+    }
+
     /// Returns the subscription state object
     pub fn subscription_state(&self) -> Arc<RwLock<SubscriptionState>> {
         self.subscription_state.clone()
@@ -2174,7 +3552,9 @@ impl Session {
         session_state.async_send_request(request, is_async)
     }
 
-    fn user_identity_token(&self) -> Result<ExtensionObject, StatusCode> {
+    /// Returns the user identity token for ActivateSession and the user token signature that goes
+    /// with it. Only X509 identity tokens are signed, the signature is null for the others.
+    fn user_identity_token(&self) -> Result<(ExtensionObject, SignatureData), StatusCode> {
         let user_token_type = match self.session_info.user_identity_token {
             client::IdentityToken::Anonymous => {
                 UserTokenType::Anonymous
@@ -2183,8 +3563,10 @@ impl Session {
                 UserTokenType::Username
             }
             client::IdentityToken::X509(_, _) => {
-                // TODO
-                panic!();
+                UserTokenType::Certificate
+            }
+            client::IdentityToken::IssuedToken(_) => {
+                UserTokenType::IssuedToken
             }
         };
 
@@ -2203,23 +3585,28 @@ impl Session {
                         let token = AnonymousIdentityToken {
                             policy_id: policy.policy_id.clone(),
                         };
-                        Ok(ExtensionObject::from_encodable(ObjectId::AnonymousIdentityToken_Encoding_DefaultBinary, &token))
+                        Ok((ExtensionObject::from_encodable(ObjectId::AnonymousIdentityToken_Encoding_DefaultBinary, &token), SignatureData::null()))
                     }
                     client::IdentityToken::UserName(ref user, ref pass) => {
                         let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
                         let token = self.make_user_name_identity_token(&secure_channel, policy, user, pass)?;
-                        Ok(ExtensionObject::from_encodable(ObjectId::UserNameIdentityToken_Encoding_DefaultBinary, &token))
+                        Ok((ExtensionObject::from_encodable(ObjectId::UserNameIdentityToken_Encoding_DefaultBinary, &token), SignatureData::null()))
                     }
-                    client::IdentityToken::X509(_, _) => {
-                        // TODO
-                        unimplemented!();
+                    client::IdentityToken::X509(ref cert_path, ref private_key_path) => {
+                        let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
+                        let (token, user_token_signature) = self.make_x509_identity_token(&secure_channel, policy, cert_path, private_key_path)?;
+                        Ok((ExtensionObject::from_encodable(ObjectId::X509IdentityToken_Encoding_DefaultBinary, &token), user_token_signature))
+                    }
+                    client::IdentityToken::IssuedToken(ref token_data) => {
+                        let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
+                        let token = self.make_issued_identity_token(&secure_channel, policy, token_data)?;
+                        Ok((ExtensionObject::from_encodable(ObjectId::IssuedIdentityToken_Encoding_DefaultBinary, &token), SignatureData::null()))
                     }
                 }
             }
         }
     }
 
-
     /// Create a filled in UserNameIdentityToken by using the endpoint's token policy, the current
     /// secure channel information and the user name and password.
     fn make_user_name_identity_token(&self, secure_channel: &SecureChannel, user_token_policy: &UserTokenPolicy, user: &str, pass: &str) -> Result<UserNameIdentityToken, StatusCode> {
@@ -2229,6 +3616,35 @@ impl Session {
         make_user_name_identity_token(channel_security_policy, user_token_policy, nonce, cert, user, pass)
     }
 
+    /// Create a filled in IssuedIdentityToken by using the endpoint's token policy, the current
+    /// secure channel information and the token data.
+    fn make_issued_identity_token(&self, secure_channel: &SecureChannel, user_token_policy: &UserTokenPolicy, token_data: &[u8]) -> Result<IssuedIdentityToken, StatusCode> {
+        let channel_security_policy = secure_channel.security_policy();
+        let nonce = secure_channel.remote_nonce();
+        let cert = secure_channel.remote_cert();
+        make_issued_identity_token(channel_security_policy, user_token_policy, nonce, cert, token_data)
+    }
+
+    /// Create a filled in X509IdentityToken and its signature by reading the user's certificate and
+    /// private key from disk and signing the server's certificate and nonce.
+    fn make_x509_identity_token(&self, secure_channel: &SecureChannel, user_token_policy: &UserTokenPolicy, cert_path: &Path, private_key_path: &Path) -> Result<(X509IdentityToken, SignatureData), StatusCode> {
+        let user_cert = CertificateStore::read_cert(cert_path).map_err(|err| {
+            error!("Cannot read X509 identity certificate, {}", err);
+            StatusCode::BadIdentityTokenInvalid
+        })?;
+        let user_key = CertificateStore::read_pkey(private_key_path).map_err(|err| {
+            error!("Cannot read X509 identity private key, {}", err);
+            StatusCode::BadIdentityTokenInvalid
+        })?;
+        let server_cert = secure_channel.remote_cert().ok_or_else(|| {
+            error!("Cannot sign X509 identity token because server cert is null");
+            StatusCode::BadUnexpectedError
+        })?;
+        let channel_security_policy = secure_channel.security_policy();
+        let nonce = secure_channel.remote_nonce();
+        make_x509_identity_token(channel_security_policy, user_token_policy, nonce, &server_cert, &user_cert, &user_key)
+    }
+
     /// Construct a request header for the session. All requests after create session are expected
     /// to supply an authentication token.
     fn make_request_header(&mut self) -> RequestHeader {
@@ -2236,6 +3652,25 @@ impl Session {
         session_state.make_request_header()
     }
 
+    /// Tests if the server has gone longer without sending a publish response than the publish
+    /// keep-alive limit allows for the subscriptions of the session.
+    fn publish_responses_stopped(&mut self) -> bool {
+        if self.publish_keep_alive_limit == 0 {
+            return false;
+        }
+        let keep_alive_timeout = {
+            let subscription_state = trace_read_lock_unwrap!(self.subscription_state);
+            subscription_state.keep_alive_timeout()
+        };
+        if let Some(keep_alive_timeout) = keep_alive_timeout {
+            self.last_publish_response.elapsed() > keep_alive_timeout * self.publish_keep_alive_limit
+        } else {
+            // Nothing is expected without subscriptions, so the wait starts from when there are some
+            self.last_publish_response = Instant::now();
+            false
+        }
+    }
+
     // Process any async messages we expect to receive
     fn handle_publish_responses(&mut self) -> bool {
         let responses = {
@@ -2246,57 +3681,175 @@ impl Session {
             false
         } else {
             debug!("Processing {} async messages", responses.len());
+            self.last_publish_response = Instant::now();
             for response in responses {
                 self.handle_async_response(response);
             }
+            self.replace_publish_requests();
             true
         }
     }
 
-    /// This is the handler for asynchronous responses which are currently assumed to be publish
-    /// responses. It maintains the acknowledgements to be sent and sends the data change
+    /// Sends publish requests to replace the ones that have been answered, so the server always
+    /// has some to respond to. Nothing is sent while there are no subscriptions.
+    fn replace_publish_requests(&mut self) {
+        let has_subscriptions = {
+            let subscription_state = trace_read_lock_unwrap!(self.subscription_state);
+            subscription_state.subscription_ids().is_some()
+        };
+        if has_subscriptions {
+            let mut session_state = trace_write_lock_unwrap!(self.session_state);
+            if !session_state.wait_for_publish_response() {
+                let _ = session_state.async_publish_to_limit();
+            }
+        }
+    }
+
+    /// Queues an acknowledgement for a notification message and sends its data change, event and
+    /// status change notifications to the subscription. Keep alives have no notifications and are
+    /// not acknowledged.
+    fn process_notification_message(&mut self, subscription_id: u32, notification_message: &NotificationMessage) {
+        if notification_message.notification_data.is_none() {
+            let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
+            subscription_state.subscription_keep_alive(subscription_id, &notification_message.publish_time);
+            return;
+        }
+
+        // Queue an acknowledgement for this notification, sent with the next publish request
+        {
+            let mut session_state = trace_write_lock_unwrap!(self.session_state);
+            session_state.add_subscription_acknowledgement(SubscriptionAcknowledgement {
+                subscription_id,
+                sequence_number: notification_message.sequence_number,
+            });
+        }
+
+        let decoding_limits = {
+            let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
+            secure_channel.decoding_limits()
+        };
+
+        // Process data change notifications
+        let data_change_notifications = notification_message.data_change_notifications(&decoding_limits);
+        if !data_change_notifications.is_empty() {
+            let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
+            subscription_state.subscription_data_change(subscription_id, &data_change_notifications);
+        }
+
+        // Process event notifications
+        let event_notifications = notification_message.event_notifications(&decoding_limits);
+        if !event_notifications.is_empty() {
+            let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
+            subscription_state.subscription_events(subscription_id, &event_notifications);
+        }
+
+        // Process status change notifications
+        let status_change_notifications = notification_message.status_change_notifications(&decoding_limits);
+        if !status_change_notifications.is_empty() {
+            let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
+            subscription_state.subscription_status_change(subscription_id, &status_change_notifications);
+        }
+    }
+
+    /// Takes the sequence numbers of notification messages that the server holds for a subscription,
+    /// e.g. after it is transferred, and acknowledges the ones already received and republishes the rest.
+    fn recover_notification_messages(&mut self, subscription_id: u32, available_sequence_numbers: &[u32]) {
+        let last_sequence_number = {
+            let subscription_state = trace_read_lock_unwrap!(self.subscription_state);
+            subscription_state.get(subscription_id).map(|s| s.last_sequence_number()).unwrap_or(0)
+        };
+        let mut available_sequence_numbers = available_sequence_numbers.to_vec();
+        available_sequence_numbers.sort();
+        let (received, not_received): (Vec<u32>, Vec<u32>) = available_sequence_numbers.into_iter()
+            .partition(|sequence_number| *sequence_number <= last_sequence_number);
+        {
+            let mut session_state = trace_write_lock_unwrap!(self.session_state);
+            received.into_iter().for_each(|sequence_number| {
+                session_state.add_subscription_acknowledgement(SubscriptionAcknowledgement {
+                    subscription_id,
+                    sequence_number,
+                });
+            });
+        }
+        if !not_received.is_empty() {
+            {
+                let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
+                let _ = subscription_state.missed_sequence_numbers(subscription_id, *not_received.last().unwrap(), false);
+            }
+            self.republish_notification_messages(subscription_id, &not_received);
+        }
+    }
+
+    /// Republishes the notification messages with the supplied sequence numbers, in order, and
+    /// processes them. Messages the server no longer holds are lost.
+    fn republish_notification_messages(&mut self, subscription_id: u32, sequence_numbers: &[u32]) {
+        for sequence_number in sequence_numbers {
+            match self.republish(subscription_id, *sequence_number) {
+                Ok(notification_message) => {
+                    self.process_notification_message(subscription_id, &notification_message);
+                }
+                Err(StatusCode::BadMessageNotAvailable) => {
+                    warn!("Notification message {} for subscription {} is no longer available", sequence_number, subscription_id);
+                }
+                Err(status_code) => {
+                    error!("Cannot republish notification messages for subscription {}, error = {}", subscription_id, status_code);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// This is the handler for asynchronous responses, which are publish responses or the reads of
+    /// the keep-alive. It maintains the acknowledgements to be sent and sends the data change
     /// notifications to the client for processing.
     fn handle_async_response(&mut self, response: SupportedMessage) {
         debug!("handle_publish_response");
+        let (is_publish_response, is_keep_alive_response) = {
+            let mut session_state = trace_write_lock_unwrap!(self.session_state);
+            let request_handle = response.request_handle();
+            (session_state.publish_response_received(request_handle), session_state.keep_alive_response_received(request_handle))
+        };
         let mut wait_for_publish_response = false;
         match response {
             SupportedMessage::PublishResponse(response) => {
                 debug!("PublishResponse");
 
-                // Update subscriptions based on response
-                // Queue acknowledgements for next request
-                let notification_message = response.notification_message.clone();
+                let notification_message = response.notification_message;
                 let subscription_id = response.subscription_id;
 
-                // Queue an acknowledgement for this request
-                {
-                    let mut session_state = trace_write_lock_unwrap!(self.session_state);
-                    session_state.add_subscription_acknowledgement(SubscriptionAcknowledgement {
-                        subscription_id,
-                        sequence_number: notification_message.sequence_number,
-                    });
-                }
-
-                let decoding_limits = {
-                    let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
-                    secure_channel.decoding_limits()
+                // Recover any notification messages that were lost before this one, e.g. because
+                // the network dropped a publish response, so they are processed in order.
+                let missed_sequence_numbers = {
+                    let keep_alive = notification_message.notification_data.is_none();
+                    let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
+                    subscription_state.missed_sequence_numbers(subscription_id, notification_message.sequence_number, keep_alive)
                 };
+                if !missed_sequence_numbers.is_empty() {
+                    warn!("Subscription {} missed notification messages {:?}, republishing them", subscription_id, missed_sequence_numbers);
+                    self.republish_notification_messages(subscription_id, &missed_sequence_numbers);
+                }
 
-                // Process data change notifications
-                let data_change_notifications = notification_message.data_change_notifications(&decoding_limits);
-                if !data_change_notifications.is_empty() {
-                    let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
-                    subscription_state.subscription_data_change(subscription_id, &data_change_notifications);
+                self.process_notification_message(subscription_id, &notification_message);
+            }
+            SupportedMessage::ReadResponse(response) if is_keep_alive_response => {
+                let server_state = response.results.as_ref()
+                    .and_then(|results| results.first())
+                    .and_then(|result| result.value.as_ref())
+                    .and_then(|value| if let Variant::Int32(value) = value { ServerState::from_i32(*value) } else { None });
+                if let Some(server_state) = server_state {
+                    let mut session_state = trace_write_lock_unwrap!(self.session_state);
+                    session_state.set_server_state(server_state);
+                } else {
+                    warn!("Session activity keep-alive could not read the state of the server");
                 }
             }
             SupportedMessage::ServiceFault(response) => {
                 let service_result = response.response_header.service_result;
                 debug!("Service fault received with {} error code", service_result);
                 trace!("ServiceFault {:?}", response);
-                // Terminate timer if
-                if service_result == StatusCode::BadTooManyPublishRequests {
-                    // Turn off publish requests until server says otherwise
-                    wait_for_publish_response = false;
+                if is_publish_response && service_result == StatusCode::BadTooManyPublishRequests {
+                    // Turn off publish requests until one of the outstanding ones is answered
+                    wait_for_publish_response = true;
                 }
             }
             _ => {
@@ -2305,7 +3858,7 @@ impl Session {
         }
 
         // Turn on/off publish requests
-        {
+        if is_publish_response {
             let mut session_state = trace_write_lock_unwrap!(self.session_state);
             session_state.set_wait_for_publish_response(wait_for_publish_response);
         }
@@ -0,0 +1,164 @@
+//! A pool of sessions to the same endpoint, for collectors whose throughput is limited by a single
+//! session. A `Session` handles one synchronous call at a time over one secure channel, so threads
+//! sharing a session wait on each other. A [`SessionPool`] spreads the calls of many threads
+//! across several sessions, each with a secure channel of its own.
+//!
+//! [`SessionPool`]: ./struct.SessionPool.html
+
+use std::{
+    sync::{Arc, RwLock, atomic::{AtomicUsize, Ordering}},
+    thread,
+};
+
+use opcua_types::{
+    *,
+    service_types::*,
+    status_code::StatusCode,
+};
+
+use crate::session::{HistoryReadValues, Session};
+
+/// A pool of sessions to the same endpoint, obtained from `Client::connect_session_pool()`.
+/// Each call is made on the next session in turn that is connected and not busy with another call,
+/// or if every session is busy, the next session in turn once its current call is complete.
+///
+/// Calls whose results depend on the state of a particular session, such as subscriptions,
+/// browse continuation points or registered nodes, should be made on one of the `sessions()`.
+pub struct SessionPool {
+    /// The sessions of the pool
+    sessions: Vec<Arc<RwLock<Session>>>,
+    /// Round robin position of the next session to make a call on
+    next: AtomicUsize,
+}
+
+impl SessionPool {
+    /// Creates a pool of sessions which should already be connected to the same endpoint
+    pub fn new(sessions: Vec<Arc<RwLock<Session>>>) -> SessionPool {
+        SessionPool {
+            sessions,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of sessions in the pool
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Tests if the pool has no sessions
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Returns the sessions of the pool
+    pub fn sessions(&self) -> &[Arc<RwLock<Session>>] {
+        &self.sessions
+    }
+
+    /// Reads the value of nodes on the next free session. See `Session::read`.
+    pub fn read(&self, nodes_to_read: &[ReadValueId]) -> Result<Option<Vec<DataValue>>, StatusCode> {
+        self.with_session(|session| session.read(nodes_to_read))
+    }
+
+    /// Writes the value of nodes on the next free session. See `Session::write`.
+    pub fn write(&self, nodes_to_write: &[WriteValue]) -> Result<Option<Vec<StatusCode>>, StatusCode> {
+        self.with_session(|session| session.write(nodes_to_write))
+    }
+
+    /// Reads raw or modified historical values of nodes on the next free session. See
+    /// `Session::history_read_raw_modified`.
+    pub fn history_read_raw_modified(&self, details: &ReadRawModifiedDetails, timestamps_to_return: TimestampsToReturn, nodes_to_read: &[NodeId]) -> Result<Vec<HistoryReadValues>, StatusCode> {
+        self.with_session(|session| session.history_read_raw_modified(details, timestamps_to_return, nodes_to_read))
+    }
+
+    /// Reads processed historical values of nodes on the next free session. See
+    /// `Session::history_read_processed`.
+    pub fn history_read_processed(&self, details: &ReadProcessedDetails, timestamps_to_return: TimestampsToReturn, nodes_to_read: &[NodeId]) -> Result<Vec<HistoryReadValues>, StatusCode> {
+        self.with_session(|session| session.history_read_processed(details, timestamps_to_return, nodes_to_read))
+    }
+
+    /// Reads the historical values of nodes at specific times on the next free session. See
+    /// `Session::history_read_at_time`.
+    pub fn history_read_at_time(&self, details: &ReadAtTimeDetails, timestamps_to_return: TimestampsToReturn, nodes_to_read: &[NodeId]) -> Result<Vec<HistoryReadValues>, StatusCode> {
+        self.with_session(|session| session.history_read_at_time(details, timestamps_to_return, nodes_to_read))
+    }
+
+    /// Reads the value of many nodes by splitting them into a read on each session of the pool
+    /// at the same time. The values are returned in the same order as the nodes. The read fails if
+    /// any of the reads fail.
+    pub fn read_parallel(&self, nodes_to_read: &[ReadValueId]) -> Result<Vec<DataValue>, StatusCode> {
+        if nodes_to_read.is_empty() {
+            error!("read_parallel, was not supplied with any nodes to read");
+            return Err(StatusCode::BadNothingToDo);
+        } else if self.sessions.is_empty() {
+            error!("read_parallel, the session pool is empty");
+            return Err(StatusCode::BadNotConnected);
+        }
+        let reads = split_evenly(nodes_to_read.len(), self.sessions.len()).into_iter()
+            .zip(self.sessions.iter())
+            .map(|((start, end), session)| {
+                let session = session.clone();
+                let nodes_to_read = nodes_to_read[start..end].to_vec();
+                thread::spawn(move || -> Result<Vec<DataValue>, StatusCode> {
+                    let mut session = trace_write_lock_unwrap!(session);
+                    match session.read(&nodes_to_read)? {
+                        Some(ref values) if values.len() != nodes_to_read.len() => {
+                            error!("read_parallel, server returned {} values for {} nodes", values.len(), nodes_to_read.len());
+                            Err(StatusCode::BadUnexpectedError)
+                        }
+                        Some(values) => Ok(values),
+                        None => Err(StatusCode::BadUnexpectedError)
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut values = Vec::with_capacity(nodes_to_read.len());
+        for read in reads {
+            // A read thread which panicked has no values to return
+            values.extend(read.join().unwrap_or(Err(StatusCode::BadUnexpectedError))?);
+        }
+        Ok(values)
+    }
+
+    /// Disconnects every session of the pool
+    pub fn disconnect(&self) {
+        self.sessions.iter().for_each(|session| {
+            let mut session = trace_write_lock_unwrap!(session);
+            session.disconnect();
+        });
+    }
+
+    /// Makes a call on the next session in turn which is connected and not busy with another call.
+    /// If there is none, the call waits for the next session in turn.
+    fn with_session<F, R>(&self, f: F) -> Result<R, StatusCode> where F: FnOnce(&mut Session) -> Result<R, StatusCode> {
+        if self.sessions.is_empty() {
+            error!("Session pool is empty");
+            return Err(StatusCode::BadNotConnected);
+        }
+        let len = self.sessions.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        for i in 0..len {
+            if let Ok(mut session) = self.sessions[(start + i) % len].try_write() {
+                if session.is_connected() {
+                    return f(&mut session);
+                }
+            }
+        }
+        let mut session = trace_write_lock_unwrap!(self.sessions[start % len]);
+        f(&mut session)
+    }
+}
+
+/// Splits a number of items into the start and end of the items for each of a number of parts.
+/// The sizes of the parts differ by at most one and there are no empty parts, so there are fewer
+/// parts than asked for if there are fewer items.
+pub(crate) fn split_evenly(items: usize, parts: usize) -> Vec<(usize, usize)> {
+    let parts = parts.min(items);
+    let mut start = 0;
+    (0..parts).map(|part| {
+        let size = items / parts + if part < items % parts { 1 } else { 0 };
+        let range = (start, start + size);
+        start += size;
+        range
+    }).collect()
+}
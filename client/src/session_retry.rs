@@ -1,3 +1,5 @@
+use std::cmp;
+
 use chrono::{DateTime, TimeZone, Utc};
 use time::Duration;
 
@@ -19,6 +21,10 @@ pub enum Answer {
 /// a `retry_interval` for the period of time in MS between each retry. Note that the policy retains
 /// its own minimum retry interval and will not retry any faster than that.
 ///
+/// The policy may also set a `max_retry_interval` larger than the `retry_interval`. In that case
+/// the interval doubles with each failed attempt until it reaches the maximum, i.e. the retries
+/// back off exponentially.
+///
 /// Once a connection succeeds, the retry limit is reset.
 #[derive(Debug, PartialEq, Clone)]
 pub struct SessionRetryPolicy {
@@ -30,6 +36,9 @@ pub struct SessionRetryPolicy {
     retry_limit: Option<u32>,
     /// Interval between retries in milliseconds
     retry_interval: u32,
+    /// Maximum interval between retries in milliseconds when backing off. When this is the same
+    /// as the retry interval, the interval does not change between retries.
+    max_retry_interval: u32,
     /// The number of failed attempts so far since the last connection. When the connection succeeds
     /// this value is reset.
     retry_count: u32,
@@ -50,6 +59,8 @@ impl SessionRetryPolicy {
     pub const DEFAULT_RETRY_INTERVAL_MS: u32 = 10000;
     /// The minimum retry interval
     pub const MIN_RETRY_INTERVAL_MS: u32 = 500;
+    /// The default maximum interval that the retry interval backs off to.
+    pub const DEFAULT_MAX_RETRY_INTERVAL_MS: u32 = 120000;
     /// The default session timeout interval in millis
     pub const DEFAULT_SESSION_TIMEOUT_MS: f64 = std::f64::MAX;
 
@@ -63,6 +74,7 @@ impl SessionRetryPolicy {
             last_attempt: Self::last_attempt_default(),
             retry_limit: Some(retry_limit),
             retry_interval,
+            max_retry_interval: retry_interval,
         }
     }
 
@@ -76,6 +88,7 @@ impl SessionRetryPolicy {
             last_attempt: Self::last_attempt_default(),
             retry_limit: None,
             retry_interval,
+            max_retry_interval: retry_interval,
         }
     }
 
@@ -92,6 +105,20 @@ impl SessionRetryPolicy {
         self.session_timeout
     }
 
+    /// Sets the maximum interval that the retry interval backs off to after consecutive failed
+    /// attempts. A value less than the retry interval disables the back off.
+    pub fn set_max_retry_interval(&mut self, max_retry_interval: u32) {
+        self.max_retry_interval = cmp::max(max_retry_interval, self.retry_interval);
+    }
+
+    /// Returns the interval to wait before the next attempt. This is the retry interval, doubled
+    /// for each failed attempt after the first up to the maximum retry interval.
+    pub fn retry_interval(&self) -> u32 {
+        let backoff = cmp::min(self.retry_count.saturating_sub(1), 31);
+        let retry_interval = (self.retry_interval as u64) << backoff;
+        cmp::min(retry_interval, self.max_retry_interval as u64) as u32
+    }
+
     pub fn retry_count(&self) -> u32 {
         self.retry_count
     }
@@ -125,7 +152,7 @@ impl SessionRetryPolicy {
 
         // Look at how much time has elapsed since the last attempt
         let elapsed = now - self.last_attempt;
-        let retry_interval = Duration::milliseconds(self.retry_interval as i64);
+        let retry_interval = Duration::milliseconds(self.retry_interval() as i64);
         if retry_interval > elapsed {
             // Wait a bit
             Answer::WaitFor((retry_interval - elapsed).num_milliseconds() as u32)
@@ -160,6 +187,34 @@ fn session_retry() {
     assert_eq!(session_retry.should_retry_connect(now), Answer::WaitFor(1000));
 }
 
+#[test]
+fn session_retry_backoff() {
+    let mut session_retry = SessionRetryPolicy::infinity(0.0, 1000);
+    session_retry.set_max_retry_interval(5000);
+
+    let now = Utc::now();
+    session_retry.set_last_attempt(now);
+
+    // Interval doubles on each failure after the first until it hits the maximum
+    let expected = [1000, 1000, 2000, 4000, 5000, 5000];
+    expected.iter().for_each(|retry_interval| {
+        assert_eq!(session_retry.retry_interval(), *retry_interval);
+        assert_eq!(session_retry.should_retry_connect(now), Answer::WaitFor(*retry_interval));
+        session_retry.increment_retry_count();
+    });
+    session_retry.retry_count = 1000;
+    assert_eq!(session_retry.retry_interval(), 5000);
+
+    // Success resets the interval
+    session_retry.reset_retry_count();
+    assert_eq!(session_retry.retry_interval(), 1000);
+
+    // A maximum lower than the interval means no back off
+    session_retry.set_max_retry_interval(0);
+    session_retry.retry_count = 5;
+    assert_eq!(session_retry.retry_interval(), 1000);
+}
+
 #[test]
 fn session_retry_infinity() {
     let session_retry = SessionRetryPolicy::infinity(444.444, 1000);
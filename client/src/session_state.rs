@@ -1,22 +1,26 @@
 use std;
 use std::u32;
+use std::collections::HashSet;
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use chrono;
+use futures::sync::oneshot;
 
 use opcua_core::{
-    comms::secure_channel::SecureChannel,
+    comms::{secure_channel::SecureChannel, buffer_pool::BufferPool},
     crypto::SecurityPolicy,
     handle::Handle,
 };
 
 use opcua_types::{
     *,
+    node_ids::VariableId,
     service_types::*,
     status_code::StatusCode,
 };
 
-use crate::{message_queue::MessageQueue, callbacks::OnSessionClosed};
+use crate::{message_queue::MessageQueue, callbacks::{OnSessionClosed, OnServerStateChange}};
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum ConnectionState {
@@ -50,6 +54,10 @@ pub(crate) struct SessionState {
     receive_buffer_size: usize,
     /// Maximum message size
     max_message_size: usize,
+    /// Idle time in milliseconds before TCP keep-alive probes are sent, or 0 for none
+    tcp_keep_alive: u32,
+    /// Pool of send buffers, kept across reconnects
+    buffer_pool: BufferPool,
     /// The session's id - used for diagnostic info
     session_id: NodeId,
     /// The sesion authentication token, used for session activation
@@ -63,10 +71,22 @@ pub(crate) struct SessionState {
     /// A flag which tells client to wait for a publish response before sending any new publish
     /// requests
     wait_for_publish_response: bool,
+    /// Maximum number of publish requests awaiting a response from the server
+    max_publish_requests: usize,
+    /// Request handles of the publish requests awaiting a response from the server
+    publish_requests: HashSet<u32>,
     /// The message queue
     message_queue: Arc<RwLock<MessageQueue>>,
     /// Connection closed callback
     session_closed_callback: Option<Box<dyn OnSessionClosed + Send + Sync + 'static>>,
+    /// When the last request was sent, so the keep-alive can tell if the session is idle
+    last_request_time: Instant,
+    /// Request handle of the keep-alive read awaiting a response from the server
+    keep_alive_request: Option<u32>,
+    /// The state of the server as last read by the keep-alive
+    server_state: Option<ServerState>,
+    /// Server state change callback
+    server_state_callback: Option<Box<dyn OnServerStateChange + Send + Sync + 'static>>,
 }
 
 impl OnSessionClosed for SessionState {
@@ -92,6 +112,10 @@ impl SessionState {
     const SEND_BUFFER_SIZE: usize = 65536;
     const RECEIVE_BUFFER_SIZE: usize = 65536;
     const MAX_BUFFER_SIZE: usize = 65536;
+    const DEFAULT_TCP_KEEP_ALIVE: u32 = 30 * 1000;
+    const DEFAULT_MAX_PUBLISH_REQUESTS: usize = 2;
+    /// A connection only needs a send buffer and a scratch buffer for securing chunks
+    const MAX_POOLED_BUFFERS: usize = 2;
 
     /// Used for synchronous polling
     const SYNC_POLLING_PERIOD: u64 = 50;
@@ -104,6 +128,8 @@ impl SessionState {
             send_buffer_size: Self::SEND_BUFFER_SIZE,
             receive_buffer_size: Self::RECEIVE_BUFFER_SIZE,
             max_message_size: Self::MAX_BUFFER_SIZE,
+            tcp_keep_alive: Self::DEFAULT_TCP_KEEP_ALIVE,
+            buffer_pool: BufferPool::new(Self::MAX_POOLED_BUFFERS, Self::SEND_BUFFER_SIZE * 2),
             request_handle: Handle::new(Self::FIRST_REQUEST_HANDLE),
             session_id: NodeId::null(),
             authentication_token: NodeId::null(),
@@ -111,7 +137,13 @@ impl SessionState {
             message_queue,
             subscription_acknowledgements: Vec::new(),
             wait_for_publish_response: false,
+            max_publish_requests: Self::DEFAULT_MAX_PUBLISH_REQUESTS,
+            publish_requests: HashSet::new(),
             session_closed_callback: None,
+            last_request_time: Instant::now(),
+            keep_alive_request: None,
+            server_state: None,
+            server_state_callback: None,
         }
     }
 
@@ -135,10 +167,28 @@ impl SessionState {
         self.request_timeout
     }
 
+    /// Sets how long in milliseconds to wait for the response to a request. The timeout is also
+    /// sent to the server as the timeout hint of the request.
+    pub fn set_request_timeout(&mut self, request_timeout: u32) {
+        self.request_timeout = request_timeout;
+    }
+
     pub fn send_buffer_size(&self) -> usize {
         self.send_buffer_size
     }
 
+    pub fn tcp_keep_alive(&self) -> u32 {
+        self.tcp_keep_alive
+    }
+
+    pub fn set_tcp_keep_alive(&mut self, tcp_keep_alive: u32) {
+        self.tcp_keep_alive = tcp_keep_alive;
+    }
+
+    pub fn buffer_pool(&self) -> BufferPool {
+        self.buffer_pool.clone()
+    }
+
     pub fn subscription_acknowledgements(&mut self) -> Vec<SubscriptionAcknowledgement> {
         self.subscription_acknowledgements.drain(..).collect()
     }
@@ -159,6 +209,10 @@ impl SessionState {
         self.session_closed_callback = Some(Box::new(session_closed_callback));
     }
 
+    pub fn set_server_state_callback<CB>(&mut self, server_state_callback: CB) where CB: OnServerStateChange + Send + Sync + 'static {
+        self.server_state_callback = Some(Box::new(server_state_callback));
+    }
+
     pub(crate) fn connection_state(&self) -> Arc<RwLock<ConnectionState>> {
         self.connection_state.clone()
     }
@@ -176,6 +230,87 @@ impl SessionState {
         self.wait_for_publish_response = wait_for_publish_response;
     }
 
+    pub fn max_publish_requests(&self) -> usize {
+        self.max_publish_requests
+    }
+
+    pub fn set_max_publish_requests(&mut self, max_publish_requests: usize) {
+        // At least one request must be outstanding for the server to publish anything
+        self.max_publish_requests = max_publish_requests.max(1);
+    }
+
+    /// Returns the number of publish requests awaiting a response from the server
+    pub fn outstanding_publish_requests(&self) -> usize {
+        self.publish_requests.len()
+    }
+
+    /// Called when a response arrives for an asynchronous request. Returns `true` if the request
+    /// was a publish request.
+    pub(crate) fn publish_response_received(&mut self, request_handle: u32) -> bool {
+        self.publish_requests.remove(&request_handle)
+    }
+
+    /// Forgets the outstanding publish requests, e.g. because they were sent on a connection that
+    /// has been lost and will never be answered.
+    pub(crate) fn clear_publish_requests(&mut self) {
+        self.publish_requests.clear();
+    }
+
+    /// Sends publish requests until the maximum number of them are awaiting a response. Any
+    /// acknowledgements go with the first request. Returns the number of requests sent.
+    pub(crate) fn async_publish_to_limit(&mut self) -> usize {
+        let mut sent = 0;
+        while self.publish_requests.len() < self.max_publish_requests {
+            let subscription_acknowledgements = self.subscription_acknowledgements();
+            if self.async_publish(&subscription_acknowledgements).is_err() {
+                break;
+            }
+            sent += 1;
+        }
+        sent
+    }
+
+    /// Returns how long it is since the last request was sent
+    pub(crate) fn idle_time(&self) -> Duration {
+        self.last_request_time.elapsed()
+    }
+
+    /// Reads the state of the server to keep an idle session alive. The response is handled
+    /// asynchronously.
+    pub(crate) fn async_keep_alive(&mut self) -> Result<u32, StatusCode> {
+        let request = ReadRequest {
+            request_header: self.make_request_header(),
+            max_age: 0f64,
+            timestamps_to_return: TimestampsToReturn::Neither,
+            nodes_to_read: Some(vec![ReadValueId::from(NodeId::from(&VariableId::Server_ServerStatus_State))]),
+        };
+        let request_handle = self.async_send_request(request, true)?;
+        self.keep_alive_request = Some(request_handle);
+        Ok(request_handle)
+    }
+
+    /// Called when a response arrives for an asynchronous request. Returns `true` if the request
+    /// was the keep-alive read.
+    pub(crate) fn keep_alive_response_received(&mut self, request_handle: u32) -> bool {
+        if self.keep_alive_request == Some(request_handle) {
+            self.keep_alive_request = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Records the state of the server read by the keep-alive and tells the callback if it has changed
+    pub(crate) fn set_server_state(&mut self, server_state: ServerState) {
+        if self.server_state != Some(server_state) {
+            info!("Server state is {:?}", server_state);
+            self.server_state = Some(server_state);
+            if let Some(ref mut server_state_callback) = self.server_state_callback {
+                server_state_callback.server_state_change(server_state);
+            }
+        }
+    }
+
     /// Construct a request header for the session. All requests after create session are expected
     /// to supply an authentication token.
     pub fn make_request_header(&mut self) -> RequestHeader {
@@ -198,6 +333,7 @@ impl SessionState {
             subscription_acknowledgements: if subscription_acknowledgements.is_empty() { None } else { Some(subscription_acknowledgements.to_vec()) },
         };
         let request_handle = self.async_send_request(request, true)?;
+        self.publish_requests.insert(request_handle);
         debug!("async_publish, request sent with handle {}", request_handle);
         Ok(request_handle)
     }
@@ -217,6 +353,8 @@ impl SessionState {
         self.authentication_token = NodeId::null();
         self.request_handle.reset();
         self.monitored_item_handle.reset();
+        self.keep_alive_request = None;
+        self.server_state = None;
 
         // Clear the message queue
         {
@@ -228,13 +366,7 @@ impl SessionState {
     /// Asynchronously sends a request. The return value is the request handle of the request
     pub(crate) fn async_send_request<T>(&mut self, request: T, is_async: bool) -> Result<u32, StatusCode> where T: Into<SupportedMessage> {
         let request = request.into();
-        match request {
-            SupportedMessage::OpenSecureChannelRequest(_) | SupportedMessage::CloseSecureChannelRequest(_) => {}
-            _ => {
-                // Make sure secure channel token hasn't expired
-                let _ = self.ensure_secure_channel_token();
-            }
-        }
+        self.prepare_to_send(&request);
 
         // TODO should error here if not connected
 
@@ -245,6 +377,32 @@ impl SessionState {
         Ok(request_handle)
     }
 
+    /// Sends a request. The return value is a receiver that the response will be sent to when it arrives
+    pub(crate) fn send_request_with_response_channel<T>(&mut self, request: T) -> Result<(u32, oneshot::Receiver<SupportedMessage>), StatusCode> where T: Into<SupportedMessage> {
+        let request = request.into();
+        self.prepare_to_send(&request);
+
+        let request_handle = request.request_handle();
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut message_queue = trace_write_lock_unwrap!(self.message_queue);
+            message_queue.add_request_with_response_channel(request, tx);
+        }
+
+        Ok((request_handle, rx))
+    }
+
+    fn prepare_to_send(&mut self, request: &SupportedMessage) {
+        self.last_request_time = Instant::now();
+        match request {
+            SupportedMessage::OpenSecureChannelRequest(_) | SupportedMessage::CloseSecureChannelRequest(_) => {}
+            _ => {
+                // Make sure secure channel token hasn't expired
+                let _ = self.ensure_secure_channel_token();
+            }
+        }
+    }
+
     /// Wait for a response with a matching request handle. If request handle is 0 then no match
     /// is performed and in fact the function is expected to receive no messages except asynchronous
     /// and housekeeping events from the server. A 0 handle will cause the wait to process at most
@@ -267,7 +425,7 @@ impl SessionState {
                 if request_duration.num_milliseconds() >= request_timeout as i64 {
                     info!("Timeout waiting for response from server");
                     self.request_has_timed_out(request_handle);
-                    return Err(StatusCode::BadTimeout);
+                    return Err(StatusCode::BadRequestTimeout);
                 }
                 // Sleep before trying again
                 std::thread::sleep(std::time::Duration::from_millis(Self::SYNC_POLLING_PERIOD));
@@ -291,7 +449,7 @@ impl SessionState {
     }
 
     /// Checks if secure channel token needs to be renewed and renews it
-    fn ensure_secure_channel_token(&mut self) -> Result<(), StatusCode> {
+    pub(crate) fn ensure_secure_channel_token(&mut self) -> Result<(), StatusCode> {
         let should_renew_security_token = {
             let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
             secure_channel.should_renew_security_token()
@@ -13,9 +13,9 @@ use std::sync::{Arc, Mutex};
 use std::marker::Sync;
 
 use opcua_types::*;
-use opcua_types::service_types::{DataChangeNotification, ReadValueId};
+use opcua_types::service_types::{DataChangeNotification, EventNotificationList, ReadValueId, StatusChangeNotification};
 
-use crate::callbacks::OnDataChange;
+use crate::callbacks::{OnEvent, SubscriptionCallbacks};
 
 pub(crate) struct CreateMonitoredItem {
     pub id: u32,
@@ -25,12 +25,14 @@ pub(crate) struct CreateMonitoredItem {
     pub queue_size: u32,
     pub discard_oldest: bool,
     pub sampling_interval: f64,
+    pub filter: ExtensionObject,
 }
 
 pub(crate) struct ModifyMonitoredItem {
     pub id: u32,
     pub sampling_interval: f64,
     pub queue_size: u32,
+    pub filter: ExtensionObject,
 }
 
 #[derive(Debug)]
@@ -49,6 +51,8 @@ pub struct MonitoredItem {
     monitoring_mode: MonitoringMode,
     /// Sampling interval
     sampling_interval: f64,
+    /// Filter, e.g. a data change filter
+    filter: ExtensionObject,
     /// Last value of the item
     value: DataValue,
     /// Triggered items
@@ -69,6 +73,7 @@ impl MonitoredItem {
             },
             monitoring_mode: MonitoringMode::Reporting,
             discard_oldest: false,
+            filter: ExtensionObject::null(),
             value: DataValue::null(),
             client_handle,
             triggered_items: BTreeSet::new(),
@@ -95,6 +100,8 @@ impl MonitoredItem {
 
     pub fn discard_oldest(&self) -> bool { self.discard_oldest }
 
+    pub fn filter(&self) -> &ExtensionObject { &self.filter }
+
     pub(crate) fn set_id(&mut self, value: u32) {
         self.id = value;
     }
@@ -119,6 +126,10 @@ impl MonitoredItem {
         self.discard_oldest = discard_oldest;
     }
 
+    pub(crate) fn set_filter(&mut self, filter: ExtensionObject) {
+        self.filter = filter;
+    }
+
     pub(crate) fn set_triggering(&mut self, links_to_add: &[u32], links_to_remove: &[u32]) {
         links_to_remove.iter().for_each(|i| { self.triggered_items.remove(i); });
         links_to_add.iter().for_each(|i| { self.triggered_items.insert(*i); });
@@ -144,19 +155,23 @@ pub struct Subscription {
     publishing_enabled: bool,
     /// Priority
     priority: u8,
-    /// The change callback will be what is called if any monitored item changes within a cycle.
-    /// The monitored item is referenced by its id
-    data_change_callback: Arc<Mutex<OnDataChange + Send + Sync>>,
+    /// The callbacks which are called with the data changes, events, keep-alives and status changes
+    /// of the subscription
+    callbacks: Arc<Mutex<dyn SubscriptionCallbacks + Send + Sync>>,
+    /// The callbacks of event monitored items (key = monitored_item_id)
+    event_callbacks: HashMap<u32, Arc<Mutex<dyn OnEvent + Send + Sync>>>,
     /// A map of monitored items associated with the subscription (key = monitored_item_id)
     monitored_items: HashMap<u32, MonitoredItem>,
     /// A map of client handle to monitored item id
     client_handles: HashMap<u32, u32>,
+    /// Sequence number of the last notification message received, 0 if none received
+    last_sequence_number: u32,
 }
 
 impl Subscription {
-    /// Creates a new subscription using the supplied parameters and the supplied data change callback.
+    /// Creates a new subscription using the supplied parameters and the supplied callbacks.
     pub fn new(subscription_id: u32, publishing_interval: f64, lifetime_count: u32, max_keep_alive_count: u32, max_notifications_per_publish: u32,
-               publishing_enabled: bool, priority: u8, data_change_callback: Arc<Mutex<dyn OnDataChange + Send + Sync>>)
+               publishing_enabled: bool, priority: u8, callbacks: Arc<Mutex<dyn SubscriptionCallbacks + Send + Sync>>)
                -> Subscription
     {
         Subscription {
@@ -167,9 +182,11 @@ impl Subscription {
             max_notifications_per_publish,
             publishing_enabled,
             priority,
-            data_change_callback,
+            callbacks,
+            event_callbacks: HashMap::new(),
             monitored_items: HashMap::new(),
             client_handles: HashMap::new(),
+            last_sequence_number: 0,
         }
     }
 
@@ -189,7 +206,14 @@ impl Subscription {
 
     pub fn priority(&self) -> u8 { self.priority }
 
-    pub fn data_change_callback(&self) -> Arc<Mutex<dyn OnDataChange + Send + Sync>> { self.data_change_callback.clone() }
+    pub fn callbacks(&self) -> Arc<Mutex<dyn SubscriptionCallbacks + Send + Sync>> { self.callbacks.clone() }
+
+    /// Returns the callback that receives the events of the monitored item, if it has one
+    pub fn event_callback(&self, monitored_item_id: u32) -> Option<Arc<Mutex<dyn OnEvent + Send + Sync>>> {
+        self.event_callbacks.get(&monitored_item_id).cloned()
+    }
+
+    pub fn last_sequence_number(&self) -> u32 { self.last_sequence_number }
 
     pub(crate) fn set_publishing_interval(&mut self, publishing_interval: f64) { self.publishing_interval = publishing_interval; }
 
@@ -203,6 +227,22 @@ impl Subscription {
 
     pub(crate) fn set_publishing_enabled(&mut self, publishing_enabled: bool) { self.publishing_enabled = publishing_enabled; }
 
+    /// Records the sequence number of a received notification message and returns the sequence
+    /// numbers of any messages before it that never arrived. A keep alive carries the sequence
+    /// number of the next message to be sent rather than one of its own.
+    pub(crate) fn missed_sequence_numbers(&mut self, sequence_number: u32, keep_alive: bool) -> Vec<u32> {
+        let received_up_to = if keep_alive { sequence_number.wrapping_sub(1) } else { sequence_number };
+        let missed = if self.last_sequence_number == 0 || received_up_to <= self.last_sequence_number {
+            // Nothing received before, or the sequence has wrapped or started over
+            Vec::new()
+        } else {
+            let end = if keep_alive { received_up_to + 1 } else { received_up_to };
+            ((self.last_sequence_number + 1)..end).collect()
+        };
+        self.last_sequence_number = received_up_to;
+        missed
+    }
+
     pub(crate) fn insert_monitored_items(&mut self, items_to_create: &[CreateMonitoredItem]) {
         items_to_create.iter().for_each(|i| {
             let mut monitored_item = MonitoredItem::new(i.client_handle);
@@ -212,6 +252,7 @@ impl Subscription {
             monitored_item.set_sampling_interval(i.sampling_interval);
             monitored_item.set_queue_size(i.queue_size);
             monitored_item.set_item_to_monitor(i.item_to_monitor.clone());
+            monitored_item.set_filter(i.filter.clone());
 
             let client_handle = monitored_item.client_handle();
             let monitored_item_id = monitored_item.id();
@@ -225,6 +266,7 @@ impl Subscription {
             if let Some(ref mut monitored_item) = self.monitored_items.get_mut(&i.id) {
                 monitored_item.set_sampling_interval(i.sampling_interval);
                 monitored_item.set_queue_size(i.queue_size);
+                monitored_item.set_filter(i.filter.clone());
             }
         });
     }
@@ -235,7 +277,18 @@ impl Subscription {
             if let Some(monitored_item) = self.monitored_items.remove(&id) {
                 let _ = self.client_handles.remove(&monitored_item.client_handle());
             }
-        })
+            let _ = self.event_callbacks.remove(&id);
+        });
+        // The server deletes any triggering links to the deleted items
+        self.monitored_items.values_mut().for_each(|monitored_item| {
+            monitored_item.set_triggering(&[], items_to_delete);
+        });
+    }
+
+    pub(crate) fn set_event_callback(&mut self, monitored_item_id: u32, event_callback: Arc<Mutex<dyn OnEvent + Send + Sync>>) {
+        if self.monitored_items.contains_key(&monitored_item_id) {
+            self.event_callbacks.insert(monitored_item_id, event_callback);
+        }
     }
 
     pub(crate) fn set_triggering(&mut self, triggering_item_id: u32, links_to_add: &[u32], links_to_remove: &[u32]) {
@@ -272,8 +325,45 @@ impl Subscription {
                 .map(|id| self.monitored_items.get(&id).unwrap()).collect();
 
             // Call the call back with the changes we collected
-            let mut cb = trace_lock_unwrap!(self.data_change_callback);
-            cb.data_change(data_change_items);
+            let mut cb = trace_lock_unwrap!(self.callbacks);
+            cb.on_data_change(data_change_items);
         }
     }
-}
\ No newline at end of file
+
+    /// Sends the fields of each event to the callback of the monitored item it belongs to, or to the
+    /// subscription's callbacks if the item has no callback of its own.
+    pub(crate) fn events(&mut self, event_notifications: &[EventNotificationList]) {
+        for n in event_notifications {
+            if let Some(ref events) = n.events {
+                for e in events {
+                    let monitored_item_id = if let Some(monitored_item_id) = self.monitored_item_id_from_handle(e.client_handle) {
+                        monitored_item_id
+                    } else {
+                        continue;
+                    };
+                    let monitored_item = self.monitored_items.get(&monitored_item_id).unwrap();
+                    let event_fields = e.event_fields.as_ref().map(|v| v.as_slice()).unwrap_or(&[]);
+                    if let Some(event_callback) = self.event_callbacks.get(&monitored_item_id) {
+                        let mut cb = trace_lock_unwrap!(event_callback);
+                        cb.event(monitored_item, event_fields);
+                    } else {
+                        let mut cb = trace_lock_unwrap!(self.callbacks);
+                        cb.on_event(monitored_item, event_fields);
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn status_change(&mut self, status_change_notifications: &[StatusChangeNotification]) {
+        let mut cb = trace_lock_unwrap!(self.callbacks);
+        status_change_notifications.iter().for_each(|n| {
+            cb.on_status_change(n.status, &n.diagnostic_info);
+        });
+    }
+
+    pub(crate) fn keep_alive(&mut self, publish_time: &DateTime) {
+        let mut cb = trace_lock_unwrap!(self.callbacks);
+        cb.on_keep_alive(publish_time);
+    }
+}
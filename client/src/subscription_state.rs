@@ -1,9 +1,15 @@
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
-use opcua_types::service_types::DataChangeNotification;
+use opcua_types::{
+    DateTime,
+    status_code::StatusCode,
+    service_types::{DataChangeNotification, EventNotificationList, StatusChangeNotification},
+};
 
 use crate::{
+    callbacks::OnEvent,
     subscription::*,
     subscription_timer::SubscriptionTimer,
 };
@@ -50,6 +56,18 @@ impl SubscriptionState {
         self.subscriptions.contains_key(&subscription_id)
     }
 
+    /// Returns the longest time the server may go without sending a publish response, keep-alive or
+    /// otherwise, which is the shortest keep-alive period of the subscriptions. There is no limit
+    /// when there are no subscriptions.
+    pub(crate) fn keep_alive_timeout(&self) -> Option<Duration> {
+        self.subscriptions.values()
+            .map(|subscription| {
+                let keep_alive_period = subscription.publishing_interval() * subscription.max_keep_alive_count() as f64;
+                Duration::from_millis(keep_alive_period as u64)
+            })
+            .min()
+    }
+
     pub fn get(&self, subscription_id: u32) -> Option<&Subscription> {
         self.subscriptions.get(&subscription_id)
     }
@@ -68,14 +86,37 @@ impl SubscriptionState {
         }
     }
 
+    /// Removes the subscription and cancels its timer so it stops sending publish requests
     pub(crate) fn delete_subscription(&mut self, subscription_id: u32) -> Option<Subscription> {
+        self.subscription_timers.retain(|timer| {
+            let mut timer = trace_write_lock_unwrap!(timer);
+            if timer.subscription_id() == subscription_id {
+                timer.cancel();
+                false
+            } else {
+                true
+            }
+        });
         self.subscriptions.remove(&subscription_id)
     }
 
-    pub(crate) fn set_publishing_mode(&mut self, subscription_ids: &[u32], publishing_enabled: bool) {
-        subscription_ids.iter().for_each(|subscription_id| {
-            if let Some(ref mut subscription) = self.subscriptions.get_mut(subscription_id) {
-                subscription.set_publishing_enabled(publishing_enabled);
+    /// Removes the subscriptions that a DeleteSubscriptions request deleted. A subscription which
+    /// the server does not know about is removed as well since it can never be used again.
+    pub(crate) fn delete_subscriptions(&mut self, subscription_ids: &[u32], results: &[StatusCode]) {
+        subscription_ids.iter().zip(results).for_each(|(subscription_id, result)| {
+            if result.is_good() || *result == StatusCode::BadSubscriptionIdInvalid {
+                let _ = self.delete_subscription(*subscription_id);
+            }
+        });
+    }
+
+    /// Sets the publishing mode of the subscriptions whose result from a SetPublishingMode request is good
+    pub(crate) fn set_publishing_mode(&mut self, subscription_ids: &[u32], results: &[StatusCode], publishing_enabled: bool) {
+        subscription_ids.iter().zip(results).for_each(|(subscription_id, result)| {
+            if result.is_good() {
+                if let Some(ref mut subscription) = self.subscriptions.get_mut(subscription_id) {
+                    subscription.set_publishing_enabled(publishing_enabled);
+                }
             }
         });
     }
@@ -86,6 +127,32 @@ impl SubscriptionState {
         }
     }
 
+    pub(crate) fn subscription_events(&mut self, subscription_id: u32, event_notifications: &[EventNotificationList]) {
+        if let Some(ref mut subscription) = self.subscriptions.get_mut(&subscription_id) {
+            subscription.events(event_notifications);
+        }
+    }
+
+    pub(crate) fn subscription_status_change(&mut self, subscription_id: u32, status_change_notifications: &[StatusChangeNotification]) {
+        if let Some(ref mut subscription) = self.subscriptions.get_mut(&subscription_id) {
+            subscription.status_change(status_change_notifications);
+        }
+    }
+
+    pub(crate) fn subscription_keep_alive(&mut self, subscription_id: u32, publish_time: &DateTime) {
+        if let Some(ref mut subscription) = self.subscriptions.get_mut(&subscription_id) {
+            subscription.keep_alive(publish_time);
+        }
+    }
+
+    pub(crate) fn missed_sequence_numbers(&mut self, subscription_id: u32, sequence_number: u32, keep_alive: bool) -> Vec<u32> {
+        if let Some(ref mut subscription) = self.subscriptions.get_mut(&subscription_id) {
+            subscription.missed_sequence_numbers(sequence_number, keep_alive)
+        } else {
+            Vec::new()
+        }
+    }
+
     pub(crate) fn insert_monitored_items(&mut self, subscription_id: u32, items_to_create: &[CreateMonitoredItem]) {
         if let Some(ref mut subscription) = self.subscriptions.get_mut(&subscription_id) {
             subscription.insert_monitored_items(items_to_create);
@@ -104,6 +171,12 @@ impl SubscriptionState {
         }
     }
 
+    pub(crate) fn set_event_callback(&mut self, subscription_id: u32, monitored_item_id: u32, event_callback: Arc<Mutex<dyn OnEvent + Send + Sync>>) {
+        if let Some(ref mut subscription) = self.subscriptions.get_mut(&subscription_id) {
+            subscription.set_event_callback(monitored_item_id, event_callback);
+        }
+    }
+
     pub(crate) fn set_triggering(&mut self, subscription_id: u32, triggering_item_id: u32, links_to_add: &[u32], links_to_remove: &[u32]) {
         if let Some(ref mut subscription) = self.subscriptions.get_mut(&subscription_id) {
             subscription.set_triggering(triggering_item_id, links_to_add, links_to_remove);
@@ -140,11 +140,10 @@ impl SubscriptionTimer {
                 if !wait_for_publish_response {
                     // We could not send the publish request if subscription is not reporting, or
                     // contains no monitored items but it probably makes no odds.
-                    debug!("Subscription timer for {} is sending a publish", subscription_id);
                     let mut session_state = trace_write_lock_unwrap!(session_state);
-                    // Send a publish request with any acknowledgements
-                    let subscription_acknowledgements = session_state.subscription_acknowledgements();
-                    let _ = session_state.async_publish(&subscription_acknowledgements);
+                    // Top up the publish requests awaiting a response, sending any acknowledgements
+                    let sent = session_state.async_publish_to_limit();
+                    debug!("Subscription timer for {} sent {} publish requests", subscription_id, sent);
                 }
                 Ok(())
             })
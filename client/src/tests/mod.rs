@@ -47,6 +47,18 @@ pub fn default_sample_config() -> ClientConfig {
                 security_policy: String::from(SecurityPolicy::Basic256Sha256.to_str()),
                 security_mode: String::from(MessageSecurityMode::SignAndEncrypt),
                 user_token_id: ANONYMOUS_USER_TOKEN_ID.to_string(),
+            }),
+            ("sample_aes128sha256rsaoaep", ClientEndpoint {
+                url: String::from("opc.tcp://127.0.0.1:4855/"),
+                security_policy: String::from(SecurityPolicy::Aes128Sha256RsaOaep.to_str()),
+                security_mode: String::from(MessageSecurityMode::SignAndEncrypt),
+                user_token_id: ANONYMOUS_USER_TOKEN_ID.to_string(),
+            }),
+            ("sample_aes256sha256rsapss", ClientEndpoint {
+                url: String::from("opc.tcp://127.0.0.1:4855/"),
+                security_policy: String::from(SecurityPolicy::Aes256Sha256RsaPss.to_str()),
+                security_mode: String::from(MessageSecurityMode::SignAndEncrypt),
+                user_token_id: ANONYMOUS_USER_TOKEN_ID.to_string(),
             })
         ])
         .default_endpoint("sample_none")
@@ -137,3 +149,723 @@ fn client_anonymous_user_tokens_id() {
     assert!(!config.is_valid());
 }
 
+
+#[test]
+fn message_queue_response_channel() {
+    use futures::{Future, sync::oneshot};
+    use opcua_types::{*, service_types::*};
+    use crate::message_queue::MessageQueue;
+
+    let mut message_queue = MessageQueue::new();
+    let _rx = message_queue.make_request_channel();
+
+    let request_header = RequestHeader::new(&NodeId::null(), &DateTime::now(), 123);
+    let request = ReadRequest {
+        request_header: request_header.clone(),
+        max_age: 0f64,
+        timestamps_to_return: TimestampsToReturn::Both,
+        nodes_to_read: None,
+    };
+    let (tx, rx) = oneshot::channel();
+    message_queue.add_request_with_response_channel(request.into(), tx);
+
+    let response = ReadResponse {
+        response_header: ResponseHeader::new_good(&request_header),
+        results: None,
+        diagnostic_infos: None,
+    };
+    message_queue.store_response(response.into());
+
+    // The response goes to the channel, not to the queue of stored responses
+    assert!(message_queue.take_response(123).is_none());
+    let response = rx.wait().unwrap();
+    assert_eq!(response.request_handle(), 123);
+}
+
+#[test]
+fn make_browse_path() {
+    use opcua_types::{NodeId, QualifiedName, node_ids::{ObjectId, ReferenceTypeId}};
+    use crate::session::Session;
+
+    // A path without a leading reference type follows hierarchical references
+    let browse_path = Session::make_browse_path(ObjectId::RootFolder.into(), "Objects/2:Device/2:Temperature").unwrap();
+    assert_eq!(browse_path.starting_node, ObjectId::RootFolder.into());
+    let elements = browse_path.relative_path.elements.unwrap();
+    assert_eq!(elements.len(), 3);
+    let expected_names = [QualifiedName::new(0, "Objects"), QualifiedName::new(2, "Device"), QualifiedName::new(2, "Temperature")];
+    elements.iter().zip(expected_names.iter()).for_each(|(element, expected_name)| {
+        assert_eq!(element.reference_type_id, ReferenceTypeId::HierarchicalReferences.into());
+        assert!(element.include_subtypes);
+        assert!(!element.is_inverse);
+        assert_eq!(&element.target_name, expected_name);
+    });
+
+    // Explicit reference types are preserved
+    let browse_path = Session::make_browse_path(NodeId::new(2, "Device"), ".2:Temperature").unwrap();
+    let elements = browse_path.relative_path.elements.unwrap();
+    assert_eq!(elements.len(), 1);
+    assert_eq!(elements[0].reference_type_id, ReferenceTypeId::Aggregates.into());
+
+    // Malformed paths are rejected
+    assert!(Session::make_browse_path(ObjectId::RootFolder.into(), "Objects/<2:Device").is_err());
+}
+
+#[test]
+fn subscription_missed_sequence_numbers() {
+    use std::sync::{Arc, Mutex};
+    use crate::{callbacks::DataChangeCallback, subscription::Subscription};
+
+    let mut subscription = Subscription::new(1, 1000.0, 30, 10, 0, true, 0, Arc::new(Mutex::new(DataChangeCallback::new(|_| {}))));
+
+    // Nothing can be missed before the first message
+    assert!(subscription.missed_sequence_numbers(1, false).is_empty());
+    assert!(subscription.missed_sequence_numbers(2, false).is_empty());
+    assert_eq!(subscription.last_sequence_number(), 2);
+
+    // A gap between messages
+    assert_eq!(subscription.missed_sequence_numbers(5, false), vec![3, 4]);
+
+    // A keep alive carries the next sequence number, so one matching it means nothing was missed
+    assert!(subscription.missed_sequence_numbers(6, true).is_empty());
+    assert_eq!(subscription.last_sequence_number(), 5);
+    assert_eq!(subscription.missed_sequence_numbers(8, true), vec![6, 7]);
+    assert_eq!(subscription.last_sequence_number(), 7);
+
+    // The server starting over is not a gap
+    assert!(subscription.missed_sequence_numbers(1, false).is_empty());
+    assert_eq!(subscription.last_sequence_number(), 1);
+}
+
+#[test]
+fn event_filter_builder() {
+    use opcua_types::{*, node_ids::ObjectTypeId, service_types::{ElementOperand, FilterOperator, LiteralOperand, SimpleAttributeOperand}};
+    use crate::event_filter::EventFilterBuilder;
+
+    let decoding_limits = DecodingLimits::default();
+
+    // Select clauses are browse paths of BaseEventType fields
+    let filter = EventFilterBuilder::new().select("Message").select("EnabledState/Id").build();
+    let select_clauses = filter.select_clauses.unwrap();
+    assert_eq!(select_clauses.len(), 2);
+    assert_eq!(select_clauses[0].type_definition_id, ObjectTypeId::BaseEventType.into());
+    assert_eq!(select_clauses[0].browse_path, Some(vec![QualifiedName::new(0, "Message")]));
+    assert_eq!(select_clauses[1].browse_path, Some(vec![QualifiedName::new(0, "EnabledState"), QualifiedName::new(0, "Id")]));
+    assert_eq!(select_clauses[1].attribute_id, AttributeId::Value as u32);
+    assert!(filter.where_clause.elements.is_none());
+
+    // A single where clause is the only element
+    let filter = EventFilterBuilder::new().select("Message").where_clause("Severity", FilterOperator::GreaterThan, 100u16).build();
+    let elements = filter.where_clause.elements.unwrap();
+    assert_eq!(elements.len(), 1);
+    assert_eq!(elements[0].filter_operator, FilterOperator::GreaterThan);
+    let operands = elements[0].filter_operands.as_ref().unwrap();
+    let field = operands[0].decode_inner::<SimpleAttributeOperand>(&decoding_limits).unwrap();
+    assert_eq!(field.browse_path, Some(vec![QualifiedName::new(0, "Severity")]));
+    let value = operands[1].decode_inner::<LiteralOperand>(&decoding_limits).unwrap();
+    assert_eq!(value.value, Variant::from(100u16));
+
+    // Three where clauses are joined by two ands which only refer to later elements
+    let filter = EventFilterBuilder::new()
+        .where_clause("Severity", FilterOperator::GreaterThan, 100u16)
+        .where_clause("SourceName", FilterOperator::Like, "Pump%")
+        .where_of_type(ObjectTypeId::AuditEventType)
+        .build();
+    let elements = filter.where_clause.elements.unwrap();
+    let operator_and_operands = elements.iter().map(|element| {
+        let operands = element.filter_operands.as_ref().unwrap().iter()
+            .map(|operand| operand.decode_inner::<ElementOperand>(&decoding_limits).map(|operand| operand.index).unwrap_or(u32::max_value()))
+            .collect::<Vec<u32>>();
+        (element.filter_operator, operands)
+    }).collect::<Vec<(FilterOperator, Vec<u32>)>>();
+    assert_eq!(operator_and_operands[0], (FilterOperator::And, vec![2, 1]));
+    assert_eq!(operator_and_operands[1], (FilterOperator::And, vec![3, 4]));
+    assert_eq!(operator_and_operands[2].0, FilterOperator::GreaterThan);
+    assert_eq!(operator_and_operands[3].0, FilterOperator::Like);
+    assert_eq!(operator_and_operands[4].0, FilterOperator::OfType);
+}
+
+#[test]
+fn subscription_events() {
+    use std::sync::{Arc, Mutex};
+    use opcua_types::{*, service_types::{EventFieldList, EventNotificationList, ReadValueId}};
+    use crate::{callbacks::{DataChangeCallback, EventCallback}, subscription::{CreateMonitoredItem, Subscription}};
+
+    let mut subscription = Subscription::new(1, 1000.0, 30, 10, 0, true, 0, Arc::new(Mutex::new(DataChangeCallback::new(|_| {}))));
+    let create_monitored_item = |id: u32, client_handle: u32| CreateMonitoredItem {
+        id,
+        client_handle,
+        item_to_monitor: ReadValueId {
+            node_id: NodeId::new(0, 2253),
+            attribute_id: AttributeId::EventNotifier as u32,
+            index_range: UAString::null(),
+            data_encoding: QualifiedName::null(),
+        },
+        monitoring_mode: MonitoringMode::Reporting,
+        queue_size: 10,
+        discard_oldest: true,
+        sampling_interval: 0f64,
+        filter: ExtensionObject::null(),
+    };
+    subscription.insert_monitored_items(&[create_monitored_item(10, 100), create_monitored_item(20, 200)]);
+
+    // Only the first item has a callback
+    let received = Arc::new(Mutex::new(Vec::new()));
+    {
+        let received = received.clone();
+        subscription.set_event_callback(10, Arc::new(Mutex::new(EventCallback::new(move |monitored_item, event_fields| {
+            received.lock().unwrap().push((monitored_item.id(), event_fields.to_vec()));
+        }))));
+    }
+    assert!(subscription.event_callback(10).is_some());
+    assert!(subscription.event_callback(20).is_none());
+
+    let event_field_list = |client_handle: u32, severity: u16| EventFieldList {
+        client_handle,
+        event_fields: Some(vec![Variant::from("Message"), Variant::from(severity)]),
+    };
+    subscription.events(&[EventNotificationList {
+        events: Some(vec![event_field_list(100, 1), event_field_list(200, 2), event_field_list(999, 3), event_field_list(100, 4)]),
+    }]);
+    {
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0], (10, vec![Variant::from("Message"), Variant::from(1u16)]));
+        assert_eq!(received[1].1[1], Variant::from(4u16));
+    }
+
+    // Deleting the item removes its callback
+    subscription.delete_monitored_items(&[10]);
+    assert!(subscription.event_callback(10).is_none());
+}
+
+#[test]
+fn subscription_callbacks() {
+    use std::sync::{Arc, Mutex};
+    use opcua_types::{*, status_code::StatusCode, service_types::{EventFieldList, EventNotificationList, ReadValueId, StatusChangeNotification}};
+    use crate::{callbacks::SubscriptionCallbacks, subscription::{CreateMonitoredItem, MonitoredItem, Subscription}};
+
+    #[derive(Default)]
+    struct Received {
+        events: Vec<u32>,
+        status_changes: Vec<StatusCode>,
+        keep_alives: usize,
+    }
+
+    struct Callbacks(Arc<Mutex<Received>>);
+
+    impl SubscriptionCallbacks for Callbacks {
+        fn on_event(&mut self, monitored_item: &MonitoredItem, _event_fields: &[Variant]) {
+            self.0.lock().unwrap().events.push(monitored_item.id());
+        }
+
+        fn on_status_change(&mut self, status: StatusCode, _diagnostic_info: &DiagnosticInfo) {
+            self.0.lock().unwrap().status_changes.push(status);
+        }
+
+        fn on_keep_alive(&mut self, _publish_time: &DateTime) {
+            self.0.lock().unwrap().keep_alives += 1;
+        }
+    }
+
+    let received = Arc::new(Mutex::new(Received::default()));
+    let mut subscription = Subscription::new(1, 1000.0, 30, 10, 0, true, 0, Arc::new(Mutex::new(Callbacks(received.clone()))));
+    subscription.insert_monitored_items(&[CreateMonitoredItem {
+        id: 10,
+        client_handle: 100,
+        item_to_monitor: ReadValueId {
+            node_id: NodeId::new(0, 2253),
+            attribute_id: AttributeId::EventNotifier as u32,
+            index_range: UAString::null(),
+            data_encoding: QualifiedName::null(),
+        },
+        monitoring_mode: MonitoringMode::Reporting,
+        queue_size: 10,
+        discard_oldest: true,
+        sampling_interval: 0f64,
+        filter: ExtensionObject::null(),
+    }]);
+
+    // Events of an item without its own callback go to the subscription's callbacks
+    subscription.events(&[EventNotificationList {
+        events: Some(vec![EventFieldList { client_handle: 100, event_fields: None }]),
+    }]);
+    subscription.keep_alive(&DateTime::now());
+    subscription.keep_alive(&DateTime::now());
+    subscription.status_change(&[StatusChangeNotification {
+        status: StatusCode::BadTimeout,
+        diagnostic_info: DiagnosticInfo::null(),
+    }]);
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.events, vec![10]);
+    assert_eq!(received.keep_alives, 2);
+    assert_eq!(received.status_changes, vec![StatusCode::BadTimeout]);
+}
+
+#[test]
+fn type_dictionary_decode() {
+    use opcua_types::{BinaryEncoder, DecodingLimits, UAString, Variant, status_code::StatusCode};
+    use crate::custom_types::{FieldValue, TypeDictionary};
+
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<opc:TypeDictionary xmlns:opc="http://opcfoundation.org/BinarySchema/" xmlns:ua="http://opcfoundation.org/UA/"
+    xmlns:tns="urn:vendor:types" DefaultByteOrder="LittleEndian" TargetNamespace="urn:vendor:types">
+  <opc:Import Namespace="http://opcfoundation.org/UA/" />
+  <opc:EnumeratedType Name="Mode" LengthInBits="32">
+    <opc:EnumeratedValue Name="Off" Value="0" />
+    <opc:EnumeratedValue Name="On" Value="1" />
+  </opc:EnumeratedType>
+  <opc:StructuredType Name="Point" BaseType="ua:ExtensionObject">
+    <opc:Field Name="X" TypeName="opc:Int16" />
+    <opc:Field Name="Y" TypeName="opc:Int16" />
+  </opc:StructuredType>
+  <opc:StructuredType Name="Sample" BaseType="ua:ExtensionObject">
+    <opc:Field Name="ValueSpecified" TypeName="opc:Bit" />
+    <opc:Field Name="Reserved1" TypeName="opc:Bit" Length="7" />
+    <opc:Field Name="Id" TypeName="opc:Int32" />
+    <opc:Field Name="Value" TypeName="opc:Double" SwitchField="ValueSpecified" />
+    <opc:Field Name="NoOfReadings" TypeName="opc:Int32" />
+    <opc:Field Name="Readings" TypeName="opc:Float" LengthField="NoOfReadings" />
+    <opc:Field Name="Mode" TypeName="tns:Mode" />
+    <opc:Field Name="Position" TypeName="tns:Point" />
+    <opc:Field Name="Name" TypeName="opc:String" />
+  </opc:StructuredType>
+</opc:TypeDictionary>"#;
+
+    let type_dictionary = TypeDictionary::parse(xml.as_bytes()).unwrap();
+    assert_eq!(type_dictionary.target_namespace(), "urn:vendor:types");
+    assert!(type_dictionary.contains_structure("Sample"));
+    assert!(type_dictionary.contains_structure("Point"));
+    assert!(!type_dictionary.contains_structure("Mode"));
+
+    let encode_sample = |value_specified: bool| {
+        let mut body = Vec::new();
+        let _ = (value_specified as u8).encode(&mut body);
+        let _ = 42i32.encode(&mut body);
+        if value_specified {
+            let _ = 1.5f64.encode(&mut body);
+        }
+        let _ = 2i32.encode(&mut body);
+        let _ = 1f32.encode(&mut body);
+        let _ = 2f32.encode(&mut body);
+        let _ = 1i32.encode(&mut body);
+        let _ = 5i16.encode(&mut body);
+        let _ = (-5i16).encode(&mut body);
+        let _ = UAString::from("abc").encode(&mut body);
+        body
+    };
+    let decoding_limits = DecodingLimits::default();
+
+    let sample = type_dictionary.decode("Sample", &encode_sample(true), &decoding_limits).unwrap();
+    assert_eq!(sample.type_name, "Sample");
+    assert_eq!(sample.get("ValueSpecified"), Some(&FieldValue::Value(Variant::Boolean(true))));
+    assert_eq!(sample.get("Id"), Some(&FieldValue::Value(Variant::Int32(42))));
+    assert_eq!(sample.get("Value"), Some(&FieldValue::Value(Variant::Double(1.5))));
+    assert_eq!(sample.get("Readings"), Some(&FieldValue::Array(vec![FieldValue::Value(Variant::Float(1.0)), FieldValue::Value(Variant::Float(2.0))])));
+    assert_eq!(sample.get("Mode"), Some(&FieldValue::Value(Variant::Int32(1))));
+    let position = sample.get("Position").unwrap().as_structure().unwrap();
+    assert_eq!(position.type_name, "Point");
+    assert_eq!(position.get("X"), Some(&FieldValue::Value(Variant::Int16(5))));
+    assert_eq!(position.get("Y"), Some(&FieldValue::Value(Variant::Int16(-5))));
+    assert_eq!(sample.get("Name"), Some(&FieldValue::Value(Variant::from("abc"))));
+
+    // An optional field which is not specified is absent from the encoding
+    let sample = type_dictionary.decode("Sample", &encode_sample(false), &decoding_limits).unwrap();
+    assert_eq!(sample.get("Value"), Some(&FieldValue::Absent));
+    assert_eq!(sample.get("Name"), Some(&FieldValue::Value(Variant::from("abc"))));
+
+    // Truncated bodies and unknown types are errors
+    let body = encode_sample(true);
+    assert!(type_dictionary.decode("Sample", &body[..body.len() - 2], &decoding_limits).is_err());
+    assert_eq!(type_dictionary.decode("Unknown", &body, &decoding_limits).unwrap_err(), StatusCode::BadDataTypeIdUnknown);
+}
+
+#[test]
+fn subscription_state_management() {
+    use std::sync::{Arc, Mutex};
+    use opcua_types::status_code::StatusCode;
+    use crate::{callbacks::DataChangeCallback, subscription::Subscription, subscription_state::SubscriptionState};
+
+    let mut subscription_state = SubscriptionState::new();
+    for subscription_id in 1..=3 {
+        subscription_state.add_subscription(Subscription::new(subscription_id, 1000.0, 30, 10, 0, true, 0, Arc::new(Mutex::new(DataChangeCallback::new(|_| {})))));
+    }
+    assert_eq!(subscription_state.subscription_ids().unwrap().len(), 3);
+
+    // Modifying a subscription takes the revised values
+    subscription_state.modify_subscription(2, 500.0, 60, 20, 100, 5);
+    {
+        let subscription = subscription_state.get(2).unwrap();
+        assert_eq!(subscription.publishing_interval(), 500.0);
+        assert_eq!(subscription.lifetime_count(), 60);
+        assert_eq!(subscription.max_keep_alive_count(), 20);
+        assert_eq!(subscription.max_notifications_per_publish(), 100);
+        assert_eq!(subscription.priority(), 5);
+        assert_eq!(subscription_state.get(1).unwrap().publishing_interval(), 1000.0);
+    }
+
+    // Only subscriptions whose mode was set successfully change
+    subscription_state.set_publishing_mode(&[1, 2, 3], &[StatusCode::Good, StatusCode::BadTooManyOperations, StatusCode::Good], false);
+    assert!(!subscription_state.get(1).unwrap().publishing_enabled());
+    assert!(subscription_state.get(2).unwrap().publishing_enabled());
+    assert!(!subscription_state.get(3).unwrap().publishing_enabled());
+
+    // Subscriptions that were deleted, or that the server does not know, are removed
+    subscription_state.delete_subscriptions(&[1, 2, 3], &[StatusCode::Good, StatusCode::BadTooManyOperations, StatusCode::BadSubscriptionIdInvalid]);
+    assert_eq!(subscription_state.subscription_ids(), Some(vec![2]));
+    assert!(subscription_state.delete_subscription(2).is_some());
+    assert!(subscription_state.subscription_ids().is_none());
+}
+
+#[test]
+fn subscription_state_keep_alive_timeout() {
+    use std::{sync::{Arc, Mutex}, time::Duration};
+    use crate::{callbacks::DataChangeCallback, subscription::Subscription, subscription_state::SubscriptionState};
+
+    let make_subscription = |subscription_id, publishing_interval, max_keep_alive_count| {
+        Subscription::new(subscription_id, publishing_interval, 30, max_keep_alive_count, 0, true, 0, Arc::new(Mutex::new(DataChangeCallback::new(|_| {}))))
+    };
+
+    // Nothing is expected from the server without subscriptions
+    let mut subscription_state = SubscriptionState::new();
+    assert!(subscription_state.keep_alive_timeout().is_none());
+
+    // The server must respond within the shortest keep-alive period of the subscriptions
+    subscription_state.add_subscription(make_subscription(1, 1000.0, 10));
+    assert_eq!(subscription_state.keep_alive_timeout(), Some(Duration::from_millis(10000)));
+    subscription_state.add_subscription(make_subscription(2, 250.0, 20));
+    assert_eq!(subscription_state.keep_alive_timeout(), Some(Duration::from_millis(5000)));
+    let _ = subscription_state.delete_subscription(2);
+    assert_eq!(subscription_state.keep_alive_timeout(), Some(Duration::from_millis(10000)));
+}
+
+#[test]
+fn session_state_publish_requests() {
+    use std::sync::{Arc, RwLock};
+    use futures::Stream;
+    use opcua_core::comms::secure_channel::SecureChannel;
+    use opcua_types::{service_types::SubscriptionAcknowledgement, SupportedMessage};
+    use crate::{message_queue::MessageQueue, session_state::SessionState};
+
+    let message_queue = Arc::new(RwLock::new(MessageQueue::new()));
+    let rx = message_queue.write().unwrap().make_request_channel();
+    let mut requests = rx.wait();
+    let secure_channel = Arc::new(RwLock::new(SecureChannel::from((SecurityPolicy::None, MessageSecurityMode::None))));
+    let mut session_state = SessionState::new(secure_channel, message_queue);
+    session_state.set_max_publish_requests(3);
+
+    // Publish requests are sent up to the limit and acknowledgements go with the first one
+    session_state.add_subscription_acknowledgement(SubscriptionAcknowledgement { subscription_id: 1, sequence_number: 5 });
+    assert_eq!(session_state.async_publish_to_limit(), 3);
+    assert_eq!(session_state.outstanding_publish_requests(), 3);
+    let mut request_handles = Vec::new();
+    for i in 0..3 {
+        if let SupportedMessage::PublishRequest(request) = requests.next().unwrap().unwrap() {
+            assert_eq!(request.subscription_acknowledgements.is_some(), i == 0);
+            request_handles.push(request.request_header.request_handle);
+        } else {
+            panic!("Expected a publish request");
+        }
+    }
+    assert_eq!(session_state.async_publish_to_limit(), 0);
+
+    // An answered request is replaced, other responses are not publish responses
+    assert!(session_state.publish_response_received(request_handles[1]));
+    assert!(!session_state.publish_response_received(request_handles[1]));
+    assert_eq!(session_state.outstanding_publish_requests(), 2);
+    assert_eq!(session_state.async_publish_to_limit(), 1);
+
+    // Requests of a lost connection are forgotten
+    session_state.clear_publish_requests();
+    assert_eq!(session_state.outstanding_publish_requests(), 0);
+
+    // There is always at least one
+    session_state.set_max_publish_requests(0);
+    assert_eq!(session_state.max_publish_requests(), 1);
+}
+
+#[test]
+fn session_state_request_timeout() {
+    use std::sync::{Arc, RwLock};
+    use futures::Stream;
+    use opcua_core::comms::secure_channel::SecureChannel;
+    use opcua_types::{service_types::CancelRequest, status_code::StatusCode, SupportedMessage};
+    use crate::{message_queue::MessageQueue, session_state::SessionState};
+
+    let message_queue = Arc::new(RwLock::new(MessageQueue::new()));
+    let rx = message_queue.write().unwrap().make_request_channel();
+    let mut requests = rx.wait();
+    let secure_channel = Arc::new(RwLock::new(SecureChannel::from((SecurityPolicy::None, MessageSecurityMode::None))));
+    let mut session_state = SessionState::new(secure_channel, message_queue);
+    session_state.set_request_timeout(100);
+
+    // The request carries the timeout as its hint and fails when nothing answers it in time
+    let request = CancelRequest {
+        request_header: session_state.make_request_header(),
+        request_handle: 1,
+    };
+    assert_eq!(session_state.send_request(request).unwrap_err(), StatusCode::BadRequestTimeout);
+    if let SupportedMessage::CancelRequest(request) = requests.next().unwrap().unwrap() {
+        assert_eq!(request.request_header.timeout_hint, 100);
+    } else {
+        panic!("Expected a cancel request");
+    }
+}
+
+#[test]
+fn message_queue_request_failures() {
+    use std::sync::{Arc, Mutex};
+    use opcua_types::{service_types::{ServiceFault, ReadRequest, RequestHeader, ResponseHeader, TimestampsToReturn}, status_code::StatusCode, NodeId, DateTime, SupportedMessage};
+    use crate::{callbacks::RequestFailureCallback, message_queue::MessageQueue};
+
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    let mut message_queue = MessageQueue::new();
+    let _rx = message_queue.make_request_channel();
+    {
+        let failures = failures.clone();
+        message_queue.set_request_failure_callback(RequestFailureCallback::new(move |request_handle, status_code| {
+            failures.lock().unwrap().push((request_handle, status_code));
+        }));
+    }
+    let read_request = |request_handle| -> SupportedMessage {
+        ReadRequest {
+            request_header: RequestHeader::new(&NodeId::null(), &DateTime::now(), request_handle),
+            max_age: 0f64,
+            timestamps_to_return: TimestampsToReturn::Both,
+            nodes_to_read: None,
+        }.into()
+    };
+    for request_handle in 1..=3 {
+        message_queue.add_request(read_request(request_handle), false);
+    }
+
+    // Each failure is reported with the handle of its request
+    let service_fault = ServiceFault {
+        response_header: ResponseHeader::new_service_result(&RequestHeader::new(&NodeId::null(), &DateTime::now(), 1), StatusCode::BadTooManyOperations),
+    };
+    message_queue.store_response(service_fault.into());
+    message_queue.request_has_timed_out(2);
+    message_queue.clear();
+    assert_eq!(*failures.lock().unwrap(), vec![
+        (1, StatusCode::BadTooManyOperations),
+        (2, StatusCode::BadRequestTimeout),
+        (3, StatusCode::BadConnectionClosed),
+    ]);
+}
+
+#[test]
+fn session_state_keep_alive() {
+    use std::sync::{Arc, Mutex, RwLock};
+    use futures::Stream;
+    use opcua_core::comms::secure_channel::SecureChannel;
+    use opcua_types::{node_ids::VariableId, service_types::ServerState, NodeId, SupportedMessage};
+    use crate::{callbacks::ServerStateCallback, message_queue::MessageQueue, session_state::SessionState};
+
+    let message_queue = Arc::new(RwLock::new(MessageQueue::new()));
+    let rx = message_queue.write().unwrap().make_request_channel();
+    let mut requests = rx.wait();
+    let secure_channel = Arc::new(RwLock::new(SecureChannel::from((SecurityPolicy::None, MessageSecurityMode::None))));
+    let mut session_state = SessionState::new(secure_channel, message_queue);
+
+    let server_states = Arc::new(Mutex::new(Vec::new()));
+    {
+        let server_states = server_states.clone();
+        session_state.set_server_state_callback(ServerStateCallback::new(move |server_state| {
+            server_states.lock().unwrap().push(server_state);
+        }));
+    }
+
+    // The keep-alive reads the state of the server and makes the session busy again
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    assert!(session_state.idle_time() >= std::time::Duration::from_millis(10));
+    let request_handle = session_state.async_keep_alive().unwrap();
+    assert!(session_state.idle_time() < std::time::Duration::from_millis(10));
+    if let SupportedMessage::ReadRequest(request) = requests.next().unwrap().unwrap() {
+        let nodes_to_read = request.nodes_to_read.unwrap();
+        assert_eq!(nodes_to_read.len(), 1);
+        assert_eq!(nodes_to_read[0].node_id, NodeId::from(&VariableId::Server_ServerStatus_State));
+    } else {
+        panic!("Expected a read request");
+    }
+
+    // Only the response to the keep-alive is recognised
+    assert!(!session_state.keep_alive_response_received(request_handle + 1));
+    assert!(session_state.keep_alive_response_received(request_handle));
+    assert!(!session_state.keep_alive_response_received(request_handle));
+
+    // The callback hears of the first state and of changes
+    session_state.set_server_state(ServerState::Running);
+    session_state.set_server_state(ServerState::Running);
+    session_state.set_server_state(ServerState::Shutdown);
+    assert_eq!(*server_states.lock().unwrap(), vec![ServerState::Running, ServerState::Shutdown]);
+}
+
+#[test]
+fn gds_application_record_encoding() {
+    use std::io::Cursor;
+    use opcua_types::{*, service_types::ApplicationType};
+    use crate::gds::ApplicationRecord;
+
+    let record = ApplicationRecord {
+        application_id: NodeId::null(),
+        application_uri: UAString::from("urn:SampleClient"),
+        application_type: ApplicationType::Client,
+        application_names: Some(vec![LocalizedText::new("en", "OPC UA Sample Client")]),
+        product_uri: UAString::from("urn:SampleClientProduct"),
+        discovery_urls: None,
+        server_capabilities: None,
+    };
+    let mut stream = Cursor::new(Vec::new());
+    let size = record.encode(&mut stream).unwrap();
+    assert_eq!(size, record.byte_len());
+    let mut stream = Cursor::new(stream.into_inner());
+    assert_eq!(ApplicationRecord::decode(&mut stream, &DecodingLimits::default()).unwrap(), record);
+}
+
+#[test]
+fn gds_install_certificate_and_trust_list() {
+    use opcua_types::{ByteString, status_code::StatusCode, service_types::TrustListDataType};
+    use opcua_core::crypto::{CertificateStore, X509Data};
+    use crate::gds::{IssuedCertificate, install_certificate, install_trust_list};
+
+    let pki_dir = make_test_file("opcua_client_gds_pki");
+    let _ = std::fs::remove_dir_all(&pki_dir);
+    let certificate_store = CertificateStore::new(&pki_dir);
+
+    // The issued cert is stored with the key of the signing request
+    let (cert, pkey) = CertificateStore::create_cert_and_pkey(&X509Data::sample_cert()).unwrap();
+    let issued_certificate = IssuedCertificate {
+        certificate: cert.as_byte_string(),
+        private_key: ByteString::null(),
+        issuer_certificates: Vec::new(),
+    };
+    assert_eq!(install_certificate(&certificate_store, &issued_certificate, None), Err(StatusCode::BadInvalidArgument));
+    install_certificate(&certificate_store, &issued_certificate, Some(&pkey)).unwrap();
+    let (own_cert, _) = certificate_store.read_own_cert_and_pkey().unwrap();
+    assert_eq!(own_cert.thumbprint().value, cert.thumbprint().value);
+
+    // Trusted and issuer certs go into their directories
+    let (trusted_cert, _) = CertificateStore::create_cert_and_pkey(&X509Data::sample_cert()).unwrap();
+    let (issuer_cert, _) = CertificateStore::create_cert_and_pkey(&X509Data::sample_cert()).unwrap();
+    let trust_list = TrustListDataType {
+        specified_lists: 0x0f,
+        trusted_certificates: Some(vec![trusted_cert.as_byte_string()]),
+        trusted_crls: None,
+        issuer_certificates: Some(vec![issuer_cert.as_byte_string()]),
+        issuer_crls: None,
+    };
+    install_trust_list(&certificate_store, &trust_list).unwrap();
+    let mut trusted_path = certificate_store.trusted_certs_dir();
+    trusted_path.push(CertificateStore::cert_file_name(&trusted_cert));
+    assert!(trusted_path.exists());
+    let mut issuer_path = certificate_store.issuers_dir();
+    issuer_path.push(CertificateStore::cert_file_name(&issuer_cert));
+    assert!(issuer_path.exists());
+
+    let _ = std::fs::remove_dir_all(&pki_dir);
+}
+
+#[test]
+fn session_pool_split_evenly() {
+    use crate::session_pool::split_evenly;
+
+    assert_eq!(split_evenly(10, 3), vec![(0, 4), (4, 7), (7, 10)]);
+    assert_eq!(split_evenly(6, 3), vec![(0, 2), (2, 4), (4, 6)]);
+    assert_eq!(split_evenly(2, 4), vec![(0, 1), (1, 2)]);
+    assert_eq!(split_evenly(5, 1), vec![(0, 5)]);
+    assert!(split_evenly(0, 4).is_empty());
+}
+
+#[test]
+fn session_pool_empty() {
+    use opcua_types::{*, service_types::ReadValueId, status_code::StatusCode};
+    use crate::session_pool::SessionPool;
+
+    let pool = SessionPool::new(Vec::new());
+    assert!(pool.is_empty());
+    let nodes_to_read = vec![ReadValueId::from(NodeId::new(2, "v1"))];
+    assert_eq!(pool.read(&nodes_to_read).unwrap_err(), StatusCode::BadNotConnected);
+    assert_eq!(pool.read_parallel(&nodes_to_read).unwrap_err(), StatusCode::BadNotConnected);
+    assert_eq!(pool.read_parallel(&[]).unwrap_err(), StatusCode::BadNothingToDo);
+}
+
+#[test]
+fn operation_limit_batches() {
+    use crate::session::batches;
+
+    // No limit or a limit that is not exceeded is one batch
+    assert_eq!(batches(10, 0), vec![(0, 10)]);
+    assert_eq!(batches(10, 10), vec![(0, 10)]);
+    assert_eq!(batches(10, 100), vec![(0, 10)]);
+    // The last batch holds whatever is left over
+    assert_eq!(batches(10, 4), vec![(0, 4), (4, 8), (8, 10)]);
+    assert_eq!(batches(9, 3), vec![(0, 3), (3, 6), (6, 9)]);
+    assert_eq!(batches(3, 1), vec![(0, 1), (1, 2), (2, 3)]);
+}
+
+#[test]
+fn operation_limit_in_batches() {
+    use opcua_types::status_code::StatusCode;
+    use crate::session::in_batches;
+
+    let operations = (0..10u32).collect::<Vec<u32>>();
+    let succeed = |operations: &[u32]| -> Result<Option<Vec<StatusCode>>, StatusCode> {
+        Ok(Some(operations.iter().map(|_| StatusCode::Good).collect()))
+    };
+
+    // The results of every batch are returned in order
+    let mut calls = Vec::new();
+    let results = in_batches(&operations, 4, |operations| {
+        calls.push(operations.to_vec());
+        succeed(operations)
+    }).unwrap().unwrap();
+    assert_eq!(results, vec![StatusCode::Good; 10]);
+    assert_eq!(calls, vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9]]);
+
+    // A failure before anything is applied fails the call
+    let result = in_batches(&operations, 4, |_| -> Result<Option<Vec<StatusCode>>, StatusCode> { Err(StatusCode::BadTimeout) });
+    assert_eq!(result.unwrap_err(), StatusCode::BadTimeout);
+
+    // A failure after a batch is applied returns what was applied and why the rest was not
+    let results = in_batches(&operations, 4, |operations| {
+        if operations[0] == 4 { Err(StatusCode::BadTimeout) } else { succeed(operations) }
+    }).unwrap().unwrap();
+    let mut expected = vec![StatusCode::Good; 4];
+    expected.extend(vec![StatusCode::BadTimeout; 4]);
+    expected.extend(vec![StatusCode::BadRequestNotAllowed; 2]);
+    assert_eq!(results, expected);
+
+    // A batch with the wrong number of results is not trusted
+    let results = in_batches(&operations, 4, |operations| {
+        if operations[0] == 8 { Ok(Some(vec![StatusCode::Good])) } else { succeed(operations) }
+    }).unwrap().unwrap();
+    let mut expected = vec![StatusCode::Good; 8];
+    expected.extend(vec![StatusCode::BadUnexpectedError; 2]);
+    assert_eq!(results, expected);
+
+    // Batches without results are no results, like a single call without results
+    let results = in_batches(&operations, 4, |_| -> Result<Option<Vec<StatusCode>>, StatusCode> { Ok(Some(Vec::new())) }).unwrap();
+    assert!(results.is_none());
+}
+
+#[test]
+fn operation_limits_from_values() {
+    use opcua_types::{*, status_code::StatusCode};
+    use crate::session::OperationLimits;
+
+    let mut unknown = DataValue::null();
+    unknown.status = Some(StatusCode::BadNodeIdUnknown.bits());
+    let values = vec![
+        DataValue::new(1000u32),
+        unknown,
+        DataValue::new(Variant::Int32(50)),
+        DataValue::new(200u32),
+    ];
+    assert_eq!(OperationLimits::from_values(&values), OperationLimits {
+        max_nodes_per_read: 1000,
+        max_nodes_per_write: 0,
+        max_nodes_per_browse: 0,
+        max_monitored_items_per_call: 200,
+    });
+    assert_eq!(OperationLimits::from_values(&[]), OperationLimits::default());
+}
@@ -0,0 +1,69 @@
+//! A pool of byte buffers shared by transports. Connections take a buffer when they have
+//! something to encode or send and give it back afterwards, so an idle connection holds no
+//! buffers and a busy one reuses buffers instead of allocating for each message.
+
+use std::sync::{Arc, Mutex};
+
+use crate::trace_lock_unwrap;
+
+/// The default number of buffers that a pool holds onto
+pub const DEFAULT_MAX_POOLED_BUFFERS: usize = 64;
+
+struct PoolState {
+    /// Buffers available for reuse
+    buffers: Vec<Vec<u8>>,
+    /// The maximum number of buffers kept in the pool
+    max_buffers: usize,
+    /// Buffers with a larger capacity than this are dropped instead of being kept
+    max_buffer_size: usize,
+}
+
+/// A pool of buffers. Clones of the pool share the same buffers.
+#[derive(Clone)]
+pub struct BufferPool {
+    state: Arc<Mutex<PoolState>>,
+}
+
+impl BufferPool {
+    /// Creates a pool that keeps up to `max_buffers` buffers of up to `max_buffer_size` bytes
+    /// capacity. The pool starts empty.
+    pub fn new(max_buffers: usize, max_buffer_size: usize) -> BufferPool {
+        BufferPool {
+            state: Arc::new(Mutex::new(PoolState {
+                buffers: Vec::with_capacity(max_buffers),
+                max_buffers,
+                max_buffer_size,
+            })),
+        }
+    }
+
+    /// Takes an empty buffer with at least the capacity from the pool, or allocates one if the
+    /// pool has none which are large enough.
+    pub fn take(&self, capacity: usize) -> Vec<u8> {
+        let buffer = {
+            let mut state = trace_lock_unwrap!(self.state);
+            if let Some(idx) = state.buffers.iter().position(|b| b.capacity() >= capacity) {
+                Some(state.buffers.swap_remove(idx))
+            } else {
+                None
+            }
+        };
+        buffer.unwrap_or_else(|| Vec::with_capacity(capacity))
+    }
+
+    /// Gives a buffer back to the pool. The buffer is dropped if the pool is full or if the
+    /// buffer has grown larger than the pool keeps.
+    pub fn give_back(&self, mut buffer: Vec<u8>) {
+        let mut state = trace_lock_unwrap!(self.state);
+        if state.buffers.len() < state.max_buffers && buffer.capacity() <= state.max_buffer_size {
+            buffer.clear();
+            state.buffers.push(buffer);
+        }
+    }
+
+    /// Returns the number of buffers which are available for reuse
+    pub fn available(&self) -> usize {
+        let state = trace_lock_unwrap!(self.state);
+        state.buffers.len()
+    }
+}
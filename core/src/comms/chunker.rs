@@ -1,7 +1,7 @@
 //! Contains code for turning messages into chunks and chunks into messages.
 
 use std;
-use std::io::Cursor;
+use std::io::{self, Cursor, Read};
 
 use opcua_types::{
     encoding::BinaryEncoder,
@@ -19,6 +19,27 @@ use crate::{
     crypto::SecurityPolicy,
 };
 
+/// Reads the bodies of a message's chunks in order as if they were one stream, so the message can
+/// be decoded without first copying the bodies into a contiguous buffer.
+struct ChunkBodies<'a> {
+    bodies: Vec<&'a [u8]>,
+    index: usize,
+}
+
+impl<'a> Read for ChunkBodies<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.index < self.bodies.len() {
+            // Reading a slice advances it past the bytes that were read
+            let size = self.bodies[self.index].read(buf)?;
+            if size > 0 || buf.is_empty() {
+                return Ok(size);
+            }
+            self.index += 1;
+        }
+        Ok(0)
+    }
+}
+
 /// The Chunker is responsible for turning messages to chunks and chunks into messages.
 pub struct Chunker;
 
@@ -139,8 +160,8 @@ impl Chunker {
     /// Decodes a series of chunks to create a message. The message must be of a `SupportedMessage`
     /// type otherwise an error will occur.
     pub fn decode(chunks: &[MessageChunk], secure_channel: &SecureChannel, expected_node_id: Option<NodeId>) -> std::result::Result<SupportedMessage, StatusCode> {
-        // Calculate the size of data held in all chunks
-        let mut data_size: usize = 0;
+        // Find the body of each chunk. The assumption is the data is decrypted / verified by now
+        let mut bodies = Vec::with_capacity(chunks.len());
         for (i, chunk) in chunks.iter().enumerate() {
             let chunk_info = chunk.chunk_info(secure_channel)?;
             // The last most chunk is expected to be final, the rest intermediate
@@ -152,26 +173,13 @@ impl Chunker {
             if chunk_info.message_header.is_final != expected_is_final {
                 return Err(StatusCode::BadDecodingError);
             }
-            // Calculate how much space data is in the chunk
-            let body_start = chunk_info.body_offset;
-            let body_end = body_start + chunk_info.body_length;
-            data_size += chunk.data[body_start..body_end].len();
-        }
-
-        // Read the data into a contiguous buffer. The assumption is the data is decrypted / verified by now
-        // TODO this buffer should be externalized so it is not allocated each time
-        let mut data = Vec::with_capacity(data_size);
-        for chunk in chunks.iter() {
-            let chunk_info = chunk.chunk_info(secure_channel)?;
-
             let body_start = chunk_info.body_offset;
             let body_end = body_start + chunk_info.body_length;
-            let body_data = &chunk.data[body_start..body_end];
-            data.extend_from_slice(body_data);
+            bodies.push(&chunk.data[body_start..body_end]);
         }
 
-        // Make a stream around the data
-        let mut data = Cursor::new(data);
+        // Make a stream that reads the bodies in place
+        let mut data = ChunkBodies { bodies, index: 0 };
 
         // The extension object prefix is just the node id. A point the spec rather unhelpfully doesn't
         // elaborate on. Probably because people enjoy debugging why the stream pos is out by 1 byte
@@ -201,7 +209,7 @@ impl Chunker {
         }
     }
 
-    fn object_id_from_node_id(node_id: NodeId, expected_node_id: Option<NodeId>) -> Result<ObjectId, StatusCode> {
+    pub(crate) fn object_id_from_node_id(node_id: NodeId, expected_node_id: Option<NodeId>) -> Result<ObjectId, StatusCode> {
         let valid_node_id = if node_id.namespace != 0 || !node_id.is_numeric() {
             // Must be ns 0 and numeric
             error!("Expecting chunk to contain a OPC UA request or response");
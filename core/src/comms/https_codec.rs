@@ -0,0 +1,47 @@
+//! Contains the encoding of messages sent over the opc.https transport. Each request and response
+//! is the whole body of an HTTP POST and its response, rather than a series of secured chunks.
+
+use std::io::Cursor;
+
+use opcua_types::{
+    encoding::{BinaryEncoder, DecodingLimits},
+    node_id::NodeId,
+    status_code::StatusCode,
+    supported_message::SupportedMessage,
+};
+
+use crate::comms::chunker::Chunker;
+
+/// Content type of a binary encoded request or response
+pub const CONTENT_TYPE_UA_BINARY: &str = "application/octet-stream";
+
+/// HTTP header in which the client names the uri of the endpoint's security policy
+pub const SECURITY_POLICY_HEADER: &str = "OPCUA-SecurityPolicy";
+
+/// Encodes the message as the body of an HTTP request or response, i.e. the node id of its binary
+/// encoding followed by the message.
+pub fn encode_https_body(message: &SupportedMessage) -> Result<Vec<u8>, StatusCode> {
+    let node_id = message.node_id();
+    let mut stream = Cursor::new(Vec::with_capacity(node_id.byte_len() + message.byte_len()));
+    let _ = node_id.encode(&mut stream)?;
+    let _ = message.encode(&mut stream)?;
+    Ok(stream.into_inner())
+}
+
+/// Decodes the body of an HTTP request or response into a message.
+pub fn decode_https_body(body: &[u8], decoding_limits: &DecodingLimits) -> Result<SupportedMessage, StatusCode> {
+    let mut stream = Cursor::new(body);
+    let node_id = NodeId::decode(&mut stream, decoding_limits)?;
+    let object_id = Chunker::object_id_from_node_id(node_id, None)?;
+    match SupportedMessage::decode_by_object_id(&mut stream, object_id, decoding_limits) {
+        Ok(SupportedMessage::Invalid(_)) => {
+            debug!("Message {:?} is unsupported", object_id);
+            Err(StatusCode::BadServiceUnsupported)
+        }
+        Ok(message) => Ok(message),
+        Err(err) => {
+            debug!("Cannot decode message {:?}, err = {:?}", object_id, err);
+            Err(StatusCode::BadDecodingError)
+        }
+    }
+}
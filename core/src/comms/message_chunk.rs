@@ -4,6 +4,8 @@
 use std;
 use std::io::{Read, Write, Cursor};
 
+use bytes::Bytes;
+
 use opcua_types::*;
 use opcua_types::status_code::StatusCode;
 use opcua_types::tcp_types::{CHUNK_MESSAGE, CLOSE_SECURE_CHANNEL_MESSAGE, OPEN_SECURE_CHANNEL_MESSAGE};
@@ -138,10 +140,13 @@ impl MessageChunkHeader {}
 /// A chunk holds a message or a portion of a message, if the message has been split into multiple chunks.
 /// The chunk's data may be signed and encrypted. To extract the message requires all the chunks
 /// to be available in sequence so they can be formed back into the message.
-#[derive(Debug)]
+///
+/// The data is reference counted so a chunk can share the buffer it was read from, and cloning a
+/// chunk does not copy its data.
+#[derive(Debug, Clone)]
 pub struct MessageChunk {
     /// All of the chunk's data including headers, payload, padding, signature
-    pub data: Vec<u8>,
+    pub data: Bytes,
 }
 
 impl BinaryEncoder<MessageChunk> for MessageChunk {
@@ -185,11 +190,28 @@ impl BinaryEncoder<MessageChunk> for MessageChunk {
         // Read remainder of stream into slice after the header
         let _ = in_stream.read_exact(&mut data[chunk_header_size..]);
 
-        Ok(MessageChunk { data })
+        Ok(MessageChunk { data: Bytes::from(data) })
     }
 }
 
 impl MessageChunk {
+    /// Makes a chunk from a buffer holding exactly one chunk, e.g. a frame read from a socket.
+    /// Unlike `decode()`, the chunk shares the buffer rather than copying it.
+    pub fn from_bytes(data: Bytes, decoding_limits: &DecodingLimits) -> Result<MessageChunk, StatusCode> {
+        let chunk_header = MessageChunkHeader::decode(&mut Cursor::new(&data[..]), decoding_limits).map_err(|err| {
+            error!("Cannot decode chunk header {}", err);
+            StatusCode::BadCommunicationError
+        })?;
+        if !chunk_header.is_valid {
+            Err(StatusCode::BadTcpMessageTypeInvalid)
+        } else if chunk_header.message_size as usize != data.len() {
+            error!("Chunk header has a size of {} but the chunk is {} bytes", chunk_header.message_size, data.len());
+            Err(StatusCode::BadCommunicationError)
+        } else {
+            Ok(MessageChunk { data })
+        }
+    }
+
     pub fn new(sequence_number: u32, request_id: u32, message_type: MessageChunkType, is_final: MessageIsFinalType, secure_channel: &SecureChannel, data: &[u8]) -> Result<MessageChunk, StatusCode> {
         // security header depends on message type
         let security_header = secure_channel.make_security_header(message_type);
@@ -223,7 +245,7 @@ impl MessageChunk {
         let _ = stream.write(data);
 
 
-        Ok(MessageChunk { data: stream.into_inner() })
+        Ok(MessageChunk { data: Bytes::from(stream.into_inner()) })
     }
 
     const SMALLEST_MESSAGE_SIZE: usize = 8196;
@@ -256,13 +278,13 @@ impl MessageChunk {
 
     pub fn message_header(&self, decoding_limits: &DecodingLimits) -> Result<MessageChunkHeader, StatusCode> {
         // Message header is first so just read it
-        let mut stream = Cursor::new(&self.data);
+        let mut stream = Cursor::new(&self.data[..]);
         MessageChunkHeader::decode(&mut stream, decoding_limits)
     }
 
     pub fn security_header(&self, decoding_limits: &DecodingLimits) -> Result<SecurityHeader, StatusCode> {
         // Message header is first so just read it
-        let mut stream = Cursor::new(&self.data);
+        let mut stream = Cursor::new(&self.data[..]);
         let message_header = MessageChunkHeader::decode(&mut stream, decoding_limits)?;
         let security_header = if message_header.message_type == MessageChunkType::OpenSecureChannel {
             SecurityHeader::Asymmetric(AsymmetricSecurityHeader::decode(&mut stream, decoding_limits)?)
@@ -35,7 +35,7 @@ pub struct ChunkInfo {
 
 impl ChunkInfo {
     pub fn new(chunk: &MessageChunk, secure_channel: &SecureChannel) -> std::result::Result<ChunkInfo, StatusCode> {
-        let mut stream = Cursor::new(&chunk.data);
+        let mut stream = Cursor::new(&chunk.data[..]);
 
         let decoding_limits = secure_channel.decoding_limits();
 
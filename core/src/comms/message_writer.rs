@@ -1,11 +1,9 @@
-use std::io::{Cursor, Write};
-
 use opcua_types::SupportedMessage;
 use opcua_types::status_code::StatusCode;
 use opcua_types::tcp_types::AcknowledgeMessage;
 use opcua_types::{BinaryEncoder, EncodingResult};
 
-use crate::comms::{secure_channel::SecureChannel, chunker::Chunker};
+use crate::comms::{secure_channel::SecureChannel, chunker::Chunker, buffer_pool::BufferPool};
 //use debug::log_buffer;
 
 const DEFAULT_REQUEST_ID: u32 = 1000;
@@ -14,8 +12,13 @@ const DEFAULT_SENT_SEQUENCE_NUMBER: u32 = 0;
 /// SocketWriter is a wrapper around the writable half of a tokio stream and a buffer which
 /// will be dumped into that stream.
 pub struct MessageWriter {
-    /// The send buffer
-    buffer: Cursor<Vec<u8>>,
+    /// The send buffer. It is taken from the pool when there is something to write and handed
+    /// over to the caller by `bytes_to_write()`.
+    buffer: Option<Vec<u8>>,
+    /// The pool that buffers are taken from
+    buffer_pool: BufferPool,
+    /// The send buffer size
+    buffer_size: usize,
     /// The last request id
     last_request_id: u32,
     /// Last sent sequence number
@@ -23,16 +26,26 @@ pub struct MessageWriter {
 }
 
 impl MessageWriter {
-    pub fn new(buffer_size: usize) -> MessageWriter {
+    pub fn new(buffer_size: usize, buffer_pool: BufferPool) -> MessageWriter {
         MessageWriter {
-            buffer: Cursor::new(vec![0u8; buffer_size]),
+            buffer: None,
+            buffer_pool,
+            buffer_size,
             last_request_id: DEFAULT_REQUEST_ID,
             last_sent_sequence_number: DEFAULT_SENT_SEQUENCE_NUMBER,
         }
     }
 
+    /// Returns the send buffer, taking one from the pool if there isn't one
+    fn buffer(&mut self) -> &mut Vec<u8> {
+        if self.buffer.is_none() {
+            self.buffer = Some(self.buffer_pool.take(self.buffer_size));
+        }
+        self.buffer.as_mut().unwrap()
+    }
+
     pub fn write_ack(&mut self, ack: &AcknowledgeMessage) -> EncodingResult<usize> {
-        ack.encode(&mut self.buffer)
+        ack.encode(self.buffer())
     }
 
     /// Encodes the message into a series of chunks, encrypts those chunks and writes the
@@ -52,8 +65,9 @@ impl MessageWriter {
 
         // This max chunk size allows the message to be encoded to a chunk with header + encoding
         // which is just slightly larger in size (up to 1024 bytes).
-        let max_chunk_size = self.buffer.get_ref().len() + 1024;
-        let mut data = vec![0u8; max_chunk_size];
+        let max_chunk_size = self.buffer_size + 1024;
+        let mut data = self.buffer_pool.take(max_chunk_size);
+        data.resize(max_chunk_size, 0u8);
 
         let decoding_limits = secure_channel.decoding_limits();
         for chunk in chunks {
@@ -63,17 +77,14 @@ impl MessageWriter {
             };
             match size {
                 Ok(size) => {
-                    let bytes_written_result = self.buffer.write(&data[..size]);
-                    if let Err(error) = bytes_written_result {
-                        error!("Error while writing bytes to stream, connection broken, check error {:?}", error);
-                        break;
-                    }
+                    self.buffer().extend_from_slice(&data[..size]);
                 }
                 Err(err) => {
                     panic!("Applying security to chunk failed - {:?}", err);
                 }
             }
         }
+        self.buffer_pool.give_back(data);
         trace!("Message written");
         Ok(request_id)
     }
@@ -83,17 +94,15 @@ impl MessageWriter {
         self.last_request_id
     }
 
-    /// Clears the buffer
-    fn clear(&mut self) {
-        self.buffer.set_position(0);
+    /// Yields any results to write. The buffer should be given back with `give_back()` once it
+    /// has been written.
+    pub fn bytes_to_write(&mut self) -> Vec<u8> {
+        // Buffer MUST be taken here, otherwise races are possible
+        self.buffer.take().unwrap_or_default()
     }
 
-    /// Yields any results to write, resetting the buffer back afterwards
-    pub fn bytes_to_write(&mut self) -> Vec<u8> {
-        let pos = self.buffer.position() as usize;
-        let result = (self.buffer.get_ref())[0..pos].to_vec();
-        // Buffer MUST be cleared here, otherwise races are possible
-        self.clear();
-        result
+    /// Gives a written buffer back to the pool
+    pub fn give_back(&self, buffer: Vec<u8>) {
+        self.buffer_pool.give_back(buffer);
     }
-}
\ No newline at end of file
+}
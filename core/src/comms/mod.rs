@@ -1,7 +1,9 @@
 //! Contains all code related to sending / receiving messages from a transport
 //! and turning those messages into and out of chunks.
 
+pub mod buffer_pool;
 pub mod chunker;
+pub mod https_codec;
 pub mod message_chunk;
 pub mod message_chunk_info;
 pub mod secure_channel;
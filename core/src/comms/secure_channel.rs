@@ -2,6 +2,7 @@ use std::io::{Cursor, Write};
 use std::ops::Range;
 use std::sync::{Arc, RwLock};
 
+use bytes::Bytes;
 use chrono;
 
 use opcua_types::*;
@@ -29,6 +30,27 @@ pub enum Role {
     Server,
 }
 
+/// A security token which has been replaced by a renewal, and the keys which secure messages
+/// with it. Messages secured with it remain valid until it expires or the other side of the
+/// channel has started using the new token.
+#[derive(Debug)]
+struct PreviousSecurityToken {
+    /// Token identifier
+    token_id: u32,
+    /// Time the token expires
+    expires_at: chrono::DateTime<chrono::Utc>,
+    /// Their keys for the token
+    remote_keys: Option<(Vec<u8>, AesKey, Vec<u8>)>,
+    /// Our keys for the token
+    local_keys: Option<(Vec<u8>, AesKey, Vec<u8>)>,
+}
+
+impl PreviousSecurityToken {
+    fn has_expired(&self) -> bool {
+        chrono::Utc::now() >= self.expires_at
+    }
+}
+
 /// Holds all of the security information related to this session
 #[derive(Debug)]
 pub struct SecureChannel {
@@ -46,6 +68,8 @@ pub struct SecureChannel {
     token_lifetime: u32,
     /// Token identifier
     token_id: u32,
+    /// The token before the last renewal, kept for the period that both tokens are in use
+    previous_token: Option<PreviousSecurityToken>,
     /// Our certificate
     cert: Option<X509>,
     /// Our private key
@@ -74,6 +98,7 @@ impl From<(SecurityPolicy, MessageSecurityMode)> for SecureChannel {
             token_id: 0,
             token_created_at: DateTime::now(),
             token_lifetime: 0,
+            previous_token: None,
             local_nonce: Vec::new(),
             remote_nonce: Vec::new(),
             cert: None,
@@ -111,6 +136,7 @@ impl SecureChannel {
             token_id: 0,
             token_created_at: DateTime::now(),
             token_lifetime: 0,
+            previous_token: None,
             local_nonce: Vec::new(),
             remote_nonce: Vec::new(),
             cert,
@@ -122,6 +148,12 @@ impl SecureChannel {
         }
     }
 
+    /// For testing purposes only
+    #[cfg(test)]
+    pub fn set_role(&mut self, role: Role) {
+        self.role = role;
+    }
+
     pub fn is_client_role(&self) -> bool {
         self.role == Role::Client
     }
@@ -167,9 +199,23 @@ impl SecureChannel {
         self.token_id = 0;
         self.token_created_at = DateTime::now();
         self.token_lifetime = 0;
+        self.previous_token = None;
     }
 
+    /// Sets the security token issued for the channel. When a token is renewed, the current token
+    /// and its keys are kept so messages secured with either token are accepted until the other
+    /// side starts using the new one. Keys for the new token must be derived afterwards.
     pub fn set_security_token(&mut self, channel_token: ChannelSecurityToken) {
+        self.previous_token = if self.token_id != 0 && self.token_id != channel_token.token_id {
+            Some(PreviousSecurityToken {
+                token_id: self.token_id,
+                expires_at: self.token_expires_at(),
+                remote_keys: self.remote_keys.take(),
+                local_keys: self.local_keys.take(),
+            })
+        } else {
+            None
+        };
         self.secure_channel_id = channel_token.channel_id;
         self.token_id = channel_token.token_id;
         self.token_created_at = channel_token.created_at;
@@ -243,19 +289,22 @@ impl SecureChannel {
                 SecurityHeader::Asymmetric(asymmetric_security_header)
             }
             _ => {
+                // A server keeps securing its messages with the previous token until the client
+                // is using the new one
+                let token_id = self.sending_previous_token().map(|t| t.token_id).unwrap_or(self.token_id);
                 SecurityHeader::Symmetric(SymmetricSecurityHeader {
-                    token_id: self.token_id,
+                    token_id,
                 })
             }
         }
     }
 
-    /// Creates a nonce for the connection. The nonce should be the same size as the symmetric key
+    /// Creates a nonce for the connection. The nonce length is dictated by the security policy
     pub fn create_random_nonce(&mut self) {
         if self.security_policy != SecurityPolicy::None && (self.security_mode == MessageSecurityMode::Sign || self.security_mode == MessageSecurityMode::SignAndEncrypt) {
             use ring::rand::{SystemRandom, SecureRandom};
             let rng = SystemRandom::new();
-            self.local_nonce = vec![0u8; self.security_policy.symmetric_key_size()];
+            self.local_nonce = vec![0u8; self.security_policy.secure_channel_nonce_length()];
             let _ = rng.fill(&mut self.local_nonce);
         } else {
             self.local_nonce = vec![0u8; 1];
@@ -281,12 +330,12 @@ impl SecureChannel {
         }
     }
 
-    /// Set their nonce which should be the same as the symmetric key
+    /// Set their nonce which should be the length dictated by the security policy
     pub fn set_remote_nonce_from_byte_string(&mut self, remote_nonce: &ByteString) -> Result<(), StatusCode> {
         if self.security_policy != SecurityPolicy::None && (self.security_mode == MessageSecurityMode::Sign || self.security_mode == MessageSecurityMode::SignAndEncrypt) {
             if let Some(ref remote_nonce) = remote_nonce.value {
-                if remote_nonce.len() != self.security_policy.symmetric_key_size() {
-                    error!("Remote nonce is invalid length {}, expecting {}. {:?}", remote_nonce.len(), self.security_policy.symmetric_key_size(), remote_nonce);
+                if remote_nonce.len() != self.security_policy.secure_channel_nonce_length() {
+                    error!("Remote nonce is invalid length {}, expecting {}. {:?}", remote_nonce.len(), self.security_policy.secure_channel_nonce_length(), remote_nonce);
                     Err(StatusCode::BadNonceInvalid)
                 } else {
                     self.remote_nonce = remote_nonce.to_vec();
@@ -344,12 +393,40 @@ impl SecureChannel {
         trace!("Derived local keys = {:?}", self.local_keys);
     }
 
+    /// Returns the time that the token expires. The lifetime is in milliseconds.
+    fn token_expires_at(&self) -> chrono::DateTime<chrono::Utc> {
+        let token_created_at: chrono::DateTime<chrono::Utc> = self.token_created_at.clone().into();
+        token_created_at + chrono::Duration::milliseconds(self.token_lifetime as i64)
+    }
+
     /// Test if the token has expired yet
     pub fn token_has_expired(&self) -> bool {
         let now: chrono::DateTime<chrono::Utc> = DateTime::now().into();
-        let token_created_at: chrono::DateTime<chrono::Utc> = self.token_created_at.clone().into();
-        let token_expires = token_created_at + chrono::Duration::seconds(self.token_lifetime as i64);
-        now.ge(&token_expires)
+        now.ge(&self.token_expires_at())
+    }
+
+    /// Returns the previous token if messages sent to the other side are still secured with it.
+    /// A client uses a renewed token straight away, whereas a server continues with the previous
+    /// token until the client sends a message secured with the new one, or the previous expires.
+    fn sending_previous_token(&self) -> Option<&PreviousSecurityToken> {
+        if self.is_client_role() {
+            None
+        } else {
+            self.previous_token.as_ref().filter(|t| !t.has_expired())
+        }
+    }
+
+    /// Checks the token of a received message is the current token, or the previous token within
+    /// the overlap period.
+    fn check_received_token_id(&self, token_id: u32) -> Result<(), StatusCode> {
+        if token_id == self.token_id {
+            Ok(())
+        } else if self.previous_token.as_ref().filter(|t| t.token_id == token_id && !t.has_expired()).is_some() {
+            Ok(())
+        } else {
+            error!("Message is secured with token {} which is not the current token {} of the secure channel", token_id, self.token_id);
+            Err(StatusCode::BadSecureChannelTokenUnknown)
+        }
     }
 
     /// Calculates the signature size for a message depending on the supplied security header
@@ -535,8 +612,9 @@ impl SecureChannel {
         Ok(size)
     }
 
-    /// Decrypts and verifies the body data if the mode / policy requires it
-    pub fn verify_and_remove_security(&mut self, src: &[u8]) -> Result<MessageChunk, StatusCode> {
+    /// Decrypts and verifies the body data if the mode / policy requires it. If there is no
+    /// security to remove, the returned chunk shares the supplied bytes rather than copying them.
+    pub fn verify_and_remove_security<T>(&mut self, src: T) -> Result<MessageChunk, StatusCode> where T: Into<Bytes> {
         self.verify_and_remove_security_forensic(src, None)
     }
 
@@ -544,10 +622,12 @@ impl SecureChannel {
     ///
     /// Note, that normally we do not have "their" key but for testing purposes and forensics, we
     /// might have the key
-    pub fn verify_and_remove_security_forensic(&mut self, src: &[u8], their_key: Option<PrivateKey>) -> Result<MessageChunk, StatusCode> {
+    pub fn verify_and_remove_security_forensic<T>(&mut self, src: T, their_key: Option<PrivateKey>) -> Result<MessageChunk, StatusCode> where T: Into<Bytes> {
+        let src: Bytes = src.into();
+
         // Get message & security header from data
         let (message_header, security_header, encrypted_data_offset) = {
-            let mut stream = Cursor::new(&src);
+            let mut stream = Cursor::new(&src[..]);
             let message_header = MessageChunkHeader::decode(&mut stream, &self.decoding_limits)?;
             let security_header = if message_header.message_type.is_open_secure_channel() {
                 SecurityHeader::Asymmetric(AsymmetricSecurityHeader::decode(&mut stream, &self.decoding_limits)?)
@@ -564,6 +644,13 @@ impl SecureChannel {
             return Err(StatusCode::BadUnexpectedError);
         }
 
+        let received_token_id = if let SecurityHeader::Symmetric(ref security_header) = security_header {
+            self.check_received_token_id(security_header.token_id)?;
+            Some(security_header.token_id)
+        } else {
+            None
+        };
+
         // S - Message Header
         // S - Security Header
         // S - Sequence Header - E
@@ -592,7 +679,7 @@ impl SecureChannel {
                 }
                 SecurityPolicy::None => {
                     // Nothing to do
-                    return Ok(MessageChunk { data: src.to_vec() });
+                    return Ok(MessageChunk { data: src });
                 }
                 _ => {}
             }
@@ -621,9 +708,9 @@ impl SecureChannel {
             trace!("Receiver thumbprint = {:?}", receiver_thumbprint);
 
             let mut decrypted_data = vec![0u8; message_size];
-            let decrypted_size = self.asymmetric_decrypt_and_verify(security_policy, &verification_key, receiver_thumbprint, src, encrypted_range, their_key, &mut decrypted_data)?;
+            let decrypted_size = self.asymmetric_decrypt_and_verify(security_policy, &verification_key, receiver_thumbprint, &src, encrypted_range, their_key, &mut decrypted_data)?;
 
-            Self::update_message_size_and_truncate(decrypted_data, decrypted_size, &self.decoding_limits)?
+            Bytes::from(Self::update_message_size_and_truncate(decrypted_data, decrypted_size, &self.decoding_limits)?)
         } else if self.security_policy != SecurityPolicy::None && (self.security_mode == MessageSecurityMode::Sign || self.security_mode == MessageSecurityMode::SignAndEncrypt) {
             // Symmetric decrypt and verify
             let signature_size = self.security_policy.symmetric_signature_size();
@@ -631,15 +718,28 @@ impl SecureChannel {
             let signed_range = 0..(message_size - signature_size);
             trace!("Decrypting block with signature info {:?} and encrypt info {:?}", signed_range, encrypted_range);
 
+            // Messages secured with the previous token are verified with its keys
+            let remote_keys = match self.previous_token {
+                Some(ref previous_token) if received_token_id == Some(previous_token.token_id) => previous_token.remote_keys.as_ref().unwrap(),
+                _ => self.remote_keys()
+            };
+
             let mut decrypted_data = vec![0u8; message_size];
-            let decrypted_size = self.symmetric_decrypt_and_verify(src, signed_range, encrypted_range, &mut decrypted_data)?;
+            let decrypted_size = self.symmetric_decrypt_and_verify_with_keys(remote_keys, &src, signed_range, encrypted_range, &mut decrypted_data)?;
 
             // Now we need to strip off signature
-            Self::update_message_size_and_truncate(decrypted_data, decrypted_size - signature_size, &self.decoding_limits)?
+            Bytes::from(Self::update_message_size_and_truncate(decrypted_data, decrypted_size - signature_size, &self.decoding_limits)?)
         } else {
-            src.to_vec()
+            src
         };
 
+        // A message verified with the current token means the other side has finished switching
+        // over so the previous token is discarded. This waits until the message has been verified
+        // so a forged message can't end the overlap period.
+        if received_token_id == Some(self.token_id) && self.previous_token.take().is_some() {
+            debug!("Other side of secure channel {} is using the renewed token {}", self.secure_channel_id, self.token_id);
+        }
+
         Ok(MessageChunk { data })
     }
 
@@ -742,7 +842,8 @@ impl SecureChannel {
     fn asymmetric_decrypt_and_verify(&self, security_policy: SecurityPolicy, verification_key: &PublicKey, receiver_thumbprint: ByteString, src: &[u8], encrypted_range: Range<usize>, their_key: Option<PrivateKey>, dst: &mut [u8]) -> Result<usize, StatusCode> {
         // Asymmetric encrypt requires the caller supply the security policy
         match security_policy {
-            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => {}
+            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 |
+            SecurityPolicy::Aes128Sha256RsaOaep | SecurityPolicy::Aes256Sha256RsaPss => {}
             _ => {
                 return Err(StatusCode::BadSecurityPolicyRejected);
             }
@@ -840,7 +941,11 @@ impl SecureChannel {
     }
 
     fn local_keys(&self) -> &(Vec<u8>, AesKey, Vec<u8>) {
-        self.local_keys.as_ref().unwrap()
+        if let Some(previous_token) = self.sending_previous_token() {
+            previous_token.local_keys.as_ref().unwrap()
+        } else {
+            self.local_keys.as_ref().unwrap()
+        }
     }
 
     fn remote_keys(&self) -> &(Vec<u8>, AesKey, Vec<u8>) {
@@ -856,13 +961,12 @@ impl SecureChannel {
         &(self.local_keys()).0
     }
 
-    fn decryption_keys(&self) -> (&AesKey, &[u8]) {
-        let keys = self.remote_keys();
-        (&keys.1, &keys.2)
+    fn decryption_keys(remote_keys: &(Vec<u8>, AesKey, Vec<u8>)) -> (&AesKey, &[u8]) {
+        (&remote_keys.1, &remote_keys.2)
     }
 
-    fn verification_key(&self) -> &[u8] {
-        &(self.remote_keys()).0
+    fn verification_key(remote_keys: &(Vec<u8>, AesKey, Vec<u8>)) -> &[u8] {
+        &remote_keys.0
     }
 
     /// Encode data using security. Destination buffer is expected to be same size as src and expected
@@ -944,6 +1048,11 @@ impl SecureChannel {
     /// S - Padding         - E
     ///     Signature       - E
     pub fn symmetric_decrypt_and_verify(&self, src: &[u8], signed_range: Range<usize>, encrypted_range: Range<usize>, dst: &mut [u8]) -> Result<usize, StatusCode> {
+        self.symmetric_decrypt_and_verify_with_keys(self.remote_keys(), src, signed_range, encrypted_range, dst)
+    }
+
+    /// Decrypts and verifies data with the supplied set of their keys
+    fn symmetric_decrypt_and_verify_with_keys(&self, remote_keys: &(Vec<u8>, AesKey, Vec<u8>), src: &[u8], signed_range: Range<usize>, encrypted_range: Range<usize>, dst: &mut [u8]) -> Result<usize, StatusCode> {
         match self.security_mode {
             MessageSecurityMode::None => {
                 // Just copy everything from src to dst
@@ -958,7 +1067,7 @@ impl SecureChannel {
                 dst[all].copy_from_slice(&src[all]);
                 // Verify signature
                 trace!("Verifying range from {:?} to signature {}..", signed_range, signed_range.end);
-                let verification_key = self.verification_key();
+                let verification_key = Self::verification_key(remote_keys);
                 self.security_policy.symmetric_verify_signature(verification_key, &dst[signed_range.clone()], &dst[signed_range.end..])?;
 
                 Ok(encrypted_range.end)
@@ -978,7 +1087,7 @@ impl SecureChannel {
 
                 // Decrypt encrypted portion
                 let mut decrypted_tmp = vec![0u8; ciphertext_size + 16]; // tmp includes +16 for blocksize
-                let (key, iv) = self.decryption_keys();
+                let (key, iv) = Self::decryption_keys(remote_keys);
 
                 trace!("Secure decrypt called with encrypted range {:?}", encrypted_range);
                 let decrypted_size = self.security_policy.symmetric_decrypt(key, iv, &src[encrypted_range.clone()], &mut decrypted_tmp[..])?;
@@ -991,7 +1100,7 @@ impl SecureChannel {
                 // Verify signature (after encrypted portion)
                 let signature_range = (encrypted_range.end - self.security_policy.symmetric_signature_size())..encrypted_range.end;
                 trace!("signed range = {:?}, signature range = {:?}", signed_range, signature_range);
-                let verification_key = self.verification_key();
+                let verification_key = Self::verification_key(remote_keys);
                 self.security_policy.symmetric_verify_signature(verification_key, &dst[signed_range.clone()], &dst[signature_range])?;
                 Ok(encrypted_range.end)
             }
@@ -1005,7 +1114,8 @@ impl SecureChannel {
     // Panic code which requires a policy
     fn expect_supported_security_policy(&self) {
         match self.security_policy {
-            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => {}
+            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 |
+            SecurityPolicy::Aes128Sha256RsaOaep | SecurityPolicy::Aes256Sha256RsaPss => {}
             _ => {
                 panic!("Unsupported security policy");
             }
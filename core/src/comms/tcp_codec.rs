@@ -4,6 +4,7 @@
 //! * HEL - Hello message
 //! * ACK - Acknowledge message
 //! * ERR - Error message
+//! * RHE - Reverse hello message
 //! * MSG - Message chunk
 //! * OPN - Open Secure Channel message
 //! * CLO - Close Secure Channel message
@@ -13,7 +14,7 @@ use std::sync::{Arc, RwLock};
 use bytes::BytesMut;
 use tokio_io::codec::{Encoder, Decoder};
 
-use opcua_types::tcp_types::{MessageType, MessageHeader, HelloMessage, AcknowledgeMessage, ErrorMessage, MESSAGE_HEADER_LEN};
+use opcua_types::tcp_types::{MessageType, MessageHeader, HelloMessage, AcknowledgeMessage, ErrorMessage, ReverseHelloMessage, MESSAGE_HEADER_LEN};
 use opcua_types::encoding::{BinaryEncoder, DecodingLimits};
 use opcua_types::status_code::StatusCode;
 
@@ -24,12 +25,13 @@ pub enum Message {
     Hello(HelloMessage),
     Acknowledge(AcknowledgeMessage),
     Error(ErrorMessage),
+    ReverseHello(ReverseHelloMessage),
     Chunk(MessageChunk),
 }
 
 /// Implements a tokio codec that as close as possible, allows incoming data to be transformed into
-/// OPC UA message chunks with no intermediate buffers. Chunks are subsequently transformed into
-/// messages so there is still some buffers within message chunks, but not at the raw socket level.
+/// OPC UA message chunks with no intermediate buffers. A chunk shares the bytes of the frame it was
+/// read from, and chunks are subsequently decoded into messages directly from those bytes.
 pub struct TcpCodec {
     decoding_limits: DecodingLimits,
     abort: Arc<RwLock<bool>>,
@@ -61,11 +63,17 @@ impl Decoder for TcpCodec {
             if buf.len() >= message_size {
                 // Extract the message bytes from the buffer & decode them into a message
                 let mut buf = buf.split_to(message_size);
-                let message = Self::decode_message(message_header, &mut buf, &self.decoding_limits)
-                    .map_err(|e| {
-                        error!("Codec got an error {:?} while decoding a message", e);
-                        io::Error::from(e)
-                    })?;
+                let message = if message_header.message_type == MessageType::Chunk {
+                    // Chunks keep the bytes of the frame rather than copying them since they are
+                    // large and frequent
+                    MessageChunk::from_bytes(buf.freeze(), &self.decoding_limits).map(Message::Chunk)
+                } else {
+                    Self::decode_message(message_header, &mut buf, &self.decoding_limits)
+                };
+                let message = message.map_err(|e| {
+                    error!("Codec got an error {:?} while decoding a message", e);
+                    io::Error::from(e)
+                })?;
                 Ok(Some(message))
             } else {
                 // Not enough bytes
@@ -87,6 +95,7 @@ impl Encoder for TcpCodec {
             Message::Hello(msg) => msg.to_vec(),
             Message::Acknowledge(msg) => msg.to_vec(),
             Message::Error(msg) => msg.to_vec(),
+            Message::ReverseHello(msg) => msg.to_vec(),
             Message::Chunk(msg) => msg.to_vec(),
         };
         // Append the bytes of the message onto the outgoing buffer
@@ -123,6 +132,9 @@ impl TcpCodec {
             MessageType::Error => {
                 Ok(Message::Error(ErrorMessage::decode(&mut buf, decoding_limits)?))
             }
+            MessageType::ReverseHello => {
+                Ok(Message::ReverseHello(ReverseHelloMessage::decode(&mut buf, decoding_limits)?))
+            }
             MessageType::Chunk => {
                 Ok(Message::Chunk(MessageChunk::decode(&mut buf, decoding_limits)?))
             }
@@ -2,13 +2,60 @@
 use std::marker::Send;
 use std::result::Result;
 
+#[cfg(all(feature = "crypto", not(feature = "pure-rust-crypto")))]
 use openssl::symm::{Cipher, Crypter};
+#[cfg(all(feature = "crypto", not(feature = "pure-rust-crypto")))]
 use openssl::symm::Mode;
 
+#[cfg(feature = "pure-rust-crypto")]
+use aes::{Aes128, Aes256};
+#[cfg(feature = "pure-rust-crypto")]
+use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit, block_padding::NoPadding};
+
 use opcua_types::status_code::StatusCode;
 
 use crate::crypto::SecurityPolicy;
 
+#[cfg(feature = "pure-rust-crypto")]
+type Aes128CbcEnc = cbc::Encryptor<Aes128>;
+#[cfg(feature = "pure-rust-crypto")]
+type Aes128CbcDec = cbc::Decryptor<Aes128>;
+#[cfg(feature = "pure-rust-crypto")]
+type Aes256CbcEnc = cbc::Encryptor<Aes256>;
+#[cfg(feature = "pure-rust-crypto")]
+type Aes256CbcDec = cbc::Decryptor<Aes256>;
+
+/// The direction of a cipher operation
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum CipherMode {
+    Encrypt,
+    Decrypt,
+}
+
+/// The symmetric ciphers used by the supported security policies
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum AesCipher {
+    Aes128Cbc,
+    Aes256Cbc,
+}
+
+impl AesCipher {
+    fn block_size(&self) -> usize {
+        16
+    }
+
+    fn iv_length(&self) -> usize {
+        16
+    }
+
+    fn key_length(&self) -> usize {
+        match *self {
+            AesCipher::Aes128Cbc => 16,
+            AesCipher::Aes256Cbc => 32,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct AesKey {
     value: Vec<u8>,
@@ -27,7 +74,7 @@ impl AesKey {
         &self.value
     }
 
-    fn validate_aes_args(cipher: &Cipher, src: &[u8], iv: &[u8], dst: &mut [u8]) -> Result<(), StatusCode> {
+    fn validate_aes_args(cipher: AesCipher, src: &[u8], iv: &[u8], dst: &mut [u8]) -> Result<(), StatusCode> {
         if dst.len() < src.len() + cipher.block_size() {
             error!("Dst buffer is too small {} vs {} + {}", src.len(), dst.len(), cipher.block_size());
             Err(StatusCode::BadUnexpectedError)
@@ -43,30 +90,33 @@ impl AesKey {
         }
     }
 
-    fn cipher(&self) -> Cipher {
+    fn cipher(&self) -> AesCipher {
         match self.security_policy {
-            SecurityPolicy::Basic128Rsa15 => {
-                // Aes128_CBC
-                Cipher::aes_128_cbc()
-            }
-            SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => {
-                // Aes256_CBC
-                Cipher::aes_256_cbc()
-            }
+            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Aes128Sha256RsaOaep => AesCipher::Aes128Cbc,
+            SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 | SecurityPolicy::Aes256Sha256RsaPss => AesCipher::Aes256Cbc,
             _ => {
                 panic!("Unsupported")
             }
         }
     }
 
-    /// Encrypt or decrypt  data according to the mode
-    fn do_cipher(&self, mode: Mode, src: &[u8], iv: &[u8], dst: &mut [u8]) -> Result<usize, StatusCode> {
-        let cipher = self.cipher();
+    /// Encrypt or decrypt data according to the mode using OpenSSL
+    #[cfg(all(feature = "crypto", not(feature = "pure-rust-crypto")))]
+    fn do_cipher(&self, mode: CipherMode, src: &[u8], iv: &[u8], dst: &mut [u8]) -> Result<usize, StatusCode> {
+        let aes_cipher = self.cipher();
 
-        Self::validate_aes_args(&cipher, src, iv, dst)?;
+        Self::validate_aes_args(aes_cipher, src, iv, dst)?;
 
         trace!("Encrypting block of size {}", src.len());
 
+        let cipher = match aes_cipher {
+            AesCipher::Aes128Cbc => Cipher::aes_128_cbc(),
+            AesCipher::Aes256Cbc => Cipher::aes_256_cbc(),
+        };
+        let mode = match mode {
+            CipherMode::Encrypt => Mode::Encrypt,
+            CipherMode::Decrypt => Mode::Decrypt,
+        };
         let crypter = Crypter::new(cipher, mode, &self.value, Some(iv));
         if let Ok(mut crypter) = crypter {
             crypter.pad(false);
@@ -91,24 +141,70 @@ impl AesKey {
         }
     }
 
+    /// Encrypt or decrypt data according to the mode using the pure Rust AES implementation. The
+    /// cipher works in place so the source is copied into the destination first.
+    #[cfg(feature = "pure-rust-crypto")]
+    fn do_cipher(&self, mode: CipherMode, src: &[u8], iv: &[u8], dst: &mut [u8]) -> Result<usize, StatusCode> {
+        let aes_cipher = self.cipher();
+
+        Self::validate_aes_args(aes_cipher, src, iv, dst)?;
+
+        trace!("Encrypting block of size {}", src.len());
+
+        let buffer = &mut dst[..src.len()];
+        buffer.copy_from_slice(src);
+
+        let result = match (aes_cipher, mode) {
+            (AesCipher::Aes128Cbc, CipherMode::Encrypt) => Aes128CbcEnc::new_from_slices(&self.value, iv)
+                .map(|c| c.encrypt_padded_mut::<NoPadding>(buffer, src.len()).is_ok()),
+            (AesCipher::Aes128Cbc, CipherMode::Decrypt) => Aes128CbcDec::new_from_slices(&self.value, iv)
+                .map(|c| c.decrypt_padded_mut::<NoPadding>(buffer).is_ok()),
+            (AesCipher::Aes256Cbc, CipherMode::Encrypt) => Aes256CbcEnc::new_from_slices(&self.value, iv)
+                .map(|c| c.encrypt_padded_mut::<NoPadding>(buffer, src.len()).is_ok()),
+            (AesCipher::Aes256Cbc, CipherMode::Decrypt) => Aes256CbcDec::new_from_slices(&self.value, iv)
+                .map(|c| c.decrypt_padded_mut::<NoPadding>(buffer).is_ok()),
+        };
+        match result {
+            Ok(true) => {
+                trace!("do cipher size {}", src.len());
+                Ok(src.len())
+            }
+            Ok(false) => {
+                error!("Encryption error, data is not a multiple of the block size");
+                Err(StatusCode::BadUnexpectedError)
+            }
+            Err(e) => {
+                error!("Encryption error, invalid key or iv length {:?}", e);
+                Err(StatusCode::BadUnexpectedError)
+            }
+        }
+    }
+
+    /// Without the `crypto` or `pure-rust-crypto` features there is no symmetric cipher
+    #[cfg(not(any(feature = "crypto", feature = "pure-rust-crypto")))]
+    fn do_cipher(&self, _mode: CipherMode, _src: &[u8], _iv: &[u8], _dst: &mut [u8]) -> Result<usize, StatusCode> {
+        error!("Symmetric encryption is unavailable without the crypto feature");
+        Err(StatusCode::BadSecurityPolicyRejected)
+    }
+
     pub fn block_size(&self) -> usize {
         self.cipher().block_size()
     }
 
     pub fn iv_length(&self) -> usize {
-        self.cipher().iv_len().unwrap()
+        self.cipher().iv_length()
     }
 
     pub fn key_length(&self) -> usize {
-        self.cipher().key_len()
+        self.cipher().key_length()
     }
 
     pub fn encrypt(&self, src: &[u8], iv: &[u8], dst: &mut [u8]) -> Result<usize, StatusCode> {
-        self.do_cipher(Mode::Encrypt, src, iv, dst)
+        self.do_cipher(CipherMode::Encrypt, src, iv, dst)
     }
 
     /// Decrypts data using AES. The initialization vector is the nonce generated for the secure channel
     pub fn decrypt(&self, src: &[u8], iv: &[u8], dst: &mut [u8]) -> Result<usize, StatusCode> {
-        self.do_cipher(Mode::Decrypt, src, iv, dst)
+        self.do_cipher(CipherMode::Decrypt, src, iv, dst)
     }
 }
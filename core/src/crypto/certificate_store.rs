@@ -3,7 +3,11 @@
 use std::path::{Path, PathBuf};
 use std::fs::{File, metadata};
 use std::io::{Write, Read};
+use std::sync::RwLock;
 
+use chrono::Utc;
+
+#[cfg(feature = "crypto")]
 use openssl::{
     x509::{self, extension::*},
     pkey,
@@ -12,11 +16,14 @@ use openssl::{
     hash::*,
 };
 
+use opcua_types::ByteString;
 use opcua_types::service_types::ApplicationDescription;
 use opcua_types::status_code::StatusCode;
 
+use crate::{trace_read_lock_unwrap, trace_write_lock_unwrap};
 use crate::crypto::{
-    x509::{X509, X509Data},
+    crl::CertificateRevocationList,
+    x509::{X509, X509Data, KEY_USAGE_DIGITAL_SIGNATURE, KEY_USAGE_KEY_ENCIPHERMENT, KEY_USAGE_KEY_CERT_SIGN},
     pkey::PrivateKey,
 };
 
@@ -33,19 +40,62 @@ const OWN_PRIVATE_KEY_DIR: &str = "private";
 const TRUSTED_CERTS_DIR: &str = "trusted";
 /// The directory holding rejected certificates
 const REJECTED_CERTS_DIR: &str = "rejected";
+/// The directory holding DER encoded certificate revocation lists
+const CRL_DIR: &str = "crl";
+/// The directory holding the certs of issuers which are used to build chains but are not trusted
+const ISSUERS_DIR: &str = "issuers";
+
+/// The maximum number of issuers between a cert and the root of its chain
+const MAX_CHAIN_LENGTH: usize = 10;
+
+/// The checks made when validating a certificate. Each may be relaxed individually, e.g. to accept
+/// certs which lack the extensions that OPC UA requires, but all are made by default.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CertificateValidation {
+    /// Check the cert and its issuers are within their validity period
+    pub check_time: bool,
+    /// Check the hostname that was connected to is one of the cert's subject alt names
+    pub check_hostname: bool,
+    /// Check the application uri of the other end is the cert's uri subject alt name
+    pub check_application_uri: bool,
+    /// Check the basic constraints of each issuer allow it to issue certs
+    pub check_basic_constraints: bool,
+    /// Check the key usage of the cert allows signing and key encipherment, and that of each
+    /// issuer allows signing certs
+    pub check_key_usage: bool,
+    /// Check the cert and its issuers against the revocation lists of their issuers. A cert issued
+    /// by a certificate authority is rejected unless the authority has a current list. Turn it off
+    /// only where the authorities publish no lists.
+    pub check_revocation: bool,
+}
+
+impl Default for CertificateValidation {
+    fn default() -> Self {
+        CertificateValidation {
+            check_time: true,
+            check_hostname: true,
+            check_application_uri: true,
+            check_basic_constraints: true,
+            check_key_usage: true,
+            check_revocation: true,
+        }
+    }
+}
 
 /// The certificate store manages the storage of a server/client's own certificate & private key
 /// and the trust / rejection of certificates from the other end.
 pub struct CertificateStore {
     /// Path to the certificate store on disk.
     pub pki_path: PathBuf,
-    /// Timestamps of the cert are normally checked on the cert to ensure it cannot be used before
-    /// or after its limits, but this check can be disabled.
-    pub check_time: bool,
+    /// The checks made on certs, each of which can be disabled.
+    pub validation: CertificateValidation,
     /// Ordinarily an unknown cert will be dropped into the rejected folder, but it can be dropped
     /// into the trusted folder if this flag is set. Certs in the trusted folder must still pass
     /// validity checks.
     pub trust_unknown_certs: bool,
+    /// The revocation lists read from the crl directory, or the reason they could not be read
+    crls: RwLock<Result<Vec<CertificateRevocationList>, StatusCode>>,
 }
 
 impl CertificateStore {
@@ -53,11 +103,14 @@ impl CertificateStore {
     /// It is a bad idea to have more than one running instance pointing to the same path
     /// location on disk.
     pub fn new(pki_path: &Path) -> CertificateStore {
-        CertificateStore {
+        let certificate_store = CertificateStore {
             pki_path: pki_path.to_path_buf(),
-            check_time: true,
+            validation: CertificateValidation::default(),
             trust_unknown_certs: false,
-        }
+            crls: RwLock::new(Ok(Vec::new())),
+        };
+        certificate_store.reload_crls();
+        certificate_store
     }
 
     /// Sets up the certificate store, creates the path to it, and optionally creates a demo cert
@@ -98,6 +151,7 @@ impl CertificateStore {
     ///
     /// In particular, application instance cert requires subjectAltName to specify alternate
     /// hostnames / ip addresses that the host runs on.
+    #[cfg(feature = "crypto")]
     pub fn create_cert_and_pkey(args: &X509Data) -> Result<(X509, PrivateKey), String> {
         // Create a public / private keypair
         let pkey = {
@@ -181,6 +235,59 @@ impl CertificateStore {
         Ok((X509::from(cert), PrivateKey::wrap_private_key(pkey)))
     }
 
+    /// Certificates cannot be created without the `crypto` feature.
+    #[cfg(not(feature = "crypto"))]
+    pub fn create_cert_and_pkey(_args: &X509Data) -> Result<(X509, PrivateKey), String> {
+        Err("Certificates cannot be created without the crypto feature".to_string())
+    }
+
+    /// Creates a DER encoded PKCS #10 certificate signing request for the public key of the
+    /// private key, which a certificate authority can use to issue a cert with the subject name
+    /// and subject alt names of the args. Entries of the subject name which are empty are left out.
+    #[cfg(feature = "crypto")]
+    pub fn create_signing_request(args: &X509Data, pkey: &PrivateKey) -> Result<Vec<u8>, String> {
+        use openssl::stack::Stack;
+
+        let to_err = |err: openssl::error::ErrorStack| format!("Cannot create certificate signing request, {}", err);
+        let mut builder = x509::X509ReqBuilder::new().map_err(to_err)?;
+        builder.set_version(0).map_err(to_err)?;
+        let subject_name = {
+            let mut name = x509::X509NameBuilder::new().map_err(to_err)?;
+            let entries = [("CN", &args.common_name), ("O", &args.organization), ("OU", &args.organizational_unit), ("C", &args.country), ("ST", &args.state)];
+            for (field, value) in entries.iter().filter(|(_, value)| !value.is_empty()) {
+                name.append_entry_by_text(field, value).map_err(to_err)?;
+            }
+            name.build()
+        };
+        builder.set_subject_name(&subject_name).map_err(to_err)?;
+        builder.set_pubkey(pkey.value()).map_err(to_err)?;
+
+        // Subject alt names - the application uri followed by the alt hostnames
+        if !args.alt_host_names.is_empty() {
+            let mut subject_alternative_name = SubjectAlternativeName::new();
+            for (i, alt_host_name) in args.alt_host_names.iter().enumerate() {
+                if i == 0 {
+                    subject_alternative_name.uri(alt_host_name);
+                } else {
+                    subject_alternative_name.dns(alt_host_name);
+                }
+            }
+            let subject_alternative_name = subject_alternative_name.build(&builder.x509v3_context(None)).map_err(to_err)?;
+            let mut extensions = Stack::new().map_err(to_err)?;
+            extensions.push(subject_alternative_name).map_err(to_err)?;
+            builder.add_extensions(&extensions).map_err(to_err)?;
+        }
+
+        builder.sign(pkey.value(), MessageDigest::sha256()).map_err(to_err)?;
+        builder.build().to_der().map_err(to_err)
+    }
+
+    /// Certificate signing requests cannot be created without the `crypto` feature.
+    #[cfg(not(feature = "crypto"))]
+    pub fn create_signing_request(_args: &X509Data, _pkey: &PrivateKey) -> Result<Vec<u8>, String> {
+        Err("Certificate signing requests cannot be created without the crypto feature".to_string())
+    }
+
     /// Reads a private key from a path on disk.
     pub fn read_pkey(path: &Path) -> Result<PrivateKey, String> {
         if let Ok(pkey_info) = metadata(path) {
@@ -188,8 +295,8 @@ impl CertificateStore {
                 let mut buffer = Vec::with_capacity(pkey_info.len() as usize);
                 let _ = f.read_to_end(&mut buffer);
                 drop(f);
-                if let Ok(pkey) = pkey::PKey::private_key_from_pem(&buffer) {
-                    return Ok(PrivateKey::wrap_private_key(pkey));
+                if let Ok(pkey) = PrivateKey::from_pem(&buffer) {
+                    return Ok(pkey);
                 }
             }
         }
@@ -217,21 +324,30 @@ impl CertificateStore {
     pub fn create_and_store_application_instance_cert(&self, args: &X509Data, overwrite: bool) -> Result<(X509, PrivateKey), String> {
         // Create the cert and corresponding private key
         let (cert, pkey) = CertificateStore::create_cert_and_pkey(args)?;
+        self.store_own_cert_and_pkey(&cert, &pkey, overwrite)?;
+        Ok((cert, pkey))
+    }
 
+    /// Writes the store's own certificate and private key, e.g. when a renewed certificate has
+    /// been issued for the application.
+    ///
+    /// # Errors
+    ///
+    /// A string description of any failure
+    ///
+    pub fn store_own_cert_and_pkey(&self, cert: &X509, pkey: &PrivateKey, overwrite: bool) -> Result<(), String> {
         // Public cert goes under own/
         let public_cert_path = CertificateStore::make_and_ensure_file_path(&self.own_cert_dir(), OWN_CERTIFICATE_NAME)?;
         // Private key goes under private/
         let private_key_path = CertificateStore::make_and_ensure_file_path(&self.private_key_dir(), OWN_PRIVATE_KEY_NAME)?;
 
         // Write the public cert
-        CertificateStore::store_cert(&cert, &public_cert_path, overwrite)?;
+        CertificateStore::store_cert(cert, &public_cert_path, overwrite)?;
 
         // Write the private key
-        let pem = pkey.private_key_to_pem().unwrap();
+        let pem = pkey.private_key_to_pem().map_err(|_| "Cannot turn private key to PEM".to_string())?;
         info!("Writing private key to {}", private_key_path.display());
-        CertificateStore::write_to_file(&pem, &private_key_path, overwrite)?;
-
-        Ok((cert, pkey))
+        CertificateStore::write_to_file(&pem, &private_key_path, overwrite)
     }
 
     /// Validates the cert as trusted and valid. If the cert is unknown, it will be written to
@@ -243,7 +359,13 @@ impl CertificateStore {
     /// order to validate it.
     ///
     pub fn validate_or_reject_application_instance_cert(&self, cert: &X509, hostname: Option<&str>, application_uri: Option<&str>) -> StatusCode {
-        let result = self.validate_application_instance_cert(cert, hostname, application_uri);
+        self.validate_or_reject_application_instance_cert_chain(cert, &[], hostname, application_uri)
+    }
+
+    /// Validates a cert that was supplied with the certs of its issuers, and rejects it in the
+    /// same way as `validate_or_reject_application_instance_cert`.
+    pub fn validate_or_reject_application_instance_cert_chain(&self, cert: &X509, issuers: &[X509], hostname: Option<&str>, application_uri: Option<&str>) -> StatusCode {
+        let result = self.validate_application_instance_cert_chain(cert, issuers, hostname, application_uri);
         if result.is_bad() {
             match result {
                 StatusCode::BadUnexpectedError | StatusCode::BadSecurityChecksFailed => {
@@ -284,9 +406,9 @@ impl CertificateStore {
     }
 
     /// Validates the certificate according to the strictness set in the CertificateStore itself.
-    /// Validation might include checking the issue time, expiration time, revocation, trust chain
-    /// etc. In the first instance this function will only check if the cert is recognized
-    /// and is already contained in the trusted or rejected folder.
+    /// The cert must be in the trusted folder or be issued by a cert in the trusted folder, and
+    /// the chain from it to a self-signed root must be complete. The validity period, hostname,
+    /// application uri, key usage and revocation of the cert and of its issuers are then checked.
     ///
     /// # Errors
     ///
@@ -294,6 +416,19 @@ impl CertificateStore {
     /// order to validate it.
     ///
     pub fn validate_application_instance_cert(&self, cert: &X509, hostname: Option<&str>, application_uri: Option<&str>) -> StatusCode {
+        self.validate_application_instance_cert_chain(cert, &[], hostname, application_uri)
+    }
+
+    /// Validates the certificate in the same way as `validate_application_instance_cert` where
+    /// the cert was supplied with the certs of its issuers, e.g. in a security header. The supplied
+    /// issuers and those in the issuers and trusted folders are used to build the cert's chain.
+    ///
+    /// # Errors
+    ///
+    /// A non `Good` status code indicates a failure in the cert or in some action required in
+    /// order to validate it.
+    ///
+    pub fn validate_application_instance_cert_chain(&self, cert: &X509, issuers: &[X509], hostname: Option<&str>, application_uri: Option<&str>) -> StatusCode {
         let cert_file_name = CertificateStore::cert_file_name(&cert);
         debug!("Validating cert with name on disk {}", cert_file_name);
 
@@ -314,68 +449,282 @@ impl CertificateStore {
 
         // Check the trusted folder. These checks are more strict to ensure the cert is genuinely
         // trusted
-        {
-            // Check the trusted folder
-            let mut cert_path = self.trusted_certs_dir();
-            if !cert_path.exists() {
-                error!("Path for rejected certificates {} does not exist", cert_path.display());
-                return StatusCode::BadUnexpectedError;
-            }
-            cert_path.push(&cert_file_name);
+        let mut cert_path = self.trusted_certs_dir();
+        if !cert_path.exists() {
+            error!("Path for rejected certificates {} does not exist", cert_path.display());
+            return StatusCode::BadUnexpectedError;
+        }
+        let trusted_certs = CertificateStore::read_certs(&cert_path);
+        cert_path.push(&cert_file_name);
 
-            // Check if cert is in the trusted folder
-            if !cert_path.exists() {
-                // ... trust checks based on ca could be added here to add cert straight to trust folder
-                if self.trust_unknown_certs {
-                    // Put the unknown cert into the trusted folder
-                    warn!("Certificate {} is unknown but policy will store it into the trusted directory", cert_file_name);
-                    let _ = self.store_trusted_cert(cert);
-                    // Note that we drop through and still check the cert for validity
-                } else {
-                    warn!("Certificate {} is unknown and untrusted so it will be stored in rejected directory", cert_file_name);
-                    let _ = self.store_rejected_cert(cert);
-                    return StatusCode::BadCertificateUntrusted;
-                }
-            }
+        // Find the issuers of the cert up to the root of its chain
+        let chain = match self.build_issuer_chain(cert, issuers, &trusted_certs) {
+            Ok(chain) => chain,
+            Err(status_code) => return status_code,
+        };
 
+        // Check if cert is in the trusted folder
+        if cert_path.exists() {
             // Read the cert from the trusted folder to make sure it matches the one supplied
             if !CertificateStore::ensure_cert_and_file_are_the_same(cert, &cert_path) {
                 error!("Certificate in memory does not match the one on disk {} so cert will automatically be treated as untrusted", cert_path.display());
                 return StatusCode::BadUnexpectedError;
             }
+        } else if chain.iter().any(|issuer| trusted_certs.iter().any(|trusted| CertificateStore::is_same_cert(issuer, trusted))) {
+            debug!("Certificate {} is trusted because one of its issuers is trusted", cert_file_name);
+        } else if self.trust_unknown_certs {
+            // Put the unknown cert into the trusted folder
+            warn!("Certificate {} is unknown but policy will store it into the trusted directory", cert_file_name);
+            let _ = self.store_trusted_cert(cert);
+            // Note that we drop through and still check the cert for validity
+        } else {
+            warn!("Certificate {} is unknown and untrusted so it will be stored in rejected directory", cert_file_name);
+            let _ = self.store_rejected_cert(cert);
+            return StatusCode::BadCertificateUntrusted;
+        }
 
-            // Now inspect the cert not before / after values to ensure its validity
-            if self.check_time {
-                use chrono::Utc;
-                let now = Utc::now();
-                let status_code = cert.is_time_valid(&now);
-                if status_code.is_bad() {
-                    return status_code;
-                }
+        // A CRL is signed by the issuer of the cert it revokes, or by the root itself
+        let status_code = self.validate_cert(cert, chain.first().unwrap_or(cert), hostname, application_uri);
+        if status_code.is_bad() {
+            return status_code;
+        }
+        for (i, issuer) in chain.iter().enumerate() {
+            let status_code = self.validate_issuer_cert(issuer, chain.get(i + 1).unwrap_or(issuer));
+            if status_code.is_bad() {
+                return status_code;
             }
+        }
+        StatusCode::Good
+    }
+
+    /// Finds the chain of issuers from the cert to a self-signed root from the supplied issuers
+    /// and those in the issuers and trusted folders. The chain is empty for a self-signed cert.
+    fn build_issuer_chain(&self, cert: &X509, issuers: &[X509], trusted_certs: &[X509]) -> Result<Vec<X509>, StatusCode> {
+        let candidates = self.issuer_candidates(issuers, trusted_certs);
 
-            // Compare the hostname of the cert against the cert supplied
-            if let Some(hostname) = hostname {
+        let mut chain: Vec<X509> = Vec::new();
+        let mut current = cert.clone();
+        while !current.is_self_issued() {
+            if chain.len() == MAX_CHAIN_LENGTH {
+                error!("Certificate chain is longer than {} issuers", MAX_CHAIN_LENGTH);
+                return Err(StatusCode::BadCertificateChainIncomplete);
+            }
+            if let Some(issuer) = candidates.iter().find(|candidate| current.is_issued_by(candidate)) {
+                current = issuer.clone();
+                chain.push(issuer.clone());
+            } else {
+                error!("Cannot find the issuer of certificate {}", CertificateStore::cert_file_name(&current));
+                return Err(StatusCode::BadCertificateChainIncomplete);
+            }
+        }
+        Ok(chain)
+    }
+
+    /// Returns the certs which may have issued a cert, i.e. the supplied issuers followed by those
+    /// in the issuers and trusted folders
+    fn issuer_candidates(&self, issuers: &[X509], trusted_certs: &[X509]) -> Vec<X509> {
+        let mut candidates = issuers.to_vec();
+        candidates.extend(CertificateStore::read_certs(&self.issuers_dir()));
+        candidates.extend(trusted_certs.iter().cloned());
+        candidates
+    }
+
+    /// Checks the cert that was supplied by the other end
+    fn validate_cert(&self, cert: &X509, issuer: &X509, hostname: Option<&str>, application_uri: Option<&str>) -> StatusCode {
+        // Now inspect the cert not before / after values to ensure its validity
+        if self.validation.check_time {
+            let now = Utc::now();
+            let status_code = cert.is_time_valid(&now);
+            if status_code.is_bad() {
+                return status_code;
+            }
+        }
+
+        // Compare the hostname of the cert against the cert supplied
+        if let Some(hostname) = hostname {
+            if self.validation.check_hostname {
                 let status_code = cert.is_hostname_valid(hostname);
                 if status_code.is_bad() {
                     return status_code;
                 }
             }
+        }
 
-            // Compare the application / product uri to the supplied application description
-            if let Some(application_uri) = application_uri {
+        // Compare the application / product uri to the supplied application description
+        if let Some(application_uri) = application_uri {
+            if self.validation.check_application_uri {
                 let status_code = cert.is_application_uri_valid(application_uri);
                 if status_code.is_bad() {
                     return status_code;
                 }
             }
+        }
 
-            // Other tests that we might do with trust lists
-            // ... issuer
-            // ... trust (self-signed, ca etc.)
-            // ... revocation
+        // The key must be usable to sign and to encrypt keys, Part 6 Table 23
+        if self.validation.check_key_usage {
+            let required = KEY_USAGE_DIGITAL_SIGNATURE | KEY_USAGE_KEY_ENCIPHERMENT;
+            if cert.key_usage().map_or(true, |key_usage| key_usage & required != required) {
+                error!("Certificate key usage does not allow signing and key encipherment");
+                return StatusCode::BadCertificateUseNotAllowed;
+            }
+        }
+
+        // Check the cert has not been revoked
+        if self.validation.check_revocation {
+            self.check_revocation_by(cert, issuer)
+        } else {
+            StatusCode::Good
+        }
+    }
+
+    /// Checks an issuer in the chain of the cert that was supplied by the other end. The issuer
+    /// was itself issued by the next cert in the chain, or by itself if it is the root.
+    fn validate_issuer_cert(&self, issuer: &X509, next_issuer: &X509) -> StatusCode {
+        let issuer_file_name = CertificateStore::cert_file_name(issuer);
+        if self.validation.check_basic_constraints && !issuer.is_ca() {
+            error!("Issuer {} is not a certificate authority", issuer_file_name);
+            return StatusCode::BadCertificateIssuerUseNotAllowed;
+        }
+        if self.validation.check_key_usage && issuer.key_usage().map_or(false, |key_usage| key_usage & KEY_USAGE_KEY_CERT_SIGN == 0) {
+            error!("Issuer {} key usage does not allow signing certificates", issuer_file_name);
+            return StatusCode::BadCertificateIssuerUseNotAllowed;
+        }
+        if self.validation.check_time {
+            let now = Utc::now();
+            if issuer.is_time_valid(&now).is_bad() {
+                return StatusCode::BadCertificateIssuerTimeInvalid;
+            }
+        }
+        if !self.validation.check_revocation {
+            return StatusCode::Good;
+        }
+        match self.check_revocation_by(issuer, next_issuer) {
+            StatusCode::BadCertificateRevoked => StatusCode::BadCertificateIssuerRevoked,
+            StatusCode::BadCertificateRevocationUnknown => StatusCode::BadCertificateIssuerRevocationUnknown,
+            status_code => status_code
         }
-        StatusCode::Good
+    }
+
+    /// Tests if two certs are the same by their DER encoded form
+    fn is_same_cert(cert: &X509, cert2: &X509) -> bool {
+        match (cert.to_der(), cert2.to_der()) {
+            (Ok(der), Ok(der2)) => der == der2,
+            _ => false
+        }
+    }
+
+    /// Reads every cert in a directory, skipping any that cannot be read
+    fn read_certs(path: &Path) -> Vec<X509> {
+        if let Ok(entries) = std::fs::read_dir(path) {
+            entries.filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                .filter_map(|path| {
+                    CertificateStore::read_cert(&path).map_err(|err| {
+                        warn!("Skipping certificate, {}", err);
+                    }).ok()
+                })
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Checks the cert against the revocation lists of its issuer. The issuer is found amongst the
+    /// supplied issuers and the certs in the issuers and trusted folders.
+    ///
+    /// # Errors
+    ///
+    /// `BadCertificateRevoked` if a list revokes the cert, or `BadCertificateRevocationUnknown`
+    /// if it is unknown whether the cert is revoked because the issuer cannot be found, the lists
+    /// cannot be read or the issuer has no current list.
+    ///
+    pub fn check_revocation(&self, cert: &X509, issuers: &[X509]) -> StatusCode {
+        if cert.is_self_issued() {
+            return self.check_revocation_by(cert, cert);
+        }
+        let trusted_certs = CertificateStore::read_certs(&self.trusted_certs_dir());
+        match self.issuer_candidates(issuers, &trusted_certs).iter().find(|candidate| cert.is_issued_by(candidate)) {
+            Some(issuer) => self.check_revocation_by(cert, issuer),
+            None => {
+                error!("Cannot find the issuer of certificate {} to check its revocation", CertificateStore::cert_file_name(cert));
+                StatusCode::BadCertificateRevocationUnknown
+            }
+        }
+    }
+
+    /// Checks the cert against the current revocation lists signed by its issuer. A cert issued by
+    /// a certificate authority must have a current list from the authority, whereas a self-signed
+    /// cert without one is not revoked.
+    fn check_revocation_by(&self, cert: &X509, issuer: &X509) -> StatusCode {
+        let crls = trace_read_lock_unwrap!(self.crls);
+        let crls = match *crls {
+            Ok(ref crls) => crls,
+            Err(status_code) => return status_code,
+        };
+        let now = Utc::now();
+        let mut has_current_crl = false;
+        for crl in crls.iter().filter(|crl| crl.is_issued_by(issuer)) {
+            if !crl.is_current(&now) {
+                warn!("Revocation list of the issuer of certificate {} is out of date", CertificateStore::cert_file_name(cert));
+                continue;
+            }
+            has_current_crl = true;
+            match crl.is_revoked(cert) {
+                Ok(true) => {
+                    warn!("Certificate {} has been revoked", CertificateStore::cert_file_name(cert));
+                    return StatusCode::BadCertificateRevoked;
+                }
+                Ok(false) => {}
+                Err(status_code) => return status_code,
+            }
+        }
+        if has_current_crl || cert.is_self_issued() {
+            StatusCode::Good
+        } else {
+            error!("There is no current revocation list of the issuer of certificate {}", CertificateStore::cert_file_name(cert));
+            StatusCode::BadCertificateRevocationUnknown
+        }
+    }
+
+    /// Reads the revocation lists in the crl directory again, e.g. after the administrator has
+    /// put a new list there. Lists which are written through the store are loaded straight away.
+    /// A missing crl directory means there are no revocation lists, while one which cannot be
+    /// read makes the revocation of every cert unknown until the lists are reloaded.
+    pub fn reload_crls(&self) {
+        let crls = self.read_crls();
+        if let Ok(ref crls) = crls {
+            debug!("Loaded {} certificate revocation lists", crls.len());
+        }
+        *trace_write_lock_unwrap!(self.crls) = crls;
+    }
+
+    /// Reads every revocation list in the crl directory
+    fn read_crls(&self) -> Result<Vec<CertificateRevocationList>, StatusCode> {
+        let crl_dir = self.crl_dir();
+        if !crl_dir.exists() {
+            return Ok(Vec::new());
+        }
+        let entries = std::fs::read_dir(&crl_dir).map_err(|_| {
+            error!("Cannot read revocation lists from {}", crl_dir.display());
+            StatusCode::BadCertificateRevocationUnknown
+        })?;
+        let mut crls = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|_| StatusCode::BadCertificateRevocationUnknown)?.path();
+            if path.is_file() {
+                let mut der = Vec::new();
+                File::open(&path).and_then(|mut file| file.read_to_end(&mut der)).map_err(|_| {
+                    error!("Cannot read revocation list {}", path.display());
+                    StatusCode::BadCertificateRevocationUnknown
+                })?;
+                crls.push(CertificateRevocationList::from_der(&der).map_err(|status_code| {
+                    error!("Revocation list {} is invalid", path.display());
+                    status_code
+                })?);
+            }
+        }
+        Ok(crls)
     }
 
     /// Returns a certificate file name from the cert's issuer and thumbprint fields.
@@ -404,7 +753,7 @@ impl CertificateStore {
     ///
     pub fn ensure_pki_path(&self) -> Result<(), String> {
         let mut path = self.pki_path.clone();
-        let subdirs = [OWN_CERTIFICATE_DIR, OWN_PRIVATE_KEY_DIR, TRUSTED_CERTS_DIR, REJECTED_CERTS_DIR];
+        let subdirs = [OWN_CERTIFICATE_DIR, OWN_PRIVATE_KEY_DIR, TRUSTED_CERTS_DIR, REJECTED_CERTS_DIR, CRL_DIR, ISSUERS_DIR];
         for subdir in &subdirs {
             path.push(subdir);
             CertificateStore::ensure_dir(&path)?;
@@ -476,6 +825,20 @@ impl CertificateStore {
         path
     }
 
+    /// Get the path to the issuers certs dir
+    pub fn issuers_dir(&self) -> PathBuf {
+        let mut path = PathBuf::from(&self.pki_path);
+        path.push(ISSUERS_DIR);
+        path
+    }
+
+    /// Get the path to the certificate revocation lists dir
+    pub fn crl_dir(&self) -> PathBuf {
+        let mut path = PathBuf::from(&self.pki_path);
+        path.push(CRL_DIR);
+        path
+    }
+
     /// Write a cert to the rejected directory. If the write succeeds, the function
     /// returns a path to the written file.
     ///
@@ -492,6 +855,102 @@ impl CertificateStore {
         Ok(cert_path)
     }
 
+    /// Writes the cert of an issuer to the issuers directory, where it is used to complete the
+    /// chains of certs without being trusted itself.
+    ///
+    /// # Errors
+    ///
+    /// A string description of any failure
+    ///
+    pub fn store_issuer_cert(&self, cert: &X509) -> Result<PathBuf, String> {
+        let cert_path = CertificateStore::make_and_ensure_file_path(&self.issuers_dir(), &CertificateStore::cert_file_name(cert))?;
+        CertificateStore::store_cert(cert, &cert_path, true)?;
+        Ok(cert_path)
+    }
+
+    /// Reads the certs in the rejected directory
+    pub fn rejected_certs(&self) -> Vec<X509> {
+        CertificateStore::read_certs(&self.rejected_certs_dir())
+    }
+
+    /// Reads the certs in the trusted directory
+    pub fn trusted_certs(&self) -> Vec<X509> {
+        CertificateStore::read_certs(&self.trusted_certs_dir())
+    }
+
+    /// Reads the certs in the issuers directory
+    pub fn issuer_certs(&self) -> Vec<X509> {
+        CertificateStore::read_certs(&self.issuers_dir())
+    }
+
+    /// Reads the DER encoded revocation lists in the crl directory, skipping any that cannot be read
+    pub fn crls(&self) -> Vec<Vec<u8>> {
+        if let Ok(entries) = std::fs::read_dir(self.crl_dir()) {
+            entries.filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                .filter_map(|path| {
+                    let mut der = Vec::new();
+                    File::open(&path).and_then(|mut file| file.read_to_end(&mut der)).map(|_| der).map_err(|_| {
+                        warn!("Skipping revocation list {}, it cannot be read", path.display());
+                    }).ok()
+                })
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Replaces the certs in the trusted directory with the supplied certs
+    ///
+    /// # Errors
+    ///
+    /// A string description of any failure
+    ///
+    pub fn replace_trusted_certs(&self, certs: &[X509]) -> Result<(), String> {
+        CertificateStore::clear_dir(&self.trusted_certs_dir())?;
+        certs.iter().map(|cert| self.store_trusted_cert(cert).map(|_| ())).collect()
+    }
+
+    /// Replaces the certs in the issuers directory with the supplied certs
+    ///
+    /// # Errors
+    ///
+    /// A string description of any failure
+    ///
+    pub fn replace_issuer_certs(&self, certs: &[X509]) -> Result<(), String> {
+        CertificateStore::clear_dir(&self.issuers_dir())?;
+        certs.iter().map(|cert| self.store_issuer_cert(cert).map(|_| ())).collect()
+    }
+
+    /// Replaces the revocation lists in the crl directory with the supplied DER encoded lists
+    ///
+    /// # Errors
+    ///
+    /// A string description of any failure
+    ///
+    pub fn replace_crls(&self, crls: &[ByteString]) -> Result<(), String> {
+        // Every list is checked before any are removed
+        if crls.iter().any(|crl| CertificateRevocationList::from_der(crl.as_ref()).is_err()) {
+            return Err("Certificate revocation list is invalid".to_string());
+        }
+        let result = CertificateStore::clear_dir(&self.crl_dir())
+            .and_then(|_| crls.iter().map(|crl| self.write_crl(crl.as_ref()).map(|_| ())).collect());
+        self.reload_crls();
+        result
+    }
+
+    /// Removes the files in a directory
+    fn clear_dir(path: &Path) -> Result<(), String> {
+        if let Ok(entries) = std::fs::read_dir(path) {
+            for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).filter(|path| path.is_file()) {
+                info!("Removing {}", path.display());
+                std::fs::remove_file(&path).map_err(|_| format!("Cannot remove {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
     /// Writes a cert to the trusted directory. If the write succeeds, the function
     /// returns a path to the written file.
     ///
@@ -499,15 +958,41 @@ impl CertificateStore {
     ///
     /// A string description of any failure
     ///
-    fn store_trusted_cert(&self, cert: &X509) -> Result<PathBuf, String> {
-        // Store the cert in the rejected folder where untrusted certs go
-        let cert_file_name = CertificateStore::cert_file_name(&cert);
-        let mut cert_path = self.trusted_certs_dir();
-        cert_path.push(&cert_file_name);
+    pub fn store_trusted_cert(&self, cert: &X509) -> Result<PathBuf, String> {
+        let cert_path = CertificateStore::make_and_ensure_file_path(&self.trusted_certs_dir(), &CertificateStore::cert_file_name(cert))?;
         CertificateStore::store_cert(cert, &cert_path, true)?;
         Ok(cert_path)
     }
 
+    /// Writes a DER encoded certificate revocation list to the crl directory. The file is named
+    /// after the issuer of the list, so a newer list from the same issuer replaces the older one.
+    ///
+    /// # Errors
+    ///
+    /// A string description of any failure
+    ///
+    pub fn store_crl(&self, der: &[u8]) -> Result<PathBuf, String> {
+        let result = self.write_crl(der);
+        self.reload_crls();
+        result
+    }
+
+    /// Writes a DER encoded certificate revocation list to the crl directory without reloading
+    /// the lists
+    fn write_crl(&self, der: &[u8]) -> Result<PathBuf, String> {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let crl = CertificateRevocationList::from_der(der).map_err(|_| "Certificate revocation list is invalid".to_string())?;
+        let mut hasher = DefaultHasher::new();
+        crl.issuer().hash(&mut hasher);
+        let crl_file_name = format!("{:016x}.crl", hasher.finish());
+        let crl_path = CertificateStore::make_and_ensure_file_path(&self.crl_dir(), &crl_file_name)?;
+        info!("Writing certificate revocation list to {}", crl_path.display());
+        CertificateStore::write_to_file(der, &crl_path, true)?;
+        Ok(crl_path)
+    }
+
     /// Writes a cert to the specified directory
     ///
     /// # Errors
@@ -520,13 +1005,13 @@ impl CertificateStore {
         CertificateStore::write_to_file(&der, &path, overwrite)
     }
 
-    /// Reads an X509 certificate in .def format from disk
+    /// Reads an X509 certificate in .der format from disk
     ///
     /// # Errors
     ///
     /// A string description of any failure
     ///
-    fn read_cert(path: &Path) -> Result<X509, String> {
+    pub fn read_cert(path: &Path) -> Result<X509, String> {
         let file = File::open(path);
         if file.is_err() {
             return Err(format!("Could not open cert file {}", path.display()));
@@ -539,12 +1024,12 @@ impl CertificateStore {
             return Err(format!("Could not read bytes from cert file {}", path.display()));
         }
 
-        let cert = x509::X509::from_der(&cert);
+        let cert = X509::from_der(&cert);
         if cert.is_err() {
             return Err(format!("Could not read cert from cert file {}", path.display()));
         }
 
-        Ok(X509::from(cert.unwrap()))
+        Ok(cert.unwrap())
     }
 
     /// Makes a path
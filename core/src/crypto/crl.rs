@@ -0,0 +1,114 @@
+//! Certificate revocation lists (CRL) as defined by RFC 5280. A CRL names the certificates that
+//! its issuer has revoked by their serial numbers. A CRL is only used if it is signed by the issuer
+//! of the certificate being checked and is current, i.e. its next update has not passed.
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "crypto")]
+use std::cmp::Ordering;
+
+#[cfg(feature = "crypto")]
+use openssl::{asn1::Asn1Time, x509::{CrlStatus, X509Crl}};
+
+use opcua_types::status_code::StatusCode;
+
+use crate::crypto::x509::X509;
+
+/// This is a wrapper around the `OpenSSL` `X509Crl` revocation list
+#[cfg(feature = "crypto")]
+pub struct CertificateRevocationList {
+    value: X509Crl,
+}
+
+/// Without the `crypto` feature a revocation list cannot be read, so none are ever made
+#[cfg(not(feature = "crypto"))]
+pub struct CertificateRevocationList {}
+
+impl std::fmt::Debug for CertificateRevocationList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[crl]")
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl CertificateRevocationList {
+    /// Reads a DER encoded CRL, failing with `BadCertificateRevocationUnknown` if it cannot be
+    /// decoded.
+    pub fn from_der(der: &[u8]) -> Result<CertificateRevocationList, StatusCode> {
+        X509Crl::from_der(der)
+            .map(|value| CertificateRevocationList { value })
+            .map_err(|_| {
+                error!("Certificate revocation list cannot be decoded");
+                StatusCode::BadCertificateRevocationUnknown
+            })
+    }
+
+    /// Returns the DER encoded name of the issuer of the CRL
+    pub fn issuer(&self) -> Vec<u8> {
+        self.value.issuer_name().to_der().unwrap_or_default()
+    }
+
+    /// Tests if the CRL was issued by the cert, i.e. the cert's subject is the issuer of the CRL
+    /// and the cert's public key verifies the signature of the CRL.
+    pub fn is_issued_by(&self, issuer: &X509) -> bool {
+        let issuer = issuer.value();
+        let has_issuer_name = self.value.issuer_name().try_cmp(issuer.subject_name())
+            .map(|ordering| ordering == Ordering::Equal)
+            .unwrap_or(false);
+        has_issuer_name && issuer.public_key()
+            .and_then(|public_key| self.value.verify(&public_key))
+            .unwrap_or(false)
+    }
+
+    /// Tests if the CRL is current, i.e. it has been issued and the time of its next update has
+    /// not passed. A CRL without a next update stays current.
+    pub fn is_current(&self, now: &DateTime<Utc>) -> bool {
+        match Asn1Time::from_unix(now.timestamp()) {
+            Ok(now) => {
+                *self.value.last_update() <= now && self.value.next_update().map_or(true, |next_update| now < *next_update)
+            }
+            Err(_) => false
+        }
+    }
+
+    /// Returns the number of certificates that the CRL revokes
+    pub fn revoked_count(&self) -> usize {
+        self.value.get_revoked().map_or(0, |revoked| revoked.len())
+    }
+
+    /// Tests if the certificate was issued by the issuer of this CRL and has been revoked
+    pub fn is_revoked(&self, cert: &X509) -> Result<bool, StatusCode> {
+        // The lookup compares the issuer name as well as the serial number of the cert
+        match self.value.get_by_cert(cert.value()) {
+            CrlStatus::Revoked(_) => Ok(true),
+            CrlStatus::NotRevoked | CrlStatus::RemoveFromCrl(_) => Ok(false),
+        }
+    }
+}
+
+#[cfg(not(feature = "crypto"))]
+impl CertificateRevocationList {
+    pub fn from_der(_der: &[u8]) -> Result<CertificateRevocationList, StatusCode> {
+        error!("Certificate revocation list cannot be decoded without the crypto feature");
+        Err(StatusCode::BadCertificateRevocationUnknown)
+    }
+
+    pub fn issuer(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    pub fn is_issued_by(&self, _issuer: &X509) -> bool {
+        false
+    }
+
+    pub fn is_current(&self, _now: &DateTime<Utc>) -> bool {
+        false
+    }
+
+    pub fn revoked_count(&self) -> usize {
+        0
+    }
+
+    pub fn is_revoked(&self, _cert: &X509) -> Result<bool, StatusCode> {
+        Err(StatusCode::BadCertificateRevocationUnknown)
+    }
+}
@@ -2,12 +2,23 @@
 
 use std::result::Result;
 
+#[cfg(all(feature = "crypto", not(feature = "pure-rust-crypto")))]
 use openssl::{pkey, sign, hash};
+#[cfg(any(feature = "pure-rust-crypto", not(feature = "crypto")))]
+use ring::{digest, hmac as ring_hmac};
 
 use opcua_types::status_code::StatusCode;
 
 use crate::crypto::{SHA1_SIZE, SHA256_SIZE};
 
+/// The message digest used by HMAC and `P_SHA` functions. This exists so that callers do not
+/// depend on the types of a particular crypto backend.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+}
+
 /// Pseudo random `P_SHA` implementation for creating pseudo random range of bytes from an input
 ///
 /// https://www.ietf.org/rfc/rfc4346.txt
@@ -21,7 +32,7 @@ use crate::crypto::{SHA1_SIZE, SHA256_SIZE};
 ///   A(0) = seed
 ///   A(n) = HMAC_SHA1(secret, A(n-1))
 /// + indicates that the results are appended to previous results.
-pub fn p_sha(message_digest: hash::MessageDigest, secret: &[u8], seed: &[u8], length: usize) -> Vec<u8> {
+pub fn p_sha(message_digest: HashAlgorithm, secret: &[u8], seed: &[u8], length: usize) -> Vec<u8> {
     let mut result = Vec::with_capacity(length);
 
     let mut hmac = Vec::with_capacity(seed.len() * 2);
@@ -50,7 +61,12 @@ pub fn p_sha(message_digest: hash::MessageDigest, secret: &[u8], seed: &[u8], le
     result
 }
 
-fn hmac_vec(digest: hash::MessageDigest, key: &[u8], data: &[u8]) -> Vec<u8> {
+#[cfg(all(feature = "crypto", not(feature = "pure-rust-crypto")))]
+fn hmac_vec(hash_algorithm: HashAlgorithm, key: &[u8], data: &[u8]) -> Vec<u8> {
+    let digest = match hash_algorithm {
+        HashAlgorithm::Sha1 => hash::MessageDigest::sha1(),
+        HashAlgorithm::Sha256 => hash::MessageDigest::sha256(),
+    };
     // Compute a signature
     let pkey = pkey::PKey::hmac(key).unwrap();
     let mut signer = sign::Signer::new(digest, &pkey).unwrap();
@@ -58,7 +74,18 @@ fn hmac_vec(digest: hash::MessageDigest, key: &[u8], data: &[u8]) -> Vec<u8> {
     signer.sign_to_vec().unwrap()
 }
 
-fn hmac(digest: hash::MessageDigest, key: &[u8], data: &[u8], signature: &mut [u8]) -> Result<(), StatusCode> {
+#[cfg(any(feature = "pure-rust-crypto", not(feature = "crypto")))]
+fn hmac_vec(hash_algorithm: HashAlgorithm, key: &[u8], data: &[u8]) -> Vec<u8> {
+    let digest = match hash_algorithm {
+        HashAlgorithm::Sha1 => &digest::SHA1,
+        HashAlgorithm::Sha256 => &digest::SHA256,
+    };
+    // Compute a signature
+    let signing_key = ring_hmac::SigningKey::new(digest, key);
+    ring_hmac::sign(&signing_key, data).as_ref().to_vec()
+}
+
+fn hmac(digest: HashAlgorithm, key: &[u8], data: &[u8], signature: &mut [u8]) -> Result<(), StatusCode> {
     let hmac = hmac_vec(digest, key, data);
     trace!("hmac length = {}", hmac.len());
     signature.copy_from_slice(&hmac);
@@ -68,7 +95,7 @@ fn hmac(digest: hash::MessageDigest, key: &[u8], data: &[u8], signature: &mut [u
 pub fn hmac_sha1(key: &[u8], data: &[u8], signature: &mut [u8]) -> Result<(), StatusCode> {
     match signature.len() {
         SHA1_SIZE => {
-            hmac(hash::MessageDigest::sha1(), key, data, signature)
+            hmac(HashAlgorithm::Sha1, key, data, signature)
         }
         _ => {
             error!("Signature buffer length {} is not enough to receive hmac_sha1 signature", signature.len());
@@ -96,7 +123,7 @@ pub fn verify_hmac_sha1(key: &[u8], data: &[u8], signature: &[u8]) -> bool {
 pub fn hmac_sha256(key: &[u8], data: &[u8], signature: &mut [u8]) -> Result<(), StatusCode> {
     match signature.len() {
         SHA256_SIZE => {
-            hmac(hash::MessageDigest::sha256(), key, data, signature)
+            hmac(HashAlgorithm::Sha256, key, data, signature)
         }
         _ => {
             error!("Signature buffer length {} is not enough to receive hmac_sha256 signature", signature.len());
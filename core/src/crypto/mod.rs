@@ -11,6 +11,7 @@ pub mod aeskey;
 pub mod pkey;
 pub mod thumbprint;
 pub mod certificate_store;
+pub mod crl;
 pub mod hash;
 pub mod security_policy;
 pub mod user_identity;
@@ -20,6 +21,7 @@ pub use self::aeskey::*;
 pub use self::pkey::*;
 pub use self::thumbprint::*;
 pub use self::certificate_store::*;
+pub use self::crl::*;
 pub use self::hash::*;
 pub use self::security_policy::*;
 pub use self::user_identity::*;
@@ -46,6 +48,9 @@ pub mod algorithms {
     /// Asymmetric encryption algorithm RSA-OAEP-MGF1P
     pub const ENC_RSA_OAEP_MGF1P: &str = "http://www.w3.org/2001/04/xmlenc#rsa-oaep-mgf1p";
 
+    /// Asymmetric encryption algorithm RSA-OAEP using SHA256
+    pub const ENC_RSA_OAEP_SHA256: &str = "http://opcfoundation.org/UA/security/rsa-oaep-sha2-256";
+
     /// SymmetricSignatureAlgorithm – HmacSha1 – (http://www.w3.org/2000/09/xmldsig#hmac-sha1).
     pub const DSIG_HMAC_SHA1: &str = "http://www.w3.org/2000/09/xmldsig#hmac-sha1";
 
@@ -58,6 +63,9 @@ pub mod algorithms {
     /// Asymmetric digital signature algorithm using RSA-SHA256
     pub const DSIG_RSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256";
 
+    /// Asymmetric digital signature algorithm using RSA-PSS-SHA256
+    pub const DSIG_RSA_PSS_SHA2_256: &str = "http://opcfoundation.org/UA/security/rsa-pss-sha2-256";
+
     /// Key derivation algorithm P_SHA1
     pub const KEY_P_SHA1: &str = "http://docs.oasis-open.org/ws-sx/ws-secureconversation/200512/dk/p_sha1";
 
@@ -80,7 +88,8 @@ pub fn create_signature_data(signing_key: &PrivateKey, security_policy: Security
         let data = concat_data_and_nonce(contained_cert.as_ref(), nonce.as_ref());
         // Sign the bytes and return the algorithm, signature
         match security_policy {
-            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => {
+            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 |
+            SecurityPolicy::Aes128Sha256RsaOaep | SecurityPolicy::Aes256Sha256RsaPss => {
                 let signing_key_size = signing_key.size();
                 let mut signature = vec![0u8; signing_key_size];
                 let _ = security_policy.asymmetric_sign(signing_key, &data, &mut signature)?;
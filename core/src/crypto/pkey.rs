@@ -4,31 +4,52 @@ use std::marker::Send;
 use std::fmt::{Debug, Formatter};
 use std::result::Result;
 
-use openssl::{pkey, rsa, sign, hash};
+#[cfg(feature = "crypto")]
+use openssl::{pkey, rsa, sign, hash, encrypt, error};
 
 use opcua_types::status_code::StatusCode;
 
+#[allow(non_camel_case_types)]
 #[derive(Copy, Clone)]
 pub enum RsaPadding {
     PKCS1,
+    /// OAEP with SHA-1 for the digest and mask generation function
     OAEP,
+    /// OAEP with SHA-256 for the digest and mask generation function
+    OAEP_SHA256,
 }
 
+#[cfg(feature = "crypto")]
 impl Into<rsa::Padding> for RsaPadding {
     fn into(self) -> rsa::Padding {
         match self {
             RsaPadding::PKCS1 => rsa::Padding::PKCS1,
-            RsaPadding::OAEP => rsa::Padding::PKCS1_OAEP,
+            RsaPadding::OAEP | RsaPadding::OAEP_SHA256 => rsa::Padding::PKCS1_OAEP,
         }
     }
 }
 
 /// This is a wrapper around an `OpenSSL` asymmetric key pair. Since openssl 0.10, the PKey is either
 /// a public or private key so we have to differentiate that as well.
+#[cfg(feature = "crypto")]
 pub struct PKey<T> {
     value: pkey::PKey<T>,
 }
 
+/// Without the `crypto` feature there is no asymmetric key implementation. Keys cannot be created
+/// or read and every operation upon them fails.
+#[cfg(not(feature = "crypto"))]
+pub struct PKey<T> {
+    _marker: std::marker::PhantomData<T>,
+}
+
+/// Marker types that stand in for the OpenSSL key types when the `crypto` feature is disabled.
+#[cfg(not(feature = "crypto"))]
+mod pkey {
+    pub struct Public;
+    pub struct Private;
+}
+
 /// A public key
 pub type PublicKey = PKey<pkey::Public>;
 // A private key
@@ -64,10 +85,12 @@ pub trait KeySize {
         // based padding modes, less than RSA_size(rsa) - 41 for RSA_PKCS1_OAEP_PADDING and exactly
         // RSA_size(rsa) for RSA_NO_PADDING.
         //
-        // Note other RSA impls use 11 and 42 so this impl will too.
+        // Note other RSA impls use 11 and 42 so this impl will too. OAEP with SHA-256 has an
+        // overhead of 2 * hash length + 2, i.e. 66.
         match padding {
             RsaPadding::PKCS1 => self.size() - 11,
             RsaPadding::OAEP => self.size() - 42,
+            RsaPadding::OAEP_SHA256 => self.size() - 66,
         }
     }
 
@@ -76,6 +99,26 @@ pub trait KeySize {
     }
 }
 
+#[cfg(feature = "crypto")]
+impl<T> PKey<T> where T: pkey::HasPublic {
+    /// Returns the DER encoded public key. Comparing it with that of a cert's public key finds
+    /// whether a private key belongs to the cert.
+    pub fn public_key_to_der(&self) -> Result<Vec<u8>, ()> {
+        self.value.public_key_to_der().map_err(|_| {
+            error!("Cannot turn the public key to DER");
+        })
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl<T> PKey<T> {
+    /// Returns the wrapped OpenSSL key
+    pub(crate) fn value(&self) -> &pkey::PKey<T> {
+        &self.value
+    }
+}
+
+#[cfg(feature = "crypto")]
 impl KeySize for PrivateKey {
     /// Length in bits
     fn bit_length(&self) -> usize {
@@ -83,14 +126,15 @@ impl KeySize for PrivateKey {
     }
 }
 
+#[cfg(feature = "crypto")]
 impl PrivateKey {
-    pub fn new(bit_length: u32) -> PrivateKey {
-        PKey {
-            value: {
-                let rsa = rsa::Rsa::generate(bit_length).unwrap();
-                pkey::PKey::from_rsa(rsa).unwrap()
-            },
-        }
+    pub fn new(bit_length: u32) -> Result<PrivateKey, ()> {
+        rsa::Rsa::generate(bit_length)
+            .and_then(pkey::PKey::from_rsa)
+            .map(|value| PKey { value })
+            .map_err(|err| {
+                error!("Cannot generate a private key of {} bits, error = {:?}", bit_length, err);
+            })
     }
 
     pub fn wrap_private_key(pkey: pkey::PKey<pkey::Private>) -> PrivateKey {
@@ -113,10 +157,15 @@ impl PrivateKey {
     }
 
     /// Creates a message digest from the specified block of data and then signs it to return a signature
-    fn sign(&self, message_digest: hash::MessageDigest, data: &[u8], signature: &mut [u8], padding: RsaPadding) -> Result<usize, StatusCode> {
+    fn sign(&self, message_digest: hash::MessageDigest, data: &[u8], signature: &mut [u8], padding: rsa::Padding) -> Result<usize, StatusCode> {
         trace!("RSA signing");
         if let Ok(mut signer) = sign::Signer::new(message_digest, &self.value) {
-            signer.set_rsa_padding(padding.into()).unwrap();
+            signer.set_rsa_padding(padding).unwrap();
+            if padding == rsa::Padding::PKCS1_PSS {
+                // PSS uses a salt the same length as the digest and the same digest for MGF1
+                signer.set_rsa_pss_saltlen(sign::RsaPssSaltlen::DIGEST_LENGTH).unwrap();
+                signer.set_rsa_mgf1_md(message_digest).unwrap();
+            }
             if signer.update(data).is_ok() {
                 let result = signer.sign_to_vec();
                 if let Ok(result) = result {
@@ -133,11 +182,15 @@ impl PrivateKey {
 
     /// Signs the data using RSA-SHA1
     pub fn sign_hmac_sha1(&self, data: &[u8], signature: &mut [u8]) -> Result<usize, StatusCode> {
-        self.sign(hash::MessageDigest::sha1(), data, signature, RsaPadding::PKCS1)
+        self.sign(hash::MessageDigest::sha1(), data, signature, rsa::Padding::PKCS1)
     }
     /// Signs the data using RSA-SHA256
     pub fn sign_hmac_sha256(&self, data: &[u8], signature: &mut [u8]) -> Result<usize, StatusCode> {
-        self.sign(hash::MessageDigest::sha256(), data, signature, RsaPadding::PKCS1)
+        self.sign(hash::MessageDigest::sha256(), data, signature, rsa::Padding::PKCS1)
+    }
+    /// Signs the data using RSA-PSS-SHA256
+    pub fn sign_sha256_pss(&self, data: &[u8], signature: &mut [u8]) -> Result<usize, StatusCode> {
+        self.sign(hash::MessageDigest::sha256(), data, signature, rsa::Padding::PKCS1_PSS)
     }
 
     /// Decrypts data in src to dst using the specified padding and returning the size of the decrypted
@@ -145,8 +198,6 @@ impl PrivateKey {
     pub fn private_decrypt(&self, src: &[u8], dst: &mut [u8], padding: RsaPadding) -> Result<usize, ()> {
         // decrypt data using our private key
         let cipher_text_block_size = self.cipher_text_block_size();
-        let rsa = self.value.rsa().unwrap();
-        let padding: rsa::Padding = padding.into();
 
         // Decrypt the data
         let mut src_idx = 0;
@@ -156,7 +207,7 @@ impl PrivateKey {
         while src_idx < src_len {
             let src = &src[src_idx..(src_idx + cipher_text_block_size)];
             let dst = &mut dst[dst_idx..(dst_idx + cipher_text_block_size)];
-            let decrypted_bytes = rsa.private_decrypt(src, dst, padding);
+            let decrypted_bytes = self.private_decrypt_block(src, dst, padding);
             if decrypted_bytes.is_err() {
                 error!("Decryption failed for key size {}, src idx {}, dst idx {} error - {:?}", cipher_text_block_size, src_idx, dst_idx, decrypted_bytes.unwrap_err());
                 return Err(());
@@ -166,8 +217,24 @@ impl PrivateKey {
         }
         Ok(dst_idx)
     }
+
+    /// Decrypts a single block of cipher text
+    fn private_decrypt_block(&self, src: &[u8], dst: &mut [u8], padding: RsaPadding) -> Result<usize, error::ErrorStack> {
+        match padding {
+            RsaPadding::OAEP_SHA256 => {
+                // The low level RSA api only does OAEP with SHA-1 so use a decrypter instead
+                let mut decrypter = encrypt::Decrypter::new(&self.value)?;
+                decrypter.set_rsa_padding(padding.into())?;
+                decrypter.set_rsa_oaep_md(hash::MessageDigest::sha256())?;
+                decrypter.set_rsa_mgf1_md(hash::MessageDigest::sha256())?;
+                decrypter.decrypt(src, dst)
+            }
+            _ => self.value.rsa()?.private_decrypt(src, dst, padding.into())
+        }
+    }
 }
 
+#[cfg(feature = "crypto")]
 impl KeySize for PublicKey {
     /// Length in bits
     fn bit_length(&self) -> usize {
@@ -175,16 +242,21 @@ impl KeySize for PublicKey {
     }
 }
 
+#[cfg(feature = "crypto")]
 impl PublicKey {
     pub fn wrap_public_key(pkey: pkey::PKey<pkey::Public>) -> PublicKey {
         PublicKey { value: pkey }
     }
 
     /// Verifies that the signature matches the hash / signing key of the supplied data
-    fn verify(&self, message_digest: hash::MessageDigest, data: &[u8], signature: &[u8], padding: RsaPadding) -> Result<bool, StatusCode> {
+    fn verify(&self, message_digest: hash::MessageDigest, data: &[u8], signature: &[u8], padding: rsa::Padding) -> Result<bool, StatusCode> {
         trace!("RSA verifying, against signature {:?}, len {}", signature, signature.len());
         if let Ok(mut verifier) = sign::Verifier::new(message_digest, &self.value) {
-            verifier.set_rsa_padding(padding.into()).unwrap();
+            verifier.set_rsa_padding(padding).unwrap();
+            if padding == rsa::Padding::PKCS1_PSS {
+                verifier.set_rsa_pss_saltlen(sign::RsaPssSaltlen::DIGEST_LENGTH).unwrap();
+                verifier.set_rsa_mgf1_md(message_digest).unwrap();
+            }
             if verifier.update(data).is_ok() {
                 let result = verifier.verify(signature);
                 if let Ok(result) = result {
@@ -200,12 +272,17 @@ impl PublicKey {
 
     /// Verifies the data using RSA-SHA1
     pub fn verify_hmac_sha1(&self, data: &[u8], signature: &[u8]) -> Result<bool, StatusCode> {
-        self.verify(hash::MessageDigest::sha1(), data, signature, RsaPadding::PKCS1)
+        self.verify(hash::MessageDigest::sha1(), data, signature, rsa::Padding::PKCS1)
     }
 
     /// Verifies the data using RSA-SHA256
     pub fn verify_hmac_sha256(&self, data: &[u8], signature: &[u8]) -> Result<bool, StatusCode> {
-        self.verify(hash::MessageDigest::sha256(), data, signature, RsaPadding::PKCS1)
+        self.verify(hash::MessageDigest::sha256(), data, signature, rsa::Padding::PKCS1)
+    }
+
+    /// Verifies the data using RSA-PSS-SHA256
+    pub fn verify_sha256_pss(&self, data: &[u8], signature: &[u8]) -> Result<bool, StatusCode> {
+        self.verify(hash::MessageDigest::sha256(), data, signature, rsa::Padding::PKCS1_PSS)
     }
 
     /// Encrypts data from src to dst using the specified padding and returns the size of encrypted
@@ -217,8 +294,6 @@ impl PublicKey {
         // For reference:
         //
         // https://www.openssl.org/docs/man1.0.2/crypto/RSA_public_encrypt.html
-        let rsa = self.value.rsa().unwrap();
-        let padding: rsa::Padding = padding.into();
 
         // Encrypt the data in chunks no larger than the key size less padding
         let mut src_idx = 0;
@@ -238,7 +313,7 @@ impl PublicKey {
             dst_idx += {
                 let src = &src[src_idx..(src_idx + bytes_to_encrypt)];
                 let dst = &mut dst[dst_idx..(dst_idx + cipher_text_block_size)];
-                let encrypted_bytes = rsa.public_encrypt(src, dst, padding);
+                let encrypted_bytes = self.public_encrypt_block(src, dst, padding);
                 if encrypted_bytes.is_err() {
                     error!("Encryption failed for bytes_to_encrypt {}, key_size {}, src_idx {}, dst_idx {} error - {:?}", bytes_to_encrypt, cipher_text_block_size, src_idx, dst_idx, encrypted_bytes.unwrap_err());
                     return Err(());
@@ -252,4 +327,89 @@ impl PublicKey {
 
         Ok(dst_idx)
     }
+
+    /// Encrypts a single block of plain text
+    fn public_encrypt_block(&self, src: &[u8], dst: &mut [u8], padding: RsaPadding) -> Result<usize, error::ErrorStack> {
+        match padding {
+            RsaPadding::OAEP_SHA256 => {
+                // The low level RSA api only does OAEP with SHA-1 so use an encrypter instead
+                let mut encrypter = encrypt::Encrypter::new(&self.value)?;
+                encrypter.set_rsa_padding(padding.into())?;
+                encrypter.set_rsa_oaep_md(hash::MessageDigest::sha256())?;
+                encrypter.set_rsa_mgf1_md(hash::MessageDigest::sha256())?;
+                encrypter.encrypt(src, dst)
+            }
+            _ => self.value.rsa()?.public_encrypt(src, dst, padding.into())
+        }
+    }
+}
+
+#[cfg(not(feature = "crypto"))]
+impl<T> KeySize for PKey<T> {
+    fn bit_length(&self) -> usize {
+        0
+    }
+}
+
+#[cfg(not(feature = "crypto"))]
+impl<T> PKey<T> {
+    pub fn public_key_to_der(&self) -> Result<Vec<u8>, ()> {
+        error!("Cannot produce a public key without the crypto feature");
+        Err(())
+    }
+}
+
+#[cfg(not(feature = "crypto"))]
+impl PrivateKey {
+    pub fn new(_bit_length: u32) -> Result<PrivateKey, ()> {
+        error!("Cannot generate a private key without the crypto feature");
+        Err(())
+    }
+
+    pub fn from_pem(_pem: &[u8]) -> Result<PrivateKey, ()> {
+        error!("Cannot produce a private key without the crypto feature");
+        Err(())
+    }
+
+    pub fn private_key_to_pem(&self) -> Result<Vec<u8>, ()> {
+        error!("Cannot turn private key to PEM without the crypto feature");
+        Err(())
+    }
+
+    pub fn sign_hmac_sha1(&self, _data: &[u8], _signature: &mut [u8]) -> Result<usize, StatusCode> {
+        Err(StatusCode::BadSecurityPolicyRejected)
+    }
+
+    pub fn sign_hmac_sha256(&self, _data: &[u8], _signature: &mut [u8]) -> Result<usize, StatusCode> {
+        Err(StatusCode::BadSecurityPolicyRejected)
+    }
+
+    pub fn sign_sha256_pss(&self, _data: &[u8], _signature: &mut [u8]) -> Result<usize, StatusCode> {
+        Err(StatusCode::BadSecurityPolicyRejected)
+    }
+
+    pub fn private_decrypt(&self, _src: &[u8], _dst: &mut [u8], _padding: RsaPadding) -> Result<usize, ()> {
+        error!("Cannot decrypt without the crypto feature");
+        Err(())
+    }
+}
+
+#[cfg(not(feature = "crypto"))]
+impl PublicKey {
+    pub fn verify_hmac_sha1(&self, _data: &[u8], _signature: &[u8]) -> Result<bool, StatusCode> {
+        Err(StatusCode::BadSecurityPolicyRejected)
+    }
+
+    pub fn verify_hmac_sha256(&self, _data: &[u8], _signature: &[u8]) -> Result<bool, StatusCode> {
+        Err(StatusCode::BadSecurityPolicyRejected)
+    }
+
+    pub fn verify_sha256_pss(&self, _data: &[u8], _signature: &[u8]) -> Result<bool, StatusCode> {
+        Err(StatusCode::BadSecurityPolicyRejected)
+    }
+
+    pub fn public_encrypt(&self, _src: &[u8], _dst: &mut [u8], _padding: RsaPadding) -> Result<usize, ()> {
+        error!("Cannot encrypt without the crypto feature");
+        Err(())
+    }
 }
@@ -3,7 +3,6 @@
 use std::fmt;
 use std::str::FromStr;
 
-use openssl::hash as openssl_hash;
 
 use opcua_types::{
     status_code::StatusCode,
@@ -167,6 +166,107 @@ pub mod basic256sha256 {
     pub const CERTIFICATE_SIGNATURE_ALGORITHM: &str = "Sha256";
 }
 
+/// Security Aes128-Sha256-RsaOaep
+///
+/// A suite of algorithms that uses Sha256 and Aes128 for symmetric operations and RSA-OAEP for key
+/// wrapping. It is intended to replace Basic128Rsa15 and Basic256, which are deprecated.
+pub mod aes128sha256rsaoaep {
+    use crate::crypto::algorithms::*;
+
+    /// SymmetricSignatureAlgorithm – Hmac_Sha256 – (http://www.w3.org/2000/09/xmldsig#hmac-sha256).
+    pub const SYMMETRIC_SIGNATURE_ALGORITHM: &str = DSIG_HMAC_SHA256;
+
+    /// SymmetricEncryptionAlgorithm – Aes128_CBC – (http://www.w3.org/2001/04/xmlenc#aes128-cbc).
+    pub const SYMMETRIC_ENCRYPTION_ALGORITHM: &str = ENC_AES128_CBC;
+
+    /// AsymmetricSignatureAlgorithm – Rsa_Sha256 – (http://www.w3.org/2001/04/xmldsig-more#rsa-sha256).
+    pub const ASYMMETRIC_SIGNATURE_ALGORITHM: &str = DSIG_RSA_SHA256;
+
+    /// AsymmetricKeyWrapAlgorithm – KwRsaOaep – (http://www.w3.org/2001/04/xmlenc#rsa-oaep-mgf1p).
+    pub const ASYMMETRIC_KEY_WRAP_ALGORITHM: &str = ENC_RSA_OAEP_MGF1P;
+
+    /// AsymmetricEncryptionAlgorithm – Rsa_Oaep_Sha1 – (http://www.w3.org/2001/04/xmlenc#rsa-oaep).
+    pub const ASYMMETRIC_ENCRYPTION_ALGORITHM: &str = ENC_RSA_OAEP;
+
+    /// KeyDerivationAlgorithm – PSHA256 – (http://docs.oasis-open.org/ws-sx/ws-secureconversation/200512/dk/p_sha256).
+    pub const KEY_DERIVATION_ALGORITHM: &str = KEY_P_SHA256;
+
+    /// DerivedSignatureKeyLength – 256 / 32 bytes.
+    pub const DERIVED_SIGNATURE_KEY_LENGTH: usize = 256;
+
+    /// DerivedEncryptionKeyLength – 128 / 16 bytes.
+    pub const DERIVED_ENCRYPTION_KEY_LENGTH: usize = 128;
+
+    /// MinAsymmetricKeyLength – 2048
+    pub const MIN_ASYMMETRIC_KEY_LENGTH: usize = 2048;
+
+    /// MaxAsymmetricKeyLength – 4096
+    pub const MAX_ASYMMETRIC_KEY_LENGTH: usize = 4096;
+
+    /// Symmetric key length - 128 / 16 bytes
+    pub const SYMMETRIC_KEY_LENGTH: usize = 128;
+
+    /// SecureChannelNonceLength – 32 bytes. Unlike the older policies, the nonce is not the same
+    /// length as the symmetric key.
+    pub const SECURE_CHANNEL_NONCE_LENGTH: usize = 32;
+
+    /// CertificateSignatureAlgorithm – Sha256
+    ///
+    /// If a certificate or any certificate in the chain is not signed with a hash that is Sha256 or stronger
+    /// then the certificate shall be rejected.
+    pub const CERTIFICATE_SIGNATURE_ALGORITHM: &str = "Sha256";
+}
+
+/// Security Aes256-Sha256-RsaPss
+///
+/// A suite of algorithms that uses Sha256 and Aes256 for symmetric operations, RSA-PSS for asymmetric
+/// signatures and RSA-OAEP with Sha256 for asymmetric encryption.
+pub mod aes256sha256rsapss {
+    use crate::crypto::algorithms::*;
+
+    /// SymmetricSignatureAlgorithm – Hmac_Sha256 – (http://www.w3.org/2000/09/xmldsig#hmac-sha256).
+    pub const SYMMETRIC_SIGNATURE_ALGORITHM: &str = DSIG_HMAC_SHA256;
+
+    /// SymmetricEncryptionAlgorithm – Aes256_CBC – (http://www.w3.org/2001/04/xmlenc#aes256-cbc).
+    pub const SYMMETRIC_ENCRYPTION_ALGORITHM: &str = ENC_AES256_CBC;
+
+    /// AsymmetricSignatureAlgorithm – RsaPss_Sha256 – (http://opcfoundation.org/UA/security/rsa-pss-sha2-256).
+    pub const ASYMMETRIC_SIGNATURE_ALGORITHM: &str = DSIG_RSA_PSS_SHA2_256;
+
+    /// AsymmetricKeyWrapAlgorithm – KwRsaOaep – (http://www.w3.org/2001/04/xmlenc#rsa-oaep-mgf1p).
+    pub const ASYMMETRIC_KEY_WRAP_ALGORITHM: &str = ENC_RSA_OAEP_MGF1P;
+
+    /// AsymmetricEncryptionAlgorithm – Rsa_Oaep_Sha2_256 – (http://opcfoundation.org/UA/security/rsa-oaep-sha2-256).
+    pub const ASYMMETRIC_ENCRYPTION_ALGORITHM: &str = ENC_RSA_OAEP_SHA256;
+
+    /// KeyDerivationAlgorithm – PSHA256 – (http://docs.oasis-open.org/ws-sx/ws-secureconversation/200512/dk/p_sha256).
+    pub const KEY_DERIVATION_ALGORITHM: &str = KEY_P_SHA256;
+
+    /// DerivedSignatureKeyLength – 256 / 32 bytes.
+    pub const DERIVED_SIGNATURE_KEY_LENGTH: usize = 256;
+
+    /// DerivedEncryptionKeyLength – 256 / 32 bytes.
+    pub const DERIVED_ENCRYPTION_KEY_LENGTH: usize = 256;
+
+    /// MinAsymmetricKeyLength – 2048
+    pub const MIN_ASYMMETRIC_KEY_LENGTH: usize = 2048;
+
+    /// MaxAsymmetricKeyLength – 4096
+    pub const MAX_ASYMMETRIC_KEY_LENGTH: usize = 4096;
+
+    /// Symmetric key length - 256 / 32 bytes
+    pub const SYMMETRIC_KEY_LENGTH: usize = 256;
+
+    /// SecureChannelNonceLength – 32 bytes.
+    pub const SECURE_CHANNEL_NONCE_LENGTH: usize = 32;
+
+    /// CertificateSignatureAlgorithm – Sha256
+    ///
+    /// If a certificate or any certificate in the chain is not signed with a hash that is Sha256 or stronger
+    /// then the certificate shall be rejected.
+    pub const CERTIFICATE_SIGNATURE_ALGORITHM: &str = "Sha256";
+}
+
 /// SecurityPolicy implies what encryption and signing algorithms and their relevant key strengths
 /// are used during an encrypted session.
 #[derive(Debug, Clone, PartialEq, Copy)]
@@ -176,6 +276,8 @@ pub enum SecurityPolicy {
     Basic128Rsa15,
     Basic256,
     Basic256Sha256,
+    Aes128Sha256RsaOaep,
+    Aes256Sha256RsaPss,
 }
 
 impl fmt::Display for SecurityPolicy {
@@ -193,6 +295,8 @@ impl FromStr for SecurityPolicy {
             constants::SECURITY_POLICY_BASIC_128_RSA_15 | constants::SECURITY_POLICY_BASIC_128_RSA_15_URI => SecurityPolicy::Basic128Rsa15,
             constants::SECURITY_POLICY_BASIC_256 | constants::SECURITY_POLICY_BASIC_256_URI => SecurityPolicy::Basic256,
             constants::SECURITY_POLICY_BASIC_256_SHA_256 | constants::SECURITY_POLICY_BASIC_256_SHA_256_URI => SecurityPolicy::Basic256Sha256,
+            constants::SECURITY_POLICY_AES_128_SHA_256_RSA_OAEP | constants::SECURITY_POLICY_AES_128_SHA_256_RSA_OAEP_URI => SecurityPolicy::Aes128Sha256RsaOaep,
+            constants::SECURITY_POLICY_AES_256_SHA_256_RSA_PSS | constants::SECURITY_POLICY_AES_256_SHA_256_RSA_PSS_URI => SecurityPolicy::Aes256Sha256RsaPss,
             _ => {
                 error!("Specified security policy {} is not recognized", s);
                 SecurityPolicy::Unknown
@@ -214,6 +318,8 @@ impl SecurityPolicy {
             SecurityPolicy::Basic128Rsa15 => constants::SECURITY_POLICY_BASIC_128_RSA_15_URI,
             SecurityPolicy::Basic256 => constants::SECURITY_POLICY_BASIC_256_URI,
             SecurityPolicy::Basic256Sha256 => constants::SECURITY_POLICY_BASIC_256_SHA_256_URI,
+            SecurityPolicy::Aes128Sha256RsaOaep => constants::SECURITY_POLICY_AES_128_SHA_256_RSA_OAEP_URI,
+            SecurityPolicy::Aes256Sha256RsaPss => constants::SECURITY_POLICY_AES_256_SHA_256_RSA_PSS_URI,
             _ => {
                 panic!("Shouldn't be turning an unknown policy into a uri");
             }
@@ -226,6 +332,8 @@ impl SecurityPolicy {
             SecurityPolicy::Basic128Rsa15 => constants::SECURITY_POLICY_BASIC_128_RSA_15,
             SecurityPolicy::Basic256 => constants::SECURITY_POLICY_BASIC_256,
             SecurityPolicy::Basic256Sha256 => constants::SECURITY_POLICY_BASIC_256_SHA_256,
+            SecurityPolicy::Aes128Sha256RsaOaep => constants::SECURITY_POLICY_AES_128_SHA_256_RSA_OAEP,
+            SecurityPolicy::Aes256Sha256RsaPss => constants::SECURITY_POLICY_AES_256_SHA_256_RSA_PSS,
             _ => {
                 panic!("Shouldn't be turning an unknown policy into a string");
             }
@@ -237,6 +345,8 @@ impl SecurityPolicy {
             SecurityPolicy::Basic128Rsa15 => basic128rsa15::ASYMMETRIC_ENCRYPTION_ALGORITHM,
             SecurityPolicy::Basic256 => basic256::ASYMMETRIC_ENCRYPTION_ALGORITHM,
             SecurityPolicy::Basic256Sha256 => basic256sha256::ASYMMETRIC_ENCRYPTION_ALGORITHM,
+            SecurityPolicy::Aes128Sha256RsaOaep => aes128sha256rsaoaep::ASYMMETRIC_ENCRYPTION_ALGORITHM,
+            SecurityPolicy::Aes256Sha256RsaPss => aes256sha256rsapss::ASYMMETRIC_ENCRYPTION_ALGORITHM,
             _ => {
                 panic!("Invalid policy");
             }
@@ -248,6 +358,8 @@ impl SecurityPolicy {
             SecurityPolicy::Basic128Rsa15 => basic128rsa15::ASYMMETRIC_SIGNATURE_ALGORITHM,
             SecurityPolicy::Basic256 => basic256::ASYMMETRIC_SIGNATURE_ALGORITHM,
             SecurityPolicy::Basic256Sha256 => basic256sha256::ASYMMETRIC_SIGNATURE_ALGORITHM,
+            SecurityPolicy::Aes128Sha256RsaOaep => aes128sha256rsaoaep::ASYMMETRIC_SIGNATURE_ALGORITHM,
+            SecurityPolicy::Aes256Sha256RsaPss => aes256sha256rsapss::ASYMMETRIC_SIGNATURE_ALGORITHM,
             _ => {
                 panic!("Invalid policy");
             }
@@ -259,6 +371,8 @@ impl SecurityPolicy {
             SecurityPolicy::Basic128Rsa15 => basic128rsa15::SYMMETRIC_SIGNATURE_ALGORITHM,
             SecurityPolicy::Basic256 => basic256::SYMMETRIC_SIGNATURE_ALGORITHM,
             SecurityPolicy::Basic256Sha256 => basic256sha256::SYMMETRIC_SIGNATURE_ALGORITHM,
+            SecurityPolicy::Aes128Sha256RsaOaep => aes128sha256rsaoaep::SYMMETRIC_SIGNATURE_ALGORITHM,
+            SecurityPolicy::Aes256Sha256RsaPss => aes256sha256rsapss::SYMMETRIC_SIGNATURE_ALGORITHM,
             _ => {
                 panic!("Invalid policy");
             }
@@ -271,6 +385,8 @@ impl SecurityPolicy {
             SecurityPolicy::Basic128Rsa15 => basic128rsa15::SYMMETRIC_KEY_LENGTH,
             SecurityPolicy::Basic256 => basic256::SYMMETRIC_KEY_LENGTH,
             SecurityPolicy::Basic256Sha256 => basic256sha256::SYMMETRIC_KEY_LENGTH,
+            SecurityPolicy::Aes128Sha256RsaOaep => aes128sha256rsaoaep::SYMMETRIC_KEY_LENGTH,
+            SecurityPolicy::Aes256Sha256RsaPss => aes256sha256rsapss::SYMMETRIC_KEY_LENGTH,
             _ => {
                 panic!("Invalid policy");
             }
@@ -281,7 +397,8 @@ impl SecurityPolicy {
     // Plaintext block size in bytes
     pub fn plain_block_size(&self) -> usize {
         match *self {
-            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => 16,
+            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 |
+            SecurityPolicy::Aes128Sha256RsaOaep | SecurityPolicy::Aes256Sha256RsaPss => 16,
             _ => {
                 panic!("Invalid policy");
             }
@@ -293,7 +410,8 @@ impl SecurityPolicy {
         match *self {
             SecurityPolicy::None => 0,
             SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 => SHA1_SIZE,
-            SecurityPolicy::Basic256Sha256 => SHA256_SIZE,
+            SecurityPolicy::Basic256Sha256 | SecurityPolicy::Aes128Sha256RsaOaep |
+            SecurityPolicy::Aes256Sha256RsaPss => SHA256_SIZE,
             _ => {
                 panic!("Invalid policy");
             }
@@ -306,6 +424,8 @@ impl SecurityPolicy {
             SecurityPolicy::Basic128Rsa15 => basic128rsa15::DERIVED_SIGNATURE_KEY_LENGTH,
             SecurityPolicy::Basic256 => basic256::DERIVED_SIGNATURE_KEY_LENGTH,
             SecurityPolicy::Basic256Sha256 => basic256sha256::DERIVED_SIGNATURE_KEY_LENGTH,
+            SecurityPolicy::Aes128Sha256RsaOaep => aes128sha256rsaoaep::DERIVED_SIGNATURE_KEY_LENGTH,
+            SecurityPolicy::Aes256Sha256RsaPss => aes256sha256rsapss::DERIVED_SIGNATURE_KEY_LENGTH,
             _ => {
                 panic!("Invalid policy");
             }
@@ -319,6 +439,8 @@ impl SecurityPolicy {
             SecurityPolicy::Basic128Rsa15 => basic128rsa15::MIN_ASYMMETRIC_KEY_LENGTH,
             SecurityPolicy::Basic256 => basic256::MIN_ASYMMETRIC_KEY_LENGTH,
             SecurityPolicy::Basic256Sha256 => basic256sha256::MIN_ASYMMETRIC_KEY_LENGTH,
+            SecurityPolicy::Aes128Sha256RsaOaep => aes128sha256rsaoaep::MIN_ASYMMETRIC_KEY_LENGTH,
+            SecurityPolicy::Aes256Sha256RsaPss => aes256sha256rsapss::MIN_ASYMMETRIC_KEY_LENGTH,
             _ => {
                 panic!("Invalid policy");
             }
@@ -331,6 +453,23 @@ impl SecurityPolicy {
             SecurityPolicy::Basic128Rsa15 => basic128rsa15::MAX_ASYMMETRIC_KEY_LENGTH,
             SecurityPolicy::Basic256 => basic256::MAX_ASYMMETRIC_KEY_LENGTH,
             SecurityPolicy::Basic256Sha256 => basic256sha256::MAX_ASYMMETRIC_KEY_LENGTH,
+            SecurityPolicy::Aes128Sha256RsaOaep => aes128sha256rsaoaep::MAX_ASYMMETRIC_KEY_LENGTH,
+            SecurityPolicy::Aes256Sha256RsaPss => aes256sha256rsapss::MAX_ASYMMETRIC_KEY_LENGTH,
+            _ => {
+                panic!("Invalid policy");
+            }
+        }
+    }
+
+    /// Returns the length in bytes of the nonces exchanged in OpenSecureChannel and CreateSession.
+    /// For the older policies this is the symmetric key size.
+    pub fn secure_channel_nonce_length(&self) -> usize {
+        match *self {
+            SecurityPolicy::Basic128Rsa15 |
+            SecurityPolicy::Basic256 |
+            SecurityPolicy::Basic256Sha256 => self.symmetric_key_size(),
+            SecurityPolicy::Aes128Sha256RsaOaep => aes128sha256rsaoaep::SECURE_CHANNEL_NONCE_LENGTH,
+            SecurityPolicy::Aes256Sha256RsaPss => aes256sha256rsapss::SECURE_CHANNEL_NONCE_LENGTH,
             _ => {
                 panic!("Invalid policy");
             }
@@ -343,7 +482,9 @@ impl SecurityPolicy {
             SecurityPolicy::None => ByteString::null(),
             SecurityPolicy::Basic128Rsa15 |
             SecurityPolicy::Basic256 |
-            SecurityPolicy::Basic256Sha256 => ByteString::random(self.symmetric_key_size()),
+            SecurityPolicy::Basic256Sha256 |
+            SecurityPolicy::Aes128Sha256RsaOaep |
+            SecurityPolicy::Aes256Sha256RsaPss => ByteString::random(self.secure_channel_nonce_length()),
             _ => {
                 panic!("Cannot make a nonce because key size is unknown");
             }
@@ -356,6 +497,8 @@ impl SecurityPolicy {
             constants::SECURITY_POLICY_BASIC_128_RSA_15_URI => SecurityPolicy::Basic128Rsa15,
             constants::SECURITY_POLICY_BASIC_256_URI => SecurityPolicy::Basic256,
             constants::SECURITY_POLICY_BASIC_256_SHA_256_URI => SecurityPolicy::Basic256Sha256,
+            constants::SECURITY_POLICY_AES_128_SHA_256_RSA_OAEP_URI => SecurityPolicy::Aes128Sha256RsaOaep,
+            constants::SECURITY_POLICY_AES_256_SHA_256_RSA_PSS_URI => SecurityPolicy::Aes256Sha256RsaPss,
             _ => {
                 error!("Specified security policy {} is not recognized", uri);
                 SecurityPolicy::Unknown
@@ -368,8 +511,9 @@ impl SecurityPolicy {
     fn prf(&self, secret: &[u8], seed: &[u8], length: usize, offset: usize) -> Vec<u8> {
         // P_SHA1 or P_SHA256
         let message_digest = match *self {
-            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 => openssl_hash::MessageDigest::sha1(),
-            SecurityPolicy::Basic256Sha256 => openssl_hash::MessageDigest::sha256(),
+            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 => hash::HashAlgorithm::Sha1,
+            SecurityPolicy::Basic256Sha256 | SecurityPolicy::Aes128Sha256RsaOaep |
+            SecurityPolicy::Aes256Sha256RsaPss => hash::HashAlgorithm::Sha256,
             _ => {
                 panic!("Invalid policy");
             }
@@ -415,8 +559,8 @@ impl SecurityPolicy {
         // Work out the length of stuff
         let signing_key_length = self.derived_signature_key_size();
         let (encrypting_key_length, encrypting_block_size) = match *self {
-            SecurityPolicy::Basic128Rsa15 => (16, 16),
-            SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => (32, 16),
+            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Aes128Sha256RsaOaep => (16, 16),
+            SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 | SecurityPolicy::Aes256Sha256RsaPss => (32, 16),
             _ => {
                 panic!("Invalid policy");
             }
@@ -437,9 +581,12 @@ impl SecurityPolicy {
             SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 => {
                 signing_key.sign_hmac_sha1(data, signature)?
             }
-            SecurityPolicy::Basic256Sha256 => {
+            SecurityPolicy::Basic256Sha256 | SecurityPolicy::Aes128Sha256RsaOaep => {
                 signing_key.sign_hmac_sha256(data, signature)?
             }
+            SecurityPolicy::Aes256Sha256RsaPss => {
+                signing_key.sign_sha256_pss(data, signature)?
+            }
             _ => {
                 panic!("Invalid policy");
             }
@@ -456,9 +603,12 @@ impl SecurityPolicy {
             SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 => {
                 verification_key.verify_hmac_sha1(data, signature)?
             }
-            SecurityPolicy::Basic256Sha256 => {
+            SecurityPolicy::Basic256Sha256 | SecurityPolicy::Aes128Sha256RsaOaep => {
                 verification_key.verify_hmac_sha256(data, signature)?
             }
+            SecurityPolicy::Aes256Sha256RsaPss => {
+                verification_key.verify_sha256_pss(data, signature)?
+            }
             _ => {
                 panic!("Invalid policy");
             }
@@ -484,7 +634,8 @@ impl SecurityPolicy {
     pub fn padding(&self) -> RsaPadding {
         match *self {
             SecurityPolicy::Basic128Rsa15 => RsaPadding::PKCS1,
-            SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => RsaPadding::OAEP,
+            SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 | SecurityPolicy::Aes128Sha256RsaOaep => RsaPadding::OAEP,
+            SecurityPolicy::Aes256Sha256RsaPss => RsaPadding::OAEP_SHA256,
             _ => {
                 panic!("Security policy is not supported, shouldn't have gotten here");
             }
@@ -519,7 +670,8 @@ impl SecurityPolicy {
                 // HMAC SHA-1
                 hash::hmac_sha1(key, data, signature)
             }
-            SecurityPolicy::Basic256Sha256 => {
+            SecurityPolicy::Basic256Sha256 | SecurityPolicy::Aes128Sha256RsaOaep |
+            SecurityPolicy::Aes256Sha256RsaPss => {
                 // HMAC SHA-256
                 hash::hmac_sha256(key, data, signature)
             }
@@ -537,7 +689,8 @@ impl SecurityPolicy {
                 // HMAC SHA-1
                 hash::verify_hmac_sha1(key, data, signature)
             }
-            SecurityPolicy::Basic256Sha256 => {
+            SecurityPolicy::Basic256Sha256 | SecurityPolicy::Aes128Sha256RsaOaep |
+            SecurityPolicy::Aes256Sha256RsaPss => {
                 // HMAC SHA-256
                 hash::verify_hmac_sha256(key, data, signature)
             }
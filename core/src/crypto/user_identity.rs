@@ -1,4 +1,5 @@
-//! Functions related to encrypting / decrypting passwords in a UserNameIdentityToken.
+//! Functions related to encrypting / decrypting passwords in a UserNameIdentityToken, token data in
+//! an IssuedIdentityToken and signing / verifying an X509IdentityToken.
 //!
 //! The code here determines how or if to encrypt the password depending on the security policy
 //! and user token policy.
@@ -14,10 +15,12 @@ use opcua_types::{
 };
 
 use super::{X509, PrivateKey, KeySize, SecurityPolicy, RsaPadding};
-use opcua_types::service_types::{X509IdentityToken, SignatureData};
+use opcua_types::service_types::{X509IdentityToken, IssuedIdentityToken, SignatureData};
 
-/// Create a filled in UserNameIdentityToken by using the supplied channel security policy, user token policy, nonce, cert, user name and password.
-pub fn make_user_name_identity_token(channel_security_policy: SecurityPolicy, user_token_policy: &UserTokenPolicy, nonce: &[u8], cert: Option<X509>, user: &str, pass: &str) -> Result<UserNameIdentityToken, StatusCode> {
+/// Determines the security policy used to encrypt a secret in a user identity token from the
+/// security policy of the channel and the user token policy of the endpoint. See table 179 in
+/// OPC UA Part 4.
+fn user_token_security_policy(channel_security_policy: SecurityPolicy, user_token_policy: &UserTokenPolicy) -> SecurityPolicy {
     // Create a user token security policy by looking at the uri it wants to use
     let token_security_policy = if user_token_policy.security_policy_uri.is_empty() {
         SecurityPolicy::None
@@ -31,7 +34,7 @@ pub fn make_user_name_identity_token(channel_security_policy: SecurityPolicy, us
     };
 
     // Table 179 Opc Part 4 provides a table of which encryption algorithm to use
-    let security_policy = if channel_security_policy == SecurityPolicy::None {
+    if channel_security_policy == SecurityPolicy::None {
         if user_token_policy.security_policy_uri.is_empty() {
             SecurityPolicy::None
         } else if token_security_policy != SecurityPolicy::None {
@@ -51,7 +54,12 @@ pub fn make_user_name_identity_token(channel_security_policy: SecurityPolicy, us
         } else {
             SecurityPolicy::None
         }
-    };
+    }
+}
+
+/// Create a filled in UserNameIdentityToken by using the supplied channel security policy, user token policy, nonce, cert, user name and password.
+pub fn make_user_name_identity_token(channel_security_policy: SecurityPolicy, user_token_policy: &UserTokenPolicy, nonce: &[u8], cert: Option<X509>, user: &str, pass: &str) -> Result<UserNameIdentityToken, StatusCode> {
+    let security_policy = user_token_security_policy(channel_security_policy, user_token_policy);
 
     // Now it should be a matter of using the policy (or lack thereof) to encrypt the password
     // using the secure channel's cert and nonce.
@@ -93,6 +101,7 @@ pub fn decrypt_user_identity_token_password(user_identity_token: &UserNameIdenti
         let padding = match user_identity_token.encryption_algorithm.as_ref() {
             super::algorithms::ENC_RSA_15 => RsaPadding::PKCS1,
             super::algorithms::ENC_RSA_OAEP => RsaPadding::OAEP,
+            super::algorithms::ENC_RSA_OAEP_SHA256 => RsaPadding::OAEP_SHA256,
             _ => { return Err(StatusCode::BadSecurityPolicyRejected); }
         };
         legacy_password_decrypt(&user_identity_token.password, server_nonce, server_key, padding)
@@ -102,13 +111,26 @@ pub fn decrypt_user_identity_token_password(user_identity_token: &UserNameIdenti
 /// Encrypt a client side user's password using the server nonce and cert. This is described in table 176
 /// OPC UA part 4. This function is prefixed "legacy" because 1.04 describes another way of encrypting passwords.
 pub(crate) fn legacy_password_encrypt(password: &str, server_nonce: &[u8], server_cert: &X509, padding: RsaPadding) -> Result<ByteString, StatusCode> {
-    // Message format is size, password, nonce
-    let plaintext_size = 4 + password.len() + server_nonce.len();
+    legacy_secret_encrypt(password.as_bytes(), server_nonce, server_cert, padding)
+}
+
+/// Decrypt the client's password using the server's nonce and private key. This function is prefixed
+/// "legacy" because 1.04 describes another way of encrypting passwords.
+pub(crate) fn legacy_password_decrypt(secret: &ByteString, server_nonce: &[u8], server_key: &PrivateKey, padding: RsaPadding) -> Result<String, StatusCode> {
+    let password = legacy_secret_decrypt(secret, server_nonce, server_key, padding)?;
+    String::from_utf8(password.as_ref().to_vec()).map_err(|_| StatusCode::BadEncodingError)
+}
+
+/// Encrypt a secret, i.e. a password or issued token, using the server nonce and cert in the
+/// format described by table 176 OPC UA part 4.
+pub(crate) fn legacy_secret_encrypt(secret: &[u8], server_nonce: &[u8], server_cert: &X509, padding: RsaPadding) -> Result<ByteString, StatusCode> {
+    // Message format is size, secret, nonce
+    let plaintext_size = 4 + secret.len() + server_nonce.len();
     let mut src = Cursor::new(vec![0u8; plaintext_size]);
 
     // Write the length of the data to be encrypted excluding the length itself)
     write_u32(&mut src, (plaintext_size - 4) as u32)?;
-    src.write(password.as_bytes()).map_err(|_| StatusCode::BadEncodingError)?;
+    src.write(secret).map_err(|_| StatusCode::BadEncodingError)?;
     src.write(server_nonce).map_err(|_| StatusCode::BadEncodingError)?;
 
     // Encrypt the data with the public key from the server's certificate
@@ -123,9 +145,9 @@ pub(crate) fn legacy_password_encrypt(password: &str, server_nonce: &[u8], serve
     Ok(ByteString::from(dst))
 }
 
-/// Decrypt the client's password using the server's nonce and private key. This function is prefixed
-/// "legacy" because 1.04 describes another way of encrypting passwords.
-pub(crate) fn legacy_password_decrypt(secret: &ByteString, server_nonce: &[u8], server_key: &PrivateKey, padding: RsaPadding) -> Result<String, StatusCode> {
+/// Decrypt a secret that was encrypted with `legacy_secret_encrypt` using the server's nonce and
+/// private key.
+pub(crate) fn legacy_secret_decrypt(secret: &ByteString, server_nonce: &[u8], server_key: &PrivateKey, padding: RsaPadding) -> Result<ByteString, StatusCode> {
     if secret.is_null() {
         Err(StatusCode::BadDecodingError)
     } else {
@@ -147,11 +169,80 @@ pub(crate) fn legacy_password_decrypt(secret: &ByteString, server_nonce: &[u8],
             if nonce != server_nonce {
                 Err(StatusCode::BadDecodingError)
             } else {
-                let password = &dst[4..nonce_begin];
-                let password = String::from_utf8(password.to_vec())
-                    .map_err(|_| StatusCode::BadEncodingError)?;
-                Ok(password)
+                Ok(ByteString::from(&dst[4..nonce_begin]))
+            }
+        }
+    }
+}
+
+/// Create a filled in IssuedIdentityToken by using the supplied channel security policy, user token
+/// policy, nonce, cert and the token data obtained from the token issuer, e.g. a JWT. The token data
+/// is encrypted in the same way as the password of a user name identity token.
+pub fn make_issued_identity_token(channel_security_policy: SecurityPolicy, user_token_policy: &UserTokenPolicy, nonce: &[u8], cert: Option<X509>, token_data: &[u8]) -> Result<IssuedIdentityToken, StatusCode> {
+    let security_policy = user_token_security_policy(channel_security_policy, user_token_policy);
+    let (token_data, encryption_algorithm) = match security_policy {
+        SecurityPolicy::None => {
+            if channel_security_policy == SecurityPolicy::None {
+                warn!("An issued identity token is being sent over the network in plain text. This could be a serious security issue");
             }
+            (ByteString::from(token_data), UAString::null())
+        }
+        SecurityPolicy::Unknown => {
+            error!("Don't know how to make the issued token for this server");
+            return Err(StatusCode::BadSecurityPolicyRejected);
+        }
+        security_policy => {
+            let cert = cert.ok_or(StatusCode::BadCertificateInvalid)?;
+            let token_data = legacy_secret_encrypt(token_data, nonce, &cert, security_policy.padding())?;
+            let encryption_algorithm = UAString::from(security_policy.asymmetric_encryption_algorithm());
+            (token_data, encryption_algorithm)
+        }
+    };
+
+    Ok(IssuedIdentityToken {
+        policy_id: user_token_policy.policy_id.clone(),
+        token_data,
+        encryption_algorithm,
+    })
+}
+
+/// Decrypt the token data inside of an issued identity token.
+pub fn decrypt_issued_identity_token(issued_identity_token: &IssuedIdentityToken, server_nonce: &[u8], server_key: &PrivateKey) -> Result<ByteString, StatusCode> {
+    if issued_identity_token.encryption_algorithm.is_empty() {
+        Ok(issued_identity_token.token_data.clone())
+    } else {
+        let padding = match issued_identity_token.encryption_algorithm.as_ref() {
+            super::algorithms::ENC_RSA_15 => RsaPadding::PKCS1,
+            super::algorithms::ENC_RSA_OAEP => RsaPadding::OAEP,
+            super::algorithms::ENC_RSA_OAEP_SHA256 => RsaPadding::OAEP_SHA256,
+            _ => { return Err(StatusCode::BadSecurityPolicyRejected); }
+        };
+        legacy_secret_decrypt(&issued_identity_token.token_data, server_nonce, server_key, padding)
+    }
+}
+
+/// Create a filled in X509IdentityToken and the user token signature that must accompany it in
+/// ActivateSession. The signature is made over the server's certificate and nonce using the user's
+/// private key and the security policy of the user token policy, or of the channel if the policy
+/// doesn't specify one.
+pub fn make_x509_identity_token(channel_security_policy: SecurityPolicy, user_token_policy: &UserTokenPolicy, nonce: &[u8], server_cert: &X509, user_cert: &X509, user_key: &PrivateKey) -> Result<(X509IdentityToken, SignatureData), StatusCode> {
+    let security_policy = if user_token_policy.security_policy_uri.is_empty() {
+        channel_security_policy
+    } else {
+        SecurityPolicy::from_str(user_token_policy.security_policy_uri.as_ref()).unwrap()
+    };
+    match security_policy {
+        SecurityPolicy::None | SecurityPolicy::Unknown => {
+            error!("An X509 identity token cannot be signed without a security policy");
+            Err(StatusCode::BadSecurityPolicyRejected)
+        }
+        security_policy => {
+            let user_token_signature = super::create_signature_data(user_key, security_policy, &server_cert.as_byte_string(), &ByteString::from(nonce))?;
+            let token = X509IdentityToken {
+                policy_id: user_token_policy.policy_id.clone(),
+                certificate_data: user_cert.as_byte_string(),
+            };
+            Ok((token, user_token_signature))
         }
     }
 }
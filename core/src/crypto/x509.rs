@@ -5,9 +5,17 @@ use std::marker::Send;
 use std::fmt::{Debug, Formatter};
 use std::result::Result;
 
+#[cfg(feature = "crypto")]
+use std::cmp::Ordering;
+
+#[cfg(feature = "crypto")]
+use foreign_types::ForeignTypeRef;
+#[cfg(feature = "crypto")]
 use openssl::{x509, nid::Nid};
 
-use chrono::{DateTime, Utc, TimeZone};
+use chrono::{DateTime, Utc};
+#[cfg(feature = "crypto")]
+use chrono::TimeZone;
 
 use opcua_types::ByteString;
 use opcua_types::service_types::ApplicationDescription;
@@ -20,6 +28,13 @@ const DEFAULT_KEYSIZE: u32 = 2048;
 const DEFAULT_COUNTRY: &str = "IE";
 const DEFAULT_STATE: &str = "Dublin";
 
+/// Key usage bit of a cert whose key may verify signatures other than on certs and CRLs
+pub const KEY_USAGE_DIGITAL_SIGNATURE: u16 = 0x8000;
+/// Key usage bit of a cert whose key may encrypt other keys
+pub const KEY_USAGE_KEY_ENCIPHERMENT: u16 = 0x2000;
+/// Key usage bit of a cert whose key may verify signatures on certs
+pub const KEY_USAGE_KEY_CERT_SIGN: u16 = 0x0400;
+
 #[derive(Debug)]
 /// Used to create an X509 cert (and private key)
 pub struct X509Data {
@@ -99,11 +114,20 @@ impl X509Data {
 }
 
 /// This is a wrapper around the `OpenSSL` `X509` cert
+#[cfg(feature = "crypto")]
 #[derive(Clone)]
 pub struct X509 {
     value: x509::X509,
 }
 
+/// Without the `crypto` feature a cert is just its DER encoded bytes. It can be passed around and
+/// its thumbprint taken, but it cannot be parsed or validated.
+#[cfg(not(feature = "crypto"))]
+#[derive(Clone)]
+pub struct X509 {
+    der: Vec<u8>,
+}
+
 impl Debug for X509 {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         // This impl will not write out the cert, and exists to keep derive happy
@@ -117,12 +141,14 @@ unsafe impl Send for X509 {}
 
 unsafe impl std::marker::Sync for X509 {}
 
+#[cfg(feature = "crypto")]
 impl From<x509::X509> for X509 {
     fn from(value: x509::X509) -> Self {
         Self { value }
     }
 }
 
+#[cfg(feature = "crypto")]
 impl X509 {
     pub fn from_der(der: &[u8]) -> Result<Self, ()> {
         x509::X509::from_der(der)
@@ -296,12 +322,94 @@ impl X509 {
         Self::parse_asn1_date(&date)
     }
 
+    /// Tests if the cert was signed by the issuer, i.e. the issuer's name is the cert's issuer
+    /// name and the issuer's public key verifies the cert's signature.
+    pub fn is_issued_by(&self, issuer: &X509) -> bool {
+        if !self.has_issuer_name_of(issuer) {
+            false
+        } else if let Ok(public_key) = issuer.value.public_key() {
+            self.value.verify(&public_key).unwrap_or(false)
+        } else {
+            false
+        }
+    }
+
     pub fn to_der(&self) -> Result<Vec<u8>, ()> {
         self.value.to_der().map_err(|e| {
             error!("Cannot turn X509 cert to DER, err = {:?}", e);
         })
     }
 
+    /// Returns the `OpenSSL` cert
+    pub(crate) fn value(&self) -> &x509::X509 {
+        &self.value
+    }
+
+    /// Reads a cert and the certs of its issuers from a byte string holding one or more DER
+    /// encoded certs one after another. The first cert is the one that the others issued.
+    pub fn chain_from_byte_string(data: &ByteString) -> Result<Vec<X509>, StatusCode> {
+        if data.is_null() {
+            error!("Cannot make certificate chain from null bytestring");
+            return Err(StatusCode::BadCertificateInvalid);
+        }
+        let mut data = &data.value.as_ref().unwrap()[..];
+        let mut chain = Vec::new();
+        while !data.is_empty() {
+            // OpenSSL reads the first cert and ignores the rest, so the length of the cert's
+            // encoding says where the next one starts
+            let cert = x509::X509::from_der(data)
+                .and_then(|cert| cert.to_der().map(|der| (cert, der.len())))
+                .ok()
+                .filter(|(_, len)| *len <= data.len());
+            if let Some((cert, len)) = cert {
+                chain.push(X509::from(cert));
+                data = &data[len..];
+            } else {
+                error!("Cannot split bytestring into certificates, does it contain .der?");
+                return Err(StatusCode::BadCertificateInvalid);
+            }
+        }
+        if chain.is_empty() {
+            Err(StatusCode::BadCertificateInvalid)
+        } else {
+            Ok(chain)
+        }
+    }
+
+    /// Tests if the cert's issuer name is the subject name of the other cert
+    fn has_issuer_name_of(&self, issuer: &X509) -> bool {
+        self.value.issuer_name().try_cmp(issuer.value.subject_name())
+            .map(|ordering| ordering == Ordering::Equal)
+            .unwrap_or(false)
+    }
+
+    /// Tests if the basic constraints of the cert say it belongs to a certificate authority which
+    /// may issue other certs. A cert without basic constraints is not a certificate authority.
+    pub fn is_ca(&self) -> bool {
+        let flags = self.extension_flags();
+        flags & openssl_sys::EXFLAG_BCONS != 0 && flags & openssl_sys::EXFLAG_CA != 0
+    }
+
+    /// Returns the bits of the key usage extension of the cert, see `KEY_USAGE_DIGITAL_SIGNATURE`
+    /// etc., or `None` if the cert does not restrict the usage of its key.
+    pub fn key_usage(&self) -> Option<u16> {
+        if self.extension_flags() & openssl_sys::EXFLAG_KUSAGE == 0 {
+            None
+        } else {
+            // OpenSSL holds the first byte of the bit string in the low byte and the second in
+            // the high byte, which is the other way around to the constants
+            let key_usage = unsafe { openssl_sys::X509_get_key_usage(self.value.as_ptr()) };
+            Some((((key_usage & 0xff) << 8) | ((key_usage >> 8) & 0xff)) as u16)
+        }
+    }
+
+    /// Returns the flags that OpenSSL sets from the extensions it decoded from the cert
+    fn extension_flags(&self) -> u32 {
+        // The cert is valid for the lifetime of the call and OpenSSL only caches the decoded
+        // extensions in it
+        unsafe { openssl_sys::X509_get_extension_flags(self.value.as_ptr()) }
+    }
+
     fn parse_asn1_date(date: &str) -> Result<DateTime<Utc>, ()> {
         // Parse ASN1 time format
         // MMM DD HH:MM:SS YYYY [GMT]
@@ -317,6 +425,102 @@ impl X509 {
     }
 }
 
+#[cfg(not(feature = "crypto"))]
+impl X509 {
+    pub fn from_der(der: &[u8]) -> Result<Self, ()> {
+        Ok(X509 { der: der.to_vec() })
+    }
+
+    pub fn from_byte_string(data: &ByteString) -> Result<X509, StatusCode> {
+        if data.is_null() {
+            error!("Cannot make certificate from null bytestring");
+            Err(StatusCode::BadCertificateInvalid)
+        } else {
+            Ok(X509 { der: data.value.as_ref().unwrap().clone() })
+        }
+    }
+
+    /// Returns a ByteString representation of the cert which is DER encoded form of X509v3
+    pub fn as_byte_string(&self) -> ByteString {
+        ByteString::from(&self.der)
+    }
+
+    pub fn public_key(&self) -> Result<PublicKey, StatusCode> {
+        error!("Cannot obtain public key from certificate without the crypto feature");
+        Err(StatusCode::BadSecurityPolicyRejected)
+    }
+
+    pub fn common_name(&self) -> Result<String, ()> {
+        Err(())
+    }
+
+    pub fn is_time_valid(&self, _now: &DateTime<Utc>) -> StatusCode {
+        error!("Certificate cannot be validated without the crypto feature");
+        StatusCode::BadCertificateInvalid
+    }
+
+    pub fn is_hostname_valid(&self, _hostname: &str) -> StatusCode {
+        error!("Certificate cannot be validated without the crypto feature");
+        StatusCode::BadCertificateInvalid
+    }
+
+    pub fn is_application_uri_valid(&self, _application_uri: &str) -> StatusCode {
+        error!("Certificate cannot be validated without the crypto feature");
+        StatusCode::BadCertificateInvalid
+    }
+
+    /// The thumbprint is the SHA1 digest of the DER form of the certificate.
+    pub fn thumbprint(&self) -> Thumbprint {
+        use ring::digest;
+        let digest = digest::digest(&digest::SHA1, &self.der);
+        Thumbprint::new(digest.as_ref())
+    }
+
+    pub fn not_before(&self) -> Result<DateTime<Utc>, ()> {
+        Err(())
+    }
+
+    pub fn not_after(&self) -> Result<DateTime<Utc>, ()> {
+        Err(())
+    }
+
+    /// Signatures cannot be verified without the `crypto` feature so no cert is issued by another
+    pub fn is_issued_by(&self, _issuer: &X509) -> bool {
+        error!("Certificate signature cannot be verified without the crypto feature");
+        false
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, ()> {
+        Ok(self.der.clone())
+    }
+
+    /// The certs cannot be split apart without parsing them, so the byte string is one cert
+    pub fn chain_from_byte_string(data: &ByteString) -> Result<Vec<X509>, StatusCode> {
+        Self::from_byte_string(data).map(|cert| vec![cert])
+    }
+
+    fn has_issuer_name_of(&self, _issuer: &X509) -> bool {
+        false
+    }
+
+    pub fn is_ca(&self) -> bool {
+        false
+    }
+
+    pub fn key_usage(&self) -> Option<u16> {
+        None
+    }
+}
+
+impl X509 {
+    /// Tests if the cert's issuer name is the same as its subject name, i.e. it claims to have
+    /// issued itself. Such a cert is the root of a chain.
+    pub fn is_self_issued(&self) -> bool {
+        self.has_issuer_name_of(self)
+    }
+}
+
+#[cfg(feature = "crypto")]
 #[test]
 fn parse_asn1_date_test() {
     use chrono::{Datelike, Timelike};
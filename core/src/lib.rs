@@ -131,7 +131,8 @@ pub mod debug {
     }
 }
 
-#[cfg(test)]
+// The tests rely upon crypto to create certificates and keys
+#[cfg(all(test, feature = "crypto"))]
 mod tests;
 
 pub mod comms;
@@ -1,6 +1,9 @@
 extern crate rustc_serialize as serialize;
 
 use std::io::{Cursor, Write};
+
+use bytes::Bytes;
+
 use opcua_types::DecodingLimits;
 use opcua_types::tcp_types::MIN_CHUNK_SIZE;
 
@@ -51,9 +54,11 @@ fn set_chunk_sequence_number(chunk: &mut MessageChunk, secure_channel: &SecureCh
     let old_sequence_number = chunk_info.sequence_header.sequence_number;
     chunk_info.sequence_header.sequence_number = sequence_number;
     // Write the sequence header out again with new value
-    let mut stream = Cursor::new(&mut chunk.data[..]);
+    let mut data = chunk.data.to_vec();
+    let mut stream = Cursor::new(&mut data[..]);
     stream.set_position(chunk_info.sequence_header_offset as u64);
     let _ = chunk_info.sequence_header.encode(&mut stream);
+    chunk.data = data.into();
     old_sequence_number
 }
 
@@ -63,9 +68,11 @@ fn set_chunk_request_id(chunk: &mut MessageChunk, secure_channel: &SecureChannel
     let old_request_id = chunk_info.sequence_header.request_id;
     chunk_info.sequence_header.request_id = request_id;
     // Write the sequence header out again with new value
-    let mut stream = Cursor::new(&mut chunk.data[..]);
+    let mut data = chunk.data.to_vec();
+    let mut stream = Cursor::new(&mut data[..]);
     stream.set_position(chunk_info.sequence_header_offset as u64);
     let _ = chunk_info.sequence_header.encode(&mut stream);
+    chunk.data = data.into();
     old_request_id
 }
 
@@ -108,6 +115,45 @@ fn chunk_multi_encode_decode() {
     assert_eq!(response, new_response);
 }
 
+/// Decode a multi chunk message from the frames its chunks were read from. The chunks share the
+/// bytes of their frames rather than copying them.
+#[test]
+fn chunk_multi_decode_from_frames() {
+    let _ = Test::setup();
+
+    let mut secure_channel = SecureChannel::new_no_certificate_store();
+    secure_channel.set_(DecodingLimits {
+        max_string_length: 65536,
+        max_byte_string_length: 65536,
+        max_array_length: 20000,
+    });
+    let decoding_limits = secure_channel.decoding_limits();
+
+    let response = make_large_read_response();
+    let encoded_chunks = Chunker::encode(1000, 100, 0, MIN_CHUNK_SIZE, &secure_channel, &response).unwrap();
+    assert!(encoded_chunks.len() > 1);
+
+    let mut chunks = Vec::new();
+    for encoded_chunk in encoded_chunks.iter() {
+        // The frame as it is read from the socket
+        let frame = Bytes::from(encoded_chunk.data.to_vec());
+        let chunk = MessageChunk::from_bytes(frame.clone(), &decoding_limits).unwrap();
+        assert_eq!(chunk.data.as_ptr(), frame.as_ptr());
+
+        // There is no security to remove so the chunk still shares the frame
+        let chunk = secure_channel.verify_and_remove_security(chunk.data).unwrap();
+        assert_eq!(chunk.data.as_ptr(), frame.as_ptr());
+        chunks.push(chunk);
+
+        // A frame must hold exactly one chunk
+        assert!(MessageChunk::from_bytes(frame.slice(0, frame.len() - 1), &decoding_limits).is_err());
+    }
+
+    assert_eq!(Chunker::validate_chunks(1000, &secure_channel, &chunks).unwrap(), 1000 + chunks.len() as u32 - 1);
+    let new_response = Chunker::decode(&chunks, &secure_channel, None).unwrap();
+    assert_eq!(response, new_response);
+}
+
 /// Encode a large message with multiple chunks. Ensure all but the last chunk is marked intermediate
 /// and the last is marked final.
 #[test]
@@ -390,5 +436,5 @@ fn asymmetric_decrypt_and_verify_sample_chunk() {
     secure_channel.set_remote_cert(Some(their_cert));
     secure_channel.set_private_key(Some(our_key));
 
-    let _ = secure_channel.verify_and_remove_security_forensic(&message_data, Some(their_key)).unwrap();
+    let _ = secure_channel.verify_and_remove_security_forensic(&message_data[..], Some(their_key)).unwrap();
 }
\ No newline at end of file
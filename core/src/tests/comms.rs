@@ -3,7 +3,7 @@ use std::io::*;
 use opcua_types::*;
 use opcua_types::tcp_types::*;
 
-use crate::comms::secure_channel::*;
+use crate::comms::{secure_channel::*, buffer_pool::BufferPool, message_writer::MessageWriter, https_codec::*};
 use crate::crypto::SecurityPolicy;
 
 fn hello_data() -> Vec<u8> {
@@ -51,6 +51,26 @@ pub fn acknowledge() {
     assert_eq!(ack.max_chunk_count, 65535);
 }
 
+#[test]
+pub fn reverse_hello() {
+    let reverse_hello = ReverseHelloMessage::new("urn:server", "opc.tcp://127.0.0.1:1234/");
+    assert!(reverse_hello.is_valid());
+    let data = reverse_hello.to_vec();
+    assert_eq!(&data[0..4], b"RHEF");
+    assert_eq!(data.len(), 51);
+
+    let mut stream = Cursor::new(data);
+    let decoding_limits = DecodingLimits::default();
+    let reverse_hello = ReverseHelloMessage::decode(&mut stream, &decoding_limits).unwrap();
+    assert_eq!(reverse_hello.message_header.message_type, MessageType::ReverseHello);
+    assert_eq!(reverse_hello.message_header.message_size, 51);
+    assert_eq!(reverse_hello.server_uri, UAString::from("urn:server"));
+    assert_eq!(reverse_hello.endpoint_url, UAString::from("opc.tcp://127.0.0.1:1234/"));
+
+    // Server uri and endpoint url are both required
+    assert!(!ReverseHelloMessage::new("", "opc.tcp://127.0.0.1:1234/").is_valid());
+}
+
 #[test]
 pub fn secure_channel_nonce() {
     let mut sc = SecureChannel::new_no_certificate_store();
@@ -65,3 +85,79 @@ pub fn secure_channel_nonce() {
     // Nonce which is 32 bytes long is good
     assert!(sc.set_remote_nonce_from_byte_string(&ByteString::from(b"01234567890123456789012345678901")).is_ok());
 }
+
+#[test]
+pub fn buffer_pool() {
+    let pool = BufferPool::new(2, 1024);
+    assert_eq!(pool.available(), 0);
+
+    // A buffer is allocated when the pool is empty and reused when it is given back
+    let mut buffer = pool.take(512);
+    assert!(buffer.capacity() >= 512);
+    buffer.extend_from_slice(b"hello");
+    let ptr = buffer.as_ptr();
+    pool.give_back(buffer);
+    assert_eq!(pool.available(), 1);
+    let buffer = pool.take(256);
+    assert_eq!(pool.available(), 0);
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.as_ptr(), ptr);
+
+    // A buffer which is too small for the request is not used
+    pool.give_back(buffer);
+    let large = pool.take(1000);
+    assert!(large.capacity() >= 1000);
+    assert_eq!(pool.available(), 1);
+
+    // Buffers over the size limit and beyond the count limit are dropped
+    pool.give_back(Vec::with_capacity(2048));
+    assert_eq!(pool.available(), 1);
+    pool.give_back(large);
+    pool.give_back(Vec::with_capacity(16));
+    assert_eq!(pool.available(), 2);
+
+    // Clones share the pool
+    let clone = pool.clone();
+    let _ = clone.take(16);
+    assert_eq!(pool.available(), 1);
+}
+
+#[test]
+pub fn message_writer_buffer_pool() {
+    let pool = BufferPool::new(4, 8192);
+    let secure_channel = SecureChannel::new_no_certificate_store();
+    let mut writer = MessageWriter::new(4096, pool.clone());
+
+    // Nothing is held until something is written
+    assert!(writer.bytes_to_write().is_empty());
+
+    let request_id = writer.next_request_id();
+    writer.write(request_id, super::make_sample_message(), &secure_channel).unwrap();
+    // The scratch buffer for securing chunks went back to the pool
+    assert_eq!(pool.available(), 1);
+
+    let bytes = writer.bytes_to_write();
+    assert_eq!(&bytes[0..3], b"MSG");
+    assert!(writer.bytes_to_write().is_empty());
+
+    // The written buffer is reused by the next message
+    writer.give_back(bytes);
+    assert_eq!(pool.available(), 2);
+    let request_id = writer.next_request_id();
+    writer.write(request_id, super::make_sample_message(), &secure_channel).unwrap();
+    assert_eq!(pool.available(), 1);
+}
+
+#[test]
+pub fn https_body() {
+    let decoding_limits = DecodingLimits::default();
+    let message = super::make_sample_message();
+    let body = encode_https_body(&message).unwrap();
+    assert_eq!(body.len(), message.node_id().byte_len() + message.byte_len());
+    let decoded = decode_https_body(&body, &decoding_limits).unwrap();
+    assert_eq!(decoded.node_id(), message.node_id());
+    assert_eq!(encode_https_body(&decoded).unwrap(), body);
+
+    // A truncated body cannot be decoded
+    assert!(decode_https_body(&body[..body.len() - 1], &decoding_limits).is_err());
+}
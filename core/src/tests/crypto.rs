@@ -2,6 +2,17 @@ extern crate rustc_serialize as serialize;
 
 use std::fs::File;
 use std::io::Write;
+use std::path::PathBuf;
+
+use openssl::{
+    asn1::Asn1Time,
+    bn::BigNum,
+    hash::MessageDigest,
+    pkey::{PKey, Private},
+    rsa::Rsa,
+    sign::Signer,
+    x509::{X509NameBuilder, extension::{BasicConstraints, KeyUsage}},
+};
 
 use opcua_types::{
     ByteString,
@@ -12,6 +23,7 @@ use crate::crypto::{
     SecurityPolicy, SHA1_SIZE, SHA256_SIZE,
     certificate_store::*,
     x509::{X509, X509Data},
+    crl::CertificateRevocationList,
     pkey::{PrivateKey, KeySize, RsaPadding},
     aeskey::AesKey,
     user_identity::{legacy_password_encrypt, legacy_password_decrypt},
@@ -181,16 +193,285 @@ fn test_and_reject_thumbprint_mismatch() {
     drop(tmp_dir);
 }
 
+/// Encodes a DER element with the tag and contents
+fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut result = vec![tag];
+    let len = contents.len();
+    if len < 0x80 {
+        result.push(len as u8);
+    } else if len < 0x100 {
+        result.extend_from_slice(&[0x81, len as u8]);
+    } else {
+        result.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]);
+    }
+    result.extend_from_slice(contents);
+    result
+}
+
+/// Makes a CRL from the issuer which revokes the serial numbers, signed with the key. The next
+/// update is a DER encoded time if there is one.
+fn make_crl(issuer: &[u8], revoked_serial_numbers: &[&[u8]], next_update: Option<&[u8]>, pkey: &PKey<Private>) -> Vec<u8> {
+    let algorithm = der(0x30, &der(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b]));
+    let this_update = der(0x17, b"190101000000Z");
+    let mut tbs_cert_list = der(0x02, &[0x01]);
+    tbs_cert_list.extend(&algorithm);
+    tbs_cert_list.extend(issuer);
+    tbs_cert_list.extend(&this_update);
+    if let Some(next_update) = next_update {
+        tbs_cert_list.extend(next_update);
+    }
+    if !revoked_serial_numbers.is_empty() {
+        let revoked = revoked_serial_numbers.iter().flat_map(|serial_number| {
+            let mut revoked_certificate = der(0x02, serial_number);
+            revoked_certificate.extend(&this_update);
+            der(0x30, &revoked_certificate)
+        }).collect::<Vec<u8>>();
+        tbs_cert_list.extend(der(0x30, &revoked));
+    }
+    let tbs_cert_list = der(0x30, &tbs_cert_list);
+    let mut signer = Signer::new(MessageDigest::sha256(), pkey).unwrap();
+    signer.update(&tbs_cert_list).unwrap();
+    let mut signature = vec![0x00];
+    signature.extend(signer.sign_to_vec().unwrap());
+
+    let mut certificate_list = tbs_cert_list;
+    certificate_list.extend(&algorithm);
+    certificate_list.extend(der(0x03, &signature));
+    der(0x30, &certificate_list)
+}
+
+/// Returns the contents of the DER encoded serial number of the cert
+fn serial_number_of(cert: &openssl::x509::X509) -> Vec<u8> {
+    let magnitude = cert.serial_number().to_bn().unwrap().to_vec();
+    // A positive integer whose top bit is set needs a leading zero byte
+    if magnitude.first().map_or(true, |b| b & 0x80 != 0) {
+        let mut serial_number = vec![0x00];
+        serial_number.extend(magnitude);
+        serial_number
+    } else {
+        magnitude
+    }
+}
+
+/// Makes an empty CRL signed by the certificate authority
+fn make_ca_crl(ca: &(openssl::x509::X509, PKey<Private>), revoked_serial_numbers: &[&[u8]]) -> Vec<u8> {
+    make_crl(&ca.0.subject_name().to_der().unwrap(), revoked_serial_numbers, None, &ca.1)
+}
+
+#[test]
+fn test_and_reject_revoked_cert() {
+    let (tmp_dir, mut cert_store) = make_certificate_store();
+
+    let (cert, pkey) = make_test_cert_1024();
+    let der_cert = cert.to_der().unwrap();
+    let mut cert_trusted_path = cert_store.trusted_certs_dir();
+    cert_trusted_path.push(CertificateStore::cert_file_name(&cert));
+    {
+        let mut file = File::create(cert_trusted_path).unwrap();
+        assert!(file.write(&der_cert).is_ok());
+    }
+
+    let issuer = cert.value().issuer_name().to_der().unwrap();
+    let serial_number = serial_number_of(cert.value());
+    let (issuer, serial_number) = (&issuer[..], &serial_number[..]);
+    let pkey = pkey.value();
+    let crl_dir = cert_store.crl_dir();
+    let write_crl = |crl: &[u8]| {
+        let mut crl_path = crl_dir.clone();
+        crl_path.push("test.crl");
+        let mut file = File::create(crl_path).unwrap();
+        assert!(file.write(crl).is_ok());
+    };
+
+    // A CRL that revokes another cert of the issuer
+    let crl = make_crl(issuer, &[&[0x7f, 0x01]], None, pkey);
+    let revocation_list = CertificateRevocationList::from_der(&crl).unwrap();
+    assert_eq!(revocation_list.revoked_count(), 1);
+    assert!(revocation_list.is_issued_by(&cert));
+    write_crl(&crl);
+    cert_store.reload_crls();
+    assert_eq!(cert_store.validate_application_instance_cert(&cert, None, None), StatusCode::Good);
+
+    // A CRL that revokes the cert only takes effect once the lists are reloaded
+    let crl = make_crl(issuer, &[&[0x7f, 0x01], serial_number], None, pkey);
+    let revocation_list = CertificateRevocationList::from_der(&crl).unwrap();
+    assert_eq!(revocation_list.revoked_count(), 2);
+    assert!(revocation_list.is_revoked(&cert).unwrap());
+    write_crl(&crl);
+    assert_eq!(cert_store.validate_application_instance_cert(&cert, None, None), StatusCode::Good);
+    cert_store.reload_crls();
+    assert_eq!(cert_store.validate_application_instance_cert(&cert, None, None), StatusCode::BadCertificateRevoked);
+
+    // A CRL of another issuer does not revoke the cert even if the serial number matches
+    let other_issuer = {
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "Other issuer").unwrap();
+        name.build().to_der().unwrap()
+    };
+    write_crl(&make_crl(&other_issuer, &[serial_number], None, pkey));
+    cert_store.reload_crls();
+    assert_eq!(cert_store.validate_application_instance_cert(&cert, None, None), StatusCode::Good);
+
+    // A CRL which is not signed by the issuer is ignored
+    let (_, other_pkey) = make_test_cert_1024();
+    let crl = make_crl(issuer, &[serial_number], None, other_pkey.value());
+    assert!(!CertificateRevocationList::from_der(&crl).unwrap().is_issued_by(&cert));
+    write_crl(&crl);
+    cert_store.reload_crls();
+    assert_eq!(cert_store.validate_application_instance_cert(&cert, None, None), StatusCode::Good);
+
+    // A CRL whose next update has passed is ignored, one whose next update is to come is used
+    write_crl(&make_crl(issuer, &[serial_number], Some(&der(0x17, b"190201000000Z")), pkey));
+    cert_store.reload_crls();
+    assert_eq!(cert_store.validate_application_instance_cert(&cert, None, None), StatusCode::Good);
+    write_crl(&make_crl(issuer, &[serial_number], Some(&der(0x18, b"20491231235959Z")), pkey));
+    cert_store.reload_crls();
+    assert_eq!(cert_store.validate_application_instance_cert(&cert, None, None), StatusCode::BadCertificateRevoked);
+
+    // A CRL which cannot be read means it is unknown whether the cert is revoked
+    write_crl(&[0x30, 0x05, 0x00]);
+    cert_store.reload_crls();
+    assert_eq!(cert_store.validate_application_instance_cert(&cert, None, None), StatusCode::BadCertificateRevocationUnknown);
+
+    // Neither a revoked cert nor an unreadable CRL fails the cert when revocation is not checked
+    cert_store.validation.check_revocation = false;
+    assert_eq!(cert_store.validate_application_instance_cert(&cert, None, None), StatusCode::Good);
+    write_crl(&make_crl(issuer, &[serial_number], None, pkey));
+    cert_store.reload_crls();
+    assert_eq!(cert_store.validate_application_instance_cert(&cert, None, None), StatusCode::Good);
+    cert_store.validation.check_revocation = true;
+    assert_eq!(cert_store.validate_application_instance_cert(&cert, None, None), StatusCode::BadCertificateRevoked);
+
+    drop(tmp_dir);
+}
+
+/// Makes a cert signed by the issuer, or a self-signed cert if there is no issuer. Certificate
+/// authorities may sign certs, other certs may sign and encrypt keys.
+fn make_chain_cert(common_name: &str, ca: bool, issuer: Option<&(openssl::x509::X509, PKey<Private>)>) -> (openssl::x509::X509, PKey<Private>) {
+    let pkey = PKey::from_rsa(Rsa::generate(1024).unwrap()).unwrap();
+    let mut builder = openssl::x509::X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    let name = {
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", common_name).unwrap();
+        name.build()
+    };
+    builder.set_subject_name(&name).unwrap();
+    if let Some((issuer_cert, _)) = issuer {
+        builder.set_issuer_name(issuer_cert.subject_name()).unwrap();
+    } else {
+        builder.set_issuer_name(&name).unwrap();
+    }
+    if ca {
+        builder.append_extension(BasicConstraints::new().critical().ca().build().unwrap()).unwrap();
+        builder.append_extension(KeyUsage::new().critical().key_cert_sign().crl_sign().build().unwrap()).unwrap();
+    } else {
+        builder.append_extension(KeyUsage::new().critical().digital_signature().key_encipherment().build().unwrap()).unwrap();
+    }
+    builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    builder.set_not_after(&Asn1Time::days_from_now(30).unwrap()).unwrap();
+    builder.set_serial_number(&BigNum::from_slice(common_name.as_bytes()).unwrap().to_asn1_integer().unwrap()).unwrap();
+    builder.set_pubkey(&pkey).unwrap();
+    let signing_key = issuer.map_or(&pkey, |(_, issuer_pkey)| issuer_pkey);
+    builder.sign(signing_key, MessageDigest::sha256()).unwrap();
+    (builder.build(), pkey)
+}
+
+/// Writes the cert into the directory in the same way as an administrator would
+fn write_cert(mut path: PathBuf, cert: &X509) {
+    path.push(CertificateStore::cert_file_name(cert));
+    let mut file = File::create(path).unwrap();
+    assert!(file.write(&cert.to_der().unwrap()).is_ok());
+}
+
+#[test]
+fn test_and_trust_cert_chain() {
+    let (tmp_dir, mut cert_store) = make_certificate_store();
+
+    let root = make_chain_cert("Root CA", true, None);
+    let intermediate = make_chain_cert("Intermediate CA", true, Some(&root));
+    let leaf = make_chain_cert("Leaf", false, Some(&intermediate));
+    let direct_leaf = make_chain_cert("Direct Leaf", false, Some(&root));
+    let (root_cert, intermediate_cert) = (X509::from(root.0.clone()), X509::from(intermediate.0.clone()));
+    let (leaf_cert, direct_leaf_cert) = (X509::from(leaf.0.clone()), X509::from(direct_leaf.0.clone()));
+
+    assert!(root_cert.is_self_issued());
+    assert!(root_cert.is_ca());
+    assert!(leaf_cert.is_issued_by(&intermediate_cert));
+    assert!(!leaf_cert.is_issued_by(&root_cert));
+    assert!(!leaf_cert.is_ca());
+
+    // The root is only an issuer so the chain is complete but nothing in it is trusted
+    write_cert(cert_store.issuers_dir(), &root_cert);
+    assert_eq!(cert_store.validate_application_instance_cert(&direct_leaf_cert, None, None), StatusCode::BadCertificateUntrusted);
+    std::fs::remove_dir_all(cert_store.rejected_certs_dir()).unwrap();
+    std::fs::create_dir(cert_store.rejected_certs_dir()).unwrap();
+
+    // Trusting the root trusts the certs it issues, once the root has published a CRL
+    write_cert(cert_store.trusted_certs_dir(), &root_cert);
+    assert_eq!(cert_store.validate_application_instance_cert(&direct_leaf_cert, None, None), StatusCode::BadCertificateRevocationUnknown);
+    cert_store.validation.check_revocation = false;
+    assert_eq!(cert_store.validate_application_instance_cert(&direct_leaf_cert, None, None), StatusCode::Good);
+    cert_store.validation.check_revocation = true;
+    cert_store.store_crl(&make_ca_crl(&root, &[])).unwrap();
+    assert_eq!(cert_store.validate_application_instance_cert(&direct_leaf_cert, None, None), StatusCode::Good);
+    assert_eq!(cert_store.check_revocation(&direct_leaf_cert, &[]), StatusCode::Good);
+
+    // The intermediate can be supplied with the leaf, e.g. in a security header, or be in the issuers dir
+    assert_eq!(cert_store.validate_application_instance_cert(&leaf_cert, None, None), StatusCode::BadCertificateChainIncomplete);
+    let mut chain = leaf.0.to_der().unwrap();
+    chain.extend(intermediate.0.to_der().unwrap());
+    let chain = X509::chain_from_byte_string(&ByteString::from(chain)).unwrap();
+    assert_eq!(chain.len(), 2);
+    assert_eq!(cert_store.validate_application_instance_cert_chain(&chain[0], &chain[1..], None, None), StatusCode::BadCertificateRevocationUnknown);
+    assert_eq!(cert_store.check_revocation(&leaf_cert, &[]), StatusCode::BadCertificateRevocationUnknown);
+    cert_store.store_crl(&make_ca_crl(&intermediate, &[])).unwrap();
+    assert_eq!(cert_store.validate_application_instance_cert_chain(&chain[0], &chain[1..], None, None), StatusCode::Good);
+    assert_eq!(cert_store.check_revocation(&leaf_cert, &chain[1..]), StatusCode::Good);
+    write_cert(cert_store.issuers_dir(), &intermediate_cert);
+    assert_eq!(cert_store.validate_application_instance_cert(&leaf_cert, None, None), StatusCode::Good);
+
+    // The CRL of each issuer revokes the certs it issued
+    let intermediate_serial_number = serial_number_of(&intermediate.0);
+    cert_store.store_crl(&make_ca_crl(&root, &[&intermediate_serial_number])).unwrap();
+    assert_eq!(cert_store.validate_application_instance_cert(&leaf_cert, None, None), StatusCode::BadCertificateIssuerRevoked);
+    assert_eq!(cert_store.validate_application_instance_cert(&direct_leaf_cert, None, None), StatusCode::Good);
+    cert_store.validation.check_revocation = false;
+    assert_eq!(cert_store.validate_application_instance_cert(&leaf_cert, None, None), StatusCode::Good);
+    cert_store.validation.check_revocation = true;
+    cert_store.store_crl(&make_ca_crl(&root, &[])).unwrap();
+    assert_eq!(cert_store.validate_application_instance_cert(&leaf_cert, None, None), StatusCode::Good);
+
+    // An issuer which is not a certificate authority and may not sign certs is not allowed to issue
+    let not_ca = make_chain_cert("Not CA", false, Some(&root));
+    let not_ca_leaf = make_chain_cert("Not CA Leaf", false, Some(&not_ca));
+    write_cert(cert_store.issuers_dir(), &X509::from(not_ca.0.clone()));
+    cert_store.store_crl(&make_ca_crl(&not_ca, &[])).unwrap();
+    let not_ca_leaf = X509::from(not_ca_leaf.0);
+    assert_eq!(cert_store.validate_application_instance_cert(&not_ca_leaf, None, None), StatusCode::BadCertificateIssuerUseNotAllowed);
+
+    // The checks can be relaxed individually
+    cert_store.validation.check_basic_constraints = false;
+    assert_eq!(cert_store.validate_application_instance_cert(&not_ca_leaf, None, None), StatusCode::BadCertificateIssuerUseNotAllowed);
+    cert_store.validation.check_key_usage = false;
+    assert_eq!(cert_store.validate_application_instance_cert(&not_ca_leaf, None, None), StatusCode::Good);
+
+    drop(tmp_dir);
+}
+
 fn test_asymmetric_encrypt_and_decrypt(cert: &X509, key: &PrivateKey, security_policy: SecurityPolicy, plaintext_size: usize) {
     let mut plaintext = vec![0u8; plaintext_size];
     for i in 0..plaintext_size {
         plaintext[i] = (i % 256) as u8;
     }
-    let mut ciphertext = vec![0u8; plaintext_size + 4096];
-    let mut plaintext2 = vec![0u8; plaintext_size + 4096];
+    let public_key = cert.public_key().unwrap();
+    // The padding overhead of each block depends on the policy, so size the buffers from the key
+    let ciphertext_size = public_key.calculate_cipher_text_size(plaintext_size, security_policy.padding());
+    let mut ciphertext = vec![0u8; ciphertext_size];
+    let mut plaintext2 = vec![0u8; ciphertext_size];
 
     trace!("Encrypting data of length {}", plaintext_size);
-    let encrypted_size = security_policy.asymmetric_encrypt(&cert.public_key().unwrap(), &plaintext, &mut ciphertext).unwrap();
+    let encrypted_size = security_policy.asymmetric_encrypt(&public_key, &plaintext, &mut ciphertext).unwrap();
     trace!("Encrypted size = {}", encrypted_size);
     trace!("Decrypting cipher text back");
     let decrypted_size = security_policy.asymmetric_decrypt(key, &ciphertext[..encrypted_size], &mut plaintext2).unwrap();
@@ -205,7 +486,7 @@ fn test_asymmetric_encrypt_and_decrypt(cert: &X509, key: &PrivateKey, security_p
 fn asymmetric_encrypt_and_decrypt() {
     let (cert, key) = make_test_cert_2048();
     // Try all security policies, ensure they encrypt / decrypt for various sizes
-    for security_policy in &[SecurityPolicy::Basic128Rsa15, SecurityPolicy::Basic256, SecurityPolicy::Basic256Sha256] {
+    for security_policy in &[SecurityPolicy::Basic128Rsa15, SecurityPolicy::Basic256, SecurityPolicy::Basic256Sha256, SecurityPolicy::Aes128Sha256RsaOaep, SecurityPolicy::Aes256Sha256RsaPss] {
         for data_size in &[0, 1, 127, 128, 129, 255, 256, 257, 13001] {
             test_asymmetric_encrypt_and_decrypt(&cert, &key, *security_policy, *data_size);
         }
@@ -233,6 +514,14 @@ fn calculate_cipher_text_size() {
     assert_eq!(pkey.calculate_cipher_text_size(255, padding), 512);
     assert_eq!(pkey.calculate_cipher_text_size(256, padding), 512);
     assert_eq!(pkey.calculate_cipher_text_size(512, padding), 768);
+
+    // Testing -66 bounds
+    let padding = RsaPadding::OAEP_SHA256;
+    assert_eq!(pkey.calculate_cipher_text_size(1, padding), 256);
+    assert_eq!(pkey.calculate_cipher_text_size(190, padding), 256);
+    assert_eq!(pkey.calculate_cipher_text_size(191, padding), 512);
+    assert_eq!(pkey.calculate_cipher_text_size(380, padding), 512);
+    assert_eq!(pkey.calculate_cipher_text_size(381, padding), 768);
 }
 
 #[test]
@@ -242,7 +531,7 @@ fn calculate_cipher_text_size2() {
 
     // The cipher text size function should report exactly the same value as the value returned
     // by encrypting bytes. This is especially important on boundary values.
-    for padding in &[RsaPadding::PKCS1, RsaPadding::OAEP] {
+    for padding in &[RsaPadding::PKCS1, RsaPadding::OAEP, RsaPadding::OAEP_SHA256] {
         for src_len in 1..550 {
             let src = vec![127u8; src_len];
 
@@ -303,6 +592,31 @@ fn sign_verify_sha256() {
     assert!(!public_key.verify_hmac_sha256(msg, &signature).unwrap());
 }
 
+#[test]
+fn sign_verify_sha256_pss() {
+    let (cert, private_key) = make_test_cert_2048();
+
+    let msg = b"Mary had a little lamb";
+    let msg2 = b"It's fleece was white as snow";
+    let mut signature = [0u8; 256];
+    let signed_len = private_key.sign_sha256_pss(msg, &mut signature).unwrap();
+
+    assert_eq!(signed_len, 256);
+    let public_key = cert.public_key().unwrap();
+
+    assert!(public_key.verify_sha256_pss(msg, &signature).unwrap());
+    assert!(!public_key.verify_sha256_pss(msg2, &signature).unwrap());
+
+    // PSS signatures are salted so signing the same data twice should produce a different signature
+    let mut signature2 = [0u8; 256];
+    let _ = private_key.sign_sha256_pss(msg, &mut signature2).unwrap();
+    assert_ne!(&signature[..], &signature2[..]);
+    assert!(public_key.verify_sha256_pss(msg, &signature2).unwrap());
+
+    // A PKCS1 signature is not a PSS signature
+    assert!(!public_key.verify_hmac_sha256(msg, &signature).unwrap());
+}
+
 #[test]
 fn sign_hmac_sha1() {
     use crate::crypto::hash;
@@ -362,6 +676,8 @@ fn generate_nonce() {
     assert_eq!(SecurityPolicy::Basic128Rsa15.random_nonce().as_ref().len(), 16);
     assert_eq!(SecurityPolicy::Basic256.random_nonce().as_ref().len(), 32);
     assert_eq!(SecurityPolicy::Basic256Sha256.random_nonce().as_ref().len(), 32);
+    assert_eq!(SecurityPolicy::Aes128Sha256RsaOaep.random_nonce().as_ref().len(), 32);
+    assert_eq!(SecurityPolicy::Aes256Sha256RsaPss.random_nonce().as_ref().len(), 32);
 }
 
 #[test]
@@ -402,6 +718,28 @@ fn derive_keys_from_nonce() {
     assert_eq!(signing_key.len(), 32);
     assert_eq!(encryption_key.value().len(), 32);
     assert_eq!(iv.len(), 16);
+
+    // Create a security policy Aes128-Sha256-RsaOaep policy
+    //
+    // a) SigningKeyLength = 32
+    // b) EncryptingKeyLength = 16
+    // c) EncryptingBlockSize = 16
+    let security_policy = SecurityPolicy::Aes128Sha256RsaOaep;
+    let (signing_key, encryption_key, iv) = security_policy.make_secure_channel_keys(&nonce1, &nonce2);
+    assert_eq!(signing_key.len(), 32);
+    assert_eq!(encryption_key.value().len(), 16);
+    assert_eq!(iv.len(), 16);
+
+    // Create a security policy Aes256-Sha256-RsaPss policy
+    //
+    // a) SigningKeyLength = 32
+    // b) EncryptingKeyLength = 32
+    // c) EncryptingBlockSize = 16
+    let security_policy = SecurityPolicy::Aes256Sha256RsaPss;
+    let (signing_key, encryption_key, iv) = security_policy.make_secure_channel_keys(&nonce1, &nonce2);
+    assert_eq!(signing_key.len(), 32);
+    assert_eq!(encryption_key.value().len(), 32);
+    assert_eq!(iv.len(), 16);
 }
 
 #[test]
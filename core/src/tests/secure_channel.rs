@@ -4,6 +4,7 @@
 
 use crate::comms::chunker::*;
 use crate::comms::secure_channel::*;
+use crate::comms::security_header::SecurityHeader;
 
 use crate::crypto::SecurityPolicy;
 
@@ -143,3 +144,168 @@ fn symmetric_sign_and_encrypt_message_chunk_basic256sha256() {
     error!("symmetric_sign_and_encrypt_message_chunk_basic256sha256");
     test_symmetric_encrypt_decrypt(make_sample_message(), MessageSecurityMode::SignAndEncrypt, SecurityPolicy::Basic256Sha256);
 }
+
+#[test]
+fn asymmetric_sign_and_encrypt_message_chunk_aes128sha256rsaoaep() {
+    let _ = Test::setup();
+    error!("asymmetric_sign_and_encrypt_message_chunk_aes128sha256rsaoaep");
+    test_asymmetric_encrypt_decrypt(make_open_secure_channel_response().into(), MessageSecurityMode::SignAndEncrypt, SecurityPolicy::Aes128Sha256RsaOaep);
+}
+
+#[test]
+fn symmetric_sign_message_chunk_aes128sha256rsaoaep() {
+    let _ = Test::setup();
+    error!("symmetric_sign_message_chunk_aes128sha256rsaoaep");
+    test_symmetric_encrypt_decrypt(make_sample_message(), MessageSecurityMode::Sign, SecurityPolicy::Aes128Sha256RsaOaep);
+}
+
+#[test]
+fn symmetric_sign_and_encrypt_message_chunk_aes128sha256rsaoaep() {
+    let _ = Test::setup();
+    error!("symmetric_sign_and_encrypt_message_chunk_aes128sha256rsaoaep");
+    test_symmetric_encrypt_decrypt(make_sample_message(), MessageSecurityMode::SignAndEncrypt, SecurityPolicy::Aes128Sha256RsaOaep);
+}
+
+#[test]
+fn asymmetric_sign_and_encrypt_message_chunk_aes256sha256rsapss() {
+    let _ = Test::setup();
+    error!("asymmetric_sign_and_encrypt_message_chunk_aes256sha256rsapss");
+    test_asymmetric_encrypt_decrypt(make_open_secure_channel_response().into(), MessageSecurityMode::SignAndEncrypt, SecurityPolicy::Aes256Sha256RsaPss);
+}
+
+#[test]
+fn symmetric_sign_message_chunk_aes256sha256rsapss() {
+    let _ = Test::setup();
+    error!("symmetric_sign_message_chunk_aes256sha256rsapss");
+    test_symmetric_encrypt_decrypt(make_sample_message(), MessageSecurityMode::Sign, SecurityPolicy::Aes256Sha256RsaPss);
+}
+
+#[test]
+fn symmetric_sign_and_encrypt_message_chunk_aes256sha256rsapss() {
+    let _ = Test::setup();
+    error!("symmetric_sign_and_encrypt_message_chunk_aes256sha256rsapss");
+    test_symmetric_encrypt_decrypt(make_sample_message(), MessageSecurityMode::SignAndEncrypt, SecurityPolicy::Aes256Sha256RsaPss);
+}
+
+/// Secures a message for sending on one channel and removes the security on the other,
+/// returning the token id of the message
+fn send_secured_message(sender: &SecureChannel, receiver: &mut SecureChannel, message: &SupportedMessage) -> Result<u32, StatusCode> {
+    let chunks = Chunker::encode(1, 1, 0, 0, sender, message).unwrap();
+    let chunk = &chunks[0];
+    let token_id = match chunk.chunk_info(sender).unwrap().security_header {
+        SecurityHeader::Symmetric(security_header) => security_header.token_id,
+        _ => panic!("Expected a symmetric security header")
+    };
+    let mut encrypted_data = vec![0u8; chunk.data.len() + 4096];
+    let encrypted_size = sender.apply_security(chunk, &mut encrypted_data[..]).unwrap();
+    receiver.verify_and_remove_security(&encrypted_data[..encrypted_size])?;
+    Ok(token_id)
+}
+
+fn set_security_token(secure_channel: &mut SecureChannel, token_id: u32, local_nonce: &[u8], remote_nonce: &[u8]) {
+    secure_channel.set_security_token(ChannelSecurityToken {
+        channel_id: 1,
+        token_id,
+        created_at: DateTime::now(),
+        revised_lifetime: 60000,
+    });
+    secure_channel.set_local_nonce(local_nonce);
+    secure_channel.set_remote_nonce(remote_nonce);
+    secure_channel.derive_keys();
+}
+
+/// Renew the token of a secure channel and check both tokens are accepted during the overlap
+#[test]
+fn renew_security_token() {
+    let _ = Test::setup();
+
+    let message = make_sample_message();
+    let (mut server, mut client) = make_secure_channels(MessageSecurityMode::SignAndEncrypt, SecurityPolicy::Basic256Sha256);
+    client.set_role(Role::Client);
+    server.set_role(Role::Server);
+
+    let client_nonce = vec![0u8; 32];
+    let server_nonce = vec![1u8; 32];
+    set_security_token(&mut server, 1, &server_nonce, &client_nonce);
+    set_security_token(&mut client, 1, &client_nonce, &server_nonce);
+    assert_eq!(send_secured_message(&client, &mut server, &message).unwrap(), 1);
+    assert_eq!(send_secured_message(&server, &mut client, &message).unwrap(), 1);
+
+    // Renew the token with new nonces
+    let client_nonce = vec![2u8; 32];
+    let server_nonce = vec![3u8; 32];
+    set_security_token(&mut server, 2, &server_nonce, &client_nonce);
+    set_security_token(&mut client, 2, &client_nonce, &server_nonce);
+
+    // The server continues with the previous token, which the client still accepts
+    assert_eq!(send_secured_message(&server, &mut client, &message).unwrap(), 1);
+
+    // The client uses the new token straight away, after which the server uses it too
+    assert_eq!(send_secured_message(&client, &mut server, &message).unwrap(), 2);
+    assert_eq!(send_secured_message(&server, &mut client, &message).unwrap(), 2);
+
+    // Now both sides have switched over, the previous token is rejected
+    let (mut old_server, _) = make_secure_channels(MessageSecurityMode::SignAndEncrypt, SecurityPolicy::Basic256Sha256);
+    set_security_token(&mut old_server, 1, &[1u8; 32], &[0u8; 32]);
+    assert_eq!(send_secured_message(&old_server, &mut client, &message).unwrap_err(), StatusCode::BadSecureChannelTokenUnknown);
+}
+
+/// A forged message with the new token must not end the overlap period of a renewed token
+#[test]
+fn renew_security_token_keeps_previous_token_after_forged_message() {
+    let _ = Test::setup();
+
+    let message = make_sample_message();
+    let (mut server, mut client) = make_secure_channels(MessageSecurityMode::SignAndEncrypt, SecurityPolicy::Basic256Sha256);
+    client.set_role(Role::Client);
+    server.set_role(Role::Server);
+
+    let client_nonce = vec![0u8; 32];
+    let server_nonce = vec![1u8; 32];
+    set_security_token(&mut server, 1, &server_nonce, &client_nonce);
+    set_security_token(&mut client, 1, &client_nonce, &server_nonce);
+
+    let client_nonce = vec![2u8; 32];
+    let server_nonce = vec![3u8; 32];
+    set_security_token(&mut server, 2, &server_nonce, &client_nonce);
+    set_security_token(&mut client, 2, &client_nonce, &server_nonce);
+
+    // Tamper with a message secured with the new token so it fails verification
+    let chunks = Chunker::encode(1, 1, 0, 0, &client, &message).unwrap();
+    let mut encrypted_data = vec![0u8; chunks[0].data.len() + 4096];
+    let encrypted_size = client.apply_security(&chunks[0], &mut encrypted_data[..]).unwrap();
+    encrypted_data[encrypted_size - 1] ^= 0xff;
+    assert!(server.verify_and_remove_security(&encrypted_data[..encrypted_size]).is_err());
+
+    // The server still uses the previous token until a genuine message arrives with the new one
+    assert_eq!(send_secured_message(&server, &mut client, &message).unwrap(), 1);
+    assert_eq!(send_secured_message(&client, &mut server, &message).unwrap(), 2);
+    assert_eq!(send_secured_message(&server, &mut client, &message).unwrap(), 2);
+}
+
+/// The revised lifetime of a token is in milliseconds
+#[test]
+fn token_lifetime_is_milliseconds() {
+    let _ = Test::setup();
+
+    let mut secure_channel = SecureChannel::new_no_certificate_store();
+    let created_at: chrono::DateTime<chrono::Utc> = DateTime::now().into();
+
+    // A lifetime of 1500ms from 1s ago has not expired
+    secure_channel.set_security_token(ChannelSecurityToken {
+        channel_id: 1,
+        token_id: 1,
+        created_at: DateTime::from(created_at - chrono::Duration::seconds(1)),
+        revised_lifetime: 1500,
+    });
+    assert!(!secure_channel.token_has_expired());
+
+    // A lifetime of 1500ms from 2s ago has expired, where it would not if it were in seconds
+    secure_channel.set_security_token(ChannelSecurityToken {
+        channel_id: 1,
+        token_id: 1,
+        created_at: DateTime::from(created_at - chrono::Duration::seconds(2)),
+        revised_lifetime: 1500,
+    });
+    assert!(secure_channel.token_has_expired());
+}
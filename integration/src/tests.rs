@@ -497,3 +497,5 @@ fn connect_with(port_offset: u16, endpoint_id: &str) {
 
     perform_test(port_offset, Some(client_test), server_test);
 }
+
+mod soak;
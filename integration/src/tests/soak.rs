@@ -0,0 +1,505 @@
+//! Long running soak tests. These start a server and hammer it with many client sessions for a
+//! configurable period of time while injecting faults on the side - abrupt disconnects, slow
+//! sockets, malformed chunks and clients whose clocks are wrong. Throughout the run the sequence
+//! numbers of the chunks that the server sends on each secure channel must strictly increase. At
+//! the end the server must still be responsive and must have released every session, subscription
+//! and connection that was created during the run.
+//!
+//! The tests are ignored by default. Run them with something like:
+//!
+//! ```text
+//! OPCUA_SOAK_DURATION_SECS=3600 OPCUA_SOAK_CLIENTS=20 cargo test -- --ignored --test-threads=1 soak
+//! ```
+use std::{
+    env,
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use log::{info, warn, error};
+
+use opcua_core::trace_read_lock_unwrap;
+use opcua_types::tcp_types::HelloMessage;
+use opcua_server::prelude::*;
+use opcua_client::prelude::*;
+
+use super::*;
+
+/// Default duration of a soak run if `OPCUA_SOAK_DURATION_SECS` is not set
+const DEFAULT_SOAK_DURATION_SECS: u64 = 60;
+/// Default number of client threads if `OPCUA_SOAK_CLIENTS` is not set
+const DEFAULT_SOAK_CLIENTS: usize = 8;
+/// How long the server is given to clean up after the run before invariants are asserted
+const QUIESCE_TIMEOUT_SECS: u64 = 60;
+/// How long a lock may be held before the watchdog declares a deadlock
+const DEADLOCK_TIMEOUT_SECS: u64 = 30;
+/// How many requests the sequence number probe sends on a secure channel before opening another
+const PROBE_REQUESTS_PER_CHANNEL: usize = 50;
+/// How far the clock of a client is wrong for the clock skew faults
+const CLOCK_SKEW_HOURS: i64 = 6;
+
+/// Settings for a soak run, taken from the environment
+struct SoakConfig {
+    duration: Duration,
+    clients: usize,
+}
+
+impl SoakConfig {
+    fn from_env() -> SoakConfig {
+        let duration = env::var("OPCUA_SOAK_DURATION_SECS").ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_SOAK_DURATION_SECS);
+        let clients = env::var("OPCUA_SOAK_CLIENTS").ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_SOAK_CLIENTS);
+        SoakConfig {
+            duration: Duration::from_secs(duration),
+            clients,
+        }
+    }
+}
+
+/// Counters accumulated over the run, reported at the end
+#[derive(Default)]
+struct SoakStats {
+    sessions: AtomicUsize,
+    session_errors: AtomicUsize,
+    dropped_sessions: AtomicUsize,
+    faults: AtomicUsize,
+    /// Chunks whose sequence numbers were checked by the probe
+    sequence_numbers: AtomicUsize,
+}
+
+/// The kinds of fault that the injector cycles through
+#[derive(Debug, Copy, Clone)]
+enum Fault {
+    /// Connect and immediately drop the socket
+    ConnectionDrop,
+    /// Trickle a HELLO message out a byte at a time
+    SlowSocket,
+    /// Send a chunk with a valid looking header but garbage for a body
+    MalformedChunk,
+    /// Send bytes which are not an OPC UA message at all
+    Garbage,
+    /// Open a secure channel and send requests whose timestamps are this many hours ahead, or
+    /// behind if negative
+    ClockSkew(i64),
+}
+
+const FAULTS: [Fault; 6] = [Fault::ConnectionDrop, Fault::SlowSocket, Fault::MalformedChunk, Fault::Garbage, Fault::ClockSkew(CLOCK_SKEW_HOURS), Fault::ClockSkew(-CLOCK_SKEW_HOURS)];
+
+/// Run the server and clients for the configured duration while injecting faults, and then
+/// check that the server has cleaned up after itself.
+#[test]
+#[ignore]
+fn soak() {
+    opcua_console_logging::init();
+
+    let config = SoakConfig::from_env();
+    info!("Soak test will run for {} seconds with {} clients", config.duration.as_secs(), config.clients);
+
+    let port_offset = next_port_offset();
+    let server = Arc::new(RwLock::new(new_server(port_offset)));
+
+    let (server_state, connections) = {
+        let server = server.read().unwrap();
+        (server.server_state(), server.connections())
+    };
+
+    let server_thread = {
+        let server = server.clone();
+        thread::spawn(move || {
+            Server::run_server(server);
+            info!("Soak server has finished");
+        })
+    };
+
+    // Give the server a moment to start listening
+    thread::sleep(Duration::from_millis(1000));
+
+    let running = Arc::new(AtomicBool::new(true));
+    let stats = Arc::new(SoakStats::default());
+    let deadline = Instant::now() + config.duration;
+
+    // Watchdog periodically takes the server's locks. If it cannot get them for too long then
+    // something is almost certainly deadlocked.
+    let watchdog_thread = {
+        let running = running.clone();
+        let server_state = server_state.clone();
+        let connections = connections.clone();
+        thread::spawn(move || {
+            while running.load(Ordering::SeqCst) {
+                assert_lock_available("server state", || server_state.try_write().is_ok());
+                assert_lock_available("connections", || connections.try_write().is_ok());
+                thread::sleep(Duration::from_millis(500));
+            }
+        })
+    };
+
+    // Client threads repeatedly connect, read, subscribe and disconnect
+    let client_threads = (0..config.clients).map(|i| {
+        let running = running.clone();
+        let stats = stats.clone();
+        thread::spawn(move || {
+            let mut iteration = 0usize;
+            while running.load(Ordering::SeqCst) && Instant::now() < deadline {
+                iteration += 1;
+                // Every so often a client vanishes without closing its session so the server
+                // has to time the session out by itself
+                let drop_abruptly = (i + iteration) % 5 == 0;
+                match client_iteration(port_offset, drop_abruptly) {
+                    Ok(_) => {
+                        stats.sessions.fetch_add(1, Ordering::SeqCst);
+                        if drop_abruptly {
+                            stats.dropped_sessions.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+                    Err(err) => {
+                        warn!("Soak client {} iteration {} failed, error = {}", i, iteration, err);
+                        stats.session_errors.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    // Probe opens secure channels of its own and checks the sequence numbers of every chunk that
+    // the server sends on them while the clients and faults are running
+    let probe_thread = {
+        let running = running.clone();
+        let stats = stats.clone();
+        thread::spawn(move || {
+            while running.load(Ordering::SeqCst) && Instant::now() < deadline {
+                match probe_sequence_numbers(port_offset, deadline) {
+                    Ok(checked) => {
+                        stats.sequence_numbers.fetch_add(checked, Ordering::SeqCst);
+                    }
+                    Err(err) => {
+                        warn!("Sequence number probe failed, error = {}", err);
+                        thread::sleep(Duration::from_millis(250));
+                    }
+                }
+            }
+        })
+    };
+
+    // Fault injector cycles through faults against the same port
+    let fault_thread = {
+        let running = running.clone();
+        let stats = stats.clone();
+        thread::spawn(move || {
+            let mut i = 0;
+            while running.load(Ordering::SeqCst) && Instant::now() < deadline {
+                let fault = FAULTS[i % FAULTS.len()];
+                i += 1;
+                if let Err(err) = inject_fault(port_offset, fault) {
+                    // The server is allowed to slam the door, that's the point
+                    info!("Fault {:?} ended with {:?}", fault, err);
+                }
+                stats.faults.fetch_add(1, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(250));
+            }
+        })
+    };
+
+    for t in client_threads {
+        t.join().expect("Soak client thread panicked");
+    }
+    fault_thread.join().expect("Fault injector thread panicked");
+    probe_thread.join().expect("Sequence number probe thread panicked");
+
+    info!("Soak run complete, sessions = {}, dropped = {}, errors = {}, faults = {}, sequence numbers checked = {}",
+          stats.sessions.load(Ordering::SeqCst), stats.dropped_sessions.load(Ordering::SeqCst),
+          stats.session_errors.load(Ordering::SeqCst), stats.faults.load(Ordering::SeqCst),
+          stats.sequence_numbers.load(Ordering::SeqCst));
+
+    // The server must still be serving requests after all of that
+    client_iteration(port_offset, false).expect("Server is no longer responsive after soak run");
+
+    // Wait for the server to expire abandoned sessions and connections
+    let start = Instant::now();
+    loop {
+        let (session_count, subscription_count) = {
+            let server_state = trace_read_lock_unwrap!(server_state);
+            let diagnostics = trace_read_lock_unwrap!(server_state.diagnostics);
+            let summary = diagnostics.server_diagnostics_summary();
+            (summary.current_session_count, summary.current_subscription_count)
+        };
+        let connection_count = connections.read().unwrap().len();
+        if session_count == 0 && subscription_count == 0 && connection_count == 0 {
+            break;
+        }
+        if start.elapsed() > Duration::from_secs(QUIESCE_TIMEOUT_SECS) {
+            panic!("Server leaked resources after soak run, sessions = {}, subscriptions = {}, connections = {}",
+                   session_count, subscription_count, connection_count);
+        }
+        thread::sleep(Duration::from_millis(1000));
+    }
+
+    running.store(false, Ordering::SeqCst);
+    let _ = watchdog_thread.join();
+
+    {
+        let mut server = server.write().unwrap();
+        server.abort();
+    }
+    let _ = server_thread.join();
+
+    assert!(stats.sessions.load(Ordering::SeqCst) > 0, "No client session succeeded during soak run");
+    assert!(stats.sequence_numbers.load(Ordering::SeqCst) > 0, "No sequence numbers were checked during soak run");
+}
+
+/// Polls the lock function until it succeeds, panicking if it does not within the deadlock timeout
+fn assert_lock_available<F>(name: &str, f: F) where F: Fn() -> bool {
+    let start = Instant::now();
+    while !f() {
+        if start.elapsed() > Duration::from_secs(DEADLOCK_TIMEOUT_SECS) {
+            panic!("Could not acquire {} lock for {} seconds, server is probably deadlocked", name, DEADLOCK_TIMEOUT_SECS);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// One client lifetime - connect, read a value, create a subscription with a monitored item,
+/// wait for a few publish cycles and disconnect. If `drop_abruptly` is set the session is
+/// abandoned without being closed.
+fn client_iteration(port_offset: u16, drop_abruptly: bool) -> Result<(), StatusCode> {
+    let mut client = new_client(port_offset);
+    let session = client.connect_to_endpoint_id(Some(ENDPOINT_ID_NONE))?;
+    {
+        let mut session = session.write().unwrap();
+
+        let values = session.read(&[ReadValueId::from(v1_node_id())])?.unwrap_or_default();
+        if values.len() != 1 || values[0].value != Some(Variant::from(100)) {
+            error!("Soak client read an unexpected value {:?}", values);
+            return Err(StatusCode::BadUnexpectedError);
+        }
+
+        let subscription_id = session.create_subscription(100f64, 30, 10, 0, 0, true, DataChangeCallback::new(|_| {}))?;
+        let items = [MonitoredItemCreateRequest::new(v1_node_id().into(), MonitoringMode::Reporting, MonitoringParameters::default())];
+        let _ = session.create_monitored_items(subscription_id, TimestampsToReturn::Both, &items)?;
+    }
+
+    // Let the session publish for a little while
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(500) {
+        let mut session = session.write().unwrap();
+        let _ = session.poll(10);
+    }
+
+    if !drop_abruptly {
+        let mut session = session.write().unwrap();
+        let _ = session.delete_all_subscriptions();
+        session.disconnect();
+    }
+    Ok(())
+}
+
+/// Opens a raw socket to the server and misbehaves on it in the manner described by the fault
+fn inject_fault(port_offset: u16, fault: Fault) -> std::io::Result<()> {
+    let mut stream = TcpStream::connect(("127.0.0.1", 4855 + port_offset))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+    match fault {
+        Fault::ConnectionDrop => {
+            // Dropping the stream closes it
+        }
+        Fault::SlowSocket => {
+            for b in hello_message(port_offset).iter() {
+                stream.write_all(&[*b])?;
+                thread::sleep(Duration::from_millis(50));
+            }
+        }
+        Fault::MalformedChunk => {
+            // "MSGF" header claiming a size far larger than what follows
+            let mut chunk = b"MSGF".to_vec();
+            chunk.extend_from_slice(&1000u32.to_le_bytes());
+            chunk.extend_from_slice(&[0xffu8; 64]);
+            stream.write_all(&chunk)?;
+        }
+        Fault::Garbage => {
+            stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+        }
+        Fault::ClockSkew(hours) => {
+            let mut channel = RawSecureChannel::open(stream, port_offset, chrono::Duration::hours(hours))
+                .map_err(|status_code| io::Error::new(io::ErrorKind::Other, format!("{}", status_code)))?;
+            for _ in 0..5 {
+                channel.get_endpoints(port_offset)
+                    .map_err(|status_code| io::Error::new(io::ErrorKind::Other, format!("{}", status_code)))?;
+            }
+            return channel.close();
+        }
+    }
+    stream.flush()
+}
+
+/// Opens a secure channel, sends requests on it until the deadline or until enough have been
+/// sent, and returns the number of chunks whose sequence numbers were checked
+fn probe_sequence_numbers(port_offset: u16, deadline: Instant) -> Result<usize, StatusCode> {
+    let stream = TcpStream::connect(("127.0.0.1", 4855 + port_offset)).map_err(|_| StatusCode::BadCommunicationError)?;
+    let mut channel = RawSecureChannel::open(stream, port_offset, chrono::Duration::zero())?;
+    for _ in 0..PROBE_REQUESTS_PER_CHANNEL {
+        if Instant::now() >= deadline {
+            break;
+        }
+        channel.get_endpoints(port_offset)?;
+        thread::sleep(Duration::from_millis(100));
+    }
+    let checked = channel.chunks_received;
+    let _ = channel.close();
+    Ok(checked)
+}
+
+/// A secure channel with no security opened on a raw socket. Every chunk that the server sends on
+/// it must have a higher sequence number than the one before, otherwise the test panics.
+struct RawSecureChannel {
+    stream: TcpStream,
+    secure_channel: SecureChannel,
+    /// Added to the timestamps of requests to simulate a client whose clock is wrong
+    clock_skew: chrono::Duration,
+    last_sent_sequence_number: u32,
+    last_request_id: u32,
+    /// Sequence number of the last chunk received from the server
+    last_received_sequence_number: Option<u32>,
+    chunks_received: usize,
+}
+
+impl RawSecureChannel {
+    /// Sends HELLO on the stream and issues a secure channel
+    fn open(mut stream: TcpStream, port_offset: u16, clock_skew: chrono::Duration) -> Result<RawSecureChannel, StatusCode> {
+        stream.set_read_timeout(Some(Duration::from_secs(10))).map_err(|_| StatusCode::BadCommunicationError)?;
+        stream.set_write_timeout(Some(Duration::from_secs(5))).map_err(|_| StatusCode::BadCommunicationError)?;
+        stream.write_all(&hello_message(port_offset)).map_err(|_| StatusCode::BadCommunicationError)?;
+        let acknowledge = read_message(&mut stream)?;
+        if &acknowledge[0..3] != b"ACK" {
+            error!("Expected an acknowledge message in reply to hello");
+            return Err(StatusCode::BadCommunicationError);
+        }
+
+        let mut channel = RawSecureChannel {
+            stream,
+            secure_channel: (SecurityPolicy::None, MessageSecurityMode::None).into(),
+            clock_skew,
+            last_sent_sequence_number: 0,
+            last_request_id: 0,
+            last_received_sequence_number: None,
+            chunks_received: 0,
+        };
+        let request = OpenSecureChannelRequest {
+            request_header: channel.request_header(),
+            client_protocol_version: 0,
+            request_type: SecurityTokenRequestType::Issue,
+            security_mode: MessageSecurityMode::None,
+            client_nonce: ByteString::null(),
+            requested_lifetime: 60000,
+        };
+        match channel.send_request(request.into())? {
+            SupportedMessage::OpenSecureChannelResponse(response) => {
+                channel.secure_channel.set_security_token(response.security_token);
+                Ok(channel)
+            }
+            response => {
+                error!("Open secure channel failed, response = {:?}", response);
+                Err(StatusCode::BadUnexpectedError)
+            }
+        }
+    }
+
+    fn request_header(&mut self) -> RequestHeader {
+        self.last_request_id += 1;
+        let timestamp = DateTime::from(Utc::now() + self.clock_skew);
+        RequestHeader::new(&NodeId::null(), &timestamp, self.last_request_id)
+    }
+
+    fn get_endpoints(&mut self, port_offset: u16) -> Result<(), StatusCode> {
+        let request = GetEndpointsRequest {
+            request_header: self.request_header(),
+            endpoint_url: UAString::from(endpoint_url(port_offset)),
+            locale_ids: None,
+            profile_uris: None,
+        };
+        match self.send_request(request.into())? {
+            SupportedMessage::GetEndpointsResponse(_) => Ok(()),
+            SupportedMessage::ServiceFault(fault) => Err(fault.response_header.service_result),
+            _ => Err(StatusCode::BadUnexpectedError)
+        }
+    }
+
+    /// Closes the secure channel, which the server does not reply to
+    fn close(mut self) -> io::Result<()> {
+        let request = CloseSecureChannelRequest {
+            request_header: self.request_header(),
+        };
+        self.write_message(&request.into())
+            .map_err(|status_code| io::Error::new(io::ErrorKind::Other, format!("{}", status_code)))?;
+        self.stream.flush()
+    }
+
+    fn write_message(&mut self, message: &SupportedMessage) -> Result<(), StatusCode> {
+        let request_id = self.last_request_id;
+        let chunks = Chunker::encode(self.last_sent_sequence_number + 1, request_id, 0, 0, &self.secure_channel, message)?;
+        self.last_sent_sequence_number += chunks.len() as u32;
+        for chunk in chunks {
+            let mut data = vec![0u8; chunk.data.len() + 1024];
+            let size = self.secure_channel.apply_security(&chunk, &mut data)?;
+            self.stream.write_all(&data[..size]).map_err(|_| StatusCode::BadCommunicationError)?;
+        }
+        Ok(())
+    }
+
+    /// Sends the request and returns its response, checking the sequence number of every chunk
+    /// of the response
+    fn send_request(&mut self, request: SupportedMessage) -> Result<SupportedMessage, StatusCode> {
+        self.write_message(&request)?;
+        let mut chunks = Vec::new();
+        loop {
+            let data = read_message(&mut self.stream)?;
+            if &data[0..3] == b"ERR" {
+                return Err(StatusCode::BadCommunicationError);
+            }
+            let chunk = self.secure_channel.verify_and_remove_security(data)?;
+            let chunk_info = chunk.chunk_info(&self.secure_channel)?;
+            let sequence_number = chunk_info.sequence_header.sequence_number;
+            if let Some(last_received_sequence_number) = self.last_received_sequence_number {
+                assert!(sequence_number > last_received_sequence_number,
+                        "Sequence number {} on secure channel {} does not follow the previous sequence number {}",
+                        sequence_number, self.secure_channel.secure_channel_id(), last_received_sequence_number);
+            }
+            self.last_received_sequence_number = Some(sequence_number);
+            self.chunks_received += 1;
+            let is_final = chunk_info.message_header.is_final;
+            chunks.push(chunk);
+            if is_final != MessageIsFinalType::Intermediate {
+                break;
+            }
+        }
+        Chunker::decode(&chunks, &self.secure_channel, None)
+    }
+}
+
+/// Reads one whole message or chunk from the stream, using the size in its header
+fn read_message(stream: &mut TcpStream) -> Result<Vec<u8>, StatusCode> {
+    let mut data = vec![0u8; 8];
+    stream.read_exact(&mut data).map_err(|_| StatusCode::BadCommunicationError)?;
+    let message_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    if message_size < 8 {
+        return Err(StatusCode::BadCommunicationError);
+    }
+    data.resize(message_size, 0u8);
+    stream.read_exact(&mut data[8..]).map_err(|_| StatusCode::BadCommunicationError)?;
+    Ok(data)
+}
+
+/// Makes the bytes of a well formed HELLO message for the slow socket fault
+fn hello_message(port_offset: u16) -> Vec<u8> {
+    let hello = HelloMessage::new(&endpoint_url(port_offset), 8196, 8196, 0);
+    let mut stream = std::io::Cursor::new(Vec::with_capacity(hello.byte_len()));
+    let _ = hello.encode(&mut stream);
+    stream.into_inner()
+}
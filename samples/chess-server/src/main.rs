@@ -56,7 +56,7 @@ fn main() {
         }
 
         let game = game.lock().unwrap();
-        update_board_state(&game, &mut address_space);
+        update_board_state(&game, &address_space);
     }
 
     // Spawn a thread for the game which will update server state
@@ -86,8 +86,8 @@ fn main() {
                 game.print_board();
 
                 {
-                    let mut address_space = address_space.write().unwrap();
-                    update_board_state(&game, &mut address_space);
+                    let address_space = address_space.read().unwrap();
+                    update_board_state(&game, &address_space);
                 }
             }
 
@@ -99,7 +99,7 @@ fn main() {
     server.run();
 }
 
-fn update_board_state(game: &game::Game, address_space: &mut AddressSpace) {
+fn update_board_state(game: &game::Game, address_space: &AddressSpace) {
     for square in BOARD_SQUARES.iter() {
         // Piece on the square
         let square_value = game.square_from_str(square);
@@ -187,9 +187,13 @@ fn add_control_switches(server: &mut Server) {
             .add_folder("Control", "Control", &AddressSpace::objects_folder_id())
             .unwrap();
 
-        let mut variable = Variable::new(&abort_node_id, "Abort", "Abort",Variant::Boolean(false));
-        variable.set_writable(true);
-        let _ = address_space.add_variable(variable, &folder_id);
+        let _ = VariableBuilder::new(&abort_node_id)
+            .browse_name("Abort")
+            .display_name("Abort")
+            .value(false)
+            .writable()
+            .organized_by(&folder_id)
+            .insert(&mut address_space);
     }
 
     server.add_polling_action(1000, move || {
@@ -291,7 +295,7 @@ fn set_dynamic_timers(server: &mut Server) {
 
     // Standard change timers
     server.add_polling_action(250, move || {
-        let mut address_space = address_space.write().unwrap();
+        let address_space = address_space.read().unwrap();
         // Scalar
         let now = DateTime::now();
         Scalar::values().iter().for_each(|sn| {
@@ -328,7 +332,7 @@ fn set_stress_timer(server: &mut Server, node_ids: Vec<NodeId>) {
     let address_space = server.address_space();
     server.add_polling_action(100, move || {
         let mut rng = rand::thread_rng();
-        let mut address_space = address_space.write().unwrap();
+        let address_space = address_space.read().unwrap();
         let now = DateTime::now();
         node_ids.iter().for_each(|node_id| {
             let value: Variant = rng.gen::<i32>().into();
@@ -25,7 +25,7 @@ fn main() {
 
     // The client API has a simple `find_servers` function that connects and returns servers for us.
     let mut client = Client::new(ClientConfig::new("DiscoveryClient", "urn:DiscoveryClient"));
-    match client.find_servers(url) {
+    match client.find_servers(url.clone()) {
         Ok(servers) => {
             println!("Discovery server responded with {} servers:", servers.len());
             servers.iter().for_each(|server| {
@@ -42,6 +42,23 @@ fn main() {
             println!("ERROR: Cannot find servers on discovery server - check this error - {:?}", err);
         }
     }
+
+    // Discovery servers supporting multicast also know about servers seen on the network
+    match client.find_servers_on_network(url, 0, 0, None) {
+        Ok((_, servers)) => {
+            println!("Discovery server has seen {} servers on the network:", servers.len());
+            servers.iter().for_each(|server| {
+                println!("Server : {} - {}", server.server_name, server.discovery_url);
+                if let Some(ref server_capabilities) = server.server_capabilities {
+                    let server_capabilities = server_capabilities.iter().map(|c| c.as_ref()).collect::<Vec<&str>>();
+                    println!("  Capabilities : {}", server_capabilities.join(", "));
+                }
+            });
+        }
+        Err(err) => {
+            println!("ERROR: Cannot find servers on network from discovery server - check this error - {:?}", err);
+        }
+    }
 }
 
 fn print_server_endpoints(discovery_url: &str) {
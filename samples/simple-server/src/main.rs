@@ -40,46 +40,55 @@ fn add_example_variables(server: &mut Server) {
             .add_folder("Sample", "Sample", &AddressSpace::objects_folder_id())
             .unwrap();
 
-        // Add some variables to our sample folder. Values will be overwritten by the timer
-        let _ = address_space.add_variables(
-            vec![Variable::new(&v1_node, "v1", "v1", 0 as i32),
-                 Variable::new(&v2_node, "v2", "v2", false),
-                 Variable::new(&v3_node, "v3", "v3", UAString::from("")),
-                 Variable::new(&v4_node, "v4", "v4", 0f64)],
-            &sample_folder_id);
-    }
+        // Add some variables to our sample folder. Values of v1 & v2 will be overwritten by the timer
+        let _ = VariableBuilder::new(&v1_node)
+            .browse_name("v1")
+            .display_name("v1")
+            .value(0 as i32)
+            .organized_by(&sample_folder_id)
+            .insert(&mut address_space);
+        let _ = VariableBuilder::new(&v2_node)
+            .browse_name("v2")
+            .display_name("v2")
+            .value(false)
+            .organized_by(&sample_folder_id)
+            .insert(&mut address_space);
 
-    // OPC UA for Rust allows you to push or pull values from a variable so here are examples
-    // of each method.
+        // OPC UA for Rust allows you to push or pull values from a variable so here are examples
+        // of each method.
 
+        // 1) Pull. This code gives v3 & v4 getters that return their values by calling a function.
 
-    // 1) Pull. This code will add getters to v3 & v4 that returns their values by calling
-    //    function.
-    {
-        let address_space = server.address_space();
-        let mut address_space = address_space.write().unwrap();
-        if let Some(ref mut v) = address_space.find_variable_mut(v3_node.clone()) {
-            // Hello world's counter will increment with each get - slower interval == slower increment
-            let mut counter = 0;
-            let getter = AttrFnGetter::new(move |_, _, _| -> Result<Option<DataValue>, StatusCode> {
-                counter += 1;
-                Ok(Some(DataValue::new(UAString::from(format!("Hello World times {}", counter)))))
-            });
-            v.set_value_getter(Arc::new(Mutex::new(getter)));
-        }
+        // Hello world's counter will increment with each get - slower interval == slower increment
+        let mut counter = 0;
+        let getter = AttrFnGetter::new(move |_, _, _| -> Result<Option<DataValue>, StatusCode> {
+            counter += 1;
+            Ok(Some(DataValue::new(UAString::from(format!("Hello World times {}", counter)))))
+        });
+        let _ = VariableBuilder::new(&v3_node)
+            .browse_name("v3")
+            .display_name("v3")
+            .value(UAString::from(""))
+            .value_getter(Arc::new(Mutex::new(getter)))
+            .organized_by(&sample_folder_id)
+            .insert(&mut address_space);
 
-        if let Some(ref mut v) = address_space.find_variable_mut(v4_node.clone()) {
-            // Sine wave draws 2*PI over course of 10 seconds
-            use std::f64::consts;
-            use chrono::Utc;
-            let start_time = Utc::now();
-            let getter = AttrFnGetter::new(move |_, _, _| -> Result<Option<DataValue>, StatusCode> {
-                let elapsed = Utc::now().signed_duration_since(start_time).num_milliseconds();
-                let moment = (elapsed % 10000) as f64 / 10000.0;
-                Ok(Some(DataValue::new((2.0 * consts::PI * moment).sin())))
-            });
-            v.set_value_getter(Arc::new(Mutex::new(getter)));
-        }
+        // Sine wave draws 2*PI over course of 10 seconds
+        use std::f64::consts;
+        use chrono::Utc;
+        let start_time = Utc::now();
+        let getter = AttrFnGetter::new(move |_, _, _| -> Result<Option<DataValue>, StatusCode> {
+            let elapsed = Utc::now().signed_duration_since(start_time).num_milliseconds();
+            let moment = (elapsed % 10000) as f64 / 10000.0;
+            Ok(Some(DataValue::new((2.0 * consts::PI * moment).sin())))
+        });
+        let _ = VariableBuilder::new(&v4_node)
+            .browse_name("v4")
+            .display_name("v4")
+            .value(0f64)
+            .value_getter(Arc::new(Mutex::new(getter)))
+            .organized_by(&sample_folder_id)
+            .insert(&mut address_space);
     }
 
     // 2) Push. This code will use a timer to set the values on variable v1 & v2 on an interval.
@@ -92,7 +101,7 @@ fn add_example_variables(server: &mut Server) {
             let mut data = data.lock().unwrap();
             data.0 += 1;
             data.1 = !data.1;
-            let mut address_space = address_space.write().unwrap();
+            let address_space = address_space.read().unwrap();
             let now = DateTime::now();
             let _ = address_space.set_variable_value(v1_node.clone(), data.0 as i32, &now, &now);
             let _ = address_space.set_variable_value(v2_node.clone(), data.1, &now, &now);
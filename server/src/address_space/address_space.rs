@@ -1,28 +1,37 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::{Arc, Mutex, RwLock};
 
 use chrono::Utc;
+use futures::IntoFuture;
 
 use opcua_types::{
     *,
     node_ids::*,
     status_code::StatusCode,
-    service_types::{CallMethodRequest, CallMethodResult, BrowseDirection, NodeClass},
+    service_types::{CallMethodRequest, CallMethodResult, BrowseDirection, NodeClass, Range, ModelChangeStructureDataType},
+    type_registry::{DecodedObject, TYPE_REGISTRY},
 };
 
 use crate::{
     address_space::{
-        AttrFnGetter,
+        AttrFnGetter, AttrFnAsyncGetter, AttrFnAsyncSetter,
         node::{Node, NodeType, HasNodeId},
+        data_type::DataType,
         object::Object,
+        method::Method,
         variable::Variable,
         references::{References, Reference, ReferenceDirection},
+        nodeset_export,
+        instantiation,
     },
     diagnostics::ServerDiagnostics,
+    events::{event::Event, condition::Condition, model_change::{self, MODEL_CHANGE_NODE_ADDED, MODEL_CHANGE_NODE_DELETED, MODEL_CHANGE_REFERENCE_ADDED, MODEL_CHANGE_REFERENCE_DELETED}},
+    file_transfer::{self, File, FileNodeIds, FileProvider},
     state::ServerState,
     session::Session,
     callbacks,
     constants,
+    roles,
 };
 
 /// Searches for the specified node by type, expecting it to exist
@@ -86,25 +95,55 @@ macro_rules! server_diagnostics_summary {
 
 type MethodCallback = Box<callbacks::Method + Send + Sync>;
 
+type ValueProvider = Box<dyn callbacks::NodeValueProvider + Send>;
+
 #[derive(PartialEq, Eq, Clone, Debug, Hash)]
 struct MethodKey {
     object_id: NodeId,
     method_id: NodeId,
 }
 
+/// The events raised on the address space, oldest to newest. Each event is numbered so that
+/// monitored items can tell which events they have already collected.
+#[derive(Default)]
+struct RaisedEvents {
+    events: VecDeque<(u64, Event)>,
+    /// The number of the most recently raised event
+    last_event_number: u64,
+}
+
 /// The address space holds references between nodes. It is populated with some standard nodes
 /// and any that the server implementation chooses to add for itself.
 pub struct AddressSpace {
-    /// A map of all the nodes that are part of the address space
-    node_map: HashMap<NodeId, NodeType>,
+    /// A map of all the nodes that are part of the address space. Nodes are shared by the
+    /// snapshots of the address space until one is modified, see `SharedAddressSpace`.
+    node_map: HashMap<NodeId, Arc<NodeType>>,
     /// The references between nodes
     references: References,
     /// This is the last time that nodes or references to nodes were added or removed from the address space.
     last_modified: DateTimeUtc,
     /// Method handlers
-    method_handlers: HashMap<MethodKey, MethodCallback>,
+    method_handlers: HashMap<MethodKey, Arc<Mutex<MethodCallback>>>,
     /// Access to server diagnostics
     server_diagnostics: Option<Arc<RwLock<ServerDiagnostics>>>,
+    /// Indicates if the extended (non-core) standard nodes have been added to the address space.
+    /// This is false when the `trimmed-address-space` feature defers them.
+    extended_nodes_populated: bool,
+    /// Recently raised events. They have a lock of their own so that events can be raised while
+    /// the address space is only locked for reading.
+    events: Arc<RwLock<RaisedEvents>>,
+    /// Conditions and alarms, keyed by the node id of the condition
+    conditions: HashMap<NodeId, Condition>,
+    /// The namespace URIs known to the server. The position of a URI is its namespace index and
+    /// the list is published as the value of the server's NamespaceArray.
+    namespaces: Vec<String>,
+    /// Providers of the values of nodes which are read in batches
+    value_providers: Vec<Arc<Mutex<ValueProvider>>>,
+    /// The index of the value provider of each node which has one
+    value_provider_nodes: HashMap<NodeId, usize>,
+    /// Indicates if adding or removing nodes and references raises model change events and
+    /// increments the `NodeVersion` property of the affected nodes
+    model_change_events: bool,
 }
 
 impl AddressSpace {
@@ -118,16 +157,156 @@ impl AddressSpace {
             last_modified: Utc::now(),
             method_handlers: HashMap::new(),
             server_diagnostics: None,
+            extended_nodes_populated: false,
+            events: Arc::new(RwLock::new(RaisedEvents::default())),
+            conditions: HashMap::new(),
+            namespaces: vec![constants::OPC_UA_NAMESPACE_URI.to_string(), constants::INTERNAL_NAMESPACE_URI.to_string()],
+            value_providers: Vec::new(),
+            value_provider_nodes: HashMap::new(),
+            model_change_events: false,
         };
         address_space.add_default_nodes();
+        address_space.update_namespace_array();
         address_space
     }
 
+    /// Copies the address space into a new snapshot which can be modified without changing this
+    /// one. Nodes are only copied when they are modified, and the values of variables, raised
+    /// events, method handlers and value providers are shared by both snapshots.
+    pub(crate) fn copy_for_snapshot(&self) -> AddressSpace {
+        AddressSpace {
+            node_map: self.node_map.clone(),
+            references: self.references.clone(),
+            last_modified: self.last_modified,
+            method_handlers: self.method_handlers.clone(),
+            server_diagnostics: self.server_diagnostics.clone(),
+            extended_nodes_populated: self.extended_nodes_populated,
+            events: self.events.clone(),
+            conditions: self.conditions.clone(),
+            namespaces: self.namespaces.clone(),
+            value_providers: self.value_providers.clone(),
+            value_provider_nodes: self.value_provider_nodes.clone(),
+            model_change_events: self.model_change_events,
+        }
+    }
+
     /// Returns the last modified date for the address space
     pub fn last_modified(&self) -> DateTimeUtc {
         self.last_modified.clone()
     }
 
+    /// Returns the namespace URIs known to the address space, ordered by namespace index.
+    pub fn namespaces(&self) -> &[String] {
+        &self.namespaces
+    }
+
+    /// Returns the index of the namespace with the supplied URI, if it has been registered.
+    pub fn namespace_index(&self, namespace: &str) -> Option<u16> {
+        self.namespaces.iter().position(|ns| ns == namespace).map(|idx| idx as u16)
+    }
+
+    /// Registers a namespace URI and returns its namespace index, which should be used for the
+    /// node ids and browse names of nodes in that namespace. Registering a URI that is already
+    /// known returns its existing index. The NamespaceArray variable is updated to include the
+    /// new URI.
+    pub fn register_namespace(&mut self, namespace: &str) -> Result<u16, ()> {
+        if namespace.is_empty() {
+            error!("Cannot register an empty namespace URI");
+            Err(())
+        } else if let Some(idx) = self.namespace_index(namespace) {
+            Ok(idx)
+        } else if self.namespaces.len() > u16::max_value() as usize {
+            error!("Cannot register namespace {}, the namespace table is full", namespace);
+            Err(())
+        } else {
+            self.namespaces.push(namespace.to_string());
+            self.update_namespace_array();
+            Ok((self.namespaces.len() - 1) as u16)
+        }
+    }
+
+    /// Registers the namespaces of an external model, e.g. one being imported from a nodeset, and
+    /// returns a map from each model namespace index to the index it has in this address space.
+    /// The model's namespace index 0 is always the OPC UA namespace. Model namespace index `n`
+    /// corresponds to `model_namespaces[n - 1]`, mirroring the NamespaceUris table of a nodeset.
+    ///
+    /// Node ids and browse names from the model should be passed through the map before they are
+    /// inserted, e.g. with [`remap_node_id`].
+    ///
+    /// [`remap_node_id`]: #method.remap_node_id
+    pub fn import_namespaces(&mut self, model_namespaces: &[&str]) -> Result<HashMap<u16, u16>, ()> {
+        let mut namespace_map = HashMap::with_capacity(model_namespaces.len() + 1);
+        namespace_map.insert(0, 0);
+        for (idx, namespace) in model_namespaces.iter().enumerate() {
+            let model_idx = idx + 1;
+            if model_idx > u16::max_value() as usize {
+                error!("Model has too many namespaces");
+                return Err(());
+            }
+            let server_idx = self.register_namespace(namespace)?;
+            namespace_map.insert(model_idx as u16, server_idx);
+        }
+        Ok(namespace_map)
+    }
+
+    /// Remaps the namespace index of a node id from an external model using a map obtained from
+    /// [`import_namespaces`]. Fails if the node id's namespace is not in the map.
+    ///
+    /// [`import_namespaces`]: #method.import_namespaces
+    pub fn remap_node_id(namespace_map: &HashMap<u16, u16>, node_id: &NodeId) -> Result<NodeId, StatusCode> {
+        if let Some(namespace) = namespace_map.get(&node_id.namespace) {
+            let mut node_id = node_id.clone();
+            node_id.namespace = *namespace;
+            Ok(node_id)
+        } else {
+            error!("Node id {:?} has a namespace that is not in the namespace map", node_id);
+            Err(StatusCode::BadNodeIdInvalid)
+        }
+    }
+
+    /// Remaps the namespace index of a browse name from an external model using a map obtained
+    /// from [`import_namespaces`]. Fails if the browse name's namespace is not in the map.
+    ///
+    /// [`import_namespaces`]: #method.import_namespaces
+    pub fn remap_browse_name(namespace_map: &HashMap<u16, u16>, browse_name: &QualifiedName) -> Result<QualifiedName, StatusCode> {
+        if let Some(namespace) = namespace_map.get(&browse_name.namespace_index) {
+            Ok(QualifiedName::new(*namespace, browse_name.name.as_ref()))
+        } else {
+            error!("Browse name {:?} has a namespace that is not in the namespace map", browse_name);
+            Err(StatusCode::BadBrowseNameInvalid)
+        }
+    }
+
+    /// Writes the namespace table to the NamespaceArray variable, if the address space has one.
+    fn update_namespace_array(&mut self) {
+        let now = DateTime::now();
+        let namespaces = self.namespaces.clone();
+        self.set_variable_value(VariableId::Server_NamespaceArray, namespaces, &now, &now);
+    }
+
+    /// Exports nodes as a UANodeSet2 XML document that can be loaded by modelling tools and other
+    /// OPC UA stacks. Only nodes in the supplied namespaces are exported, or if `None`, all nodes
+    /// except those of the OPC UA namespace. Namespace indices in the document refer to its own
+    /// NamespaceUris table and nodes are written in node id order.
+    pub fn export_nodeset(&self, namespaces: Option<&[u16]>) -> String {
+        let mut nodes = self.node_map.values()
+            .map(|node| node.as_ref())
+            .filter(|node| {
+                let namespace = node.node_id().namespace;
+                if let Some(namespaces) = namespaces {
+                    namespaces.contains(&namespace)
+                } else {
+                    namespace != 0
+                }
+            })
+            .collect::<Vec<&NodeType>>();
+        nodes.sort_by_key(|node| {
+            let node_id = node.node_id();
+            (node_id.namespace, node_id.to_string())
+        });
+        nodeset_export::export_nodeset(self, &nodes)
+    }
+
     /// Sets the getter for a variable node
     pub fn set_variable_getter<N, F>(&mut self, variable_id: N, getter: F) where
         N: Into<NodeId>,
@@ -139,6 +318,34 @@ impl AddressSpace {
         }
     }
 
+    /// Sets the async getter for a variable node. The getter returns a future, or anything else
+    /// which turns into one, that resolves to the value.
+    pub fn set_variable_async_getter<N, F, R>(&mut self, variable_id: N, getter: F) where
+        N: Into<NodeId>,
+        F: FnMut(&NodeId, AttributeId, f64) -> R + Send + 'static,
+        R: IntoFuture<Item=Option<DataValue>, Error=StatusCode>,
+        R::Future: Send + 'static
+    {
+        if let Some(ref mut v) = self.find_variable_mut(variable_id) {
+            let getter = AttrFnAsyncGetter::new(getter);
+            v.set_value_async_getter(Arc::new(Mutex::new(getter)));
+        }
+    }
+
+    /// Sets the async setter for a variable node. The setter returns a future, or anything else
+    /// which turns into one, that resolves once the value is set.
+    pub fn set_variable_async_setter<N, F, R>(&mut self, variable_id: N, setter: F) where
+        N: Into<NodeId>,
+        F: FnMut(&NodeId, AttributeId, DataValue) -> R + Send + 'static,
+        R: IntoFuture<Item=(), Error=StatusCode>,
+        R::Future: Send + 'static
+    {
+        if let Some(ref mut v) = self.find_variable_mut(variable_id) {
+            let setter = AttrFnAsyncSetter::new(setter);
+            v.set_value_async_setter(Arc::new(Mutex::new(setter)));
+        }
+    }
+
     /// Sets values for nodes representing the server.
     pub fn set_server_state(&mut self, server_state: Arc<RwLock<ServerState>>) {
         // Server state requires the generated address space, otherwise nothing
@@ -150,9 +357,6 @@ impl AddressSpace {
             // Server variables
             {
                 let server_state = trace_read_lock_unwrap!(server_state);
-                if let Some(ref mut v) = self.find_variable_mut(Server_NamespaceArray) {
-                    v.set_value_direct(Variant::from(&server_state.namespaces), &now, &now);
-                }
                 if let Some(ref mut v) = self.find_variable_mut(Server_ServerArray) {
                     v.set_value_direct(Variant::from(&server_state.servers), &now, &now);
                 }
@@ -165,10 +369,23 @@ impl AddressSpace {
                 self.set_variable_value(Server_ServerCapabilities_MaxArrayLength, server_config.max_array_length as u32, &now, &now);
                 self.set_variable_value(Server_ServerCapabilities_MaxStringLength, server_config.max_string_length as u32, &now, &now);
                 self.set_variable_value(Server_ServerCapabilities_MaxByteStringLength, server_config.max_byte_string_length as u32, &now, &now);
-                self.set_variable_value(Server_ServerCapabilities_MaxBrowseContinuationPoints, constants::MAX_BROWSE_CONTINUATION_POINTS as u32, &now, &now);
+                self.set_variable_value(Server_ServerCapabilities_MaxBrowseContinuationPoints, server_config.max_browse_continuation_points, &now, &now);
                 self.set_variable_value(Server_ServerCapabilities_MaxHistoryContinuationPoints, constants::MAX_HISTORY_CONTINUATION_POINTS as u32, &now, &now);
                 self.set_variable_value(Server_ServerCapabilities_MaxQueryContinuationPoints, constants::MAX_QUERY_CONTINUATION_POINTS as u32, &now, &now);
-                self.set_variable_value(Server_ServerCapabilities_MinSupportedSampleRate, constants::MIN_SAMPLING_INTERVAL as f64, &now, &now);
+                // MinSupportedSampleRate is a Duration, i.e. milliseconds. Items are sampled on the
+                // subscription timer so nothing is sampled faster than it fires.
+                let min_supported_sample_rate = server_config.min_sampling_interval.max(constants::SUBSCRIPTION_TIMER_RATE_MS as f64);
+                self.set_variable_value(Server_ServerCapabilities_MinSupportedSampleRate, min_supported_sample_rate, &now, &now);
+                // The server does not restrict itself to particular locales
+                self.set_variable_value(Server_ServerCapabilities_LocaleIdArray, Vec::<String>::new(), &now, &now);
+                // No software certificates are advertised
+                self.set_variable_value(Server_ServerCapabilities_SoftwareCertificates, Vec::<Variant>::new(), &now, &now);
+            }
+
+            // Server_ServerCapabilities_RoleSet
+            {
+                let server_state = trace_read_lock_unwrap!(server_state);
+                self.add_role_set(server_state.role_set.clone());
             }
 
             // Server_ServerCapabilities_ServerProfileArray
@@ -258,9 +475,6 @@ impl AddressSpace {
                 v.set_value_direct(Variant::from(&server_profiles[..]), &now, &now);
             }
 
-            // Server_ServerCapabilities_LocaleIdArray
-            // Server_ServerCapabilities_MinSupportedSampleRate
-
             // Server_ServerDiagnostics_ServerDiagnosticsSummary
             // Server_ServerDiagnostics_SamplingIntervalDiagnosticsArray
             // Server_ServerDiagnostics_SubscriptionDiagnosticsArray
@@ -280,25 +494,51 @@ impl AddressSpace {
                 server_diagnostics_summary!(self, Server_ServerDiagnostics_ServerDiagnosticsSummary_CumulatedSubscriptionCount, cumulated_subscription_count);
                 server_diagnostics_summary!(self, Server_ServerDiagnostics_ServerDiagnosticsSummary_SecurityRejectedRequestsCount, security_rejected_requests_count);
                 server_diagnostics_summary!(self, Server_ServerDiagnostics_ServerDiagnosticsSummary_RejectedRequestsCount, rejected_requests_count);
+
+                let server_diagnostics = server_state.diagnostics.clone();
+                self.set_variable_getter(Server_ServerDiagnostics_ServerDiagnosticsSummary, move |_, _, _| {
+                    let server_diagnostics = server_diagnostics.read().unwrap();
+                    let server_diagnostics_summary = server_diagnostics.server_diagnostics_summary();
+                    let value = ExtensionObject::from_encodable(ObjectId::ServerDiagnosticsSummaryDataType_Encoding_DefaultBinary, server_diagnostics_summary);
+                    Ok(Some(DataValue::from(Variant::from(value))))
+                });
+
+                let server_diagnostics = server_state.diagnostics.clone();
+                self.set_variable_getter(Server_ServerDiagnostics_SessionsDiagnosticsSummary_SessionDiagnosticsArray, move |_, _, _| {
+                    let server_diagnostics = server_diagnostics.read().unwrap();
+                    let session_diagnostics = server_diagnostics.session_diagnostics().iter()
+                        .map(|d| Variant::from(ExtensionObject::from_encodable(ObjectId::SessionDiagnosticsDataType_Encoding_DefaultBinary, d)))
+                        .collect::<Vec<Variant>>();
+                    Ok(Some(DataValue::from(Variant::Array(session_diagnostics))))
+                });
+
+                self.set_variable_value(Server_ServerDiagnostics_EnabledFlag, true, &now, &now);
             }
 
-            // Server_ServerCapabilities_OperationLimits_MaxNodesPerRead = 11705,
-            // Server_ServerCapabilities_OperationLimits_MaxNodesPerWrite = 11707,
-            // Server_ServerCapabilities_OperationLimits_MaxNodesPerMethodCall = 11709,
-            // Server_ServerCapabilities_OperationLimits_MaxNodesPerBrowse = 11710,
-            // Server_ServerCapabilities_OperationLimits_MaxNodesPerRegisterNodes = 11711,
-            // Server_ServerCapabilities_OperationLimits_MaxNodesPerTranslateBrowsePathsToNodeIds = 11712,
-            // Server_ServerCapabilities_OperationLimits_MaxNodesPerNodeManagement = 11713,
-            // Server_ServerCapabilities_OperationLimits_MaxMonitoredItemsPerCall = 11714,
-            // Server_ServerCapabilities_OperationLimits_MaxNodesPerHistoryReadData = 12165,
-            // Server_ServerCapabilities_OperationLimits_MaxNodesPerHistoryReadEvents = 12166,
-            // Server_ServerCapabilities_OperationLimits_MaxNodesPerHistoryUpdateData = 12167,
-            // Server_ServerCapabilities_OperationLimits_MaxNodesPerHistoryUpdateEvents = 12168,
+            // ServerCapabilities_OperationLimits
+            {
+                let server_state = trace_read_lock_unwrap!(server_state);
+                let operational_limits = server_state.operational_limits();
+                self.set_variable_value(Server_ServerCapabilities_OperationLimits_MaxNodesPerRead, operational_limits.max_nodes_per_read as u32, &now, &now);
+                self.set_variable_value(Server_ServerCapabilities_OperationLimits_MaxNodesPerWrite, operational_limits.max_nodes_per_write as u32, &now, &now);
+                self.set_variable_value(Server_ServerCapabilities_OperationLimits_MaxNodesPerMethodCall, operational_limits.max_nodes_per_method_call as u32, &now, &now);
+                self.set_variable_value(Server_ServerCapabilities_OperationLimits_MaxNodesPerBrowse, operational_limits.max_nodes_per_browse as u32, &now, &now);
+                self.set_variable_value(Server_ServerCapabilities_OperationLimits_MaxNodesPerRegisterNodes, operational_limits.max_nodes_per_register_nodes as u32, &now, &now);
+                self.set_variable_value(Server_ServerCapabilities_OperationLimits_MaxNodesPerTranslateBrowsePathsToNodeIds, operational_limits.max_nodes_per_translate_browse_paths_to_node_ids as u32, &now, &now);
+                self.set_variable_value(Server_ServerCapabilities_OperationLimits_MaxNodesPerNodeManagement, operational_limits.max_nodes_per_node_management as u32, &now, &now);
+                self.set_variable_value(Server_ServerCapabilities_OperationLimits_MaxMonitoredItemsPerCall, operational_limits.max_monitored_items_per_call as u32, &now, &now);
+                self.set_variable_value(Server_ServerCapabilities_OperationLimits_MaxNodesPerHistoryReadData, operational_limits.max_nodes_per_history_read_data as u32, &now, &now);
+                self.set_variable_value(Server_ServerCapabilities_OperationLimits_MaxNodesPerHistoryReadEvents, operational_limits.max_nodes_per_history_read_events as u32, &now, &now);
+                self.set_variable_value(Server_ServerCapabilities_OperationLimits_MaxNodesPerHistoryUpdateData, operational_limits.max_nodes_per_history_update_data as u32, &now, &now);
+                self.set_variable_value(Server_ServerCapabilities_OperationLimits_MaxNodesPerHistoryUpdateEvents, operational_limits.max_nodes_per_history_update_events as u32, &now, &now);
+            }
 
             // ServiceLevel - 0-255 worst to best quality of service
             self.set_variable_value(Server_ServiceLevel, 255u8, &now, &now);
 
-            // Auditing - var
+            // Auditing - the server raises audit events
+            self.set_variable_value(Server_Auditing, true, &now, &now);
+
             // ServerDiagnostics
             // VendorServiceInfo
             // ServerRedundancy
@@ -313,10 +553,30 @@ impl AddressSpace {
 
             // State OPC UA Part 5 12.6, Valid states are
             //     State (Server_ServerStatus_State)
-            self.set_variable_getter(Server_ServerStatus_State, move |_, _, _| {
-                // let server_state =  trace_read_lock_unwrap!(server_state);
-                Ok(Some(DataValue::new(0 as i32)))
-            });
+            {
+                let server_state = server_state.clone();
+                self.set_variable_getter(Server_ServerStatus_State, move |_, _, _| {
+                    let server_state = trace_read_lock_unwrap!(server_state);
+                    Ok(Some(DataValue::new(server_state.state() as i32)))
+                });
+            }
+
+            // Server_ServerStatus_SecondsTillShutdown and Server_ServerStatus_ShutdownReason are
+            // set by Server::shutdown()
+            {
+                let server_state = server_state.clone();
+                self.set_variable_getter(Server_ServerStatus_SecondsTillShutdown, move |_, _, _| {
+                    let server_state = trace_read_lock_unwrap!(server_state);
+                    Ok(Some(DataValue::new(server_state.seconds_till_shutdown())))
+                });
+            }
+            {
+                let server_state = server_state.clone();
+                self.set_variable_getter(Server_ServerStatus_ShutdownReason, move |_, _, _| {
+                    let server_state = trace_read_lock_unwrap!(server_state);
+                    Ok(Some(DataValue::new(server_state.shutdown_reason())))
+                });
+            }
 
             // ServerStatus_BuildInfo
             {
@@ -384,32 +644,137 @@ impl AddressSpace {
         if self.node_exists(&node_id) {
             panic!("This node {:?} already exists", node_id);
         }
-        self.node_map.insert(node_id.clone(), node_type);
+        self.node_map.insert(node_id.clone(), Arc::new(node_type));
 
         // If references are supplied, add them now
         if let Some(references) = references {
             self.references.insert(&node_id, references);
         }
         self.update_last_modified();
+
+        if self.model_change_events {
+            // Nodes with an inverse reference to the new node now have a reference to it
+            let mut changes = vec![(node_id, MODEL_CHANGE_NODE_ADDED)];
+            if let Some(references) = references {
+                references.iter()
+                    .filter(|r| if let ReferenceDirection::Inverse = r.2 { true } else { false })
+                    .for_each(|r| changes.push((r.0.clone(), MODEL_CHANGE_REFERENCE_ADDED)));
+            }
+            self.model_changed(changes);
+        }
     }
 
-    /// Adds the standard nodeset to the address space
+    /// Adds the standard nodeset to the address space. If the `trimmed-address-space` feature is
+    /// enabled, only the core nodes (the base type system and the Server object) are added and the
+    /// rest are deferred until `populate_extended_nodes()` is called.
     pub fn add_default_nodes(&mut self) {
         debug!("populating address space");
 
-        // Reserve space in the maps. The default node set contains just under 2000 values for
-        // nodes, references and inverse references.
         #[cfg(feature = "generated-address-space")] {
-            self.node_map.reserve(2000);
-
             // Run the generated code that will populate the address space with the default nodes
-            super::generated::populate_address_space(self);
+            #[cfg(not(feature = "trimmed-address-space"))] {
+                // Reserve space in the maps. The default node set contains just under 2000 values for
+                // nodes, references and inverse references.
+                self.node_map.reserve(2000);
+                super::generated::populate_address_space(self);
+                self.extended_nodes_populated = true;
+            }
+            #[cfg(feature = "trimmed-address-space")] {
+                super::generated::populate_core_address_space(self);
+            }
 
 //        debug!("finished populating address space, number of nodes = {}, number of references = {}, number of reverse references = {}",
 //               self.node_map.len(), self.references.len(), self.inverse_references.len());
 
             // Build up the map of subtypes
             self.references.build_reference_type_subtypes();
+
+            self.add_server_capabilities_folders();
+        }
+    }
+
+    /// Tests if the extended standard nodes have been added to the address space.
+    pub fn extended_nodes_populated(&self) -> bool {
+        self.extended_nodes_populated
+    }
+
+    /// Adds the extended standard nodes (data access, alarms, programs, historical access,
+    /// aggregates etc.) to the address space if they have not been added already. With the
+    /// `trimmed-address-space` feature a server can call this once it is up and running, or never
+    /// if it has no use for them.
+    pub fn populate_extended_nodes(&mut self) {
+        if self.extended_nodes_populated {
+            return;
+        }
+        #[cfg(feature = "generated-address-space")] {
+            debug!("populating address space with extended nodes");
+            super::generated::populate_extended_address_space(self);
+            self.extended_nodes_populated = true;
+        }
+    }
+
+    /// The generated node set declares the standard modelling rules and the ServerCapabilities
+    /// folders but does not link them together. This organizes the modelling rules that the server
+    /// supports beneath ServerCapabilities/ModellingRules. The AggregateFunctions folder is left
+    /// empty since the server implements no aggregates.
+    #[cfg(feature = "generated-address-space")]
+    fn add_server_capabilities_folders(&mut self) {
+        let modelling_rules_folder_id: NodeId = ObjectId::Server_ServerCapabilities_ModellingRules.into();
+        let modelling_rules = [
+            ObjectId::ModellingRule_Mandatory,
+            ObjectId::ModellingRule_Optional,
+            ObjectId::ModellingRule_ExposesItsArray,
+            ObjectId::ModellingRule_OptionalPlaceholder,
+            ObjectId::ModellingRule_MandatoryPlaceholder,
+        ];
+        for modelling_rule in modelling_rules.iter() {
+            let modelling_rule_id: NodeId = (*modelling_rule).into();
+            if self.node_exists(&modelling_rule_id) && !self.has_reference(&modelling_rules_folder_id, &modelling_rule_id, ReferenceTypeId::Organizes) {
+                self.add_organizes(&modelling_rules_folder_id, &modelling_rule_id);
+            }
+        }
+    }
+
+    /// The RoleSet of Part 18 is not in the generated node set. This adds it beneath ServerCapabilities
+    /// with an object for each role whose Identities property reflects the identity mapping rules
+    /// of the role.
+    #[cfg(feature = "generated-address-space")]
+    fn add_role_set(&mut self, role_set: Arc<RwLock<roles::RoleSet>>) {
+        let role_set_id = NodeId::new(0, SERVER_SERVER_CAPABILITIES_ROLE_SET_ID);
+        if self.node_exists(&role_set_id) {
+            return;
+        }
+        let server_capabilities_id: NodeId = ObjectId::Server_ServerCapabilities.into();
+        self.insert(Object::new(&role_set_id, "RoleSet", "RoleSet", 0), Some(&[
+            (&server_capabilities_id, ReferenceTypeId::HasComponent, ReferenceDirection::Inverse),
+            (&NodeId::new(0, ROLE_SET_TYPE_ID), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+        ]));
+
+        let role_names = {
+            let role_set = trace_read_lock_unwrap!(role_set);
+            role_set.roles().iter().map(|role| (role.role_id().clone(), role.name().to_string())).collect::<Vec<(NodeId, String)>>()
+        };
+        for (role_id, name) in role_names {
+            self.insert(Object::new(&role_id, name.as_str(), name.as_str(), 0), Some(&[
+                (&role_set_id, ReferenceTypeId::HasComponent, ReferenceDirection::Inverse),
+                (&NodeId::new(0, ROLE_TYPE_ID), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+            ]));
+            let identities_id = NodeId::next_numeric();
+            let variable = Variable::new_data_value(&identities_id, "Identities", "Identities", NodeId::new(0, IDENTITY_MAPPING_RULE_TYPE_ID), Vec::<Variant>::new());
+            self.insert(variable, Some(&[
+                (&role_id, ReferenceTypeId::HasProperty, ReferenceDirection::Inverse),
+                (&VariableTypeId::PropertyType.into(), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+            ]));
+            let role_set = role_set.clone();
+            self.set_variable_getter(identities_id, move |_, _, _| {
+                let role_set = role_set.read().unwrap();
+                let identities = role_set.find_role(&role_id)
+                    .map(|role| role.identities().iter()
+                        .map(|rule| Variant::from(ExtensionObject::from_encodable(NodeId::new(0, IDENTITY_MAPPING_RULE_TYPE_ENCODING_DEFAULT_BINARY), rule)))
+                        .collect::<Vec<Variant>>())
+                    .unwrap_or_default();
+                Ok(Some(DataValue::from(Variant::Array(identities))))
+            });
         }
     }
 
@@ -417,6 +782,12 @@ impl AddressSpace {
     pub fn insert_references(&mut self, references: &[(&NodeId, &NodeId, ReferenceTypeId)]) {
         self.references.insert_references(references);
         self.update_last_modified();
+        if self.model_change_events {
+            let changes = references.iter()
+                .map(|r| (r.0.clone(), MODEL_CHANGE_REFERENCE_ADDED))
+                .collect();
+            self.model_changed(changes);
+        }
     }
 
     /// Inserts a single reference between two nodes in the address space
@@ -445,11 +816,25 @@ impl AddressSpace {
     }
 
     pub fn find_node(&self, node_id: &NodeId) -> Option<&NodeType> {
-        self.node_map.get(node_id)
+        self.node_map.get(node_id).map(|node| node.as_ref())
     }
 
     pub fn find_node_mut(&mut self, node_id: &NodeId) -> Option<&mut NodeType> {
-        self.node_map.get_mut(node_id)
+        self.node_map.get_mut(node_id).map(Self::node_mut)
+    }
+
+    /// Returns the node to modify, copying it first if another snapshot of the address space
+    /// shares it
+    fn node_mut(node: &mut Arc<NodeType>) -> &mut NodeType {
+        if Arc::get_mut(node).is_none() {
+            *node = Arc::new(node.copy_for_snapshot());
+        }
+        Arc::get_mut(node).unwrap()
+    }
+
+    /// Returns all the nodes of the address space, in no particular order
+    pub fn nodes(&self) -> impl Iterator<Item=&NodeType> {
+        self.node_map.values().map(|node| node.as_ref())
     }
 
     pub fn node_exists(&self, node_id: &NodeId) -> bool {
@@ -512,18 +897,131 @@ impl AddressSpace {
 
     /// Deletes a node and optionally any references to / from it in the address space
     pub fn delete_node(&mut self, node_id: &NodeId, delete_target_references: bool) -> bool {
+        // The changes are found first because the type of the node is known from its references
+        let changes = if self.model_change_events && self.node_exists(node_id) {
+            let mut changes = vec![(node_id.clone(), MODEL_CHANGE_NODE_DELETED)];
+            if delete_target_references {
+                if let Some(references) = self.find_references_to(node_id, None) {
+                    references.into_iter()
+                        .for_each(|r| changes.push((r.target_node_id, MODEL_CHANGE_REFERENCE_DELETED)));
+                }
+            }
+            Some(self.model_change_structures(changes))
+        } else {
+            None
+        };
+
         let removed_node = self.node_map.remove(&node_id);
         let removed_target_references = if delete_target_references {
             self.references.delete_references_to_node(node_id)
         } else {
             false
         };
-        removed_node.is_some() || removed_target_references
+        let deleted = removed_node.is_some() || removed_target_references;
+        if deleted {
+            self.update_last_modified();
+        }
+        if let Some(changes) = changes {
+            self.raise_model_change_event(changes);
+        }
+        deleted
     }
 
     /// Finds the matching reference and deletes it
     pub fn delete_reference(&mut self, node_id: &NodeId, target_node_id: &NodeId, reference_type_id: ReferenceTypeId) -> bool {
-        self.references.delete_reference(node_id, target_node_id, reference_type_id)
+        let deleted = self.references.delete_reference(node_id, target_node_id, reference_type_id);
+        if deleted {
+            self.update_last_modified();
+            if self.model_change_events {
+                self.model_changed(vec![(node_id.clone(), MODEL_CHANGE_REFERENCE_DELETED)]);
+            }
+        }
+        deleted
+    }
+
+    /// Sets whether adding or removing nodes and references raises a `GeneralModelChangeEventType`
+    /// event from the Server object and increments the `NodeVersion` property of the affected
+    /// nodes, so clients which cache the address space can tell that it has changed. This is off
+    /// by default and should be enabled after the server implementation has populated the address
+    /// space.
+    pub fn set_model_change_events(&mut self, model_change_events: bool) {
+        self.model_change_events = model_change_events;
+    }
+
+    /// Returns true if adding or removing nodes and references raises model change events
+    pub fn model_change_events(&self) -> bool {
+        self.model_change_events
+    }
+
+    /// Adds a `NodeVersion` property to the node with a version of "0". The version is incremented
+    /// each time the node or its references change while model change events are enabled.
+    pub fn add_node_version(&mut self, node_id: &NodeId) -> Result<NodeId, ()> {
+        if !self.node_exists(node_id) || self.find_node_version(node_id).is_some() {
+            Err(())
+        } else {
+            let property_id = NodeId::next_numeric();
+            self.insert(Variable::new(&property_id, "NodeVersion", "NodeVersion", UAString::from("0")), Some(&[
+                (node_id, ReferenceTypeId::HasProperty, ReferenceDirection::Inverse),
+                (&VariableTypeId::PropertyType.into(), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+            ]));
+            Ok(property_id)
+        }
+    }
+
+    /// Finds the `NodeVersion` property of the node
+    fn find_node_version(&self, node_id: &NodeId) -> Option<NodeId> {
+        let browse_name = QualifiedName::from("NodeVersion");
+        self.find_references_from(node_id, Some((ReferenceTypeId::HasProperty, false)))
+            .and_then(|references| {
+                references.into_iter()
+                    .map(|r| r.target_node_id)
+                    .find(|property_id| {
+                        self.find_node(property_id)
+                            .map(|property| property.as_node().browse_name() == browse_name)
+                            .unwrap_or(false)
+                    })
+            })
+    }
+
+    /// Increments the value of the node's `NodeVersion` property, if it has one
+    fn increment_node_version(&mut self, node_id: &NodeId) {
+        if let Some(property_id) = self.find_node_version(node_id) {
+            if let Some(property) = self.find_variable_mut_by_ref(&property_id) {
+                let version = if let Some(Variant::String(version)) = property.value().value {
+                    version.as_ref().parse::<u64>().unwrap_or(0)
+                } else {
+                    0
+                };
+                property.set_value(UAString::from((version + 1).to_string()));
+            }
+        }
+    }
+
+    /// Describes each change to a node by its type definition and the verb of the change
+    fn model_change_structures(&self, changes: Vec<(NodeId, u8)>) -> Vec<ModelChangeStructureDataType> {
+        changes.into_iter().map(|(affected, verb)| {
+            let affected_type = self.get_type_id(&affected).unwrap_or_else(NodeId::null);
+            ModelChangeStructureDataType {
+                affected,
+                affected_type,
+                verb,
+            }
+        }).collect()
+    }
+
+    /// Called when nodes or references have been added or removed, with the affected nodes and
+    /// the verb of each change.
+    fn model_changed(&mut self, changes: Vec<(NodeId, u8)>) {
+        let changes = self.model_change_structures(changes);
+        self.raise_model_change_event(changes);
+    }
+
+    /// Increments the `NodeVersion` of the affected nodes and raises a model change event
+    fn raise_model_change_event(&mut self, changes: Vec<ModelChangeStructureDataType>) {
+        changes.iter()
+            .filter(|change| change.verb & MODEL_CHANGE_NODE_DELETED == 0)
+            .for_each(|change| self.increment_node_version(&change.affected));
+        self.raise_event(model_change::model_change_event(&changes));
     }
 
     /// Find and return a variable with the specified node id or return None if it cannot be
@@ -535,7 +1033,7 @@ impl AddressSpace {
     /// Find and return a variable with the specified node id or return None if it cannot be
     /// found or is not a variable
     pub fn find_variable_by_ref(&self, node_id: &NodeId) -> Option<&Variable> {
-        if let Some(node) = self.node_map.get(node_id) {
+        if let Some(node) = self.find_node(node_id) {
             if let &NodeType::Variable(ref variable) = node {
                 Some(variable)
             } else {
@@ -555,7 +1053,7 @@ impl AddressSpace {
     /// Find and return a variable with the specified node id or return None if it cannot be
     /// found or is not a variable
     pub fn find_variable_mut_by_ref(&mut self, node_id: &NodeId) -> Option<&mut Variable> {
-        if let Some(node) = self.node_map.get_mut(node_id) {
+        if let Some(node) = self.find_node_mut(node_id) {
             if let &mut NodeType::Variable(ref mut variable) = node {
                 Some(variable)
             } else {
@@ -568,16 +1066,17 @@ impl AddressSpace {
 
     /// Set a variable value from its NodeId. The function will return false if the variable does
     /// not exist, or the node is not a variable.
-    pub fn set_variable_value<N, V>(&mut self, node_id: N, value: V, source_timestamp: &DateTime, server_timestamp: &DateTime) -> bool
+    pub fn set_variable_value<N, V>(&self, node_id: N, value: V, source_timestamp: &DateTime, server_timestamp: &DateTime) -> bool
         where N: Into<NodeId>, V: Into<Variant> {
         self.set_variable_value_by_ref(&node_id.into(), value, source_timestamp, server_timestamp)
     }
 
     /// Set a variable value from its NodeId. The function will return false if the variable does
-    /// not exist, or the node is not a variable.
-    pub fn set_variable_value_by_ref<V>(&mut self, node_id: &NodeId, value: V, source_timestamp: &DateTime, server_timestamp: &DateTime) -> bool
+    /// not exist, or the node is not a variable. Values have locks of their own so a value may be
+    /// set while the address space is only locked for reading.
+    pub fn set_variable_value_by_ref<V>(&self, node_id: &NodeId, value: V, source_timestamp: &DateTime, server_timestamp: &DateTime) -> bool
         where V: Into<Variant> {
-        if let Some(ref mut variable) = self.find_variable_mut_by_ref(node_id) {
+        if let Some(variable) = self.find_variable_by_ref(node_id) {
             variable.set_value_direct(value, source_timestamp, server_timestamp);
             true
         } else {
@@ -593,6 +1092,41 @@ impl AddressSpace {
             .ok_or_else(|| ())
     }
 
+    /// Adds a method to an object. The input and output arguments become the `InputArguments`
+    /// and `OutputArguments` properties of the method and calls to the method are validated
+    /// against them. A handler for the method must be registered with `register_method_handler()`.
+    pub fn add_method<R, S>(&mut self, node_id: &NodeId, browse_name: R, display_name: S, object_id: &NodeId, input_arguments: &[Argument], output_arguments: &[Argument]) -> Result<NodeId, ()>
+        where R: Into<QualifiedName>, S: Into<LocalizedText>
+    {
+        if self.node_exists(node_id) || !is_object!(self, object_id) {
+            error!("Method {:?} cannot be added, either it already exists or its object {:?} does not", node_id, object_id);
+            Err(())
+        } else {
+            self.insert(Method::new(node_id, browse_name, display_name, true, true), Some(&[
+                (object_id, ReferenceTypeId::HasComponent, ReferenceDirection::Inverse),
+            ]));
+            if !input_arguments.is_empty() {
+                self.add_method_arguments(node_id, "InputArguments", input_arguments);
+            }
+            if !output_arguments.is_empty() {
+                self.add_method_arguments(node_id, "OutputArguments", output_arguments);
+            }
+            Ok(node_id.clone())
+        }
+    }
+
+    /// Adds an arguments property to a method
+    pub(crate) fn add_method_arguments(&mut self, method_id: &NodeId, property_name: &str, arguments: &[Argument]) {
+        let value = arguments.iter().map(|argument| {
+            Variant::from(ExtensionObject::from_encodable(ObjectId::Argument_Encoding_DefaultBinary, argument))
+        }).collect::<Vec<Variant>>();
+        let variable = Variable::new_data_value(&NodeId::next_numeric(), property_name, property_name, DataTypeId::Argument, value);
+        self.insert(variable, Some(&[
+            (method_id, ReferenceTypeId::HasProperty, ReferenceDirection::Inverse),
+            (&VariableTypeId::PropertyType.into(), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+        ]));
+    }
+
     /// Registers a method callback on the specified object id and method id
     pub fn register_method_handler<N1, N2>(&mut self, object_id: N1, method_id: N2, handler: MethodCallback) where N1: Into<NodeId>, N2: Into<NodeId> {
         // Check the object id and method id actually exist as things in the address space
@@ -602,11 +1136,241 @@ impl AddressSpace {
             panic!("Invalid id {:?} / {:?} supplied to method handler", object_id, method_id)
         }
         let key = MethodKey { object_id, method_id };
-        if let Some(_) = self.method_handlers.insert(key, handler) {
+        if let Some(_) = self.method_handlers.insert(key, Arc::new(Mutex::new(handler))) {
             trace!("Registration replaced a previous callback");
         }
     }
 
+    /// Registers a provider of the values of the variable nodes. The Read service and monitored
+    /// items get the values of these nodes from the provider, in one batch for all the nodes that
+    /// they need rather than from each variable. A node given to a later provider is provided by
+    /// that provider instead.
+    pub fn register_value_provider(&mut self, node_ids: &[NodeId], provider: ValueProvider) {
+        let provider_idx = self.value_providers.len();
+        self.value_providers.push(Arc::new(Mutex::new(provider)));
+        node_ids.iter().for_each(|node_id| {
+            self.value_provider_nodes.insert(node_id.clone(), provider_idx);
+        });
+    }
+
+    /// Tests if the value of the node comes from a value provider
+    pub fn has_value_provider(&self, node_id: &NodeId) -> bool {
+        self.value_provider_nodes.contains_key(node_id)
+    }
+
+    /// Reads the values of the nodes from their value providers, calling each provider once with
+    /// all of its nodes. Nodes without a value provider are left out of the result. A value which
+    /// cannot be read is a data value with a bad status.
+    pub(crate) fn read_provided_values(&self, node_ids: &[NodeId], max_age: f64) -> HashMap<NodeId, DataValue> {
+        // Group the nodes by their provider, asking for each node once
+        let mut nodes_by_provider: HashMap<usize, Vec<NodeId>> = HashMap::new();
+        let mut grouped_nodes = HashSet::with_capacity(node_ids.len());
+        node_ids.iter().for_each(|node_id| {
+            if let Some(provider_idx) = self.value_provider_nodes.get(node_id) {
+                if grouped_nodes.insert(node_id) {
+                    nodes_by_provider.entry(*provider_idx).or_insert_with(Vec::new).push(node_id.clone());
+                }
+            }
+        });
+
+        let mut values = HashMap::with_capacity(node_ids.len());
+        for (provider_idx, node_ids) in nodes_by_provider {
+            let results = {
+                let mut provider = trace_lock_unwrap!(self.value_providers[provider_idx]);
+                provider.read_values(&node_ids, max_age)
+            };
+            if results.len() != node_ids.len() {
+                error!("Value provider returned {} values for {} nodes", results.len(), node_ids.len());
+            }
+            let mut results = results.into_iter();
+            for node_id in node_ids {
+                let value = match results.next() {
+                    Some(Ok(value)) => value,
+                    Some(Err(status_code)) => DataValue {
+                        status: Some(status_code.bits()),
+                        ..DataValue::null()
+                    },
+                    None => DataValue {
+                        status: Some(StatusCode::BadInternalError.bits()),
+                        ..DataValue::null()
+                    }
+                };
+                values.insert(node_id, value);
+            }
+        }
+        values
+    }
+
+    /// Adds a file object of `FileType` beneath the parent node. Clients transfer the contents of
+    /// the file with its `Open`, `Read`, `Write` and other methods, which are served by the provider.
+    pub fn add_file<R, S>(&mut self, node_id: &NodeId, browse_name: R, display_name: S, parent_node_id: &NodeId, provider: Box<dyn FileProvider>) -> Result<NodeId, ()>
+        where R: Into<QualifiedName>, S: Into<LocalizedText>
+    {
+        if self.node_exists(node_id) || !self.node_exists(parent_node_id) {
+            error!("File {:?} cannot be added, either it already exists or its parent {:?} does not", node_id, parent_node_id);
+            Err(())
+        } else {
+            self.insert(Object::new(node_id, browse_name, display_name, 0), Some(&[
+                (parent_node_id, ReferenceTypeId::HasComponent, ReferenceDirection::Inverse),
+                (&ObjectTypeId::FileType.into(), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+            ]));
+            let file = Arc::new(RwLock::new(File::new(provider)));
+            file_transfer::add_file_nodes(self, node_id, &FileNodeIds::next_numeric(), file, None);
+            Ok(node_id.clone())
+        }
+    }
+
+    /// Adds the data type of a structure defined in Rust with `#[derive(UaStructure)]` as a subtype
+    /// of `Structure`, with a `Default Binary` encoding object whose node id is the structure's binary
+    /// encoding id. The structure is registered with the type registry so that extension objects
+    /// holding it, e.g. in written values, can be decoded with `ExtensionObject::decode_object()`.
+    pub fn add_structure_data_type<T>(&mut self) -> Result<NodeId, ()> where T: UaStructure + DecodedObject {
+        let data_type_id = T::data_type_id();
+        let encoding_id = T::binary_encoding_id();
+        if self.node_exists(&data_type_id) || self.node_exists(&encoding_id) {
+            error!("Data type {} cannot be added, its data type {:?} or encoding {:?} already exists", T::type_name(), data_type_id, encoding_id);
+            return Err(());
+        }
+        let browse_name = QualifiedName::new(data_type_id.namespace, T::type_name());
+        self.insert(DataType::new(&data_type_id, browse_name, T::type_name(), false), Some(&[
+            (&DataTypeId::Structure.into(), ReferenceTypeId::HasSubtype, ReferenceDirection::Inverse),
+        ]));
+        self.insert(Object::new(&encoding_id, QualifiedName::new(0, "Default Binary"), "Default Binary", 0), Some(&[
+            (&data_type_id, ReferenceTypeId::HasEncoding, ReferenceDirection::Inverse),
+            (&ObjectTypeId::DataTypeEncodingType.into(), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+        ]));
+        {
+            let mut type_registry = trace_write_lock_unwrap!(TYPE_REGISTRY);
+            T::register(&mut type_registry);
+        }
+        Ok(data_type_id)
+    }
+
+    /// Raises an event. The event is delivered to monitored items on its source node and on the
+    /// Server object the next time that their subscriptions are ticked.
+    pub fn raise_event(&self, event: Event) {
+        let mut raised_events = trace_write_lock_unwrap!(self.events);
+        // Discard the oldest events to make room
+        while raised_events.events.len() >= constants::MAX_RAISED_EVENTS {
+            let _ = raised_events.events.pop_front();
+        }
+        raised_events.last_event_number += 1;
+        let event_number = raised_events.last_event_number;
+        raised_events.events.push_back((event_number, event));
+    }
+
+    /// Returns the number of the most recently raised event
+    pub(crate) fn last_event_number(&self) -> u64 {
+        trace_read_lock_unwrap!(self.events).last_event_number
+    }
+
+    /// Returns the events raised after the event with the specified number, oldest to newest
+    pub(crate) fn events_since(&self, event_number: u64) -> Vec<Event> {
+        trace_read_lock_unwrap!(self.events).events.iter()
+            .filter(|(n, _)| *n > event_number)
+            .map(|(_, event)| event.clone())
+            .collect()
+    }
+
+    /// Adds a condition to the address space. The condition becomes an object beneath its source
+    /// node, which must exist, with a `HasCondition` reference to it. The condition id must not
+    /// already be used by another node.
+    pub fn add_condition(&mut self, condition: Condition) -> Result<NodeId, ()> {
+        let condition_id = condition.condition_id().clone();
+        if self.node_exists(&condition_id) || !self.node_exists(condition.source_node()) {
+            error!("Condition {:?} cannot be added, either it already exists or its source {:?} does not", condition_id, condition.source_node());
+            Err(())
+        } else {
+            let condition_name = condition.condition_name().as_ref().to_string();
+            // Event notifier is SubscribeToEvents
+            self.insert(Object::new(&condition_id, condition_name.as_str(), condition_name.as_str(), 1), Some(&[
+                (condition.source_node(), ReferenceTypeId::HasCondition, ReferenceDirection::Inverse),
+                (&condition.kind().event_type().into(), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+            ]));
+            self.conditions.insert(condition_id.clone(), condition);
+            Ok(condition_id)
+        }
+    }
+
+    /// Finds the condition with the specified id
+    pub fn find_condition(&self, condition_id: &NodeId) -> Option<&Condition> {
+        self.conditions.get(condition_id)
+    }
+
+    /// Changes the state of a condition through the supplied function and raises an event with
+    /// the new state. No event is raised if the function returns an error.
+    pub fn update_condition<N, F>(&mut self, condition_id: N, f: F) -> Result<(), StatusCode>
+        where N: Into<NodeId>, F: FnOnce(&mut Condition) -> Result<(), StatusCode>
+    {
+        let condition_id = condition_id.into();
+        let event = if let Some(condition) = self.conditions.get_mut(&condition_id) {
+            f(condition)?;
+            condition.new_event()
+        } else {
+            return Err(StatusCode::BadNodeIdUnknown);
+        };
+        self.raise_event(event);
+        Ok(())
+    }
+
+    /// Sets the input value of a limit alarm. An event is raised if the value causes the alarm to
+    /// change limit state.
+    pub fn set_condition_input_value<N>(&mut self, condition_id: N, value: f64) -> Result<(), StatusCode> where N: Into<NodeId> {
+        let condition_id = condition_id.into();
+        let event = if let Some(condition) = self.conditions.get_mut(&condition_id) {
+            if condition.set_input_value(value)? {
+                Some(condition.new_event())
+            } else {
+                None
+            }
+        } else {
+            return Err(StatusCode::BadNodeIdUnknown);
+        };
+        if let Some(event) = event {
+            self.raise_event(event);
+        }
+        Ok(())
+    }
+
+    /// Returns the events of every retained condition for a condition refresh
+    pub(crate) fn retained_condition_events(&self) -> Vec<Event> {
+        self.conditions.values()
+            .filter(|condition| condition.retain())
+            .map(|condition| condition.refresh_event())
+            .collect()
+    }
+
+    /// Handles calls to the methods of conditions and to ConditionRefresh. These are handled by
+    /// the address space rather than registered handlers because they change condition state.
+    /// Returns `None` if the call is not to a condition method.
+    #[cfg(feature = "generated-address-space")]
+    fn call_condition_method(&mut self, session: &mut Session, request: &CallMethodRequest) -> Option<Result<CallMethodResult, StatusCode>> {
+        use crate::address_space::method_impls;
+        let (object_id, method_id) = (&request.object_id, &request.method_id);
+        if *object_id == ObjectTypeId::ConditionType.into() && *method_id == MethodId::ConditionType_ConditionRefresh.into() {
+            let events = self.retained_condition_events();
+            Some(method_impls::condition_refresh(session, self, events, request))
+        } else if self.conditions.contains_key(object_id) {
+            // Condition methods are identified by browse name since each condition type has its own copy
+            let method_name = if let Some(NodeType::Method(ref method)) = self.find_node(method_id) {
+                method.browse_name().name.as_ref().to_string()
+            } else {
+                return Some(Err(StatusCode::BadMethodInvalid));
+            };
+            let result = {
+                let condition = self.conditions.get_mut(object_id).unwrap();
+                method_impls::condition_method(condition, &method_name, request)
+                    .map(|result| (result, condition.new_event()))
+            };
+            Some(result.map(|(result, event)| {
+                self.raise_event(event);
+                result
+            }))
+        } else {
+            None
+        }
+    }
+
     /// Test if the type definition is defined and valid for a class of the specified type.
     /// i.e. if we have a Variable or Object class that the type is a VariableType or ObjectType
     /// respectively.
@@ -641,11 +1405,45 @@ impl AddressSpace {
         }
     }
 
+    /// Creates an instance of an object type or variable type under the parent. The instance is
+    /// organized by the parent if the parent is a folder, or is a component of it otherwise.
+    ///
+    /// The instance gets a copy of every component and property of the type and its supertypes
+    /// whose modelling rule is `Mandatory`, and each copy is itself instantiated from its type
+    /// definition. A subtype's declaration overrides a supertype's declaration of the same browse
+    /// name. `Optional` declarations are not copied and may be added afterwards, as are the
+    /// children of `Placeholder` declarations, which are named by the caller. Methods are shared
+    /// with the type rather than copied. Fails with `BadTypeDefinitionInvalid` if the type is not
+    /// a concrete object type or variable type.
+    pub fn instantiate<R>(&mut self, type_node_id: &NodeId, browse_name: R, parent_node_id: &NodeId) -> Result<NodeId, StatusCode>
+        where R: Into<QualifiedName>
+    {
+        instantiation::instantiate(self, type_node_id, browse_name.into(), parent_node_id)
+    }
+
     /// This finds the type definition (if any corresponding to the input object)
-    fn get_type_id(&self, node_id: &NodeId) -> Option<NodeId> {
+    pub fn get_type_id(&self, node_id: &NodeId) -> Option<NodeId> {
         self.references.get_type_id(node_id)
     }
 
+    /// Tests if a type is the base type or one of its subtypes by following the `HasSubtype`
+    /// references of the type up to its supertypes.
+    pub fn is_subtype(&self, type_id: &NodeId, base_type_id: &NodeId) -> bool {
+        let mut type_id = type_id.clone();
+        loop {
+            if type_id == *base_type_id {
+                return true;
+            }
+            let supertype = self.find_references_to(&type_id, Some((ReferenceTypeId::HasSubtype, false)))
+                .and_then(|references| references.first().map(|r| r.target_node_id.clone()));
+            if let Some(supertype) = supertype {
+                type_id = supertype;
+            } else {
+                return false;
+            }
+        }
+    }
+
     /// Test if a reference relationship exists between one node and another node
     pub fn has_reference(&self, from_node_id: &NodeId, to_node_id: &NodeId, reference_type: ReferenceTypeId) -> bool {
         self.references.has_reference(from_node_id, to_node_id, reference_type)
@@ -669,7 +1467,14 @@ impl AddressSpace {
     ///
     /// Calls require a registered handler to handle the method. If there is no handler, or if
     /// the request refers to a non existent object / method, the function will return an error.
-    pub fn call_method(&mut self, _server_state: &ServerState, session: &mut Session, request: &CallMethodRequest) -> Result<CallMethodResult, StatusCode> {
+    pub fn call_method(&mut self, server_state: &ServerState, session: &mut Session, request: &CallMethodRequest) -> Result<CallMethodResult, StatusCode> {
+        // The condition methods only exist in the generated address space
+        #[cfg(feature = "generated-address-space")] {
+            if let Some(result) = self.call_condition_method(session, request) {
+                return result;
+            }
+        }
+
         let (object_id, method_id) = (&request.object_id, &request.method_id);
 
         // Handle the call
@@ -682,24 +1487,184 @@ impl AddressSpace {
         } else if !self.method_exists_on_object(object_id, method_id) {
             error!("Method call to {:?} on {:?} but the method does not exist on the object!", method_id, object_id);
             Err(StatusCode::BadMethodInvalid)
+        } else if !self.find_node(method_id).map_or(false, |method| roles::is_permitted(method, &session.roles, PermissionType::CALL)) ||
+            !server_state.can_call(&session.identity, object_id, method_id) {
+            error!("Method call to {:?} on {:?} was denied to the user of the session", method_id, object_id);
+            Err(StatusCode::BadUserAccessDenied)
         } else {
-            // TODO check security - session / user may not have permission to call methods
-
             // Find the handler for this method call
             let key = MethodKey {
                 object_id: object_id.clone(),
                 method_id: method_id.clone(),
             };
-            if let Some(handler) = self.method_handlers.get_mut(&key) {
-                // Call the handler
-                trace!("Method call to {:?} on {:?} being handled by a registered handler", method_id, object_id);
-                handler.call(session, request)
-            } else {
+            if !self.method_handlers.contains_key(&key) {
                 // TODO we could do a secondary search on a (NodeId::null(), method_id) here
                 //  so that method handler is reusable for multiple objects
                 error!("Method call to {:?} on {:?} has no handler, treating as invalid", method_id, object_id);
-                Err(StatusCode::BadMethodInvalid)
+                return Err(StatusCode::BadMethodInvalid);
+            }
+
+            // Check the input arguments against the method's InputArguments property
+            let input_arguments = request.input_arguments.as_ref().map(|v| v.as_slice()).unwrap_or(&[]);
+            if let Some(arguments) = self.method_arguments(method_id, "InputArguments") {
+                if input_arguments.len() < arguments.len() {
+                    return Err(StatusCode::BadArgumentsMissing);
+                } else if input_arguments.len() > arguments.len() {
+                    return Err(StatusCode::BadTooManyArguments);
+                }
+                let input_argument_results = arguments.iter().zip(input_arguments.iter()).map(|(argument, value)| {
+                    if self.is_valid_argument(argument, value) { StatusCode::Good } else { StatusCode::BadTypeMismatch }
+                }).collect::<Vec<StatusCode>>();
+                if input_argument_results.iter().any(|r| *r != StatusCode::Good) {
+                    error!("Method call to {:?} on {:?} has invalid input arguments", method_id, object_id);
+                    return Ok(CallMethodResult {
+                        status_code: StatusCode::BadInvalidArgument,
+                        input_argument_results: Some(input_argument_results),
+                        input_argument_diagnostic_infos: None,
+                        output_arguments: None,
+                    });
+                }
+            }
+
+            // Call the handler
+            trace!("Method call to {:?} on {:?} being handled by a registered handler", method_id, object_id);
+            let result = {
+                let mut handler = trace_lock_unwrap!(self.method_handlers[&key]);
+                handler.call(session, request)?
+            };
+
+            // Check the output arguments against the method's OutputArguments property
+            if result.status_code.is_good() {
+                if let Some(arguments) = self.method_arguments(method_id, "OutputArguments") {
+                    let output_arguments = result.output_arguments.as_ref().map(|v| v.as_slice()).unwrap_or(&[]);
+                    if output_arguments.len() != arguments.len() ||
+                        arguments.iter().zip(output_arguments.iter()).any(|(argument, value)| !self.is_valid_argument(argument, value)) {
+                        error!("Method call to {:?} on {:?} returned output arguments that do not match its OutputArguments", method_id, object_id);
+                        return Err(StatusCode::BadInternalError);
+                    }
+                }
+            }
+            Ok(result)
+        }
+    }
+
+    /// Returns the low and high limits of the `EURange` property of an analog variable, or `None`
+    /// if the variable does not have the property.
+    pub fn eu_range(&self, node_id: &NodeId) -> Option<(f64, f64)> {
+        let variable = self.find_property(node_id, "EURange")?;
+        if let Some(Variant::ExtensionObject(ref value)) = variable.value().value {
+            value.decode_inner::<Range>(&DecodingLimits::default()).ok().map(|range| (range.low, range.high))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the `EnumStrings` property of a multi-state discrete variable, i.e. the name of each
+    /// state of the variable, or `None` if the variable does not have the property.
+    pub fn enum_strings(&self, node_id: &NodeId) -> Option<Vec<LocalizedText>> {
+        let variable = self.find_property(node_id, "EnumStrings")?;
+        if let Some(Variant::Array(values)) = variable.value().value {
+            Some(values.into_iter().filter_map(|value| {
+                if let Variant::LocalizedText(value) = value {
+                    Some(*value)
+                } else {
+                    None
+                }
+            }).collect())
+        } else {
+            None
+        }
+    }
+
+    /// Finds the property of the node with the browse name
+    fn find_property(&self, node_id: &NodeId, property_name: &str) -> Option<&Variable> {
+        let references = self.find_references_from(node_id, Some((ReferenceTypeId::HasProperty, false)))?;
+        references.iter()
+            .filter_map(|r| self.find_variable_by_ref(&r.target_node_id))
+            .find(|v| v.browse_name().name.as_ref() == property_name)
+    }
+
+    /// Returns the arguments described by the `InputArguments` or `OutputArguments` property of a
+    /// method, or `None` if the method does not have the property.
+    fn method_arguments(&self, method_id: &NodeId, property_name: &str) -> Option<Vec<Argument>> {
+        let variable = self.find_property(method_id, property_name)?;
+        let decoding_limits = DecodingLimits::default();
+        let arguments = match variable.value().value {
+            Some(Variant::Array(values)) => values,
+            Some(Variant::ExtensionObject(value)) => vec![Variant::ExtensionObject(value)],
+            _ => Vec::new()
+        };
+        Some(arguments.iter().filter_map(|value| {
+            if let Variant::ExtensionObject(ref value) = *value {
+                value.decode_inner::<Argument>(&decoding_limits).ok()
+            } else {
+                None
             }
+        }).collect())
+    }
+
+    /// Tests if a value is acceptable for a method argument. The value rank must agree with the
+    /// value being a scalar or an array and the value must be of the argument's data type.
+    fn is_valid_argument(&self, argument: &Argument, value: &Variant) -> bool {
+        let rank_is_valid = match argument.value_rank {
+            // Scalar
+            -1 => !value.is_array(),
+            // Any or ScalarOrOneDimension
+            -2 | -3 => true,
+            // OneOrMoreDimensions or a specific number of dimensions
+            _ => value.is_array(),
+        };
+        if !rank_is_valid {
+            false
+        } else {
+            let data_type = self.builtin_data_type(&argument.data_type);
+            match *value {
+                Variant::Array(ref values) => values.iter().all(|v| Self::is_value_of_type(data_type, v)),
+                Variant::MultiDimensionArray(ref mda) => mda.values.iter().all(|v| Self::is_value_of_type(data_type, v)),
+                ref value => Self::is_value_of_type(data_type, value)
+            }
+        }
+    }
+
+    /// Finds the built-in or abstract base type of a data type by following its supertypes,
+    /// e.g. `UtcTime` is a `DateTime` and an enumerated type is an `Enumeration`.
+    fn builtin_data_type(&self, data_type: &NodeId) -> Option<DataTypeId> {
+        let mut data_type = data_type.clone();
+        loop {
+            if data_type.namespace == 0 {
+                if let Identifier::Numeric(id) = data_type.identifier {
+                    if id <= DataTypeId::Enumeration as u32 {
+                        return DataTypeId::from_u32(id).ok();
+                    }
+                }
+            }
+            let supertype = self.find_references_to(&data_type, Some((ReferenceTypeId::HasSubtype, false)))
+                .and_then(|references| references.first().map(|r| r.target_node_id.clone()));
+            if let Some(supertype) = supertype {
+                data_type = supertype;
+            } else {
+                return None;
+            }
+        }
+    }
+
+    /// Tests if a scalar value is of the data type. Abstract numeric types accept any number of
+    /// the right kind and a data type that is unknown accepts anything.
+    fn is_value_of_type(data_type: Option<DataTypeId>, value: &Variant) -> bool {
+        match data_type {
+            None | Some(DataTypeId::BaseDataType) => true,
+            Some(DataTypeId::Number) => value.is_numeric(),
+            Some(DataTypeId::Integer) => match *value {
+                Variant::SByte(_) | Variant::Int16(_) | Variant::Int32(_) | Variant::Int64(_) => true,
+                _ => false
+            },
+            Some(DataTypeId::UInteger) => match *value {
+                Variant::Byte(_) | Variant::UInt16(_) | Variant::UInt32(_) | Variant::UInt64(_) => true,
+                _ => false
+            },
+            Some(DataTypeId::Enumeration) => value.type_id() == VariantTypeId::Int32,
+            Some(DataTypeId::Structure) => value.type_id() == VariantTypeId::ExtensionObject,
+            Some(data_type) => value.data_type() == Some(data_type),
         }
     }
 
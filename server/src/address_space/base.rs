@@ -5,7 +5,7 @@ use opcua_types::{
 };
 
 /// Base node class contains the attributes that all other kinds of nodes need. Part 3, diagram B.4
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Base {
     /// The node id of this node
     node_id: NodeId,
@@ -21,6 +21,8 @@ pub struct Base {
     write_mask: Option<u32>,
     /// User write mask bits (optional)
     user_write_mask: Option<u32>,
+    /// The permissions of each role on the node (optional). Without them the node is unrestricted.
+    role_permissions: Option<Vec<RolePermissionType>>,
 }
 
 impl Base {
@@ -36,6 +38,7 @@ impl Base {
             description: None,
             write_mask: None,
             user_write_mask: None,
+            role_permissions: None,
         }
     }
 
@@ -74,6 +77,16 @@ impl Base {
                     None
                 }
             }
+            AttributeId::RolePermissions => {
+                if let Some(ref role_permissions) = self.role_permissions {
+                    let value = role_permissions.iter()
+                        .map(|r| Variant::from(ExtensionObject::from_encodable(NodeId::new(0, ROLE_PERMISSION_TYPE_ENCODING_DEFAULT_BINARY), r)))
+                        .collect::<Vec<Variant>>();
+                    Some(DataValue::from(Variant::Array(value)))
+                } else {
+                    None
+                }
+            }
             _ => {
                 None
             }
@@ -150,6 +163,22 @@ impl Base {
                     Err(StatusCode::BadTypeMismatch)
                 }
             }
+            AttributeId::RolePermissions => {
+                if let Variant::Array(values) = value {
+                    let decoding_limits = DecodingLimits::default();
+                    let role_permissions = values.iter().map(|v| {
+                        if let Variant::ExtensionObject(ref v) = *v {
+                            v.decode_inner::<RolePermissionType>(&decoding_limits).map_err(|_| StatusCode::BadTypeMismatch)
+                        } else {
+                            Err(StatusCode::BadTypeMismatch)
+                        }
+                    }).collect::<Result<Vec<RolePermissionType>, StatusCode>>()?;
+                    self.role_permissions = Some(role_permissions);
+                    Ok(None)
+                } else {
+                    Err(StatusCode::BadTypeMismatch)
+                }
+            }
             _ => {
                 // The value is sent back to the caller for further processing.
                 Ok(Some(value))
@@ -216,4 +245,12 @@ impl Base {
     pub fn set_user_write_mask(&mut self, user_write_mask: WriteMask) {
         self.user_write_mask = Some(user_write_mask.bits());
     }
+
+    pub fn role_permissions(&self) -> Option<&[RolePermissionType]> {
+        self.role_permissions.as_ref().map(|r| r.as_slice())
+    }
+
+    pub fn set_role_permissions(&mut self, role_permissions: Vec<RolePermissionType>) {
+        self.role_permissions = Some(role_permissions);
+    }
 }
@@ -0,0 +1,249 @@
+//! Builders for the variable types of Data Access (Part 8) - analog items and discrete items. Each
+//! builder inserts the variable, its type definition and the properties of its type into the
+//! address space in one call.
+
+use opcua_types::{
+    *,
+    node_ids::{DataTypeId, ObjectId, ReferenceTypeId, VariableTypeId},
+    service_types::{EUInformation, Range},
+    status_code::StatusCode,
+};
+
+use crate::address_space::{
+    AccessLevel, UserAccessLevel,
+    address_space::AddressSpace,
+    node::Node,
+    references::ReferenceDirection,
+    variable::Variable,
+};
+
+/// Builds a variable of `AnalogItemType`, a numeric value with an `EURange` property which is the
+/// range that the value normally lies within. Writes of values outside of the range fail with
+/// `BadOutOfRange`.
+pub struct AnalogItemBuilder {
+    node: Variable,
+    eu_range: Option<Range>,
+    instrument_range: Option<Range>,
+    engineering_units: Option<EUInformation>,
+}
+
+impl AnalogItemBuilder {
+    /// Creates a builder for an analog item. The data type of the item is inferred from the value,
+    /// which should be numeric.
+    pub fn new<R, S, V>(node_id: &NodeId, browse_name: R, display_name: S, value: V) -> AnalogItemBuilder
+        where R: Into<QualifiedName>,
+              S: Into<LocalizedText>,
+              V: Into<Variant>
+    {
+        AnalogItemBuilder {
+            node: Variable::new(node_id, browse_name, display_name, value),
+            eu_range: None,
+            instrument_range: None,
+            engineering_units: None,
+        }
+    }
+
+    /// Sets the `EURange` property. This property is mandatory.
+    pub fn eu_range(mut self, low: f64, high: f64) -> Self {
+        self.eu_range = Some(Range { low, high });
+        self
+    }
+
+    /// Sets the optional `InstrumentRange` property, the range of values that the instrument
+    /// producing the value can return
+    pub fn instrument_range(mut self, low: f64, high: f64) -> Self {
+        self.instrument_range = Some(Range { low, high });
+        self
+    }
+
+    /// Sets the optional `EngineeringUnits` property, the units that the value is measured in
+    pub fn engineering_units(mut self, engineering_units: EUInformation) -> Self {
+        self.engineering_units = Some(engineering_units);
+        self
+    }
+
+    pub fn description<V>(mut self, description: V) -> Self where V: Into<LocalizedText> {
+        self.node.set_description(description.into());
+        self
+    }
+
+    /// Makes the value writable by clients
+    pub fn writable(mut self) -> Self {
+        set_writable(&mut self.node);
+        self
+    }
+
+    /// Inserts the analog item and its properties into the address space, organized by the
+    /// parent. Fails if the item has no `EURange` or a node with its id already exists.
+    pub fn insert(self, address_space: &mut AddressSpace, parent_node_id: &NodeId) -> Result<NodeId, ()> {
+        if let Some(eu_range) = self.eu_range {
+            let mut properties: Vec<(&str, DataTypeId, Variant)> = vec![
+                ("EURange", DataTypeId::Range, ExtensionObject::from_encodable(ObjectId::Range_Encoding_DefaultBinary, &eu_range).into()),
+            ];
+            if let Some(instrument_range) = self.instrument_range {
+                properties.push(("InstrumentRange", DataTypeId::Range, ExtensionObject::from_encodable(ObjectId::Range_Encoding_DefaultBinary, &instrument_range).into()));
+            }
+            if let Some(engineering_units) = self.engineering_units {
+                properties.push(("EngineeringUnits", DataTypeId::EUInformation, ExtensionObject::from_encodable(ObjectId::EUInformation_Encoding_DefaultBinary, &engineering_units).into()));
+            }
+            insert_item(address_space, self.node, VariableTypeId::AnalogItemType, parent_node_id, properties)
+        } else {
+            error!("Analog item {:?} cannot be inserted without an EURange", self.node.node_id());
+            Err(())
+        }
+    }
+}
+
+/// Builds a variable of `TwoStateDiscreteType`, a boolean value with `TrueState` and `FalseState`
+/// properties which name its two states.
+pub struct TwoStateDiscreteBuilder {
+    node: Variable,
+    true_state: LocalizedText,
+    false_state: LocalizedText,
+}
+
+impl TwoStateDiscreteBuilder {
+    /// Creates a builder for a two-state discrete item with the names of its states. The value is
+    /// initially false.
+    pub fn new<R, S, T, F>(node_id: &NodeId, browse_name: R, display_name: S, true_state: T, false_state: F) -> TwoStateDiscreteBuilder
+        where R: Into<QualifiedName>,
+              S: Into<LocalizedText>,
+              T: Into<LocalizedText>,
+              F: Into<LocalizedText>
+    {
+        TwoStateDiscreteBuilder {
+            node: Variable::new(node_id, browse_name, display_name, false),
+            true_state: true_state.into(),
+            false_state: false_state.into(),
+        }
+    }
+
+    pub fn value(mut self, value: bool) -> Self {
+        self.node.set_value(value);
+        self
+    }
+
+    pub fn description<V>(mut self, description: V) -> Self where V: Into<LocalizedText> {
+        self.node.set_description(description.into());
+        self
+    }
+
+    /// Makes the value writable by clients
+    pub fn writable(mut self) -> Self {
+        set_writable(&mut self.node);
+        self
+    }
+
+    /// Inserts the discrete item and its properties into the address space, organized by the
+    /// parent. Fails if a node with its id already exists.
+    pub fn insert(self, address_space: &mut AddressSpace, parent_node_id: &NodeId) -> Result<NodeId, ()> {
+        let properties: Vec<(&str, DataTypeId, Variant)> = vec![
+            ("TrueState", DataTypeId::LocalizedText, self.true_state.into()),
+            ("FalseState", DataTypeId::LocalizedText, self.false_state.into()),
+        ];
+        insert_item(address_space, self.node, VariableTypeId::TwoStateDiscreteType, parent_node_id, properties)
+    }
+}
+
+/// Builds a variable of `MultiStateDiscreteType`, an unsigned integer value which is the index of
+/// its state in the `EnumStrings` property. Writes of values which are not the index of a state
+/// fail with `BadOutOfRange`.
+pub struct MultiStateDiscreteBuilder {
+    node: Variable,
+    enum_strings: Vec<LocalizedText>,
+}
+
+impl MultiStateDiscreteBuilder {
+    /// Creates a builder for a multi-state discrete item with the names of its states. The value
+    /// is initially 0, the first state.
+    pub fn new<R, S>(node_id: &NodeId, browse_name: R, display_name: S, enum_strings: Vec<LocalizedText>) -> MultiStateDiscreteBuilder
+        where R: Into<QualifiedName>,
+              S: Into<LocalizedText>
+    {
+        MultiStateDiscreteBuilder {
+            node: Variable::new(node_id, browse_name, display_name, 0u32),
+            enum_strings,
+        }
+    }
+
+    pub fn value(mut self, value: u32) -> Self {
+        self.node.set_value(value);
+        self
+    }
+
+    pub fn description<V>(mut self, description: V) -> Self where V: Into<LocalizedText> {
+        self.node.set_description(description.into());
+        self
+    }
+
+    /// Makes the value writable by clients
+    pub fn writable(mut self) -> Self {
+        set_writable(&mut self.node);
+        self
+    }
+
+    /// Inserts the discrete item and its properties into the address space, organized by the
+    /// parent. Fails if a node with its id already exists.
+    pub fn insert(self, address_space: &mut AddressSpace, parent_node_id: &NodeId) -> Result<NodeId, ()> {
+        let enum_strings = self.enum_strings.into_iter().map(Variant::from).collect::<Vec<Variant>>();
+        let properties = vec![
+            ("EnumStrings", DataTypeId::LocalizedText, Variant::from(enum_strings)),
+        ];
+        insert_item(address_space, self.node, VariableTypeId::MultiStateDiscreteType, parent_node_id, properties)
+    }
+}
+
+fn set_writable(node: &mut Variable) {
+    node.set_access_level(AccessLevel::CURRENT_READ | AccessLevel::CURRENT_WRITE);
+    node.set_user_access_level(UserAccessLevel::CURRENT_READ | UserAccessLevel::CURRENT_WRITE);
+}
+
+/// Inserts the item with a reference to its type definition, followed by its properties
+fn insert_item(address_space: &mut AddressSpace, node: Variable, type_definition_id: VariableTypeId, parent_node_id: &NodeId, properties: Vec<(&str, DataTypeId, Variant)>) -> Result<NodeId, ()> {
+    let node_id = node.node_id();
+    let type_definition_id: NodeId = type_definition_id.into();
+    if address_space.node_exists(&node_id) {
+        error!("Data access item {:?} already exists", node_id);
+        return Err(());
+    }
+    address_space.insert(node, Some(&[
+        (parent_node_id, ReferenceTypeId::Organizes, ReferenceDirection::Inverse),
+        (&type_definition_id, ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+    ]));
+    for (browse_name, data_type, value) in properties {
+        let property_id = NodeId::next_numeric();
+        address_space.insert(Variable::new_data_value(&property_id, browse_name, browse_name, data_type, value), Some(&[
+            (&node_id, ReferenceTypeId::HasProperty, ReferenceDirection::Inverse),
+            (&VariableTypeId::PropertyType.into(), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+        ]));
+    }
+    Ok(node_id)
+}
+
+/// Tests if a value written to a variable is within the `EURange` of an analog item, or is the
+/// index of a state in the `EnumStrings` of a multi-state discrete item. Every element of an array
+/// is tested. Values of variables without either property are always acceptable.
+pub(crate) fn validate_value(address_space: &AddressSpace, node_id: &NodeId, value: &Variant) -> Result<(), StatusCode> {
+    let in_range = if let Some((low, high)) = address_space.eu_range(node_id) {
+        all_values(value, |v| v.as_f64().map(|v| v >= low && v <= high).unwrap_or(true))
+    } else if let Some(enum_strings) = address_space.enum_strings(node_id) {
+        let states = enum_strings.len() as f64;
+        all_values(value, |v| v.as_f64().map(|v| v >= 0f64 && v < states).unwrap_or(true))
+    } else {
+        true
+    };
+    if in_range {
+        Ok(())
+    } else {
+        Err(StatusCode::BadOutOfRange)
+    }
+}
+
+/// Tests the predicate against the value, or each element of the value if it is an array
+fn all_values<F>(value: &Variant, f: F) -> bool where F: Fn(&Variant) -> bool {
+    match value {
+        Variant::Array(values) => values.iter().all(f),
+        Variant::MultiDimensionArray(mda) => mda.values.iter().all(f),
+        value => f(value),
+    }
+}
@@ -30,6 +30,12 @@ mod nodeset_999;
 
 /// Populates the address space with all defined node sets
 pub fn populate_address_space(address_space: &mut AddressSpace) {
+    populate_core_address_space(address_space);
+    populate_extended_address_space(address_space);
+}
+
+/// Populates the address space with the core node sets - the base type system and the Server object
+pub fn populate_core_address_space(address_space: &mut AddressSpace) {
     nodeset_3::populate_address_space(address_space);
     nodeset_4_1::populate_address_space(address_space);
     nodeset_4_2::populate_address_space(address_space);
@@ -44,6 +50,10 @@ pub fn populate_address_space(address_space: &mut AddressSpace) {
     nodeset_5_9::populate_address_space(address_space);
     nodeset_5_10::populate_address_space(address_space);
     nodeset_5_11::populate_address_space(address_space);
+}
+
+/// Populates the address space with the remaining, non-core node sets
+pub fn populate_extended_address_space(address_space: &mut AddressSpace) {
     nodeset_8::populate_address_space(address_space);
     nodeset_9_1::populate_address_space(address_space);
     nodeset_9_2::populate_address_space(address_space);
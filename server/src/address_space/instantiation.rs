@@ -0,0 +1,191 @@
+//! Creates instances of object and variable types. The instance gets a copy of each instance
+//! declaration of its type, i.e. each component or property of the type or its supertypes whose
+//! modelling rule is `Mandatory`, and each copy is instantiated from its own type in turn.
+
+use std::collections::HashSet;
+
+use opcua_types::{
+    *,
+    node_ids::{ObjectId, ObjectTypeId, ReferenceTypeId},
+    status_code::StatusCode,
+};
+
+use crate::address_space::{
+    AddressSpace,
+    node::{Node, NodeType},
+    object::Object,
+    references::ReferenceDirection,
+    variable::Variable,
+};
+
+/// Instance declarations nested deeper than this are not instantiated, which stops a type whose
+/// declarations are of the type itself from recursing forever
+const MAX_INSTANTIATION_DEPTH: usize = 32;
+
+/// A child of a type, or of another instance declaration, which is copied into instances
+struct InstanceDeclaration {
+    node_id: NodeId,
+    reference_type_id: ReferenceTypeId,
+}
+
+pub(crate) fn instantiate(address_space: &mut AddressSpace, type_id: &NodeId, browse_name: QualifiedName, parent_node_id: &NodeId) -> Result<NodeId, StatusCode> {
+    if !address_space.node_exists(parent_node_id) {
+        error!("Cannot instantiate {:?} under {:?}, the parent does not exist", type_id, parent_node_id);
+        return Err(StatusCode::BadParentNodeIdInvalid);
+    }
+
+    let node_id = NodeId::next_numeric();
+    let display_name = browse_name.name.as_ref().to_string();
+    let instance: NodeType = match address_space.find_node(type_id) {
+        Some(NodeType::ObjectType(object_type)) if !object_type.is_abstract() => {
+            Object::new(&node_id, browse_name, display_name, 0).into()
+        }
+        Some(NodeType::VariableType(variable_type)) if !variable_type.is_abstract() => {
+            let value = variable_type.value().and_then(|v| v.value).unwrap_or(Variant::Empty);
+            let mut variable = Variable::new_data_value(&node_id, browse_name, display_name, variable_type.data_type(), value);
+            variable.set_value_rank(variable_type.value_rank());
+            if let Some(array_dimensions) = variable_type.array_dimensions() {
+                variable.set_array_dimensions(&array_dimensions);
+            }
+            variable.into()
+        }
+        _ => {
+            error!("Cannot instantiate {:?}, it is not a concrete object or variable type", type_id);
+            return Err(StatusCode::BadTypeDefinitionInvalid);
+        }
+    };
+
+    // Folders organize their contents, anything else has the instance as a component
+    let reference_type_id = match address_space.get_type_id(parent_node_id) {
+        Some(ref parent_type_id) if address_space.is_subtype(parent_type_id, &ObjectTypeId::FolderType.into()) => ReferenceTypeId::Organizes,
+        _ => ReferenceTypeId::HasComponent
+    };
+    address_space.insert(instance, Some(&[
+        (parent_node_id, reference_type_id, ReferenceDirection::Inverse),
+        (type_id, ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+    ]));
+
+    let sources = supertypes(address_space, type_id);
+    instantiate_declarations(address_space, &node_id, &sources, 0);
+    Ok(node_id)
+}
+
+/// Copies the instance declarations of the sources into the instance
+fn instantiate_declarations(address_space: &mut AddressSpace, instance_id: &NodeId, sources: &[NodeId], depth: usize) {
+    if depth >= MAX_INSTANTIATION_DEPTH {
+        error!("Instance declarations of {:?} are nested too deeply to instantiate", instance_id);
+        return;
+    }
+    for declaration in instance_declarations(address_space, sources) {
+        let node_id = NodeId::next_numeric();
+        let copy: NodeType = match address_space.find_node(&declaration.node_id) {
+            Some(NodeType::Object(object)) => {
+                let mut copy = Object::new(&node_id, object.browse_name(), object.display_name(), object.event_notifier());
+                if let Some(description) = object.description() {
+                    copy.set_description(description);
+                }
+                copy.into()
+            }
+            Some(NodeType::Variable(variable)) => {
+                let value = variable.value().value.unwrap_or(Variant::Empty);
+                let mut copy = Variable::new_data_value(&node_id, variable.browse_name(), variable.display_name(), variable.data_type(), value);
+                copy.set_value_rank(variable.value_rank());
+                if let Some(array_dimensions) = variable.array_dimensions() {
+                    copy.set_array_dimensions(&array_dimensions);
+                }
+                copy.set_access_level(variable.access_level());
+                copy.set_user_access_level(variable.user_access_level());
+                if let Some(minimum_sampling_interval) = variable.minimum_sampling_interval() {
+                    copy.set_minimum_sampling_interval(minimum_sampling_interval);
+                }
+                if let Some(description) = variable.description() {
+                    copy.set_description(description);
+                }
+                copy.into()
+            }
+            Some(NodeType::Method(_)) => {
+                // Methods are shared by the instances rather than copied, so a method handler
+                // registered for the method of the type works for each instance
+                address_space.insert_reference(instance_id, &declaration.node_id, declaration.reference_type_id);
+                continue;
+            }
+            _ => continue
+        };
+
+        let type_id = address_space.get_type_id(&declaration.node_id);
+        if let Some(ref type_id) = type_id {
+            address_space.insert(copy, Some(&[
+                (instance_id, declaration.reference_type_id, ReferenceDirection::Inverse),
+                (type_id, ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+            ]));
+        } else {
+            address_space.insert(copy, Some(&[
+                (instance_id, declaration.reference_type_id, ReferenceDirection::Inverse),
+            ]));
+        }
+
+        // The children of the declaration override those of its type
+        let mut sources = vec![declaration.node_id];
+        if let Some(ref type_id) = type_id {
+            sources.extend(supertypes(address_space, type_id));
+        }
+        instantiate_declarations(address_space, &node_id, &sources, depth + 1);
+    }
+}
+
+/// Returns the type followed by its supertypes, most derived first
+fn supertypes(address_space: &AddressSpace, type_id: &NodeId) -> Vec<NodeId> {
+    let mut result = vec![type_id.clone()];
+    let mut type_id = type_id.clone();
+    while let Some(supertype) = address_space.find_references_to(&type_id, Some((ReferenceTypeId::HasSubtype, false)))
+        .and_then(|references| references.first().map(|r| r.target_node_id.clone())) {
+        if result.contains(&supertype) {
+            break;
+        }
+        result.push(supertype.clone());
+        type_id = supertype;
+    }
+    result
+}
+
+/// Finds the mandatory instance declarations of the sources. A child of an earlier source hides
+/// the children of later sources with the same browse name, so a subtype can override or make
+/// optional a declaration of its supertype. Optional and placeholder declarations are not
+/// instantiated.
+fn instance_declarations(address_space: &AddressSpace, sources: &[NodeId]) -> Vec<InstanceDeclaration> {
+    let mut browse_names = HashSet::new();
+    let mut result = Vec::new();
+    for source in sources {
+        let references = address_space.find_references_from(source, None).unwrap_or_default();
+        for reference in references {
+            let reference_type_id = match reference.reference_type_id.as_reference_type_id() {
+                Ok(reference_type_id @ ReferenceTypeId::HasComponent) |
+                Ok(reference_type_id @ ReferenceTypeId::HasOrderedComponent) |
+                Ok(reference_type_id @ ReferenceTypeId::HasProperty) => reference_type_id,
+                _ => continue
+            };
+            let modelling_rule = match modelling_rule(address_space, &reference.target_node_id) {
+                Some(modelling_rule) => modelling_rule,
+                None => continue
+            };
+            let browse_name = match address_space.find_node(&reference.target_node_id) {
+                Some(node) => node.as_node().browse_name(),
+                None => continue
+            };
+            if browse_names.insert((browse_name.namespace_index, browse_name.name)) && modelling_rule == ObjectId::ModellingRule_Mandatory {
+                result.push(InstanceDeclaration {
+                    node_id: reference.target_node_id,
+                    reference_type_id,
+                });
+            }
+        }
+    }
+    result
+}
+
+/// Returns the modelling rule of the node, or `None` if it has no rule, i.e. it is not an
+/// instance declaration
+fn modelling_rule(address_space: &AddressSpace, node_id: &NodeId) -> Option<ObjectId> {
+    address_space.find_references_from(node_id, Some((ReferenceTypeId::HasModellingRule, false)))
+        .and_then(|references| references.first().and_then(|r| r.target_node_id.as_object_id().ok()))
+}
@@ -1,8 +1,106 @@
-use opcua_types::service_types::MethodAttributes;
+use std::mem;
 
-use crate::address_space::{base::Base, node::Node, node::NodeAttributes};
+use opcua_types::{
+    node_ids::ReferenceTypeId,
+    service_types::MethodAttributes,
+};
 
-#[derive(Debug)]
+use crate::{
+    address_space::{AddressSpace, base::Base, node::Node, node::NodeAttributes, references::ReferenceDirection},
+    callbacks,
+};
+
+/// This is a builder object for constructing method nodes programmatically. The method is
+/// executable unless the builder says otherwise.
+pub struct MethodBuilder {
+    node: Method,
+    references: Vec<(NodeId, ReferenceTypeId, ReferenceDirection)>,
+    input_arguments: Vec<Argument>,
+    output_arguments: Vec<Argument>,
+    callback: Option<Box<dyn callbacks::Method + Send + Sync>>,
+}
+
+node_builder_impl!(MethodBuilder);
+
+impl MethodBuilder {
+    pub fn new(node_id: &NodeId) -> MethodBuilder {
+        MethodBuilder {
+            node: Method::new(&NodeId::null(), "", "", true, true),
+            references: Vec::new(),
+            input_arguments: Vec::new(),
+            output_arguments: Vec::new(),
+            callback: None,
+        }.node_id(node_id.clone())
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.node.node_id().is_null()
+    }
+
+    pub fn executable(mut self, executable: bool) -> Self {
+        self.node.set_executable(executable);
+        self
+    }
+
+    pub fn user_executable(mut self, user_executable: bool) -> Self {
+        self.node.set_user_executable(user_executable);
+        self
+    }
+
+    /// Sets the arguments of the method's `InputArguments` property
+    pub fn input_args(mut self, input_arguments: &[Argument]) -> Self {
+        self.input_arguments = input_arguments.to_vec();
+        self
+    }
+
+    /// Sets the arguments of the method's `OutputArguments` property
+    pub fn output_args(mut self, output_arguments: &[Argument]) -> Self {
+        self.output_arguments = output_arguments.to_vec();
+        self
+    }
+
+    /// Sets the handler of calls to the method. The handler is registered for the object that
+    /// the method is a component of, so the builder must also be given `component_of()`.
+    pub fn callback(mut self, callback: Box<dyn callbacks::Method + Send + Sync>) -> Self {
+        self.callback = Some(callback);
+        self
+    }
+
+    /// Builds the method and inserts it into the address space with its references, argument
+    /// properties and handler. Fails if the method is invalid, a node with the same id already
+    /// exists, or it has a handler but is not the component of an object.
+    pub fn insert(mut self, address_space: &mut AddressSpace) -> Result<NodeId, ()> {
+        let object_id = self.references.iter()
+            .find(|(_, reference_type_id, reference_direction)| {
+                *reference_type_id == ReferenceTypeId::HasComponent && if let ReferenceDirection::Inverse = reference_direction { true } else { false }
+            })
+            .map(|(object_id, _, _)| object_id.clone());
+        if !self.is_valid() {
+            error!("The method is not valid, node id = {:?}", self.node.node_id());
+            Err(())
+        } else if self.callback.is_some() && !object_id.as_ref().map_or(false, |object_id| if let Some(NodeType::Object(_)) = address_space.find_node(object_id) { true } else { false }) {
+            error!("The method {:?} has a callback but is not the component of an object", self.node.node_id());
+            Err(())
+        } else {
+            let input_arguments = mem::replace(&mut self.input_arguments, Vec::new());
+            let output_arguments = mem::replace(&mut self.output_arguments, Vec::new());
+            let callback = self.callback.take();
+            let method_id = self.insert_node(address_space)?;
+            if !input_arguments.is_empty() {
+                address_space.add_method_arguments(&method_id, "InputArguments", &input_arguments);
+            }
+            if !output_arguments.is_empty() {
+                address_space.add_method_arguments(&method_id, "OutputArguments", &output_arguments);
+            }
+            if let (Some(callback), Some(object_id)) = (callback, object_id) {
+                address_space.register_method_handler(object_id, method_id.clone(), callback);
+            }
+            Ok(method_id)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Method {
     base: Base,
     executable: bool,
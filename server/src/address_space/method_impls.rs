@@ -1,10 +1,13 @@
 use opcua_types::*;
+use opcua_types::node_ids::{ObjectId, ObjectTypeId};
 use opcua_types::status_code::StatusCode;
 use opcua_types::service_types::{CallMethodRequest, CallMethodResult};
 
 use crate::{
+    address_space::AddressSpace,
     session::Session,
     callbacks::Method,
+    events::{event::Event, condition::Condition},
 };
 
 /// Count the number of provided input arguments, comparing them to the expected number.
@@ -120,4 +123,93 @@ impl Method for ServerGetMonitoredItemsMethod {
             Err(StatusCode::BadSubscriptionIdInvalid)
         }
     }
-}
\ No newline at end of file
+}
+/// Handles the ConditionType.ConditionRefresh method call. The events of the retained conditions
+/// are queued on the event monitored items of the subscription between a RefreshStartEvent and a
+/// RefreshEndEvent.
+pub(crate) fn condition_refresh(session: &mut Session, address_space: &AddressSpace, events: Vec<Event>, request: &CallMethodRequest) -> Result<CallMethodResult, StatusCode> {
+    debug!("Method handler for ConditionRefresh");
+
+    // OPC UA part 9 - ConditionRefresh([in] UInt32 subscriptionId);
+    //
+    // subscriptionId - Identifier of the subscription that is to be refreshed
+    //
+    // Return codes
+    //
+    // BadSubscriptionIdInvalid
+    // BadRefreshInProgress
+    // BadUserAccessDenied
+
+    ensure_input_argument_count(request, 1)?;
+
+    let subscription_id = get_input_argument!(request, 0, UInt32)?;
+
+    if let Some(subscription) = session.subscriptions.get_mut(*subscription_id) {
+        let refresh_start = Event::new(ObjectTypeId::RefreshStartEventType, ObjectId::Server, "Server", "", 100);
+        let refresh_end = Event::new(ObjectTypeId::RefreshEndEventType, ObjectId::Server, "Server", "", 100);
+        let mut refresh_events = Vec::with_capacity(events.len() + 2);
+        refresh_events.push(refresh_start);
+        refresh_events.extend(events);
+        refresh_events.push(refresh_end);
+        subscription.refresh_events(address_space, &refresh_events);
+        Ok(CallMethodResult {
+            status_code: StatusCode::Good,
+            input_argument_results: Some(vec![StatusCode::Good]),
+            input_argument_diagnostic_infos: None,
+            output_arguments: None,
+        })
+    } else {
+        Err(StatusCode::BadSubscriptionIdInvalid)
+    }
+}
+
+/// Handles the Enable, Disable, AddComment, Acknowledge and Confirm method calls on a condition.
+pub(crate) fn condition_method(condition: &mut Condition, method_name: &str, request: &CallMethodRequest) -> Result<CallMethodResult, StatusCode> {
+    debug!("Method handler for condition method {}", method_name);
+
+    // OPC UA part 9
+    //
+    // Enable();
+    // Disable();
+    // AddComment([in] ByteString EventId, [in] LocalizedText Comment);
+    // Acknowledge([in] ByteString EventId, [in] LocalizedText Comment);
+    // Confirm([in] ByteString EventId, [in] LocalizedText Comment);
+    //
+    // Return codes
+    //
+    // BadConditionAlreadyEnabled
+    // BadConditionAlreadyDisabled
+    // BadConditionDisabled
+    // BadEventIdUnknown
+    // BadConditionBranchAlreadyAcked
+    // BadConditionBranchAlreadyConfirmed
+
+    let input_argument_results = match method_name {
+        "Enable" | "Disable" => {
+            ensure_input_argument_count(request, 0)?;
+            if method_name == "Enable" { condition.enable()?; } else { condition.disable()?; }
+            None
+        }
+        "AddComment" | "Acknowledge" | "Confirm" => {
+            ensure_input_argument_count(request, 2)?;
+            let event_id = get_input_argument!(request, 0, ByteString)?;
+            let comment = get_input_argument!(request, 1, LocalizedText)?;
+            let comment = (**comment).clone();
+            match method_name {
+                "AddComment" => condition.add_comment(event_id, comment)?,
+                "Acknowledge" => condition.acknowledge(event_id, comment)?,
+                _ => condition.confirm(event_id, comment)?,
+            }
+            Some(vec![StatusCode::Good, StatusCode::Good])
+        }
+        _ => {
+            return Err(StatusCode::BadMethodInvalid);
+        }
+    };
+    Ok(CallMethodResult {
+        status_code: StatusCode::Good,
+        input_argument_results,
+        input_argument_diagnostic_infos: None,
+        output_arguments: None,
+    })
+}
@@ -3,10 +3,14 @@
 
 use std::result::Result;
 
-use opcua_types::{NodeId, AttributeId, DataValue};
+use futures::IntoFuture;
+
+use opcua_types::{NodeId, AttributeId, DataValue, Variant};
 use opcua_types::status_code::StatusCode;
+use opcua_types::service_types::{CallMethodRequest, CallMethodResult};
 
-use crate::callbacks::{AttributeGetter, AttributeSetter};
+use crate::callbacks::{AttributeGetter, AttributeSetter, AsyncAttributeGetter, AsyncAttributeSetter, AsyncAttributeValue, AsyncAttributeResult, Method};
+use crate::session::Session;
 
 /// An implementation of attribute getter that can be easily constructed from a mutable function
 pub struct AttrFnGetter<F> where F: FnMut(&NodeId, AttributeId, f64) -> Result<Option<DataValue>, StatusCode> + Send {
@@ -38,6 +42,67 @@ impl<F> AttrFnSetter<F> where F: FnMut(&NodeId, AttributeId, DataValue) -> Resul
     pub fn new(setter: F) -> AttrFnSetter<F> { AttrFnSetter { setter } }
 }
 
+/// An implementation of async attribute getter that can be easily constructed from a mutable
+/// function returning a future, or anything else which turns into one such as a `Result`
+pub struct AttrFnAsyncGetter<F> {
+    getter: F
+}
+
+impl<F, R> AsyncAttributeGetter for AttrFnAsyncGetter<F> where F: FnMut(&NodeId, AttributeId, f64) -> R + Send,
+                                                               R: IntoFuture<Item=Option<DataValue>, Error=StatusCode>,
+                                                               R::Future: Send + 'static {
+    fn get(&mut self, node_id: &NodeId, attribute_id: AttributeId, max_age: f64) -> AsyncAttributeValue {
+        Box::new((self.getter)(node_id, attribute_id, max_age).into_future())
+    }
+}
+
+impl<F> AttrFnAsyncGetter<F> {
+    pub fn new(getter: F) -> AttrFnAsyncGetter<F> { AttrFnAsyncGetter { getter } }
+}
+
+/// An implementation of async attribute setter that can be easily constructed from a mutable
+/// function returning a future, or anything else which turns into one such as a `Result`
+pub struct AttrFnAsyncSetter<F> {
+    setter: F
+}
+
+impl<F, R> AsyncAttributeSetter for AttrFnAsyncSetter<F> where F: FnMut(&NodeId, AttributeId, DataValue) -> R + Send,
+                                                               R: IntoFuture<Item=(), Error=StatusCode>,
+                                                               R::Future: Send + 'static {
+    fn set(&mut self, node_id: &NodeId, attribute_id: AttributeId, data_value: DataValue) -> AsyncAttributeResult {
+        Box::new((self.setter)(node_id, attribute_id, data_value).into_future())
+    }
+}
+
+impl<F> AttrFnAsyncSetter<F> {
+    pub fn new(setter: F) -> AttrFnAsyncSetter<F> { AttrFnAsyncSetter { setter } }
+}
+
+/// An implementation of a method handler that can be easily constructed from a mutable function.
+/// The function is called with the input arguments and returns the output arguments. The input
+/// arguments have already been validated against the method's `InputArguments` property and the
+/// output arguments are validated against its `OutputArguments` property.
+pub struct MethodFn<F> where F: FnMut(&[Variant]) -> Result<Vec<Variant>, StatusCode> + Send + Sync {
+    method: F
+}
+
+impl<F> Method for MethodFn<F> where F: FnMut(&[Variant]) -> Result<Vec<Variant>, StatusCode> + Send + Sync {
+    fn call(&mut self, _session: &mut Session, request: &CallMethodRequest) -> Result<CallMethodResult, StatusCode> {
+        let input_arguments = request.input_arguments.as_ref().map(|v| v.as_slice()).unwrap_or(&[]);
+        let output_arguments = (self.method)(input_arguments)?;
+        Ok(CallMethodResult {
+            status_code: StatusCode::Good,
+            input_argument_results: Some(vec![StatusCode::Good; input_arguments.len()]),
+            input_argument_diagnostic_infos: None,
+            output_arguments: Some(output_arguments),
+        })
+    }
+}
+
+impl<F> MethodFn<F> where F: FnMut(&[Variant]) -> Result<Vec<Variant>, StatusCode> + Send + Sync {
+    pub fn new(method: F) -> MethodFn<F> { MethodFn { method } }
+}
+
 /// This is a sanity saving macro that adds Node trait methods to all types that have a base
 /// member.
 macro_rules! node_impl {
@@ -103,6 +168,88 @@ macro_rules! node_impl {
             fn set_user_write_mask(&mut self, user_write_mask: WriteMask) {
                 self.base_mut().set_user_write_mask(user_write_mask)
             }
+
+            fn role_permissions(&self) -> Option<&[RolePermissionType]> {
+                self.base().role_permissions()
+            }
+
+            fn set_role_permissions(&mut self, role_permissions: Vec<RolePermissionType>) {
+                self.base_mut().set_role_permissions(role_permissions)
+            }
+        }
+    }
+}
+
+/// Adds the common builder methods to node builders. Builders have a node member and a list of
+/// the references to insert with the node.
+macro_rules! node_builder_impl {
+    ( $node_builder_struct:ident ) => {
+        impl $node_builder_struct {
+            fn node_id(mut self, node_id: NodeId) -> Self {
+                let _ = self.node.base.set_node_id(node_id);
+                self
+            }
+
+            pub fn browse_name<V>(mut self, browse_name: V) -> Self where V: Into<QualifiedName> {
+                let _ = self.node.base.set_browse_name(browse_name);
+                self
+            }
+
+            pub fn display_name<V>(mut self, display_name: V) -> Self where V: Into<LocalizedText> {
+                self.node.set_display_name(display_name.into());
+                self
+            }
+
+            pub fn description<V>(mut self, description: V) -> Self where V: Into<LocalizedText>{
+                self.node.set_description(description.into());
+                self
+            }
+
+            pub fn write_mask(mut self, write_mask: WriteMask) -> Self {
+                self.node.set_write_mask(write_mask);
+                self
+            }
+
+            /// Adds a reference to or from the node, which is inserted with the node
+            pub fn reference(mut self, node_id: &NodeId, reference_type_id: opcua_types::node_ids::ReferenceTypeId, reference_direction: crate::address_space::references::ReferenceDirection) -> Self {
+                self.references.push((node_id.clone(), reference_type_id, reference_direction));
+                self
+            }
+
+            /// The node is organized by the parent, e.g. a folder
+            pub fn organized_by(self, parent_node_id: &NodeId) -> Self {
+                self.reference(parent_node_id, opcua_types::node_ids::ReferenceTypeId::Organizes, crate::address_space::references::ReferenceDirection::Inverse)
+            }
+
+            /// The node is a component of the parent
+            pub fn component_of(self, parent_node_id: &NodeId) -> Self {
+                self.reference(parent_node_id, opcua_types::node_ids::ReferenceTypeId::HasComponent, crate::address_space::references::ReferenceDirection::Inverse)
+            }
+
+            /// The node is a property of the parent
+            pub fn property_of(self, parent_node_id: &NodeId) -> Self {
+                self.reference(parent_node_id, opcua_types::node_ids::ReferenceTypeId::HasProperty, crate::address_space::references::ReferenceDirection::Inverse)
+            }
+
+            pub fn has_type_definition<T>(self, type_id: T) -> Self where T: Into<NodeId> {
+                self.reference(&type_id.into(), opcua_types::node_ids::ReferenceTypeId::HasTypeDefinition, crate::address_space::references::ReferenceDirection::Forward)
+            }
+
+            /// Inserts the node and its references into the address space. Fails if a node with
+            /// the same id already exists.
+            fn insert_node(self, address_space: &mut crate::address_space::AddressSpace) -> Result<NodeId, ()> {
+                let node_id = self.node.node_id();
+                if address_space.node_exists(&node_id) {
+                    error!("Node {:?} cannot be inserted because it already exists", node_id);
+                    Err(())
+                } else {
+                    let references = self.references.iter()
+                        .map(|(node_id, reference_type_id, reference_direction)| (node_id, *reference_type_id, reference_direction.clone()))
+                        .collect::<Vec<_>>();
+                    address_space.insert(self.node, Some(&references));
+                    Ok(node_id)
+                }
+            }
         }
     }
 }
@@ -119,7 +266,11 @@ pub mod object_type;
 pub mod variable_type;
 pub mod data_type;
 pub mod view;
+pub mod data_access;
+pub mod shared;
 mod references;
+mod nodeset_export;
+mod instantiation;
 
 #[cfg(feature = "generated-address-space")]
 mod generated;
@@ -130,9 +281,9 @@ bitflags! {
     pub struct AccessLevel: u8 {
         const CURRENT_READ = 1;
         const CURRENT_WRITE = 2;
+        const HISTORY_READ = 4;
+        const HISTORY_WRITE = 8;
         // These can be uncommented if they become used
-        // const HISTORY_READ = 4;
-        // const HISTORY_WRITE = 8;
         // const SEMANTIC_CHANGE = 16;
         // const STATUS_WRITE = 32;
         // const TIMESTAMP_WRITE = 64;
@@ -143,9 +294,9 @@ bitflags! {
     pub struct UserAccessLevel: u8 {
         const CURRENT_READ = 1;
         const CURRENT_WRITE = 2;
+        const HISTORY_READ = 4;
+        const HISTORY_WRITE = 8;
         // These can be uncommented if they become used
-        // const HISTORY_READ = 4;
-        // const HISTORY_WRITE = 8;
         // const STATUS_WRITE = 32;
         // const TIMESTAMP_WRITE = 64;
     }
@@ -153,18 +304,21 @@ bitflags! {
 
 pub mod types {
     pub use super::base::Base;
-    pub use super::{AttrFnGetter, AttrFnSetter};
+    pub use super::{AttrFnGetter, AttrFnSetter, AttrFnAsyncGetter, AttrFnAsyncSetter, MethodFn};
     pub use super::address_space::AddressSpace;
+    pub use super::shared::SharedAddressSpace;
     pub use super::references::ReferenceDirection;
     pub use super::data_type::DataType;
-    pub use super::object::Object;
+    pub use super::object::{ObjectBuilder, Object};
     pub use super::variable::{VariableBuilder, Variable};
-    pub use super::method::Method;
+    pub use super::method::{MethodBuilder, Method};
     pub use super::reference_type::ReferenceType;
     pub use super::object_type::ObjectType;
     pub use super::variable_type::VariableType;
     pub use super::view::View;
+    pub use super::data_access::{AnalogItemBuilder, TwoStateDiscreteBuilder, MultiStateDiscreteBuilder};
     pub use super::node::{Node, NodeType};
 }
 
 pub use self::address_space::AddressSpace;
+pub use self::shared::SharedAddressSpace;
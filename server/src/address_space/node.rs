@@ -1,5 +1,5 @@
 use opcua_types::{
-    NodeId, QualifiedName, LocalizedText, AttributeId, DataValue, WriteMask, Variant,
+    NodeId, QualifiedName, LocalizedText, AttributeId, DataValue, WriteMask, Variant, RolePermissionType,
     service_types::NodeClass,
     status_code::StatusCode,
 };
@@ -44,6 +44,21 @@ impl NodeType {
         }
     }
 
+    /// Copies the node into another snapshot of the address space, so it can be modified without
+    /// changing the node in the other snapshots. A variable shares its value with the copy.
+    pub(crate) fn copy_for_snapshot(&self) -> NodeType {
+        match *self {
+            NodeType::Object(ref value) => NodeType::Object(value.clone()),
+            NodeType::ObjectType(ref value) => NodeType::ObjectType(value.clone()),
+            NodeType::ReferenceType(ref value) => NodeType::ReferenceType(value.clone()),
+            NodeType::Variable(ref value) => NodeType::Variable(value.copy_for_snapshot()),
+            NodeType::VariableType(ref value) => NodeType::VariableType(value.clone()),
+            NodeType::View(ref value) => NodeType::View(value.clone()),
+            NodeType::DataType(ref value) => NodeType::DataType(value.clone()),
+            NodeType::Method(ref value) => NodeType::Method(value.clone()),
+        }
+    }
+
     pub fn as_mut_node(&mut self) -> &mut dyn NodeAttributes {
         match *self {
             NodeType::Object(ref mut value) => value,
@@ -86,6 +101,10 @@ pub trait Node {
     fn user_write_mask(&self) -> Option<WriteMask>;
 
     fn set_user_write_mask(&mut self, write_mask: WriteMask);
+
+    fn role_permissions(&self) -> Option<&[RolePermissionType]>;
+
+    fn set_role_permissions(&mut self, role_permissions: Vec<RolePermissionType>);
 }
 
 /// This trait is for the benefit of the Attributes service set - Read and Write. Internal
@@ -0,0 +1,316 @@
+//! Serializes nodes from the address space to a UANodeSet2 XML document so that models built in
+//! code can be consumed by modelling tools and other OPC UA stacks.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use opcua_types::*;
+
+use crate::address_space::{
+    AddressSpace,
+    node::{HasNodeId, Node, NodeType},
+};
+
+const NODESET_NAMESPACE: &str = "http://opcfoundation.org/UA/2011/03/UANodeSet.xsd";
+const TYPES_NAMESPACE: &str = "http://opcfoundation.org/UA/2008/02/Types.xsd";
+
+/// Maps the namespace indices of the address space onto the indices of the exported document. The
+/// document has its own NamespaceUris table where index 1 is the first URI in the table.
+struct NamespaceTable<'a> {
+    server_namespaces: &'a [String],
+    /// Server namespace indices in the order they appear in the document's table
+    exported: Vec<u16>,
+}
+
+impl<'a> NamespaceTable<'a> {
+    fn new(server_namespaces: &'a [String]) -> NamespaceTable<'a> {
+        NamespaceTable {
+            server_namespaces,
+            exported: Vec::new(),
+        }
+    }
+
+    fn index(&mut self, namespace: u16) -> u16 {
+        if namespace == 0 {
+            0
+        } else if let Some(idx) = self.exported.iter().position(|ns| *ns == namespace) {
+            (idx + 1) as u16
+        } else {
+            self.exported.push(namespace);
+            self.exported.len() as u16
+        }
+    }
+
+    fn node_id(&mut self, node_id: &NodeId) -> String {
+        let mut node_id = node_id.clone();
+        node_id.namespace = self.index(node_id.namespace);
+        node_id.to_string()
+    }
+
+    fn browse_name(&mut self, browse_name: &QualifiedName) -> String {
+        let namespace = self.index(browse_name.namespace_index);
+        if namespace == 0 {
+            browse_name.name.as_ref().to_string()
+        } else {
+            format!("{}:{}", namespace, browse_name.name.as_ref())
+        }
+    }
+
+    fn uris(&self) -> Vec<&str> {
+        self.exported.iter().map(|ns| {
+            self.server_namespaces.get(*ns as usize).map(|uri| uri.as_str()).unwrap_or("")
+        }).collect()
+    }
+}
+
+/// Escapes text for use in XML content and attribute values
+fn escape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    value.chars().for_each(|c| match c {
+        '&' => result.push_str("&amp;"),
+        '<' => result.push_str("&lt;"),
+        '>' => result.push_str("&gt;"),
+        '"' => result.push_str("&quot;"),
+        '\'' => result.push_str("&apos;"),
+        c => result.push(c),
+    });
+    result
+}
+
+/// Writes the nodes as a UANodeSet2 document. Nodes are written in the order supplied.
+pub(crate) fn export_nodeset(address_space: &AddressSpace, nodes: &[&NodeType]) -> String {
+    let mut namespaces = NamespaceTable::new(address_space.namespaces());
+    let exported_nodes = nodes.iter().map(|n| n.node_id()).collect::<HashSet<NodeId>>();
+
+    // Namespaces of the exported nodes come first in the document's table, in order of appearance
+    nodes.iter().for_each(|n| { let _ = namespaces.index(n.node_id().namespace); });
+
+    let mut body = String::new();
+    nodes.iter().for_each(|node| write_node(&mut body, &mut namespaces, address_space, &exported_nodes, node));
+
+    let mut xml = String::new();
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    let _ = writeln!(xml, r#"<UANodeSet xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns="{}">"#, NODESET_NAMESPACE);
+    let uris = namespaces.uris();
+    if !uris.is_empty() {
+        let _ = writeln!(xml, "  <NamespaceUris>");
+        uris.iter().for_each(|uri| { let _ = writeln!(xml, "    <Uri>{}</Uri>", escape(uri)); });
+        let _ = writeln!(xml, "  </NamespaceUris>");
+    }
+    xml.push_str(&body);
+    let _ = writeln!(xml, "</UANodeSet>");
+    xml
+}
+
+fn write_node(xml: &mut String, namespaces: &mut NamespaceTable, address_space: &AddressSpace, exported_nodes: &HashSet<NodeId>, node_type: &NodeType) {
+    let node = node_type.as_node();
+    let node_id = node.node_id();
+
+    // Attributes common to every node class
+    let mut attributes = format!(r#"NodeId="{}" BrowseName="{}""#,
+                                 escape(&namespaces.node_id(&node_id)), escape(&namespaces.browse_name(&node.browse_name())));
+    if let Some(write_mask) = node.write_mask() {
+        if !write_mask.is_empty() {
+            let _ = write!(attributes, r#" WriteMask="{}""#, write_mask.bits());
+        }
+    }
+    if let Some(user_write_mask) = node.user_write_mask() {
+        if !user_write_mask.is_empty() {
+            let _ = write!(attributes, r#" UserWriteMask="{}""#, user_write_mask.bits());
+        }
+    }
+
+    // Attributes and values specific to the node class
+    let mut value = None;
+    let mut inverse_name = None;
+    let element = match node_type {
+        NodeType::Object(ref node) => {
+            if node.event_notifier() != 0 {
+                let _ = write!(attributes, r#" EventNotifier="{}""#, node.event_notifier());
+            }
+            "UAObject"
+        }
+        NodeType::ObjectType(ref node) => {
+            if node.is_abstract() {
+                attributes.push_str(r#" IsAbstract="true""#);
+            }
+            "UAObjectType"
+        }
+        NodeType::Variable(ref node) => {
+            let _ = write!(attributes, r#" DataType="{}""#, escape(&namespaces.node_id(&node.data_type())));
+            write_value_rank(&mut attributes, node.value_rank(), node.array_dimensions());
+            let _ = write!(attributes, r#" AccessLevel="{}" UserAccessLevel="{}""#, node.access_level().bits(), node.user_access_level().bits());
+            if let Some(minimum_sampling_interval) = node.minimum_sampling_interval() {
+                let _ = write!(attributes, r#" MinimumSamplingInterval="{}""#, minimum_sampling_interval);
+            }
+            if node.historizing() {
+                attributes.push_str(r#" Historizing="true""#);
+            }
+            value = node.value().value;
+            "UAVariable"
+        }
+        NodeType::VariableType(ref node) => {
+            let _ = write!(attributes, r#" DataType="{}""#, escape(&namespaces.node_id(&node.data_type())));
+            write_value_rank(&mut attributes, node.value_rank(), node.array_dimensions());
+            if node.is_abstract() {
+                attributes.push_str(r#" IsAbstract="true""#);
+            }
+            value = node.value().and_then(|v| v.value);
+            "UAVariableType"
+        }
+        NodeType::ReferenceType(ref node) => {
+            if node.is_abstract() {
+                attributes.push_str(r#" IsAbstract="true""#);
+            }
+            if node.symmetric() {
+                attributes.push_str(r#" Symmetric="true""#);
+            }
+            inverse_name = node.inverse_name();
+            "UAReferenceType"
+        }
+        NodeType::DataType(ref node) => {
+            if node.is_abstract() {
+                attributes.push_str(r#" IsAbstract="true""#);
+            }
+            "UADataType"
+        }
+        NodeType::Method(ref node) => {
+            if !node.executable() {
+                attributes.push_str(r#" Executable="false""#);
+            }
+            if !node.user_executable() {
+                attributes.push_str(r#" UserExecutable="false""#);
+            }
+            "UAMethod"
+        }
+        NodeType::View(ref node) => {
+            if node.contains_no_loops() {
+                attributes.push_str(r#" ContainsNoLoops="true""#);
+            }
+            if node.event_notifier() != 0 {
+                let _ = write!(attributes, r#" EventNotifier="{}""#, node.event_notifier());
+            }
+            "UAView"
+        }
+    };
+
+    let _ = writeln!(xml, "  <{} {}>", element, attributes);
+    write_localized_text(xml, "DisplayName", &node.display_name());
+    if let Some(description) = node.description() {
+        write_localized_text(xml, "Description", &description);
+    }
+    if let Some(inverse_name) = inverse_name {
+        write_localized_text(xml, "InverseName", &inverse_name);
+    }
+    write_references(xml, namespaces, address_space, exported_nodes, &node_id);
+    if let Some(value) = value {
+        if let Some(value) = variant_to_xml(namespaces, &value) {
+            let _ = writeln!(xml, "    <Value>{}</Value>", value);
+        } else {
+            debug!("Value of node {} cannot be exported, type {:?} is not supported", node_id, value.type_id());
+        }
+    }
+    let _ = writeln!(xml, "  </{}>", element);
+}
+
+fn write_value_rank(attributes: &mut String, value_rank: i32, array_dimensions: Option<Vec<u32>>) {
+    // -1, i.e. scalar, is the default value rank
+    if value_rank != -1 {
+        let _ = write!(attributes, r#" ValueRank="{}""#, value_rank);
+    }
+    if let Some(array_dimensions) = array_dimensions {
+        if !array_dimensions.is_empty() {
+            let array_dimensions = array_dimensions.iter().map(|d| d.to_string()).collect::<Vec<String>>().join(",");
+            let _ = write!(attributes, r#" ArrayDimensions="{}""#, array_dimensions);
+        }
+    }
+}
+
+fn write_localized_text(xml: &mut String, element: &str, text: &LocalizedText) {
+    if text.locale.is_null() || text.locale.as_ref().is_empty() {
+        let _ = writeln!(xml, "    <{}>{}</{}>", element, escape(text.text.as_ref()), element);
+    } else {
+        let _ = writeln!(xml, r#"    <{} Locale="{}">{}</{}>"#, element, escape(text.locale.as_ref()), escape(text.text.as_ref()), element);
+    }
+}
+
+/// Writes the forward references of the node, and the inverse references from nodes that are
+/// not part of the export. Inverse references between exported nodes are implied by the forward
+/// reference written for the source node.
+fn write_references(xml: &mut String, namespaces: &mut NamespaceTable, address_space: &AddressSpace, exported_nodes: &HashSet<NodeId>, node_id: &NodeId) {
+    let mut references = Vec::new();
+    if let Some(forward) = address_space.find_references_from(node_id, None) {
+        forward.into_iter().for_each(|r| references.push((r, true)));
+    }
+    if let Some(inverse) = address_space.find_references_to(node_id, None) {
+        inverse.into_iter()
+            .filter(|r| !exported_nodes.contains(&r.target_node_id))
+            .for_each(|r| references.push((r, false)));
+    }
+    if !references.is_empty() {
+        let _ = writeln!(xml, "    <References>");
+        references.iter().for_each(|(r, is_forward)| {
+            let reference_type = escape(&namespaces.node_id(&r.reference_type_id));
+            let target = escape(&namespaces.node_id(&r.target_node_id));
+            if *is_forward {
+                let _ = writeln!(xml, r#"      <Reference ReferenceType="{}">{}</Reference>"#, reference_type, target);
+            } else {
+                let _ = writeln!(xml, r#"      <Reference ReferenceType="{}" IsForward="false">{}</Reference>"#, reference_type, target);
+            }
+        });
+        let _ = writeln!(xml, "    </References>");
+    }
+}
+
+/// Returns the element name and XML content of a scalar value, or `None` if the type cannot be exported
+fn scalar_to_xml(namespaces: &mut NamespaceTable, value: &Variant) -> Option<(&'static str, String)> {
+    let result = match value {
+        Variant::Boolean(v) => ("Boolean", v.to_string()),
+        Variant::SByte(v) => ("SByte", v.to_string()),
+        Variant::Byte(v) => ("Byte", v.to_string()),
+        Variant::Int16(v) => ("Int16", v.to_string()),
+        Variant::UInt16(v) => ("UInt16", v.to_string()),
+        Variant::Int32(v) => ("Int32", v.to_string()),
+        Variant::UInt32(v) => ("UInt32", v.to_string()),
+        Variant::Int64(v) => ("Int64", v.to_string()),
+        Variant::UInt64(v) => ("UInt64", v.to_string()),
+        Variant::Float(v) => ("Float", v.to_string()),
+        Variant::Double(v) => ("Double", v.to_string()),
+        Variant::String(v) => ("String", escape(v.as_ref())),
+        Variant::DateTime(v) => ("DateTime", v.as_chrono().to_rfc3339()),
+        Variant::Guid(v) => ("Guid", format!("<String>{}</String>", v.to_string())),
+        Variant::StatusCode(v) => ("StatusCode", format!("<Code>{}</Code>", v.bits())),
+        Variant::ByteString(v) => ("ByteString", v.as_base64()),
+        Variant::QualifiedName(v) => ("QualifiedName", format!("<NamespaceIndex>{}</NamespaceIndex><Name>{}</Name>",
+                                                                namespaces.index(v.namespace_index), escape(v.name.as_ref()))),
+        Variant::LocalizedText(v) => ("LocalizedText", format!("<Locale>{}</Locale><Text>{}</Text>",
+                                                                escape(v.locale.as_ref()), escape(v.text.as_ref()))),
+        Variant::NodeId(v) => ("NodeId", format!("<Identifier>{}</Identifier>", escape(&namespaces.node_id(v)))),
+        _ => {
+            return None;
+        }
+    };
+    Some(result)
+}
+
+/// Converts a value to its XML representation inside a `<Value>` element
+fn variant_to_xml(namespaces: &mut NamespaceTable, value: &Variant) -> Option<String> {
+    match value {
+        Variant::Empty => None,
+        Variant::Array(values) => {
+            // An empty array has no type to derive the element name from
+            let mut element = None;
+            let mut content = String::new();
+            for v in values {
+                let (name, v) = scalar_to_xml(namespaces, v)?;
+                let _ = write!(content, "<{}>{}</{}>", name, v, name);
+                element = Some(name);
+            }
+            element.map(|element| format!(r#"<ListOf{} xmlns="{}">{}</ListOf{}>"#, element, TYPES_NAMESPACE, content, element))
+        }
+        value => {
+            let (element, content) = scalar_to_xml(namespaces, value)?;
+            Some(format!(r#"<{} xmlns="{}">{}</{}>"#, element, TYPES_NAMESPACE, content, element))
+        }
+    }
+}
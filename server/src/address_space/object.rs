@@ -1,8 +1,53 @@
-use opcua_types::service_types::ObjectAttributes;
+use opcua_types::{
+    node_ids::{ObjectTypeId, ReferenceTypeId},
+    service_types::ObjectAttributes,
+};
 
-use crate::address_space::{base::Base, node::Node, node::NodeAttributes};
+use crate::address_space::{AddressSpace, base::Base, node::Node, node::NodeAttributes, references::ReferenceDirection};
 
-#[derive(Debug)]
+/// This is a builder object for constructing object nodes programmatically.
+pub struct ObjectBuilder {
+    node: Object,
+    references: Vec<(NodeId, ReferenceTypeId, ReferenceDirection)>,
+}
+
+node_builder_impl!(ObjectBuilder);
+
+impl ObjectBuilder {
+    pub fn new(node_id: &NodeId) -> ObjectBuilder {
+        ObjectBuilder {
+            node: Object::new(&NodeId::null(), "", "", 0),
+            references: Vec::new(),
+        }.node_id(node_id.clone())
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.node.node_id().is_null()
+    }
+
+    pub fn event_notifier(mut self, event_notifier: u8) -> Self {
+        self.node.set_event_notifier(event_notifier);
+        self
+    }
+
+    /// Makes the object a folder, i.e. its type definition is `FolderType`
+    pub fn is_folder(self) -> Self {
+        self.has_type_definition(ObjectTypeId::FolderType)
+    }
+
+    /// Builds the object and inserts it into the address space with its references. Fails if
+    /// the object is invalid or a node with the same id already exists.
+    pub fn insert(self, address_space: &mut AddressSpace) -> Result<NodeId, ()> {
+        if self.is_valid() {
+            self.insert_node(address_space)
+        } else {
+            error!("The object is not valid, node id = {:?}", self.node.node_id());
+            Err(())
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Object {
     base: Base,
     event_notifier: u8,
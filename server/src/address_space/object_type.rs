@@ -2,7 +2,7 @@ use opcua_types::service_types::ObjectTypeAttributes;
 
 use crate::address_space::{base::Base, node::Node, node::NodeAttributes};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ObjectType {
     base: Base,
     is_abstract: bool,
@@ -2,7 +2,7 @@ use opcua_types::service_types::ReferenceTypeAttributes;
 
 use crate::address_space::{base::Base, node::Node, node::NodeAttributes};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ReferenceType {
     base: Base,
     symmetric: bool,
@@ -39,6 +39,7 @@ impl Default for References {
     }
 }
 
+#[derive(Clone)]
 pub(super) struct References {
     /// A map of references where the source node is the key to one or more target nodes. Note this and `references_from_map` are NOT the same
     /// as IsForward/Inverse references. When a reference is added to `references_to_map`, the opposite but equivalent
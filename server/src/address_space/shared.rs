@@ -0,0 +1,119 @@
+//! The address space that is shared by the sessions and tasks of a server.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, LockResult, Mutex, MutexGuard, PoisonError, RwLock, RwLockWriteGuard};
+use std::thread;
+
+use crate::address_space::AddressSpace;
+
+/// The address space shared by the sessions and tasks of a server. Readers take a snapshot of the
+/// address space, which is never modified while they hold it, so they do not wait for writers.
+///
+/// A writer modifies the current snapshot in place if no reader holds it. Otherwise the writer
+/// modifies a copy, which replaces the current snapshot when the writer is finished, and readers
+/// carry on with the snapshot they hold. Copying is cheap because nodes are only copied when they
+/// are modified. The values of variables and the raised events have locks of their own and are
+/// shared by every snapshot, so a value set through an older snapshot is never lost.
+///
+/// `read()` and `write()` have the same form as those of a `RwLock` so the two can be used
+/// interchangeably.
+pub struct SharedAddressSpace {
+    /// The current snapshot. The lock is only held to take or replace the snapshot, or while a
+    /// writer modifies the snapshot in place.
+    current: RwLock<Arc<AddressSpace>>,
+    /// Writers hold this for as long as they modify the address space, one at a time
+    writer: Mutex<()>,
+}
+
+impl SharedAddressSpace {
+    pub fn new(address_space: AddressSpace) -> SharedAddressSpace {
+        SharedAddressSpace {
+            current: RwLock::new(Arc::new(address_space)),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// Returns a snapshot of the address space. Changes made after the snapshot was taken are
+    /// not seen through it, except for the values of variables and raised events.
+    pub fn read(&self) -> LockResult<Arc<AddressSpace>> {
+        match self.current.read() {
+            Ok(current) => Ok(current.clone()),
+            Err(err) => Err(PoisonError::new(err.into_inner().clone())),
+        }
+    }
+
+    /// Locks the address space for writing. The changes are seen by snapshots which are taken
+    /// after the guard is dropped.
+    pub fn write(&self) -> LockResult<AddressSpaceWriteGuard<'_>> {
+        // A writer which panicked has not replaced the snapshot so there is nothing to recover
+        let writer = self.writer.lock().unwrap_or_else(|err| err.into_inner());
+        let (current, poisoned) = match self.current.write() {
+            Ok(current) => (current, false),
+            Err(err) => (err.into_inner(), true),
+        };
+        let snapshot = if Arc::strong_count(&current) == 1 {
+            Snapshot::InPlace(current)
+        } else {
+            // Readers hold the snapshot, so they are let go before it is copied
+            let held = current.clone();
+            drop(current);
+            Snapshot::Copy(Some(held.copy_for_snapshot()))
+        };
+        let guard = AddressSpaceWriteGuard {
+            shared: self,
+            snapshot,
+            _writer: writer,
+        };
+        if poisoned { Err(PoisonError::new(guard)) } else { Ok(guard) }
+    }
+}
+
+/// The snapshot of the address space that a writer modifies
+enum Snapshot<'a> {
+    /// The current snapshot, which no reader holds
+    InPlace(RwLockWriteGuard<'a, Arc<AddressSpace>>),
+    /// A copy of the current snapshot, which replaces it when the writer is finished
+    Copy(Option<AddressSpace>),
+}
+
+/// The guard of a writer of a `SharedAddressSpace`
+pub struct AddressSpaceWriteGuard<'a> {
+    shared: &'a SharedAddressSpace,
+    snapshot: Snapshot<'a>,
+    _writer: MutexGuard<'a, ()>,
+}
+
+impl<'a> Deref for AddressSpaceWriteGuard<'a> {
+    type Target = AddressSpace;
+
+    fn deref(&self) -> &AddressSpace {
+        match self.snapshot {
+            Snapshot::InPlace(ref current) => current,
+            Snapshot::Copy(ref address_space) => address_space.as_ref().unwrap(),
+        }
+    }
+}
+
+impl<'a> DerefMut for AddressSpaceWriteGuard<'a> {
+    fn deref_mut(&mut self) -> &mut AddressSpace {
+        match self.snapshot {
+            // No reader can take the snapshot while the guard holds the lock
+            Snapshot::InPlace(ref mut current) => Arc::get_mut(current).unwrap(),
+            Snapshot::Copy(ref mut address_space) => address_space.as_mut().unwrap(),
+        }
+    }
+}
+
+impl<'a> Drop for AddressSpaceWriteGuard<'a> {
+    fn drop(&mut self) {
+        if let Snapshot::Copy(ref mut address_space) = self.snapshot {
+            // A writer that panicked may have left the copy half modified, so it is thrown away
+            if let Some(address_space) = address_space.take() {
+                if !thread::panicking() {
+                    let mut current = self.shared.current.write().unwrap_or_else(|err| err.into_inner());
+                    *current = Arc::new(address_space);
+                }
+            }
+        }
+    }
+}
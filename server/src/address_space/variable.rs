@@ -1,48 +1,24 @@
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use std::convert::{Into, TryFrom};
 
-use opcua_types::node_ids::DataTypeId;
+use opcua_types::node_ids::{DataTypeId, ReferenceTypeId};
 
 use crate::{
-    callbacks::{AttributeGetter, AttributeSetter},
+    callbacks::{AttributeGetter, AttributeSetter, AsyncAttributeGetter, AsyncAttributeSetter, AsyncAttributeValue, AsyncAttributeResult},
     address_space::{
-        AccessLevel, UserAccessLevel,
+        AccessLevel, UserAccessLevel, AddressSpace,
         base::Base,
         node::{Node, NodeAttributes},
+        references::ReferenceDirection,
     },
 };
 use opcua_types::service_types::VariableAttributes;
 
-/// This is a builder object for constructing variable nodes programmatically.
+/// This is a builder object for constructing variable nodes programmatically. Variables are
+/// readable by clients unless the builder sets other access levels.
 pub struct VariableBuilder {
-    node: Variable
-}
-
-macro_rules! node_builder_impl {
-    ( $node_builder_struct:ident ) => {
-        impl $node_builder_struct {
-
-            fn node_id(mut self, node_id: NodeId) -> Self {
-                let _ = self.node.base.set_node_id(node_id);
-                self
-            }
-
-            pub fn browse_name<V>(mut self, browse_name: V) -> Self where V: Into<QualifiedName> {
-                let _ = self.node.base.set_browse_name(browse_name);
-                self
-            }
-
-            pub fn display_name<V>(mut self, display_name: V) -> Self where V: Into<LocalizedText> {
-                self.node.set_display_name(display_name.into());
-                self
-            }
-
-            pub fn description<V>(mut self, description: V) -> Self where V: Into<LocalizedText>{
-                self.node.set_description(description.into());
-                self
-            }
-        }
-    }
+    node: Variable,
+    references: Vec<(NodeId, ReferenceTypeId, ReferenceDirection)>,
 }
 
 node_builder_impl!(VariableBuilder);
@@ -50,15 +26,24 @@ node_builder_impl!(VariableBuilder);
 impl VariableBuilder {
     pub fn new(node_id: &NodeId) -> VariableBuilder {
         VariableBuilder {
-            node: Variable::default()
+            node: Variable::default(),
+            references: Vec::new(),
         }.node_id(node_id.clone())
+            .access_level(AccessLevel::CURRENT_READ)
+            .user_access_level(UserAccessLevel::CURRENT_READ)
     }
 
     pub fn is_valid(&self) -> bool {
         self.node.is_valid()
     }
 
+    /// Sets the value. The data type is set to the type of the value if it can be inferred, so
+    /// call `data_type()` afterwards to set a different data type.
     pub fn value<V>(mut self, value: V) -> Self where V: Into<Variant> {
+        let value = value.into();
+        if let Some(data_type) = value.data_type() {
+            self.node.set_data_type(data_type);
+        }
         let _ = self.node.set_value(value);
         self
     }
@@ -98,6 +83,34 @@ impl VariableBuilder {
         self
     }
 
+    /// Makes the value readable and writable by clients
+    pub fn writable(mut self) -> Self {
+        self.node.set_access_level(AccessLevel::CURRENT_READ | AccessLevel::CURRENT_WRITE);
+        self.node.set_user_access_level(UserAccessLevel::CURRENT_READ | UserAccessLevel::CURRENT_WRITE);
+        self
+    }
+
+    pub fn value_getter(mut self, value_getter: Arc<Mutex<dyn AttributeGetter + Send>>) -> Self {
+        self.node.set_value_getter(value_getter);
+        self
+    }
+
+    pub fn value_setter(mut self, value_setter: Arc<Mutex<dyn AttributeSetter + Send>>) -> Self {
+        self.node.set_value_setter(value_setter);
+        self
+    }
+
+    /// Builds the variable and inserts it into the address space with its references. Fails if
+    /// the variable is invalid or a node with the same id already exists.
+    pub fn insert(self, address_space: &mut AddressSpace) -> Result<NodeId, ()> {
+        if self.is_valid() {
+            self.insert_node(address_space)
+        } else {
+            error!("The variable is not valid, node id = {:?}", self.node.base.node_id());
+            Err(())
+        }
+    }
+
     /// Yields the built variable. This function will panic if the variable is invalid.
     pub fn build(self) -> Variable {
         if self.is_valid() {
@@ -117,7 +130,10 @@ pub struct Variable {
     data_type: NodeId,
     historizing: bool,
     value_rank: i32,
-    value: DataValue,
+    /// The value has a lock of its own so that it can be set while the address space is only
+    /// locked for reading, e.g. by a timer which updates it from a device. It is shared by the
+    /// copies of the variable in every snapshot of the address space.
+    value: Arc<RwLock<DataValue>>,
     access_level: u8,
     user_access_level: u8,
     array_dimensions: Option<Vec<u32>>,
@@ -126,6 +142,10 @@ pub struct Variable {
     value_setter: Option<Arc<Mutex<dyn AttributeSetter + Send>>>,
     #[derivative(Debug = "ignore")]
     value_getter: Option<Arc<Mutex<dyn AttributeGetter + Send>>>,
+    #[derivative(Debug = "ignore")]
+    value_async_setter: Option<Arc<Mutex<dyn AsyncAttributeSetter + Send>>>,
+    #[derivative(Debug = "ignore")]
+    value_async_getter: Option<Arc<Mutex<dyn AsyncAttributeGetter + Send>>>,
 }
 
 node_impl!(Variable);
@@ -138,13 +158,15 @@ impl Default for Variable {
             data_type,
             historizing: false,
             value_rank: -1,
-            value: Variant::Empty.into(),
+            value: Arc::new(RwLock::new(Variant::Empty.into())),
             access_level: 0,
             user_access_level: 0,
             array_dimensions: None,
             minimum_sampling_interval: None,
             value_getter: None,
             value_setter: None,
+            value_async_getter: None,
+            value_async_setter: None,
         }
     }
 }
@@ -199,8 +221,13 @@ impl NodeAttributes for Variable {
                     Err(StatusCode::BadTypeMismatch)
                 },
                 AttributeId::ValueRank => if let Variant::Int32(v) = value {
-                    self.set_value_rank(v);
-                    Ok(())
+                    // -3 (ScalarOrOneDimension) is the lowest value rank
+                    if v < -3 {
+                        Err(StatusCode::BadOutOfRange)
+                    } else {
+                        self.set_value_rank(v);
+                        Ok(())
+                    }
                 } else {
                     Err(StatusCode::BadTypeMismatch)
                 },
@@ -223,8 +250,13 @@ impl NodeAttributes for Variable {
                 AttributeId::ArrayDimensions => {
                     let array_dimensions = <Vec<u32>>::try_from(&value);
                     if let Ok(array_dimensions) = array_dimensions {
-                        self.set_array_dimensions(&array_dimensions);
-                        Ok(())
+                        // A variable with a fixed number of dimensions has the length of each of them
+                        if self.value_rank > 0 && array_dimensions.len() != self.value_rank as usize {
+                            Err(StatusCode::BadTypeMismatch)
+                        } else {
+                            self.set_array_dimensions(&array_dimensions);
+                            Ok(())
+                        }
                     } else {
                         Err(StatusCode::BadTypeMismatch)
                     }
@@ -310,24 +342,18 @@ impl Variable {
               V: Into<Variant>
     {
         let value = value.into();
-        let array_dimensions = match value {
-            Variant::Array(ref values) => Some(vec![values.len() as u32]),
-            Variant::MultiDimensionArray(ref values) => {
-                // Multidimensional arrays encode/decode dimensions with Int32 in Part 6, but arrayDimensions in Part 3
-                // wants them as u32. Go figure... So convert Int32 to u32
-                Some(values.dimensions.iter().map(|v| *v as u32).collect::<Vec<u32>>())
-            }
-            _ => None
-        };
+        // Multidimensional arrays encode/decode dimensions with Int32 in Part 6, but arrayDimensions in Part 3
+        // wants them as u32. Go figure... So convert Int32 to u32
+        let array_dimensions = value.array_dimensions();
 
         let builder = VariableBuilder::new(node_id)
             .display_name(display_name)
             .browse_name(browse_name)
             .user_access_level(UserAccessLevel::CURRENT_READ)
             .access_level(AccessLevel::CURRENT_READ)
-            .data_type(data_type)
             .historizing(false)
-            .value(value);
+            .value(value)
+            .data_type(data_type);
 
         // Set the array info
         let builder = if let Some(array_dimensions) = array_dimensions {
@@ -342,17 +368,37 @@ impl Variable {
         !self.base.node_id().is_null()
     }
 
+    /// Copies the variable into another snapshot of the address space. The copy shares the value
+    /// of the variable, so a value that is set through either one is seen through both.
+    pub(crate) fn copy_for_snapshot(&self) -> Variable {
+        Variable {
+            base: self.base.clone(),
+            data_type: self.data_type.clone(),
+            historizing: self.historizing,
+            value_rank: self.value_rank,
+            value: self.value.clone(),
+            access_level: self.access_level,
+            user_access_level: self.user_access_level,
+            array_dimensions: self.array_dimensions.clone(),
+            minimum_sampling_interval: self.minimum_sampling_interval,
+            value_setter: self.value_setter.clone(),
+            value_getter: self.value_getter.clone(),
+            value_async_setter: self.value_async_setter.clone(),
+            value_async_getter: self.value_async_getter.clone(),
+        }
+    }
+
     pub fn value(&self) -> DataValue {
         if let Some(ref value_getter) = self.value_getter {
             let mut value_getter = value_getter.lock().unwrap();
             value_getter.get(&self.node_id(), AttributeId::Value, 0f64).unwrap().unwrap()
         } else {
-            self.value.clone().into()
+            trace_read_lock_unwrap!(self.value).clone()
         }
     }
 
     /// Sets the variable's `Variant` value. The timestamps for the change are updated to now.
-    pub fn set_value<V>(&mut self, value: V) where V: Into<Variant> {
+    pub fn set_value<V>(&self, value: V) where V: Into<Variant> {
         let value = value.into();
         // The value set to the value getter
         if let Some(ref value_setter) = self.value_setter {
@@ -378,10 +424,19 @@ impl Variable {
     }
 
     /// Sets the variable's `DataValue`
-    pub fn set_value_direct<V>(&mut self, value: V, server_timestamp: &DateTime, source_timestamp: &DateTime) where V: Into<Variant> {
-        self.value.value = Some(value.into());
-        self.value.server_timestamp = Some(server_timestamp.clone());
-        self.value.source_timestamp = Some(source_timestamp.clone());
+    pub fn set_value_direct<V>(&self, value: V, server_timestamp: &DateTime, source_timestamp: &DateTime) where V: Into<Variant> {
+        let mut data_value = trace_write_lock_unwrap!(self.value);
+        data_value.value = Some(value.into());
+        data_value.server_timestamp = Some(server_timestamp.clone());
+        data_value.source_timestamp = Some(source_timestamp.clone());
+    }
+
+    /// Sets the status of the variable's `DataValue` without changing its value, e.g. to mark the
+    /// value of a variable that mirrors a device as bad while the device cannot be reached.
+    pub fn set_value_status(&self, status: StatusCode, server_timestamp: &DateTime) {
+        let mut data_value = trace_write_lock_unwrap!(self.value);
+        data_value.status = if status == StatusCode::Good { None } else { Some(status.bits()) };
+        data_value.server_timestamp = Some(server_timestamp.clone());
     }
 
     /// Sets a getter function that will be called to get the value of this variable. Note
@@ -398,6 +453,35 @@ impl Variable {
         self.value_setter = Some(value_setter);
     }
 
+    /// Sets an async getter that the Read service calls to get the value of this variable from a
+    /// slow source without blocking other requests. Anything else which reads the value, such as
+    /// a monitored item, gets the value held by the variable, i.e. the last value set on it.
+    pub fn set_value_async_getter(&mut self, value_async_getter: Arc<Mutex<dyn AsyncAttributeGetter + Send>>) {
+        self.value_async_getter = Some(value_async_getter);
+    }
+
+    /// Sets an async setter that the Write service calls to write the value of this variable to a
+    /// slow source without blocking other requests. The written value is also held by the variable.
+    pub fn set_value_async_setter(&mut self, value_async_setter: Arc<Mutex<dyn AsyncAttributeSetter + Send>>) {
+        self.value_async_setter = Some(value_async_setter);
+    }
+
+    /// Returns a future for the value from the async getter, if the variable has one
+    pub(crate) fn async_value(&self, max_age: f64) -> Option<AsyncAttributeValue> {
+        self.value_async_getter.as_ref().map(|value_async_getter| {
+            let mut value_async_getter = value_async_getter.lock().unwrap();
+            value_async_getter.get(&self.node_id(), AttributeId::Value, max_age)
+        })
+    }
+
+    /// Returns a future which sets the value with the async setter, if the variable has one
+    pub(crate) fn async_set_value(&self, data_value: DataValue) -> Option<AsyncAttributeResult> {
+        self.value_async_setter.as_ref().map(|value_async_setter| {
+            let mut value_async_setter = value_async_setter.lock().unwrap();
+            value_async_setter.set(&self.node_id(), AttributeId::Value, data_value)
+        })
+    }
+
     /// Gets the minimum sampling interval, if the attribute was set
     pub fn minimum_sampling_interval(&self) -> Option<f64> {
         self.minimum_sampling_interval.clone()
@@ -470,6 +554,35 @@ impl Variable {
         self.historizing = historizing;
     }
 
+    /// Tests if the value has a shape allowed by the value rank and array dimensions of the variable.
+    /// A dimension of 0 in the array dimensions means the length of that dimension is not fixed,
+    /// otherwise it is the maximum length. A null value is always allowed.
+    pub fn is_valid_value_shape(&self, value: &Variant) -> bool {
+        if let Variant::Empty = value {
+            return true;
+        }
+        let value_rank = value.value_rank();
+        let valid_rank = match self.value_rank {
+            // ScalarOrOneDimension
+            -3 => value_rank == -1 || value_rank == 1,
+            // Any
+            -2 => true,
+            // Scalar
+            -1 => value_rank == -1,
+            // OneOrMoreDimensions
+            0 => value_rank >= 1,
+            n => value_rank == n,
+        };
+        if !valid_rank {
+            false
+        } else if let (Some(array_dimensions), Some(value_dimensions)) = (self.array_dimensions.as_ref(), value.array_dimensions()) {
+            array_dimensions.len() != value_dimensions.len() ||
+                array_dimensions.iter().zip(value_dimensions.iter()).all(|(max, len)| *max == 0 || len <= max)
+        } else {
+            true
+        }
+    }
+
     pub fn array_dimensions(&self) -> Option<Vec<u32>> {
         self.array_dimensions.clone()
     }
@@ -4,7 +4,7 @@ use opcua_types::service_types::VariableTypeAttributes;
 
 use crate::address_space::{base::Base, node::Node, node::NodeAttributes};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct VariableType {
     base: Base,
     data_type: NodeId,
@@ -2,7 +2,7 @@ use opcua_types::service_types::ViewAttributes;
 
 use crate::address_space::{base::Base, node::Node, node::NodeAttributes};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct View {
     base: Base,
     event_notifier: u8,
@@ -0,0 +1,396 @@
+//! Provides aggregation of the address spaces of the `aggregated_servers` of the server config.
+//! The server connects to each of them as a client and mirrors the objects and variables beneath
+//! their Objects folders in a folder of its own, so its clients can reach many servers through
+//! one, i.e. the server acts as a gateway or concentrator.
+//!
+//! Objects are mirrored as folders and variables as variables. Reads and writes of a mirrored
+//! variable's value are forwarded to the other server by async getters and setters, and a
+//! subscription on the other server keeps the mirrored values up to date for monitored items.
+//! While the other server cannot be reached, the mirrored values hold `BadCommunicationError`.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::Future;
+
+use opcua_client::prelude::{ClientBuilder, DataChangeCallback, IdentityToken, MonitoredItem, Session};
+use opcua_types::{
+    *,
+    node_ids::{ObjectId, ReferenceTypeId},
+    service_types::*,
+    status_code::StatusCode,
+};
+
+use crate::{
+    address_space::{AddressSpace, SharedAddressSpace, AccessLevel, variable::VariableBuilder},
+    config::AggregatedServerConfig,
+    constants,
+    state::ServerState,
+};
+
+/// Lifetime count of the subscription on an aggregated server
+const SUBSCRIPTION_LIFETIME_COUNT: u32 = 30;
+/// Keep alive count of the subscription on an aggregated server
+const SUBSCRIPTION_MAX_KEEP_ALIVE_COUNT: u32 = 10;
+
+/// Returns the node id of the folder beneath Objects that an aggregated server is mirrored in
+pub(crate) fn aggregation_folder_id(namespace: u16, name: &str) -> NodeId {
+    NodeId::new(namespace, name.to_string())
+}
+
+/// Returns the node id that a node of an aggregated server is mirrored as. The id is a string
+/// made of the name of the aggregated server and the node's id on it, e.g. "PLC1.ns=2;i=10" so
+/// that nodes of different servers never clash.
+pub(crate) fn mirrored_node_id(namespace: u16, name: &str, remote_node_id: &NodeId) -> NodeId {
+    NodeId::new(namespace, format!("{}.{}", name, remote_node_id))
+}
+
+/// A node beneath the Objects folder of an aggregated server that is mirrored
+struct RemoteNode {
+    /// Node id on the aggregated server
+    node_id: NodeId,
+    /// Node id of the mirrored node
+    local_node_id: NodeId,
+    /// Node id of the mirrored parent of the node
+    local_parent_id: NodeId,
+    /// Browse name, without the aggregated server's namespace
+    browse_name: String,
+    display_name: LocalizedText,
+}
+
+/// Returns the interval before the next reconnect to an aggregated server after one that waited
+/// for `delay` millis, which doubles up to `AGGREGATION_RECONNECT_MAX_MS`
+pub(crate) fn next_reconnect_delay(delay: u64) -> u64 {
+    (delay * 2).max(constants::AGGREGATION_RECONNECT_MIN_MS).min(constants::AGGREGATION_RECONNECT_MAX_MS)
+}
+
+/// Sets the status of the values of the mirrored variables, e.g. `BadCommunicationError` while
+/// the connection to the aggregated server is down. The last known values are kept.
+pub(crate) fn set_mirrored_status<'a, I>(address_space: &Arc<SharedAddressSpace>, local_node_ids: I, status: StatusCode)
+    where I: Iterator<Item=&'a NodeId>
+{
+    let now = DateTime::now();
+    let address_space = trace_read_lock_unwrap!(address_space);
+    for local_node_id in local_node_ids {
+        if let Some(variable) = address_space.find_variable_by_ref(local_node_id) {
+            variable.set_value_status(status, &now);
+        }
+    }
+}
+
+/// Sets the value of a mirrored variable, and its status, from a value of the aggregated server
+fn set_mirrored_value(address_space: &AddressSpace, local_node_id: &NodeId, data_value: &DataValue, now: &DateTime) {
+    if let Some(variable) = address_space.find_variable_by_ref(local_node_id) {
+        if let Some(ref value) = data_value.value {
+            let source_timestamp = data_value.source_timestamp.clone().unwrap_or_else(|| now.clone());
+            variable.set_value_direct(value.clone(), now, &source_timestamp);
+        }
+        variable.set_value_status(StatusCode::from_bits_truncate(data_value.status()), now);
+    }
+}
+
+fn is_server_running(server_state: &Arc<RwLock<ServerState>>) -> bool {
+    let server_state = trace_read_lock_unwrap!(server_state);
+    !server_state.is_abort() && server_state.is_running()
+}
+
+/// Creates the folder that the aggregated server is mirrored in and starts a thread which connects
+/// to the aggregated server, mirrors its address space and then keeps the session to it running
+/// for as long as the server is running.
+///
+/// A failure to connect, mirror or subscribe, or a lost connection, is retried on an interval that
+/// backs off from `AGGREGATION_RECONNECT_MIN_MS` to `AGGREGATION_RECONNECT_MAX_MS`. The mirrored
+/// variables hold `BadCommunicationError` until the aggregated server is reached again.
+pub(crate) fn start_aggregation(server_state: Arc<RwLock<ServerState>>, address_space: Arc<SharedAddressSpace>, config: AggregatedServerConfig) {
+    let (application_name, application_uri, pki_dir) = {
+        let server_state = trace_read_lock_unwrap!(server_state);
+        let server_config = trace_read_lock_unwrap!(server_state.config);
+        (server_config.application_name.clone(), server_config.application_uri.clone(), server_config.pki_dir.clone())
+    };
+
+    let namespace = {
+        let mut address_space = trace_write_lock_unwrap!(address_space);
+        let namespace = address_space.namespace_index(&application_uri).unwrap_or(1);
+        let folder_id = aggregation_folder_id(namespace, &config.name);
+        if !address_space.node_exists(&folder_id) &&
+            address_space.add_folder_with_id(&folder_id, config.name.as_str(), config.name.as_str(), &AddressSpace::objects_folder_id()).is_err() {
+            error!("Cannot create the {} folder for aggregated server {}", config.name, config.url);
+            return;
+        }
+        namespace
+    };
+
+    let _ = thread::spawn(move || {
+        // The server connects with its own identity. The session does not retry by itself because
+        // a new session has to mirror and subscribe again, which is done here.
+        let client = ClientBuilder::new()
+            .application_name(application_name)
+            .application_uri(application_uri)
+            .pki_dir(pki_dir)
+            .session_retry_limit(0)
+            .client();
+        let mut client = if let Some(client) = client {
+            client
+        } else {
+            error!("Cannot create a client for aggregated server {}", config.url);
+            return;
+        };
+
+        let identity_token = if let Some(ref user) = config.user {
+            IdentityToken::UserName(user.clone(), config.pass.clone().unwrap_or_default())
+        } else {
+            IdentityToken::Anonymous
+        };
+
+        // Local node ids of the variables mirrored so far by their remote node ids
+        let mut mirrored = HashMap::new();
+        let mut reconnect_delay = constants::AGGREGATION_RECONNECT_MIN_MS;
+        while is_server_running(&server_state) {
+            let endpoint = (config.url.as_ref(), config.security_policy().to_uri(), config.message_security_mode());
+            match client.connect_to_endpoint(endpoint, identity_token.clone()) {
+                Ok(session) => {
+                    let linked = {
+                        let mut session = trace_write_lock_unwrap!(session);
+                        if !session.is_connected() {
+                            Err(StatusCode::BadNotConnected)
+                        } else {
+                            mirror_address_space(&mut session, &address_space, namespace, &config)
+                                .and_then(|local_node_ids| {
+                                    info!("Aggregated server {} has {} variables mirrored in folder {}", config.url, local_node_ids.len(), config.name);
+                                    mirrored.extend(local_node_ids.iter().map(|(k, v)| (k.clone(), v.clone())));
+                                    subscribe(&mut session, &address_space, local_node_ids, &config)
+                                })
+                        }
+                    };
+                    match linked {
+                        Ok(_) => {
+                            reconnect_delay = constants::AGGREGATION_RECONNECT_MIN_MS;
+                            while is_server_running(&server_state) {
+                                let mut session = trace_write_lock_unwrap!(session);
+                                if session.poll(constants::AGGREGATION_POLL_MS).is_err() || !session.is_connected() {
+                                    error!("Lost the connection to aggregated server {}", config.url);
+                                    break;
+                                }
+                            }
+                        }
+                        Err(err) => {
+                            error!("Cannot mirror or subscribe to aggregated server {}, error = {}", config.url, err);
+                        }
+                    }
+                    let mut session = trace_write_lock_unwrap!(session);
+                    if session.is_connected() {
+                        session.disconnect();
+                    }
+                }
+                Err(err) => {
+                    error!("Cannot connect to aggregated server {}, error = {}", config.url, err);
+                }
+            }
+
+            if !is_server_running(&server_state) {
+                break;
+            }
+            set_mirrored_status(&address_space, mirrored.values(), StatusCode::BadCommunicationError);
+
+            // Wait before reconnecting, in steps so that a server shutdown is not held up
+            info!("Reconnecting to aggregated server {} in {} ms", config.url, reconnect_delay);
+            let reconnect_at = Instant::now() + Duration::from_millis(reconnect_delay);
+            while Instant::now() < reconnect_at && is_server_running(&server_state) {
+                thread::sleep(Duration::from_millis(constants::AGGREGATION_POLL_MS));
+            }
+            reconnect_delay = next_reconnect_delay(reconnect_delay);
+        }
+        info!("Aggregation of server {} is finished", config.url);
+    });
+}
+
+/// Browses the objects and variables beneath the Objects folder of the aggregated server and
+/// mirrors them in the aggregation folder. Nodes in namespace 0, such as the Server object, are
+/// not mirrored. Returns the local node ids of the mirrored variables by their remote node ids.
+fn mirror_address_space(session: &mut Session, address_space: &Arc<SharedAddressSpace>, namespace: u16, config: &AggregatedServerConfig) -> Result<HashMap<NodeId, NodeId>, StatusCode> {
+    let mut visited = HashSet::new();
+    let mut variables = Vec::new();
+
+    // Breadth first, a level at a time
+    let objects_folder_id: NodeId = ObjectId::ObjectsFolder.into();
+    let mut level = vec![(objects_folder_id, aggregation_folder_id(namespace, &config.name))];
+    let mut depth = 0;
+    while !level.is_empty() && depth < config.max_browse_depth {
+        let references = browse_children(session, &level)?;
+        let mut objects = Vec::new();
+        for ((_, local_parent_id), references) in level.iter().zip(references) {
+            for reference in references {
+                let node_id = reference.node_id.node_id;
+                if reference.node_id.server_index != 0 || node_id.namespace == 0 || !visited.insert(node_id.clone()) {
+                    continue;
+                }
+                let remote_node = RemoteNode {
+                    local_node_id: mirrored_node_id(namespace, &config.name, &node_id),
+                    local_parent_id: local_parent_id.clone(),
+                    node_id,
+                    browse_name: reference.browse_name.name.as_ref().to_string(),
+                    display_name: reference.display_name,
+                };
+                match reference.node_class {
+                    NodeClass::Object => objects.push(remote_node),
+                    NodeClass::Variable => variables.push(remote_node),
+                    _ => {}
+                }
+            }
+        }
+
+        {
+            let mut address_space = trace_write_lock_unwrap!(address_space);
+            for object in &objects {
+                if !address_space.node_exists(&object.local_node_id) {
+                    let _ = address_space.add_folder_with_id(&object.local_node_id, object.browse_name.as_str(), object.display_name.clone(), &object.local_parent_id);
+                }
+            }
+        }
+        level = objects.into_iter().map(|object| (object.node_id, object.local_node_id)).collect();
+        depth += 1;
+    }
+
+    if variables.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    // Read the value and type of the variables to create their mirrors with
+    let attribute_ids = [AttributeId::Value, AttributeId::DataType, AttributeId::ValueRank, AttributeId::AccessLevel];
+    let nodes_to_read = variables.iter().flat_map(|variable| {
+        attribute_ids.iter().map(move |attribute_id| ReadValueId {
+            node_id: variable.node_id.clone(),
+            attribute_id: *attribute_id as u32,
+            index_range: UAString::null(),
+            data_encoding: QualifiedName::null(),
+        })
+    }).collect::<Vec<_>>();
+    let values = session.read(&nodes_to_read)?.unwrap_or_default();
+    if values.len() != nodes_to_read.len() {
+        return Err(StatusCode::BadUnexpectedError);
+    }
+
+    let async_session = session.async_session();
+    let mut local_node_ids = HashMap::with_capacity(variables.len());
+    let now = DateTime::now();
+    let mut address_space = trace_write_lock_unwrap!(address_space);
+    for (variable, values) in variables.into_iter().zip(values.chunks(attribute_ids.len())) {
+        if address_space.node_exists(&variable.local_node_id) {
+            // Mirrored before the connection was lost, so it gets its current value and the
+            // getter and setter below are pointed at the new session
+            if address_space.find_variable_by_ref(&variable.local_node_id).is_none() {
+                continue;
+            }
+            set_mirrored_value(&address_space, &variable.local_node_id, &values[0], &now);
+        } else {
+            let mut builder = VariableBuilder::new(&variable.local_node_id)
+                .browse_name(variable.browse_name.as_str())
+                .display_name(variable.display_name.clone())
+                .value(values[0].value.clone().unwrap_or(Variant::Empty))
+                .organized_by(&variable.local_parent_id);
+            // Data types of other namespaces are not known to the server
+            if let Some(Variant::NodeId(ref data_type)) = values[1].value {
+                if data_type.namespace == 0 {
+                    builder = builder.data_type(data_type.as_ref().clone());
+                }
+            }
+            if let Some(Variant::Int32(value_rank)) = values[2].value {
+                builder = builder.value_rank(value_rank);
+            }
+            if let Some(Variant::Byte(access_level)) = values[3].value {
+                if AccessLevel::from_bits_truncate(access_level).contains(AccessLevel::CURRENT_WRITE) {
+                    builder = builder.writable();
+                }
+            }
+            if builder.insert(&mut address_space).is_err() {
+                continue;
+            }
+        }
+
+        let getter_session = async_session.clone();
+        let remote_node_id = variable.node_id.clone();
+        address_space.set_variable_async_getter(variable.local_node_id.clone(), move |_, _, _| {
+            getter_session.read(&[ReadValueId::from(&remote_node_id)])
+                .map(|values| values.and_then(|values| values.into_iter().next()))
+        });
+        let setter_session = async_session.clone();
+        let remote_node_id = variable.node_id.clone();
+        address_space.set_variable_async_setter(variable.local_node_id.clone(), move |_, _, data_value| {
+            let write_value = WriteValue {
+                node_id: remote_node_id.clone(),
+                attribute_id: AttributeId::Value as u32,
+                index_range: UAString::null(),
+                value: data_value,
+            };
+            setter_session.write(&[write_value])
+                .and_then(|results| match results.and_then(|results| results.into_iter().next()) {
+                    Some(status_code) if status_code.is_good() => Ok(()),
+                    Some(status_code) => Err(status_code),
+                    None => Err(StatusCode::BadUnexpectedError),
+                })
+        });
+
+        local_node_ids.insert(variable.node_id, variable.local_node_id);
+    }
+    Ok(local_node_ids)
+}
+
+/// Browses the forward hierarchical references of the remote nodes to objects and variables,
+/// following continuation points until all of the references are returned
+fn browse_children(session: &mut Session, nodes: &[(NodeId, NodeId)]) -> Result<Vec<Vec<ReferenceDescription>>, StatusCode> {
+    let nodes_to_browse = nodes.iter().map(|(node_id, _)| BrowseDescription {
+        node_id: node_id.clone(),
+        browse_direction: BrowseDirection::Forward,
+        reference_type_id: ReferenceTypeId::HierarchicalReferences.into(),
+        include_subtypes: true,
+        node_class_mask: NodeClass::Object as u32 | NodeClass::Variable as u32,
+        // All of the fields of the reference descriptions
+        result_mask: 0x3f,
+    }).collect::<Vec<_>>();
+    let results = session.browse(&nodes_to_browse)?.unwrap_or_default();
+    if results.len() != nodes_to_browse.len() {
+        return Err(StatusCode::BadUnexpectedError);
+    }
+
+    let mut references = Vec::with_capacity(results.len());
+    for mut result in results {
+        let mut node_references = result.references.take().unwrap_or_default();
+        while result.status_code.is_good() && !result.continuation_point.is_null() {
+            result = session.browse_next(false, &[result.continuation_point])?
+                .and_then(|results| results.into_iter().next())
+                .ok_or(StatusCode::BadUnexpectedError)?;
+            node_references.extend(result.references.take().unwrap_or_default());
+        }
+        references.push(node_references);
+    }
+    Ok(references)
+}
+
+/// Creates a subscription on the aggregated server to the values of the mirrored variables
+/// which sets the values of the mirrors whenever they change
+fn subscribe(session: &mut Session, address_space: &Arc<SharedAddressSpace>, local_node_ids: HashMap<NodeId, NodeId>, config: &AggregatedServerConfig) -> Result<(), StatusCode> {
+    if local_node_ids.is_empty() {
+        return Ok(());
+    }
+    let items_to_create = local_node_ids.keys().map(|node_id| {
+        MonitoredItemCreateRequest::new(node_id.into(), MonitoringMode::Reporting, MonitoringParameters {
+            sampling_interval: config.publishing_interval as f64,
+            ..Default::default()
+        })
+    }).collect::<Vec<_>>();
+
+    let address_space = address_space.clone();
+    let subscription_id = session.create_subscription(config.publishing_interval as f64, SUBSCRIPTION_LIFETIME_COUNT, SUBSCRIPTION_MAX_KEEP_ALIVE_COUNT, 0, 0, true, DataChangeCallback::new(move |items: Vec<&MonitoredItem>| {
+        let now = DateTime::now();
+        let address_space = trace_read_lock_unwrap!(address_space);
+        for item in items {
+            if let Some(local_node_id) = local_node_ids.get(&item.item_to_monitor().node_id) {
+                set_mirrored_value(&address_space, local_node_id, item.value(), &now);
+            }
+        }
+    }))?;
+    let _ = session.create_monitored_items(subscription_id, TimestampsToReturn::Both, &items_to_create)?;
+    Ok(())
+}
@@ -0,0 +1,227 @@
+//! Provides bridges between the server and external protocols, such as Modbus, MQTT or BACnet.
+//! A bridge reads and writes the tags of its protocol, and the server polls it on an interval to
+//! set the values of the variables that the tags are mapped to, reconnecting it whenever it loses
+//! its connection. Writes of the variables are passed to the bridge.
+//!
+//! An integration implements [`Bridge`] and is added to the server with `Server::add_bridge()`
+//! rather than running its own polling actions and timers.
+//!
+//! [`Bridge`]: ./trait.Bridge.html
+
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use opcua_types::{*, status_code::StatusCode};
+
+use crate::{
+    address_space::{AddressSpace, SharedAddressSpace},
+    constants,
+    state::ServerState,
+};
+
+/// A bridge to an external protocol. The server calls `connect()` when it starts the bridge and
+/// then calls `read_tags()` every poll interval with all of the bridge's tags. If either fails,
+/// the server calls `disconnect()` and tries to connect again after a reconnect interval.
+///
+/// The functions are called from the bridge's own thread, or from the Write service for
+/// `write_tag()`, so they may block while they wait for the external system, but a bridge is
+/// locked while it is called so a slow call holds up the others.
+pub trait Bridge {
+    /// Returns the name of the bridge for log messages, e.g. "modbus://plc1:502"
+    fn name(&self) -> String;
+
+    /// Opens the connection to the external system
+    fn connect(&mut self) -> Result<(), StatusCode>;
+
+    /// Closes the connection to the external system, if it is open
+    fn disconnect(&mut self) {}
+
+    /// Reads the values of the tags. Returns the values in the same order as the tags, or the
+    /// status code of each value which cannot be read, e.g. `BadNodeIdUnknown` for a tag that
+    /// does not exist. An error means the connection is lost.
+    fn read_tags(&mut self, tags: &[String]) -> Result<Vec<Result<Variant, StatusCode>>, StatusCode>;
+
+    /// Writes the value of a tag. The default implementation returns `BadNotWritable`.
+    fn write_tag(&mut self, _tag: &str, _value: &Variant) -> Result<(), StatusCode> {
+        Err(StatusCode::BadNotWritable)
+    }
+}
+
+/// A tag of a bridge and the variable that it is mapped to
+#[derive(Debug, Clone, PartialEq)]
+pub struct BridgeTag {
+    /// Address of the tag in the external protocol, e.g. "holding/40001"
+    pub tag: String,
+    /// Node id of the variable that the tag's value is set on. The variable must exist.
+    pub node_id: NodeId,
+    /// Writes of the variable are written to the tag if true
+    pub writable: bool,
+}
+
+impl BridgeTag {
+    /// Maps a tag to a read only variable
+    pub fn new<T, N>(tag: T, node_id: N) -> BridgeTag where T: Into<String>, N: Into<NodeId> {
+        BridgeTag {
+            tag: tag.into(),
+            node_id: node_id.into(),
+            writable: false,
+        }
+    }
+
+    /// Maps a tag to a variable whose writes are written to the tag
+    pub fn new_writable<T, N>(tag: T, node_id: N) -> BridgeTag where T: Into<String>, N: Into<NodeId> {
+        BridgeTag {
+            writable: true,
+            ..BridgeTag::new(tag, node_id)
+        }
+    }
+}
+
+/// Runs a bridge, i.e. connects it, polls its tags and reconnects it when it loses its connection
+pub(crate) struct BridgeRunner {
+    /// The bridge, which is shared with the setters of its writable variables
+    bridge: Arc<Mutex<dyn Bridge + Send>>,
+    /// Tags of the bridge
+    tags: Vec<BridgeTag>,
+    /// The tag addresses which are passed to the bridge, in the same order as the tags
+    tag_names: Vec<String>,
+    /// Interval in milliseconds between polls of the tags
+    poll_interval: u64,
+    /// True if the bridge is connected
+    connected: bool,
+    /// Time of the next attempt to connect
+    next_connect: Instant,
+}
+
+impl BridgeRunner {
+    pub fn new<B>(bridge: B, tags: Vec<BridgeTag>, poll_interval: u64) -> BridgeRunner where B: Bridge + Send + 'static {
+        let tag_names = tags.iter().map(|tag| tag.tag.clone()).collect();
+        BridgeRunner {
+            bridge: Arc::new(Mutex::new(bridge)),
+            tags,
+            tag_names,
+            poll_interval,
+            connected: false,
+            next_connect: Instant::now(),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        trace_lock_unwrap!(self.bridge).name()
+    }
+
+    pub fn poll_interval(&self) -> u64 {
+        self.poll_interval
+    }
+
+    /// Sets async setters on the variables of the writable tags which write their values to the
+    /// bridge. A tag whose variable does not exist is logged and ignored.
+    pub fn set_variable_setters(&self, address_space: &mut AddressSpace) {
+        for tag in &self.tags {
+            if address_space.find_variable_by_ref(&tag.node_id).is_none() {
+                error!("Bridge tag {} cannot be mapped, node {:?} is not a variable", tag.tag, tag.node_id);
+            } else if tag.writable {
+                let bridge = self.bridge.clone();
+                let tag_name = tag.tag.clone();
+                address_space.set_variable_async_setter(tag.node_id.clone(), move |_, _, data_value| {
+                    let value = data_value.value.unwrap_or(Variant::Empty);
+                    let mut bridge = trace_lock_unwrap!(bridge);
+                    bridge.write_tag(&tag_name, &value)
+                });
+            }
+        }
+    }
+
+    /// Connects the bridge if it is not connected and its reconnect interval has passed, and
+    /// then reads its tags and sets the values of their variables. A bridge which fails to
+    /// connect or loses its connection is disconnected and tried again on the reconnect interval.
+    pub fn poll(&mut self, address_space: &Arc<SharedAddressSpace>) {
+        let now = Instant::now();
+        if !self.connected {
+            if now < self.next_connect {
+                return;
+            }
+            let mut bridge = trace_lock_unwrap!(self.bridge);
+            match bridge.connect() {
+                Ok(_) => {
+                    info!("Bridge {} is connected", bridge.name());
+                    self.connected = true;
+                }
+                Err(err) => {
+                    warn!("Bridge {} cannot connect, error = {}", bridge.name(), err);
+                    bridge.disconnect();
+                    self.next_connect = now + Duration::from_millis(constants::BRIDGE_RECONNECT_INTERVAL_MS);
+                    return;
+                }
+            }
+        }
+
+        let values = {
+            let mut bridge = trace_lock_unwrap!(self.bridge);
+            match bridge.read_tags(&self.tag_names) {
+                Ok(ref values) if values.len() != self.tag_names.len() => {
+                    error!("Bridge {} returned {} values for {} tags", bridge.name(), values.len(), self.tag_names.len());
+                    return;
+                }
+                Ok(values) => values,
+                Err(err) => {
+                    warn!("Bridge {} lost its connection, error = {}", bridge.name(), err);
+                    bridge.disconnect();
+                    self.connected = false;
+                    self.next_connect = now + Duration::from_millis(constants::BRIDGE_RECONNECT_INTERVAL_MS);
+                    return;
+                }
+            }
+        };
+
+        let now = DateTime::now();
+        let address_space = trace_read_lock_unwrap!(address_space);
+        for (tag, value) in self.tags.iter().zip(values) {
+            match value {
+                Ok(value) => {
+                    let _ = address_space.set_variable_value_by_ref(&tag.node_id, value, &now, &now);
+                }
+                Err(err) => {
+                    debug!("Bridge tag {} cannot be read, error = {}", tag.tag, err);
+                }
+            }
+        }
+    }
+
+    /// Disconnects the bridge if it is connected
+    pub fn stop(&mut self) {
+        if self.connected {
+            trace_lock_unwrap!(self.bridge).disconnect();
+            self.connected = false;
+        }
+    }
+}
+
+/// Starts a thread that polls the bridge every poll interval for as long as the server is running
+/// and disconnects it when the server stops.
+pub(crate) fn start_bridge(server_state: Arc<RwLock<ServerState>>, address_space: Arc<SharedAddressSpace>, mut runner: BridgeRunner) {
+    {
+        let mut address_space = trace_write_lock_unwrap!(address_space);
+        runner.set_variable_setters(&mut address_space);
+    }
+    let _ = thread::spawn(move || {
+        let poll_interval = Duration::from_millis(runner.poll_interval());
+        loop {
+            {
+                let server_state = trace_read_lock_unwrap!(server_state);
+                if server_state.is_abort() || !server_state.is_running() {
+                    break;
+                }
+            }
+            let started = Instant::now();
+            runner.poll(&address_space);
+            let elapsed = started.elapsed();
+            if elapsed < poll_interval {
+                thread::sleep(poll_interval - elapsed);
+            }
+        }
+        runner.stop();
+        info!("Bridge {} is finished", runner.name());
+    });
+}
@@ -4,7 +4,7 @@ use opcua_core::config::Config;
 
 use crate::{
     constants,
-    config::{ServerConfig, ServerEndpoint, ServerUserToken, ANONYMOUS_USER_TOKEN_ID},
+    config::{AggregatedServerConfig, ServerConfig, ServerEndpoint, DiscoveryRegistrationConfig, MqttForwarderConfig, OperationalLimits, PubSubConnectionConfig, RestGatewayConfig, ServerUserToken, SimulatedVariableConfig, SimulationWaveform, TcpListenAddress, TlsConfig, ANONYMOUS_USER_TOKEN_ID},
     server::Server,
 };
 
@@ -35,6 +35,24 @@ impl ServerBuilder {
             .discovery_urls(vec![
                 DEFAULT_ENDPOINT_PATH.into()
             ])
+            .simulated_variable(Self::sample_simulated_variable("Sine", SimulationWaveform::Sine))
+            .simulated_variable(Self::sample_simulated_variable("Ramp", SimulationWaveform::Ramp))
+            .simulated_variable(Self::sample_simulated_variable("RandomWalk", SimulationWaveform::RandomWalk))
+            .simulated_variable(Self::sample_simulated_variable("Square", SimulationWaveform::Square))
+    }
+
+    /// A simulated variable of the sample configuration, which has a period of 10s and updates
+    /// every 500ms
+    fn sample_simulated_variable(name: &str, waveform: SimulationWaveform) -> SimulatedVariableConfig {
+        SimulatedVariableConfig {
+            node_id: format!("ns=2;s=Simulation.{}", name),
+            name: name.to_string(),
+            waveform,
+            period: 10000,
+            amplitude: 100.0,
+            offset: 0.0,
+            update_interval: 500,
+        }
     }
 
     /// Sample mode turns on everything including a hard coded user/pass
@@ -60,21 +78,36 @@ impl ServerBuilder {
                 pass: Some("unused1".to_string()),
                 x509: None,
             })
-            .endpoints(vec![
-                ("none", ServerEndpoint::new_none(path, &user_token_ids)),
-                ("basic128rsa15_sign", ServerEndpoint::new_basic128rsa15_sign(path, &user_token_ids)),
-                ("basic128rsa15_sign_encrypt", ServerEndpoint::new_basic128rsa15_sign_encrypt(path, &user_token_ids)),
-                ("basic256_sign", ServerEndpoint::new_basic256_sign(path, &user_token_ids)),
-                ("basic256_sign_encrypt", ServerEndpoint::new_basic256_sign_encrypt(path, &user_token_ids)),
-                ("basic256sha256_sign", ServerEndpoint::new_basic256sha256_sign(path, &user_token_ids)),
-                ("basic256sha256_sign_encrypt", ServerEndpoint::new_basic256sha256_sign_encrypt(path, &user_token_ids)),
-                ("no_access", ServerEndpoint::new_none("/noaccess", &[]))
-            ])
+            .endpoints(Self::sample_endpoints(path, &user_token_ids))
             .discovery_urls(vec![
                 DEFAULT_ENDPOINT_PATH.into()
             ])
     }
 
+    /// The endpoints of the sample configuration. Secure endpoints are only present when the server
+    /// is built with the `crypto` feature.
+    fn sample_endpoints(path: &str, user_token_ids: &[String]) -> Vec<(&'static str, ServerEndpoint)> {
+        let mut endpoints = vec![
+            ("none", ServerEndpoint::new_none(path, user_token_ids)),
+        ];
+        #[cfg(feature = "crypto")] {
+            endpoints.extend(vec![
+                ("basic128rsa15_sign", ServerEndpoint::new_basic128rsa15_sign(path, user_token_ids)),
+                ("basic128rsa15_sign_encrypt", ServerEndpoint::new_basic128rsa15_sign_encrypt(path, user_token_ids)),
+                ("basic256_sign", ServerEndpoint::new_basic256_sign(path, user_token_ids)),
+                ("basic256_sign_encrypt", ServerEndpoint::new_basic256_sign_encrypt(path, user_token_ids)),
+                ("basic256sha256_sign", ServerEndpoint::new_basic256sha256_sign(path, user_token_ids)),
+                ("basic256sha256_sign_encrypt", ServerEndpoint::new_basic256sha256_sign_encrypt(path, user_token_ids)),
+                ("aes128sha256rsaoaep_sign", ServerEndpoint::new_aes128sha256rsaoaep_sign(path, user_token_ids)),
+                ("aes128sha256rsaoaep_sign_encrypt", ServerEndpoint::new_aes128sha256rsaoaep_sign_encrypt(path, user_token_ids)),
+                ("aes256sha256rsapss_sign", ServerEndpoint::new_aes256sha256rsapss_sign(path, user_token_ids)),
+                ("aes256sha256rsapss_sign_encrypt", ServerEndpoint::new_aes256sha256rsapss_sign_encrypt(path, user_token_ids)),
+            ]);
+        }
+        endpoints.push(("no_access", ServerEndpoint::new_none("/noaccess", &[])));
+        endpoints
+    }
+
     /// Yields a [`Client`] from the values set by the builder. If the builder is not in a valid state
     /// it will return `None`.
     ///
@@ -156,6 +189,13 @@ impl ServerBuilder {
         self
     }
 
+    /// Sets the additional discovery server urls, the retry policy and the server capabilities
+    /// used when registering the server with discovery servers.
+    pub fn discovery_registration(mut self, discovery_registration: DiscoveryRegistrationConfig) -> Self {
+        self.config.discovery_registration = discovery_registration;
+        self
+    }
+
     /// Sets the hostname and port to listen on
     pub fn host_and_port<T>(mut self, host: T, port: u16) -> Self where T: Into<String> {
         self.config.tcp_config.host = host.into();
@@ -163,6 +203,75 @@ impl ServerBuilder {
         self
     }
 
+    /// Adds an address and port that the server listens on in addition to its host and port, e.g.
+    /// another network interface or an IPv6 address such as `::`
+    pub fn listen_address<T>(mut self, host: T, port: u16) -> Self where T: Into<String> {
+        self.config.tcp_config.listen_addresses.push(TcpListenAddress {
+            host: host.into(),
+            port,
+        });
+        self
+    }
+
+    /// Enables the experimental opc.tls transport on the port, using the PEM certificate chain and
+    /// private key for the TLS handshake. Endpoints are served through it when their `tls` flag is
+    /// set. Requires the server to be built with the tls feature.
+    pub fn tls<T>(mut self, port: u16, certificate_path: T, private_key_path: T) -> Self where T: Into<PathBuf> {
+        self.config.tcp_config.tls = Some(TlsConfig {
+            port,
+            certificate_path: certificate_path.into(),
+            private_key_path: private_key_path.into(),
+        });
+        self
+    }
+
+    /// Enables the experimental opc.https transport on the port, using the PEM certificate chain
+    /// and private key for the TLS handshake. Endpoints are served through it when their `https`
+    /// flag is set. Requires the server to be built with the https feature.
+    pub fn https<T>(mut self, port: u16, certificate_path: T, private_key_path: T) -> Self where T: Into<PathBuf> {
+        self.config.tcp_config.https = Some(TlsConfig {
+            port,
+            certificate_path: certificate_path.into(),
+            private_key_path: private_key_path.into(),
+        });
+        self
+    }
+
+    /// Sets the sizes in bytes of the buffers that chunks are received into and sent from. These
+    /// are upper limits, the sizes are negotiated with each client during HELLO.
+    pub fn buffer_sizes(mut self, receive_buffer_size: usize, send_buffer_size: usize) -> Self {
+        self.config.tcp_config.receive_buffer_size = receive_buffer_size;
+        self.config.tcp_config.send_buffer_size = send_buffer_size;
+        self
+    }
+
+    /// Sets the maximum size in bytes of a request and the maximum number of chunks it may be
+    /// split into. A value of 0 means no limit.
+    pub fn max_message_size_and_chunk_count(mut self, max_message_size: usize, max_chunk_count: usize) -> Self {
+        self.config.tcp_config.max_message_size = max_message_size;
+        self.config.tcp_config.max_chunk_count = max_chunk_count;
+        self
+    }
+
+    /// Sets the limits which protect the server from a misbehaving client - the maximum number of
+    /// connections open at once from one client address, the maximum number of chunks per second
+    /// a connection may send, and the number of messages which fail to decode that a connection
+    /// may send before it is closed. A value of 0 for the first two means no limit.
+    pub fn connection_limits(mut self, max_connections_per_client: usize, max_chunk_rate: usize, max_decoding_errors: usize) -> Self {
+        self.config.tcp_config.max_connections_per_client = max_connections_per_client;
+        self.config.tcp_config.max_chunk_rate = max_chunk_rate;
+        self.config.tcp_config.max_decoding_errors = max_decoding_errors;
+        self
+    }
+
+    /// Sets the range in milliseconds that the lifetime a client requests for a secure channel
+    /// token is revised to fit within. A channel whose token expires without being renewed is closed.
+    pub fn token_lifetime_range(mut self, min_token_lifetime: u32, max_token_lifetime: u32) -> Self {
+        self.config.tcp_config.min_token_lifetime = min_token_lifetime;
+        self.config.tcp_config.max_token_lifetime = max_token_lifetime;
+        self
+    }
+
     /// Discovery endpoint urls - the urls of this server used by clients to get endpoints.
     /// If the url is relative, e.g. "/" then the code will make a url for you using the port/host
     /// settings as they are at the time this function is executed.
@@ -178,12 +287,101 @@ impl ServerBuilder {
         self
     }
 
+    /// Urls of clients that the server makes reverse connections to, e.g. "opc.tcp://client:4844"
+    pub fn reverse_connect_urls(mut self, reverse_connect_urls: Vec<String>) -> Self {
+        self.config.reverse_connect_urls = reverse_connect_urls;
+        self
+    }
+
+    /// Adds a connection to an MQTT broker that the server publishes PubSub network messages to
+    pub fn pubsub_connection(mut self, pubsub_connection: PubSubConnectionConfig) -> Self {
+        self.config.pubsub_connections.push(pubsub_connection);
+        self
+    }
+
+    /// Adds a forwarder which publishes the value changes of variables to an MQTT broker
+    pub fn mqtt_forwarder(mut self, mqtt_forwarder: MqttForwarderConfig) -> Self {
+        self.config.mqtt_forwarders.push(mqtt_forwarder);
+        self
+    }
+
+    /// Adds a variable that the server animates with simulated values
+    pub fn simulated_variable(mut self, simulated_variable: SimulatedVariableConfig) -> Self {
+        self.config.simulated_variables.push(simulated_variable);
+        self
+    }
+
+    /// Adds another server whose address space the server mirrors beneath its Objects folder
+    pub fn aggregated_server(mut self, aggregated_server: AggregatedServerConfig) -> Self {
+        self.config.aggregated_servers.push(aggregated_server);
+        self
+    }
+
+    /// Serves the REST gateway, an HTTP JSON API for reading, writing and browsing nodes. Requires
+    /// the server to be built with the http feature.
+    pub fn rest_gateway(mut self, rest_gateway: RestGatewayConfig) -> Self {
+        self.config.rest_gateway = Some(rest_gateway);
+        self
+    }
+
+    /// Maximum number of sessions open on the server at the same time, 0 means no limit
+    pub fn max_sessions(mut self, max_sessions: u32) -> Self {
+        self.config.max_sessions = max_sessions;
+        self
+    }
+
+    /// Sets the range in milliseconds that a client's requested session timeout is revised to fit
+    /// within. A session with no requests for longer than its timeout is closed.
+    pub fn session_timeout_range(mut self, min_session_timeout: f64, max_session_timeout: f64) -> Self {
+        self.config.min_session_timeout = min_session_timeout;
+        self.config.max_session_timeout = max_session_timeout;
+        self
+    }
+
     /// Maximum number of subscriptions in a session
     pub fn max_subscriptions(mut self, max_subscriptions: u32) -> Self {
         self.config.max_subscriptions = max_subscriptions;
         self
     }
 
+    /// Maximum number of monitored items in a subscription, 0 means no limit
+    pub fn max_monitored_items_per_subscription(mut self, max_monitored_items_per_subscription: u32) -> Self {
+        self.config.max_monitored_items_per_subscription = max_monitored_items_per_subscription;
+        self
+    }
+
+    /// Minimum sampling interval in milliseconds of monitored items
+    pub fn min_sampling_interval(mut self, min_sampling_interval: f64) -> Self {
+        self.config.min_sampling_interval = min_sampling_interval;
+        self
+    }
+
+    /// Maximum number of publish requests that a session may have queued, 0 means no limit other
+    /// than two per subscription
+    pub fn max_publish_requests(mut self, max_publish_requests: u32) -> Self {
+        self.config.max_publish_requests = max_publish_requests;
+        self
+    }
+
+    /// Maximum number of browse continuation points that a session may hold
+    pub fn max_browse_continuation_points(mut self, max_browse_continuation_points: u32) -> Self {
+        self.config.max_browse_continuation_points = max_browse_continuation_points;
+        self
+    }
+
+    /// Time in milliseconds to wait for the async getters and setters of variables during a read
+    /// or write
+    pub fn async_timeout(mut self, async_timeout: u32) -> Self {
+        self.config.async_timeout = async_timeout;
+        self
+    }
+
+    /// Limits on the number of operations in a single request to a service
+    pub fn operational_limits(mut self, operational_limits: OperationalLimits) -> Self {
+        self.config.operational_limits = operational_limits;
+        self
+    }
+
     /// Max array length in elements
     pub fn max_array_length(mut self, max_array_length: u32) -> Self {
         self.config.max_array_length = max_array_length;
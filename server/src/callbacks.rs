@@ -1,16 +1,26 @@
 //! Callbacks that a server implementation may register with the library
 
+use std::net::SocketAddr;
 use std::sync::{Arc, RwLock};
 
+use futures::Future;
+
 use opcua_types::{
     NodeId,
+    ByteString,
+    UAString,
     DataValue,
     AttributeId,
     status_code::StatusCode,
-    service_types::{CallMethodRequest, CallMethodResult},
+    service_types::{
+        CallMethodRequest, CallMethodResult, ReadRawModifiedDetails, ReadProcessedDetails,
+        ReadAtTimeDetails, TimestampsToReturn, UpdateDataDetails, UpdateEventDetails,
+        DeleteRawModifiedDetails, DeleteAtTimeDetails, DeleteEventDetails, NodeTypeDescription,
+        ContentFilter,
+    },
 };
 
-use crate::session::Session;
+use crate::{address_space::AddressSpace, events::event::Event, session::Session};
 
 /// An attribute getter trait is used to obtain the data value associated with the particular attribute id
 /// This allows server implementations to supply a value on demand, usually in response to a polling action
@@ -37,6 +47,42 @@ pub trait AttributeSetter {
     fn set(&mut self, node_id: &NodeId, attribute_id: AttributeId, data_value: DataValue) -> Result<(), StatusCode>;
 }
 
+/// The future returned by an `AsyncAttributeGetter`, which resolves to the value of the attribute
+pub type AsyncAttributeValue = Box<dyn Future<Item=Option<DataValue>, Error=StatusCode> + Send>;
+
+/// The future returned by an `AsyncAttributeSetter`, which resolves once the value has been set
+pub type AsyncAttributeResult = Box<dyn Future<Item=(), Error=StatusCode> + Send>;
+
+/// An async attribute getter obtains the value of an attribute from a source which may be slow to
+/// respond, such as a device that must be polled or a database query. The Read service does not
+/// wait for the value while it holds the address space, it sends the response once the values of
+/// all the requested nodes have resolved, or failed with `BadTimeout` if they take longer than
+/// the server's `async_timeout`.
+///
+/// `max_age` has the same meaning as for `AttributeGetter`.
+pub trait AsyncAttributeGetter {
+    /// Returns a future which resolves to some datavalue or none
+    fn get(&mut self, node_id: &NodeId, attribute_id: AttributeId, max_age: f64) -> AsyncAttributeValue;
+}
+
+/// An async attribute setter writes the value of an attribute to a source which may be slow to
+/// respond. The Write service sends its response once the setters have finished, or failed with
+/// `BadTimeout` if they take longer than the server's `async_timeout`.
+pub trait AsyncAttributeSetter {
+    /// Returns a future which sets the attribute on the specified node
+    fn set(&mut self, node_id: &NodeId, attribute_id: AttributeId, data_value: DataValue) -> AsyncAttributeResult;
+}
+
+/// A node value provider supplies the values of many variables at once from an external data
+/// source, such as a PLC driver which reads a block of registers in one request. The Read service
+/// and the sampling of monitored items call the provider once with all of its nodes that they need
+/// values for, rather than once per node.
+pub trait NodeValueProvider {
+    /// Returns the values of the nodes in the same order as the node ids, or the status code of
+    /// each value which cannot be read. `max_age` has the same meaning as for `AttributeGetter`.
+    fn read_values(&mut self, node_ids: &[NodeId], max_age: f64) -> Vec<Result<DataValue, StatusCode>>;
+}
+
 /// Called by RegisterNodes service
 pub trait RegisterNodes {
     /// Called when a client calls the RegisterNodes service. This implementation should return a list
@@ -69,3 +115,185 @@ pub trait Method {
     /// be invoked to handle the call.
     fn call(&mut self, session: &mut Session, request: &CallMethodRequest) -> Result<CallMethodResult, StatusCode>;
 }
+
+/// Called by the HistoryRead and HistoryUpdate services to read and modify historical values. A
+/// server that stores history registers an implementation with `ServerState::set_historical_data_provider`.
+///
+/// Only variables whose access level contains `HISTORY_READ` are passed to the read functions and
+/// only variables whose access level contains `HISTORY_WRITE` are passed to the data update
+/// functions. Every function has a default implementation that returns `BadHistoryOperationUnsupported`
+/// so an implementation need only supply the operations that it actually supports.
+///
+/// Values should be returned in the order described by the details, i.e. forwards in time if the
+/// start time is before the end time and backwards if it is after. The server takes care of
+/// splitting large results and handing out continuation points to the client.
+pub trait HistoricalDataProvider {
+    /// Reads raw or modified values between the start and end times in the details. The
+    /// `num_values_per_node` in the details is a limit per call which the server enforces
+    /// through continuation points, so an implementation may return more values than that.
+    fn read_raw_modified_details(&mut self, _node_id: &NodeId, _details: &ReadRawModifiedDetails, _timestamps_to_return: TimestampsToReturn) -> Result<Vec<DataValue>, StatusCode> {
+        Err(StatusCode::BadHistoryOperationUnsupported)
+    }
+
+    /// Reads values that are computed by applying the aggregate function to the raw values in
+    /// each processing interval of the details.
+    fn read_processed_details(&mut self, _node_id: &NodeId, _aggregate_type: &NodeId, _details: &ReadProcessedDetails, _timestamps_to_return: TimestampsToReturn) -> Result<Vec<DataValue>, StatusCode> {
+        Err(StatusCode::BadHistoryOperationUnsupported)
+    }
+
+    /// Reads values at each of the requested times in the details, interpolating if there is no
+    /// value stored for that exact time.
+    fn read_at_time_details(&mut self, _node_id: &NodeId, _details: &ReadAtTimeDetails, _timestamps_to_return: TimestampsToReturn) -> Result<Vec<DataValue>, StatusCode> {
+        Err(StatusCode::BadHistoryOperationUnsupported)
+    }
+
+    /// Inserts, replaces or updates the values of the node in the details. The result holds a
+    /// status code for each value in the same order, e.g. `GoodEntryInserted`, `GoodEntryReplaced`,
+    /// `BadEntryExists` or `BadNoEntryExists`.
+    fn update_data_details(&mut self, _details: &UpdateDataDetails) -> Result<Vec<StatusCode>, StatusCode> {
+        Err(StatusCode::BadHistoryOperationUnsupported)
+    }
+
+    /// Inserts, replaces or updates the events of the node in the details. The result holds a
+    /// status code for each event in the same order.
+    fn update_event_details(&mut self, _details: &UpdateEventDetails) -> Result<Vec<StatusCode>, StatusCode> {
+        Err(StatusCode::BadHistoryOperationUnsupported)
+    }
+
+    /// Deletes raw or modified values between the start and end times in the details.
+    fn delete_raw_modified_details(&mut self, _details: &DeleteRawModifiedDetails) -> Result<(), StatusCode> {
+        Err(StatusCode::BadHistoryOperationUnsupported)
+    }
+
+    /// Deletes the values at each of the times in the details. The result holds a status code for
+    /// each time in the same order.
+    fn delete_at_time_details(&mut self, _details: &DeleteAtTimeDetails) -> Result<Vec<StatusCode>, StatusCode> {
+        Err(StatusCode::BadHistoryOperationUnsupported)
+    }
+
+    /// Deletes the events with the ids in the details. The result holds a status code for each
+    /// event id in the same order.
+    fn delete_event_details(&mut self, _details: &DeleteEventDetails) -> Result<Vec<StatusCode>, StatusCode> {
+        Err(StatusCode::BadHistoryOperationUnsupported)
+    }
+}
+
+/// Validates the token data of an `IssuedIdentityToken`, e.g. a JWT obtained by the client from an
+/// OAuth2 authorization service. A server that accepts issued tokens registers an implementation
+/// with `ServerState::set_issued_token_validator` and adds the `ISSUED_TOKEN` user token id to the
+/// endpoints that should accept them.
+pub trait IssuedTokenValidator {
+    /// The type of token that is accepted, e.g. `http://opcfoundation.org/UA/UserToken#JWT`. This
+    /// is advertised to clients in the user token policy of the endpoint.
+    fn issued_token_type(&self) -> UAString;
+
+    /// The url of the service that issues tokens, advertised to clients in the user token policy
+    /// of the endpoint. For JWTs this is a JSON document describing the authorization service.
+    fn issuer_endpoint_url(&self) -> UAString {
+        UAString::null()
+    }
+
+    /// Validates the token data, which has already been decrypted if the client encrypted it. An
+    /// implementation returns `BadIdentityTokenInvalid` if the token cannot be parsed and
+    /// `BadIdentityTokenRejected` if it is parsed but not accepted, e.g. it has expired or its
+    /// signature is wrong.
+    fn validate(&self, endpoint_url: &str, token_data: &ByteString) -> Result<(), StatusCode>;
+}
+
+/// Receives the audit events that the server raises for security related operations such as
+/// creating and activating sessions, rejected certificates, writes and method calls. A server that
+/// keeps an audit trail registers an implementation with `ServerState::set_audit_log`.
+pub trait AuditLog {
+    /// Called for each audit event. The event is also raised on the `Server` object for clients
+    /// that monitor it for events.
+    fn log(&mut self, event: &Event);
+}
+
+/// The identity of the user of a session, as authenticated by ActivateSession or, for session-less
+/// calls, by the access token of the request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UserIdentity {
+    /// The session has not been activated so it has no user
+    NotActivated,
+    /// An anonymous user
+    Anonymous,
+    /// A user authenticated by user name and password
+    UserName(String),
+    /// A user authenticated by an X509 certificate, holding the DER of the certificate
+    X509(ByteString),
+    /// A user authenticated by an issued token or access token, holding the decrypted token data
+    IssuedToken(ByteString),
+}
+
+/// Decides what the user of a session may do to the nodes of the address space. A server that
+/// restricts access per user registers an implementation with `ServerState::set_access_control`.
+///
+/// The access control is consulted by the Read, Write, Browse and Call services after the access
+/// level of variables and the executable flag of methods have been checked, so it can only restrict
+/// access further. Denied operations fail with `BadUserAccessDenied`. Every function has a default
+/// implementation that allows access so an implementation need only supply the checks it needs.
+pub trait AccessControl {
+    /// Tests if the user may read the attribute of the node
+    fn can_read(&self, _identity: &UserIdentity, _node_id: &NodeId, _attribute_id: AttributeId) -> bool {
+        true
+    }
+
+    /// Tests if the user may write the attribute of the node
+    fn can_write(&self, _identity: &UserIdentity, _node_id: &NodeId, _attribute_id: AttributeId) -> bool {
+        true
+    }
+
+    /// Tests if the user may browse the node. The node is hidden from the references returned when
+    /// browsing other nodes if it may not be browsed.
+    fn can_browse(&self, _identity: &UserIdentity, _node_id: &NodeId) -> bool {
+        true
+    }
+
+    /// Tests if the user may call the method on the object
+    fn can_call(&self, _identity: &UserIdentity, _object_id: &NodeId, _method_id: &NodeId) -> bool {
+        true
+    }
+}
+
+/// Finds the nodes that match a query made through the QueryFirst service. By default the server
+/// uses `AddressSpaceQueryEvaluator` which searches the instances of the address space. A server
+/// that can answer queries more efficiently, e.g. from a database, registers an implementation with
+/// `ServerState::set_query_evaluator`.
+///
+/// The node type descriptions and content filter have been validated before the evaluator is
+/// called. The server drops any nodes that the user of the session may not browse, reads the data
+/// to return for each node and hands out continuation points when there are too many results.
+pub trait QueryEvaluator {
+    /// Returns the ids of the nodes that are instances of one of the node types and which satisfy
+    /// the content filter, each with the index of the node type description that it matched. A
+    /// filter with no elements matches every instance.
+    fn evaluate(&self, address_space: &AddressSpace, node_types: &[NodeTypeDescription], filter: &ContentFilter) -> Result<Vec<(NodeId, usize)>, StatusCode>;
+}
+
+/// Receives the lifecycle events of the server's clients, i.e. their connections, sessions and
+/// subscriptions, e.g. to log them or to limit the clients that may connect. A server registers
+/// an implementation with `ServerState::set_server_callbacks`. Every function has a default
+/// implementation that does nothing so an implementation need only supply the events it needs.
+///
+/// The functions are called while the server state is locked so they must not lock it themselves.
+/// The session passed to them has the address of its client and the identity of its user.
+pub trait ServerCallbacks {
+    /// Called when a client connects, before anything has been read from the connection.
+    /// Returning an error refuses the connection.
+    fn on_client_connected(&mut self, _client_address: &SocketAddr) -> Result<(), StatusCode> {
+        Ok(())
+    }
+
+    /// Called when CreateSession has created a session
+    fn on_session_created(&mut self, _session: &Session) {}
+
+    /// Called when ActivateSession has activated a session with the identity of its user
+    fn on_session_activated(&mut self, _session: &Session) {}
+
+    /// Called when a session is closed by CloseSession, or when the connection of a session which
+    /// was not closed has closed or timed out
+    fn on_session_closed(&mut self, _session: &Session) {}
+
+    /// Called when a session has created a subscription
+    fn on_subscription_created(&mut self, _session: &Session, _subscription_id: u32) {}
+}
@@ -0,0 +1,190 @@
+//! Provides the experimental opc.https transport. Each request is the binary encoded body of an
+//! HTTP POST and its response is the body of the HTTP response. There is no secure channel because
+//! TLS secures the connection, so sessions are found from the authentication token of each request
+//! rather than from the connection that the request arrived on.
+//!
+//! Publish requests are not supported because there is no connection to send a response on later.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock, Mutex};
+
+use futures::{Future, Stream, future};
+use hyper::{self, Body, Method, Request, Response, header, server::conn::Http, service::service_fn};
+use tokio::{self, io::{AsyncRead, AsyncWrite}};
+
+use opcua_core::{
+    prelude::*,
+    comms::https_codec::{CONTENT_TYPE_UA_BINARY, encode_https_body, decode_https_body},
+};
+use opcua_types::service_types::{RequestHeader, ServiceFault};
+
+use crate::{
+    state::ServerState,
+    services::message_handler::MessageHandler,
+    session::Session,
+};
+
+/// A session created through the opc.https transport and the handler of its messages
+struct HttpsSession {
+    session: Arc<RwLock<Session>>,
+    message_handler: Arc<Mutex<MessageHandler>>,
+}
+
+/// The opc.https transport. It serves every connection made to the https port.
+pub(crate) struct HttpsTransport {
+    /// Server state, address space etc.
+    server_state: Arc<RwLock<ServerState>>,
+    /// Makes a new session and the handler of its messages
+    new_session: Box<dyn Fn() -> (Arc<RwLock<Session>>, MessageHandler) + Send + Sync>,
+    /// Sessions by their authentication token
+    sessions: RwLock<HashMap<NodeId, HttpsSession>>,
+}
+
+impl HttpsTransport {
+    pub fn new<F>(server_state: Arc<RwLock<ServerState>>, new_session: F) -> HttpsTransport
+        where F: Fn() -> (Arc<RwLock<Session>>, MessageHandler) + Send + Sync + 'static {
+        HttpsTransport {
+            server_state,
+            new_session: Box::new(new_session),
+            sessions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Serves HTTP requests on the stream until the client closes it. The stream has already
+    /// completed its TLS handshake.
+    pub fn serve<S>(transport: Arc<HttpsTransport>, stream: S, client_address: SocketAddr) where S: AsyncRead + AsyncWrite + Send + 'static {
+        info!("Serving opc.https connection from {}", client_address);
+        let service = service_fn(move |request: Request<Body>| {
+            let transport = transport.clone();
+            transport.handle_http_request(request)
+        });
+        tokio::spawn(Http::new().serve_connection(stream, service)
+            .map_err(move |err| {
+                error!("opc.https connection from {} failed, err = {:?}", client_address, err);
+            }));
+    }
+
+    fn handle_http_request(self: Arc<Self>, request: Request<Body>) -> Box<dyn Future<Item=Response<Body>, Error=hyper::Error> + Send> {
+        if request.method() != Method::POST {
+            return Box::new(future::ok(Self::http_response(hyper::StatusCode::METHOD_NOT_ALLOWED, Body::empty())));
+        }
+        let max_message_size = {
+            let server_state = trace_read_lock_unwrap!(self.server_state);
+            let config = trace_read_lock_unwrap!(server_state.config);
+            config.tcp_config.max_message_size
+        };
+        Box::new(request.into_body().concat2().map(move |body| {
+            if max_message_size > 0 && body.len() > max_message_size {
+                error!("opc.https request of {} bytes exceeds the max message size {}", body.len(), max_message_size);
+                Self::http_response(hyper::StatusCode::PAYLOAD_TOO_LARGE, Body::empty())
+            } else {
+                match self.handle_body(&body) {
+                    Ok(body) => {
+                        let mut response = Self::http_response(hyper::StatusCode::OK, Body::from(body));
+                        response.headers_mut().insert(header::CONTENT_TYPE, header::HeaderValue::from_static(CONTENT_TYPE_UA_BINARY));
+                        response
+                    }
+                    Err(status_code) => {
+                        error!("opc.https request cannot be processed, status code = {}", status_code);
+                        Self::http_response(hyper::StatusCode::BAD_REQUEST, Body::empty())
+                    }
+                }
+            }
+        }))
+    }
+
+    fn http_response(status: hyper::StatusCode, body: Body) -> Response<Body> {
+        let mut response = Response::new(body);
+        *response.status_mut() = status;
+        response
+    }
+
+    /// Decodes the request in the body, handles it with the session its authentication token
+    /// belongs to and returns the encoded response.
+    fn handle_body(&self, body: &[u8]) -> Result<Vec<u8>, StatusCode> {
+        let decoding_limits = {
+            let server_state = trace_read_lock_unwrap!(self.server_state);
+            let config = trace_read_lock_unwrap!(server_state.config);
+            config.decoding_limits()
+        };
+        let request = decode_https_body(body, &decoding_limits)?;
+        let request_header = Self::request_header(body, &decoding_limits)?;
+
+        self.remove_expired_sessions();
+
+        // Requests without a session, e.g. GetEndpoints, CreateSession or a session-less call,
+        // are handled by a new session which is kept only if the request creates it
+        let message_handler = {
+            let sessions = trace_read_lock_unwrap!(self.sessions);
+            sessions.get(&request_header.authentication_token).map(|s| s.message_handler.clone())
+        };
+        let (new_session, message_handler) = if let Some(message_handler) = message_handler {
+            (None, message_handler)
+        } else {
+            let (session, message_handler) = (self.new_session)();
+            (Some(session), Arc::new(Mutex::new(message_handler)))
+        };
+
+        let is_publish = if let SupportedMessage::PublishRequest(_) = request { true } else { false };
+        let response = if is_publish {
+            ServiceFault::new_supported_message(&request_header, StatusCode::BadServiceUnsupported)
+        } else {
+            let mut message_handler = trace_lock_unwrap!(message_handler);
+            match message_handler.handle_message(0, request) {
+                Ok(Some(response)) => response,
+                Ok(None) => ServiceFault::new_supported_message(&request_header, StatusCode::BadServiceUnsupported),
+                Err(status_code) => ServiceFault::new_supported_message(&request_header, status_code),
+            }
+        };
+
+        if let (Some(session), SupportedMessage::CreateSessionResponse(response)) = (new_session, &response) {
+            info!("opc.https session {} has been created", response.session_id);
+            let mut sessions = trace_write_lock_unwrap!(self.sessions);
+            sessions.insert(response.authentication_token.clone(), HttpsSession {
+                session,
+                message_handler,
+            });
+        }
+
+        encode_https_body(&response)
+    }
+
+    /// Decodes the header of the request in the body, which follows the node id of its encoding
+    fn request_header(body: &[u8], decoding_limits: &DecodingLimits) -> Result<RequestHeader, StatusCode> {
+        let mut stream = Cursor::new(body);
+        let _ = NodeId::decode(&mut stream, decoding_limits)?;
+        Ok(RequestHeader::decode(&mut stream, decoding_limits)?)
+    }
+
+    /// Removes sessions which have been closed or have received no requests for longer than their
+    /// session timeout.
+    fn remove_expired_sessions(&self) {
+        let now = chrono::Utc::now();
+        let mut timed_out = 0;
+        {
+            let mut sessions = trace_write_lock_unwrap!(self.sessions);
+            sessions.retain(|authentication_token, s| {
+                let mut session = trace_write_lock_unwrap!(s.session);
+                if session.is_timed_out(&now) {
+                    info!("opc.https session has received no requests for more than its session timeout and will now close");
+                    session.set_terminated();
+                    timed_out += 1;
+                    false
+                } else {
+                    !session.terminated() && session.authentication_token == *authentication_token
+                }
+            });
+        }
+        // Diagnostics are recorded after the sessions are released because the message handler
+        // locks the server state before a session
+        if timed_out > 0 {
+            let server_state = trace_read_lock_unwrap!(self.server_state);
+            let mut diagnostics = trace_write_lock_unwrap!(server_state.diagnostics);
+            for _ in 0..timed_out {
+                diagnostics.on_session_timeout();
+            }
+        }
+    }
+}
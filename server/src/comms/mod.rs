@@ -5,3 +5,7 @@ mod secure_channel_service;
 
 pub mod transport;
 pub mod tcp_transport;
+#[cfg(any(feature = "tls", feature = "https"))]
+pub(crate) mod tls;
+#[cfg(feature = "https")]
+pub(crate) mod https_transport;
@@ -1,4 +1,5 @@
 use std::result::Result;
+use std::sync::{Arc, RwLock};
 
 use opcua_types::{
     *,
@@ -7,7 +8,7 @@ use opcua_types::{
 };
 
 use opcua_core::comms::prelude::*;
-use opcua_core::crypto::SecurityPolicy;
+use opcua_core::crypto::{CertificateStore, SecurityPolicy, X509};
 
 struct SecureChannelState {
     // Issued flag
@@ -44,12 +45,31 @@ impl SecureChannelState {
 pub struct SecureChannelService {
     // Secure channel info for the session
     secure_channel_state: SecureChannelState,
+    // Certificate store that the client's certificate is checked against for revocation
+    certificate_store: Arc<RwLock<CertificateStore>>,
+    // Range in millis that the lifetime requested for a security token is revised to fit within
+    token_lifetime_range: (u32, u32),
 }
 
 impl SecureChannelService {
-    pub fn new() -> SecureChannelService {
+    pub fn new(certificate_store: Arc<RwLock<CertificateStore>>, min_token_lifetime: u32, max_token_lifetime: u32) -> SecureChannelService {
         SecureChannelService {
             secure_channel_state: SecureChannelState::new(),
+            certificate_store,
+            token_lifetime_range: (min_token_lifetime, max_token_lifetime),
+        }
+    }
+
+    /// Revises the lifetime in millis requested for a security token to fit within the configured
+    /// range. A requested lifetime of 0 gets the largest permitted value.
+    pub(crate) fn revise_token_lifetime(&self, requested_lifetime: u32) -> u32 {
+        let (min_token_lifetime, max_token_lifetime) = self.token_lifetime_range;
+        if requested_lifetime == 0 || requested_lifetime > max_token_lifetime {
+            max_token_lifetime
+        } else if requested_lifetime < min_token_lifetime {
+            min_token_lifetime
+        } else {
+            requested_lifetime
         }
     }
 
@@ -128,10 +148,26 @@ impl SecureChannelService {
         // Create a new secure channel info
         let security_mode = request.security_mode;
         secure_channel.set_security_mode(security_mode);
-        secure_channel.set_token_id(self.secure_channel_state.create_token_id());
-        secure_channel.set_secure_channel_id(self.secure_channel_state.create_secure_channel_id());
         secure_channel.set_remote_cert_from_byte_string(&security_header.sender_certificate)?;
 
+        // Reject a client whose certificate, or the certificate of an issuer supplied with it, has
+        // been revoked. The rest of the chain is validated when the session is created.
+        if secure_channel.security_policy() != SecurityPolicy::None && !security_header.sender_certificate.is_null() {
+            let chain = X509::chain_from_byte_string(&security_header.sender_certificate)?;
+            let certificate_store = trace_read_lock_unwrap!(self.certificate_store);
+            let status_code = chain.iter().enumerate().map(|(i, cert)| {
+                match certificate_store.check_revocation(cert, &chain[i + 1..]) {
+                    StatusCode::BadCertificateRevoked if i > 0 => StatusCode::BadCertificateIssuerRevoked,
+                    StatusCode::BadCertificateRevocationUnknown if i > 0 => StatusCode::BadCertificateIssuerRevocationUnknown,
+                    status_code => status_code
+                }
+            }).find(|status_code| status_code.is_bad()).unwrap_or(StatusCode::Good);
+            if status_code.is_bad() {
+                error!("Client certificate failed the revocation check, {}", status_code);
+                return Ok(ServiceFault::new_supported_message(&request.request_header, status_code));
+            }
+        }
+
         let nonce_result = secure_channel.set_remote_nonce_from_byte_string(&request.client_nonce);
         if nonce_result.is_ok() {
             secure_channel.create_random_nonce();
@@ -140,6 +176,23 @@ impl SecureChannelService {
             return Ok(ServiceFault::new_supported_message(&request.request_header, nonce_result.unwrap_err()));
         }
 
+        // A renewed token belongs to the same channel. The token it replaces stays valid until the
+        // client uses the new one or it expires.
+        let channel_id = if request.request_type == SecurityTokenRequestType::Renew {
+            secure_channel.secure_channel_id()
+        } else {
+            secure_channel.clear_security_token();
+            self.secure_channel_state.create_secure_channel_id()
+        };
+        let security_token = ChannelSecurityToken {
+            channel_id,
+            token_id: self.secure_channel_state.create_token_id(),
+            created_at: DateTime::now(),
+            revised_lifetime: self.revise_token_lifetime(request.requested_lifetime),
+        };
+        debug!("Secure channel {} has token {} with a lifetime of {}ms", security_token.channel_id, security_token.token_id, security_token.revised_lifetime);
+        secure_channel.set_security_token(security_token.clone());
+
         let security_policy = secure_channel.security_policy();
         if security_policy != SecurityPolicy::None && (security_mode == MessageSecurityMode::Sign || security_mode == MessageSecurityMode::SignAndEncrypt) {
             secure_channel.derive_keys();
@@ -148,12 +201,7 @@ impl SecureChannelService {
         let response = OpenSecureChannelResponse {
             response_header: ResponseHeader::new_good(&request.request_header),
             server_protocol_version: 0,
-            security_token: ChannelSecurityToken {
-                channel_id: secure_channel.secure_channel_id(),
-                token_id: secure_channel.token_id(),
-                created_at: DateTime::now(),
-                revised_lifetime: request.requested_lifetime,
-            },
+            security_token,
             server_nonce: secure_channel.local_nonce_as_byte_string(),
         };
         Ok(response.into())
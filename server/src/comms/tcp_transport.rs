@@ -17,9 +17,10 @@ use futures::{
     Stream, Future,
     future,
     sync::mpsc::{self, UnboundedSender, UnboundedReceiver, unbounded},
+    sync::oneshot,
 };
 use tokio::{self, net::TcpStream};
-use tokio_io::{AsyncRead, io::{self, ReadHalf, WriteHalf}};
+use tokio_io::{AsyncRead, AsyncWrite, io};
 use tokio_codec::FramedRead;
 use tokio_timer::Interval;
 
@@ -27,14 +28,15 @@ use opcua_core::{
     prelude::*,
     comms::{
         message_writer::MessageWriter,
+        buffer_pool::BufferPool,
         tcp_codec::{Message, TcpCodec},
         secure_channel::SecureChannel,
     },
 };
-use opcua_types::{status_code::StatusCode, tcp_types::*};
+use opcua_types::{status_code::StatusCode, tcp_types::*, service_types::{RequestHeader, ServiceFault}, is_opc_ua_binary_url, is_opc_ua_tls_url};
 
 use crate::{
-    address_space::types::AddressSpace,
+    address_space::types::SharedAddressSpace,
     comms::secure_channel_service::SecureChannelService,
     comms::transport::*,
     constants,
@@ -42,13 +44,16 @@ use crate::{
     services::message_handler::MessageHandler,
     session::Session,
     subscriptions::PublishResponseEntry,
+    subscriptions::detached::{DetachedSubscription, user_identity_key},
     subscriptions::subscription::TickReason,
+    telemetry::Span,
 };
 
-// TODO these need to go, and use session settings
-const RECEIVE_BUFFER_SIZE: usize = 1024 * 64;
-const SEND_BUFFER_SIZE: usize = 1024 * 64;
-const MAX_MESSAGE_SIZE: usize = 1024 * 64;
+/// Reading half of a connection, which is either a TCP socket or a TLS stream over one
+type StreamReader = Box<dyn AsyncRead + Send>;
+
+/// Writing half of a connection, which is either a TCP socket or a TLS stream over one
+type StreamWriter = Box<dyn AsyncWrite + Send>;
 
 macro_rules! connection_finished_test {
     ( $id: expr, $connection:expr ) => {
@@ -67,10 +72,6 @@ macro_rules! connection_finished_test {
 struct ReadState {
     /// The associated connection
     pub transport: Arc<RwLock<TcpTransport>>,
-    /// Raw bytes in buffer
-    pub in_buf: Vec<u8>,
-    /// Bytes read in buffer
-    pub bytes_read: usize,
     /// Sender of responses
     pub sender: Arc<RwLock<UnboundedSender<(u32, SupportedMessage)>>>,
 }
@@ -81,7 +82,7 @@ struct WriteState {
     /// Secure channel state
     pub secure_channel: Arc<RwLock<SecureChannel>>,
     /// Writing portion of socket
-    pub writer: Option<WriteHalf<TcpStream>>,
+    pub writer: Option<StreamWriter>,
     /// Write buffer (protected since it might be accessed by publish response / event activity)
     pub send_buffer: Arc<Mutex<MessageWriter>>,
 }
@@ -98,11 +99,13 @@ pub struct TcpTransport {
     /// Secure channel state
     secure_channel: Arc<RwLock<SecureChannel>>,
     /// Address space
-    address_space: Arc<RwLock<AddressSpace>>,
+    address_space: Arc<SharedAddressSpace>,
     /// The current transport state
     transport_state: TransportState,
     /// Client address
     client_address: Option<SocketAddr>,
+    /// True if the connection came through the opc.tls transport
+    tls: bool,
     /// Secure channel handler
     secure_channel_service: SecureChannelService,
     /// Message handler
@@ -113,6 +116,24 @@ pub struct TcpTransport {
     last_received_sequence_number: u32,
     /// A message may consist of one or more chunks which are stored here until complete.
     pending_chunks: Vec<MessageChunk>,
+    /// Receive buffer size, from config and then negotiated during HELLO
+    receive_buffer_size: usize,
+    /// Send buffer size, from config and then negotiated during HELLO
+    send_buffer_size: usize,
+    /// Maximum size of a request, or 0 for no limit
+    max_message_size: usize,
+    /// Maximum number of chunks in a request, or 0 for no limit
+    max_chunk_count: usize,
+    /// Maximum number of chunks per second that the client may send, or 0 for no limit
+    max_chunk_rate: usize,
+    /// Start of the current one second period and the number of chunks received during it
+    chunk_rate_period: (Instant, usize),
+    /// Number of messages which may fail to decode before the connection is closed
+    max_decoding_errors: usize,
+    /// Number of messages which have failed to decode
+    decoding_errors: usize,
+    /// Pool of send buffers shared with the other connections of the server
+    buffer_pool: BufferPool,
 }
 
 impl Transport for TcpTransport {
@@ -124,8 +145,27 @@ impl Transport for TcpTransport {
     fn finish(&mut self, status_code: StatusCode) {
         if !self.is_finished() {
             self.transport_state = TransportState::Finished(status_code);
-            let mut session = trace_write_lock_unwrap!(self.session);
-            session.set_terminated();
+            let (subscriptions, user_identity) = {
+                let mut session = trace_write_lock_unwrap!(self.session);
+                session.set_terminated();
+                (session.subscriptions.take_all(), session.user_identity.clone())
+            };
+            // Subscriptions outlive the session so that a reconnecting client can transfer them
+            if !subscriptions.is_empty() {
+                let mut server_state = trace_write_lock_unwrap!(self.server_state);
+                let decoding_limits = {
+                    let config = trace_read_lock_unwrap!(server_state.config);
+                    config.decoding_limits()
+                };
+                let user_identity = user_identity_key(&user_identity, &decoding_limits);
+                subscriptions.into_iter().for_each(|(subscription, unacknowledged_notifications)| {
+                    server_state.detached_subscriptions.insert(DetachedSubscription {
+                        subscription,
+                        unacknowledged_notifications,
+                        user_identity: user_identity.clone(),
+                    });
+                });
+            }
         }
     }
 
@@ -148,12 +188,17 @@ impl Transport for TcpTransport {
 }
 
 impl TcpTransport {
-    pub fn new(server_state: Arc<RwLock<ServerState>>, session: Arc<RwLock<Session>>, address_space: Arc<RwLock<AddressSpace>>, message_handler: MessageHandler) -> TcpTransport {
+    pub fn new(server_state: Arc<RwLock<ServerState>>, session: Arc<RwLock<Session>>, address_space: Arc<SharedAddressSpace>, message_handler: MessageHandler) -> TcpTransport {
         let (secure_channel, session_id) = {
             let session = trace_read_lock_unwrap!(session);
             (session.secure_channel.clone(), session.session_id.clone())
         };
-        let secure_channel_service = SecureChannelService::new();
+        let (tcp_config, buffer_pool) = {
+            let server_state = trace_read_lock_unwrap!(server_state);
+            let server_config = trace_read_lock_unwrap!(server_state.config);
+            (server_config.tcp_config.clone(), server_state.buffer_pool.clone())
+        };
+        let secure_channel_service = SecureChannelService::new(message_handler.certificate_store(), tcp_config.min_token_lifetime, tcp_config.max_token_lifetime);
         TcpTransport {
             server_state,
             session,
@@ -161,26 +206,52 @@ impl TcpTransport {
             address_space,
             transport_state: TransportState::New,
             client_address: None,
+            tls: false,
             message_handler,
             secure_channel,
             secure_channel_service,
             client_protocol_version: 0,
             last_received_sequence_number: 0,
             pending_chunks: Vec::with_capacity(2),
+            receive_buffer_size: tcp_config.receive_buffer_size,
+            send_buffer_size: tcp_config.send_buffer_size,
+            max_message_size: tcp_config.max_message_size,
+            max_chunk_count: tcp_config.max_chunk_count,
+            max_chunk_rate: tcp_config.max_chunk_rate,
+            chunk_rate_period: (Instant::now(), 0),
+            max_decoding_errors: tcp_config.max_decoding_errors,
+            decoding_errors: 0,
+            buffer_pool,
         }
     }
 
     /// This is the entry point for the session. This function is asynchronous - it spawns tokio
     /// tasks to handle the session execution loop so this function will returns immediately.
     pub fn run(connection: Arc<RwLock<TcpTransport>>, socket: TcpStream) {
+        let client_address = socket.peer_addr().unwrap();
+        Self::run_stream(connection, socket, client_address, false);
+    }
+
+    /// Runs the session over a stream other than a plain TCP socket, e.g. a TLS stream. The
+    /// client address is the peer address of the underlying socket and `tls` says whether the
+    /// stream came through the opc.tls transport.
+    pub fn run_stream<S>(connection: Arc<RwLock<TcpTransport>>, stream: S, client_address: SocketAddr, tls: bool) where S: AsyncRead + AsyncWrite + Send + 'static {
         // Store the address of the client
         {
             let mut connection = trace_write_lock_unwrap!(connection);
-            connection.client_address = Some(socket.peer_addr().unwrap());
+            connection.client_address = Some(client_address);
+            connection.tls = tls;
+            let connection_span = Span::connection(&client_address, if tls { "opc.tls" } else { "opc.tcp" });
+            connection.message_handler.set_connection_span(connection_span);
+            {
+                let mut session = trace_write_lock_unwrap!(connection.session);
+                session.client_address = Some(client_address);
+            }
             connection.transport_state = TransportState::WaitingHello;
         }
         // Spawn the tasks we need to run
-        Self::spawn_looping_task(connection, socket);
+        let (reader, writer) = stream.split();
+        Self::spawn_looping_task(connection, Box::new(reader), Box::new(writer));
     }
 
     fn write_bytes_task(connection: Arc<Mutex<WriteState>>) -> impl Future<Item=Arc<Mutex<WriteState>>, Error=Arc<Mutex<WriteState>>> {
@@ -199,11 +270,13 @@ impl TcpTransport {
             error!("Write IO error {:?}", err);
             let mut transport = trace_write_lock_unwrap!(transport);
             transport.finish(StatusCode::BadCommunicationError);
-        }).map(move |(writer, _)| {
+        }).map(move |(writer, bytes_written)| {
             // Build a new connection state
             {
                 let mut connection = trace_lock_unwrap!(connection);
                 connection.writer = Some(writer);
+                let send_buffer = trace_lock_unwrap!(connection.send_buffer);
+                send_buffer.give_back(bytes_written);
             }
             connection
         }).map_err(move |_| {
@@ -211,20 +284,24 @@ impl TcpTransport {
         })
     }
 
-    fn spawn_looping_task(transport: Arc<RwLock<TcpTransport>>, socket: TcpStream) {
+    fn spawn_looping_task(transport: Arc<RwLock<TcpTransport>>, reader: StreamReader, writer: StreamWriter) {
         let session_start_time = Utc::now();
         info!("Session started {}", session_start_time);
 
         // Spawn the hello timeout task
         Self::spawn_hello_timeout_task(transport.clone(), session_start_time.clone());
 
-        // These should really come from the session
-        let (send_buffer_size, receive_buffer_size) = (SEND_BUFFER_SIZE, RECEIVE_BUFFER_SIZE);
+        // Buffers are sized from the configuration. The sizes negotiated during HELLO can only be
+        // the same or smaller. Send buffers come from the server's pool when there is something
+        // to send and go back afterwards.
+        let (send_buffer_size, buffer_pool) = {
+            let transport = trace_read_lock_unwrap!(transport);
+            (transport.send_buffer_size, transport.buffer_pool.clone())
+        };
 
         // The reader task will send responses, the writer task will receive responses
         let (tx, rx) = unbounded::<(u32, SupportedMessage)>();
-        let send_buffer = Arc::new(Mutex::new(MessageWriter::new(send_buffer_size)));
-        let (reader, writer) = socket.split();
+        let send_buffer = Arc::new(Mutex::new(MessageWriter::new(send_buffer_size, buffer_pool)));
         let secure_channel = {
             let transport = trace_read_lock_unwrap!(transport);
             transport.secure_channel.clone()
@@ -233,12 +310,17 @@ impl TcpTransport {
         // This is set to true when the session is finished.
         let finished_flag = Arc::new(RwLock::new(false));
 
+        // The finished monitor stops the reader and writer through these once the transport is
+        // finished, so the connection is closed even if the client leaves its end open.
+        let (reader_stop_tx, reader_stop_rx) = oneshot::channel::<()>();
+        let (writer_stop_tx, writer_stop_rx) = oneshot::channel::<()>();
+
         // Spawn all the tasks that monitor the session - the subscriptions, finished state,
         // reading and writing.
         Self::spawn_subscriptions_task(transport.clone(), tx.clone());
-        Self::spawn_finished_monitor_task(transport.clone(), finished_flag.clone());
-        Self::spawn_reading_loop_task(reader, finished_flag.clone(), tx, transport.clone(), receive_buffer_size);
-        Self::spawn_writing_loop_task(writer, rx, secure_channel.clone(), transport.clone(), send_buffer);
+        Self::spawn_finished_monitor_task(transport.clone(), finished_flag.clone(), reader_stop_tx, writer_stop_tx);
+        Self::spawn_reading_loop_task(reader, finished_flag.clone(), tx, transport.clone(), reader_stop_rx);
+        Self::spawn_writing_loop_task(writer, rx, secure_channel.clone(), transport.clone(), send_buffer, writer_stop_rx);
     }
 
     fn make_session_id(component: &str, transport: Arc<RwLock<TcpTransport>>) -> String {
@@ -248,8 +330,8 @@ impl TcpTransport {
 
     /// Spawns the finished monitor task. This checks for the session to be in a finished
     /// state and ensures the session is placed into a finished state once the transport
-    /// aborts or finishes.
-    fn spawn_finished_monitor_task(transport: Arc<RwLock<TcpTransport>>, finished_flag: Arc<RwLock<bool>>) {
+    /// aborts or finishes. When it does, the reader and writer are told to stop.
+    fn spawn_finished_monitor_task(transport: Arc<RwLock<TcpTransport>>, finished_flag: Arc<RwLock<bool>>, reader_stop: oneshot::Sender<()>, writer_stop: oneshot::Sender<()>) {
         let id = Self::make_session_id("finished_monitor_task", transport.clone());
         let id_for_map = id.clone();
         let id_for_map_err = id.clone();
@@ -271,6 +353,10 @@ impl TcpTransport {
             .for_each(move |_| Ok(()))
             .map(|_| {
                 info!("Finished monitor task is finished");
+                // The reader and writer only look at the transport when something arrives, which
+                // may be never if the client keeps the socket open
+                let _ = reader_stop.send(());
+                let _ = writer_stop.send(());
                 deregister_runtime_component!(id_for_map);
             })
             .map_err(move |err| {
@@ -282,7 +368,7 @@ impl TcpTransport {
 
     /// Spawns the writing loop task. The writing loop takes messages to send off of a queue
     /// and sends them to the stream.
-    fn spawn_writing_loop_task(writer: WriteHalf<TcpStream>, receiver: UnboundedReceiver<(u32, SupportedMessage)>, secure_channel: Arc<RwLock<SecureChannel>>, transport: Arc<RwLock<TcpTransport>>, send_buffer: Arc<Mutex<MessageWriter>>) {
+    fn spawn_writing_loop_task(writer: StreamWriter, receiver: UnboundedReceiver<(u32, SupportedMessage)>, secure_channel: Arc<RwLock<SecureChannel>>, transport: Arc<RwLock<TcpTransport>>, send_buffer: Arc<Mutex<MessageWriter>>, stop: oneshot::Receiver<()>) {
         let id = Self::make_session_id("writing_loop_task", transport.clone());
         let id_for_map = id.clone();
         let id_for_map_err = id.clone();
@@ -355,10 +441,10 @@ impl TcpTransport {
                     error!("Write bytes task is in error");
                 };
             })
-        }).map(move |_| {
+        }).select(stop.map_err(|_| ())).map(move |_| {
             info!("Writer is finished");
             deregister_runtime_component!(id_for_map);
-        }).map_err(move |err| {
+        }).map_err(move |(err, _)| {
             error!("Writer is finished with an error {:?}", err);
             deregister_runtime_component!(id_for_map_err);
         });
@@ -368,7 +454,7 @@ impl TcpTransport {
 
     /// Creates the framed read task / future. This will read chunks from the
     /// reader and process them.
-    fn framed_read_task(reader: ReadHalf<TcpStream>, finished_flag: Arc<RwLock<bool>>, connection: Arc<RwLock<ReadState>>) -> impl Future<Item=(), Error=()>
+    fn framed_read_task(reader: StreamReader, finished_flag: Arc<RwLock<bool>>, connection: Arc<RwLock<ReadState>>) -> impl Future<Item=(), Error=()>
     {
         let transport = {
             let connection = trace_read_lock_unwrap!(connection);
@@ -450,21 +536,25 @@ impl TcpTransport {
 
     /// Spawns the reading loop where a reader task continuously reads messages, chunks from the
     /// input and process them. The reading task will terminate upon error.
-    fn spawn_reading_loop_task(reader: ReadHalf<TcpStream>, finished_flag: Arc<RwLock<bool>>, sender: UnboundedSender<(u32, SupportedMessage)>, transport: Arc<RwLock<TcpTransport>>, receive_buffer_size: usize) {
+    fn spawn_reading_loop_task(reader: StreamReader, finished_flag: Arc<RwLock<bool>>, sender: UnboundedSender<(u32, SupportedMessage)>, transport: Arc<RwLock<TcpTransport>>, stop: oneshot::Receiver<()>) {
         // Connection state is maintained for looping through each task
         let connection = Arc::new(RwLock::new(ReadState {
             transport: transport.clone(),
-            bytes_read: 0,
-            in_buf: vec![0u8; receive_buffer_size],
             sender: Arc::new(RwLock::new(sender)),
         }));
-        let framed_read_task = Self::framed_read_task(reader, finished_flag, connection.clone());
+        // Dropping the framed read task when told to stop drops the reader
+        let framed_read_task = Self::framed_read_task(reader, finished_flag, connection.clone())
+            .select(stop.map_err(|_| ()))
+            .map(|_| ())
+            .map_err(|_| ());
 
         let id = Self::make_session_id("reading_loop_task", transport.clone());
         let id_for_map = id.clone();
         let id_for_map_err = id.clone();
         register_runtime_component!(id);
 
+        let transport_for_map = transport.clone();
+
         let looping_task = framed_read_task
             .and_then(move |_| {
                 let connection = trace_write_lock_unwrap!(connection);
@@ -493,6 +583,10 @@ impl TcpTransport {
             })
             .map(move |_| {
                 info!("Read loop is finished");
+                // The stream ended while the transport was still running, i.e. the client closed
+                // the socket
+                let mut transport = trace_write_lock_unwrap!(transport_for_map);
+                transport.finish(StatusCode::BadConnectionClosed);
                 deregister_runtime_component!(id_for_map);
             })
             .map_err(move |err| {
@@ -587,6 +681,7 @@ impl TcpTransport {
         #[derive(Clone, Debug)]
         enum SubscriptionEvent {
             PublishResponses(VecDeque<PublishResponseEntry>),
+            AsyncResponses(VecDeque<(u32, SupportedMessage)>),
         }
         debug!("spawn_subscriptions_task ");
 
@@ -619,16 +714,55 @@ impl TcpTransport {
                     connection_finished_test!("subscriptions_task.take_while", transport_for_take_while)
                 })
                 .for_each(move |_| {
+                    let now = Utc::now();
+
+                    // A session which has received no requests for longer than its timeout is closed.
+                    // Its subscriptions are parked so a client can still transfer them to a new session.
+                    let session_timed_out = {
+                        let transport = trace_read_lock_unwrap!(state.transport);
+                        let session = trace_read_lock_unwrap!(transport.session);
+                        session.is_timed_out(&now)
+                    };
+                    if session_timed_out {
+                        info!("Session has received no requests for more than its session timeout and will now close");
+                        let mut transport = trace_write_lock_unwrap!(state.transport);
+                        transport.finish(StatusCode::BadTimeout);
+
+                        // Diagnostics
+                        let server_state = trace_read_lock_unwrap!(transport.server_state);
+                        let mut diagnostics = trace_write_lock_unwrap!(server_state.diagnostics);
+                        diagnostics.on_session_timeout();
+                        return Ok(());
+                    }
+
+                    // A secure channel whose token has expired without being renewed is closed
+                    let token_expired = {
+                        let transport = trace_read_lock_unwrap!(state.transport);
+                        let secure_channel = trace_read_lock_unwrap!(transport.secure_channel);
+                        secure_channel.token_id() != 0 && secure_channel.token_has_expired()
+                    };
+                    if token_expired {
+                        info!("Secure channel token has expired without being renewed and the connection will now close");
+                        let mut transport = trace_write_lock_unwrap!(state.transport);
+                        transport.finish(StatusCode::BadSecureChannelTokenUnknown);
+                        return Ok(());
+                    }
+
                     let transport = trace_read_lock_unwrap!(state.transport);
                     let mut session = trace_write_lock_unwrap!(transport.session);
 
-                    let now = Utc::now();
-
                     // Request queue might contain stale publish requests
                     session.expire_stale_publish_requests(&now);
 
-                    // Process subscriptions
-                    {
+                    // Process subscriptions, or close them with a final status change if the server
+                    // is due to shut down
+                    let shutdown_due = {
+                        let server_state = trace_read_lock_unwrap!(transport.server_state);
+                        server_state.is_shutdown_due(&now)
+                    };
+                    if shutdown_due {
+                        session.shutdown_subscriptions(&now);
+                    } else {
                         let address_space = trace_read_lock_unwrap!(transport.address_space);
                         let _ = session.tick_subscriptions(&now, &address_space, TickReason::TickTimerFired);
                     }
@@ -644,6 +778,13 @@ impl TcpTransport {
                             }
                         }
                     }
+
+                    // Check if there are responses to requests which completed asynchronously
+                    if let Some(async_responses) = session.take_async_responses() {
+                        if let Err(error) = subscription_tx.unbounded_send(SubscriptionEvent::AsyncResponses(async_responses)) {
+                            error!("Cannot send async responses, err = {}", error);
+                        }
+                    }
                     Ok(())
                 })
                 .map(move |_| {
@@ -657,7 +798,7 @@ impl TcpTransport {
             tokio::spawn(task);
         }
 
-        // Create the receiving task - this takes publish responses and sends them back to the client
+        // Create the receiving task - this takes publish and async responses and sends them back to the client
         {
             let id = Self::make_session_id("subscriptions_task_receiver", transport.clone());
             let id_for_map = id.clone();
@@ -691,6 +832,12 @@ impl TcpTransport {
                                 let _ = sender.unbounded_send((publish_response.request_id, publish_response.response));
                             }
                         }
+                        SubscriptionEvent::AsyncResponses(async_responses) => {
+                            trace!("Got {} async responses to send", async_responses.len());
+                            for (request_id, response) in async_responses {
+                                let _ = sender.unbounded_send((request_id, response));
+                            }
+                        }
                     }
                     Ok(())
                 })
@@ -715,7 +862,7 @@ impl TcpTransport {
         let server_protocol_version = 0;
         let endpoints = {
             let server_state = trace_read_lock_unwrap!(self.server_state);
-            server_state.endpoints(&None)
+            server_state.endpoints(&UAString::null(), &None)
         }.unwrap();
 
         trace!("Server received HELLO {:?}", hello);
@@ -723,6 +870,16 @@ impl TcpTransport {
             error!("HELLO endpoint url is invalid");
             return Err(StatusCode::BadTcpEndpointUrlInvalid);
         }
+        // The endpoint must be one reached through the transport that the client connected with
+        let is_transport_url = if self.tls {
+            is_opc_ua_tls_url(hello.endpoint_url.as_ref())
+        } else {
+            is_opc_ua_binary_url(hello.endpoint_url.as_ref())
+        };
+        if !is_transport_url {
+            error!("HELLO endpoint url {} is not for the transport the client connected through", hello.endpoint_url);
+            return Err(StatusCode::BadTcpEndpointUrlInvalid);
+        }
         if !hello.is_valid_buffer_sizes() {
             error!("HELLO buffer sizes are invalid");
             return Err(StatusCode::BadCommunicationError);
@@ -735,14 +892,26 @@ impl TcpTransport {
 
         let client_protocol_version = hello.protocol_version;
 
+        // The endpoint is remembered for calls made without a session, which have no CreateSession
+        // to supply it.
+        {
+            let mut session = trace_write_lock_unwrap!(self.session);
+            session.endpoint_url = hello.endpoint_url.clone();
+        }
+
         // Send acknowledge
+        // Negotiate buffer sizes - the server never receives chunks larger than the client sends or
+        // sends chunks larger than the client receives.
+        self.receive_buffer_size = std::cmp::min(self.receive_buffer_size, hello.send_buffer_size as usize);
+        self.send_buffer_size = std::cmp::min(self.send_buffer_size, hello.receive_buffer_size as usize);
+
         let mut acknowledge = AcknowledgeMessage {
             message_header: MessageHeader::new(MessageType::Acknowledge),
             protocol_version: server_protocol_version,
-            receive_buffer_size: RECEIVE_BUFFER_SIZE as u32,
-            send_buffer_size: SEND_BUFFER_SIZE as u32,
-            max_message_size: MAX_MESSAGE_SIZE as u32,
-            max_chunk_count: MAX_CHUNK_COUNT as u32,
+            receive_buffer_size: self.receive_buffer_size as u32,
+            send_buffer_size: self.send_buffer_size as u32,
+            max_message_size: self.max_message_size as u32,
+            max_chunk_count: self.max_chunk_count as u32,
         };
         acknowledge.message_header.message_size = acknowledge.byte_len() as u32;
         let acknowledge: SupportedMessage = acknowledge.into();
@@ -756,15 +925,49 @@ impl TcpTransport {
         Ok(())
     }
 
-    fn turn_received_chunks_into_message(&mut self, chunks: &Vec<MessageChunk>) -> std::result::Result<SupportedMessage, StatusCode> {
+    fn turn_received_chunks_into_message(&mut self, chunks: &Vec<MessageChunk>) -> std::result::Result<std::result::Result<SupportedMessage, StatusCode>, StatusCode> {
         // Validate that all chunks have incrementing sequence numbers and valid chunk types
         let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
         self.last_received_sequence_number = Chunker::validate_chunks(self.last_received_sequence_number + 1, &secure_channel, chunks)?;
-        // Now decode
-        Chunker::decode(&chunks, &secure_channel, None)
+        // Now decode. A message which cannot be decoded is returned as the inner error.
+        Ok(Chunker::decode(&chunks, &secure_channel, None))
+    }
+
+    /// Counts a chunk against the rate the client is permitted to send chunks at. Fails with
+    /// `BadTcpServerTooBusy` if the client exceeds the rate so the connection is closed.
+    fn check_chunk_rate(&mut self) -> std::result::Result<(), StatusCode> {
+        if self.max_chunk_rate > 0 {
+            let now = Instant::now();
+            let (period_start, chunk_count) = self.chunk_rate_period;
+            self.chunk_rate_period = if now.duration_since(period_start) >= Duration::from_secs(1) {
+                (now, 1)
+            } else {
+                (period_start, chunk_count + 1)
+            };
+            if self.chunk_rate_period.1 > self.max_chunk_rate {
+                error!("Client {:?} has sent more than the maximum {} chunks per second", self.client_address, self.max_chunk_rate);
+                return Err(StatusCode::BadTcpServerTooBusy);
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts a message which failed to decode. The client gets a service fault unless it has sent
+    /// more of them than permitted, in which case the error is returned and the connection is closed.
+    fn on_decoding_error(&mut self, status_code: StatusCode) -> std::result::Result<SupportedMessage, StatusCode> {
+        self.decoding_errors += 1;
+        if self.decoding_errors > self.max_decoding_errors {
+            error!("Client {:?} has sent {} messages which failed to decode, more than the maximum {}", self.client_address, self.decoding_errors, self.max_decoding_errors);
+            Err(status_code)
+        } else {
+            error!("Message from client {:?} failed to decode, {}", self.client_address, status_code);
+            Ok(ServiceFault::new_supported_message(&RequestHeader::dummy(), status_code))
+        }
     }
 
     fn process_chunk(&mut self, chunk: MessageChunk, sender: &mut UnboundedSender<(u32, SupportedMessage)>) -> std::result::Result<(), StatusCode> {
+        self.check_chunk_rate()?;
+
         let decoding_limits = {
             let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
             secure_channel.decoding_limits()
@@ -779,10 +982,21 @@ impl TcpTransport {
             // Decrypt / verify chunk if necessary
             let chunk = {
                 let mut secure_channel = trace_write_lock_unwrap!(self.secure_channel);
-                secure_channel.verify_and_remove_security(&chunk.data)?
+                secure_channel.verify_and_remove_security(chunk.data)?
             };
 
-            // Put the chunk on the list
+            // Put the chunk on the list, providing it stays within the limits sent in the ACK
+            if self.max_chunk_count > 0 && self.pending_chunks.len() >= self.max_chunk_count {
+                error!("Message has more than the maximum {} chunks", self.max_chunk_count);
+                self.pending_chunks.clear();
+                return Err(StatusCode::BadTcpMessageTooLarge);
+            }
+            let message_size = self.pending_chunks.iter().fold(chunk.data.len(), |size, c| size + c.data.len());
+            if self.max_message_size > 0 && message_size > self.max_message_size {
+                error!("Message size {} exceeds the maximum {}", message_size, self.max_message_size);
+                self.pending_chunks.clear();
+                return Err(StatusCode::BadTcpMessageTooLarge);
+            }
             self.pending_chunks.push(chunk);
 
             // If this is the final chunk, then
@@ -794,9 +1008,18 @@ impl TcpTransport {
                     chunks[0].chunk_info(&secure_channel)?
                 };
 
-                // Handle the request, and then send the response back to the caller
-                let request = self.turn_received_chunks_into_message(&chunks)?;
+                // Handle the request, and then send the response back to the caller. A message
+                // which cannot be decoded is faulted unless it opens or closes the secure channel.
                 let request_id = chunk_info.sequence_header.request_id;
+                let request = match self.turn_received_chunks_into_message(&chunks)? {
+                    Ok(request) => request,
+                    Err(status_code) if message_header.message_type == MessageChunkType::Message => {
+                        let response = self.on_decoding_error(status_code)?;
+                        let _ = sender.unbounded_send((request_id, response));
+                        return Ok(());
+                    }
+                    Err(status_code) => return Err(status_code)
+                };
                 let response = match message_header.message_type {
                     MessageChunkType::OpenSecureChannel => {
                         let mut secure_channel = trace_write_lock_unwrap!(self.secure_channel);
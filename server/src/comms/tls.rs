@@ -0,0 +1,46 @@
+//! Provides the TLS settings of the experimental opc.tls and opc.https transports, which are
+//! secured by TLS instead of the OPC UA secure channel encryption.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::{ServerConfig, NoClientAuth, Certificate, PrivateKey, internal::pemfile};
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::TlsConfig;
+
+/// Makes the acceptor which performs the TLS handshake of incoming connections using the
+/// configured certificate chain and private key. Client certificates are not requested.
+pub(crate) fn make_tls_acceptor(tls_config: &TlsConfig) -> Result<TlsAcceptor, String> {
+    let certs = read_certs(&tls_config.certificate_path)?;
+    let key = read_private_key(&tls_config.private_key_path)?;
+    let mut server_config = ServerConfig::new(NoClientAuth::new());
+    server_config.set_single_cert(certs, key)
+        .map_err(|err| format!("Cannot use TLS certificate {}, err = {:?}", tls_config.certificate_path.display(), err))?;
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+fn read_pem<T>(path: &Path, parse: fn(&mut dyn BufRead) -> Result<Vec<T>, ()>) -> Result<Vec<T>, String> {
+    let file = File::open(path).map_err(|err| format!("Cannot open {}, err = {}", path.display(), err))?;
+    parse(&mut BufReader::new(file)).map_err(|_| format!("Cannot parse PEM file {}", path.display()))
+}
+
+fn read_certs(path: &Path) -> Result<Vec<Certificate>, String> {
+    let certs = read_pem(path, pemfile::certs)?;
+    if certs.is_empty() {
+        Err(format!("TLS certificate file {} contains no certificates", path.display()))
+    } else {
+        Ok(certs)
+    }
+}
+
+fn read_private_key(path: &Path) -> Result<PrivateKey, String> {
+    // The key may be PKCS#8 or RSA so try each in turn
+    let mut keys = read_pem(path, pemfile::pkcs8_private_keys)?;
+    if keys.is_empty() {
+        keys = read_pem(path, pemfile::rsa_private_keys)?;
+    }
+    keys.into_iter().next().ok_or_else(|| format!("TLS private key file {} contains no PKCS#8 or RSA private key", path.display()))
+}
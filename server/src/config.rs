@@ -1,18 +1,44 @@
 //! Provides configuration settings for the server including serialization and deserialization from file.
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::collections::{BTreeMap, BTreeSet};
 
-use opcua_types::{MessageSecurityMode, UAString, DecodingLimits};
+use opcua_types::{MessageSecurityMode, UAString, DecodingLimits, NodeId};
 use opcua_types::constants as opcua_types_constants;
-use opcua_types::url_matches_except_host;
+use opcua_types::{url_matches_except_host, is_valid_opc_ua_url};
+use opcua_types::tcp_types::MIN_CHUNK_SIZE;
 
-use opcua_core::crypto::SecurityPolicy;
+use opcua_core::crypto::{SecurityPolicy, X509, CertificateStore, CertificateValidation};
 use opcua_core::config::Config;
 
-use crate::constants;
+use crate::{constants, pubsub::{forwarder, mqtt}};
 
 pub const ANONYMOUS_USER_TOKEN_ID: &str = "ANONYMOUS";
+/// Reserved user token id of endpoints that accept issued identity tokens, e.g. JWTs, which are
+/// validated by the server's `IssuedTokenValidator`.
+pub const ISSUED_TOKEN_USER_TOKEN_ID: &str = "ISSUED_TOKEN";
+
+/// An address and port that the server listens on for connections, e.g. a particular network
+/// interface. The host may be an IPv4 or IPv6 address, or a name which resolves to one.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct TcpListenAddress {
+    /// The host address to listen on
+    pub host: String,
+    /// The port to listen on
+    pub port: u16,
+}
+
+/// Settings of a transport which is secured by TLS instead of the OPC UA secure channel, i.e. the
+/// experimental opc.tls and opc.https transports. Endpoints opt in with their `tls` or `https` flag.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct TlsConfig {
+    /// The port that the server listens on for connections of the transport
+    pub port: u16,
+    /// Path to the server's TLS certificate chain in PEM format
+    pub certificate_path: PathBuf,
+    /// Path to the server's TLS private key in PEM format, PKCS#8 or RSA
+    pub private_key_path: PathBuf,
+}
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct TcpConfig {
@@ -22,6 +48,717 @@ pub struct TcpConfig {
     pub host: String,
     /// The port number of the service
     pub port: u16,
+    /// Further addresses and ports that the server listens on, in addition to its host and port,
+    /// e.g. to accept connections on several network interfaces or on IPv6 as well as IPv4
+    #[serde(default)]
+    pub listen_addresses: Vec<TcpListenAddress>,
+    /// Size in bytes of the buffer that chunks are received into. The size sent in the ACK will
+    /// be the smaller of this and the client's send buffer size.
+    #[serde(default = "TcpConfig::default_receive_buffer_size")]
+    pub receive_buffer_size: usize,
+    /// Size in bytes of the buffer that chunks are sent from. The size sent in the ACK will
+    /// be the smaller of this and the client's receive buffer size.
+    #[serde(default = "TcpConfig::default_send_buffer_size")]
+    pub send_buffer_size: usize,
+    /// Maximum size in bytes of a request, or 0 for no limit
+    #[serde(default = "TcpConfig::default_max_message_size")]
+    pub max_message_size: usize,
+    /// Maximum number of chunks that a request may be split into, or 0 for no limit
+    #[serde(default = "TcpConfig::default_max_chunk_count")]
+    pub max_chunk_count: usize,
+    /// Smallest lifetime in milliseconds that the lifetime a client requests for a secure channel
+    /// token is revised up to
+    #[serde(default = "TcpConfig::default_min_token_lifetime")]
+    pub min_token_lifetime: u32,
+    /// Largest lifetime in milliseconds that the lifetime a client requests for a secure channel
+    /// token is revised down to. A channel whose token expires without being renewed is closed.
+    #[serde(default = "TcpConfig::default_max_token_lifetime")]
+    pub max_token_lifetime: u32,
+    /// Maximum number of chunks per second that a connection may send before it is closed, or 0 for
+    /// no limit
+    #[serde(default = "TcpConfig::default_max_chunk_rate")]
+    pub max_chunk_rate: usize,
+    /// Number of messages which fail to decode that a connection may send before it is closed. Each
+    /// is answered with a service fault.
+    #[serde(default = "TcpConfig::default_max_decoding_errors")]
+    pub max_decoding_errors: usize,
+    /// Maximum number of connections open at the same time from one client address, or 0 for no
+    /// limit. Further connections from the address are refused.
+    #[serde(default = "TcpConfig::default_max_connections_per_client")]
+    pub max_connections_per_client: usize,
+    /// Settings of the opc.tls transport, or none to disable it. Requires the server to be built
+    /// with the tls feature.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Settings of the opc.https transport, or none to disable it. Requires the server to be built
+    /// with the https feature.
+    #[serde(default)]
+    pub https: Option<TlsConfig>,
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        TcpConfig {
+            host: "127.0.0.1".to_string(),
+            port: constants::DEFAULT_RUST_OPC_UA_SERVER_PORT,
+            listen_addresses: Vec::new(),
+            hello_timeout: constants::DEFAULT_HELLO_TIMEOUT_SECONDS,
+            receive_buffer_size: Self::default_receive_buffer_size(),
+            send_buffer_size: Self::default_send_buffer_size(),
+            max_message_size: Self::default_max_message_size(),
+            max_chunk_count: Self::default_max_chunk_count(),
+            min_token_lifetime: Self::default_min_token_lifetime(),
+            max_token_lifetime: Self::default_max_token_lifetime(),
+            max_chunk_rate: Self::default_max_chunk_rate(),
+            max_decoding_errors: Self::default_max_decoding_errors(),
+            max_connections_per_client: Self::default_max_connections_per_client(),
+            tls: None,
+            https: None,
+        }
+    }
+}
+
+impl TcpConfig {
+    fn default_receive_buffer_size() -> usize { constants::DEFAULT_RECEIVE_BUFFER_SIZE }
+
+    fn default_send_buffer_size() -> usize { constants::DEFAULT_SEND_BUFFER_SIZE }
+
+    fn default_max_message_size() -> usize { constants::DEFAULT_MAX_MESSAGE_SIZE }
+
+    fn default_max_chunk_count() -> usize { constants::DEFAULT_MAX_CHUNK_COUNT }
+
+    fn default_min_token_lifetime() -> u32 { constants::DEFAULT_MIN_TOKEN_LIFETIME }
+
+    fn default_max_token_lifetime() -> u32 { constants::DEFAULT_MAX_TOKEN_LIFETIME }
+
+    fn default_max_chunk_rate() -> usize { constants::DEFAULT_MAX_CHUNK_RATE }
+
+    fn default_max_decoding_errors() -> usize { constants::DEFAULT_MAX_DECODING_ERRORS }
+
+    fn default_max_connections_per_client() -> usize { constants::DEFAULT_MAX_CONNECTIONS_PER_CLIENT }
+
+    pub fn is_valid(&self) -> bool {
+        let mut valid = true;
+        // Part 6 sets a minimum size for the transport buffers
+        if self.receive_buffer_size < MIN_CHUNK_SIZE {
+            error!("Server configuration is invalid. Receive buffer size {} is less than the minimum {}", self.receive_buffer_size, MIN_CHUNK_SIZE);
+            valid = false;
+        }
+        if self.send_buffer_size < MIN_CHUNK_SIZE {
+            error!("Server configuration is invalid. Send buffer size {} is less than the minimum {}", self.send_buffer_size, MIN_CHUNK_SIZE);
+            valid = false;
+        }
+        if self.max_message_size != 0 && self.max_message_size < self.receive_buffer_size {
+            error!("Server configuration is invalid. Max message size {} is less than the receive buffer size {}", self.max_message_size, self.receive_buffer_size);
+            valid = false;
+        }
+        for listen_address in &self.listen_addresses {
+            if listen_address.host.is_empty() {
+                error!("Server configuration is invalid. Listen address with port {} has no host", listen_address.port);
+                valid = false;
+            }
+        }
+        if let Some(ref tls) = self.tls {
+            if cfg!(not(feature = "tls")) {
+                error!("Server configuration is invalid. The opc.tls transport requires the server to be built with the tls feature");
+                valid = false;
+            } else if tls.port == self.port {
+                error!("Server configuration is invalid. The opc.tls port {} is the same as the opc.tcp port", tls.port);
+                valid = false;
+            }
+        }
+        if let Some(ref https) = self.https {
+            if cfg!(not(feature = "https")) {
+                error!("Server configuration is invalid. The opc.https transport requires the server to be built with the https feature");
+                valid = false;
+            } else if https.port == self.port || self.tls.as_ref().map(|tls| tls.port) == Some(https.port) {
+                error!("Server configuration is invalid. The opc.https port {} is already used by another transport", https.port);
+                valid = false;
+            }
+        }
+        if self.min_token_lifetime == 0 || self.min_token_lifetime > self.max_token_lifetime {
+            error!("Server configuration is invalid. Secure channel token lifetime range {}ms to {}ms is invalid", self.min_token_lifetime, self.max_token_lifetime);
+            valid = false;
+        }
+        valid
+    }
+}
+
+/// Settings for registering the server with discovery servers. Registration happens in the
+/// background for as long as the server is running.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct DiscoveryRegistrationConfig {
+    /// Urls of discovery servers to register with, in addition to the server's `discovery_server_url`
+    #[serde(default)]
+    pub discovery_server_urls: Vec<String>,
+    /// Interval in seconds between registrations. Part 12 requires a server to re-register at
+    /// least every 10 minutes.
+    #[serde(default = "DiscoveryRegistrationConfig::default_register_interval")]
+    pub register_interval: u32,
+    /// Interval in seconds before a failed registration is retried
+    #[serde(default = "DiscoveryRegistrationConfig::default_retry_interval")]
+    pub retry_interval: u32,
+    /// Number of times a failed registration is retried before waiting for the next register interval
+    #[serde(default = "DiscoveryRegistrationConfig::default_max_retries")]
+    pub max_retries: u32,
+    /// Server capability identifiers, e.g. "DA", "HD", "AC", which are supplied to the discovery
+    /// server for mDNS. See Part 12 Annex D for the list of identifiers.
+    #[serde(default)]
+    pub server_capabilities: Vec<String>,
+}
+
+impl Default for DiscoveryRegistrationConfig {
+    fn default() -> Self {
+        DiscoveryRegistrationConfig {
+            discovery_server_urls: Vec::new(),
+            register_interval: Self::default_register_interval(),
+            retry_interval: Self::default_retry_interval(),
+            max_retries: Self::default_max_retries(),
+            server_capabilities: Vec::new(),
+        }
+    }
+}
+
+impl DiscoveryRegistrationConfig {
+    fn default_register_interval() -> u32 { constants::DEFAULT_DISCOVERY_REGISTER_INTERVAL }
+
+    fn default_retry_interval() -> u32 { constants::DEFAULT_DISCOVERY_RETRY_INTERVAL }
+
+    fn default_max_retries() -> u32 { constants::DEFAULT_DISCOVERY_MAX_RETRIES }
+
+    pub fn is_valid(&self) -> bool {
+        let mut valid = true;
+        if self.register_interval == 0 || self.register_interval > constants::MAX_DISCOVERY_REGISTER_INTERVAL {
+            error!("Server configuration is invalid. Discovery register interval {} must be between 1 and {} seconds", self.register_interval, constants::MAX_DISCOVERY_REGISTER_INTERVAL);
+            valid = false;
+        }
+        if self.retry_interval == 0 {
+            error!("Server configuration is invalid. Discovery retry interval is invalid");
+            valid = false;
+        }
+        if self.server_capabilities.iter().any(|c| c.is_empty()) {
+            error!("Server configuration is invalid. Discovery server capabilities contain an empty identifier");
+            valid = false;
+        }
+        valid
+    }
+}
+
+/// Limits on the number of operations in a single request to a service. The limits are advertised
+/// through the `Server.ServerCapabilities.OperationLimits` nodes and a request which exceeds one
+/// fails with `BadTooManyOperations`. A limit of 0 means no limit.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct OperationalLimits {
+    /// Maximum number of nodes to read in a Read request
+    #[serde(default = "OperationalLimits::default_max_nodes_per_read")]
+    pub max_nodes_per_read: usize,
+    /// Maximum number of nodes to write in a Write request
+    #[serde(default = "OperationalLimits::default_max_nodes_per_write")]
+    pub max_nodes_per_write: usize,
+    /// Maximum number of methods to call in a Call request
+    #[serde(default = "OperationalLimits::default_max_nodes_per_method_call")]
+    pub max_nodes_per_method_call: usize,
+    /// Maximum number of nodes to browse in a Browse request
+    #[serde(default = "OperationalLimits::default_max_nodes_per_browse")]
+    pub max_nodes_per_browse: usize,
+    /// Maximum number of nodes in a RegisterNodes or UnregisterNodes request
+    #[serde(default = "OperationalLimits::default_max_nodes_per_register_nodes")]
+    pub max_nodes_per_register_nodes: usize,
+    /// Maximum number of browse paths in a TranslateBrowsePathsToNodeIds request
+    #[serde(default = "OperationalLimits::default_max_nodes_per_translate_browse_paths_to_node_ids")]
+    pub max_nodes_per_translate_browse_paths_to_node_ids: usize,
+    /// Maximum number of nodes or references in a request to the NodeManagement services
+    #[serde(default = "OperationalLimits::default_max_nodes_per_node_management")]
+    pub max_nodes_per_node_management: usize,
+    /// Maximum number of monitored items in a request to the MonitoredItem services
+    #[serde(default = "OperationalLimits::default_max_monitored_items_per_call")]
+    pub max_monitored_items_per_call: usize,
+    /// Maximum number of nodes in a HistoryRead request for values
+    #[serde(default = "OperationalLimits::default_max_nodes_per_history")]
+    pub max_nodes_per_history_read_data: usize,
+    /// Maximum number of nodes in a HistoryRead request for events
+    #[serde(default = "OperationalLimits::default_max_nodes_per_history")]
+    pub max_nodes_per_history_read_events: usize,
+    /// Maximum number of updates of values in a HistoryUpdate request
+    #[serde(default = "OperationalLimits::default_max_nodes_per_history")]
+    pub max_nodes_per_history_update_data: usize,
+    /// Maximum number of updates of events in a HistoryUpdate request
+    #[serde(default = "OperationalLimits::default_max_nodes_per_history")]
+    pub max_nodes_per_history_update_events: usize,
+}
+
+impl Default for OperationalLimits {
+    fn default() -> Self {
+        OperationalLimits {
+            max_nodes_per_read: Self::default_max_nodes_per_read(),
+            max_nodes_per_write: Self::default_max_nodes_per_write(),
+            max_nodes_per_method_call: Self::default_max_nodes_per_method_call(),
+            max_nodes_per_browse: Self::default_max_nodes_per_browse(),
+            max_nodes_per_register_nodes: Self::default_max_nodes_per_register_nodes(),
+            max_nodes_per_translate_browse_paths_to_node_ids: Self::default_max_nodes_per_translate_browse_paths_to_node_ids(),
+            max_nodes_per_node_management: Self::default_max_nodes_per_node_management(),
+            max_monitored_items_per_call: Self::default_max_monitored_items_per_call(),
+            max_nodes_per_history_read_data: Self::default_max_nodes_per_history(),
+            max_nodes_per_history_read_events: Self::default_max_nodes_per_history(),
+            max_nodes_per_history_update_data: Self::default_max_nodes_per_history(),
+            max_nodes_per_history_update_events: Self::default_max_nodes_per_history(),
+        }
+    }
+}
+
+impl OperationalLimits {
+    fn default_max_nodes_per_read() -> usize { constants::MAX_NODES_PER_READ }
+
+    fn default_max_nodes_per_write() -> usize { constants::MAX_NODES_PER_WRITE }
+
+    fn default_max_nodes_per_method_call() -> usize { constants::MAX_METHOD_CALLS }
+
+    fn default_max_nodes_per_browse() -> usize { constants::MAX_NODES_PER_BROWSE }
+
+    fn default_max_nodes_per_register_nodes() -> usize { constants::MAX_NODES_PER_REGISTER_NODES }
+
+    fn default_max_nodes_per_translate_browse_paths_to_node_ids() -> usize { constants::MAX_BROWSE_PATHS_PER_TRANSLATE }
+
+    fn default_max_nodes_per_node_management() -> usize { constants::MAX_NODES_PER_NODE_MANAGEMENT }
+
+    fn default_max_monitored_items_per_call() -> usize { constants::MAX_MONITORED_ITEMS_PER_CALL }
+
+    fn default_max_nodes_per_history() -> usize { constants::MAX_NODES_PER_HISTORY }
+
+    /// Tests if a request with this many operations exceeds the limit, where 0 means no limit
+    pub fn is_exceeded(limit: usize, operations: usize) -> bool {
+        limit > 0 && operations > limit
+    }
+}
+
+/// A field of a published data set, which holds the value of a variable in the address space
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct PublishedFieldConfig {
+    /// Name of the field in the payload of a data set message
+    pub name: String,
+    /// Node id of the variable, e.g. "ns=2;s=Temperature"
+    pub node_id: String,
+}
+
+/// A data set writer publishes the values of its fields in a data set message
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct DataSetWriterConfig {
+    /// Id of the writer, unique within the publisher
+    pub data_set_writer_id: u16,
+    /// Fields of the published data set
+    pub fields: Vec<PublishedFieldConfig>,
+}
+
+/// A writer group publishes the data set messages of its writers in a single network message to
+/// its topic every publishing interval.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct WriterGroupConfig {
+    /// Id of the writer group, unique within the publisher
+    pub writer_group_id: u16,
+    /// Interval in milliseconds between network messages
+    pub publishing_interval: u32,
+    /// MQTT topic that network messages are published to
+    pub topic: String,
+    /// Asks the broker to retain the last network message for new subscribers
+    #[serde(default)]
+    pub retain: bool,
+    /// Writers of the data sets in the group
+    pub data_set_writers: Vec<DataSetWriterConfig>,
+}
+
+/// A PubSub connection to an MQTT broker. Network messages are JSON encoded as described in
+/// Part 14 so that they can be consumed by any subscriber that understands OPC UA PubSub JSON.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct PubSubConnectionConfig {
+    /// Url of the broker, e.g. "mqtt://localhost:1883"
+    pub broker_url: String,
+    /// MQTT client id, the application uri if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// PublisherId of network messages, the application uri if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publisher_id: Option<String>,
+    /// User name to connect to the broker with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Password to connect to the broker with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pass: Option<String>,
+    /// Writer groups of the connection
+    pub writer_groups: Vec<WriterGroupConfig>,
+}
+
+impl PubSubConnectionConfig {
+    pub fn is_valid(&self) -> bool {
+        let mut valid = true;
+        if mqtt::broker_host_port(&self.broker_url).is_err() {
+            error!("Server configuration is invalid. PubSub broker url {} is invalid", self.broker_url);
+            valid = false;
+        }
+        if self.pass.is_some() && self.user.is_none() {
+            error!("Server configuration is invalid. PubSub connection to {} has a password but no user", self.broker_url);
+            valid = false;
+        }
+        let mut writer_group_ids = BTreeSet::new();
+        let mut data_set_writer_ids = BTreeSet::new();
+        for writer_group in &self.writer_groups {
+            if !writer_group_ids.insert(writer_group.writer_group_id) {
+                error!("Server configuration is invalid. PubSub writer group id {} is not unique", writer_group.writer_group_id);
+                valid = false;
+            }
+            if writer_group.publishing_interval == 0 {
+                error!("Server configuration is invalid. PubSub writer group {} has no publishing interval", writer_group.writer_group_id);
+                valid = false;
+            }
+            if writer_group.topic.is_empty() || writer_group.topic.contains(|c| c == '+' || c == '#') {
+                error!("Server configuration is invalid. PubSub writer group {} topic \"{}\" is invalid", writer_group.writer_group_id, writer_group.topic);
+                valid = false;
+            }
+            for data_set_writer in &writer_group.data_set_writers {
+                if !data_set_writer_ids.insert(data_set_writer.data_set_writer_id) {
+                    error!("Server configuration is invalid. PubSub data set writer id {} is not unique", data_set_writer.data_set_writer_id);
+                    valid = false;
+                }
+                for field in &data_set_writer.fields {
+                    if NodeId::from_str(&field.node_id).is_err() {
+                        error!("Server configuration is invalid. PubSub field {} has an invalid node id {}", field.name, field.node_id);
+                        valid = false;
+                    }
+                }
+            }
+        }
+        valid
+    }
+}
+
+/// The JSON encoding of the values that an MQTT forwarder publishes
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+pub enum MqttPayloadEncoding {
+    /// The reversible form of Part 6 5.4, where a value holds its built-in "Type" and its "Body"
+    Reversible,
+    /// The non-reversible form of Part 6 5.4, where a value is just its body, e.g. a number, which
+    /// is simpler for consumers which know nothing of OPC UA
+    NonReversible,
+}
+
+/// A variable whose value changes are forwarded to an MQTT broker
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct ForwardedVariableConfig {
+    /// Node id of the variable, e.g. "ns=2;s=Temperature"
+    pub node_id: String,
+    /// Name of the variable in its topic and payload, the browse name of the variable if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// A forwarder which samples variables of the address space and publishes their values to an MQTT
+/// broker whenever they change, e.g. to feed an IoT platform from the server. Each variable is
+/// published to its own topic, which is made from the topic template by replacing `{name}` with the
+/// name of the variable, `{node_id}` with its node id and `{namespace}` with its namespace index.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct MqttForwarderConfig {
+    /// Url of the broker, e.g. "mqtt://localhost:1883"
+    pub broker_url: String,
+    /// MQTT client id, the application uri if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// User name to connect to the broker with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Password to connect to the broker with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pass: Option<String>,
+    /// Template of the topics that values are published to, e.g. "opcua/{name}"
+    #[serde(default = "MqttForwarderConfig::default_topic_template")]
+    pub topic_template: String,
+    /// Encoding of the published values
+    #[serde(default = "MqttForwarderConfig::default_payload_encoding")]
+    pub payload_encoding: MqttPayloadEncoding,
+    /// Interval in milliseconds between samples of the variables
+    #[serde(default = "MqttForwarderConfig::default_sampling_interval")]
+    pub sampling_interval: u64,
+    /// Asks the broker to retain the last value of each topic for new subscribers
+    #[serde(default)]
+    pub retain: bool,
+    /// Variables whose values are forwarded
+    pub variables: Vec<ForwardedVariableConfig>,
+}
+
+impl MqttForwarderConfig {
+    pub fn new<T>(broker_url: T) -> Self where T: Into<String> {
+        MqttForwarderConfig {
+            broker_url: broker_url.into(),
+            client_id: None,
+            user: None,
+            pass: None,
+            topic_template: Self::default_topic_template(),
+            payload_encoding: Self::default_payload_encoding(),
+            sampling_interval: Self::default_sampling_interval(),
+            retain: false,
+            variables: Vec::new(),
+        }
+    }
+
+    fn default_topic_template() -> String { constants::DEFAULT_MQTT_FORWARDER_TOPIC_TEMPLATE.to_string() }
+
+    fn default_payload_encoding() -> MqttPayloadEncoding { MqttPayloadEncoding::NonReversible }
+
+    fn default_sampling_interval() -> u64 { constants::DEFAULT_MQTT_FORWARDER_SAMPLING_INTERVAL_MS }
+
+    pub fn is_valid(&self) -> bool {
+        let mut valid = true;
+        if mqtt::broker_host_port(&self.broker_url).is_err() {
+            error!("Server configuration is invalid. MQTT forwarder broker url {} is invalid", self.broker_url);
+            valid = false;
+        }
+        if self.pass.is_some() && self.user.is_none() {
+            error!("Server configuration is invalid. MQTT forwarder to {} has a password but no user", self.broker_url);
+            valid = false;
+        }
+        if forwarder::topic(&self.topic_template, "name", &NodeId::new(1, "node")).is_err() {
+            error!("Server configuration is invalid. MQTT forwarder to {} topic template \"{}\" is invalid", self.broker_url, self.topic_template);
+            valid = false;
+        }
+        if self.sampling_interval == 0 {
+            error!("Server configuration is invalid. MQTT forwarder to {} has no sampling interval", self.broker_url);
+            valid = false;
+        }
+        for variable in &self.variables {
+            if NodeId::from_str(&variable.node_id).is_err() {
+                error!("Server configuration is invalid. MQTT forwarder to {} has an invalid node id {}", self.broker_url, variable.node_id);
+                valid = false;
+            }
+        }
+        valid
+    }
+}
+
+/// The waveform that a simulated variable follows
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+pub enum SimulationWaveform {
+    /// A sine wave between -amplitude and +amplitude
+    Sine,
+    /// A sawtooth which rises from 0 to amplitude over each period
+    Ramp,
+    /// A random walk between -amplitude and +amplitude, which moves at most amplitude per period
+    RandomWalk,
+    /// +amplitude for the first half of each period and -amplitude for the second
+    Square,
+}
+
+/// A variable whose value the server animates with a waveform, e.g. to give the clients of a
+/// demo or load test something to monitor. The value is a Double, offset + the waveform.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct SimulatedVariableConfig {
+    /// Node id of the variable, e.g. "ns=2;s=Simulation.Sine". The variable is created in the
+    /// Simulation folder if it does not exist.
+    pub node_id: String,
+    /// Browse and display name of the variable if it is created
+    pub name: String,
+    /// Waveform of the value
+    pub waveform: SimulationWaveform,
+    /// Period of the waveform in milliseconds
+    pub period: u64,
+    /// Amplitude of the waveform
+    pub amplitude: f64,
+    /// Value that the waveform is centred on, or starts from for a ramp
+    #[serde(default)]
+    pub offset: f64,
+    /// Interval in milliseconds between updates of the value
+    pub update_interval: u64,
+}
+
+impl SimulatedVariableConfig {
+    pub fn is_valid(&self) -> bool {
+        let mut valid = true;
+        if NodeId::from_str(&self.node_id).is_err() {
+            error!("Server configuration is invalid. Simulated variable {} has an invalid node id {}", self.name, self.node_id);
+            valid = false;
+        }
+        if self.period == 0 {
+            error!("Server configuration is invalid. Simulated variable {} has no period", self.name);
+            valid = false;
+        }
+        if self.update_interval == 0 {
+            error!("Server configuration is invalid. Simulated variable {} has no update interval", self.name);
+            valid = false;
+        }
+        if !self.amplitude.is_finite() || !self.offset.is_finite() {
+            error!("Server configuration is invalid. Simulated variable {} has an invalid amplitude or offset", self.name);
+            valid = false;
+        }
+        valid
+    }
+}
+
+/// Another OPC UA server whose address space the server aggregates, i.e. mirrors beneath a
+/// folder of Objects, like a gateway or concentrator. The objects and variables beneath the other
+/// server's Objects folder are browsed when the server connects to it. Reads and writes of the
+/// mirrored variables are forwarded to the other server, and their values are kept up to date by
+/// a subscription on it so they may be monitored.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct AggregatedServerConfig {
+    /// Browse and display name of the folder beneath Objects that the other server's objects and
+    /// variables are mirrored in. It must be unique amongst the aggregated servers.
+    pub name: String,
+    /// Endpoint url of the other server, e.g. "opc.tcp://plc1:4855/"
+    pub url: String,
+    /// Security policy of the endpoint, e.g. None, Basic256Sha256
+    #[serde(default = "AggregatedServerConfig::default_security_policy")]
+    pub security_policy: String,
+    /// Security mode of the endpoint, i.e. None, Sign or SignAndEncrypt
+    #[serde(default = "AggregatedServerConfig::default_security_mode")]
+    pub security_mode: String,
+    /// User name to activate the session with, anonymous if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Password of the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pass: Option<String>,
+    /// Publishing interval in milliseconds of the subscription to the values of the variables
+    #[serde(default = "AggregatedServerConfig::default_publishing_interval")]
+    pub publishing_interval: u64,
+    /// Maximum depth beneath the other server's Objects folder that is mirrored
+    #[serde(default = "AggregatedServerConfig::default_max_browse_depth")]
+    pub max_browse_depth: u32,
+}
+
+impl AggregatedServerConfig {
+    pub fn new<T>(name: T, url: T) -> Self where T: Into<String> {
+        AggregatedServerConfig {
+            name: name.into(),
+            url: url.into(),
+            security_policy: Self::default_security_policy(),
+            security_mode: Self::default_security_mode(),
+            user: None,
+            pass: None,
+            publishing_interval: Self::default_publishing_interval(),
+            max_browse_depth: Self::default_max_browse_depth(),
+        }
+    }
+
+    fn default_security_policy() -> String { SecurityPolicy::None.to_str().to_string() }
+
+    fn default_security_mode() -> String { MessageSecurityMode::None.into() }
+
+    fn default_publishing_interval() -> u64 { constants::DEFAULT_AGGREGATION_PUBLISHING_INTERVAL_MS }
+
+    fn default_max_browse_depth() -> u32 { constants::DEFAULT_AGGREGATION_MAX_BROWSE_DEPTH }
+
+    pub fn is_valid(&self) -> bool {
+        let mut valid = true;
+        if self.name.is_empty() {
+            error!("Server configuration is invalid. Aggregated server {} has no name", self.url);
+            valid = false;
+        }
+        if !is_valid_opc_ua_url(&self.url) {
+            error!("Server configuration is invalid. Aggregated server {} url {} is invalid", self.name, self.url);
+            valid = false;
+        }
+        let security_policy = SecurityPolicy::from_str(&self.security_policy).unwrap();
+        let security_mode = MessageSecurityMode::from(self.security_mode.as_ref());
+        if security_policy == SecurityPolicy::Unknown || security_mode == MessageSecurityMode::Invalid {
+            error!("Server configuration is invalid. Aggregated server {} security policy \"{}\" or security mode \"{}\" is invalid", self.name, self.security_policy, self.security_mode);
+            valid = false;
+        } else if (security_policy == SecurityPolicy::None) != (security_mode == MessageSecurityMode::None) {
+            error!("Server configuration is invalid. Aggregated server {} security policy and security mode must both contain None or neither of them should", self.name);
+            valid = false;
+        }
+        if self.pass.is_some() && self.user.is_none() {
+            error!("Server configuration is invalid. Aggregated server {} has a password but no user", self.name);
+            valid = false;
+        }
+        if self.publishing_interval == 0 {
+            error!("Server configuration is invalid. Aggregated server {} has no publishing interval", self.name);
+            valid = false;
+        }
+        valid
+    }
+
+    pub fn security_policy(&self) -> SecurityPolicy {
+        SecurityPolicy::from_str(&self.security_policy).unwrap()
+    }
+
+    pub fn message_security_mode(&self) -> MessageSecurityMode {
+        MessageSecurityMode::from(self.security_mode.as_ref())
+    }
+}
+
+/// The REST gateway, an HTTP JSON API for reading and writing the values of variables and browsing
+/// nodes without an OPC UA client. Requests are served by the Read, Write and Browse services, so
+/// the user they authenticate as is subject to the same roles and access control as a session.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct RestGatewayConfig {
+    /// Address and port that the gateway listens on, e.g. "127.0.0.1:8080"
+    pub address: String,
+    /// User tokens that requests may authenticate with, like the user token ids of an endpoint.
+    /// "ANONYMOUS" accepts requests without an Authorization header, "ISSUED_TOKEN" accepts
+    /// Bearer access tokens and the id of a user name token accepts Basic authentication. Any but
+    /// "ANONYMOUS" requires the address to be a loopback address.
+    pub user_token_ids: BTreeSet<String>,
+}
+
+impl RestGatewayConfig {
+    /// Creates a gateway that only accepts anonymous requests
+    pub fn new<T>(address: T) -> Self where T: Into<String> {
+        let mut user_token_ids = BTreeSet::new();
+        user_token_ids.insert(ANONYMOUS_USER_TOKEN_ID.to_string());
+        RestGatewayConfig {
+            address: address.into(),
+            user_token_ids,
+        }
+    }
+
+    pub fn is_valid(&self, user_tokens: &BTreeMap<String, ServerUserToken>) -> bool {
+        let mut valid = true;
+        if cfg!(not(feature = "http")) {
+            error!("Server configuration is invalid. The REST gateway requires the server to be built with the http feature");
+            valid = false;
+        }
+        if self.address.parse::<std::net::SocketAddr>().is_err() {
+            error!("Server configuration is invalid. REST gateway address {} is not an address and port", self.address);
+            valid = false;
+        }
+        if self.user_token_ids.is_empty() {
+            error!("Server configuration is invalid. REST gateway accepts no user tokens");
+            valid = false;
+        }
+        if !self.is_loopback() && self.user_token_ids.iter().any(|id| id != ANONYMOUS_USER_TOKEN_ID) {
+            error!("Server configuration is invalid. REST gateway serves plain HTTP so it only accepts credentials on a loopback address, not {}", self.address);
+            valid = false;
+        }
+        for id in &self.user_token_ids {
+            if id == ANONYMOUS_USER_TOKEN_ID || id == ISSUED_TOKEN_USER_TOKEN_ID {
+                continue;
+            }
+            match user_tokens.get(id) {
+                Some(user_token) if user_token.is_user_pass() => {}
+                Some(_) => {
+                    error!("Server configuration is invalid. REST gateway user token {} is not a user name token", id);
+                    valid = false;
+                }
+                None => {
+                    error!("Server configuration is invalid. Cannot find REST gateway user token {}", id);
+                    valid = false;
+                }
+            }
+        }
+        valid
+    }
+
+    /// The base url of the gateway, which access tokens are validated for. The gateway serves
+    /// plain HTTP, so a client reaches it through a TLS terminating proxy if it is not local.
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.address)
+    }
+
+    /// Tests if the gateway listens on a loopback address, which is the only kind of address that
+    /// passwords and access tokens may be sent to over plain HTTP
+    pub fn is_loopback(&self) -> bool {
+        self.address.parse::<std::net::SocketAddr>()
+            .map(|address| address.ip().is_loopback())
+            .unwrap_or(false)
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
@@ -31,7 +768,9 @@ pub struct ServerUserToken {
     /// Password
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pass: Option<String>,
-    // X509 file path
+    /// Path to the user's X509 certificate in .der format. A client that activates a session
+    /// with an X509 identity token is only accepted if its certificate matches one of these
+    /// certificates, i.e. the X509 user tokens of an endpoint are its trust list.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub x509: Option<PathBuf>,
 }
@@ -45,10 +784,18 @@ impl ServerUserToken {
         }
     }
 
+    pub fn new_x509<T>(user: T, cert_path: &Path) -> Self where T: Into<String> {
+        ServerUserToken {
+            user: user.into(),
+            pass: None,
+            x509: Some(cert_path.to_path_buf()),
+        }
+    }
+
     pub fn is_valid(&self, id: &str) -> bool {
         let mut valid = true;
-        if id == ANONYMOUS_USER_TOKEN_ID {
-            error!("User token {} uses the reserved name \"anonymous\"", id);
+        if id == ANONYMOUS_USER_TOKEN_ID || id == ISSUED_TOKEN_USER_TOKEN_ID {
+            error!("User token {} uses a reserved name", id);
             valid = false;
         }
         if self.user.is_empty() {
@@ -75,6 +822,15 @@ impl ServerUserToken {
     pub fn is_x509(&self) -> bool {
         self.x509.is_some()
     }
+
+    /// Reads the user's X509 certificate from disk
+    pub fn read_x509(&self) -> Result<X509, String> {
+        if let Some(ref path) = self.x509 {
+            CertificateStore::read_cert(path)
+        } else {
+            Err(format!("User token {} has no x509 cert", self.user))
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
@@ -91,6 +847,12 @@ pub struct ServerEndpoint {
     pub password_security_policy: Option<String>,
     /// User tokens
     pub user_token_ids: BTreeSet<String>,
+    /// Experimental. The endpoint is reached through the opc.tls transport instead of opc.tcp
+    #[serde(default)]
+    pub tls: bool,
+    /// Experimental. The endpoint is reached through the opc.https transport instead of opc.tcp
+    #[serde(default)]
+    pub https: bool,
 }
 
 /// Convenience method to make an endpoint from a tuple
@@ -103,6 +865,8 @@ impl<'a> From<(&'a str, SecurityPolicy, MessageSecurityMode, &'a [&'a str])> for
             security_level: Self::security_level(v.1),
             password_security_policy: None,
             user_token_ids: v.3.iter().map(|id| id.to_string()).collect(),
+            tls: false,
+            https: false,
         }
     }
 }
@@ -116,6 +880,8 @@ impl ServerEndpoint {
             security_level: Self::security_level(security_policy),
             password_security_policy: None,
             user_token_ids: user_token_ids.iter().map(|id| id.clone()).collect(),
+            tls: false,
+            https: false,
         }
     }
 
@@ -126,6 +892,8 @@ impl ServerEndpoint {
             SecurityPolicy::Basic128Rsa15 => 2,
             SecurityPolicy::Basic256 => 3,
             SecurityPolicy::Basic256Sha256 => 4,
+            SecurityPolicy::Aes128Sha256RsaOaep => 5,
+            SecurityPolicy::Aes256Sha256RsaPss => 6,
             _ => 0
         }
     }
@@ -134,37 +902,63 @@ impl ServerEndpoint {
         Self::new(path, SecurityPolicy::None, MessageSecurityMode::None, user_token_ids)
     }
 
+    #[cfg(feature = "crypto")]
     pub fn new_basic128rsa15_sign<T>(path: T, user_token_ids: &[String]) -> Self where T: Into<String> {
         Self::new(path, SecurityPolicy::Basic128Rsa15, MessageSecurityMode::Sign, user_token_ids)
     }
 
+    #[cfg(feature = "crypto")]
     pub fn new_basic128rsa15_sign_encrypt<T>(path: T, user_token_ids: &[String]) -> Self where T: Into<String> {
         Self::new(path, SecurityPolicy::Basic128Rsa15, MessageSecurityMode::SignAndEncrypt, user_token_ids)
     }
 
+    #[cfg(feature = "crypto")]
     pub fn new_basic256_sign<T>(path: T, user_token_ids: &[String]) -> Self where T: Into<String> {
         Self::new(path, SecurityPolicy::Basic256, MessageSecurityMode::Sign, user_token_ids)
     }
 
+    #[cfg(feature = "crypto")]
     pub fn new_basic256_sign_encrypt<T>(path: T, user_token_ids: &[String]) -> Self where T: Into<String> {
         Self::new(path, SecurityPolicy::Basic256, MessageSecurityMode::SignAndEncrypt, user_token_ids)
     }
 
+    #[cfg(feature = "crypto")]
     pub fn new_basic256sha256_sign<T>(path: T, user_token_ids: &[String]) -> Self where T: Into<String> {
         Self::new(path, SecurityPolicy::Basic256Sha256, MessageSecurityMode::Sign, user_token_ids)
     }
 
+    #[cfg(feature = "crypto")]
     pub fn new_basic256sha256_sign_encrypt<T>(path: T, user_token_ids: &[String]) -> Self where T: Into<String> {
         Self::new(path, SecurityPolicy::Basic256Sha256, MessageSecurityMode::SignAndEncrypt, user_token_ids)
     }
 
+    #[cfg(feature = "crypto")]
+    pub fn new_aes128sha256rsaoaep_sign<T>(path: T, user_token_ids: &[String]) -> Self where T: Into<String> {
+        Self::new(path, SecurityPolicy::Aes128Sha256RsaOaep, MessageSecurityMode::Sign, user_token_ids)
+    }
+
+    #[cfg(feature = "crypto")]
+    pub fn new_aes128sha256rsaoaep_sign_encrypt<T>(path: T, user_token_ids: &[String]) -> Self where T: Into<String> {
+        Self::new(path, SecurityPolicy::Aes128Sha256RsaOaep, MessageSecurityMode::SignAndEncrypt, user_token_ids)
+    }
+
+    #[cfg(feature = "crypto")]
+    pub fn new_aes256sha256rsapss_sign<T>(path: T, user_token_ids: &[String]) -> Self where T: Into<String> {
+        Self::new(path, SecurityPolicy::Aes256Sha256RsaPss, MessageSecurityMode::Sign, user_token_ids)
+    }
+
+    #[cfg(feature = "crypto")]
+    pub fn new_aes256sha256rsapss_sign_encrypt<T>(path: T, user_token_ids: &[String]) -> Self where T: Into<String> {
+        Self::new(path, SecurityPolicy::Aes256Sha256RsaPss, MessageSecurityMode::SignAndEncrypt, user_token_ids)
+    }
+
     pub fn is_valid(&self, id: &str, user_tokens: &BTreeMap<String, ServerUserToken>) -> bool {
         let mut valid = true;
 
         // Validate that the user token ids exist
         for id in &self.user_token_ids {
-            // Skip anonymous
-            if id == ANONYMOUS_USER_TOKEN_ID {
+            // Skip anonymous and issued tokens
+            if id == ANONYMOUS_USER_TOKEN_ID || id == ISSUED_TOKEN_USER_TOKEN_ID {
                 continue;
             }
             if !user_tokens.contains_key(id) {
@@ -176,7 +970,7 @@ impl ServerEndpoint {
         if let Some(ref password_security_policy) = self.password_security_policy {
             let password_security_policy = SecurityPolicy::from_str(password_security_policy).unwrap();
             if password_security_policy == SecurityPolicy::Unknown {
-                error!("Endpoint {} is invalid. Password security policy \"{}\" is invalid. Valid values are None, Basic128Rsa15, Basic256, Basic256Sha256", id, password_security_policy);
+                error!("Endpoint {} is invalid. Password security policy \"{}\" is invalid. Valid values are None, Basic128Rsa15, Basic256, Basic256Sha256, Aes128-Sha256-RsaOaep, Aes256-Sha256-RsaPss", id, password_security_policy);
                 valid = false;
             }
         }
@@ -184,8 +978,11 @@ impl ServerEndpoint {
         // Validate the security policy and mode
         let security_policy = SecurityPolicy::from_str(&self.security_policy).unwrap();
         let security_mode = MessageSecurityMode::from(self.security_mode.as_ref());
-        if security_policy == SecurityPolicy::Unknown {
-            error!("Endpoint {} is invalid. Security policy \"{}\" is invalid. Valid values are None, Basic128Rsa15, Basic256, Basic256Sha256", id, self.security_policy);
+        if cfg!(not(feature = "crypto")) && security_policy != SecurityPolicy::None {
+            error!("Endpoint {} is invalid. Security policy \"{}\" requires the server to be built with the crypto feature", id, self.security_policy);
+            valid = false;
+        } else if security_policy == SecurityPolicy::Unknown {
+            error!("Endpoint {} is invalid. Security policy \"{}\" is invalid. Valid values are None, Basic128Rsa15, Basic256, Basic256Sha256, Aes128-Sha256-RsaOaep, Aes256-Sha256-RsaPss", id, self.security_policy);
             valid = false;
         } else if security_mode == MessageSecurityMode::Invalid {
             error!("Endpoint {} is invalid. Security mode \"{}\" is invalid. Valid values are None, Sign, SignAndEncrypt", id, self.security_mode);
@@ -218,6 +1015,11 @@ impl ServerEndpoint {
         self.supports_user_token_id(ANONYMOUS_USER_TOKEN_ID)
     }
 
+    /// Test if the endpoint supports issued identity tokens
+    pub fn supports_issued_token(&self) -> bool {
+        self.supports_user_token_id(ISSUED_TOKEN_USER_TOKEN_ID)
+    }
+
     pub fn supports_user_token_id(&self, id: &str) -> bool {
         self.user_token_ids.contains(id)
     }
@@ -239,19 +1041,78 @@ pub struct ServerConfig {
     /// Auto trusts client certificates. For testing/samples only unless you're sure what you're
     /// doing.
     pub trust_client_certs: bool,
+    /// The checks made on client certificates, each of which can be relaxed
+    #[serde(default)]
+    pub certificate_validation: CertificateValidation,
     /// Url to a discovery server - adding this string causes the server to assume you wish to
     /// register the server with a discovery server.
     pub discovery_server_url: Option<String>,
+    /// Settings for registering the server with discovery servers
+    #[serde(default)]
+    pub discovery_registration: DiscoveryRegistrationConfig,
     /// tcp configuration information
     pub tcp_config: TcpConfig,
     /// User tokens
     pub user_tokens: BTreeMap<String, ServerUserToken>,
     /// discovery endpoint url which may or may not be the same as the service endpoints below.
     pub discovery_urls: Vec<String>,
+    /// Urls of clients the server connects to with reverse connect, e.g. for clients which cannot
+    /// be reached by the server's own listener. The server sends a ReverseHello on each connection
+    /// and the client then proceeds as if it had connected.
+    #[serde(default)]
+    pub reverse_connect_urls: Vec<String>,
+    /// Connections to MQTT brokers that the server publishes PubSub network messages to
+    #[serde(default)]
+    pub pubsub_connections: Vec<PubSubConnectionConfig>,
+    /// Forwarders which publish the value changes of variables to MQTT brokers
+    #[serde(default)]
+    pub mqtt_forwarders: Vec<MqttForwarderConfig>,
+    /// Variables that the server animates with simulated values
+    #[serde(default)]
+    pub simulated_variables: Vec<SimulatedVariableConfig>,
+    /// Other servers whose address spaces the server mirrors beneath its Objects folder
+    #[serde(default)]
+    pub aggregated_servers: Vec<AggregatedServerConfig>,
+    /// The REST gateway, or none to disable it. Requires the server to be built with the http
+    /// feature.
+    #[serde(default)]
+    pub rest_gateway: Option<RestGatewayConfig>,
     /// Endpoints supported by the server
     pub endpoints: BTreeMap<String, ServerEndpoint>,
+    /// Maximum number of sessions open on the server at the same time, 0 means no limit
+    #[serde(default = "ServerConfig::default_max_sessions")]
+    pub max_sessions: u32,
+    /// Smallest session timeout in milliseconds that the server will revise a client's requested
+    /// timeout up to
+    #[serde(default = "ServerConfig::default_min_session_timeout")]
+    pub min_session_timeout: f64,
+    /// Largest session timeout in milliseconds that the server will revise a client's requested
+    /// timeout down to. A session with no requests for longer than its timeout is closed.
+    #[serde(default = "ServerConfig::default_max_session_timeout")]
+    pub max_session_timeout: f64,
     /// Maximum number of subscriptions in a session
     pub max_subscriptions: u32,
+    /// Maximum number of monitored items in a subscription, 0 means no limit
+    #[serde(default = "ServerConfig::default_max_monitored_items_per_subscription")]
+    pub max_monitored_items_per_subscription: u32,
+    /// Minimum sampling interval in milliseconds of monitored items. Requested sampling intervals
+    /// are revised up to this, or to the `MinimumSamplingInterval` of the monitored node if larger.
+    #[serde(default = "ServerConfig::default_min_sampling_interval")]
+    pub min_sampling_interval: f64,
+    /// Maximum number of publish requests that a session may have queued. A session may never
+    /// queue more than two per subscription. 0 means no limit other than that.
+    #[serde(default = "ServerConfig::default_max_publish_requests")]
+    pub max_publish_requests: u32,
+    /// Maximum number of browse continuation points that a session may hold
+    #[serde(default = "ServerConfig::default_max_browse_continuation_points")]
+    pub max_browse_continuation_points: u32,
+    /// Time in milliseconds that the Read and Write services wait for the async getters and
+    /// setters of variables before the items fail with `BadTimeout`
+    #[serde(default = "ServerConfig::default_async_timeout")]
+    pub async_timeout: u32,
+    /// Limits on the number of operations in a single request to a service
+    #[serde(default)]
+    pub operational_limits: OperationalLimits,
     /// Max array length in elements
     pub max_array_length: u32,
     /// Max string length in characters
@@ -271,10 +1132,35 @@ impl Config for ServerConfig {
             error!("Server configuration is invalid. It defines no endpoints");
             valid = false;
         }
+        if !self.tcp_config.is_valid() {
+            valid = false;
+        }
+        if !self.discovery_registration.is_valid() {
+            valid = false;
+        }
         for (id, endpoint) in &self.endpoints {
             if !endpoint.is_valid(&id, &self.user_tokens) {
                 valid = false;
             }
+            if endpoint.tls && self.tcp_config.tls.is_none() {
+                error!("Endpoint {} is invalid. It uses the opc.tls transport but the server has no tls configuration", id);
+                valid = false;
+            }
+            if endpoint.https {
+                if endpoint.tls {
+                    error!("Endpoint {} is invalid. It cannot use both the opc.tls and opc.https transports", id);
+                    valid = false;
+                }
+                if self.tcp_config.https.is_none() {
+                    error!("Endpoint {} is invalid. It uses the opc.https transport but the server has no https configuration", id);
+                    valid = false;
+                }
+                // TLS secures the transport so there is no secure channel to sign or encrypt with
+                if endpoint.security_policy() != SecurityPolicy::None {
+                    error!("Endpoint {} is invalid. An opc.https endpoint must have the security policy None", id);
+                    valid = false;
+                }
+            }
         }
         for (id, user_token) in &self.user_tokens {
             if !user_token.is_valid(&id) {
@@ -293,10 +1179,65 @@ impl Config for ServerConfig {
             error!("Server configuration is invalid. Max byte string length is invalid");
             valid = false;
         }
+        if self.max_browse_continuation_points == 0 {
+            error!("Server configuration is invalid. Max browse continuation points is invalid");
+            valid = false;
+        }
+        if self.async_timeout == 0 {
+            error!("Server configuration is invalid. Async timeout is invalid");
+            valid = false;
+        }
+        if !(self.min_sampling_interval >= 0f64) {
+            error!("Server configuration is invalid. Min sampling interval {}ms is invalid", self.min_sampling_interval);
+            valid = false;
+        }
+        if !(self.min_session_timeout > 0f64) || !(self.min_session_timeout <= self.max_session_timeout) {
+            error!("Server configuration is invalid. Session timeout range {}ms to {}ms is invalid", self.min_session_timeout, self.max_session_timeout);
+            valid = false;
+        }
         if self.discovery_urls.is_empty() {
             error!("Server configuration is invalid. Discovery urls not set");
             valid = false;
         }
+        for url in &self.reverse_connect_urls {
+            if !is_valid_opc_ua_url(url) {
+                error!("Server configuration is invalid. Reverse connect url {} is invalid", url);
+                valid = false;
+            }
+        }
+        for pubsub_connection in &self.pubsub_connections {
+            if !pubsub_connection.is_valid() {
+                valid = false;
+            }
+        }
+        for mqtt_forwarder in &self.mqtt_forwarders {
+            if !mqtt_forwarder.is_valid() {
+                valid = false;
+            }
+        }
+        let mut simulated_node_ids = BTreeSet::new();
+        for simulated_variable in &self.simulated_variables {
+            if !simulated_variable.is_valid() {
+                valid = false;
+            } else if !simulated_node_ids.insert(&simulated_variable.node_id) {
+                error!("Server configuration is invalid. Simulated variable node id {} is not unique", simulated_variable.node_id);
+                valid = false;
+            }
+        }
+        let mut aggregated_server_names = BTreeSet::new();
+        for aggregated_server in &self.aggregated_servers {
+            if !aggregated_server.is_valid() {
+                valid = false;
+            } else if !aggregated_server_names.insert(&aggregated_server.name) {
+                error!("Server configuration is invalid. Aggregated server name {} is not unique", aggregated_server.name);
+                valid = false;
+            }
+        }
+        if let Some(ref rest_gateway) = self.rest_gateway {
+            if !rest_gateway.is_valid(&self.user_tokens) {
+                valid = false;
+            }
+        }
         valid
     }
 
@@ -317,25 +1258,54 @@ impl Default for ServerConfig {
             pki_dir,
             create_sample_keypair: false,
             trust_client_certs: false,
+            certificate_validation: CertificateValidation::default(),
             discovery_server_url: None,
-            tcp_config: TcpConfig {
-                host: "127.0.0.1".to_string(),
-                port: constants::DEFAULT_RUST_OPC_UA_SERVER_PORT,
-                hello_timeout: constants::DEFAULT_HELLO_TIMEOUT_SECONDS,
-            },
+            discovery_registration: DiscoveryRegistrationConfig::default(),
+            tcp_config: TcpConfig::default(),
             user_tokens: BTreeMap::new(),
             discovery_urls: Vec::new(),
+            reverse_connect_urls: Vec::new(),
+            pubsub_connections: Vec::new(),
+            mqtt_forwarders: Vec::new(),
+            simulated_variables: Vec::new(),
+            aggregated_servers: Vec::new(),
+            rest_gateway: None,
             endpoints: BTreeMap::new(),
             max_array_length: opcua_types_constants::MAX_ARRAY_LENGTH as u32,
             max_string_length: opcua_types_constants::MAX_STRING_LENGTH as u32,
             max_byte_string_length: opcua_types_constants::MAX_BYTE_STRING_LENGTH as u32,
+            max_sessions: constants::DEFAULT_MAX_SESSIONS,
+            min_session_timeout: constants::MIN_SESSION_TIMEOUT,
+            max_session_timeout: constants::MAX_SESSION_TIMEOUT,
             max_subscriptions: constants::DEFAULT_MAX_SUBSCRIPTIONS,
+            max_monitored_items_per_subscription: constants::DEFAULT_MAX_MONITORED_ITEMS_PER_SUBSCRIPTION,
+            min_sampling_interval: constants::MIN_SAMPLING_INTERVAL,
+            max_publish_requests: constants::DEFAULT_MAX_PUBLISH_REQUESTS,
+            max_browse_continuation_points: constants::MAX_BROWSE_CONTINUATION_POINTS as u32,
+            async_timeout: constants::DEFAULT_ASYNC_TIMEOUT,
+            operational_limits: OperationalLimits::default(),
             clients_can_modify_address_space: false,
         }
     }
 }
 
 impl ServerConfig {
+    fn default_max_sessions() -> u32 { constants::DEFAULT_MAX_SESSIONS }
+
+    fn default_min_session_timeout() -> f64 { constants::MIN_SESSION_TIMEOUT }
+
+    fn default_max_session_timeout() -> f64 { constants::MAX_SESSION_TIMEOUT }
+
+    fn default_max_monitored_items_per_subscription() -> u32 { constants::DEFAULT_MAX_MONITORED_ITEMS_PER_SUBSCRIPTION }
+
+    fn default_min_sampling_interval() -> f64 { constants::MIN_SAMPLING_INTERVAL }
+
+    fn default_max_publish_requests() -> u32 { constants::DEFAULT_MAX_PUBLISH_REQUESTS }
+
+    fn default_max_browse_continuation_points() -> u32 { constants::MAX_BROWSE_CONTINUATION_POINTS as u32 }
+
+    fn default_async_timeout() -> u32 { constants::DEFAULT_ASYNC_TIMEOUT }
+
     pub fn new<T>(application_name: T, user_tokens: BTreeMap<String, ServerUserToken>, endpoints: BTreeMap<String, ServerEndpoint>) -> Self where T: Into<String> {
         let host = "127.0.0.1".to_string();
         let port = constants::DEFAULT_RUST_OPC_UA_SERVER_PORT;
@@ -354,19 +1324,36 @@ impl ServerConfig {
             pki_dir,
             create_sample_keypair: false,
             trust_client_certs: false,
+            certificate_validation: CertificateValidation::default(),
             discovery_server_url,
+            discovery_registration: DiscoveryRegistrationConfig::default(),
             tcp_config: TcpConfig {
                 host,
                 port,
-                hello_timeout: constants::DEFAULT_HELLO_TIMEOUT_SECONDS,
+                ..Default::default()
             },
             user_tokens,
             discovery_urls,
+            reverse_connect_urls: Vec::new(),
+            pubsub_connections: Vec::new(),
+            mqtt_forwarders: Vec::new(),
+            simulated_variables: Vec::new(),
+            aggregated_servers: Vec::new(),
+            rest_gateway: None,
             endpoints,
             max_array_length: opcua_types_constants::MAX_ARRAY_LENGTH as u32,
             max_string_length: opcua_types_constants::MAX_STRING_LENGTH as u32,
             max_byte_string_length: opcua_types_constants::MAX_BYTE_STRING_LENGTH as u32,
+            max_sessions: constants::DEFAULT_MAX_SESSIONS,
+            min_session_timeout: constants::MIN_SESSION_TIMEOUT,
+            max_session_timeout: constants::MAX_SESSION_TIMEOUT,
             max_subscriptions: constants::DEFAULT_MAX_SUBSCRIPTIONS,
+            max_monitored_items_per_subscription: constants::DEFAULT_MAX_MONITORED_ITEMS_PER_SUBSCRIPTION,
+            min_sampling_interval: constants::MIN_SAMPLING_INTERVAL,
+            max_publish_requests: constants::DEFAULT_MAX_PUBLISH_REQUESTS,
+            max_browse_continuation_points: constants::MAX_BROWSE_CONTINUATION_POINTS as u32,
+            async_timeout: constants::DEFAULT_ASYNC_TIMEOUT,
+            operational_limits: OperationalLimits::default(),
             clients_can_modify_address_space: false,
         }
     }
@@ -388,13 +1375,23 @@ impl ServerConfig {
         format!("opc.tcp://{}:{}", self.tcp_config.host, self.tcp_config.port)
     }
 
+    /// Returns the url that the endpoint's path is appended onto, i.e. the opc.tls://server:port
+    /// or opc.https://server:port url for an endpoint on one of those transports, or the base
+    /// endpoint url otherwise
+    pub fn endpoint_base_url(&self, endpoint: &ServerEndpoint) -> String {
+        match (&self.tcp_config.tls, &self.tcp_config.https) {
+            (Some(tls), _) if endpoint.tls => format!("opc.tls://{}:{}", self.tcp_config.host, tls.port),
+            (_, Some(https)) if endpoint.https => format!("opc.https://{}:{}", self.tcp_config.host, https.port),
+            _ => self.base_endpoint_url()
+        }
+    }
+
     /// Find the first endpoint that matches the specified url, security policy and message
     /// security mode.
     pub fn find_endpoint(&self, endpoint_url: &str, security_policy: SecurityPolicy, security_mode: MessageSecurityMode) -> Option<&ServerEndpoint> {
-        let base_endpoint_url = self.base_endpoint_url();
         let endpoint = self.endpoints.iter().find(|&(_, e)| {
             // Test end point's security_policy_uri and matching url
-            if url_matches_except_host(&e.endpoint_url(&base_endpoint_url), endpoint_url) {
+            if url_matches_except_host(&e.endpoint_url(&self.endpoint_base_url(e)), endpoint_url) {
                 if e.security_policy() == security_policy && e.message_security_mode() == security_mode {
                     trace!("Found matching endpoint for url {} - {:?}", endpoint_url, e);
                     true
@@ -0,0 +1,382 @@
+//! Evaluation of content filters, OPC UA Part 4 7.4. Content filters are the where clauses of
+//! event filters and the filters of the QueryFirst service. A filter is evaluated against a
+//! `FilterTarget`, i.e. an event or an instance in the address space, which resolves the
+//! attribute operands of the filter to values.
+
+use std::cmp::Ordering;
+
+use opcua_types::*;
+use opcua_types::status_code::StatusCode;
+use opcua_types::node_ids::ObjectId;
+use opcua_types::service_types::*;
+
+use crate::address_space::{AddressSpace, types::*};
+
+/// Something that a content filter is evaluated against
+pub trait FilterTarget {
+    /// Tests if the target is of the type or one of its subtypes
+    fn is_of_type(&self, type_definition_id: &NodeId) -> bool;
+
+    /// Returns the value of the attribute of the target that the operand refers to, or an empty
+    /// variant if there is no such attribute. The target is of the operand's type.
+    fn attribute_operand(&self, operand: &AttributeOperand) -> Variant;
+
+    /// Returns the value of the attribute of the target that the operand refers to, or an empty
+    /// variant if there is no such attribute. The target is of the operand's type.
+    fn simple_attribute_operand(&self, operand: &SimpleAttributeOperand) -> Variant;
+}
+
+/// A decoded operand of a content filter element
+enum Operand {
+    Literal(Variant),
+    Element(usize),
+    Attribute(AttributeOperand),
+    SimpleAttribute(SimpleAttributeOperand),
+}
+
+impl Operand {
+    fn decode(operand: &ExtensionObject) -> Result<Operand, StatusCode> {
+        let decoding_limits = DecodingLimits::default();
+        let object_id = operand.node_id.as_object_id().map_err(|_| StatusCode::BadFilterOperandInvalid)?;
+        let result = match object_id {
+            ObjectId::LiteralOperand_Encoding_DefaultBinary => {
+                operand.decode_inner::<LiteralOperand>(&decoding_limits).map(|operand| Operand::Literal(operand.value))
+            }
+            ObjectId::ElementOperand_Encoding_DefaultBinary => {
+                operand.decode_inner::<ElementOperand>(&decoding_limits).map(|operand| Operand::Element(operand.index as usize))
+            }
+            ObjectId::AttributeOperand_Encoding_DefaultBinary => {
+                operand.decode_inner::<AttributeOperand>(&decoding_limits).map(Operand::Attribute)
+            }
+            ObjectId::SimpleAttributeOperand_Encoding_DefaultBinary => {
+                operand.decode_inner::<SimpleAttributeOperand>(&decoding_limits).map(Operand::SimpleAttribute)
+            }
+            _ => {
+                return Err(StatusCode::BadFilterOperandInvalid);
+            }
+        };
+        result.map_err(|_| StatusCode::BadFilterOperandInvalid)
+    }
+}
+
+/// A content filter that has been decoded so it can be evaluated against any number of targets.
+///
+/// The evaluator supports all the filter operators except `Cast`, `InView`, `RelatedTo`,
+/// `BitwiseAnd` and `BitwiseOr`. Comparisons between a null operand and anything else are false,
+/// numbers of different types are compared by value, and `Like` compares the text of strings,
+/// qualified names and localized text.
+pub struct ContentFilterEvaluator {
+    elements: Vec<(FilterOperator, Vec<Operand>)>,
+}
+
+impl ContentFilterEvaluator {
+    /// Decodes the elements of the content filter, failing with `BadContentFilterInvalid` if any
+    /// element could not be evaluated. Use `validate_content_filter` to find out what is wrong with
+    /// each element.
+    pub fn new(filter: &ContentFilter) -> Result<ContentFilterEvaluator, StatusCode> {
+        let elements = if let Some(ref elements) = filter.elements {
+            elements.iter().enumerate().map(|(element_idx, element)| {
+                let operands = if let Some(ref operands) = element.filter_operands {
+                    operands.iter().map(Operand::decode).collect::<Result<Vec<Operand>, StatusCode>>()
+                        .map_err(|_| StatusCode::BadContentFilterInvalid)?
+                } else {
+                    Vec::new()
+                };
+                let operands_valid = operand_count(element.filter_operator).map_or(false, |(min, max)| {
+                    operands.len() >= min && operands.len() <= max
+                }) && operands.iter().all(|operand| {
+                    if let Operand::Element(idx) = *operand {
+                        idx > element_idx && idx < elements.len()
+                    } else {
+                        true
+                    }
+                });
+                if operands_valid {
+                    Ok((element.filter_operator, operands))
+                } else {
+                    Err(StatusCode::BadContentFilterInvalid)
+                }
+            }).collect::<Result<Vec<(FilterOperator, Vec<Operand>)>, StatusCode>>()?
+        } else {
+            Vec::new()
+        };
+        Ok(ContentFilterEvaluator { elements })
+    }
+
+    /// Tests if the filter has no elements
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Evaluates the filter against the target. The target passes if the first element evaluates
+    /// to true. An empty filter passes every target.
+    pub fn evaluate<T>(&self, target: &T) -> bool where T: FilterTarget {
+        self.elements.is_empty() || is_true(&self.evaluate_element(target, 0))
+    }
+
+    fn evaluate_element<T>(&self, target: &T, element_idx: usize) -> Variant where T: FilterTarget {
+        let (filter_operator, ref operands) = self.elements[element_idx];
+        let value = |idx: usize| self.operand_value(target, &operands[idx]);
+        match filter_operator {
+            FilterOperator::Equals => Variant::Boolean(compare(&value(0), &value(1)) == Some(Ordering::Equal)),
+            FilterOperator::IsNull => Variant::Boolean(value(0) == Variant::Empty),
+            FilterOperator::GreaterThan => Variant::Boolean(compare(&value(0), &value(1)) == Some(Ordering::Greater)),
+            FilterOperator::LessThan => Variant::Boolean(compare(&value(0), &value(1)) == Some(Ordering::Less)),
+            FilterOperator::GreaterThanOrEqual => {
+                let ordering = compare(&value(0), &value(1));
+                Variant::Boolean(ordering == Some(Ordering::Greater) || ordering == Some(Ordering::Equal))
+            }
+            FilterOperator::LessThanOrEqual => {
+                let ordering = compare(&value(0), &value(1));
+                Variant::Boolean(ordering == Some(Ordering::Less) || ordering == Some(Ordering::Equal))
+            }
+            FilterOperator::Like => {
+                if let (Some(value), Some(pattern)) = (as_string(&value(0)), as_string(&value(1))) {
+                    Variant::Boolean(like(&value, &pattern))
+                } else {
+                    Variant::Boolean(false)
+                }
+            }
+            FilterOperator::Not => {
+                if let Variant::Boolean(value) = value(0) {
+                    Variant::Boolean(!value)
+                } else {
+                    Variant::Empty
+                }
+            }
+            FilterOperator::Between => {
+                let v = value(0);
+                let lower = compare(&v, &value(1));
+                let upper = compare(&v, &value(2));
+                Variant::Boolean((lower == Some(Ordering::Greater) || lower == Some(Ordering::Equal)) &&
+                    (upper == Some(Ordering::Less) || upper == Some(Ordering::Equal)))
+            }
+            FilterOperator::InList => {
+                let v = value(0);
+                Variant::Boolean(operands[1..].iter().any(|operand| compare(&v, &self.operand_value(target, operand)) == Some(Ordering::Equal)))
+            }
+            FilterOperator::And => Variant::Boolean(is_true(&value(0)) && is_true(&value(1))),
+            FilterOperator::Or => Variant::Boolean(is_true(&value(0)) || is_true(&value(1))),
+            FilterOperator::OfType => {
+                if let Variant::NodeId(type_definition_id) = value(0) {
+                    Variant::Boolean(target.is_of_type(&type_definition_id))
+                } else {
+                    Variant::Boolean(false)
+                }
+            }
+            // Rejected when the filter is decoded
+            FilterOperator::Cast | FilterOperator::InView | FilterOperator::RelatedTo |
+            FilterOperator::BitwiseAnd | FilterOperator::BitwiseOr => Variant::Empty,
+        }
+    }
+
+    fn operand_value<T>(&self, target: &T, operand: &Operand) -> Variant where T: FilterTarget {
+        match *operand {
+            Operand::Literal(ref value) => value.clone(),
+            Operand::Element(idx) => self.evaluate_element(target, idx),
+            // An attribute operand is null if the target is not of the operand's type
+            Operand::Attribute(ref operand) => {
+                if operand.node_id.is_null() || target.is_of_type(&operand.node_id) {
+                    target.attribute_operand(operand)
+                } else {
+                    Variant::Empty
+                }
+            }
+            Operand::SimpleAttribute(ref operand) => {
+                if operand.type_definition_id.is_null() || target.is_of_type(&operand.type_definition_id) {
+                    target.simple_attribute_operand(operand)
+                } else {
+                    Variant::Empty
+                }
+            }
+        }
+    }
+}
+
+/// The range of operands that each filter operator takes, or `None` if the operator is unsupported
+fn operand_count(filter_operator: FilterOperator) -> Option<(usize, usize)> {
+    match filter_operator {
+        FilterOperator::IsNull | FilterOperator::Not | FilterOperator::OfType => Some((1, 1)),
+        FilterOperator::Equals | FilterOperator::GreaterThan | FilterOperator::LessThan |
+        FilterOperator::GreaterThanOrEqual | FilterOperator::LessThanOrEqual | FilterOperator::Like |
+        FilterOperator::And | FilterOperator::Or => Some((2, 2)),
+        FilterOperator::Between => Some((3, 3)),
+        FilterOperator::InList => Some((2, usize::max_value())),
+        FilterOperator::Cast | FilterOperator::InView | FilterOperator::RelatedTo |
+        FilterOperator::BitwiseAnd | FilterOperator::BitwiseOr => None,
+    }
+}
+
+/// Tests if the node is an object type or variable type
+pub(crate) fn is_type_node(address_space: &AddressSpace, node_id: &NodeId) -> bool {
+    address_space.find_node(node_id).map_or(false, |node| {
+        match node.as_node().node_class() {
+            NodeClass::ObjectType | NodeClass::VariableType => true,
+            _ => false
+        }
+    })
+}
+
+fn validate_attribute(address_space: &AddressSpace, type_definition_id: &NodeId, attribute_id: u32, index_range: &UAString) -> StatusCode {
+    if AttributeId::from_u32(attribute_id).is_err() {
+        StatusCode::BadAttributeIdInvalid
+    } else if !index_range.is_null() {
+        // Index ranges are not supported
+        StatusCode::BadIndexRangeInvalid
+    } else if !type_definition_id.is_null() && !is_type_node(address_space, type_definition_id) {
+        StatusCode::BadNodeIdUnknown
+    } else {
+        StatusCode::Good
+    }
+}
+
+fn validate_operand(address_space: &AddressSpace, filter_operator: FilterOperator, element_idx: usize, num_elements: usize, operand: &ExtensionObject) -> StatusCode {
+    match Operand::decode(operand) {
+        // The operand of OfType is the id of a type
+        Ok(Operand::Literal(Variant::NodeId(ref type_definition_id))) if filter_operator == FilterOperator::OfType => {
+            if is_type_node(address_space, type_definition_id) {
+                StatusCode::Good
+            } else {
+                StatusCode::BadNodeIdUnknown
+            }
+        }
+        Ok(_) if filter_operator == FilterOperator::OfType => StatusCode::BadFilterOperandInvalid,
+        Ok(Operand::Literal(_)) => StatusCode::Good,
+        // An element operand must refer to an element after its own so the filter has no cycles
+        Ok(Operand::Element(idx)) => if idx > element_idx && idx < num_elements {
+            StatusCode::Good
+        } else {
+            StatusCode::BadFilterOperandInvalid
+        },
+        Ok(Operand::Attribute(ref operand)) => validate_attribute(address_space, &operand.node_id, operand.attribute_id, &operand.index_range),
+        Ok(Operand::SimpleAttribute(ref operand)) => validate_attribute(address_space, &operand.type_definition_id, operand.attribute_id, &operand.index_range),
+        Err(status_code) => status_code
+    }
+}
+
+/// Validates a content filter. If any element is invalid, the error holds a result for each
+/// element saying what is wrong with it.
+pub fn validate_content_filter(address_space: &AddressSpace, filter: &ContentFilter) -> Result<(), ContentFilterResult> {
+    if let Some(ref elements) = filter.elements {
+        let element_results = elements.iter().enumerate().map(|(element_idx, element)| {
+            let operand_status_codes = if let Some(ref operands) = element.filter_operands {
+                operands.iter().map(|operand| validate_operand(address_space, element.filter_operator, element_idx, elements.len(), operand)).collect()
+            } else {
+                Vec::new()
+            };
+            let status_code = if let Some((min, max)) = operand_count(element.filter_operator) {
+                if operand_status_codes.len() < min || operand_status_codes.len() > max {
+                    StatusCode::BadFilterOperandCountMismatch
+                } else if operand_status_codes.iter().any(|status_code| status_code.is_bad()) {
+                    StatusCode::BadFilterOperandInvalid
+                } else {
+                    StatusCode::Good
+                }
+            } else {
+                StatusCode::BadFilterOperatorUnsupported
+            };
+            ContentFilterElementResult {
+                status_code,
+                operand_status_codes: Some(operand_status_codes),
+                operand_diagnostic_infos: None,
+            }
+        }).collect::<Vec<ContentFilterElementResult>>();
+        if element_results.iter().any(|result| result.status_code.is_bad()) {
+            Err(ContentFilterResult {
+                element_results: Some(element_results),
+                element_diagnostic_infos: None,
+            })
+        } else {
+            Ok(())
+        }
+    } else {
+        Ok(())
+    }
+}
+
+fn is_true(value: &Variant) -> bool {
+    *value == Variant::Boolean(true)
+}
+
+/// The text of a string, qualified name or localized text value
+fn as_string(value: &Variant) -> Option<String> {
+    match *value {
+        Variant::String(ref value) if !value.is_null() => Some(value.as_ref().to_string()),
+        Variant::QualifiedName(ref value) => Some(value.name.as_ref().to_string()),
+        Variant::LocalizedText(ref value) => Some(value.text.as_ref().to_string()),
+        _ => None
+    }
+}
+
+/// Compares two values. Numbers of different types are compared by value, other values can only
+/// be ordered against values of the same type.
+fn compare(v1: &Variant, v2: &Variant) -> Option<Ordering> {
+    match (v1, v2) {
+        (Variant::Empty, _) | (_, Variant::Empty) => None,
+        (Variant::String(ref v1), Variant::String(ref v2)) => Some(v1.as_ref().cmp(v2.as_ref())),
+        (Variant::DateTime(ref v1), Variant::DateTime(ref v2)) => Some(v1.ticks().cmp(&v2.ticks())),
+        (Variant::Boolean(v1), Variant::Boolean(v2)) => Some(v1.cmp(v2)),
+        _ => if v1.is_numeric() && v2.is_numeric() {
+            v1.as_f64().and_then(|v1| v2.as_f64().and_then(|v2| v1.partial_cmp(&v2)))
+        } else if v1 == v2 {
+            Some(Ordering::Equal)
+        } else {
+            None
+        }
+    }
+}
+
+/// Matches a string against the pattern of a Like operator, Part 4 Table 117. `%` matches any
+/// number of characters, `_` matches any single character, `[]` matches any of the characters or
+/// ranges it contains, `[^]` matches any character it does not contain and `\` escapes the next
+/// character.
+fn like(value: &str, pattern: &str) -> bool {
+    let value = value.chars().collect::<Vec<char>>();
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    like_chars(&value, &pattern)
+}
+
+fn like_chars(value: &[char], pattern: &[char]) -> bool {
+    if pattern.is_empty() {
+        return value.is_empty();
+    }
+    match pattern[0] {
+        '%' => (0..=value.len()).any(|skip| like_chars(&value[skip..], &pattern[1..])),
+        '_' => !value.is_empty() && like_chars(&value[1..], &pattern[1..]),
+        '[' => {
+            if let Some(end) = pattern.iter().skip(1).position(|c| *c == ']').map(|end| end + 1) {
+                !value.is_empty() && char_list_matches(value[0], &pattern[1..end]) && like_chars(&value[1..], &pattern[end + 1..])
+            } else {
+                // An unterminated list is treated literally
+                !value.is_empty() && value[0] == '[' && like_chars(&value[1..], &pattern[1..])
+            }
+        }
+        '\\' if pattern.len() > 1 => !value.is_empty() && value[0] == pattern[1] && like_chars(&value[1..], &pattern[2..]),
+        c => !value.is_empty() && value[0] == c && like_chars(&value[1..], &pattern[1..]),
+    }
+}
+
+/// Tests if a character matches the contents of a `[]` list, e.g. `abc`, `a-z` or `^0-9`
+fn char_list_matches(c: char, list: &[char]) -> bool {
+    let (negate, list) = if !list.is_empty() && list[0] == '^' {
+        (true, &list[1..])
+    } else {
+        (false, list)
+    };
+    let mut found = false;
+    let mut i = 0;
+    while i < list.len() {
+        if i + 2 < list.len() && list[i + 1] == '-' {
+            if c >= list[i] && c <= list[i + 2] {
+                found = true;
+            }
+            i += 3;
+        } else {
+            if c == list[i] {
+                found = true;
+            }
+            i += 1;
+        }
+    }
+    found != negate
+}
@@ -1,9 +1,10 @@
-//! Provides a browse continuation point type for tracking a browse operation initiated by a client.
+//! Provides continuation point types for tracking browse and history read operations initiated by
+//! a client.
 
 use std::sync::{Arc, Mutex};
 
-use opcua_types::{ByteString, DateTimeUtc};
-use opcua_types::service_types::ReferenceDescription;
+use opcua_types::{ByteString, DataValue, DateTimeUtc, NodeId};
+use opcua_types::service_types::{QueryDataSet, ReferenceDescription};
 
 use crate::prelude::AddressSpace;
 
@@ -22,4 +23,28 @@ impl BrowseContinuationPoint {
     pub fn is_valid_browse_continuation_point(&self, address_space: &AddressSpace) -> bool {
         self.address_space_last_modified >= address_space.last_modified()
     }
-}
\ No newline at end of file
+}
+
+/// Holds the values of a history read that did not fit into the response. The client obtains them
+/// by calling HistoryRead again with the continuation point id.
+#[derive(Clone)]
+pub struct HistoryContinuationPoint {
+    pub id: ByteString,
+    /// The node that the values belong to
+    pub node_id: NodeId,
+    /// The maximum number of values to return in each response
+    pub num_values_per_node: usize,
+    /// The values that have not yet been returned
+    pub values: Vec<DataValue>,
+}
+
+/// Holds the data sets of a query that did not fit into the response. The client obtains them by
+/// calling QueryNext with the continuation point id.
+#[derive(Clone)]
+pub struct QueryContinuationPoint {
+    pub id: ByteString,
+    /// The maximum number of data sets to return in each response
+    pub max_data_sets_to_return: usize,
+    /// The data sets that have not yet been returned
+    pub data_sets: Vec<QueryDataSet>,
+}
@@ -1,6 +1,13 @@
 //! Provides diagnostics structures and functions for gathering information about the running
 //! state of a server.
+use std::collections::BTreeMap;
+
 use opcua_client::prelude::ServerDiagnosticsSummaryDataType;
+use opcua_types::{
+    *,
+    status_code::StatusCode,
+    service_types::{ServiceCounterDataType, SessionDiagnosticsDataType},
+};
 
 use crate::{
     subscriptions::subscription::Subscription,
@@ -12,6 +19,9 @@ use crate::{
 pub struct ServerDiagnostics {
     /// This is a live summary of the server diagnostics
     server_diagnostics_summary: ServerDiagnosticsSummaryDataType,
+    /// Diagnostics of each session, keyed by the session's numeric id
+    #[serde(skip)]
+    session_diagnostics: BTreeMap<u32, SessionDiagnosticsDataType>,
 }
 
 const SERVER_DIAGNOSTICS: &'static str = "ServerDiagnostics";
@@ -21,6 +31,7 @@ impl Default for ServerDiagnostics {
         register_runtime_component!(SERVER_DIAGNOSTICS);
         Self {
             server_diagnostics_summary: ServerDiagnosticsSummaryDataType::default(),
+            session_diagnostics: BTreeMap::new(),
         }
     }
 }
@@ -52,15 +63,165 @@ impl ServerDiagnostics {
         self.server_diagnostics_summary.rejected_session_count += 1;
     }
 
+    /// Returns the diagnostics of each session currently established in the server.
+    pub fn session_diagnostics(&self) -> Vec<SessionDiagnosticsDataType> {
+        self.session_diagnostics.values().cloned().collect()
+    }
+
     /// Increment the number of client sessions currently established in the server.
-    pub(crate) fn on_create_session(&mut self, _session: &Session) {
+    pub(crate) fn on_create_session(&mut self, session: &Session) {
         self.server_diagnostics_summary.current_session_count += 1;
         self.server_diagnostics_summary.cumulated_session_count += 1;
+        if let Some(key) = Self::session_key(session) {
+            let _ = self.session_diagnostics.insert(key, Self::new_session_diagnostics(session));
+        }
     }
 
     /// Decrement the number of client sessions currently established in the server.
-    pub(crate) fn on_destroy_session(&mut self, _session: &Session) {
+    pub(crate) fn on_destroy_session(&mut self, session: &Session) {
         self.server_diagnostics_summary.current_session_count -= 1;
+        if let Some(key) = Self::session_key(session) {
+            let _ = self.session_diagnostics.remove(&key);
+        }
+    }
+
+    /// Records a request processed on the session and the response, if any, that it produced.
+    /// Requests that produce a service fault count as errors and, if the fault is for security
+    /// reasons, as security rejected requests.
+    pub(crate) fn on_request(&mut self, session: &Session, request: &SupportedMessage, response: Option<&SupportedMessage>) {
+        let service_result = match response {
+            Some(SupportedMessage::ServiceFault(ref fault)) => Some(fault.response_header.service_result),
+            _ => None
+        };
+        let security_rejected = match service_result {
+            Some(StatusCode::BadIdentityTokenInvalid) | Some(StatusCode::BadIdentityTokenRejected) |
+            Some(StatusCode::BadUserAccessDenied) | Some(StatusCode::BadSecurityChecksFailed) => true,
+            _ => false
+        };
+        let is_error = service_result.is_some();
+        if is_error {
+            self.server_diagnostics_summary.rejected_requests_count += 1;
+        }
+        if security_rejected {
+            self.server_diagnostics_summary.security_rejected_requests_count += 1;
+        }
+
+        let key = if let Some(key) = Self::session_key(session) { key } else { return; };
+        let session_diagnostics = self.session_diagnostics.entry(key)
+            .or_insert_with(|| Self::new_session_diagnostics(session));
+
+        // Refresh the values which change over the life of the session
+        session_diagnostics.session_name = session.session_name.clone();
+        session_diagnostics.client_description = session.client_description.clone();
+        session_diagnostics.endpoint_url = session.endpoint_url.clone();
+        session_diagnostics.actual_session_timeout = session.session_timeout;
+        session_diagnostics.max_response_message_size = session.max_response_message_size;
+        session_diagnostics.client_last_contact_time = DateTime::now();
+        session_diagnostics.current_subscriptions_count = session.subscriptions.len() as u32;
+        session_diagnostics.current_monitored_items_count = session.subscriptions.monitored_items_len() as u32;
+        session_diagnostics.current_publish_requests_in_queue = session.subscriptions.publish_request_queue_len() as u32;
+
+        Self::count(&mut session_diagnostics.total_request_count, is_error);
+        if security_rejected {
+            session_diagnostics.unauthorized_request_count += 1;
+        }
+        let counter = match *request {
+            SupportedMessage::ReadRequest(_) => &mut session_diagnostics.read_count,
+            SupportedMessage::HistoryReadRequest(_) => &mut session_diagnostics.history_read_count,
+            SupportedMessage::WriteRequest(_) => &mut session_diagnostics.write_count,
+            SupportedMessage::HistoryUpdateRequest(_) => &mut session_diagnostics.history_update_count,
+            SupportedMessage::CallRequest(_) => &mut session_diagnostics.call_count,
+            SupportedMessage::CreateMonitoredItemsRequest(_) => &mut session_diagnostics.create_monitored_items_count,
+            SupportedMessage::ModifyMonitoredItemsRequest(_) => &mut session_diagnostics.modify_monitored_items_count,
+            SupportedMessage::SetMonitoringModeRequest(_) => &mut session_diagnostics.set_monitoring_mode_count,
+            SupportedMessage::SetTriggeringRequest(_) => &mut session_diagnostics.set_triggering_count,
+            SupportedMessage::DeleteMonitoredItemsRequest(_) => &mut session_diagnostics.delete_monitored_items_count,
+            SupportedMessage::CreateSubscriptionRequest(_) => &mut session_diagnostics.create_subscription_count,
+            SupportedMessage::ModifySubscriptionRequest(_) => &mut session_diagnostics.modify_subscription_count,
+            SupportedMessage::SetPublishingModeRequest(_) => &mut session_diagnostics.set_publishing_mode_count,
+            SupportedMessage::PublishRequest(_) => &mut session_diagnostics.publish_count,
+            SupportedMessage::RepublishRequest(_) => &mut session_diagnostics.republish_count,
+            SupportedMessage::TransferSubscriptionsRequest(_) => &mut session_diagnostics.transfer_subscriptions_count,
+            SupportedMessage::DeleteSubscriptionsRequest(_) => &mut session_diagnostics.delete_subscriptions_count,
+            SupportedMessage::AddNodesRequest(_) => &mut session_diagnostics.add_nodes_count,
+            SupportedMessage::AddReferencesRequest(_) => &mut session_diagnostics.add_references_count,
+            SupportedMessage::DeleteNodesRequest(_) => &mut session_diagnostics.delete_nodes_count,
+            SupportedMessage::DeleteReferencesRequest(_) => &mut session_diagnostics.delete_references_count,
+            SupportedMessage::QueryFirstRequest(_) => &mut session_diagnostics.query_first_count,
+            SupportedMessage::QueryNextRequest(_) => &mut session_diagnostics.query_next_count,
+            SupportedMessage::BrowseRequest(_) => &mut session_diagnostics.browse_count,
+            SupportedMessage::BrowseNextRequest(_) => &mut session_diagnostics.browse_next_count,
+            SupportedMessage::TranslateBrowsePathsToNodeIdsRequest(_) => &mut session_diagnostics.translate_browse_paths_to_node_ids_count,
+            SupportedMessage::RegisterNodesRequest(_) => &mut session_diagnostics.register_nodes_count,
+            SupportedMessage::UnregisterNodesRequest(_) => &mut session_diagnostics.unregister_nodes_count,
+            _ => return
+        };
+        Self::count(counter, is_error);
+    }
+
+    fn count(counter: &mut ServiceCounterDataType, is_error: bool) {
+        counter.total_count += 1;
+        if is_error {
+            counter.error_count += 1;
+        }
+    }
+
+    /// Sessions are created with a numeric session id so it serves as the key
+    fn session_key(session: &Session) -> Option<u32> {
+        match session.session_id.identifier {
+            Identifier::Numeric(id) => Some(id),
+            _ => None
+        }
+    }
+
+    fn new_session_diagnostics(session: &Session) -> SessionDiagnosticsDataType {
+        let counter = || ServiceCounterDataType { total_count: 0, error_count: 0 };
+        let now = DateTime::now();
+        SessionDiagnosticsDataType {
+            session_id: session.session_id.clone(),
+            session_name: session.session_name.clone(),
+            client_description: session.client_description.clone(),
+            server_uri: UAString::null(),
+            endpoint_url: session.endpoint_url.clone(),
+            locale_ids: None,
+            actual_session_timeout: session.session_timeout,
+            max_response_message_size: session.max_response_message_size,
+            client_connection_time: now.clone(),
+            client_last_contact_time: now,
+            current_subscriptions_count: 0,
+            current_monitored_items_count: 0,
+            current_publish_requests_in_queue: 0,
+            total_request_count: counter(),
+            unauthorized_request_count: 0,
+            read_count: counter(),
+            history_read_count: counter(),
+            write_count: counter(),
+            history_update_count: counter(),
+            call_count: counter(),
+            create_monitored_items_count: counter(),
+            modify_monitored_items_count: counter(),
+            set_monitoring_mode_count: counter(),
+            set_triggering_count: counter(),
+            delete_monitored_items_count: counter(),
+            create_subscription_count: counter(),
+            modify_subscription_count: counter(),
+            set_publishing_mode_count: counter(),
+            publish_count: counter(),
+            republish_count: counter(),
+            transfer_subscriptions_count: counter(),
+            delete_subscriptions_count: counter(),
+            add_nodes_count: counter(),
+            add_references_count: counter(),
+            delete_nodes_count: counter(),
+            delete_references_count: counter(),
+            browse_count: counter(),
+            browse_next_count: counter(),
+            translate_browse_paths_to_node_ids_count: counter(),
+            query_first_count: counter(),
+            query_next_count: counter(),
+            register_nodes_count: counter(),
+            unregister_nodes_count: counter(),
+        }
     }
 
     /// Increment the number of subscriptions currently established in the server.
@@ -99,22 +260,6 @@ impl ServerDiagnostics {
         self.server_diagnostics_summary.publishing_interval_count += 1;
         unimplemented!()
     }
-
-    /// Increment the number of requests that were rejected due to security constraints since the server was
-    /// started (or restarted). The requests include all Services defined in Part 4, also requests
-    /// to create sessions.
-    pub fn on_security_rejected_request(&mut self) {
-        self.server_diagnostics_summary.security_rejected_requests_count += 1;
-        unimplemented!()
-    }
-
-    /// Increment the number of requests that were rejected since the server was started (or restarted). The
-    /// requests include all Services defined in Part 4, also requests to create sessions. This
-    /// number includes the securityRejectedRequestsCount.
-    pub(crate) fn on_rejected_request(&mut self) {
-        self.server_diagnostics_summary.rejected_requests_count += 1;
-        unimplemented!()
-    }
     */
 }
 
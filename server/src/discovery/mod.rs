@@ -1,21 +1,108 @@
+use std::time::{Duration, Instant};
+
 use opcua_client::prelude::ClientBuilder;
+use opcua_types::{
+    ExtensionObject, UAString,
+    node_ids::ObjectId,
+    service_types::MdnsDiscoveryConfiguration,
+    status_code::StatusCode,
+};
 
-use crate::state::ServerState;
+use crate::{
+    config::DiscoveryRegistrationConfig,
+    state::ServerState,
+};
 
-/// Registers the specified endpoints with the specified discovery server
-pub fn register_with_discovery_server(discovery_server_url: &str, server_state: &ServerState) {
-    debug!("register_with_discovery_server, for {}", discovery_server_url);
+/// Server capability identifier meaning no information is available, used when the server has
+/// not been configured with any capabilities.
+const NO_CAPABILITIES: &str = "NA";
+
+/// Tracks when the server should next register itself with a discovery server. Successful
+/// registrations are repeated every register interval, failed registrations are retried on the
+/// retry interval until the retries are exhausted.
+pub(crate) struct RegistrationSchedule {
+    register_interval: Duration,
+    retry_interval: Duration,
+    max_retries: u32,
+    next_registration: Instant,
+    retries: u32,
+    in_progress: bool,
+}
+
+impl RegistrationSchedule {
+    /// Creates a schedule whose first registration is due immediately
+    pub fn new(config: &DiscoveryRegistrationConfig, now: Instant) -> RegistrationSchedule {
+        RegistrationSchedule {
+            register_interval: Duration::from_secs(config.register_interval as u64),
+            retry_interval: Duration::from_secs(config.retry_interval as u64),
+            max_retries: config.max_retries,
+            next_registration: now,
+            retries: 0,
+            in_progress: false,
+        }
+    }
+
+    /// Tests if a registration should start, i.e. one is due and none is underway
+    pub fn is_due(&self, now: Instant) -> bool {
+        !self.in_progress && now >= self.next_registration
+    }
+
+    pub fn start(&mut self) {
+        self.in_progress = true;
+    }
+
+    /// Records the outcome of a registration and schedules the next one
+    pub fn finish(&mut self, now: Instant, registered: bool) {
+        self.in_progress = false;
+        if registered || self.retries >= self.max_retries {
+            self.retries = 0;
+            self.next_registration = now + self.register_interval;
+        } else {
+            self.retries += 1;
+            self.next_registration = now + self.retry_interval;
+        }
+    }
+
+    pub fn next_registration(&self) -> Instant {
+        self.next_registration
+    }
+}
+
+/// Makes the discovery configuration sent with RegisterServer2, which holds the mDNS server name
+/// and capabilities of the server.
+pub(crate) fn discovery_configuration(server_state: &ServerState) -> Vec<ExtensionObject> {
     let server_config = trace_read_lock_unwrap!(server_state.config);
+    let server_capabilities = if server_config.discovery_registration.server_capabilities.is_empty() {
+        vec![UAString::from(NO_CAPABILITIES)]
+    } else {
+        server_config.discovery_registration.server_capabilities.iter().map(|c| UAString::from(c.as_ref())).collect()
+    };
+    let mdns_discovery_configuration = MdnsDiscoveryConfiguration {
+        mdns_server_name: server_state.application_name.text.clone(),
+        server_capabilities: Some(server_capabilities),
+    };
+    vec![ExtensionObject::from_encodable(ObjectId::MdnsDiscoveryConfiguration_Encoding_DefaultBinary, &mdns_discovery_configuration)]
+}
+
+/// Registers the specified endpoints with the specified discovery server, returning `true` if
+/// registration succeeded. RegisterServer2 is tried first, falling back to RegisterServer for
+/// discovery servers that do not support it.
+pub fn register_with_discovery_server(discovery_server_url: &str, server_state: &ServerState) -> bool {
+    debug!("register_with_discovery_server, for {}", discovery_server_url);
+    let pki_dir = {
+        let server_config = trace_read_lock_unwrap!(server_state.config);
+        server_config.pki_dir.clone()
+    };
 
     // Create a client, ensuring to retry only once
     let client = ClientBuilder::new()
         .application_name("DiscoveryClient")
         .application_uri("urn:DiscoveryClient")
-        .pki_dir(server_config.pki_dir.clone())
+        .pki_dir(pki_dir)
         .session_retry_limit(1)
         .client();
 
-    if let Some(mut client) = client {
+    let registered = if let Some(mut client) = client {
         // This follows the local discovery process described in part 12 of the spec, calling
         // find_servers on it first.
 
@@ -25,24 +112,40 @@ pub fn register_with_discovery_server(discovery_server_url: &str, server_state:
                 debug!("Servers on the discovery endpoint - {:?}", servers);
                 // Register the server
                 let registered_server = server_state.registered_server();
-                match client.register_server(discovery_server_url, registered_server) {
-                    Ok(_) => {}
+                let discovery_configuration = discovery_configuration(server_state);
+                let result = match client.register_server2(discovery_server_url, registered_server.clone(), Some(discovery_configuration)) {
+                    Ok(configuration_results) => {
+                        debug!("Discovery configuration results - {:?}", configuration_results);
+                        Ok(())
+                    }
+                    Err(StatusCode::BadServiceUnsupported) => {
+                        debug!("Discovery server does not support RegisterServer2, so falling back to RegisterServer");
+                        client.register_server(discovery_server_url, registered_server)
+                    }
+                    Err(err) => Err(err)
+                };
+                match result {
+                    Ok(_) => true,
                     Err(err) => {
                         error!(r#"Cannot register server with discovery server \"{}\".
 The errors immediately preceding this message may be caused by this issue.
 Check if the error "{}" indicates the reason why that the registration could not happen.
 The first thing you should ensure is that your server can connect to the discovery server and your
 server's cert is trusted by the discovery server and vice versa."#, discovery_server_url, err);
+                        false
                     }
                 }
             }
             Err(err) => {
                 error!("Cannot find servers on discovery url {}, error = {:?}", discovery_server_url, err);
+                false
             }
         }
     } else {
         error!("Cannot create a discovery server client config");
-    }
+        false
+    };
 
     debug!("register_with_discovery_server, finished");
+    registered
 }
@@ -0,0 +1,195 @@
+//! Provides the audit events that the server raises for security related operations, such as
+//! creating and activating sessions, rejecting certificates, writing values and calling methods.
+//!
+//! Audit events are raised on the `Server` object so clients monitoring it receive them, and are
+//! passed to the `AuditLog` registered with `ServerState::set_audit_log()` so a server
+//! implementation can persist an audit trail.
+
+use opcua_core::crypto::X509;
+use opcua_types::{
+    *,
+    node_ids::{ObjectId, ObjectTypeId},
+    status_code::StatusCode,
+    service_types::*,
+};
+
+use crate::{
+    events::event::Event,
+    session::Session,
+};
+
+/// Severity of an audit event for an operation which succeeded
+const AUDIT_SEVERITY_SUCCESS: u16 = 100;
+/// Severity of an audit event for an operation which failed
+const AUDIT_SEVERITY_FAILURE: u16 = 500;
+
+/// Creates an event of an audit event type from the `Server` object, filling in the fields of
+/// `AuditEventType`. The action name is used for the source name, e.g. `"Session/CreateSession"`.
+pub fn audit_event<T>(event_type: T, action: &str, status: bool, server_id: &UAString, request_header: &RequestHeader, client_user_id: &UAString) -> Event where T: Into<NodeId> {
+    let (message, severity) = if status {
+        (format!("{} succeeded", action), AUDIT_SEVERITY_SUCCESS)
+    } else {
+        (format!("{} failed", action), AUDIT_SEVERITY_FAILURE)
+    };
+    let mut event = Event::new(event_type, ObjectId::Server, action, LocalizedText::new("", &message), severity);
+    event.set_field("ActionTimeStamp", event.time.clone());
+    event.set_field("Status", status);
+    event.set_field("ServerId", server_id.clone());
+    event.set_field("ClientAuditEntryId", request_header.audit_entry_id.clone());
+    event.set_field("ClientUserId", client_user_id.clone());
+    event
+}
+
+/// Creates the audit events for a request processed on a session, or returns an empty list if the
+/// request is not audited. The response is `None` when the request has no immediate response,
+/// otherwise a service fault means the operation failed.
+pub fn audit_request(server_id: &UAString, session: &Session, request: &SupportedMessage, response: Option<&SupportedMessage>) -> Vec<Event> {
+    let service_result = match response {
+        Some(SupportedMessage::ServiceFault(ref fault)) => fault.response_header.service_result,
+        _ => StatusCode::Good
+    };
+    let status = service_result.is_good();
+    let client_user_id = client_user_id(session);
+    match *request {
+        SupportedMessage::CreateSessionRequest(ref request) => {
+            let mut event = audit_event(ObjectTypeId::AuditCreateSessionEventType, "Session/CreateSession", status, server_id, &request.request_header, &client_user_id);
+            event.set_field("SessionId", session.session_id.clone());
+            event.set_field("SecureChannelId", UAString::from(secure_channel_id(session)));
+            event.set_field("ClientCertificate", request.client_certificate.clone());
+            let thumbprint = X509::from_byte_string(&request.client_certificate)
+                .map(|cert| UAString::from(cert.thumbprint().as_hex_string()))
+                .unwrap_or_else(|_| UAString::null());
+            event.set_field("ClientCertificateThumbprint", thumbprint);
+            event.set_field("RevisedSessionTimeout", session.session_timeout);
+
+            // A rejected certificate is audited in its own right
+            if let Some(event_type) = certificate_event_type(service_result) {
+                let mut certificate_event = audit_event(event_type, "Session/CreateSession", false, server_id, &request.request_header, &client_user_id);
+                certificate_event.set_field("Certificate", request.client_certificate.clone());
+                vec![event, certificate_event]
+            } else {
+                vec![event]
+            }
+        }
+        SupportedMessage::ActivateSessionRequest(ref request) => {
+            let mut event = audit_event(ObjectTypeId::AuditActivateSessionEventType, "Session/ActivateSession", status, server_id, &request.request_header, &client_user_id);
+            event.set_field("SessionId", session.session_id.clone());
+            event.set_field("SecureChannelId", UAString::from(secure_channel_id(session)));
+            event.set_field("UserIdentityToken", request.user_identity_token.clone());
+            vec![event]
+        }
+        SupportedMessage::CloseSessionRequest(ref request) => {
+            let mut event = audit_event(ObjectTypeId::AuditSessionEventType, "Session/CloseSession", status, server_id, &request.request_header, &client_user_id);
+            event.set_field("SessionId", session.session_id.clone());
+            vec![event]
+        }
+        SupportedMessage::WriteRequest(ref request) => {
+            let results = match response {
+                Some(SupportedMessage::WriteResponse(ref response)) => response.results.as_ref(),
+                _ => None
+            };
+            let nodes_to_write = request.nodes_to_write.as_ref().map(|n| n.as_slice()).unwrap_or(&[]);
+            nodes_to_write.iter().enumerate().map(|(idx, node_to_write)| {
+                let status = status && results.and_then(|r| r.get(idx)).map(|r| r.is_good()).unwrap_or(false);
+                let mut event = audit_event(ObjectTypeId::AuditWriteUpdateEventType, "Attribute/Write", status, server_id, &request.request_header, &client_user_id);
+                event.set_field("AttributeId", node_to_write.attribute_id);
+                event.set_field("IndexRange", node_to_write.index_range.clone());
+                event.set_field("NewValue", node_to_write.value.value.clone().unwrap_or(Variant::Empty));
+                event.source_node = node_to_write.node_id.clone();
+                event
+            }).collect()
+        }
+        SupportedMessage::CallRequest(ref request) => {
+            let results = match response {
+                Some(SupportedMessage::CallResponse(ref response)) => response.results.as_ref(),
+                _ => None
+            };
+            let methods_to_call = request.methods_to_call.as_ref().map(|m| m.as_slice()).unwrap_or(&[]);
+            methods_to_call.iter().enumerate().map(|(idx, method_to_call)| {
+                let status = status && results.and_then(|r| r.get(idx)).map(|r| r.status_code.is_good()).unwrap_or(false);
+                let mut event = audit_event(ObjectTypeId::AuditUpdateMethodEventType, "Method/Call", status, server_id, &request.request_header, &client_user_id);
+                event.set_field("MethodId", method_to_call.method_id.clone());
+                event.set_field("InputArguments", Variant::Array(method_to_call.input_arguments.clone().unwrap_or_default()));
+                event.source_node = method_to_call.object_id.clone();
+                event
+            }).collect()
+        }
+        _ => {
+            // Any request whose authentication token or access token is rejected is audited,
+            // including the requests that are handled under a read lock such as Read and Browse
+            match (token_rejected(service_result), request_header(request)) {
+                (true, Some(request_header)) => {
+                    let mut event = audit_event(ObjectTypeId::AuditSessionEventType, "Session/ValidateRequest", false, server_id, request_header, &client_user_id);
+                    event.set_field("SessionId", session.session_id.clone());
+                    vec![event]
+                }
+                _ => Vec::new()
+            }
+        }
+    }
+}
+
+/// Tests if the status is the service result of a request whose authentication token or
+/// session-less access token was rejected
+fn token_rejected(status_code: StatusCode) -> bool {
+    match status_code {
+        StatusCode::BadIdentityTokenRejected | StatusCode::BadIdentityTokenInvalid => true,
+        _ => false
+    }
+}
+
+macro_rules! request_header {
+    ($request: expr, $( $message_type: ident ),*) => {
+        match *$request {
+            $( SupportedMessage::$message_type(ref request) => Some(&request.request_header), )*
+            _ => None
+        }
+    }
+}
+
+/// Returns the header of a request handled on a session, or `None` if the message is not one
+fn request_header(request: &SupportedMessage) -> Option<&RequestHeader> {
+    request_header!(request,
+        CancelRequest, AddNodesRequest, AddReferencesRequest, DeleteNodesRequest, DeleteReferencesRequest,
+        BrowseRequest, BrowseNextRequest, TranslateBrowsePathsToNodeIdsRequest, RegisterNodesRequest,
+        UnregisterNodesRequest, QueryFirstRequest, QueryNextRequest, ReadRequest, HistoryReadRequest,
+        HistoryUpdateRequest, CreateMonitoredItemsRequest, ModifyMonitoredItemsRequest,
+        SetMonitoringModeRequest, SetTriggeringRequest, DeleteMonitoredItemsRequest,
+        CreateSubscriptionRequest, ModifySubscriptionRequest, SetPublishingModeRequest, PublishRequest,
+        RepublishRequest, TransferSubscriptionsRequest, DeleteSubscriptionsRequest)
+}
+
+/// Returns the type of certificate audit event for the reason a certificate was rejected, or
+/// `None` if the status is not a certificate error.
+fn certificate_event_type(status_code: StatusCode) -> Option<ObjectTypeId> {
+    match status_code {
+        StatusCode::BadCertificateTimeInvalid | StatusCode::BadCertificateIssuerTimeInvalid => Some(ObjectTypeId::AuditCertificateExpiredEventType),
+        StatusCode::BadCertificateUntrusted => Some(ObjectTypeId::AuditCertificateUntrustedEventType),
+        StatusCode::BadCertificateRevoked | StatusCode::BadCertificateIssuerRevoked |
+        StatusCode::BadCertificateRevocationUnknown | StatusCode::BadCertificateIssuerRevocationUnknown => Some(ObjectTypeId::AuditCertificateRevokedEventType),
+        StatusCode::BadCertificateInvalid | StatusCode::BadCertificateHostNameInvalid |
+        StatusCode::BadCertificateUriInvalid | StatusCode::BadCertificateUseNotAllowed |
+        StatusCode::BadCertificateIssuerUseNotAllowed | StatusCode::BadSecurityChecksFailed => Some(ObjectTypeId::AuditCertificateInvalidEventType),
+        _ => None
+    }
+}
+
+fn secure_channel_id(session: &Session) -> String {
+    let secure_channel = trace_read_lock_unwrap!(session.secure_channel);
+    format!("{}", secure_channel.secure_channel_id())
+}
+
+/// The user of the session, which is the user name of a user name identity token. Other kinds of
+/// identity have no user name.
+fn client_user_id(session: &Session) -> UAString {
+    if let Some(ref user_identity) = session.user_identity {
+        if let Ok(object_id) = user_identity.node_id.as_object_id() {
+            if object_id == ObjectId::UserNameIdentityToken_Encoding_DefaultBinary {
+                if let Ok(token) = user_identity.decode_inner::<UserNameIdentityToken>(&DecodingLimits::default()) {
+                    return token.user_name;
+                }
+            }
+        }
+    }
+    UAString::null()
+}
@@ -0,0 +1,386 @@
+//! Provides the `Condition` type which holds the state of a condition or alarm. Conditions are
+//! added to the address space with `AddressSpace::add_condition()` and every change of state is
+//! reported to clients as an event of the condition's type.
+
+use opcua_types::{
+    *,
+    node_ids::ObjectTypeId,
+    status_code::StatusCode,
+};
+
+use crate::events::event::Event;
+
+/// The limit that a limit alarm's input value has exceeded
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LimitState {
+    HighHigh,
+    High,
+    Low,
+    LowLow,
+}
+
+impl LimitState {
+    /// The browse name of the state, which is also the prefix of its state variable in a
+    /// non-exclusive limit alarm, e.g. `"HighHighState"`
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LimitState::HighHigh => "HighHigh",
+            LimitState::High => "High",
+            LimitState::Low => "Low",
+            LimitState::LowLow => "LowLow",
+        }
+    }
+}
+
+/// The limits of a limit alarm. A limit that is `None` is never exceeded.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AlarmLimits {
+    pub high_high: Option<f64>,
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    pub low_low: Option<f64>,
+}
+
+impl AlarmLimits {
+    /// Returns the limits that the value exceeds, most severe first
+    pub fn exceeded(&self, value: f64) -> Vec<LimitState> {
+        let mut result = Vec::with_capacity(2);
+        if self.high_high.map(|limit| value >= limit).unwrap_or(false) {
+            result.push(LimitState::HighHigh);
+        }
+        if self.high.map(|limit| value >= limit).unwrap_or(false) {
+            result.push(LimitState::High);
+        }
+        if self.low_low.map(|limit| value <= limit).unwrap_or(false) {
+            result.push(LimitState::LowLow);
+        }
+        if self.low.map(|limit| value <= limit).unwrap_or(false) {
+            result.push(LimitState::Low);
+        }
+        result
+    }
+}
+
+/// The kind of condition, which determines its event type and the states that it has
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConditionKind {
+    /// `AcknowledgeableConditionType` - can be acknowledged and confirmed by a client
+    Acknowledgeable,
+    /// `AlarmConditionType` - an acknowledgeable condition that is made active by the application
+    Alarm,
+    /// `ExclusiveLimitAlarmType` - an alarm that is active while its input exceeds a limit and
+    /// which is in one limit state at a time
+    ExclusiveLimitAlarm(AlarmLimits),
+    /// `NonExclusiveLimitAlarmType` - an alarm that is active while its input exceeds a limit and
+    /// which is in every limit state that is exceeded
+    NonExclusiveLimitAlarm(AlarmLimits),
+}
+
+impl ConditionKind {
+    /// The event type of the condition
+    pub fn event_type(&self) -> ObjectTypeId {
+        match *self {
+            ConditionKind::Acknowledgeable => ObjectTypeId::AcknowledgeableConditionType,
+            ConditionKind::Alarm => ObjectTypeId::AlarmConditionType,
+            ConditionKind::ExclusiveLimitAlarm(_) => ObjectTypeId::ExclusiveLimitAlarmType,
+            ConditionKind::NonExclusiveLimitAlarm(_) => ObjectTypeId::NonExclusiveLimitAlarmType,
+        }
+    }
+
+    /// Test if the condition has an active state
+    pub fn is_alarm(&self) -> bool {
+        if let ConditionKind::Acknowledgeable = *self { false } else { true }
+    }
+
+    fn limits(&self) -> Option<&AlarmLimits> {
+        match *self {
+            ConditionKind::ExclusiveLimitAlarm(ref limits) | ConditionKind::NonExclusiveLimitAlarm(ref limits) => Some(limits),
+            _ => None
+        }
+    }
+}
+
+/// The state of a condition. The functions which change state follow the state machines of
+/// OPC UA Part 9 and return the status code that the corresponding method returns to a client
+/// when the change is not allowed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    /// The node id of the condition object in the address space
+    condition_id: NodeId,
+    /// The kind of condition
+    kind: ConditionKind,
+    /// The node that the condition belongs to
+    source_node: NodeId,
+    /// A description of the source of the condition
+    source_name: UAString,
+    /// The name of the condition
+    condition_name: UAString,
+    /// The event id of the most recent event for the condition
+    event_id: ByteString,
+    enabled: bool,
+    acked: bool,
+    confirmed: bool,
+    active: bool,
+    /// The limits exceeded by a limit alarm, most severe first
+    limit_states: Vec<LimitState>,
+    severity: u16,
+    last_severity: u16,
+    message: LocalizedText,
+    comment: LocalizedText,
+    quality: StatusCode,
+}
+
+impl Condition {
+    /// Creates an enabled condition of the specified kind on a source node. A condition starts
+    /// inactive, acknowledged and confirmed.
+    pub fn new<C, S, N, M>(kind: ConditionKind, condition_id: C, source_node: S, source_name: N, condition_name: M) -> Condition
+        where C: Into<NodeId>, S: Into<NodeId>, N: Into<UAString>, M: Into<UAString> {
+        Condition {
+            condition_id: condition_id.into(),
+            kind,
+            source_node: source_node.into(),
+            source_name: source_name.into(),
+            condition_name: condition_name.into(),
+            event_id: ByteString::null(),
+            enabled: true,
+            acked: true,
+            confirmed: true,
+            active: false,
+            limit_states: Vec::new(),
+            severity: 0,
+            last_severity: 0,
+            message: LocalizedText::null(),
+            comment: LocalizedText::null(),
+            quality: StatusCode::Good,
+        }
+    }
+
+    /// Creates an acknowledgeable condition
+    pub fn new_acknowledgeable<C, S, N, M>(condition_id: C, source_node: S, source_name: N, condition_name: M) -> Condition
+        where C: Into<NodeId>, S: Into<NodeId>, N: Into<UAString>, M: Into<UAString> {
+        Self::new(ConditionKind::Acknowledgeable, condition_id, source_node, source_name, condition_name)
+    }
+
+    /// Creates an alarm
+    pub fn new_alarm<C, S, N, M>(condition_id: C, source_node: S, source_name: N, condition_name: M) -> Condition
+        where C: Into<NodeId>, S: Into<NodeId>, N: Into<UAString>, M: Into<UAString> {
+        Self::new(ConditionKind::Alarm, condition_id, source_node, source_name, condition_name)
+    }
+
+    /// Creates a limit alarm. An exclusive limit alarm is only in the most severe of the limit
+    /// states that its input exceeds, a non-exclusive one is in all of them.
+    pub fn new_limit_alarm<C, S, N, M>(condition_id: C, source_node: S, source_name: N, condition_name: M, limits: AlarmLimits, exclusive: bool) -> Condition
+        where C: Into<NodeId>, S: Into<NodeId>, N: Into<UAString>, M: Into<UAString> {
+        let kind = if exclusive { ConditionKind::ExclusiveLimitAlarm(limits) } else { ConditionKind::NonExclusiveLimitAlarm(limits) };
+        Self::new(kind, condition_id, source_node, source_name, condition_name)
+    }
+
+    pub fn condition_id(&self) -> &NodeId { &self.condition_id }
+
+    pub fn kind(&self) -> &ConditionKind { &self.kind }
+
+    pub fn source_node(&self) -> &NodeId { &self.source_node }
+
+    pub fn condition_name(&self) -> &UAString { &self.condition_name }
+
+    /// The event id of the most recent event raised for the condition
+    pub fn event_id(&self) -> &ByteString { &self.event_id }
+
+    pub fn is_enabled(&self) -> bool { self.enabled }
+
+    pub fn is_acked(&self) -> bool { self.acked }
+
+    pub fn is_confirmed(&self) -> bool { self.confirmed }
+
+    pub fn is_active(&self) -> bool { self.active }
+
+    pub fn limit_states(&self) -> &[LimitState] { &self.limit_states }
+
+    pub fn severity(&self) -> u16 { self.severity }
+
+    /// A condition is retained, i.e. it is of interest to a client refreshing its conditions,
+    /// while it is enabled and is either active or waiting to be acknowledged.
+    pub fn retain(&self) -> bool {
+        self.enabled && (self.active || !self.acked)
+    }
+
+    /// Sets the severity and message that are reported by the next event
+    pub fn set_severity<M>(&mut self, severity: u16, message: M) where M: Into<LocalizedText> {
+        self.last_severity = self.severity;
+        self.severity = severity;
+        self.message = message.into();
+    }
+
+    /// Sets the quality of the condition's source
+    pub fn set_quality(&mut self, quality: StatusCode) {
+        self.quality = quality;
+    }
+
+    pub fn enable(&mut self) -> Result<(), StatusCode> {
+        if self.enabled {
+            Err(StatusCode::BadConditionAlreadyEnabled)
+        } else {
+            self.enabled = true;
+            Ok(())
+        }
+    }
+
+    pub fn disable(&mut self) -> Result<(), StatusCode> {
+        if !self.enabled {
+            Err(StatusCode::BadConditionAlreadyDisabled)
+        } else {
+            self.enabled = false;
+            Ok(())
+        }
+    }
+
+    /// Adds a comment to the condition. The event id must be that of the condition's most
+    /// recent event.
+    pub fn add_comment(&mut self, event_id: &ByteString, comment: LocalizedText) -> Result<(), StatusCode> {
+        self.check_event_id(event_id)?;
+        self.comment = comment;
+        Ok(())
+    }
+
+    /// Acknowledges the condition with an optional comment. The event id must be that of the
+    /// condition's most recent event.
+    pub fn acknowledge(&mut self, event_id: &ByteString, comment: LocalizedText) -> Result<(), StatusCode> {
+        self.check_event_id(event_id)?;
+        if self.acked {
+            Err(StatusCode::BadConditionBranchAlreadyAcked)
+        } else {
+            self.acked = true;
+            self.set_comment(comment);
+            Ok(())
+        }
+    }
+
+    /// Confirms the condition with an optional comment. The event id must be that of the
+    /// condition's most recent event.
+    pub fn confirm(&mut self, event_id: &ByteString, comment: LocalizedText) -> Result<(), StatusCode> {
+        self.check_event_id(event_id)?;
+        if self.confirmed {
+            Err(StatusCode::BadConditionBranchAlreadyConfirmed)
+        } else {
+            self.confirmed = true;
+            self.set_comment(comment);
+            Ok(())
+        }
+    }
+
+    /// Makes an alarm active or inactive. When an alarm becomes active it must be acknowledged
+    /// and confirmed again.
+    pub fn set_active(&mut self, active: bool) -> Result<(), StatusCode> {
+        if !self.kind.is_alarm() {
+            Err(StatusCode::BadNotSupported)
+        } else if !self.enabled {
+            Err(StatusCode::BadConditionDisabled)
+        } else {
+            if active && !self.active {
+                self.acked = false;
+                self.confirmed = false;
+            }
+            self.active = active;
+            Ok(())
+        }
+    }
+
+    /// Sets the input value of a limit alarm. The alarm is active while the value exceeds any of
+    /// its limits. The function returns true if the limit states changed.
+    pub fn set_input_value(&mut self, value: f64) -> Result<bool, StatusCode> {
+        let limit_states = if let Some(limits) = self.kind.limits() {
+            let mut limit_states = limits.exceeded(value);
+            if let ConditionKind::ExclusiveLimitAlarm(_) = self.kind {
+                limit_states.truncate(1);
+            }
+            limit_states
+        } else {
+            return Err(StatusCode::BadNotSupported);
+        };
+        if limit_states == self.limit_states {
+            Ok(false)
+        } else {
+            self.set_active(!limit_states.is_empty())?;
+            self.limit_states = limit_states;
+            Ok(true)
+        }
+    }
+
+    /// Creates an event describing the current state of the condition, giving it a new event id
+    pub(crate) fn new_event(&mut self) -> Event {
+        let event = self.make_event(ByteString::random(16));
+        self.event_id = event.event_id.clone();
+        event
+    }
+
+    /// Creates an event describing the current state of the condition with the event id of its
+    /// most recent event. This is used to refresh clients.
+    pub(crate) fn refresh_event(&self) -> Event {
+        self.make_event(self.event_id.clone())
+    }
+
+    fn check_event_id(&self, event_id: &ByteString) -> Result<(), StatusCode> {
+        if !self.enabled {
+            Err(StatusCode::BadConditionDisabled)
+        } else if self.event_id.is_null() || *event_id != self.event_id {
+            Err(StatusCode::BadEventIdUnknown)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn set_comment(&mut self, comment: LocalizedText) {
+        if !comment.text.is_empty() {
+            self.comment = comment;
+        }
+    }
+
+    fn make_event(&self, event_id: ByteString) -> Event {
+        let mut event = Event::new(self.kind.event_type(), self.source_node.clone(), self.source_name.clone(), self.message.clone(), self.severity);
+        event.event_id = event_id;
+        event.set_field("ConditionId", self.condition_id.clone());
+        event.set_field("ConditionName", self.condition_name.clone());
+        event.set_field("BranchId", NodeId::null());
+        event.set_field("Retain", self.retain());
+        event.set_field("Quality", self.quality);
+        event.set_field("LastSeverity", self.last_severity);
+        event.set_field("Comment", self.comment.clone());
+        Self::set_state_fields(&mut event, "EnabledState", self.enabled, "Enabled", "Disabled");
+        Self::set_state_fields(&mut event, "AckedState", self.acked, "Acknowledged", "Unacknowledged");
+        Self::set_state_fields(&mut event, "ConfirmedState", self.confirmed, "Confirmed", "Unconfirmed");
+        if self.kind.is_alarm() {
+            Self::set_state_fields(&mut event, "ActiveState", self.active, "Active", "Inactive");
+        }
+        match self.kind {
+            ConditionKind::ExclusiveLimitAlarm(ref limits) => {
+                Self::set_limit_fields(&mut event, limits);
+                let current_state = self.limit_states.first().map(|s| LocalizedText::new("", s.name())).unwrap_or_else(|| LocalizedText::null());
+                event.set_field("LimitState/CurrentState", current_state);
+            }
+            ConditionKind::NonExclusiveLimitAlarm(ref limits) => {
+                Self::set_limit_fields(&mut event, limits);
+                [LimitState::HighHigh, LimitState::High, LimitState::Low, LimitState::LowLow].iter().for_each(|s| {
+                    let state = format!("{}State", s.name());
+                    let name = s.name();
+                    Self::set_state_fields(&mut event, &state, self.limit_states.contains(s), name, "");
+                });
+            }
+            _ => {}
+        }
+        event
+    }
+
+    /// Sets the text and id of a two state variable, e.g. `"AckedState"` and `"AckedState/Id"`
+    fn set_state_fields(event: &mut Event, state: &str, value: bool, true_state: &str, false_state: &str) {
+        let text = if value { true_state } else { false_state };
+        event.set_field(state, LocalizedText::new("", text));
+        event.set_field(format!("{}/Id", state), value);
+    }
+
+    fn set_limit_fields(event: &mut Event, limits: &AlarmLimits) {
+        if let Some(limit) = limits.high_high { event.set_field("HighHighLimit", limit); }
+        if let Some(limit) = limits.high { event.set_field("HighLimit", limit); }
+        if let Some(limit) = limits.low { event.set_field("LowLimit", limit); }
+        if let Some(limit) = limits.low_low { event.set_field("LowLowLimit", limit); }
+    }
+}
@@ -0,0 +1,135 @@
+//! Provides the `Event` type which holds the fields of an event.
+
+use std::collections::HashMap;
+
+use opcua_types::{
+    *,
+    node_ids::ObjectTypeId,
+    service_types::{AttributeOperand, EventFieldList, SimpleAttributeOperand},
+};
+
+use crate::{
+    address_space::AddressSpace,
+    content_filter::FilterTarget,
+};
+
+/// An event. The standard fields of `BaseEventType` are held as members and the fields of any
+/// subtype are held by their browse path, e.g. `"EnabledState/Id"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Uniquely identifies this event
+    pub event_id: ByteString,
+    /// The type of event, `BaseEventType` or a subtype of it
+    pub event_type: NodeId,
+    /// The node that the event originated from
+    pub source_node: NodeId,
+    /// A description of the source of the event
+    pub source_name: UAString,
+    /// The time that the event occurred
+    pub time: DateTime,
+    /// The time that the server received the event
+    pub receive_time: DateTime,
+    /// A human readable description of the event
+    pub message: LocalizedText,
+    /// The urgency of the event from 1 (lowest) to 1000 (highest)
+    pub severity: u16,
+    /// Fields that are not part of `BaseEventType`, keyed by browse path
+    fields: HashMap<String, Variant>,
+}
+
+impl Event {
+    /// Creates an event of the specified type from a source node. The event is given a unique
+    /// id and the current time.
+    pub fn new<T, S, N, M>(event_type: T, source_node: S, source_name: N, message: M, severity: u16) -> Event
+        where T: Into<NodeId>, S: Into<NodeId>, N: Into<UAString>, M: Into<LocalizedText> {
+        let now = DateTime::now();
+        Event {
+            event_id: ByteString::random(16),
+            event_type: event_type.into(),
+            source_node: source_node.into(),
+            source_name: source_name.into(),
+            time: now.clone(),
+            receive_time: now,
+            message: message.into(),
+            severity,
+            fields: HashMap::new(),
+        }
+    }
+
+    /// Creates an event of `BaseEventType`
+    pub fn new_base_event<S, N, M>(source_node: S, source_name: N, message: M, severity: u16) -> Event
+        where S: Into<NodeId>, N: Into<UAString>, M: Into<LocalizedText> {
+        Self::new(ObjectTypeId::BaseEventType, source_node, source_name, message, severity)
+    }
+
+    /// Sets a field that is not part of `BaseEventType`. The browse path is the browse names from
+    /// the event type to the field separated by `/`, e.g. `"EnabledState/Id"`.
+    pub fn set_field<P, V>(&mut self, browse_path: P, value: V) where P: Into<String>, V: Into<Variant> {
+        self.fields.insert(browse_path.into(), value.into());
+    }
+
+    /// Gets the value of a field by its browse path, or `None` if the event has no such field.
+    pub fn field(&self, browse_path: &str) -> Option<Variant> {
+        match browse_path {
+            "EventId" => Some(Variant::from(self.event_id.clone())),
+            "EventType" => Some(Variant::from(self.event_type.clone())),
+            "SourceNode" => Some(Variant::from(self.source_node.clone())),
+            "SourceName" => Some(Variant::from(self.source_name.clone())),
+            "Time" => Some(Variant::from(self.time.clone())),
+            "ReceiveTime" => Some(Variant::from(self.receive_time.clone())),
+            "Message" => Some(Variant::from(self.message.clone())),
+            "Severity" => Some(Variant::from(self.severity)),
+            browse_path => self.fields.get(browse_path).cloned()
+        }
+    }
+
+    /// Resolves the operand of a select clause to the value of a field. Fields that the event
+    /// does not have are returned as an empty variant, as are attributes other than `Value`.
+    /// The exception is the `NodeId` attribute of the event itself, which is how a client
+    /// selects the `ConditionId` of a condition's event.
+    pub(crate) fn select_field(&self, operand: &SimpleAttributeOperand) -> Variant {
+        if operand.attribute_id == AttributeId::NodeId as u32 && is_empty_option_vec!(operand.browse_path) {
+            self.field("ConditionId").unwrap_or(Variant::Empty)
+        } else if operand.attribute_id != AttributeId::Value as u32 {
+            Variant::Empty
+        } else {
+            let browse_path = if let Some(ref browse_path) = operand.browse_path {
+                browse_path.iter().map(|name| name.name.as_ref()).collect::<Vec<&str>>().join("/")
+            } else {
+                String::new()
+            };
+            self.field(&browse_path).unwrap_or(Variant::Empty)
+        }
+    }
+
+    /// Makes the fields of this event that are selected by the select clauses into a notification
+    pub(crate) fn event_field_list(&self, client_handle: u32, select_clauses: &[SimpleAttributeOperand]) -> EventFieldList {
+        let event_fields = select_clauses.iter().map(|operand| self.select_field(operand)).collect();
+        EventFieldList {
+            client_handle,
+            event_fields: Some(event_fields),
+        }
+    }
+}
+
+/// An event that the where clause of an event filter is evaluated against. The event is of the
+/// types that its event type is a subtype of in the address space. Attribute operands are not
+/// allowed in event filters so they are always null.
+pub struct EventTarget<'a> {
+    pub address_space: &'a AddressSpace,
+    pub event: &'a Event,
+}
+
+impl<'a> FilterTarget for EventTarget<'a> {
+    fn is_of_type(&self, type_definition_id: &NodeId) -> bool {
+        self.address_space.is_subtype(&self.event.event_type, type_definition_id)
+    }
+
+    fn attribute_operand(&self, _operand: &AttributeOperand) -> Variant {
+        Variant::Empty
+    }
+
+    fn simple_attribute_operand(&self, operand: &SimpleAttributeOperand) -> Variant {
+        self.event.select_field(operand)
+    }
+}
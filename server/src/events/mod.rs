@@ -0,0 +1,14 @@
+//! Events that are raised by the server or by a server implementation. Events are raised through
+//! `AddressSpace::raise_event()` and delivered to clients through monitored items that have an
+//! `EventFilter` on the `EventNotifier` attribute of an object.
+//!
+//! Conditions and alarms are added with `AddressSpace::add_condition()` and raise an event each
+//! time that their state changes.
+//!
+//! The server raises audit events for security related operations on the `Server` object, and
+//! model change events when nodes or references are added or removed if they are enabled.
+
+pub mod event;
+pub mod condition;
+pub mod audit;
+pub mod model_change;
@@ -0,0 +1,37 @@
+//! Provides the model change events that the address space raises when nodes or references are
+//! added or removed at runtime, so clients which cache the address space know to refresh it.
+//!
+//! Model change events are only raised once they are enabled with
+//! `AddressSpace::set_model_change_events()`.
+
+use opcua_types::{
+    *,
+    node_ids::{ObjectId, ObjectTypeId},
+    service_types::ModelChangeStructureDataType,
+};
+
+use crate::events::event::Event;
+
+/// Verb bit of a change which added the affected node
+pub const MODEL_CHANGE_NODE_ADDED: u8 = 1;
+/// Verb bit of a change which deleted the affected node
+pub const MODEL_CHANGE_NODE_DELETED: u8 = 2;
+/// Verb bit of a change which added a reference to the affected node
+pub const MODEL_CHANGE_REFERENCE_ADDED: u8 = 4;
+/// Verb bit of a change which deleted a reference from the affected node
+pub const MODEL_CHANGE_REFERENCE_DELETED: u8 = 8;
+
+/// Severity of a model change event
+const MODEL_CHANGE_SEVERITY: u16 = 100;
+
+/// Creates a `GeneralModelChangeEventType` event from the `Server` object whose `Changes` field
+/// holds the changes that were made.
+pub fn model_change_event(changes: &[ModelChangeStructureDataType]) -> Event {
+    let message = format!("The address space changed, {} nodes affected", changes.len());
+    let mut event = Event::new(ObjectTypeId::GeneralModelChangeEventType, ObjectId::Server, "Server", LocalizedText::new("", &message), MODEL_CHANGE_SEVERITY);
+    let changes = changes.iter()
+        .map(|change| Variant::from(ExtensionObject::from_encodable(ObjectId::ModelChangeStructureDataType_Encoding_DefaultBinary, change)))
+        .collect::<Vec<Variant>>();
+    event.set_field("Changes", Variant::Array(changes));
+    event
+}
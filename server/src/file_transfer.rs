@@ -0,0 +1,445 @@
+//! File transfer through objects of `FileType`, OPC UA Part 5 Annex C. A file object has the
+//! `Open`, `Close`, `Read`, `Write`, `GetPosition` and `SetPosition` methods and the `Size`,
+//! `Writable`, `UserWritable` and `OpenCount` properties. Its contents come from a `FileProvider`
+//! that the server implementation supplies, e.g. to transfer firmware or recipes to and from a device.
+//!
+//! A file may be opened by any number of readers or by a single writer. Each open returns a file
+//! handle which can only be used by the session that opened it.
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use opcua_types::*;
+use opcua_types::node_ids::{DataTypeId, ReferenceTypeId, VariableTypeId};
+use opcua_types::status_code::StatusCode;
+use opcua_types::service_types::{CallMethodRequest, CallMethodResult};
+
+use crate::{
+    address_space::types::{AddressSpace, ReferenceDirection, Variable},
+    callbacks::Method,
+    server_configuration::argument,
+    session::Session,
+};
+
+bitflags! {
+    /// The mode that a file is opened with, Part 5 C.2.1
+    pub struct FileOpenMode: u8 {
+        const READ = 1;
+        const WRITE = 2;
+        const ERASE_EXISTING = 4;
+        const APPEND = 8;
+    }
+}
+
+/// The contents of an open file. Anything that can be read, written and seeked is a stream, e.g.
+/// a `std::fs::File` or a `std::io::Cursor<Vec<u8>>`.
+pub trait FileStream: Read + Write + Seek + Send + Sync {}
+
+impl<T> FileStream for T where T: Read + Write + Seek + Send + Sync {}
+
+/// Supplies the contents of a file object. A server implementation registers a provider for each
+/// file with `AddressSpace::add_file`.
+pub trait FileProvider: Send + Sync {
+    /// Returns the size of the file in bytes
+    fn size(&self) -> u64;
+
+    /// Returns true if the file may be opened for writing. The default is false.
+    fn writable(&self) -> bool { false }
+
+    /// Opens the file with the mode. The mode has been checked to be a valid combination of flags,
+    /// the file to be writable when the mode contains `WRITE`, and that no other open is in the way.
+    /// A provider must empty the file when the mode contains `ERASE_EXISTING`. The server moves the
+    /// position of the stream to the end when the mode contains `APPEND`.
+    fn open(&mut self, mode: FileOpenMode) -> Result<Box<dyn FileStream>, StatusCode>;
+
+    /// Called when a client closes the file, e.g. so a provider can apply the contents that were
+    /// written. The default does nothing.
+    fn close(&mut self, _mode: FileOpenMode, _stream: Box<dyn FileStream>) -> Result<(), StatusCode> { Ok(()) }
+}
+
+/// A provider for a file on the server's file system
+pub struct PathFileProvider {
+    path: PathBuf,
+    writable: bool,
+}
+
+impl PathFileProvider {
+    /// Creates a provider for the file at the path. A writable file is created by the first open
+    /// for writing if it does not exist.
+    pub fn new<T>(path: T, writable: bool) -> PathFileProvider where T: Into<PathBuf> {
+        PathFileProvider {
+            path: path.into(),
+            writable,
+        }
+    }
+}
+
+impl FileProvider for PathFileProvider {
+    fn size(&self) -> u64 {
+        self.path.metadata().map(|metadata| metadata.len()).unwrap_or(0)
+    }
+
+    fn writable(&self) -> bool {
+        self.writable
+    }
+
+    fn open(&mut self, mode: FileOpenMode) -> Result<Box<dyn FileStream>, StatusCode> {
+        let writing = mode.contains(FileOpenMode::WRITE);
+        let file = OpenOptions::new()
+            .read(mode.contains(FileOpenMode::READ))
+            .write(writing)
+            .create(writing)
+            .truncate(mode.contains(FileOpenMode::ERASE_EXISTING))
+            .open(&self.path)
+            .map_err(|err| {
+                error!("File {} cannot be opened, {}", self.path.display(), err);
+                if writing { StatusCode::BadNotWritable } else { StatusCode::BadNotReadable }
+            })?;
+        Ok(Box::new(file))
+    }
+}
+
+/// A file opened by a session
+struct OpenFile {
+    session_id: NodeId,
+    mode: FileOpenMode,
+    stream: Box<dyn FileStream>,
+}
+
+/// A file object's provider and the handles of the sessions which have it open
+pub(crate) struct File {
+    provider: Box<dyn FileProvider>,
+    open_files: HashMap<u32, OpenFile>,
+    last_file_handle: u32,
+}
+
+impl File {
+    pub fn new(provider: Box<dyn FileProvider>) -> File {
+        File {
+            provider,
+            open_files: HashMap::new(),
+            last_file_handle: 0,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.provider.size()
+    }
+
+    pub fn writable(&self) -> bool {
+        self.provider.writable()
+    }
+
+    pub fn open_count(&self) -> usize {
+        self.open_files.len()
+    }
+
+    fn open_file(&mut self, session_id: &NodeId, file_handle: u32) -> Result<&mut OpenFile, StatusCode> {
+        match self.open_files.get_mut(&file_handle) {
+            Some(open_file) if open_file.session_id == *session_id => Ok(open_file),
+            _ => Err(StatusCode::BadInvalidArgument)
+        }
+    }
+
+    /// Returns the mode that a file handle of the session was opened with
+    pub fn mode(&self, session_id: &NodeId, file_handle: u32) -> Result<FileOpenMode, StatusCode> {
+        match self.open_files.get(&file_handle) {
+            Some(open_file) if open_file.session_id == *session_id => Ok(open_file.mode),
+            _ => Err(StatusCode::BadInvalidArgument)
+        }
+    }
+
+    /// Checks the mode of an open and that the file is not open in a way that prevents it
+    fn check_open(&self, mode: u8) -> Result<FileOpenMode, StatusCode> {
+        let mode = match FileOpenMode::from_bits(mode) {
+            Some(mode) if mode.intersects(FileOpenMode::READ | FileOpenMode::WRITE) => mode,
+            _ => {
+                error!("File cannot be opened with mode {}", mode);
+                return Err(StatusCode::BadInvalidArgument);
+            }
+        };
+        if mode.intersects(FileOpenMode::ERASE_EXISTING | FileOpenMode::APPEND) && !mode.contains(FileOpenMode::WRITE) {
+            error!("File cannot be erased or appended to without being opened for writing");
+            Err(StatusCode::BadInvalidArgument)
+        } else if mode.contains(FileOpenMode::WRITE) && !self.writable() {
+            Err(StatusCode::BadNotWritable)
+        } else if mode.contains(FileOpenMode::WRITE) && !self.open_files.is_empty() {
+            // A writer has the file to itself
+            error!("File cannot be opened for writing while it is open");
+            Err(StatusCode::BadInvalidState)
+        } else if self.open_files.values().any(|open_file| open_file.mode.contains(FileOpenMode::WRITE)) {
+            error!("File cannot be opened while it is open for writing");
+            Err(StatusCode::BadNotReadable)
+        } else {
+            Ok(mode)
+        }
+    }
+
+    /// Opens the file from the provider and returns the handle of the open file
+    pub fn open(&mut self, session_id: &NodeId, mode: u8) -> Result<u32, StatusCode> {
+        let mode = self.check_open(mode)?;
+        let stream = self.provider.open(mode)?;
+        self.insert_open_file(session_id, mode, stream)
+    }
+
+    /// Opens the file with contents that the caller supplies rather than the provider, e.g. for
+    /// an open method which takes more arguments than the mode
+    pub fn open_with_stream(&mut self, session_id: &NodeId, mode: u8, stream: Box<dyn FileStream>) -> Result<u32, StatusCode> {
+        let mode = self.check_open(mode)?;
+        self.insert_open_file(session_id, mode, stream)
+    }
+
+    fn insert_open_file(&mut self, session_id: &NodeId, mode: FileOpenMode, mut stream: Box<dyn FileStream>) -> Result<u32, StatusCode> {
+        if mode.contains(FileOpenMode::APPEND) {
+            stream.seek(SeekFrom::End(0)).map_err(|_| StatusCode::BadUnexpectedError)?;
+        }
+        self.last_file_handle += 1;
+        self.open_files.insert(self.last_file_handle, OpenFile {
+            session_id: session_id.clone(),
+            mode,
+            stream,
+        });
+        Ok(self.last_file_handle)
+    }
+
+    pub fn read(&mut self, session_id: &NodeId, file_handle: u32, length: i32) -> Result<ByteString, StatusCode> {
+        let open_file = self.open_file(session_id, file_handle)?;
+        if !open_file.mode.contains(FileOpenMode::READ) {
+            Err(StatusCode::BadInvalidState)
+        } else if length < 0 {
+            Err(StatusCode::BadInvalidArgument)
+        } else {
+            let mut data = Vec::new();
+            Read::by_ref(&mut open_file.stream).take(length as u64).read_to_end(&mut data).map_err(|err| {
+                error!("File cannot be read, {}", err);
+                StatusCode::BadUnexpectedError
+            })?;
+            Ok(ByteString::from(data))
+        }
+    }
+
+    pub fn write(&mut self, session_id: &NodeId, file_handle: u32, data: &ByteString) -> Result<(), StatusCode> {
+        let open_file = self.open_file(session_id, file_handle)?;
+        if !open_file.mode.contains(FileOpenMode::WRITE) {
+            Err(StatusCode::BadInvalidState)
+        } else {
+            open_file.stream.write_all(data.as_ref()).map_err(|err| {
+                error!("File cannot be written, {}", err);
+                StatusCode::BadUnexpectedError
+            })
+        }
+    }
+
+    pub fn get_position(&mut self, session_id: &NodeId, file_handle: u32) -> Result<u64, StatusCode> {
+        let open_file = self.open_file(session_id, file_handle)?;
+        open_file.stream.seek(SeekFrom::Current(0)).map_err(|_| StatusCode::BadUnexpectedError)
+    }
+
+    pub fn set_position(&mut self, session_id: &NodeId, file_handle: u32, position: u64) -> Result<(), StatusCode> {
+        let open_file = self.open_file(session_id, file_handle)?;
+        // A position past the end of the file is the end of the file
+        let end = open_file.stream.seek(SeekFrom::End(0)).map_err(|_| StatusCode::BadUnexpectedError)?;
+        open_file.stream.seek(SeekFrom::Start(end.min(position))).map_err(|_| StatusCode::BadUnexpectedError)?;
+        Ok(())
+    }
+
+    /// Closes an open file and hands its contents to the provider
+    pub fn close(&mut self, session_id: &NodeId, file_handle: u32) -> Result<(), StatusCode> {
+        let (mode, stream) = self.take(session_id, file_handle)?;
+        self.provider.close(mode, stream)
+    }
+
+    /// Closes an open file without involving the provider and returns its mode and contents
+    pub fn take(&mut self, session_id: &NodeId, file_handle: u32) -> Result<(FileOpenMode, Box<dyn FileStream>), StatusCode> {
+        self.open_file(session_id, file_handle)?;
+        let open_file = self.open_files.remove(&file_handle).unwrap();
+        Ok((open_file.mode, open_file.stream))
+    }
+}
+
+/// Gets an input argument of a method call
+pub(crate) fn input_argument<T>(input_arguments: &[Variant], index: usize) -> Result<T, StatusCode> where T: for<'a> std::convert::TryFrom<&'a Variant> {
+    input_arguments.get(index)
+        .and_then(|value| T::try_from(value).ok())
+        .ok_or(StatusCode::BadInvalidArgument)
+}
+
+/// The methods of a file object
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum FileMethodType {
+    Open,
+    Close,
+    Read,
+    Write,
+    GetPosition,
+    SetPosition,
+}
+
+/// Checks that the session may call the methods of a file object
+pub(crate) type FileAccessCheck = fn(&Session) -> Result<(), StatusCode>;
+
+/// The handler of one of the methods of a file object
+struct FileMethod {
+    file: Arc<RwLock<File>>,
+    method_type: FileMethodType,
+    access_check: Option<FileAccessCheck>,
+}
+
+impl Method for FileMethod {
+    fn call(&mut self, session: &mut Session, request: &CallMethodRequest) -> Result<CallMethodResult, StatusCode> {
+        if let Some(access_check) = self.access_check {
+            access_check(session)?;
+        }
+
+        let input_arguments = request.input_arguments.as_ref().map(|v| v.as_slice()).unwrap_or(&[]);
+        let session_id = &session.session_id;
+        let mut file = trace_write_lock_unwrap!(self.file);
+
+        let output_arguments = match self.method_type {
+            FileMethodType::Open => {
+                vec![Variant::from(file.open(session_id, input_argument(input_arguments, 0)?)?)]
+            }
+            FileMethodType::Close => {
+                file.close(session_id, input_argument(input_arguments, 0)?)?;
+                Vec::new()
+            }
+            FileMethodType::Read => {
+                vec![Variant::from(file.read(session_id, input_argument(input_arguments, 0)?, input_argument(input_arguments, 1)?)?)]
+            }
+            FileMethodType::Write => {
+                let data: ByteString = input_argument(input_arguments, 1)?;
+                file.write(session_id, input_argument(input_arguments, 0)?, &data)?;
+                Vec::new()
+            }
+            FileMethodType::GetPosition => {
+                vec![Variant::from(file.get_position(session_id, input_argument(input_arguments, 0)?)?)]
+            }
+            FileMethodType::SetPosition => {
+                file.set_position(session_id, input_argument(input_arguments, 0)?, input_argument(input_arguments, 1)?)?;
+                Vec::new()
+            }
+        };
+
+        Ok(CallMethodResult {
+            status_code: StatusCode::Good,
+            input_argument_results: Some(vec![StatusCode::Good; input_arguments.len()]),
+            input_argument_diagnostic_infos: None,
+            output_arguments: if output_arguments.is_empty() { None } else { Some(output_arguments) },
+        })
+    }
+}
+
+/// The ids of the properties and methods of a file object. Objects of the standard node set have
+/// well known ids, others are given generated ids.
+pub(crate) struct FileNodeIds {
+    pub size: NodeId,
+    pub writable: NodeId,
+    pub user_writable: NodeId,
+    pub open_count: NodeId,
+    pub open: NodeId,
+    pub close: NodeId,
+    pub read: NodeId,
+    pub write: NodeId,
+    pub get_position: NodeId,
+    pub set_position: NodeId,
+}
+
+impl FileNodeIds {
+    pub fn next_numeric() -> FileNodeIds {
+        FileNodeIds {
+            size: NodeId::next_numeric(),
+            writable: NodeId::next_numeric(),
+            user_writable: NodeId::next_numeric(),
+            open_count: NodeId::next_numeric(),
+            open: NodeId::next_numeric(),
+            close: NodeId::next_numeric(),
+            read: NodeId::next_numeric(),
+            write: NodeId::next_numeric(),
+            get_position: NodeId::next_numeric(),
+            set_position: NodeId::next_numeric(),
+        }
+    }
+}
+
+/// Adds the properties and methods of `FileType` to an object and connects them to the file. The
+/// access check, if any, is made before each method call.
+pub(crate) fn add_file_nodes(address_space: &mut AddressSpace, object_id: &NodeId, node_ids: &FileNodeIds, file: Arc<RwLock<File>>, access_check: Option<FileAccessCheck>) {
+    // Properties
+    let properties = [
+        (&node_ids.size, "Size", DataTypeId::UInt64, Variant::from(0u64)),
+        (&node_ids.writable, "Writable", DataTypeId::Boolean, Variant::from(false)),
+        (&node_ids.user_writable, "UserWritable", DataTypeId::Boolean, Variant::from(false)),
+        (&node_ids.open_count, "OpenCount", DataTypeId::UInt16, Variant::from(0u16)),
+    ];
+    for (variable_id, name, data_type, value) in properties.iter() {
+        let variable = Variable::new_data_value(*variable_id, *name, *name, *data_type, value.clone());
+        address_space.insert(variable, Some(&[
+            (object_id, ReferenceTypeId::HasProperty, ReferenceDirection::Inverse),
+            (&VariableTypeId::PropertyType.into(), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+        ]));
+    }
+    {
+        let file = file.clone();
+        address_space.set_variable_getter(node_ids.size.clone(), move |_, _, _| {
+            let file = trace_read_lock_unwrap!(file);
+            Ok(Some(DataValue::new(file.size())))
+        });
+    }
+    for writable_id in &[&node_ids.writable, &node_ids.user_writable] {
+        let file = file.clone();
+        address_space.set_variable_getter((*writable_id).clone(), move |_, _, _| {
+            let file = trace_read_lock_unwrap!(file);
+            Ok(Some(DataValue::new(file.writable())))
+        });
+    }
+    {
+        let file = file.clone();
+        address_space.set_variable_getter(node_ids.open_count.clone(), move |_, _, _| {
+            let file = trace_read_lock_unwrap!(file);
+            Ok(Some(DataValue::new(file.open_count() as u16)))
+        });
+    }
+
+    // Methods
+    let methods = [
+        (&node_ids.open, "Open", FileMethodType::Open, vec![
+            argument("Mode", DataTypeId::Byte, -1),
+        ], vec![
+            argument("FileHandle", DataTypeId::UInt32, -1),
+        ]),
+        (&node_ids.close, "Close", FileMethodType::Close, vec![
+            argument("FileHandle", DataTypeId::UInt32, -1),
+        ], vec![]),
+        (&node_ids.read, "Read", FileMethodType::Read, vec![
+            argument("FileHandle", DataTypeId::UInt32, -1),
+            argument("Length", DataTypeId::Int32, -1),
+        ], vec![
+            argument("Data", DataTypeId::ByteString, -1),
+        ]),
+        (&node_ids.write, "Write", FileMethodType::Write, vec![
+            argument("FileHandle", DataTypeId::UInt32, -1),
+            argument("Data", DataTypeId::ByteString, -1),
+        ], vec![]),
+        (&node_ids.get_position, "GetPosition", FileMethodType::GetPosition, vec![
+            argument("FileHandle", DataTypeId::UInt32, -1),
+        ], vec![
+            argument("Position", DataTypeId::UInt64, -1),
+        ]),
+        (&node_ids.set_position, "SetPosition", FileMethodType::SetPosition, vec![
+            argument("FileHandle", DataTypeId::UInt32, -1),
+            argument("Position", DataTypeId::UInt64, -1),
+        ], vec![]),
+    ];
+    for (method_id, name, method_type, input_arguments, output_arguments) in methods.iter() {
+        if address_space.add_method(method_id, *name, *name, object_id, input_arguments, output_arguments).is_ok() {
+            address_space.register_method_handler(object_id.clone(), (*method_id).clone(), Box::new(FileMethod {
+                file: file.clone(),
+                method_type: *method_type,
+                access_check,
+            }));
+        }
+    }
+}
@@ -14,6 +14,8 @@ use actix_web::{
 };
 use serde_json;
 
+pub mod rest;
+
 use crate::{
     server::Connections,
     metrics::ServerMetrics,
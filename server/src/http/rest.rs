@@ -0,0 +1,507 @@
+//! Provides the REST gateway, an HTTP JSON API onto the Read, Write and Browse services for
+//! integrators who want quick web access to the address space without an OPC UA client.
+//!
+//! * `GET /nodes/{id}/value` reads the value of a variable
+//! * `PUT /nodes/{id}/value` writes the value of a variable from a body such as `{"Value": 42}`
+//! * `POST /browse` browses the references of a node from a body such as `{"NodeId": "i=85"}`
+//!
+//! Node ids are in their string form, e.g. `ns=2;s=Demo`, percent-encoded in paths where
+//! necessary. Requests are not made in a session. Each one is served for the user it authenticates
+//! as, so the roles and access control of the server apply just as they do to an OPC UA client.
+//!
+//! The gateway serves plain HTTP, so it only accepts credentials, i.e. Basic authorization or
+//! access tokens, when it listens on a loopback address such as behind a TLS terminating proxy.
+
+use std::{
+    str::FromStr,
+    sync::{Arc, RwLock, mpsc},
+    thread,
+    time::Duration,
+};
+
+use chrono::{self, Utc};
+use futures::{Future, future};
+use serde_json::{Map, Value};
+
+use actix_web::{
+    actix, http, server, App, AsyncResponder, Error, FutureResponse, HttpMessage, HttpRequest, HttpResponse,
+};
+
+use opcua_types::{
+    *,
+    node_ids::{DataTypeId, ReferenceTypeId},
+    service_types::*,
+    status_code::StatusCode,
+};
+
+use crate::{
+    address_space::{AddressSpace, SharedAddressSpace},
+    callbacks::UserIdentity,
+    pubsub::json::{date_time_to_json, variant_to_non_reversible_json},
+    services::{
+        ServiceUser,
+        attribute::{AttributeService, AsyncResponse},
+        view::ViewService,
+    },
+    state::ServerState,
+};
+
+use super::HttpQuit;
+
+/// The result of a request to the gateway, or the status code of a request which fails entirely
+type RestFuture<T> = Box<dyn Future<Item=T, Error=StatusCode> + Send>;
+
+/// The user that a request to the gateway authenticates as, which the services are performed for
+pub(crate) struct RestUser {
+    identity: UserIdentity,
+    roles: Vec<NodeId>,
+}
+
+impl ServiceUser for RestUser {
+    fn identity(&self) -> &UserIdentity {
+        &self.identity
+    }
+
+    fn roles(&self) -> &[NodeId] {
+        &self.roles
+    }
+}
+
+/// Serves requests to the gateway with the services of the server
+#[derive(Clone)]
+pub(crate) struct RestGateway {
+    server_state: Arc<RwLock<ServerState>>,
+    address_space: Arc<SharedAddressSpace>,
+}
+
+impl RestGateway {
+    pub fn new(server_state: Arc<RwLock<ServerState>>, address_space: Arc<SharedAddressSpace>) -> RestGateway {
+        RestGateway {
+            server_state,
+            address_space,
+        }
+    }
+
+    /// Reads the value of a variable. A value which cannot be read has a bad status.
+    pub fn read_value(&self, authorization: Option<&str>, node_id: NodeId) -> RestFuture<DataValue> {
+        let response = self.user(authorization).and_then(|user| {
+            let request = ReadRequest {
+                request_header: RequestHeader::dummy(),
+                max_age: 0f64,
+                timestamps_to_return: TimestampsToReturn::Both,
+                nodes_to_read: Some(vec![ReadValueId {
+                    node_id,
+                    attribute_id: AttributeId::Value as u32,
+                    index_range: UAString::null(),
+                    data_encoding: QualifiedName::null(),
+                }]),
+            };
+            let server_state = trace_read_lock_unwrap!(self.server_state);
+            let address_space = trace_read_lock_unwrap!(self.address_space);
+            let attribute_service = AttributeService::new();
+            let response = attribute_service.read(&server_state, &user, &address_space, &request)?;
+            let async_response = match response {
+                SupportedMessage::ReadResponse(ref response) => {
+                    attribute_service.async_read(&user, &address_space, &request, response, Self::async_timeout(&server_state))
+                }
+                _ => None
+            };
+            Ok(Self::response_future(response, async_response))
+        });
+        match response {
+            Ok(response) => Box::new(response.and_then(|response| match response {
+                SupportedMessage::ReadResponse(response) => {
+                    response.results.and_then(|mut results| results.pop()).ok_or(StatusCode::BadUnexpectedError)
+                }
+                response => Err(Self::service_result(&response))
+            })),
+            Err(status_code) => Box::new(future::err(status_code))
+        }
+    }
+
+    /// Writes the value of a variable from the "Value" of the body, which is converted to the
+    /// data type of the variable. Returns the status code of the write.
+    pub fn write_value(&self, authorization: Option<&str>, node_id: NodeId, body: &Value) -> RestFuture<StatusCode> {
+        let response = self.user(authorization).and_then(|user| {
+            let value = body.get("Value").ok_or(StatusCode::BadDecodingError)?;
+            let server_state = trace_read_lock_unwrap!(self.server_state);
+            let mut address_space = trace_write_lock_unwrap!(self.address_space);
+            let data_type = address_space.find_variable_by_ref(&node_id)
+                .map(|variable| variable.data_type())
+                .ok_or(StatusCode::BadNodeIdUnknown)?;
+            let request = WriteRequest {
+                request_header: RequestHeader::dummy(),
+                nodes_to_write: Some(vec![WriteValue {
+                    node_id,
+                    attribute_id: AttributeId::Value as u32,
+                    index_range: UAString::null(),
+                    value: DataValue::new(json_to_variant(value, &data_type)?),
+                }]),
+            };
+            let attribute_service = AttributeService::new();
+            let response = attribute_service.write(&server_state, &user, &mut address_space, &request)?;
+            let async_response = match response {
+                SupportedMessage::WriteResponse(ref response) => {
+                    attribute_service.async_write(&user, &address_space, &request, response, Self::async_timeout(&server_state))
+                }
+                _ => None
+            };
+            Ok(Self::response_future(response, async_response))
+        });
+        match response {
+            Ok(response) => Box::new(response.and_then(|response| match response {
+                SupportedMessage::WriteResponse(response) => {
+                    response.results.and_then(|mut results| results.pop()).ok_or(StatusCode::BadUnexpectedError)
+                }
+                response => Err(Self::service_result(&response))
+            })),
+            Err(status_code) => Box::new(future::err(status_code))
+        }
+    }
+
+    /// Browses the references of the node described by the body. Every reference is returned,
+    /// since there is no session to hold a continuation point for the rest.
+    pub fn browse(&self, authorization: Option<&str>, body: &Value) -> Result<Vec<ReferenceDescription>, StatusCode> {
+        let user = self.user(authorization)?;
+        let node_to_browse = browse_description(body)?;
+        let server_state = trace_read_lock_unwrap!(self.server_state);
+        let address_space = trace_read_lock_unwrap!(self.address_space);
+        ViewService::browse_references(&server_state, &user, &address_space, 0, &node_to_browse)
+    }
+
+    /// Returns the user that the Authorization header of a request authenticates as, with the
+    /// roles that the user is granted
+    fn user(&self, authorization: Option<&str>) -> Result<RestUser, StatusCode> {
+        let server_state = trace_read_lock_unwrap!(self.server_state);
+        let identity = server_state.authenticate_rest_request(authorization)?;
+        let roles = server_state.roles_for(&identity);
+        Ok(RestUser { identity, roles })
+    }
+
+    fn async_timeout(server_state: &ServerState) -> Duration {
+        let config = trace_read_lock_unwrap!(server_state.config);
+        Duration::from_millis(config.async_timeout as u64)
+    }
+
+    /// The response, or the response completed by the async getters or setters of variables
+    fn response_future(response: SupportedMessage, async_response: Option<AsyncResponse>) -> RestFuture<SupportedMessage> {
+        match async_response {
+            Some(async_response) => Box::new(async_response.map_err(|_| StatusCode::BadInternalError)),
+            None => Box::new(future::ok(response))
+        }
+    }
+
+    /// The service result of a service fault
+    fn service_result(response: &SupportedMessage) -> StatusCode {
+        match *response {
+            SupportedMessage::ServiceFault(ref fault) => fault.response_header.service_result,
+            _ => StatusCode::BadUnexpectedError
+        }
+    }
+}
+
+/// Parses the node id of a path, which may be percent-encoded
+pub(crate) fn parse_node_id(segment: &str) -> Result<NodeId, StatusCode> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' && tail.len() >= 2 {
+            let hex = std::str::from_utf8(&tail[..2]).map_err(|_| StatusCode::BadNodeIdInvalid)?;
+            bytes.push(u8::from_str_radix(hex, 16).map_err(|_| StatusCode::BadNodeIdInvalid)?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    let node_id = String::from_utf8(bytes).map_err(|_| StatusCode::BadNodeIdInvalid)?;
+    NodeId::from_str(&node_id).map_err(|_| StatusCode::BadNodeIdInvalid)
+}
+
+/// Converts the JSON of a value being written to a variant of the data type of the variable. An
+/// array is converted element by element. A variable of BaseDataType takes a boolean, integer,
+/// double or string from the kind of JSON value.
+pub(crate) fn json_to_variant(value: &Value, data_type: &NodeId) -> Result<Variant, StatusCode> {
+    if let Value::Array(ref values) = *value {
+        let values = values.iter()
+            .map(|value| json_to_scalar(value, data_type))
+            .collect::<Result<Vec<Variant>, StatusCode>>()?;
+        Ok(Variant::Array(values))
+    } else {
+        json_to_scalar(value, data_type)
+    }
+}
+
+fn json_to_scalar(value: &Value, data_type: &NodeId) -> Result<Variant, StatusCode> {
+    let is = |data_type_id: DataTypeId| {
+        let data_type_id: NodeId = data_type_id.into();
+        *data_type == data_type_id
+    };
+    let integer = |min: i64, max: i64| value.as_i64().filter(|v| *v >= min && *v <= max);
+    let variant = if is(DataTypeId::Boolean) {
+        value.as_bool().map(Variant::Boolean)
+    } else if is(DataTypeId::SByte) {
+        integer(i8::min_value() as i64, i8::max_value() as i64).map(|v| Variant::SByte(v as i8))
+    } else if is(DataTypeId::Byte) {
+        integer(0, u8::max_value() as i64).map(|v| Variant::Byte(v as u8))
+    } else if is(DataTypeId::Int16) {
+        integer(i16::min_value() as i64, i16::max_value() as i64).map(|v| Variant::Int16(v as i16))
+    } else if is(DataTypeId::UInt16) {
+        integer(0, u16::max_value() as i64).map(|v| Variant::UInt16(v as u16))
+    } else if is(DataTypeId::Int32) {
+        integer(i32::min_value() as i64, i32::max_value() as i64).map(|v| Variant::Int32(v as i32))
+    } else if is(DataTypeId::UInt32) {
+        integer(0, u32::max_value() as i64).map(|v| Variant::UInt32(v as u32))
+    } else if is(DataTypeId::Int64) {
+        value.as_i64().map(Variant::Int64)
+    } else if is(DataTypeId::UInt64) {
+        value.as_u64().map(Variant::UInt64)
+    } else if is(DataTypeId::Float) {
+        value.as_f64().map(|v| Variant::Float(v as f32))
+    } else if is(DataTypeId::Double) {
+        value.as_f64().map(Variant::Double)
+    } else if is(DataTypeId::String) {
+        value.as_str().map(Variant::from)
+    } else if is(DataTypeId::DateTime) {
+        value.as_str()
+            .and_then(|v| chrono::DateTime::parse_from_rfc3339(v).ok())
+            .map(|v| Variant::from(DateTime::from(v.with_timezone(&Utc))))
+    } else if is(DataTypeId::BaseDataType) {
+        match *value {
+            Value::Bool(v) => Some(Variant::Boolean(v)),
+            Value::Number(ref v) if v.is_i64() => v.as_i64().map(Variant::Int64),
+            Value::Number(ref v) => v.as_f64().map(Variant::Double),
+            Value::String(ref v) => Some(Variant::from(v.as_str())),
+            _ => None
+        }
+    } else {
+        None
+    };
+    variant.ok_or(StatusCode::BadTypeMismatch)
+}
+
+/// Makes the description of the node to browse from the body of a browse request. The body holds
+/// the "NodeId" to browse and optionally the "BrowseDirection" (Forward, Inverse or Both), the
+/// "ReferenceTypeId" and whether to "IncludeSubtypes". The default is to browse the forward
+/// hierarchical references and their subtypes.
+pub(crate) fn browse_description(body: &Value) -> Result<BrowseDescription, StatusCode> {
+    let node_id = body.get("NodeId")
+        .and_then(|node_id| node_id.as_str())
+        .and_then(|node_id| NodeId::from_str(node_id).ok())
+        .ok_or(StatusCode::BadNodeIdInvalid)?;
+    let browse_direction = match body.get("BrowseDirection").map(|browse_direction| browse_direction.as_str()) {
+        None | Some(Some("Forward")) => BrowseDirection::Forward,
+        Some(Some("Inverse")) => BrowseDirection::Inverse,
+        Some(Some("Both")) => BrowseDirection::Both,
+        _ => {
+            return Err(StatusCode::BadBrowseDirectionInvalid);
+        }
+    };
+    let reference_type_id = match body.get("ReferenceTypeId") {
+        Some(reference_type_id) => reference_type_id.as_str()
+            .and_then(|reference_type_id| NodeId::from_str(reference_type_id).ok())
+            .ok_or(StatusCode::BadReferenceTypeIdInvalid)?,
+        None => ReferenceTypeId::HierarchicalReferences.into(),
+    };
+    let include_subtypes = match body.get("IncludeSubtypes") {
+        Some(include_subtypes) => include_subtypes.as_bool().ok_or(StatusCode::BadDecodingError)?,
+        None => true,
+    };
+    Ok(BrowseDescription {
+        node_id,
+        browse_direction,
+        reference_type_id,
+        include_subtypes,
+        node_class_mask: 0,
+        result_mask: 0x3f,
+    })
+}
+
+/// Makes the JSON of a value that was read, holding the node id of the variable and its value,
+/// status code and timestamps
+pub(crate) fn data_value_to_json(node_id: &NodeId, value: &DataValue) -> Value {
+    let mut json = Map::new();
+    json.insert("NodeId".into(), Value::from(node_id.to_string()));
+    json.insert("Value".into(), value.value.as_ref().map(variant_to_non_reversible_json).unwrap_or(Value::Null));
+    json.insert("Status".into(), Value::from(value.status()));
+    if let Some(ref source_timestamp) = value.source_timestamp {
+        json.insert("SourceTimestamp".into(), date_time_to_json(source_timestamp));
+    }
+    if let Some(ref server_timestamp) = value.server_timestamp {
+        json.insert("ServerTimestamp".into(), date_time_to_json(server_timestamp));
+    }
+    Value::Object(json)
+}
+
+/// Makes the JSON of a reference that was browsed
+pub(crate) fn reference_to_json(reference: &ReferenceDescription) -> Value {
+    let browse_name = &reference.browse_name;
+    let mut json = Map::new();
+    json.insert("NodeId".into(), Value::from(reference.node_id.node_id.to_string()));
+    json.insert("ReferenceTypeId".into(), Value::from(reference.reference_type_id.to_string()));
+    json.insert("IsForward".into(), Value::from(reference.is_forward));
+    json.insert("BrowseName".into(), Value::from(if browse_name.namespace_index == 0 {
+        browse_name.name.as_ref().to_string()
+    } else {
+        format!("{}:{}", browse_name.namespace_index, browse_name.name.as_ref())
+    }));
+    json.insert("DisplayName".into(), Value::from(reference.display_name.text.as_ref()));
+    json.insert("NodeClass".into(), Value::from(format!("{:?}", reference.node_class)));
+    if !reference.type_definition.is_null() {
+        json.insert("TypeDefinition".into(), Value::from(reference.type_definition.node_id.to_string()));
+    }
+    Value::Object(json)
+}
+
+/// The HTTP status of a request whose result has the status code
+pub(crate) fn http_status(status_code: StatusCode) -> u16 {
+    if !status_code.is_bad() {
+        return 200;
+    }
+    match status_code & StatusCode::STATUS_MASK {
+        StatusCode::BadNodeIdInvalid | StatusCode::BadTypeMismatch | StatusCode::BadDecodingError |
+        StatusCode::BadBrowseDirectionInvalid | StatusCode::BadReferenceTypeIdInvalid | StatusCode::BadOutOfRange => 400,
+        StatusCode::BadIdentityTokenInvalid | StatusCode::BadIdentityTokenRejected => 401,
+        StatusCode::BadUserAccessDenied | StatusCode::BadNotReadable | StatusCode::BadNotWritable |
+        StatusCode::BadSecurityModeInsufficient => 403,
+        StatusCode::BadNodeIdUnknown | StatusCode::BadAttributeIdInvalid => 404,
+        StatusCode::BadTimeout => 504,
+        _ => 500
+    }
+}
+
+fn json_response(status_code: StatusCode, json: Value) -> HttpResponse {
+    let status = http::StatusCode::from_u16(http_status(status_code)).unwrap();
+    HttpResponse::build(status)
+        .content_type("application/json")
+        .body(json.to_string())
+}
+
+fn error_response(status_code: StatusCode) -> HttpResponse {
+    let mut json = Map::new();
+    json.insert("Status".into(), Value::from(status_code.bits()));
+    json.insert("Error".into(), Value::from(status_code.name()));
+    json_response(status_code, Value::Object(json))
+}
+
+fn authorization(req: &HttpRequest<RestGateway>) -> Option<String> {
+    req.headers().get(http::header::AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+        .map(|authorization| authorization.to_string())
+}
+
+fn path_node_id(req: &HttpRequest<RestGateway>) -> Result<NodeId, StatusCode> {
+    req.match_info().get("id").ok_or(StatusCode::BadNodeIdInvalid).and_then(parse_node_id)
+}
+
+fn read_value(req: &HttpRequest<RestGateway>) -> FutureResponse<HttpResponse> {
+    let node_id = match path_node_id(req) {
+        Ok(node_id) => node_id,
+        Err(status_code) => {
+            return Box::new(future::ok(error_response(status_code)));
+        }
+    };
+    let authorization = authorization(req);
+    req.state().read_value(authorization.as_ref().map(|a| a.as_str()), node_id.clone())
+        .then(move |result| Ok::<_, Error>(match result {
+            Ok(value) => json_response(StatusCode::from_bits_truncate(value.status()), data_value_to_json(&node_id, &value)),
+            Err(status_code) => error_response(status_code)
+        }))
+        .responder()
+}
+
+fn write_value(req: &HttpRequest<RestGateway>) -> FutureResponse<HttpResponse> {
+    let node_id = match path_node_id(req) {
+        Ok(node_id) => node_id,
+        Err(status_code) => {
+            return Box::new(future::ok(error_response(status_code)));
+        }
+    };
+    let authorization = authorization(req);
+    let gateway = req.state().clone();
+    req.json::<Value>()
+        .then(move |body| -> RestFuture<StatusCode> {
+            match body {
+                Ok(body) => gateway.write_value(authorization.as_ref().map(|a| a.as_str()), node_id, &body),
+                Err(err) => {
+                    error!("REST request body is invalid, error = {}", err);
+                    Box::new(future::err(StatusCode::BadDecodingError))
+                }
+            }
+        })
+        .then(|result| Ok::<_, Error>(match result {
+            Ok(status_code) if status_code.is_good() => {
+                let mut json = Map::new();
+                json.insert("Status".into(), Value::from(status_code.bits()));
+                json_response(status_code, Value::Object(json))
+            }
+            Ok(status_code) | Err(status_code) => error_response(status_code)
+        }))
+        .responder()
+}
+
+fn browse(req: &HttpRequest<RestGateway>) -> FutureResponse<HttpResponse> {
+    let authorization = authorization(req);
+    let gateway = req.state().clone();
+    req.json::<Value>()
+        .then(move |body| Ok::<_, Error>(match body {
+            Ok(body) => match gateway.browse(authorization.as_ref().map(|a| a.as_str()), &body) {
+                Ok(references) => {
+                    let mut json = Map::new();
+                    json.insert("References".into(), Value::Array(references.iter().map(reference_to_json).collect()));
+                    json_response(StatusCode::Good, Value::Object(json))
+                }
+                Err(status_code) => error_response(status_code)
+            },
+            Err(err) => {
+                error!("REST request body is invalid, error = {}", err);
+                error_response(StatusCode::BadDecodingError)
+            }
+        }))
+        .responder()
+}
+
+/// Runs the REST gateway on the specified binding address until the server aborts
+pub fn run_rest_gateway(address: &str, server_state: Arc<RwLock<ServerState>>, address_space: Arc<SharedAddressSpace>) {
+    let address = String::from(address);
+    let gateway = RestGateway::new(server_state.clone(), address_space);
+
+    let quit_task = HttpQuit { server_state };
+
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        info!("REST gateway is running on http://{}/", address);
+        let sys = actix::System::new("rest-gateway");
+        let addr = server::new(
+            move || {
+                App::with_state(gateway.clone())
+                    .resource("/nodes/{id}/value", |r| {
+                        r.method(http::Method::GET).f(read_value);
+                        r.method(http::Method::PUT).f(write_value);
+                    })
+                    .resource("/browse", |r| r.method(http::Method::POST).f(browse))
+            })
+            .bind(&address).unwrap()
+            .start();
+
+        // Give the address info to the quit task
+        let _ = tx.send(addr);
+
+        // Run
+        let _ = sys.run();
+    });
+
+    // Get the address info from the gateway thread
+    let addr = rx.recv().unwrap();
+
+    // Spawn a tokio task to monitor for quit and to shutdown the gateway
+    thread::spawn(move || {
+        tokio::run(quit_task.map(move |_| {
+            info!("REST gateway will be stopped");
+            let _ = addr.send(server::StopServer {
+                graceful: false
+            });
+        }));
+    });
+}
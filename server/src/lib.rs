@@ -50,6 +50,9 @@ mod services;
 mod session;
 mod discovery;
 mod completion_pact;
+mod server_configuration;
+mod trust_list;
+mod telemetry;
 
 pub mod comms;
 pub mod metrics;
@@ -58,6 +61,7 @@ pub mod builder;
 pub mod state;
 pub mod diagnostics;
 pub mod subscriptions;
+pub mod events;
 pub mod config;
 pub mod address_space;
 pub mod util;
@@ -65,6 +69,14 @@ pub mod continuation_point;
 #[cfg(feature = "http")]
 pub mod http;
 pub mod callbacks;
+pub mod content_filter;
+pub mod file_transfer;
+pub mod query;
+pub mod pubsub;
+pub mod simulation;
+pub mod aggregation;
+pub mod bridge;
+pub mod roles;
 
 pub mod prelude {
     //! Provides a way to use most types and functions commonly used by server implementations from a
@@ -74,9 +86,18 @@ pub mod prelude {
     pub use opcua_core::prelude::*;
     pub use crate::{
         address_space::types::*,
+        bridge::{Bridge, BridgeTag},
         builder::*,
         callbacks::*,
         config::*,
+        events::event::*,
+        events::condition::*,
+        events::audit::*,
+        events::model_change::*,
+        content_filter::*,
+        file_transfer::{FileOpenMode, FileProvider, FileStream, PathFileProvider},
+        query::*,
+        roles::*,
         server::*,
         subscriptions::*,
         util::*,
@@ -87,12 +108,48 @@ pub mod constants {
     //! Provides constants that govern the internal workings of the server implementation.
     /// The default hello timeout period in seconds
     pub const DEFAULT_HELLO_TIMEOUT_SECONDS: u32 = 120;
+    /// The default size in bytes of the buffer that chunks are received into
+    pub const DEFAULT_RECEIVE_BUFFER_SIZE: usize = 1024 * 64;
+    /// The default size in bytes of the buffer that chunks are sent from
+    pub const DEFAULT_SEND_BUFFER_SIZE: usize = 1024 * 64;
+    /// The default maximum number of chunks per second that a connection may send
+    pub const DEFAULT_MAX_CHUNK_RATE: usize = 1000;
+    /// The default number of messages which fail to decode that a connection may send before it is closed
+    pub const DEFAULT_MAX_DECODING_ERRORS: usize = 3;
+    /// The default maximum number of connections open at the same time from one client address
+    pub const DEFAULT_MAX_CONNECTIONS_PER_CLIENT: usize = 20;
+    /// The default smallest lifetime in millis of a secure channel token
+    pub const DEFAULT_MIN_TOKEN_LIFETIME: u32 = 10000;
+    /// The default largest lifetime in millis of a secure channel token
+    pub const DEFAULT_MAX_TOKEN_LIFETIME: u32 = 3600000;
+    /// The default maximum number of chunks that a request may be split into
+    pub const DEFAULT_MAX_CHUNK_COUNT: usize = 16;
+    /// The default maximum size in bytes of a request
+    pub const DEFAULT_MAX_MESSAGE_SIZE: usize = DEFAULT_RECEIVE_BUFFER_SIZE * DEFAULT_MAX_CHUNK_COUNT;
     /// Default OPC UA server port for this implementation
     pub const DEFAULT_RUST_OPC_UA_SERVER_PORT: u16 = 4855;
     /// Default maximum number of subscriptions in a session
     pub const DEFAULT_MAX_SUBSCRIPTIONS: u32 = 100;
+    /// Default maximum number of sessions that may be open on the server at the same time
+    pub const DEFAULT_MAX_SESSIONS: u32 = 100;
+    /// Default maximum number of monitored items in a subscription
+    pub const DEFAULT_MAX_MONITORED_ITEMS_PER_SUBSCRIPTION: u32 = 1000;
+    /// Default maximum number of publish requests that a session may have queued
+    pub const DEFAULT_MAX_PUBLISH_REQUESTS: u32 = 2 * DEFAULT_MAX_SUBSCRIPTIONS;
     /// Default, well known address for TCP discovery server
     pub const DEFAULT_DISCOVERY_SERVER_URL: &str = "opc.tcp://localhost:4840/UADiscovery";
+    /// Default interval in seconds between registrations with a discovery server
+    pub const DEFAULT_DISCOVERY_REGISTER_INTERVAL: u32 = 5 * 60;
+    /// Maximum interval in seconds between registrations with a discovery server, from Part 12
+    pub const MAX_DISCOVERY_REGISTER_INTERVAL: u32 = 10 * 60;
+    /// Default interval in seconds before retrying a failed registration with a discovery server
+    pub const DEFAULT_DISCOVERY_RETRY_INTERVAL: u32 = 30;
+    /// Default number of times a failed registration with a discovery server is retried
+    pub const DEFAULT_DISCOVERY_MAX_RETRIES: u32 = 3;
+    /// The URI of the OPC UA namespace, always namespace index 0
+    pub const OPC_UA_NAMESPACE_URI: &str = "http://opcfoundation.org/UA/";
+    /// The URI of the namespace used internally by this implementation, namespace index 1
+    pub const INTERNAL_NAMESPACE_URI: &str = "urn:OPCUA-Rust-Internal";
 
     // Internally controlled values
 
@@ -106,11 +163,47 @@ pub mod constants {
     pub const MIN_SAMPLING_INTERVAL: f64 = (SUBSCRIPTION_TIMER_RATE_MS as f64) / 1000.0;
     /// Maximum data change queue allowed by clients on monitored items
     pub const MAX_DATA_CHANGE_QUEUE_SIZE: usize = 10;
+    /// Default event queue on monitored items when the client does not request a size
+    pub const DEFAULT_EVENT_QUEUE_SIZE: usize = 100;
+    /// Maximum event queue allowed by clients on monitored items
+    pub const MAX_EVENT_QUEUE_SIZE: usize = 1000;
+    /// Maximum number of raised events held by the address space for monitored items to collect
+    pub const MAX_RAISED_EVENTS: usize = 1000;
+    /// Maximum number of sent but unacknowledged notifications held for republishing, per subscription
+    pub const MAX_RETRANSMISSION_QUEUE_SIZE: usize = 20;
     /// The default size of preallocated vecs of monitored items per subscription
     pub const DEFAULT_MONITORED_ITEM_CAPACITY: usize = 100;
+    /// Interval in millis between attempts to make reverse connections to clients which have none
+    pub const REVERSE_CONNECT_POLL_MS: u64 = 5000;
+    /// Interval in millis between checks for PubSub writer groups which are due to publish
+    pub const PUBSUB_POLL_MS: u64 = 100;
+    /// Interval in millis before reconnecting to an MQTT broker after a failed or lost connection
+    pub const PUBSUB_RECONNECT_INTERVAL_MS: u64 = 5000;
+    /// Default topic template of an MQTT forwarder
+    pub const DEFAULT_MQTT_FORWARDER_TOPIC_TEMPLATE: &str = "opcua/{name}";
+    /// Default interval in millis between samples of the variables of an MQTT forwarder
+    pub const DEFAULT_MQTT_FORWARDER_SAMPLING_INTERVAL_MS: u64 = 1000;
+    /// Default publishing interval in millis of the subscription on an aggregated server
+    pub const DEFAULT_AGGREGATION_PUBLISHING_INTERVAL_MS: u64 = 1000;
+    /// Default depth of the Objects folder of an aggregated server that is mirrored
+    pub const DEFAULT_AGGREGATION_MAX_BROWSE_DEPTH: u32 = 10;
+    /// Interval in millis that the session to an aggregated server is polled on
+    pub const AGGREGATION_POLL_MS: u64 = 50;
+    /// Interval in millis before the first reconnect to an aggregated server after a failed or lost
+    /// connection, which doubles on each failure after that
+    pub const AGGREGATION_RECONNECT_MIN_MS: u64 = 1000;
+    /// Longest interval in millis between reconnects to an aggregated server
+    pub const AGGREGATION_RECONNECT_MAX_MS: u64 = 60000;
+    /// Interval in millis before reconnecting a bridge after a failed or lost connection
+    pub const BRIDGE_RECONNECT_INTERVAL_MS: u64 = 5000;
     /// Interval to check for HELLO timeout in millis. This can be fairly coarse because it's not
     /// something that requires huge accuracy.
     pub const HELLO_TIMEOUT_POLL_MS: u64 = 500;
+    /// Time in millis after a shutdown is due that the final publish responses have to be sent
+    /// before connections are closed
+    pub const SHUTDOWN_FINAL_PUBLISH_MS: i64 = 1000;
+    /// Minimum time in MS that a session can be inactive before a timeout
+    pub const MIN_SESSION_TIMEOUT: f64 = 1000f64;
     /// Maximum time in MS that a session can be inactive before a timeout
     pub const MAX_SESSION_TIMEOUT: f64 = 60000f64;
     /// Maximum size in bytes that a request message is allowed to be
@@ -119,18 +212,34 @@ pub mod constants {
     pub const DEFAULT_KEEP_ALIVE_COUNT: u32 = 10;
     /// Maxmimum keep alive count
     pub const MAX_KEEP_ALIVE_COUNT: u32 = 30000;
-    /// Maximum browse continuation points
+    /// Default maximum browse continuation points of a session
     pub const MAX_BROWSE_CONTINUATION_POINTS: usize = 10;
+    /// Default time in milliseconds to wait for async getters and setters of variables
+    pub const DEFAULT_ASYNC_TIMEOUT: u32 = 5000;
     /// Maximum history continuation points
-    pub const MAX_HISTORY_CONTINUATION_POINTS: usize = 0;
+    pub const MAX_HISTORY_CONTINUATION_POINTS: usize = 10;
+    /// Maximum number of nodes that a session may hold registered through RegisterNodes
+    pub const MAX_REGISTERED_NODES: usize = 1000;
     /// Maximum query continuation points
-    pub const MAX_QUERY_CONTINUATION_POINTS: usize = 0;
+    pub const MAX_QUERY_CONTINUATION_POINTS: usize = 10;
     /// Maximum method calls per request
     pub const MAX_METHOD_CALLS: usize = 10;
     /// Maximum number of nodes in a TranslateBrowsePathsToNodeIdsRequest
     pub const MAX_BROWSE_PATHS_PER_TRANSLATE: usize = 10;
     /// Maximum number of nodes / references per node manaument operation
     pub const MAX_NODES_PER_NODE_MANAGEMENT: usize = 100;
+    /// Maximum number of nodes in a ReadRequest
+    pub const MAX_NODES_PER_READ: usize = 10000;
+    /// Maximum number of nodes in a WriteRequest
+    pub const MAX_NODES_PER_WRITE: usize = 10000;
+    /// Maximum number of nodes in a BrowseRequest
+    pub const MAX_NODES_PER_BROWSE: usize = 1000;
+    /// Maximum number of nodes in a RegisterNodesRequest or UnregisterNodesRequest
+    pub const MAX_NODES_PER_REGISTER_NODES: usize = 1000;
+    /// Maximum number of monitored items in a call to the monitored item services
+    pub const MAX_MONITORED_ITEMS_PER_CALL: usize = 1000;
+    /// Maximum number of nodes in a HistoryReadRequest or HistoryUpdateRequest
+    pub const MAX_NODES_PER_HISTORY: usize = 100;
 }
 
 #[cfg(test)]
@@ -0,0 +1,240 @@
+//! Forwards the value changes of variables to MQTT brokers. Each configured forwarder runs on its
+//! own thread, which samples its variables from the address space every sampling interval and
+//! publishes each one whose value or status has changed to its own topic as a JSON object.
+//!
+//! Unlike a PubSub writer group, which publishes all of its fields every publishing interval, a
+//! forwarder only publishes changes, like a monitored item, so it suits IoT platforms which store
+//! every message they receive.
+
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{self, Map, Value};
+
+use opcua_types::{
+    DataValue, NodeId,
+    status_code::StatusCode,
+};
+
+use crate::{
+    address_space::{types::{AddressSpace, SharedAddressSpace}, node::Node},
+    config::{MqttForwarderConfig, MqttPayloadEncoding},
+    constants,
+    state::ServerState,
+};
+
+use super::{
+    json::{date_time_to_json, variant_to_json, variant_to_non_reversible_json},
+    mqtt::MqttClient,
+};
+
+/// Makes a topic from a topic template by replacing the `{name}`, `{node_id}` and `{namespace}`
+/// placeholders. Fails if the template has any other placeholder or an unmatched brace, or if the
+/// topic is empty or contains a wildcard, which cannot be published to.
+pub fn topic(template: &str, name: &str, node_id: &NodeId) -> Result<String, ()> {
+    let mut topic = String::with_capacity(template.len() + name.len());
+    let mut rest = template;
+    while let Some(start) = rest.find(|c| c == '{' || c == '}') {
+        if rest[start..].starts_with('}') {
+            return Err(());
+        }
+        topic.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or(())? + start;
+        match &rest[start + 1..end] {
+            "name" => topic.push_str(name),
+            "node_id" => topic.push_str(&node_id.to_string()),
+            "namespace" => topic.push_str(&node_id.namespace.to_string()),
+            _ => {
+                return Err(());
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    topic.push_str(rest);
+    if topic.is_empty() || topic.contains(|c| c == '+' || c == '#') {
+        Err(())
+    } else {
+        Ok(topic)
+    }
+}
+
+/// Makes the payload of a value change, a JSON object holding the name and node id of the variable
+/// and its value, status code and timestamps
+pub fn payload(name: &str, node_id: &NodeId, value: &DataValue, payload_encoding: MqttPayloadEncoding) -> Value {
+    let mut payload = Map::new();
+    payload.insert("Name".into(), Value::from(name));
+    payload.insert("NodeId".into(), Value::from(node_id.to_string()));
+    let json_value = match value.value {
+        Some(ref value) => match payload_encoding {
+            MqttPayloadEncoding::Reversible => variant_to_json(value),
+            MqttPayloadEncoding::NonReversible => variant_to_non_reversible_json(value),
+        },
+        None => Value::Null,
+    };
+    payload.insert("Value".into(), json_value);
+    payload.insert("Status".into(), Value::from(value.status()));
+    if let Some(ref source_timestamp) = value.source_timestamp {
+        payload.insert("SourceTimestamp".into(), date_time_to_json(source_timestamp));
+    }
+    if let Some(ref server_timestamp) = value.server_timestamp {
+        payload.insert("ServerTimestamp".into(), date_time_to_json(server_timestamp));
+    }
+    Value::Object(payload)
+}
+
+/// A forwarded variable and the value that was last published for it
+struct ForwardedVariable {
+    name: String,
+    node_id: NodeId,
+    topic: String,
+    last_value: Option<DataValue>,
+}
+
+/// The variables of a forwarder and when they are next sampled
+pub(crate) struct Forwarder {
+    variables: Vec<ForwardedVariable>,
+    payload_encoding: MqttPayloadEncoding,
+    sampling_interval: Duration,
+    next_sample: Instant,
+}
+
+impl Forwarder {
+    /// Creates a forwarder whose first sample is due immediately. Variables without a name are
+    /// named after their browse name, and variables whose topic is invalid are logged and ignored.
+    pub fn new(config: &MqttForwarderConfig, address_space: &AddressSpace, now: Instant) -> Forwarder {
+        let variables = config.variables.iter().filter_map(|variable| {
+            // Node ids were validated with the config
+            let node_id = NodeId::from_str(&variable.node_id).unwrap();
+            let name = variable.name.clone().unwrap_or_else(|| {
+                address_space.find_variable_by_ref(&node_id)
+                    .map(|v| v.browse_name().name.as_ref().to_string())
+                    .unwrap_or_else(|| variable.node_id.clone())
+            });
+            match topic(&config.topic_template, &name, &node_id) {
+                Ok(topic) => Some(ForwardedVariable {
+                    name,
+                    node_id,
+                    topic,
+                    last_value: None,
+                }),
+                Err(_) => {
+                    error!("MQTT forwarder cannot forward variable {}, its topic is invalid", name);
+                    None
+                }
+            }
+        }).collect();
+        Forwarder {
+            variables,
+            payload_encoding: config.payload_encoding,
+            sampling_interval: Duration::from_millis(config.sampling_interval),
+            next_sample: now,
+        }
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        now >= self.next_sample
+    }
+
+    /// Samples the variables and schedules the next sample. Returns the topic and payload of each
+    /// variable whose value or status has changed since it was last published. A variable which
+    /// does not exist has a `BadNodeIdUnknown` status.
+    pub fn changes(&mut self, address_space: &AddressSpace, now: Instant) -> Vec<(String, Vec<u8>)> {
+        self.next_sample = now + self.sampling_interval;
+        let payload_encoding = self.payload_encoding;
+        self.variables.iter_mut().filter_map(|variable| {
+            let value = if let Some(v) = address_space.find_variable_by_ref(&variable.node_id) {
+                v.value()
+            } else {
+                let mut value = DataValue::null();
+                value.status = Some(StatusCode::BadNodeIdUnknown.bits());
+                value
+            };
+            let changed = match variable.last_value {
+                Some(ref last_value) => last_value.value != value.value || last_value.status() != value.status(),
+                None => true,
+            };
+            if changed {
+                let payload = payload(&variable.name, &variable.node_id, &value, payload_encoding);
+                variable.last_value = Some(value);
+                Some((variable.topic.clone(), serde_json::to_vec(&payload).unwrap()))
+            } else {
+                None
+            }
+        }).collect()
+    }
+
+    /// Forgets the values which were last published, so every variable is published on the next
+    /// sample, e.g. after reconnecting to the broker
+    pub fn reset(&mut self) {
+        self.variables.iter_mut().for_each(|variable| variable.last_value = None);
+    }
+}
+
+/// Starts a thread that forwards the value changes of the variables for as long as the server is
+/// running. A lost connection to the broker is reestablished on the reconnect interval, after
+/// which the values of all the variables are published again.
+pub(crate) fn start_forwarder(server_state: Arc<RwLock<ServerState>>, address_space: Arc<SharedAddressSpace>, config: MqttForwarderConfig) {
+    let client_id = config.client_id.clone().unwrap_or_else(|| {
+        let server_state = trace_read_lock_unwrap!(server_state);
+        server_state.application_uri.as_ref().to_string()
+    });
+
+    let _ = thread::spawn(move || {
+        let now = Instant::now();
+        let mut forwarder = {
+            let address_space = trace_read_lock_unwrap!(address_space);
+            Forwarder::new(&config, &address_space, now)
+        };
+        let mut client: Option<MqttClient> = None;
+        let mut next_connect = now;
+        let tick = Duration::from_millis(constants::PUBSUB_POLL_MS);
+
+        loop {
+            {
+                let server_state = trace_read_lock_unwrap!(server_state);
+                if server_state.is_abort() || !server_state.is_running() {
+                    break;
+                }
+            }
+
+            let now = Instant::now();
+            if client.is_none() && now >= next_connect {
+                match MqttClient::connect(&config.broker_url, &client_id, config.user.as_ref().map(|u| u.as_ref()), config.pass.as_ref().map(|p| p.as_ref())) {
+                    Ok(c) => {
+                        forwarder.reset();
+                        client = Some(c);
+                    }
+                    Err(_) => {
+                        next_connect = now + Duration::from_millis(constants::PUBSUB_RECONNECT_INTERVAL_MS);
+                    }
+                }
+            }
+
+            if let Some(mut c) = client.take() {
+                if forwarder.is_due(now) {
+                    let changes = {
+                        let address_space = trace_read_lock_unwrap!(address_space);
+                        forwarder.changes(&address_space, now)
+                    };
+                    if changes.iter().all(|(topic, payload)| c.publish(topic, payload, config.retain).is_ok()) {
+                        client = Some(c);
+                    } else {
+                        warn!("Lost connection to MQTT broker {}", config.broker_url);
+                        next_connect = now + Duration::from_millis(constants::PUBSUB_RECONNECT_INTERVAL_MS);
+                    }
+                } else {
+                    client = Some(c);
+                }
+            }
+
+            thread::sleep(tick);
+        }
+
+        if let Some(client) = client {
+            client.disconnect();
+        }
+        info!("MQTT forwarder to broker {} is finished", config.broker_url);
+    });
+}
@@ -0,0 +1,280 @@
+//! Encodes PubSub network messages with the OPC UA JSON encoding described in OPC UA Part 14
+//! 7.2.3. Values are written with the reversible form of the JSON encoding from Part 6 5.4, so
+//! each field holds the built-in type of its variant alongside the body.
+
+use chrono::SecondsFormat;
+use serde_json::{self, Map, Value};
+
+use opcua_types::{
+    *,
+    node_ids::DataTypeId,
+    status_code::StatusCode,
+};
+
+/// The message type of a network message holding data set messages
+pub const MESSAGE_TYPE_DATA: &str = "ua-data";
+/// The message type of a data set message holding every field of the data set
+pub const MESSAGE_TYPE_KEY_FRAME: &str = "ua-keyframe";
+
+/// Built-in type id of an extension object, which has no `DataTypeId` of its own
+const EXTENSION_OBJECT_TYPE_ID: u32 = 22;
+
+/// A data set message, the values of the fields published by one data set writer
+pub struct DataSetMessage {
+    pub data_set_writer_id: u16,
+    pub sequence_number: u32,
+    pub timestamp: DateTime,
+    /// Field names and their values, in the order of the published data set
+    pub payload: Vec<(String, DataValue)>,
+}
+
+/// A network message, the data set messages of a writer group sent in a single MQTT publish
+pub struct NetworkMessage {
+    pub message_id: String,
+    pub publisher_id: String,
+    pub messages: Vec<DataSetMessage>,
+}
+
+impl NetworkMessage {
+    pub fn to_json(&self) -> Value {
+        let mut message = Map::new();
+        message.insert("MessageId".into(), Value::from(self.message_id.as_str()));
+        message.insert("MessageType".into(), Value::from(MESSAGE_TYPE_DATA));
+        message.insert("PublisherId".into(), Value::from(self.publisher_id.as_str()));
+        message.insert("Messages".into(), Value::Array(self.messages.iter().map(|m| m.to_json()).collect()));
+        Value::Object(message)
+    }
+
+    /// Serializes the network message to the bytes of an MQTT payload
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(&self.to_json()).unwrap()
+    }
+}
+
+impl DataSetMessage {
+    pub fn to_json(&self) -> Value {
+        let mut payload = Map::new();
+        self.payload.iter().for_each(|(name, value)| {
+            payload.insert(name.clone(), field_to_json(value));
+        });
+        let mut message = Map::new();
+        message.insert("DataSetWriterId".into(), Value::from(self.data_set_writer_id));
+        message.insert("SequenceNumber".into(), Value::from(self.sequence_number));
+        message.insert("Timestamp".into(), date_time_to_json(&self.timestamp));
+        message.insert("MessageType".into(), Value::from(MESSAGE_TYPE_KEY_FRAME));
+        message.insert("Payload".into(), Value::Object(payload));
+        Value::Object(message)
+    }
+}
+
+/// Encodes the value of a field. A field with a bad status has no usable value so its status code
+/// is published in its place, as the data set field content mask would with the status code flag.
+fn field_to_json(value: &DataValue) -> Value {
+    let status = value.status();
+    if status & StatusCode::IS_ERROR.bits() != 0 {
+        let mut field = Map::new();
+        field.insert("Type".into(), Value::from(DataTypeId::StatusCode as u32));
+        field.insert("Body".into(), Value::from(status));
+        Value::Object(field)
+    } else if let Some(ref value) = value.value {
+        variant_to_json(value)
+    } else {
+        Value::Null
+    }
+}
+
+/// Encodes a variant with the reversible JSON encoding, an object holding the built-in "Type" of
+/// the value and its "Body". Multi-dimensional arrays also hold their "Dimensions".
+pub fn variant_to_json(value: &Variant) -> Value {
+    let type_id = match *value {
+        Variant::Empty => {
+            return Value::Null;
+        }
+        Variant::ExtensionObject(_) => EXTENSION_OBJECT_TYPE_ID,
+        Variant::Array(ref values) if values.is_empty() => {
+            // An empty array has no type of its own
+            0
+        }
+        _ => match value.data_type() {
+            Some(data_type) => data_type as u32,
+            None => {
+                error!("Cannot encode the variant {:?} as JSON", value);
+                return Value::Null;
+            }
+        }
+    };
+    let mut variant = Map::new();
+    variant.insert("Type".into(), Value::from(type_id));
+    match *value {
+        Variant::Array(ref values) => {
+            variant.insert("Body".into(), Value::Array(values.iter().map(variant_body_to_json).collect()));
+        }
+        Variant::MultiDimensionArray(ref mda) => {
+            variant.insert("Body".into(), Value::Array(mda.values.iter().map(variant_body_to_json).collect()));
+            variant.insert("Dimensions".into(), Value::Array(mda.dimensions.iter().map(|d| Value::from(*d)).collect()));
+        }
+        _ => {
+            variant.insert("Body".into(), variant_body_to_json(value));
+        }
+    }
+    Value::Object(variant)
+}
+
+/// Encodes a variant with the non-reversible JSON encoding, i.e. just the body of the value, or an
+/// array of the bodies of an array. The dimensions of a multi-dimensional array are not kept.
+pub fn variant_to_non_reversible_json(value: &Variant) -> Value {
+    match *value {
+        Variant::Array(ref values) => Value::Array(values.iter().map(variant_body_to_json).collect()),
+        Variant::MultiDimensionArray(ref mda) => Value::Array(mda.values.iter().map(variant_body_to_json).collect()),
+        _ => variant_body_to_json(value),
+    }
+}
+
+/// Encodes the body of a scalar variant
+fn variant_body_to_json(value: &Variant) -> Value {
+    match *value {
+        Variant::Empty => Value::Null,
+        Variant::Boolean(v) => Value::from(v),
+        Variant::SByte(v) => Value::from(v),
+        Variant::Byte(v) => Value::from(v),
+        Variant::Int16(v) => Value::from(v),
+        Variant::UInt16(v) => Value::from(v),
+        Variant::Int32(v) => Value::from(v),
+        Variant::UInt32(v) => Value::from(v),
+        // 64-bit integers are strings because JSON numbers cannot hold them without loss
+        Variant::Int64(v) => Value::from(v.to_string()),
+        Variant::UInt64(v) => Value::from(v.to_string()),
+        Variant::Float(v) => float_to_json(v as f64),
+        Variant::Double(v) => float_to_json(v),
+        Variant::String(ref v) => string_to_json(v),
+        Variant::DateTime(ref v) => date_time_to_json(v),
+        Variant::Guid(ref v) => Value::from(v.to_string()),
+        Variant::StatusCode(v) => Value::from(v.bits()),
+        Variant::ByteString(ref v) => byte_string_to_json(v),
+        Variant::XmlElement(ref v) => string_to_json(v),
+        Variant::QualifiedName(ref v) => {
+            let mut name = Map::new();
+            name.insert("Name".into(), string_to_json(&v.name));
+            if v.namespace_index != 0 {
+                name.insert("Uri".into(), Value::from(v.namespace_index));
+            }
+            Value::Object(name)
+        }
+        Variant::LocalizedText(ref v) => {
+            let mut text = Map::new();
+            if !v.locale.is_null() {
+                text.insert("Locale".into(), string_to_json(&v.locale));
+            }
+            text.insert("Text".into(), string_to_json(&v.text));
+            Value::Object(text)
+        }
+        Variant::NodeId(ref v) => node_id_to_json(v),
+        Variant::ExpandedNodeId(ref v) => {
+            let mut node_id = node_id_to_json(&v.node_id);
+            if let Value::Object(ref mut node_id) = node_id {
+                if !v.namespace_uri.is_null() {
+                    node_id.insert("Namespace".into(), string_to_json(&v.namespace_uri));
+                }
+                if v.server_index != 0 {
+                    node_id.insert("ServerUri".into(), Value::from(v.server_index));
+                }
+            }
+            node_id
+        }
+        Variant::ExtensionObject(ref v) => {
+            let mut object = Map::new();
+            object.insert("TypeId".into(), node_id_to_json(&v.node_id));
+            match v.body {
+                ExtensionObjectEncoding::None => {}
+                ExtensionObjectEncoding::ByteString(ref body) => {
+                    object.insert("Encoding".into(), Value::from(1));
+                    object.insert("Body".into(), byte_string_to_json(body));
+                }
+                ExtensionObjectEncoding::XmlElement(ref body) => {
+                    object.insert("Encoding".into(), Value::from(2));
+                    object.insert("Body".into(), string_to_json(body));
+                }
+            }
+            Value::Object(object)
+        }
+        Variant::DataValue(ref v) => {
+            let mut data_value = Map::new();
+            if let Some(ref value) = v.value {
+                data_value.insert("Value".into(), variant_to_json(value));
+            }
+            if let Some(status) = v.status {
+                data_value.insert("Status".into(), Value::from(status));
+            }
+            if let Some(ref source_timestamp) = v.source_timestamp {
+                data_value.insert("SourceTimestamp".into(), date_time_to_json(source_timestamp));
+            }
+            if let Some(ref server_timestamp) = v.server_timestamp {
+                data_value.insert("ServerTimestamp".into(), date_time_to_json(server_timestamp));
+            }
+            Value::Object(data_value)
+        }
+        Variant::Array(_) | Variant::MultiDimensionArray(_) => {
+            error!("Cannot encode a nested array as JSON");
+            Value::Null
+        }
+    }
+}
+
+/// Encodes a node id as an object with the "IdType" for non-numeric ids, the "Id" and the
+/// "Namespace" index when it is not 0.
+fn node_id_to_json(node_id: &NodeId) -> Value {
+    let mut value = Map::new();
+    match node_id.identifier {
+        Identifier::Numeric(v) => {
+            value.insert("Id".into(), Value::from(v));
+        }
+        Identifier::String(ref v) => {
+            value.insert("IdType".into(), Value::from(1));
+            value.insert("Id".into(), string_to_json(v));
+        }
+        Identifier::Guid(ref v) => {
+            value.insert("IdType".into(), Value::from(2));
+            value.insert("Id".into(), Value::from(v.to_string()));
+        }
+        Identifier::ByteString(ref v) => {
+            value.insert("IdType".into(), Value::from(3));
+            value.insert("Id".into(), byte_string_to_json(v));
+        }
+    }
+    if node_id.namespace != 0 {
+        value.insert("Namespace".into(), Value::from(node_id.namespace));
+    }
+    Value::Object(value)
+}
+
+fn string_to_json(value: &UAString) -> Value {
+    if value.is_null() {
+        Value::Null
+    } else {
+        Value::from(value.as_ref())
+    }
+}
+
+fn byte_string_to_json(value: &ByteString) -> Value {
+    if value.is_null() {
+        Value::Null
+    } else {
+        Value::from(value.as_base64())
+    }
+}
+
+/// Dates are ISO 8601 strings in UTC
+pub fn date_time_to_json(value: &DateTime) -> Value {
+    Value::from(value.as_chrono().to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+/// Numbers which JSON cannot represent are written as the strings the encoding uses for them
+fn float_to_json(value: f64) -> Value {
+    if value.is_nan() {
+        Value::from("NaN")
+    } else if value.is_infinite() {
+        Value::from(if value.is_sign_positive() { "Infinity" } else { "-Infinity" })
+    } else {
+        Value::from(value)
+    }
+}
@@ -0,0 +1,171 @@
+//! Provides PubSub publishing of variable values to MQTT brokers. Each configured connection runs
+//! on its own thread, which samples the fields of its writer groups from the address space every
+//! publishing interval and publishes them as JSON network messages to the group's topic.
+
+use std::sync::{Arc, RwLock};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use opcua_types::{
+    DataValue, DateTime, Guid, NodeId,
+    status_code::StatusCode,
+};
+
+use crate::{
+    address_space::types::{AddressSpace, SharedAddressSpace},
+    config::{PubSubConnectionConfig, WriterGroupConfig},
+    constants,
+    state::ServerState,
+};
+
+use self::{
+    json::{DataSetMessage, NetworkMessage},
+    mqtt::MqttClient,
+};
+
+pub mod forwarder;
+pub mod json;
+pub mod mqtt;
+
+/// A data set writer and the sequence number of its next data set message
+struct DataSetWriter {
+    data_set_writer_id: u16,
+    fields: Vec<(String, NodeId)>,
+    sequence_number: u32,
+}
+
+/// A writer group and when its next network message is due
+pub(crate) struct WriterGroup {
+    topic: String,
+    retain: bool,
+    publishing_interval: Duration,
+    next_publish: Instant,
+    data_set_writers: Vec<DataSetWriter>,
+}
+
+impl WriterGroup {
+    /// Creates a writer group whose first network message is due immediately
+    pub fn new(config: &WriterGroupConfig, now: Instant) -> WriterGroup {
+        let data_set_writers = config.data_set_writers.iter().map(|data_set_writer| {
+            DataSetWriter {
+                data_set_writer_id: data_set_writer.data_set_writer_id,
+                // Node ids were validated with the config
+                fields: data_set_writer.fields.iter()
+                    .map(|field| (field.name.clone(), NodeId::from_str(&field.node_id).unwrap()))
+                    .collect(),
+                sequence_number: 0,
+            }
+        }).collect();
+        WriterGroup {
+            topic: config.topic.clone(),
+            retain: config.retain,
+            publishing_interval: Duration::from_millis(config.publishing_interval as u64),
+            next_publish: now,
+            data_set_writers,
+        }
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        now >= self.next_publish
+    }
+
+    /// Makes the network message of the group from the current values of its fields and schedules
+    /// the next one. Fields whose node is not a variable have a `BadNodeIdUnknown` status.
+    pub fn network_message(&mut self, publisher_id: &str, address_space: &AddressSpace, now: Instant) -> NetworkMessage {
+        self.next_publish = now + self.publishing_interval;
+        let timestamp = DateTime::now();
+        let messages = self.data_set_writers.iter_mut().map(|data_set_writer| {
+            let payload = data_set_writer.fields.iter().map(|(name, node_id)| {
+                let value = if let Some(variable) = address_space.find_variable_by_ref(node_id) {
+                    variable.value()
+                } else {
+                    let mut value = DataValue::null();
+                    value.status = Some(StatusCode::BadNodeIdUnknown.bits());
+                    value
+                };
+                (name.clone(), value)
+            }).collect();
+            let sequence_number = data_set_writer.sequence_number;
+            data_set_writer.sequence_number = data_set_writer.sequence_number.wrapping_add(1);
+            DataSetMessage {
+                data_set_writer_id: data_set_writer.data_set_writer_id,
+                sequence_number,
+                timestamp: timestamp.clone(),
+                payload,
+            }
+        }).collect();
+        NetworkMessage {
+            message_id: Guid::new().to_string(),
+            publisher_id: publisher_id.to_string(),
+            messages,
+        }
+    }
+}
+
+/// Starts a thread that publishes the writer groups of the connection for as long as the server
+/// is running. A lost connection to the broker is reestablished on the reconnect interval.
+pub(crate) fn start_publisher(server_state: Arc<RwLock<ServerState>>, address_space: Arc<SharedAddressSpace>, config: PubSubConnectionConfig) {
+    let application_uri = {
+        let server_state = trace_read_lock_unwrap!(server_state);
+        server_state.application_uri.as_ref().to_string()
+    };
+    let publisher_id = config.publisher_id.clone().unwrap_or_else(|| application_uri.clone());
+    let client_id = config.client_id.clone().unwrap_or(application_uri);
+
+    let _ = thread::spawn(move || {
+        let now = Instant::now();
+        let mut writer_groups = config.writer_groups.iter().map(|writer_group| WriterGroup::new(writer_group, now)).collect::<Vec<_>>();
+        let mut client: Option<MqttClient> = None;
+        let mut next_connect = now;
+        let tick = Duration::from_millis(constants::PUBSUB_POLL_MS);
+
+        loop {
+            {
+                let server_state = trace_read_lock_unwrap!(server_state);
+                if server_state.is_abort() || !server_state.is_running() {
+                    break;
+                }
+            }
+
+            let now = Instant::now();
+            if client.is_none() && now >= next_connect {
+                match MqttClient::connect(&config.broker_url, &client_id, config.user.as_ref().map(|u| u.as_ref()), config.pass.as_ref().map(|p| p.as_ref())) {
+                    Ok(c) => {
+                        client = Some(c);
+                    }
+                    Err(_) => {
+                        next_connect = now + Duration::from_millis(constants::PUBSUB_RECONNECT_INTERVAL_MS);
+                    }
+                }
+            }
+
+            if let Some(mut c) = client.take() {
+                let mut connected = true;
+                for writer_group in writer_groups.iter_mut().filter(|writer_group| writer_group.is_due(now)) {
+                    let network_message = {
+                        let address_space = trace_read_lock_unwrap!(address_space);
+                        writer_group.network_message(&publisher_id, &address_space, now)
+                    };
+                    if c.publish(&writer_group.topic, &network_message.to_bytes(), writer_group.retain).is_err() {
+                        connected = false;
+                        break;
+                    }
+                }
+                if connected {
+                    client = Some(c);
+                } else {
+                    warn!("Lost connection to MQTT broker {}", config.broker_url);
+                    next_connect = now + Duration::from_millis(constants::PUBSUB_RECONNECT_INTERVAL_MS);
+                }
+            }
+
+            thread::sleep(tick);
+        }
+
+        if let Some(client) = client {
+            client.disconnect();
+        }
+        info!("PubSub publisher to MQTT broker {} is finished", config.broker_url);
+    });
+}
@@ -0,0 +1,184 @@
+//! A minimal MQTT 3.1.1 client which is just enough to publish network messages to a broker. It
+//! connects with a clean session and publishes with QoS 0, i.e. messages are fire and forget.
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use opcua_types::status_code::StatusCode;
+
+/// Default port of an MQTT broker without TLS
+pub const DEFAULT_MQTT_PORT: u16 = 1883;
+/// Scheme of an MQTT broker url, e.g. `mqtt://localhost:1883`
+pub const MQTT_SCHEME: &str = "mqtt://";
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBLISH_RETAIN: u8 = 0x01;
+const DISCONNECT: u8 = 0xe0;
+
+const CONNECT_CLEAN_SESSION: u8 = 0x02;
+const CONNECT_PASSWORD: u8 = 0x40;
+const CONNECT_USER_NAME: u8 = 0x80;
+/// Protocol level of MQTT 3.1.1
+const PROTOCOL_LEVEL: u8 = 4;
+/// Largest value that the remaining length of a packet can hold
+const MAX_REMAINING_LENGTH: usize = 268_435_455;
+/// Largest length of a string or binary field, which has a two byte length prefix
+const MAX_FIELD_LENGTH: usize = 65_535;
+/// Timeout of connecting to, reading from and writing to the broker
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Returns the host and port of a broker url, e.g. `mqtt://localhost:1883`. The port is the default
+/// MQTT port if the url has none.
+pub fn broker_host_port(broker_url: &str) -> Result<(String, u16), ()> {
+    if !broker_url.starts_with(MQTT_SCHEME) {
+        return Err(());
+    }
+    let authority = broker_url[MQTT_SCHEME.len()..].split('/').next().unwrap();
+    if authority.is_empty() {
+        return Err(());
+    }
+    if let Some(idx) = authority.rfind(':') {
+        let (host, port) = (&authority[..idx], &authority[idx + 1..]);
+        if host.is_empty() {
+            Err(())
+        } else {
+            port.parse::<u16>().map(|port| (host.to_string(), port)).map_err(|_| ())
+        }
+    } else {
+        Ok((authority.to_string(), DEFAULT_MQTT_PORT))
+    }
+}
+
+/// Writes the variable length remaining length of a packet
+fn write_remaining_length(packet: &mut Vec<u8>, mut length: usize) {
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+}
+
+/// Writes a length prefixed UTF-8 string or binary field
+fn write_field(body: &mut Vec<u8>, field: &[u8]) -> Result<(), StatusCode> {
+    if field.len() > MAX_FIELD_LENGTH {
+        error!("MQTT field of {} bytes is longer than the limit of {} bytes", field.len(), MAX_FIELD_LENGTH);
+        return Err(StatusCode::BadEncodingLimitsExceeded);
+    }
+    body.push((field.len() >> 8) as u8);
+    body.push(field.len() as u8);
+    body.extend_from_slice(field);
+    Ok(())
+}
+
+fn make_packet(packet_type: u8, body: &[u8]) -> Result<Vec<u8>, StatusCode> {
+    if body.len() > MAX_REMAINING_LENGTH {
+        error!("MQTT packet of {} bytes is too large", body.len());
+        return Err(StatusCode::BadEncodingLimitsExceeded);
+    }
+    let mut packet = Vec::with_capacity(body.len() + 5);
+    packet.push(packet_type);
+    write_remaining_length(&mut packet, body.len());
+    packet.extend_from_slice(body);
+    Ok(packet)
+}
+
+/// Makes a CONNECT packet for a clean session with the keep alive disabled
+pub fn connect_packet(client_id: &str, user_name: Option<&str>, password: Option<&str>) -> Result<Vec<u8>, StatusCode> {
+    let mut flags = CONNECT_CLEAN_SESSION;
+    if user_name.is_some() {
+        flags |= CONNECT_USER_NAME;
+        if password.is_some() {
+            flags |= CONNECT_PASSWORD;
+        }
+    }
+    let mut body = Vec::new();
+    write_field(&mut body, b"MQTT")?;
+    body.push(PROTOCOL_LEVEL);
+    body.push(flags);
+    // Keep alive of 0 means the broker never disconnects an idle client
+    body.extend_from_slice(&[0, 0]);
+    write_field(&mut body, client_id.as_bytes())?;
+    if let Some(user_name) = user_name {
+        write_field(&mut body, user_name.as_bytes())?;
+        if let Some(password) = password {
+            write_field(&mut body, password.as_bytes())?;
+        }
+    }
+    make_packet(CONNECT, &body)
+}
+
+/// Makes a PUBLISH packet with QoS 0, which has no packet identifier
+pub fn publish_packet(topic: &str, payload: &[u8], retain: bool) -> Result<Vec<u8>, StatusCode> {
+    let mut body = Vec::with_capacity(topic.len() + payload.len() + 2);
+    write_field(&mut body, topic.as_bytes())?;
+    body.extend_from_slice(payload);
+    make_packet(if retain { PUBLISH | PUBLISH_RETAIN } else { PUBLISH }, &body)
+}
+
+/// A connection to an MQTT broker
+pub struct MqttClient {
+    stream: TcpStream,
+}
+
+impl MqttClient {
+    /// Connects to the broker and waits for it to accept the connection
+    pub fn connect(broker_url: &str, client_id: &str, user_name: Option<&str>, password: Option<&str>) -> Result<MqttClient, StatusCode> {
+        let (host, port) = broker_host_port(broker_url).map_err(|_| {
+            error!("MQTT broker url {} is invalid", broker_url);
+            StatusCode::BadTcpEndpointUrlInvalid
+        })?;
+        let addr = (host.as_ref(), port).to_socket_addrs().ok().and_then(|mut addrs| addrs.next()).ok_or_else(|| {
+            error!("Cannot resolve the address of MQTT broker {}", broker_url);
+            StatusCode::BadTcpEndpointUrlInvalid
+        })?;
+        let mut stream = TcpStream::connect_timeout(&addr, SOCKET_TIMEOUT).map_err(|err| {
+            error!("Cannot connect to MQTT broker {}, error = {:?}", broker_url, err);
+            StatusCode::BadCommunicationError
+        })?;
+        let _ = stream.set_read_timeout(Some(SOCKET_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(SOCKET_TIMEOUT));
+
+        let connect = connect_packet(client_id, user_name, password)?;
+        stream.write_all(&connect).map_err(|_| StatusCode::BadCommunicationError)?;
+
+        // The CONNACK holds the session present flag and the return code
+        let mut connack = [0u8; 4];
+        stream.read_exact(&mut connack).map_err(|_| StatusCode::BadCommunicationError)?;
+        if connack[0] != CONNACK || connack[1] != 2 {
+            error!("MQTT broker {} did not acknowledge the connection", broker_url);
+            Err(StatusCode::BadCommunicationError)
+        } else if connack[3] != 0 {
+            error!("MQTT broker {} refused the connection, return code = {}", broker_url, connack[3]);
+            if connack[3] == 4 || connack[3] == 5 {
+                Err(StatusCode::BadUserAccessDenied)
+            } else {
+                Err(StatusCode::BadConnectionRejected)
+            }
+        } else {
+            info!("Connected to MQTT broker {}", broker_url);
+            Ok(MqttClient { stream })
+        }
+    }
+
+    /// Publishes the payload to the topic
+    pub fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<(), StatusCode> {
+        let publish = publish_packet(topic, payload, retain)?;
+        self.stream.write_all(&publish).map_err(|err| {
+            error!("Cannot publish to MQTT topic {}, error = {:?}", topic, err);
+            StatusCode::BadCommunicationError
+        })
+    }
+
+    pub fn disconnect(mut self) {
+        let _ = self.stream.write_all(&[DISCONNECT, 0]);
+    }
+}
@@ -0,0 +1,140 @@
+//! The query evaluator used by the QueryFirst service, OPC UA Part 4 5.9.3. Instances in the
+//! address space are matched against the node type descriptions of the query and then kept if the
+//! content filter, Part 4 7.4, evaluates to true for them.
+
+use opcua_types::*;
+use opcua_types::status_code::StatusCode;
+use opcua_types::node_ids::ReferenceTypeId;
+use opcua_types::service_types::*;
+
+use crate::{
+    address_space::{AddressSpace, relative_path, node::HasNodeId},
+    callbacks::QueryEvaluator,
+    content_filter::{self, ContentFilterEvaluator, FilterTarget},
+};
+
+/// The default query evaluator. It searches every instance of the address space, so a server with
+/// a very large address space or one which holds its data elsewhere may wish to register its own
+/// evaluator with `ServerState::set_query_evaluator`.
+pub struct AddressSpaceQueryEvaluator;
+
+impl QueryEvaluator for AddressSpaceQueryEvaluator {
+    fn evaluate(&self, address_space: &AddressSpace, node_types: &[NodeTypeDescription], filter: &ContentFilter) -> Result<Vec<(NodeId, usize)>, StatusCode> {
+        let evaluator = ContentFilterEvaluator::new(filter)?;
+        let mut result = Vec::new();
+        for node in address_space.nodes() {
+            let node_id = node.node_id();
+            // Only instances have a type definition
+            if let Some(type_id) = address_space.get_type_id(&node_id) {
+                let node_type_idx = node_types.iter().position(|node_type| {
+                    let type_definition_id = &node_type.type_definition_node.node_id;
+                    if node_type.include_sub_types {
+                        address_space.is_subtype(&type_id, type_definition_id)
+                    } else {
+                        type_id == *type_definition_id
+                    }
+                });
+                if let Some(node_type_idx) = node_type_idx {
+                    let instance = InstanceTarget {
+                        address_space,
+                        node_id: &node_id,
+                        type_id: &type_id,
+                    };
+                    if evaluator.evaluate(&instance) {
+                        result.push((node_id, node_type_idx));
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// An instance in the address space that a content filter is evaluated against. Attribute
+/// operands are read from the node at the end of their path from the instance.
+pub struct InstanceTarget<'a> {
+    pub address_space: &'a AddressSpace,
+    pub node_id: &'a NodeId,
+    pub type_id: &'a NodeId,
+}
+
+impl<'a> InstanceTarget<'a> {
+    fn attribute_value(&self, relative_path: &RelativePath, attribute_id: u32) -> Variant {
+        if let Ok(attribute_id) = AttributeId::from_u32(attribute_id) {
+            find_relative_path_targets(self.address_space, self.node_id, relative_path).first()
+                .and_then(|node_id| read_attribute(self.address_space, node_id, attribute_id))
+                .unwrap_or(Variant::Empty)
+        } else {
+            Variant::Empty
+        }
+    }
+}
+
+impl<'a> FilterTarget for InstanceTarget<'a> {
+    fn is_of_type(&self, type_definition_id: &NodeId) -> bool {
+        self.address_space.is_subtype(self.type_id, type_definition_id)
+    }
+
+    fn attribute_operand(&self, operand: &AttributeOperand) -> Variant {
+        self.attribute_value(&operand.browse_path, operand.attribute_id)
+    }
+
+    fn simple_attribute_operand(&self, operand: &SimpleAttributeOperand) -> Variant {
+        // The browse path of a simple attribute operand follows hierarchical references
+        let elements = operand.browse_path.as_ref().map(|browse_path| {
+            browse_path.iter().map(|browse_name| RelativePathElement {
+                reference_type_id: ReferenceTypeId::HierarchicalReferences.into(),
+                is_inverse: false,
+                include_subtypes: true,
+                target_name: browse_name.clone(),
+            }).collect()
+        });
+        self.attribute_value(&RelativePath { elements }, operand.attribute_id)
+    }
+}
+
+/// Validates the node type descriptions of a query, returning a parsing result for each of them
+pub(crate) fn validate_node_types(address_space: &AddressSpace, node_types: &[NodeTypeDescription]) -> Vec<ParsingResult> {
+    node_types.iter().map(|node_type| {
+        let data_status_codes = node_type.data_to_return.as_ref().map(|data_to_return| {
+            data_to_return.iter().map(|data| {
+                if AttributeId::from_u32(data.attribute_id).is_err() {
+                    StatusCode::BadAttributeIdInvalid
+                } else if !data.index_range.is_null() {
+                    StatusCode::BadIndexRangeInvalid
+                } else {
+                    StatusCode::Good
+                }
+            }).collect::<Vec<StatusCode>>()
+        });
+        let status_code = if node_type.type_definition_node.server_index != 0 || !content_filter::is_type_node(address_space, &node_type.type_definition_node.node_id) {
+            StatusCode::BadNodeIdUnknown
+        } else if data_status_codes.as_ref().map_or(false, |codes| codes.iter().any(|status_code| status_code.is_bad())) {
+            StatusCode::BadInvalidArgument
+        } else {
+            StatusCode::Good
+        };
+        ParsingResult {
+            status_code,
+            data_status_codes,
+            data_diagnostic_infos: None,
+        }
+    }).collect()
+}
+
+/// Finds the nodes at the end of the relative path from the node. An empty path refers to the node
+/// itself.
+pub(crate) fn find_relative_path_targets(address_space: &AddressSpace, node_id: &NodeId, relative_path: &RelativePath) -> Vec<NodeId> {
+    if is_empty_option_vec!(relative_path.elements) {
+        vec![node_id.clone()]
+    } else {
+        relative_path::find_nodes_relative_path(address_space, node_id, relative_path).unwrap_or_default()
+    }
+}
+
+/// Reads the value of an attribute of the node, or `None` if the node or attribute does not exist
+pub(crate) fn read_attribute(address_space: &AddressSpace, node_id: &NodeId, attribute_id: AttributeId) -> Option<Variant> {
+    address_space.find_node(node_id)
+        .and_then(|node| node.as_node().get_attribute(attribute_id, 0f64))
+        .and_then(|data_value| data_value.value)
+}
@@ -0,0 +1,174 @@
+//! Role-based security as described in OPC UA Part 18. Users are mapped onto roles by the identity
+//! mapping rules of each role, and the RolePermissions attribute of a node says what each role may
+//! do to it.
+
+use opcua_types::*;
+use opcua_types::status_code::StatusCode;
+
+use opcua_core::crypto::X509;
+
+use crate::{
+    address_space::node::NodeType,
+    callbacks::UserIdentity,
+};
+
+/// A role that users are granted through its identity mapping rules
+#[derive(Debug, Clone)]
+pub struct Role {
+    role_id: NodeId,
+    name: String,
+    identities: Vec<IdentityMappingRuleType>,
+}
+
+impl Role {
+    pub fn new<T>(role_id: T, name: &str) -> Role where T: Into<NodeId> {
+        Role {
+            role_id: role_id.into(),
+            name: name.to_string(),
+            identities: Vec::new(),
+        }
+    }
+
+    pub fn role_id(&self) -> &NodeId {
+        &self.role_id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn identities(&self) -> &[IdentityMappingRuleType] {
+        &self.identities
+    }
+
+    /// Tests if any of the identity mapping rules of the role match the user
+    pub fn matches(&self, identity: &UserIdentity) -> bool {
+        self.identities.iter().any(|rule| Self::rule_matches(rule, identity))
+    }
+
+    fn rule_matches(rule: &IdentityMappingRuleType, identity: &UserIdentity) -> bool {
+        match (rule.criteria_type, identity) {
+            (IdentityCriteriaType::Anonymous, UserIdentity::Anonymous) => true,
+            (IdentityCriteriaType::AuthenticatedUser, UserIdentity::UserName(_)) |
+            (IdentityCriteriaType::AuthenticatedUser, UserIdentity::X509(_)) |
+            (IdentityCriteriaType::AuthenticatedUser, UserIdentity::IssuedToken(_)) => true,
+            (IdentityCriteriaType::UserName, UserIdentity::UserName(ref user_name)) => {
+                rule.criteria.as_ref() == user_name.as_str()
+            }
+            (IdentityCriteriaType::Thumbprint, UserIdentity::X509(ref certificate)) => {
+                if let Ok(certificate) = X509::from_byte_string(certificate) {
+                    rule.criteria.as_ref().eq_ignore_ascii_case(&certificate.thumbprint().as_hex_string())
+                } else {
+                    false
+                }
+            }
+            // Role and group claims of issued tokens are not understood
+            _ => false
+        }
+    }
+}
+
+/// The roles of the server, exposed beneath Server.ServerCapabilities.RoleSet in the address space.
+#[derive(Debug, Clone)]
+pub struct RoleSet {
+    roles: Vec<Role>,
+}
+
+impl Default for RoleSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RoleSet {
+    /// Creates a role set holding the well-known roles. Anonymous users are mapped onto the
+    /// Anonymous role and all other users onto the AuthenticatedUser role. The other roles have no
+    /// identity mapping rules until some are added.
+    pub fn new() -> RoleSet {
+        let roles = WellKnownRole::all().iter().map(|role| {
+            let mut result = Role::new(*role, role.name());
+            match *role {
+                WellKnownRole::Anonymous => {
+                    result.identities.push(IdentityMappingRuleType::new(IdentityCriteriaType::Anonymous, UAString::null()));
+                }
+                WellKnownRole::AuthenticatedUser => {
+                    result.identities.push(IdentityMappingRuleType::new(IdentityCriteriaType::AuthenticatedUser, UAString::null()));
+                }
+                _ => {}
+            }
+            result
+        }).collect();
+        RoleSet { roles }
+    }
+
+    pub fn roles(&self) -> &[Role] {
+        &self.roles
+    }
+
+    pub fn find_role(&self, role_id: &NodeId) -> Option<&Role> {
+        self.roles.iter().find(|role| role.role_id == *role_id)
+    }
+
+    /// Adds an identity mapping rule to a role. Adding a rule the role already has does nothing.
+    pub fn add_identity(&mut self, role_id: &NodeId, rule: IdentityMappingRuleType) -> Result<(), StatusCode> {
+        let role = self.roles.iter_mut().find(|role| role.role_id == *role_id).ok_or(StatusCode::BadNodeIdUnknown)?;
+        if !role.identities.contains(&rule) {
+            role.identities.push(rule);
+        }
+        Ok(())
+    }
+
+    /// Removes an identity mapping rule from a role
+    pub fn remove_identity(&mut self, role_id: &NodeId, rule: &IdentityMappingRuleType) -> Result<(), StatusCode> {
+        let role = self.roles.iter_mut().find(|role| role.role_id == *role_id).ok_or(StatusCode::BadNodeIdUnknown)?;
+        let len = role.identities.len();
+        role.identities.retain(|r| r != rule);
+        if role.identities.len() == len {
+            Err(StatusCode::BadNoMatch)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the ids of the roles that the user is granted
+    pub fn roles_for(&self, identity: &UserIdentity) -> Vec<NodeId> {
+        self.roles.iter()
+            .filter(|role| role.matches(identity))
+            .map(|role| role.role_id.clone())
+            .collect()
+    }
+}
+
+/// Returns the permissions that the roles have on the node, or `None` if the node has no
+/// RolePermissions and is therefore unrestricted.
+pub(crate) fn user_permissions(node: &NodeType, roles: &[NodeId]) -> Option<PermissionType> {
+    node.as_node().role_permissions().map(|role_permissions| {
+        role_permissions.iter()
+            .filter(|r| roles.contains(&r.role_id))
+            .fold(PermissionType::empty(), |permissions, r| permissions | r.permissions())
+    })
+}
+
+/// Tests if the roles have the permission on the node
+pub(crate) fn is_permitted(node: &NodeType, roles: &[NodeId], permission: PermissionType) -> bool {
+    user_permissions(node, roles).map_or(true, |permissions| permissions.contains(permission))
+}
+
+/// The permission needed to read the attribute
+pub(crate) fn read_permission(attribute_id: AttributeId) -> PermissionType {
+    match attribute_id {
+        AttributeId::Value => PermissionType::READ,
+        AttributeId::RolePermissions => PermissionType::READ_ROLE_PERMISSIONS,
+        _ => PermissionType::BROWSE
+    }
+}
+
+/// The permission needed to write the attribute
+pub(crate) fn write_permission(attribute_id: AttributeId) -> PermissionType {
+    match attribute_id {
+        AttributeId::Value => PermissionType::WRITE,
+        AttributeId::Historizing => PermissionType::WRITE_HISTORIZING,
+        AttributeId::RolePermissions => PermissionType::WRITE_ROLE_PERMISSIONS,
+        _ => PermissionType::WRITE_ATTRIBUTE
+    }
+}
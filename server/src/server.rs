@@ -6,31 +6,55 @@ use std::marker::Sync;
 use std::time::{Instant, Duration};
 use std::thread;
 
-use futures::{Future, Stream, future, sync::mpsc::{unbounded, UnboundedSender}};
+use futures::{Future, Stream, future, sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender}};
 use tokio::{self, net::{TcpListener, TcpStream}};
 use tokio_timer::Interval;
 
 use opcua_types::service_types::ServerState as ServerStateType;
+use opcua_types::tcp_types::ReverseHelloMessage;
 use opcua_core::config::Config;
 use opcua_core::prelude::*;
+use opcua_core::comms::buffer_pool::{BufferPool, DEFAULT_MAX_POOLED_BUFFERS};
 
+#[cfg(any(feature = "tls", feature = "https"))]
+use rustls::ServerSession;
+#[cfg(any(feature = "tls", feature = "https"))]
+use tokio_rustls::TlsStream;
+#[cfg(any(feature = "tls", feature = "https"))]
+use crate::comms::tls;
+#[cfg(feature = "https")]
+use crate::comms::https_transport::HttpsTransport;
 use crate::{
-    address_space::types::AddressSpace,
+    address_space::types::{AddressSpace, SharedAddressSpace},
+    aggregation,
+    bridge::{self, Bridge, BridgeRunner, BridgeTag},
     comms::tcp_transport::*,
     comms::transport::Transport,
-    config::ServerConfig,
+    config::{ServerConfig, TlsConfig},
     constants,
     diagnostics::ServerDiagnostics,
-    discovery,
+    discovery::{self, RegistrationSchedule},
     metrics::ServerMetrics,
+    pubsub,
+    roles::RoleSet,
+    server_configuration::CertificateManager,
     services::message_handler::MessageHandler,
     session::Session,
+    simulation,
     state::ServerState,
+    subscriptions::detached::DetachedSubscriptions,
     util::PollingAction,
 };
 
 pub type Connections = Vec<Arc<RwLock<TcpTransport>>>;
 
+/// The state of the server's reverse connection to a client
+enum ReverseConnection {
+    NotConnected,
+    Connecting,
+    Connected(Arc<RwLock<TcpTransport>>),
+}
+
 /// This represents a running instance of an OPC UA server. There can be more than one server running
 /// at a time providing they do not share the same thread or listen on the same ports.
 ///
@@ -51,8 +75,12 @@ pub type Connections = Vec<Arc<RwLock<TcpTransport>>>;
 pub struct Server {
     /// List of pending polling actions to add to the server once run is called
     pending_polling_actions: Vec<(u64, Box<dyn Fn() + Send + Sync + 'static>)>,
+    /// List of bridges to start once run is called
+    pending_bridges: Vec<BridgeRunner>,
     /// Certificate store for certs
     certificate_store: Arc<RwLock<CertificateStore>>,
+    /// Holds a certificate pushed through the ServerConfiguration object until it is applied
+    certificate_manager: Arc<RwLock<CertificateManager>>,
     /// Server metrics - diagnostics and anything else that someone might be interested in that
     /// describes the current state of the server
     server_metrics: Arc<RwLock<ServerMetrics>>,
@@ -60,7 +88,7 @@ pub struct Server {
     /// is initialised from a [`ServerConfig`].
     server_state: Arc<RwLock<ServerState>>,
     /// Address space
-    address_space: Arc<RwLock<AddressSpace>>,
+    address_space: Arc<SharedAddressSpace>,
     /// List of open connections
     connections: Arc<RwLock<Connections>>,
 }
@@ -85,12 +113,22 @@ impl Server {
         let application_name = config.application_name.clone();
         let application_uri = UAString::from(config.application_uri.as_ref());
         let product_uri = UAString::from(config.product_uri.as_ref());
-        let namespaces = vec!["http://opcfoundation.org/UA/".to_string(), "urn:OPCUA-Rust-Internal".to_string(), config.application_uri.clone()];
         let start_time = DateTime::now();
         let servers = vec![config.application_uri.clone()];
         let base_endpoint = format!("opc.tcp://{}:{}", config.tcp_config.host, config.tcp_config.port);
+        let max_sessions = config.max_sessions as usize;
+        let (min_session_timeout, max_session_timeout) = (config.min_session_timeout, config.max_session_timeout);
         let max_subscriptions = config.max_subscriptions as usize;
+        let max_monitored_items_per_subscription = config.max_monitored_items_per_subscription as usize;
+        let min_sampling_interval = config.min_sampling_interval;
+        let max_publish_requests = config.max_publish_requests as usize;
+        let max_browse_continuation_points = config.max_browse_continuation_points as usize;
+        let operational_limits = config.operational_limits.clone();
         let diagnostics = Arc::new(RwLock::new(ServerDiagnostics::default()));
+        // Pooled buffers hold an encoded message or a secured chunk. Buffers which grew larger
+        // than that are not kept.
+        let max_pooled_buffer_size = config.tcp_config.send_buffer_size * 2;
+        let buffer_pool = BufferPool::new(DEFAULT_MAX_POOLED_BUFFERS, max_pooled_buffer_size);
         // TODO max string, byte string and array lengths
 
         // Security, pki auto create cert
@@ -106,6 +144,11 @@ impl Server {
             info!("Server has chosen to auto trust client certificates. You do not want to do this in production code.");
             certificate_store.trust_unknown_certs = true;
         }
+        certificate_store.validation = config.certificate_validation.clone();
+
+        // Cert store
+        let certificate_store = Arc::new(RwLock::new(certificate_store));
+        let certificate_manager = Arc::new(RwLock::new(CertificateManager::new(certificate_store.clone(), config.application_description())));
 
         let config = Arc::new(RwLock::new(config.clone()));
 
@@ -116,7 +159,6 @@ impl Server {
                 locale: UAString::null(),
                 text: UAString::from(application_name),
             },
-            namespaces,
             servers,
             base_endpoint,
             state: ServerStateType::Shutdown,
@@ -125,41 +167,76 @@ impl Server {
             server_certificate,
             server_pkey,
             last_subscription_id: 0,
+            max_sessions,
+            min_session_timeout,
+            max_session_timeout,
             max_subscriptions,
+            max_monitored_items_per_subscription,
+            min_sampling_interval,
+            max_publish_requests,
+            max_browse_continuation_points,
             min_publishing_interval: constants::MIN_PUBLISHING_INTERVAL,
             default_keep_alive_count: constants::DEFAULT_KEEP_ALIVE_COUNT,
             max_keep_alive_count: constants::MAX_KEEP_ALIVE_COUNT,
             max_lifetime_count: constants::MAX_KEEP_ALIVE_COUNT * 3,
-            max_method_calls: constants::MAX_METHOD_CALLS,
-            max_nodes_per_node_management: constants::MAX_NODES_PER_NODE_MANAGEMENT,
-            max_browse_paths_per_translate: constants::MAX_BROWSE_PATHS_PER_TRANSLATE,
+            operational_limits,
             diagnostics,
+            role_set: Arc::new(RwLock::new(RoleSet::default())),
             abort: false,
+            shutdown_time: None,
+            shutdown_reason: LocalizedText::null(),
             register_nodes_callback: None,
             unregister_nodes_callback: None,
+            historical_data_provider: None,
+            issued_token_validator: None,
+            detached_subscriptions: DetachedSubscriptions::new(),
+            audit_log: None,
+            access_control: None,
+            query_evaluator: None,
+            server_callbacks: None,
+            buffer_pool,
         };
         let server_state = Arc::new(RwLock::new(server_state));
 
         // Set some values in the address space from the server state
-        let address_space = Arc::new(RwLock::new(AddressSpace::new()));
+        let address_space = Arc::new(SharedAddressSpace::new(AddressSpace::new()));
 
         {
             let mut address_space = trace_write_lock_unwrap!(address_space);
+            // The server's own namespace follows the OPC UA and internal namespaces, i.e. it is namespace 2
+            let application_uri = trace_read_lock_unwrap!(server_state).application_uri.as_ref().to_string();
+            let namespace = address_space.register_namespace(&application_uri).unwrap_or_else(|_| {
+                error!("Cannot register the application uri {} as a namespace", application_uri);
+                1
+            });
             address_space.set_server_state(server_state.clone());
+
+            // Variables of the config whose values are simulated
+            let simulated_variables = {
+                let server_state = trace_read_lock_unwrap!(server_state);
+                let config = trace_read_lock_unwrap!(server_state.config);
+                config.simulated_variables.clone()
+            };
+            simulation::add_simulated_variables(&mut address_space, namespace, &simulated_variables);
         }
 
         // Server metrics
         let server_metrics = Arc::new(RwLock::new(ServerMetrics::new()));
 
-        // Cert store
-        let certificate_store = Arc::new(RwLock::new(certificate_store));
+        // Push management of the server's certificate
+        #[cfg(feature = "generated-address-space")] {
+            let mut address_space = trace_write_lock_unwrap!(address_space);
+            crate::server_configuration::add_server_configuration(&mut address_space, certificate_manager.clone());
+        }
 
         let server = Server {
             pending_polling_actions: Vec::new(),
+            pending_bridges: Vec::new(),
             server_state,
             server_metrics: server_metrics.clone(),
             address_space,
             certificate_store,
+            certificate_manager,
             connections: Arc::new(RwLock::new(Vec::new())),
         };
 
@@ -179,36 +256,39 @@ impl Server {
     /// Runs the supplied server reference counted server. The function will block until the server
     /// terminates, i.e. all running tokio tasks finish.
     pub fn run_server(server: Arc<RwLock<Server>>) {
-        // Get the address and discovery url
-        let (sock_addr, discovery_server_url) = {
+        // Get the address and discovery urls
+        let (sock_addrs, (tls_sock_addr, https_sock_addr), discovery_server_urls) = {
             let server = trace_read_lock_unwrap!(server);
 
             // Debug endpoints
             server.log_endpoint_info();
 
-            let sock_addr = server.get_socket_address();
+            let sock_addrs = server.get_socket_addresses();
+            let tls_sock_addrs = server.get_tls_socket_addresses();
             let server_state = trace_read_lock_unwrap!(server.server_state);
             let config = trace_read_lock_unwrap!(server_state.config);
 
-            // Discovery url must be present and valid
-            let discovery_server_url = if let Some(ref discovery_server_url) = config.discovery_server_url {
-                if is_valid_opc_ua_url(discovery_server_url) {
-                    Some(discovery_server_url.clone())
-                } else {
-                    None
-                }
-            } else {
-                None
-            };
+            // Discovery urls must be valid
+            let discovery_server_urls = config.discovery_server_url.iter()
+                .chain(config.discovery_registration.discovery_server_urls.iter())
+                .filter(|discovery_server_url| {
+                    if is_valid_opc_ua_url(discovery_server_url) {
+                        true
+                    } else {
+                        error!("Discovery server url {} is not a valid OPC UA url and will be ignored", discovery_server_url);
+                        false
+                    }
+                })
+                .cloned()
+                .collect::<Vec<String>>();
 
-            (sock_addr, discovery_server_url)
+            (sock_addrs, tls_sock_addrs, discovery_server_urls)
         };
 
-        if sock_addr.is_none() {
+        if sock_addrs.is_empty() {
             error!("Cannot resolve server address, check configuration of server");
             return;
         }
-        let sock_addr = sock_addr.unwrap();
 
         // These are going to be used to abort the thread via the completion pack
 
@@ -218,7 +298,9 @@ impl Server {
             let server = server.clone();
             let server_for_listener = server.clone();
 
-            let (tx_abort, rx_abort) = unbounded::<()>();
+            // Each listener is broken out of its loop by its own abort channel
+            let listener_count = sock_addrs.len() + tls_sock_addr.iter().count() + https_sock_addr.iter().count();
+            let (tx_aborts, rx_aborts): (Vec<_>, Vec<_>) = (0..listener_count).map(|_| unbounded::<()>()).unzip();
 
             // Put the server into a running state
             future::lazy(move || {
@@ -232,41 +314,45 @@ impl Server {
                     }
 
                     // Start a timer that registers the server with a discovery server
-                    server.start_discovery_server_registration_timer(discovery_server_url);
+                    server.start_discovery_server_registration_timer(discovery_server_urls);
                     // Start any pending polling action timers
                     server.start_pending_polling_actions();
+                    // Start polling the bridges to external protocols
+                    server.start_pending_bridges();
+                    // Keep subscriptions of disconnected sessions alive until they are transferred or expire
+                    server.start_detached_subscriptions_timer();
+                    // Publish to the configured MQTT brokers
+                    server.start_pubsub_publishers();
+                    // Forward value changes to the configured MQTT brokers
+                    server.start_mqtt_forwarders();
+                    // Animate the simulated variables
+                    server.start_simulation();
+                    // Mirror the address spaces of the aggregated servers
+                    server.start_aggregation();
+                    // Serve the REST gateway
+                    server.start_rest_gateway();
                 }
 
+                // Start a timer that makes reverse connections to clients
+                Self::start_reverse_connect_timer(server.clone());
                 // Start a server abort task loop
-                Self::start_abort_poll(server, tx_abort);
+                Self::start_abort_poll(server, tx_aborts);
 
                 future::ok(())
             }).and_then(move |_| {
-                use crate::completion_pact::stream_completion_pact;
-                // Listen for connections
-                let listener = TcpListener::bind(&sock_addr).unwrap();
-                stream_completion_pact(listener.incoming(), rx_abort)
-                    .for_each(move |socket| {
-                        // Clear out dead sessions
-                        info!("Handling new connection {:?}", socket);
-                        let mut server = trace_write_lock_unwrap!(server_for_listener);
-                        // Check for abort
-                        if {
-                            let server_state = trace_read_lock_unwrap!(server.server_state);
-                            server_state.is_abort()
-                        } {
-                            info!("Server is aborting so it will not accept new connections");
-                        } else {
-                            server.handle_connection(socket);
-                        }
-                        Ok(())
-                    })
-                    .map(|_| {
-                        info!("Completion pact has completed");
-                    })
-                    .map_err(|err| {
-                        error!("Completion pact, incoming error = {:?}", err);
-                    })
+                // Listen for connections on each of the server's addresses
+                let mut rx_aborts = rx_aborts.into_iter();
+                let mut listeners = sock_addrs.into_iter().zip(rx_aborts.by_ref()).map(|(sock_addr, rx_abort)| {
+                    Self::listen(server_for_listener.clone(), sock_addr, rx_abort)
+                }).collect::<Vec<_>>();
+                // The opc.tls and opc.https transports have listeners of their own
+                if let Some(tls_sock_addr) = tls_sock_addr {
+                    listeners.push(Self::listen_tls(server_for_listener.clone(), tls_sock_addr, rx_aborts.next().unwrap()));
+                }
+                if let Some(https_sock_addr) = https_sock_addr {
+                    listeners.push(Self::listen_https(server_for_listener.clone(), https_sock_addr, rx_aborts.next().unwrap()));
+                }
+                future::join_all(listeners).map(|_| ())
             }).map(|_| {
                 info!("Server task is finished");
             }).map_err(|err| {
@@ -276,6 +362,140 @@ impl Server {
         info!("Server has stopped");
     }
 
+    /// Makes the task which listens for connections on a socket address until the abort channel
+    /// is signalled.
+    fn listen(server: Arc<RwLock<Server>>, sock_addr: SocketAddr, rx_abort: UnboundedReceiver<()>) -> Box<dyn Future<Item=(), Error=()> + Send> {
+        use crate::completion_pact::stream_completion_pact;
+        let listener = match TcpListener::bind(&sock_addr) {
+            Ok(listener) => listener,
+            Err(err) => {
+                error!("Server cannot listen for connections on {}, err = {:?}", sock_addr, err);
+                return Box::new(future::ok(()));
+            }
+        };
+        info!("Server is listening for connections on {}", sock_addr);
+        Box::new(stream_completion_pact(listener.incoming(), rx_abort)
+            .for_each(move |socket| {
+                // Clear out dead sessions
+                info!("Handling new connection {:?}", socket);
+                let mut server = trace_write_lock_unwrap!(server);
+                if server.can_accept_connection(&socket) {
+                    server.handle_connection(socket);
+                }
+                Ok(())
+            })
+            .map(|_| {
+                info!("Completion pact has completed");
+            })
+            .map_err(|err| {
+                error!("Completion pact, incoming error = {:?}", err);
+            }))
+    }
+
+    /// Makes the task which listens for opc.tls connections on a socket address until the abort
+    /// channel is signalled. Each connection performs a TLS handshake before its session starts.
+    #[cfg(feature = "tls")]
+    fn listen_tls(server: Arc<RwLock<Server>>, sock_addr: SocketAddr, rx_abort: UnboundedReceiver<()>) -> Box<dyn Future<Item=(), Error=()> + Send> {
+        let tls_config = {
+            let server = trace_read_lock_unwrap!(server);
+            let server_state = trace_read_lock_unwrap!(server.server_state);
+            let config = trace_read_lock_unwrap!(server_state.config);
+            config.tcp_config.tls.clone()
+        };
+        Self::listen_with_tls(server, sock_addr, tls_config, rx_abort, "opc.tls", |server, stream, client_address| {
+            let mut server = trace_write_lock_unwrap!(server);
+            server.handle_tls_connection(stream, client_address);
+        })
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn listen_tls(_server: Arc<RwLock<Server>>, sock_addr: SocketAddr, _rx_abort: UnboundedReceiver<()>) -> Box<dyn Future<Item=(), Error=()> + Send> {
+        error!("Server cannot listen for opc.tls connections on {} because it was built without the tls feature", sock_addr);
+        Box::new(future::ok(()))
+    }
+
+    /// Makes the task which listens for opc.https connections on a socket address until the abort
+    /// channel is signalled. Each connection performs a TLS handshake and then serves HTTP requests,
+    /// each of which carries one service call.
+    #[cfg(feature = "https")]
+    fn listen_https(server: Arc<RwLock<Server>>, sock_addr: SocketAddr, rx_abort: UnboundedReceiver<()>) -> Box<dyn Future<Item=(), Error=()> + Send> {
+        let (https_config, https_transport) = {
+            let server = trace_read_lock_unwrap!(server);
+            let https_config = {
+                let server_state = trace_read_lock_unwrap!(server.server_state);
+                let config = trace_read_lock_unwrap!(server_state.config);
+                config.tcp_config.https.clone()
+            };
+            (https_config, Arc::new(server.new_https_transport()))
+        };
+        Self::listen_with_tls(server, sock_addr, https_config, rx_abort, "opc.https", move |_, stream, client_address| {
+            HttpsTransport::serve(https_transport.clone(), stream, client_address);
+        })
+    }
+
+    #[cfg(not(feature = "https"))]
+    fn listen_https(_server: Arc<RwLock<Server>>, sock_addr: SocketAddr, _rx_abort: UnboundedReceiver<()>) -> Box<dyn Future<Item=(), Error=()> + Send> {
+        error!("Server cannot listen for opc.https connections on {} because it was built without the https feature", sock_addr);
+        Box::new(future::ok(()))
+    }
+
+    /// Makes the task which listens for connections of a TLS secured transport on a socket address
+    /// until the abort channel is signalled. Each connection performs a TLS handshake with the
+    /// configured certificate and is then passed to the handler.
+    #[cfg(any(feature = "tls", feature = "https"))]
+    fn listen_with_tls<F>(server: Arc<RwLock<Server>>, sock_addr: SocketAddr, tls_config: Option<TlsConfig>, rx_abort: UnboundedReceiver<()>, transport_name: &'static str, handler: F) -> Box<dyn Future<Item=(), Error=()> + Send>
+        where F: Fn(Arc<RwLock<Server>>, TlsStream<TcpStream, ServerSession>, SocketAddr) + Send + Sync + 'static {
+        use crate::completion_pact::stream_completion_pact;
+        let acceptor = match tls_config.as_ref().map(tls::make_tls_acceptor) {
+            Some(Ok(acceptor)) => acceptor,
+            Some(Err(err)) => {
+                error!("Server cannot listen for {} connections, err = {}", transport_name, err);
+                return Box::new(future::ok(()));
+            }
+            None => {
+                return Box::new(future::ok(()));
+            }
+        };
+        let listener = match TcpListener::bind(&sock_addr) {
+            Ok(listener) => listener,
+            Err(err) => {
+                error!("Server cannot listen for {} connections on {}, err = {:?}", transport_name, sock_addr, err);
+                return Box::new(future::ok(()));
+            }
+        };
+        info!("Server is listening for {} connections on {}", transport_name, sock_addr);
+        let handler = Arc::new(handler);
+        Box::new(stream_completion_pact(listener.incoming(), rx_abort)
+            .for_each(move |socket| {
+                info!("Handling new {} connection {:?}", transport_name, socket);
+                let can_accept = {
+                    let server = trace_read_lock_unwrap!(server);
+                    server.can_accept_connection(&socket)
+                };
+                if can_accept {
+                    // The handshake consumes the socket so the client address is taken first
+                    if let Ok(client_address) = socket.peer_addr() {
+                        let server = server.clone();
+                        let handler = handler.clone();
+                        tokio::spawn(acceptor.accept(socket)
+                            .map(move |stream| {
+                                handler(server, stream, client_address);
+                            })
+                            .map_err(move |err| {
+                                error!("TLS handshake with {} failed, err = {:?}", client_address, err);
+                            }));
+                    }
+                }
+                Ok(())
+            })
+            .map(move |_| {
+                info!("{} completion pact has completed", transport_name);
+            })
+            .map_err(move |err| {
+                error!("{} completion pact, incoming error = {:?}", transport_name, err);
+            }))
+    }
+
     pub fn server_state(&self) -> Arc<RwLock<ServerState>> {
         self.server_state.clone()
     }
@@ -284,7 +504,7 @@ impl Server {
         self.certificate_store.clone()
     }
 
-    pub fn address_space(&self) -> Arc<RwLock<AddressSpace>> {
+    pub fn address_space(&self) -> Arc<SharedAddressSpace> {
         self.address_space.clone()
     }
 
@@ -304,21 +524,70 @@ impl Server {
         server_state.abort();
     }
 
+    /// Reloads the server's own certificate and private key from the PKI directory, e.g. after the
+    /// certificate has been renewed. Endpoint descriptions and sessions use the new certificate
+    /// from now on and connections opened afterwards secure their channels with it. The existing
+    /// certificate is kept if the new one cannot be read.
+    ///
+    /// The revocation lists in the crl directory are reloaded too. Trusted and rejected client
+    /// certificates do not need to be reloaded because they are read from the PKI directory
+    /// whenever a certificate is validated.
+    pub fn reload_pki(&self) -> Result<(), String> {
+        let (server_certificate, server_pkey) = {
+            let certificate_store = trace_read_lock_unwrap!(self.certificate_store);
+            certificate_store.reload_crls();
+            certificate_store.read_own_cert_and_pkey()?
+        };
+        info!("Server has reloaded its certificate {}", CertificateStore::cert_file_name(&server_certificate));
+        let mut server_state = trace_write_lock_unwrap!(self.server_state);
+        server_state.server_certificate = Some(server_certificate);
+        server_state.server_pkey = Some(server_pkey);
+        Ok(())
+    }
+
+    /// Shuts down the server after the delay. The server state becomes `Shutdown` with the
+    /// seconds till shutdown and the reason exposed through the `ServerStatus` variables, and the
+    /// server stops accepting new connections. When the delay has elapsed each subscription
+    /// answers a queued publish request with a `BadShutdown` status change notification and then
+    /// the server aborts, closing the remaining connections.
+    pub fn shutdown<T>(&mut self, delay: Duration, reason: T) where T: Into<LocalizedText> {
+        info!("Server has been instructed to shut down");
+        let mut server_state = trace_write_lock_unwrap!(self.server_state);
+        server_state.begin_shutdown(delay, reason.into());
+    }
+
     /// Strip out dead connections, i.e those which have disconnected. Returns `true` if there are
     /// still open connections after this function completes.
     fn remove_dead_connections(&self) -> bool {
         // Go through all connections, removing those that have terminated
         let mut connections = trace_write_lock_unwrap!(self.connections);
+        let mut closed_sessions = Vec::new();
         connections.retain(|connection| {
             // Try to obtain the lock on the transport and the session and check if session is terminated
             // if it is, then we'll use its termination status to sweep it out.
             let mut lock = connection.try_read();
             if let Ok(ref mut connection) = lock {
-                !connection.is_session_terminated()
+                if connection.is_session_terminated() {
+                    closed_sessions.push(connection.session());
+                    false
+                } else {
+                    true
+                }
             } else {
                 true
             }
         });
+        // Sessions which were not closed by CloseSession are closed with their connection. The
+        // server state is locked before the sessions.
+        if !closed_sessions.is_empty() {
+            let mut server_state = trace_write_lock_unwrap!(self.server_state);
+            for session in closed_sessions {
+                let session = trace_read_lock_unwrap!(session);
+                if !session.authentication_token.is_null() {
+                    server_state.on_session_closed(&session);
+                }
+            }
+        }
         !connections.is_empty()
     }
 
@@ -339,23 +608,57 @@ impl Server {
         }
     }
 
-    fn get_socket_address(&self) -> Option<SocketAddr> {
+    /// Resolves the addresses that the server listens on, its host and port followed by any
+    /// additional listen addresses. Addresses which do not resolve are logged and skipped.
+    fn get_socket_addresses(&self) -> Vec<SocketAddr> {
         use std::net::ToSocketAddrs;
         let server_state = trace_read_lock_unwrap!(self.server_state);
         let config = trace_read_lock_unwrap!(server_state.config);
-        // Resolve this host / port to an address (or not)
-        let address = format!("{}:{}", config.tcp_config.host, config.tcp_config.port);
-        if let Ok(mut addrs_iter) = address.to_socket_addrs() {
-            addrs_iter.next()
-        } else {
-            None
+        let tcp_config = &config.tcp_config;
+        let mut sock_addrs: Vec<SocketAddr> = Vec::with_capacity(1 + tcp_config.listen_addresses.len());
+        let listen_addresses = std::iter::once((tcp_config.host.as_str(), tcp_config.port))
+            .chain(tcp_config.listen_addresses.iter().map(|a| (a.host.as_str(), a.port)));
+        for (host, port) in listen_addresses {
+            // Resolve this host / port to an address (or not)
+            match (host, port).to_socket_addrs().map(|mut addrs_iter| addrs_iter.next()) {
+                Ok(Some(sock_addr)) => {
+                    if !sock_addrs.contains(&sock_addr) {
+                        sock_addrs.push(sock_addr);
+                    }
+                }
+                _ => {
+                    error!("Cannot resolve listen address {}, port {}", host, port);
+                }
+            }
         }
+        sock_addrs
+    }
+
+    /// Resolves the addresses that the opc.tls and opc.https transports listen on, the server's
+    /// host and the port of each transport, or none for a transport which is not configured.
+    fn get_tls_socket_addresses(&self) -> (Option<SocketAddr>, Option<SocketAddr>) {
+        use std::net::ToSocketAddrs;
+        let server_state = trace_read_lock_unwrap!(self.server_state);
+        let config = trace_read_lock_unwrap!(server_state.config);
+        let tcp_config = &config.tcp_config;
+        let resolve = |tls_config: &Option<TlsConfig>| {
+            tls_config.as_ref().and_then(|tls_config| {
+                match (tcp_config.host.as_str(), tls_config.port).to_socket_addrs().map(|mut addrs_iter| addrs_iter.next()) {
+                    Ok(Some(sock_addr)) => Some(sock_addr),
+                    _ => {
+                        error!("Cannot resolve TLS listen address {}, port {}", tcp_config.host, tls_config.port);
+                        None
+                    }
+                }
+            })
+        };
+        (resolve(&tcp_config.tls), resolve(&tcp_config.https))
     }
 
     // This timer will poll the server to see if it has aborted. It also cleans up dead connections.
     // If it determines to abort it will signal the tx_abort so that the main listener loop can
     // be broken at its convenience.
-    fn start_abort_poll(server: Arc<RwLock<Server>>, tx_abort: UnboundedSender<()>) {
+    fn start_abort_poll(server: Arc<RwLock<Server>>, tx_aborts: Vec<UnboundedSender<()>>) {
         let task = Interval::new(Instant::now(), Duration::from_millis(1000))
             .take_while(move |_| {
                 trace!("abort_poll_task.take_while");
@@ -363,6 +666,27 @@ impl Server {
                     // Check if there are any open sessions
                     let server = trace_read_lock_unwrap!(server);
                     let has_open_connections = server.remove_dead_connections();
+                    // A certificate pushed through ServerConfiguration.ApplyChanges is loaded here,
+                    // since the method is called while the server state is locked
+                    let reload_required = trace_write_lock_unwrap!(server.certificate_manager).take_reload_required();
+                    if reload_required {
+                        if let Err(err) = server.reload_pki() {
+                            error!("Server cannot reload its certificate after ApplyChanges, {}", err);
+                        }
+                    }
+                    // A shutdown aborts once the final publish responses have had time to go out
+                    let shutdown_complete = {
+                        let server_state = trace_read_lock_unwrap!(server.server_state);
+                        let final_publish = chrono::Duration::milliseconds(constants::SHUTDOWN_FINAL_PUBLISH_MS);
+                        server_state.is_shutdown_due(&(chrono::Utc::now() - final_publish))
+                    };
+                    if shutdown_complete {
+                        let mut server_state = trace_write_lock_unwrap!(server.server_state);
+                        if !server_state.is_abort() {
+                            info!("Server shutdown is complete");
+                            server_state.abort();
+                        }
+                    }
                     let server_state = trace_read_lock_unwrap!(server.server_state);
                     // Predicate breaks take_while on abort & no open connections
                     if server_state.is_abort() {
@@ -375,8 +699,10 @@ impl Server {
                     }
                 };
                 if abort {
-                    info!("Server has aborted so, sending a command to break the listen loop");
-                    tx_abort.unbounded_send(()).unwrap();
+                    info!("Server has aborted so, sending a command to break the listen loops");
+                    tx_aborts.iter().for_each(|tx_abort| {
+                        let _ = tx_abort.unbounded_send(());
+                    });
                 }
                 future::ok(!abort)
             })
@@ -394,22 +720,118 @@ impl Server {
         tokio::spawn(task);
     }
 
-    /// Start a timer that triggers every 5 minutes and causes the server to register itself with a discovery server
-    fn start_discovery_server_registration_timer(&self, discovery_server_url: Option<String>) {
-        if let Some(discovery_server_url) = discovery_server_url {
-            info!("Server has set a discovery server url {} which will be used to register the server", discovery_server_url);
+    /// Start a timer that makes reverse connections to the clients in the server's
+    /// `reverse_connect_urls`. Each client is connected to whenever it has no connection, so a new
+    /// connection is made after the previous one closes or fails to connect.
+    fn start_reverse_connect_timer(server: Arc<RwLock<Server>>) {
+        let (server_state, client_urls, reverse_hello) = {
+            let server = trace_read_lock_unwrap!(server);
+            let server_state = trace_read_lock_unwrap!(server.server_state);
+            let config = trace_read_lock_unwrap!(server_state.config);
+            let reverse_hello = ReverseHelloMessage::new(&config.application_uri, &config.base_endpoint_url());
+            (server.server_state.clone(), config.reverse_connect_urls.clone(), reverse_hello)
+        };
+        if !client_urls.is_empty() {
+            let clients = client_urls.into_iter().map(|client_url| {
+                info!("Server will make reverse connections to client {}", client_url);
+                (client_url, Arc::new(RwLock::new(ReverseConnection::NotConnected)))
+            }).collect::<Vec<_>>();
+
+            let task = Interval::new(Instant::now(), Duration::from_millis(constants::REVERSE_CONNECT_POLL_MS))
+                .take_while(move |_| {
+                    trace!("reverse_connect.take_while");
+                    let server_state = trace_read_lock_unwrap!(server_state);
+                    future::ok(server_state.is_running() && !server_state.is_abort())
+                })
+                .for_each(move |_| {
+                    trace!("reverse_connect.for_each");
+                    for (client_url, reverse_connection) in &clients {
+                        let connect = match *trace_read_lock_unwrap!(reverse_connection) {
+                            ReverseConnection::NotConnected => true,
+                            ReverseConnection::Connecting => false,
+                            ReverseConnection::Connected(ref connection) => {
+                                let connection = trace_read_lock_unwrap!(connection);
+                                connection.is_finished()
+                            }
+                        };
+                        if connect {
+                            if let Some(addr) = Self::client_socket_address(client_url) {
+                                *trace_write_lock_unwrap!(reverse_connection) = ReverseConnection::Connecting;
+                                Self::reverse_connect(server.clone(), client_url.clone(), addr, reverse_hello.clone(), reverse_connection.clone());
+                            }
+                        }
+                    }
+                    Ok(())
+                })
+                .map(|_| {
+                    info!("Reverse connect timer task is finished");
+                })
+                .map_err(|err| {
+                    error!("Reverse connect timer task error = {:?}", err);
+                });
+            tokio::spawn(task);
+        }
+    }
+
+    /// Resolves the url of a client into the socket address to make a reverse connection to
+    fn client_socket_address(client_url: &str) -> Option<SocketAddr> {
+        use std::net::ToSocketAddrs;
+        if let Ok((host, port)) = hostname_port_from_url(client_url) {
+            if let Ok(mut addrs_iter) = (host.as_str(), port).to_socket_addrs() {
+                return addrs_iter.next();
+            }
+        }
+        error!("Reverse connect url {} does not resolve to an address", client_url);
+        None
+    }
+
+    /// Connects to a client, sends it a ReverseHello and hands the socket to a new transport
+    /// which processes it the same as a connection the client made.
+    fn reverse_connect(server: Arc<RwLock<Server>>, client_url: String, addr: SocketAddr, reverse_hello: ReverseHelloMessage, reverse_connection: Arc<RwLock<ReverseConnection>>) {
+        debug!("Making reverse connection to client {}", client_url);
+        let task = TcpStream::connect(&addr)
+            .and_then(move |socket| {
+                tokio_io::io::write_all(socket, reverse_hello.to_vec())
+            })
+            .then(move |result| {
+                match result {
+                    Ok((socket, _)) => {
+                        info!("Made reverse connection to client {}", client_url);
+                        let mut server = trace_write_lock_unwrap!(server);
+                        let connection = server.handle_connection(socket);
+                        *trace_write_lock_unwrap!(reverse_connection) = ReverseConnection::Connected(connection);
+                    }
+                    Err(err) => {
+                        error!("Cannot make reverse connection to client {}, error = {:?}", client_url, err);
+                        *trace_write_lock_unwrap!(reverse_connection) = ReverseConnection::NotConnected;
+                    }
+                }
+                Ok(())
+            });
+        tokio::spawn(task);
+    }
+
+    /// Start a timer that causes the server to register itself with each discovery server on the
+    /// configured register interval, retrying failed registrations according to the retry policy.
+    fn start_discovery_server_registration_timer(&self, discovery_server_urls: Vec<String>) {
+        if !discovery_server_urls.is_empty() {
             let server_state = self.server_state.clone();
             let server_state_for_take = self.server_state.clone();
 
-            // The registration timer fires on a duration, so make that duration and pretend the
-            // last time it fired was now - duration, so it should instantly fire when polled next.
-            let register_duration = Duration::from_secs(5 * 60);
-            let last_registered = Instant::now() - register_duration;
-            let last_registered = Arc::new(Mutex::new(last_registered));
+            // Each discovery server has its own schedule, the first registration being due
+            // straight away.
+            let schedules = {
+                let server_state = trace_read_lock_unwrap!(self.server_state);
+                let config = trace_read_lock_unwrap!(server_state.config);
+                let now = Instant::now();
+                discovery_server_urls.into_iter().map(|discovery_server_url| {
+                    info!("Server has set a discovery server url {} which will be used to register the server", discovery_server_url);
+                    (discovery_server_url, Arc::new(Mutex::new(RegistrationSchedule::new(&config.discovery_registration, now))))
+                }).collect::<Vec<_>>()
+            };
 
             // Polling happens fairly quickly so task can terminate on server abort, however
-            // it is looking for the registration duration to have elapsed until it actually does
-            // anything.
+            // it is looking for a registration to be due until it actually does anything.
             let task = Interval::new(Instant::now(), Duration::from_millis(1000))
                 .take_while(move |_| {
                     trace!("discovery_server_register.take_while");
@@ -417,27 +839,30 @@ impl Server {
                     future::ok(server_state.is_running() && !server_state.is_abort())
                 })
                 .for_each(move |_| {
-                    // Test if registration needs to happen, i.e. if this is first time around,
-                    // or if duration has elapsed since last attempt.
                     trace!("discovery_server_register.for_each");
                     let now = Instant::now();
-                    let mut last_registered = trace_lock_unwrap!(last_registered);
-                    if now.duration_since(*last_registered) >= register_duration {
-                        *last_registered = now;
+                    for (discovery_server_url, schedule) in &schedules {
+                        {
+                            let mut schedule = trace_lock_unwrap!(schedule);
+                            if !schedule.is_due(now) {
+                                continue;
+                            }
+                            schedule.start();
+                        }
                         // Even though the client uses tokio internally, the client's API is synchronous
-                        // so the registration will happen on its own thread. The expectation is that
-                        // it will run and either succeed, or it will fail but either way the operation
-                        // will have completed before the next timer fires.
+                        // so the registration will happen on its own thread. The schedule is updated
+                        // with the outcome when the registration completes.
                         let server_state = server_state.clone();
                         let discovery_server_url = discovery_server_url.clone();
+                        let schedule = schedule.clone();
                         let _ = thread::spawn(move || {
                             use std;
-                            let _ = std::panic::catch_unwind(move || {
+                            let registered = std::panic::catch_unwind(move || {
                                 let server_state = trace_read_lock_unwrap!(server_state);
-                                if server_state.is_running() {
-                                    discovery::register_with_discovery_server(&discovery_server_url, &server_state);
-                                }
-                            });
+                                server_state.is_running() && discovery::register_with_discovery_server(&discovery_server_url, &server_state)
+                            }).unwrap_or(false);
+                            let mut schedule = trace_lock_unwrap!(schedule);
+                            schedule.finish(Instant::now(), registered);
                         });
                     }
                     Ok(())
@@ -474,6 +899,31 @@ impl Server {
         }
     }
 
+    /// Adds a bridge to an external protocol which the server connects and then polls every
+    /// `poll_interval` milliseconds while it is running, setting the values of the variables that
+    /// the tags are mapped to. The variables must already be in the address space.
+    pub fn add_bridge<B>(&mut self, bridge: B, tags: Vec<BridgeTag>, poll_interval: u64)
+        where B: Bridge + Send + 'static {
+        let runner = BridgeRunner::new(bridge, tags, poll_interval);
+        // If the server is not yet running, the bridge is queued and is started later
+        let server_state = trace_read_lock_unwrap!(self.server_state);
+        if server_state.is_abort() {
+            error!("Bridge {} added when server is aborting", runner.name());
+        } else if !server_state.is_running() {
+            self.pending_bridges.push(runner);
+        } else {
+            bridge::start_bridge(self.server_state.clone(), self.address_space.clone(), runner);
+        }
+    }
+
+    /// Starts any bridges which were queued ready to start but not yet
+    fn start_pending_bridges(&mut self) {
+        for runner in self.pending_bridges.drain(..) {
+            info!("Server will poll bridge {} every {} ms", runner.name(), runner.poll_interval());
+            bridge::start_bridge(self.server_state.clone(), self.address_space.clone(), runner);
+        }
+    }
+
     /// Starts any polling actions which were queued ready to start but not yet
     fn start_pending_polling_actions(&mut self) {
         let server_state = self.server_state.clone();
@@ -488,6 +938,89 @@ impl Server {
             });
     }
 
+    /// Starts a timer that ticks subscriptions detached from their sessions, so their monitored
+    /// items keep sampling until the subscriptions are transferred to a new session or expire.
+    fn start_detached_subscriptions_timer(&self) {
+        let server_state = self.server_state.clone();
+        let address_space = self.address_space.clone();
+        let _ = PollingAction::spawn(self.server_state.clone(), constants::SUBSCRIPTION_TIMER_RATE_MS, move || {
+            let mut server_state = trace_write_lock_unwrap!(server_state);
+            if !server_state.detached_subscriptions.is_empty() {
+                let address_space = trace_read_lock_unwrap!(address_space);
+                server_state.detached_subscriptions.tick(&chrono::Utc::now(), &address_space);
+            }
+        });
+    }
+
+    /// Starts a publisher for each PubSub connection in the config
+    fn start_pubsub_publishers(&self) {
+        let pubsub_connections = {
+            let server_state = trace_read_lock_unwrap!(self.server_state);
+            let config = trace_read_lock_unwrap!(server_state.config);
+            config.pubsub_connections.clone()
+        };
+        for pubsub_connection in pubsub_connections {
+            info!("Server will publish to MQTT broker {}", pubsub_connection.broker_url);
+            pubsub::start_publisher(self.server_state.clone(), self.address_space.clone(), pubsub_connection);
+        }
+    }
+
+    /// Starts a forwarder for each MQTT forwarder in the config
+    fn start_mqtt_forwarders(&self) {
+        let mqtt_forwarders = {
+            let server_state = trace_read_lock_unwrap!(self.server_state);
+            let config = trace_read_lock_unwrap!(server_state.config);
+            config.mqtt_forwarders.clone()
+        };
+        for mqtt_forwarder in mqtt_forwarders {
+            info!("Server will forward {} variables to MQTT broker {}", mqtt_forwarder.variables.len(), mqtt_forwarder.broker_url);
+            pubsub::forwarder::start_forwarder(self.server_state.clone(), self.address_space.clone(), mqtt_forwarder);
+        }
+    }
+
+    /// Starts updating the values of the simulated variables in the config
+    fn start_simulation(&self) {
+        let simulated_variables = {
+            let server_state = trace_read_lock_unwrap!(self.server_state);
+            let config = trace_read_lock_unwrap!(server_state.config);
+            config.simulated_variables.clone()
+        };
+        if !simulated_variables.is_empty() {
+            info!("Server will simulate the values of {} variables", simulated_variables.len());
+            simulation::start_simulation(self.server_state.clone(), self.address_space.clone(), simulated_variables);
+        }
+    }
+
+    /// Starts connecting to and mirroring the aggregated servers in the config
+    fn start_aggregation(&self) {
+        let aggregated_servers = {
+            let server_state = trace_read_lock_unwrap!(self.server_state);
+            let config = trace_read_lock_unwrap!(server_state.config);
+            config.aggregated_servers.clone()
+        };
+        for aggregated_server in aggregated_servers {
+            info!("Server will aggregate server {} in folder {}", aggregated_server.url, aggregated_server.name);
+            aggregation::start_aggregation(self.server_state.clone(), self.address_space.clone(), aggregated_server);
+        }
+    }
+
+    /// Starts the REST gateway if the config has one
+    #[cfg(feature = "http")]
+    fn start_rest_gateway(&self) {
+        let rest_gateway = {
+            let server_state = trace_read_lock_unwrap!(self.server_state);
+            let config = trace_read_lock_unwrap!(server_state.config);
+            config.rest_gateway.clone()
+        };
+        if let Some(rest_gateway) = rest_gateway {
+            crate::http::rest::run_rest_gateway(&rest_gateway.address, self.server_state.clone(), self.address_space.clone());
+        }
+    }
+
+    /// The REST gateway requires the http feature, which the config validation reports
+    #[cfg(not(feature = "http"))]
+    fn start_rest_gateway(&self) {}
+
     pub fn new_transport(&self) -> TcpTransport {
         let session = {
             Arc::new(RwLock::new(Session::new(self)))
@@ -499,8 +1032,75 @@ impl Server {
         TcpTransport::new(self.server_state.clone(), session, address_space, message_handler)
     }
 
+    /// Makes the opc.https transport, which makes a session for each client that creates one
+    #[cfg(feature = "https")]
+    fn new_https_transport(&self) -> HttpsTransport {
+        let server_state = self.server_state.clone();
+        let certificate_store = self.certificate_store.clone();
+        let address_space = self.address_space.clone();
+        HttpsTransport::new(self.server_state.clone(), move || {
+            let session = Arc::new(RwLock::new(Session::new_with_state(&server_state, certificate_store.clone())));
+            let message_handler = MessageHandler::new(certificate_store.clone(), server_state.clone(), session.clone(), address_space.clone());
+            (session, message_handler)
+        })
+    }
+
+    /// Tests if a new connection on the socket may be accepted. Connections are refused while the
+    /// server is aborting or shutting down, or from a client at its connection limit.
+    fn can_accept_connection(&self, socket: &TcpStream) -> bool {
+        // Check for abort or shutdown
+        if {
+            let server_state = trace_read_lock_unwrap!(self.server_state);
+            server_state.is_abort() || server_state.is_shutting_down()
+        } {
+            info!("Server is aborting or shutting down so it will not accept new connections");
+            false
+        } else if self.is_client_at_connection_limit(socket) {
+            warn!("Server is refusing a connection from {:?} which already has the maximum number of connections open", socket.peer_addr());
+            false
+        } else if let Err(status_code) = self.is_client_refused(socket) {
+            info!("Server is refusing a connection from {:?} which its callbacks refused, status code = {}", socket.peer_addr(), status_code);
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Asks the server callbacks, if there are any, whether the client connecting on the socket is
+    /// accepted
+    fn is_client_refused(&self, socket: &TcpStream) -> Result<(), StatusCode> {
+        if let Ok(client_address) = socket.peer_addr() {
+            let mut server_state = trace_write_lock_unwrap!(self.server_state);
+            server_state.on_client_connected(&client_address)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Tests if the client connecting on the socket already has as many connections open as the
+    /// configured limit for one client address.
+    fn is_client_at_connection_limit(&self, socket: &TcpStream) -> bool {
+        let max_connections_per_client = {
+            let server_state = trace_read_lock_unwrap!(self.server_state);
+            let config = trace_read_lock_unwrap!(server_state.config);
+            config.tcp_config.max_connections_per_client
+        };
+        if max_connections_per_client == 0 {
+            false
+        } else if let Ok(peer_addr) = socket.peer_addr() {
+            let connections = trace_read_lock_unwrap!(self.connections);
+            let client_connections = connections.iter().filter(|connection| {
+                let connection = trace_read_lock_unwrap!(connection);
+                !connection.is_finished() && connection.client_address().map(|address| address.ip()) == Some(peer_addr.ip())
+            }).count();
+            client_connections >= max_connections_per_client
+        } else {
+            false
+        }
+    }
+
     /// Handles the incoming request
-    fn handle_connection(&mut self, socket: TcpStream) {
+    fn handle_connection(&mut self, socket: TcpStream) -> Arc<RwLock<TcpTransport>> {
         trace!("Connection thread spawning");
 
         // Spawn a thread for the connection
@@ -511,6 +1111,23 @@ impl Server {
         }
 
         // Run adds a session task to the tokio session
-        TcpTransport::run(connection, socket);
+        TcpTransport::run(connection.clone(), socket);
+        connection
+    }
+
+    /// Handles an incoming opc.tls connection whose TLS handshake has completed
+    #[cfg(feature = "tls")]
+    fn handle_tls_connection<S>(&mut self, stream: S, client_address: SocketAddr) -> Arc<RwLock<TcpTransport>>
+        where S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + 'static {
+        trace!("TLS connection thread spawning");
+
+        let connection = Arc::new(RwLock::new(self.new_transport()));
+        {
+            let mut connections = trace_write_lock_unwrap!(self.connections);
+            connections.push(connection.clone());
+        }
+
+        TcpTransport::run_stream(connection.clone(), stream, client_address, true);
+        connection
     }
 }
@@ -0,0 +1,409 @@
+//! The ServerConfiguration object of OPC UA Part 12 7.7.3, through which a global discovery server
+//! or an administrator pushes a new application instance certificate to the server. The methods
+//! operate on the server's PKI directory and a pushed certificate only takes effect when
+//! ApplyChanges is called, after which the server reloads its certificate and private key.
+
+use std::sync::{Arc, RwLock};
+
+use opcua_types::*;
+use opcua_types::argument::Argument;
+use opcua_types::node_ids::{DataTypeId, MethodId, ObjectId, ObjectTypeId, ReferenceTypeId};
+use opcua_types::status_code::StatusCode;
+use opcua_types::service_types::{ApplicationDescription, CallMethodRequest, CallMethodResult};
+
+use opcua_core::crypto::{CertificateStore, KeySize, PrivateKey, X509, X509Data};
+
+use crate::{
+    address_space::types::{AddressSpace, Object, ReferenceDirection},
+    callbacks::Method,
+    session::Session,
+    trust_list,
+};
+
+/// The only private key format that UpdateCertificate accepts
+const PRIVATE_KEY_FORMAT_PEM: &str = "PEM";
+
+/// The node id of the DefaultApplicationGroup, the only certificate group of the server
+const DEFAULT_APPLICATION_GROUP_ID: ObjectId = ObjectId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup;
+
+/// A certificate and key that have been pushed by UpdateCertificate and are waiting for
+/// ApplyChanges.
+struct PendingUpdate {
+    certificate: X509,
+    issuer_certificates: Vec<X509>,
+    private_key: PrivateKey,
+}
+
+/// Holds the state of a push of a new certificate between the calls of the ServerConfiguration
+/// methods.
+pub(crate) struct CertificateManager {
+    certificate_store: Arc<RwLock<CertificateStore>>,
+    /// The description of the server, from which the subject of a signing request is made
+    application_description: ApplicationDescription,
+    /// A private key generated by CreateSigningRequest, for the cert that will be issued for it
+    new_private_key: Option<PrivateKey>,
+    /// The certificate waiting for ApplyChanges
+    pending_update: Option<PendingUpdate>,
+    /// Set when changes have been applied and the server must reload its certificate
+    reload_required: bool,
+}
+
+impl CertificateManager {
+    pub fn new(certificate_store: Arc<RwLock<CertificateStore>>, application_description: ApplicationDescription) -> CertificateManager {
+        CertificateManager {
+            certificate_store,
+            application_description,
+            new_private_key: None,
+            pending_update: None,
+            reload_required: false,
+        }
+    }
+
+    /// Returns `true` once after ApplyChanges has written a new certificate
+    pub fn take_reload_required(&mut self) -> bool {
+        let reload_required = self.reload_required;
+        self.reload_required = false;
+        reload_required
+    }
+
+    fn update_certificate(&mut self, certificate: &ByteString, issuer_certificates: &[ByteString], private_key_format: &UAString, private_key: &ByteString) -> Result<bool, StatusCode> {
+        let certificate = X509::from_byte_string(certificate)?;
+        let issuer_certificates = issuer_certificates.iter()
+            .map(|c| X509::from_byte_string(c))
+            .collect::<Result<Vec<X509>, StatusCode>>()?;
+
+        // The key is either supplied, was generated by CreateSigningRequest or is the current one
+        let private_key = if !private_key.is_null_or_empty() {
+            if private_key_format.as_ref() != PRIVATE_KEY_FORMAT_PEM {
+                error!("UpdateCertificate was passed a private key in unsupported format {}", private_key_format);
+                return Err(StatusCode::BadNotSupported);
+            }
+            PrivateKey::from_pem(private_key.as_ref()).map_err(|_| StatusCode::BadSecurityChecksFailed)?
+        } else if let Some(private_key) = self.new_private_key.take() {
+            private_key
+        } else {
+            let certificate_store = trace_read_lock_unwrap!(self.certificate_store);
+            let (_, private_key) = certificate_store.read_own_cert_and_pkey().map_err(|err| {
+                error!("UpdateCertificate cannot read the current private key, {}", err);
+                StatusCode::BadSecurityChecksFailed
+            })?;
+            private_key
+        };
+
+        // The certificate must be for the key
+        let certificate_public_key = certificate.public_key()?.public_key_to_der().map_err(|_| StatusCode::BadCertificateInvalid)?;
+        let public_key = private_key.public_key_to_der().map_err(|_| StatusCode::BadSecurityChecksFailed)?;
+        if certificate_public_key != public_key {
+            error!("UpdateCertificate was passed a certificate which does not belong to the private key");
+            return Err(StatusCode::BadSecurityChecksFailed);
+        }
+
+        // The certificate must be complete with the issuers supplied
+        if !certificate.is_self_issued() && !issuer_certificates.iter().any(|issuer| certificate.is_issued_by(issuer)) {
+            error!("UpdateCertificate was passed a certificate whose issuer was not supplied");
+            return Err(StatusCode::BadCertificateChainIncomplete);
+        }
+
+        self.pending_update = Some(PendingUpdate {
+            certificate,
+            issuer_certificates,
+            private_key,
+        });
+        // ApplyChanges must be called for the certificate to be used
+        Ok(true)
+    }
+
+    fn create_signing_request(&mut self, subject_name: &UAString, regenerate_private_key: bool) -> Result<ByteString, StatusCode> {
+        let (certificate, private_key) = {
+            let certificate_store = trace_read_lock_unwrap!(self.certificate_store);
+            certificate_store.read_own_cert_and_pkey().map_err(|err| {
+                error!("CreateSigningRequest cannot read the current certificate, {}", err);
+                StatusCode::BadInternalError
+            })?
+        };
+
+        let mut args = X509Data::from(self.application_description.clone());
+        args.key_size = private_key.bit_length() as u32;
+        if let Ok(common_name) = certificate.common_name() {
+            args.common_name = common_name;
+        }
+        if !subject_name.is_null() {
+            Self::apply_subject_name(&mut args, subject_name.as_ref())?;
+        }
+
+        let certificate_request = if regenerate_private_key {
+            let new_private_key = PrivateKey::new(args.key_size).map_err(|_| StatusCode::BadInternalError)?;
+            let certificate_request = CertificateStore::create_signing_request(&args, &new_private_key);
+            self.new_private_key = Some(new_private_key);
+            certificate_request
+        } else {
+            self.new_private_key = None;
+            CertificateStore::create_signing_request(&args, &private_key)
+        };
+        certificate_request.map(ByteString::from).map_err(|err| {
+            error!("{}", err);
+            StatusCode::BadInternalError
+        })
+    }
+
+    /// Replaces the subject fields of the args with those of a subject name such as
+    /// "CN=MyServer/O=MyCompany". Entries may be separated by '/' or ','.
+    fn apply_subject_name(args: &mut X509Data, subject_name: &str) -> Result<(), StatusCode> {
+        args.common_name.clear();
+        args.organization.clear();
+        args.organizational_unit.clear();
+        args.country.clear();
+        args.state.clear();
+        for entry in subject_name.split(|c| c == '/' || c == ',').map(|e| e.trim()).filter(|e| !e.is_empty()) {
+            let mut parts = entry.splitn(2, '=');
+            let (field, value) = match (parts.next(), parts.next()) {
+                (Some(field), Some(value)) => (field.trim(), value.trim().to_string()),
+                _ => {
+                    error!("CreateSigningRequest was passed an invalid subject name {}", subject_name);
+                    return Err(StatusCode::BadInvalidArgument);
+                }
+            };
+            match field {
+                "CN" => args.common_name = value,
+                "O" => args.organization = value,
+                "OU" => args.organizational_unit = value,
+                "C" => args.country = value,
+                "ST" => args.state = value,
+                // Other fields such as DC are not supported and left out of the request
+                _ => warn!("CreateSigningRequest ignores the subject name field {}", field),
+            }
+        }
+        Ok(())
+    }
+
+    fn get_rejected_list(&self) -> Vec<ByteString> {
+        let certificate_store = trace_read_lock_unwrap!(self.certificate_store);
+        certificate_store.rejected_certs().iter().map(|c| c.as_byte_string()).collect()
+    }
+
+    fn apply_changes(&mut self) -> Result<(), StatusCode> {
+        if let Some(pending_update) = self.pending_update.take() {
+            let certificate_store = trace_read_lock_unwrap!(self.certificate_store);
+            for issuer_certificate in &pending_update.issuer_certificates {
+                certificate_store.store_issuer_cert(issuer_certificate).map_err(|err| {
+                    error!("ApplyChanges cannot store an issuer certificate, {}", err);
+                    StatusCode::BadInternalError
+                })?;
+            }
+            certificate_store.store_own_cert_and_pkey(&pending_update.certificate, &pending_update.private_key, true).map_err(|err| {
+                error!("ApplyChanges cannot store the certificate, {}", err);
+                StatusCode::BadInternalError
+            })?;
+            info!("ApplyChanges has stored a new certificate, the server will reload it");
+            self.reload_required = true;
+        }
+        Ok(())
+    }
+}
+
+/// The methods of the ServerConfiguration object and its trust list may only be called by a
+/// SecurityAdmin over an encrypted channel
+pub(crate) fn check_security_admin(session: &Session) -> Result<(), StatusCode> {
+    let security_mode = {
+        let secure_channel = trace_read_lock_unwrap!(session.secure_channel);
+        secure_channel.security_mode()
+    };
+    if security_mode != MessageSecurityMode::SignAndEncrypt {
+        error!("ServerConfiguration methods must be called over an encrypted channel");
+        Err(StatusCode::BadSecurityModeInsufficient)
+    } else if !session.roles.contains(&WellKnownRole::SecurityAdmin.into()) {
+        error!("ServerConfiguration methods may only be called by a SecurityAdmin");
+        Err(StatusCode::BadUserAccessDenied)
+    } else {
+        Ok(())
+    }
+}
+
+/// The handler of the ServerConfiguration methods
+struct ServerConfigurationMethod {
+    certificate_manager: Arc<RwLock<CertificateManager>>,
+}
+
+impl ServerConfigurationMethod {
+    /// The certificate group must be the default application group and the certificate type
+    /// an application certificate type. Null ids mean the defaults.
+    fn check_group_and_type(group_id: &NodeId, type_id: &NodeId) -> Result<(), StatusCode> {
+        let valid_group = group_id.is_null() || *group_id == DEFAULT_APPLICATION_GROUP_ID.into();
+        let valid_type = type_id.is_null() || [
+            ObjectTypeId::ApplicationCertificateType,
+            ObjectTypeId::RsaMinApplicationCertificateType,
+            ObjectTypeId::RsaSha256ApplicationCertificateType
+        ].iter().any(|t| *type_id == (*t).into());
+        if valid_group && valid_type {
+            Ok(())
+        } else {
+            error!("ServerConfiguration method was passed an unsupported certificate group {:?} or type {:?}", group_id, type_id);
+            Err(StatusCode::BadInvalidArgument)
+        }
+    }
+
+    fn node_id_argument(input_arguments: &[Variant], index: usize) -> Result<NodeId, StatusCode> {
+        match input_arguments.get(index) {
+            Some(Variant::NodeId(node_id)) => Ok(node_id.as_ref().clone()),
+            Some(Variant::Empty) => Ok(NodeId::null()),
+            _ => Err(StatusCode::BadInvalidArgument)
+        }
+    }
+
+    fn byte_string_argument(input_arguments: &[Variant], index: usize) -> Result<ByteString, StatusCode> {
+        match input_arguments.get(index) {
+            Some(Variant::ByteString(value)) => Ok(value.clone()),
+            Some(Variant::Empty) => Ok(ByteString::null()),
+            _ => Err(StatusCode::BadInvalidArgument)
+        }
+    }
+
+    fn byte_string_array_argument(input_arguments: &[Variant], index: usize) -> Result<Vec<ByteString>, StatusCode> {
+        match input_arguments.get(index) {
+            Some(Variant::Array(values)) => values.iter().map(|value| {
+                if let Variant::ByteString(value) = value { Ok(value.clone()) } else { Err(StatusCode::BadInvalidArgument) }
+            }).collect(),
+            Some(Variant::Empty) => Ok(Vec::new()),
+            _ => Err(StatusCode::BadInvalidArgument)
+        }
+    }
+
+    fn string_argument(input_arguments: &[Variant], index: usize) -> Result<UAString, StatusCode> {
+        match input_arguments.get(index) {
+            Some(Variant::String(value)) => Ok(value.clone()),
+            Some(Variant::Empty) => Ok(UAString::null()),
+            _ => Err(StatusCode::BadInvalidArgument)
+        }
+    }
+
+    fn boolean_argument(input_arguments: &[Variant], index: usize) -> Result<bool, StatusCode> {
+        match input_arguments.get(index) {
+            Some(Variant::Boolean(value)) => Ok(*value),
+            _ => Err(StatusCode::BadInvalidArgument)
+        }
+    }
+}
+
+impl Method for ServerConfigurationMethod {
+    fn call(&mut self, session: &mut Session, request: &CallMethodRequest) -> Result<CallMethodResult, StatusCode> {
+        check_security_admin(session)?;
+
+        let input_arguments = request.input_arguments.as_ref().map(|v| v.as_slice()).unwrap_or(&[]);
+        let mut certificate_manager = trace_write_lock_unwrap!(self.certificate_manager);
+
+        let output_arguments = if request.method_id == MethodId::ServerConfiguration_UpdateCertificate.into() {
+            // UpdateCertificate([in] NodeId certificateGroupId, [in] NodeId certificateTypeId,
+            //     [in] ByteString certificate, [in] ByteString[] issuerCertificates,
+            //     [in] String privateKeyFormat, [in] ByteString privateKey,
+            //     [out] Boolean applyChangesRequired)
+            Self::check_group_and_type(&Self::node_id_argument(input_arguments, 0)?, &Self::node_id_argument(input_arguments, 1)?)?;
+            let certificate = Self::byte_string_argument(input_arguments, 2)?;
+            let issuer_certificates = Self::byte_string_array_argument(input_arguments, 3)?;
+            let private_key_format = Self::string_argument(input_arguments, 4)?;
+            let private_key = Self::byte_string_argument(input_arguments, 5)?;
+            let apply_changes_required = certificate_manager.update_certificate(&certificate, &issuer_certificates, &private_key_format, &private_key)?;
+            vec![Variant::Boolean(apply_changes_required)]
+        } else if request.method_id == MethodId::ServerConfiguration_CreateSigningRequest.into() {
+            // CreateSigningRequest([in] NodeId certificateGroupId, [in] NodeId certificateTypeId,
+            //     [in] String subjectName, [in] Boolean regeneratePrivateKey, [in] ByteString nonce,
+            //     [out] ByteString certificateRequest)
+            //
+            // The nonce is extra entropy for the key generation, which OpenSSL does not need
+            Self::check_group_and_type(&Self::node_id_argument(input_arguments, 0)?, &Self::node_id_argument(input_arguments, 1)?)?;
+            let subject_name = Self::string_argument(input_arguments, 2)?;
+            let regenerate_private_key = Self::boolean_argument(input_arguments, 3)?;
+            let certificate_request = certificate_manager.create_signing_request(&subject_name, regenerate_private_key)?;
+            vec![Variant::ByteString(certificate_request)]
+        } else if request.method_id == MethodId::ServerConfiguration_GetRejectedList.into() {
+            // GetRejectedList([out] ByteString[] certificates)
+            let certificates = certificate_manager.get_rejected_list();
+            vec![Variant::Array(certificates.into_iter().map(Variant::ByteString).collect())]
+        } else if request.method_id == MethodId::ServerConfiguration_ApplyChanges.into() {
+            // ApplyChanges()
+            certificate_manager.apply_changes()?;
+            Vec::new()
+        } else {
+            return Err(StatusCode::BadMethodInvalid);
+        };
+
+        Ok(CallMethodResult {
+            status_code: StatusCode::Good,
+            input_argument_results: Some(vec![StatusCode::Good; input_arguments.len()]),
+            input_argument_diagnostic_infos: None,
+            output_arguments: if output_arguments.is_empty() { None } else { Some(output_arguments) },
+        })
+    }
+}
+
+pub(crate) fn argument(name: &str, data_type: DataTypeId, value_rank: i32) -> Argument {
+    Argument {
+        name: UAString::from(name),
+        data_type: data_type.into(),
+        value_rank,
+        // An array argument must have a length for each dimension, where 0 is any length
+        array_dimensions: if value_rank > 0 { Some(vec![0; value_rank as usize]) } else { None },
+        description: LocalizedText::null(),
+    }
+}
+
+/// The ServerConfiguration object is not in the generated node set. This adds it beneath the Server
+/// object with its UpdateCertificate, CreateSigningRequest, GetRejectedList and ApplyChanges methods,
+/// and the trust list of its DefaultApplicationGroup.
+pub(crate) fn add_server_configuration(address_space: &mut AddressSpace, certificate_manager: Arc<RwLock<CertificateManager>>) {
+    let server_configuration_id: NodeId = ObjectId::ServerConfiguration.into();
+    if address_space.node_exists(&server_configuration_id) {
+        return;
+    }
+    address_space.insert(Object::new(&server_configuration_id, "ServerConfiguration", "ServerConfiguration", 0), Some(&[
+        (&ObjectId::Server.into(), ReferenceTypeId::HasComponent, ReferenceDirection::Inverse),
+        (&ObjectTypeId::ServerConfigurationType.into(), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+    ]));
+
+    // The certificate groups, of which only the DefaultApplicationGroup is supported
+    let certificate_groups_id: NodeId = ObjectId::ServerConfiguration_CertificateGroups.into();
+    address_space.insert(Object::new(&certificate_groups_id, "CertificateGroups", "CertificateGroups", 0), Some(&[
+        (&server_configuration_id, ReferenceTypeId::HasComponent, ReferenceDirection::Inverse),
+        (&ObjectTypeId::CertificateGroupFolderType.into(), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+    ]));
+    let default_application_group_id: NodeId = DEFAULT_APPLICATION_GROUP_ID.into();
+    address_space.insert(Object::new(&default_application_group_id, "DefaultApplicationGroup", "DefaultApplicationGroup", 0), Some(&[
+        (&certificate_groups_id, ReferenceTypeId::HasComponent, ReferenceDirection::Inverse),
+        (&ObjectTypeId::CertificateGroupType.into(), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+    ]));
+    let certificate_store = trace_read_lock_unwrap!(certificate_manager).certificate_store.clone();
+    trust_list::add_trust_list(address_space, &default_application_group_id, certificate_store);
+
+    let methods = [
+        (MethodId::ServerConfiguration_UpdateCertificate, "UpdateCertificate", vec![
+            argument("CertificateGroupId", DataTypeId::NodeId, -1),
+            argument("CertificateTypeId", DataTypeId::NodeId, -1),
+            argument("Certificate", DataTypeId::ByteString, -1),
+            argument("IssuerCertificates", DataTypeId::ByteString, 1),
+            argument("PrivateKeyFormat", DataTypeId::String, -1),
+            argument("PrivateKey", DataTypeId::ByteString, -1),
+        ], vec![
+            argument("ApplyChangesRequired", DataTypeId::Boolean, -1),
+        ]),
+        (MethodId::ServerConfiguration_CreateSigningRequest, "CreateSigningRequest", vec![
+            argument("CertificateGroupId", DataTypeId::NodeId, -1),
+            argument("CertificateTypeId", DataTypeId::NodeId, -1),
+            argument("SubjectName", DataTypeId::String, -1),
+            argument("RegeneratePrivateKey", DataTypeId::Boolean, -1),
+            argument("Nonce", DataTypeId::ByteString, -1),
+        ], vec![
+            argument("CertificateRequest", DataTypeId::ByteString, -1),
+        ]),
+        (MethodId::ServerConfiguration_GetRejectedList, "GetRejectedList", vec![], vec![
+            argument("Certificates", DataTypeId::ByteString, 1),
+        ]),
+        (MethodId::ServerConfiguration_ApplyChanges, "ApplyChanges", vec![], vec![]),
+    ];
+    for (method_id, name, input_arguments, output_arguments) in methods.iter() {
+        let method_id: NodeId = (*method_id).into();
+        if address_space.add_method(&method_id, *name, *name, &server_configuration_id, input_arguments, output_arguments).is_ok() {
+            address_space.register_method_handler(server_configuration_id.clone(), method_id, Box::new(ServerConfigurationMethod {
+                certificate_manager: certificate_manager.clone(),
+            }));
+        }
+    }
+}
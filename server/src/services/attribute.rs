@@ -1,14 +1,130 @@
 use std::result::Result;
+use std::str::FromStr;
+use std::time::Duration;
+
+use futures::{Future, future};
+use tokio_timer::Timeout;
 
 use opcua_types::*;
+use opcua_types::node_ids::ObjectId;
 use opcua_types::status_code::StatusCode;
 use opcua_types::service_types::*;
 
 use crate::{
-    services::Service,
-    address_space::{AccessLevel, AddressSpace, node::NodeType},
+    services::{Deadline, Service, ServiceUser},
+    address_space::{AccessLevel, AddressSpace, data_access, node::NodeType},
+    config::OperationalLimits,
+    continuation_point::HistoryContinuationPoint,
+    roles,
+    session::Session,
+    state::ServerState,
 };
 
+/// The response to a read or write which is completed once the async getters or setters of
+/// variables have finished
+pub(crate) type AsyncResponse = Box<dyn Future<Item=SupportedMessage, Error=()> + Send>;
+
+/// The kinds of history read details that the HistoryRead service understands
+enum HistoryReadDetails {
+    RawModified(ReadRawModifiedDetails),
+    Processed(ReadProcessedDetails),
+    AtTime(ReadAtTimeDetails),
+}
+
+impl HistoryReadDetails {
+    /// Decodes the details from the extension object in the request
+    fn decode(history_read_details: &ExtensionObject, decoding_limits: &DecodingLimits) -> Result<HistoryReadDetails, StatusCode> {
+        let object_id = history_read_details.node_id.as_object_id()
+            .map_err(|_| StatusCode::BadHistoryOperationInvalid)?;
+        let details = match object_id {
+            ObjectId::ReadRawModifiedDetails_Encoding_DefaultBinary => {
+                history_read_details.decode_inner::<ReadRawModifiedDetails>(decoding_limits).map(HistoryReadDetails::RawModified)
+            }
+            ObjectId::ReadProcessedDetails_Encoding_DefaultBinary => {
+                history_read_details.decode_inner::<ReadProcessedDetails>(decoding_limits).map(HistoryReadDetails::Processed)
+            }
+            ObjectId::ReadAtTimeDetails_Encoding_DefaultBinary => {
+                history_read_details.decode_inner::<ReadAtTimeDetails>(decoding_limits).map(HistoryReadDetails::AtTime)
+            }
+            ObjectId::ReadEventDetails_Encoding_DefaultBinary => {
+                // Event history is not supported
+                return Err(StatusCode::BadHistoryOperationUnsupported);
+            }
+            _ => {
+                return Err(StatusCode::BadHistoryOperationInvalid);
+            }
+        };
+        details.map_err(|err| {
+            error!("Cannot decode history read details, error = {}", err);
+            StatusCode::BadHistoryOperationInvalid
+        })
+    }
+}
+
+/// The kinds of history update details that the HistoryUpdate service understands
+enum HistoryUpdateDetails {
+    UpdateData(UpdateDataDetails),
+    UpdateEvent(UpdateEventDetails),
+    DeleteRawModified(DeleteRawModifiedDetails),
+    DeleteAtTime(DeleteAtTimeDetails),
+    DeleteEvent(DeleteEventDetails),
+}
+
+impl HistoryUpdateDetails {
+    /// Decodes the details from one of the extension objects in the request
+    fn decode(history_update_details: &ExtensionObject, decoding_limits: &DecodingLimits) -> Result<HistoryUpdateDetails, StatusCode> {
+        let object_id = history_update_details.node_id.as_object_id()
+            .map_err(|_| StatusCode::BadHistoryOperationInvalid)?;
+        let details = match object_id {
+            ObjectId::UpdateDataDetails_Encoding_DefaultBinary => {
+                history_update_details.decode_inner::<UpdateDataDetails>(decoding_limits).map(HistoryUpdateDetails::UpdateData)
+            }
+            ObjectId::UpdateEventDetails_Encoding_DefaultBinary => {
+                history_update_details.decode_inner::<UpdateEventDetails>(decoding_limits).map(HistoryUpdateDetails::UpdateEvent)
+            }
+            ObjectId::DeleteRawModifiedDetails_Encoding_DefaultBinary => {
+                history_update_details.decode_inner::<DeleteRawModifiedDetails>(decoding_limits).map(HistoryUpdateDetails::DeleteRawModified)
+            }
+            ObjectId::DeleteAtTimeDetails_Encoding_DefaultBinary => {
+                history_update_details.decode_inner::<DeleteAtTimeDetails>(decoding_limits).map(HistoryUpdateDetails::DeleteAtTime)
+            }
+            ObjectId::DeleteEventDetails_Encoding_DefaultBinary => {
+                history_update_details.decode_inner::<DeleteEventDetails>(decoding_limits).map(HistoryUpdateDetails::DeleteEvent)
+            }
+            ObjectId::UpdateStructureDataDetails_Encoding_DefaultBinary => {
+                // Structure history is not supported
+                return Err(StatusCode::BadHistoryOperationUnsupported);
+            }
+            _ => {
+                return Err(StatusCode::BadHistoryOperationInvalid);
+            }
+        };
+        details.map_err(|err| {
+            error!("Cannot decode history update details, error = {}", err);
+            StatusCode::BadHistoryOperationInvalid
+        })
+    }
+
+    /// The node that the update applies to
+    fn node_id(&self) -> &NodeId {
+        match *self {
+            HistoryUpdateDetails::UpdateData(ref details) => &details.node_id,
+            HistoryUpdateDetails::UpdateEvent(ref details) => &details.node_id,
+            HistoryUpdateDetails::DeleteRawModified(ref details) => &details.node_id,
+            HistoryUpdateDetails::DeleteAtTime(ref details) => &details.node_id,
+            HistoryUpdateDetails::DeleteEvent(ref details) => &details.node_id,
+        }
+    }
+
+    /// Tests if the update is to the events of a node rather than its values
+    fn is_event_update(&self) -> bool {
+        match *self {
+            HistoryUpdateDetails::UpdateEvent(_) | HistoryUpdateDetails::DeleteEvent(_) => true,
+            _ => false
+        }
+    }
+}
+
 /// The attribute service. Allows attributes to be read and written from the address space.
 pub(crate) struct AttributeService;
 
@@ -29,9 +145,14 @@ impl AttributeService {
     /// elements or to read ranges of elements of the composite. Servers may make historical
     /// values available to Clients using this Service, although the historical values themselves
     /// are not visible in the AddressSpace.
-    pub fn read(&self, address_space: &AddressSpace, request: &ReadRequest) -> Result<SupportedMessage, StatusCode> {
+    ///
+    /// Node ids registered by the session through RegisterNodes are resolved to their nodes. Attributes
+    /// that the server's access control denies to the session's user are `BadUserAccessDenied`.
+    pub fn read<U: ServiceUser>(&self, server_state: &ServerState, user: &U, address_space: &AddressSpace, request: &ReadRequest) -> Result<SupportedMessage, StatusCode> {
         if is_empty_option_vec!(request.nodes_to_read) {
             Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
+        } else if OperationalLimits::is_exceeded(server_state.operational_limits.max_nodes_per_read, request.nodes_to_read.as_ref().unwrap().len()) {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadTooManyOperations))
         } else if request.max_age < 0f64 {
             // Negative values are invalid for max_age
             warn!("ReadRequest max age is invalid");
@@ -41,9 +162,21 @@ impl AttributeService {
 
             // Read nodes and their attributes
             let timestamps_to_return = request.timestamps_to_return;
-            let results = nodes_to_read.iter().map(|node_to_read| {
-                Self::read_node_value(&address_space, node_to_read, request.max_age, timestamps_to_return)
-            }).collect();
+            let deadline = Deadline::new(&request.request_header);
+            let mut results = nodes_to_read.iter().map(|node_to_read| {
+                if deadline.has_passed() {
+                    // Slow getters have used up the time the client allowed for the read
+                    let mut result_value = DataValue::null();
+                    result_value.status = Some(StatusCode::BadTimeout.bits());
+                    return result_value;
+                }
+                let node_id = user.resolve_node_id(&node_to_read.node_id);
+                Self::read_node_value(server_state, user, &address_space, node_id, node_to_read, request.max_age, timestamps_to_return)
+            }).collect::<Vec<DataValue>>();
+            Self::read_provided_values(user, address_space, nodes_to_read, &mut results, request.max_age, timestamps_to_return);
+            nodes_to_read.iter().zip(results.iter_mut()).for_each(|(node_to_read, result_value)| {
+                Self::apply_index_range(&node_to_read.index_range, result_value);
+            });
 
             let diagnostic_infos = None;
             let response = ReadResponse {
@@ -61,12 +194,18 @@ impl AttributeService {
     /// constructed Attribute values whose elements are indexed, such as an array, this Service
     /// allows Clients to write the entire set of indexed values as a composite, to write individual
     /// elements or to write ranges of elements of the composite.
-    pub fn write(&self, address_space: &mut AddressSpace, request: &WriteRequest) -> Result<SupportedMessage, StatusCode> {
+    ///
+    /// Node ids registered by the session through RegisterNodes are resolved to their nodes. Attributes
+    /// that the server's access control denies to the session's user are `BadUserAccessDenied`.
+    pub fn write<U: ServiceUser>(&self, server_state: &ServerState, user: &U, address_space: &mut AddressSpace, request: &WriteRequest) -> Result<SupportedMessage, StatusCode> {
         if is_empty_option_vec!(request.nodes_to_write) {
             Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
+        } else if OperationalLimits::is_exceeded(server_state.operational_limits.max_nodes_per_write, request.nodes_to_write.as_ref().unwrap().len()) {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadTooManyOperations))
         } else {
             let results = request.nodes_to_write.as_ref().unwrap().iter().map(|node_to_write| {
-                Self::write_node_value(address_space, node_to_write)
+                let node_id = user.resolve_node_id(&node_to_write.node_id);
+                Self::write_node_value(server_state, user, address_space, node_id, node_to_write)
             }).collect();
 
             let diagnostic_infos = None;
@@ -79,7 +218,300 @@ impl AttributeService {
         }
     }
 
-    fn read_node_value(address_space: &AddressSpace, node_to_read: &ReadValueId, max_age: f64, timestamps_to_return: TimestampsToReturn) -> DataValue {
+    /// Spec:
+    ///
+    /// This Service is used to read historical values or Events of one or more Nodes. Servers may
+    /// make historical values available to Clients using this Service, although the historical
+    /// values themselves are not visible in the AddressSpace.
+    ///
+    /// Values are obtained from the `HistoricalDataProvider` registered with the server state.
+    /// Event history is not supported.
+    pub fn history_read(&self, server_state: &mut ServerState, session: &mut Session, address_space: &AddressSpace, request: &HistoryReadRequest) -> Result<SupportedMessage, StatusCode> {
+        if is_empty_option_vec!(request.nodes_to_read) {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
+        } else if Self::is_history_read_exceeded(server_state.operational_limits(), request) {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadTooManyOperations))
+        } else if request.timestamps_to_return == TimestampsToReturn::Neither {
+            // Historical values without any timestamps are meaningless
+            warn!("HistoryReadRequest timestamps to return is invalid");
+            Ok(self.service_fault(&request.request_header, StatusCode::BadTimestampsToReturnInvalid))
+        } else {
+            let nodes_to_read = request.nodes_to_read.as_ref().unwrap();
+            let results = if request.release_continuation_points {
+                // Release the continuation points and return no data
+                let continuation_points = nodes_to_read.iter()
+                    .map(|node_to_read| node_to_read.continuation_point.clone())
+                    .collect::<Vec<ByteString>>();
+                session.remove_history_continuation_points(&continuation_points);
+                nodes_to_read.iter().map(|_| HistoryReadResult {
+                    status_code: StatusCode::Good,
+                    continuation_point: ByteString::null(),
+                    history_data: ExtensionObject::null(),
+                }).collect()
+            } else {
+                let decoding_limits = {
+                    let config = trace_read_lock_unwrap!(server_state.config);
+                    config.decoding_limits()
+                };
+                let details = match HistoryReadDetails::decode(&request.history_read_details, &decoding_limits) {
+                    Ok(details) => details,
+                    Err(status_code) => {
+                        return Ok(self.service_fault(&request.request_header, status_code));
+                    }
+                };
+                // There must be one aggregate for every node being read
+                if let HistoryReadDetails::Processed(ref details) = details {
+                    let aggregate_count = details.aggregate_type.as_ref().map(|a| a.len()).unwrap_or(0);
+                    if aggregate_count != nodes_to_read.len() {
+                        return Ok(self.service_fault(&request.request_header, StatusCode::BadAggregateListMismatch));
+                    }
+                }
+                let deadline = Deadline::new(&request.request_header);
+                nodes_to_read.iter().enumerate().map(|(idx, node_to_read)| {
+                    if deadline.has_passed() {
+                        // The provider has used up the time the client allowed for the read
+                        HistoryReadResult {
+                            status_code: StatusCode::BadTimeout,
+                            continuation_point: ByteString::null(),
+                            history_data: ExtensionObject::null(),
+                        }
+                    } else {
+                        Self::history_read_node(server_state, session, address_space, &details, idx, node_to_read, request.timestamps_to_return)
+                    }
+                }).collect()
+            };
+
+            let diagnostic_infos = None;
+            let response = HistoryReadResponse {
+                response_header: ResponseHeader::new_good(&request.request_header),
+                results: Some(results),
+                diagnostic_infos,
+            };
+            Ok(response.into())
+        }
+    }
+
+    /// Reads the history of a single node, either afresh or by continuing from a continuation
+    /// point supplied by the client.
+    fn history_read_node(server_state: &mut ServerState, session: &mut Session, address_space: &AddressSpace, details: &HistoryReadDetails, idx: usize, node_to_read: &HistoryReadValueId, timestamps_to_return: TimestampsToReturn) -> HistoryReadResult {
+        let values = if node_to_read.continuation_point.is_null() {
+            Self::read_node_history(server_state, address_space, details, idx, node_to_read, timestamps_to_return)
+        } else {
+            // Continue from the values held by the continuation point
+            match session.take_history_continuation_point(&node_to_read.continuation_point) {
+                Some(ref continuation_point) if continuation_point.node_id == node_to_read.node_id => {
+                    Ok((continuation_point.values.clone(), continuation_point.num_values_per_node))
+                }
+                _ => {
+                    warn!("History continuation point for node {:?} is invalid", node_to_read.node_id);
+                    Err(StatusCode::BadContinuationPointInvalid)
+                }
+            }
+        };
+        match values {
+            Ok((values, num_values_per_node)) => {
+                Self::history_read_result(session, &node_to_read.node_id, values, num_values_per_node)
+            }
+            Err(status_code) => HistoryReadResult {
+                status_code,
+                continuation_point: ByteString::null(),
+                history_data: ExtensionObject::null(),
+            }
+        }
+    }
+
+    /// Obtains the historical values of a node from the provider. The result is the values and
+    /// the maximum number of them to return in a response, where 0 means no limit.
+    fn read_node_history(server_state: &mut ServerState, address_space: &AddressSpace, details: &HistoryReadDetails, idx: usize, node_to_read: &HistoryReadValueId, timestamps_to_return: TimestampsToReturn) -> Result<(Vec<DataValue>, usize), StatusCode> {
+        let node_id = &node_to_read.node_id;
+        if let Some(node) = address_space.find_node(node_id) {
+            if let NodeType::Variable(ref node) = *node {
+                if !node.access_level().contains(AccessLevel::HISTORY_READ) {
+                    return Err(StatusCode::BadNotReadable);
+                }
+            } else {
+                // Only variables have historical values
+                return Err(StatusCode::BadHistoryOperationUnsupported);
+            }
+        } else {
+            warn!("Cannot find node id {:?}", node_id);
+            return Err(StatusCode::BadNodeIdUnknown);
+        }
+        if !node_to_read.index_range.is_null() {
+            // Index ranges are not supported
+            return Err(StatusCode::BadNotReadable);
+        }
+
+        let provider = server_state.historical_data_provider.as_mut()
+            .ok_or(StatusCode::BadHistoryOperationUnsupported)?;
+        match *details {
+            HistoryReadDetails::RawModified(ref details) => {
+                let start_time_null = details.start_time.checked_ticks() == 0;
+                let end_time_null = details.end_time.checked_ticks() == 0;
+                // At least two of start time, end time and number of values must be specified
+                if (start_time_null && end_time_null) ||
+                    (details.num_values_per_node == 0 && (start_time_null || end_time_null)) {
+                    Err(StatusCode::BadHistoryOperationInvalid)
+                } else {
+                    let values = provider.read_raw_modified_details(node_id, details, timestamps_to_return)?;
+                    Ok((values, details.num_values_per_node as usize))
+                }
+            }
+            HistoryReadDetails::Processed(ref details) => {
+                let aggregate_type = &details.aggregate_type.as_ref().unwrap()[idx];
+                let values = provider.read_processed_details(node_id, aggregate_type, details, timestamps_to_return)?;
+                Ok((values, 0))
+            }
+            HistoryReadDetails::AtTime(ref details) => {
+                let values = provider.read_at_time_details(node_id, details, timestamps_to_return)?;
+                Ok((values, 0))
+            }
+        }
+    }
+
+    /// Makes the result for a node, creating a continuation point for any values beyond the
+    /// number allowed in a single response.
+    fn history_read_result(session: &mut Session, node_id: &NodeId, mut values: Vec<DataValue>, num_values_per_node: usize) -> HistoryReadResult {
+        let continuation_point = if num_values_per_node > 0 && values.len() > num_values_per_node {
+            let remaining_values = values.split_off(num_values_per_node);
+            let continuation_point = ByteString::random(6);
+            session.add_history_continuation_point(HistoryContinuationPoint {
+                id: continuation_point.clone(),
+                node_id: node_id.clone(),
+                num_values_per_node,
+                values: remaining_values,
+            });
+            continuation_point
+        } else {
+            ByteString::null()
+        };
+        let history_data = HistoryData {
+            data_values: Some(values),
+        };
+        HistoryReadResult {
+            status_code: StatusCode::Good,
+            continuation_point,
+            history_data: ExtensionObject::from_encodable(ObjectId::HistoryData_Encoding_DefaultBinary, &history_data),
+        }
+    }
+
+    /// Spec:
+    ///
+    /// This Service is used to update historical values or Events of one or more Nodes. Several
+    /// request parameters indicate how the Server is to update the historical value or Event.
+    /// Valid actions are Insert, Replace or Delete.
+    ///
+    /// Updates are passed to the `HistoricalDataProvider` registered with the server state.
+    pub fn history_update(&self, server_state: &mut ServerState, address_space: &AddressSpace, request: &HistoryUpdateRequest) -> Result<SupportedMessage, StatusCode> {
+        if is_empty_option_vec!(request.history_update_details) {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
+        } else if Self::is_history_update_exceeded(server_state.operational_limits(), request) {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadTooManyOperations))
+        } else {
+            let decoding_limits = {
+                let config = trace_read_lock_unwrap!(server_state.config);
+                config.decoding_limits()
+            };
+            let results = request.history_update_details.as_ref().unwrap().iter().map(|history_update_details| {
+                match HistoryUpdateDetails::decode(history_update_details, &decoding_limits) {
+                    Ok(details) => Self::history_update_node(server_state, address_space, &details),
+                    Err(status_code) => HistoryUpdateResult {
+                        status_code,
+                        operation_results: None,
+                        diagnostic_infos: None,
+                    }
+                }
+            }).collect();
+
+            let diagnostic_infos = None;
+            let response = HistoryUpdateResponse {
+                response_header: ResponseHeader::new_good(&request.request_header),
+                results: Some(results),
+                diagnostic_infos,
+            };
+            Ok(response.into())
+        }
+    }
+
+    /// Tests if a history read has more nodes than the limit for reads of values, or of events
+    /// when the details are for events
+    fn is_history_read_exceeded(operational_limits: &OperationalLimits, request: &HistoryReadRequest) -> bool {
+        let is_event_read = request.history_read_details.node_id == ObjectId::ReadEventDetails_Encoding_DefaultBinary.into();
+        let limit = if is_event_read {
+            operational_limits.max_nodes_per_history_read_events
+        } else {
+            operational_limits.max_nodes_per_history_read_data
+        };
+        OperationalLimits::is_exceeded(limit, request.nodes_to_read.as_ref().unwrap().len())
+    }
+
+    /// Tests if a history update has more updates of values or of events than their limits
+    fn is_history_update_exceeded(operational_limits: &OperationalLimits, request: &HistoryUpdateRequest) -> bool {
+        let update_event_details: NodeId = ObjectId::UpdateEventDetails_Encoding_DefaultBinary.into();
+        let delete_event_details: NodeId = ObjectId::DeleteEventDetails_Encoding_DefaultBinary.into();
+        let (event_updates, data_updates) = request.history_update_details.as_ref().unwrap().iter()
+            .fold((0, 0), |(event_updates, data_updates), details| {
+                if details.node_id == update_event_details || details.node_id == delete_event_details {
+                    (event_updates + 1, data_updates)
+                } else {
+                    (event_updates, data_updates + 1)
+                }
+            });
+        OperationalLimits::is_exceeded(operational_limits.max_nodes_per_history_update_events, event_updates) ||
+            OperationalLimits::is_exceeded(operational_limits.max_nodes_per_history_update_data, data_updates)
+    }
+
+    /// Performs a single update on the provider
+    fn history_update_node(server_state: &mut ServerState, address_space: &AddressSpace, details: &HistoryUpdateDetails) -> HistoryUpdateResult {
+        let operation_results = Self::is_history_updatable(address_space, details).and_then(|_| {
+            let provider = server_state.historical_data_provider.as_mut()
+                .ok_or(StatusCode::BadHistoryOperationUnsupported)?;
+            match *details {
+                HistoryUpdateDetails::UpdateData(ref details) => provider.update_data_details(details).map(Some),
+                HistoryUpdateDetails::UpdateEvent(ref details) => provider.update_event_details(details).map(Some),
+                HistoryUpdateDetails::DeleteRawModified(ref details) => provider.delete_raw_modified_details(details).map(|_| None),
+                HistoryUpdateDetails::DeleteAtTime(ref details) => provider.delete_at_time_details(details).map(Some),
+                HistoryUpdateDetails::DeleteEvent(ref details) => provider.delete_event_details(details).map(Some),
+            }
+        });
+        match operation_results {
+            Ok(operation_results) => HistoryUpdateResult {
+                status_code: StatusCode::Good,
+                operation_results,
+                diagnostic_infos: None,
+            },
+            Err(status_code) => HistoryUpdateResult {
+                status_code,
+                operation_results: None,
+                diagnostic_infos: None,
+            }
+        }
+    }
+
+    /// Tests if the node exists and its history may be updated in the manner of the details.
+    /// Values may only be updated on variables with `HISTORY_WRITE` access and events may only
+    /// be updated on objects and views.
+    fn is_history_updatable(address_space: &AddressSpace, details: &HistoryUpdateDetails) -> Result<(), StatusCode> {
+        let node_id = details.node_id();
+        if let Some(node) = address_space.find_node(node_id) {
+            match *node {
+                NodeType::Variable(ref node) if !details.is_event_update() => {
+                    if node.access_level().contains(AccessLevel::HISTORY_WRITE) {
+                        Ok(())
+                    } else {
+                        Err(StatusCode::BadNotWritable)
+                    }
+                }
+                NodeType::Object(_) | NodeType::View(_) if details.is_event_update() => Ok(()),
+                _ => Err(StatusCode::BadHistoryOperationUnsupported)
+            }
+        } else {
+            warn!("Cannot find node id {:?}", node_id);
+            Err(StatusCode::BadNodeIdUnknown)
+        }
+    }
+
+    fn read_node_value<U: ServiceUser>(server_state: &ServerState, user: &U, address_space: &AddressSpace, node_id: &NodeId, node_to_read: &ReadValueId, max_age: f64, timestamps_to_return: TimestampsToReturn) -> DataValue {
         let mut result_value = DataValue {
             value: None,
             status: None,
@@ -89,39 +521,22 @@ impl AttributeService {
             server_picoseconds: None,
         };
         // Node node found
-        if let Some(node) = address_space.find_node(&node_to_read.node_id) {
+        if let Some(node) = address_space.find_node(node_id) {
             if let Ok(attribute_id) = AttributeId::from_u32(node_to_read.attribute_id) {
-                if let Some(attribute) = node.as_node().get_attribute(attribute_id, max_age) {
+                let attribute = if attribute_id == AttributeId::UserRolePermissions {
+                    Self::user_role_permissions(node, user.roles())
+                } else {
+                    node.as_node().get_attribute(attribute_id, max_age)
+                };
+                if let Some(attribute) = attribute {
                     let is_readable = Self::is_readable(&node);
                     if !is_readable {
                         result_value.status = Some(StatusCode::BadNotReadable.bits())
-                    } else if !node_to_read.index_range.is_null() {
-                        // Index ranges are not supported
-                        result_value.status = Some(StatusCode::BadNotReadable.bits());
+                    } else if !roles::is_permitted(node, user.roles(), roles::read_permission(attribute_id)) || !server_state.can_read(user.identity(), node_id, attribute_id) {
+                        result_value.status = Some(StatusCode::BadUserAccessDenied.bits())
                     } else {
                         // Result value is clone from the attribute
-                        result_value.value = attribute.value.clone();
-
-                        result_value.status = attribute.status;
-                        match timestamps_to_return {
-                            TimestampsToReturn::Source => {
-                                result_value.source_timestamp = attribute.source_timestamp.clone();
-                                result_value.source_picoseconds = attribute.source_picoseconds;
-                            }
-                            TimestampsToReturn::Server => {
-                                result_value.server_timestamp = attribute.server_timestamp.clone();
-                                result_value.server_picoseconds = attribute.server_picoseconds;
-                            }
-                            TimestampsToReturn::Both => {
-                                result_value.source_timestamp = attribute.source_timestamp.clone();
-                                result_value.source_picoseconds = attribute.source_picoseconds;
-                                result_value.server_timestamp = attribute.server_timestamp.clone();
-                                result_value.server_picoseconds = attribute.server_picoseconds;
-                            }
-                            TimestampsToReturn::Neither => {
-                                // Nothing needs to change
-                            }
-                        }
+                        Self::set_result_value(&mut result_value, &attribute, timestamps_to_return);
                     }
                 } else {
                     result_value.status = Some(StatusCode::BadAttributeIdInvalid.bits());
@@ -131,12 +546,216 @@ impl AttributeService {
                 result_value.status = Some(StatusCode::BadAttributeIdInvalid.bits());
             }
         } else {
-            warn!("Cannot find node id {:?}", node_to_read.node_id);
+            warn!("Cannot find node id {:?}", node_id);
             result_value.status = Some(StatusCode::BadNodeIdUnknown.bits());
         }
         result_value
     }
 
+    /// Sets the value and status of the result from the attribute, with the timestamps that the
+    /// client asked for
+    fn set_result_value(result_value: &mut DataValue, attribute: &DataValue, timestamps_to_return: TimestampsToReturn) {
+        result_value.value = attribute.value.clone();
+
+        result_value.status = attribute.status;
+        match timestamps_to_return {
+            TimestampsToReturn::Source => {
+                result_value.source_timestamp = attribute.source_timestamp.clone();
+                result_value.source_picoseconds = attribute.source_picoseconds;
+            }
+            TimestampsToReturn::Server => {
+                result_value.server_timestamp = attribute.server_timestamp.clone();
+                result_value.server_picoseconds = attribute.server_picoseconds;
+            }
+            TimestampsToReturn::Both => {
+                result_value.source_timestamp = attribute.source_timestamp.clone();
+                result_value.source_picoseconds = attribute.source_picoseconds;
+                result_value.server_timestamp = attribute.server_timestamp.clone();
+                result_value.server_picoseconds = attribute.server_picoseconds;
+            }
+            TimestampsToReturn::Neither => {
+                // Nothing needs to change
+            }
+        }
+    }
+
+    /// Replaces a value which was read successfully with the elements selected by the index range,
+    /// if there is one. A range which cannot be parsed is `BadIndexRangeInvalid` and a range which
+    /// selects nothing from the value is `BadIndexRangeNoData`.
+    fn apply_index_range(index_range: &UAString, result_value: &mut DataValue) {
+        if index_range.is_null() || result_value.status.map(|status| StatusCode::from_bits_truncate(status).is_bad()).unwrap_or(false) {
+            return;
+        }
+        let value = match NumericRange::from_str(index_range.as_ref()) {
+            Ok(ref range) if range.is_valid() => match result_value.value {
+                Some(ref value) => value.range_of(range),
+                None => Err(StatusCode::BadIndexRangeNoData)
+            },
+            _ => Err(StatusCode::BadIndexRangeInvalid)
+        };
+        match value {
+            Ok(value) => result_value.value = Some(value),
+            Err(status_code) => {
+                result_value.value = None;
+                result_value.status = Some(status_code.bits());
+            }
+        }
+    }
+
+    /// Replaces the values which were read successfully from nodes with value providers with the
+    /// values from the providers, which are read in one batch per provider
+    fn read_provided_values<U: ServiceUser>(user: &U, address_space: &AddressSpace, nodes_to_read: &[ReadValueId], results: &mut [DataValue], max_age: f64, timestamps_to_return: TimestampsToReturn) {
+        let provided = nodes_to_read.iter().zip(results.iter()).enumerate()
+            .filter(|(_, (node_to_read, result))| {
+                node_to_read.attribute_id == AttributeId::Value as u32 && result.status.map(|status| StatusCode::from_bits_truncate(status).is_good()).unwrap_or(true)
+            })
+            .map(|(idx, (node_to_read, _))| (idx, user.resolve_node_id(&node_to_read.node_id).clone()))
+            .filter(|(_, node_id)| address_space.has_value_provider(node_id))
+            .collect::<Vec<(usize, NodeId)>>();
+        if !provided.is_empty() {
+            let node_ids = provided.iter().map(|(_, node_id)| node_id.clone()).collect::<Vec<NodeId>>();
+            let values = address_space.read_provided_values(&node_ids, max_age);
+            for (idx, node_id) in provided {
+                if let Some(value) = values.get(&node_id) {
+                    let mut result_value = DataValue::null();
+                    Self::set_result_value(&mut result_value, value, timestamps_to_return);
+                    results[idx] = result_value;
+                }
+            }
+        }
+    }
+
+    /// Returns the read response completed with the values from the async getters of variables,
+    /// or `None` if none of the values read come from async getters. Only values which were read
+    /// successfully are replaced and any getter which takes longer than the timeout fails with
+    /// `BadTimeout`. A getter which returns no value leaves the value held by the variable.
+    pub(crate) fn async_read<U: ServiceUser>(&self, user: &U, address_space: &AddressSpace, request: &ReadRequest, response: &ReadResponse, timeout: Duration) -> Option<AsyncResponse> {
+        let (nodes_to_read, results) = match (request.nodes_to_read.as_ref(), response.results.as_ref()) {
+            (Some(nodes_to_read), Some(results)) => (nodes_to_read, results),
+            _ => return None
+        };
+        let values = nodes_to_read.iter().zip(results.iter()).enumerate()
+            .filter(|(_, (node_to_read, result))| {
+                node_to_read.attribute_id == AttributeId::Value as u32 && result.status.map(|status| StatusCode::from_bits_truncate(status).is_good()).unwrap_or(true)
+            })
+            .filter_map(|(idx, (node_to_read, _))| {
+                let node_id = user.resolve_node_id(&node_to_read.node_id);
+                if let Some(NodeType::Variable(variable)) = address_space.find_node(node_id) {
+                    variable.async_value(request.max_age).map(|value| (idx, value))
+                } else {
+                    None
+                }
+            })
+            .map(|(idx, value)| {
+                Timeout::new(value, timeout).then(move |result| {
+                    let value = match result {
+                        Ok(value) => value.map(Ok),
+                        Err(err) => Some(Err(Self::async_status_code(err)))
+                    };
+                    Ok::<_, ()>((idx, value))
+                })
+            })
+            .collect::<Vec<_>>();
+        if values.is_empty() {
+            None
+        } else {
+            let timestamps_to_return = request.timestamps_to_return;
+            let index_ranges = nodes_to_read.iter().map(|node_to_read| node_to_read.index_range.clone()).collect::<Vec<UAString>>();
+            let mut response = response.clone();
+            Some(Box::new(future::join_all(values).map(move |values| -> SupportedMessage {
+                let results = response.results.as_mut().unwrap();
+                for (idx, value) in values {
+                    match value {
+                        Some(Ok(value)) => {
+                            let mut result_value = DataValue::null();
+                            Self::set_result_value(&mut result_value, &value, timestamps_to_return);
+                            Self::apply_index_range(&index_ranges[idx], &mut result_value);
+                            results[idx] = result_value;
+                        }
+                        Some(Err(status_code)) => {
+                            let mut result_value = DataValue::null();
+                            result_value.status = Some(status_code.bits());
+                            results[idx] = result_value;
+                        }
+                        None => {}
+                    }
+                }
+                response.into()
+            })))
+        }
+    }
+
+    /// Returns the write response completed with the results of the async setters of variables,
+    /// or `None` if none of the values written have async setters. Only values which were written
+    /// successfully are passed to the setters and any setter which takes longer than the timeout
+    /// fails with `BadTimeout`. A write to an index range passes the whole value that the
+    /// elements were written into.
+    pub(crate) fn async_write<U: ServiceUser>(&self, user: &U, address_space: &AddressSpace, request: &WriteRequest, response: &WriteResponse, timeout: Duration) -> Option<AsyncResponse> {
+        let (nodes_to_write, results) = match (request.nodes_to_write.as_ref(), response.results.as_ref()) {
+            (Some(nodes_to_write), Some(results)) => (nodes_to_write, results),
+            _ => return None
+        };
+        let results_of_setters = nodes_to_write.iter().zip(results.iter()).enumerate()
+            .filter(|(_, (node_to_write, result))| node_to_write.attribute_id == AttributeId::Value as u32 && result.is_good())
+            .filter_map(|(idx, (node_to_write, _))| {
+                let node_id = user.resolve_node_id(&node_to_write.node_id);
+                if let Some(NodeType::Variable(variable)) = address_space.find_node(node_id) {
+                    let mut data_value = node_to_write.value.clone();
+                    if !node_to_write.index_range.is_null() {
+                        data_value.value = variable.value().value;
+                    }
+                    variable.async_set_value(data_value).map(|result| (idx, result))
+                } else {
+                    None
+                }
+            })
+            .map(|(idx, result)| {
+                Timeout::new(result, timeout).then(move |result| {
+                    let status_code = match result {
+                        Ok(_) => StatusCode::Good,
+                        Err(err) => Self::async_status_code(err)
+                    };
+                    Ok::<_, ()>((idx, status_code))
+                })
+            })
+            .collect::<Vec<_>>();
+        if results_of_setters.is_empty() {
+            None
+        } else {
+            let mut response = response.clone();
+            Some(Box::new(future::join_all(results_of_setters).map(move |results_of_setters| -> SupportedMessage {
+                let results = response.results.as_mut().unwrap();
+                for (idx, status_code) in results_of_setters {
+                    results[idx] = status_code;
+                }
+                response.into()
+            })))
+        }
+    }
+
+    /// The status code of an async getter or setter which failed or took too long
+    fn async_status_code(err: tokio_timer::timeout::Error<StatusCode>) -> StatusCode {
+        if err.is_elapsed() {
+            StatusCode::BadTimeout
+        } else if let Some(status_code) = err.into_inner() {
+            status_code
+        } else {
+            error!("Timer error while waiting for an async getter or setter");
+            StatusCode::BadInternalError
+        }
+    }
+
+    /// The role permissions of the node restricted to the roles of the session's user
+    fn user_role_permissions(node: &NodeType, roles: &[NodeId]) -> Option<DataValue> {
+        node.as_node().role_permissions().map(|role_permissions| {
+            let value = role_permissions.iter()
+                .filter(|r| roles.contains(&r.role_id))
+                .map(|r| Variant::from(ExtensionObject::from_encodable(NodeId::new(0, ROLE_PERMISSION_TYPE_ENCODING_DEFAULT_BINARY), r)))
+                .collect::<Vec<Variant>>();
+            DataValue::from(Variant::Array(value))
+        })
+    }
+
     fn is_readable(node: &NodeType) -> bool {
         // Check for access level, user access level
         if let NodeType::Variable(ref node) = *node {
@@ -147,22 +766,48 @@ impl AttributeService {
         true
     }
 
-    fn write_node_value(address_space: &mut AddressSpace, node_to_write: &WriteValue) -> StatusCode {
-        if let Some(node) = address_space.find_node_mut(&node_to_write.node_id) {
+    fn write_node_value<U: ServiceUser>(server_state: &ServerState, user: &U, address_space: &mut AddressSpace, node_id: &NodeId, node_to_write: &WriteValue) -> StatusCode {
+        // Values of analog and discrete items must lie within their EURange or EnumStrings
+        let valid_value = match node_to_write.value.value {
+            Some(ref value) if node_to_write.attribute_id == AttributeId::Value as u32 => data_access::validate_value(address_space, node_id, value),
+            _ => Ok(())
+        };
+        if let Some(node) = address_space.find_node_mut(node_id) {
             if let Ok(attribute_id) = AttributeId::from_u32(node_to_write.attribute_id) {
                 if !Self::is_writable(&node, attribute_id) {
                     StatusCode::BadNotWritable
-                } else if !node_to_write.index_range.is_null() {
-                    // Index ranges are not supported
-                    error!("Server does not support indexes in write");
-                    StatusCode::BadWriteNotSupported
+                } else if !roles::is_permitted(node, user.roles(), roles::write_permission(attribute_id)) || !server_state.can_write(user.identity(), node_id, attribute_id) {
+                    StatusCode::BadUserAccessDenied
 //                } else if node_to_write.value.server_timestamp.is_some() || node_to_write.value.server_picoseconds.is_some() ||
 //                    node_to_write.value.source_timestamp.is_some() || node_to_write.value.source_picoseconds.is_some() {
 //                    error!("Server does not support timestamps in write");
 //                    StatusCode::BadWriteNotSupported
                 } else if let Some(ref value) = node_to_write.value.value {
+                    let value = if node_to_write.index_range.is_null() {
+                        value.clone()
+                    } else {
+                        match Self::write_index_range(node, attribute_id, &node_to_write.index_range, value) {
+                            Ok(value) => value,
+                            Err(status_code) => {
+                                error!("Value cannot be written to index range {} of {:?}", node_to_write.index_range, node_id);
+                                return status_code;
+                            }
+                        }
+                    };
+                    if attribute_id == AttributeId::Value {
+                        if let NodeType::Variable(ref variable) = node {
+                            if !variable.is_valid_value_shape(&value) {
+                                error!("Value written to {:?} does not match its value rank / array dimensions", node_id);
+                                return StatusCode::BadTypeMismatch;
+                            }
+                        }
+                        if let Err(status_code) = valid_value {
+                            error!("Value written to {:?} is out of range", node_id);
+                            return status_code;
+                        }
+                    }
                     let node = node.as_mut_node();
-                    if let Err(err) = node.set_attribute(attribute_id, value.clone()) {
+                    if let Err(err) = node.set_attribute(attribute_id, value) {
                         err
                     } else {
                         StatusCode::Good
@@ -176,11 +821,25 @@ impl AttributeService {
                 StatusCode::BadAttributeIdInvalid
             }
         } else {
-            warn!("Cannot find node id {:?}", node_to_write.node_id);
+            warn!("Cannot find node id {:?}", node_id);
             StatusCode::BadNodeIdUnknown
         }
     }
 
+    /// Returns the value of the attribute with the elements selected by the index range replaced
+    /// by the elements of the value being written
+    fn write_index_range(node: &NodeType, attribute_id: AttributeId, index_range: &UAString, value: &Variant) -> Result<Variant, StatusCode> {
+        let range = match NumericRange::from_str(index_range.as_ref()) {
+            Ok(ref range) if range.is_valid() => range.clone(),
+            _ => return Err(StatusCode::BadIndexRangeInvalid)
+        };
+        let mut current_value = node.as_node().get_attribute(attribute_id, 0f64)
+            .and_then(|data_value| data_value.value)
+            .ok_or(StatusCode::BadIndexRangeNoData)?;
+        current_value.set_range_of(&range, value)?;
+        Ok(current_value)
+    }
+
     fn is_writable(node: &NodeType, attribute_id: AttributeId) -> bool {
         use opcua_types::WriteMask;
 
@@ -219,6 +878,8 @@ impl AttributeService {
                 AttributeId::Historizing => write_mask.contains(WriteMask::HISTORIZING),
                 AttributeId::Executable => write_mask.contains(WriteMask::EXECUTABLE),
                 AttributeId::UserExecutable => write_mask.contains(WriteMask::USER_EXECUTABLE),
+                AttributeId::RolePermissions => write_mask.contains(WriteMask::ROLE_PERMISSIONS),
+                AttributeId::UserRolePermissions => false,
             }
         } else {
             false
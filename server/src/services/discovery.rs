@@ -19,10 +19,8 @@ impl DiscoveryService {
     }
 
     pub fn get_endpoints(&self, server_state: &ServerState, request: &GetEndpointsRequest) -> Result<SupportedMessage, StatusCode> {
-        // TODO some of the arguments in the request are ignored
-        //  endpointUrl - for diagnostics and to determine what urls to return in response
-        //  localeIds - list of locales to use for human readable strings (in the endpoint descriptions)
-        let endpoints = server_state.endpoints(&request.profile_uris);
+        // TODO localeIds is ignored - list of locales to use for human readable strings (in the endpoint descriptions)
+        let endpoints = server_state.endpoints(&request.endpoint_url, &request.profile_uris);
         let response = GetEndpointsResponse {
             response_header: ResponseHeader::new_good(&request.request_header),
             endpoints,
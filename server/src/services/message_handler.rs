@@ -1,6 +1,9 @@
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use chrono::Utc;
+use futures::{Future, future::Either};
+use tokio;
 
 use opcua_core::crypto::{CertificateStore, SecurityPolicy};
 use opcua_types::*;
@@ -8,18 +11,22 @@ use opcua_types::service_types::*;
 use opcua_types::status_code::StatusCode;
 
 use crate::{
-    address_space::AddressSpace,
+    address_space::{AddressSpace, SharedAddressSpace},
+    events::audit,
     state::ServerState,
     services::{
-        attribute::AttributeService,
+        Deadline,
+        attribute::{AttributeService, AsyncResponse},
         discovery::DiscoveryService,
         method::MethodService,
         monitored_item::MonitoredItemService,
+        query::QueryService,
         session::SessionService,
         subscription::SubscriptionService,
         view::ViewService,
     },
     session::Session,
+    telemetry::Span,
 };
 use crate::services::node_management::NodeManagementService;
 
@@ -33,6 +40,16 @@ macro_rules! validated_request {
     }
 }
 
+macro_rules! sessionless_request {
+    ($validator: expr, $request: expr, $server_state: expr, $session: expr, $action: block) => {
+        if let Err(response) = $validator.validate_sessionless_request($server_state, $session, &$request.request_header) {
+            Some(response)
+        } else {
+            Some($action?)
+        }
+    }
+}
+
 /// Processes and dispatches messages for handling
 pub struct MessageHandler {
     /// Certificate store for certs
@@ -40,7 +57,7 @@ pub struct MessageHandler {
     /// Server state
     server_state: Arc<RwLock<ServerState>>,
     /// Address space
-    address_space: Arc<RwLock<AddressSpace>>,
+    address_space: Arc<SharedAddressSpace>,
     /// Session state
     session: Arc<RwLock<Session>>,
     /// Attribute service
@@ -53,16 +70,22 @@ pub struct MessageHandler {
     method_service: MethodService,
     /// MonitoredItem service
     monitored_item_service: MonitoredItemService,
+    /// Query service
+    query_service: QueryService,
     /// Session service
     session_service: SessionService,
     /// Subscription service
     subscription_service: SubscriptionService,
     /// View service
     view_service: ViewService,
+    /// Telemetry span of the connection
+    connection_span: Span,
+    /// Telemetry span of the session and the id of the session it was made for
+    session_span: Option<(NodeId, Span)>,
 }
 
 impl MessageHandler {
-    pub fn new(certificate_store: Arc<RwLock<CertificateStore>>, server_state: Arc<RwLock<ServerState>>, session: Arc<RwLock<Session>>, address_space: Arc<RwLock<AddressSpace>>) -> MessageHandler {
+    pub fn new(certificate_store: Arc<RwLock<CertificateStore>>, server_state: Arc<RwLock<ServerState>>, session: Arc<RwLock<Session>>, address_space: Arc<SharedAddressSpace>) -> MessageHandler {
         MessageHandler {
             certificate_store,
             server_state,
@@ -72,13 +95,27 @@ impl MessageHandler {
             discovery_service: DiscoveryService::new(),
             method_service: MethodService::new(),
             monitored_item_service: MonitoredItemService::new(),
+            query_service: QueryService::new(),
             node_management_service: NodeManagementService::new(),
             session_service: SessionService::new(),
             view_service: ViewService::new(),
             subscription_service: SubscriptionService::new(),
+            connection_span: Span::none(),
+            session_span: None,
         }
     }
 
+    /// Sets the telemetry span of the connection, which the spans of sessions are within
+    pub(crate) fn set_connection_span(&mut self, connection_span: Span) {
+        self.connection_span = connection_span;
+        self.session_span = None;
+    }
+
+    /// Returns the certificate store that the remote end's certificates are validated against
+    pub(crate) fn certificate_store(&self) -> Arc<RwLock<CertificateStore>> {
+        self.certificate_store.clone()
+    }
+
     /// Validates the request header information to ensure it is valid for the session.
     ///
     /// The request header should contain the session authentication token issued during a
@@ -98,137 +135,238 @@ impl MessageHandler {
             error!("supplied authentication token {:?} does not match session's expected token {:?}", request_header.authentication_token, session.authentication_token);
             Err(ServiceFault::new_supported_message(request_header, StatusCode::BadIdentityTokenRejected))
         } else {
+            // A valid request keeps the session from timing out
+            session.on_activity();
             Ok(())
         }
     }
 
-    pub fn handle_message(&mut self, request_id: u32, message: SupportedMessage) -> Result<Option<SupportedMessage>, StatusCode> {
-        // Note address space has to be locked before server_state because of deadlock in address_space.rs
-        // or other vars tied to state that will happen the other way around.
-        let mut server_state = trace_write_lock_unwrap!(self.server_state);
-        let mut session = trace_write_lock_unwrap!(self.session);
+    /// Validates a request which may be a session-less call, i.e. a call on a secure channel that
+    /// has no session, whose authentication token is an access token. Otherwise the request is
+    /// validated for the session.
+    fn validate_sessionless_request(&self, server_state: &ServerState, session: &mut Session, request_header: &RequestHeader) -> Result<(), SupportedMessage> {
+        if let Some(access_token) = Self::sessionless_access_token(session, request_header) {
+            let (security_policy, security_mode) = {
+                let secure_channel = trace_read_lock_unwrap!(session.secure_channel);
+                (secure_channel.security_policy(), secure_channel.security_mode())
+            };
+            let identity = server_state.authenticate_access_token(session.endpoint_url.as_ref(), security_policy, security_mode, &access_token)
+                .map_err(|status_code| {
+                    error!("Session-less call was rejected, status code = {}", status_code);
+                    ServiceFault::new_supported_message(request_header, status_code)
+                })?;
+            session.roles = server_state.roles_for(&identity);
+            session.identity = identity;
+            Ok(())
+        } else {
+            self.validate_request(session, request_header)
+        }
+    }
 
-        // This MUST be last of the lockable items because server impls may set timers on this but not
-        // state / session.
-        let mut address_space = trace_write_lock_unwrap!(self.address_space);
+    /// Returns the access token of a session-less call. A call is session-less when no session
+    /// has been created and the authentication token is a string or byte string node id in
+    /// namespace 0 holding the access token, e.g. a JWT.
+    fn sessionless_access_token(session: &Session, request_header: &RequestHeader) -> Option<ByteString> {
+        if !session.authentication_token.is_null() || request_header.authentication_token.namespace != 0 {
+            None
+        } else {
+            match request_header.authentication_token.identifier {
+                Identifier::String(ref token) => Some(ByteString::from(token.as_ref().as_bytes())),
+                Identifier::ByteString(ref token) => Some(token.clone()),
+                _ => None
+            }
+        }
+    }
 
-        let response = match message {
+    pub fn handle_message(&mut self, request_id: u32, message: SupportedMessage) -> Result<Option<SupportedMessage>, StatusCode> {
+        let started = Instant::now();
+        let request_span = self.request_span(request_id, &message);
+        let _entered = request_span.enter();
+        // Each handler passes on the requests it does not handle to the next, which takes
+        // stronger locks. The locks that a handler takes are the types its services are called
+        // with, so a request can only be handled under locks that allow what its service does.
+        let result = self.handle_read_only_message(request_id, message);
+        request_span.record_response(started, &result);
+        result
+    }
 
-            // Discovery Service Set, OPC UA Part 4, Section 5.4
-            SupportedMessage::GetEndpointsRequest(ref request) => {
-                Some(self.discovery_service.get_endpoints(&server_state, request)?)
+    /// Returns the telemetry span of the request, which is within the span of the session. The
+    /// session span is made again when the session changes, e.g. when a session is created.
+    fn request_span(&mut self, request_id: u32, message: &SupportedMessage) -> Span {
+        let session_id = {
+            let session = trace_read_lock_unwrap!(self.session);
+            session.session_id.clone()
+        };
+        if session_id.is_null() {
+            self.session_span = None;
+            self.connection_span.request(request_id, message)
+        } else {
+            let is_current = self.session_span.as_ref().map(|(id, _)| *id == session_id).unwrap_or(false);
+            if !is_current {
+                let session_span = self.connection_span.session(&session_id);
+                self.session_span = Some((session_id, session_span));
             }
+            self.session_span.as_ref().unwrap().1.request(request_id, message)
+        }
+    }
 
-            // Session Service Set, OPC UA Part 4, Section 5.6
+    /// Handles a request that only reads the server state and address space. These are handled
+    /// under read locks so sessions can browse, read and publish concurrently. The session is
+    /// still locked for writing but it belongs to this connection alone. Any other request is
+    /// passed on to `handle_server_state_message`.
+    fn handle_read_only_message(&mut self, request_id: u32, message: SupportedMessage) -> Result<Option<SupportedMessage>, StatusCode> {
+        // The locks are always taken in the order server state, session, address space
+        let server_state = trace_read_lock_unwrap!(self.server_state);
+        let mut session = trace_write_lock_unwrap!(self.session);
+        let address_space = trace_read_lock_unwrap!(self.address_space);
 
-            SupportedMessage::CreateSessionRequest(ref request) => {
-                let certificate_store = trace_read_lock_unwrap!(self.certificate_store);
-                Some(self.session_service.create_session(&certificate_store, &mut server_state, &mut session, request)?)
-            }
-            SupportedMessage::CloseSessionRequest(ref request) => {
-                Some(self.session_service.close_session(&mut session, request)?)
-            }
+        let response = match message {
 
-            // NOTE - ALL THE REQUESTS BEYOND THIS POINT MUST BE VALIDATED AGAINST THE SESSION
+            // View Service Set, OPC UA Part 4, Section 5.8
 
-            SupportedMessage::ActivateSessionRequest(ref request) => {
+            SupportedMessage::BrowseRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
-                    self.session_service.activate_session(&mut server_state, &mut session, request)
+                    self.view_service.browse(&server_state, &mut session, &address_space, request)
                 })
             }
-            SupportedMessage::CancelRequest(ref request) => {
+            SupportedMessage::BrowseNextRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
-                    self.session_service.cancel(&mut server_state, &mut session, request)
+                    self.view_service.browse_next(&mut session, &address_space, request)
                 })
             }
-
-            // NodeManagement Service Set, OPC UA Part 4, Section 5.7
-
-            SupportedMessage::AddNodesRequest(ref request) => {
+            SupportedMessage::TranslateBrowsePathsToNodeIdsRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
-                    self.node_management_service.add_nodes(&server_state, &session, &mut address_space, request)
+                    self.view_service.translate_browse_paths_to_node_ids(&server_state, &address_space, request)
                 })
             }
 
-            SupportedMessage::AddReferencesRequest(ref request) => {
-                validated_request!(self, request, &mut session, {
-                    self.node_management_service.add_references(&server_state, &session, &mut address_space, request)
-                })
-            }
+            // Query Service Set, OPC UA Part 4, Section 5.9
 
-            SupportedMessage::DeleteNodesRequest(ref request) => {
+            SupportedMessage::QueryFirstRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
-                    self.node_management_service.delete_nodes(&server_state, &session, &mut address_space, request)
+                    self.query_service.query_first(&server_state, &mut session, &address_space, request)
                 })
             }
 
-            SupportedMessage::DeleteReferencesRequest(ref request) => {
-                validated_request!(self, request, &mut session, {
-                    self.node_management_service.delete_references(&server_state, &session, &mut address_space, request)
+            // Attribute Service Set, OPC UA Part 4, Section 5.10
+
+            SupportedMessage::ReadRequest(ref request) => {
+                sessionless_request!(self, request, &server_state, &mut session, {
+                    self.attribute_service.read(&server_state, &*session, &address_space, request)
                 })
             }
 
-            // View Service Set, OPC UA Part 4, Section 5.8
+            // Monitored Item Service Set, OPC UA Part 4, Section 5.12
 
-            SupportedMessage::BrowseRequest(ref request) => {
+            SupportedMessage::CreateMonitoredItemsRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
-                    self.view_service.browse(&mut session, &address_space, request)
+                    self.monitored_item_service.create_monitored_items(&mut session, &address_space, request)
                 })
             }
-            SupportedMessage::BrowseNextRequest(ref request) => {
+            SupportedMessage::ModifyMonitoredItemsRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
-                    self.view_service.browse_next(&mut session, &address_space, request)
+                    self.monitored_item_service.modify_monitored_items(&mut session, &address_space, request)
                 })
             }
-            SupportedMessage::TranslateBrowsePathsToNodeIdsRequest(ref request) => {
-                validated_request!(self, request, &mut session, {
-                    self.view_service.translate_browse_paths_to_node_ids(&server_state, &address_space, request)
-                })
+
+            // Subscription Service Set, OPC UA Part 4, Section 5.13
+
+            SupportedMessage::PublishRequest(ref request) => {
+                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                    Some(response)
+                } else {
+                    // Unlike other calls which return immediately, this one is asynchronous - the
+                    // request is queued and the response will come back out of sequence some time in
+                    // the future.
+                    self.subscription_service.async_publish(&Utc::now(), &mut session, &address_space, request_id, &request)?
+                }
             }
-            SupportedMessage::RegisterNodesRequest(ref request) => {
+
+            _ => {
+                drop(address_space);
+                drop(session);
+                drop(server_state);
+                return self.handle_server_state_message(request_id, message);
+            }
+        };
+
+        self.complete_response(request_id, &server_state, &mut session, &address_space, &message, response)
+    }
+
+    /// Handles a request that modifies the server state or the session but only reads the address
+    /// space. Any other request is passed on to `handle_address_space_message`.
+    fn handle_server_state_message(&mut self, request_id: u32, message: SupportedMessage) -> Result<Option<SupportedMessage>, StatusCode> {
+        let mut server_state = trace_write_lock_unwrap!(self.server_state);
+        let mut session = trace_write_lock_unwrap!(self.session);
+        let address_space = trace_read_lock_unwrap!(self.address_space);
+
+        let response = match message {
+
+            // Discovery Service Set, OPC UA Part 4, Section 5.4
+            SupportedMessage::GetEndpointsRequest(ref request) => {
+                Some(self.discovery_service.get_endpoints(&server_state, request)?)
+            }
+
+            // Session Service Set, OPC UA Part 4, Section 5.6
+
+            SupportedMessage::CreateSessionRequest(ref request) => {
+                let certificate_store = trace_read_lock_unwrap!(self.certificate_store);
+                Some(self.session_service.create_session(&certificate_store, &mut server_state, &mut session, request)?)
+            }
+            SupportedMessage::CloseSessionRequest(ref request) => {
+                Some(self.session_service.close_session(&mut server_state, &mut session, request)?)
+            }
+
+            // NOTE - ALL THE REQUESTS BEYOND THIS POINT MUST BE VALIDATED AGAINST THE SESSION
+
+            SupportedMessage::ActivateSessionRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
-                    self.view_service.register_nodes(&mut server_state, self.session.clone(), request)
+                    let certificate_store = trace_read_lock_unwrap!(self.certificate_store);
+                    self.session_service.activate_session(&certificate_store, &mut server_state, &mut session, request)
                 })
             }
-            SupportedMessage::UnregisterNodesRequest(ref request) => {
+            SupportedMessage::CancelRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
-                    self.view_service.unregister_nodes(&mut server_state, self.session.clone(), request)
+                    self.session_service.cancel(&mut server_state, &mut session, request)
                 })
             }
 
-            // Attribute Service Set, OPC UA Part 4, Section 5.10
+            // View Service Set, OPC UA Part 4, Section 5.8
 
-            SupportedMessage::ReadRequest(ref request) => {
+            SupportedMessage::RegisterNodesRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
-                    self.attribute_service.read(&address_space, request)
+                    self.view_service.register_nodes(&mut server_state, &mut session, self.session.clone(), request)
                 })
             }
-            SupportedMessage::WriteRequest(ref request) => {
+            SupportedMessage::UnregisterNodesRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
-                    self.attribute_service.write(&mut address_space, request)
+                    self.view_service.unregister_nodes(&mut server_state, &mut session, self.session.clone(), request)
                 })
             }
 
-            // Method Service Set, OPC UA Part 4, Section 5.11
+            // Query Service Set, OPC UA Part 4, Section 5.9
 
-            SupportedMessage::CallRequest(ref request) => {
+            SupportedMessage::QueryNextRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
-                    self.method_service.call(&mut address_space, &server_state, &mut session, request)
+                    self.query_service.query_next(&mut session, request)
                 })
             }
 
-            // Monitored Item Service Set, OPC UA Part 4, Section 5.12
+            // Attribute Service Set, OPC UA Part 4, Section 5.10
 
-            SupportedMessage::CreateMonitoredItemsRequest(ref request) => {
+            SupportedMessage::HistoryReadRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
-                    self.monitored_item_service.create_monitored_items(&mut session, request)
+                    self.attribute_service.history_read(&mut server_state, &mut session, &address_space, request)
                 })
             }
-            SupportedMessage::ModifyMonitoredItemsRequest(ref request) => {
+            SupportedMessage::HistoryUpdateRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
-                    self.monitored_item_service.modify_monitored_items(&mut session, request)
+                    self.attribute_service.history_update(&mut server_state, &address_space, request)
                 })
             }
+
+            // Monitored Item Service Set, OPC UA Part 4, Section 5.12
+
             SupportedMessage::SetMonitoringModeRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
                     self.monitored_item_service.set_monitoring_mode(&mut session, request)
@@ -269,30 +407,153 @@ impl MessageHandler {
             }
             SupportedMessage::TransferSubscriptionsRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
-                    self.subscription_service.transfer_subscriptions(&mut session, request)
+                    self.subscription_service.transfer_subscriptions(&mut server_state, &mut session, request)
                 })
             }
-            SupportedMessage::PublishRequest(ref request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
-                    Some(response)
-                } else {
-                    // Unlike other calls which return immediately, this one is asynchronous - the
-                    // request is queued and the response will come back out of sequence some time in
-                    // the future.
-                    self.subscription_service.async_publish(&Utc::now(), &mut session, &address_space, request_id, &request)?
-                }
-            }
             SupportedMessage::RepublishRequest(ref request) => {
                 validated_request!(self, request, &mut session, {
                     self.subscription_service.republish(&mut session, request)
                 })
             }
 
+            _ => {
+                drop(address_space);
+                drop(session);
+                drop(server_state);
+                return self.handle_address_space_message(request_id, message);
+            }
+        };
+
+        self.complete_response(request_id, &server_state, &mut session, &address_space, &message, response)
+    }
+
+    /// Handles a request that modifies the address space. Only these requests lock the address
+    /// space for writing.
+    fn handle_address_space_message(&mut self, request_id: u32, message: SupportedMessage) -> Result<Option<SupportedMessage>, StatusCode> {
+        let server_state = trace_read_lock_unwrap!(self.server_state);
+        let mut session = trace_write_lock_unwrap!(self.session);
+
+        // This MUST be last of the lockable items because server impls may set timers on this but not
+        // state / session.
+        let mut address_space = trace_write_lock_unwrap!(self.address_space);
+
+        let response = match message {
+
+            // NodeManagement Service Set, OPC UA Part 4, Section 5.7
+
+            SupportedMessage::AddNodesRequest(ref request) => {
+                validated_request!(self, request, &mut session, {
+                    self.node_management_service.add_nodes(&server_state, &session, &mut address_space, request)
+                })
+            }
+            SupportedMessage::AddReferencesRequest(ref request) => {
+                validated_request!(self, request, &mut session, {
+                    self.node_management_service.add_references(&server_state, &session, &mut address_space, request)
+                })
+            }
+            SupportedMessage::DeleteNodesRequest(ref request) => {
+                validated_request!(self, request, &mut session, {
+                    self.node_management_service.delete_nodes(&server_state, &session, &mut address_space, request)
+                })
+            }
+            SupportedMessage::DeleteReferencesRequest(ref request) => {
+                validated_request!(self, request, &mut session, {
+                    self.node_management_service.delete_references(&server_state, &session, &mut address_space, request)
+                })
+            }
+
+            // Attribute Service Set, OPC UA Part 4, Section 5.10
+
+            SupportedMessage::WriteRequest(ref request) => {
+                sessionless_request!(self, request, &server_state, &mut session, {
+                    self.attribute_service.write(&server_state, &*session, &mut address_space, request)
+                })
+            }
+
+            // Method Service Set, OPC UA Part 4, Section 5.11
+
+            SupportedMessage::CallRequest(ref request) => {
+                sessionless_request!(self, request, &server_state, &mut session, {
+                    self.method_service.call(&mut address_space, &server_state, &mut session, request)
+                })
+            }
+
             _ => {
                 debug!("Message handler does not handle this kind of message {:?}", message);
                 return Err(StatusCode::BadServiceUnsupported);
             }
         };
+
+        self.complete_response(request_id, &server_state, &mut session, &address_space, &message, response)
+    }
+
+    /// Records the request in the diagnostics, raises its audit events and returns its response,
+    /// or `None` if the response is completed later by the async getters or setters of variables.
+    fn complete_response(&self, request_id: u32, server_state: &ServerState, session: &mut Session, address_space: &AddressSpace, message: &SupportedMessage, response: Option<SupportedMessage>) -> Result<Option<SupportedMessage>, StatusCode> {
+        // Record the request in the server and session diagnostics
+        {
+            let mut diagnostics = trace_write_lock_unwrap!(server_state.diagnostics);
+            diagnostics.on_request(session, message, response.as_ref());
+        }
+
+        // Audit events are logged and raised through their own locks so the server state and
+        // address space only need to be read
+        let server_id = server_state.application_uri.clone();
+        for event in audit::audit_request(&server_id, session, message, response.as_ref()) {
+            server_state.log_audit_event(&event);
+            address_space.raise_event(event);
+        }
+
+        // Reads and writes of variables with async getters and setters are completed later
+        if let Some((request_header, async_response)) = self.async_response(server_state, session, address_space, message, response.as_ref()) {
+            self.spawn_async_response(session, request_id, request_header, async_response);
+            return Ok(None);
+        }
+
         Ok(response)
     }
+
+    /// Returns the future of the response to a read or write whose values come from the async
+    /// getters or setters of variables, or `None` if the response is already complete. Getters and
+    /// setters time out after the server's async timeout or the timeout hint of the request,
+    /// whichever is shorter.
+    fn async_response<'a>(&self, server_state: &ServerState, session: &Session, address_space: &AddressSpace, message: &'a SupportedMessage, response: Option<&SupportedMessage>) -> Option<(&'a RequestHeader, AsyncResponse)> {
+        let async_timeout = {
+            let config = trace_read_lock_unwrap!(server_state.config);
+            Duration::from_millis(config.async_timeout as u64)
+        };
+        match (message, response) {
+            (SupportedMessage::ReadRequest(request), Some(SupportedMessage::ReadResponse(response))) => {
+                let timeout = Deadline::new(&request.request_header).limit(async_timeout);
+                self.attribute_service.async_read(session, address_space, request, response, timeout)
+                    .map(|async_response| (&request.request_header, async_response))
+            }
+            (SupportedMessage::WriteRequest(request), Some(SupportedMessage::WriteResponse(response))) => {
+                let timeout = Deadline::new(&request.request_header).limit(async_timeout);
+                self.attribute_service.async_write(session, address_space, request, response, timeout)
+                    .map(|async_response| (&request.request_header, async_response))
+            }
+            _ => None
+        }
+    }
+
+    /// Spawns the future of an async response, which queues the response on the session when it
+    /// completes. If the request is cancelled first, the future is dropped and the request is
+    /// answered with `BadRequestCancelledByClient` instead.
+    fn spawn_async_response(&self, session: &mut Session, request_id: u32, request_header: &RequestHeader, async_response: AsyncResponse) {
+        let cancelled = session.add_async_request(request_id, request_header.request_handle);
+        let cancelled_response = ServiceFault::new_supported_message(request_header, StatusCode::BadRequestCancelledByClient);
+        let session = self.session.clone();
+        tokio::spawn(async_response.select2(cancelled).then(move |result| {
+            let response = match result {
+                Ok(Either::A((response, _))) => response,
+                Ok(Either::B(_)) => cancelled_response,
+                // The response failed or the session has gone
+                Err(_) => return Ok(())
+            };
+            let mut session = trace_write_lock_unwrap!(session);
+            session.enqueue_async_response(request_id, response);
+            Ok::<(), ()>(())
+        }));
+    }
 }
@@ -6,6 +6,7 @@ use opcua_types::service_types::*;
 
 use crate::{
     address_space::AddressSpace,
+    config::OperationalLimits,
     services::Service,
     session::Session,
     state::ServerState,
@@ -25,7 +26,7 @@ impl MethodService {
 
     pub fn call(&self, address_space: &mut AddressSpace, server_state: &ServerState, session: &mut Session, request: &CallRequest) -> Result<SupportedMessage, StatusCode> {
         if let Some(ref calls) = request.methods_to_call {
-            if calls.len() >= server_state.max_method_calls() {
+            if OperationalLimits::is_exceeded(server_state.operational_limits.max_nodes_per_method_call, calls.len()) {
                 Ok(self.service_fault(&request.request_header, StatusCode::BadTooManyOperations))
             } else {
                 let results: Vec<CallMethodResult> = calls.iter().map(|request| {
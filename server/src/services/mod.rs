@@ -1,7 +1,11 @@
-use opcua_types::SupportedMessage;
+use std::time::{Duration, Instant};
+
+use opcua_types::{NodeId, SupportedMessage};
 use opcua_types::service_types::{RequestHeader, ServiceFault};
 use opcua_types::status_code::StatusCode;
 
+use crate::callbacks::UserIdentity;
+
 pub mod message_handler;
 
 /// The implementation of a service, or a set of services will implement this trait
@@ -14,11 +18,63 @@ trait Service {
     }
 }
 
+/// The time by which a request should be complete, from the timeout hint of its header. A client
+/// gives up on a request after its timeout hint, so long running services stop work on a request
+/// which is past its deadline and fail what remains with `BadTimeout`. A hint of 0 means no
+/// deadline.
+pub(crate) struct Deadline {
+    deadline: Option<Instant>,
+}
+
+impl Deadline {
+    pub fn new(request_header: &RequestHeader) -> Deadline {
+        let deadline = if request_header.timeout_hint > 0 {
+            Some(Instant::now() + Duration::from_millis(u64::from(request_header.timeout_hint)))
+        } else {
+            None
+        };
+        Deadline { deadline }
+    }
+
+    /// Tests if the deadline has passed
+    pub fn has_passed(&self) -> bool {
+        self.deadline.map(|deadline| Instant::now() >= deadline).unwrap_or(false)
+    }
+
+    /// Shortens the timeout to the time remaining before the deadline
+    pub fn limit(&self, timeout: Duration) -> Duration {
+        if let Some(deadline) = self.deadline {
+            let now = Instant::now();
+            let remaining = if deadline > now { deadline - now } else { Duration::from_millis(0) };
+            timeout.min(remaining)
+        } else {
+            timeout
+        }
+    }
+}
+
+/// The user that a service is performed for, whose identity and roles the server's access control
+/// is applied to. A `Session` is the user of the services that a client calls, while the REST
+/// gateway performs them for the user that each of its requests authenticates as.
+pub(crate) trait ServiceUser {
+    /// The user authenticated by the user identity token or request
+    fn identity(&self) -> &UserIdentity;
+
+    /// The roles granted to the user
+    fn roles(&self) -> &[NodeId];
+
+    /// Resolves a node id which was registered through RegisterNodes to the node it stands for
+    fn resolve_node_id<'a>(&'a self, node_id: &'a NodeId) -> &'a NodeId {
+        node_id
+    }
+}
+
 pub mod attribute;
 pub mod discovery;
 pub mod method;
 pub mod monitored_item;
 pub mod node_management;
+pub mod query;
 pub mod session;
 pub mod subscription;
 pub mod view;
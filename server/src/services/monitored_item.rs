@@ -4,7 +4,7 @@ use opcua_types::*;
 use opcua_types::status_code::StatusCode;
 use opcua_types::service_types::*;
 
-use crate::{session::Session, services::Service};
+use crate::{address_space::AddressSpace, config::OperationalLimits, session::Session, services::Service};
 
 /// The monitored item service. Allows client to create, modify and delete monitored items on a subscription.
 pub(crate) struct MonitoredItemService;
@@ -19,15 +19,19 @@ impl MonitoredItemService {
     }
 
     /// Implementation of CreateMonitoredItems service. See OPC Unified Architecture, Part 4 5.12.2
-    pub fn create_monitored_items(&self, session: &mut Session, request: &CreateMonitoredItemsRequest) -> Result<SupportedMessage, StatusCode> {
+    pub fn create_monitored_items(&self, session: &mut Session, address_space: &AddressSpace, request: &CreateMonitoredItemsRequest) -> Result<SupportedMessage, StatusCode> {
         if is_empty_option_vec!(request.items_to_create) {
             Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
+        } else if OperationalLimits::is_exceeded(session.max_monitored_items_per_call(), request.items_to_create.as_ref().unwrap().len()) {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadTooManyOperations))
         } else {
             let items_to_create = request.items_to_create.as_ref().unwrap();
             // Find subscription and add items to it
+            let max_monitored_items = session.subscriptions.max_monitored_items_per_subscription();
+            let min_sampling_interval = session.subscriptions.min_sampling_interval();
             if let Some(subscription) = session.subscriptions.get_mut(request.subscription_id) {
                 let now = chrono::Utc::now();
-                let results = Some(subscription.create_monitored_items(&now, request.timestamps_to_return, items_to_create));
+                let results = Some(subscription.create_monitored_items(&now, address_space, request.timestamps_to_return, items_to_create, max_monitored_items, min_sampling_interval));
                 let response = CreateMonitoredItemsResponse {
                     response_header: ResponseHeader::new_good(&request.request_header),
                     results,
@@ -42,15 +46,18 @@ impl MonitoredItemService {
     }
 
     /// Implementation of ModifyMonitoredItems service. See OPC Unified Architecture, Part 4 5.12.3
-    pub fn modify_monitored_items(&self, session: &mut Session, request: &ModifyMonitoredItemsRequest) -> Result<SupportedMessage, StatusCode> {
+    pub fn modify_monitored_items(&self, session: &mut Session, address_space: &AddressSpace, request: &ModifyMonitoredItemsRequest) -> Result<SupportedMessage, StatusCode> {
         if is_empty_option_vec!(request.items_to_modify) {
             Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
+        } else if OperationalLimits::is_exceeded(session.max_monitored_items_per_call(), request.items_to_modify.as_ref().unwrap().len()) {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadTooManyOperations))
         } else {
             let items_to_modify = request.items_to_modify.as_ref().unwrap();
             // Find subscription and modify items in it
             let subscription_id = request.subscription_id;
+            let min_sampling_interval = session.subscriptions.min_sampling_interval();
             if let Some(subscription) = session.subscriptions.get_mut(subscription_id) {
-                let results = Some(subscription.modify_monitored_items(request.timestamps_to_return, items_to_modify));
+                let results = Some(subscription.modify_monitored_items(address_space, request.timestamps_to_return, items_to_modify, min_sampling_interval));
                 let response = ModifyMonitoredItemsResponse {
                     response_header: ResponseHeader::new_good(&request.request_header),
                     results,
@@ -68,6 +75,8 @@ impl MonitoredItemService {
     pub fn set_monitoring_mode(&self, session: &mut Session, request: &SetMonitoringModeRequest) -> Result<SupportedMessage, StatusCode> {
         if is_empty_option_vec!(request.monitored_item_ids) {
             Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
+        } else if OperationalLimits::is_exceeded(session.max_monitored_items_per_call(), request.monitored_item_ids.as_ref().unwrap().len()) {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadTooManyOperations))
         } else {
             let monitored_item_ids = request.monitored_item_ids.as_ref().unwrap();
             let subscription_id = request.subscription_id;
@@ -101,6 +110,9 @@ impl MonitoredItemService {
                 Some(ref links_to_remove) => &links_to_remove[..],
                 None => &[]
             };
+            if OperationalLimits::is_exceeded(session.max_monitored_items_per_call(), links_to_add.len() + links_to_remove.len()) {
+                return Ok(self.service_fault(&request.request_header, StatusCode::BadTooManyOperations));
+            }
 
             // Set the triggering on the subscription.
             let subscription_id = request.subscription_id;
@@ -130,6 +142,8 @@ impl MonitoredItemService {
     pub fn delete_monitored_items(&self, session: &mut Session, request: &DeleteMonitoredItemsRequest) -> Result<SupportedMessage, StatusCode> {
         if is_empty_option_vec!(request.monitored_item_ids) {
             Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
+        } else if OperationalLimits::is_exceeded(session.max_monitored_items_per_call(), request.monitored_item_ids.as_ref().unwrap().len()) {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadTooManyOperations))
         } else {
             let monitored_item_ids = request.monitored_item_ids.as_ref().unwrap();
             // Find subscription and delete items from it
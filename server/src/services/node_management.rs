@@ -4,7 +4,7 @@ use opcua_types::{
     *,
     status_code::StatusCode,
     service_types::*,
-    node_ids::ObjectId,
+    node_ids::{ObjectId, ReferenceTypeId},
 };
 
 use crate::{
@@ -13,6 +13,7 @@ use crate::{
         relative_path,
         types::*,
     },
+    config::OperationalLimits,
     session::Session,
     services::Service,
     state::ServerState,
@@ -33,7 +34,7 @@ impl NodeManagementService {
     pub fn add_nodes(&self, server_state: &ServerState, session: &Session, address_space: &mut AddressSpace, request: &AddNodesRequest) -> Result<SupportedMessage, StatusCode> {
         if let Some(ref nodes_to_add) = request.nodes_to_add {
             if !nodes_to_add.is_empty() {
-                if nodes_to_add.len() <= server_state.max_nodes_per_node_management() {
+                if !OperationalLimits::is_exceeded(server_state.operational_limits.max_nodes_per_node_management, nodes_to_add.len()) {
                     let results = nodes_to_add.iter().map(|node_to_add| {
                         let (status_code, added_node_id) = Self::add_node(session, address_space, node_to_add);
                         AddNodesResult {
@@ -62,7 +63,7 @@ impl NodeManagementService {
     pub fn add_references(&self, server_state: &ServerState, session: &Session, address_space: &mut AddressSpace, request: &AddReferencesRequest) -> Result<SupportedMessage, StatusCode> {
         if let Some(ref references_to_add) = request.references_to_add {
             if !references_to_add.is_empty() {
-                if references_to_add.len() <= server_state.max_nodes_per_node_management() {
+                if !OperationalLimits::is_exceeded(server_state.operational_limits.max_nodes_per_node_management, references_to_add.len()) {
                     let results = references_to_add.iter().map(|r| {
                         Self::add_reference(session, address_space, r)
                     }).collect();
@@ -86,7 +87,7 @@ impl NodeManagementService {
     pub fn delete_nodes(&self, server_state: &ServerState, session: &Session, address_space: &mut AddressSpace, request: &DeleteNodesRequest) -> Result<SupportedMessage, StatusCode> {
         if let Some(ref nodes_to_delete) = request.nodes_to_delete {
             if !nodes_to_delete.is_empty() {
-                if nodes_to_delete.len() <= server_state.max_nodes_per_node_management() {
+                if !OperationalLimits::is_exceeded(server_state.operational_limits.max_nodes_per_node_management, nodes_to_delete.len()) {
                     let results = nodes_to_delete.iter().map(|node_to_delete| {
                         Self::delete_node(session, address_space, node_to_delete)
                     }).collect();
@@ -111,7 +112,7 @@ impl NodeManagementService {
     pub fn delete_references(&self, server_state: &ServerState, session: &Session, address_space: &mut AddressSpace, request: &DeleteReferencesRequest) -> Result<SupportedMessage, StatusCode> {
         if let Some(ref references_to_delete) = request.references_to_delete {
             if !references_to_delete.is_empty() {
-                if references_to_delete.len() <= server_state.max_nodes_per_node_management() {
+                if !OperationalLimits::is_exceeded(server_state.operational_limits.max_nodes_per_node_management, references_to_delete.len()) {
                     let results = references_to_delete.iter().map(|r| {
                         Self::delete_reference(session, address_space, r)
                     }).collect();
@@ -250,12 +251,14 @@ impl NodeManagementService {
         }
 
         // Test duplicate browse name to same parent
-        let browse_name = if item.browse_name.namespace_index != 0 {
-            format!("{}:{}", item.browse_name.namespace_index, item.browse_name.name.as_ref())
-        } else {
-            format!("/{}", item.browse_name.name.as_ref())
+        let relative_path = RelativePath {
+            elements: Some(vec![RelativePathElement {
+                reference_type_id: ReferenceTypeId::HierarchicalReferences.into(),
+                is_inverse: false,
+                include_subtypes: true,
+                target_name: item.browse_name.clone(),
+            }]),
         };
-        let relative_path = RelativePath::from_str(&browse_name, &RelativePathElement::default_node_resolver).unwrap();
         if let Ok(nodes) = relative_path::find_nodes_relative_path(address_space, &item.parent_node_id.node_id, &relative_path) {
             if !nodes.is_empty() {
                 error!("node cannot be created because the browse name is a duplicate");
@@ -282,16 +285,16 @@ impl NodeManagementService {
             }
 
             // Check that the parent node exists
-            if !item.parent_node_id.server_index == 0 || !address_space.node_exists(&item.parent_node_id.node_id) {
+            if item.parent_node_id.server_index != 0 || !address_space.node_exists(&item.parent_node_id.node_id) {
                 error!("node cannot be created because parent node id is invalid or does not exist");
                 return (StatusCode::BadParentNodeIdInvalid, NodeId::null());
             }
 
             // Create a node
             if let Ok(node) = Self::create_node(&new_node_id, item.node_class, item.browse_name.clone(), &item.node_attributes) {
-                // Add the node to the address space
+                // Add the node to the address space with the reference from its parent
                 address_space.insert(node, Some(&[
-                    (&item.parent_node_id.node_id, reference_type_id, ReferenceDirection::Forward),
+                    (&item.parent_node_id.node_id, reference_type_id, ReferenceDirection::Inverse),
                 ]));
                 // Object / Variable types must add a reference to the type
                 if item.node_class == NodeClass::Object || item.node_class == NodeClass::Variable {
@@ -0,0 +1,205 @@
+use std::result::Result;
+
+use opcua_types::*;
+use opcua_types::status_code::StatusCode;
+use opcua_types::service_types::*;
+
+use crate::{
+    address_space::{AccessLevel, AddressSpace, node::NodeType},
+    callbacks::QueryEvaluator,
+    content_filter,
+    continuation_point::QueryContinuationPoint,
+    query::{self, AddressSpaceQueryEvaluator},
+    roles,
+    session::Session,
+    state::ServerState,
+    services::{Deadline, Service},
+};
+
+/// The query service. Allows the client to search the address space for instances of types
+/// which satisfy a content filter.
+pub(crate) struct QueryService;
+
+impl Service for QueryService {
+    fn name(&self) -> String { String::from("QueryService") }
+}
+
+impl QueryService {
+    pub fn new() -> QueryService {
+        QueryService {}
+    }
+
+    /// Finds the instances of the node types in the request that satisfy its content filter and
+    /// returns the requested data of each. Instances which the session's user cannot browse are
+    /// left out of the results.
+    pub fn query_first(&self, server_state: &ServerState, session: &mut Session, address_space: &AddressSpace, request: &QueryFirstRequest) -> Result<SupportedMessage, StatusCode> {
+        if is_empty_option_vec!(request.node_types) {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
+        } else if !request.view.view_id.is_null() {
+            // Views are not supported
+            info!("Query request ignored because view was specified (views not supported)");
+            Ok(self.service_fault(&request.request_header, StatusCode::BadViewIdUnknown))
+        } else {
+            let node_types = request.node_types.as_ref().unwrap();
+            let parsing_results = query::validate_node_types(address_space, node_types);
+            if parsing_results.iter().any(|parsing_result| parsing_result.status_code.is_bad()) {
+                return Ok(Self::query_first_error(request, StatusCode::BadInvalidArgument, Some(parsing_results), Self::empty_filter_result()));
+            }
+            if let Err(filter_result) = content_filter::validate_content_filter(address_space, &request.filter) {
+                return Ok(Self::query_first_error(request, StatusCode::BadContentFilterInvalid, None, filter_result));
+            }
+
+            let deadline = Deadline::new(&request.request_header);
+            let matches = if let Some(ref query_evaluator) = server_state.query_evaluator {
+                query_evaluator.evaluate(address_space, node_types, &request.filter)
+            } else {
+                AddressSpaceQueryEvaluator.evaluate(address_space, node_types, &request.filter)
+            };
+            match matches {
+                Ok(matches) => {
+                    let max_references_to_return = request.max_references_to_return as usize;
+                    let mut data_sets = Vec::with_capacity(matches.len());
+                    for (node_id, node_type_idx) in matches.iter() {
+                        // The query is abandoned once it takes longer than the client allowed
+                        if deadline.has_passed() {
+                            return Ok(self.service_fault(&request.request_header, StatusCode::BadTimeout));
+                        }
+                        if Self::is_browsable(server_state, session, address_space, node_id) {
+                            data_sets.push(Self::query_data_set(server_state, session, address_space, node_id, &node_types[*node_type_idx], max_references_to_return));
+                        }
+                    }
+                    match Self::next_data_sets(session, data_sets, request.max_data_sets_to_return as usize) {
+                        Ok((query_data_sets, continuation_point)) => {
+                            let response = QueryFirstResponse {
+                                response_header: ResponseHeader::new_good(&request.request_header),
+                                query_data_sets: Some(query_data_sets),
+                                continuation_point,
+                                parsing_results: None,
+                                diagnostic_infos: None,
+                                filter_result: Self::empty_filter_result(),
+                            };
+                            Ok(response.into())
+                        }
+                        Err(status_code) => Ok(self.service_fault(&request.request_header, status_code))
+                    }
+                }
+                Err(status_code) => {
+                    error!("Query evaluator failed with status {}", status_code);
+                    Ok(self.service_fault(&request.request_header, status_code))
+                }
+            }
+        }
+    }
+
+    /// Returns the next data sets from a continuation point, or releases the continuation point
+    /// if the client no longer wants the rest of the data sets.
+    pub fn query_next(&self, session: &mut Session, request: &QueryNextRequest) -> Result<SupportedMessage, StatusCode> {
+        if let Some(continuation_point) = session.take_query_continuation_point(&request.continuation_point) {
+            let (query_data_sets, revised_continuation_point) = if request.release_continuation_point {
+                (None, ByteString::null())
+            } else {
+                match Self::next_data_sets(session, continuation_point.data_sets, continuation_point.max_data_sets_to_return) {
+                    Ok((query_data_sets, revised_continuation_point)) => (Some(query_data_sets), revised_continuation_point),
+                    Err(status_code) => {
+                        return Ok(self.service_fault(&request.request_header, status_code));
+                    }
+                }
+            };
+            let response = QueryNextResponse {
+                response_header: ResponseHeader::new_good(&request.request_header),
+                query_data_sets,
+                revised_continuation_point,
+            };
+            Ok(response.into())
+        } else {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadContinuationPointInvalid))
+        }
+    }
+
+    fn query_first_error(request: &QueryFirstRequest, service_result: StatusCode, parsing_results: Option<Vec<ParsingResult>>, filter_result: ContentFilterResult) -> SupportedMessage {
+        QueryFirstResponse {
+            response_header: ResponseHeader::new_service_result(&request.request_header, service_result),
+            query_data_sets: None,
+            continuation_point: ByteString::null(),
+            parsing_results,
+            diagnostic_infos: None,
+            filter_result,
+        }.into()
+    }
+
+    fn empty_filter_result() -> ContentFilterResult {
+        ContentFilterResult {
+            element_results: None,
+            element_diagnostic_infos: None,
+        }
+    }
+
+    fn is_browsable(server_state: &ServerState, session: &Session, address_space: &AddressSpace, node_id: &NodeId) -> bool {
+        address_space.find_node(node_id).map_or(false, |node| {
+            roles::is_permitted(node, &session.roles, PermissionType::BROWSE) && server_state.can_browse(&session.identity, node_id)
+        })
+    }
+
+    /// Makes the data set of a node from the data to return of its node type. Each value is null
+    /// if the relative path leads nowhere or the attribute cannot be read, and an array if the
+    /// relative path leads to more than one node.
+    fn query_data_set(server_state: &ServerState, session: &Session, address_space: &AddressSpace, node_id: &NodeId, node_type: &NodeTypeDescription, max_references_to_return: usize) -> QueryDataSet {
+        let values = node_type.data_to_return.as_ref().map(|data_to_return| {
+            data_to_return.iter().map(|data| {
+                // The attribute id was validated with the node types
+                let attribute_id = AttributeId::from_u32(data.attribute_id).unwrap();
+                let mut targets = query::find_relative_path_targets(address_space, node_id, &data.relative_path);
+                if max_references_to_return > 0 && targets.len() > max_references_to_return {
+                    targets.truncate(max_references_to_return);
+                }
+                let mut values = targets.iter()
+                    .map(|target| Self::read_value(server_state, session, address_space, target, attribute_id))
+                    .collect::<Vec<Variant>>();
+                match values.len() {
+                    0 => Variant::Empty,
+                    1 => values.remove(0),
+                    _ => Variant::Array(values)
+                }
+            }).collect()
+        });
+        QueryDataSet {
+            node_id: ExpandedNodeId::new(node_id.clone()),
+            type_definition_node: node_type.type_definition_node.clone(),
+            values,
+        }
+    }
+
+    fn read_value(server_state: &ServerState, session: &Session, address_space: &AddressSpace, node_id: &NodeId, attribute_id: AttributeId) -> Variant {
+        let readable = address_space.find_node(node_id).map_or(false, |node| {
+            let current_read = if let NodeType::Variable(ref variable) = *node {
+                attribute_id != AttributeId::Value || variable.access_level().contains(AccessLevel::CURRENT_READ)
+            } else {
+                true
+            };
+            current_read && roles::is_permitted(node, &session.roles, roles::read_permission(attribute_id)) &&
+                server_state.can_read(&session.identity, node_id, attribute_id)
+        });
+        if readable {
+            query::read_attribute(address_space, node_id, attribute_id).unwrap_or(Variant::Empty)
+        } else {
+            Variant::Empty
+        }
+    }
+
+    /// Splits off the data sets to return in a response. If there are more data sets than the
+    /// maximum, the remainder is held by a new continuation point.
+    fn next_data_sets(session: &mut Session, mut data_sets: Vec<QueryDataSet>, max_data_sets_to_return: usize) -> Result<(Vec<QueryDataSet>, ByteString), StatusCode> {
+        if max_data_sets_to_return > 0 && data_sets.len() > max_data_sets_to_return {
+            let remaining_data_sets = data_sets.split_off(max_data_sets_to_return);
+            let id = ByteString::random(6);
+            session.add_query_continuation_point(QueryContinuationPoint {
+                id: id.clone(),
+                max_data_sets_to_return,
+                data_sets: remaining_data_sets,
+            })?;
+            Ok((data_sets, id))
+        } else {
+            Ok((data_sets, ByteString::null()))
+        }
+    }
+}
@@ -9,6 +9,7 @@ use opcua_core::crypto::SecurityPolicy;
 use opcua_core::crypto::CertificateStore;
 
 use crate::{
+    callbacks::UserIdentity,
     constants,
     state::ServerState,
     session::Session,
@@ -27,6 +28,20 @@ impl SessionService {
         SessionService {}
     }
 
+    /// Revises the session timeout requested by a client to fit within the server's configured
+    /// range. A timeout which is zero, negative or not a number gets the largest permitted value.
+    pub(crate) fn revise_session_timeout(server_state: &ServerState, requested_session_timeout: f64) -> f64 {
+        if !(requested_session_timeout > 0f64) {
+            server_state.max_session_timeout
+        } else if requested_session_timeout < server_state.min_session_timeout {
+            server_state.min_session_timeout
+        } else if requested_session_timeout > server_state.max_session_timeout {
+            server_state.max_session_timeout
+        } else {
+            requested_session_timeout
+        }
+    }
+
     pub fn create_session(&self, certificate_store: &CertificateStore, server_state: &mut ServerState, session: &mut Session, request: &CreateSessionRequest) -> Result<SupportedMessage, StatusCode> {
         debug!("Create session request {:?}", request);
 
@@ -34,8 +49,16 @@ impl SessionService {
 
         // Check the args
         let service_result = {
-            // Validate the endpoint url
-            if request.endpoint_url.is_null() {
+            // The session of this connection is already counted
+            let current_session_count = {
+                let diagnostics = trace_read_lock_unwrap!(server_state.diagnostics);
+                diagnostics.server_diagnostics_summary().current_session_count as usize
+            };
+            if server_state.max_sessions > 0 && current_session_count > server_state.max_sessions {
+                error!("Create session was rejected, there are too many sessions {} for the limit {}", current_session_count, server_state.max_sessions);
+                StatusCode::BadTooManySessions
+            } else if request.endpoint_url.is_null() {
+                // Validate the endpoint url
                 error!("Create session was passed an null endpoint url");
                 StatusCode::BadTcpEndpointUrlInvalid
             } else {
@@ -57,8 +80,10 @@ impl SessionService {
         } else {
             let endpoints = endpoints.unwrap();
 
-            // Extract the client certificate if one is supplied
-            let client_certificate = crypto::X509::from_byte_string(&request.client_certificate).ok();
+            // Extract the client certificate and the certs of its issuers if they are supplied
+            let client_certificate_chain = crypto::X509::chain_from_byte_string(&request.client_certificate).ok();
+            let client_certificate = client_certificate_chain.as_ref().map(|chain| chain[0].clone());
+            let client_certificate_issuers = client_certificate_chain.as_ref().map(|chain| chain[1..].to_vec()).unwrap_or_default();
 
             // Check the client's certificate for validity and acceptance
             let security_policy = {
@@ -66,8 +91,11 @@ impl SessionService {
                 secure_channel.security_policy()
             };
             let service_result = if security_policy != SecurityPolicy::None {
-                let result = if let Some(ref client_certificate) = client_certificate {
-                    certificate_store.validate_or_reject_application_instance_cert(client_certificate, None, None)
+                let result = if let Some(ref chain) = client_certificate_chain {
+                    // The certificate must belong to the application that the client says it is
+                    let application_uri = request.client_description.application_uri.as_ref();
+                    let application_uri = if application_uri.is_empty() { None } else { Some(application_uri) };
+                    certificate_store.validate_or_reject_application_instance_cert_chain(&chain[0], &chain[1..], None, application_uri)
                 } else {
                     warn!("Certificate supplied by client is invalid");
                     StatusCode::BadCertificateInvalid
@@ -85,11 +113,7 @@ impl SessionService {
             let response = if service_result.is_bad() {
                 self.service_fault(&request.request_header, service_result)
             } else {
-                let session_timeout = if request.requested_session_timeout > constants::MAX_SESSION_TIMEOUT {
-                    constants::MAX_SESSION_TIMEOUT
-                } else {
-                    request.requested_session_timeout
-                };
+                let session_timeout = Self::revise_session_timeout(server_state, request.requested_session_timeout);
 
                 let max_request_message_size = constants::MAX_REQUEST_MESSAGE_SIZE;
 
@@ -106,13 +130,20 @@ impl SessionService {
 
                 session.authentication_token = authentication_token.clone();
                 session.session_timeout = session_timeout;
+                session.on_activity();
                 session.max_request_message_size = max_request_message_size;
                 session.max_response_message_size = request.max_response_message_size;
                 session.endpoint_url = request.endpoint_url.clone();
+                session.session_name = request.session_name.clone();
+                session.client_description = request.client_description.clone();
                 session.security_policy_uri = security_policy.to_uri().to_string();
                 session.user_identity = None;
+                session.identity = UserIdentity::NotActivated;
+                session.roles.clear();
                 session.client_certificate = client_certificate;
+                session.client_certificate_issuers = client_certificate_issuers;
                 session.session_nonce = server_nonce.clone();
+                server_state.on_session_created(session);
 
                 CreateSessionResponse {
                     response_header: ResponseHeader::new_good(&request.request_header),
@@ -131,7 +162,7 @@ impl SessionService {
         }
     }
 
-    pub fn activate_session(&self, server_state: &mut ServerState, session: &mut Session, request: &ActivateSessionRequest) -> Result<SupportedMessage, StatusCode> {
+    pub fn activate_session(&self, certificate_store: &CertificateStore, server_state: &mut ServerState, session: &mut Session, request: &ActivateSessionRequest) -> Result<SupportedMessage, StatusCode> {
         let endpoint_url = session.endpoint_url.as_ref();
 
         let (security_policy, security_mode) = {
@@ -141,58 +172,81 @@ impl SessionService {
 
         let server_nonce = security_policy.random_nonce();
 
-        let mut service_result = if !server_state.endpoint_exists(endpoint_url, security_policy, security_mode) {
+        let service_result = if !server_state.endpoint_exists(endpoint_url, security_policy, security_mode) {
             // Need an endpoint
             error!("Endpoint does not exist for requested url & mode {}, {:?} / {:?}", endpoint_url, security_policy, security_mode);
             StatusCode::BadTcpEndpointUrlInvalid
         } else if security_policy != SecurityPolicy::None {
             // Crypto see 5.6.3.1 verify the caller is the same caller as create_session by validating
             // signature supplied by the client during the create.
-            Self::verify_client_signature(server_state, session, &request.client_signature)
+            let result = Self::verify_client_signature(server_state, session, &request.client_signature);
+            // The certificate may have been revoked since the session was created
+            match session.client_certificate {
+                Some(ref client_certificate) if result.is_good() => certificate_store.check_revocation(client_certificate, &session.client_certificate_issuers),
+                _ => result
+            }
         } else {
             // No cert checks for no security
             StatusCode::Good
         };
 
-        if service_result.is_good() {
-            if let Err(err) = server_state.authenticate_endpoint(request, endpoint_url, security_policy, security_mode, &request.user_identity_token, &session.session_nonce) {
-                service_result = err;
-            }
-        }
-
         // Authenticate the user identity token
-        let response = if service_result.is_good() {
-            session.activated = true;
-            session.session_nonce = server_nonce;
-            let diagnostic_infos = None;
-
-            ActivateSessionResponse {
-                response_header: ResponseHeader::new_good(&request.request_header),
-                server_nonce: session.session_nonce.clone(),
-                results: None,
-                diagnostic_infos,
-            }.into()
+        let identity = if service_result.is_good() {
+            server_state.authenticate_endpoint(request, endpoint_url, security_policy, security_mode, &request.user_identity_token, &session.session_nonce)
         } else {
-            self.service_fault(&request.request_header, service_result)
+            Err(service_result)
+        };
+
+        let response = match identity {
+            Ok(identity) => {
+                session.activated = true;
+                session.session_nonce = server_nonce;
+                session.user_identity = Some(request.user_identity_token.clone());
+                session.roles = server_state.roles_for(&identity);
+                session.identity = identity;
+                server_state.on_session_activated(session);
+                let diagnostic_infos = None;
+
+                ActivateSessionResponse {
+                    response_header: ResponseHeader::new_good(&request.request_header),
+                    server_nonce: session.session_nonce.clone(),
+                    results: None,
+                    diagnostic_infos,
+                }.into()
+            }
+            Err(service_result) => self.service_fault(&request.request_header, service_result)
         };
         Ok(response)
     }
 
-    pub fn close_session(&self, session: &mut Session, request: &CloseSessionRequest) -> Result<SupportedMessage, StatusCode> {
+    pub fn close_session(&self, server_state: &mut ServerState, session: &mut Session, request: &CloseSessionRequest) -> Result<SupportedMessage, StatusCode> {
+        if !session.authentication_token.is_null() {
+            server_state.on_session_closed(session);
+        }
         session.authentication_token = NodeId::null();
-        session.user_identity = None;
         session.activated = false;
+        if request.delete_subscriptions {
+            // Dropping the subscriptions means they are not kept alive when the connection closes
+            let _ = session.subscriptions.take_all();
+        }
+        // The user identity is retained so that subscriptions which outlive the session can only be
+        // transferred to another session of the same user
         let response = CloseSessionResponse {
             response_header: ResponseHeader::new_good(&request.request_header),
         };
         Ok(response.into())
     }
 
-    pub fn cancel(&self, _server_state: &mut ServerState, _session: &mut Session, request: &CancelRequest) -> Result<SupportedMessage, StatusCode> {
-        // This service call currently does nothing
+    /// Cancels the outstanding requests of the session which have the request handle. Only requests
+    /// which are still outstanding once they have been handled can be cancelled, i.e. publish
+    /// requests and reads or writes waiting on async getters or setters. Each cancelled request is
+    /// answered with `BadRequestCancelledByClient`.
+    pub fn cancel(&self, _server_state: &mut ServerState, session: &mut Session, request: &CancelRequest) -> Result<SupportedMessage, StatusCode> {
+        let cancel_count = session.cancel_requests(request.request_handle);
+        debug!("Cancel of request handle {} cancelled {} requests", request.request_handle, cancel_count);
         let response = CancelResponse {
             response_header: ResponseHeader::new_good(&request.request_header),
-            cancel_count: 0,
+            cancel_count,
         };
         Ok(response.into())
     }
@@ -5,7 +5,7 @@ use opcua_types::status_code::StatusCode;
 use opcua_types::service_types::*;
 
 use crate::{
-    subscriptions::subscription::Subscription,
+    subscriptions::{subscription::Subscription, detached::user_identity_key},
     address_space::AddressSpace,
     state::ServerState,
     session::Session,
@@ -48,6 +48,7 @@ impl SubscriptionService {
                 revised_max_keep_alive_count,
                 request.priority);
             subscriptions.insert(subscription_id, subscription);
+            server_state.on_subscription_created(session, subscription_id);
 
             // Create the response
             CreateSubscriptionResponse {
@@ -124,19 +125,57 @@ impl SubscriptionService {
         }
     }
 
-    /// Handles a TransferSubscriptionsRequest
-    pub fn transfer_subscriptions(&self, _session: &mut Session, request: &TransferSubscriptionsRequest) -> Result<SupportedMessage, StatusCode> {
+    /// Handles a TransferSubscriptionsRequest. Subscriptions that were detached from their session
+    /// when it ended are moved to this session, provided it belongs to the same user.
+    pub fn transfer_subscriptions(&self, server_state: &mut ServerState, session: &mut Session, request: &TransferSubscriptionsRequest) -> Result<SupportedMessage, StatusCode> {
         if is_empty_option_vec!(request.subscription_ids) {
             Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
         } else {
             let subscription_ids = request.subscription_ids.as_ref().unwrap();
             let results = {
-                // TODO this is a stub. The real thing should look up subscriptions belonging to
-                //  other sessions and transfer them across to this one.
-                let results = subscription_ids.iter().map(|_subscription_id| {
-                    TransferResult {
-                        status_code: StatusCode::BadSubscriptionIdInvalid,
-                        available_sequence_numbers: None,
+                let user_identity = {
+                    let config = trace_read_lock_unwrap!(server_state.config);
+                    user_identity_key(&session.user_identity, &config.decoding_limits())
+                };
+                let max_subscriptions = server_state.max_subscriptions;
+                let results = subscription_ids.iter().map(|subscription_id| {
+                    let subscription_id = *subscription_id;
+                    if session.subscriptions.contains(subscription_id) {
+                        // Already belongs to this session
+                        TransferResult {
+                            status_code: StatusCode::Good,
+                            available_sequence_numbers: None,
+                        }
+                    } else if !server_state.detached_subscriptions.contains(subscription_id) {
+                        TransferResult {
+                            status_code: StatusCode::BadSubscriptionIdInvalid,
+                            available_sequence_numbers: None,
+                        }
+                    } else if max_subscriptions > 0 && session.subscriptions.len() >= max_subscriptions {
+                        TransferResult {
+                            status_code: StatusCode::BadTooManySubscriptions,
+                            available_sequence_numbers: None,
+                        }
+                    } else {
+                        match server_state.detached_subscriptions.take(subscription_id, &user_identity) {
+                            Ok(detached) => {
+                                let mut subscription = detached.subscription;
+                                subscription.reset_lifetime_counter();
+                                subscription.reset_keep_alive_counter();
+                                if request.send_initial_values {
+                                    subscription.set_resend_data();
+                                }
+                                let available_sequence_numbers = session.subscriptions.insert_transferred(subscription, detached.unacknowledged_notifications);
+                                TransferResult {
+                                    status_code: StatusCode::Good,
+                                    available_sequence_numbers,
+                                }
+                            }
+                            Err(status_code) => TransferResult {
+                                status_code,
+                                available_sequence_numbers: None,
+                            }
+                        }
                     }
                 }).collect::<Vec<TransferResult>>();
                 Some(results)
@@ -8,10 +8,12 @@ use opcua_types::service_types::*;
 
 use crate::{
     address_space::{AddressSpace, relative_path},
+    config::OperationalLimits,
     session::Session,
     state::ServerState,
-    services::Service,
+    services::{Service, ServiceUser},
     continuation_point::BrowseContinuationPoint,
+    roles,
 };
 
 // Bits that control the reference description coming back from browse()
@@ -39,9 +41,13 @@ impl ViewService {
         ViewService {}
     }
 
-    pub fn browse(&self, session: &mut Session, address_space: &AddressSpace, request: &BrowseRequest) -> Result<SupportedMessage, StatusCode> {
+    /// Browses the references of the nodes in the request. Nodes that the server's access control
+    /// denies to the session's user cannot be browsed and are omitted from the references.
+    pub fn browse(&self, server_state: &ServerState, session: &mut Session, address_space: &AddressSpace, request: &BrowseRequest) -> Result<SupportedMessage, StatusCode> {
         if is_empty_option_vec!(request.nodes_to_browse) {
             Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
+        } else if OperationalLimits::is_exceeded(server_state.operational_limits.max_nodes_per_browse, request.nodes_to_browse.as_ref().unwrap().len()) {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadTooManyOperations))
         } else {
             if !request.view.view_id.is_null() {
                 // Views are not supported
@@ -49,7 +55,7 @@ impl ViewService {
                 Ok(self.service_fault(&request.request_header, StatusCode::BadViewIdUnknown))
             } else {
                 let nodes_to_browse = request.nodes_to_browse.as_ref().unwrap();
-                let results = Some(Self::browse_nodes(session, address_space, nodes_to_browse, request.requested_max_references_per_node as usize));
+                let results = Some(Self::browse_nodes(server_state, session, address_space, nodes_to_browse, request.requested_max_references_per_node as usize));
                 let diagnostic_infos = None;
                 let response = BrowseResponse {
                     response_header: ResponseHeader::new_good(&request.request_header),
@@ -61,21 +67,34 @@ impl ViewService {
         }
     }
 
+    /// Continues browses from their continuation points, or releases the continuation points if
+    /// the client no longer wants the rest of the references. Either way the continuation points
+    /// are used up, though a continuation point may be returned for what remains.
     pub fn browse_next(&self, session: &mut Session, address_space: &AddressSpace, request: &BrowseNextRequest) -> Result<SupportedMessage, StatusCode> {
         if is_empty_option_vec!(request.continuation_points) {
             Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
         } else {
             let continuation_points = request.continuation_points.as_ref().unwrap();
-            let results = if request.release_continuation_points {
-                session.remove_browse_continuation_points(continuation_points);
-                None
+            let results: Vec<BrowseResult> = if request.release_continuation_points {
+                continuation_points.iter().map(|continuation_point| {
+                    let status_code = if session.remove_browse_continuation_point(continuation_point) {
+                        StatusCode::Good
+                    } else {
+                        StatusCode::BadContinuationPointInvalid
+                    };
+                    BrowseResult {
+                        status_code,
+                        continuation_point: ByteString::null(),
+                        references: None,
+                    }
+                }).collect()
             } else {
                 // Iterate from the continuation point, assuming it is valid
-                let results = continuation_points.iter().map(|continuation_point| {
+                continuation_points.iter().map(|continuation_point| {
                     Self::browse_from_continuation_point(session, address_space, continuation_point)
-                }).collect();
-                Some(results)
+                }).collect()
             };
+            let results = Some(results);
 
             let diagnostic_infos = None;
             let response = BrowseNextResponse {
@@ -92,8 +111,8 @@ impl ViewService {
             Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
         } else {
             let browse_paths = request.browse_paths.as_ref().unwrap();
-            let max_browse_paths_per_translate = server_state.max_browse_paths_per_translate();
-            if browse_paths.len() > max_browse_paths_per_translate {
+            let max_browse_paths_per_translate = server_state.operational_limits.max_nodes_per_translate_browse_paths_to_node_ids;
+            if OperationalLimits::is_exceeded(max_browse_paths_per_translate, browse_paths.len()) {
                 trace!("Browse paths size {} exceeds max nodes {}", browse_paths.len(), max_browse_paths_per_translate);
                 Ok(self.service_fault(&request.request_header, StatusCode::BadTooManyOperations))
             } else {
@@ -149,58 +168,71 @@ impl ViewService {
         }
     }
 
-    pub fn register_nodes(&self, server_state: &mut ServerState, session: Arc<RwLock<Session>>, request: &RegisterNodesRequest) -> Result<SupportedMessage, StatusCode> {
+    /// Registers the nodes for the session. If a `RegisterNodes` callback has been set it decides
+    /// what registered node ids are returned, otherwise the nodes are registered under their own
+    /// ids. Either way the registered ids are held by the session so other services can quickly
+    /// map them back to the actual nodes.
+    pub fn register_nodes(&self, server_state: &mut ServerState, session: &mut Session, session_ref: Arc<RwLock<Session>>, request: &RegisterNodesRequest) -> Result<SupportedMessage, StatusCode> {
         if is_empty_option_vec!(request.nodes_to_register) {
             Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
+        } else if OperationalLimits::is_exceeded(server_state.operational_limits.max_nodes_per_register_nodes, request.nodes_to_register.as_ref().unwrap().len()) {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadTooManyOperations))
         } else {
-            if let Some(ref mut callback) = server_state.register_nodes_callback {
-                let nodes_to_register = request.nodes_to_register.as_ref().unwrap();
-                match callback.register_nodes(session, &nodes_to_register[..]) {
-                    Ok(registered_node_ids) => {
-                        let response = RegisterNodesResponse {
-                            response_header: ResponseHeader::new_good(&request.request_header),
-                            registered_node_ids: Some(registered_node_ids),
-                        };
-                        Ok(response.into())
-                    }
+            let nodes_to_register = request.nodes_to_register.as_ref().unwrap();
+            if nodes_to_register.iter().any(|node_id| node_id.is_null()) {
+                return Ok(self.service_fault(&request.request_header, StatusCode::BadNodeIdInvalid));
+            }
+            let registered_node_ids = if let Some(ref mut callback) = server_state.register_nodes_callback {
+                match callback.register_nodes(session_ref, &nodes_to_register[..]) {
+                    Ok(registered_node_ids) => registered_node_ids,
                     Err(err) => {
-                        Ok(self.service_fault(&request.request_header, err))
+                        return Ok(self.service_fault(&request.request_header, err));
                     }
                 }
             } else {
-                Ok(self.service_fault(&request.request_header, StatusCode::BadNodeIdInvalid))
+                nodes_to_register.clone()
+            };
+            if registered_node_ids.len() != nodes_to_register.len() {
+                error!("Register nodes returned {} node ids for {} nodes to register", registered_node_ids.len(), nodes_to_register.len());
+                return Ok(self.service_fault(&request.request_header, StatusCode::BadInternalError));
             }
+            for (registered_node_id, node_id) in registered_node_ids.iter().zip(nodes_to_register.iter()) {
+                if let Err(err) = session.register_node(registered_node_id.clone(), node_id.clone()) {
+                    return Ok(self.service_fault(&request.request_header, err));
+                }
+            }
+            let response = RegisterNodesResponse {
+                response_header: ResponseHeader::new_good(&request.request_header),
+                registered_node_ids: Some(registered_node_ids),
+            };
+            Ok(response.into())
         }
     }
 
-    pub fn unregister_nodes(&self, server_state: &mut ServerState, session: Arc<RwLock<Session>>, request: &UnregisterNodesRequest) -> Result<SupportedMessage, StatusCode> {
+    /// Unregisters the nodes from the session and informs the `UnregisterNodes` callback if one
+    /// has been set. Nodes which were never registered are ignored.
+    pub fn unregister_nodes(&self, server_state: &mut ServerState, session: &mut Session, session_ref: Arc<RwLock<Session>>, request: &UnregisterNodesRequest) -> Result<SupportedMessage, StatusCode> {
         if is_empty_option_vec!(request.nodes_to_unregister) {
             Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo))
+        } else if OperationalLimits::is_exceeded(server_state.operational_limits.max_nodes_per_register_nodes, request.nodes_to_unregister.as_ref().unwrap().len()) {
+            Ok(self.service_fault(&request.request_header, StatusCode::BadTooManyOperations))
         } else {
+            let nodes_to_unregister = request.nodes_to_unregister.as_ref().unwrap();
+            nodes_to_unregister.iter().for_each(|node_id| session.unregister_node(node_id));
             if let Some(ref mut callback) = server_state.unregister_nodes_callback {
-                let nodes_to_unregister = request.nodes_to_unregister.as_ref().unwrap();
-                match callback.unregister_nodes(session, &nodes_to_unregister[..]) {
-                    Ok(_) => {
-                        let response = UnregisterNodesResponse {
-                            response_header: ResponseHeader::new_good(&request.request_header),
-                        };
-                        Ok(response.into())
-                    }
-                    Err(err) => {
-                        Ok(self.service_fault(&request.request_header, err))
-                    }
+                if let Err(err) = callback.unregister_nodes(session_ref, &nodes_to_unregister[..]) {
+                    return Ok(self.service_fault(&request.request_header, err));
                 }
-            } else {
-                Ok(UnregisterNodesResponse {
-                    response_header: ResponseHeader::new_good(&request.request_header),
-                }.into())
             }
+            Ok(UnregisterNodesResponse {
+                response_header: ResponseHeader::new_good(&request.request_header),
+            }.into())
         }
     }
 
-    fn browse_nodes(session: &mut Session, address_space: &AddressSpace, nodes_to_browse: &[BrowseDescription], max_references_per_node: usize) -> Vec<BrowseResult> {
+    fn browse_nodes(server_state: &ServerState, session: &mut Session, address_space: &AddressSpace, nodes_to_browse: &[BrowseDescription], max_references_per_node: usize) -> Vec<BrowseResult> {
         nodes_to_browse.iter().map(|node_to_browse| {
-            match Self::browse_node(session, &address_space, 0, node_to_browse, max_references_per_node) {
+            match Self::browse_node(server_state, session, &address_space, 0, node_to_browse, max_references_per_node) {
                 Ok(browse_result) => browse_result,
                 Err(status_code) => BrowseResult {
                     status_code,
@@ -211,10 +243,19 @@ impl ViewService {
         }).collect()
     }
 
-    fn browse_node(session: &mut Session, address_space: &AddressSpace, starting_index: usize, node_to_browse: &BrowseDescription, max_references_per_node: usize) -> Result<BrowseResult, StatusCode> {
+    fn browse_node(server_state: &ServerState, session: &mut Session, address_space: &AddressSpace, starting_index: usize, node_to_browse: &BrowseDescription, max_references_per_node: usize) -> Result<BrowseResult, StatusCode> {
+        let reference_descriptions = Self::browse_references(server_state, &*session, address_space, starting_index, node_to_browse)?;
+        Ok(Self::reference_description_to_browse_result(session, address_space, &reference_descriptions, 0, max_references_per_node))
+    }
+
+    /// Returns every reference of the node to browse that the user may browse, from the starting
+    /// index onwards. Unlike a browse by a session, there is no limit to the number of references.
+    pub(crate) fn browse_references<U: ServiceUser>(server_state: &ServerState, user: &U, address_space: &AddressSpace, starting_index: usize, node_to_browse: &BrowseDescription) -> Result<Vec<ReferenceDescription>, StatusCode> {
         // Node must exist or there will be no references
-        if node_to_browse.node_id.is_null() || !address_space.node_exists(&node_to_browse.node_id) {
-            return Err(StatusCode::BadNodeIdUnknown);
+        let node = if node_to_browse.node_id.is_null() { None } else { address_space.find_node(&node_to_browse.node_id) };
+        let node = node.ok_or(StatusCode::BadNodeIdUnknown)?;
+        if !roles::is_permitted(node, user.roles(), PermissionType::BROWSE) || !server_state.can_browse(user.identity(), &node_to_browse.node_id) {
+            return Err(StatusCode::BadUserAccessDenied);
         }
 
         // Request may wish to filter by a kind of reference
@@ -236,7 +277,7 @@ impl ViewService {
         let node_class_mask = node_to_browse.node_class_mask;
 
         // Construct descriptions for each reference
-        let mut reference_descriptions: Vec<ReferenceDescription> = Vec::with_capacity(max_references_per_node);
+        let mut reference_descriptions: Vec<ReferenceDescription> = Vec::with_capacity(references.len());
         for (idx, reference) in references.iter().enumerate() {
             if idx < starting_index {
                 continue;
@@ -246,7 +287,10 @@ impl ViewService {
                 continue;
             }
             let target_node = address_space.find_node(&target_node_id);
-            if target_node.is_none() {
+            if target_node.is_none() || !server_state.can_browse(user.identity(), &target_node_id) {
+                continue;
+            }
+            if !roles::is_permitted(target_node.unwrap(), user.roles(), PermissionType::BROWSE) {
                 continue;
             }
 
@@ -317,14 +361,13 @@ impl ViewService {
             };
             reference_descriptions.push(reference_description);
         }
-
-        Ok(Self::reference_description_to_browse_result(session, address_space, &reference_descriptions, 0, max_references_per_node))
+        Ok(reference_descriptions)
     }
 
     fn browse_from_continuation_point(session: &mut Session, address_space: &AddressSpace, continuation_point: &ByteString) -> BrowseResult {
         // Find the continuation point in the session
         session.remove_expired_browse_continuation_points(address_space);
-        if let Some(continuation_point) = session.find_browse_continuation_point(continuation_point) {
+        if let Some(continuation_point) = session.take_browse_continuation_point(continuation_point) {
             let reference_descriptions = continuation_point.reference_descriptions.lock().unwrap();
             // Use the existing result. This may result in another continuation point being created
             Self::reference_description_to_browse_result(session, address_space, &reference_descriptions, continuation_point.starting_index, continuation_point.max_references_per_node)
@@ -350,14 +393,23 @@ impl ViewService {
             //  be factored to allow for that
 
             // Create a continuation point for the remainder of the result. The point will hold the entire result
+            session.remove_expired_browse_continuation_points(address_space);
             let continuation_point = ByteString::random(6);
-            session.add_browse_continuation_point(BrowseContinuationPoint {
+            let result = session.add_browse_continuation_point(BrowseContinuationPoint {
                 id: continuation_point.clone(),
                 address_space_last_modified: address_space.last_modified(),
                 max_references_per_node,
                 starting_index: ending_index,
                 reference_descriptions: Arc::new(Mutex::new(reference_descriptions.to_vec())),
             });
+            if let Err(status_code) = result {
+                // The session has no continuation points left, so the references cannot be returned
+                return BrowseResult {
+                    status_code,
+                    continuation_point: ByteString::null(),
+                    references: None,
+                };
+            }
             (reference_descriptions_slice, continuation_point)
         } else {
             let reference_descriptions_slice = reference_descriptions[starting_index..].to_vec();
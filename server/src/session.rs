@@ -1,21 +1,26 @@
 use std::{
-    collections::{VecDeque, HashSet},
+    collections::{VecDeque, HashSet, HashMap},
+    net::SocketAddr,
     sync::{Arc, RwLock, Mutex},
 };
 use chrono;
+use futures::sync::oneshot;
 
 use opcua_core::{
-    comms::secure_channel::{Role, SecureChannel}, crypto::X509,
+    comms::secure_channel::{Role, SecureChannel}, crypto::{CertificateStore, X509},
 };
 use opcua_types::{
-    *, service_types::PublishRequest, status_code::StatusCode,
+    *, service_types::{ApplicationDescription, PublishRequest}, status_code::StatusCode,
 };
 
 use crate::{
     address_space::AddressSpace,
-    continuation_point::BrowseContinuationPoint,
+    callbacks::UserIdentity,
+    continuation_point::{BrowseContinuationPoint, HistoryContinuationPoint, QueryContinuationPoint},
     diagnostics::ServerDiagnostics,
     server::Server,
+    services::ServiceUser,
+    state::ServerState,
     subscriptions::subscription::TickReason,
     subscriptions::subscriptions::Subscriptions,
 };
@@ -49,6 +54,8 @@ pub struct Session {
     pub security_policy_uri: String,
     /// Client's certificate
     pub client_certificate: Option<X509>,
+    /// The certs of the issuers that the client supplied with its certificate
+    pub client_certificate_issuers: Vec<X509>,
     /// Authentication token for the session
     pub authentication_token: NodeId,
     /// Secure channel state
@@ -59,27 +66,73 @@ pub struct Session {
     pub session_timeout: f64,
     /// User identity token
     pub user_identity: Option<ExtensionObject>,
+    /// The user authenticated by the user identity token
+    pub identity: UserIdentity,
+    /// The roles granted to the user
+    pub roles: Vec<NodeId>,
     /// Negotiated max request message size
     pub max_request_message_size: u32,
     /// Negotiated max response message size
     pub max_response_message_size: u32,
     /// Endpoint url for this session
     pub endpoint_url: UAString,
+    /// Session name supplied by the client
+    pub session_name: UAString,
+    /// Description of the client application supplied in CreateSession
+    pub client_description: ApplicationDescription,
+    /// Address of the client whose connection the session belongs to, if it is known
+    pub client_address: Option<SocketAddr>,
     /// Maximum number of continuation points
     max_browse_continuation_points: usize,
     /// Browse continuation points (oldest to newest)
     browse_continuation_points: VecDeque<BrowseContinuationPoint>,
+    /// Maximum number of history continuation points
+    max_history_continuation_points: usize,
+    /// History continuation points (oldest to newest)
+    history_continuation_points: VecDeque<HistoryContinuationPoint>,
+    /// Maximum number of query continuation points
+    max_query_continuation_points: usize,
+    /// Query continuation points (oldest to newest)
+    query_continuation_points: VecDeque<QueryContinuationPoint>,
+    /// Maximum number of registered nodes
+    max_registered_nodes: usize,
+    /// Nodes registered through RegisterNodes, mapping the registered node id to the actual node id
+    registered_nodes: HashMap<NodeId, NodeId>,
+    /// Maximum number of monitored items in a single call to the monitored item services, 0 means no limit
+    max_monitored_items_per_call: usize,
     /// Diagnostics associated with the session
     diagnostics: Arc<RwLock<ServerDiagnostics>>,
     /// Indicates if the session has received an ActivateSession
     pub activated: bool,
     /// Time that session was terminated, helps with recovering sessions, or clearing them out
     terminated_at: DateTimeUtc,
+    /// Time that the last request for this session was received
+    last_activity: DateTimeUtc,
     /// Flag indicating session is actually terminated
     terminated: bool,
     /// Flag indicating broadly if this session may modify the address space by adding or removing
     /// nodes or references to nodes.
     can_modify_address_space: bool,
+    /// Responses to requests, e.g. reads of variables with async getters, which completed after
+    /// the request was handled and are waiting to be sent
+    async_responses: VecDeque<(u32, SupportedMessage)>,
+    /// Requests whose responses are still to be completed asynchronously, by request id, with the
+    /// request handle that a Cancel names them by and the sender which cancels them
+    async_requests: HashMap<u32, (u32, oneshot::Sender<()>)>,
+}
+
+impl ServiceUser for Session {
+    fn identity(&self) -> &UserIdentity {
+        &self.identity
+    }
+
+    fn roles(&self) -> &[NodeId] {
+        &self.roles
+    }
+
+    fn resolve_node_id<'a>(&'a self, node_id: &'a NodeId) -> &'a NodeId {
+        Session::resolve_node_id(self, node_id)
+    }
 }
 
 impl Drop for Session {
@@ -94,26 +147,45 @@ impl Session {
     #[cfg(test)]
     pub fn new_no_certificate_store(secure_channel: SecureChannel) -> Session {
         let max_browse_continuation_points = super::constants::MAX_BROWSE_CONTINUATION_POINTS;
+        let max_monitored_items_per_call = super::constants::MAX_MONITORED_ITEMS_PER_CALL;
+        let max_history_continuation_points = super::constants::MAX_HISTORY_CONTINUATION_POINTS;
+        let max_query_continuation_points = super::constants::MAX_QUERY_CONTINUATION_POINTS;
         let session = Session {
-            subscriptions: Subscriptions::new(100, PUBLISH_REQUEST_TIMEOUT),
+            subscriptions: Subscriptions::new(100, 0, super::constants::MIN_SAMPLING_INTERVAL, 0, PUBLISH_REQUEST_TIMEOUT),
             session_id: next_session_id(),
             activated: false,
             terminate_session: false,
             terminated: false,
             terminated_at: chrono::Utc::now(),
+            last_activity: chrono::Utc::now(),
             client_certificate: None,
+            client_certificate_issuers: Vec::new(),
             security_policy_uri: String::new(),
             authentication_token: NodeId::null(),
             secure_channel: Arc::new(RwLock::new(secure_channel)),
             session_nonce: ByteString::null(),
             session_timeout: 0f64,
             user_identity: None,
+            identity: UserIdentity::NotActivated,
+            roles: Vec::new(),
             max_request_message_size: 0,
             max_response_message_size: 0,
             endpoint_url: UAString::null(),
+            session_name: UAString::null(),
+            client_description: ApplicationDescription::null(),
+            client_address: None,
             max_browse_continuation_points,
             browse_continuation_points: VecDeque::with_capacity(max_browse_continuation_points),
+            max_history_continuation_points,
+            history_continuation_points: VecDeque::with_capacity(max_history_continuation_points),
+            max_query_continuation_points,
+            query_continuation_points: VecDeque::with_capacity(max_query_continuation_points),
+            max_registered_nodes: super::constants::MAX_REGISTERED_NODES,
+            registered_nodes: HashMap::new(),
+            max_monitored_items_per_call,
             can_modify_address_space: true,
+            async_responses: VecDeque::new(),
+            async_requests: HashMap::new(),
             diagnostics: Arc::new(RwLock::new(ServerDiagnostics::default())),
         };
         {
@@ -125,11 +197,22 @@ impl Session {
 
     /// Create a `Session` from a `Server`
     pub fn new(server: &Server) -> Session {
-        let max_browse_continuation_points = super::constants::MAX_BROWSE_CONTINUATION_POINTS;
+        Self::new_with_state(&server.server_state(), server.certificate_store())
+    }
+
+    /// Create a `Session` from the state and certificate store of a server, e.g. for a transport
+    /// which makes sessions after the server has started running
+    pub(crate) fn new_with_state(server_state: &Arc<RwLock<ServerState>>, certificate_store: Arc<RwLock<CertificateStore>>) -> Session {
+        let max_history_continuation_points = super::constants::MAX_HISTORY_CONTINUATION_POINTS;
+        let max_query_continuation_points = super::constants::MAX_QUERY_CONTINUATION_POINTS;
 
-        let server_state = server.server_state();
         let server_state = trace_read_lock_unwrap!(server_state);
         let max_subscriptions = server_state.max_subscriptions;
+        let max_monitored_items_per_subscription = server_state.max_monitored_items_per_subscription;
+        let min_sampling_interval = server_state.min_sampling_interval;
+        let max_publish_requests = server_state.max_publish_requests;
+        let max_browse_continuation_points = server_state.max_browse_continuation_points;
+        let max_monitored_items_per_call = server_state.operational_limits.max_monitored_items_per_call;
         let diagnostics = server_state.diagnostics.clone();
         let (decoding_limits, can_modify_address_space) = {
             let config = trace_read_lock_unwrap!(server_state.config);
@@ -137,25 +220,41 @@ impl Session {
         };
 
         let session = Session {
-            subscriptions: Subscriptions::new(max_subscriptions, PUBLISH_REQUEST_TIMEOUT),
+            subscriptions: Subscriptions::new(max_subscriptions, max_monitored_items_per_subscription, min_sampling_interval, max_publish_requests, PUBLISH_REQUEST_TIMEOUT),
             session_id: next_session_id(),
             activated: false,
             terminate_session: false,
             terminated: false,
             terminated_at: chrono::Utc::now(),
+            last_activity: chrono::Utc::now(),
             client_certificate: None,
+            client_certificate_issuers: Vec::new(),
             security_policy_uri: String::new(),
             authentication_token: NodeId::null(),
-            secure_channel: Arc::new(RwLock::new(SecureChannel::new(server.certificate_store(), Role::Server, decoding_limits))),
+            secure_channel: Arc::new(RwLock::new(SecureChannel::new(certificate_store, Role::Server, decoding_limits))),
             session_nonce: ByteString::null(),
             session_timeout: 0f64,
             user_identity: None,
+            identity: UserIdentity::NotActivated,
+            roles: Vec::new(),
             max_request_message_size: 0,
             max_response_message_size: 0,
             endpoint_url: UAString::null(),
+            session_name: UAString::null(),
+            client_description: ApplicationDescription::null(),
+            client_address: None,
             max_browse_continuation_points,
             browse_continuation_points: VecDeque::with_capacity(max_browse_continuation_points),
+            max_history_continuation_points,
+            history_continuation_points: VecDeque::with_capacity(max_history_continuation_points),
+            max_query_continuation_points,
+            query_continuation_points: VecDeque::with_capacity(max_query_continuation_points),
+            max_registered_nodes: super::constants::MAX_REGISTERED_NODES,
+            registered_nodes: HashMap::new(),
+            max_monitored_items_per_call,
             can_modify_address_space,
+            async_responses: VecDeque::new(),
+            async_requests: HashMap::new(),
             diagnostics,
         };
         {
@@ -175,6 +274,22 @@ impl Session {
         self.terminated_at = chrono::Utc::now();
     }
 
+    /// Records that a request has been received for the session, resetting its timeout
+    pub(crate) fn on_activity(&mut self) {
+        self.last_activity = chrono::Utc::now();
+    }
+
+    /// Tests if the session has been created and has received no requests for longer than its
+    /// session timeout.
+    pub(crate) fn is_timed_out(&self, now: &DateTimeUtc) -> bool {
+        if self.terminated || self.session_timeout <= 0f64 {
+            false
+        } else {
+            let elapsed = now.signed_duration_since(self.last_activity.clone());
+            elapsed.num_milliseconds() as f64 > self.session_timeout
+        }
+    }
+
     pub(crate) fn enqueue_publish_request(&mut self, now: &DateTimeUtc, request_id: u32, request: PublishRequest, address_space: &AddressSpace) -> Result<(), StatusCode> {
         self.subscriptions.enqueue_publish_request(now, request_id, request, address_space)
     }
@@ -183,6 +298,15 @@ impl Session {
         self.subscriptions.tick(now, address_space, reason)
     }
 
+    /// Sends a final `BadShutdown` status change for each subscription and removes them because
+    /// the server is shutting down.
+    pub(crate) fn shutdown_subscriptions(&mut self, now: &DateTimeUtc) {
+        if !self.subscriptions.is_empty() {
+            info!("Session {} is closing its subscriptions because the server is shutting down", self.session_id);
+            self.subscriptions.close_all(now, StatusCode::BadShutdown);
+        }
+    }
+
     /// Reset the lifetime counter on the subscription, e.g. because a service references the
     /// subscription.
     pub(crate) fn reset_subscription_lifetime_counter(&mut self, subscription_id: u32) {
@@ -197,22 +321,69 @@ impl Session {
         self.subscriptions.expire_stale_publish_requests(now);
     }
 
-    pub(crate) fn add_browse_continuation_point(&mut self, continuation_point: BrowseContinuationPoint) {
-        // Remove excess browse continuation points
-        while self.browse_continuation_points.len() >= self.max_browse_continuation_points {
-            let _ = self.browse_continuation_points.pop_front();
+    /// Registers a request whose response is completed asynchronously so that a Cancel can name it
+    /// by its request handle. The receiver completes if the request is cancelled.
+    pub(crate) fn add_async_request(&mut self, request_id: u32, request_handle: u32) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        self.async_requests.insert(request_id, (request_handle, tx));
+        rx
+    }
+
+    /// Queues the response to a request which completed after the request was handled
+    pub(crate) fn enqueue_async_response(&mut self, request_id: u32, response: SupportedMessage) {
+        self.async_requests.remove(&request_id);
+        self.async_responses.push_back((request_id, response));
+    }
+
+    /// Cancels the outstanding requests with the request handle, i.e. requests whose responses are
+    /// completed asynchronously and queued publish requests, which are answered with
+    /// `BadRequestCancelledByClient`. Returns the number of requests cancelled.
+    pub(crate) fn cancel_requests(&mut self, request_handle: u32) -> u32 {
+        let request_ids = self.async_requests.iter()
+            .filter(|(_, (handle, _))| *handle == request_handle)
+            .map(|(request_id, _)| *request_id)
+            .collect::<Vec<u32>>();
+        let cancelled_async_requests = request_ids.iter()
+            .filter_map(|request_id| self.async_requests.remove(request_id))
+            .filter_map(|(_, canceller)| canceller.send(()).ok())
+            .count() as u32;
+        cancelled_async_requests + self.subscriptions.cancel_publish_requests(request_handle)
+    }
+
+    /// Takes the queued responses to requests which have completed, oldest first
+    pub(crate) fn take_async_responses(&mut self) -> Option<VecDeque<(u32, SupportedMessage)>> {
+        if self.async_responses.is_empty() {
+            None
+        } else {
+            Some(self.async_responses.drain(..).collect())
+        }
+    }
+
+    /// The maximum number of monitored items in a single call to the monitored item services,
+    /// where 0 means no limit
+    pub(crate) fn max_monitored_items_per_call(&self) -> usize {
+        self.max_monitored_items_per_call
+    }
+
+    /// Adds a browse continuation point to the session. Fails with `BadNoContinuationPoints` if the
+    /// session already holds as many continuation points as it is allowed to.
+    pub(crate) fn add_browse_continuation_point(&mut self, continuation_point: BrowseContinuationPoint) -> Result<(), StatusCode> {
+        if self.browse_continuation_points.len() >= self.max_browse_continuation_points {
+            error!("Session has too many browse continuation points {} for the limit {}", self.browse_continuation_points.len(), self.max_browse_continuation_points);
+            Err(StatusCode::BadNoContinuationPoints)
+        } else {
+            self.browse_continuation_points.push_back(continuation_point);
+            Ok(())
         }
-        self.browse_continuation_points.push_back(continuation_point);
     }
 
-    /// Find a continuation point by id. If the continuation point is out of date is removed and None
-    /// is returned.
-    pub(crate) fn find_browse_continuation_point(&self, id: &ByteString) -> Option<BrowseContinuationPoint> {
-        let continuation_point = self.browse_continuation_points.iter().find(|continuation_point| {
+    /// Removes a continuation point by id and returns it. A continuation point can only be used once,
+    /// so once it has been taken it is released.
+    pub(crate) fn take_browse_continuation_point(&mut self, id: &ByteString) -> Option<BrowseContinuationPoint> {
+        let idx = self.browse_continuation_points.iter().position(|continuation_point| {
             continuation_point.id.eq(id)
         });
-
-        continuation_point.map(|continuation_point| continuation_point.clone())
+        idx.and_then(|idx| self.browse_continuation_points.remove(idx))
     }
 
     pub(crate) fn remove_expired_browse_continuation_points(&mut self, address_space: &AddressSpace) {
@@ -221,16 +392,86 @@ impl Session {
         });
     }
 
-    /// Remove all the specified continuation points by id
-    pub(crate) fn remove_browse_continuation_points(&mut self, continuation_points: &[ByteString]) {
-        // Turn the supplied slice into a set
+    /// Removes a continuation point by id, returning `false` if the session does not hold it
+    pub(crate) fn remove_browse_continuation_point(&mut self, id: &ByteString) -> bool {
+        self.take_browse_continuation_point(id).is_some()
+    }
+
+    /// Returns the number of browse continuation points held by the session
+    pub fn browse_continuation_points_len(&self) -> usize {
+        self.browse_continuation_points.len()
+    }
+
+    /// Registers a node for the session. The registered node id is what the client will use to
+    /// refer to the actual node in subsequent requests. Fails if the session has registered as
+    /// many nodes as it is allowed to.
+    pub(crate) fn register_node(&mut self, registered_node_id: NodeId, node_id: NodeId) -> Result<(), StatusCode> {
+        if !self.registered_nodes.contains_key(&registered_node_id) && self.registered_nodes.len() >= self.max_registered_nodes {
+            Err(StatusCode::BadTooManyOperations)
+        } else {
+            self.registered_nodes.insert(registered_node_id, node_id);
+            Ok(())
+        }
+    }
+
+    /// Unregisters a node previously registered for the session. Nodes which are not registered
+    /// are ignored.
+    pub(crate) fn unregister_node(&mut self, registered_node_id: &NodeId) {
+        let _ = self.registered_nodes.remove(registered_node_id);
+    }
+
+    /// Returns the actual node id for the supplied node id. If the node id was registered with
+    /// RegisterNodes the node it was registered for is returned, otherwise the node id itself.
+    pub(crate) fn resolve_node_id<'a>(&'a self, node_id: &'a NodeId) -> &'a NodeId {
+        self.registered_nodes.get(node_id).unwrap_or(node_id)
+    }
+
+    pub(crate) fn add_history_continuation_point(&mut self, continuation_point: HistoryContinuationPoint) {
+        // Remove excess history continuation points
+        while self.history_continuation_points.len() >= self.max_history_continuation_points {
+            let _ = self.history_continuation_points.pop_front();
+        }
+        self.history_continuation_points.push_back(continuation_point);
+    }
+
+    /// Removes and returns the history continuation point with the id. A continuation point can
+    /// only be used once, so if there are more values to come a new one must be added.
+    pub(crate) fn take_history_continuation_point(&mut self, id: &ByteString) -> Option<HistoryContinuationPoint> {
+        let idx = self.history_continuation_points.iter().position(|continuation_point| {
+            continuation_point.id.eq(id)
+        });
+        idx.and_then(|idx| self.history_continuation_points.remove(idx))
+    }
+
+    /// Remove all the specified history continuation points by id
+    pub(crate) fn remove_history_continuation_points(&mut self, continuation_points: &[ByteString]) {
         let continuation_points_set: HashSet<ByteString> = continuation_points.iter().cloned().collect();
-        // Now remove any continuation points that are part of that set
-        self.browse_continuation_points.retain(|continuation_point| {
+        self.history_continuation_points.retain(|continuation_point| {
             !continuation_points_set.contains(&continuation_point.id)
         });
     }
 
+    /// Adds a query continuation point to the session. Fails with `BadNoContinuationPoints` if the
+    /// session already holds as many continuation points as it is allowed to.
+    pub(crate) fn add_query_continuation_point(&mut self, continuation_point: QueryContinuationPoint) -> Result<(), StatusCode> {
+        if self.query_continuation_points.len() >= self.max_query_continuation_points {
+            error!("Session has too many query continuation points {} for the limit {}", self.query_continuation_points.len(), self.max_query_continuation_points);
+            Err(StatusCode::BadNoContinuationPoints)
+        } else {
+            self.query_continuation_points.push_back(continuation_point);
+            Ok(())
+        }
+    }
+
+    /// Removes and returns the query continuation point with the id. A continuation point can
+    /// only be used once, so if there are more data sets to come a new one must be added.
+    pub(crate) fn take_query_continuation_point(&mut self, id: &ByteString) -> Option<QueryContinuationPoint> {
+        let idx = self.query_continuation_points.iter().position(|continuation_point| {
+            continuation_point.id.eq(id)
+        });
+        idx.and_then(|idx| self.query_continuation_points.remove(idx))
+    }
+
     pub(crate) fn can_modify_address_space(&self) -> bool {
         self.can_modify_address_space
     }
@@ -0,0 +1,137 @@
+//! Provides simulated values for the `simulated_variables` of the server config. Each variable
+//! follows a waveform - a sine, ramp, random walk or square wave - and is updated by its own
+//! polling action, so a demo or load test server has changing values to monitor without any code.
+
+use std::f64::consts::PI;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use opcua_types::*;
+
+use crate::{
+    address_space::{AddressSpace, SharedAddressSpace, variable::VariableBuilder},
+    config::{SimulatedVariableConfig, SimulationWaveform},
+    state::ServerState,
+    util::PollingAction,
+};
+
+/// Name of the folder beneath Objects that simulated variables are created in
+const SIMULATION_FOLDER: &str = "Simulation";
+
+/// The waveform of a simulated variable and its position along it
+pub(crate) struct Simulation {
+    waveform: SimulationWaveform,
+    period: f64,
+    amplitude: f64,
+    offset: f64,
+    update_interval: f64,
+    /// Position of a random walk, between -amplitude and +amplitude
+    position: f64,
+    /// State of the xorshift generator which steps a random walk, never 0
+    seed: u64,
+}
+
+impl Simulation {
+    pub fn new(config: &SimulatedVariableConfig, seed: u64) -> Simulation {
+        Simulation {
+            waveform: config.waveform,
+            period: config.period as f64,
+            amplitude: config.amplitude,
+            offset: config.offset,
+            update_interval: config.update_interval as f64,
+            position: 0f64,
+            seed: if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed },
+        }
+    }
+
+    /// Returns the value at a time in milliseconds since the simulation started. A random walk
+    /// takes one step per call whatever the time.
+    pub fn value(&mut self, elapsed: f64) -> f64 {
+        let phase = (elapsed % self.period) / self.period;
+        let value = match self.waveform {
+            SimulationWaveform::Sine => self.amplitude * (2f64 * PI * phase).sin(),
+            SimulationWaveform::Ramp => self.amplitude * phase,
+            SimulationWaveform::Square => if phase < 0.5f64 { self.amplitude } else { -self.amplitude },
+            SimulationWaveform::RandomWalk => {
+                // Steps are sized so the walk can move at most the amplitude in a period
+                let limit = self.amplitude.abs();
+                let max_step = limit * (self.update_interval / self.period).min(1f64);
+                let step = max_step * (2f64 * self.next_random() - 1f64);
+                self.position = (self.position + step).max(-limit).min(limit);
+                self.position
+            }
+        };
+        self.offset + value
+    }
+
+    /// Returns a pseudo random number in the range [0, 1)
+    fn next_random(&mut self) -> f64 {
+        let mut x = self.seed;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.seed = x;
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Creates the simulated variables which do not already exist in the address space. They are
+/// Double variables organized by a Simulation folder beneath Objects, which is in the namespace.
+pub(crate) fn add_simulated_variables(address_space: &mut AddressSpace, namespace: u16, simulated_variables: &[SimulatedVariableConfig]) {
+    let folder_id = NodeId::new(namespace, SIMULATION_FOLDER);
+    for simulated_variable in simulated_variables {
+        // Node ids were validated with the config
+        let node_id = NodeId::from_str(&simulated_variable.node_id).unwrap();
+        if address_space.node_exists(&node_id) {
+            continue;
+        }
+        if !address_space.node_exists(&folder_id) &&
+            address_space.add_folder_with_id(&folder_id, SIMULATION_FOLDER, SIMULATION_FOLDER, &AddressSpace::objects_folder_id()).is_err() {
+            error!("Cannot create the {} folder for simulated variables", SIMULATION_FOLDER);
+            return;
+        }
+        let name = simulated_variable.name.as_str();
+        let _ = VariableBuilder::new(&node_id)
+            .browse_name(name)
+            .display_name(name)
+            .value(simulated_variable.offset)
+            .organized_by(&folder_id)
+            .insert(address_space);
+    }
+}
+
+/// Starts a polling action for each simulated variable which sets its value every update interval
+pub(crate) fn start_simulation(server_state: Arc<RwLock<ServerState>>, address_space: Arc<SharedAddressSpace>, simulated_variables: Vec<SimulatedVariableConfig>) {
+    let start = Instant::now();
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() ^ u64::from(d.subsec_nanos()))
+        .unwrap_or(0);
+    for (i, simulated_variable) in simulated_variables.into_iter().enumerate() {
+        let node_id = NodeId::from_str(&simulated_variable.node_id).unwrap();
+        {
+            let address_space = trace_read_lock_unwrap!(address_space);
+            if address_space.find_variable_by_ref(&node_id).is_none() {
+                error!("Simulated variable {} cannot be simulated, node {:?} is not a variable", simulated_variable.name, node_id);
+                continue;
+            }
+        }
+        debug!("Simulating variable {} every {}ms", simulated_variable.name, simulated_variable.update_interval);
+
+        // Each variable has its own random sequence
+        let seed = seed ^ (i as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let simulation = Mutex::new(Simulation::new(&simulated_variable, seed));
+        let address_space = address_space.clone();
+        let _ = PollingAction::spawn(server_state.clone(), simulated_variable.update_interval, move || {
+            let elapsed = start.elapsed();
+            let elapsed = elapsed.as_secs() as f64 * 1000f64 + f64::from(elapsed.subsec_nanos()) / 1_000_000f64;
+            let value = {
+                let mut simulation = trace_lock_unwrap!(simulation);
+                simulation.value(elapsed)
+            };
+            let now = DateTime::now();
+            let address_space = trace_read_lock_unwrap!(address_space);
+            let _ = address_space.set_variable_value_by_ref(&node_id, value, &now, &now);
+        });
+    }
+}
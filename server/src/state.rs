@@ -1,28 +1,36 @@
 //! Provides server state information, such as status, configuration, running servers and so on.
 
+use std::net::SocketAddr;
 use std::sync::{Arc, RwLock};
 use std::str::FromStr;
 
 use opcua_core::prelude::*;
 use opcua_core::crypto::user_identity;
+use opcua_core::comms::buffer_pool::BufferPool;
 
 use opcua_types::{
     node_ids::ObjectId,
     profiles,
     service_types::{
         ActivateSessionRequest, ApplicationDescription, RegisteredServer, ApplicationType, EndpointDescription,
-        UserNameIdentityToken, UserTokenPolicy, UserTokenType, X509IdentityToken, SignatureData,
+        UserNameIdentityToken, UserTokenPolicy, UserTokenType, X509IdentityToken, IssuedIdentityToken, SignatureData,
         ServerState as ServerStateType,
     },
     status_code::StatusCode,
 };
 
-use crate::config::{ServerConfig, ServerEndpoint};
+use crate::config::{OperationalLimits, ServerConfig, ServerEndpoint, ANONYMOUS_USER_TOKEN_ID, ISSUED_TOKEN_USER_TOKEN_ID};
 use crate::diagnostics::ServerDiagnostics;
-use crate::callbacks::{RegisterNodes, UnregisterNodes};
+use crate::subscriptions::detached::DetachedSubscriptions;
+use crate::callbacks::{RegisterNodes, UnregisterNodes, HistoricalDataProvider, IssuedTokenValidator, AuditLog, AccessControl, QueryEvaluator, ServerCallbacks, UserIdentity};
+use crate::events::event::Event;
+use crate::roles::RoleSet;
+use crate::session::Session;
 
 const TOKEN_POLICY_ANONYMOUS: &str = "anonymous";
 const TOKEN_POLICY_USER_PASS_PLAINTEXT: &str = "userpass_plaintext";
+const TOKEN_POLICY_X509: &str = "x509";
+const TOKEN_POLICY_ISSUED_TOKEN: &str = "issued_token";
 
 /// Server state is any state associated with the server as a whole that individual sessions might
 /// be interested in. That includes configuration info etc.
@@ -37,8 +45,6 @@ pub struct ServerState {
     pub base_endpoint: String,
     /// The time the server started
     pub start_time: DateTime,
-    /// The list of namespaces
-    pub namespaces: Vec<String>,
     /// The list of servers (by urn)
     pub servers: Vec<String>,
     /// Server configuration
@@ -50,8 +56,22 @@ pub struct ServerState {
     /// The next subscription id - subscriptions are shared across the whole server. Initial value
     /// is a random u32.
     pub last_subscription_id: u32,
+    /// Maximum number of sessions open on the server, 0 means no limit (danger)
+    pub max_sessions: usize,
+    /// Smallest session timeout in millis that a client's requested timeout is revised up to
+    pub min_session_timeout: f64,
+    /// Largest session timeout in millis that a client's requested timeout is revised down to
+    pub max_session_timeout: f64,
     /// Maximum number of subscriptions per session, 0 means no limit (danger)
     pub max_subscriptions: usize,
+    /// Maximum number of monitored items per subscription, 0 means no limit (danger)
+    pub max_monitored_items_per_subscription: usize,
+    /// Minimum sampling interval in millis of monitored items
+    pub min_sampling_interval: f64,
+    /// Maximum number of publish requests queued per session, 0 means two per subscription
+    pub max_publish_requests: usize,
+    /// Maximum number of browse continuation points per session
+    pub max_browse_continuation_points: usize,
     /// Minimum publishing interval
     pub min_publishing_interval: Duration,
     /// Default keep alive count
@@ -60,34 +80,57 @@ pub struct ServerState {
     pub max_keep_alive_count: u32,
     /// Maximum lifetime count (3 times as large as max keep alive)
     pub max_lifetime_count: u32,
-    /// Limits on method service
-    pub max_method_calls: usize,
-    /// Limits on node management service
-    pub max_nodes_per_node_management: usize,
-    /// Limits on view service
-    pub max_browse_paths_per_translate: usize,
+    /// Limits on the number of operations in a single request to a service
+    pub operational_limits: OperationalLimits,
     //// Current state
     pub state: ServerStateType,
     /// Sets the abort flag that terminates the associated server
     pub abort: bool,
+    /// The time that a shutdown begun with `Server::shutdown()` is due
+    pub(crate) shutdown_time: Option<DateTimeUtc>,
+    /// The reason given for the shutdown
+    pub(crate) shutdown_reason: LocalizedText,
     /// Diagnostic information
     pub diagnostics: Arc<RwLock<ServerDiagnostics>>,
+    /// The roles of the server and the identity mapping rules that grant them to users
+    pub role_set: Arc<RwLock<RoleSet>>,
     /// Callback for register nodes
     pub(crate) register_nodes_callback: Option<Box<RegisterNodes + Send + Sync>>,
     /// Callback for unregister nodes
     pub(crate) unregister_nodes_callback: Option<Box<UnregisterNodes + Send + Sync>>,
-
+    /// Provider of historical data for the HistoryRead service
+    pub(crate) historical_data_provider: Option<Box<HistoricalDataProvider + Send + Sync>>,
+    /// Validator of issued identity tokens for ActivateSession
+    pub(crate) issued_token_validator: Option<Box<IssuedTokenValidator + Send + Sync>>,
+    /// Subscriptions that outlived their sessions and may be transferred to another session
+    pub(crate) detached_subscriptions: DetachedSubscriptions,
+    /// Audit log that audit events are passed to. It has a lock of its own so that requests
+    /// handled under a read lock of the server state are audited too.
+    pub(crate) audit_log: Option<RwLock<Box<AuditLog + Send + Sync>>>,
+    /// Access control that decides what the user of a session may do to nodes
+    pub(crate) access_control: Option<Box<AccessControl + Send + Sync>>,
+    /// Evaluates the queries of the QueryFirst service, or the address space query evaluator if none is set
+    pub(crate) query_evaluator: Option<Box<QueryEvaluator + Send + Sync>>,
+    /// Pool of send buffers shared by the connections of the server
+    pub(crate) buffer_pool: BufferPool,
+    /// Callbacks for the lifecycle events of clients
+    pub(crate) server_callbacks: Option<Box<ServerCallbacks + Send + Sync>>,
 }
 
 impl ServerState {
-    pub fn endpoints(&self, transport_profile_uris: &Option<Vec<UAString>>) -> Option<Vec<EndpointDescription>> {
+    /// Returns the endpoints of the server for GetEndpoints. Endpoints are filtered by the url that
+    /// the client connected through so a client sees the endpoints sharing its path, or all of
+    /// the endpoints if none share it or the url is null. An empty list of transport profile uris
+    /// matches any profile.
+    pub fn endpoints(&self, endpoint_url: &UAString, transport_profile_uris: &Option<Vec<UAString>>) -> Option<Vec<EndpointDescription>> {
         // Filter endpoints based on profile_uris
         debug!("Endpoints requested {:?}", transport_profile_uris);
         if let Some(ref transport_profile_uris) = *transport_profile_uris {
             if !transport_profile_uris.is_empty() {
-                // As we only support binary transport, the result is None if the supplied profile_uris does not contain that profile
+                // As we only support binary transports, the result is None if the supplied profile_uris does not contain one of them
                 let found_binary_transport = transport_profile_uris.iter().find(|profile_uri| {
-                    profile_uri.as_ref() == profiles::TRANSPORT_PROFILE_URI_BINARY
+                    profile_uri.as_ref() == profiles::TRANSPORT_PROFILE_URI_BINARY ||
+                        profile_uri.as_ref() == profiles::TRANSPORT_PROFILE_URI_HTTPS_BINARY
                 });
                 if found_binary_transport.is_none() {
                     error!("Client wants to connect with a non binary transport {:#?}", transport_profile_uris);
@@ -97,9 +140,20 @@ impl ServerState {
         }
         // Return the endpoints
         let config = trace_read_lock_unwrap!(self.config);
-        Some(config.endpoints.iter().map(|(_, e)| {
-            self.new_endpoint_description(&config, e, true)
-        }).collect())
+        let matching_endpoints = if endpoint_url.is_empty() {
+            Vec::new()
+        } else {
+            config.endpoints.iter()
+                .filter(|&(_, e)| url_matches_except_host(&e.endpoint_url(&config.endpoint_base_url(e)), endpoint_url.as_ref()))
+                .map(|(_, e)| e)
+                .collect::<Vec<_>>()
+        };
+        let endpoints = if matching_endpoints.is_empty() {
+            config.endpoints.values().collect::<Vec<_>>()
+        } else {
+            matching_endpoints
+        };
+        Some(endpoints.into_iter().map(|e| self.new_endpoint_description(&config, e, true)).collect())
     }
 
     pub fn endpoint_exists(&self, endpoint_url: &str, security_policy: SecurityPolicy, security_mode: MessageSecurityMode) -> bool {
@@ -113,19 +167,18 @@ impl ServerState {
     pub fn new_endpoint_descriptions(&self, endpoint_url: &str) -> Option<Vec<EndpointDescription>> {
         debug!("find_endpoint, url = {}", endpoint_url);
         let config = trace_read_lock_unwrap!(self.config);
-        let base_endpoint_url = config.base_endpoint_url();
         let endpoints: Vec<EndpointDescription> = config.endpoints.iter().filter(|&(_, e)| {
             // Test end point's security_policy_uri and matching url
-            url_matches_except_host(&e.endpoint_url(&base_endpoint_url), endpoint_url)
+            url_matches_except_host(&e.endpoint_url(&config.endpoint_base_url(e)), endpoint_url)
         }).map(|(_, e)| self.new_endpoint_description(&config, e, false)).collect();
         if endpoints.is_empty() { None } else { Some(endpoints) }
     }
 
     /// Constructs a new endpoint description using the server's info and that in an Endpoint
     fn new_endpoint_description(&self, config: &ServerConfig, endpoint: &ServerEndpoint, all_fields: bool) -> EndpointDescription {
-        let base_endpoint_url = config.base_endpoint_url();
+        let base_endpoint_url = config.endpoint_base_url(endpoint);
 
-        let mut user_identity_tokens = Vec::with_capacity(2);
+        let mut user_identity_tokens = Vec::with_capacity(4);
         if endpoint.supports_anonymous() {
             user_identity_tokens.push(UserTokenPolicy {
                 policy_id: UAString::from(TOKEN_POLICY_ANONYMOUS),
@@ -135,7 +188,10 @@ impl ServerState {
                 security_policy_uri: UAString::null(),
             });
         }
-        if !endpoint.user_token_ids.is_empty() {
+        let user_tokens = endpoint.user_token_ids.iter()
+            .filter_map(|id| config.user_tokens.get(id))
+            .collect::<Vec<_>>();
+        if user_tokens.iter().any(|t| t.is_user_pass()) {
             // The endpoint may set a password security policy
             let password_security_policy = if let Some(ref security_policy) = endpoint.password_security_policy {
                 if let Ok(security_policy) = SecurityPolicy::from_str(security_policy) {
@@ -158,6 +214,29 @@ impl ServerState {
                 security_policy_uri: password_security_policy,
             });
         }
+        // X509 tokens are signed with the endpoint's security policy so there must be one
+        if endpoint.security_policy() != SecurityPolicy::None && user_tokens.iter().any(|t| t.is_x509()) {
+            user_identity_tokens.push(UserTokenPolicy {
+                policy_id: UAString::from(TOKEN_POLICY_X509),
+                token_type: UserTokenType::Certificate,
+                issued_token_type: UAString::null(),
+                issuer_endpoint_url: UAString::null(),
+                security_policy_uri: UAString::null(),
+            });
+        }
+
+        // Issued tokens are only advertised if there is something to validate them
+        if endpoint.supports_issued_token() {
+            if let Some(ref issued_token_validator) = self.issued_token_validator {
+                user_identity_tokens.push(UserTokenPolicy {
+                    policy_id: UAString::from(TOKEN_POLICY_ISSUED_TOKEN),
+                    token_type: UserTokenType::IssuedToken,
+                    issued_token_type: issued_token_validator.issued_token_type(),
+                    issuer_endpoint_url: issued_token_validator.issuer_endpoint_url(),
+                    security_policy_uri: UAString::null(),
+                });
+            }
+        }
 
         // CreateSession doesn't need all the endpoint description
         // and docs say not to bother sending the server and server
@@ -191,7 +270,7 @@ impl ServerState {
             security_mode: endpoint.message_security_mode(),
             security_policy_uri: UAString::from(endpoint.security_policy().to_uri()),
             user_identity_tokens: Some(user_identity_tokens),
-            transport_profile_uri: UAString::from(profiles::TRANSPORT_PROFILE_URI_BINARY),
+            transport_profile_uri: UAString::from(if endpoint.https { profiles::TRANSPORT_PROFILE_URI_HTTPS_BINARY } else { profiles::TRANSPORT_PROFILE_URI_BINARY }),
             security_level: endpoint.security_level,
         }
     }
@@ -223,18 +302,39 @@ impl ServerState {
 
     pub fn is_abort(&self) -> bool { self.abort }
 
-    pub fn is_running(&self) -> bool { self.state == ServerStateType::Running }
+    /// Puts the server into the shutdown state. The server stops accepting new connections and
+    /// the shutdown is due once the delay has elapsed.
+    pub(crate) fn begin_shutdown(&mut self, delay: std::time::Duration, reason: LocalizedText) {
+        info!("Server is shutting down in {} seconds, reason = {}", delay.as_secs(), reason.text);
+        let delay = chrono::Duration::from_std(delay).unwrap_or_else(|_| chrono::Duration::zero());
+        self.shutdown_time = Some(chrono::Utc::now() + delay);
+        self.shutdown_reason = reason;
+        self.state = ServerStateType::Shutdown;
+    }
 
-    pub fn max_method_calls(&self) -> usize {
-        self.max_method_calls
+    /// Test if the server has begun to shut down
+    pub fn is_shutting_down(&self) -> bool { self.shutdown_time.is_some() }
+
+    /// Test if a shutdown is due at the supplied time
+    pub(crate) fn is_shutdown_due(&self, now: &DateTimeUtc) -> bool {
+        self.shutdown_time.map_or(false, |shutdown_time| *now >= shutdown_time)
     }
 
-    pub fn max_nodes_per_node_management(&self) -> usize {
-        self.max_nodes_per_node_management
+    /// The number of seconds until the server shuts down, or 0 if it is not shutting down
+    pub fn seconds_till_shutdown(&self) -> u32 {
+        self.shutdown_time.map_or(0, |shutdown_time| {
+            let seconds = shutdown_time.signed_duration_since(chrono::Utc::now()).num_seconds();
+            if seconds > 0 { seconds as u32 } else { 0 }
+        })
     }
 
-    pub fn max_browse_paths_per_translate(&self) -> usize {
-        self.max_browse_paths_per_translate
+    /// The reason for shutting down, or null if the server is not shutting down
+    pub fn shutdown_reason(&self) -> LocalizedText { self.shutdown_reason.clone() }
+
+    pub fn is_running(&self) -> bool { self.state == ServerStateType::Running }
+
+    pub fn operational_limits(&self) -> &OperationalLimits {
+        &self.operational_limits
     }
 
     pub fn server_certificate_as_byte_string(&self) -> ByteString {
@@ -276,8 +376,9 @@ impl ServerState {
     ///
     /// It is possible that the endpoint does not exist, or that the token is invalid / unsupported
     /// or that the token cannot be used with the end point. The return codes reflect the responses
-    /// that ActivateSession would expect from a service call.
-    pub fn authenticate_endpoint(&self, request: &ActivateSessionRequest, endpoint_url: &str, security_policy: SecurityPolicy, security_mode: MessageSecurityMode, user_identity_token: &ExtensionObject, server_nonce: &ByteString) -> Result<(), StatusCode> {
+    /// that ActivateSession would expect from a service call. An authenticated token returns the
+    /// identity of the user.
+    pub fn authenticate_endpoint(&self, request: &ActivateSessionRequest, endpoint_url: &str, security_policy: SecurityPolicy, security_mode: MessageSecurityMode, user_identity_token: &ExtensionObject, server_nonce: &ByteString) -> Result<UserIdentity, StatusCode> {
         // Get security from endpoint url
         let config = trace_read_lock_unwrap!(self.config);
         let decoding_limits = config.decoding_limits();
@@ -285,18 +386,19 @@ impl ServerState {
             // Now validate the user identity token
             if user_identity_token.is_empty() {
                 // Empty tokens are treated as anonymous
-                Self::authenticate_anonymous_token(endpoint)
+                Self::authenticate_anonymous_token(endpoint).map(|_| UserIdentity::Anonymous)
             } else if let Ok(object_id) = user_identity_token.node_id.as_object_id() {
                 // Read the token out from the extension object
                 match object_id {
                     ObjectId::AnonymousIdentityToken_Encoding_DefaultBinary => {
                         // Anonymous
-                        Self::authenticate_anonymous_token(endpoint)
+                        Self::authenticate_anonymous_token(endpoint).map(|_| UserIdentity::Anonymous)
                     }
                     ObjectId::UserNameIdentityToken_Encoding_DefaultBinary => {
                         // Username / password
                         if let Ok(token) = user_identity_token.decode_inner::<UserNameIdentityToken>(&decoding_limits) {
                             self.authenticate_username_identity_token(&config, endpoint, &token, &self.server_pkey, server_nonce)
+                                .map(|_| UserIdentity::UserName(token.user_name.as_ref().to_string()))
                         } else {
                             // Garbage in the extension object
                             error!("User name identity token could not be decoded");
@@ -307,12 +409,24 @@ impl ServerState {
                         // X509 certs
                         if let Ok(token) = user_identity_token.decode_inner::<X509IdentityToken>(&decoding_limits) {
                             self.authenticate_x509_identity_token(&config, endpoint, &token, &request.user_token_signature, &self.server_certificate, server_nonce)
+                                .map(|_| UserIdentity::X509(token.certificate_data.clone()))
                         } else {
                             // Garbage in the extension object
                             error!("X509 identity token could not be decoded");
                             Err(StatusCode::BadIdentityTokenInvalid)
                         }
                     }
+                    ObjectId::IssuedIdentityToken_Encoding_DefaultBinary => {
+                        // Issued tokens, e.g. JWT
+                        if let Ok(token) = user_identity_token.decode_inner::<IssuedIdentityToken>(&decoding_limits) {
+                            self.authenticate_issued_identity_token(endpoint_url, endpoint, &token, &self.server_pkey, server_nonce)
+                                .map(UserIdentity::IssuedToken)
+                        } else {
+                            // Garbage in the extension object
+                            error!("Issued identity token could not be decoded");
+                            Err(StatusCode::BadIdentityTokenInvalid)
+                        }
+                    }
                     _ => {
                         error!("User identity token type {:?} is unrecognized", object_id);
                         Err(StatusCode::BadIdentityTokenInvalid)
@@ -328,11 +442,203 @@ impl ServerState {
         }
     }
 
+    /// Authenticates the access token of a session-less service call, which is validated the same
+    /// as the token data of an issued identity token. The endpoint must accept issued tokens.
+    pub fn authenticate_access_token(&self, endpoint_url: &str, security_policy: SecurityPolicy, security_mode: MessageSecurityMode, access_token: &ByteString) -> Result<UserIdentity, StatusCode> {
+        let config = trace_read_lock_unwrap!(self.config);
+        if let Some(endpoint) = config.find_endpoint(endpoint_url, security_policy, security_mode) {
+            if !endpoint.supports_issued_token() {
+                error!("Endpoint \"{}\" does not support session-less calls because it does not accept issued tokens", endpoint.path);
+                Err(StatusCode::BadIdentityTokenRejected)
+            } else if access_token.is_null_or_empty() {
+                error!("Session-less call supplies no access token");
+                Err(StatusCode::BadIdentityTokenInvalid)
+            } else if let Some(ref issued_token_validator) = self.issued_token_validator {
+                issued_token_validator.validate(endpoint_url, access_token)
+                    .map(|_| UserIdentity::IssuedToken(access_token.clone()))
+            } else {
+                error!("Access token cannot be validated because there is no issued token validator");
+                Err(StatusCode::BadIdentityTokenRejected)
+            }
+        } else {
+            error!("Cannot find endpoint that matches path \"{}\", security policy {:?}, and security mode {:?}", endpoint_url, security_policy, security_mode);
+            Err(StatusCode::BadTcpEndpointUrlInvalid)
+        }
+    }
+
+    /// Authenticates a request to the REST gateway from its Authorization header. A request
+    /// without the header is anonymous, Basic authorization holds the user name and password of a
+    /// user name token and Bearer authorization holds an access token for the issued token
+    /// validator. The gateway must accept the kind of user token that the request uses, and it
+    /// must listen on a loopback address for credentials to be sent to it over plain HTTP.
+    pub(crate) fn authenticate_rest_request(&self, authorization: Option<&str>) -> Result<UserIdentity, StatusCode> {
+        let config = trace_read_lock_unwrap!(self.config);
+        let rest_gateway = config.rest_gateway.as_ref().ok_or(StatusCode::BadServiceUnsupported)?;
+        let authorization = authorization.map(|authorization| authorization.trim()).unwrap_or("");
+        if !authorization.is_empty() && !rest_gateway.is_loopback() {
+            error!("REST request was rejected because credentials cannot be accepted over plain HTTP on {}", rest_gateway.address);
+            Err(StatusCode::BadSecurityModeInsufficient)
+        } else if authorization.is_empty() {
+            if rest_gateway.user_token_ids.contains(ANONYMOUS_USER_TOKEN_ID) {
+                Ok(UserIdentity::Anonymous)
+            } else {
+                error!("REST request was rejected because it is anonymous");
+                Err(StatusCode::BadIdentityTokenInvalid)
+            }
+        } else if authorization.starts_with("Bearer ") {
+            let access_token = ByteString::from(authorization[7..].trim().as_bytes());
+            if !rest_gateway.user_token_ids.contains(ISSUED_TOKEN_USER_TOKEN_ID) {
+                error!("REST request was rejected because the gateway does not accept access tokens");
+                Err(StatusCode::BadIdentityTokenRejected)
+            } else if access_token.is_null_or_empty() {
+                error!("REST request supplies no access token");
+                Err(StatusCode::BadIdentityTokenInvalid)
+            } else if let Some(ref issued_token_validator) = self.issued_token_validator {
+                issued_token_validator.validate(&rest_gateway.url(), &access_token)
+                    .map(|_| UserIdentity::IssuedToken(access_token))
+            } else {
+                error!("Access token cannot be validated because there is no issued token validator");
+                Err(StatusCode::BadIdentityTokenRejected)
+            }
+        } else if authorization.starts_with("Basic ") {
+            // The credentials are the base64 of "user:pass"
+            let credentials = ByteString::from_base64(authorization[6..].trim())
+                .and_then(|credentials| credentials.value)
+                .and_then(|credentials| String::from_utf8(credentials).ok());
+            let (user, pass) = match credentials {
+                Some(ref credentials) if credentials.contains(':') => {
+                    let idx = credentials.find(':').unwrap();
+                    (&credentials[..idx], &credentials[idx + 1..])
+                }
+                _ => {
+                    error!("REST request Basic authorization is invalid");
+                    return Err(StatusCode::BadIdentityTokenInvalid);
+                }
+            };
+            let valid = rest_gateway.user_token_ids.iter()
+                .filter_map(|user_token_id| config.user_tokens.get(user_token_id))
+                .any(|server_user_token| {
+                    // Password compared as UTF-8 bytes
+                    server_user_token.is_user_pass() && server_user_token.user == user &&
+                        server_user_token.pass.as_ref().map(|p| p.as_str()).unwrap_or("") == pass
+                });
+            if valid {
+                Ok(UserIdentity::UserName(user.to_string()))
+            } else {
+                error!("Cannot authenticate \"{}\" for REST request, user or password is invalid", user);
+                Err(StatusCode::BadIdentityTokenRejected)
+            }
+        } else {
+            error!("REST request Authorization header has an unsupported scheme");
+            Err(StatusCode::BadIdentityTokenInvalid)
+        }
+    }
+
     pub fn set_register_nodes_callbacks(&mut self, register_nodes_callback: Box<RegisterNodes + Send + Sync>, unregister_nodes_callback: Box<UnregisterNodes + Send + Sync>) {
         self.register_nodes_callback = Some(register_nodes_callback);
         self.unregister_nodes_callback = Some(unregister_nodes_callback);
     }
 
+    /// Sets the provider that the HistoryRead service obtains historical values from
+    pub fn set_historical_data_provider(&mut self, historical_data_provider: Box<HistoricalDataProvider + Send + Sync>) {
+        self.historical_data_provider = Some(historical_data_provider);
+    }
+
+    /// Sets the validator that ActivateSession passes issued identity tokens to
+    pub fn set_issued_token_validator(&mut self, issued_token_validator: Box<IssuedTokenValidator + Send + Sync>) {
+        self.issued_token_validator = Some(issued_token_validator);
+    }
+
+    /// Sets the audit log that audit events raised by the server are passed to
+    pub fn set_audit_log(&mut self, audit_log: Box<AuditLog + Send + Sync>) {
+        self.audit_log = Some(RwLock::new(audit_log));
+    }
+
+    /// Returns the ids of the roles that the user is granted by the role set
+    pub(crate) fn roles_for(&self, identity: &UserIdentity) -> Vec<NodeId> {
+        let role_set = trace_read_lock_unwrap!(self.role_set);
+        role_set.roles_for(identity)
+    }
+
+    /// Sets the access control that the Read, Write, Browse and Call services consult to decide what
+    /// the user of a session may do to each node
+    pub fn set_access_control(&mut self, access_control: Box<AccessControl + Send + Sync>) {
+        self.access_control = Some(access_control);
+    }
+
+    /// Sets the evaluator that the QueryFirst service uses to find the nodes that match a query
+    pub fn set_query_evaluator(&mut self, query_evaluator: Box<QueryEvaluator + Send + Sync>) {
+        self.query_evaluator = Some(query_evaluator);
+    }
+
+    /// Sets the callbacks that are told when clients connect, when sessions are created, activated
+    /// and closed, and when subscriptions are created
+    pub fn set_server_callbacks(&mut self, server_callbacks: Box<ServerCallbacks + Send + Sync>) {
+        self.server_callbacks = Some(server_callbacks);
+    }
+
+    /// Tests if the server callbacks, if there are any, accept a connection from the client address
+    pub(crate) fn on_client_connected(&mut self, client_address: &SocketAddr) -> Result<(), StatusCode> {
+        if let Some(ref mut server_callbacks) = self.server_callbacks {
+            server_callbacks.on_client_connected(client_address)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn on_session_created(&mut self, session: &Session) {
+        if let Some(ref mut server_callbacks) = self.server_callbacks {
+            server_callbacks.on_session_created(session);
+        }
+    }
+
+    pub(crate) fn on_session_activated(&mut self, session: &Session) {
+        if let Some(ref mut server_callbacks) = self.server_callbacks {
+            server_callbacks.on_session_activated(session);
+        }
+    }
+
+    pub(crate) fn on_session_closed(&mut self, session: &Session) {
+        if let Some(ref mut server_callbacks) = self.server_callbacks {
+            server_callbacks.on_session_closed(session);
+        }
+    }
+
+    pub(crate) fn on_subscription_created(&mut self, session: &Session, subscription_id: u32) {
+        if let Some(ref mut server_callbacks) = self.server_callbacks {
+            server_callbacks.on_subscription_created(session, subscription_id);
+        }
+    }
+
+    /// Tests if the user may read the attribute of the node. Anything may be read if there is no
+    /// access control.
+    pub(crate) fn can_read(&self, identity: &UserIdentity, node_id: &NodeId, attribute_id: AttributeId) -> bool {
+        self.access_control.as_ref().map_or(true, |access_control| access_control.can_read(identity, node_id, attribute_id))
+    }
+
+    /// Tests if the user may write the attribute of the node
+    pub(crate) fn can_write(&self, identity: &UserIdentity, node_id: &NodeId, attribute_id: AttributeId) -> bool {
+        self.access_control.as_ref().map_or(true, |access_control| access_control.can_write(identity, node_id, attribute_id))
+    }
+
+    /// Tests if the user may browse the node
+    pub(crate) fn can_browse(&self, identity: &UserIdentity, node_id: &NodeId) -> bool {
+        self.access_control.as_ref().map_or(true, |access_control| access_control.can_browse(identity, node_id))
+    }
+
+    /// Tests if the user may call the method on the object
+    pub(crate) fn can_call(&self, identity: &UserIdentity, object_id: &NodeId, method_id: &NodeId) -> bool {
+        self.access_control.as_ref().map_or(true, |access_control| access_control.can_call(identity, object_id, method_id))
+    }
+
+    /// Passes an audit event to the audit log, if there is one
+    pub(crate) fn log_audit_event(&self, event: &Event) {
+        if let Some(ref audit_log) = self.audit_log {
+            let mut audit_log = trace_write_lock_unwrap!(audit_log);
+            audit_log.log(event);
+        }
+    }
+
     /// Authenticates an anonymous token, i.e. does the endpoint support anonymous access or not
     fn authenticate_anonymous_token(endpoint: &ServerEndpoint) -> Result<(), StatusCode> {
         if endpoint.supports_anonymous() {
@@ -344,13 +650,18 @@ impl ServerState {
         }
     }
 
+    /// Authenticates the X509 identity token with the supplied endpoint. The token's signature
+    /// must be valid and the certificate must match one of the X509 user tokens of the endpoint.
     fn authenticate_x509_identity_token(&self, config: &ServerConfig, endpoint: &ServerEndpoint, token: &X509IdentityToken, user_token_signature: &SignatureData, server_certificate: &Option<X509>, server_nonce: &ByteString) -> Result<(), StatusCode> {
-        let result = match server_certificate {
+        match server_certificate {
             Some(ref server_certificate) => {
                 let security_policy = endpoint.security_policy();
-                // The security policy has to be something that can encrypt
+                // The security policy has to be something that can sign
                 match security_policy {
-                    SecurityPolicy::Unknown | SecurityPolicy::None => Err(StatusCode::BadIdentityTokenInvalid),
+                    SecurityPolicy::Unknown | SecurityPolicy::None => {
+                        error!("X509 identity token cannot be verified on endpoint \"{}\" with no security policy", endpoint.path);
+                        Err(StatusCode::BadIdentityTokenInvalid)
+                    }
                     security_policy => {
                         // Verify token
                         user_identity::verify_x509_identity_token(token, user_token_signature, security_policy, server_certificate, server_nonce.as_ref())
@@ -358,24 +669,67 @@ impl ServerState {
                 }
             }
             None => {
+                error!("X509 identity token cannot be verified because the server has no certificate");
                 Err(StatusCode::BadIdentityTokenInvalid)
             }
-        };
+        }?;
 
-        result.and_then(|_| {
-            let valid = true;
-            // Check the endpoint to see if this token is supported
-            for user_token_id in &endpoint.user_token_ids {
-                if let Some(server_user_token) = config.user_tokens.get(user_token_id) {
-                    if server_user_token.is_x509()  {
-                        // TODO verify there is a x509 on disk that matches the one supplied
+        let user_cert = X509::from_byte_string(&token.certificate_data)?;
+        let status_code = user_cert.is_time_valid(&chrono::Utc::now());
+        if status_code.is_bad() {
+            error!("X509 identity token certificate is not valid at this time, status code = {}", status_code);
+            return Err(StatusCode::BadIdentityTokenRejected);
+        }
+
+        // Check the endpoint to see if this certificate belongs to one of its users
+        let thumbprint = user_cert.thumbprint();
+        for user_token_id in &endpoint.user_token_ids {
+            if let Some(server_user_token) = config.user_tokens.get(user_token_id) {
+                if server_user_token.is_x509() {
+                    match server_user_token.read_x509() {
+                        Ok(server_user_cert) => if server_user_cert.thumbprint().value == thumbprint.value {
+                            debug!("X509 identity \"{}\" is authenticated", server_user_token.user);
+                            return Ok(());
+                        }
+                        Err(err) => {
+                            error!("Cannot read the x509 cert of user token {}, {}", user_token_id, err);
+                        }
                     }
                 }
             }
-            if valid { Ok(()) } else { Err(StatusCode::BadIdentityTokenInvalid) }
-        })
+        }
+        error!("Cannot authenticate X509 identity token, certificate is not trusted by endpoint \"{}\"", endpoint.path);
+        Err(StatusCode::BadIdentityTokenRejected)
     }
 
+    /// Authenticates the issued identity token with the supplied endpoint. The token data is
+    /// decrypted if necessary and passed to the issued token validator.
+    fn authenticate_issued_identity_token(&self, endpoint_url: &str, endpoint: &ServerEndpoint, token: &IssuedIdentityToken, server_key: &Option<PrivateKey>, server_nonce: &ByteString) -> Result<ByteString, StatusCode> {
+        if !endpoint.supports_issued_token() {
+            error!("Endpoint \"{}\" does not support issued identity tokens", endpoint.path);
+            Err(StatusCode::BadIdentityTokenRejected)
+        } else if let Some(ref issued_token_validator) = self.issued_token_validator {
+            let token_data = if !token.encryption_algorithm.is_null() {
+                if let Some(ref server_key) = server_key {
+                    user_identity::decrypt_issued_identity_token(token, server_nonce.as_ref(), server_key)?
+                } else {
+                    error!("Issued identity token is encrypted and no server private key was supplied");
+                    return Err(StatusCode::BadIdentityTokenInvalid);
+                }
+            } else {
+                token.token_data.clone()
+            };
+            if token_data.is_null_or_empty() {
+                error!("Issued identity token supplies no token data");
+                Err(StatusCode::BadIdentityTokenInvalid)
+            } else {
+                issued_token_validator.validate(endpoint_url, &token_data).map(|_| token_data)
+            }
+        } else {
+            error!("Issued identity token cannot be validated because there is no issued token validator");
+            Err(StatusCode::BadIdentityTokenRejected)
+        }
+    }
 
     /// Authenticates the username identity token with the supplied endpoint
     fn authenticate_username_identity_token(&self, config: &ServerConfig, endpoint: &ServerEndpoint, token: &UserNameIdentityToken, server_key: &Option<PrivateKey>, server_nonce: &ByteString) -> Result<(), StatusCode> {
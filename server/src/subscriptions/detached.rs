@@ -0,0 +1,108 @@
+//! Subscriptions whose session has ended without deleting them. They are kept alive for their
+//! lifetime so a client that reconnects can reclaim them with the TransferSubscriptions service.
+
+use std::collections::BTreeMap;
+
+use opcua_types::{
+    *,
+    node_ids::ObjectId,
+    service_types::{NotificationMessage, UserNameIdentityToken, X509IdentityToken},
+    status_code::StatusCode,
+};
+
+use crate::{
+    address_space::AddressSpace,
+    subscriptions::subscription::{Subscription, SubscriptionState, TickReason},
+};
+
+/// A subscription that has been detached from its session, with the notifications that were sent
+/// to the client but not yet acknowledged.
+pub(crate) struct DetachedSubscription {
+    pub subscription: Subscription,
+    pub unacknowledged_notifications: Vec<NotificationMessage>,
+    /// Identifies the user of the session the subscription belonged to. Only the same user may
+    /// reclaim the subscription. A `None` means the subscription cannot be transferred.
+    pub user_identity: Option<String>,
+}
+
+/// Holds subscriptions detached from their sessions until they are transferred to a new session
+/// or their lifetime expires.
+pub(crate) struct DetachedSubscriptions {
+    subscriptions: BTreeMap<u32, DetachedSubscription>,
+}
+
+impl DetachedSubscriptions {
+    pub fn new() -> DetachedSubscriptions {
+        DetachedSubscriptions {
+            subscriptions: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+
+    pub fn contains(&self, subscription_id: u32) -> bool {
+        self.subscriptions.contains_key(&subscription_id)
+    }
+
+    pub fn insert(&mut self, detached: DetachedSubscription) {
+        let subscription_id = detached.subscription.subscription_id();
+        debug!("Subscription {} is detached from its session", subscription_id);
+        self.subscriptions.insert(subscription_id, detached);
+    }
+
+    /// Removes the detached subscription so it can be transferred to a session belonging to the
+    /// supplied user. The subscription stays detached if the user is not the one who created it.
+    pub fn take(&mut self, subscription_id: u32, user_identity: &Option<String>) -> Result<DetachedSubscription, StatusCode> {
+        if let Some(detached) = self.subscriptions.get(&subscription_id) {
+            if detached.user_identity.is_none() || detached.user_identity != *user_identity {
+                error!("Subscription {} cannot be transferred to a different user", subscription_id);
+                return Err(StatusCode::BadUserAccessDenied);
+            }
+        } else {
+            return Err(StatusCode::BadSubscriptionIdInvalid);
+        }
+        Ok(self.subscriptions.remove(&subscription_id).unwrap())
+    }
+
+    /// Ticks the detached subscriptions so their monitored items continue to sample and queue
+    /// notifications. There are no publish requests, so each subscription counts down its lifetime
+    /// and is removed when it expires.
+    pub fn tick(&mut self, now: &DateTimeUtc, address_space: &AddressSpace) {
+        self.subscriptions.values_mut().for_each(|detached| {
+            detached.subscription.tick(now, address_space, TickReason::TickTimerFired, false);
+        });
+        self.subscriptions.retain(|subscription_id, detached| {
+            if detached.subscription.state() == SubscriptionState::Closed {
+                debug!("Detached subscription {} has expired", subscription_id);
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+/// Derives a value identifying the user from a user identity token so that sessions belonging to
+/// the same user can be recognized. Anonymous users are all the same user. Returns `None` if the
+/// session was never activated or for tokens that cannot be compared, e.g. issued tokens whose
+/// data differs between sessions.
+pub(crate) fn user_identity_key(user_identity_token: &Option<ExtensionObject>, decoding_limits: &DecodingLimits) -> Option<String> {
+    match user_identity_token {
+        None => None,
+        Some(token) if token.is_empty() => Some("anonymous".to_string()),
+        Some(token) => match token.node_id.as_object_id() {
+            Ok(ObjectId::AnonymousIdentityToken_Encoding_DefaultBinary) => Some("anonymous".to_string()),
+            Ok(ObjectId::UserNameIdentityToken_Encoding_DefaultBinary) => {
+                token.decode_inner::<UserNameIdentityToken>(decoding_limits).ok()
+                    .map(|token| format!("user:{}", token.user_name.as_ref()))
+            }
+            Ok(ObjectId::X509IdentityToken_Encoding_DefaultBinary) => {
+                token.decode_inner::<X509IdentityToken>(decoding_limits).ok()
+                    .map(|token| format!("x509:{}", token.certificate_data.as_base64()))
+            }
+            _ => None
+        }
+    }
+}
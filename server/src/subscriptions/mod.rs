@@ -36,3 +36,5 @@ fn duration_from_ms(d: f64) -> time::Duration {
 pub mod subscriptions;
 pub mod subscription;
 pub mod monitored_item;
+pub mod sampling_group;
+pub mod detached;
@@ -6,16 +6,28 @@ use opcua_types::{
     status_code::StatusCode,
     node_ids::ObjectId,
     service_types::{
-        TimestampsToReturn, DataChangeFilter, ReadValueId, MonitoredItemCreateRequest, MonitoredItemModifyRequest, MonitoredItemNotification,
+        TimestampsToReturn, DataChangeFilter, EventFilter, EventFilterResult, EventFieldList, ContentFilterResult,
+        ReadValueId, MonitoredItemCreateRequest, MonitoredItemModifyRequest, MonitoredItemNotification,
     },
 };
 
-use crate::{constants, address_space::AddressSpace};
+use crate::{
+    constants,
+    address_space::AddressSpace,
+    content_filter::{self, ContentFilterEvaluator},
+    events::event::{Event, EventTarget},
+};
+
+/// Data change filter deadband type of no deadband
+const DEADBAND_TYPE_NONE: u32 = 0;
+/// Data change filter deadband type of a percentage of the EURange of an analog item
+const DEADBAND_TYPE_PERCENT: u32 = 2;
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub(crate) enum FilterType {
     None,
     DataChangeFilter(DataChangeFilter),
+    EventFilter(EventFilter),
 }
 
 impl FilterType {
@@ -28,11 +40,126 @@ impl FilterType {
         } else if filter_type_id == &ObjectId::DataChangeFilter_Encoding_DefaultBinary.into() {
             let decoding_limits = DecodingLimits::minimal();
             Ok(FilterType::DataChangeFilter(filter.decode_inner::<DataChangeFilter>(&decoding_limits)?))
+        } else if filter_type_id == &ObjectId::EventFilter_Encoding_DefaultBinary.into() {
+            let decoding_limits = DecodingLimits::default();
+            Ok(FilterType::EventFilter(filter.decode_inner::<EventFilter>(&decoding_limits)?))
         } else {
             error!("Requested data filter type is not supported, {:?}", filter_type_id);
             Err(StatusCode::BadFilterNotAllowed)
         }
     }
+
+    /// Validates that the filter may be used on the attribute. Event filters must be used, and
+    /// may only be used, on the `EventNotifier` attribute.
+    pub fn validate(&self, attribute_id: u32) -> Result<(), StatusCode> {
+        let is_event_notifier = attribute_id == AttributeId::EventNotifier as u32;
+        match *self {
+            FilterType::EventFilter(ref filter) => {
+                if !is_event_notifier {
+                    error!("Event filter is only allowed on the EventNotifier attribute");
+                    Err(StatusCode::BadFilterNotAllowed)
+                } else if is_empty_option_vec!(filter.select_clauses) {
+                    error!("Event filter has no select clauses");
+                    Err(StatusCode::BadEventFilterInvalid)
+                } else {
+                    Ok(())
+                }
+            }
+            FilterType::DataChangeFilter(_) if is_event_notifier => {
+                error!("Data change filter is not allowed on the EventNotifier attribute");
+                Err(StatusCode::BadFilterNotAllowed)
+            }
+            FilterType::DataChangeFilter(ref filter) => {
+                let deadband_type = filter.deadband_type;
+                if deadband_type > DEADBAND_TYPE_PERCENT || filter.deadband_value < 0f64 ||
+                    (deadband_type == DEADBAND_TYPE_PERCENT && filter.deadband_value > 100f64) {
+                    error!("Data change filter has an invalid deadband, type {}, value {}", deadband_type, filter.deadband_value);
+                    Err(StatusCode::BadDeadbandFilterInvalid)
+                } else if deadband_type != DEADBAND_TYPE_NONE && attribute_id != AttributeId::Value as u32 {
+                    error!("Deadband is only allowed on the Value attribute");
+                    Err(StatusCode::BadFilterNotAllowed)
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(())
+        }
+    }
+
+    /// Validates the filter against the node being monitored. A percent deadband is a percentage
+    /// of the `EURange` so the node must be an analog item with that property. The where clause
+    /// of an event filter must refer to types in the address space.
+    pub fn validate_node(&self, address_space: &AddressSpace, node_id: &NodeId) -> Result<(), StatusCode> {
+        match *self {
+            FilterType::EventFilter(ref filter) => {
+                content_filter::validate_content_filter(address_space, &filter.where_clause).map_err(|_| {
+                    error!("Event filter on node {:?} has an invalid where clause", node_id);
+                    StatusCode::BadMonitoredItemFilterInvalid
+                })
+            }
+            FilterType::DataChangeFilter(ref filter) if filter.deadband_type == DEADBAND_TYPE_PERCENT => {
+                match address_space.eu_range(node_id) {
+                    Some((low, high)) if low < high => Ok(()),
+                    _ => {
+                        error!("Percent deadband requires node {:?} to have a valid EURange", node_id);
+                        Err(StatusCode::BadMonitoredItemFilterUnsupported)
+                    }
+                }
+            }
+            _ => Ok(())
+        }
+    }
+
+    /// Returns the filter result that is sent back to the client for this filter. Only event
+    /// filters have a result.
+    pub fn filter_result(&self) -> ExtensionObject {
+        if let FilterType::EventFilter(ref filter) = *self {
+            let select_clause_results = filter.select_clauses.as_ref().map(|select_clauses| {
+                select_clauses.iter().map(|select_clause| {
+                    if AttributeId::from_u32(select_clause.attribute_id).is_ok() {
+                        StatusCode::Good
+                    } else {
+                        StatusCode::BadAttributeIdInvalid
+                    }
+                }).collect()
+            });
+            let filter_result = EventFilterResult {
+                select_clause_results,
+                select_clause_diagnostic_infos: None,
+                where_clause_result: ContentFilterResult {
+                    element_results: None,
+                    element_diagnostic_infos: None,
+                },
+            };
+            ExtensionObject::from_encodable(ObjectId::EventFilterResult_Encoding_DefaultBinary, &filter_result)
+        } else {
+            ExtensionObject::null()
+        }
+    }
+
+    /// Test if this is an event filter
+    pub fn is_event_filter(&self) -> bool {
+        if let FilterType::EventFilter(_) = *self { true } else { false }
+    }
+}
+
+/// A notification that is queued on a monitored item
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) enum Notification {
+    MonitoredItemNotification(MonitoredItemNotification),
+    EventFieldList(EventFieldList),
+}
+
+impl From<MonitoredItemNotification> for Notification {
+    fn from(v: MonitoredItemNotification) -> Self {
+        Notification::MonitoredItemNotification(v)
+    }
+}
+
+impl From<EventFieldList> for Notification {
+    fn from(v: EventFieldList) -> Self {
+        Notification::EventFieldList(v)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
@@ -50,11 +177,17 @@ pub(crate) struct MonitoredItem {
     queue_size: usize,
     /// The notification queue is arranged from oldest to newest, i.e. pop front gets the oldest
     /// message, pop back gets the most recent.
-    notification_queue: VecDeque<MonitoredItemNotification>,
+    notification_queue: VecDeque<Notification>,
     queue_overflow: bool,
     timestamps_to_return: TimestampsToReturn,
     last_sample_time: DateTimeUtc,
     last_data_value: Option<DataValue>,
+    /// The value of the item's node read by its sampling group in one pass with other items,
+    /// which is used by the next sample
+    sampled_value: Option<DataValue>,
+    /// For event monitored items, the number of the last event in the address space that has
+    /// been collected. This is `None` until the first tick.
+    last_event_number: Option<u64>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -70,8 +203,9 @@ pub(crate) enum TickResult {
 impl MonitoredItem {
     pub fn new(now: &DateTimeUtc, monitored_item_id: u32, timestamps_to_return: TimestampsToReturn, request: &MonitoredItemCreateRequest) -> Result<MonitoredItem, StatusCode> {
         let filter = FilterType::from_filter(&request.requested_parameters.filter)?;
+        filter.validate(request.item_to_monitor.attribute_id)?;
         let sampling_interval = Self::sanitize_sampling_interval(request.requested_parameters.sampling_interval);
-        let queue_size = Self::sanitize_queue_size(request.requested_parameters.queue_size as usize);
+        let queue_size = Self::sanitize_queue_size(&filter, request.requested_parameters.queue_size as usize);
         Ok(MonitoredItem {
             monitored_item_id,
             item_to_monitor: request.item_to_monitor.clone(),
@@ -84,28 +218,73 @@ impl MonitoredItem {
             timestamps_to_return,
             last_sample_time: now.clone(),
             last_data_value: None,
+            sampled_value: None,
+            last_event_number: None,
             queue_size,
             notification_queue: VecDeque::with_capacity(queue_size),
             queue_overflow: false,
         })
     }
 
+    /// Validates the item's filter against the node being monitored, e.g. that a node has the
+    /// range needed for a percent deadband.
+    pub fn validate_node(&self, address_space: &AddressSpace) -> Result<(), StatusCode> {
+        self.filter.validate_node(address_space, &self.item_to_monitor.node_id)
+    }
+
+    /// Revises the sampling interval of a data change item up to the server's minimum sampling
+    /// interval, or up to the `MinimumSamplingInterval` of the monitored variable if that is
+    /// larger, so a value is not sampled faster than it can change. A negative interval, i.e. the
+    /// publishing interval of the subscription, is left alone.
+    pub fn revise_sampling_interval(&mut self, address_space: &AddressSpace, min_sampling_interval: f64) {
+        if self.sampling_interval < 0f64 || self.filter.is_event_filter() {
+            return;
+        }
+        // A MinimumSamplingInterval of 0 means the variable is sampled continuously and -1 means
+        // it is indeterminate, so only a positive value restricts sampling
+        let node_min_sampling_interval = if self.item_to_monitor.attribute_id == AttributeId::Value as u32 {
+            address_space.find_variable_by_ref(&self.item_to_monitor.node_id)
+                .and_then(|v| v.minimum_sampling_interval())
+                .filter(|v| *v > 0f64)
+        } else {
+            None
+        };
+        let min_sampling_interval = match node_min_sampling_interval {
+            Some(node_min_sampling_interval) if node_min_sampling_interval > min_sampling_interval => node_min_sampling_interval,
+            _ => min_sampling_interval
+        };
+        if self.sampling_interval < min_sampling_interval {
+            self.sampling_interval = min_sampling_interval;
+        }
+    }
+
     /// Modifies the existing item with the values of the modify request. On success, the result
     /// holds the filter result.
-    pub fn modify(&mut self, timestamps_to_return: TimestampsToReturn, request: &MonitoredItemModifyRequest) -> Result<ExtensionObject, StatusCode> {
+    pub fn modify(&mut self, address_space: &AddressSpace, timestamps_to_return: TimestampsToReturn, request: &MonitoredItemModifyRequest) -> Result<ExtensionObject, StatusCode> {
+        let filter = FilterType::from_filter(&request.requested_parameters.filter)?;
+        filter.validate(self.item_to_monitor.attribute_id)?;
+        filter.validate_node(address_space, &self.item_to_monitor.node_id)?;
         self.timestamps_to_return = timestamps_to_return;
-        self.filter = FilterType::from_filter(&request.requested_parameters.filter)?;
+        self.filter = filter;
         self.sampling_interval = Self::sanitize_sampling_interval(request.requested_parameters.sampling_interval);
-        self.queue_size = Self::sanitize_queue_size(request.requested_parameters.queue_size as usize);
+        self.queue_size = Self::sanitize_queue_size(&self.filter, request.requested_parameters.queue_size as usize);
         self.client_handle = request.requested_parameters.client_handle;
         self.discard_oldest = request.requested_parameters.discard_oldest;
 
         // Shrink / grow the notification queue to the new threshold
         if self.notification_queue.len() > self.queue_size {
-            // Discard old notifications
-            let discard = self.queue_size - self.notification_queue.len();
-            let _ = self.notification_queue.drain(0..discard);
-            // TODO potential edge case with discard oldest behaviour
+            // Discard notifications the same way as an overflow would, either the oldest ones or
+            // the ones before the newest
+            let discard = self.notification_queue.len() - self.queue_size;
+            if self.discard_oldest {
+                let _ = self.notification_queue.drain(0..discard);
+            } else {
+                let end = self.notification_queue.len() - 1;
+                let _ = self.notification_queue.drain((end - discard)..end);
+            }
+            if self.queue_size > 1 {
+                self.set_overflow_bit();
+            }
             // Shrink the queue
             self.notification_queue.shrink_to_fit();
         } else if self.notification_queue.capacity() < self.queue_size {
@@ -114,11 +293,7 @@ impl MonitoredItem {
             self.notification_queue.reserve(extra_capacity);
         }
 
-        // DataChangeFilter has no result but if the impl adds support for EventFilter, AggregateFilter
-        // then this filter result will have to be filled in.
-        let filter_result = ExtensionObject::null();
-
-        Ok(filter_result)
+        Ok(self.filter_result())
     }
 
     /// Adds or removes other monitored items which will be triggered when this monitored item changes
@@ -137,29 +312,28 @@ impl MonitoredItem {
     /// Function returns a `TickResult` denoting if the value changed or not, and whether it should
     /// be reported.
     pub fn tick(&mut self, now: &DateTimeUtc, address_space: &AddressSpace, publishing_interval_elapsed: bool, resend_data: bool) -> TickResult {
+        let sampling_due = self.is_sampling_due(now, publishing_interval_elapsed, resend_data);
+        self.tick_sampling(now, address_space, sampling_due, resend_data)
+    }
+
+    /// Ticks the monitored item like `tick()`, except that whether a data change item samples its
+    /// value on this tick has already been decided, e.g. by the item's sampling group.
+    pub fn tick_sampling(&mut self, now: &DateTimeUtc, address_space: &AddressSpace, sampling_due: bool, resend_data: bool) -> TickResult {
         if self.monitoring_mode == MonitoringMode::Disabled {
             TickResult::NoChange
-        } else {
-            let check_value = if resend_data {
-                // Always check for resend_data flag
-                true
-            } else if self.sampling_interval < 0f64 {
-                // -1 means use the subscription publishing interval so if the publishing interval elapsed,
-                // then this monitored item is evaluated otherwise it won't be.
-                publishing_interval_elapsed
-            } else if self.sampling_interval == 0f64 {
-                // 0 means fastest practical rate, i.e. the tick quantum itself
-                // 0 is also used for clients subscribing for events.
-                true
+        } else if self.filter.is_event_filter() {
+            // Events are not sampled, they are collected as they are raised
+            let _ = self.check_events(address_space);
+            if self.notification_queue.is_empty() {
+                TickResult::NoChange
+            } else if self.monitoring_mode == MonitoringMode::Reporting {
+                TickResult::ReportValueChanged
             } else {
-                // Compare sample interval to the time elapsed
-                let sampling_interval = super::duration_from_ms(self.sampling_interval);
-                let elapsed = now.signed_duration_since(self.last_sample_time);
-                elapsed >= sampling_interval
-            };
-
+                TickResult::ValueChanged
+            }
+        } else {
             // Test the value (or don't)
-            let value_changed = check_value && {
+            let value_changed = sampling_due && {
                 // Indicate a change if reporting is enabled
                 let first_tick = self.last_data_value.is_none();
                 let value_changed = self.check_value(address_space, now, resend_data);
@@ -178,6 +352,34 @@ impl MonitoredItem {
         }
     }
 
+    /// Tests if a data change item should sample the value of its node on this tick
+    pub fn is_sampling_due(&self, now: &DateTimeUtc, publishing_interval_elapsed: bool, resend_data: bool) -> bool {
+        if self.monitoring_mode == MonitoringMode::Disabled || self.filter.is_event_filter() {
+            false
+        } else if resend_data {
+            // Always check for resend_data flag
+            true
+        } else if self.sampling_interval < 0f64 {
+            // -1 means use the subscription publishing interval so if the publishing interval elapsed,
+            // then this monitored item is evaluated otherwise it won't be.
+            publishing_interval_elapsed
+        } else if self.sampling_interval == 0f64 {
+            // 0 means fastest practical rate, i.e. the tick quantum itself
+            // 0 is also used for clients subscribing for events.
+            true
+        } else {
+            // Compare sample interval to the time elapsed
+            let sampling_interval = super::duration_from_ms(self.sampling_interval);
+            let elapsed = now.signed_duration_since(self.last_sample_time);
+            elapsed >= sampling_interval
+        }
+    }
+
+    /// Sets the value of the item's node, read by its sampling group, for the next sample
+    pub fn set_sampled_value(&mut self, sampled_value: DataValue) {
+        self.sampled_value = Some(sampled_value);
+    }
+
     /// Fetches the most recent value of the monitored item from the source and compares
     /// it to the last value. If the value has changed according to a filter / equality
     /// check, the latest value and its timestamps will be stored in the monitored item.
@@ -187,7 +389,12 @@ impl MonitoredItem {
         if self.monitoring_mode == MonitoringMode::Disabled {
             panic!("Should not check value while monitoring mode is disabled");
         }
+        if self.filter.is_event_filter() {
+            // Event monitored items have no value
+            return false;
+        }
         self.last_sample_time = *now;
+        let sampled_value = self.sampled_value.take();
         if let Some(node) = address_space.find_node(&self.item_to_monitor.node_id) {
             let node = node.as_node();
             let attribute_id = AttributeId::from_u32(self.item_to_monitor.attribute_id);
@@ -196,7 +403,14 @@ impl MonitoredItem {
                 return false;
             }
             let attribute_id = attribute_id.unwrap();
-            let data_value = node.get_attribute(attribute_id, 0.0);
+            let node_id = &self.item_to_monitor.node_id;
+            let data_value = if sampled_value.is_some() {
+                sampled_value
+            } else if attribute_id == AttributeId::Value && address_space.has_value_provider(node_id) {
+                address_space.read_provided_values(&[node_id.clone()], 0.0).remove(node_id)
+            } else {
+                node.get_attribute(attribute_id, 0.0)
+            };
             if let Some(mut data_value) = data_value {
                 // Test for data change
                 let data_change = if resend_data {
@@ -206,7 +420,13 @@ impl MonitoredItem {
                     // if the value is considered to have changed, otherwise it is a straight
                     // equality test.
                     if let FilterType::DataChangeFilter(ref filter) = self.filter {
-                        !filter.compare(&data_value, last_data_value, None)
+                        // Percent deadbands need the range the value is expected to vary over
+                        let eu_range = if filter.deadband_type == DEADBAND_TYPE_PERCENT {
+                            address_space.eu_range(&self.item_to_monitor.node_id)
+                        } else {
+                            None
+                        };
+                        !filter.compare(&data_value, last_data_value, eu_range)
                     } else {
                         data_value.value != last_data_value.value
                     }
@@ -263,8 +483,58 @@ impl MonitoredItem {
         }
     }
 
+    /// Collects the events raised on the address space since the last call which come from the
+    /// node being monitored and queues them as notifications. Items monitoring the Server object
+    /// receive every event.
+    ///
+    /// The function will return true if any events were queued.
+    pub fn check_events(&mut self, address_space: &AddressSpace) -> bool {
+        let last_event_number = address_space.last_event_number();
+        let events_since = self.last_event_number.replace(last_event_number);
+        match events_since {
+            // The first tick only marks the point from which events are collected
+            None => false,
+            Some(events_since) if events_since == last_event_number => false,
+            Some(events_since) => self.enqueue_events(address_space, address_space.events_since(events_since).iter()),
+        }
+    }
+
+    /// Queues notifications for the events which come from the node being monitored and satisfy
+    /// the where clause of the filter. Items monitoring the Server object receive every event.
+    ///
+    /// The function will return true if any events were queued.
+    pub fn enqueue_events<'a, I>(&mut self, address_space: &AddressSpace, events: I) -> bool where I: Iterator<Item=&'a Event> {
+        let event_field_lists = if let FilterType::EventFilter(ref filter) = self.filter {
+            let node_id = &self.item_to_monitor.node_id;
+            let is_server = *node_id == ObjectId::Server.into();
+            let select_clauses = filter.select_clauses.as_ref().map(|v| v.as_slice()).unwrap_or(&[]);
+            // The where clause was validated when the item was created
+            let where_clause = ContentFilterEvaluator::new(&filter.where_clause).ok();
+            events
+                .filter(|event| is_server || event.source_node == *node_id)
+                .filter(|event| where_clause.as_ref().map_or(false, |where_clause| {
+                    where_clause.evaluate(&EventTarget { address_space, event })
+                }))
+                .map(|event| event.event_field_list(self.client_handle, select_clauses))
+                .collect::<Vec<EventFieldList>>()
+        } else {
+            Vec::new()
+        };
+        let events_queued = !event_field_lists.is_empty();
+        event_field_lists.into_iter().for_each(|event_field_list| {
+            self.enqueue_notification(event_field_list);
+        });
+        events_queued
+    }
+
     /// Enqueues a notification message for the monitored item
-    pub fn enqueue_notification_message(&mut self, mut notification: MonitoredItemNotification) {
+    pub fn enqueue_notification_message(&mut self, notification: MonitoredItemNotification) {
+        self.enqueue_notification(notification);
+    }
+
+    /// Enqueues a data change or event notification for the monitored item
+    fn enqueue_notification<T>(&mut self, notification: T) where T: Into<Notification> {
+        let notification = notification.into();
         // test for overflow
         let overflow = if self.notification_queue.len() == self.queue_size {
             trace!("Data change overflow, node {:?}", self.item_to_monitor.node_id);
@@ -281,14 +551,26 @@ impl MonitoredItem {
         } else {
             false
         };
+        self.notification_queue.push_back(notification);
         if overflow {
-            // Set the overflow bit on the data value's status
-            let mut status_code = notification.value.status();
-            status_code = status_code | StatusCode::OVERFLOW.bits();
+            self.set_overflow_bit();
+        }
+    }
+
+    /// Sets the overflow bit on the status of a data change after notifications were discarded.
+    /// When discarding the oldest, the bit goes on the oldest remaining value, otherwise on the
+    /// newest value, i.e. the one which replaced the discarded ones.
+    fn set_overflow_bit(&mut self) {
+        let notification = if self.discard_oldest {
+            self.notification_queue.front_mut()
+        } else {
+            self.notification_queue.back_mut()
+        };
+        if let Some(Notification::MonitoredItemNotification(ref mut notification)) = notification {
+            let status_code = notification.value.status() | StatusCode::OVERFLOW.bits();
             notification.value.status = Some(status_code);
-            self.queue_overflow = true;
         }
-        self.notification_queue.push_back(notification);
+        self.queue_overflow = true;
     }
 
     /// Gets the oldest notification message from the notification queue
@@ -298,12 +580,15 @@ impl MonitoredItem {
             None
         } else {
             self.queue_overflow = false;
-            self.notification_queue.pop_front()
+            match self.notification_queue.pop_front() {
+                Some(Notification::MonitoredItemNotification(notification)) => Some(notification),
+                _ => None
+            }
         }
     }
 
     /// Retrieves all the notification messages from the queue, oldest to newest
-    pub fn all_notifications(&mut self) -> Option<Vec<MonitoredItemNotification>> {
+    pub fn all_notifications(&mut self) -> Option<Vec<Notification>> {
         if self.notification_queue.is_empty() {
             None
         } else {
@@ -328,18 +613,24 @@ impl MonitoredItem {
     }
 
     /// Takes the requested queue size and ensures it is within the range supported by the server
-    fn sanitize_queue_size(requested_queue_size: usize) -> usize {
-        if requested_queue_size == 0 {
+    fn sanitize_queue_size(filter: &FilterType, requested_queue_size: usize) -> usize {
+        if filter.is_event_filter() {
+            if requested_queue_size == 0 {
+                // For event monitored items 0 -> the default queue size for event notifications
+                constants::DEFAULT_EVENT_QUEUE_SIZE
+            } else if requested_queue_size > constants::MAX_EVENT_QUEUE_SIZE {
+                // MaxUInt32 and anything else too large returns the maximum queue size
+                constants::MAX_EVENT_QUEUE_SIZE
+            } else {
+                requested_queue_size
+            }
+        } else if requested_queue_size == 0 {
             // For data monitored items 0 -> 1
             1
-            // Future - for event monitored items, queue size should be the default queue size for event notifications
         } else if requested_queue_size == 1 {
             1
-            // Future - for event monitored items, the minimum queue size the server requires for event notifications
         } else if requested_queue_size > constants::MAX_DATA_CHANGE_QUEUE_SIZE {
             constants::MAX_DATA_CHANGE_QUEUE_SIZE
-            // Future - for event monitored items MaxUInt32 returns the maximum queue size the server support
-            // for event notifications
         } else {
             requested_queue_size
         }
@@ -349,6 +640,10 @@ impl MonitoredItem {
         self.monitored_item_id
     }
 
+    pub fn item_to_monitor(&self) -> &ReadValueId {
+        &self.item_to_monitor
+    }
+
     pub fn client_handle(&self) -> u32 {
         self.client_handle
     }
@@ -362,6 +657,12 @@ impl MonitoredItem {
     }
 
     pub fn set_monitoring_mode(&mut self, monitoring_mode: MonitoringMode) {
+        if monitoring_mode == MonitoringMode::Disabled {
+            // A disabled item discards its queue and samples afresh when it is enabled again
+            self.notification_queue.clear();
+            self.queue_overflow = false;
+            self.last_data_value = None;
+        }
         self.monitoring_mode = monitoring_mode;
     }
 
@@ -369,17 +670,27 @@ impl MonitoredItem {
         self.monitoring_mode
     }
 
+    /// Tests if the item monitors events rather than sampling a value
+    pub fn is_event_item(&self) -> bool {
+        self.filter.is_event_filter()
+    }
+
     pub fn queue_size(&self) -> usize {
         self.queue_size
     }
 
+    /// Returns the filter result that is sent back to the client when the item is created or modified
+    pub fn filter_result(&self) -> ExtensionObject {
+        self.filter.filter_result()
+    }
+
     #[cfg(test)]
     pub fn queue_overflow(&self) -> bool {
         self.queue_overflow
     }
 
     #[cfg(test)]
-    pub fn notification_queue(&self) -> &VecDeque<MonitoredItemNotification> {
+    pub fn notification_queue(&self) -> &VecDeque<Notification> {
         &self.notification_queue
     }
 
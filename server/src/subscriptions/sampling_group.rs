@@ -0,0 +1,164 @@
+//! Sampling groups coalesce the sampling of the monitored items in a subscription which share a
+//! revised sampling interval. Each group decides once per tick whether its items are due, and the
+//! values of all the due items are read in one pass over the address space, rather than every
+//! item testing its own interval and reading its own node.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use opcua_types::*;
+
+use crate::{
+    address_space::AddressSpace,
+    subscriptions::monitored_item::MonitoredItem,
+};
+
+/// Identifies the group of a sampling interval. The interval is held in microseconds so that
+/// fractional millisecond intervals are told apart, and negative intervals, i.e. the publishing
+/// interval of the subscription, all share one group.
+type SamplingGroupKey = i64;
+
+fn sampling_group_key(sampling_interval: Duration) -> SamplingGroupKey {
+    if sampling_interval < 0f64 {
+        -1
+    } else {
+        (sampling_interval * 1000f64) as SamplingGroupKey
+    }
+}
+
+/// The monitored items which are sampled at the same interval
+#[derive(Debug, Clone)]
+struct SamplingGroup {
+    sampling_interval: Duration,
+    /// The time that the items of the group were last sampled, `None` until the first sample
+    last_sample_time: Option<DateTimeUtc>,
+    monitored_item_ids: BTreeSet<u32>,
+}
+
+impl SamplingGroup {
+    fn new(sampling_interval: Duration) -> SamplingGroup {
+        SamplingGroup {
+            sampling_interval,
+            last_sample_time: None,
+            monitored_item_ids: BTreeSet::new(),
+        }
+    }
+
+    /// Tests if the items of the group are due to be sampled on this tick
+    fn is_sampling_due(&self, now: &DateTimeUtc, publishing_interval_elapsed: bool) -> bool {
+        if self.sampling_interval < 0f64 {
+            // The items are sampled at the publishing interval
+            publishing_interval_elapsed
+        } else if let Some(ref last_sample_time) = self.last_sample_time {
+            // 0 means the fastest practical rate, i.e. every tick
+            self.sampling_interval == 0f64 ||
+                now.signed_duration_since(*last_sample_time) >= super::duration_from_ms(self.sampling_interval)
+        } else {
+            true
+        }
+    }
+}
+
+/// The sampling groups of the data change items of a subscription. Event items are not sampled
+/// so they belong to no group.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SamplingGroups {
+    groups: BTreeMap<SamplingGroupKey, SamplingGroup>,
+}
+
+impl SamplingGroups {
+    pub fn new() -> SamplingGroups {
+        SamplingGroups::default()
+    }
+
+    /// Puts the item into the group of its sampling interval, moving it from the group of its
+    /// previous interval if the interval has been modified
+    pub fn insert(&mut self, monitored_item: &MonitoredItem) {
+        let monitored_item_id = monitored_item.monitored_item_id();
+        self.remove(monitored_item_id);
+        if !monitored_item.is_event_item() {
+            let sampling_interval = monitored_item.sampling_interval();
+            self.groups.entry(sampling_group_key(sampling_interval))
+                .or_insert_with(|| SamplingGroup::new(sampling_interval))
+                .monitored_item_ids.insert(monitored_item_id);
+        }
+    }
+
+    /// Removes the item from its group. Groups without items are removed.
+    pub fn remove(&mut self, monitored_item_id: u32) {
+        self.groups.retain(|_, group| {
+            group.monitored_item_ids.remove(&monitored_item_id);
+            !group.monitored_item_ids.is_empty()
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.groups.clear();
+    }
+
+    /// Returns the number of sampling groups
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Returns the ids of the items in the groups which are due to be sampled on this tick, or of
+    /// every item if data is to be resent, and records that those groups have been sampled.
+    pub fn take_due_items(&mut self, now: &DateTimeUtc, publishing_interval_elapsed: bool, resend_data: bool) -> BTreeSet<u32> {
+        let mut due_items = BTreeSet::new();
+        self.groups.values_mut()
+            .filter(|group| resend_data || group.is_sampling_due(now, publishing_interval_elapsed))
+            .for_each(|group| {
+                group.last_sample_time = Some(*now);
+                due_items.extend(group.monitored_item_ids.iter().cloned());
+            });
+        due_items
+    }
+}
+
+/// Reads the values of the due items in one pass over the address space and gives each item its
+/// value for the sample. Items monitoring the same attribute of the same node share one read, and
+/// the nodes with value providers are read in one batch per provider.
+pub(crate) fn sample_values(address_space: &AddressSpace, monitored_items: &mut HashMap<u32, MonitoredItem>, due_items: &BTreeSet<u32>) {
+    // The items monitoring each attribute of each node
+    let mut reads: HashMap<(NodeId, u32), Vec<u32>> = HashMap::new();
+    due_items.iter()
+        .filter_map(|monitored_item_id| monitored_items.get(monitored_item_id))
+        .filter(|monitored_item| monitored_item.monitoring_mode() != MonitoringMode::Disabled && !monitored_item.is_event_item())
+        .for_each(|monitored_item| {
+            let item_to_monitor = monitored_item.item_to_monitor();
+            reads.entry((item_to_monitor.node_id.clone(), item_to_monitor.attribute_id))
+                .or_insert_with(Vec::new)
+                .push(monitored_item.monitored_item_id());
+        });
+    if reads.is_empty() {
+        return;
+    }
+
+    let provided_node_ids = reads.keys()
+        .filter(|(node_id, attribute_id)| *attribute_id == AttributeId::Value as u32 && address_space.has_value_provider(node_id))
+        .map(|(node_id, _)| node_id.clone())
+        .collect::<Vec<NodeId>>();
+    let mut provided_values = if provided_node_ids.is_empty() {
+        HashMap::new()
+    } else {
+        address_space.read_provided_values(&provided_node_ids, 0.0)
+    };
+
+    for ((node_id, attribute_id), monitored_item_ids) in reads {
+        let data_value = if attribute_id == AttributeId::Value as u32 && provided_values.contains_key(&node_id) {
+            provided_values.remove(&node_id)
+        } else if let Ok(attribute_id) = AttributeId::from_u32(attribute_id) {
+            address_space.find_node(&node_id).and_then(|node| node.as_node().get_attribute(attribute_id, 0.0))
+        } else {
+            None
+        };
+        // Items without a value look for it themselves, which finds the node or attribute is missing
+        if let Some(data_value) = data_value {
+            monitored_item_ids.iter().for_each(|monitored_item_id| {
+                if let Some(monitored_item) = monitored_items.get_mut(monitored_item_id) {
+                    monitored_item.set_sampled_value(data_value.clone());
+                }
+            });
+        }
+    }
+}
@@ -15,9 +15,13 @@ use opcua_core::handle::Handle;
 
 use crate::{
     constants,
-    subscriptions::monitored_item::{MonitoredItem, TickResult},
+    subscriptions::{
+        monitored_item::{MonitoredItem, Notification, TickResult},
+        sampling_group::{self, SamplingGroups},
+    },
     address_space::AddressSpace,
     diagnostics::ServerDiagnostics,
+    events::event::Event,
 };
 
 /// The state of the subscription
@@ -30,7 +34,7 @@ pub(crate) enum SubscriptionState {
     KeepAlive,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub(crate) struct SubscriptionStateParams {
     pub notifications_available: bool,
     pub more_notifications: bool,
@@ -114,6 +118,9 @@ pub struct Subscription {
     priority: u8,
     /// Map of monitored items
     monitored_items: HashMap<u32, MonitoredItem>,
+    /// The monitored items grouped by their sampling interval
+    #[serde(skip)]
+    sampling_groups: SamplingGroups,
     /// State of the subscription
     state: SubscriptionState,
     /// A value that contains the number of consecutive publishing timer expirations without Client
@@ -169,6 +176,7 @@ impl Subscription {
             publishing_interval,
             priority,
             monitored_items: HashMap::with_capacity(constants::DEFAULT_MONITORED_ITEM_CAPACITY),
+            sampling_groups: SamplingGroups::new(),
             max_lifetime_counter: lifetime_counter,
             max_keep_alive_counter: keep_alive_counter,
             // State variables
@@ -199,18 +207,27 @@ impl Subscription {
     }
 
     /// Creates monitored items on the specified subscription, returning the creation results
-    pub fn create_monitored_items(&mut self, now: &DateTimeUtc, timestamps_to_return: TimestampsToReturn, items_to_create: &[MonitoredItemCreateRequest]) -> Vec<MonitoredItemCreateResult> {
+    pub fn create_monitored_items(&mut self, now: &DateTimeUtc, address_space: &AddressSpace, timestamps_to_return: TimestampsToReturn, items_to_create: &[MonitoredItemCreateRequest], max_monitored_items: usize, min_sampling_interval: f64) -> Vec<MonitoredItemCreateResult> {
         self.reset_lifetime_counter();
 
         // Add items to the subscription if they're not already in its
         items_to_create.iter().map(|item_to_create| {
             // Create a monitored item, if possible
             let monitored_item_id = self.next_monitored_item_id;
-            match MonitoredItem::new(now, monitored_item_id, timestamps_to_return, item_to_create) {
-                Ok(monitored_item) => {
+            let monitored_item = if max_monitored_items > 0 && self.monitored_items.len() >= max_monitored_items {
+                Err(StatusCode::BadTooManyMonitoredItems)
+            } else {
+                MonitoredItem::new(now, monitored_item_id, timestamps_to_return, item_to_create)
+                    .and_then(|monitored_item| monitored_item.validate_node(address_space).map(|_| monitored_item))
+            };
+            match monitored_item {
+                Ok(mut monitored_item) => {
+                    monitored_item.revise_sampling_interval(address_space, min_sampling_interval);
                     // Register the item with the subscription
                     let revised_sampling_interval = monitored_item.sampling_interval();
                     let revised_queue_size = monitored_item.queue_size() as u32;
+                    let filter_result = monitored_item.filter_result();
+                    self.sampling_groups.insert(&monitored_item);
                     self.monitored_items.insert(monitored_item_id, monitored_item);
                     self.next_monitored_item_id += 1;
                     MonitoredItemCreateResult {
@@ -218,7 +235,7 @@ impl Subscription {
                         monitored_item_id,
                         revised_sampling_interval,
                         revised_queue_size,
-                        filter_result: ExtensionObject::null(),
+                        filter_result,
                     }
                 }
                 Err(status_code) => {
@@ -235,13 +252,17 @@ impl Subscription {
     }
 
     /// Modify the specified monitored items, returning a result for each
-    pub fn modify_monitored_items(&mut self, timestamps_to_return: TimestampsToReturn, items_to_modify: &[MonitoredItemModifyRequest]) -> Vec<MonitoredItemModifyResult> {
+    pub fn modify_monitored_items(&mut self, address_space: &AddressSpace, timestamps_to_return: TimestampsToReturn, items_to_modify: &[MonitoredItemModifyRequest], min_sampling_interval: f64) -> Vec<MonitoredItemModifyResult> {
         self.reset_lifetime_counter();
         items_to_modify.iter().map(|item_to_modify| {
             match self.monitored_items.get_mut(&item_to_modify.monitored_item_id) {
                 Some(monitored_item) => {
                     // Try to change the monitored item according to the modify request
-                    let modify_result = monitored_item.modify(timestamps_to_return, item_to_modify);
+                    let modify_result = monitored_item.modify(address_space, timestamps_to_return, item_to_modify);
+                    if modify_result.is_ok() {
+                        monitored_item.revise_sampling_interval(address_space, min_sampling_interval);
+                        self.sampling_groups.insert(monitored_item);
+                    }
                     match modify_result {
                         Ok(filter_result) => MonitoredItemModifyResult {
                             status_code: StatusCode::Good,
@@ -281,12 +302,25 @@ impl Subscription {
     /// Delete the specified monitored items (by item id), returning a status code for each
     pub fn delete_monitored_items(&mut self, items_to_delete: &[u32]) -> Vec<StatusCode> {
         self.reset_lifetime_counter();
-        items_to_delete.iter().map(|item_to_delete| {
+        let results = items_to_delete.iter().map(|item_to_delete| {
             match self.monitored_items.remove(item_to_delete) {
-                Some(_) => StatusCode::Good,
+                Some(_) => {
+                    self.sampling_groups.remove(*item_to_delete);
+                    StatusCode::Good
+                }
                 None => StatusCode::BadMonitoredItemIdInvalid
             }
-        }).collect()
+        }).collect();
+        // Links from other items to the deleted items are deleted with them
+        self.monitored_items.values_mut().for_each(|monitored_item| {
+            monitored_item.set_triggering(&[], items_to_delete);
+        });
+        results
+    }
+
+    /// Returns the number of monitored items in the subscription
+    pub fn monitored_items_len(&self) -> usize {
+        self.monitored_items.len()
     }
 
     // Returns two vecs representing the server and client handles for each monitored item.
@@ -303,6 +337,16 @@ impl Subscription {
         self.resend_data = true;
     }
 
+    /// Queues the events on every enabled event monitored item of the subscription. This is
+    /// used by ConditionRefresh to send the state of retained conditions.
+    pub fn refresh_events(&mut self, address_space: &AddressSpace, events: &[Event]) {
+        self.monitored_items.values_mut()
+            .filter(|monitored_item| monitored_item.monitoring_mode() != MonitoringMode::Disabled)
+            .for_each(|monitored_item| {
+                let _ = monitored_item.enqueue_events(address_space, events.iter());
+            });
+    }
+
     /// Tests if the publishing interval has elapsed since the last time this function in which case
     /// it returns `true` and updates its internal state.
     fn test_and_set_publishing_timer_expired(&mut self, now: &DateTimeUtc) -> bool {
@@ -373,7 +417,10 @@ impl Subscription {
             panic!("Notification's sequence number is not sequential, expecting {}, got {}", expected_sequence_number, notification.sequence_number);
         }
         // debug!("Enqueuing notification {:?}", notification);
-        self.last_sequence_number = notification.sequence_number;
+        // A keep alive does not use up its sequence number
+        if notification.notification_data.is_some() {
+            self.last_sequence_number = notification.sequence_number;
+        }
         self.notifications.push_back(notification);
     }
 
@@ -398,7 +445,11 @@ impl Subscription {
                 }
                 // Send a keep alive
                 debug!("Sending keep alive response");
-                let notification = NotificationMessage::keep_alive(self.sequence_number.next(), DateTime::from(now.clone()));
+                // The keep alive carries the sequence number of the next notification so the client
+                // can tell if it missed any, but the number is not consumed.
+                let sequence_number = self.sequence_number.next();
+                self.sequence_number.set_next(sequence_number);
+                let notification = NotificationMessage::keep_alive(sequence_number, DateTime::from(now.clone()));
                 self.enqueue_notification(notification);
             }
             UpdateStateAction::ReturnNotifications => {
@@ -422,12 +473,21 @@ impl Subscription {
                 // Delete the monitored items, issue a status change for the subscription
                 debug!("Subscription status change to closed / timeout");
                 self.monitored_items.clear();
+                self.sampling_groups.clear();
                 let notification = NotificationMessage::status_change(self.sequence_number.next(), DateTime::from(now.clone()), StatusCode::BadTimeout);
                 self.enqueue_notification(notification);
             }
         }
     }
 
+    /// Makes a status change notification with the next sequence number, e.g. to tell the client
+    /// that the server is shutting down.
+    pub(crate) fn status_change_notification(&mut self, now: &DateTimeUtc, status: StatusCode) -> NotificationMessage {
+        let sequence_number = self.sequence_number.next();
+        self.last_sequence_number = sequence_number;
+        NotificationMessage::status_change(sequence_number, DateTime::from(now.clone()), status)
+    }
+
     pub(crate) fn take_notification(&mut self) -> Option<NotificationMessage> {
         self.notifications.pop_front()
     }
@@ -571,11 +631,14 @@ impl Subscription {
                     return UpdateStateResult::new(HandledState::KeepAlive13, UpdateStateAction::None);
                 } else if p.publishing_timer_expired && self.publishing_enabled && p.notifications_available && p.publishing_req_queued {
                     // State #14
+                    self.reset_lifetime_counter();
+                    self.start_publishing_timer();
                     self.first_message_sent = true;
                     self.state = SubscriptionState::Normal;
                     return UpdateStateResult::new(HandledState::KeepAlive14, UpdateStateAction::ReturnNotifications);
-                } else if p.publishing_timer_expired && p.publishing_req_queued && self.keep_alive_counter == 1 && (!self.publishing_enabled || (self.publishing_enabled && p.notifications_available)) {
+                } else if p.publishing_timer_expired && p.publishing_req_queued && self.keep_alive_counter == 1 && (!self.publishing_enabled || (self.publishing_enabled && !p.notifications_available)) {
                     // State #15
+                    self.reset_lifetime_counter();
                     self.start_publishing_timer();
                     self.reset_keep_alive_counter();
                     return UpdateStateResult::new(HandledState::KeepAlive15, UpdateStateAction::ReturnKeepAlive);
@@ -610,10 +673,15 @@ impl Subscription {
         let mut triggered_items: BTreeSet<u32> = BTreeSet::new();
         let mut monitored_item_notifications = Vec::with_capacity(self.monitored_items.len() * 2);
 
-        for (_, monitored_item) in &mut self.monitored_items {
+        // Sample the items of the sampling groups which are due in one pass
+        let due_items = self.sampling_groups.take_due_items(now, publishing_interval_elapsed, resend_data);
+        sampling_group::sample_values(address_space, &mut self.monitored_items, &due_items);
+
+        for (monitored_item_id, monitored_item) in &mut self.monitored_items {
             // If this returns true then the monitored item wants to report its notification
             let monitoring_mode = monitored_item.monitoring_mode();
-            match monitored_item.tick(now, address_space, publishing_interval_elapsed, resend_data) {
+            let sampling_due = due_items.contains(monitored_item_id);
+            match monitored_item.tick_sampling(now, address_space, sampling_due, resend_data) {
                 TickResult::ReportValueChanged => {
                     if publishing_interval_elapsed {
                         // If this monitored item has triggered items, then they need to be handled
@@ -695,11 +763,19 @@ impl Subscription {
         });
 
 
-        // Produce a data change notification
+        // Produce a data change and / or event notification
         if !monitored_item_notifications.is_empty() {
             let next_sequence_number = self.sequence_number.next();
             debug!("Create notification for subscription {}, sequence number {}", self.subscription_id, next_sequence_number);
-            let notification = NotificationMessage::data_change(next_sequence_number, DateTime::from(now.clone()), monitored_item_notifications);
+            let mut data_change_notifications = Vec::with_capacity(monitored_item_notifications.len());
+            let mut event_notifications = Vec::new();
+            monitored_item_notifications.into_iter().for_each(|notification| {
+                match notification {
+                    Notification::MonitoredItemNotification(n) => data_change_notifications.push(n),
+                    Notification::EventFieldList(n) => event_notifications.push(n),
+                }
+            });
+            let notification = NotificationMessage::data_change_and_events(next_sequence_number, DateTime::from(now.clone()), data_change_notifications, event_notifications);
             (Some(notification), false)
         } else {
             (None, false)
@@ -721,7 +797,7 @@ impl Subscription {
 
     /// Start or restart the publishing timer and decrement the LifetimeCounter Variable.
     pub fn start_publishing_timer(&mut self) {
-        self.lifetime_counter -= 1;
+        self.lifetime_counter = self.lifetime_counter.saturating_sub(1);
         debug!("Decrementing life time counter {}", self.lifetime_counter);
     }
 
@@ -748,6 +824,10 @@ impl Subscription {
     }
 
     #[cfg(test)]
+    pub(crate) fn monitored_item_mut(&mut self, monitored_item_id: u32) -> Option<&mut MonitoredItem> {
+        self.monitored_items.get_mut(&monitored_item_id)
+    }
+
     pub(crate) fn state(&self) -> SubscriptionState {
         self.state
     }
@@ -799,6 +879,10 @@ impl Subscription {
         self.priority = priority;
     }
 
+    pub fn publishing_enabled(&self) -> bool {
+        self.publishing_enabled
+    }
+
     pub(crate) fn set_publishing_enabled(&mut self, publishing_enabled: bool) {
         self.publishing_enabled = publishing_enabled;
         self.reset_lifetime_counter();
@@ -821,16 +905,20 @@ impl Subscription {
     }
 
     /// Sets the triggering monitored items on a subscription. This function will validate that
-    /// the items to add / remove actually exist and will only pass through existing monitored items
-    /// onto the monitored item itself.
+    /// the items to add actually exist and the links to remove are actually linked, and will only
+    /// pass through valid items onto the monitored item itself.
     pub(crate) fn set_triggering(&mut self, monitored_item_id: u32, items_to_add: &[u32], items_to_remove: &[u32]) -> Result<(Vec<StatusCode>, Vec<StatusCode>), StatusCode> {
         // Find monitored items that do or do not exist
         let (add_results, items_to_add) = self.validate_triggered_items(monitored_item_id, items_to_add);
-        let (remove_results, items_to_remove) = self.validate_triggered_items(monitored_item_id, items_to_remove);
 
         if let Some(ref mut monitored_item) = self.monitored_items.get_mut(&monitored_item_id) {
-            // Set the triggering monitored items
-            monitored_item.set_triggering(items_to_add.as_slice(), items_to_remove.as_slice());
+            // A link can only be removed if the item is linked to it
+            let remove_results = items_to_remove.iter().map(|i| {
+                if monitored_item.triggered_items().contains(i) { StatusCode::Good } else { StatusCode::BadMonitoredItemIdInvalid }
+            }).collect();
+
+            // Set the triggering monitored items. Links are removed before they are added.
+            monitored_item.set_triggering(items_to_add.as_slice(), items_to_remove);
 
             Ok((add_results, remove_results))
         } else {
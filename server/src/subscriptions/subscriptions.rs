@@ -1,3 +1,4 @@
+use std::cmp;
 use std::collections::{BTreeMap, VecDeque};
 
 use time;
@@ -10,6 +11,7 @@ use opcua_types::{
 
 use crate::{
     address_space::types::AddressSpace,
+    constants::MAX_RETRANSMISSION_QUEUE_SIZE,
     subscriptions::{
         PublishRequestEntry, PublishResponseEntry,
         subscription::{Subscription, TickReason},
@@ -39,6 +41,16 @@ pub(crate) struct Subscriptions {
     // Notifications that have been sent but have yet to be acknowledged (retransmission queue).
     // Key is (subscription_id, sequence_number). Value is notification message.
     retransmission_queue: BTreeMap<(u32, u32), NotificationMessage>,
+    /// Maximum number of monitored items in each subscription, 0 means no limit
+    max_monitored_items_per_subscription: usize,
+    /// Minimum sampling interval in millis of monitored items
+    min_sampling_interval: f64,
+    /// Maximum number of publish requests that can be queued, 0 means no limit other than two per
+    /// subscription
+    max_publish_requests: usize,
+    /// The subscription which was last given a publish request. Subscriptions of the same priority
+    /// take turns starting after it.
+    last_serviced_subscription_id: u32,
 }
 
 #[derive(Serialize)]
@@ -51,15 +63,20 @@ pub struct Metrics {
 }
 
 impl Subscriptions {
-    pub fn new(max_subscriptions: usize, publish_request_timeout: i64) -> Subscriptions {
-        let max_publish_requests = if max_subscriptions > 0 { 2 * max_subscriptions } else { 100 };
+    pub fn new(max_subscriptions: usize, max_monitored_items_per_subscription: usize, min_sampling_interval: f64, max_publish_requests: usize, publish_request_timeout: i64) -> Subscriptions {
+        let queue_capacity = if max_subscriptions > 0 { 2 * max_subscriptions } else { 100 };
+        let queue_capacity = if max_publish_requests > 0 && max_publish_requests < queue_capacity { max_publish_requests } else { queue_capacity };
         Subscriptions {
-            publish_request_queue: VecDeque::with_capacity(max_publish_requests),
-            publish_response_queue: VecDeque::with_capacity(max_publish_requests),
+            publish_request_queue: VecDeque::with_capacity(queue_capacity),
+            publish_response_queue: VecDeque::with_capacity(queue_capacity),
             publish_request_timeout,
             subscriptions: BTreeMap::new(),
-            transmission_queue: VecDeque::with_capacity(max_publish_requests),
+            transmission_queue: VecDeque::with_capacity(queue_capacity),
             retransmission_queue: BTreeMap::new(),
+            max_monitored_items_per_subscription,
+            min_sampling_interval,
+            max_publish_requests,
+            last_serviced_subscription_id: 0,
         }
     }
 
@@ -109,8 +126,23 @@ impl Subscriptions {
 
     /// Returns the number of maxmimum publish requests allowable for the current number of subscriptions
     pub fn max_publish_requests(&self) -> usize {
-        // Allow for two requests per subscription
-        self.subscriptions.len() * 2
+        // Allow for two requests per subscription, up to the configured limit
+        let max_publish_requests = self.subscriptions.len() * 2;
+        if self.max_publish_requests > 0 && max_publish_requests > self.max_publish_requests {
+            self.max_publish_requests
+        } else {
+            max_publish_requests
+        }
+    }
+
+    /// Returns the maximum number of monitored items in each subscription, 0 means no limit
+    pub fn max_monitored_items_per_subscription(&self) -> usize {
+        self.max_monitored_items_per_subscription
+    }
+
+    /// Returns the minimum sampling interval in millis of monitored items
+    pub fn min_sampling_interval(&self) -> f64 {
+        self.min_sampling_interval
     }
 
     /// Places a new publish request onto the queue of publish requests.
@@ -162,6 +194,16 @@ impl Subscriptions {
     }
 
     /// Tests if the subscriptions contain the supplied subscription id.
+    /// Returns the number of monitored items across all of the subscriptions
+    pub fn monitored_items_len(&self) -> usize {
+        self.subscriptions.values().map(|s| s.monitored_items_len()).sum()
+    }
+
+    /// Returns the number of publish requests waiting for notifications
+    pub fn publish_request_queue_len(&self) -> usize {
+        self.publish_request_queue.len()
+    }
+
     pub fn contains(&self, subscription_id: u32) -> bool {
         self.subscriptions.contains_key(&subscription_id)
     }
@@ -178,17 +220,50 @@ impl Subscriptions {
         self.subscriptions.get_mut(&subscription_id)
     }
 
+    /// Removes every subscription, e.g. because the session has ended, and returns each with the
+    /// notifications that the client has not acknowledged. Notifications that are queued for
+    /// transmission are returned too since the client will never receive them. Pending publish
+    /// requests are discarded.
+    pub(crate) fn take_all(&mut self) -> Vec<(Subscription, Vec<NotificationMessage>)> {
+        self.publish_request_queue.clear();
+        while let Some((subscription_id, _, notification_message)) = self.transmission_queue.pop_back() {
+            self.retransmission_queue.insert((subscription_id, notification_message.sequence_number), notification_message);
+        }
+        let subscriptions = std::mem::replace(&mut self.subscriptions, BTreeMap::new());
+        let mut retransmission_queue = std::mem::replace(&mut self.retransmission_queue, BTreeMap::new());
+        subscriptions.into_iter().map(|(subscription_id, subscription)| {
+            let keys = retransmission_queue.keys()
+                .filter(|k| k.0 == subscription_id)
+                .cloned()
+                .collect::<Vec<(u32, u32)>>();
+            let notifications = keys.iter()
+                .filter_map(|k| retransmission_queue.remove(k))
+                .collect();
+            (subscription, notifications)
+        }).collect()
+    }
+
+    /// Adds a subscription transferred from another session along with its unacknowledged
+    /// notifications, which the client may republish. Returns the sequence numbers available for
+    /// republishing.
+    pub(crate) fn insert_transferred(&mut self, subscription: Subscription, notifications: Vec<NotificationMessage>) -> Option<Vec<u32>> {
+        let subscription_id = subscription.subscription_id();
+        self.subscriptions.insert(subscription_id, subscription);
+        notifications.into_iter().for_each(|n| {
+            self.retransmission_queue.insert((subscription_id, n.sequence_number), n);
+        });
+        self.available_sequence_numbers(subscription_id)
+    }
+
     /// The tick causes the subscription manager to iterate through individual subscriptions calling tick
     /// on each in order of priority. In each case this could generate data change notifications. Data change
     /// notifications will be attached to the next available publish response and queued for sending
     /// to the client.
+    ///
+    /// When there are fewer publish requests than subscriptions with notifications, those with the
+    /// highest priority get the requests first. Subscriptions of equal priority take turns.
     pub(crate) fn tick(&mut self, now: &DateTimeUtc, address_space: &AddressSpace, tick_reason: TickReason) -> Result<(), StatusCode> {
-        let subscription_ids = {
-            // Sort subscriptions by priority
-            let mut subscription_priority: Vec<(u32, u8)> = self.subscriptions.values().map(|v| (v.subscription_id(), v.priority())).collect();
-            subscription_priority.sort_by(|s1, s2| s1.1.cmp(&s2.1));
-            subscription_priority.iter().map(|s| s.0).collect::<Vec<u32>>()
-        };
+        let subscription_ids = self.subscriptions_in_service_order();
 
         // Iterate through all subscriptions. If there is a publish request it will be used to
         // acknowledge notifications and the response to return new notifications.
@@ -208,6 +283,7 @@ impl Subscriptions {
                 if !self.publish_request_queue.is_empty() {
                     if let Some(notification_message) = subscription.take_notification() {
                         let publish_request = self.publish_request_queue.pop_back().unwrap();
+                        self.last_serviced_subscription_id = subscription_id;
                         // Consume the publish request and queue the notification onto the transmission queue
                         self.transmission_queue.push_front((subscription_id, publish_request, notification_message));
                     } else {
@@ -236,8 +312,11 @@ impl Subscriptions {
             // Get a list of available sequence numbers
             let available_sequence_numbers = self.available_sequence_numbers(subscription_id);
 
-            // The notification to be sent is now put into the retransmission queue
-            self.retransmission_queue.insert((subscription_id, notification_message.sequence_number), notification_message.clone());
+            // The notification to be sent is now put into the retransmission queue. Keep alives
+            // contain no notifications so are never republished.
+            if notification_message.notification_data.is_some() {
+                self.retransmission_queue.insert((subscription_id, notification_message.sequence_number), notification_message.clone());
+            }
 
             // Enqueue a publish response
             let response = self.make_publish_response(publish_request, subscription_id, now, notification_message, more_notifications, available_sequence_numbers);
@@ -250,6 +329,36 @@ impl Subscriptions {
         Ok(())
     }
 
+    /// Returns the ids of the subscriptions from the highest priority to the lowest. Subscriptions
+    /// of equal priority are in round robin order, starting after the one last given a publish
+    /// request.
+    fn subscriptions_in_service_order(&self) -> Vec<u32> {
+        let last_serviced_subscription_id = self.last_serviced_subscription_id;
+        let mut subscription_priority = self.subscriptions.values()
+            .map(|s| (s.subscription_id(), s.priority()))
+            .collect::<Vec<(u32, u8)>>();
+        subscription_priority.sort_by_key(|&(subscription_id, priority)| {
+            (cmp::Reverse(priority), subscription_id <= last_serviced_subscription_id, subscription_id)
+        });
+        subscription_priority.into_iter().map(|(subscription_id, _)| subscription_id).collect()
+    }
+
+    /// Answers a queued publish request for each subscription with a status change notification
+    /// and then removes all the subscriptions, e.g. because the server is shutting down.
+    /// Subscriptions for which there is no queued publish request are removed without one.
+    pub(crate) fn close_all(&mut self, now: &DateTimeUtc, status: StatusCode) {
+        let subscriptions = std::mem::replace(&mut self.subscriptions, BTreeMap::new());
+        for (subscription_id, mut subscription) in subscriptions {
+            if let Some(publish_request) = self.publish_request_queue.pop_back() {
+                let notification_message = subscription.status_change_notification(now, status);
+                let response = self.make_publish_response(publish_request, subscription_id, now, notification_message, false, None);
+                self.publish_response_queue.push_back(response);
+            }
+        }
+        self.transmission_queue.clear();
+        self.retransmission_queue.clear();
+    }
+
     /// Iterates through the existing queued publish requests and creates a timeout
     /// publish response any that have expired.
     pub fn expire_stale_publish_requests(&mut self, now: &DateTimeUtc) {
@@ -289,6 +398,30 @@ impl Subscriptions {
         self.publish_response_queue.append(&mut expired_publish_responses);
     }
 
+    /// Removes the queued publish requests with the request handle, answering each with
+    /// `BadRequestCancelledByClient`. Returns the number of requests cancelled.
+    pub fn cancel_publish_requests(&mut self, request_handle: u32) -> u32 {
+        let mut cancelled_publish_responses = VecDeque::new();
+        self.publish_request_queue.retain(|request| {
+            let request_header = &request.request.request_header;
+            if request_header.request_handle == request_handle {
+                debug!("Publish request {} has been cancelled", request_handle);
+                cancelled_publish_responses.push_front(PublishResponseEntry {
+                    request_id: request.request_id,
+                    response: ServiceFault {
+                        response_header: ResponseHeader::new_timestamped_service_result(DateTime::now(), request_header, StatusCode::BadRequestCancelledByClient),
+                    }.into(),
+                });
+                false
+            } else {
+                true
+            }
+        });
+        let cancel_count = cancelled_publish_responses.len() as u32;
+        self.publish_response_queue.append(&mut cancelled_publish_responses);
+        cancel_count
+    }
+
     /// Deletes the acknowledged notifications, returning a list of status code for each according
     /// to whether it was found or not.
     ///
@@ -367,7 +500,7 @@ impl Subscriptions {
     }
 
     /// Finds a notification message in the retransmission queue matching the supplied subscription id
-    /// and sequence number. Returns `BadNoSubscription` or `BadMessageNotAvailable` if a matching
+    /// and sequence number. Returns `BadSubscriptionIdInvalid` or `BadMessageNotAvailable` if a matching
     /// notification is not found.
     pub fn find_notification_message(&self, subscription_id: u32, sequence_number: u32) -> Result<NotificationMessage, StatusCode> {
         // Look for the subscription
@@ -379,30 +512,30 @@ impl Subscriptions {
                 Err(StatusCode::BadMessageNotAvailable)
             }
         } else {
-            Err(StatusCode::BadNoSubscription)
+            Err(StatusCode::BadSubscriptionIdInvalid)
         }
     }
 
-    /// Purges notifications waiting for acknowledgement if they exceed the max retransmission queue
-    /// size.
-    fn remove_old_unacknowledged_notifications(&mut self) {
-        let max_retransmission_queue = self.max_publish_requests() * 2;
-        if self.retransmission_queue.len() > max_retransmission_queue {
-            // Compare number of items in retransmission queue to max permissible and remove the older
-            // notifications.
-            let remove_count = self.retransmission_queue.len() - max_retransmission_queue;
-
-            // Iterate the map, taking the sequence nr of each notification to remove
-            let sequence_nrs_to_remove = self.retransmission_queue.iter()
-                .take(remove_count)
-                .map(|v| *v.0)
-                .collect::<Vec<_>>();
-
-            // Remove in order of insertion, i.e. oldest first
-            sequence_nrs_to_remove.iter()
-                .for_each(|n| {
-                    self.retransmission_queue.remove(n);
+    /// Purges the oldest notifications waiting for acknowledgement from any subscription that holds
+    /// more than the max retransmission queue size.
+    pub(crate) fn remove_old_unacknowledged_notifications(&mut self) {
+        let mut counts = BTreeMap::new();
+        self.retransmission_queue.keys().for_each(|k| {
+            *counts.entry(k.0).or_insert(0usize) += 1;
+        });
+        counts.into_iter()
+            .filter(|(_, count)| *count > MAX_RETRANSMISSION_QUEUE_SIZE)
+            .for_each(|(subscription_id, count)| {
+                // Keys are ordered by sequence number within the subscription, so the oldest are first
+                let keys_to_remove = self.retransmission_queue.keys()
+                    .filter(|k| k.0 == subscription_id)
+                    .take(count - MAX_RETRANSMISSION_QUEUE_SIZE)
+                    .cloned()
+                    .collect::<Vec<(u32, u32)>>();
+                keys_to_remove.iter().for_each(|k| {
+                    debug!("Purging unacknowledged notification with sequence number {} from subscription {}", k.1, k.0);
+                    self.retransmission_queue.remove(k);
                 });
-        }
+            });
     }
 }
\ No newline at end of file
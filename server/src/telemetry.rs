@@ -0,0 +1,113 @@
+//! Structured telemetry of connections, sessions and requests through the `tracing` crate, when
+//! the server is built with the `tracing` feature. Each connection has a span, each session has a
+//! span within the span of its connection, and each request is handled within a span of its
+//! session which records the request id and handle, the service, the status of the response and
+//! how long the request took. The application's subscriber receives the spans, e.g. to export them
+//! to OpenTelemetry.
+//!
+//! Without the feature the spans are empty and cost nothing.
+
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use opcua_types::{*, status_code::StatusCode};
+
+/// A span of the server's telemetry, or nothing when the `tracing` feature is disabled
+#[derive(Clone, Debug)]
+pub(crate) struct Span {
+    #[cfg(feature = "tracing")]
+    inner: tracing::Span,
+}
+
+/// Guard which leaves the span it was entered from when it is dropped
+pub(crate) struct Entered<'a> {
+    #[cfg(feature = "tracing")]
+    _inner: tracing::span::Entered<'a>,
+    #[cfg(not(feature = "tracing"))]
+    _span: std::marker::PhantomData<&'a Span>,
+}
+
+impl Span {
+    /// A span that is not part of the server's telemetry. Spans within it have no parent.
+    pub fn none() -> Span {
+        Span {
+            #[cfg(feature = "tracing")]
+            inner: tracing::Span::none(),
+        }
+    }
+
+    /// The span of a connection from the client address
+    #[allow(unused_variables)]
+    pub fn connection(client_address: &SocketAddr, transport: &str) -> Span {
+        Span {
+            #[cfg(feature = "tracing")]
+            inner: tracing::info_span!("connection", client_address = %client_address, transport = transport),
+        }
+    }
+
+    /// The span of a session within this connection span
+    #[allow(unused_variables)]
+    pub fn session(&self, session_id: &NodeId) -> Span {
+        Span {
+            #[cfg(feature = "tracing")]
+            inner: tracing::info_span!(parent: &self.inner, "session", session_id = %session_id),
+        }
+    }
+
+    /// The span of a request within this session span. The status and duration are recorded when
+    /// the request has been handled.
+    #[allow(unused_variables)]
+    pub fn request(&self, request_id: u32, request: &SupportedMessage) -> Span {
+        Span {
+            #[cfg(feature = "tracing")]
+            inner: tracing::info_span!(parent: &self.inner, "request",
+                request_id = request_id,
+                request_handle = request.request_handle(),
+                service = service_name(request).as_str(),
+                status = tracing::field::Empty,
+                duration_ms = tracing::field::Empty),
+        }
+    }
+
+    /// Enters the span until the returned guard is dropped
+    pub fn enter(&self) -> Entered<'_> {
+        Entered {
+            #[cfg(feature = "tracing")]
+            _inner: self.inner.enter(),
+            #[cfg(not(feature = "tracing"))]
+            _span: std::marker::PhantomData,
+        }
+    }
+
+    /// Records on a request span how long the request took since it started and the status of its
+    /// response. A request which is answered later, e.g. a publish request, has no status.
+    #[allow(unused_variables)]
+    pub fn record_response(&self, started: Instant, result: &Result<Option<SupportedMessage>, StatusCode>) {
+        #[cfg(feature = "tracing")] {
+            let elapsed = started.elapsed();
+            let duration_ms = elapsed.as_secs() as f64 * 1000f64 + f64::from(elapsed.subsec_nanos()) / 1_000_000f64;
+            self.inner.record("duration_ms", &duration_ms);
+            let status = match result {
+                Ok(Some(SupportedMessage::ServiceFault(ref service_fault))) => Some(service_fault.response_header.service_result),
+                Ok(Some(_)) => Some(StatusCode::Good),
+                Ok(None) => None,
+                Err(status_code) => Some(*status_code),
+            };
+            if let Some(status) = status {
+                self.inner.record("status", &tracing::field::display(status));
+            }
+        }
+    }
+}
+
+/// Returns the name of the service of a request, e.g. "Read" for a `ReadRequest`
+#[cfg(feature = "tracing")]
+fn service_name(request: &SupportedMessage) -> String {
+    use opcua_types::node_ids::ObjectId;
+    let name = match request {
+        SupportedMessage::Invalid(_) | SupportedMessage::AcknowledgeMessage(_) => return String::from("Invalid"),
+        request => format!("{:?}", request.node_id().as_object_id().unwrap_or(ObjectId::BaseObjectType)),
+    };
+    let name = name.trim_end_matches("_Encoding_DefaultBinary");
+    name.trim_end_matches("Request").to_string()
+}
@@ -1,4 +1,7 @@
+use opcua_types::Argument;
+
 use crate::prelude::*;
+use crate::address_space::types::Method;
 
 use crate::tests::*;
 
@@ -104,6 +107,41 @@ fn find_common_nodes() {
     }
 }
 
+#[test]
+fn server_capabilities_modelling_rules() {
+    let address_space = AddressSpace::new();
+    let modelling_rules_folder_id: NodeId = ObjectId::Server_ServerCapabilities_ModellingRules.into();
+    let modelling_rules = [
+        ObjectId::ModellingRule_Mandatory,
+        ObjectId::ModellingRule_Optional,
+        ObjectId::ModellingRule_ExposesItsArray,
+        ObjectId::ModellingRule_OptionalPlaceholder,
+        ObjectId::ModellingRule_MandatoryPlaceholder,
+    ];
+    for modelling_rule in modelling_rules.iter() {
+        let modelling_rule_id: NodeId = (*modelling_rule).into();
+        assert!(address_space.node_exists(&modelling_rule_id));
+        assert!(address_space.has_reference(&modelling_rules_folder_id, &modelling_rule_id, ReferenceTypeId::Organizes));
+    }
+    assert!(address_space.node_exists(&ObjectId::Server_ServerCapabilities_AggregateFunctions.into()));
+}
+
+#[test]
+fn populate_extended_nodes() {
+    let mut address_space = AddressSpace::new();
+    let analog_item_type_id: NodeId = VariableTypeId::AnalogItemType.into();
+    if !address_space.extended_nodes_populated() {
+        // Trimmed address space defers extended nodes
+        assert!(!address_space.node_exists(&analog_item_type_id));
+        address_space.populate_extended_nodes();
+    }
+    assert!(address_space.extended_nodes_populated());
+    assert!(address_space.node_exists(&analog_item_type_id));
+    // Populating again is harmless
+    address_space.populate_extended_nodes();
+    assert!(address_space.node_exists(&analog_item_type_id));
+}
+
 #[test]
 fn object_attributes() {
     let on = NodeId::new(1, "o1");
@@ -249,3 +287,531 @@ fn variable_builder() {
     assert_eq!(v.value().value.unwrap(), Variant::from(999));
     assert_eq!(v.minimum_sampling_interval().unwrap(), 123.0);
 }
+
+#[test]
+fn node_builders() {
+    let mut address_space = AddressSpace::new();
+
+    let folder_id = NodeId::new(1, "Folder");
+    let object_id = NodeId::new(1, "Object");
+    let variable_id = NodeId::new(1, "Variable");
+    let method_id = NodeId::new(1, "Method");
+    ObjectBuilder::new(&folder_id)
+        .browse_name("Folder")
+        .display_name("Folder")
+        .is_folder()
+        .organized_by(&AddressSpace::objects_folder_id())
+        .insert(&mut address_space).unwrap();
+    ObjectBuilder::new(&object_id)
+        .browse_name("Object")
+        .display_name("Object")
+        .event_notifier(1)
+        .organized_by(&folder_id)
+        .has_type_definition(ObjectTypeId::BaseObjectType)
+        .insert(&mut address_space).unwrap();
+    VariableBuilder::new(&variable_id)
+        .browse_name("Variable")
+        .display_name("Variable")
+        .value(1.5f64)
+        .writable()
+        .component_of(&object_id)
+        .has_type_definition(VariableTypeId::BaseDataVariableType)
+        .insert(&mut address_space).unwrap();
+    let input_arguments = [Argument {
+        name: UAString::from("Value"),
+        data_type: DataTypeId::Int32.into(),
+        value_rank: -1,
+        array_dimensions: None,
+        description: LocalizedText::null(),
+    }];
+    MethodBuilder::new(&method_id)
+        .browse_name("Method")
+        .display_name("Method")
+        .input_args(&input_arguments)
+        .callback(Box::new(MethodFn::new(|_| Ok(Vec::new()))))
+        .component_of(&object_id)
+        .insert(&mut address_space).unwrap();
+
+    assert!(address_space.has_reference(&AddressSpace::objects_folder_id(), &folder_id, ReferenceTypeId::Organizes));
+    assert_eq!(address_space.get_type_id(&folder_id), Some(ObjectTypeId::FolderType.into()));
+    assert!(address_space.has_reference(&folder_id, &object_id, ReferenceTypeId::Organizes));
+    if let Some(NodeType::Object(object)) = address_space.find_node(&object_id) {
+        assert_eq!(object.event_notifier(), 1);
+    } else {
+        panic!("Object is not an object");
+    }
+
+    // The variable's data type comes from its value
+    assert!(address_space.has_reference(&object_id, &variable_id, ReferenceTypeId::HasComponent));
+    let variable = address_space.find_variable(variable_id.clone()).unwrap();
+    assert_eq!(variable.data_type(), DataTypeId::Double.into());
+    assert!(variable.is_readable());
+    assert!(variable.is_writable());
+
+    assert!(address_space.has_reference(&object_id, &method_id, ReferenceTypeId::HasComponent));
+    let properties = address_space.find_references_from(&method_id, Some((ReferenceTypeId::HasProperty, false))).unwrap();
+    assert_eq!(properties.len(), 1);
+
+    // Nodes cannot be inserted twice, and a method with a callback needs an object
+    assert!(VariableBuilder::new(&variable_id).insert(&mut address_space).is_err());
+    assert!(MethodBuilder::new(&NodeId::new(1, "Method2"))
+        .callback(Box::new(MethodFn::new(|_| Ok(Vec::new()))))
+        .insert(&mut address_space).is_err());
+    assert!(!address_space.node_exists(&NodeId::new(1, "Method2")));
+}
+
+#[test]
+fn conditions() {
+    let mut address_space = AddressSpace::new();
+    let source_node = NodeId::new(1, "Boiler");
+    let _ = address_space.add_organized_node(&source_node, "Boiler", "Boiler", &AddressSpace::objects_folder_id(), ObjectTypeId::BaseObjectType).unwrap();
+
+    // A condition must have a source that exists
+    let condition = Condition::new_alarm(NodeId::new(1, "BadAlarm"), NodeId::new(1, "Missing"), "Missing", "BadAlarm");
+    assert!(address_space.add_condition(condition).is_err());
+
+    let condition_id = NodeId::new(1, "BoilerAlarm");
+    let condition = Condition::new_alarm(condition_id.clone(), source_node.clone(), "Boiler", "BoilerAlarm");
+    assert_eq!(address_space.add_condition(condition).unwrap(), condition_id);
+    assert!(address_space.has_reference(&source_node, &condition_id, ReferenceTypeId::HasCondition));
+    assert!(address_space.retained_condition_events().is_empty());
+
+    // Activating the alarm raises an event and makes it unacknowledged
+    let last_event_number = address_space.last_event_number();
+    address_space.update_condition(condition_id.clone(), |condition| {
+        condition.set_severity(500, "Too hot");
+        condition.set_active(true)
+    }).unwrap();
+    let event_id = {
+        let events = address_space.events_since(last_event_number);
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.event_type, ObjectTypeId::AlarmConditionType.into());
+        assert_eq!(event.source_node, source_node);
+        assert_eq!(event.severity, 500);
+        assert_eq!(event.field("ConditionId"), Some(Variant::from(condition_id.clone())));
+        assert_eq!(event.field("ActiveState/Id"), Some(Variant::from(true)));
+        assert_eq!(event.field("AckedState/Id"), Some(Variant::from(false)));
+        assert_eq!(event.field("Retain"), Some(Variant::from(true)));
+        event.event_id.clone()
+    };
+    assert_eq!(address_space.retained_condition_events().len(), 1);
+
+    // Acknowledgement needs the id of the latest event
+    assert_eq!(address_space.update_condition(condition_id.clone(), |condition| {
+        condition.acknowledge(&ByteString::from(vec![1u8, 2u8]), LocalizedText::null())
+    }).unwrap_err(), StatusCode::BadEventIdUnknown);
+    address_space.update_condition(condition_id.clone(), |condition| {
+        condition.acknowledge(&event_id, LocalizedText::new("", "On it"))
+    }).unwrap();
+    assert_eq!(address_space.update_condition(condition_id.clone(), |condition| {
+        let event_id = condition.event_id().clone();
+        condition.acknowledge(&event_id, LocalizedText::null())
+    }).unwrap_err(), StatusCode::BadConditionBranchAlreadyAcked);
+
+    // Still retained while active, then not once inactive
+    assert_eq!(address_space.retained_condition_events().len(), 1);
+    address_space.update_condition(condition_id.clone(), |condition| condition.set_active(false)).unwrap();
+    assert!(address_space.retained_condition_events().is_empty());
+
+    // A disabled condition cannot be made active
+    address_space.update_condition(condition_id.clone(), |condition| condition.disable()).unwrap();
+    assert_eq!(address_space.update_condition(condition_id.clone(), |condition| condition.disable()).unwrap_err(), StatusCode::BadConditionAlreadyDisabled);
+    assert_eq!(address_space.update_condition(condition_id.clone(), |condition| condition.set_active(true)).unwrap_err(), StatusCode::BadConditionDisabled);
+
+    // Unknown condition
+    assert_eq!(address_space.update_condition(NodeId::new(1, "Nope"), |condition| condition.enable()).unwrap_err(), StatusCode::BadNodeIdUnknown);
+}
+
+#[test]
+fn limit_alarms() {
+    let mut address_space = AddressSpace::new();
+    let source_node = NodeId::new(1, "Tank");
+    let _ = address_space.add_organized_node(&source_node, "Tank", "Tank", &AddressSpace::objects_folder_id(), ObjectTypeId::BaseObjectType).unwrap();
+
+    let limits = AlarmLimits {
+        high_high: Some(90.0),
+        high: Some(80.0),
+        low: Some(20.0),
+        low_low: Some(10.0),
+    };
+    let exclusive_id = NodeId::new(1, "TankLevelExclusive");
+    let non_exclusive_id = NodeId::new(1, "TankLevelNonExclusive");
+    let _ = address_space.add_condition(Condition::new_limit_alarm(exclusive_id.clone(), source_node.clone(), "Tank", "Level", limits.clone(), true)).unwrap();
+    let _ = address_space.add_condition(Condition::new_limit_alarm(non_exclusive_id.clone(), source_node.clone(), "Tank", "Level", limits, false)).unwrap();
+
+    // Values within the limits do not raise events
+    let last_event_number = address_space.last_event_number();
+    address_space.set_condition_input_value(exclusive_id.clone(), 50.0).unwrap();
+    assert_eq!(address_space.last_event_number(), last_event_number);
+
+    // Exceeding a limit raises an event
+    address_space.set_condition_input_value(exclusive_id.clone(), 95.0).unwrap();
+    address_space.set_condition_input_value(non_exclusive_id.clone(), 95.0).unwrap();
+    assert_eq!(address_space.last_event_number(), last_event_number + 2);
+    {
+        let exclusive = address_space.find_condition(&exclusive_id).unwrap();
+        assert!(exclusive.is_active());
+        assert_eq!(exclusive.limit_states(), &[LimitState::HighHigh]);
+        let non_exclusive = address_space.find_condition(&non_exclusive_id).unwrap();
+        assert_eq!(non_exclusive.limit_states(), &[LimitState::HighHigh, LimitState::High]);
+
+        let events = address_space.events_since(last_event_number);
+        assert_eq!(events[0].field("LimitState/CurrentState"), Some(Variant::from(LocalizedText::new("", "HighHigh"))));
+        assert_eq!(events[1].field("HighHighState/Id"), Some(Variant::from(true)));
+        assert_eq!(events[1].field("HighState/Id"), Some(Variant::from(true)));
+        assert_eq!(events[1].field("LowState/Id"), Some(Variant::from(false)));
+    }
+
+    // The same limit state does not raise another event
+    address_space.set_condition_input_value(exclusive_id.clone(), 96.0).unwrap();
+    assert_eq!(address_space.last_event_number(), last_event_number + 2);
+
+    // Back within limits
+    address_space.set_condition_input_value(exclusive_id.clone(), 50.0).unwrap();
+    assert_eq!(address_space.last_event_number(), last_event_number + 3);
+    assert!(!address_space.find_condition(&exclusive_id).unwrap().is_active());
+
+    // Only limit alarms have an input value
+    let alarm_id = NodeId::new(1, "TankAlarm");
+    let _ = address_space.add_condition(Condition::new_alarm(alarm_id.clone(), source_node.clone(), "Tank", "Alarm")).unwrap();
+    assert_eq!(address_space.set_condition_input_value(alarm_id, 50.0).unwrap_err(), StatusCode::BadNotSupported);
+}
+
+#[test]
+fn register_namespace() {
+    let mut address_space = AddressSpace::new();
+    assert_eq!(address_space.namespaces().len(), 2);
+    assert_eq!(address_space.namespace_index("http://opcfoundation.org/UA/"), Some(0));
+    assert_eq!(address_space.namespace_index("urn:foo"), None);
+
+    // New namespaces are appended, existing namespaces keep their index
+    assert_eq!(address_space.register_namespace("urn:foo").unwrap(), 2);
+    assert_eq!(address_space.register_namespace("urn:bar").unwrap(), 3);
+    assert_eq!(address_space.register_namespace("urn:foo").unwrap(), 2);
+    assert_eq!(address_space.namespace_index("urn:bar"), Some(3));
+    assert!(address_space.register_namespace("").is_err());
+
+    // The NamespaceArray reflects the registered namespaces
+    let namespace_array = address_space.find_variable(VariableId::Server_NamespaceArray).unwrap().value();
+    assert_eq!(namespace_array.value.unwrap(), Variant::from(address_space.namespaces().to_vec()));
+}
+
+#[test]
+fn server_namespace() {
+    // The application uri of a server is its namespace 2
+    let server = ServerBuilder::new_anonymous("foo").application_uri("urn:foo").server().unwrap();
+    let application_uri = server.server_state().read().unwrap().application_uri.as_ref().to_string();
+    let address_space = server.address_space();
+    let address_space = address_space.read().unwrap();
+    assert_eq!(address_space.namespace_index(&application_uri), Some(2));
+}
+
+#[test]
+fn import_namespaces() {
+    let mut address_space = AddressSpace::new();
+    let _ = address_space.register_namespace("urn:foo");
+
+    // A model which uses urn:bar as its namespace 1 and urn:foo as its namespace 2
+    let namespace_map = address_space.import_namespaces(&["urn:bar", "urn:foo"]).unwrap();
+    assert_eq!(namespace_map.len(), 3);
+    assert_eq!(namespace_map[&0], 0);
+    assert_eq!(namespace_map[&1], 3);
+    assert_eq!(namespace_map[&2], 2);
+
+    assert_eq!(AddressSpace::remap_node_id(&namespace_map, &NodeId::new(1, "x")).unwrap(), NodeId::new(3, "x"));
+    assert_eq!(AddressSpace::remap_node_id(&namespace_map, &NodeId::new(2, 100)).unwrap(), NodeId::new(2, 100));
+    assert_eq!(AddressSpace::remap_node_id(&namespace_map, &ObjectId::ObjectsFolder.into()).unwrap(), ObjectId::ObjectsFolder.into());
+    assert_eq!(AddressSpace::remap_node_id(&namespace_map, &NodeId::new(5, "x")).unwrap_err(), StatusCode::BadNodeIdInvalid);
+
+    assert_eq!(AddressSpace::remap_browse_name(&namespace_map, &QualifiedName::new(1, "x")).unwrap(), QualifiedName::new(3, "x"));
+    assert_eq!(AddressSpace::remap_browse_name(&namespace_map, &QualifiedName::new(5, "x")).unwrap_err(), StatusCode::BadBrowseNameInvalid);
+}
+
+#[test]
+fn export_nodeset() {
+    let mut address_space = AddressSpace::new();
+    let ns = address_space.register_namespace("urn:export&test").unwrap();
+    assert_eq!(ns, 2);
+
+    let folder_id = NodeId::new(ns, "folder");
+    let _ = address_space.add_folder_with_id(&folder_id, QualifiedName::new(ns, "Folder"), "Folder", &AddressSpace::objects_folder_id());
+    let _ = address_space.add_variables(vec![
+        Variable::new(&NodeId::new(ns, "v1"), QualifiedName::new(ns, "v1"), "v1 <value>", 100i32),
+        Variable::new(&NodeId::new(ns, 2), QualifiedName::new(ns, "v2"), "v2", vec![1.5f64, 2.5f64]),
+    ], &folder_id);
+
+    let xml = address_space.export_nodeset(Some(&[ns]));
+    assert!(xml.starts_with(r#"<?xml version="1.0" encoding="utf-8"?>"#));
+    assert!(xml.trim_end().ends_with("</UANodeSet>"));
+
+    // The exported namespace is namespace 1 of the document
+    assert!(xml.contains("<NamespaceUris>\n    <Uri>urn:export&amp;test</Uri>\n  </NamespaceUris>"));
+
+    // Folder, with an inverse reference from the Objects folder which is not exported
+    assert!(xml.contains(r#"<UAObject NodeId="ns=1;s=folder" BrowseName="1:Folder">"#));
+    assert!(xml.contains(r#"<Reference ReferenceType="i=35" IsForward="false">i=85</Reference>"#));
+    assert!(xml.contains(r#"<Reference ReferenceType="i=40">i=61</Reference>"#));
+    assert!(xml.contains(r#"<Reference ReferenceType="i=35">ns=1;s=v1</Reference>"#));
+
+    // Variables and their values
+    assert!(xml.contains(r#"<UAVariable NodeId="ns=1;i=2" BrowseName="1:v2" DataType="i=11""#));
+    assert!(xml.contains(r#"<UAVariable NodeId="ns=1;s=v1" BrowseName="1:v1" DataType="i=6""#));
+    assert!(xml.contains("<DisplayName>v1 &lt;value&gt;</DisplayName>"));
+    assert!(xml.contains(r#"<Value><Int32 xmlns="http://opcfoundation.org/UA/2008/02/Types.xsd">100</Int32></Value>"#));
+    assert!(xml.contains(r#"<Value><ListOfDouble xmlns="http://opcfoundation.org/UA/2008/02/Types.xsd"><Double>1.5</Double><Double>2.5</Double></ListOfDouble></Value>"#));
+
+    // Nodes are written in node id order and nodes from other namespaces are not exported
+    assert!(xml.find("ns=1;i=2\" ").unwrap() < xml.find("ns=1;s=folder\" ").unwrap());
+    assert!(!xml.contains("<UAObject NodeId=\"i=85\""));
+    assert_eq!(xml.matches("<UAVariable ").count(), 2);
+    assert_eq!(xml.matches("<UAObject ").count(), 1);
+}
+
+#[test]
+fn model_change_events() {
+    fn changes(event: &Event) -> Vec<ModelChangeStructureDataType> {
+        if let Some(Variant::Array(changes)) = event.field("Changes") {
+            changes.iter().map(|change| {
+                if let Variant::ExtensionObject(change) = change {
+                    change.decode_inner::<ModelChangeStructureDataType>(&DecodingLimits::default()).unwrap()
+                } else {
+                    panic!("Change is not an extension object");
+                }
+            }).collect()
+        } else {
+            panic!("Event has no changes");
+        }
+    }
+
+    let mut address_space = AddressSpace::new();
+    let folder_id = NodeId::new(1, "Folder");
+
+    // Changes are not raised until enabled
+    let last_event_number = address_space.last_event_number();
+    let _ = address_space.add_folder_with_id(&folder_id, "Folder", "Folder", &AddressSpace::objects_folder_id()).unwrap();
+    assert_eq!(address_space.last_event_number(), last_event_number);
+    assert!(!address_space.model_change_events());
+
+    address_space.set_model_change_events(true);
+    let node_version_id = address_space.add_node_version(&folder_id).unwrap();
+    assert!(address_space.add_node_version(&folder_id).is_err());
+    assert!(address_space.add_node_version(&NodeId::new(1, "Missing")).is_err());
+    // Adding the property is itself a change to the folder
+    assert_eq!(address_space.get_variable_value(node_version_id.clone()).unwrap().value, Some(Variant::from("1")));
+
+    // Adding a node
+    let last_event_number = address_space.last_event_number();
+    let variable_id = NodeId::new(1, "Variable");
+    let _ = address_space.add_variable(Variable::new(&variable_id, "Variable", "Variable", 1i32), &folder_id).unwrap();
+    {
+        let events = address_space.events_since(last_event_number);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, ObjectTypeId::GeneralModelChangeEventType.into());
+        assert_eq!(events[0].source_node, ObjectId::Server.into());
+        let changes = changes(&events[0]);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].affected, variable_id);
+        assert_eq!(changes[0].verb, MODEL_CHANGE_NODE_ADDED);
+        assert_eq!(changes[1].affected, folder_id);
+        assert_eq!(changes[1].affected_type, ObjectTypeId::FolderType.into());
+        assert_eq!(changes[1].verb, MODEL_CHANGE_REFERENCE_ADDED);
+    }
+    assert_eq!(address_space.get_variable_value(node_version_id.clone()).unwrap().value, Some(Variant::from("2")));
+
+    // Deleting the node and the references to it
+    let last_event_number = address_space.last_event_number();
+    assert!(address_space.delete_node(&variable_id, true));
+    {
+        let events = address_space.events_since(last_event_number);
+        assert_eq!(events.len(), 1);
+        let changes = changes(&events[0]);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].affected, variable_id);
+        assert_eq!(changes[0].verb, MODEL_CHANGE_NODE_DELETED);
+        assert_eq!(changes[1].affected, folder_id);
+        assert_eq!(changes[1].verb, MODEL_CHANGE_REFERENCE_DELETED);
+    }
+    assert_eq!(address_space.get_variable_value(node_version_id.clone()).unwrap().value, Some(Variant::from("3")));
+
+    // Deleting a reference which does not exist is not a change
+    let last_event_number = address_space.last_event_number();
+    assert!(!address_space.delete_reference(&folder_id, &variable_id, ReferenceTypeId::Organizes));
+    assert_eq!(address_space.last_event_number(), last_event_number);
+}
+
+/// Adds an instance declaration to a type, or to another declaration
+fn add_instance_declaration<T>(address_space: &mut AddressSpace, parent_id: &NodeId, node: T, reference_type_id: ReferenceTypeId, modelling_rule: ObjectId, type_definition_id: NodeId)
+    where T: Into<NodeType>
+{
+    address_space.insert(node, Some(&[
+        (parent_id, reference_type_id, ReferenceDirection::Inverse),
+        (&type_definition_id, ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+        (&modelling_rule.into(), ReferenceTypeId::HasModellingRule, ReferenceDirection::Forward),
+    ]));
+}
+
+/// Finds the child of the node with the browse name
+fn find_child(address_space: &AddressSpace, node_id: &NodeId, browse_name: &str) -> Option<NodeId> {
+    address_space.find_references_from(node_id, None)?.into_iter()
+        .map(|r| r.target_node_id)
+        .find(|target_node_id| {
+            address_space.find_node(target_node_id)
+                .map(|node| node.as_node().browse_name() == QualifiedName::from(browse_name))
+                .unwrap_or(false)
+        })
+}
+
+#[test]
+fn instantiate_type() {
+    let mut address_space = AddressSpace::new();
+
+    // PumpType has a mandatory SerialNumber, Speed with mandatory Units, an optional Temperature,
+    // a Start method and a placeholder for valves. FastPumpType adds MaxSpeed and makes the
+    // Temperature mandatory.
+    let pump_type_id = NodeId::new(1, "PumpType");
+    let fast_pump_type_id = NodeId::new(1, "FastPumpType");
+    address_space.insert(ObjectType::new(&pump_type_id, "PumpType", "PumpType", false), Some(&[
+        (&ObjectTypeId::BaseObjectType.into(), ReferenceTypeId::HasSubtype, ReferenceDirection::Inverse),
+    ]));
+    address_space.insert(ObjectType::new(&fast_pump_type_id, "FastPumpType", "FastPumpType", false), Some(&[
+        (&pump_type_id, ReferenceTypeId::HasSubtype, ReferenceDirection::Inverse),
+    ]));
+
+    let property_type_id: NodeId = VariableTypeId::PropertyType.into();
+    let data_variable_type_id: NodeId = VariableTypeId::BaseDataVariableType.into();
+    let speed_id = NodeId::new(1, "PumpType.Speed");
+    add_instance_declaration(&mut address_space, &pump_type_id, Variable::new(&NodeId::new(1, "PumpType.SerialNumber"), "SerialNumber", "SerialNumber", "unknown"),
+                             ReferenceTypeId::HasProperty, ObjectId::ModellingRule_Mandatory, property_type_id.clone());
+    add_instance_declaration(&mut address_space, &pump_type_id, Variable::new(&speed_id, "Speed", "Speed", 0f64),
+                             ReferenceTypeId::HasComponent, ObjectId::ModellingRule_Mandatory, data_variable_type_id.clone());
+    add_instance_declaration(&mut address_space, &speed_id, Variable::new(&NodeId::new(1, "PumpType.Speed.Units"), "Units", "Units", "rpm"),
+                             ReferenceTypeId::HasProperty, ObjectId::ModellingRule_Mandatory, property_type_id.clone());
+    add_instance_declaration(&mut address_space, &pump_type_id, Variable::new(&NodeId::new(1, "PumpType.Temperature"), "Temperature", "Temperature", 0f64),
+                             ReferenceTypeId::HasComponent, ObjectId::ModellingRule_Optional, data_variable_type_id.clone());
+    add_instance_declaration(&mut address_space, &pump_type_id, Object::new(&NodeId::new(1, "PumpType.Valve"), "<Valve>", "<Valve>", 0),
+                             ReferenceTypeId::HasComponent, ObjectId::ModellingRule_MandatoryPlaceholder, ObjectTypeId::BaseObjectType.into());
+    add_instance_declaration(&mut address_space, &fast_pump_type_id, Variable::new(&NodeId::new(1, "FastPumpType.MaxSpeed"), "MaxSpeed", "MaxSpeed", 5000f64),
+                             ReferenceTypeId::HasProperty, ObjectId::ModellingRule_Mandatory, property_type_id.clone());
+    add_instance_declaration(&mut address_space, &fast_pump_type_id, Variable::new(&NodeId::new(1, "FastPumpType.Temperature"), "Temperature", "Temperature", 0f64),
+                             ReferenceTypeId::HasComponent, ObjectId::ModellingRule_Mandatory, data_variable_type_id.clone());
+    let start_id = NodeId::new(1, "PumpType.Start");
+    address_space.insert(Method::new(&start_id, "Start", "Start", true, true), Some(&[
+        (&pump_type_id, ReferenceTypeId::HasComponent, ReferenceDirection::Inverse),
+        (&ObjectId::ModellingRule_Mandatory.into(), ReferenceTypeId::HasModellingRule, ReferenceDirection::Forward),
+    ]));
+
+    let pump_id = address_space.instantiate(&fast_pump_type_id, "Pump1", &AddressSpace::objects_folder_id()).unwrap();
+    assert!(address_space.has_reference(&AddressSpace::objects_folder_id(), &pump_id, ReferenceTypeId::Organizes));
+    assert_eq!(address_space.get_type_id(&pump_id), Some(fast_pump_type_id.clone()));
+
+    // Mandatory declarations of the type and its supertype are copied, with their own children
+    let serial_number_id = find_child(&address_space, &pump_id, "SerialNumber").unwrap();
+    assert_ne!(serial_number_id, NodeId::new(1, "PumpType.SerialNumber"));
+    assert!(address_space.has_reference(&pump_id, &serial_number_id, ReferenceTypeId::HasProperty));
+    assert_eq!(address_space.get_type_id(&serial_number_id), Some(property_type_id));
+    assert_eq!(address_space.get_variable_value(serial_number_id).unwrap().value, Some(Variant::from("unknown")));
+    let pump_speed_id = find_child(&address_space, &pump_id, "Speed").unwrap();
+    assert!(address_space.has_reference(&pump_id, &pump_speed_id, ReferenceTypeId::HasComponent));
+    assert!(find_child(&address_space, &pump_speed_id, "Units").is_some());
+    assert!(find_child(&address_space, &pump_id, "MaxSpeed").is_some());
+    // The subtype makes the temperature mandatory
+    assert!(find_child(&address_space, &pump_id, "Temperature").is_some());
+    // Placeholders are not copied and methods are shared
+    assert!(find_child(&address_space, &pump_id, "<Valve>").is_none());
+    assert!(address_space.has_reference(&pump_id, &start_id, ReferenceTypeId::HasComponent));
+
+    // The optional temperature is not copied into instances of the supertype
+    let pump_id = address_space.instantiate(&pump_type_id, "Pump2", &AddressSpace::objects_folder_id()).unwrap();
+    assert!(find_child(&address_space, &pump_id, "Speed").is_some());
+    assert!(find_child(&address_space, &pump_id, "Temperature").is_none());
+    assert!(find_child(&address_space, &pump_id, "MaxSpeed").is_none());
+
+    // Instances of objects which are not folders are components
+    let component_id = address_space.instantiate(&pump_type_id, "Pump3", &pump_id).unwrap();
+    assert!(address_space.has_reference(&pump_id, &component_id, ReferenceTypeId::HasComponent));
+
+    // Abstract types, other nodes and missing parents are rejected
+    assert_eq!(address_space.instantiate(&ObjectTypeId::BaseEventType.into(), "Event", &AddressSpace::objects_folder_id()), Err(StatusCode::BadTypeDefinitionInvalid));
+    assert_eq!(address_space.instantiate(&speed_id, "Speed", &AddressSpace::objects_folder_id()), Err(StatusCode::BadTypeDefinitionInvalid));
+    assert_eq!(address_space.instantiate(&pump_type_id, "Pump4", &NodeId::new(1, "Missing")), Err(StatusCode::BadParentNodeIdInvalid));
+}
+
+#[test]
+fn shared_address_space_snapshots() {
+    let address_space = SharedAddressSpace::new(AddressSpace::new());
+    let variable_id = NodeId::new(1, "v1");
+    {
+        let mut address_space = address_space.write().unwrap();
+        let _ = address_space.add_variables(vec![Variable::new(&variable_id, "v1", "v1", 0i32)], &AddressSpace::objects_folder_id());
+    }
+
+    // A writer modifies the address space in place when no reader holds a snapshot
+    let snapshot_ptr = |address_space: &SharedAddressSpace| {
+        let snapshot = address_space.read().unwrap();
+        &*snapshot as *const AddressSpace
+    };
+    let current = snapshot_ptr(&address_space);
+    {
+        let mut address_space = address_space.write().unwrap();
+        let _ = address_space.add_folder("Folder1", "Folder1", &AddressSpace::objects_folder_id());
+    }
+    assert_eq!(snapshot_ptr(&address_space), current);
+
+    // A snapshot is not changed by a writer, which does not wait for its reader
+    let snapshot = address_space.read().unwrap();
+    let folder_id = {
+        let mut address_space = address_space.write().unwrap();
+        let folder_id = address_space.add_folder("Folder2", "Folder2", &AddressSpace::objects_folder_id()).unwrap();
+        address_space.find_node_mut(&variable_id).unwrap().as_mut_node().set_display_name(LocalizedText::from("Renamed"));
+
+        // The value of a variable is shared, even with the copy the writer is modifying
+        let now = DateTime::now();
+        assert!(snapshot.set_variable_value(variable_id.clone(), 1i32, &now, &now));
+        assert_eq!(address_space.get_variable_value(variable_id.clone()).unwrap().value, Some(Variant::from(1i32)));
+        folder_id
+    };
+    assert!(snapshot.find_node(&folder_id).is_none());
+    assert_eq!(snapshot.find_node(&variable_id).unwrap().as_node().display_name(), LocalizedText::from("v1"));
+
+    // A later snapshot has the changes
+    let latest = address_space.read().unwrap();
+    assert!(latest.find_node(&folder_id).is_some());
+    assert_eq!(latest.find_node(&variable_id).unwrap().as_node().display_name(), LocalizedText::from("Renamed"));
+    assert_eq!(latest.get_variable_value(variable_id.clone()).unwrap().value, Some(Variant::from(1i32)));
+
+    // A value set through the old snapshot is seen by the new one
+    let now = DateTime::now();
+    assert!(snapshot.set_variable_value(variable_id.clone(), 2i32, &now, &now));
+    assert_eq!(latest.get_variable_value(variable_id).unwrap().value, Some(Variant::from(2i32)));
+}
+
+#[test]
+fn shared_address_space_readers_do_not_wait_for_writers() {
+    use std::sync::{Arc, mpsc};
+    use std::thread;
+    use std::time::Duration;
+
+    let address_space = Arc::new(SharedAddressSpace::new(AddressSpace::new()));
+    let snapshot = address_space.read().unwrap();
+
+    // A writer holds the address space while the reader takes another snapshot
+    let (tx_locked, rx_locked) = mpsc::channel();
+    let (tx_release, rx_release) = mpsc::channel::<()>();
+    let writer = {
+        let address_space = address_space.clone();
+        thread::spawn(move || {
+            let mut address_space = address_space.write().unwrap();
+            let _ = address_space.add_folder("Folder", "Folder", &AddressSpace::objects_folder_id());
+            tx_locked.send(()).unwrap();
+            rx_release.recv_timeout(Duration::from_secs(5)).unwrap();
+        })
+    };
+    rx_locked.recv_timeout(Duration::from_secs(5)).unwrap();
+    let during_write = address_space.read().unwrap();
+    assert!(Arc::ptr_eq(&snapshot, &during_write));
+    tx_release.send(()).unwrap();
+    writer.join().unwrap();
+
+    assert!(!Arc::ptr_eq(&snapshot, &address_space.read().unwrap()));
+}
@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use crate::{
+    aggregation::{aggregation_folder_id, mirrored_node_id, next_reconnect_delay, set_mirrored_status},
+    config::AggregatedServerConfig,
+    constants,
+};
+
+use super::*;
+
+#[test]
+fn aggregated_server_config_invalid() {
+    let aggregated_server = AggregatedServerConfig::new("PLC1", "opc.tcp://plc1:4855/");
+    let mut config = ServerBuilder::new_anonymous("foo").aggregated_server(aggregated_server.clone()).config();
+    assert!(config.is_valid());
+
+    config.aggregated_servers[0].url = "http://plc1:4855/".to_string();
+    assert_eq!(config.is_valid(), false);
+
+    config.aggregated_servers[0] = aggregated_server.clone();
+    config.aggregated_servers[0].name = String::new();
+    assert_eq!(config.is_valid(), false);
+
+    config.aggregated_servers[0] = aggregated_server.clone();
+    config.aggregated_servers[0].security_policy = "Basic256Sha256".to_string();
+    assert_eq!(config.is_valid(), false);
+
+    config.aggregated_servers[0].security_mode = "SignAndEncrypt".to_string();
+    assert!(config.is_valid());
+
+    config.aggregated_servers[0].security_mode = "Xyz".to_string();
+    assert_eq!(config.is_valid(), false);
+
+    // A password without a user
+    config.aggregated_servers[0] = aggregated_server.clone();
+    config.aggregated_servers[0].pass = Some("secret".to_string());
+    assert_eq!(config.is_valid(), false);
+
+    config.aggregated_servers[0].user = Some("user".to_string());
+    assert!(config.is_valid());
+
+    config.aggregated_servers[0] = aggregated_server.clone();
+    config.aggregated_servers[0].publishing_interval = 0;
+    assert_eq!(config.is_valid(), false);
+
+    // Two aggregated servers mirrored in the same folder
+    config.aggregated_servers[0] = aggregated_server.clone();
+    config.aggregated_servers.push(AggregatedServerConfig::new("PLC1", "opc.tcp://plc2:4855/"));
+    assert_eq!(config.is_valid(), false);
+
+    config.aggregated_servers[1].name = "PLC2".to_string();
+    assert!(config.is_valid());
+}
+
+#[test]
+fn aggregated_node_ids() {
+    assert_eq!(aggregation_folder_id(2, "PLC1"), NodeId::new(2, "PLC1"));
+
+    // Nodes of different servers with the same ids are mirrored as different nodes
+    let remote_node_id = NodeId::new(3, 10);
+    let plc1_node_id = mirrored_node_id(2, "PLC1", &remote_node_id);
+    let plc2_node_id = mirrored_node_id(2, "PLC2", &remote_node_id);
+    assert_eq!(plc1_node_id, NodeId::new(2, "PLC1.ns=3;i=10"));
+    assert_ne!(plc1_node_id, plc2_node_id);
+    assert_ne!(mirrored_node_id(2, "PLC1", &NodeId::new(3, "10")), plc1_node_id);
+}
+
+#[test]
+fn aggregated_reconnect_delay() {
+    // Doubles from the minimum and stops at the maximum
+    let mut delay = constants::AGGREGATION_RECONNECT_MIN_MS;
+    assert_eq!(next_reconnect_delay(delay), delay * 2);
+    for _ in 0..20 {
+        let next = next_reconnect_delay(delay);
+        assert!(next >= delay);
+        assert!(next <= constants::AGGREGATION_RECONNECT_MAX_MS);
+        delay = next;
+    }
+    assert_eq!(delay, constants::AGGREGATION_RECONNECT_MAX_MS);
+    assert_eq!(next_reconnect_delay(0), constants::AGGREGATION_RECONNECT_MIN_MS);
+}
+
+#[test]
+fn aggregated_status_while_disconnected() {
+    let address_space = Arc::new(SharedAddressSpace::new(AddressSpace::new()));
+    let folder_id = aggregation_folder_id(2, "PLC1");
+    let local_node_id = mirrored_node_id(2, "PLC1", &NodeId::new(3, 10));
+    {
+        let mut address_space = address_space.write().unwrap();
+        address_space.add_folder_with_id(&folder_id, "PLC1", "PLC1", &AddressSpace::objects_folder_id()).unwrap();
+        VariableBuilder::new(&local_node_id)
+            .browse_name("Speed")
+            .display_name("Speed")
+            .value(12.5f64)
+            .organized_by(&folder_id)
+            .insert(&mut address_space).unwrap();
+    }
+
+    // The last known value is kept while the link is down
+    set_mirrored_status(&address_space, [local_node_id.clone()].iter(), StatusCode::BadCommunicationError);
+    {
+        let address_space = address_space.read().unwrap();
+        let value = address_space.find_variable_by_ref(&local_node_id).unwrap().value();
+        assert_eq!(value.status(), StatusCode::BadCommunicationError.bits());
+        assert_eq!(value.value, Some(Variant::Double(12.5)));
+    }
+
+    // Good once it is back up
+    set_mirrored_status(&address_space, [local_node_id.clone()].iter(), StatusCode::Good);
+    let address_space = address_space.read().unwrap();
+    let value = address_space.find_variable_by_ref(&local_node_id).unwrap().value();
+    assert_eq!(value.status(), StatusCode::Good.bits());
+}
@@ -0,0 +1,133 @@
+use std::sync::{Arc, Mutex};
+
+use futures::Future;
+
+use crate::bridge::{Bridge, BridgeRunner, BridgeTag};
+
+use super::*;
+
+#[derive(Default)]
+struct TestBridgeState {
+    connects: usize,
+    disconnects: usize,
+    polls: usize,
+    lose_connection: bool,
+    written: Vec<(String, Variant)>,
+}
+
+struct TestBridge {
+    state: Arc<Mutex<TestBridgeState>>,
+}
+
+impl Bridge for TestBridge {
+    fn name(&self) -> String {
+        "test".to_string()
+    }
+
+    fn connect(&mut self) -> Result<(), StatusCode> {
+        self.state.lock().unwrap().connects += 1;
+        Ok(())
+    }
+
+    fn disconnect(&mut self) {
+        self.state.lock().unwrap().disconnects += 1;
+    }
+
+    fn read_tags(&mut self, tags: &[String]) -> Result<Vec<Result<Variant, StatusCode>>, StatusCode> {
+        let mut state = self.state.lock().unwrap();
+        if state.lose_connection {
+            return Err(StatusCode::BadConnectionClosed);
+        }
+        state.polls += 1;
+        let polls = state.polls as i32;
+        Ok(tags.iter().map(|tag| {
+            if tag == "missing" {
+                Err(StatusCode::BadNodeIdUnknown)
+            } else {
+                Ok(Variant::from(polls * 10))
+            }
+        }).collect())
+    }
+
+    fn write_tag(&mut self, tag: &str, value: &Variant) -> Result<(), StatusCode> {
+        if let Variant::Int32(_) = value {
+            self.state.lock().unwrap().written.push((tag.to_string(), value.clone()));
+            Ok(())
+        } else {
+            Err(StatusCode::BadTypeMismatch)
+        }
+    }
+}
+
+fn make_bridge_address_space() -> (Arc<SharedAddressSpace>, Vec<NodeId>) {
+    let mut address_space = AddressSpace::new();
+    let node_ids = vec![NodeId::new(1, "Tag1"), NodeId::new(1, "Tag2"), NodeId::new(1, "Missing")];
+    let variables = node_ids.iter().map(|node_id| Variable::new(node_id, "Tag", "Tag", 0i32)).collect();
+    let _ = address_space.add_variables(variables, &AddressSpace::objects_folder_id());
+    (Arc::new(SharedAddressSpace::new(address_space)), node_ids)
+}
+
+fn value_of(address_space: &Arc<SharedAddressSpace>, node_id: &NodeId) -> Option<Variant> {
+    let address_space = address_space.read().unwrap();
+    address_space.find_variable_by_ref(node_id).unwrap().value().value
+}
+
+#[test]
+fn bridge_polls_tags() {
+    let (address_space, node_ids) = make_bridge_address_space();
+    let state = Arc::new(Mutex::new(TestBridgeState::default()));
+    let tags = vec![
+        BridgeTag::new("t1", node_ids[0].clone()),
+        BridgeTag::new("t2", node_ids[1].clone()),
+        BridgeTag::new("missing", node_ids[2].clone()),
+    ];
+    let mut runner = BridgeRunner::new(TestBridge { state: state.clone() }, tags, 100);
+
+    // The first poll connects and sets the values of the variables
+    runner.poll(&address_space);
+    assert_eq!(state.lock().unwrap().connects, 1);
+    assert_eq!(value_of(&address_space, &node_ids[0]), Some(Variant::Int32(10)));
+    assert_eq!(value_of(&address_space, &node_ids[1]), Some(Variant::Int32(10)));
+    // A tag which cannot be read leaves its variable alone
+    assert_eq!(value_of(&address_space, &node_ids[2]), Some(Variant::Int32(0)));
+
+    runner.poll(&address_space);
+    assert_eq!(state.lock().unwrap().connects, 1);
+    assert_eq!(value_of(&address_space, &node_ids[0]), Some(Variant::Int32(20)));
+
+    // A lost connection disconnects the bridge, and it is not reconnected before the reconnect
+    // interval has passed
+    state.lock().unwrap().lose_connection = true;
+    runner.poll(&address_space);
+    assert_eq!(state.lock().unwrap().disconnects, 1);
+    state.lock().unwrap().lose_connection = false;
+    runner.poll(&address_space);
+    assert_eq!(state.lock().unwrap().connects, 1);
+    assert_eq!(value_of(&address_space, &node_ids[0]), Some(Variant::Int32(20)));
+
+    runner.stop();
+    assert_eq!(state.lock().unwrap().disconnects, 1);
+}
+
+#[test]
+fn bridge_writes_tags() {
+    let (address_space, node_ids) = make_bridge_address_space();
+    let state = Arc::new(Mutex::new(TestBridgeState::default()));
+    let tags = vec![
+        BridgeTag::new_writable("t1", node_ids[0].clone()),
+        BridgeTag::new("t2", node_ids[1].clone()),
+    ];
+    let runner = BridgeRunner::new(TestBridge { state: state.clone() }, tags, 100);
+    let mut address_space = address_space.write().unwrap();
+    runner.set_variable_setters(&mut address_space);
+
+    // Writes of a writable tag's variable are written to the bridge, and its errors are returned
+    let variable = address_space.find_variable_by_ref(&node_ids[0]).unwrap();
+    assert_eq!(variable.async_set_value(DataValue::new(5i32)).unwrap().wait(), Ok(()));
+    assert_eq!(variable.async_set_value(DataValue::new("x")).unwrap().wait(), Err(StatusCode::BadTypeMismatch));
+    assert_eq!(state.lock().unwrap().written, vec![("t1".to_string(), Variant::Int32(5))]);
+
+    // A read only tag's variable is not written to the bridge
+    let variable = address_space.find_variable_by_ref(&node_ids[1]).unwrap();
+    assert!(variable.async_set_value(DataValue::new(5i32)).is_none());
+}
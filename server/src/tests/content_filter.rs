@@ -0,0 +1,221 @@
+use crate::{
+    content_filter::*,
+    events::event::{Event, EventTarget},
+};
+
+use super::*;
+
+// Content filter tests. The filters are evaluated against events since every event has the same
+// fields and a type from the generated address space.
+
+fn field_operand(browse_path: &str) -> ExtensionObject {
+    ExtensionObject::from_encodable(ObjectId::SimpleAttributeOperand_Encoding_DefaultBinary, &SimpleAttributeOperand {
+        type_definition_id: ObjectTypeId::BaseEventType.into(),
+        browse_path: Some(vec![QualifiedName::new(0, browse_path)]),
+        attribute_id: AttributeId::Value as u32,
+        index_range: UAString::null(),
+    })
+}
+
+fn make_event() -> Event {
+    let mut event = Event::new(ObjectTypeId::AuditWriteUpdateEventType, ObjectId::Server, "Server", "Golden", 500);
+    event.set_field("Status", true);
+    event.set_field("Name", "Thorn");
+    event
+}
+
+/// Evaluates a filter of the elements against the event
+fn evaluate(address_space: &AddressSpace, event: &Event, elements: Vec<ContentFilterElement>) -> bool {
+    let filter = ContentFilter { elements: Some(elements) };
+    assert!(validate_content_filter(address_space, &filter).is_ok());
+    ContentFilterEvaluator::new(&filter).unwrap().evaluate(&EventTarget { address_space, event })
+}
+
+fn evaluate_like(address_space: &AddressSpace, event: &Event, field: &str, pattern: &str) -> bool {
+    evaluate(address_space, event, vec![filter_element(FilterOperator::Like, vec![field_operand(field), literal_operand(pattern)])])
+}
+
+#[test]
+fn content_filter_empty() {
+    let address_space = AddressSpace::new();
+    let event = make_event();
+    let filter = ContentFilter { elements: None };
+    let evaluator = ContentFilterEvaluator::new(&filter).unwrap();
+    assert!(evaluator.is_empty());
+    assert!(evaluator.evaluate(&EventTarget { address_space: &address_space, event: &event }));
+}
+
+#[test]
+fn content_filter_comparisons() {
+    let address_space = AddressSpace::new();
+    let event = make_event();
+    let compare = |filter_operator, value: i32| {
+        evaluate(&address_space, &event, vec![filter_element(filter_operator, vec![field_operand("Severity"), literal_operand(value)])])
+    };
+
+    // Severity is a UInt16 but it compares by value with other numeric types
+    assert!(compare(FilterOperator::Equals, 500));
+    assert!(!compare(FilterOperator::Equals, 501));
+    assert!(compare(FilterOperator::GreaterThan, 499));
+    assert!(!compare(FilterOperator::GreaterThan, 500));
+    assert!(compare(FilterOperator::GreaterThanOrEqual, 500));
+    assert!(compare(FilterOperator::LessThan, 501));
+    assert!(!compare(FilterOperator::LessThan, 500));
+    assert!(compare(FilterOperator::LessThanOrEqual, 500));
+
+    // Between is inclusive
+    let between = |low: i32, high: i32| {
+        evaluate(&address_space, &event, vec![filter_element(FilterOperator::Between, vec![field_operand("Severity"), literal_operand(low), literal_operand(high)])])
+    };
+    assert!(between(500, 600));
+    assert!(between(400, 500));
+    assert!(!between(501, 600));
+
+    // Fields that the event does not have are null, and null never compares
+    assert!(!evaluate(&address_space, &event, vec![filter_element(FilterOperator::Equals, vec![field_operand("Missing"), literal_operand(Variant::Empty)])]));
+    assert!(evaluate(&address_space, &event, vec![filter_element(FilterOperator::IsNull, vec![field_operand("Missing")])]));
+    assert!(!evaluate(&address_space, &event, vec![filter_element(FilterOperator::IsNull, vec![field_operand("Severity")])]));
+
+    // Values of different types are unequal
+    assert!(!evaluate(&address_space, &event, vec![filter_element(FilterOperator::Equals, vec![field_operand("Status"), literal_operand("true")])]));
+    assert!(evaluate(&address_space, &event, vec![filter_element(FilterOperator::Equals, vec![field_operand("Status"), literal_operand(true)])]));
+}
+
+#[test]
+fn content_filter_like() {
+    let address_space = AddressSpace::new();
+    let event = make_event();
+
+    // The examples of Part 4 Table 117
+    assert!(evaluate_like(&address_space, &event, "Name", "Th%"));
+    assert!(!evaluate_like(&address_space, &event, "Name", "Te%"));
+    assert!(evaluate_like(&address_space, &event, "Message", "%en%"));
+    assert!(evaluate_like(&address_space, &event, "Name", "Th_rn"));
+    assert!(!evaluate_like(&address_space, &event, "Name", "Th_n"));
+    assert!(evaluate_like(&address_space, &event, "Name", "[CT]horn"));
+    assert!(!evaluate_like(&address_space, &event, "Name", "[CB]horn"));
+    assert!(evaluate_like(&address_space, &event, "Name", "[A-Z]horn"));
+    assert!(!evaluate_like(&address_space, &event, "Name", "[a-z]horn"));
+    assert!(evaluate_like(&address_space, &event, "Name", "[^C]horn"));
+    assert!(!evaluate_like(&address_space, &event, "Name", "[^T]horn"));
+    assert!(evaluate_like(&address_space, &event, "Name", "%[^a-m]"));
+
+    // Escaped characters match literally
+    let mut event = make_event();
+    event.set_field("Name", "100%_done");
+    assert!(evaluate_like(&address_space, &event, "Name", "100\\%\\_done"));
+    assert!(!evaluate_like(&address_space, &event, "Name", "100\\%\\_d"));
+
+    // Values which are not text never match
+    assert!(!evaluate_like(&address_space, &event, "Severity", "%"));
+}
+
+#[test]
+fn content_filter_in_list() {
+    let address_space = AddressSpace::new();
+    let event = make_event();
+    let in_list = |values: Vec<i32>| {
+        let mut operands = vec![field_operand("Severity")];
+        operands.extend(values.into_iter().map(literal_operand));
+        evaluate(&address_space, &event, vec![filter_element(FilterOperator::InList, operands)])
+    };
+    assert!(in_list(vec![100, 500, 900]));
+    assert!(in_list(vec![500]));
+    assert!(!in_list(vec![100, 200]));
+}
+
+#[test]
+fn content_filter_logical_operators() {
+    let address_space = AddressSpace::new();
+    let event = make_event();
+
+    // Severity > 100 AND Status == true
+    let elements = |severity: i32, status: bool| vec![
+        filter_element(FilterOperator::And, vec![element_operand(1), element_operand(2)]),
+        filter_element(FilterOperator::GreaterThan, vec![field_operand("Severity"), literal_operand(severity)]),
+        filter_element(FilterOperator::Equals, vec![field_operand("Status"), literal_operand(status)]),
+    ];
+    assert!(evaluate(&address_space, &event, elements(100, true)));
+    assert!(!evaluate(&address_space, &event, elements(100, false)));
+    assert!(!evaluate(&address_space, &event, elements(900, true)));
+
+    // The same with Or
+    let mut or_elements = elements(900, true);
+    or_elements[0].filter_operator = FilterOperator::Or;
+    assert!(evaluate(&address_space, &event, or_elements));
+    let mut or_elements = elements(900, false);
+    or_elements[0].filter_operator = FilterOperator::Or;
+    assert!(!evaluate(&address_space, &event, or_elements));
+
+    // NOT (Severity > 900)
+    assert!(evaluate(&address_space, &event, vec![
+        filter_element(FilterOperator::Not, vec![element_operand(1)]),
+        filter_element(FilterOperator::GreaterThan, vec![field_operand("Severity"), literal_operand(900)]),
+    ]));
+}
+
+#[test]
+fn content_filter_of_type() {
+    let address_space = AddressSpace::new();
+    let event = make_event();
+    let of_type = |type_id: ObjectTypeId| {
+        evaluate(&address_space, &event, vec![filter_element(FilterOperator::OfType, vec![literal_operand(NodeId::from(&type_id))])])
+    };
+
+    // The event is of its own type and all of its supertypes
+    assert!(of_type(ObjectTypeId::AuditWriteUpdateEventType));
+    assert!(of_type(ObjectTypeId::AuditUpdateEventType));
+    assert!(of_type(ObjectTypeId::AuditEventType));
+    assert!(of_type(ObjectTypeId::BaseEventType));
+    assert!(!of_type(ObjectTypeId::SystemEventType));
+
+    // An operand of a type that the event is not of is null
+    let operand = ExtensionObject::from_encodable(ObjectId::SimpleAttributeOperand_Encoding_DefaultBinary, &SimpleAttributeOperand {
+        type_definition_id: ObjectTypeId::SystemEventType.into(),
+        browse_path: Some(vec![QualifiedName::new(0, "Severity")]),
+        attribute_id: AttributeId::Value as u32,
+        index_range: UAString::null(),
+    });
+    assert!(evaluate(&address_space, &event, vec![filter_element(FilterOperator::IsNull, vec![operand])]));
+}
+
+#[test]
+fn content_filter_invalid() {
+    let address_space = AddressSpace::new();
+
+    let validate = |elements: Vec<ContentFilterElement>| {
+        let filter = ContentFilter { elements: Some(elements) };
+        validate_content_filter(&address_space, &filter).unwrap_err().element_results.unwrap()
+    };
+
+    // Wrong number of operands
+    let results = validate(vec![filter_element(FilterOperator::Equals, vec![field_operand("Severity")])]);
+    assert_eq!(results[0].status_code, StatusCode::BadFilterOperandCountMismatch);
+
+    // Unsupported operators
+    let results = validate(vec![filter_element(FilterOperator::InView, vec![literal_operand(NodeId::new(1, 1))])]);
+    assert_eq!(results[0].status_code, StatusCode::BadFilterOperatorUnsupported);
+
+    // Element operands must refer to a later element
+    let results = validate(vec![filter_element(FilterOperator::Not, vec![element_operand(0)])]);
+    assert_eq!(results[0].status_code, StatusCode::BadFilterOperandInvalid);
+    assert_eq!(results[0].operand_status_codes, Some(vec![StatusCode::BadFilterOperandInvalid]));
+    assert!(ContentFilterEvaluator::new(&ContentFilter { elements: Some(vec![filter_element(FilterOperator::Not, vec![element_operand(0)])]) }).is_err());
+
+    // OfType must be given a type
+    let results = validate(vec![filter_element(FilterOperator::OfType, vec![literal_operand(NodeId::new(1, 1))])]);
+    assert_eq!(results[0].status_code, StatusCode::BadFilterOperandInvalid);
+    assert_eq!(results[0].operand_status_codes, Some(vec![StatusCode::BadNodeIdUnknown]));
+
+    // A filter with any invalid element cannot be evaluated
+    let filter = ContentFilter {
+        elements: Some(vec![
+            filter_element(FilterOperator::OfType, vec![literal_operand(NodeId::from(&ObjectTypeId::BaseEventType))]),
+            filter_element(FilterOperator::Cast, vec![literal_operand(1), literal_operand(2)]),
+        ]),
+    };
+    let results = validate_content_filter(&address_space, &filter).unwrap_err().element_results.unwrap();
+    assert_eq!(results[0].status_code, StatusCode::Good);
+    assert_eq!(results[1].status_code, StatusCode::BadFilterOperatorUnsupported);
+    assert!(ContentFilterEvaluator::new(&filter).is_err());
+}
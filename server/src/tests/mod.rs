@@ -6,7 +6,7 @@ use time;
 
 use opcua_types::{
     *,
-    node_ids::{ObjectId, ObjectTypeId, DataTypeId, ReferenceTypeId, VariableId},
+    node_ids::{ObjectId, ObjectTypeId, DataTypeId, ReferenceTypeId, VariableId, VariableTypeId},
     status_code::StatusCode,
     service_types::*,
 };
@@ -20,6 +20,7 @@ use opcua_core::{
 use crate::{
     address_space::{
         address_space::*,
+        shared::SharedAddressSpace,
         variable::*,
     },
     session::*,
@@ -29,8 +30,18 @@ use crate::{
 };
 
 mod address_space;
+mod content_filter;
 mod services;
 mod subscriptions;
+mod pubsub;
+mod roles;
+mod simulation;
+mod aggregation;
+mod bridge;
+mod ua_structure;
+mod tcp_transport;
+#[cfg(feature = "http")]
+mod rest;
 
 fn make_test_file(filename: &str) -> PathBuf {
     let mut path = std::env::temp_dir();
@@ -58,6 +69,25 @@ fn add_sample_vars_to_address_space(address_space: &mut AddressSpace) {
     let _ = address_space.add_variables(vars, &sample_folder_id);
 }
 
+fn literal_operand<V>(value: V) -> ExtensionObject where V: Into<Variant> {
+    ExtensionObject::from_encodable(ObjectId::LiteralOperand_Encoding_DefaultBinary, &LiteralOperand {
+        value: value.into(),
+    })
+}
+
+fn element_operand(index: u32) -> ExtensionObject {
+    ExtensionObject::from_encodable(ObjectId::ElementOperand_Encoding_DefaultBinary, &ElementOperand {
+        index,
+    })
+}
+
+fn filter_element(filter_operator: FilterOperator, filter_operands: Vec<ExtensionObject>) -> ContentFilterElement {
+    ContentFilterElement {
+        filter_operator,
+        filter_operands: Some(filter_operands),
+    }
+}
+
 #[test]
 pub fn server_config_sample_save() {
     // This test only exists to dump a sample config
@@ -94,6 +124,66 @@ pub fn server_config_invalid() {
     config = ServerBuilder::new_anonymous("foo").config();
     config.endpoints.get_mut("none").unwrap().user_token_ids.insert("hello".to_string());
     assert_eq!(config.is_valid(), false);
+
+    // Receive buffer smaller than the minimum allowed by part 6
+    config = ServerBuilder::new_anonymous("foo").config();
+    config.tcp_config.receive_buffer_size = 1024;
+    assert_eq!(config.is_valid(), false);
+
+    // Max message size smaller than a single chunk
+    config = ServerBuilder::new_anonymous("foo").config();
+    config.tcp_config.max_message_size = config.tcp_config.receive_buffer_size - 1;
+    assert_eq!(config.is_valid(), false);
+
+    // Zero means no limit
+    config.tcp_config.max_message_size = 0;
+    config.tcp_config.max_chunk_count = 0;
+    assert!(config.is_valid());
+
+    // A listen address needs a host
+    config = ServerBuilder::new_anonymous("foo").listen_address("::", 4855).config();
+    assert!(config.is_valid());
+    config.tcp_config.listen_addresses[0].host.clear();
+    assert_eq!(config.is_valid(), false);
+
+    // An opc.tls endpoint needs the tls transport to be configured
+    config = ServerBuilder::new_anonymous("foo").config();
+    config.endpoints.get_mut("none").unwrap().tls = true;
+    assert_eq!(config.is_valid(), false);
+
+    // An opc.https endpoint needs the https transport to be configured
+    config = ServerBuilder::new_anonymous("foo").config();
+    config.endpoints.get_mut("none").unwrap().https = true;
+    assert_eq!(config.is_valid(), false);
+}
+
+#[test]
+pub fn server_config_tls_endpoint_url() {
+    let mut config = ServerBuilder::new_anonymous("foo")
+        .host_and_port("localhost", 4855)
+        .tls(4856, "server.pem", "server.key")
+        .config();
+    let endpoint = config.endpoints.get("none").unwrap().clone();
+    assert_eq!(config.endpoint_base_url(&endpoint), "opc.tcp://localhost:4855");
+
+    config.endpoints.get_mut("none").unwrap().tls = true;
+    let endpoint = config.endpoints.get("none").unwrap().clone();
+    assert_eq!(config.endpoint_base_url(&endpoint), "opc.tls://localhost:4856");
+    assert!(config.find_endpoint("opc.tls://127.0.0.1:4856/", SecurityPolicy::None, MessageSecurityMode::None).is_some());
+    assert!(config.find_endpoint("opc.tcp://127.0.0.1:4855/", SecurityPolicy::None, MessageSecurityMode::None).is_none());
+}
+
+#[test]
+pub fn server_config_https_endpoint_url() {
+    let mut config = ServerBuilder::new_anonymous("foo")
+        .host_and_port("localhost", 4855)
+        .https(4443, "server.pem", "server.key")
+        .config();
+    config.endpoints.get_mut("none").unwrap().https = true;
+    let endpoint = config.endpoints.get("none").unwrap().clone();
+    assert_eq!(config.endpoint_base_url(&endpoint), "opc.https://localhost:4443");
+    assert!(config.find_endpoint("opc.https://127.0.0.1:4443/", SecurityPolicy::None, MessageSecurityMode::None).is_some());
+    assert!(config.find_endpoint("opc.tcp://127.0.0.1:4855/", SecurityPolicy::None, MessageSecurityMode::None).is_none());
 }
 
 #[test]
@@ -162,4 +252,118 @@ pub fn expired_publish_requests() {
             panic!("Expected service faults for timed out publish requests")
         }
     }
-}
\ No newline at end of file
+}
+#[test]
+fn server_diagnostics_requests() {
+    use crate::diagnostics::ServerDiagnostics;
+
+    let secure_channel: SecureChannel = (SecurityPolicy::None, MessageSecurityMode::None).into();
+    let session = Session::new_no_certificate_store(secure_channel);
+
+    let mut diagnostics = ServerDiagnostics::default();
+    diagnostics.on_create_session(&session);
+    assert_eq!(diagnostics.session_diagnostics().len(), 1);
+
+    let request: SupportedMessage = ReadRequest {
+        request_header: RequestHeader::new(&NodeId::null(), &DateTime::now(), 1),
+        max_age: 0f64,
+        timestamps_to_return: TimestampsToReturn::Both,
+        nodes_to_read: None,
+    }.into();
+
+    // A good response counts as a request, a service fault as an error
+    diagnostics.on_request(&session, &request, None);
+    let fault = ServiceFault::new_supported_message(&RequestHeader::new(&NodeId::null(), &DateTime::now(), 1), StatusCode::BadNothingToDo);
+    diagnostics.on_request(&session, &request, Some(&fault));
+    let fault = ServiceFault::new_supported_message(&RequestHeader::new(&NodeId::null(), &DateTime::now(), 1), StatusCode::BadUserAccessDenied);
+    diagnostics.on_request(&session, &request, Some(&fault));
+
+    let summary = diagnostics.server_diagnostics_summary();
+    assert_eq!(summary.current_session_count, 1);
+    assert_eq!(summary.rejected_requests_count, 2);
+    assert_eq!(summary.security_rejected_requests_count, 1);
+
+    let session_diagnostics = &diagnostics.session_diagnostics()[0];
+    assert_eq!(session_diagnostics.session_id, session.session_id);
+    assert_eq!(session_diagnostics.total_request_count.total_count, 3);
+    assert_eq!(session_diagnostics.total_request_count.error_count, 2);
+    assert_eq!(session_diagnostics.read_count.total_count, 3);
+    assert_eq!(session_diagnostics.read_count.error_count, 2);
+    assert_eq!(session_diagnostics.unauthorized_request_count, 1);
+
+    diagnostics.on_destroy_session(&session);
+    assert!(diagnostics.session_diagnostics().is_empty());
+}
+
+#[test]
+fn audit_events_for_requests() {
+    use crate::events::audit::audit_request;
+
+    let secure_channel: SecureChannel = (SecurityPolicy::None, MessageSecurityMode::None).into();
+    let session = Session::new_no_certificate_store(secure_channel);
+    let server_id = UAString::from("urn:test");
+    let request_header = RequestHeader::new(&NodeId::null(), &DateTime::now(), 1);
+
+    // A write produces an event for each node written with the status of that write
+    let write_value = |node_id: NodeId| WriteValue {
+        node_id,
+        attribute_id: AttributeId::Value as u32,
+        index_range: UAString::null(),
+        value: DataValue::new(100i32),
+    };
+    let request: SupportedMessage = WriteRequest {
+        request_header: request_header.clone(),
+        nodes_to_write: Some(vec![write_value(NodeId::new(1, 1)), write_value(NodeId::new(1, 2))]),
+    }.into();
+    let response: SupportedMessage = WriteResponse {
+        response_header: ResponseHeader::new_good(&request_header),
+        results: Some(vec![StatusCode::Good, StatusCode::BadNotWritable]),
+        diagnostic_infos: None,
+    }.into();
+    let events = audit_request(&server_id, &session, &request, Some(&response));
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].event_type, ObjectTypeId::AuditWriteUpdateEventType.into());
+    assert_eq!(events[0].source_node, NodeId::new(1, 1));
+    assert_eq!(events[0].field("Status"), Some(Variant::from(true)));
+    assert_eq!(events[0].field("NewValue"), Some(Variant::from(100i32)));
+    assert_eq!(events[0].field("ServerId"), Some(Variant::from(server_id.clone())));
+    assert_eq!(events[1].field("Status"), Some(Variant::from(false)));
+
+    // A create session rejected for its certificate is also audited as a certificate event
+    let request: SupportedMessage = CreateSessionRequest {
+        request_header: request_header.clone(),
+        client_description: ApplicationDescription::null(),
+        server_uri: UAString::null(),
+        endpoint_url: UAString::from("opc.tcp://localhost:4855/"),
+        session_name: UAString::null(),
+        client_nonce: ByteString::null(),
+        client_certificate: ByteString::null(),
+        requested_session_timeout: 0f64,
+        max_response_message_size: 0,
+    }.into();
+    let response = ServiceFault::new_supported_message(&request_header, StatusCode::BadCertificateUntrusted);
+    let events = audit_request(&server_id, &session, &request, Some(&response));
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].event_type, ObjectTypeId::AuditCreateSessionEventType.into());
+    assert_eq!(events[0].field("Status"), Some(Variant::from(false)));
+    assert_eq!(events[1].event_type, ObjectTypeId::AuditCertificateUntrustedEventType.into());
+
+    // Other requests are not audited
+    let request: SupportedMessage = ReadRequest {
+        request_header: request_header.clone(),
+        max_age: 0f64,
+        timestamps_to_return: TimestampsToReturn::Both,
+        nodes_to_read: None,
+    }.into();
+    assert!(audit_request(&server_id, &session, &request, None).is_empty());
+    let response = ServiceFault::new_supported_message(&request_header, StatusCode::BadNodeIdUnknown);
+    assert!(audit_request(&server_id, &session, &request, Some(&response)).is_empty());
+
+    // Unless their authentication token is rejected, which is audited for any request
+    let response = ServiceFault::new_supported_message(&request_header, StatusCode::BadIdentityTokenRejected);
+    let events = audit_request(&server_id, &session, &request, Some(&response));
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].event_type, ObjectTypeId::AuditSessionEventType.into());
+    assert_eq!(events[0].source_name, UAString::from("Session/ValidateRequest"));
+    assert_eq!(events[0].field("Status"), Some(Variant::from(false)));
+}
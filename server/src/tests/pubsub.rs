@@ -0,0 +1,280 @@
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::{
+    config::{PubSubConnectionConfig, WriterGroupConfig, DataSetWriterConfig, PublishedFieldConfig, MqttForwarderConfig, ForwardedVariableConfig, MqttPayloadEncoding},
+    pubsub::{WriterGroup, forwarder::{self, Forwarder}, json::{variant_to_json, variant_to_non_reversible_json}, mqtt},
+};
+
+use super::*;
+
+fn make_pubsub_connection() -> PubSubConnectionConfig {
+    PubSubConnectionConfig {
+        broker_url: "mqtt://localhost:1883".to_string(),
+        client_id: None,
+        publisher_id: Some("publisher".to_string()),
+        user: None,
+        pass: None,
+        writer_groups: vec![
+            WriterGroupConfig {
+                writer_group_id: 1,
+                publishing_interval: 1000,
+                topic: "opcua/json/data/publisher".to_string(),
+                retain: false,
+                data_set_writers: vec![
+                    DataSetWriterConfig {
+                        data_set_writer_id: 10,
+                        fields: vec![
+                            PublishedFieldConfig { name: "v1".to_string(), node_id: "ns=1;s=v1".to_string() },
+                            PublishedFieldConfig { name: "v4".to_string(), node_id: "ns=1;s=v4".to_string() },
+                            PublishedFieldConfig { name: "missing".to_string(), node_id: "ns=1;s=missing".to_string() },
+                        ],
+                    }
+                ],
+            }
+        ],
+    }
+}
+
+#[test]
+fn pubsub_config_invalid() {
+    let pubsub_connection = make_pubsub_connection();
+    assert!(pubsub_connection.is_valid());
+
+    let mut config = ServerBuilder::new_anonymous("foo").pubsub_connection(pubsub_connection.clone()).config();
+    assert!(config.is_valid());
+
+    config.pubsub_connections[0].broker_url = "opc.tcp://localhost:1883".to_string();
+    assert!(!config.is_valid());
+
+    let mut c = pubsub_connection.clone();
+    c.writer_groups[0].topic = "opcua/#".to_string();
+    assert!(!c.is_valid());
+
+    let mut c = pubsub_connection.clone();
+    c.writer_groups[0].publishing_interval = 0;
+    assert!(!c.is_valid());
+
+    let mut c = pubsub_connection.clone();
+    c.writer_groups[0].data_set_writers[0].fields[0].node_id = "xyz".to_string();
+    assert!(!c.is_valid());
+
+    let mut c = pubsub_connection.clone();
+    let writer_group = c.writer_groups[0].clone();
+    c.writer_groups.push(writer_group);
+    assert!(!c.is_valid());
+
+    let mut c = pubsub_connection;
+    c.pass = Some("pass".to_string());
+    assert!(!c.is_valid());
+}
+
+#[test]
+fn broker_host_port() {
+    assert_eq!(mqtt::broker_host_port("mqtt://localhost:1884").unwrap(), ("localhost".to_string(), 1884));
+    assert_eq!(mqtt::broker_host_port("mqtt://localhost").unwrap(), ("localhost".to_string(), mqtt::DEFAULT_MQTT_PORT));
+    assert_eq!(mqtt::broker_host_port("mqtt://localhost/xyz").unwrap(), ("localhost".to_string(), mqtt::DEFAULT_MQTT_PORT));
+    assert!(mqtt::broker_host_port("mqtt://").is_err());
+    assert!(mqtt::broker_host_port("mqtt://localhost:xyz").is_err());
+    assert!(mqtt::broker_host_port("http://localhost:1883").is_err());
+}
+
+#[test]
+fn mqtt_packets() {
+    let connect = mqtt::connect_packet("id", Some("user"), Some("pw")).unwrap();
+    assert_eq!(connect, vec![
+        0x10, 24,
+        0, 4, b'M', b'Q', b'T', b'T', 4, 0xc2, 0, 0,
+        0, 2, b'i', b'd',
+        0, 4, b'u', b's', b'e', b'r',
+        0, 2, b'p', b'w'
+    ]);
+
+    let publish = mqtt::publish_packet("a/b", b"xyz", true).unwrap();
+    assert_eq!(publish, vec![0x31, 8, 0, 3, b'a', b'/', b'b', b'x', b'y', b'z']);
+
+    // Remaining length of 200 takes two bytes
+    let payload = vec![0u8; 195];
+    let publish = mqtt::publish_packet("a/b", &payload, false).unwrap();
+    assert_eq!(&publish[0..3], &[0x30, 0xc8, 0x01]);
+    assert_eq!(publish.len(), 203);
+
+    // Fields are limited to the 65535 bytes their length prefix can hold
+    let topic = "t".repeat(65535);
+    assert_eq!(mqtt::publish_packet(&topic, b"xyz", false).unwrap().len(), 1 + 3 + 2 + 65535 + 3);
+    let topic = "t".repeat(65536);
+    assert_eq!(mqtt::publish_packet(&topic, b"xyz", false).unwrap_err(), StatusCode::BadEncodingLimitsExceeded);
+    let password = "p".repeat(70000);
+    assert_eq!(mqtt::connect_packet("id", Some("user"), Some(&password)).unwrap_err(), StatusCode::BadEncodingLimitsExceeded);
+}
+
+#[test]
+fn variant_json() {
+    use serde_json::json;
+
+    assert_eq!(variant_to_json(&Variant::Empty), Value::Null);
+    assert_eq!(variant_to_json(&Variant::Boolean(true)), json!({"Type": 1, "Body": true}));
+    assert_eq!(variant_to_json(&Variant::Int32(-5)), json!({"Type": 6, "Body": -5}));
+    // 64-bit integers are strings
+    assert_eq!(variant_to_json(&Variant::Int64(-5)), json!({"Type": 8, "Body": "-5"}));
+    assert_eq!(variant_to_json(&Variant::Double(std::f64::NAN)), json!({"Type": 11, "Body": "NaN"}));
+    assert_eq!(variant_to_json(&Variant::from("Hello")), json!({"Type": 12, "Body": "Hello"}));
+    assert_eq!(variant_to_json(&Variant::from(ByteString::from(&[1u8, 2, 3]))), json!({"Type": 15, "Body": "AQID"}));
+    assert_eq!(variant_to_json(&Variant::from(NodeId::new(2, "x"))), json!({"Type": 17, "Body": {"IdType": 1, "Id": "x", "Namespace": 2}}));
+    assert_eq!(variant_to_json(&Variant::from(LocalizedText::new("en", "Hi"))), json!({"Type": 21, "Body": {"Locale": "en", "Text": "Hi"}}));
+    assert_eq!(variant_to_json(&Variant::from(vec![1u16, 2u16])), json!({"Type": 5, "Body": [1, 2]}));
+    let mda = MultiDimensionArray::new(vec![Variant::from(1u8), Variant::from(2u8)], vec![1, 2]);
+    assert_eq!(variant_to_json(&Variant::from(mda)), json!({"Type": 3, "Body": [1, 2], "Dimensions": [1, 2]}));
+}
+
+#[test]
+fn writer_group_network_message() {
+    let address_space = make_sample_address_space();
+    let pubsub_connection = make_pubsub_connection();
+
+    let now = Instant::now();
+    let mut writer_group = WriterGroup::new(&pubsub_connection.writer_groups[0], now);
+    assert!(writer_group.is_due(now));
+
+    let network_message = writer_group.network_message("publisher", &address_space, now);
+    assert!(!writer_group.is_due(now));
+    assert!(writer_group.is_due(now + Duration::from_millis(1000)));
+
+    let json: Value = serde_json::from_slice(&network_message.to_bytes()).unwrap();
+    assert_eq!(json["MessageType"], "ua-data");
+    assert_eq!(json["PublisherId"], "publisher");
+    let message = &json["Messages"][0];
+    assert_eq!(message["DataSetWriterId"], 10);
+    assert_eq!(message["SequenceNumber"], 0);
+    assert_eq!(message["MessageType"], "ua-keyframe");
+    assert_eq!(message["Payload"]["v1"]["Type"], 6);
+    assert_eq!(message["Payload"]["v1"]["Body"], 30);
+    assert_eq!(message["Payload"]["v4"]["Body"], 100.123);
+    // Unknown nodes publish their status code
+    assert_eq!(message["Payload"]["missing"]["Type"], 19);
+    assert_eq!(message["Payload"]["missing"]["Body"], StatusCode::BadNodeIdUnknown.bits());
+
+    // Sequence numbers increment with each message
+    let network_message = writer_group.network_message("publisher", &address_space, now);
+    let json = network_message.to_json();
+    assert_eq!(json["Messages"][0]["SequenceNumber"], 1);
+}
+
+fn make_mqtt_forwarder() -> MqttForwarderConfig {
+    let mut mqtt_forwarder = MqttForwarderConfig::new("mqtt://localhost:1883");
+    mqtt_forwarder.topic_template = "plant/{namespace}/{name}".to_string();
+    mqtt_forwarder.variables = vec![
+        ForwardedVariableConfig { node_id: "ns=1;s=v1".to_string(), name: None },
+        ForwardedVariableConfig { node_id: "ns=1;s=v4".to_string(), name: Some("Temperature".to_string()) },
+        ForwardedVariableConfig { node_id: "ns=1;s=missing".to_string(), name: Some("Missing".to_string()) },
+    ];
+    mqtt_forwarder
+}
+
+#[test]
+fn mqtt_forwarder_config_invalid() {
+    let mqtt_forwarder = make_mqtt_forwarder();
+    let mut config = ServerBuilder::new_anonymous("foo").mqtt_forwarder(mqtt_forwarder.clone()).config();
+    assert!(config.is_valid());
+
+    config.mqtt_forwarders[0].broker_url = "opc.tcp://localhost:1883".to_string();
+    assert!(!config.is_valid());
+
+    let mut c = mqtt_forwarder.clone();
+    c.topic_template = "plant/{unknown}".to_string();
+    assert!(!c.is_valid());
+
+    let mut c = mqtt_forwarder.clone();
+    c.topic_template = "plant/#".to_string();
+    assert!(!c.is_valid());
+
+    let mut c = mqtt_forwarder.clone();
+    c.sampling_interval = 0;
+    assert!(!c.is_valid());
+
+    let mut c = mqtt_forwarder.clone();
+    c.variables[0].node_id = "xyz".to_string();
+    assert!(!c.is_valid());
+
+    let mut c = mqtt_forwarder;
+    c.pass = Some("pass".to_string());
+    assert!(!c.is_valid());
+}
+
+#[test]
+fn mqtt_forwarder_topic() {
+    let node_id = NodeId::new(2, "Temp");
+    assert_eq!(forwarder::topic("opcua/{name}", "Temp", &node_id).unwrap(), "opcua/Temp");
+    assert_eq!(forwarder::topic("{namespace}/{node_id}/value", "Temp", &node_id).unwrap(), "2/ns=2;s=Temp/value");
+    assert_eq!(forwarder::topic("opcua/values", "Temp", &node_id).unwrap(), "opcua/values");
+    assert!(forwarder::topic("opcua/{name", "Temp", &node_id).is_err());
+    assert!(forwarder::topic("opcua/name}", "Temp", &node_id).is_err());
+    assert!(forwarder::topic("opcua/{browse_name}", "Temp", &node_id).is_err());
+    assert!(forwarder::topic("{name}", "", &node_id).is_err());
+    assert!(forwarder::topic("opcua/{name}", "a+b", &node_id).is_err());
+}
+
+#[test]
+fn variant_non_reversible_json() {
+    use serde_json::json;
+
+    assert_eq!(variant_to_non_reversible_json(&Variant::Empty), Value::Null);
+    assert_eq!(variant_to_non_reversible_json(&Variant::Int32(-5)), json!(-5));
+    assert_eq!(variant_to_non_reversible_json(&Variant::from("Hello")), json!("Hello"));
+    assert_eq!(variant_to_non_reversible_json(&Variant::from(vec![1u16, 2u16])), json!([1, 2]));
+}
+
+#[test]
+fn mqtt_forwarder_changes() {
+    let mut address_space = make_sample_address_space();
+    let mqtt_forwarder = make_mqtt_forwarder();
+
+    let now = Instant::now();
+    let mut forwarder = Forwarder::new(&mqtt_forwarder, &address_space, now);
+    assert!(forwarder.is_due(now));
+
+    // Every variable is published on the first sample, to topics named by the config, or by the
+    // browse name of the variable
+    let changes = forwarder.changes(&address_space, now);
+    assert!(!forwarder.is_due(now));
+    assert!(forwarder.is_due(now + Duration::from_millis(1000)));
+    let topics = changes.iter().map(|(topic, _)| topic.as_str()).collect::<Vec<_>>();
+    assert_eq!(topics, vec!["plant/1/v1", "plant/1/Temperature", "plant/1/Missing"]);
+
+    let json: Value = serde_json::from_slice(&changes[0].1).unwrap();
+    assert_eq!(json["Name"], "v1");
+    assert_eq!(json["NodeId"], "ns=1;s=v1");
+    assert_eq!(json["Value"], 30);
+    assert_eq!(json["Status"], 0);
+    assert!(json["SourceTimestamp"].is_string());
+    let json: Value = serde_json::from_slice(&changes[2].1).unwrap();
+    assert_eq!(json["Value"], Value::Null);
+    assert_eq!(json["Status"], StatusCode::BadNodeIdUnknown.bits());
+
+    // Only changes are published after that
+    assert!(forwarder.changes(&address_space, now).is_empty());
+    let now_dt = DateTime::now();
+    let _ = address_space.set_variable_value_by_ref(&NodeId::new(1, "v1"), 31i32, &now_dt, &now_dt);
+    let changes = forwarder.changes(&address_space, now);
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].0, "plant/1/v1");
+
+    // The same value with a new timestamp is not a change
+    let now_dt = DateTime::now();
+    let _ = address_space.set_variable_value_by_ref(&NodeId::new(1, "v1"), 31i32, &now_dt, &now_dt);
+    assert!(forwarder.changes(&address_space, now).is_empty());
+
+    // Everything is published again after a reset, e.g. on reconnecting
+    forwarder.reset();
+    assert_eq!(forwarder.changes(&address_space, now).len(), 3);
+
+    // The reversible encoding holds the type of the value
+    let mut mqtt_forwarder = make_mqtt_forwarder();
+    mqtt_forwarder.payload_encoding = MqttPayloadEncoding::Reversible;
+    let mut forwarder = Forwarder::new(&mqtt_forwarder, &address_space, now);
+    let changes = forwarder.changes(&address_space, now);
+    let json: Value = serde_json::from_slice(&changes[1].1).unwrap();
+    assert_eq!(json["Value"]["Type"], 11);
+    assert_eq!(json["Value"]["Body"], 100.123);
+}
@@ -0,0 +1,260 @@
+use std::collections::BTreeSet;
+
+use futures::Future;
+use serde_json::Value;
+
+use crate::{
+    callbacks::{AccessControl, UserIdentity},
+    config::{RestGatewayConfig, ServerUserToken, ANONYMOUS_USER_TOKEN_ID, ISSUED_TOKEN_USER_TOKEN_ID},
+    http::rest::{RestGateway, browse_description, http_status, json_to_variant, parse_node_id},
+    server::Server,
+};
+
+use super::*;
+
+/// Lets user "admin" write anything, and everyone else read anything but v2
+struct TestAccessControl;
+
+impl AccessControl for TestAccessControl {
+    fn can_read(&self, identity: &UserIdentity, node_id: &NodeId, _attribute_id: AttributeId) -> bool {
+        *identity == UserIdentity::UserName("admin".to_string()) || *node_id != NodeId::new(2, "v2")
+    }
+
+    fn can_write(&self, identity: &UserIdentity, _node_id: &NodeId, _attribute_id: AttributeId) -> bool {
+        *identity == UserIdentity::UserName("admin".to_string())
+    }
+}
+
+fn rest_gateway_config() -> RestGatewayConfig {
+    let mut rest_gateway = RestGatewayConfig::new("127.0.0.1:4880");
+    rest_gateway.user_token_ids.insert("admin".to_string());
+    rest_gateway
+}
+
+fn make_rest_gateway() -> (Server, RestGateway) {
+    let server = ServerBuilder::new_anonymous("foo")
+        .user_token("admin", ServerUserToken::new_user_pass("admin", "secret"))
+        .rest_gateway(rest_gateway_config())
+        .server().unwrap();
+    {
+        let address_space = server.address_space();
+        let mut address_space = address_space.write().unwrap();
+        let folder_id = address_space.add_folder("Rest", "Rest", &AddressSpace::objects_folder_id()).unwrap();
+        let mut v1 = Variable::new(&NodeId::new(2, "v1"), "v1", "v1", 30i32);
+        v1.set_writable(true);
+        let v2 = Variable::new(&NodeId::new(2, "v2"), "v2", "v2", 1.5f64);
+        let _ = address_space.add_variables(vec![v1, v2], &folder_id);
+    }
+    {
+        let server_state = server.server_state();
+        let mut server_state = server_state.write().unwrap();
+        server_state.set_access_control(Box::new(TestAccessControl));
+    }
+    let rest_gateway = RestGateway::new(server.server_state(), server.address_space());
+    (server, rest_gateway)
+}
+
+#[test]
+fn rest_gateway_config_invalid() {
+    let mut config = ServerBuilder::new_anonymous("foo")
+        .user_token("admin", ServerUserToken::new_user_pass("admin", "secret"))
+        .rest_gateway(rest_gateway_config())
+        .config();
+    assert!(config.is_valid());
+
+    config.rest_gateway.as_mut().unwrap().address = "localhost".to_string();
+    assert!(!config.is_valid());
+
+    config.rest_gateway = Some(rest_gateway_config());
+    config.rest_gateway.as_mut().unwrap().user_token_ids.insert("nobody".to_string());
+    assert!(!config.is_valid());
+
+    config.rest_gateway.as_mut().unwrap().user_token_ids = BTreeSet::new();
+    assert!(!config.is_valid());
+
+    config.rest_gateway.as_mut().unwrap().user_token_ids.insert(ISSUED_TOKEN_USER_TOKEN_ID.to_string());
+    assert!(config.is_valid());
+
+    // Credentials are only accepted over plain HTTP on a loopback address
+    config.rest_gateway = Some(rest_gateway_config());
+    config.rest_gateway.as_mut().unwrap().address = "[::1]:4880".to_string();
+    assert!(config.is_valid());
+    config.rest_gateway.as_mut().unwrap().address = "0.0.0.0:4880".to_string();
+    assert!(!config.is_valid());
+    config.rest_gateway = Some(RestGatewayConfig::new("0.0.0.0:4880"));
+    assert!(config.is_valid());
+}
+
+#[test]
+fn rest_parse_node_id() {
+    assert_eq!(parse_node_id("i=85").unwrap(), NodeId::new(0, 85));
+    assert_eq!(parse_node_id("ns=2;s=Demo").unwrap(), NodeId::new(2, "Demo"));
+    assert_eq!(parse_node_id("ns%3D2%3Bs%3DDemo%20Value").unwrap(), NodeId::new(2, "Demo Value"));
+    assert_eq!(parse_node_id("ns=2;x=Demo").unwrap_err(), StatusCode::BadNodeIdInvalid);
+    assert_eq!(parse_node_id("ns=2;s=%zz").unwrap_err(), StatusCode::BadNodeIdInvalid);
+}
+
+#[test]
+fn rest_json_to_variant() {
+    let json = |s: &str| serde_json::from_str::<Value>(s).unwrap();
+    let data_type = |data_type_id: DataTypeId| -> NodeId { data_type_id.into() };
+
+    assert_eq!(json_to_variant(&json("true"), &data_type(DataTypeId::Boolean)).unwrap(), Variant::Boolean(true));
+    assert_eq!(json_to_variant(&json("-5"), &data_type(DataTypeId::SByte)).unwrap(), Variant::SByte(-5));
+    assert_eq!(json_to_variant(&json("300"), &data_type(DataTypeId::Byte)).unwrap_err(), StatusCode::BadTypeMismatch);
+    assert_eq!(json_to_variant(&json("-1"), &data_type(DataTypeId::UInt32)).unwrap_err(), StatusCode::BadTypeMismatch);
+    assert_eq!(json_to_variant(&json("42"), &data_type(DataTypeId::Int32)).unwrap(), Variant::Int32(42));
+    assert_eq!(json_to_variant(&json("42.5"), &data_type(DataTypeId::Int32)).unwrap_err(), StatusCode::BadTypeMismatch);
+    assert_eq!(json_to_variant(&json("42"), &data_type(DataTypeId::Double)).unwrap(), Variant::Double(42f64));
+    assert_eq!(json_to_variant(&json("\"x\""), &data_type(DataTypeId::String)).unwrap(), Variant::from("x"));
+    assert_eq!(json_to_variant(&json("\"2019-04-01T12:00:00Z\""), &data_type(DataTypeId::DateTime)).unwrap(),
+               Variant::from(DateTime::from((2019, 4, 1, 12, 0, 0))));
+    assert_eq!(json_to_variant(&json("[1, 2]"), &data_type(DataTypeId::UInt16)).unwrap(),
+               Variant::Array(vec![Variant::UInt16(1), Variant::UInt16(2)]));
+    assert_eq!(json_to_variant(&json("[1, \"2\"]"), &data_type(DataTypeId::UInt16)).unwrap_err(), StatusCode::BadTypeMismatch);
+    assert_eq!(json_to_variant(&json("7"), &data_type(DataTypeId::BaseDataType)).unwrap(), Variant::Int64(7));
+    assert_eq!(json_to_variant(&json("7.5"), &data_type(DataTypeId::BaseDataType)).unwrap(), Variant::Double(7.5));
+    assert_eq!(json_to_variant(&json("null"), &data_type(DataTypeId::Int32)).unwrap_err(), StatusCode::BadTypeMismatch);
+    assert_eq!(json_to_variant(&json("{}"), &data_type(DataTypeId::Guid)).unwrap_err(), StatusCode::BadTypeMismatch);
+}
+
+#[test]
+fn rest_browse_description() {
+    let json = |s: &str| serde_json::from_str::<Value>(s).unwrap();
+
+    let description = browse_description(&json(r#"{"NodeId": "i=85"}"#)).unwrap();
+    assert_eq!(description.node_id, AddressSpace::objects_folder_id());
+    assert_eq!(description.browse_direction, BrowseDirection::Forward);
+    let reference_type_id: NodeId = ReferenceTypeId::HierarchicalReferences.into();
+    assert_eq!(description.reference_type_id, reference_type_id);
+    assert!(description.include_subtypes);
+
+    let description = browse_description(&json(r#"{"NodeId": "i=85", "BrowseDirection": "Inverse", "ReferenceTypeId": "i=35", "IncludeSubtypes": false}"#)).unwrap();
+    assert_eq!(description.browse_direction, BrowseDirection::Inverse);
+    let reference_type_id: NodeId = ReferenceTypeId::Organizes.into();
+    assert_eq!(description.reference_type_id, reference_type_id);
+    assert!(!description.include_subtypes);
+
+    assert_eq!(browse_description(&json(r#"{}"#)).unwrap_err(), StatusCode::BadNodeIdInvalid);
+    assert_eq!(browse_description(&json(r#"{"NodeId": "i=85", "BrowseDirection": "Sideways"}"#)).unwrap_err(), StatusCode::BadBrowseDirectionInvalid);
+    assert_eq!(browse_description(&json(r#"{"NodeId": "i=85", "ReferenceTypeId": 35}"#)).unwrap_err(), StatusCode::BadReferenceTypeIdInvalid);
+}
+
+#[test]
+fn rest_http_status() {
+    assert_eq!(http_status(StatusCode::Good), 200);
+    assert_eq!(http_status(StatusCode::UncertainLastUsableValue), 200);
+    assert_eq!(http_status(StatusCode::BadTypeMismatch), 400);
+    assert_eq!(http_status(StatusCode::BadIdentityTokenRejected), 401);
+    assert_eq!(http_status(StatusCode::BadUserAccessDenied), 403);
+    assert_eq!(http_status(StatusCode::BadNodeIdUnknown), 404);
+    assert_eq!(http_status(StatusCode::BadTimeout), 504);
+    assert_eq!(http_status(StatusCode::BadInternalError), 500);
+}
+
+#[test]
+fn rest_gateway_read_value() {
+    let (_server, rest_gateway) = make_rest_gateway();
+
+    let value = rest_gateway.read_value(None, NodeId::new(2, "v1")).wait().unwrap();
+    assert_eq!(value.value, Some(Variant::Int32(30)));
+    assert_eq!(value.status(), StatusCode::Good.bits());
+
+    // Access control applies to anonymous requests
+    let value = rest_gateway.read_value(None, NodeId::new(2, "v2")).wait().unwrap();
+    assert_eq!(value.status(), StatusCode::BadUserAccessDenied.bits());
+    let value = rest_gateway.read_value(Some("Basic YWRtaW46c2VjcmV0"), NodeId::new(2, "v2")).wait().unwrap();
+    assert_eq!(value.value, Some(Variant::Double(1.5)));
+
+    let value = rest_gateway.read_value(None, NodeId::new(2, "v3")).wait().unwrap();
+    assert_eq!(value.status(), StatusCode::BadNodeIdUnknown.bits());
+
+    // Bad passwords and unaccepted kinds of user token are rejected
+    assert_eq!(rest_gateway.read_value(Some("Basic YWRtaW46d3Jvbmc="), NodeId::new(2, "v1")).wait().unwrap_err(), StatusCode::BadIdentityTokenRejected);
+    assert_eq!(rest_gateway.read_value(Some("Bearer abc"), NodeId::new(2, "v1")).wait().unwrap_err(), StatusCode::BadIdentityTokenRejected);
+    assert_eq!(rest_gateway.read_value(Some("Digest abc"), NodeId::new(2, "v1")).wait().unwrap_err(), StatusCode::BadIdentityTokenInvalid);
+}
+
+#[test]
+fn rest_gateway_write_value() {
+    let (server, rest_gateway) = make_rest_gateway();
+    let body = serde_json::from_str::<Value>(r#"{"Value": 12}"#).unwrap();
+
+    // Anonymous users may not write
+    assert_eq!(rest_gateway.write_value(None, NodeId::new(2, "v1"), &body).wait().unwrap(), StatusCode::BadUserAccessDenied);
+
+    assert_eq!(rest_gateway.write_value(Some("Basic YWRtaW46c2VjcmV0"), NodeId::new(2, "v1"), &body).wait().unwrap(), StatusCode::Good);
+    {
+        let address_space = server.address_space();
+        let address_space = address_space.read().unwrap();
+        assert_eq!(address_space.find_variable_by_ref(&NodeId::new(2, "v1")).unwrap().value().value, Some(Variant::Int32(12)));
+    }
+
+    // The value must suit the data type and the variable must be writable
+    let body = serde_json::from_str::<Value>(r#"{"Value": "twelve"}"#).unwrap();
+    assert_eq!(rest_gateway.write_value(Some("Basic YWRtaW46c2VjcmV0"), NodeId::new(2, "v1"), &body).wait().unwrap_err(), StatusCode::BadTypeMismatch);
+    let body = serde_json::from_str::<Value>(r#"{"Value": 2.5}"#).unwrap();
+    assert_eq!(rest_gateway.write_value(Some("Basic YWRtaW46c2VjcmV0"), NodeId::new(2, "v2"), &body).wait().unwrap(), StatusCode::BadNotWritable);
+    let body = serde_json::from_str::<Value>(r#"{}"#).unwrap();
+    assert_eq!(rest_gateway.write_value(Some("Basic YWRtaW46c2VjcmV0"), NodeId::new(2, "v1"), &body).wait().unwrap_err(), StatusCode::BadDecodingError);
+}
+
+#[test]
+fn rest_gateway_browse() {
+    let (_server, rest_gateway) = make_rest_gateway();
+    let body = serde_json::from_str::<Value>(r#"{"NodeId": "i=85"}"#).unwrap();
+    let references = rest_gateway.browse(None, &body).unwrap();
+    assert!(references.iter().any(|r| r.browse_name.name.as_ref() == "Rest"));
+
+    let body = serde_json::from_str::<Value>(r#"{"NodeId": "ns=2;s=Nothing"}"#).unwrap();
+    assert_eq!(rest_gateway.browse(None, &body).unwrap_err(), StatusCode::BadNodeIdUnknown);
+}
+
+#[test]
+fn rest_gateway_anonymous_rejected() {
+    let mut rest_gateway_config = rest_gateway_config();
+    rest_gateway_config.user_token_ids.remove(ANONYMOUS_USER_TOKEN_ID);
+    let server = ServerBuilder::new_anonymous("foo")
+        .user_token("admin", ServerUserToken::new_user_pass("admin", "secret"))
+        .rest_gateway(rest_gateway_config)
+        .server().unwrap();
+    let rest_gateway = RestGateway::new(server.server_state(), server.address_space());
+    assert_eq!(rest_gateway.read_value(None, AddressSpace::objects_folder_id()).wait().unwrap_err(), StatusCode::BadIdentityTokenInvalid);
+    assert!(rest_gateway.read_value(Some("Basic YWRtaW46c2VjcmV0"), AddressSpace::objects_folder_id()).wait().is_ok());
+}
+
+#[test]
+fn rest_gateway_credentials_not_loopback() {
+    let (server, rest_gateway) = make_rest_gateway();
+    {
+        let server_state = server.server_state();
+        let server_state = server_state.read().unwrap();
+        let mut config = server_state.config.write().unwrap();
+        config.rest_gateway.as_mut().unwrap().address = "192.168.1.10:4880".to_string();
+    }
+    // The password would be sent in the clear, so it is refused even though it is right
+    assert_eq!(rest_gateway.read_value(Some("Basic YWRtaW46c2VjcmV0"), NodeId::new(2, "v1")).wait().unwrap_err(), StatusCode::BadSecurityModeInsufficient);
+    assert_eq!(http_status(StatusCode::BadSecurityModeInsufficient), 403);
+    assert!(rest_gateway.read_value(None, NodeId::new(2, "v1")).wait().is_ok());
+}
+
+#[test]
+fn rest_gateway_no_sessions() {
+    let (server, rest_gateway) = make_rest_gateway();
+    let session_counts = || {
+        let server_state = server.server_state();
+        let server_state = server_state.read().unwrap();
+        let diagnostics = server_state.diagnostics.read().unwrap();
+        let summary = diagnostics.server_diagnostics_summary();
+        (summary.current_session_count, summary.cumulated_session_count)
+    };
+    let before = session_counts();
+
+    // Requests are served without sessions so they do not count towards the sessions of the server
+    let body = serde_json::from_str::<Value>(r#"{"Value": 12}"#).unwrap();
+    assert!(rest_gateway.read_value(Some("Basic YWRtaW46c2VjcmV0"), NodeId::new(2, "v1")).wait().is_ok());
+    assert!(rest_gateway.write_value(Some("Basic YWRtaW46c2VjcmV0"), NodeId::new(2, "v1"), &body).wait().is_ok());
+    let body = serde_json::from_str::<Value>(r#"{"NodeId": "i=85"}"#).unwrap();
+    assert!(rest_gateway.browse(None, &body).is_ok());
+    assert_eq!(session_counts(), before);
+}
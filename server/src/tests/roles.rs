@@ -0,0 +1,44 @@
+use crate::prelude::*;
+
+use crate::tests::*;
+
+#[test]
+fn role_set_identity_mapping() {
+    let mut role_set = RoleSet::new();
+    assert_eq!(role_set.roles().len(), 8);
+
+    let anonymous: NodeId = WellKnownRole::Anonymous.into();
+    let authenticated_user: NodeId = WellKnownRole::AuthenticatedUser.into();
+    let operator: NodeId = WellKnownRole::Operator.into();
+
+    // Default mappings
+    assert!(role_set.roles_for(&UserIdentity::NotActivated).is_empty());
+    assert_eq!(role_set.roles_for(&UserIdentity::Anonymous), vec![anonymous.clone()]);
+    assert_eq!(role_set.roles_for(&UserIdentity::UserName("operator".to_string())), vec![authenticated_user.clone()]);
+
+    // Map a user onto the operator role
+    let rule = IdentityMappingRuleType::new(IdentityCriteriaType::UserName, "operator");
+    assert!(role_set.add_identity(&operator, rule.clone()).is_ok());
+    assert_eq!(role_set.find_role(&operator).unwrap().identities().len(), 1);
+    assert_eq!(role_set.roles_for(&UserIdentity::UserName("operator".to_string())), vec![authenticated_user.clone(), operator.clone()]);
+    assert_eq!(role_set.roles_for(&UserIdentity::UserName("sample".to_string())), vec![authenticated_user.clone()]);
+
+    // Unknown roles and rules
+    assert_eq!(role_set.add_identity(&NodeId::new(1, "xyz"), rule.clone()).unwrap_err(), StatusCode::BadNodeIdUnknown);
+    assert!(role_set.remove_identity(&operator, &rule).is_ok());
+    assert_eq!(role_set.remove_identity(&operator, &rule).unwrap_err(), StatusCode::BadNoMatch);
+    assert_eq!(role_set.roles_for(&UserIdentity::UserName("operator".to_string())), vec![authenticated_user]);
+}
+
+#[test]
+fn role_set_in_address_space() {
+    let server = ServerBuilder::new_sample().server().unwrap();
+    let address_space = server.address_space();
+    let address_space = address_space.read().unwrap();
+
+    let role_set_id = NodeId::new(0, SERVER_SERVER_CAPABILITIES_ROLE_SET_ID);
+    assert!(address_space.find_node(&role_set_id).is_some());
+    let operator: NodeId = WellKnownRole::Operator.into();
+    let node = address_space.find_node(&operator).unwrap();
+    assert_eq!(node.as_node().browse_name(), QualifiedName::from("Operator"));
+}
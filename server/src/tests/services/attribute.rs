@@ -1,6 +1,6 @@
 use super::*;
 
-use opcua_types::{WriteMask, Variant};
+use opcua_types::{WriteMask, Variant, node_ids::ObjectId};
 
 use crate::{
     services::attribute::AttributeService,
@@ -17,17 +17,18 @@ fn read_value(node_id: &NodeId, attribute_id: AttributeId) -> ReadValueId {
 }
 
 fn do_attribute_service_test<F>(f: F)
-    where F: FnOnce(&mut AddressSpace, &AttributeService)
+    where F: FnOnce(&mut ServerState, &mut Session, &mut AddressSpace, &AttributeService)
 {
     // Set up some nodes
     let st = ServiceTest::new();
+    let (mut server_state, mut session) = st.get_server_state_and_session();
     let mut address_space = st.address_space.write().unwrap();
-    f(&mut address_space, &AttributeService::new())
+    f(&mut server_state, &mut session, &mut address_space, &AttributeService::new())
 }
 
 #[test]
 fn read_test() {
-    do_attribute_service_test(|address_space, ats| {
+    do_attribute_service_test(|server_state, session, address_space, ats| {
         // set up some nodes
         let node_ids = {
             let (_, node_ids) = add_many_vars_to_address_space(address_space, 10);
@@ -59,7 +60,7 @@ fn read_test() {
                 nodes_to_read: Some(nodes_to_read),
             };
 
-            let response = ats.read(&address_space, &request);
+            let response = ats.read(server_state, session, &address_space, &request);
             assert!(response.is_ok());
             let response: ReadResponse = supported_message_as!(response.unwrap(), ReadResponse);
 
@@ -87,7 +88,6 @@ fn read_test() {
 
         // OTHER POTENTIAL TESTS
 
-        // read index range
         // distinguish between read and user read
         // test max_age
         // test timestamps to return Server, Source, None, Both
@@ -105,7 +105,7 @@ fn write_value(node_id: &NodeId, attribute_id: AttributeId, value: DataValue) ->
 
 #[test]
 fn write_test() {
-    do_attribute_service_test(|address_space, ats| {
+    do_attribute_service_test(|server_state, session, address_space, ats| {
         // Set up some nodes
         // Create some variable nodes and modify permissions in the address space so we
         // can see what happens when they are written to.
@@ -164,7 +164,7 @@ fn write_test() {
         };
 
         // do a write with the following write
-        let response = ats.write(address_space, &request);
+        let response = ats.write(server_state, session, address_space, &request);
         assert!(response.is_ok());
         let response: WriteResponse = supported_message_as!(response.unwrap(), WriteResponse);
         let results = response.results.unwrap();
@@ -186,8 +186,813 @@ fn write_test() {
 
         // OTHER POTENTIAL TESTS
 
-        // write index range
         // distinguish between write and user write
         // test max_age
     });
 }
+
+#[test]
+fn write_value_rank_test() {
+    do_attribute_service_test(|server_state, session, address_space, ats| {
+        // A 2x2 matrix, and a scalar
+        let (folder_id, node_ids) = add_many_vars_to_address_space(address_space, 1);
+        let matrix_id = NodeId::new(1, "matrix");
+        let _ = address_space.add_variables(vec![Variable::new(&matrix_id, "matrix", "", Variant::from_matrix(vec![vec![1i32, 2], vec![3, 4]]).unwrap())], &folder_id);
+        for node_id in &[matrix_id.clone(), node_ids[0].clone()] {
+            let node = address_space.find_node_mut(node_id).unwrap();
+            let _ = node.as_mut_node().set_attribute(AttributeId::AccessLevel, Variant::from(AccessLevel::CURRENT_WRITE.bits())).unwrap();
+        }
+        if let Some(NodeType::Variable(variable)) = address_space.find_node(&matrix_id) {
+            assert_eq!(variable.value_rank(), 2);
+            assert_eq!(variable.array_dimensions(), Some(vec![2, 2]));
+        } else {
+            panic!("matrix is not a variable");
+        }
+
+        let nodes_to_write = vec![
+            // 1. a matrix of the same dimensions
+            write_value(&matrix_id, AttributeId::Value, DataValue::new(Variant::from_matrix(vec![vec![5i32, 6], vec![7, 8]]).unwrap())),
+            // 2. a smaller matrix
+            write_value(&matrix_id, AttributeId::Value, DataValue::new(Variant::from_matrix(vec![vec![5i32, 6]]).unwrap())),
+            // 3. a matrix exceeding the array dimensions
+            write_value(&matrix_id, AttributeId::Value, DataValue::new(Variant::from_matrix(vec![vec![5i32, 6], vec![7, 8], vec![9, 10]]).unwrap())),
+            // 4. a scalar to the matrix
+            write_value(&matrix_id, AttributeId::Value, DataValue::new(5i32)),
+            // 5. an array to the scalar
+            write_value(&node_ids[0], AttributeId::Value, DataValue::new(vec![1i32, 2])),
+            // 6. array dimensions that do not match the value rank
+            write_value(&matrix_id, AttributeId::ArrayDimensions, DataValue::new(vec![4u32])),
+        ];
+        {
+            let node = address_space.find_node_mut(&matrix_id).unwrap();
+            node.as_mut_node().set_write_mask(WriteMask::ARRAY_DIMENSIONS);
+        }
+
+        let request = WriteRequest {
+            request_header: make_request_header(),
+            nodes_to_write: Some(nodes_to_write),
+        };
+        let response = ats.write(server_state, session, address_space, &request);
+        let response: WriteResponse = supported_message_as!(response.unwrap(), WriteResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0], StatusCode::Good);
+        assert_eq!(results[1], StatusCode::Good);
+        assert_eq!(results[2], StatusCode::BadTypeMismatch);
+        assert_eq!(results[3], StatusCode::BadTypeMismatch);
+        assert_eq!(results[4], StatusCode::BadTypeMismatch);
+        assert_eq!(results[5], StatusCode::BadTypeMismatch);
+    });
+}
+
+#[test]
+fn index_range_test() {
+    do_attribute_service_test(|server_state, session, address_space, ats| {
+        let (folder_id, _) = add_many_vars_to_address_space(address_space, 1);
+        let array_id = NodeId::new(1, "array");
+        let matrix_id = NodeId::new(1, "matrix");
+        let _ = address_space.add_variables(vec![
+            Variable::new(&array_id, "array", "", vec![1i32, 2, 3, 4, 5]),
+            Variable::new(&matrix_id, "matrix", "", Variant::from_matrix(vec![vec![1i32, 2], vec![3, 4]]).unwrap()),
+        ], &folder_id);
+        for node_id in &[array_id.clone(), matrix_id.clone()] {
+            let node = address_space.find_node_mut(node_id).unwrap();
+            let _ = node.as_mut_node().set_attribute(AttributeId::AccessLevel, Variant::from((AccessLevel::CURRENT_READ | AccessLevel::CURRENT_WRITE).bits())).unwrap();
+        }
+
+        let read_range = |node_id: &NodeId, index_range: &str| {
+            let mut read_value = read_value(node_id, AttributeId::Value);
+            read_value.index_range = UAString::from(index_range);
+            read_value
+        };
+        let write_range = |node_id: &NodeId, index_range: &str, value: Variant| {
+            let mut write_value = write_value(node_id, AttributeId::Value, DataValue::new(value));
+            write_value.index_range = UAString::from(index_range);
+            write_value
+        };
+
+        let request = ReadRequest {
+            request_header: make_request_header(),
+            max_age: 0f64,
+            timestamps_to_return: TimestampsToReturn::Both,
+            nodes_to_read: Some(vec![
+                read_range(&array_id, "1:3"),
+                read_range(&array_id, "3:10"),
+                read_range(&array_id, "5:10"),
+                read_range(&array_id, "3:1"),
+                read_range(&matrix_id, "1,0:1"),
+            ]),
+        };
+        let response = ats.read(server_state, session, &address_space, &request);
+        let response: ReadResponse = supported_message_as!(response.unwrap(), ReadResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].value, Some(Variant::from(vec![2i32, 3, 4])));
+        // The range is trimmed to the end of the array
+        assert_eq!(results[1].value, Some(Variant::from(vec![4i32, 5])));
+        assert_eq!(results[2].status, Some(StatusCode::BadIndexRangeNoData.bits()));
+        assert_eq!(results[3].status, Some(StatusCode::BadIndexRangeInvalid.bits()));
+        assert_eq!(results[4].value, Some(Variant::from_matrix(vec![vec![3i32, 4]]).unwrap()));
+
+        let request = WriteRequest {
+            request_header: make_request_header(),
+            nodes_to_write: Some(vec![
+                write_range(&array_id, "1:2", Variant::from(vec![20i32, 30])),
+                write_range(&array_id, "4:5", Variant::from(vec![50i32, 60])),
+                write_range(&array_id, "0:2", Variant::from(vec![10i32])),
+                write_range(&array_id, "0", Variant::from(vec![true])),
+                write_range(&array_id, "x", Variant::from(vec![10i32])),
+                write_range(&matrix_id, "0:1,1", Variant::from_matrix(vec![vec![20i32], vec![40]]).unwrap()),
+            ]),
+        };
+        let response = ats.write(server_state, session, address_space, &request);
+        let response: WriteResponse = supported_message_as!(response.unwrap(), WriteResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0], StatusCode::Good);
+        assert_eq!(results[1], StatusCode::BadIndexRangeNoData);
+        assert_eq!(results[2], StatusCode::BadIndexRangeInvalid);
+        assert_eq!(results[3], StatusCode::BadTypeMismatch);
+        assert_eq!(results[4], StatusCode::BadIndexRangeInvalid);
+        assert_eq!(results[5], StatusCode::Good);
+
+        // Only the elements in the ranges were written
+        if let Some(NodeType::Variable(variable)) = address_space.find_node(&array_id) {
+            assert_eq!(variable.value().value, Some(Variant::from(vec![1i32, 20, 30, 4, 5])));
+        } else {
+            panic!("array is not a variable");
+        }
+        if let Some(NodeType::Variable(variable)) = address_space.find_node(&matrix_id) {
+            assert_eq!(variable.value().value, Some(Variant::from_matrix(vec![vec![1i32, 20], vec![3, 40]]).unwrap()));
+        } else {
+            panic!("matrix is not a variable");
+        }
+    });
+}
+
+#[test]
+fn write_data_access_items_test() {
+    do_attribute_service_test(|server_state, session, address_space, ats| {
+        let (folder_id, _) = add_many_vars_to_address_space(address_space, 1);
+        let analog_id = NodeId::new(1, "analog");
+        let two_state_id = NodeId::new(1, "two_state");
+        let multi_state_id = NodeId::new(1, "multi_state");
+        AnalogItemBuilder::new(&analog_id, "analog", "", 50f64)
+            .eu_range(0f64, 100f64)
+            .writable()
+            .insert(address_space, &folder_id).unwrap();
+        TwoStateDiscreteBuilder::new(&two_state_id, "two_state", "", "Open", "Closed")
+            .writable()
+            .insert(address_space, &folder_id).unwrap();
+        MultiStateDiscreteBuilder::new(&multi_state_id, "multi_state", "", vec![LocalizedText::from("Low"), LocalizedText::from("Medium"), LocalizedText::from("High")])
+            .writable()
+            .insert(address_space, &folder_id).unwrap();
+
+        // The properties and type definitions are added with the items
+        assert_eq!(address_space.eu_range(&analog_id), Some((0f64, 100f64)));
+        assert_eq!(address_space.enum_strings(&multi_state_id).unwrap().len(), 3);
+        assert!(address_space.has_reference(&analog_id, &VariableTypeId::AnalogItemType.into(), ReferenceTypeId::HasTypeDefinition));
+        assert!(address_space.has_reference(&two_state_id, &VariableTypeId::TwoStateDiscreteType.into(), ReferenceTypeId::HasTypeDefinition));
+
+        // An analog item needs an EURange, and an item cannot be inserted twice
+        assert!(AnalogItemBuilder::new(&NodeId::new(1, "no_range"), "no_range", "", 0f64).insert(address_space, &folder_id).is_err());
+        assert!(TwoStateDiscreteBuilder::new(&two_state_id, "two_state", "", "Open", "Closed").insert(address_space, &folder_id).is_err());
+
+        let nodes_to_write = vec![
+            // 1. a value in the EURange
+            write_value(&analog_id, AttributeId::Value, DataValue::new(100f64)),
+            // 2. a value out of the EURange
+            write_value(&analog_id, AttributeId::Value, DataValue::new(100.5f64)),
+            // 3. an array, which does not match the scalar value rank of the item
+            write_value(&analog_id, AttributeId::Value, DataValue::new(vec![1f64, -1f64])),
+            // 4. a two-state item
+            write_value(&two_state_id, AttributeId::Value, DataValue::new(true)),
+            // 5. the index of a state
+            write_value(&multi_state_id, AttributeId::Value, DataValue::new(2u32)),
+            // 6. not the index of a state
+            write_value(&multi_state_id, AttributeId::Value, DataValue::new(3u32)),
+        ];
+        let request = WriteRequest {
+            request_header: make_request_header(),
+            nodes_to_write: Some(nodes_to_write),
+        };
+        let response = ats.write(server_state, session, address_space, &request);
+        let response: WriteResponse = supported_message_as!(response.unwrap(), WriteResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0], StatusCode::Good);
+        assert_eq!(results[1], StatusCode::BadOutOfRange);
+        assert_eq!(results[2], StatusCode::BadTypeMismatch);
+        assert_eq!(results[3], StatusCode::Good);
+        assert_eq!(results[4], StatusCode::Good);
+        assert_eq!(results[5], StatusCode::BadOutOfRange);
+        assert_eq!(address_space.find_variable(analog_id).unwrap().value().value, Some(Variant::Double(100f64)));
+    });
+}
+
+/// Lets user "admin" do anything, and everyone else read anything but v1
+struct TestAccessControl;
+
+impl AccessControl for TestAccessControl {
+    fn can_read(&self, identity: &UserIdentity, node_id: &NodeId, _attribute_id: AttributeId) -> bool {
+        *identity == UserIdentity::UserName("admin".to_string()) || *node_id != NodeId::new(1, "v1")
+    }
+
+    fn can_write(&self, identity: &UserIdentity, _node_id: &NodeId, _attribute_id: AttributeId) -> bool {
+        *identity == UserIdentity::UserName("admin".to_string())
+    }
+}
+
+#[test]
+fn access_control_test() {
+    do_attribute_service_test(|server_state, session, address_space, ats| {
+        let (_, node_ids) = add_many_vars_to_address_space(address_space, 2);
+        let node = address_space.find_node_mut(&node_ids[0]).unwrap();
+        let _ = node.as_mut_node().set_attribute(AttributeId::AccessLevel, Variant::from((AccessLevel::CURRENT_READ | AccessLevel::CURRENT_WRITE).bits())).unwrap();
+
+        server_state.set_access_control(Box::new(TestAccessControl));
+
+        let read_request = ReadRequest {
+            request_header: make_request_header(),
+            max_age: 0f64,
+            timestamps_to_return: TimestampsToReturn::Both,
+            nodes_to_read: Some(vec![read_value(&node_ids[0], AttributeId::Value), read_value(&node_ids[1], AttributeId::Value)]),
+        };
+        let write_request = WriteRequest {
+            request_header: make_request_header(),
+            nodes_to_write: Some(vec![write_value(&node_ids[0], AttributeId::Value, DataValue::new(10i32))]),
+        };
+
+        // Anonymous user
+        session.identity = UserIdentity::Anonymous;
+        let response: ReadResponse = supported_message_as!(ats.read(server_state, session, address_space, &read_request).unwrap(), ReadResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].status, Some(StatusCode::Good.bits()));
+        assert_eq!(results[1].status, Some(StatusCode::BadUserAccessDenied.bits()));
+        assert!(results[1].value.is_none());
+        let response: WriteResponse = supported_message_as!(ats.write(server_state, session, address_space, &write_request).unwrap(), WriteResponse);
+        assert_eq!(response.results.unwrap()[0], StatusCode::BadUserAccessDenied);
+
+        // Admin user
+        session.identity = UserIdentity::UserName("admin".to_string());
+        let response: ReadResponse = supported_message_as!(ats.read(server_state, session, address_space, &read_request).unwrap(), ReadResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[1].status, Some(StatusCode::Good.bits()));
+        let response: WriteResponse = supported_message_as!(ats.write(server_state, session, address_space, &write_request).unwrap(), WriteResponse);
+        assert_eq!(response.results.unwrap()[0], StatusCode::Good);
+    });
+}
+
+#[test]
+fn async_getter_setter_test() {
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use futures::future;
+    use tokio::runtime::current_thread::Runtime;
+
+    do_attribute_service_test(|server_state, session, address_space, ats| {
+        let (_, node_ids) = add_many_vars_to_address_space(address_space, 4);
+        let node = address_space.find_node_mut(&node_ids[0]).unwrap().as_mut_node();
+        let _ = node.set_attribute(AttributeId::AccessLevel, Variant::from((AccessLevel::CURRENT_READ | AccessLevel::CURRENT_WRITE).bits())).unwrap();
+
+        // A getter with a value, one which never finishes and one which fails
+        address_space.set_variable_async_getter(node_ids[0].clone(), |_, _, _| Ok::<_, StatusCode>(Some(DataValue::new(99i32))));
+        address_space.set_variable_async_getter(node_ids[1].clone(), |_, _, _| future::empty::<Option<DataValue>, StatusCode>());
+        address_space.set_variable_async_getter(node_ids[2].clone(), |_, _, _| Err::<Option<DataValue>, _>(StatusCode::BadCommunicationError));
+        let written = Arc::new(Mutex::new(None));
+        {
+            let written = written.clone();
+            address_space.set_variable_async_setter(node_ids[0].clone(), move |_, _, data_value| {
+                *written.lock().unwrap() = data_value.value;
+                Ok::<_, StatusCode>(())
+            });
+        }
+
+        let timeout = Duration::from_millis(100);
+        let mut runtime = Runtime::new().unwrap();
+
+        let read_request = ReadRequest {
+            request_header: make_request_header(),
+            max_age: 0f64,
+            timestamps_to_return: TimestampsToReturn::Both,
+            nodes_to_read: Some(node_ids.iter().map(|node_id| read_value(node_id, AttributeId::Value)).collect()),
+        };
+        let response: ReadResponse = supported_message_as!(ats.read(server_state, session, address_space, &read_request).unwrap(), ReadResponse);
+        // The immediate response holds the values of the variables
+        assert_eq!(response.results.as_ref().unwrap()[0].value, Some(Variant::from(0i32)));
+        let async_response = ats.async_read(session, address_space, &read_request, &response, timeout).unwrap();
+        let response: ReadResponse = supported_message_as!(runtime.block_on(async_response).unwrap(), ReadResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].value, Some(Variant::from(99i32)));
+        assert_eq!(results[1].status, Some(StatusCode::BadTimeout.bits()));
+        assert_eq!(results[2].status, Some(StatusCode::BadCommunicationError.bits()));
+        // No async getter
+        assert_eq!(results[3].value, Some(Variant::from(3i32)));
+
+        let write_request = WriteRequest {
+            request_header: make_request_header(),
+            nodes_to_write: Some(vec![write_value(&node_ids[0], AttributeId::Value, DataValue::new(10i32))]),
+        };
+        let response: WriteResponse = supported_message_as!(ats.write(server_state, session, address_space, &write_request).unwrap(), WriteResponse);
+        let async_response = ats.async_write(session, address_space, &write_request, &response, timeout).unwrap();
+        let response: WriteResponse = supported_message_as!(runtime.block_on(async_response).unwrap(), WriteResponse);
+        assert_eq!(response.results.unwrap()[0], StatusCode::Good);
+        assert_eq!(*written.lock().unwrap(), Some(Variant::from(10i32)));
+
+        // Nothing to wait for without async getters
+        let read_request = ReadRequest {
+            request_header: make_request_header(),
+            max_age: 0f64,
+            timestamps_to_return: TimestampsToReturn::Both,
+            nodes_to_read: Some(vec![read_value(&node_ids[3], AttributeId::Value)]),
+        };
+        let response: ReadResponse = supported_message_as!(ats.read(server_state, session, address_space, &read_request).unwrap(), ReadResponse);
+        assert!(ats.async_read(session, address_space, &read_request, &response, timeout).is_none());
+    });
+}
+
+#[test]
+fn value_provider_test() {
+    use std::sync::Mutex;
+
+    struct BlockReader {
+        batches: Arc<Mutex<Vec<Vec<NodeId>>>>,
+    }
+
+    impl NodeValueProvider for BlockReader {
+        fn read_values(&mut self, node_ids: &[NodeId], _max_age: f64) -> Vec<Result<DataValue, StatusCode>> {
+            self.batches.lock().unwrap().push(node_ids.to_vec());
+            node_ids.iter().map(|node_id| {
+                if *node_id == var_node_id(2) {
+                    Err(StatusCode::BadNoCommunication)
+                } else {
+                    Ok(DataValue::new(100i32))
+                }
+            }).collect()
+        }
+    }
+
+    do_attribute_service_test(|server_state, session, address_space, ats| {
+        let (_, node_ids) = add_many_vars_to_address_space(address_space, 4);
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        address_space.register_value_provider(&node_ids[0..3], Box::new(BlockReader { batches: batches.clone() }));
+        assert!(address_space.has_value_provider(&node_ids[0]));
+        assert!(!address_space.has_value_provider(&node_ids[3]));
+
+        let request = ReadRequest {
+            request_header: make_request_header(),
+            max_age: 0f64,
+            timestamps_to_return: TimestampsToReturn::Both,
+            nodes_to_read: Some(vec![
+                read_value(&node_ids[0], AttributeId::Value),
+                read_value(&node_ids[1], AttributeId::Value),
+                read_value(&node_ids[2], AttributeId::Value),
+                read_value(&node_ids[3], AttributeId::Value),
+                read_value(&node_ids[0], AttributeId::DisplayName),
+            ]),
+        };
+        let response: ReadResponse = supported_message_as!(ats.read(server_state, session, address_space, &request).unwrap(), ReadResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].value, Some(Variant::from(100i32)));
+        assert_eq!(results[1].value, Some(Variant::from(100i32)));
+        assert_eq!(results[2].status, Some(StatusCode::BadNoCommunication.bits()));
+        // Not provided, or not the value
+        assert_eq!(results[3].value, Some(Variant::from(3i32)));
+        assert!(results[4].value.is_some());
+
+        // The provider was asked for its values in one batch
+        let batches = batches.lock().unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].len(), 3);
+    });
+}
+
+#[test]
+fn role_permissions_test() {
+    do_attribute_service_test(|server_state, session, address_space, ats| {
+        let (_, node_ids) = add_many_vars_to_address_space(address_space, 1);
+        let operator: NodeId = WellKnownRole::Operator.into();
+        {
+            let node = address_space.find_node_mut(&node_ids[0]).unwrap().as_mut_node();
+            let _ = node.set_attribute(AttributeId::AccessLevel, Variant::from((AccessLevel::CURRENT_READ | AccessLevel::CURRENT_WRITE).bits())).unwrap();
+            node.set_role_permissions(vec![
+                RolePermissionType::new(WellKnownRole::AuthenticatedUser, PermissionType::BROWSE | PermissionType::READ),
+                RolePermissionType::new(WellKnownRole::Operator, PermissionType::BROWSE | PermissionType::READ | PermissionType::WRITE),
+            ]);
+        }
+
+        let read_request = ReadRequest {
+            request_header: make_request_header(),
+            max_age: 0f64,
+            timestamps_to_return: TimestampsToReturn::Both,
+            nodes_to_read: Some(vec![read_value(&node_ids[0], AttributeId::Value), read_value(&node_ids[0], AttributeId::UserRolePermissions)]),
+        };
+        let write_request = WriteRequest {
+            request_header: make_request_header(),
+            nodes_to_write: Some(vec![write_value(&node_ids[0], AttributeId::Value, DataValue::new(10i32))]),
+        };
+
+        // Anonymous users have no permissions on the node
+        session.roles = server_state.roles_for(&UserIdentity::Anonymous);
+        let response: ReadResponse = supported_message_as!(ats.read(server_state, session, address_space, &read_request).unwrap(), ReadResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].status, Some(StatusCode::BadUserAccessDenied.bits()));
+        assert_eq!(results[1].status, Some(StatusCode::BadUserAccessDenied.bits()));
+
+        // Authenticated users can read but not write
+        session.roles = server_state.roles_for(&UserIdentity::UserName("sample".to_string()));
+        let response: ReadResponse = supported_message_as!(ats.read(server_state, session, address_space, &read_request).unwrap(), ReadResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].status, Some(StatusCode::Good.bits()));
+        if let Some(Variant::Array(ref values)) = results[1].value {
+            assert_eq!(values.len(), 1);
+        } else {
+            panic!("UserRolePermissions is not an array");
+        }
+        let response: WriteResponse = supported_message_as!(ats.write(server_state, session, address_space, &write_request).unwrap(), WriteResponse);
+        assert_eq!(response.results.unwrap()[0], StatusCode::BadUserAccessDenied);
+
+        // Operators can write
+        {
+            let role_set = server_state.role_set.clone();
+            let mut role_set = role_set.write().unwrap();
+            let _ = role_set.add_identity(&operator, IdentityMappingRuleType::new(IdentityCriteriaType::UserName, "sample")).unwrap();
+        }
+        session.roles = server_state.roles_for(&UserIdentity::UserName("sample".to_string()));
+        let response: WriteResponse = supported_message_as!(ats.write(server_state, session, address_space, &write_request).unwrap(), WriteResponse);
+        assert_eq!(response.results.unwrap()[0], StatusCode::Good);
+    });
+}
+
+/// Provides the same number of raw values for any node and accepts any update of data
+struct TestHistoricalDataProvider {
+    num_values: usize,
+}
+
+impl HistoricalDataProvider for TestHistoricalDataProvider {
+    fn read_raw_modified_details(&mut self, _node_id: &NodeId, _details: &ReadRawModifiedDetails, _timestamps_to_return: TimestampsToReturn) -> Result<Vec<DataValue>, StatusCode> {
+        Ok((0..self.num_values).map(|i| DataValue::new(i as i32)).collect())
+    }
+
+    fn update_data_details(&mut self, details: &UpdateDataDetails) -> Result<Vec<StatusCode>, StatusCode> {
+        let update_values = details.update_values.as_ref().map(|v| v.len()).unwrap_or(0);
+        Ok(vec![StatusCode::GoodEntryInserted; update_values])
+    }
+
+    fn delete_raw_modified_details(&mut self, _details: &DeleteRawModifiedDetails) -> Result<(), StatusCode> {
+        Ok(())
+    }
+}
+
+fn do_history_read_test<F>(f: F)
+    where F: FnOnce(&mut ServerState, &mut Session, &mut AddressSpace, &AttributeService)
+{
+    let st = ServiceTest::new();
+    let (mut server_state, mut session) = st.get_server_state_and_session();
+    let mut address_space = st.address_space.write().unwrap();
+    f(&mut server_state, &mut session, &mut address_space, &AttributeService::new())
+}
+
+fn history_read_value_id(node_id: &NodeId, continuation_point: ByteString) -> HistoryReadValueId {
+    HistoryReadValueId {
+        node_id: node_id.clone(),
+        index_range: UAString::null(),
+        data_encoding: QualifiedName::null(),
+        continuation_point,
+    }
+}
+
+fn read_raw_details(num_values_per_node: u32) -> ExtensionObject {
+    let details = ReadRawModifiedDetails {
+        is_read_modified: false,
+        start_time: DateTime::ymd(2019, 1, 1),
+        end_time: DateTime::now(),
+        num_values_per_node,
+        return_bounds: false,
+    };
+    ExtensionObject::from_encodable(ObjectId::ReadRawModifiedDetails_Encoding_DefaultBinary, &details)
+}
+
+fn history_read_request(history_read_details: ExtensionObject, release_continuation_points: bool, nodes_to_read: Vec<HistoryReadValueId>) -> HistoryReadRequest {
+    HistoryReadRequest {
+        request_header: make_request_header(),
+        history_read_details,
+        timestamps_to_return: TimestampsToReturn::Both,
+        release_continuation_points,
+        nodes_to_read: Some(nodes_to_read),
+    }
+}
+
+fn history_data_values(result: &HistoryReadResult) -> Vec<DataValue> {
+    let history_data = result.history_data.decode_inner::<HistoryData>(&DecodingLimits::default()).unwrap();
+    history_data.data_values.unwrap()
+}
+
+fn make_history_readable(address_space: &mut AddressSpace, node_id: &NodeId) {
+    set_access_level(address_space, node_id, AccessLevel::CURRENT_READ | AccessLevel::HISTORY_READ);
+}
+
+fn set_access_level(address_space: &mut AddressSpace, node_id: &NodeId, access_level: AccessLevel) {
+    if let NodeType::Variable(ref mut v) = *address_space.find_node_mut(node_id).unwrap() {
+        v.set_access_level(access_level);
+    }
+}
+
+#[test]
+fn history_read_nothing_to_do() {
+    do_history_read_test(|server_state, session, address_space, ats| {
+        let request = history_read_request(read_raw_details(0), false, vec![]);
+        let response = ats.history_read(server_state, session, address_space, &request);
+        let response: ServiceFault = supported_message_as!(response.unwrap(), ServiceFault);
+        assert_eq!(response.response_header.service_result, StatusCode::BadNothingToDo);
+    });
+}
+
+#[test]
+fn history_read_invalid_details() {
+    do_history_read_test(|server_state, session, address_space, ats| {
+        let (_, node_ids) = add_many_vars_to_address_space(address_space, 1);
+        make_history_readable(address_space, &node_ids[0]);
+
+        // Details that are not history read details at all
+        let request = history_read_request(ExtensionObject::null(), false, vec![history_read_value_id(&node_ids[0], ByteString::null())]);
+        let response = ats.history_read(server_state, session, address_space, &request);
+        let response: ServiceFault = supported_message_as!(response.unwrap(), ServiceFault);
+        assert_eq!(response.response_header.service_result, StatusCode::BadHistoryOperationInvalid);
+
+        // Processed details where the aggregates do not match the nodes
+        let details = ReadProcessedDetails {
+            start_time: DateTime::ymd(2019, 1, 1),
+            end_time: DateTime::now(),
+            processing_interval: 1000f64,
+            aggregate_type: None,
+            aggregate_configuration: AggregateConfiguration {
+                use_server_capabilities_defaults: true,
+                treat_uncertain_as_bad: false,
+                percent_data_bad: 0,
+                percent_data_good: 0,
+                use_sloped_extrapolation: false,
+            },
+        };
+        let details = ExtensionObject::from_encodable(ObjectId::ReadProcessedDetails_Encoding_DefaultBinary, &details);
+        let request = history_read_request(details, false, vec![history_read_value_id(&node_ids[0], ByteString::null())]);
+        let response = ats.history_read(server_state, session, address_space, &request);
+        let response: ServiceFault = supported_message_as!(response.unwrap(), ServiceFault);
+        assert_eq!(response.response_header.service_result, StatusCode::BadAggregateListMismatch);
+    });
+}
+
+#[test]
+fn history_read_raw() {
+    do_history_read_test(|server_state, session, address_space, ats| {
+        let (_, node_ids) = add_many_vars_to_address_space(address_space, 2);
+        // Only the first var has history read access
+        make_history_readable(address_space, &node_ids[0]);
+
+        let nodes_to_read = vec![
+            // 1. a variable with history read access
+            history_read_value_id(&node_ids[0], ByteString::null()),
+            // 2. a variable without history read access
+            history_read_value_id(&node_ids[1], ByteString::null()),
+            // 3. a non existent variable
+            history_read_value_id(&NodeId::new(1, "vxxx"), ByteString::null()),
+        ];
+
+        // No provider so the operation is unsupported
+        let request = history_read_request(read_raw_details(10), false, nodes_to_read.clone());
+        let response = ats.history_read(server_state, session, address_space, &request);
+        let response: HistoryReadResponse = supported_message_as!(response.unwrap(), HistoryReadResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].status_code, StatusCode::BadHistoryOperationUnsupported);
+        assert_eq!(results[1].status_code, StatusCode::BadNotReadable);
+        assert_eq!(results[2].status_code, StatusCode::BadNodeIdUnknown);
+
+        server_state.set_historical_data_provider(Box::new(TestHistoricalDataProvider { num_values: 25 }));
+
+        // First read returns 10 values and a continuation point
+        let response = ats.history_read(server_state, session, address_space, &request);
+        let response: HistoryReadResponse = supported_message_as!(response.unwrap(), HistoryReadResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].status_code, StatusCode::Good);
+        assert_eq!(history_data_values(&results[0]).len(), 10);
+        assert!(!results[0].continuation_point.is_null());
+        assert_eq!(results[1].status_code, StatusCode::BadNotReadable);
+        assert_eq!(results[2].status_code, StatusCode::BadNodeIdUnknown);
+
+        // Continue, 10 more values and another continuation point
+        let continuation_point = results[0].continuation_point.clone();
+        let request = history_read_request(read_raw_details(10), false, vec![history_read_value_id(&node_ids[0], continuation_point.clone())]);
+        let response = ats.history_read(server_state, session, address_space, &request);
+        let response: HistoryReadResponse = supported_message_as!(response.unwrap(), HistoryReadResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].status_code, StatusCode::Good);
+        let values = history_data_values(&results[0]);
+        assert_eq!(values.len(), 10);
+        assert_eq!(values[0].value, Some(Variant::Int32(10)));
+        assert!(!results[0].continuation_point.is_null());
+
+        // The old continuation point has been used up
+        let response = ats.history_read(server_state, session, address_space, &request);
+        let response: HistoryReadResponse = supported_message_as!(response.unwrap(), HistoryReadResponse);
+        assert_eq!(response.results.unwrap()[0].status_code, StatusCode::BadContinuationPointInvalid);
+
+        // Continue, the last 5 values and no continuation point
+        let request = history_read_request(read_raw_details(10), false, vec![history_read_value_id(&node_ids[0], results[0].continuation_point.clone())]);
+        let response = ats.history_read(server_state, session, address_space, &request);
+        let response: HistoryReadResponse = supported_message_as!(response.unwrap(), HistoryReadResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].status_code, StatusCode::Good);
+        let values = history_data_values(&results[0]);
+        assert_eq!(values.len(), 5);
+        assert_eq!(values[4].value, Some(Variant::Int32(24)));
+        assert!(results[0].continuation_point.is_null());
+    });
+}
+
+#[test]
+fn history_read_release_continuation_points() {
+    do_history_read_test(|server_state, session, address_space, ats| {
+        let (_, node_ids) = add_many_vars_to_address_space(address_space, 1);
+        make_history_readable(address_space, &node_ids[0]);
+        server_state.set_historical_data_provider(Box::new(TestHistoricalDataProvider { num_values: 25 }));
+
+        let request = history_read_request(read_raw_details(10), false, vec![history_read_value_id(&node_ids[0], ByteString::null())]);
+        let response = ats.history_read(server_state, session, address_space, &request);
+        let response: HistoryReadResponse = supported_message_as!(response.unwrap(), HistoryReadResponse);
+        let continuation_point = response.results.unwrap()[0].continuation_point.clone();
+        assert!(!continuation_point.is_null());
+
+        // Release the continuation point
+        let request = history_read_request(read_raw_details(10), true, vec![history_read_value_id(&node_ids[0], continuation_point.clone())]);
+        let response = ats.history_read(server_state, session, address_space, &request);
+        let response: HistoryReadResponse = supported_message_as!(response.unwrap(), HistoryReadResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].status_code, StatusCode::Good);
+        assert!(results[0].continuation_point.is_null());
+
+        // The released point cannot be used
+        let request = history_read_request(read_raw_details(10), false, vec![history_read_value_id(&node_ids[0], continuation_point)]);
+        let response = ats.history_read(server_state, session, address_space, &request);
+        let response: HistoryReadResponse = supported_message_as!(response.unwrap(), HistoryReadResponse);
+        assert_eq!(response.results.unwrap()[0].status_code, StatusCode::BadContinuationPointInvalid);
+    });
+}
+
+/// Takes longer to provide the values of a node than the client allows for the whole read
+struct SlowHistoricalDataProvider;
+
+impl HistoricalDataProvider for SlowHistoricalDataProvider {
+    fn read_raw_modified_details(&mut self, _node_id: &NodeId, _details: &ReadRawModifiedDetails, _timestamps_to_return: TimestampsToReturn) -> Result<Vec<DataValue>, StatusCode> {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        Ok(vec![DataValue::new(1i32)])
+    }
+}
+
+#[test]
+fn history_read_timeout() {
+    do_history_read_test(|server_state, session, address_space, ats| {
+        let (_, node_ids) = add_many_vars_to_address_space(address_space, 3);
+        node_ids.iter().for_each(|node_id| make_history_readable(address_space, node_id));
+        server_state.set_historical_data_provider(Box::new(SlowHistoricalDataProvider));
+
+        // The first node uses up the timeout hint so the rest are not read
+        let nodes_to_read = node_ids.iter().map(|node_id| history_read_value_id(node_id, ByteString::null())).collect();
+        let mut request = history_read_request(read_raw_details(0), false, nodes_to_read);
+        request.request_header.timeout_hint = 20;
+        let response = ats.history_read(server_state, session, address_space, &request);
+        let response: HistoryReadResponse = supported_message_as!(response.unwrap(), HistoryReadResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].status_code, StatusCode::Good);
+        assert_eq!(results[1].status_code, StatusCode::BadTimeout);
+        assert_eq!(results[2].status_code, StatusCode::BadTimeout);
+
+        // Without a timeout hint every node is read
+        request.request_header.timeout_hint = 0;
+        let response = ats.history_read(server_state, session, address_space, &request);
+        let response: HistoryReadResponse = supported_message_as!(response.unwrap(), HistoryReadResponse);
+        assert!(response.results.unwrap().iter().all(|result| result.status_code == StatusCode::Good));
+    });
+}
+
+fn history_update_request(history_update_details: Vec<ExtensionObject>) -> HistoryUpdateRequest {
+    HistoryUpdateRequest {
+        request_header: make_request_header(),
+        history_update_details: Some(history_update_details),
+    }
+}
+
+fn update_data_details(node_id: &NodeId, num_values: usize) -> ExtensionObject {
+    let details = UpdateDataDetails {
+        node_id: node_id.clone(),
+        perform_insert_replace: PerformUpdateType::Insert,
+        update_values: Some((0..num_values).map(|i| DataValue::new(i as i32)).collect()),
+    };
+    ExtensionObject::from_encodable(ObjectId::UpdateDataDetails_Encoding_DefaultBinary, &details)
+}
+
+#[test]
+fn history_update_nothing_to_do() {
+    do_history_read_test(|server_state, _session, address_space, ats| {
+        let request = history_update_request(vec![]);
+        let response = ats.history_update(server_state, address_space, &request);
+        let response: ServiceFault = supported_message_as!(response.unwrap(), ServiceFault);
+        assert_eq!(response.response_header.service_result, StatusCode::BadNothingToDo);
+    });
+}
+
+#[test]
+fn history_update() {
+    do_history_read_test(|server_state, _session, address_space, ats| {
+        let (_, node_ids) = add_many_vars_to_address_space(address_space, 2);
+        // Only the first var has history write access
+        set_access_level(address_space, &node_ids[0], AccessLevel::CURRENT_READ | AccessLevel::HISTORY_WRITE);
+
+        let delete_raw_details = DeleteRawModifiedDetails {
+            node_id: node_ids[0].clone(),
+            is_delete_modified: false,
+            start_time: DateTime::ymd(2019, 1, 1),
+            end_time: DateTime::now(),
+        };
+        let delete_event_details = DeleteEventDetails {
+            node_id: node_ids[0].clone(),
+            event_ids: None,
+        };
+        let request = history_update_request(vec![
+            // 1. an update of a variable with history write access
+            update_data_details(&node_ids[0], 3),
+            // 2. an update of a variable without history write access
+            update_data_details(&node_ids[1], 3),
+            // 3. an update of a non existent variable
+            update_data_details(&NodeId::new(1, "vxxx"), 3),
+            // 4. a delete of raw values
+            ExtensionObject::from_encodable(ObjectId::DeleteRawModifiedDetails_Encoding_DefaultBinary, &delete_raw_details),
+            // 5. a delete of events from a variable
+            ExtensionObject::from_encodable(ObjectId::DeleteEventDetails_Encoding_DefaultBinary, &delete_event_details),
+            // 6. something that is not an update at all
+            ExtensionObject::null(),
+        ]);
+
+        // No provider so the operation is unsupported
+        let response = ats.history_update(server_state, address_space, &request);
+        let response: HistoryUpdateResponse = supported_message_as!(response.unwrap(), HistoryUpdateResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].status_code, StatusCode::BadHistoryOperationUnsupported);
+        assert_eq!(results[1].status_code, StatusCode::BadNotWritable);
+
+        server_state.set_historical_data_provider(Box::new(TestHistoricalDataProvider { num_values: 0 }));
+
+        let response = ats.history_update(server_state, address_space, &request);
+        let response: HistoryUpdateResponse = supported_message_as!(response.unwrap(), HistoryUpdateResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results.len(), 6);
+
+        // 1. an update of a variable with history write access
+        assert_eq!(results[0].status_code, StatusCode::Good);
+        assert_eq!(results[0].operation_results.as_ref().unwrap(), &vec![StatusCode::GoodEntryInserted; 3]);
+        // 2. an update of a variable without history write access
+        assert_eq!(results[1].status_code, StatusCode::BadNotWritable);
+        // 3. an update of a non existent variable
+        assert_eq!(results[2].status_code, StatusCode::BadNodeIdUnknown);
+        // 4. a delete of raw values
+        assert_eq!(results[3].status_code, StatusCode::Good);
+        assert!(results[3].operation_results.is_none());
+        // 5. a delete of events from a variable
+        assert_eq!(results[4].status_code, StatusCode::BadHistoryOperationUnsupported);
+        // 6. something that is not an update at all
+        assert_eq!(results[5].status_code, StatusCode::BadHistoryOperationInvalid);
+    });
+}
+
+#[test]
+fn too_many_operations() {
+    do_attribute_service_test(|server_state, session, address_space, ats| {
+        let (_, node_ids) = add_many_vars_to_address_space(address_space, 4);
+        server_state.operational_limits.max_nodes_per_read = 3;
+        server_state.operational_limits.max_nodes_per_history_update_data = 3;
+
+        // A read of as many nodes as the limit is allowed
+        let mut request = ReadRequest {
+            request_header: make_request_header(),
+            max_age: 0f64,
+            timestamps_to_return: TimestampsToReturn::Both,
+            nodes_to_read: Some(node_ids[..3].iter().map(|n| read_value(n, AttributeId::Value)).collect()),
+        };
+        let response = ats.read(server_state, session, &address_space, &request);
+        let response: ReadResponse = supported_message_as!(response.unwrap(), ReadResponse);
+        assert_eq!(response.results.unwrap().len(), 3);
+
+        // A read of more nodes than the limit is rejected
+        request.nodes_to_read = Some(node_ids.iter().map(|n| read_value(n, AttributeId::Value)).collect());
+        let response = ats.read(server_state, session, &address_space, &request);
+        let response: ServiceFault = supported_message_as!(response.unwrap(), ServiceFault);
+        assert_eq!(response.response_header.service_result, StatusCode::BadTooManyOperations);
+
+        // No limit
+        server_state.operational_limits.max_nodes_per_read = 0;
+        let response = ats.read(server_state, session, &address_space, &request);
+        let response: ReadResponse = supported_message_as!(response.unwrap(), ReadResponse);
+        assert_eq!(response.results.unwrap().len(), 4);
+
+        // Updates of values count towards their own limit
+        let request = history_update_request(node_ids.iter().map(|n| update_data_details(n, 1)).collect());
+        let response = ats.history_update(server_state, address_space, &request);
+        let response: ServiceFault = supported_message_as!(response.unwrap(), ServiceFault);
+        assert_eq!(response.response_header.service_result, StatusCode::BadTooManyOperations);
+    });
+}
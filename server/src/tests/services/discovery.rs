@@ -1,7 +1,13 @@
+use std::time::{Duration, Instant};
+
 use opcua_types::UAString;
 
 use super::*;
-use crate::services::discovery::DiscoveryService;
+use crate::{
+    config::DiscoveryRegistrationConfig,
+    discovery::{self, RegistrationSchedule},
+    services::discovery::DiscoveryService,
+};
 
 #[test]
 fn get_endpoints() {
@@ -83,4 +89,93 @@ fn discovery_test() {
         let endpoints = result.endpoints.unwrap();
         assert!(!endpoints.is_empty())
     }
-}
\ No newline at end of file
+}
+#[test]
+fn get_endpoints_filter_by_url() {
+    let server = ServerBuilder::new_sample().server().unwrap();
+    let server_state = server.server_state();
+    let server_state = server_state.read().unwrap();
+
+    let endpoints = |endpoint_url: &str| {
+        server_state.endpoints(&UAString::from(endpoint_url), &None).unwrap()
+    };
+
+    let all_endpoints = endpoints("");
+    assert!(all_endpoints.len() > 1);
+
+    // Only the endpoints sharing the path are returned
+    let noaccess_endpoints = endpoints("opc.tcp://localhost:4855/noaccess");
+    assert_eq!(noaccess_endpoints.len(), 1);
+    assert!(noaccess_endpoints[0].endpoint_url.as_ref().ends_with("/noaccess"));
+    assert_eq!(endpoints("opc.tcp://localhost:4855/").len(), all_endpoints.len() - 1);
+
+    // A url matching no endpoint returns all of them
+    assert_eq!(endpoints("opc.tcp://localhost:4855/xyz").len(), all_endpoints.len());
+}
+
+#[test]
+fn discovery_registration_schedule() {
+    let config = DiscoveryRegistrationConfig {
+        register_interval: 300,
+        retry_interval: 10,
+        max_retries: 2,
+        ..Default::default()
+    };
+    let now = Instant::now();
+    let mut schedule = RegistrationSchedule::new(&config, now);
+
+    // First registration is due immediately, but not while it is underway
+    assert!(schedule.is_due(now));
+    schedule.start();
+    assert!(!schedule.is_due(now));
+
+    // Failures are retried on the retry interval until the retries are exhausted
+    schedule.finish(now, false);
+    assert_eq!(schedule.next_registration(), now + Duration::from_secs(10));
+    assert!(!schedule.is_due(now));
+    assert!(schedule.is_due(now + Duration::from_secs(10)));
+    schedule.start();
+    schedule.finish(now, false);
+    assert_eq!(schedule.next_registration(), now + Duration::from_secs(10));
+    schedule.start();
+    schedule.finish(now, false);
+    assert_eq!(schedule.next_registration(), now + Duration::from_secs(300));
+
+    // Retries start again after the register interval
+    schedule.start();
+    schedule.finish(now, false);
+    assert_eq!(schedule.next_registration(), now + Duration::from_secs(10));
+
+    // Success waits for the register interval and resets the retries
+    schedule.start();
+    schedule.finish(now, true);
+    assert_eq!(schedule.next_registration(), now + Duration::from_secs(300));
+    schedule.start();
+    schedule.finish(now, false);
+    assert_eq!(schedule.next_registration(), now + Duration::from_secs(10));
+}
+
+#[test]
+fn discovery_registration_capabilities() {
+    let st = ServiceTest::new();
+    let (server_state, _) = st.get_server_state_and_session();
+
+    let mdns_discovery_configuration = |server_state: &ServerState| {
+        let discovery_configuration = discovery::discovery_configuration(server_state);
+        assert_eq!(discovery_configuration.len(), 1);
+        assert_eq!(discovery_configuration[0].node_id, ObjectId::MdnsDiscoveryConfiguration_Encoding_DefaultBinary.into());
+        discovery_configuration[0].decode_inner::<MdnsDiscoveryConfiguration>(&DecodingLimits::default()).unwrap()
+    };
+
+    // Without capabilities, the server says none are known
+    let config = mdns_discovery_configuration(&server_state);
+    assert_eq!(config.mdns_server_name, server_state.application_name.text);
+    assert_eq!(config.server_capabilities, Some(vec![UAString::from("NA")]));
+
+    {
+        let mut config = server_state.config.write().unwrap();
+        config.discovery_registration.server_capabilities = vec!["DA".to_string(), "HD".to_string()];
+    }
+    let config = mdns_discovery_configuration(&server_state);
+    assert_eq!(config.server_capabilities, Some(vec![UAString::from("DA"), UAString::from("HD")]));
+}
@@ -0,0 +1,62 @@
+use crate::services::message_handler::MessageHandler;
+
+use super::*;
+
+fn make_message_handler(st: &ServiceTest) -> MessageHandler {
+    MessageHandler::new(st.server.certificate_store(), st.server_state.clone(), st.session.clone(), st.address_space.clone())
+}
+
+#[test]
+fn requests_which_do_not_modify_the_address_space_only_read_it() {
+    let st = ServiceTest::new();
+    let mut message_handler = make_message_handler(&st);
+
+    // Holding a read lock would deadlock any handler which locks the address space for writing
+    let address_space = trace_read_lock_unwrap!(st.address_space);
+
+    let request = ReadRequest {
+        request_header: make_request_header(),
+        max_age: 0f64,
+        timestamps_to_return: TimestampsToReturn::Both,
+        nodes_to_read: Some(vec![ReadValueId::from(NodeId::from(&VariableId::Server_ServerStatus_State))]),
+    };
+    let response = message_handler.handle_message(1, request.into()).unwrap();
+    assert!(match response { Some(SupportedMessage::ReadResponse(_)) => true, _ => false });
+
+    let request = create_subscription_request(10, 30);
+    let response = message_handler.handle_message(2, request.into()).unwrap();
+    assert!(match response { Some(SupportedMessage::CreateSubscriptionResponse(_)) => true, _ => false });
+
+    drop(address_space);
+}
+
+#[test]
+fn requests_which_modify_the_address_space_are_handled() {
+    let st = ServiceTest::new();
+    let mut message_handler = make_message_handler(&st);
+
+    let request = WriteRequest {
+        request_header: make_request_header(),
+        nodes_to_write: Some(vec![WriteValue {
+            node_id: NodeId::new(1, "unknown"),
+            attribute_id: AttributeId::Value as u32,
+            index_range: UAString::null(),
+            value: DataValue::new(1i32),
+        }]),
+    };
+    let response = message_handler.handle_message(1, request.into()).unwrap();
+    match response {
+        Some(SupportedMessage::WriteResponse(response)) => {
+            assert_eq!(response.results, Some(vec![StatusCode::BadNodeIdUnknown]));
+        }
+        response => panic!("Unexpected response {:?}", response)
+    }
+}
+
+#[test]
+fn unsupported_message_is_rejected() {
+    let st = ServiceTest::new();
+    let mut message_handler = make_message_handler(&st);
+    let message = SupportedMessage::Invalid(ObjectId::ReadRequest_Encoding_DefaultBinary);
+    assert_eq!(message_handler.handle_message(1, message).unwrap_err(), StatusCode::BadServiceUnsupported);
+}
@@ -1,11 +1,13 @@
 use opcua_types::{
+    argument::Argument,
     status_code::StatusCode,
     service_types::{CallRequest, CallResponse, CallMethodRequest, CallMethodResult},
-    node_ids::{ObjectId, MethodId},
+    node_ids::{ObjectId, ObjectTypeId, MethodId, DataTypeId},
 };
 
 use super::*;
 
+use crate::subscriptions::monitored_item::Notification;
 use crate::services::{
     method::MethodService,
     subscription::SubscriptionService,
@@ -143,7 +145,7 @@ fn call_getmonitoreditems() {
             // Create a monitored item
             let monitored_item_id = {
                 let request = create_monitored_items_request(subscription_id, 999, VariableId::Server_ServerStatus_CurrentTime);
-                let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(session, &request).unwrap(), CreateMonitoredItemsResponse);
+                let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(session, address_space, &request).unwrap(), CreateMonitoredItemsResponse);
                 response.results.unwrap()[0].monitored_item_id
             };
 
@@ -215,3 +217,455 @@ fn call_resend_data() {
         }
     });
 }
+
+fn add_test_alarm(address_space: &mut AddressSpace) -> NodeId {
+    let source_node = NodeId::new(1, "Pump");
+    let _ = address_space.add_organized_node(&source_node, "Pump", "Pump", &AddressSpace::objects_folder_id(), ObjectTypeId::BaseObjectType).unwrap();
+    let condition_id = NodeId::new(1, "PumpAlarm");
+    let _ = address_space.add_condition(Condition::new_alarm(condition_id.clone(), source_node, "Pump", "PumpAlarm")).unwrap();
+    condition_id
+}
+
+#[test]
+fn call_condition_methods() {
+    do_method_service_test(|server_state, session, address_space, s| {
+        let condition_id = add_test_alarm(address_space);
+        address_space.update_condition(condition_id.clone(), |condition| condition.set_active(true)).unwrap();
+        let event_id = address_space.find_condition(&condition_id).unwrap().event_id().clone();
+
+        // Acknowledge with the wrong event id
+        {
+            let args: Vec<Variant> = vec![ByteString::from(vec![1u8]).into(), LocalizedText::null().into()];
+            let request = new_call_method_request(condition_id.clone(), MethodId::AcknowledgeableConditionType_Acknowledge, Some(args));
+            let response = call_single(s, address_space, &server_state, session, request).unwrap();
+            assert_eq!(response.status_code, StatusCode::BadEventIdUnknown);
+        }
+
+        // Acknowledge with the wrong argument types
+        {
+            let args: Vec<Variant> = vec![100u32.into(), LocalizedText::null().into()];
+            let request = new_call_method_request(condition_id.clone(), MethodId::AcknowledgeableConditionType_Acknowledge, Some(args));
+            let response = call_single(s, address_space, &server_state, session, request).unwrap();
+            assert_eq!(response.status_code, StatusCode::BadInvalidArgument);
+        }
+
+        // Acknowledge with the right event id
+        let last_event_number = address_space.last_event_number();
+        {
+            let args: Vec<Variant> = vec![event_id.into(), LocalizedText::new("", "Fixed").into()];
+            let request = new_call_method_request(condition_id.clone(), MethodId::AcknowledgeableConditionType_Acknowledge, Some(args));
+            let response = call_single(s, address_space, &server_state, session, request).unwrap();
+            assert_eq!(response.status_code, StatusCode::Good);
+            assert!(address_space.find_condition(&condition_id).unwrap().is_acked());
+            assert_eq!(address_space.last_event_number(), last_event_number + 1);
+        }
+
+        // Disable, twice
+        {
+            let request = new_call_method_request(condition_id.clone(), MethodId::ConditionType_Disable, None);
+            let response = call_single(s, address_space, &server_state, session, request).unwrap();
+            assert_eq!(response.status_code, StatusCode::Good);
+            let request = new_call_method_request(condition_id.clone(), MethodId::ConditionType_Disable, None);
+            let response = call_single(s, address_space, &server_state, session, request).unwrap();
+            assert_eq!(response.status_code, StatusCode::BadConditionAlreadyDisabled);
+        }
+    });
+}
+
+#[test]
+fn call_condition_refresh() {
+    do_method_service_test(|server_state, session, address_space, s| {
+        let condition_id = add_test_alarm(address_space);
+        address_space.update_condition(condition_id.clone(), |condition| condition.set_active(true)).unwrap();
+
+        // Call with invalid subscription id
+        {
+            let args: Vec<Variant> = vec![100u32.into()];
+            let request = new_call_method_request(ObjectTypeId::ConditionType, MethodId::ConditionType_ConditionRefresh, Some(args));
+            let response = call_single(s, address_space, &server_state, session, request).unwrap();
+            assert_eq!(response.status_code, StatusCode::BadSubscriptionIdInvalid);
+        }
+
+        let ss = SubscriptionService::new();
+        let mis = MonitoredItemService::new();
+        let subscription_id = {
+            let request = create_subscription_request();
+            let response: CreateSubscriptionResponse = supported_message_as!(ss.create_subscription(server_state, session, &request).unwrap(), CreateSubscriptionResponse);
+            response.subscription_id
+        };
+
+        // Monitor events from the server, selecting the event type
+        let monitored_item_id = {
+            let mut request = create_monitored_items_request(subscription_id, 999, ObjectId::Server);
+            {
+                let item = &mut request.items_to_create.as_mut().unwrap()[0];
+                item.item_to_monitor.attribute_id = AttributeId::EventNotifier as u32;
+                item.requested_parameters.queue_size = 0;
+                item.requested_parameters.filter = ExtensionObject::from_encodable(ObjectId::EventFilter_Encoding_DefaultBinary, &EventFilter {
+                    select_clauses: Some(vec![SimpleAttributeOperand {
+                        type_definition_id: ObjectTypeId::BaseEventType.into(),
+                        browse_path: Some(vec![QualifiedName::new(0, "EventType")]),
+                        attribute_id: AttributeId::Value as u32,
+                        index_range: UAString::null(),
+                    }]),
+                    where_clause: ContentFilter { elements: None },
+                });
+            }
+            let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(session, address_space, &request).unwrap(), CreateMonitoredItemsResponse);
+            let result = &response.results.unwrap()[0];
+            assert_eq!(result.status_code, StatusCode::Good);
+            result.monitored_item_id
+        };
+
+        let args: Vec<Variant> = vec![subscription_id.into()];
+        let request = new_call_method_request(ObjectTypeId::ConditionType, MethodId::ConditionType_ConditionRefresh, Some(args));
+        let response = call_single(s, address_space, &server_state, session, request).unwrap();
+        assert_eq!(response.status_code, StatusCode::Good);
+
+        // The retained alarm is sent between the refresh start and end events
+        let subscription = session.subscriptions.get_mut(subscription_id).unwrap();
+        let monitored_item = subscription.monitored_item_mut(monitored_item_id).unwrap();
+        let event_types = monitored_item.all_notifications().unwrap().into_iter().map(|n| {
+            if let Notification::EventFieldList(n) = n {
+                n.event_fields.unwrap().remove(0)
+            } else {
+                panic!("Expected an event notification");
+            }
+        }).collect::<Vec<Variant>>();
+        let expected_event_types = [ObjectTypeId::RefreshStartEventType, ObjectTypeId::AlarmConditionType, ObjectTypeId::RefreshEndEventType]
+            .iter().map(|event_type| {
+            let event_type: NodeId = (*event_type).into();
+            Variant::from(event_type)
+        }).collect::<Vec<Variant>>();
+        assert_eq!(event_types, expected_event_types);
+    });
+}
+
+fn argument(name: &str, data_type: DataTypeId, value_rank: i32) -> Argument {
+    Argument {
+        name: UAString::from(name),
+        data_type: data_type.into(),
+        value_rank,
+        // An array argument has a length for each dimension, where 0 is any length
+        array_dimensions: if value_rank > 0 { Some(vec![0; value_rank as usize]) } else { None },
+        description: LocalizedText::null(),
+    }
+}
+
+#[test]
+fn call_method_fn() {
+    do_method_service_test(|server_state, session, address_space, s| {
+        let object_id = address_space.add_folder("Calculator", "Calculator", &AddressSpace::objects_folder_id()).unwrap();
+
+        // Add(Int32 a, Double b, String[] labels) -> (Double sum, UInt32 labels)
+        let add_id = NodeId::new(1, "Add");
+        let _ = address_space.add_method(&add_id, "Add", "Add", &object_id,
+                                         &[argument("a", DataTypeId::Int32, -1), argument("b", DataTypeId::Number, -1), argument("labels", DataTypeId::String, 1)],
+                                         &[argument("sum", DataTypeId::Double, -1), argument("labels", DataTypeId::UInt32, -1)]).unwrap();
+        address_space.register_method_handler(object_id.clone(), add_id.clone(), Box::new(MethodFn::new(|args| {
+            let a = args[0].as_f64().unwrap();
+            let b = args[1].as_f64().unwrap();
+            let labels = if let Variant::Array(ref labels) = args[2] { labels.len() as u32 } else { 0 };
+            Ok(vec![Variant::from(a + b), Variant::from(labels)])
+        })));
+
+        // Broken() -> (Double result) but returns a string
+        let broken_id = NodeId::new(1, "Broken");
+        let _ = address_space.add_method(&broken_id, "Broken", "Broken", &object_id, &[], &[argument("result", DataTypeId::Double, -1)]).unwrap();
+        address_space.register_method_handler(object_id.clone(), broken_id.clone(), Box::new(MethodFn::new(|_| {
+            Ok(vec![Variant::from("oops")])
+        })));
+
+        // Good call
+        {
+            let args: Vec<Variant> = vec![1i32.into(), 2.5f32.into(), vec!["x".to_string(), "y".to_string()].into()];
+            let request = new_call_method_request(object_id.clone(), add_id.clone(), Some(args));
+            let response = call_single(s, address_space, &server_state, session, request).unwrap();
+            assert_eq!(response.status_code, StatusCode::Good);
+            assert_eq!(response.output_arguments, Some(vec![Variant::from(3.5f64), Variant::from(2u32)]));
+        }
+
+        // Missing arguments
+        {
+            let args: Vec<Variant> = vec![1i32.into(), 2.5f64.into()];
+            let request = new_call_method_request(object_id.clone(), add_id.clone(), Some(args));
+            let response = call_single(s, address_space, &server_state, session, request).unwrap();
+            assert_eq!(response.status_code, StatusCode::BadArgumentsMissing);
+        }
+
+        // Wrong types - a is not an Int32, b is not a number and labels is not an array
+        {
+            let args: Vec<Variant> = vec![1u32.into(), "2".into(), "x".into()];
+            let request = new_call_method_request(object_id.clone(), add_id.clone(), Some(args));
+            let response = call_single(s, address_space, &server_state, session, request).unwrap();
+            assert_eq!(response.status_code, StatusCode::BadInvalidArgument);
+            assert_eq!(response.input_argument_results, Some(vec![StatusCode::BadTypeMismatch, StatusCode::BadTypeMismatch, StatusCode::BadTypeMismatch]));
+        }
+
+        // Output arguments that do not match are an error
+        {
+            let request = new_call_method_request(object_id.clone(), broken_id.clone(), None);
+            let response = call_single(s, address_space, &server_state, session, request).unwrap();
+            assert_eq!(response.status_code, StatusCode::BadInternalError);
+        }
+    });
+}
+
+#[test]
+fn call_server_configuration_methods() {
+    let pki_dir = make_test_file("opcua_server_configuration_pki");
+    let _ = std::fs::remove_dir_all(&pki_dir);
+    let server = ServerBuilder::new_anonymous("foo")
+        .pki_dir(&pki_dir)
+        .create_sample_keypair(true)
+        .server().unwrap();
+    let st = ServiceTest::new_with_server(server);
+    let s = MethodService::new();
+    let (server_state, mut session) = st.get_server_state_and_session();
+    let mut address_space = st.address_space.write().unwrap();
+
+    let get_rejected_list = || new_call_method_request(ObjectId::ServerConfiguration, MethodId::ServerConfiguration_GetRejectedList, None);
+
+    // The channel must be encrypted
+    {
+        let response = call_single(&s, &mut address_space, &server_state, &mut session, get_rejected_list()).unwrap();
+        assert_eq!(response.status_code, StatusCode::BadSecurityModeInsufficient);
+    }
+    session.secure_channel.write().unwrap().set_security_mode(MessageSecurityMode::SignAndEncrypt);
+
+    // The user must be a security admin
+    {
+        let response = call_single(&s, &mut address_space, &server_state, &mut session, get_rejected_list()).unwrap();
+        assert_eq!(response.status_code, StatusCode::BadUserAccessDenied);
+    }
+    session.roles.push(WellKnownRole::SecurityAdmin.into());
+
+    {
+        let response = call_single(&s, &mut address_space, &server_state, &mut session, get_rejected_list()).unwrap();
+        assert_eq!(response.status_code, StatusCode::Good);
+    }
+
+    // Signing request for the current key
+    {
+        let args: Vec<Variant> = vec![NodeId::null().into(), NodeId::null().into(), "CN=Pushed/O=Test".into(), false.into(), ByteString::null().into()];
+        let request = new_call_method_request(ObjectId::ServerConfiguration, MethodId::ServerConfiguration_CreateSigningRequest, Some(args));
+        let response = call_single(&s, &mut address_space, &server_state, &mut session, request).unwrap();
+        assert_eq!(response.status_code, StatusCode::Good);
+        if let Some(Variant::ByteString(ref certificate_request)) = response.output_arguments.unwrap().first() {
+            assert!(!certificate_request.is_null_or_empty());
+        } else {
+            panic!("CreateSigningRequest did not return a certificate request");
+        }
+    }
+
+    let (cert, pkey) = CertificateStore::create_cert_and_pkey(&X509Data::sample_cert()).unwrap();
+    let update_certificate = |pkey: &PrivateKey| {
+        let args: Vec<Variant> = vec![
+            NodeId::null().into(), NodeId::null().into(), cert.as_byte_string().into(), Variant::Array(Vec::new()),
+            "PEM".into(), ByteString::from(pkey.private_key_to_pem().unwrap()).into()
+        ];
+        new_call_method_request(ObjectId::ServerConfiguration, MethodId::ServerConfiguration_UpdateCertificate, Some(args))
+    };
+
+    // The key must belong to the cert
+    {
+        let (_, other_pkey) = CertificateStore::create_cert_and_pkey(&X509Data::sample_cert()).unwrap();
+        let response = call_single(&s, &mut address_space, &server_state, &mut session, update_certificate(&other_pkey)).unwrap();
+        assert_eq!(response.status_code, StatusCode::BadSecurityChecksFailed);
+    }
+
+    // Update and apply the new cert
+    {
+        let response = call_single(&s, &mut address_space, &server_state, &mut session, update_certificate(&pkey)).unwrap();
+        assert_eq!(response.status_code, StatusCode::Good);
+        assert_eq!(response.output_arguments, Some(vec![Variant::Boolean(true)]));
+
+        let request = new_call_method_request(ObjectId::ServerConfiguration, MethodId::ServerConfiguration_ApplyChanges, None);
+        let response = call_single(&s, &mut address_space, &server_state, &mut session, request).unwrap();
+        assert_eq!(response.status_code, StatusCode::Good);
+
+        let (own_cert, _) = CertificateStore::new(&pki_dir).read_own_cert_and_pkey().unwrap();
+        assert_eq!(own_cert.thumbprint().value, cert.thumbprint().value);
+    }
+
+    let _ = std::fs::remove_dir_all(&pki_dir);
+}
+
+#[test]
+fn call_trust_list_methods() {
+    let pki_dir = make_test_file("opcua_trust_list_pki");
+    let _ = std::fs::remove_dir_all(&pki_dir);
+    let server = ServerBuilder::new_anonymous("foo")
+        .pki_dir(&pki_dir)
+        .create_sample_keypair(true)
+        .server().unwrap();
+    let st = ServiceTest::new_with_server(server);
+    let s = MethodService::new();
+    let (server_state, mut session) = st.get_server_state_and_session();
+    let mut address_space = st.address_space.write().unwrap();
+
+    session.secure_channel.write().unwrap().set_security_mode(MessageSecurityMode::SignAndEncrypt);
+    session.roles.push(WellKnownRole::SecurityAdmin.into());
+
+    let trust_list_id = ObjectId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList;
+    let mut call = |method_id: MethodId, args: Vec<Variant>| {
+        let request = new_call_method_request(trust_list_id, method_id, Some(args));
+        call_single(&s, &mut address_space, &server_state, &mut session, request).unwrap()
+    };
+    let file_handle = |response: &CallMethodResult| {
+        match response.output_arguments.as_ref().unwrap().first() {
+            Some(Variant::UInt32(file_handle)) => *file_handle,
+            _ => panic!("Open did not return a file handle")
+        }
+    };
+
+    // Read the current trust list, which starts off empty
+    {
+        let response = call(MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_Open, vec![1u8.into()]);
+        assert_eq!(response.status_code, StatusCode::Good);
+        let handle = file_handle(&response);
+
+        let response = call(MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_Read, vec![handle.into(), 65536i32.into()]);
+        assert_eq!(response.status_code, StatusCode::Good);
+        let data = match response.output_arguments.unwrap().remove(0) {
+            Variant::ByteString(data) => data,
+            _ => panic!("Read did not return data")
+        };
+        let trust_list = TrustListDataType::decode(&mut std::io::Cursor::new(data.as_ref()), &DecodingLimits::default()).unwrap();
+        assert_eq!(trust_list.specified_lists, 15);
+        assert!(trust_list.trusted_certificates.unwrap_or_default().is_empty());
+
+        // Cannot write to a file opened for reading
+        let response = call(MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_Write, vec![handle.into(), ByteString::from(vec![1u8]).into()]);
+        assert_eq!(response.status_code, StatusCode::BadInvalidState);
+
+        let response = call(MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_Close, vec![handle.into()]);
+        assert_eq!(response.status_code, StatusCode::Good);
+    }
+
+    // Replace the trust list with one holding a trusted cert
+    let (cert, _) = CertificateStore::create_cert_and_pkey(&X509Data::sample_cert()).unwrap();
+    {
+        let trust_list = TrustListDataType {
+            specified_lists: 15,
+            trusted_certificates: Some(vec![cert.as_byte_string()]),
+            trusted_crls: None,
+            issuer_certificates: None,
+            issuer_crls: None,
+        };
+        let mut stream = std::io::Cursor::new(Vec::new());
+        trust_list.encode(&mut stream).unwrap();
+
+        let response = call(MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_Open, vec![6u8.into()]);
+        assert_eq!(response.status_code, StatusCode::Good);
+        let handle = file_handle(&response);
+
+        // Not readable while it is being written
+        let response = call(MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_Open, vec![1u8.into()]);
+        assert_eq!(response.status_code, StatusCode::BadNotReadable);
+
+        let response = call(MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_Write, vec![handle.into(), ByteString::from(stream.into_inner()).into()]);
+        assert_eq!(response.status_code, StatusCode::Good);
+
+        let response = call(MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_CloseAndUpdate, vec![handle.into()]);
+        assert_eq!(response.status_code, StatusCode::Good);
+        assert_eq!(response.output_arguments, Some(vec![Variant::Boolean(false)]));
+    }
+
+    let certificate_store = CertificateStore::new(&pki_dir);
+    let trusted_certs = certificate_store.trusted_certs();
+    assert_eq!(trusted_certs.len(), 1);
+    assert_eq!(trusted_certs[0].thumbprint().value, cert.thumbprint().value);
+
+    let _ = std::fs::remove_dir_all(&pki_dir);
+}
+
+/// A file held in memory, whose contents are replaced when a writer closes it
+struct MemoryFileProvider {
+    contents: Arc<RwLock<Vec<u8>>>,
+}
+
+impl FileProvider for MemoryFileProvider {
+    fn size(&self) -> u64 {
+        self.contents.read().unwrap().len() as u64
+    }
+
+    fn writable(&self) -> bool { true }
+
+    fn open(&mut self, mode: FileOpenMode) -> Result<Box<dyn FileStream>, StatusCode> {
+        let contents = if mode.contains(FileOpenMode::ERASE_EXISTING) { Vec::new() } else { self.contents.read().unwrap().clone() };
+        Ok(Box::new(std::io::Cursor::new(contents)))
+    }
+
+    fn close(&mut self, mode: FileOpenMode, mut stream: Box<dyn FileStream>) -> Result<(), StatusCode> {
+        use std::io::{Read, Seek, SeekFrom};
+        if mode.contains(FileOpenMode::WRITE) {
+            let mut contents = Vec::new();
+            stream.seek(SeekFrom::Start(0)).unwrap();
+            stream.read_to_end(&mut contents).unwrap();
+            *self.contents.write().unwrap() = contents;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn call_file_methods() {
+    do_method_service_test(|server_state, session, address_space, s| {
+        let contents = Arc::new(RwLock::new(b"hello".to_vec()));
+        let file_id = NodeId::new(1, "Recipe");
+        address_space.add_file(&file_id, "Recipe", "Recipe", &ObjectId::ObjectsFolder.into(), Box::new(MemoryFileProvider {
+            contents: contents.clone(),
+        })).unwrap();
+
+        // Finds the method of the file by its browse name
+        let method_id = |address_space: &AddressSpace, name: &str| {
+            address_space.find_references_from(&file_id, Some((ReferenceTypeId::HasComponent, false))).unwrap()
+                .into_iter()
+                .map(|reference| reference.target_node_id)
+                .find(|node_id| address_space.find_node(node_id).unwrap().as_node().browse_name() == QualifiedName::new(0, name))
+                .unwrap()
+        };
+        let open_id = method_id(address_space, "Open");
+        let close_id = method_id(address_space, "Close");
+        let read_id = method_id(address_space, "Read");
+        let write_id = method_id(address_space, "Write");
+        let get_position_id = method_id(address_space, "GetPosition");
+        let set_position_id = method_id(address_space, "SetPosition");
+
+        let mut call = |method_id: &NodeId, args: Vec<Variant>| {
+            let request = new_call_method_request(file_id.clone(), method_id.clone(), Some(args));
+            call_single(s, address_space, server_state, session, request).unwrap()
+        };
+        let output = |response: CallMethodResult| response.output_arguments.unwrap().remove(0);
+
+        // Invalid modes
+        assert_eq!(call(&open_id, vec![0u8.into()]).status_code, StatusCode::BadInvalidArgument);
+        assert_eq!(call(&open_id, vec![5u8.into()]).status_code, StatusCode::BadInvalidArgument);
+
+        // Read part of the file from a position
+        let handle = output(call(&open_id, vec![1u8.into()]));
+        assert_eq!(call(&set_position_id, vec![handle.clone(), 1u64.into()]).status_code, StatusCode::Good);
+        assert_eq!(output(call(&read_id, vec![handle.clone(), 3i32.into()])), Variant::from(ByteString::from(b"ell".to_vec())));
+        assert_eq!(output(call(&get_position_id, vec![handle.clone()])), Variant::from(4u64));
+        assert_eq!(call(&write_id, vec![handle.clone(), ByteString::from(vec![1u8]).into()]).status_code, StatusCode::BadInvalidState);
+
+        // A writer has to wait for the reader to close
+        assert_eq!(call(&open_id, vec![2u8.into()]).status_code, StatusCode::BadInvalidState);
+        assert_eq!(call(&close_id, vec![handle.clone()]).status_code, StatusCode::Good);
+        assert_eq!(call(&close_id, vec![handle]).status_code, StatusCode::BadInvalidArgument);
+
+        // Append to the file
+        let handle = output(call(&open_id, vec![10u8.into()]));
+        assert_eq!(output(call(&get_position_id, vec![handle.clone()])), Variant::from(5u64));
+        assert_eq!(call(&write_id, vec![handle.clone(), ByteString::from(b" world".to_vec()).into()]).status_code, StatusCode::Good);
+        assert_eq!(call(&close_id, vec![handle]).status_code, StatusCode::Good);
+        assert_eq!(contents.read().unwrap().as_slice(), b"hello world");
+
+        // Erase and write the file
+        let handle = output(call(&open_id, vec![6u8.into()]));
+        assert_eq!(call(&write_id, vec![handle.clone(), ByteString::from(b"bye".to_vec()).into()]).status_code, StatusCode::Good);
+        assert_eq!(call(&close_id, vec![handle]).status_code, StatusCode::Good);
+        assert_eq!(contents.read().unwrap().as_slice(), b"bye");
+    });
+}
@@ -15,13 +15,16 @@ use crate::{
 struct ServiceTest {
     pub server: Server,
     pub server_state: Arc<RwLock<ServerState>>,
-    pub address_space: Arc<RwLock<AddressSpace>>,
+    pub address_space: Arc<SharedAddressSpace>,
     pub session: Arc<RwLock<Session>>,
 }
 
 impl ServiceTest {
     pub fn new() -> ServiceTest {
-        let server = ServerBuilder::new_anonymous("foo").server().unwrap();
+        Self::new_with_server(ServerBuilder::new_anonymous("foo").server().unwrap())
+    }
+
+    pub fn new_with_server(server: Server) -> ServiceTest {
         let tcp_transport = server.new_transport();
         let server_state = server.server_state();
         let address_space = server.address_space();
@@ -131,9 +134,11 @@ fn create_monitored_items_request<T>(subscription_id: u32, mut node_id: Vec<T>)
 
 pub mod attribute;
 pub mod discovery;
+pub mod message_handler;
 pub mod method;
 pub mod monitored_item;
 pub mod node_management;
+pub mod query;
 pub mod session;
 pub mod subscription;
 pub mod view;
@@ -7,6 +7,7 @@ use crate::{
     prelude::*,
     subscriptions::subscription::{TickReason, SubscriptionState},
     subscriptions::monitored_item::*,
+    subscriptions::sampling_group::SamplingGroups,
     services::{
         subscription::SubscriptionService,
         monitored_item::MonitoredItemService,
@@ -267,6 +268,86 @@ fn deadband_pct() {
     assert_eq!(DataChangeFilter::pct_compare(101.0001f64, 100f64, 0f64, 100f64, 1.0002f64), true);
 }
 
+fn make_deadband_create_request(attribute_id: AttributeId, deadband_type: u32, deadband_value: f64) -> MonitoredItemCreateRequest {
+    let mut request = make_create_request(-1f64, 5);
+    request.item_to_monitor.attribute_id = attribute_id as u32;
+    request.requested_parameters.filter = ExtensionObject::from_encodable(ObjectId::DataChangeFilter_Encoding_DefaultBinary, &DataChangeFilter {
+        trigger: DataChangeTrigger::StatusValue,
+        deadband_type,
+        deadband_value,
+    });
+    request
+}
+
+fn add_eu_range(address_space: &mut AddressSpace, node_id: &NodeId, low: f64, high: f64) {
+    let eu_range_id = NodeId::new(1, "EURange");
+    let range = ExtensionObject::from_encodable(ObjectId::Range_Encoding_DefaultBinary, &Range { low, high });
+    address_space.insert(Variable::new_data_value(&eu_range_id, "EURange", "EURange", DataTypeId::Range, Variant::from(range)), None);
+    address_space.insert_reference(node_id, &eu_range_id, ReferenceTypeId::HasProperty);
+}
+
+fn set_test_var_value(address_space: &mut AddressSpace, value: Variant) {
+    if let &mut NodeType::Variable(ref mut node) = address_space.find_node_mut(&test_var_node_id()).unwrap() {
+        node.set_value(value);
+    } else {
+        panic!("Expected a variable, didn't get one!!");
+    }
+}
+
+#[test]
+fn monitored_item_deadband_validation() {
+    let now = chrono::Utc::now();
+
+    // Unknown deadband type, negative deadbands and percentages over 100 are invalid
+    let invalid = [(3, 1f64), (1, -1f64), (2, -1f64), (2, 101f64)];
+    invalid.iter().for_each(|(deadband_type, deadband_value)| {
+        let request = make_deadband_create_request(AttributeId::Value, *deadband_type, *deadband_value);
+        assert_eq!(MonitoredItem::new(&now, 1, TimestampsToReturn::Both, &request).unwrap_err(), StatusCode::BadDeadbandFilterInvalid);
+    });
+
+    // Deadbands only apply to the value attribute
+    let request = make_deadband_create_request(AttributeId::DisplayName, 1, 1f64);
+    assert_eq!(MonitoredItem::new(&now, 1, TimestampsToReturn::Both, &request).unwrap_err(), StatusCode::BadFilterNotAllowed);
+
+    // An absolute deadband doesn't care about the node
+    let mut address_space = make_address_space();
+    let request = make_deadband_create_request(AttributeId::Value, 1, 1f64);
+    let monitored_item = MonitoredItem::new(&now, 1, TimestampsToReturn::Both, &request).unwrap();
+    assert!(monitored_item.validate_node(&address_space).is_ok());
+
+    // A percent deadband requires the node to have an EURange
+    let request = make_deadband_create_request(AttributeId::Value, 2, 10f64);
+    let monitored_item = MonitoredItem::new(&now, 1, TimestampsToReturn::Both, &request).unwrap();
+    assert_eq!(monitored_item.validate_node(&address_space).unwrap_err(), StatusCode::BadMonitoredItemFilterUnsupported);
+
+    add_eu_range(&mut address_space, &test_var_node_id(), 0f64, 200f64);
+    assert!(monitored_item.validate_node(&address_space).is_ok());
+}
+
+#[test]
+fn monitored_item_deadband_pct() {
+    let mut address_space = make_address_space();
+    add_eu_range(&mut address_space, &test_var_node_id(), 0f64, 200f64);
+    set_test_var_value(&mut address_space, Variant::Double(100f64));
+
+    // 10% of a 0..200 range means changes of 20 or less are not reported
+    let request = make_deadband_create_request(AttributeId::Value, 2, 10f64);
+    let mut monitored_item = MonitoredItem::new(&chrono::Utc::now(), 1, TimestampsToReturn::Both, &request).unwrap();
+    assert!(monitored_item.validate_node(&address_space).is_ok());
+
+    let now = Utc::now();
+    assert_eq!(monitored_item.tick(&now, &address_space, true, false), TickResult::ReportValueChanged);
+
+    // The first notification is still queued so the tick reports, but the change is not queued
+    set_test_var_value(&mut address_space, Variant::Double(115f64));
+    assert_eq!(monitored_item.tick(&now, &address_space, true, false), TickResult::ReportValueChanged);
+    assert_eq!(monitored_item.notification_queue().len(), 1);
+
+    set_test_var_value(&mut address_space, Variant::Double(125f64));
+    assert_eq!(monitored_item.tick(&now, &address_space, true, false), TickResult::ReportValueChanged);
+    assert_eq!(monitored_item.notification_queue().len(), 2);
+}
+
 #[test]
 fn monitored_item_data_change_filter() {
     // create an address space
@@ -330,7 +411,7 @@ fn monitored_item_triggers() {
             NodeId::new(1, var_name(2)),
             NodeId::new(1, var_name(3)),
         ]);
-        let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(session, &request).unwrap(), CreateMonitoredItemsResponse);
+        let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(session, address_space, &request).unwrap(), CreateMonitoredItemsResponse);
 
         // The first monitored item will be the triggering item, the other 3 will be triggered items
         let monitored_item_ids: Vec<u32> = response.results.unwrap().iter().map(|mir| {
@@ -427,6 +508,42 @@ fn monitored_item_triggers() {
     });
 }
 
+#[test]
+fn monitored_item_triggers_remove() {
+    do_subscription_service_test(|server_state, session, address_space, ss: SubscriptionService, mis: MonitoredItemService| {
+        let subscription_id = {
+            let request = create_subscription_request(0, 0);
+            let response: CreateSubscriptionResponse = supported_message_as!(ss.create_subscription(server_state, session, &request).unwrap(), CreateSubscriptionResponse);
+            response.subscription_id
+        };
+
+        let request = create_monitored_items_request(subscription_id, vec![var_node_id(0), var_node_id(1), var_node_id(2)]);
+        let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(session, address_space, &request).unwrap(), CreateMonitoredItemsResponse);
+        let monitored_item_ids: Vec<u32> = response.results.unwrap().iter().map(|mir| mir.monitored_item_id).collect();
+        let triggering_item_id = monitored_item_ids[0];
+
+        let (add_results, _) = set_triggering(session, subscription_id, triggering_item_id, &[monitored_item_ids[1], monitored_item_ids[2]], &[], &mis);
+        assert_eq!(add_results.unwrap(), vec![StatusCode::Good, StatusCode::Good]);
+
+        let (_, remove_results) = set_triggering(session, subscription_id, triggering_item_id, &[], &[monitored_item_ids[1]], &mis);
+        assert_eq!(remove_results.unwrap(), vec![StatusCode::Good]);
+
+        // Removing a link that does not exist is an error, even when the item exists
+        let (_, remove_results) = set_triggering(session, subscription_id, triggering_item_id, &[], &[monitored_item_ids[1], 999], &mis);
+        assert_eq!(remove_results.unwrap(), vec![StatusCode::BadMonitoredItemIdInvalid, StatusCode::BadMonitoredItemIdInvalid]);
+
+        // Deleting a linked item deletes the link too
+        let request = DeleteMonitoredItemsRequest {
+            request_header: RequestHeader::dummy(),
+            subscription_id,
+            monitored_item_ids: Some(vec![monitored_item_ids[2]]),
+        };
+        let _ = mis.delete_monitored_items(session, &request).unwrap();
+        let subscription = session.subscriptions.get_mut(subscription_id).unwrap();
+        assert!(subscription.monitored_item_mut(triggering_item_id).unwrap().triggered_items().is_empty());
+    });
+}
+
 #[test]
 fn monitored_item_queue_discard_oldest() {
     // The purpose of this test is to monitor the discard oldest behaviour. Depending on true/false
@@ -452,3 +569,333 @@ fn monitored_item_queue_discard_oldest() {
         assert_first_notification_is_i32(&mut monitored_item, 10);
     }
 }
+
+fn notification_has_overflow(monitored_item: &mut MonitoredItem) -> bool {
+    let status = monitored_item.oldest_notification_message().unwrap().value.status();
+    status & StatusCode::OVERFLOW.bits() != 0
+}
+
+#[test]
+fn monitored_item_queue_overflow_bit() {
+    // discard_oldest = true sets the overflow bit on the oldest remaining value
+    {
+        let mut monitored_item = populate_monitored_item(true);
+        assert!(notification_has_overflow(&mut monitored_item));
+        (0..4).for_each(|_| assert!(!notification_has_overflow(&mut monitored_item)));
+    }
+
+    // discard_oldest = false sets the overflow bit on the newest value
+    {
+        let mut monitored_item = populate_monitored_item(false);
+        (0..4).for_each(|_| assert!(!notification_has_overflow(&mut monitored_item)));
+        assert!(notification_has_overflow(&mut monitored_item));
+    }
+}
+
+#[test]
+fn monitored_item_modify_queue_size() {
+    let address_space = make_address_space();
+    let modify_request = |discard_oldest| {
+        let mut request = make_create_request(-1f64, 3);
+        request.requested_parameters.discard_oldest = discard_oldest;
+        MonitoredItemModifyRequest {
+            monitored_item_id: 1,
+            requested_parameters: request.requested_parameters,
+        }
+    };
+
+    // Shrinking the queue discards the oldest values
+    let mut monitored_item = populate_monitored_item(true);
+    let _ = monitored_item.modify(&address_space, TimestampsToReturn::Both, &modify_request(true)).unwrap();
+    assert_eq!(monitored_item.queue_size(), 3);
+    assert_eq!(monitored_item.notification_queue().len(), 3);
+    assert_first_notification_is_i32(&mut monitored_item, 3);
+    assert_first_notification_is_i32(&mut monitored_item, 4);
+    assert_first_notification_is_i32(&mut monitored_item, 10);
+
+    // Or keeps the oldest and the newest values
+    let mut monitored_item = populate_monitored_item(false);
+    let _ = monitored_item.modify(&address_space, TimestampsToReturn::Both, &modify_request(false)).unwrap();
+    assert_eq!(monitored_item.notification_queue().len(), 3);
+    assert_first_notification_is_i32(&mut monitored_item, 0);
+    assert_first_notification_is_i32(&mut monitored_item, 1);
+    assert_first_notification_is_i32(&mut monitored_item, 10);
+}
+
+#[test]
+fn monitored_item_min_sampling_interval() {
+    let mut address_space = make_address_space();
+    address_space.find_variable_mut(test_var_node_id()).unwrap().set_minimum_sampling_interval(500f64);
+    let now = chrono::Utc::now();
+    let revised_sampling_interval = |address_space: &AddressSpace, node_id: NodeId, requested_sampling_interval: f64, min_sampling_interval: f64| {
+        let mut request = make_create_request(requested_sampling_interval, 1);
+        request.item_to_monitor.node_id = node_id;
+        let mut monitored_item = MonitoredItem::new(&now, 1, TimestampsToReturn::Both, &request).unwrap();
+        monitored_item.revise_sampling_interval(address_space, min_sampling_interval);
+        monitored_item.sampling_interval()
+    };
+
+    // The node's minimum sampling interval is honoured
+    assert_eq!(revised_sampling_interval(&address_space, test_var_node_id(), 1f64, 100f64), 500f64);
+    assert_eq!(revised_sampling_interval(&address_space, test_var_node_id(), 1000f64, 100f64), 1000f64);
+    // Unless the server's minimum is larger
+    assert_eq!(revised_sampling_interval(&address_space, test_var_node_id(), 1f64, 2000f64), 2000f64);
+    // Nodes without a minimum only get the server's minimum
+    assert_eq!(revised_sampling_interval(&address_space, NodeId::new(1, 2), 1f64, 100f64), 100f64);
+    // The publishing interval is left alone
+    assert_eq!(revised_sampling_interval(&address_space, test_var_node_id(), -1f64, 100f64), -1f64);
+}
+
+#[test]
+fn monitored_item_sampling_groups() {
+    let now = chrono::Utc::now();
+    let make_monitored_item = |monitored_item_id: u32, sampling_interval: f64| {
+        MonitoredItem::new(&now, monitored_item_id, TimestampsToReturn::Both, &make_create_request(sampling_interval, 1)).unwrap()
+    };
+
+    // Items with the same interval share a group
+    let mut sampling_groups = SamplingGroups::new();
+    sampling_groups.insert(&make_monitored_item(1, 100f64));
+    sampling_groups.insert(&make_monitored_item(2, 100f64));
+    sampling_groups.insert(&make_monitored_item(3, 500f64));
+    sampling_groups.insert(&make_event_monitored_item(&now, 4));
+    assert_eq!(sampling_groups.len(), 2);
+
+    // Every group is sampled on its first tick
+    let due_items = sampling_groups.take_due_items(&now, false, false);
+    assert_eq!(due_items.into_iter().collect::<Vec<u32>>(), vec![1, 2, 3]);
+
+    // Nothing is due until an interval has passed
+    assert!(sampling_groups.take_due_items(&now.add(chrono::Duration::milliseconds(50)), false, false).is_empty());
+
+    // Only the faster group is due after its interval
+    let now = now.add(chrono::Duration::milliseconds(200));
+    let due_items = sampling_groups.take_due_items(&now, false, false);
+    assert_eq!(due_items.into_iter().collect::<Vec<u32>>(), vec![1, 2]);
+
+    // Unless the data is being resent
+    let due_items = sampling_groups.take_due_items(&now, false, true);
+    assert_eq!(due_items.into_iter().collect::<Vec<u32>>(), vec![1, 2, 3]);
+
+    // A modified item moves group and the empty group is removed
+    sampling_groups.insert(&make_monitored_item(3, 100f64));
+    assert_eq!(sampling_groups.len(), 1);
+
+    // Removing the last items removes the group
+    sampling_groups.remove(1);
+    sampling_groups.remove(2);
+    sampling_groups.remove(3);
+    assert_eq!(sampling_groups.len(), 0);
+}
+
+fn make_event_monitored_item(now: &DateTimeUtc, monitored_item_id: u32) -> MonitoredItem {
+    let request = make_event_create_request(ObjectId::Server.into(), AttributeId::EventNotifier, Some(vec![event_select_clause("Message")]), 5);
+    MonitoredItem::new(now, monitored_item_id, TimestampsToReturn::Both, &request).unwrap()
+}
+
+fn event_select_clause(browse_path: &str) -> SimpleAttributeOperand {
+    SimpleAttributeOperand {
+        type_definition_id: ObjectTypeId::BaseEventType.into(),
+        browse_path: Some(vec![QualifiedName::new(0, browse_path)]),
+        attribute_id: AttributeId::Value as u32,
+        index_range: UAString::null(),
+    }
+}
+
+fn make_event_create_request(node_id: NodeId, attribute_id: AttributeId, select_clauses: Option<Vec<SimpleAttributeOperand>>, queue_size: u32) -> MonitoredItemCreateRequest {
+    let filter = ExtensionObject::from_encodable(ObjectId::EventFilter_Encoding_DefaultBinary, &EventFilter {
+        select_clauses,
+        where_clause: ContentFilter {
+            elements: None
+        },
+    });
+    MonitoredItemCreateRequest {
+        item_to_monitor: ReadValueId {
+            node_id,
+            attribute_id: attribute_id as u32,
+            index_range: UAString::null(),
+            data_encoding: QualifiedName::null(),
+        },
+        monitoring_mode: MonitoringMode::Reporting,
+        requested_parameters: MonitoringParameters {
+            client_handle: 999,
+            sampling_interval: 0f64,
+            filter,
+            queue_size,
+            discard_oldest: true,
+        },
+    }
+}
+
+#[test]
+fn monitored_item_event_filter() {
+    let now = chrono::Utc::now();
+    let select_clauses = Some(vec![event_select_clause("Message"), event_select_clause("Severity")]);
+
+    // Event filters are only allowed on the EventNotifier attribute
+    let request = make_event_create_request(ObjectId::Server.into(), AttributeId::Value, select_clauses.clone(), 0);
+    assert_eq!(MonitoredItem::new(&now, 1, TimestampsToReturn::Both, &request).unwrap_err(), StatusCode::BadFilterNotAllowed);
+
+    // An event filter must select something
+    let request = make_event_create_request(ObjectId::Server.into(), AttributeId::EventNotifier, None, 0);
+    assert_eq!(MonitoredItem::new(&now, 1, TimestampsToReturn::Both, &request).unwrap_err(), StatusCode::BadEventFilterInvalid);
+
+    // Data change filters are not allowed on the EventNotifier attribute
+    let mut request = make_create_request(0f64, 1);
+    request.item_to_monitor.attribute_id = AttributeId::EventNotifier as u32;
+    assert_eq!(MonitoredItem::new(&now, 1, TimestampsToReturn::Both, &request).unwrap_err(), StatusCode::BadFilterNotAllowed);
+
+    // A valid filter gets the default event queue size and a filter result
+    let request = make_event_create_request(ObjectId::Server.into(), AttributeId::EventNotifier, select_clauses, 0);
+    let monitored_item = MonitoredItem::new(&now, 1, TimestampsToReturn::Both, &request).unwrap();
+    assert_eq!(monitored_item.queue_size(), crate::constants::DEFAULT_EVENT_QUEUE_SIZE);
+    let filter_result = monitored_item.filter_result();
+    assert_eq!(filter_result.node_id, ObjectId::EventFilterResult_Encoding_DefaultBinary.into());
+    let filter_result = filter_result.decode_inner::<EventFilterResult>(&DecodingLimits::default()).unwrap();
+    assert_eq!(filter_result.select_clause_results, Some(vec![StatusCode::Good, StatusCode::Good]));
+}
+
+#[test]
+fn monitored_item_events() {
+    let mut address_space = make_address_space();
+    let now = chrono::Utc::now();
+
+    let source_node = test_var_node_id();
+    let other_node = NodeId::new(1, 2);
+    let select_clauses = Some(vec![event_select_clause("SourceNode"), event_select_clause("Message"), event_select_clause("Severity"), event_select_clause("Custom")]);
+
+    // One item monitors the server object which receives everything, the other only monitors the source node
+    let request = make_event_create_request(ObjectId::Server.into(), AttributeId::EventNotifier, select_clauses.clone(), 10);
+    let mut server_item = MonitoredItem::new(&now, 1, TimestampsToReturn::Both, &request).unwrap();
+    let request = make_event_create_request(source_node.clone(), AttributeId::EventNotifier, select_clauses, 10);
+    let mut source_item = MonitoredItem::new(&now, 2, TimestampsToReturn::Both, &request).unwrap();
+
+    // Events raised before the first tick are not delivered
+    address_space.raise_event(Event::new_base_event(source_node.clone(), "test1", "Too early", 100));
+    assert_eq!(server_item.tick(&now, &address_space, true, false), TickResult::NoChange);
+    assert_eq!(source_item.tick(&now, &address_space, true, false), TickResult::NoChange);
+
+    let mut event = Event::new_base_event(source_node.clone(), "test1", "Event 1", 200);
+    event.set_field("Custom", 123i32);
+    address_space.raise_event(event);
+    address_space.raise_event(Event::new_base_event(other_node.clone(), "test2", "Event 2", 300));
+
+    assert_eq!(server_item.tick(&now, &address_space, true, false), TickResult::ReportValueChanged);
+    assert_eq!(source_item.tick(&now, &address_space, true, false), TickResult::ReportValueChanged);
+    assert_eq!(server_item.notification_queue().len(), 2);
+    assert_eq!(source_item.notification_queue().len(), 1);
+
+    let notifications = source_item.all_notifications().unwrap();
+    if let Notification::EventFieldList(ref event_field_list) = notifications[0] {
+        assert_eq!(event_field_list.client_handle, 999);
+        assert_eq!(event_field_list.event_fields, Some(vec![
+            Variant::from(source_node.clone()),
+            Variant::from(LocalizedText::from("Event 1")),
+            Variant::from(200u16),
+            Variant::from(123i32),
+        ]));
+    } else {
+        panic!("Expected an event notification");
+    }
+
+    // Fields that the event does not have are empty
+    let notifications = server_item.all_notifications().unwrap();
+    if let Notification::EventFieldList(ref event_field_list) = notifications[1] {
+        let event_fields = event_field_list.event_fields.as_ref().unwrap();
+        assert_eq!(event_fields[0], Variant::from(other_node));
+        assert_eq!(event_fields[3], Variant::Empty);
+    } else {
+        panic!("Expected an event notification");
+    }
+
+    // Nothing more to report
+    assert_eq!(server_item.tick(&now, &address_space, true, false), TickResult::NoChange);
+    assert_eq!(source_item.tick(&now, &address_space, true, false), TickResult::NoChange);
+}
+
+#[test]
+fn monitored_item_events_where_clause() {
+    let mut address_space = make_address_space();
+    let now = chrono::Utc::now();
+
+    let severity_operand = ExtensionObject::from_encodable(ObjectId::SimpleAttributeOperand_Encoding_DefaultBinary, &event_select_clause("Severity"));
+    let literal_operand = |value: Variant| ExtensionObject::from_encodable(ObjectId::LiteralOperand_Encoding_DefaultBinary, &LiteralOperand { value });
+    let make_request = |filter_operands: Vec<ExtensionObject>| {
+        let mut request = make_event_create_request(ObjectId::Server.into(), AttributeId::EventNotifier, Some(vec![event_select_clause("Message")]), 10);
+        request.requested_parameters.filter = ExtensionObject::from_encodable(ObjectId::EventFilter_Encoding_DefaultBinary, &EventFilter {
+            select_clauses: Some(vec![event_select_clause("Message")]),
+            where_clause: ContentFilter {
+                elements: Some(vec![ContentFilterElement {
+                    filter_operator: FilterOperator::GreaterThan,
+                    filter_operands: Some(filter_operands),
+                }]),
+            },
+        });
+        request
+    };
+
+    // A where clause with the wrong number of operands is invalid
+    let request = make_request(vec![severity_operand.clone()]);
+    let monitored_item = MonitoredItem::new(&now, 1, TimestampsToReturn::Both, &request).unwrap();
+    assert_eq!(monitored_item.validate_node(&address_space).unwrap_err(), StatusCode::BadMonitoredItemFilterInvalid);
+
+    // Only events with a severity over 250 are queued
+    let request = make_request(vec![severity_operand, literal_operand(Variant::from(250u16))]);
+    let mut monitored_item = MonitoredItem::new(&now, 1, TimestampsToReturn::Both, &request).unwrap();
+    assert!(monitored_item.validate_node(&address_space).is_ok());
+    assert_eq!(monitored_item.tick(&now, &address_space, true, false), TickResult::NoChange);
+
+    address_space.raise_event(Event::new_base_event(test_var_node_id(), "test1", "Event 1", 200));
+    address_space.raise_event(Event::new_base_event(test_var_node_id(), "test1", "Event 2", 300));
+    assert_eq!(monitored_item.tick(&now, &address_space, true, false), TickResult::ReportValueChanged);
+
+    let notifications = monitored_item.all_notifications().unwrap();
+    assert_eq!(notifications.len(), 1);
+    if let Notification::EventFieldList(ref event_field_list) = notifications[0] {
+        assert_eq!(event_field_list.event_fields, Some(vec![Variant::from(LocalizedText::from("Event 2"))]));
+    } else {
+        panic!("Expected an event notification");
+    }
+}
+
+#[test]
+fn monitored_item_events_publish() {
+    do_subscription_service_test(|server_state, session, address_space, ss: SubscriptionService, mis: MonitoredItemService| {
+        let subscription_id = {
+            let request = create_subscription_request(0, 0);
+            let response: CreateSubscriptionResponse = supported_message_as!(ss.create_subscription(server_state, session, &request).unwrap(), CreateSubscriptionResponse);
+            response.subscription_id
+        };
+        session.subscriptions.get_mut(subscription_id).unwrap().set_state(SubscriptionState::Normal);
+
+        let request = CreateMonitoredItemsRequest {
+            request_header: RequestHeader::dummy(),
+            subscription_id,
+            timestamps_to_return: TimestampsToReturn::Both,
+            items_to_create: Some(vec![make_event_create_request(ObjectId::Server.into(), AttributeId::EventNotifier, Some(vec![event_select_clause("Message")]), 0)]),
+        };
+        let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(session, address_space, &request).unwrap(), CreateMonitoredItemsResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].status_code, StatusCode::Good);
+        assert!(!results[0].filter_result.is_null());
+
+        // The first tick primes the monitored item and sends the subscription's first keep alive
+        let now = Utc::now();
+        let now = publish_tick_response(session, &ss, address_space, now, chrono::Duration::seconds(2), |response| {
+            assert!(response.notification_message.notification_data.is_none());
+        });
+
+        address_space.raise_event(Event::new_base_event(ObjectId::Server, "Server", "Hello", 100));
+
+        let _ = publish_tick_response(session, &ss, address_space, now, chrono::Duration::seconds(2), |response| {
+            assert!(response.notification_message.data_change_notifications(&DecodingLimits::default()).is_empty());
+            let notifications = response.notification_message.event_notifications(&DecodingLimits::default());
+            assert_eq!(notifications.len(), 1);
+            let events = notifications[0].events.as_ref().unwrap();
+            assert_eq!(events.len(), 1);
+            // The empty locale of the message is not encoded so it is null in the notification
+            let message = LocalizedText { locale: UAString::null(), text: UAString::from("Hello") };
+            assert_eq!(events[0].event_fields, Some(vec![Variant::from(message)]));
+        });
+    });
+}
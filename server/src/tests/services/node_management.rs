@@ -1,7 +1,7 @@
 use opcua_types::node_ids::{ObjectId, ObjectTypeId, ReferenceTypeId, DataTypeId, MethodId};
 
 use crate::{
-    services::node_management::NodeManagementService,
+    services::{node_management::NodeManagementService, view::ViewService},
 };
 
 use super::*;
@@ -52,14 +52,20 @@ fn do_add_references_test(can_modify_address_space: bool, item: AddReferencesIte
     do_node_management_service_test(can_modify_address_space, |server_state, session, address_space, nms| {
         let response = nms.add_references(server_state, session, address_space, &AddReferencesRequest {
             request_header: RequestHeader::dummy(),
-            references_to_add: Some(vec![item]),
+            references_to_add: Some(vec![item.clone()]),
         });
         let response: AddReferencesResponse = supported_message_as!(response.unwrap(), AddReferencesResponse);
         let results = response.results.unwrap();
         assert_eq!(results.len(), 1);
         assert_eq!(format!("{}", results[0]), format!("{}", expected_status_code));
         if expected_status_code.is_good() {
-            // TODO expect the reference to exist
+            let reference_type_id = item.reference_type_id.as_reference_type_id().unwrap();
+            let (from, to) = if item.is_forward {
+                (&item.source_node_id, &item.target_node_id.node_id)
+            } else {
+                (&item.target_node_id.node_id, &item.source_node_id)
+            };
+            assert!(address_space.has_reference(from, to, reference_type_id));
         }
     });
 }
@@ -309,6 +315,116 @@ fn add_nodes_valid() {
     }, StatusCode::Good);
 }
 
+#[test]
+fn add_nodes_browse_name_with_namespace() {
+    // Add a node whose browse name is in a non-zero namespace
+    do_add_node_test_with_expected_error(true, AddNodesItem {
+        parent_node_id: ObjectId::ObjectsFolder.into(),
+        reference_type_id: ReferenceTypeId::Organizes.into(),
+        requested_new_node_id: ExpandedNodeId::null(),
+        browse_name: QualifiedName::new(2, "boo"),
+        node_class: NodeClass::Object,
+        node_attributes: object_attributes("foo"),
+        type_definition: ObjectTypeId::BaseObjectType.into(),
+    }, StatusCode::Good);
+
+    // Add a node whose browse name contains characters reserved by the relative path syntax
+    do_add_node_test_with_expected_error(true, AddNodesItem {
+        parent_node_id: ObjectId::ObjectsFolder.into(),
+        reference_type_id: ReferenceTypeId::Organizes.into(),
+        requested_new_node_id: ExpandedNodeId::null(),
+        browse_name: QualifiedName::new(1, "a/b.c<d>"),
+        node_class: NodeClass::Object,
+        node_attributes: object_attributes("foo"),
+        type_definition: ObjectTypeId::BaseObjectType.into(),
+    }, StatusCode::Good);
+}
+
+#[test]
+fn add_nodes_browse_name_with_namespace_duplicated() {
+    // Adding the same namespaced browse name twice under one parent must fail the second time
+    do_node_management_service_test(true, |server_state, session, address_space, nms| {
+        let item = AddNodesItem {
+            parent_node_id: ObjectId::ObjectsFolder.into(),
+            reference_type_id: ReferenceTypeId::Organizes.into(),
+            requested_new_node_id: ExpandedNodeId::null(),
+            browse_name: QualifiedName::new(2, "boo"),
+            node_class: NodeClass::Object,
+            node_attributes: object_attributes("foo"),
+            type_definition: ObjectTypeId::BaseObjectType.into(),
+        };
+        let response = nms.add_nodes(server_state, session, address_space, &AddNodesRequest {
+            request_header: RequestHeader::dummy(),
+            nodes_to_add: Some(vec![item.clone(), item]),
+        });
+        let response: AddNodesResponse = supported_message_as!(response.unwrap(), AddNodesResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].status_code, StatusCode::Good);
+        assert_eq!(results[1].status_code, StatusCode::BadBrowseNameDuplicated);
+    });
+}
+
+#[test]
+fn add_nodes_parent_references_child() {
+    // Browsing forward from the parent must find the node that was added beneath it
+    do_node_management_service_test(true, |server_state, session, address_space, nms| {
+        let response = nms.add_nodes(server_state, session, address_space, &AddNodesRequest {
+            request_header: RequestHeader::dummy(),
+            nodes_to_add: Some(vec![AddNodesItem {
+                parent_node_id: ObjectId::ObjectsFolder.into(),
+                reference_type_id: ReferenceTypeId::Organizes.into(),
+                requested_new_node_id: ExpandedNodeId::null(),
+                browse_name: QualifiedName::from("boo"),
+                node_class: NodeClass::Object,
+                node_attributes: object_attributes("foo"),
+                type_definition: ObjectTypeId::BaseObjectType.into(),
+            }]),
+        });
+        let response: AddNodesResponse = supported_message_as!(response.unwrap(), AddNodesResponse);
+        let added_node_id = response.results.unwrap()[0].added_node_id.clone();
+
+        let response = ViewService::new().browse(server_state, session, address_space, &BrowseRequest {
+            request_header: RequestHeader::dummy(),
+            view: ViewDescription {
+                view_id: NodeId::null(),
+                timestamp: DateTime::now(),
+                view_version: 0,
+            },
+            requested_max_references_per_node: 0,
+            nodes_to_browse: Some(vec![BrowseDescription {
+                node_id: ObjectId::ObjectsFolder.into(),
+                browse_direction: BrowseDirection::Forward,
+                reference_type_id: ReferenceTypeId::Organizes.into(),
+                include_subtypes: true,
+                node_class_mask: 0xff,
+                result_mask: 0xff,
+            }]),
+        });
+        let response: BrowseResponse = supported_message_as!(response.unwrap(), BrowseResponse);
+        let references = response.results.unwrap()[0].references.clone().unwrap();
+        let reference = references.iter().find(|r| r.node_id.node_id == added_node_id).unwrap();
+        assert!(reference.is_forward);
+        assert_eq!(reference.reference_type_id, ReferenceTypeId::Organizes.into());
+    });
+}
+
+#[test]
+fn add_nodes_parent_on_other_server() {
+    // Add a node whose parent lives on another server
+    let mut parent_node_id: ExpandedNodeId = ObjectId::ObjectsFolder.into();
+    parent_node_id.server_index = 1;
+    do_add_node_test_with_expected_error(true, AddNodesItem {
+        parent_node_id,
+        reference_type_id: ReferenceTypeId::Organizes.into(),
+        requested_new_node_id: ExpandedNodeId::null(),
+        browse_name: QualifiedName::from("boo"),
+        node_class: NodeClass::Object,
+        node_attributes: object_attributes("foo"),
+        type_definition: ObjectTypeId::BaseObjectType.into(),
+    }, StatusCode::BadParentNodeIdInvalid);
+}
+
 #[test]
 fn add_nodes_user_access_denied() {
     // Add a node without permission
@@ -0,0 +1,340 @@
+use opcua_types::node_ids::{ObjectId, ObjectTypeId, DataTypeId, ReferenceTypeId};
+
+use crate::services::query::QueryService;
+
+use super::*;
+
+// Query service tests
+
+const PUMP_COUNT: usize = 5;
+
+fn pump_type_id() -> NodeId { NodeId::new(1, "PumpType") }
+
+fn pump_id(idx: usize) -> NodeId { NodeId::new(1, format!("Pump{}", idx)) }
+
+/// Adds a pump type and pumps of that type. Each pump has a Speed property of ten times its index.
+fn add_pumps_to_address_space(address_space: &mut AddressSpace) {
+    let pump_type_id = pump_type_id();
+    address_space.insert(ObjectType::new(&pump_type_id, "PumpType", "PumpType", false), Some(&[
+        (&ObjectTypeId::BaseObjectType.into(), ReferenceTypeId::HasSubtype, ReferenceDirection::Inverse),
+    ]));
+    let folder_id = address_space.add_folder("Pumps", "Pumps", &AddressSpace::objects_folder_id()).unwrap();
+    for idx in 0..PUMP_COUNT {
+        let pump_id = pump_id(idx);
+        let pump_name = format!("Pump{}", idx);
+        address_space.insert(Object::new(&pump_id, pump_name.as_str(), pump_name.as_str(), 0), Some(&[
+            (&folder_id, ReferenceTypeId::Organizes, ReferenceDirection::Inverse),
+            (&pump_type_id, ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+        ]));
+        let speed_id = NodeId::new(1, format!("Pump{}.Speed", idx));
+        address_space.insert(Variable::new(&speed_id, "Speed", "Speed", (idx * 10) as i32), Some(&[
+            (&pump_id, ReferenceTypeId::HasProperty, ReferenceDirection::Inverse),
+        ]));
+    }
+}
+
+fn do_query_service_test<F>(f: F)
+    where F: FnOnce(&mut ServerState, &mut Session, &mut AddressSpace, &QueryService)
+{
+    let st = ServiceTest::new();
+    let (mut server_state, mut session) = st.get_server_state_and_session();
+    let mut address_space = trace_write_lock_unwrap!(st.address_space);
+    add_pumps_to_address_space(&mut address_space);
+    f(&mut server_state, &mut session, &mut address_space, &QueryService::new());
+}
+
+fn speed_operand() -> ExtensionObject {
+    ExtensionObject::from_encodable(ObjectId::SimpleAttributeOperand_Encoding_DefaultBinary, &SimpleAttributeOperand {
+        type_definition_id: pump_type_id(),
+        browse_path: Some(vec![QualifiedName::new(0, "Speed")]),
+        attribute_id: AttributeId::Value as u32,
+        index_range: UAString::null(),
+    })
+}
+
+fn browse_name_operand() -> ExtensionObject {
+    ExtensionObject::from_encodable(ObjectId::SimpleAttributeOperand_Encoding_DefaultBinary, &SimpleAttributeOperand {
+        type_definition_id: pump_type_id(),
+        browse_path: None,
+        attribute_id: AttributeId::BrowseName as u32,
+        index_range: UAString::null(),
+    })
+}
+
+fn pump_node_type() -> NodeTypeDescription {
+    NodeTypeDescription {
+        type_definition_node: pump_type_id().into(),
+        include_sub_types: false,
+        data_to_return: Some(vec![QueryDataDescription {
+            relative_path: RelativePath {
+                elements: Some(vec![RelativePathElement {
+                    reference_type_id: ReferenceTypeId::HasProperty.into(),
+                    is_inverse: false,
+                    include_subtypes: false,
+                    target_name: QualifiedName::new(0, "Speed"),
+                }]),
+            },
+            attribute_id: AttributeId::Value as u32,
+            index_range: UAString::null(),
+        }]),
+    }
+}
+
+fn make_query_first_request(node_types: Vec<NodeTypeDescription>, elements: Vec<ContentFilterElement>, max_data_sets_to_return: u32) -> QueryFirstRequest {
+    QueryFirstRequest {
+        request_header: make_request_header(),
+        view: ViewDescription {
+            view_id: NodeId::null(),
+            timestamp: DateTime::now(),
+            view_version: 0,
+        },
+        node_types: Some(node_types),
+        filter: ContentFilter {
+            elements: if elements.is_empty() { None } else { Some(elements) },
+        },
+        max_data_sets_to_return,
+        max_references_to_return: 0,
+    }
+}
+
+fn make_query_next_request(continuation_point: &ByteString, release_continuation_point: bool) -> QueryNextRequest {
+    QueryNextRequest {
+        request_header: make_request_header(),
+        release_continuation_point,
+        continuation_point: continuation_point.clone(),
+    }
+}
+
+fn do_query_first(qs: &QueryService, server_state: &ServerState, session: &mut Session, address_space: &AddressSpace, elements: Vec<ContentFilterElement>, max_data_sets_to_return: u32) -> QueryFirstResponse {
+    let request = make_query_first_request(vec![pump_node_type()], elements, max_data_sets_to_return);
+    let response = qs.query_first(server_state, session, address_space, &request);
+    assert!(response.is_ok());
+    supported_message_as!(response.unwrap(), QueryFirstResponse)
+}
+
+/// Returns the indexes of the pumps in the data sets, sorted
+fn pump_indexes(query_data_sets: &[QueryDataSet]) -> Vec<usize> {
+    let mut result = query_data_sets.iter().map(|data_set| {
+        (0..PUMP_COUNT).find(|idx| data_set.node_id.node_id == pump_id(*idx)).unwrap()
+    }).collect::<Vec<usize>>();
+    result.sort();
+    result
+}
+
+fn query_pumps(elements: Vec<ContentFilterElement>) -> Vec<usize> {
+    let mut result = Vec::new();
+    do_query_service_test(|server_state, session, address_space, qs| {
+        let response = do_query_first(qs, server_state, session, address_space, elements, 0);
+        assert_eq!(response.response_header.service_result, StatusCode::Good);
+        assert!(response.continuation_point.is_null());
+        result = pump_indexes(response.query_data_sets.as_ref().unwrap());
+    });
+    result
+}
+
+#[test]
+fn query_first_nothing_to_do() {
+    do_query_service_test(|server_state, session, address_space, qs| {
+        let request = make_query_first_request(vec![], vec![], 0);
+        let response = qs.query_first(server_state, session, address_space, &request);
+        let response: ServiceFault = supported_message_as!(response.unwrap(), ServiceFault);
+        assert_eq!(response.response_header.service_result, StatusCode::BadNothingToDo);
+    });
+}
+
+#[test]
+fn query_first_all_instances() {
+    do_query_service_test(|server_state, session, address_space, qs| {
+        let response = do_query_first(qs, server_state, session, address_space, vec![], 0);
+        assert_eq!(response.response_header.service_result, StatusCode::Good);
+        let query_data_sets = response.query_data_sets.unwrap();
+        assert_eq!(pump_indexes(&query_data_sets), vec![0, 1, 2, 3, 4]);
+
+        // Each data set holds the speed of the pump
+        query_data_sets.iter().for_each(|data_set| {
+            let idx = pump_indexes(&[data_set.clone()])[0];
+            assert_eq!(data_set.type_definition_node.node_id, pump_type_id());
+            assert_eq!(data_set.values.as_ref().unwrap(), &vec![Variant::Int32((idx * 10) as i32)]);
+        });
+    });
+}
+
+#[test]
+fn query_first_sub_types() {
+    do_query_service_test(|server_state, session, address_space, qs| {
+        // Pumps are objects, so a query for objects and their subtypes finds them
+        let node_type = NodeTypeDescription {
+            type_definition_node: ObjectTypeId::BaseObjectType.into(),
+            include_sub_types: true,
+            data_to_return: None,
+        };
+        let request = make_query_first_request(vec![node_type.clone()], vec![], 0);
+        let response = supported_message_as!(qs.query_first(server_state, session, address_space, &request).unwrap(), QueryFirstResponse);
+        let query_data_sets = response.query_data_sets.unwrap();
+        assert!(query_data_sets.len() > PUMP_COUNT);
+        (0..PUMP_COUNT).for_each(|idx| {
+            assert!(query_data_sets.iter().any(|data_set| data_set.node_id.node_id == pump_id(idx)));
+        });
+
+        // But not when subtypes are excluded
+        let node_type = NodeTypeDescription {
+            include_sub_types: false,
+            ..node_type
+        };
+        let request = make_query_first_request(vec![node_type], vec![], 0);
+        let response = supported_message_as!(qs.query_first(server_state, session, address_space, &request).unwrap(), QueryFirstResponse);
+        let query_data_sets = response.query_data_sets.unwrap();
+        assert!(!query_data_sets.iter().any(|data_set| data_set.node_id.node_id == pump_id(0)));
+    });
+}
+
+#[test]
+fn query_first_comparisons() {
+    assert_eq!(query_pumps(vec![filter_element(FilterOperator::Equals, vec![speed_operand(), literal_operand(20)])]), vec![2]);
+    assert_eq!(query_pumps(vec![filter_element(FilterOperator::GreaterThan, vec![speed_operand(), literal_operand(20)])]), vec![3, 4]);
+    assert_eq!(query_pumps(vec![filter_element(FilterOperator::LessThan, vec![speed_operand(), literal_operand(20)])]), vec![0, 1]);
+    assert_eq!(query_pumps(vec![filter_element(FilterOperator::GreaterThanOrEqual, vec![speed_operand(), literal_operand(20)])]), vec![2, 3, 4]);
+    assert_eq!(query_pumps(vec![filter_element(FilterOperator::LessThanOrEqual, vec![speed_operand(), literal_operand(20)])]), vec![0, 1, 2]);
+    // Numbers of different types compare by value
+    assert_eq!(query_pumps(vec![filter_element(FilterOperator::Equals, vec![speed_operand(), literal_operand(30f64)])]), vec![3]);
+    assert_eq!(query_pumps(vec![filter_element(FilterOperator::Between, vec![speed_operand(), literal_operand(10), literal_operand(30)])]), vec![1, 2, 3]);
+    assert_eq!(query_pumps(vec![filter_element(FilterOperator::InList, vec![speed_operand(), literal_operand(0), literal_operand(40), literal_operand(50)])]), vec![0, 4]);
+    assert_eq!(query_pumps(vec![filter_element(FilterOperator::IsNull, vec![speed_operand()])]), Vec::<usize>::new());
+}
+
+#[test]
+fn query_first_like() {
+    assert_eq!(query_pumps(vec![filter_element(FilterOperator::Like, vec![browse_name_operand(), literal_operand("Pump%")])]), vec![0, 1, 2, 3, 4]);
+    assert_eq!(query_pumps(vec![filter_element(FilterOperator::Like, vec![browse_name_operand(), literal_operand("Pump_")])]), vec![0, 1, 2, 3, 4]);
+    assert_eq!(query_pumps(vec![filter_element(FilterOperator::Like, vec![browse_name_operand(), literal_operand("%[13]")])]), vec![1, 3]);
+    assert_eq!(query_pumps(vec![filter_element(FilterOperator::Like, vec![browse_name_operand(), literal_operand("Pump[^0-2]")])]), vec![3, 4]);
+    assert_eq!(query_pumps(vec![filter_element(FilterOperator::Like, vec![browse_name_operand(), literal_operand("Valve%")])]), Vec::<usize>::new());
+}
+
+#[test]
+fn query_first_logical_operators() {
+    // Speed >= 10 And Not(Speed == 30)
+    let elements = vec![
+        filter_element(FilterOperator::And, vec![element_operand(1), element_operand(2)]),
+        filter_element(FilterOperator::GreaterThanOrEqual, vec![speed_operand(), literal_operand(10)]),
+        filter_element(FilterOperator::Not, vec![element_operand(3)]),
+        filter_element(FilterOperator::Equals, vec![speed_operand(), literal_operand(30)]),
+    ];
+    assert_eq!(query_pumps(elements), vec![1, 2, 4]);
+
+    // Speed == 0 Or Speed == 40
+    let elements = vec![
+        filter_element(FilterOperator::Or, vec![element_operand(1), element_operand(2)]),
+        filter_element(FilterOperator::Equals, vec![speed_operand(), literal_operand(0)]),
+        filter_element(FilterOperator::Equals, vec![speed_operand(), literal_operand(40)]),
+    ];
+    assert_eq!(query_pumps(elements), vec![0, 4]);
+}
+
+#[test]
+fn query_first_invalid_filter() {
+    do_query_service_test(|server_state, session, address_space, qs| {
+        let elements = vec![
+            filter_element(FilterOperator::And, vec![element_operand(1), element_operand(0)]),
+            filter_element(FilterOperator::Cast, vec![speed_operand(), literal_operand(NodeId::from(&DataTypeId::String))]),
+            filter_element(FilterOperator::Equals, vec![speed_operand()]),
+        ];
+        let response = do_query_first(qs, server_state, session, address_space, elements, 0);
+        assert_eq!(response.response_header.service_result, StatusCode::BadContentFilterInvalid);
+        assert!(response.query_data_sets.is_none());
+        let element_results = response.filter_result.element_results.unwrap();
+        assert_eq!(element_results.len(), 3);
+        // An element operand may only refer to a later element
+        assert_eq!(element_results[0].status_code, StatusCode::BadFilterOperandInvalid);
+        assert_eq!(element_results[0].operand_status_codes.as_ref().unwrap(), &vec![StatusCode::Good, StatusCode::BadFilterOperandInvalid]);
+        assert_eq!(element_results[1].status_code, StatusCode::BadFilterOperatorUnsupported);
+        assert_eq!(element_results[2].status_code, StatusCode::BadFilterOperandCountMismatch);
+    });
+}
+
+#[test]
+fn query_first_invalid_node_type() {
+    do_query_service_test(|server_state, session, address_space, qs| {
+        let node_type = NodeTypeDescription {
+            type_definition_node: NodeId::new(1, "UnknownType").into(),
+            include_sub_types: false,
+            data_to_return: None,
+        };
+        let request = make_query_first_request(vec![pump_node_type(), node_type], vec![], 0);
+        let response = supported_message_as!(qs.query_first(server_state, session, address_space, &request).unwrap(), QueryFirstResponse);
+        assert_eq!(response.response_header.service_result, StatusCode::BadInvalidArgument);
+        let parsing_results = response.parsing_results.unwrap();
+        assert_eq!(parsing_results.len(), 2);
+        assert_eq!(parsing_results[0].status_code, StatusCode::Good);
+        assert_eq!(parsing_results[1].status_code, StatusCode::BadNodeIdUnknown);
+    });
+}
+
+#[test]
+fn query_next() {
+    do_query_service_test(|server_state, session, address_space, qs| {
+        // Two data sets at a time
+        let response = do_query_first(qs, server_state, session, address_space, vec![], 2);
+        let mut pumps = response.query_data_sets.unwrap();
+        assert_eq!(pumps.len(), 2);
+        assert!(!response.continuation_point.is_null());
+
+        let request = make_query_next_request(&response.continuation_point, false);
+        let response = supported_message_as!(qs.query_next(session, &request).unwrap(), QueryNextResponse);
+        let mut query_data_sets = response.query_data_sets.unwrap();
+        assert_eq!(query_data_sets.len(), 2);
+        assert!(!response.revised_continuation_point.is_null());
+        pumps.append(&mut query_data_sets);
+
+        // The continuation point is used up
+        let fault = qs.query_next(session, &request).unwrap();
+        let fault: ServiceFault = supported_message_as!(fault, ServiceFault);
+        assert_eq!(fault.response_header.service_result, StatusCode::BadContinuationPointInvalid);
+
+        // The last data set has no continuation point
+        let request = make_query_next_request(&response.revised_continuation_point, false);
+        let response = supported_message_as!(qs.query_next(session, &request).unwrap(), QueryNextResponse);
+        let mut query_data_sets = response.query_data_sets.unwrap();
+        assert_eq!(query_data_sets.len(), 1);
+        assert!(response.revised_continuation_point.is_null());
+        pumps.append(&mut query_data_sets);
+
+        assert_eq!(pump_indexes(&pumps), vec![0, 1, 2, 3, 4]);
+    });
+}
+
+#[test]
+fn query_next_release() {
+    do_query_service_test(|server_state, session, address_space, qs| {
+        let response = do_query_first(qs, server_state, session, address_space, vec![], 2);
+        assert!(!response.continuation_point.is_null());
+
+        // Releasing the continuation point returns nothing and uses it up
+        let request = make_query_next_request(&response.continuation_point, true);
+        let response = supported_message_as!(qs.query_next(session, &request).unwrap(), QueryNextResponse);
+        assert!(response.query_data_sets.is_none());
+        assert!(response.revised_continuation_point.is_null());
+
+        let response = qs.query_next(session, &request).unwrap();
+        let response: ServiceFault = supported_message_as!(response, ServiceFault);
+        assert_eq!(response.response_header.service_result, StatusCode::BadContinuationPointInvalid);
+    });
+}
+
+struct FirstPumpQueryEvaluator;
+
+impl QueryEvaluator for FirstPumpQueryEvaluator {
+    fn evaluate(&self, _address_space: &AddressSpace, _node_types: &[NodeTypeDescription], _filter: &ContentFilter) -> Result<Vec<(NodeId, usize)>, StatusCode> {
+        Ok(vec![(pump_id(0), 0)])
+    }
+}
+
+#[test]
+fn query_first_custom_evaluator() {
+    do_query_service_test(|server_state, session, address_space, qs| {
+        server_state.set_query_evaluator(Box::new(FirstPumpQueryEvaluator));
+        let response = do_query_first(qs, server_state, session, address_space, vec![], 0);
+        assert_eq!(response.response_header.service_result, StatusCode::Good);
+        assert_eq!(pump_indexes(response.query_data_sets.as_ref().unwrap()), vec![0]);
+    });
+}
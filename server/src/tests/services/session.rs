@@ -1,6 +1,12 @@
+use std::sync::{Arc, Mutex};
+
 use crate::tests::*;
 
 use crate::builder::ServerBuilder;
+use crate::callbacks::{IssuedTokenValidator, ServerCallbacks, UserIdentity};
+use crate::comms::transport::Transport;
+use crate::services::session::SessionService;
+use crate::subscriptions::PublishRequestEntry;
 use opcua_types::service_types::{ActivateSessionRequest, SignatureData, RequestHeader};
 
 fn dummy_activate_session_request() -> ActivateSessionRequest {
@@ -32,7 +38,7 @@ fn anonymous_user_token() {
 
     let result = server_state.authenticate_endpoint(&request, "opc.tcp://localhost:4855/", SecurityPolicy::None, MessageSecurityMode::None, &token, &server_nonce);
     trace!("result = {:?}", result);
-    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), UserIdentity::Anonymous);
 
     let result = server_state.authenticate_endpoint(&request, "opc.tcp://localhost:4855/x", SecurityPolicy::None, MessageSecurityMode::None, &token, &server_nonce);
     trace!("result = {:?}", result);
@@ -66,7 +72,7 @@ fn user_name_pass_token() {
     // Test that a good user authenticates
     let token = make_user_name_identity_token("sample", b"sample1");
     let result = server_state.authenticate_endpoint(&request, "opc.tcp://localhost:4855/", SecurityPolicy::None, MessageSecurityMode::None, &token, &server_nonce);
-    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), UserIdentity::UserName("sample".to_string()));
 
     // Invalid tests
     let token = make_user_name_identity_token("samplex", b"sample1");
@@ -81,3 +87,365 @@ fn user_name_pass_token() {
     let result = server_state.authenticate_endpoint(&request, "opc.tcp://localhost:4855/", SecurityPolicy::None, MessageSecurityMode::None, &token, &server_nonce);
     assert_eq!(result.unwrap_err(), StatusCode::BadIdentityTokenRejected);
 }
+
+#[cfg(feature = "crypto")]
+fn make_x509_identity_token(cert: &X509, key: &PrivateKey, server_cert: &X509, server_nonce: &ByteString) -> (ExtensionObject, SignatureData) {
+    let token = X509IdentityToken {
+        policy_id: UAString::from("x509"),
+        certificate_data: cert.as_byte_string(),
+    };
+    let token = ExtensionObject::from_encodable(ObjectId::X509IdentityToken_Encoding_DefaultBinary, &token);
+    let signature = create_signature_data(key, SecurityPolicy::Basic256Sha256, &server_cert.as_byte_string(), server_nonce).unwrap();
+    (token, signature)
+}
+
+#[cfg(feature = "crypto")]
+#[test]
+fn x509_user_token() {
+    use crate::config::{ServerEndpoint, ServerUserToken};
+
+    // The trusted user cert is stored on disk, the untrusted one is not
+    let (user_cert, user_key) = CertificateStore::create_cert_and_pkey(&X509Data::sample_cert()).unwrap();
+    let (other_cert, other_key) = CertificateStore::create_cert_and_pkey(&X509Data::sample_cert()).unwrap();
+    let user_cert_path = make_test_file("x509_user_token.der");
+    std::fs::write(&user_cert_path, user_cert.to_der().unwrap()).unwrap();
+
+    let user_token_ids = vec!["x509_user".to_string()];
+    let server = ServerBuilder::new_sample()
+        .user_token("x509_user", ServerUserToken::new_x509("x509", &user_cert_path))
+        .endpoint("basic256sha256_sign_encrypt", ServerEndpoint::new_basic256sha256_sign_encrypt("/", &user_token_ids))
+        .endpoint("none_x509", ServerEndpoint::new_none("/x509", &user_token_ids))
+        .server().unwrap();
+    let server_state = server.server_state();
+    let server_state = server_state.read().unwrap();
+    let server_cert = server_state.server_certificate.clone().unwrap();
+
+    let server_nonce = ByteString::random(32);
+    let mut request = dummy_activate_session_request();
+
+    // Trusted cert with a valid signature
+    let (token, signature) = make_x509_identity_token(&user_cert, &user_key, &server_cert, &server_nonce);
+    request.user_token_signature = signature;
+    let result = server_state.authenticate_endpoint(&request, "opc.tcp://localhost:4855/", SecurityPolicy::Basic256Sha256, MessageSecurityMode::SignAndEncrypt, &token, &server_nonce);
+    assert!(result.is_ok());
+
+    // Signature over a different nonce
+    let result = server_state.authenticate_endpoint(&request, "opc.tcp://localhost:4855/", SecurityPolicy::Basic256Sha256, MessageSecurityMode::SignAndEncrypt, &token, &ByteString::random(32));
+    assert!(result.is_err());
+
+    // Endpoint without a security policy cannot verify the signature
+    let result = server_state.authenticate_endpoint(&request, "opc.tcp://localhost:4855/x509", SecurityPolicy::None, MessageSecurityMode::None, &token, &server_nonce);
+    assert_eq!(result.unwrap_err(), StatusCode::BadIdentityTokenInvalid);
+
+    // Untrusted cert with a valid signature
+    let (token, signature) = make_x509_identity_token(&other_cert, &other_key, &server_cert, &server_nonce);
+    request.user_token_signature = signature;
+    let result = server_state.authenticate_endpoint(&request, "opc.tcp://localhost:4855/", SecurityPolicy::Basic256Sha256, MessageSecurityMode::SignAndEncrypt, &token, &server_nonce);
+    assert_eq!(result.unwrap_err(), StatusCode::BadIdentityTokenRejected);
+}
+
+struct TestIssuedTokenValidator;
+
+impl IssuedTokenValidator for TestIssuedTokenValidator {
+    fn issued_token_type(&self) -> UAString {
+        UAString::from("http://opcfoundation.org/UA/UserToken#JWT")
+    }
+
+    fn validate(&self, _endpoint_url: &str, token_data: &ByteString) -> Result<(), StatusCode> {
+        if token_data.as_ref() == b"good_token" {
+            Ok(())
+        } else {
+            Err(StatusCode::BadIdentityTokenRejected)
+        }
+    }
+}
+
+fn make_issued_identity_token(token_data: &[u8]) -> ExtensionObject {
+    let token = IssuedIdentityToken {
+        policy_id: UAString::from("issued_token"),
+        token_data: ByteString::from(token_data),
+        encryption_algorithm: UAString::null(),
+    };
+    ExtensionObject::from_encodable(ObjectId::IssuedIdentityToken_Encoding_DefaultBinary, &token)
+}
+
+#[test]
+fn issued_identity_token() {
+    use crate::config::{ServerEndpoint, ISSUED_TOKEN_USER_TOKEN_ID};
+
+    let server = ServerBuilder::new_sample()
+        .endpoint("issued", ServerEndpoint::new_none("/issued", &[ISSUED_TOKEN_USER_TOKEN_ID.to_string()]))
+        .server().unwrap();
+    let server_state = server.server_state();
+
+    let server_nonce = ByteString::random(20);
+    let request = dummy_activate_session_request();
+    let token = make_issued_identity_token(b"good_token");
+
+    // No validator, so the token is rejected and not advertised
+    {
+        let server_state = server_state.read().unwrap();
+        let result = server_state.authenticate_endpoint(&request, "opc.tcp://localhost:4855/issued", SecurityPolicy::None, MessageSecurityMode::None, &token, &server_nonce);
+        assert_eq!(result.unwrap_err(), StatusCode::BadIdentityTokenRejected);
+        let endpoints = server_state.new_endpoint_descriptions("opc.tcp://localhost:4855/issued").unwrap();
+        assert!(endpoints[0].find_policy(UserTokenType::IssuedToken).is_none());
+    }
+
+    let mut server_state = server_state.write().unwrap();
+    server_state.set_issued_token_validator(Box::new(TestIssuedTokenValidator));
+
+    let endpoints = server_state.new_endpoint_descriptions("opc.tcp://localhost:4855/issued").unwrap();
+    let policy = endpoints[0].find_policy(UserTokenType::IssuedToken).unwrap();
+    assert_eq!(policy.issued_token_type.as_ref(), "http://opcfoundation.org/UA/UserToken#JWT");
+
+    let result = server_state.authenticate_endpoint(&request, "opc.tcp://localhost:4855/issued", SecurityPolicy::None, MessageSecurityMode::None, &token, &server_nonce);
+    assert!(result.is_ok());
+
+    let token = make_issued_identity_token(b"bad_token");
+    let result = server_state.authenticate_endpoint(&request, "opc.tcp://localhost:4855/issued", SecurityPolicy::None, MessageSecurityMode::None, &token, &server_nonce);
+    assert_eq!(result.unwrap_err(), StatusCode::BadIdentityTokenRejected);
+
+    let token = make_issued_identity_token(b"");
+    let result = server_state.authenticate_endpoint(&request, "opc.tcp://localhost:4855/issued", SecurityPolicy::None, MessageSecurityMode::None, &token, &server_nonce);
+    assert_eq!(result.unwrap_err(), StatusCode::BadIdentityTokenInvalid);
+
+    // The sample endpoint doesn't accept issued tokens
+    let token = make_issued_identity_token(b"good_token");
+    let result = server_state.authenticate_endpoint(&request, "opc.tcp://localhost:4855/", SecurityPolicy::None, MessageSecurityMode::None, &token, &server_nonce);
+    assert_eq!(result.unwrap_err(), StatusCode::BadIdentityTokenRejected);
+}
+
+#[test]
+fn sessionless_access_token() {
+    use crate::config::{ServerEndpoint, ISSUED_TOKEN_USER_TOKEN_ID};
+
+    let server = ServerBuilder::new_sample()
+        .endpoint("issued", ServerEndpoint::new_none("/issued", &[ISSUED_TOKEN_USER_TOKEN_ID.to_string()]))
+        .server().unwrap();
+    let server_state = server.server_state();
+    let mut server_state = server_state.write().unwrap();
+
+    // No validator, so the access token is rejected
+    let result = server_state.authenticate_access_token("opc.tcp://localhost:4855/issued", SecurityPolicy::None, MessageSecurityMode::None, &ByteString::from(b"good_token"));
+    assert_eq!(result.unwrap_err(), StatusCode::BadIdentityTokenRejected);
+
+    server_state.set_issued_token_validator(Box::new(TestIssuedTokenValidator));
+
+    let result = server_state.authenticate_access_token("opc.tcp://localhost:4855/issued", SecurityPolicy::None, MessageSecurityMode::None, &ByteString::from(b"good_token"));
+    assert_eq!(result.unwrap(), UserIdentity::IssuedToken(ByteString::from(b"good_token")));
+
+    let result = server_state.authenticate_access_token("opc.tcp://localhost:4855/issued", SecurityPolicy::None, MessageSecurityMode::None, &ByteString::from(b"bad_token"));
+    assert_eq!(result.unwrap_err(), StatusCode::BadIdentityTokenRejected);
+
+    let result = server_state.authenticate_access_token("opc.tcp://localhost:4855/issued", SecurityPolicy::None, MessageSecurityMode::None, &ByteString::null());
+    assert_eq!(result.unwrap_err(), StatusCode::BadIdentityTokenInvalid);
+
+    // The sample endpoint doesn't accept issued tokens so it can't be called session-less
+    let result = server_state.authenticate_access_token("opc.tcp://localhost:4855/", SecurityPolicy::None, MessageSecurityMode::None, &ByteString::from(b"good_token"));
+    assert_eq!(result.unwrap_err(), StatusCode::BadIdentityTokenRejected);
+}
+
+fn create_session_request(endpoint_url: &str) -> CreateSessionRequest {
+    CreateSessionRequest {
+        request_header: RequestHeader::dummy(),
+        client_description: ApplicationDescription::null(),
+        server_uri: UAString::null(),
+        endpoint_url: UAString::from(endpoint_url),
+        session_name: UAString::from("session"),
+        client_nonce: ByteString::null(),
+        client_certificate: ByteString::null(),
+        requested_session_timeout: 60000f64,
+        max_response_message_size: 0,
+    }
+}
+
+#[test]
+fn too_many_sessions() {
+    let server = ServerBuilder::new_anonymous("foo").max_sessions(1).server().unwrap();
+    let ss = SessionService::new();
+    let request = create_session_request("opc.tcp://localhost:4855/");
+
+    let certificate_store = server.certificate_store();
+    let server_state = server.server_state();
+
+    // The first session is allowed
+    let session1 = server.new_transport().session();
+    {
+        let certificate_store = trace_read_lock_unwrap!(certificate_store);
+        let mut server_state = trace_write_lock_unwrap!(server_state);
+        let mut session = trace_write_lock_unwrap!(session1);
+        let response = ss.create_session(&certificate_store, &mut server_state, &mut session, &request).unwrap();
+        let _ = supported_message_as!(response, CreateSessionResponse);
+    }
+
+    // The second session exceeds the limit
+    let session2 = server.new_transport().session();
+    {
+        let certificate_store = trace_read_lock_unwrap!(certificate_store);
+        let mut server_state = trace_write_lock_unwrap!(server_state);
+        let mut session = trace_write_lock_unwrap!(session2);
+        let response = ss.create_session(&certificate_store, &mut server_state, &mut session, &request).unwrap();
+        let response = supported_message_as!(response, ServiceFault);
+        assert_eq!(response.response_header.service_result, StatusCode::BadTooManySessions);
+    }
+}
+
+#[test]
+fn revised_session_timeout() {
+    let server = ServerBuilder::new_anonymous("foo").session_timeout_range(5000f64, 30000f64).server().unwrap();
+    let ss = SessionService::new();
+
+    let certificate_store = server.certificate_store();
+    let server_state = server.server_state();
+
+    // Requested timeouts are revised to fit within the configured range, with the largest value
+    // for anything that isn't a positive number
+    [(10000f64, 10000f64), (100f64, 5000f64), (120000f64, 30000f64), (0f64, 30000f64), (-1f64, 30000f64), (std::f64::NAN, 30000f64)].iter().for_each(|(requested, revised)| {
+        let mut request = create_session_request("opc.tcp://localhost:4855/");
+        request.requested_session_timeout = *requested;
+
+        let session = server.new_transport().session();
+        let certificate_store = trace_read_lock_unwrap!(certificate_store);
+        let mut server_state = trace_write_lock_unwrap!(server_state);
+        let mut session = trace_write_lock_unwrap!(session);
+        let response = ss.create_session(&certificate_store, &mut server_state, &mut session, &request).unwrap();
+        let response = supported_message_as!(response, CreateSessionResponse);
+        assert_eq!(response.revised_session_timeout, *revised);
+        assert_eq!(session.session_timeout, *revised);
+    });
+}
+
+#[test]
+fn session_timed_out() {
+    let server = ServerBuilder::new_anonymous("foo").server().unwrap();
+    let session = server.new_transport().session();
+    let mut session = trace_write_lock_unwrap!(session);
+
+    // A session that hasn't been created has no timeout
+    let now = chrono::Utc::now();
+    assert!(!session.is_timed_out(&(now + chrono::Duration::days(1))));
+
+    session.session_timeout = 5000f64;
+    session.on_activity();
+    let now = chrono::Utc::now();
+    assert!(!session.is_timed_out(&now));
+    assert!(!session.is_timed_out(&(now + chrono::Duration::milliseconds(4000))));
+    assert!(session.is_timed_out(&(now + chrono::Duration::milliseconds(6000))));
+
+    // Activity resets the timeout
+    session.on_activity();
+    assert!(!session.is_timed_out(&(chrono::Utc::now() + chrono::Duration::milliseconds(4000))));
+
+    // A terminated session doesn't time out again
+    session.set_terminated();
+    assert!(!session.is_timed_out(&(now + chrono::Duration::milliseconds(6000))));
+}
+
+#[test]
+fn cancel_requests() {
+    let server = ServerBuilder::new_anonymous("foo").server().unwrap();
+    let ss = SessionService::new();
+    let server_state = server.server_state();
+    let session = server.new_transport().session();
+    let mut server_state = trace_write_lock_unwrap!(server_state);
+    let mut session = trace_write_lock_unwrap!(session);
+
+    // Two requests waiting on async getters and a queued publish request, two with the same handle
+    let mut cancelled = session.add_async_request(1, 100);
+    let mut not_cancelled = session.add_async_request(2, 200);
+    let mut request_header = RequestHeader::dummy();
+    request_header.request_handle = 100;
+    session.subscriptions.publish_request_queue().push_front(PublishRequestEntry {
+        request_id: 3,
+        request: PublishRequest {
+            request_header,
+            subscription_acknowledgements: None,
+        },
+        results: None,
+    });
+
+    let request = CancelRequest {
+        request_header: RequestHeader::dummy(),
+        request_handle: 100,
+    };
+    let response = ss.cancel(&mut server_state, &mut session, &request).unwrap();
+    let response = supported_message_as!(response, CancelResponse);
+    assert_eq!(response.cancel_count, 2);
+
+    // The async request is told to cancel and the publish request is answered
+    assert_eq!(cancelled.try_recv(), Ok(Some(())));
+    assert_eq!(not_cancelled.try_recv(), Ok(None));
+    assert!(session.subscriptions.publish_request_queue().is_empty());
+    let publish_response = session.subscriptions.publish_response_queue().pop_back().unwrap();
+    assert_eq!(publish_response.request_id, 3);
+    let response = supported_message_as!(publish_response.response, ServiceFault);
+    assert_eq!(response.response_header.service_result, StatusCode::BadRequestCancelledByClient);
+
+    // Cancelled requests cannot be cancelled again
+    let response = ss.cancel(&mut server_state, &mut session, &request).unwrap();
+    let response = supported_message_as!(response, CancelResponse);
+    assert_eq!(response.cancel_count, 0);
+
+    // Nor can requests which have completed
+    session.enqueue_async_response(2, ServiceFault::new_supported_message(&RequestHeader::dummy(), StatusCode::Good));
+    assert_eq!(session.cancel_requests(200), 0);
+}
+
+struct LifecycleEvents(Arc<Mutex<Vec<String>>>);
+
+impl ServerCallbacks for LifecycleEvents {
+    fn on_session_created(&mut self, session: &Session) {
+        self.0.lock().unwrap().push(format!("created {}", session.session_name));
+    }
+
+    fn on_session_activated(&mut self, session: &Session) {
+        self.0.lock().unwrap().push(format!("activated {:?}", session.identity));
+    }
+
+    fn on_session_closed(&mut self, session: &Session) {
+        self.0.lock().unwrap().push(format!("closed {}", session.session_name));
+    }
+}
+
+#[test]
+fn session_lifecycle_callbacks() {
+    let server = ServerBuilder::new_anonymous("foo").server().unwrap();
+    let ss = SessionService::new();
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    let certificate_store = server.certificate_store();
+    let server_state = server.server_state();
+    {
+        let mut server_state = trace_write_lock_unwrap!(server_state);
+        server_state.set_server_callbacks(Box::new(LifecycleEvents(events.clone())));
+    }
+
+    let session = server.new_transport().session();
+    let certificate_store = trace_read_lock_unwrap!(certificate_store);
+    let mut server_state = trace_write_lock_unwrap!(server_state);
+    let mut session = trace_write_lock_unwrap!(session);
+    let response = ss.create_session(&certificate_store, &mut server_state, &mut session, &create_session_request("opc.tcp://localhost:4855/")).unwrap();
+    let _ = supported_message_as!(response, CreateSessionResponse);
+
+    let token = AnonymousIdentityToken {
+        policy_id: UAString::from(SecurityPolicy::None.to_uri())
+    };
+    let mut request = dummy_activate_session_request();
+    request.user_identity_token = ExtensionObject::from_encodable(ObjectId::AnonymousIdentityToken_Encoding_DefaultBinary, &token);
+    let response = ss.activate_session(&certificate_store, &mut server_state, &mut session, &request).unwrap();
+    let _ = supported_message_as!(response, ActivateSessionResponse);
+
+    // Closing a session which is already closed is not another event
+    let request = CloseSessionRequest {
+        request_header: RequestHeader::dummy(),
+        delete_subscriptions: true,
+    };
+    let _ = ss.close_session(&mut server_state, &mut session, &request).unwrap();
+    let _ = ss.close_session(&mut server_state, &mut session, &request).unwrap();
+
+    assert_eq!(*events.lock().unwrap(), vec![
+        "created session".to_string(),
+        format!("activated {:?}", UserIdentity::Anonymous),
+        "closed session".to_string(),
+    ]);
+}
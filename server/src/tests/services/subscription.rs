@@ -2,12 +2,16 @@ use std::ops::Add;
 
 use chrono::Utc;
 
+use opcua_types::node_ids::ObjectId;
+
 use crate::{
     prelude::*,
     state::ServerState,
     services::subscription::SubscriptionService,
     services::monitored_item::MonitoredItemService,
     subscriptions::subscription::*,
+    subscriptions::PublishRequestEntry,
+    subscriptions::detached::{DetachedSubscription, user_identity_key},
 };
 
 use super::*;
@@ -20,11 +24,11 @@ fn create_subscription(server_state: &mut ServerState, session: &mut Session, ss
     response.subscription_id
 }
 
-fn create_monitored_item<T>(subscription_id: u32, node_to_monitor: T, session: &mut Session, mis: &MonitoredItemService) where T: Into<NodeId> {
+fn create_monitored_item<T>(subscription_id: u32, node_to_monitor: T, session: &mut Session, address_space: &AddressSpace, mis: &MonitoredItemService) where T: Into<NodeId> {
     // Create a monitored item
     let request = create_monitored_items_request(subscription_id, vec![node_to_monitor]);
     debug!("CreateMonitoredItemsRequest {:#?}", request);
-    let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(session, &request).unwrap(), CreateMonitoredItemsResponse);
+    let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(session, address_space, &request).unwrap(), CreateMonitoredItemsResponse);
     debug!("CreateMonitoredItemsResponse {:#?}", response);
     // let result = response.results.unwrap()[0].monitored_item_id;
 }
@@ -94,7 +98,7 @@ fn publish_response_subscription() {
         let now = Utc::now();
 
         // Create a monitored item
-        create_monitored_item(subscription_id, VariableId::Server_ServerStatus_StartTime, session, &mis);
+        create_monitored_item(subscription_id, VariableId::Server_ServerStatus_StartTime, session, address_space, &mis);
 
         // Put the subscription into normal state
         session.subscriptions.get_mut(subscription_id).unwrap().set_state(SubscriptionState::Normal);
@@ -168,7 +172,7 @@ fn publish_keep_alive() {
                 (1, "v1"),
             ]);
             debug!("CreateMonitoredItemsRequest {:#?}", request);
-            let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(session, &request).unwrap(), CreateMonitoredItemsResponse);
+            let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(session, address_space, &request).unwrap(), CreateMonitoredItemsResponse);
             debug!("CreateMonitoredItemsResponse {:#?}", response);
             // let result = response.results.unwrap()[0].monitored_item_id;
         }
@@ -220,6 +224,48 @@ fn publish_keep_alive() {
         // Expect the notification message to be a keep-alive
         assert_eq!(notification_message.sequence_number, 1);
         assert_eq!(notification_message.notification_data, None);
+
+        // Keep alives are not held for republishing
+        assert!(session.subscriptions.retransmission_queue().is_empty());
+    })
+}
+
+#[test]
+fn subscription_lifetime_expires() {
+    do_subscription_service_test(|server_state, session, address_space, ss, _| {
+        let subscription_id = create_subscription(server_state, session, &ss);
+
+        // The client stops sending publish requests so the subscription is late
+        {
+            let subscription = session.subscriptions.get_mut(subscription_id).unwrap();
+            subscription.set_state(SubscriptionState::Late);
+            subscription.set_current_lifetime_count(3);
+        }
+
+        // Each publishing interval counts down the lifetime until the subscription closes
+        let mut now = Utc::now();
+        for _ in 0..3 {
+            now = now.add(chrono::Duration::milliseconds(200));
+            let _ = session.tick_subscriptions(&now, &address_space, TickReason::TickTimerFired);
+        }
+        assert_eq!(session.subscriptions.subscriptions().get(&subscription_id).unwrap().state(), SubscriptionState::Closed);
+
+        // The next publish request is answered with a status change and the subscription is removed
+        let request = PublishRequest {
+            request_header: RequestHeader::dummy(),
+            subscription_acknowledgements: None,
+        };
+        let response = ss.async_publish(&now, session, address_space, 1001, &request).unwrap();
+        assert!(response.is_none());
+        assert!(!session.subscriptions.contains(subscription_id));
+        assert_eq!(session.subscriptions.publish_response_queue().len(), 1);
+
+        let response = session.subscriptions.publish_response_queue().pop_back().unwrap().response;
+        let response: PublishResponse = supported_message_as!(response, PublishResponse);
+        assert_eq!(response.subscription_id, subscription_id);
+        let status_changes = response.notification_message.status_change_notifications(&DecodingLimits::default());
+        assert_eq!(status_changes.len(), 1);
+        assert_eq!(status_changes[0].status, StatusCode::BadTimeout);
     })
 }
 
@@ -233,6 +279,53 @@ fn multiple_publish_response_subscription() {
     //})
 }
 
+#[test]
+fn publish_response_subscription_priority() {
+    do_subscription_service_test(|server_state, session, address_space, ss, mis| {
+        // A low priority subscription created before a high priority one
+        let subscription_ids = [0u8, 100u8].iter().map(|priority| {
+            let mut request = create_subscription_request(0, 0);
+            request.priority = *priority;
+            let response: CreateSubscriptionResponse = supported_message_as!(ss.create_subscription(server_state, session, &request).unwrap(), CreateSubscriptionResponse);
+            create_monitored_item(response.subscription_id, VariableId::Server_ServerStatus_StartTime, session, address_space, &mis);
+            session.subscriptions.get_mut(response.subscription_id).unwrap().set_state(SubscriptionState::Normal);
+            response.subscription_id
+        }).collect::<Vec<u32>>();
+
+        // Both subscriptions have notifications but there is only one publish request
+        let now = Utc::now();
+        let request = PublishRequest {
+            request_header: RequestHeader::dummy(),
+            subscription_acknowledgements: None,
+        };
+        let _ = ss.async_publish(&now, session, address_space, 1001, &request).unwrap();
+        let now = now.add(chrono::Duration::seconds(2));
+        let _ = session.tick_subscriptions(&now, &address_space, TickReason::TickTimerFired);
+
+        // The high priority subscription is serviced first
+        assert_eq!(session.subscriptions.publish_response_queue().len(), 1);
+        let response = session.subscriptions.publish_response_queue().pop_back().unwrap().response;
+        let response: PublishResponse = supported_message_as!(response, PublishResponse);
+        assert_eq!(response.subscription_id, subscription_ids[1]);
+        assert!(response.notification_message.notification_data.is_some());
+    })
+}
+
+#[test]
+fn set_publishing_mode() {
+    do_subscription_service_test(|server_state, session, _, ss, _| {
+        let subscription_id = create_subscription(server_state, session, &ss);
+        let request = SetPublishingModeRequest {
+            request_header: RequestHeader::dummy(),
+            publishing_enabled: false,
+            subscription_ids: Some(vec![subscription_id, subscription_id + 1000]),
+        };
+        let response: SetPublishingModeResponse = supported_message_as!(ss.set_publishing_mode(session, &request).unwrap(), SetPublishingModeResponse);
+        assert_eq!(response.results.unwrap(), vec![StatusCode::Good, StatusCode::BadSubscriptionIdInvalid]);
+        assert!(!session.subscriptions.subscriptions().get(&subscription_id).unwrap().publishing_enabled());
+    })
+}
+
 #[test]
 fn acknowledge_unknown_sequence_nr() {
     // TODO acknowledge an unknown seqid
@@ -271,7 +364,7 @@ fn republish() {
             retransmit_sequence_number: sequence_number,
         };
         let response: ServiceFault = supported_message_as!(ss.republish(session, &request).unwrap(), ServiceFault);
-        assert_eq!(response.response_header.service_result, StatusCode::BadNoSubscription);
+        assert_eq!(response.response_header.service_result, StatusCode::BadSubscriptionIdInvalid);
 
         // try for a sequence nr that does not exist
         let request = RepublishRequest {
@@ -282,4 +375,262 @@ fn republish() {
         let response: ServiceFault = supported_message_as!(ss.republish(session, &request).unwrap(), ServiceFault);
         assert_eq!(response.response_header.service_result, StatusCode::BadMessageNotAvailable);
     })
-}
\ No newline at end of file
+}
+
+#[test]
+fn retransmission_queue_purges_oldest() {
+    use crate::constants::MAX_RETRANSMISSION_QUEUE_SIZE;
+
+    do_subscription_service_test(|server_state, session, _, ss, _| {
+        let subscription_id_1 = create_subscription(server_state, session, &ss);
+        let subscription_id_2 = create_subscription(server_state, session, &ss);
+
+        // Fill the first subscription beyond the limit and put a couple in the second
+        let sequence_numbers_1 = (1..=(MAX_RETRANSMISSION_QUEUE_SIZE as u32 + 3)).collect::<Vec<u32>>();
+        sequence_numbers_1.iter().for_each(|sequence_number| {
+            let notification = NotificationMessage::data_change(*sequence_number, DateTime::now(), vec![]);
+            session.subscriptions.retransmission_queue().insert((subscription_id_1, *sequence_number), notification);
+        });
+        [1u32, 2u32].iter().for_each(|sequence_number| {
+            let notification = NotificationMessage::data_change(*sequence_number, DateTime::now(), vec![]);
+            session.subscriptions.retransmission_queue().insert((subscription_id_2, *sequence_number), notification);
+        });
+
+        session.subscriptions.remove_old_unacknowledged_notifications();
+
+        // The oldest 3 notifications of the first subscription are gone, the second is untouched
+        let retransmission_queue = session.subscriptions.retransmission_queue();
+        assert_eq!(retransmission_queue.len(), MAX_RETRANSMISSION_QUEUE_SIZE + 2);
+        assert!(!retransmission_queue.contains_key(&(subscription_id_1, 3)));
+        assert!(retransmission_queue.contains_key(&(subscription_id_1, 4)));
+        assert!(retransmission_queue.contains_key(&(subscription_id_1, MAX_RETRANSMISSION_QUEUE_SIZE as u32 + 3)));
+        assert!(retransmission_queue.contains_key(&(subscription_id_2, 1)));
+        assert!(retransmission_queue.contains_key(&(subscription_id_2, 2)));
+    })
+}
+
+/// Detaches the session's subscriptions the way a transport does when its connection closes
+fn detach_subscriptions(server_state: &mut ServerState, session: &mut Session) {
+    let user_identity = user_identity_key(&session.user_identity, &DecodingLimits::default());
+    session.subscriptions.take_all().into_iter().for_each(|(subscription, unacknowledged_notifications)| {
+        server_state.detached_subscriptions.insert(DetachedSubscription {
+            subscription,
+            unacknowledged_notifications,
+            user_identity: user_identity.clone(),
+        });
+    });
+}
+
+fn transfer_subscription_ids(server_state: &mut ServerState, session: &mut Session, ss: &SubscriptionService, subscription_ids: Vec<u32>) -> Vec<TransferResult> {
+    let request = TransferSubscriptionsRequest {
+        request_header: RequestHeader::dummy(),
+        subscription_ids: Some(subscription_ids),
+        send_initial_values: true,
+    };
+    let response: TransferSubscriptionsResponse = supported_message_as!(ss.transfer_subscriptions(server_state, session, &request).unwrap(), TransferSubscriptionsResponse);
+    response.results.unwrap()
+}
+
+#[test]
+fn transfer_subscriptions() {
+    do_subscription_service_test(|server_state, session, _, ss, _| {
+        // An anonymous session
+        session.user_identity = Some(ExtensionObject::null());
+        let subscription_id = create_subscription(server_state, session, &ss);
+
+        // A notification that was sent but not acknowledged
+        let notification = NotificationMessage::data_change(1, DateTime::now(), vec![]);
+        let sequence_number = notification.sequence_number;
+        session.subscriptions.retransmission_queue().insert((subscription_id, sequence_number), notification);
+
+        // Session ends and the subscription is detached
+        detach_subscriptions(server_state, session);
+        assert!(session.subscriptions.is_empty());
+        assert!(server_state.detached_subscriptions.contains(subscription_id));
+
+        // Nothing to do
+        let request = TransferSubscriptionsRequest {
+            request_header: RequestHeader::dummy(),
+            subscription_ids: None,
+            send_initial_values: false,
+        };
+        let response: ServiceFault = supported_message_as!(ss.transfer_subscriptions(server_state, session, &request).unwrap(), ServiceFault);
+        assert_eq!(response.response_header.service_result, StatusCode::BadNothingToDo);
+
+        // The same user reclaims the subscription and its unacknowledged notification
+        let results = transfer_subscription_ids(server_state, session, &ss, vec![subscription_id, subscription_id + 1]);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].status_code, StatusCode::Good);
+        assert_eq!(results[0].available_sequence_numbers, Some(vec![sequence_number]));
+        assert_eq!(results[1].status_code, StatusCode::BadSubscriptionIdInvalid);
+        assert!(session.subscriptions.contains(subscription_id));
+        assert!(!server_state.detached_subscriptions.contains(subscription_id));
+        assert!(session.subscriptions.find_notification_message(subscription_id, sequence_number).is_ok());
+
+        // Transferring a subscription the session already owns succeeds
+        let results = transfer_subscription_ids(server_state, session, &ss, vec![subscription_id]);
+        assert_eq!(results[0].status_code, StatusCode::Good);
+    })
+}
+
+#[test]
+fn transfer_subscriptions_different_user() {
+    do_subscription_service_test(|server_state, session, _, ss, _| {
+        let subscription_id = create_subscription(server_state, session, &ss);
+
+        // A session that was never activated has no user so its subscriptions cannot be transferred
+        detach_subscriptions(server_state, session);
+        let results = transfer_subscription_ids(server_state, session, &ss, vec![subscription_id]);
+        assert_eq!(results[0].status_code, StatusCode::BadUserAccessDenied);
+        assert!(server_state.detached_subscriptions.contains(subscription_id));
+
+        // A subscription belonging to a named user cannot be taken by an anonymous user
+        let user_token = UserNameIdentityToken {
+            policy_id: UAString::from("userpass_plaintext"),
+            user_name: UAString::from("sample"),
+            password: ByteString::from(b"sample1".to_vec()),
+            encryption_algorithm: UAString::null(),
+        };
+        session.user_identity = Some(ExtensionObject::from_encodable(ObjectId::UserNameIdentityToken_Encoding_DefaultBinary, &user_token));
+        let subscription_id = create_subscription(server_state, session, &ss);
+        detach_subscriptions(server_state, session);
+
+        session.user_identity = Some(ExtensionObject::null());
+        let results = transfer_subscription_ids(server_state, session, &ss, vec![subscription_id]);
+        assert_eq!(results[0].status_code, StatusCode::BadUserAccessDenied);
+        assert!(server_state.detached_subscriptions.contains(subscription_id));
+
+        // But it can be taken by the same user
+        session.user_identity = Some(ExtensionObject::from_encodable(ObjectId::UserNameIdentityToken_Encoding_DefaultBinary, &user_token));
+        let results = transfer_subscription_ids(server_state, session, &ss, vec![subscription_id]);
+        assert_eq!(results[0].status_code, StatusCode::Good);
+    })
+}
+
+#[test]
+fn detached_subscription_expires() {
+    do_subscription_service_test(|server_state, session, address_space, ss, _| {
+        session.user_identity = Some(ExtensionObject::null());
+        let subscription_id = create_subscription(server_state, session, &ss);
+        detach_subscriptions(server_state, session);
+
+        // Without publish requests, the detached subscription lives until its lifetime expires
+        let mut now = Utc::now();
+        for _ in 0..1000 {
+            if !server_state.detached_subscriptions.contains(subscription_id) {
+                break;
+            }
+            now = now.add(chrono::Duration::milliseconds(200));
+            server_state.detached_subscriptions.tick(&now, address_space);
+        }
+        assert!(server_state.detached_subscriptions.is_empty());
+    })
+}
+
+#[test]
+fn too_many_subscriptions() {
+    let st = ServiceTest::new_with_server(ServerBuilder::new_anonymous("foo").max_subscriptions(1).server().unwrap());
+    let (mut server_state, mut session) = st.get_server_state_and_session();
+    let ss = SubscriptionService::new();
+
+    let _ = create_subscription(&mut server_state, &mut session, &ss);
+
+    // The second subscription exceeds the limit
+    let request = create_subscription_request(0, 0);
+    let response: ServiceFault = supported_message_as!(ss.create_subscription(&mut server_state, &mut session, &request).unwrap(), ServiceFault);
+    assert_eq!(response.response_header.service_result, StatusCode::BadTooManySubscriptions);
+}
+
+#[test]
+fn too_many_monitored_items() {
+    let st = ServiceTest::new_with_server(ServerBuilder::new_anonymous("foo").max_monitored_items_per_subscription(2).server().unwrap());
+    let (mut server_state, mut session) = st.get_server_state_and_session();
+    let address_space = trace_read_lock_unwrap!(st.address_space);
+    let ss = SubscriptionService::new();
+    let mis = MonitoredItemService::new();
+
+    let subscription_id = create_subscription(&mut server_state, &mut session, &ss);
+
+    // Only the first two items fit into the subscription
+    let request = create_monitored_items_request(subscription_id, vec![
+        VariableId::Server_ServerStatus_StartTime,
+        VariableId::Server_ServerStatus_CurrentTime,
+        VariableId::Server_ServerStatus_State,
+    ]);
+    let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(&mut session, &address_space, &request).unwrap(), CreateMonitoredItemsResponse);
+    let results = response.results.unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].status_code, StatusCode::Good);
+    assert_eq!(results[1].status_code, StatusCode::Good);
+    assert_eq!(results[2].status_code, StatusCode::BadTooManyMonitoredItems);
+
+    // Nor do any more items in a later request
+    let request = create_monitored_items_request(subscription_id, vec![VariableId::Server_ServerStatus_State]);
+    let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(&mut session, &address_space, &request).unwrap(), CreateMonitoredItemsResponse);
+    assert_eq!(response.results.unwrap()[0].status_code, StatusCode::BadTooManyMonitoredItems);
+}
+
+#[test]
+fn too_many_publish_requests() {
+    let st = ServiceTest::new_with_server(ServerBuilder::new_anonymous("foo").max_publish_requests(1).server().unwrap());
+    let (mut server_state, mut session) = st.get_server_state_and_session();
+    let address_space = trace_read_lock_unwrap!(st.address_space);
+    let ss = SubscriptionService::new();
+
+    // A subscription would normally allow two publish requests to be queued
+    let _ = create_subscription(&mut server_state, &mut session, &ss);
+    assert_eq!(session.subscriptions.max_publish_requests(), 1);
+
+    let now = Utc::now();
+    let request = PublishRequest {
+        request_header: RequestHeader::dummy(),
+        subscription_acknowledgements: None,
+    };
+    let response = ss.async_publish(&now, &mut session, &address_space, 1001, &request).unwrap();
+    assert!(response.is_none());
+
+    let response = ss.async_publish(&now, &mut session, &address_space, 1002, &request).unwrap().unwrap();
+    let response: ServiceFault = supported_message_as!(response, ServiceFault);
+    assert_eq!(response.response_header.service_result, StatusCode::BadTooManyPublishRequests);
+}
+
+#[test]
+fn shutdown_closes_subscriptions() {
+    do_subscription_service_test(|server_state, session, _, ss, _| {
+        let subscription_id_1 = create_subscription(server_state, session, &ss);
+        let _ = create_subscription(server_state, session, &ss);
+
+        // Only one subscription has a publish request to answer
+        session.subscriptions.publish_request_queue().push_front(PublishRequestEntry {
+            request_id: 1001,
+            request: PublishRequest {
+                request_header: RequestHeader::dummy(),
+                subscription_acknowledgements: None,
+            },
+            results: None,
+        });
+
+        // The server state reflects the shutdown before it is due
+        let now = Utc::now();
+        server_state.begin_shutdown(std::time::Duration::from_secs(60), "Maintenance".into());
+        assert!(server_state.is_shutting_down());
+        assert_eq!(server_state.state(), opcua_types::service_types::ServerState::Shutdown);
+        assert!(server_state.seconds_till_shutdown() > 55);
+        assert_eq!(server_state.shutdown_reason(), LocalizedText::from("Maintenance"));
+        assert!(!server_state.is_shutdown_due(&now));
+        assert!(server_state.is_shutdown_due(&now.add(chrono::Duration::seconds(61))));
+
+        // Closing the subscriptions answers the publish request with a status change
+        session.shutdown_subscriptions(&now);
+        assert!(session.subscriptions.is_empty());
+        assert!(session.subscriptions.publish_request_queue().is_empty());
+        assert_eq!(session.subscriptions.publish_response_queue().len(), 1);
+
+        let response = session.subscriptions.publish_response_queue().pop_back().unwrap().response;
+        let response: PublishResponse = supported_message_as!(response, PublishResponse);
+        assert_eq!(response.subscription_id, subscription_id_1);
+        let status_changes = response.notification_message.status_change_notifications(&DecodingLimits::default());
+        assert_eq!(status_changes.len(), 1);
+        assert_eq!(status_changes[0].status, StatusCode::BadShutdown);
+    })
+}
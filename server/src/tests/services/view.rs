@@ -1,7 +1,7 @@
 use std::sync::Weak;
 
 use opcua_console_logging;
-use crate::services::view::ViewService;
+use crate::services::{view::ViewService, attribute::AttributeService};
 
 use super::*;
 
@@ -61,9 +61,9 @@ fn do_view_service_test<F>(f: F)
     f(&mut server_state, &mut session, st.session.clone(), &mut address_space, &ViewService::new());
 }
 
-fn do_browse(vs: &ViewService, session: &mut Session, address_space: &AddressSpace, nodes: &[NodeId], max_references_per_node: usize) -> BrowseResponse {
+fn do_browse(vs: &ViewService, server_state: &ServerState, session: &mut Session, address_space: &AddressSpace, nodes: &[NodeId], max_references_per_node: usize) -> BrowseResponse {
     let request = make_browse_request(nodes, max_references_per_node, BrowseDirection::Forward, ReferenceTypeId::Organizes);
-    let result = vs.browse(session, address_space, &request);
+    let result = vs.browse(server_state, session, address_space, &request);
     assert!(result.is_ok());
     supported_message_as!(result.unwrap(), BrowseResponse)
 }
@@ -77,11 +77,11 @@ fn do_browse_next(vs: &ViewService, session: &mut Session, address_space: &Addre
 
 #[test]
 fn browse() {
-    do_view_service_test(|_server_state, session, _, address_space, vs| {
+    do_view_service_test(|server_state, session, _, address_space, vs| {
         add_sample_vars_to_address_space(address_space);
 
         let nodes: Vec<NodeId> = vec![ObjectId::RootFolder.into()];
-        let response = do_browse(&vs, session, &address_space, &nodes, 1000);
+        let response = do_browse(&vs, server_state, session, &address_space, &nodes, 1000);
         assert!(response.results.is_some());
 
         let results = response.results.unwrap();
@@ -107,13 +107,13 @@ fn browse() {
 
 #[test]
 fn browse_next() {
-    do_view_service_test(|_server_state, session, _, address_space, vs| {
+    do_view_service_test(|server_state, session, _, address_space, vs| {
         let parent_node_id = add_many_vars_to_address_space(address_space, 100).0;
         let nodes = vec![parent_node_id.clone()];
 
         // Browse with requested_max_references_per_node = 101, expect 100 results, no continuation point
         {
-            let response = do_browse(&vs, session, &address_space, &nodes, 101);
+            let response = do_browse(&vs, server_state, session, &address_space, &nodes, 101);
             assert!(response.results.is_some());
             let r1 = &response.results.unwrap()[0];
             let references = r1.references.as_ref().unwrap();
@@ -123,7 +123,7 @@ fn browse_next() {
 
         // Browse with requested_max_references_per_node = 100, expect 100 results, no continuation point
         {
-            let response = do_browse(&vs, session, &address_space, &nodes, 100);
+            let response = do_browse(&vs, server_state, session, &address_space, &nodes, 100);
             let r1 = &response.results.unwrap()[0];
             let references = r1.references.as_ref().unwrap();
             assert!(r1.continuation_point.is_null());
@@ -134,7 +134,7 @@ fn browse_next() {
         // Browse next with continuation point, expect 1 result leaving off from last continuation point
         let continuation_point = {
             // Get first 99
-            let response = do_browse(&vs, session, &address_space, &nodes, 99);
+            let response = do_browse(&vs, server_state, session, &address_space, &nodes, 99);
             let r1 = &response.results.unwrap()[0];
             let references = r1.references.as_ref().unwrap();
             assert!(!r1.continuation_point.is_null());
@@ -147,25 +147,36 @@ fn browse_next() {
             let references = r2.references.as_ref().unwrap();
             verify_references_to_many_vars(references, 1, 99);
 
-            // Browse next again with same continuation point, expect same 1 result
+            // Browse next again with same continuation point, expect BadContinuationPointInvalid
+            // because it was used up
             let response = do_browse_next(&vs, session, &address_space, &r1.continuation_point, false);
             let r2 = &response.results.unwrap()[0];
-            assert!(r2.continuation_point.is_null());
-            let references = r2.references.as_ref().unwrap();
-            verify_references_to_many_vars(references, 1, 99);
+            assert_eq!(r2.status_code, StatusCode::BadContinuationPointInvalid);
 
+            // Browse again for a fresh continuation point
+            let response = do_browse(&vs, server_state, session, &address_space, &nodes, 99);
+            let r1 = &response.results.unwrap()[0];
+            assert!(!r1.continuation_point.is_null());
             r1.continuation_point.clone()
         };
 
-        // Browse next and release the previous continuation points, expect Null result
+        // Browse next and release the previous continuation points, expect a Good result with no references
         {
             let response = do_browse_next(&vs, session, &address_space, &continuation_point, true);
-            assert!(response.results.is_none());
+            let r1 = &response.results.unwrap()[0];
+            assert_eq!(r1.status_code, StatusCode::Good);
+            assert!(r1.continuation_point.is_null());
+            assert!(r1.references.is_none());
 
             // Browse next again with same continuation point, expect BadContinuationPointInvalid
             let response = do_browse_next(&vs, session, &address_space, &continuation_point, false);
             let r1 = &response.results.unwrap()[0];
             assert_eq!(r1.status_code, StatusCode::BadContinuationPointInvalid);
+
+            // Releasing it again also fails
+            let response = do_browse_next(&vs, session, &address_space, &continuation_point, true);
+            let r1 = &response.results.unwrap()[0];
+            assert_eq!(r1.status_code, StatusCode::BadContinuationPointInvalid);
         }
 
         // Browse with 35 expect continuation point cp1
@@ -173,7 +184,7 @@ fn browse_next() {
         // Browse next with cp2 expect 30 results
         {
             // Get first 35
-            let response = do_browse(&vs, session, &address_space, &nodes, 35);
+            let response = do_browse(&vs, server_state, session, &address_space, &nodes, 35);
             let r1 = &response.results.unwrap()[0];
             let references = r1.references.as_ref().unwrap();
             assert!(!r1.continuation_point.is_null());
@@ -200,6 +211,13 @@ fn browse_next() {
             use std::thread;
             use std::time::Duration;
 
+            let continuation_point = {
+                let response = do_browse(&vs, server_state, session, &address_space, &nodes, 99);
+                let r1 = &response.results.unwrap()[0];
+                assert!(!r1.continuation_point.is_null());
+                r1.continuation_point.clone()
+            };
+
             // Sleep a bit, modify the address space so the old continuation point is out of date
             thread::sleep(Duration::from_millis(50));
             {
@@ -217,6 +235,52 @@ fn browse_next() {
     });
 }
 
+#[test]
+fn browse_continuation_point_limit() {
+    do_view_service_test(|server_state, session, _, address_space, vs| {
+        let parent_node_id = add_many_vars_to_address_space(address_space, 10).0;
+        let nodes = vec![parent_node_id.clone()];
+
+        // Use up every continuation point of the session
+        let max_browse_continuation_points = server_state.max_browse_continuation_points;
+        let continuation_points: Vec<ByteString> = (0..max_browse_continuation_points).map(|_| {
+            let response = do_browse(&vs, server_state, session, &address_space, &nodes, 1);
+            let r1 = &response.results.unwrap()[0];
+            assert_eq!(r1.status_code, StatusCode::Good);
+            assert!(!r1.continuation_point.is_null());
+            r1.continuation_point.clone()
+        }).collect();
+        assert_eq!(session.browse_continuation_points_len(), max_browse_continuation_points);
+
+        // The next browse that needs a continuation point fails
+        let response = do_browse(&vs, server_state, session, &address_space, &nodes, 1);
+        let r1 = &response.results.unwrap()[0];
+        assert_eq!(r1.status_code, StatusCode::BadNoContinuationPoints);
+        assert!(r1.continuation_point.is_null());
+
+        // But one which doesn't need one is fine
+        let response = do_browse(&vs, server_state, session, &address_space, &nodes, 0);
+        let r1 = &response.results.unwrap()[0];
+        assert_eq!(r1.status_code, StatusCode::Good);
+        verify_references_to_many_vars(r1.references.as_ref().unwrap(), 10, 0);
+
+        // Browsing next from a continuation point replaces it with another
+        let response = do_browse_next(&vs, session, &address_space, &continuation_points[0], false);
+        let r1 = &response.results.unwrap()[0];
+        assert_eq!(r1.status_code, StatusCode::Good);
+        assert!(!r1.continuation_point.is_null());
+        assert_eq!(session.browse_continuation_points_len(), max_browse_continuation_points);
+
+        // Releasing a continuation point frees it up for another browse
+        let _ = do_browse_next(&vs, session, &address_space, &continuation_points[1], true);
+        assert_eq!(session.browse_continuation_points_len(), max_browse_continuation_points - 1);
+        let response = do_browse(&vs, server_state, session, &address_space, &nodes, 1);
+        let r1 = &response.results.unwrap()[0];
+        assert_eq!(r1.status_code, StatusCode::Good);
+        assert!(!r1.continuation_point.is_null());
+    });
+}
+
 #[test]
 fn translate_browse_paths_to_node_ids() {
     do_view_service_test(|server_state, _session, _, address_space, vs| {
@@ -408,20 +472,20 @@ impl UnregisterNodes for UnregisterNodesImpl {
 
 #[test]
 fn register_nodes() {
-    do_view_service_test(|server_state, _, session, _address_space, vs| {
+    do_view_service_test(|server_state, session, session_ref, _address_space, vs| {
         // Empty request
-        let result = vs.register_nodes(server_state, session.clone(), &RegisterNodesRequest {
+        let result = vs.register_nodes(server_state, session, session_ref.clone(), &RegisterNodesRequest {
             request_header: make_request_header(),
             nodes_to_register: None,
         });
         let response: ServiceFault = supported_message_as!(result.unwrap(), ServiceFault);
         assert_eq!(response.response_header.service_result, StatusCode::BadNothingToDo);
 
-        // Invalid request because impl has no registered handler
-        let result = vs.register_nodes(server_state, session.clone(), &RegisterNodesRequest {
+        // Invalid request because of a null node id
+        let result = vs.register_nodes(server_state, session, session_ref.clone(), &RegisterNodesRequest {
             request_header: make_request_header(),
             nodes_to_register: Some(vec![
-                ObjectId::ObjectsFolder.into()
+                NodeId::null()
             ]),
         });
         let response: ServiceFault = supported_message_as!(result.unwrap(), ServiceFault);
@@ -434,7 +498,7 @@ fn register_nodes() {
         );
 
         // Make a good call to register
-        let result = vs.register_nodes(server_state, session.clone(), &RegisterNodesRequest {
+        let result = vs.register_nodes(server_state, session, session_ref.clone(), &RegisterNodesRequest {
             request_header: make_request_header(),
             nodes_to_register: Some(vec![
                 NodeId::new(1, 99),
@@ -449,8 +513,12 @@ fn register_nodes() {
         assert_eq!(registered_node_ids[1], NodeId::new(1, 200));
         assert_eq!(registered_node_ids[2], NodeId::new(1, 101));
 
+        // The session should map the alias back to the node
+        assert_eq!(session.resolve_node_id(&NodeId::new(1, 200)), &NodeId::new(1, 100));
+        assert_eq!(session.resolve_node_id(&NodeId::new(1, 99)), &NodeId::new(1, 99));
+
         // Make a bad call to register nodes
-        let result = vs.register_nodes(server_state, session.clone(), &RegisterNodesRequest {
+        let result = vs.register_nodes(server_state, session, session_ref.clone(), &RegisterNodesRequest {
             request_header: make_request_header(),
             nodes_to_register: Some(vec![
                 ObjectId::ObjectsFolder.into()
@@ -461,11 +529,95 @@ fn register_nodes() {
     });
 }
 
+#[test]
+fn register_nodes_without_callback() {
+    do_view_service_test(|server_state, session, session_ref, address_space, vs| {
+        // Without a callback, nodes are registered under their own ids
+        let _ = add_many_vars_to_address_space(address_space, 2);
+        let node_id = var_node_id(1);
+        let result = vs.register_nodes(server_state, session, session_ref.clone(), &RegisterNodesRequest {
+            request_header: make_request_header(),
+            nodes_to_register: Some(vec![
+                node_id.clone(),
+                ObjectId::ObjectsFolder.into(),
+            ]),
+        });
+        let response: RegisterNodesResponse = supported_message_as!(result.unwrap(), RegisterNodesResponse);
+        let registered_node_ids = response.registered_node_ids.unwrap();
+        assert_eq!(registered_node_ids.len(), 2);
+        assert_eq!(registered_node_ids[0], node_id);
+        assert_eq!(registered_node_ids[1], ObjectId::ObjectsFolder.into());
+
+        // The registered node should be readable through its registered id
+        let ats = AttributeService::new();
+        let request = ReadRequest {
+            request_header: make_request_header(),
+            max_age: 0f64,
+            timestamps_to_return: TimestampsToReturn::Both,
+            nodes_to_read: Some(vec![ReadValueId {
+                node_id: registered_node_ids[0].clone(),
+                attribute_id: AttributeId::Value as u32,
+                index_range: UAString::null(),
+                data_encoding: QualifiedName::null(),
+            }]),
+        };
+        let response: ReadResponse = supported_message_as!(ats.read(server_state, session, address_space, &request).unwrap(), ReadResponse);
+        let results = response.results.unwrap();
+        assert_eq!(results[0].value.as_ref().unwrap(), &Variant::Int32(1));
+    });
+}
+
+#[test]
+fn register_nodes_aliases_resolve() {
+    do_view_service_test(|server_state, session, session_ref, address_space, vs| {
+        server_state.set_register_nodes_callbacks(
+            Box::new(RegisterNodesImpl { session: Weak::new() }),
+            Box::new(UnregisterNodesImpl {}),
+        );
+
+        // Register a node with an alias and write to the node through the alias
+        let alias_node = NodeId::new(1, 200);
+        let mut variable = Variable::new(&NodeId::new(1, 100), "v100", "", 0i32);
+        variable.set_writable(true);
+        let _ = address_space.add_variables(vec![variable], &AddressSpace::objects_folder_id());
+        let result = vs.register_nodes(server_state, session, session_ref.clone(), &RegisterNodesRequest {
+            request_header: make_request_header(),
+            nodes_to_register: Some(vec![NodeId::new(1, 100)]),
+        });
+        let response: RegisterNodesResponse = supported_message_as!(result.unwrap(), RegisterNodesResponse);
+        assert_eq!(response.registered_node_ids.unwrap()[0], alias_node);
+
+        let ats = AttributeService::new();
+        let request = WriteRequest {
+            request_header: make_request_header(),
+            nodes_to_write: Some(vec![WriteValue {
+                node_id: alias_node.clone(),
+                attribute_id: AttributeId::Value as u32,
+                index_range: UAString::null(),
+                value: DataValue::new(123i32),
+            }]),
+        };
+        let response: WriteResponse = supported_message_as!(ats.write(server_state, session, address_space, &request).unwrap(), WriteResponse);
+        assert_eq!(response.results.unwrap()[0], StatusCode::Good);
+        let node = address_space.find_variable(NodeId::new(1, 100)).unwrap();
+        assert_eq!(node.value().value.unwrap(), Variant::Int32(123));
+
+        // Once unregistered, the alias means nothing
+        let _ = vs.unregister_nodes(server_state, session, session_ref.clone(), &UnregisterNodesRequest {
+            request_header: make_request_header(),
+            nodes_to_unregister: Some(vec![alias_node.clone()]),
+        });
+        assert_eq!(session.resolve_node_id(&alias_node), &alias_node);
+        let response: WriteResponse = supported_message_as!(ats.write(server_state, session, address_space, &request).unwrap(), WriteResponse);
+        assert_eq!(response.results.unwrap()[0], StatusCode::BadNodeIdUnknown);
+    });
+}
+
 #[test]
 fn unregister_nodes() {
-    do_view_service_test(|server_state, _, session, _address_space, vs| {
+    do_view_service_test(|server_state, session, session_ref, _address_space, vs| {
         // Empty request
-        let result = vs.unregister_nodes(server_state, session.clone(), &UnregisterNodesRequest {
+        let result = vs.unregister_nodes(server_state, session, session_ref.clone(), &UnregisterNodesRequest {
             request_header: make_request_header(),
             nodes_to_unregister: None,
         });
@@ -479,7 +631,7 @@ fn unregister_nodes() {
         );
 
         // Not much to validate except that the function returns good
-        let result = vs.unregister_nodes(server_state, session.clone(), &UnregisterNodesRequest {
+        let result = vs.unregister_nodes(server_state, session, session_ref.clone(), &UnregisterNodesRequest {
             request_header: make_request_header(),
             nodes_to_unregister: Some(vec![
                 NodeId::new(1, 99),
@@ -491,4 +643,4 @@ fn unregister_nodes() {
         let response: UnregisterNodesResponse = supported_message_as!(result.unwrap(), UnregisterNodesResponse);
         assert_eq!(response.response_header.service_result, StatusCode::Good);
     });
-}
\ No newline at end of file
+}
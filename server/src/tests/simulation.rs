@@ -0,0 +1,112 @@
+use std::str::FromStr;
+
+use crate::{
+    config::{SimulatedVariableConfig, SimulationWaveform},
+    simulation::{Simulation, add_simulated_variables},
+};
+
+use super::*;
+
+fn make_simulated_variable(name: &str, waveform: SimulationWaveform) -> SimulatedVariableConfig {
+    SimulatedVariableConfig {
+        node_id: format!("ns=1;s=Simulation.{}", name),
+        name: name.to_string(),
+        waveform,
+        period: 1000,
+        amplitude: 10.0,
+        offset: 5.0,
+        update_interval: 100,
+    }
+}
+
+fn assert_near(value: f64, expected: f64) {
+    assert!((value - expected).abs() < 1e-9, "value {} is not {}", value, expected);
+}
+
+#[test]
+fn simulated_waveforms() {
+    let mut sine = Simulation::new(&make_simulated_variable("Sine", SimulationWaveform::Sine), 1);
+    assert_near(sine.value(0.0), 5.0);
+    assert_near(sine.value(250.0), 15.0);
+    assert_near(sine.value(750.0), -5.0);
+    assert_near(sine.value(1250.0), 15.0);
+
+    let mut ramp = Simulation::new(&make_simulated_variable("Ramp", SimulationWaveform::Ramp), 1);
+    assert_near(ramp.value(0.0), 5.0);
+    assert_near(ramp.value(500.0), 10.0);
+    assert_near(ramp.value(1100.0), 6.0);
+
+    let mut square = Simulation::new(&make_simulated_variable("Square", SimulationWaveform::Square), 1);
+    assert_near(square.value(100.0), 15.0);
+    assert_near(square.value(600.0), -5.0);
+    assert_near(square.value(1400.0), 15.0);
+}
+
+#[test]
+fn simulated_random_walk() {
+    let mut random_walk = Simulation::new(&make_simulated_variable("RandomWalk", SimulationWaveform::RandomWalk), 12345);
+    let mut last_value = 5.0;
+    let mut moved = false;
+    for i in 0..1000 {
+        let value = random_walk.value(i as f64 * 100.0);
+        // Each step is at most amplitude * update interval / period, and the walk stays within
+        // the amplitude of the offset
+        assert!((value - last_value).abs() <= 1.0 + 1e-9);
+        assert!(value >= -5.0 && value <= 15.0);
+        if value != last_value {
+            moved = true;
+        }
+        last_value = value;
+    }
+    assert!(moved);
+}
+
+#[test]
+fn simulated_variable_config_invalid() {
+    let simulated_variable = make_simulated_variable("Sine", SimulationWaveform::Sine);
+    let mut config = ServerBuilder::new_anonymous("foo").simulated_variable(simulated_variable.clone()).config();
+    assert!(config.is_valid());
+
+    config.simulated_variables[0].node_id = "x=1".to_string();
+    assert_eq!(config.is_valid(), false);
+
+    config.simulated_variables[0] = simulated_variable.clone();
+    config.simulated_variables[0].period = 0;
+    assert_eq!(config.is_valid(), false);
+
+    config.simulated_variables[0] = simulated_variable.clone();
+    config.simulated_variables[0].update_interval = 0;
+    assert_eq!(config.is_valid(), false);
+
+    // Two simulations of the same variable
+    config.simulated_variables[0] = simulated_variable.clone();
+    config.simulated_variables.push(simulated_variable);
+    assert_eq!(config.is_valid(), false);
+}
+
+#[test]
+fn simulated_variables_added() {
+    let mut address_space = AddressSpace::new();
+    let existing_id = NodeId::new(1, "Existing");
+    let _ = address_space.add_variables(vec![Variable::new(&existing_id, "Existing", "Existing", 1i32)], &AddressSpace::objects_folder_id());
+
+    let mut existing = make_simulated_variable("Existing", SimulationWaveform::Sine);
+    existing.node_id = "ns=1;s=Existing".to_string();
+    let simulated_variables = vec![
+        make_simulated_variable("Sine", SimulationWaveform::Sine),
+        existing,
+    ];
+    add_simulated_variables(&mut address_space, 1, &simulated_variables);
+
+    // The missing variable is created in the folder with the offset as its value
+    let folder_id = NodeId::new(1, "Simulation");
+    assert!(address_space.find_node(&folder_id).is_some());
+    let node_id = NodeId::from_str("ns=1;s=Simulation.Sine").unwrap();
+    let variable = address_space.find_variable_by_ref(&node_id).unwrap();
+    assert_eq!(variable.value().value, Some(Variant::Double(5.0)));
+    assert!(address_space.has_reference(&folder_id, &node_id, ReferenceTypeId::Organizes));
+
+    // The existing variable is left alone
+    let variable = address_space.find_variable_by_ref(&existing_id).unwrap();
+    assert_eq!(variable.value().value, Some(Variant::Int32(1)));
+}
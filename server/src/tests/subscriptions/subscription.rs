@@ -333,6 +333,8 @@ fn update_state_14() {
     assert_eq!(update_state_result.handled_state, HandledState::KeepAlive14);
     assert_eq!(update_state_result.update_state_action, UpdateStateAction::ReturnNotifications);
     assert_eq!(s.state(), SubscriptionState::Normal);
+    assert_eq!(s.lifetime_counter(), DEFAULT_LIFETIME_COUNT - 1);
+    assert_eq!(s.message_sent(), true);
 }
 
 #[test]
@@ -352,6 +354,31 @@ fn update_state_15() {
 
     let update_state_result = s.update_state(tick_reason, p);
 
+    assert_eq!(update_state_result.handled_state, HandledState::KeepAlive15);
+    assert_eq!(update_state_result.update_state_action, UpdateStateAction::ReturnKeepAlive);
+    assert_eq!(s.state(), SubscriptionState::KeepAlive);
+    assert_eq!(s.keep_alive_counter(), s.max_keep_alive_count());
+    assert_eq!(s.lifetime_counter(), DEFAULT_LIFETIME_COUNT - 1);
+}
+
+#[test]
+fn update_state_15_publishing_enabled() {
+    // A keep alive is also due when publishing is enabled but there is nothing to publish
+    let mut s = make_subscription(SubscriptionState::KeepAlive);
+
+    let tick_reason = TickReason::TickTimerFired;
+    let p = SubscriptionStateParams {
+        notifications_available: false,
+        more_notifications: false,
+        publishing_req_queued: true,
+        publishing_timer_expired: true,
+    };
+
+    s.set_keep_alive_counter(1);
+    s.set_publishing_enabled(true);
+
+    let update_state_result = s.update_state(tick_reason, p);
+
     assert_eq!(update_state_result.handled_state, HandledState::KeepAlive15);
     assert_eq!(update_state_result.update_state_action, UpdateStateAction::ReturnKeepAlive);
     assert_eq!(s.state(), SubscriptionState::KeepAlive);
@@ -435,3 +462,60 @@ fn update_state_27() {
     assert_eq!(s.lifetime_counter(), 1);
     assert_eq!(s.message_sent(), false);
 }
+
+#[test]
+fn keep_alive_counter_counts_down() {
+    // Each publishing interval without notifications counts down the keep alive counter until a
+    // keep alive is sent, which restarts the count and the lifetime
+    let mut s = make_subscription(SubscriptionState::KeepAlive);
+    s.set_publishing_enabled(true);
+    s.set_keep_alive_counter(3);
+
+    let p = SubscriptionStateParams {
+        notifications_available: false,
+        more_notifications: false,
+        publishing_req_queued: true,
+        publishing_timer_expired: true,
+    };
+
+    let expected = [
+        (HandledState::KeepAlive16, UpdateStateAction::None, 2),
+        (HandledState::KeepAlive16, UpdateStateAction::None, 1),
+        (HandledState::KeepAlive15, UpdateStateAction::ReturnKeepAlive, DEFAULT_KEEPALIVE_COUNT),
+    ];
+    for (handled_state, update_state_action, keep_alive_counter) in expected.iter() {
+        let update_state_result = s.update_state(TickReason::TickTimerFired, p);
+        assert_eq!(update_state_result.handled_state, *handled_state);
+        assert_eq!(update_state_result.update_state_action, *update_state_action);
+        assert_eq!(s.keep_alive_counter(), *keep_alive_counter);
+    }
+    assert_eq!(s.state(), SubscriptionState::KeepAlive);
+    assert_eq!(s.lifetime_counter(), DEFAULT_LIFETIME_COUNT - 1);
+}
+
+#[test]
+fn lifetime_counter_expires() {
+    // Each publishing interval without a publish request counts down the lifetime counter until
+    // the subscription expires
+    let mut s = make_subscription(SubscriptionState::Late);
+    s.set_current_lifetime_count(3);
+
+    let p = SubscriptionStateParams {
+        notifications_available: true,
+        more_notifications: false,
+        publishing_req_queued: false,
+        publishing_timer_expired: true,
+    };
+
+    let expected = [
+        (HandledState::Late12, UpdateStateAction::None, SubscriptionState::Late),
+        (HandledState::Late12, UpdateStateAction::None, SubscriptionState::Late),
+        (HandledState::Closed27, UpdateStateAction::SubscriptionExpired, SubscriptionState::Closed),
+    ];
+    for (handled_state, update_state_action, state) in expected.iter() {
+        let update_state_result = s.update_state(TickReason::TickTimerFired, p);
+        assert_eq!(update_state_result.handled_state, *handled_state);
+        assert_eq!(update_state_result.update_state_action, *update_state_action);
+        assert_eq!(s.state(), *state);
+    }
+}
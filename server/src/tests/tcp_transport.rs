@@ -0,0 +1,57 @@
+use std::{
+    net::{TcpListener, TcpStream},
+    sync::{Arc, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use futures::{Future, future};
+use tokio::{self, reactor::Handle, runtime::Runtime};
+
+use crate::comms::{
+    tcp_transport::TcpTransport,
+    transport::{Transport, TransportState},
+};
+
+use super::*;
+
+/// Waits for the transport to finish, failing the test if it takes too long
+fn wait_for_finished(transport: &Arc<RwLock<TcpTransport>>) -> TransportState {
+    let started = Instant::now();
+    loop {
+        let state = trace_read_lock_unwrap!(transport).state();
+        if let TransportState::Finished(_) = state {
+            return state;
+        }
+        assert!(started.elapsed() < Duration::from_secs(5), "Transport did not finish, state = {:?}", state);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn transport_finishes_when_client_closes_socket() {
+    let server = ServerBuilder::new_anonymous("foo").server().unwrap();
+    let transport = Arc::new(RwLock::new(server.new_transport()));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (socket, _) = listener.accept().unwrap();
+
+    let runtime = Runtime::new().unwrap();
+    {
+        let transport = transport.clone();
+        runtime.executor().spawn(future::lazy(move || {
+            let socket = tokio::net::TcpStream::from_std(socket, &Handle::default()).unwrap();
+            TcpTransport::run(transport, socket);
+            Ok(())
+        }));
+    }
+
+    // The client goes away without sending anything
+    drop(client);
+
+    // The transport and its session are finished, and every task of the connection ends
+    assert_eq!(wait_for_finished(&transport), TransportState::Finished(StatusCode::BadConnectionClosed));
+    assert!(trace_read_lock_unwrap!(transport).is_session_terminated());
+    runtime.shutdown_on_idle().wait().unwrap();
+}
@@ -0,0 +1,121 @@
+use std::str::FromStr;
+
+use opcua_types::type_registry::TYPE_REGISTRY;
+use opcua_types_derive::UaStructure;
+
+use crate::prelude::*;
+
+use super::*;
+
+#[derive(Debug, Clone, PartialEq, UaStructure)]
+#[ua(data_type_id = "ns=2;s=PumpStatus", encoding_id = "ns=2;s=PumpStatus_Encoding_DefaultBinary")]
+struct PumpStatus {
+    name: UAString,
+    speed: f64,
+    running: bool,
+    alarms: Option<Vec<StatusCode>>,
+    location: LocalizedText,
+}
+
+#[derive(Debug, Clone, PartialEq, UaStructure)]
+#[ua(name = "Valve", data_type_id = "ns=2;i=3001", encoding_id = "ns=2;i=3002")]
+struct ValveStatus {
+    position: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, UaStructure)]
+#[ua(data_type_id = "ns=2;g=72962b91-fa75-4ae6-8d28-b404dc7daf63", encoding_id = "ns=2;b=AQID")]
+struct Heartbeat {}
+
+fn pump_status() -> PumpStatus {
+    PumpStatus {
+        name: UAString::from("Pump 1"),
+        speed: 1450.5,
+        running: true,
+        alarms: Some(vec![StatusCode::BadOutOfRange, StatusCode::Good]),
+        location: LocalizedText::new("en", "Plant room"),
+    }
+}
+
+#[test]
+fn ua_structure_ids() {
+    assert_eq!(PumpStatus::type_name(), "PumpStatus");
+    assert_eq!(PumpStatus::data_type_id(), NodeId::new(2, "PumpStatus"));
+    assert_eq!(PumpStatus::binary_encoding_id(), NodeId::new(2, "PumpStatus_Encoding_DefaultBinary"));
+    assert_eq!(ValveStatus::type_name(), "Valve");
+    assert_eq!(ValveStatus::data_type_id(), NodeId::new(2, 3001));
+    assert_eq!(ValveStatus::binary_encoding_id(), NodeId::new(2, 3002));
+    assert_eq!(Heartbeat::data_type_id(), NodeId::new(2, Guid::from_str("72962b91-fa75-4ae6-8d28-b404dc7daf63").unwrap()));
+    assert_eq!(Heartbeat::binary_encoding_id(), NodeId::new(2, ByteString::from(vec![1u8, 2, 3])));
+}
+
+#[test]
+fn ua_structure_encoding() {
+    // Fields are encoded in the order they are declared
+    let pump_status = pump_status();
+    let mut expected = Vec::new();
+    expected.extend(pump_status.name.to_vec());
+    expected.extend(pump_status.speed.to_vec());
+    expected.extend(pump_status.running.to_vec());
+    expected.extend(write_array_to_vec(&pump_status.alarms));
+    expected.extend(pump_status.location.to_vec());
+    let encoded = pump_status.to_vec();
+    assert_eq!(encoded, expected);
+    assert_eq!(pump_status.byte_len(), encoded.len());
+
+    let decoded = PumpStatus::decode(&mut std::io::Cursor::new(encoded), &DecodingLimits::default()).unwrap();
+    assert_eq!(decoded, pump_status);
+
+    // A null array stays null
+    let pump_status = PumpStatus { alarms: None, ..pump_status };
+    let decoded = PumpStatus::decode(&mut std::io::Cursor::new(pump_status.to_vec()), &DecodingLimits::default()).unwrap();
+    assert_eq!(decoded, pump_status);
+
+    // A structure without fields encodes to nothing
+    assert!(Heartbeat {}.to_vec().is_empty());
+    assert_eq!(Heartbeat::decode(&mut std::io::Cursor::new(Vec::new()), &DecodingLimits::default()).unwrap(), Heartbeat {});
+}
+
+fn write_array_to_vec(values: &Option<Vec<StatusCode>>) -> Vec<u8> {
+    let mut stream = std::io::Cursor::new(Vec::new());
+    let _ = write_array(&mut stream, values).unwrap();
+    stream.into_inner()
+}
+
+#[test]
+fn ua_structure_extension_object() {
+    let pump_status = pump_status();
+    let extension_object = pump_status.to_extension_object();
+    assert_eq!(extension_object.node_id, PumpStatus::binary_encoding_id());
+    assert_eq!(PumpStatus::from_extension_object(&extension_object, &DecodingLimits::default()).unwrap(), pump_status);
+
+    // An extension object holding another structure is rejected
+    let extension_object = ValveStatus { position: 50 }.to_extension_object();
+    assert_eq!(PumpStatus::from_extension_object(&extension_object, &DecodingLimits::default()).unwrap_err(), StatusCode::BadDataTypeIdUnknown);
+}
+
+#[test]
+fn add_structure_data_type() {
+    let mut address_space = AddressSpace::new();
+
+    let data_type_id = address_space.add_structure_data_type::<PumpStatus>().unwrap();
+    assert_eq!(data_type_id, PumpStatus::data_type_id());
+    let data_type = address_space.find_node(&data_type_id).unwrap().as_node();
+    assert_eq!(data_type.node_class(), NodeClass::DataType);
+    assert_eq!(data_type.browse_name(), QualifiedName::new(2, "PumpStatus"));
+
+    // The data type is a structure with a binary encoding
+    assert!(address_space.is_subtype(&data_type_id, &DataTypeId::Structure.into()));
+    let encoding_id = PumpStatus::binary_encoding_id();
+    assert!(address_space.has_reference(&data_type_id, &encoding_id, ReferenceTypeId::HasEncoding));
+    let encoding_type_id: NodeId = ObjectTypeId::DataTypeEncodingType.into();
+    assert_eq!(address_space.get_type_id(&encoding_id), Some(encoding_type_id));
+
+    // Extension objects holding the structure can be decoded
+    assert!(TYPE_REGISTRY.read().unwrap().is_registered(&encoding_id));
+    let decoded = pump_status().to_extension_object().decode_object(&DecodingLimits::default()).unwrap();
+    assert_eq!(decoded.downcast_ref::<PumpStatus>(), Some(&pump_status()));
+
+    // The data type cannot be added twice
+    assert!(address_space.add_structure_data_type::<PumpStatus>().is_err());
+}
@@ -0,0 +1,231 @@
+//! The TrustList object of the DefaultApplicationGroup, OPC UA Part 12 7.5.2. The trust list is a
+//! file holding an encoded `TrustListDataType` which an administrator reads or replaces through the
+//! file methods of the object. It is backed by the trusted, issuers and crl directories of the
+//! server's PKI directory, so an update takes effect on the next validation of a certificate.
+
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::sync::{Arc, RwLock};
+
+use opcua_types::*;
+use opcua_types::node_ids::{DataTypeId, MethodId, ObjectId, ObjectTypeId, ReferenceTypeId, VariableId, VariableTypeId};
+use opcua_types::status_code::StatusCode;
+use opcua_types::service_types::{CallMethodRequest, CallMethodResult, TrustListDataType};
+
+use opcua_core::crypto::{CertificateStore, X509};
+
+use crate::{
+    address_space::types::{AddressSpace, Object, ReferenceDirection, Variable},
+    callbacks::Method,
+    file_transfer::{self, File, FileNodeIds, FileOpenMode, FileProvider, FileStream, input_argument},
+    server_configuration::{argument, check_security_admin},
+    session::Session,
+};
+
+// The masks of the lists in a TrustListDataType, Part 12 7.5.8
+const TRUSTED_CERTIFICATES: u32 = 1;
+const TRUSTED_CRLS: u32 = 2;
+const ISSUER_CERTIFICATES: u32 = 4;
+const ISSUER_CRLS: u32 = 8;
+const ALL_LISTS: u32 = TRUSTED_CERTIFICATES | TRUSTED_CRLS | ISSUER_CERTIFICATES | ISSUER_CRLS;
+
+/// The trust list of the certificate store as the contents of a file
+#[derive(Clone)]
+struct TrustListFile {
+    certificate_store: Arc<RwLock<CertificateStore>>,
+    last_update_time: Arc<RwLock<DateTime>>,
+}
+
+impl TrustListFile {
+    /// Encodes the lists of the masks
+    fn contents(&self, masks: u32) -> Vec<u8> {
+        let certificate_store = trace_read_lock_unwrap!(self.certificate_store);
+        let as_byte_strings = |certs: Vec<X509>| Some(certs.iter().map(|c| c.as_byte_string()).collect::<Vec<ByteString>>());
+        // The crl directory holds the lists of trusted certificates and issuers alike, so they are
+        // returned as trusted lists unless only the issuer lists are asked for
+        let crls = || Some(certificate_store.crls().into_iter().map(ByteString::from).collect::<Vec<ByteString>>());
+        let trust_list = TrustListDataType {
+            specified_lists: masks,
+            trusted_certificates: if masks & TRUSTED_CERTIFICATES != 0 { as_byte_strings(certificate_store.trusted_certs()) } else { None },
+            trusted_crls: if masks & TRUSTED_CRLS != 0 { crls() } else { None },
+            issuer_certificates: if masks & ISSUER_CERTIFICATES != 0 { as_byte_strings(certificate_store.issuer_certs()) } else { None },
+            issuer_crls: if masks & (TRUSTED_CRLS | ISSUER_CRLS) == ISSUER_CRLS { crls() } else { None },
+        };
+        let mut stream = Cursor::new(Vec::with_capacity(trust_list.byte_len()));
+        let _ = trust_list.encode(&mut stream);
+        stream.into_inner()
+    }
+
+    fn update(&self, trust_list: &TrustListDataType) -> Result<(), StatusCode> {
+        let to_certs = |certs: &Option<Vec<ByteString>>| certs.iter().flatten()
+            .map(|cert| X509::from_byte_string(cert))
+            .collect::<Result<Vec<X509>, StatusCode>>();
+        // Everything is checked before anything is replaced
+        let trusted_certs = to_certs(&trust_list.trusted_certificates)?;
+        let issuer_certs = to_certs(&trust_list.issuer_certificates)?;
+        let crls = trust_list.trusted_crls.iter().flatten().chain(trust_list.issuer_crls.iter().flatten()).cloned().collect::<Vec<ByteString>>();
+
+        let to_status_code = |err: String| {
+            error!("TrustList cannot be updated, {}", err);
+            StatusCode::BadCertificateInvalid
+        };
+        let masks = trust_list.specified_lists;
+        {
+            let certificate_store = trace_read_lock_unwrap!(self.certificate_store);
+            if masks & (TRUSTED_CRLS | ISSUER_CRLS) != 0 {
+                certificate_store.replace_crls(&crls).map_err(to_status_code)?;
+            }
+            if masks & TRUSTED_CERTIFICATES != 0 {
+                certificate_store.replace_trusted_certs(&trusted_certs).map_err(to_status_code)?;
+            }
+            if masks & ISSUER_CERTIFICATES != 0 {
+                certificate_store.replace_issuer_certs(&issuer_certs).map_err(to_status_code)?;
+            }
+        }
+        *trace_write_lock_unwrap!(self.last_update_time) = DateTime::now();
+        info!("TrustList has been updated");
+        Ok(())
+    }
+}
+
+impl FileProvider for TrustListFile {
+    fn size(&self) -> u64 {
+        self.contents(ALL_LISTS).len() as u64
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn open(&mut self, mode: FileOpenMode) -> Result<Box<dyn FileStream>, StatusCode> {
+        // The trust list is read whole, or written whole and applied by CloseAndUpdate
+        if mode == FileOpenMode::READ {
+            Ok(Box::new(Cursor::new(self.contents(ALL_LISTS))))
+        } else if mode == (FileOpenMode::WRITE | FileOpenMode::ERASE_EXISTING) {
+            Ok(Box::new(Cursor::new(Vec::new())))
+        } else {
+            error!("TrustList cannot be opened with mode {:?}", mode);
+            Err(StatusCode::BadInvalidArgument)
+        }
+    }
+}
+
+/// The handler of the methods that the TrustList object adds to those of a file
+struct TrustListMethod {
+    file: Arc<RwLock<File>>,
+    trust_list: TrustListFile,
+}
+
+impl TrustListMethod {
+    fn open_with_masks(&self, file: &mut File, session_id: &NodeId, masks: u32) -> Result<u32, StatusCode> {
+        file.open_with_stream(session_id, FileOpenMode::READ.bits(), Box::new(Cursor::new(self.trust_list.contents(masks))))
+    }
+
+    /// Closes a trust list which was opened for writing and replaces the lists that it specifies
+    fn close_and_update(&self, file: &mut File, session_id: &NodeId, file_handle: u32) -> Result<bool, StatusCode> {
+        if !file.mode(session_id, file_handle)?.contains(FileOpenMode::WRITE) {
+            return Err(StatusCode::BadInvalidState);
+        }
+        let (_, mut stream) = file.take(session_id, file_handle)?;
+        let mut contents = Vec::new();
+        stream.seek(SeekFrom::Start(0)).and_then(|_| stream.read_to_end(&mut contents)).map_err(|_| StatusCode::BadUnexpectedError)?;
+        let trust_list = TrustListDataType::decode(&mut Cursor::new(contents), &DecodingLimits::default()).map_err(|_| {
+            error!("TrustList was written with contents that cannot be decoded");
+            StatusCode::BadDecodingError
+        })?;
+        self.trust_list.update(&trust_list)?;
+        // The certificate store reads the directories on each validation
+        Ok(false)
+    }
+}
+
+impl Method for TrustListMethod {
+    fn call(&mut self, session: &mut Session, request: &CallMethodRequest) -> Result<CallMethodResult, StatusCode> {
+        check_security_admin(session)?;
+
+        let input_arguments = request.input_arguments.as_ref().map(|v| v.as_slice()).unwrap_or(&[]);
+        let session_id = &session.session_id;
+        let mut file = trace_write_lock_unwrap!(self.file);
+
+        let method_id = &request.method_id;
+        let output_arguments = if *method_id == MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_OpenWithMasks.into() {
+            vec![Variant::from(self.open_with_masks(&mut file, session_id, input_argument(input_arguments, 0)?)?)]
+        } else if *method_id == MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_CloseAndUpdate.into() {
+            vec![Variant::from(self.close_and_update(&mut file, session_id, input_argument(input_arguments, 0)?)?)]
+        } else {
+            return Err(StatusCode::BadMethodInvalid);
+        };
+
+        Ok(CallMethodResult {
+            status_code: StatusCode::Good,
+            input_argument_results: Some(vec![StatusCode::Good; input_arguments.len()]),
+            input_argument_diagnostic_infos: None,
+            output_arguments: Some(output_arguments),
+        })
+    }
+}
+
+/// Adds the TrustList object with its properties and methods to the certificate group
+pub(crate) fn add_trust_list(address_space: &mut AddressSpace, certificate_group_id: &NodeId, certificate_store: Arc<RwLock<CertificateStore>>) {
+    let trust_list_id: NodeId = ObjectId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList.into();
+    address_space.insert(Object::new(&trust_list_id, "TrustList", "TrustList", 0), Some(&[
+        (certificate_group_id, ReferenceTypeId::HasComponent, ReferenceDirection::Inverse),
+        (&ObjectTypeId::TrustListType.into(), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+    ]));
+
+    let trust_list = TrustListFile {
+        certificate_store,
+        last_update_time: Arc::new(RwLock::new(DateTime::epoch())),
+    };
+    let file = Arc::new(RwLock::new(File::new(Box::new(trust_list.clone()))));
+
+    // The properties and methods of a file
+    let node_ids = FileNodeIds {
+        size: VariableId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_Size.into(),
+        writable: VariableId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_Writable.into(),
+        user_writable: VariableId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_UserWritable.into(),
+        open_count: VariableId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_OpenCount.into(),
+        open: MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_Open.into(),
+        close: MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_Close.into(),
+        read: MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_Read.into(),
+        write: MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_Write.into(),
+        get_position: MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_GetPosition.into(),
+        set_position: MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_SetPosition.into(),
+    };
+    file_transfer::add_file_nodes(address_space, &trust_list_id, &node_ids, file.clone(), Some(check_security_admin));
+
+    // The properties and methods of a trust list
+    let last_update_time_id: NodeId = VariableId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_LastUpdateTime.into();
+    address_space.insert(Variable::new_data_value(&last_update_time_id, "LastUpdateTime", "LastUpdateTime", DataTypeId::UtcTime, DateTime::epoch()), Some(&[
+        (&trust_list_id, ReferenceTypeId::HasProperty, ReferenceDirection::Inverse),
+        (&VariableTypeId::PropertyType.into(), ReferenceTypeId::HasTypeDefinition, ReferenceDirection::Forward),
+    ]));
+    {
+        let last_update_time = trust_list.last_update_time.clone();
+        address_space.set_variable_getter(last_update_time_id, move |_, _, _| {
+            let last_update_time = trace_read_lock_unwrap!(last_update_time);
+            Ok(Some(DataValue::new(last_update_time.clone())))
+        });
+    }
+
+    let methods = [
+        (MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_OpenWithMasks, "OpenWithMasks", vec![
+            argument("Masks", DataTypeId::UInt32, -1),
+        ], vec![
+            argument("FileHandle", DataTypeId::UInt32, -1),
+        ]),
+        (MethodId::ServerConfiguration_CertificateGroups_DefaultApplicationGroup_TrustList_CloseAndUpdate, "CloseAndUpdate", vec![
+            argument("FileHandle", DataTypeId::UInt32, -1),
+        ], vec![
+            argument("ApplyChangesRequired", DataTypeId::Boolean, -1),
+        ]),
+    ];
+    for (method_id, name, input_arguments, output_arguments) in methods.iter() {
+        let method_id: NodeId = (*method_id).into();
+        if address_space.add_method(&method_id, *name, *name, &trust_list_id, input_arguments, output_arguments).is_ok() {
+            address_space.register_method_handler(trust_list_id.clone(), method_id, Box::new(TrustListMethod {
+                file: file.clone(),
+                trust_list: trust_list.clone(),
+            }));
+        }
+    }
+}
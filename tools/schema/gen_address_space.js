@@ -13,13 +13,13 @@ let trace = false;
 let node_set =
     [
         {
-            name: "Opc.Ua.NodeSet2.Part3.xml", module: "nodeset_3"
+            name: "Opc.Ua.NodeSet2.Part3.xml", module: "nodeset_3", core: true
         },
         {
-            name: "Opc.Ua.NodeSet2.Part4.xml", module: "nodeset_4"
+            name: "Opc.Ua.NodeSet2.Part4.xml", module: "nodeset_4", core: true
         },
         {
-            name: "Opc.Ua.NodeSet2.Part5.xml", module: "nodeset_5"
+            name: "Opc.Ua.NodeSet2.Part5.xml", module: "nodeset_5", core: true
         },
         {
             name: "Opc.Ua.NodeSet2.Part8.xml", module: "nodeset_8"
@@ -52,14 +52,20 @@ let node_set =
 
 let parser = new xml2js.Parser();
 
+// Core modules are the parts of namespace 0 that a nano / micro profile server cannot do without,
+// i.e. the base type system and the Server object. Everything else is extended.
 let modules = [];
+let core_modules = [];
 _.each(node_set, ns => {
     let data = fs.readFileSync(`${settings.schema_dir}/${ns.name}`);
     parser.parseString(data, (err, result) => {
         ns.data = result;
         console.log(`Generating code for module ${ns.module}`);
         let node_set_modules = generate_node_set(ns);
-        modules.push(...node_set_modules)
+        modules.push(...node_set_modules);
+        if (ns.core) {
+            core_modules.push(...node_set_modules);
+        }
     });
 });
 console.log(`modules = ${modules}`);
@@ -82,9 +88,24 @@ mod_contents += `\n`;
 
 // in a populate_address_space method
 mod_contents += `/// Populates the address space with all defined node sets
-pub fn populate_address_space(address_space: &mut AddressSpace) {\n`;
+pub fn populate_address_space(address_space: &mut AddressSpace) {
+    populate_core_address_space(address_space);
+    populate_extended_address_space(address_space);
+}
 
-_.each(modules, module => {
+/// Populates the address space with the core node sets - the base type system and the Server object
+pub fn populate_core_address_space(address_space: &mut AddressSpace) {\n`;
+
+_.each(core_modules, module => {
+    mod_contents += `    ${module}::populate_address_space(address_space);\n`
+});
+
+mod_contents += `}
+
+/// Populates the address space with the remaining, non-core node sets
+pub fn populate_extended_address_space(address_space: &mut AddressSpace) {\n`;
+
+_.each(_.difference(modules, core_modules), module => {
     mod_contents += `    ${module}::populate_address_space(address_space);\n`
 });
 
@@ -158,7 +158,9 @@ generate_supported_message([
     // Discovery service
     "GetEndpointsRequest", "GetEndpointsResponse",
     "FindServersRequest", "FindServersResponse",
+    "FindServersOnNetworkRequest", "FindServersOnNetworkResponse",
     "RegisterServerRequest", "RegisterServerResponse",
+    "RegisterServer2Request", "RegisterServer2Response",
     // Session service
     "CreateSessionRequest", "CreateSessionResponse",
     "CloseSessionRequest", "CloseSessionResponse",
@@ -189,9 +191,14 @@ generate_supported_message([
     "TranslateBrowsePathsToNodeIdsRequest", "TranslateBrowsePathsToNodeIdsResponse",
     "RegisterNodesRequest", "RegisterNodesResponse",
     "UnregisterNodesRequest", "UnregisterNodesResponse",
+    // Query service
+    "QueryFirstRequest", "QueryFirstResponse",
+    "QueryNextRequest", "QueryNextResponse",
     // Attribute service
     "ReadRequest", "ReadResponse",
     "WriteRequest", "WriteResponse",
+    "HistoryReadRequest", "HistoryReadResponse",
+    "HistoryUpdateRequest", "HistoryUpdateResponse",
     // Method service
     "CallRequest", "CallResponse",
 ]);
\ No newline at end of file
@@ -19,11 +19,9 @@ let ignored_types = [
     "Node", "InstanceNode", "TypeNode", "ObjectNode", "ObjectTypeNode", "VariableNode", "VariableTypeNode", "ReferenceTypeNode",
     "MethodNode", "ViewNode", "DataTypeNode", "ReferenceNode",
     // Excluded because they use unimplemented enums, or are used by unimplemented services
-    "ModificationInfo", "HistoryModifiedData", "UpdateDataDetails", "UpdateEventDetails", "UpdateStructureDataDetails", "RedundantServerDataType",
-    "ServerStatusDataType", "AxisInformation", "RegisterServer2Request", "RegisterServer2Response", "HistoryData", "HistoryEvent", "HistoryReadDetails",
-    "HistoryEventFieldList", "HistoryReadRequest", "HistoryReadResponse", "HistoryReadResult", "HistoryReadValueId", "HistoryUpdateDetails",
-    "HistoryUpdateRequest", "HistoryUpdateResponse", "HistoryUpdateResult", "SemanticChangeStructureDataType", "SemanticChangeStructureDataType",
-    "ReadAtTimeDetails", "ReadProcessedDetails"
+    "ModificationInfo", "HistoryModifiedData", "UpdateStructureDataDetails", "RedundantServerDataType",
+    "ServerStatusDataType", "AxisInformation", "HistoryEvent", "HistoryReadDetails",
+    "HistoryUpdateDetails", "SemanticChangeStructureDataType", "SemanticChangeStructureDataType"
 ];
 
 let basic_types_import_map = {
@@ -36,14 +34,15 @@ let basic_types_import_map = {
     "extension_object": ["ExtensionObject"],
     "data_types": ["MessageSecurityMode", "Duration", "UtcTime", "MonitoringMode"],
     "service_types::impls": ["RequestHeader", "ResponseHeader"],
-    "service_types::enums": ["TimestampsToReturn", "FilterOperator", "BrowseDirection", "NodeClass", "SecurityTokenRequestType", "ApplicationType", "UserTokenType", "DataChangeTrigger"],
+    "service_types::enums": ["TimestampsToReturn", "FilterOperator", "BrowseDirection", "NodeClass", "SecurityTokenRequestType", "ApplicationType", "UserTokenType", "DataChangeTrigger", "PerformUpdateType"],
     "node_id": ["NodeId", "ExpandedNodeId"],
     "data_value": ["DataValue"],
     "date_time": ["DateTime"],
     "status_codes": ["StatusCode"]
 };
 
-let serde_supported_types = ["ReadValueId", "DataChangeFilter", "MonitoredItemNotification", "ServerDiagnosticsSummaryDataType"];
+let serde_supported_types = ["ReadValueId", "DataChangeFilter", "MonitoredItemNotification", "ServerDiagnosticsSummaryDataType",
+    "EventFilter", "SimpleAttributeOperand", "ContentFilter", "ContentFilterElement", "EventFieldList"];
 
 // Contains a flattened reverse lookup of the import map
 let basic_types_reverse_import_map = {};
@@ -164,6 +163,8 @@ mod impls;
 pub use self::enums::*;
 pub use self::impls::*;
 
+use crate::{node_ids::ObjectId, type_registry::TypeRegistry};
+
 // All of the remaining are generated by script
 
 `;
@@ -181,6 +182,20 @@ pub use self::impls::*;
 `
     });
 
+    // Types encoded in extension objects are registered so they can be decoded from their encoding id
+    contents += `
+/// Registers the generated types which are encoded in extension objects with the type registry
+pub(crate) fn register_types(type_registry: &mut TypeRegistry) {
+`;
+    _.each(structured_types, structured_type => {
+        if (_.has(structured_type, "base_type") && structured_type.base_type === "ua:ExtensionObject") {
+            contents += `    type_registry.register::<${structured_type.name}, _>(ObjectId::${structured_type.name}_Encoding_DefaultBinary);
+`;
+        }
+    });
+    contents += `}
+`;
+
     settings.write_to_file(file_path, contents);
 }
 
@@ -0,0 +1,280 @@
+//! Provides `#[derive(UaStructure)]`, which encodes a Rust struct as an OPC UA structure so that an
+//! application can define its own data types in Rust.
+//!
+//! The derive implements `BinaryEncoder` for the struct, which encodes its fields in the order that
+//! they are declared, and `UaStructure`, which holds the node ids of its data type and of its
+//! binary encoding. Every field must implement `BinaryEncoder` except arrays, which are declared
+//! as `Option<Vec<T>>` like those of the generated types.
+//!
+//! ```ignore
+//! use opcua_types::*;
+//! use opcua_types_derive::UaStructure;
+//!
+//! #[derive(Debug, Clone, PartialEq, UaStructure)]
+//! #[ua(data_type_id = "ns=2;i=3001", encoding_id = "ns=2;i=3002")]
+//! pub struct PumpStatus {
+//!     pub name: UAString,
+//!     pub speed: f64,
+//!     pub alarms: Option<Vec<StatusCode>>,
+//! }
+//! ```
+//!
+//! The node ids are written in the string form of part 6, e.g. `ns=2;s=PumpStatus`, and an invalid
+//! one is reported as a compile error. The data type is named after the struct unless the attribute
+//! has a `name`. A server adds it to the `Structure` data type hierarchy of its address space with
+//! `add_structure_data_type()`.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Fields, GenericArgument, Ident, Lit, LitStr, Meta,
+    NestedMeta, PathArguments, Type,
+};
+
+#[proc_macro_derive(UaStructure, attributes(ua))]
+pub fn derive_ua_structure(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match ua_structure(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// The values of the `#[ua(...)]` attribute of a struct
+struct StructureAttributes {
+    name: Option<String>,
+    data_type_id: Option<LitStr>,
+    encoding_id: Option<LitStr>,
+}
+
+fn structure_attributes(input: &DeriveInput) -> Result<StructureAttributes, Error> {
+    let mut attributes = StructureAttributes {
+        name: None,
+        data_type_id: None,
+        encoding_id: None,
+    };
+    for attr in &input.attrs {
+        let meta = attr.parse_meta()?;
+        let list = match meta {
+            Meta::List(ref list) if list.ident == "ua" => list,
+            _ => continue,
+        };
+        for nested in &list.nested {
+            match *nested {
+                NestedMeta::Meta(Meta::NameValue(ref name_value)) => {
+                    let value = if let Lit::Str(ref value) = name_value.lit {
+                        value.clone()
+                    } else {
+                        return Err(Error::new_spanned(&name_value.lit, "expected a string"));
+                    };
+                    if name_value.ident == "name" {
+                        attributes.name = Some(value.value());
+                    } else if name_value.ident == "data_type_id" {
+                        attributes.data_type_id = Some(value);
+                    } else if name_value.ident == "encoding_id" {
+                        attributes.encoding_id = Some(value);
+                    } else {
+                        return Err(Error::new_spanned(&name_value.ident, "expected `name`, `data_type_id` or `encoding_id`"));
+                    }
+                }
+                _ => {
+                    return Err(Error::new_spanned(nested, "expected `name = \"...\"`, `data_type_id = \"...\"` or `encoding_id = \"...\"`"));
+                }
+            }
+        }
+    }
+    Ok(attributes)
+}
+
+/// Parses a node id such as `ns=2;i=3001` when the derive runs so that a bad one is a compile error,
+/// and returns the tokens that construct it
+fn node_id(value: &LitStr) -> Result<TokenStream2, Error> {
+    let s = value.value();
+    let invalid = || Error::new_spanned(value, format!("\"{}\" is not a valid node id, expected e.g. \"ns=2;i=3001\"", s));
+
+    // ns=<namespaceindex>;<type>=<value> where ns=0; may be omitted
+    let (namespace, identifier) = if s.starts_with("ns=") {
+        let end = s.find(';').ok_or_else(invalid)?;
+        let namespace = &s[3..end];
+        if namespace.is_empty() || !namespace.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        (namespace.parse::<u16>().map_err(|_| invalid())?, &s[end + 1..])
+    } else {
+        (0u16, &s[..])
+    };
+    let mut parts = identifier.splitn(2, '=');
+    let (identifier_type, v) = match (parts.next(), parts.next()) {
+        (Some(identifier_type), Some(v)) if !v.is_empty() => (identifier_type, v),
+        _ => return Err(invalid())
+    };
+    let identifier = match identifier_type {
+        "i" => {
+            if !v.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            let number = v.parse::<u32>().map_err(|_| invalid())?;
+            quote! { #number }
+        }
+        "s" => quote! { ::opcua_types::UAString::from(#v) },
+        "g" => {
+            let bytes = guid_bytes(v).ok_or_else(invalid)?;
+            quote! { ::opcua_types::Guid::from_bytes([#(#bytes),*]) }
+        }
+        "b" => {
+            let bytes = base64::decode(v).map_err(|_| invalid())?;
+            quote! { ::opcua_types::ByteString::from(vec![#(#bytes),*]) }
+        }
+        _ => return Err(invalid())
+    };
+    Ok(quote! { ::opcua_types::NodeId::new(#namespace, #identifier) })
+}
+
+/// Returns the bytes of a guid written as 32 hex digits, optionally hyphenated 8-4-4-4-12
+fn guid_bytes(value: &str) -> Option<Vec<u8>> {
+    let hex = if value.len() == 36 {
+        let hyphens = [8, 13, 18, 23];
+        if value.char_indices().any(|(i, c)| (c == '-') != hyphens.contains(&i)) {
+            return None;
+        }
+        value.replace('-', "")
+    } else {
+        value.to_string()
+    };
+    if hex.len() != 32 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..16).map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()).collect()
+}
+
+/// Returns the type of the values if the type is an array, i.e. `Option<Vec<T>>`
+fn array_value_type(ty: &Type) -> Option<&Type> {
+    fn single_type_argument<'a>(ty: &'a Type, ident: &str) -> Option<&'a Type> {
+        if let Type::Path(ref type_path) = *ty {
+            if type_path.qself.is_some() {
+                return None;
+            }
+            let segment = type_path.path.segments.last()?.into_value();
+            if segment.ident != ident {
+                return None;
+            }
+            if let PathArguments::AngleBracketed(ref arguments) = segment.arguments {
+                if arguments.args.len() == 1 {
+                    if let GenericArgument::Type(ref ty) = arguments.args[0] {
+                        return Some(ty);
+                    }
+                }
+            }
+        }
+        None
+    }
+    single_type_argument(ty, "Option").and_then(|ty| single_type_argument(ty, "Vec"))
+}
+
+fn ua_structure(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let struct_name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(&input.generics, "UaStructure cannot be derived for a generic struct"));
+    }
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(struct_name, "UaStructure can only be derived for a struct with named fields"));
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(struct_name, "UaStructure can only be derived for a struct"));
+        }
+    };
+
+    let attributes = structure_attributes(input)?;
+    let type_name = attributes.name.unwrap_or_else(|| struct_name.to_string());
+    let data_type_id = attributes.data_type_id
+        .ok_or_else(|| Error::new(Span::call_site(), "UaStructure requires #[ua(data_type_id = \"...\")]"))
+        .and_then(|data_type_id| node_id(&data_type_id))?;
+    let encoding_id = attributes.encoding_id
+        .ok_or_else(|| Error::new(Span::call_site(), "UaStructure requires #[ua(encoding_id = \"...\")]"))
+        .and_then(|encoding_id| node_id(&encoding_id))?;
+
+    let mut byte_len = Vec::with_capacity(fields.len());
+    let mut encode = Vec::with_capacity(fields.len());
+    let mut decode = Vec::with_capacity(fields.len());
+    let mut field_names: Vec<&Ident> = Vec::with_capacity(fields.len());
+    for field in fields {
+        // Named fields always have an ident
+        let name = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        if let Some(value_type) = array_value_type(ty) {
+            byte_len.push(quote! {
+                size += ::opcua_types::byte_len_array(&self.#name);
+            });
+            encode.push(quote! {
+                size += ::opcua_types::write_array(stream, &self.#name)?;
+            });
+            decode.push(quote! {
+                let #name: Option<Vec<#value_type>> = ::opcua_types::read_array(stream, decoding_limits)?;
+            });
+        } else {
+            byte_len.push(quote! {
+                size += <#ty as ::opcua_types::BinaryEncoder<#ty>>::byte_len(&self.#name);
+            });
+            encode.push(quote! {
+                size += <#ty as ::opcua_types::BinaryEncoder<#ty>>::encode(&self.#name, stream)?;
+            });
+            decode.push(quote! {
+                let #name = <#ty as ::opcua_types::BinaryEncoder<#ty>>::decode(stream, decoding_limits)?;
+            });
+        }
+        field_names.push(name);
+    }
+
+    // A struct without fields never adds to its size
+    let size_binding = if fields.is_empty() {
+        quote! { let size = 0; }
+    } else {
+        quote! { let mut size = 0; }
+    };
+
+    Ok(quote! {
+        impl ::opcua_types::BinaryEncoder<#struct_name> for #struct_name {
+            fn byte_len(&self) -> usize {
+                #size_binding
+                #(#byte_len)*
+                size
+            }
+
+            #[allow(unused_variables)]
+            fn encode<S: ::std::io::Write>(&self, stream: &mut S) -> ::opcua_types::EncodingResult<usize> {
+                #size_binding
+                #(#encode)*
+                Ok(size)
+            }
+
+            #[allow(unused_variables)]
+            fn decode<S: ::std::io::Read>(stream: &mut S, decoding_limits: &::opcua_types::DecodingLimits) -> ::opcua_types::EncodingResult<Self> {
+                #(#decode)*
+                Ok(#struct_name {
+                    #(#field_names),*
+                })
+            }
+        }
+
+        impl ::opcua_types::UaStructure for #struct_name {
+            fn type_name() -> &'static str {
+                #type_name
+            }
+
+            fn data_type_id() -> ::opcua_types::NodeId {
+                #data_type_id
+            }
+
+            fn binary_encoding_id() -> ::opcua_types::NodeId {
+                #encoding_id
+            }
+        }
+    })
+}
@@ -26,6 +26,8 @@ pub enum AttributeId {
     Historizing = 20,
     Executable = 21,
     UserExecutable = 22,
+    RolePermissions = 24,
+    UserRolePermissions = 25,
 }
 
 impl AttributeId {
@@ -53,6 +55,8 @@ impl AttributeId {
             20 => AttributeId::Historizing,
             21 => AttributeId::Executable,
             22 => AttributeId::UserExecutable,
+            24 => AttributeId::RolePermissions,
+            25 => AttributeId::UserRolePermissions,
             _ => {
                 debug!("Invalid attribute id {}", attribute_id);
                 return Err(());
@@ -215,6 +215,15 @@ impl DataValue {
         }
     }
 
+    /// Creates a DataValue holding just a value, without a status or timestamps, e.g. for writing
+    /// attributes which do not have timestamps.
+    pub fn value_only<V>(value: V) -> DataValue where V: Into<Variant> {
+        DataValue {
+            value: Some(value.into()),
+            ..DataValue::null()
+        }
+    }
+
     /// Sets the value of the data value, updating the timestamps at the same point
     pub fn set_value<V>(&mut self, value: V, source_timestamp: &DateTime, server_timestamp: &DateTime) where V: Into<Variant> {
         self.value = Some(value.into());
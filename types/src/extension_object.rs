@@ -8,6 +8,7 @@ use crate::{
     node_id::NodeId,
     byte_string::ByteString,
     status_codes::StatusCode,
+    type_registry::{DecodedObject, TYPE_REGISTRY},
 };
 
 /// Enumeration that holds the kinds of encoding that an ExtensionObject data may be encoded with.
@@ -147,4 +148,12 @@ impl ExtensionObject {
             }
         }
     }
+
+    /// Decodes the inner content of the extension object into the type registered with its node
+    /// id in the type registry, which holds the generated types. The concrete type is obtained by
+    /// downcasting the result, e.g. `decode_object(&limits)?.downcast_ref::<EUInformation>()`.
+    pub fn decode_object(&self, decoding_limits: &DecodingLimits) -> EncodingResult<Box<dyn DecodedObject>> {
+        let type_registry = TYPE_REGISTRY.read().unwrap();
+        type_registry.decode(self, decoding_limits)
+    }
 }
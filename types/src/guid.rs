@@ -103,4 +103,9 @@ impl Guid {
     pub fn new() -> Guid {
         Guid { uuid: Uuid::new_v4() }
     }
+
+    /// Creates a Guid from its 16 bytes, in the order they appear in its string form
+    pub fn from_bytes(bytes: [u8; 16]) -> Guid {
+        Guid { uuid: Uuid::from_bytes(&bytes).unwrap() }
+    }
 }
@@ -30,6 +30,7 @@ macro_rules! supported_message_as {
 /// profiles used during communication and encryption.
 pub mod profiles {
     pub const TRANSPORT_PROFILE_URI_BINARY: &str = "http://opcfoundation.org/UA-Profile/Transport/uatcp-uasc-uabinary";
+    pub const TRANSPORT_PROFILE_URI_HTTPS_BINARY: &str = "http://opcfoundation.org/UA-Profile/Transport/https-uabinary";
 
     pub const SECURITY_USER_TOKEN_POLICY_ANONYMOUS: &str = "http://opcfoundation.org/UA-Profile/Security/UserToken/Anonymous";
     pub const SECURITY_USER_TOKEN_POLICY_USERPASS: &str = "http://opcfoundation.org/UA-Profile/ Security/UserToken-Server/UserNamePassword";
@@ -57,6 +58,10 @@ pub mod constants {
     pub const SECURITY_POLICY_BASIC_256_URI: &str = "http://opcfoundation.org/UA/SecurityPolicy#Basic256";
     /// URI supplied for the `Basic256Sha256` security policy
     pub const SECURITY_POLICY_BASIC_256_SHA_256_URI: &str = "http://opcfoundation.org/UA/SecurityPolicy#Basic256Sha256";
+    /// URI supplied for the `Aes128-Sha256-RsaOaep` security policy
+    pub const SECURITY_POLICY_AES_128_SHA_256_RSA_OAEP_URI: &str = "http://opcfoundation.org/UA/SecurityPolicy#Aes128_Sha256_RsaOaep";
+    /// URI supplied for the `Aes256-Sha256-RsaPss` security policy
+    pub const SECURITY_POLICY_AES_256_SHA_256_RSA_PSS_URI: &str = "http://opcfoundation.org/UA/SecurityPolicy#Aes256_Sha256_RsaPss";
 
     /// String used as shorthand in config files, debug etc.for `None` security policy
     pub const SECURITY_POLICY_NONE: &str = "None";
@@ -66,6 +71,10 @@ pub mod constants {
     pub const SECURITY_POLICY_BASIC_256: &str = "Basic256";
     /// String used as shorthand in config files, debug etc.for `Basic256Sha256` security policy
     pub const SECURITY_POLICY_BASIC_256_SHA_256: &str = "Basic256Sha256";
+    /// String used as shorthand in config files, debug etc.for `Aes128-Sha256-RsaOaep` security policy
+    pub const SECURITY_POLICY_AES_128_SHA_256_RSA_OAEP: &str = "Aes128-Sha256-RsaOaep";
+    /// String used as shorthand in config files, debug etc.for `Aes256-Sha256-RsaPss` security policy
+    pub const SECURITY_POLICY_AES_256_SHA_256_RSA_PSS: &str = "Aes256-Sha256-RsaPss";
 }
 
 /// Attributes mask bits
@@ -161,6 +170,8 @@ bitflags! {
         /// since this is handled by the AccessLevel and UserAccessLevel Attributes for the Variable.
         /// For Variables this bit shall be set to 0.
         const VALUE_FOR_VARIABLE_TYPE = 1 << 21;
+        /// Indicates if the RolePermissions Attribute is writable.
+        const ROLE_PERMISSIONS = 1 << 23;
     }
 }
 
@@ -178,6 +189,7 @@ pub mod guid;
 pub mod node_id;
 pub mod node_ids;
 pub mod variant;
+pub mod variant_conversion;
 pub mod data_types;
 pub mod notification_message;
 pub mod attribute;
@@ -189,6 +201,9 @@ pub mod tcp_types;
 pub mod service_types;
 pub mod status_code;
 pub mod relative_path;
+pub mod type_registry;
+pub mod roles;
+pub mod ua_structure;
 
 pub use crate::{
     encoding::*,
@@ -202,12 +217,15 @@ pub use crate::{
     guid::*,
     node_id::*,
     variant::*,
+    variant_conversion::*,
     data_types::*,
     attribute::*,
     supported_message::*,
     numeric_range::*,
     url::*,
     argument::*,
+    roles::*,
+    ua_structure::*,
 };
 
 #[cfg(test)]
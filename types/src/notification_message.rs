@@ -7,7 +7,10 @@ use crate::{
     node_ids::ObjectId,
     status_code::StatusCode,
     diagnostic_info::DiagnosticInfo,
-    service_types::{NotificationMessage, MonitoredItemNotification, DataChangeNotification, StatusChangeNotification},
+    service_types::{
+        NotificationMessage, MonitoredItemNotification, DataChangeNotification, StatusChangeNotification,
+        EventFieldList, EventNotificationList,
+    },
 };
 
 impl NotificationMessage {
@@ -29,6 +32,32 @@ impl NotificationMessage {
         }
     }
 
+    /// Create a notification message which contains data change and / or event notifications.
+    /// Either list may be empty in which case that kind of notification is left out.
+    pub fn data_change_and_events(sequence_number: u32, publish_time: DateTime, monitored_items: Vec<MonitoredItemNotification>, events: Vec<EventFieldList>) -> NotificationMessage {
+        let mut notification_data = Vec::with_capacity(2);
+        if !monitored_items.is_empty() {
+            let data_change_notification = DataChangeNotification {
+                monitored_items: Some(monitored_items),
+                diagnostic_infos: None,
+            };
+            trace!("data change notification = {:?}", data_change_notification);
+            notification_data.push(ExtensionObject::from_encodable(ObjectId::DataChangeNotification_Encoding_DefaultBinary, &data_change_notification));
+        }
+        if !events.is_empty() {
+            let event_notification_list = EventNotificationList {
+                events: Some(events),
+            };
+            trace!("event notification list = {:?}", event_notification_list);
+            notification_data.push(ExtensionObject::from_encodable(ObjectId::EventNotificationList_Encoding_DefaultBinary, &event_notification_list));
+        }
+        NotificationMessage {
+            sequence_number,
+            publish_time,
+            notification_data: Some(notification_data),
+        }
+    }
+
     /// Create a status change notification message
     pub fn status_change(sequence_number: u32, publish_time: DateTime, status: StatusCode) -> NotificationMessage {
         let status_change_notification = StatusChangeNotification {
@@ -69,4 +98,38 @@ impl NotificationMessage {
         }
         result
     }
+
+    /// Extract event notifications from this notification. If the message contains no event
+    /// notifications, it will return an empty list.
+    pub fn event_notifications(&self, decoding_limits: &DecodingLimits) -> Vec<EventNotificationList> {
+        let mut result = Vec::new();
+        if let Some(ref notification_data) = self.notification_data {
+            for n in notification_data {
+                if n.node_id != ObjectId::EventNotificationList_Encoding_DefaultBinary.into() {
+                    continue;
+                }
+                if let Ok(notification) = n.decode_inner::<EventNotificationList>(decoding_limits) {
+                    result.push(notification);
+                }
+            }
+        }
+        result
+    }
+
+    /// Extract status change notifications from this notification. If the message contains no status
+    /// change notifications, it will return an empty list.
+    pub fn status_change_notifications(&self, decoding_limits: &DecodingLimits) -> Vec<StatusChangeNotification> {
+        let mut result = Vec::new();
+        if let Some(ref notification_data) = self.notification_data {
+            for n in notification_data {
+                if n.node_id != ObjectId::StatusChangeNotification_Encoding_DefaultBinary.into() {
+                    continue;
+                }
+                if let Ok(notification) = n.decode_inner::<StatusChangeNotification>(decoding_limits) {
+                    result.push(notification);
+                }
+            }
+        }
+        result
+    }
 }
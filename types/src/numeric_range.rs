@@ -225,21 +225,9 @@ impl NumericRange {
         }
     }
 
-    // This version should test the range against the supplied array
+    /// Tests if the range is valid for the array, i.e. it has a range or index for each dimension
+    /// of the array and every index lies within its dimension
     pub fn is_valid_for_array(&self, array: &Variant) -> bool {
-        match *array {
-            Variant::Array(_) => {
-                // Only accept range / index numeric ranges. Members of range have to be inside array
-                unimplemented!();
-            }
-            Variant::MultiDimensionArray(_) => {
-                // Only accept multi dimension numeric range with same # of dimensions as the array
-                // ensure that each range / index is inside that array dimension
-                unimplemented!();
-            }
-            _ => {
-                panic!("The array is not an array")
-            }
-        }
+        array.is_array() && self.is_valid() && array.range_lies_within(self)
     }
 }
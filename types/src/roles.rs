@@ -0,0 +1,242 @@
+use std::io::{Read, Write};
+
+use crate::{
+    encoding::*,
+    node_id::NodeId,
+    string::UAString,
+    status_codes::StatusCode,
+};
+
+// From OPC UA Part 3 - Address Space Model 1.04 Specification and OPC UA Part 18 - Role-Based
+// Security 1.04 Specification. These types are not part of the 1.03 schemas that the rest of the
+// types are generated from, so their node ids are declared here.
+
+/// Node id of the `RolePermissionType` data type
+pub const ROLE_PERMISSION_TYPE_ID: u32 = 96;
+/// Node id of the default binary encoding of `RolePermissionType`
+pub const ROLE_PERMISSION_TYPE_ENCODING_DEFAULT_BINARY: u32 = 128;
+/// Node id of the `IdentityMappingRuleType` data type
+pub const IDENTITY_MAPPING_RULE_TYPE_ID: u32 = 15634;
+/// Node id of the default binary encoding of `IdentityMappingRuleType`
+pub const IDENTITY_MAPPING_RULE_TYPE_ENCODING_DEFAULT_BINARY: u32 = 15736;
+/// Node id of the `RoleSetType` object type
+pub const ROLE_SET_TYPE_ID: u32 = 15607;
+/// Node id of the `RoleType` object type
+pub const ROLE_TYPE_ID: u32 = 15620;
+/// Node id of the `Server.ServerCapabilities.RoleSet` object
+pub const SERVER_SERVER_CAPABILITIES_ROLE_SET_ID: u32 = 15606;
+
+bitflags! {
+    /// The permissions that a role has on a node, Part 3 8.55
+    pub struct PermissionType: u32 {
+        /// The node is visible to Browse and its non-value attributes can be read
+        const BROWSE = 1;
+        /// The RolePermissions attribute can be read
+        const READ_ROLE_PERMISSIONS = 1 << 1;
+        /// Attributes other than Value, Historizing and RolePermissions can be written
+        const WRITE_ATTRIBUTE = 1 << 2;
+        /// The RolePermissions attribute can be written
+        const WRITE_ROLE_PERMISSIONS = 1 << 3;
+        /// The Historizing attribute can be written
+        const WRITE_HISTORIZING = 1 << 4;
+        /// The Value attribute can be read
+        const READ = 1 << 5;
+        /// The Value attribute can be written
+        const WRITE = 1 << 6;
+        /// The history of the node can be read
+        const READ_HISTORY = 1 << 7;
+        /// History can be inserted
+        const INSERT_HISTORY = 1 << 8;
+        /// History can be modified
+        const MODIFY_HISTORY = 1 << 9;
+        /// History can be deleted
+        const DELETE_HISTORY = 1 << 10;
+        /// Events of the node can be received
+        const RECEIVE_EVENTS = 1 << 11;
+        /// The method can be called
+        const CALL = 1 << 12;
+        /// References can be added to the node
+        const ADD_REFERENCE = 1 << 13;
+        /// References can be removed from the node
+        const REMOVE_REFERENCE = 1 << 14;
+        /// The node can be deleted
+        const DELETE_NODE = 1 << 15;
+        /// Nodes can be added beneath the node
+        const ADD_NODE = 1 << 16;
+    }
+}
+
+/// The permissions granted to a role on a node. A node's RolePermissions attribute is an array of
+/// these. Part 3 8.56
+#[derive(Clone, Debug, PartialEq)]
+pub struct RolePermissionType {
+    pub role_id: NodeId,
+    pub permissions: u32,
+}
+
+impl BinaryEncoder<RolePermissionType> for RolePermissionType {
+    fn byte_len(&self) -> usize {
+        self.role_id.byte_len() + self.permissions.byte_len()
+    }
+
+    fn encode<S: Write>(&self, stream: &mut S) -> EncodingResult<usize> {
+        let mut size = 0;
+        size += self.role_id.encode(stream)?;
+        size += self.permissions.encode(stream)?;
+        Ok(size)
+    }
+
+    fn decode<S: Read>(stream: &mut S, decoding_limits: &DecodingLimits) -> EncodingResult<Self> {
+        let role_id = NodeId::decode(stream, decoding_limits)?;
+        let permissions = u32::decode(stream, decoding_limits)?;
+        Ok(RolePermissionType {
+            role_id,
+            permissions,
+        })
+    }
+}
+
+impl RolePermissionType {
+    pub fn new<T>(role_id: T, permissions: PermissionType) -> RolePermissionType where T: Into<NodeId> {
+        RolePermissionType {
+            role_id: role_id.into(),
+            permissions: permissions.bits(),
+        }
+    }
+
+    pub fn permissions(&self) -> PermissionType {
+        PermissionType::from_bits_truncate(self.permissions)
+    }
+}
+
+/// The kind of criteria that an identity mapping rule matches a user against. Part 18 4.4.3
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IdentityCriteriaType {
+    /// The criteria is the name of a user in a user name identity token
+    UserName = 1,
+    /// The criteria is the hex thumbprint of the certificate in an X509 identity token
+    Thumbprint = 2,
+    /// The criteria is a role claimed by an issued token
+    Role = 3,
+    /// The criteria is a group claimed by an issued token
+    GroupId = 4,
+    /// Matches anonymous users. The criteria is empty
+    Anonymous = 5,
+    /// Matches any user that is not anonymous. The criteria is empty
+    AuthenticatedUser = 6,
+}
+
+impl BinaryEncoder<IdentityCriteriaType> for IdentityCriteriaType {
+    fn byte_len(&self) -> usize {
+        4
+    }
+
+    fn encode<S: Write>(&self, stream: &mut S) -> EncodingResult<usize> {
+        // All enums are Int32
+        write_i32(stream, *self as i32)
+    }
+
+    fn decode<S: Read>(stream: &mut S, _: &DecodingLimits) -> EncodingResult<Self> {
+        // All enums are Int32
+        let criteria_type = read_i32(stream)?;
+        match criteria_type {
+            1 => Ok(IdentityCriteriaType::UserName),
+            2 => Ok(IdentityCriteriaType::Thumbprint),
+            3 => Ok(IdentityCriteriaType::Role),
+            4 => Ok(IdentityCriteriaType::GroupId),
+            5 => Ok(IdentityCriteriaType::Anonymous),
+            6 => Ok(IdentityCriteriaType::AuthenticatedUser),
+            _ => {
+                error!("Don't know what identity criteria type {} is", criteria_type);
+                Err(StatusCode::BadUnexpectedError)
+            }
+        }
+    }
+}
+
+/// A rule that maps users onto a role. Part 18 4.4.2
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdentityMappingRuleType {
+    pub criteria_type: IdentityCriteriaType,
+    pub criteria: UAString,
+}
+
+impl BinaryEncoder<IdentityMappingRuleType> for IdentityMappingRuleType {
+    fn byte_len(&self) -> usize {
+        self.criteria_type.byte_len() + self.criteria.byte_len()
+    }
+
+    fn encode<S: Write>(&self, stream: &mut S) -> EncodingResult<usize> {
+        let mut size = 0;
+        size += self.criteria_type.encode(stream)?;
+        size += self.criteria.encode(stream)?;
+        Ok(size)
+    }
+
+    fn decode<S: Read>(stream: &mut S, decoding_limits: &DecodingLimits) -> EncodingResult<Self> {
+        let criteria_type = IdentityCriteriaType::decode(stream, decoding_limits)?;
+        let criteria = UAString::decode(stream, decoding_limits)?;
+        Ok(IdentityMappingRuleType {
+            criteria_type,
+            criteria,
+        })
+    }
+}
+
+impl IdentityMappingRuleType {
+    pub fn new<T>(criteria_type: IdentityCriteriaType, criteria: T) -> IdentityMappingRuleType where T: Into<UAString> {
+        IdentityMappingRuleType {
+            criteria_type,
+            criteria: criteria.into(),
+        }
+    }
+}
+
+/// The well-known roles of Part 3 4.8.2, found in the RoleSet of the server
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum WellKnownRole {
+    Anonymous = 15644,
+    AuthenticatedUser = 15656,
+    Observer = 15668,
+    Operator = 15680,
+    Supervisor = 15692,
+    SecurityAdmin = 15704,
+    ConfigureAdmin = 15716,
+    Engineer = 16036,
+}
+
+impl From<WellKnownRole> for NodeId {
+    fn from(role: WellKnownRole) -> Self {
+        NodeId::new(0, role as u32)
+    }
+}
+
+impl WellKnownRole {
+    /// All of the well-known roles
+    pub fn all() -> &'static [WellKnownRole] {
+        &[
+            WellKnownRole::Anonymous,
+            WellKnownRole::AuthenticatedUser,
+            WellKnownRole::Observer,
+            WellKnownRole::Operator,
+            WellKnownRole::Engineer,
+            WellKnownRole::Supervisor,
+            WellKnownRole::ConfigureAdmin,
+            WellKnownRole::SecurityAdmin,
+        ]
+    }
+
+    /// The browse name of the role
+    pub fn name(&self) -> &'static str {
+        match *self {
+            WellKnownRole::Anonymous => "Anonymous",
+            WellKnownRole::AuthenticatedUser => "AuthenticatedUser",
+            WellKnownRole::Observer => "Observer",
+            WellKnownRole::Operator => "Operator",
+            WellKnownRole::Supervisor => "Supervisor",
+            WellKnownRole::SecurityAdmin => "SecurityAdmin",
+            WellKnownRole::ConfigureAdmin => "ConfigureAdmin",
+            WellKnownRole::Engineer => "Engineer",
+        }
+    }
+}
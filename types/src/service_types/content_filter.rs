@@ -12,7 +12,7 @@ use crate::{
     service_types::ContentFilterElement,
 };
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct ContentFilter {
     pub elements: Option<Vec<ContentFilterElement>>,
 }
@@ -197,7 +197,7 @@ impl BinaryEncoder<DataChangeTrigger> for DataChangeTrigger {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
 pub enum FilterOperator {
     Equals = 0,
     IsNull = 1,
@@ -212,6 +212,9 @@ pub enum FilterOperator {
     And = 10,
     Or = 11,
     Cast = 12,
+    InView = 13,
+    OfType = 14,
+    RelatedTo = 15,
     BitwiseAnd = 16,
     BitwiseOr = 17,
 }
@@ -243,6 +246,9 @@ impl BinaryEncoder<FilterOperator> for FilterOperator {
             10 => Ok(FilterOperator::And),
             11 => Ok(FilterOperator::Or),
             12 => Ok(FilterOperator::Cast),
+            13 => Ok(FilterOperator::InView),
+            14 => Ok(FilterOperator::OfType),
+            15 => Ok(FilterOperator::RelatedTo),
             16 => Ok(FilterOperator::BitwiseAnd),
             17 => Ok(FilterOperator::BitwiseOr),
             _ => {
@@ -338,20 +344,64 @@ impl BinaryEncoder<ServerState> for ServerState {
         write_i32(stream, *self as i32)
     }
 
+    fn decode<S: Read>(stream: &mut S, _: &DecodingLimits) -> EncodingResult<Self> {
+        // All enums are Int32
+        let value = read_i32(stream)?;
+        if let Some(result) = ServerState::from_i32(value) {
+            Ok(result)
+        } else {
+            error!("Don't know what server state {} is", value);
+            Err(StatusCode::BadUnexpectedError)
+        }
+    }
+}
+
+impl ServerState {
+    pub fn from_i32(value: i32) -> Option<ServerState> {
+        match value {
+            0 => Some(ServerState::Running),
+            1 => Some(ServerState::Failed),
+            2 => Some(ServerState::NoConfiguration),
+            3 => Some(ServerState::Suspended),
+            4 => Some(ServerState::Shutdown),
+            5 => Some(ServerState::Test),
+            6 => Some(ServerState::CommunicationFault),
+            7 => Some(ServerState::Unknown),
+            _ => {
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PerformUpdateType {
+    Insert = 1,
+    Replace = 2,
+    Update = 3,
+    Remove = 4,
+}
+
+impl BinaryEncoder<PerformUpdateType> for PerformUpdateType {
+    fn byte_len(&self) -> usize {
+        4
+    }
+
+    fn encode<S: Write>(&self, stream: &mut S) -> EncodingResult<usize> {
+        // All enums are Int32
+        write_i32(stream, *self as i32)
+    }
+
     fn decode<S: Read>(stream: &mut S, _: &DecodingLimits) -> EncodingResult<Self> {
         // All enums are Int32
         let value = read_i32(stream)?;
         match value {
-            0 => Ok(ServerState::Running),
-            1 => Ok(ServerState::Failed),
-            2 => Ok(ServerState::NoConfiguration),
-            3 => Ok(ServerState::Suspended),
-            4 => Ok(ServerState::Shutdown),
-            5 => Ok(ServerState::Test),
-            6 => Ok(ServerState::CommunicationFault),
-            7 => Ok(ServerState::Unknown),
+            1 => Ok(PerformUpdateType::Insert),
+            2 => Ok(PerformUpdateType::Replace),
+            3 => Ok(PerformUpdateType::Update),
+            4 => Ok(PerformUpdateType::Remove),
             _ => {
-                error!("Don't know what server state {} is", value);
+                error!("Don't know what perform update type {} is", value);
                 Err(StatusCode::BadUnexpectedError)
             }
         }
@@ -11,7 +11,7 @@ use crate::{
     service_types::ContentFilter,
 };
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct EventFilter {
     pub select_clauses: Option<Vec<SimpleAttributeOperand>>,
     pub where_clause: ContentFilter,
@@ -0,0 +1,59 @@
+// This file was autogenerated from Opc.Ua.Types.bsd.xml by tools/schema/gen_types.js
+// DO NOT EDIT THIS FILE
+
+use std::io::{Read, Write};
+
+#[allow(unused_imports)]
+use crate::{
+    encoding::*,
+    basic_types::*,
+    service_types::impls::MessageInfo,
+    node_ids::ObjectId,
+    service_types::impls::ResponseHeader,
+    diagnostic_info::DiagnosticInfo,
+    service_types::HistoryUpdateResult,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryUpdateResponse {
+    pub response_header: ResponseHeader,
+    pub results: Option<Vec<HistoryUpdateResult>>,
+    pub diagnostic_infos: Option<Vec<DiagnosticInfo>>,
+}
+
+impl MessageInfo for HistoryUpdateResponse {
+    fn object_id(&self) -> ObjectId {
+        ObjectId::HistoryUpdateResponse_Encoding_DefaultBinary
+    }
+}
+
+impl BinaryEncoder<HistoryUpdateResponse> for HistoryUpdateResponse {
+    fn byte_len(&self) -> usize {
+        let mut size = 0;
+        size += self.response_header.byte_len();
+        size += byte_len_array(&self.results);
+        size += byte_len_array(&self.diagnostic_infos);
+        size
+    }
+
+    #[allow(unused_variables)]
+    fn encode<S: Write>(&self, stream: &mut S) -> EncodingResult<usize> {
+        let mut size = 0;
+        size += self.response_header.encode(stream)?;
+        size += write_array(stream, &self.results)?;
+        size += write_array(stream, &self.diagnostic_infos)?;
+        Ok(size)
+    }
+
+    #[allow(unused_variables)]
+    fn decode<S: Read>(stream: &mut S, decoding_limits: &DecodingLimits) -> EncodingResult<Self> {
+        let response_header = ResponseHeader::decode(stream, decoding_limits)?;
+        let results: Option<Vec<HistoryUpdateResult>> = read_array(stream, decoding_limits)?;
+        let diagnostic_infos: Option<Vec<DiagnosticInfo>> = read_array(stream, decoding_limits)?;
+        Ok(HistoryUpdateResponse {
+            response_header,
+            results,
+            diagnostic_infos,
+        })
+    }
+}
@@ -8,6 +8,8 @@ mod impls;
 pub use self::enums::*;
 pub use self::impls::*;
 
+use crate::{node_ids::ObjectId, type_registry::TypeRegistry};
+
 // All of the remaining are generated by script
 
 mod trust_list_data_type;
@@ -31,6 +33,8 @@ mod register_server_request;
 mod register_server_response;
 mod discovery_configuration;
 mod mdns_discovery_configuration;
+mod register_server2_request;
+mod register_server2_response;
 mod channel_security_token;
 mod open_secure_channel_request;
 mod open_secure_channel_response;
@@ -114,14 +118,26 @@ mod query_next_response;
 mod read_value_id;
 mod read_request;
 mod read_response;
+mod history_read_value_id;
+mod history_read_result;
 mod read_event_details;
 mod read_raw_modified_details;
+mod read_processed_details;
+mod read_at_time_details;
+mod history_data;
+mod history_read_request;
+mod history_read_response;
 mod write_value;
 mod write_request;
 mod write_response;
+mod update_data_details;
+mod update_event_details;
 mod delete_raw_modified_details;
 mod delete_at_time_details;
 mod delete_event_details;
+mod history_update_result;
+mod history_update_request;
+mod history_update_response;
 mod call_method_request;
 mod call_method_result;
 mod call_request;
@@ -161,6 +177,7 @@ mod data_change_notification;
 mod monitored_item_notification;
 mod event_notification_list;
 mod event_field_list;
+mod history_event_field_list;
 mod status_change_notification;
 mod subscription_acknowledgement;
 mod publish_request;
@@ -212,6 +229,8 @@ pub use self::register_server_request::*;
 pub use self::register_server_response::*;
 pub use self::discovery_configuration::*;
 pub use self::mdns_discovery_configuration::*;
+pub use self::register_server2_request::*;
+pub use self::register_server2_response::*;
 pub use self::channel_security_token::*;
 pub use self::open_secure_channel_request::*;
 pub use self::open_secure_channel_response::*;
@@ -295,14 +314,26 @@ pub use self::query_next_response::*;
 pub use self::read_value_id::*;
 pub use self::read_request::*;
 pub use self::read_response::*;
+pub use self::history_read_value_id::*;
+pub use self::history_read_result::*;
 pub use self::read_event_details::*;
 pub use self::read_raw_modified_details::*;
+pub use self::read_processed_details::*;
+pub use self::read_at_time_details::*;
+pub use self::history_data::*;
+pub use self::history_read_request::*;
+pub use self::history_read_response::*;
 pub use self::write_value::*;
 pub use self::write_request::*;
 pub use self::write_response::*;
+pub use self::update_data_details::*;
+pub use self::update_event_details::*;
 pub use self::delete_raw_modified_details::*;
 pub use self::delete_at_time_details::*;
 pub use self::delete_event_details::*;
+pub use self::history_update_result::*;
+pub use self::history_update_request::*;
+pub use self::history_update_response::*;
 pub use self::call_method_request::*;
 pub use self::call_method_result::*;
 pub use self::call_request::*;
@@ -342,6 +373,7 @@ pub use self::data_change_notification::*;
 pub use self::monitored_item_notification::*;
 pub use self::event_notification_list::*;
 pub use self::event_field_list::*;
+pub use self::history_event_field_list::*;
 pub use self::status_change_notification::*;
 pub use self::subscription_acknowledgement::*;
 pub use self::publish_request::*;
@@ -371,3 +403,168 @@ pub use self::double_complex_number_type::*;
 pub use self::xv_type::*;
 pub use self::program_diagnostic_data_type::*;
 pub use self::annotation::*;
+
+/// Registers the generated types which are encoded in extension objects with the type registry
+pub(crate) fn register_types(type_registry: &mut TypeRegistry) {
+    type_registry.register::<TrustListDataType, _>(ObjectId::TrustListDataType_Encoding_DefaultBinary);
+    type_registry.register::<Argument, _>(ObjectId::Argument_Encoding_DefaultBinary);
+    type_registry.register::<EnumValueType, _>(ObjectId::EnumValueType_Encoding_DefaultBinary);
+    type_registry.register::<OptionSet, _>(ObjectId::OptionSet_Encoding_DefaultBinary);
+    type_registry.register::<TimeZoneDataType, _>(ObjectId::TimeZoneDataType_Encoding_DefaultBinary);
+    type_registry.register::<ApplicationDescription, _>(ObjectId::ApplicationDescription_Encoding_DefaultBinary);
+    type_registry.register::<ServiceFault, _>(ObjectId::ServiceFault_Encoding_DefaultBinary);
+    type_registry.register::<FindServersRequest, _>(ObjectId::FindServersRequest_Encoding_DefaultBinary);
+    type_registry.register::<FindServersResponse, _>(ObjectId::FindServersResponse_Encoding_DefaultBinary);
+    type_registry.register::<ServerOnNetwork, _>(ObjectId::ServerOnNetwork_Encoding_DefaultBinary);
+    type_registry.register::<FindServersOnNetworkRequest, _>(ObjectId::FindServersOnNetworkRequest_Encoding_DefaultBinary);
+    type_registry.register::<FindServersOnNetworkResponse, _>(ObjectId::FindServersOnNetworkResponse_Encoding_DefaultBinary);
+    type_registry.register::<UserTokenPolicy, _>(ObjectId::UserTokenPolicy_Encoding_DefaultBinary);
+    type_registry.register::<EndpointDescription, _>(ObjectId::EndpointDescription_Encoding_DefaultBinary);
+    type_registry.register::<GetEndpointsRequest, _>(ObjectId::GetEndpointsRequest_Encoding_DefaultBinary);
+    type_registry.register::<GetEndpointsResponse, _>(ObjectId::GetEndpointsResponse_Encoding_DefaultBinary);
+    type_registry.register::<RegisteredServer, _>(ObjectId::RegisteredServer_Encoding_DefaultBinary);
+    type_registry.register::<RegisterServerRequest, _>(ObjectId::RegisterServerRequest_Encoding_DefaultBinary);
+    type_registry.register::<RegisterServerResponse, _>(ObjectId::RegisterServerResponse_Encoding_DefaultBinary);
+    type_registry.register::<DiscoveryConfiguration, _>(ObjectId::DiscoveryConfiguration_Encoding_DefaultBinary);
+    type_registry.register::<RegisterServer2Request, _>(ObjectId::RegisterServer2Request_Encoding_DefaultBinary);
+    type_registry.register::<RegisterServer2Response, _>(ObjectId::RegisterServer2Response_Encoding_DefaultBinary);
+    type_registry.register::<ChannelSecurityToken, _>(ObjectId::ChannelSecurityToken_Encoding_DefaultBinary);
+    type_registry.register::<OpenSecureChannelRequest, _>(ObjectId::OpenSecureChannelRequest_Encoding_DefaultBinary);
+    type_registry.register::<OpenSecureChannelResponse, _>(ObjectId::OpenSecureChannelResponse_Encoding_DefaultBinary);
+    type_registry.register::<CloseSecureChannelRequest, _>(ObjectId::CloseSecureChannelRequest_Encoding_DefaultBinary);
+    type_registry.register::<CloseSecureChannelResponse, _>(ObjectId::CloseSecureChannelResponse_Encoding_DefaultBinary);
+    type_registry.register::<SignedSoftwareCertificate, _>(ObjectId::SignedSoftwareCertificate_Encoding_DefaultBinary);
+    type_registry.register::<SignatureData, _>(ObjectId::SignatureData_Encoding_DefaultBinary);
+    type_registry.register::<CreateSessionRequest, _>(ObjectId::CreateSessionRequest_Encoding_DefaultBinary);
+    type_registry.register::<CreateSessionResponse, _>(ObjectId::CreateSessionResponse_Encoding_DefaultBinary);
+    type_registry.register::<UserIdentityToken, _>(ObjectId::UserIdentityToken_Encoding_DefaultBinary);
+    type_registry.register::<ActivateSessionRequest, _>(ObjectId::ActivateSessionRequest_Encoding_DefaultBinary);
+    type_registry.register::<ActivateSessionResponse, _>(ObjectId::ActivateSessionResponse_Encoding_DefaultBinary);
+    type_registry.register::<CloseSessionRequest, _>(ObjectId::CloseSessionRequest_Encoding_DefaultBinary);
+    type_registry.register::<CloseSessionResponse, _>(ObjectId::CloseSessionResponse_Encoding_DefaultBinary);
+    type_registry.register::<CancelRequest, _>(ObjectId::CancelRequest_Encoding_DefaultBinary);
+    type_registry.register::<CancelResponse, _>(ObjectId::CancelResponse_Encoding_DefaultBinary);
+    type_registry.register::<NodeAttributes, _>(ObjectId::NodeAttributes_Encoding_DefaultBinary);
+    type_registry.register::<AddNodesItem, _>(ObjectId::AddNodesItem_Encoding_DefaultBinary);
+    type_registry.register::<AddNodesResult, _>(ObjectId::AddNodesResult_Encoding_DefaultBinary);
+    type_registry.register::<AddNodesRequest, _>(ObjectId::AddNodesRequest_Encoding_DefaultBinary);
+    type_registry.register::<AddNodesResponse, _>(ObjectId::AddNodesResponse_Encoding_DefaultBinary);
+    type_registry.register::<AddReferencesItem, _>(ObjectId::AddReferencesItem_Encoding_DefaultBinary);
+    type_registry.register::<AddReferencesRequest, _>(ObjectId::AddReferencesRequest_Encoding_DefaultBinary);
+    type_registry.register::<AddReferencesResponse, _>(ObjectId::AddReferencesResponse_Encoding_DefaultBinary);
+    type_registry.register::<DeleteNodesItem, _>(ObjectId::DeleteNodesItem_Encoding_DefaultBinary);
+    type_registry.register::<DeleteNodesRequest, _>(ObjectId::DeleteNodesRequest_Encoding_DefaultBinary);
+    type_registry.register::<DeleteNodesResponse, _>(ObjectId::DeleteNodesResponse_Encoding_DefaultBinary);
+    type_registry.register::<DeleteReferencesItem, _>(ObjectId::DeleteReferencesItem_Encoding_DefaultBinary);
+    type_registry.register::<DeleteReferencesRequest, _>(ObjectId::DeleteReferencesRequest_Encoding_DefaultBinary);
+    type_registry.register::<DeleteReferencesResponse, _>(ObjectId::DeleteReferencesResponse_Encoding_DefaultBinary);
+    type_registry.register::<ViewDescription, _>(ObjectId::ViewDescription_Encoding_DefaultBinary);
+    type_registry.register::<BrowseDescription, _>(ObjectId::BrowseDescription_Encoding_DefaultBinary);
+    type_registry.register::<ReferenceDescription, _>(ObjectId::ReferenceDescription_Encoding_DefaultBinary);
+    type_registry.register::<BrowseResult, _>(ObjectId::BrowseResult_Encoding_DefaultBinary);
+    type_registry.register::<BrowseRequest, _>(ObjectId::BrowseRequest_Encoding_DefaultBinary);
+    type_registry.register::<BrowseResponse, _>(ObjectId::BrowseResponse_Encoding_DefaultBinary);
+    type_registry.register::<BrowseNextRequest, _>(ObjectId::BrowseNextRequest_Encoding_DefaultBinary);
+    type_registry.register::<BrowseNextResponse, _>(ObjectId::BrowseNextResponse_Encoding_DefaultBinary);
+    type_registry.register::<RelativePathElement, _>(ObjectId::RelativePathElement_Encoding_DefaultBinary);
+    type_registry.register::<RelativePath, _>(ObjectId::RelativePath_Encoding_DefaultBinary);
+    type_registry.register::<BrowsePath, _>(ObjectId::BrowsePath_Encoding_DefaultBinary);
+    type_registry.register::<BrowsePathTarget, _>(ObjectId::BrowsePathTarget_Encoding_DefaultBinary);
+    type_registry.register::<BrowsePathResult, _>(ObjectId::BrowsePathResult_Encoding_DefaultBinary);
+    type_registry.register::<TranslateBrowsePathsToNodeIdsRequest, _>(ObjectId::TranslateBrowsePathsToNodeIdsRequest_Encoding_DefaultBinary);
+    type_registry.register::<TranslateBrowsePathsToNodeIdsResponse, _>(ObjectId::TranslateBrowsePathsToNodeIdsResponse_Encoding_DefaultBinary);
+    type_registry.register::<RegisterNodesRequest, _>(ObjectId::RegisterNodesRequest_Encoding_DefaultBinary);
+    type_registry.register::<RegisterNodesResponse, _>(ObjectId::RegisterNodesResponse_Encoding_DefaultBinary);
+    type_registry.register::<UnregisterNodesRequest, _>(ObjectId::UnregisterNodesRequest_Encoding_DefaultBinary);
+    type_registry.register::<UnregisterNodesResponse, _>(ObjectId::UnregisterNodesResponse_Encoding_DefaultBinary);
+    type_registry.register::<EndpointConfiguration, _>(ObjectId::EndpointConfiguration_Encoding_DefaultBinary);
+    type_registry.register::<QueryDataDescription, _>(ObjectId::QueryDataDescription_Encoding_DefaultBinary);
+    type_registry.register::<NodeTypeDescription, _>(ObjectId::NodeTypeDescription_Encoding_DefaultBinary);
+    type_registry.register::<QueryDataSet, _>(ObjectId::QueryDataSet_Encoding_DefaultBinary);
+    type_registry.register::<NodeReference, _>(ObjectId::NodeReference_Encoding_DefaultBinary);
+    type_registry.register::<ContentFilterElement, _>(ObjectId::ContentFilterElement_Encoding_DefaultBinary);
+    type_registry.register::<ContentFilter, _>(ObjectId::ContentFilter_Encoding_DefaultBinary);
+    type_registry.register::<FilterOperand, _>(ObjectId::FilterOperand_Encoding_DefaultBinary);
+    type_registry.register::<ContentFilterElementResult, _>(ObjectId::ContentFilterElementResult_Encoding_DefaultBinary);
+    type_registry.register::<ContentFilterResult, _>(ObjectId::ContentFilterResult_Encoding_DefaultBinary);
+    type_registry.register::<ParsingResult, _>(ObjectId::ParsingResult_Encoding_DefaultBinary);
+    type_registry.register::<QueryFirstRequest, _>(ObjectId::QueryFirstRequest_Encoding_DefaultBinary);
+    type_registry.register::<QueryFirstResponse, _>(ObjectId::QueryFirstResponse_Encoding_DefaultBinary);
+    type_registry.register::<QueryNextRequest, _>(ObjectId::QueryNextRequest_Encoding_DefaultBinary);
+    type_registry.register::<QueryNextResponse, _>(ObjectId::QueryNextResponse_Encoding_DefaultBinary);
+    type_registry.register::<ReadValueId, _>(ObjectId::ReadValueId_Encoding_DefaultBinary);
+    type_registry.register::<ReadRequest, _>(ObjectId::ReadRequest_Encoding_DefaultBinary);
+    type_registry.register::<ReadResponse, _>(ObjectId::ReadResponse_Encoding_DefaultBinary);
+    type_registry.register::<HistoryReadValueId, _>(ObjectId::HistoryReadValueId_Encoding_DefaultBinary);
+    type_registry.register::<HistoryReadResult, _>(ObjectId::HistoryReadResult_Encoding_DefaultBinary);
+    type_registry.register::<HistoryData, _>(ObjectId::HistoryData_Encoding_DefaultBinary);
+    type_registry.register::<HistoryReadRequest, _>(ObjectId::HistoryReadRequest_Encoding_DefaultBinary);
+    type_registry.register::<HistoryReadResponse, _>(ObjectId::HistoryReadResponse_Encoding_DefaultBinary);
+    type_registry.register::<WriteValue, _>(ObjectId::WriteValue_Encoding_DefaultBinary);
+    type_registry.register::<WriteRequest, _>(ObjectId::WriteRequest_Encoding_DefaultBinary);
+    type_registry.register::<WriteResponse, _>(ObjectId::WriteResponse_Encoding_DefaultBinary);
+    type_registry.register::<HistoryUpdateResult, _>(ObjectId::HistoryUpdateResult_Encoding_DefaultBinary);
+    type_registry.register::<HistoryUpdateRequest, _>(ObjectId::HistoryUpdateRequest_Encoding_DefaultBinary);
+    type_registry.register::<HistoryUpdateResponse, _>(ObjectId::HistoryUpdateResponse_Encoding_DefaultBinary);
+    type_registry.register::<CallMethodRequest, _>(ObjectId::CallMethodRequest_Encoding_DefaultBinary);
+    type_registry.register::<CallMethodResult, _>(ObjectId::CallMethodResult_Encoding_DefaultBinary);
+    type_registry.register::<CallRequest, _>(ObjectId::CallRequest_Encoding_DefaultBinary);
+    type_registry.register::<CallResponse, _>(ObjectId::CallResponse_Encoding_DefaultBinary);
+    type_registry.register::<MonitoringFilter, _>(ObjectId::MonitoringFilter_Encoding_DefaultBinary);
+    type_registry.register::<AggregateConfiguration, _>(ObjectId::AggregateConfiguration_Encoding_DefaultBinary);
+    type_registry.register::<MonitoringFilterResult, _>(ObjectId::MonitoringFilterResult_Encoding_DefaultBinary);
+    type_registry.register::<MonitoringParameters, _>(ObjectId::MonitoringParameters_Encoding_DefaultBinary);
+    type_registry.register::<MonitoredItemCreateRequest, _>(ObjectId::MonitoredItemCreateRequest_Encoding_DefaultBinary);
+    type_registry.register::<MonitoredItemCreateResult, _>(ObjectId::MonitoredItemCreateResult_Encoding_DefaultBinary);
+    type_registry.register::<CreateMonitoredItemsRequest, _>(ObjectId::CreateMonitoredItemsRequest_Encoding_DefaultBinary);
+    type_registry.register::<CreateMonitoredItemsResponse, _>(ObjectId::CreateMonitoredItemsResponse_Encoding_DefaultBinary);
+    type_registry.register::<MonitoredItemModifyRequest, _>(ObjectId::MonitoredItemModifyRequest_Encoding_DefaultBinary);
+    type_registry.register::<MonitoredItemModifyResult, _>(ObjectId::MonitoredItemModifyResult_Encoding_DefaultBinary);
+    type_registry.register::<ModifyMonitoredItemsRequest, _>(ObjectId::ModifyMonitoredItemsRequest_Encoding_DefaultBinary);
+    type_registry.register::<ModifyMonitoredItemsResponse, _>(ObjectId::ModifyMonitoredItemsResponse_Encoding_DefaultBinary);
+    type_registry.register::<SetMonitoringModeRequest, _>(ObjectId::SetMonitoringModeRequest_Encoding_DefaultBinary);
+    type_registry.register::<SetMonitoringModeResponse, _>(ObjectId::SetMonitoringModeResponse_Encoding_DefaultBinary);
+    type_registry.register::<SetTriggeringRequest, _>(ObjectId::SetTriggeringRequest_Encoding_DefaultBinary);
+    type_registry.register::<SetTriggeringResponse, _>(ObjectId::SetTriggeringResponse_Encoding_DefaultBinary);
+    type_registry.register::<DeleteMonitoredItemsRequest, _>(ObjectId::DeleteMonitoredItemsRequest_Encoding_DefaultBinary);
+    type_registry.register::<DeleteMonitoredItemsResponse, _>(ObjectId::DeleteMonitoredItemsResponse_Encoding_DefaultBinary);
+    type_registry.register::<CreateSubscriptionRequest, _>(ObjectId::CreateSubscriptionRequest_Encoding_DefaultBinary);
+    type_registry.register::<CreateSubscriptionResponse, _>(ObjectId::CreateSubscriptionResponse_Encoding_DefaultBinary);
+    type_registry.register::<ModifySubscriptionRequest, _>(ObjectId::ModifySubscriptionRequest_Encoding_DefaultBinary);
+    type_registry.register::<ModifySubscriptionResponse, _>(ObjectId::ModifySubscriptionResponse_Encoding_DefaultBinary);
+    type_registry.register::<SetPublishingModeRequest, _>(ObjectId::SetPublishingModeRequest_Encoding_DefaultBinary);
+    type_registry.register::<SetPublishingModeResponse, _>(ObjectId::SetPublishingModeResponse_Encoding_DefaultBinary);
+    type_registry.register::<NotificationMessage, _>(ObjectId::NotificationMessage_Encoding_DefaultBinary);
+    type_registry.register::<NotificationData, _>(ObjectId::NotificationData_Encoding_DefaultBinary);
+    type_registry.register::<MonitoredItemNotification, _>(ObjectId::MonitoredItemNotification_Encoding_DefaultBinary);
+    type_registry.register::<EventFieldList, _>(ObjectId::EventFieldList_Encoding_DefaultBinary);
+    type_registry.register::<HistoryEventFieldList, _>(ObjectId::HistoryEventFieldList_Encoding_DefaultBinary);
+    type_registry.register::<SubscriptionAcknowledgement, _>(ObjectId::SubscriptionAcknowledgement_Encoding_DefaultBinary);
+    type_registry.register::<PublishRequest, _>(ObjectId::PublishRequest_Encoding_DefaultBinary);
+    type_registry.register::<PublishResponse, _>(ObjectId::PublishResponse_Encoding_DefaultBinary);
+    type_registry.register::<RepublishRequest, _>(ObjectId::RepublishRequest_Encoding_DefaultBinary);
+    type_registry.register::<RepublishResponse, _>(ObjectId::RepublishResponse_Encoding_DefaultBinary);
+    type_registry.register::<TransferResult, _>(ObjectId::TransferResult_Encoding_DefaultBinary);
+    type_registry.register::<TransferSubscriptionsRequest, _>(ObjectId::TransferSubscriptionsRequest_Encoding_DefaultBinary);
+    type_registry.register::<TransferSubscriptionsResponse, _>(ObjectId::TransferSubscriptionsResponse_Encoding_DefaultBinary);
+    type_registry.register::<DeleteSubscriptionsRequest, _>(ObjectId::DeleteSubscriptionsRequest_Encoding_DefaultBinary);
+    type_registry.register::<DeleteSubscriptionsResponse, _>(ObjectId::DeleteSubscriptionsResponse_Encoding_DefaultBinary);
+    type_registry.register::<BuildInfo, _>(ObjectId::BuildInfo_Encoding_DefaultBinary);
+    type_registry.register::<EndpointUrlListDataType, _>(ObjectId::EndpointUrlListDataType_Encoding_DefaultBinary);
+    type_registry.register::<NetworkGroupDataType, _>(ObjectId::NetworkGroupDataType_Encoding_DefaultBinary);
+    type_registry.register::<SamplingIntervalDiagnosticsDataType, _>(ObjectId::SamplingIntervalDiagnosticsDataType_Encoding_DefaultBinary);
+    type_registry.register::<ServerDiagnosticsSummaryDataType, _>(ObjectId::ServerDiagnosticsSummaryDataType_Encoding_DefaultBinary);
+    type_registry.register::<SessionDiagnosticsDataType, _>(ObjectId::SessionDiagnosticsDataType_Encoding_DefaultBinary);
+    type_registry.register::<SessionSecurityDiagnosticsDataType, _>(ObjectId::SessionSecurityDiagnosticsDataType_Encoding_DefaultBinary);
+    type_registry.register::<ServiceCounterDataType, _>(ObjectId::ServiceCounterDataType_Encoding_DefaultBinary);
+    type_registry.register::<StatusResult, _>(ObjectId::StatusResult_Encoding_DefaultBinary);
+    type_registry.register::<SubscriptionDiagnosticsDataType, _>(ObjectId::SubscriptionDiagnosticsDataType_Encoding_DefaultBinary);
+    type_registry.register::<ModelChangeStructureDataType, _>(ObjectId::ModelChangeStructureDataType_Encoding_DefaultBinary);
+    type_registry.register::<Range, _>(ObjectId::Range_Encoding_DefaultBinary);
+    type_registry.register::<EUInformation, _>(ObjectId::EUInformation_Encoding_DefaultBinary);
+    type_registry.register::<ComplexNumberType, _>(ObjectId::ComplexNumberType_Encoding_DefaultBinary);
+    type_registry.register::<DoubleComplexNumberType, _>(ObjectId::DoubleComplexNumberType_Encoding_DefaultBinary);
+    type_registry.register::<XVType, _>(ObjectId::XVType_Encoding_DefaultBinary);
+    type_registry.register::<ProgramDiagnosticDataType, _>(ObjectId::ProgramDiagnosticDataType_Encoding_DefaultBinary);
+    type_registry.register::<Annotation, _>(ObjectId::Annotation_Encoding_DefaultBinary);
+}
@@ -0,0 +1,59 @@
+// This file was autogenerated from Opc.Ua.Types.bsd.xml by tools/schema/gen_types.js
+// DO NOT EDIT THIS FILE
+
+use std::io::{Read, Write};
+
+#[allow(unused_imports)]
+use crate::{
+    encoding::*,
+    basic_types::*,
+    service_types::impls::MessageInfo,
+    node_ids::ObjectId,
+    service_types::impls::ResponseHeader,
+    status_codes::StatusCode,
+    diagnostic_info::DiagnosticInfo,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterServer2Response {
+    pub response_header: ResponseHeader,
+    pub configuration_results: Option<Vec<StatusCode>>,
+    pub diagnostic_infos: Option<Vec<DiagnosticInfo>>,
+}
+
+impl MessageInfo for RegisterServer2Response {
+    fn object_id(&self) -> ObjectId {
+        ObjectId::RegisterServer2Response_Encoding_DefaultBinary
+    }
+}
+
+impl BinaryEncoder<RegisterServer2Response> for RegisterServer2Response {
+    fn byte_len(&self) -> usize {
+        let mut size = 0;
+        size += self.response_header.byte_len();
+        size += byte_len_array(&self.configuration_results);
+        size += byte_len_array(&self.diagnostic_infos);
+        size
+    }
+
+    #[allow(unused_variables)]
+    fn encode<S: Write>(&self, stream: &mut S) -> EncodingResult<usize> {
+        let mut size = 0;
+        size += self.response_header.encode(stream)?;
+        size += write_array(stream, &self.configuration_results)?;
+        size += write_array(stream, &self.diagnostic_infos)?;
+        Ok(size)
+    }
+
+    #[allow(unused_variables)]
+    fn decode<S: Read>(stream: &mut S, decoding_limits: &DecodingLimits) -> EncodingResult<Self> {
+        let response_header = ResponseHeader::decode(stream, decoding_limits)?;
+        let configuration_results: Option<Vec<StatusCode>> = read_array(stream, decoding_limits)?;
+        let diagnostic_infos: Option<Vec<DiagnosticInfo>> = read_array(stream, decoding_limits)?;
+        Ok(RegisterServer2Response {
+            response_header,
+            configuration_results,
+            diagnostic_infos,
+        })
+    }
+}
@@ -93,8 +93,12 @@ impl SupportedMessage {
             SupportedMessage::GetEndpointsResponse(ref r) => r.response_header.request_handle,
             SupportedMessage::FindServersRequest(ref r) => r.request_header.request_handle,
             SupportedMessage::FindServersResponse(ref r) => r.response_header.request_handle,
+            SupportedMessage::FindServersOnNetworkRequest(ref r) => r.request_header.request_handle,
+            SupportedMessage::FindServersOnNetworkResponse(ref r) => r.response_header.request_handle,
             SupportedMessage::RegisterServerRequest(ref r) => r.request_header.request_handle,
             SupportedMessage::RegisterServerResponse(ref r) => r.response_header.request_handle,
+            SupportedMessage::RegisterServer2Request(ref r) => r.request_header.request_handle,
+            SupportedMessage::RegisterServer2Response(ref r) => r.response_header.request_handle,
             SupportedMessage::CreateSessionRequest(ref r) => r.request_header.request_handle,
             SupportedMessage::CreateSessionResponse(ref r) => r.response_header.request_handle,
             SupportedMessage::CloseSessionRequest(ref r) => r.request_header.request_handle,
@@ -145,10 +149,18 @@ impl SupportedMessage {
             SupportedMessage::RegisterNodesResponse(ref r) => r.response_header.request_handle,
             SupportedMessage::UnregisterNodesRequest(ref r) => r.request_header.request_handle,
             SupportedMessage::UnregisterNodesResponse(ref r) => r.response_header.request_handle,
+            SupportedMessage::QueryFirstRequest(ref r) => r.request_header.request_handle,
+            SupportedMessage::QueryFirstResponse(ref r) => r.response_header.request_handle,
+            SupportedMessage::QueryNextRequest(ref r) => r.request_header.request_handle,
+            SupportedMessage::QueryNextResponse(ref r) => r.response_header.request_handle,
             SupportedMessage::ReadRequest(ref r) => r.request_header.request_handle,
             SupportedMessage::ReadResponse(ref r) => r.response_header.request_handle,
             SupportedMessage::WriteRequest(ref r) => r.request_header.request_handle,
             SupportedMessage::WriteResponse(ref r) => r.response_header.request_handle,
+            SupportedMessage::HistoryReadRequest(ref r) => r.request_header.request_handle,
+            SupportedMessage::HistoryReadResponse(ref r) => r.response_header.request_handle,
+            SupportedMessage::HistoryUpdateRequest(ref r) => r.request_header.request_handle,
+            SupportedMessage::HistoryUpdateResponse(ref r) => r.response_header.request_handle,
             SupportedMessage::CallRequest(ref r) => r.request_header.request_handle,
             SupportedMessage::CallResponse(ref r) => r.response_header.request_handle,
         }
@@ -184,12 +196,24 @@ impl SupportedMessage {
             ObjectId::FindServersResponse_Encoding_DefaultBinary => {
                 FindServersResponse::decode(stream, decoding_limits)?.into()
             }
+            ObjectId::FindServersOnNetworkRequest_Encoding_DefaultBinary => {
+                FindServersOnNetworkRequest::decode(stream, decoding_limits)?.into()
+            }
+            ObjectId::FindServersOnNetworkResponse_Encoding_DefaultBinary => {
+                FindServersOnNetworkResponse::decode(stream, decoding_limits)?.into()
+            }
             ObjectId::RegisterServerRequest_Encoding_DefaultBinary => {
                 RegisterServerRequest::decode(stream, decoding_limits)?.into()
             }
             ObjectId::RegisterServerResponse_Encoding_DefaultBinary => {
                 RegisterServerResponse::decode(stream, decoding_limits)?.into()
             }
+            ObjectId::RegisterServer2Request_Encoding_DefaultBinary => {
+                RegisterServer2Request::decode(stream, decoding_limits)?.into()
+            }
+            ObjectId::RegisterServer2Response_Encoding_DefaultBinary => {
+                RegisterServer2Response::decode(stream, decoding_limits)?.into()
+            }
             ObjectId::CreateSessionRequest_Encoding_DefaultBinary => {
                 CreateSessionRequest::decode(stream, decoding_limits)?.into()
             }
@@ -340,6 +364,18 @@ impl SupportedMessage {
             ObjectId::UnregisterNodesResponse_Encoding_DefaultBinary => {
                 UnregisterNodesResponse::decode(stream, decoding_limits)?.into()
             }
+            ObjectId::QueryFirstRequest_Encoding_DefaultBinary => {
+                QueryFirstRequest::decode(stream, decoding_limits)?.into()
+            }
+            ObjectId::QueryFirstResponse_Encoding_DefaultBinary => {
+                QueryFirstResponse::decode(stream, decoding_limits)?.into()
+            }
+            ObjectId::QueryNextRequest_Encoding_DefaultBinary => {
+                QueryNextRequest::decode(stream, decoding_limits)?.into()
+            }
+            ObjectId::QueryNextResponse_Encoding_DefaultBinary => {
+                QueryNextResponse::decode(stream, decoding_limits)?.into()
+            }
             ObjectId::ReadRequest_Encoding_DefaultBinary => {
                 ReadRequest::decode(stream, decoding_limits)?.into()
             }
@@ -352,6 +388,18 @@ impl SupportedMessage {
             ObjectId::WriteResponse_Encoding_DefaultBinary => {
                 WriteResponse::decode(stream, decoding_limits)?.into()
             }
+            ObjectId::HistoryReadRequest_Encoding_DefaultBinary => {
+                HistoryReadRequest::decode(stream, decoding_limits)?.into()
+            }
+            ObjectId::HistoryReadResponse_Encoding_DefaultBinary => {
+                HistoryReadResponse::decode(stream, decoding_limits)?.into()
+            }
+            ObjectId::HistoryUpdateRequest_Encoding_DefaultBinary => {
+                HistoryUpdateRequest::decode(stream, decoding_limits)?.into()
+            }
+            ObjectId::HistoryUpdateResponse_Encoding_DefaultBinary => {
+                HistoryUpdateResponse::decode(stream, decoding_limits)?.into()
+            }
             ObjectId::CallRequest_Encoding_DefaultBinary => {
                 CallRequest::decode(stream, decoding_limits)?.into()
             }
@@ -379,8 +427,12 @@ supported_messages_enum![
     GetEndpointsResponse,
     FindServersRequest,
     FindServersResponse,
+    FindServersOnNetworkRequest,
+    FindServersOnNetworkResponse,
     RegisterServerRequest,
     RegisterServerResponse,
+    RegisterServer2Request,
+    RegisterServer2Response,
     CreateSessionRequest,
     CreateSessionResponse,
     CloseSessionRequest,
@@ -431,10 +483,18 @@ supported_messages_enum![
     RegisterNodesResponse,
     UnregisterNodesRequest,
     UnregisterNodesResponse,
+    QueryFirstRequest,
+    QueryFirstResponse,
+    QueryNextRequest,
+    QueryNextResponse,
     ReadRequest,
     ReadResponse,
     WriteRequest,
     WriteResponse,
+    HistoryReadRequest,
+    HistoryReadResponse,
+    HistoryUpdateRequest,
+    HistoryUpdateResponse,
     CallRequest,
     CallResponse,
 ];
@@ -17,6 +17,7 @@ pub const CLOSE_SECURE_CHANNEL_MESSAGE: &[u8] = b"CLO";
 const HELLO_MESSAGE: &[u8] = b"HEL";
 const ACKNOWLEDGE_MESSAGE: &[u8] = b"ACK";
 const ERROR_MESSAGE: &[u8] = b"ERR";
+const REVERSE_HELLO_MESSAGE: &[u8] = b"RHE";
 
 pub const CHUNK_FINAL: u8 = b'F';
 pub const CHUNK_INTERMEDIATE: u8 = b'C';
@@ -38,6 +39,7 @@ pub enum MessageType {
     Acknowledge,
     Chunk,
     Error,
+    ReverseHello,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -57,6 +59,7 @@ impl BinaryEncoder<MessageHeader> for MessageHeader {
             MessageType::Hello => stream.write(HELLO_MESSAGE),
             MessageType::Acknowledge => stream.write(ACKNOWLEDGE_MESSAGE),
             MessageType::Error => stream.write(ERROR_MESSAGE),
+            MessageType::ReverseHello => stream.write(REVERSE_HELLO_MESSAGE),
             MessageType::Chunk => {
                 panic!("Don't write chunks to stream with this call, use Chunk and Chunker");
             }
@@ -133,6 +136,7 @@ impl MessageHeader {
                 HELLO_MESSAGE => MessageType::Hello,
                 ACKNOWLEDGE_MESSAGE => MessageType::Acknowledge,
                 ERROR_MESSAGE => MessageType::Error,
+                REVERSE_HELLO_MESSAGE => MessageType::ReverseHello,
                 CHUNK_MESSAGE | OPEN_SECURE_CHANNEL_MESSAGE | CLOSE_SECURE_CHANNEL_MESSAGE => MessageType::Chunk,
                 _ => {
                     error!("message type doesn't match anything");
@@ -350,3 +354,58 @@ impl ErrorMessage {
         error
     }
 }
+
+/// Implementation of the RHE message in OPC UA. A server sends it on a connection it initiates to
+/// a client, after which the client proceeds as if it had connected, starting with HEL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReverseHelloMessage {
+    pub message_header: MessageHeader,
+    pub server_uri: UAString,
+    pub endpoint_url: UAString,
+}
+
+impl BinaryEncoder<ReverseHelloMessage> for ReverseHelloMessage {
+    fn byte_len(&self) -> usize {
+        self.message_header.byte_len() + self.server_uri.byte_len() + self.endpoint_url.byte_len()
+    }
+
+    fn encode<S: Write>(&self, stream: &mut S) -> EncodingResult<usize> {
+        let mut size: usize = 0;
+        size += self.message_header.encode(stream)?;
+        size += self.server_uri.encode(stream)?;
+        size += self.endpoint_url.encode(stream)?;
+        Ok(size)
+    }
+
+    fn decode<S: Read>(stream: &mut S, decoding_limits: &DecodingLimits) -> EncodingResult<Self> {
+        let message_header = MessageHeader::decode(stream, decoding_limits)?;
+        let server_uri = UAString::decode(stream, decoding_limits)?;
+        let endpoint_url = UAString::decode(stream, decoding_limits)?;
+        Ok(ReverseHelloMessage {
+            message_header,
+            server_uri,
+            endpoint_url,
+        })
+    }
+}
+
+impl ReverseHelloMessage {
+    /// Creates a RHE message
+    pub fn new(server_uri: &str, endpoint_url: &str) -> ReverseHelloMessage {
+        let mut msg = ReverseHelloMessage {
+            message_header: MessageHeader::new(MessageType::ReverseHello),
+            server_uri: UAString::from(server_uri),
+            endpoint_url: UAString::from(endpoint_url),
+        };
+        msg.message_header.message_size = msg.byte_len() as u32;
+        msg
+    }
+
+    /// Tests if the server uri and endpoint url are within the limits set by part 6, which are
+    /// 4096 bytes for each.
+    pub fn is_valid(&self) -> bool {
+        const MAX_LEN: usize = 4096;
+        let valid_len = |s: &UAString| s.value.as_ref().map(|s| !s.is_empty() && s.len() <= MAX_LEN).unwrap_or(false);
+        valid_len(&self.server_uri) && valid_len(&self.endpoint_url)
+    }
+}
@@ -234,6 +234,46 @@ fn extension_object() {
     serialize_test(eo);
 }
 
+#[test]
+fn extension_object_decode_object() {
+    use crate::service_types::{EUInformation, Range};
+    use crate::type_registry::TypeRegistry;
+
+    let decoding_limits = DecodingLimits::default();
+    let eu_information = EUInformation {
+        namespace_uri: UAString::from("http://www.opcfoundation.org/UA/units/un/cefact"),
+        unit_id: 4408652,
+        // An empty locale is not encoded, so it is null after a round trip
+        display_name: LocalizedText { locale: UAString::null(), text: UAString::from("°C") },
+        description: LocalizedText { locale: UAString::null(), text: UAString::from("degree Celsius") },
+    };
+    let eo = ExtensionObject::from_encodable(ObjectId::EUInformation_Encoding_DefaultBinary, &eu_information);
+
+    // The generated type is found from the encoding id
+    let decoded = eo.decode_object(&decoding_limits).unwrap();
+    assert!(decoded.is::<EUInformation>());
+    assert!(decoded.downcast_ref::<Range>().is_none());
+    assert_eq!(decoded.downcast_ref::<EUInformation>().unwrap(), &eu_information);
+    assert_eq!(*decoded.downcast::<EUInformation>().unwrap(), eu_information);
+
+    // Unknown encoding ids and XML bodies cannot be decoded
+    let eo = ExtensionObject::from_encodable(NodeId::new(2, "Custom"), &eu_information);
+    assert_eq!(eo.decode_object(&decoding_limits).unwrap_err(), StatusCode::BadDataTypeIdUnknown);
+    let eo = ExtensionObject {
+        node_id: ObjectId::EUInformation_Encoding_DefaultBinary.into(),
+        body: ExtensionObjectEncoding::XmlElement(XmlElement::from("<EUInformation/>")),
+    };
+    assert_eq!(eo.decode_object(&decoding_limits).unwrap_err(), StatusCode::BadDataEncodingUnsupported);
+
+    // Types can be registered with their own encoding ids
+    let mut type_registry = TypeRegistry::new();
+    assert!(!type_registry.is_registered(&NodeId::new(2, "Custom")));
+    type_registry.register::<EUInformation, _>(NodeId::new(2, "Custom"));
+    let eo = ExtensionObject::from_encodable(NodeId::new(2, "Custom"), &eu_information);
+    let decoded = type_registry.decode(&eo, &decoding_limits).unwrap();
+    assert_eq!(decoded.downcast_ref::<EUInformation>().unwrap(), &eu_information);
+}
+
 #[test]
 fn localized_text() {
     let t = LocalizedText {
@@ -395,6 +435,66 @@ fn variant_multi_dimension_array() {
     serialize_test(v);
 }
 
+#[test]
+fn variant_matrix() {
+    let v = Variant::from_matrix(vec![vec![1.0f64, 2.0, 3.0], vec![4.0, 5.0, 6.0]]).unwrap();
+    assert!(v.is_valid());
+    assert!(v.is_array_of_type(VariantTypeId::Double));
+    assert_eq!(v.value_rank(), 2);
+    assert_eq!(v.array_dimensions(), Some(vec![2, 3]));
+    let v = serialize_test_and_return(v);
+    if let Variant::MultiDimensionArray(mda) = v {
+        assert_eq!(mda.dimensions, vec![2, 3]);
+        assert_eq!(mda.get(&[0, 0]), Some(&Variant::Double(1.0)));
+        assert_eq!(mda.get(&[0, 2]), Some(&Variant::Double(3.0)));
+        assert_eq!(mda.get(&[1, 0]), Some(&Variant::Double(4.0)));
+        assert_eq!(mda.get(&[2, 0]), None);
+        assert_eq!(mda.get(&[0, 3]), None);
+        assert_eq!(mda.get(&[0]), None);
+    } else {
+        panic!("Not a multi dimensional array");
+    }
+
+    // Rows must be the same length
+    assert!(Variant::from_matrix(vec![vec![1i32, 2], vec![3]]).is_err());
+
+    assert_eq!(Variant::from(1i32).value_rank(), -1);
+    assert_eq!(Variant::from(1i32).array_dimensions(), None);
+    assert_eq!(Variant::from(vec![1i32, 2]).value_rank(), 1);
+    assert_eq!(Variant::from(vec![1i32, 2]).array_dimensions(), Some(vec![2]));
+
+    // An empty array and an empty matrix
+    serialize_test(Variant::Array(Vec::new()));
+    serialize_test(Variant::from_matrix::<i32>(vec![vec![], vec![]]).unwrap());
+}
+
+#[test]
+fn variant_array_dimensions_invalid() {
+    let decoding_limits = DecodingLimits::default();
+
+    // Dimensions which do not match the number of values
+    let mut stream = Cursor::new(Vec::new());
+    let _ = Variant::new_multi_dimension_array(vec![Variant::Int32(1), Variant::Int32(2)], vec![2]).encode(&mut stream);
+    let mut bytes = stream.into_inner();
+    let len = bytes.len();
+    bytes[len - 4] = 3;
+    assert_eq!(Variant::decode(&mut Cursor::new(bytes), &decoding_limits).unwrap_err(), StatusCode::BadDecodingError);
+
+    // Dimensions whose product overflows
+    let mut stream = Cursor::new(Vec::new());
+    let _ = Variant::new_multi_dimension_array(vec![Variant::Int32(1)], vec![1, 1, 1, 1]).encode(&mut stream);
+    let mut bytes = stream.into_inner();
+    let len = bytes.len();
+    for i in 0..4 {
+        bytes[len - 4 - i * 4..len - i * 4].copy_from_slice(&[0xff, 0xff, 0xff, 0x7f]);
+    }
+    assert_eq!(Variant::decode(&mut Cursor::new(bytes), &decoding_limits).unwrap_err(), StatusCode::BadDecodingError);
+
+    // A null array decodes as an empty array
+    let bytes = vec![DataTypeId::Int32 as u8 | 0x80, 0xff, 0xff, 0xff, 0xff];
+    assert_eq!(Variant::decode(&mut Cursor::new(bytes), &decoding_limits).unwrap(), Variant::Array(Vec::new()));
+}
+
 #[test]
 fn diagnostic_info() {
     let mut d = DiagnosticInfo {
@@ -452,3 +552,10 @@ fn argument() {
         description: LocalizedText::new("foo", "bar"),
     });
 }
+
+#[test]
+fn role_permission_and_identity_mapping_rule() {
+    serialize_test(RolePermissionType::new(WellKnownRole::Operator, PermissionType::BROWSE | PermissionType::READ | PermissionType::CALL));
+    serialize_test(IdentityMappingRuleType::new(IdentityCriteriaType::UserName, "operator"));
+    serialize_test(IdentityMappingRuleType::new(IdentityCriteriaType::Anonymous, UAString::null()));
+}
@@ -1,4 +1,14 @@
-use crate::supported_message::SupportedMessage;
+use std::io::Cursor;
+
+use crate::{
+    encoding::{BinaryEncoder, DecodingLimits},
+    date_time::DateTime,
+    node_id::NodeId,
+    node_ids::ObjectId,
+    service_types::*,
+    string::UAString,
+    supported_message::SupportedMessage,
+};
 
 #[test]
 fn size() {
@@ -8,3 +18,42 @@ fn size() {
     println!("SupportedMessage size = {}", size);
     assert!(size <= 16);
 }
+
+#[test]
+fn decode_find_servers_on_network() {
+    let request_header = RequestHeader::new(&NodeId::null(), &DateTime::now(), 5);
+    let request = FindServersOnNetworkRequest {
+        request_header: request_header.clone(),
+        starting_record_id: 10,
+        max_records_to_return: 20,
+        server_capability_filter: Some(vec![UAString::from("DA")]),
+    };
+    let response = FindServersOnNetworkResponse {
+        response_header: ResponseHeader::new_good(&request_header),
+        last_counter_reset_time: DateTime::now(),
+        servers: Some(vec![ServerOnNetwork {
+            record_id: 11,
+            server_name: UAString::from("Server"),
+            discovery_url: UAString::from("opc.tcp://localhost:4855/"),
+            server_capabilities: Some(vec![UAString::from("DA")]),
+        }]),
+    };
+
+    // Messages are decoded from their object ids into the matching supported message
+    let decoded = encode_and_decode(&request, ObjectId::FindServersOnNetworkRequest_Encoding_DefaultBinary);
+    assert_eq!(decoded.request_handle(), 5);
+    let expected: SupportedMessage = request.into();
+    assert_eq!(decoded, expected);
+
+    let decoded = encode_and_decode(&response, ObjectId::FindServersOnNetworkResponse_Encoding_DefaultBinary);
+    assert_eq!(decoded.request_handle(), 5);
+    let expected: SupportedMessage = response.into();
+    assert_eq!(decoded, expected);
+}
+
+fn encode_and_decode<T>(message: &T, object_id: ObjectId) -> SupportedMessage where T: BinaryEncoder<T> {
+    let mut stream = Cursor::new(Vec::with_capacity(message.byte_len()));
+    message.encode(&mut stream).unwrap();
+    stream.set_position(0);
+    SupportedMessage::decode_by_object_id(&mut stream, object_id, &DecodingLimits::default()).unwrap()
+}
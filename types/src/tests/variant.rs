@@ -1,6 +1,9 @@
 use std::convert::TryFrom;
 
-use crate::variant::{Variant, VariantTypeId, MultiDimensionArray};
+use crate::{
+    string::UAString,
+    variant::{Variant, VariantTypeId, MultiDimensionArray},
+};
 
 #[test]
 fn is_numeric() {
@@ -106,6 +109,18 @@ fn variant_try_into_u32_array() {
     assert_eq!(result.len(), 3);
 }
 
+#[test]
+fn variant_try_into_scalar() {
+    assert_eq!(u32::try_from(&Variant::UInt32(10)), Ok(10u32));
+    assert_eq!(f64::try_from(&Variant::Double(1.5)), Ok(1.5f64));
+    assert_eq!(bool::try_from(&Variant::Boolean(true)), Ok(true));
+    assert_eq!(UAString::try_from(&Variant::from("hello")), Ok(UAString::from("hello")));
+    // No implicit numeric conversions
+    assert!(u32::try_from(&Variant::Int32(10)).is_err());
+    assert!(f64::try_from(&Variant::Float(1.5)).is_err());
+    assert!(bool::try_from(&Variant::Empty).is_err());
+}
+
 #[test]
 fn variant_i32_array() {
     let vars = [1, 2, 3];
@@ -163,4 +178,126 @@ fn variant_multi_dimensional_array() {
     assert!(v.is_array());
     assert!(v.is_array_of_type(VariantTypeId::Int32));
     assert!(!v.is_valid());
-}
\ No newline at end of file
+}
+
+#[test]
+fn variant_to_boxed_scalar() {
+    use crate::{basic_types::{LocalizedText, QualifiedName}, node_id::NodeId};
+
+    let v = Variant::from(LocalizedText::new("en", "Temperature"));
+    assert_eq!(LocalizedText::try_from(&v).unwrap(), LocalizedText::new("en", "Temperature"));
+    assert!(QualifiedName::try_from(&v).is_err());
+
+    let v = Variant::from(QualifiedName::new(2, "Temperature"));
+    assert_eq!(QualifiedName::try_from(&v).unwrap(), QualifiedName::new(2, "Temperature"));
+
+    let v = Variant::from(NodeId::new(2, "Temperature"));
+    assert_eq!(NodeId::try_from(&v).unwrap(), NodeId::new(2, "Temperature"));
+    assert!(NodeId::try_from(&Variant::Empty).is_err());
+}
+
+#[test]
+fn variant_range_of() {
+    use std::str::FromStr;
+    use crate::{byte_string::ByteString, numeric_range::NumericRange, status_codes::StatusCode};
+
+    let range = |s: &str| NumericRange::from_str(s).unwrap();
+
+    let v = Variant::from(vec![1i32, 2, 3, 4, 5]);
+    assert_eq!(v.range_of(&range("1")).unwrap(), Variant::from(vec![2i32]));
+    assert_eq!(v.range_of(&range("1:3")).unwrap(), Variant::from(vec![2i32, 3, 4]));
+    // Ranges past the end are trimmed
+    assert_eq!(v.range_of(&range("3:10")).unwrap(), Variant::from(vec![4i32, 5]));
+    assert_eq!(v.range_of(&range("5:10")).unwrap_err(), StatusCode::BadIndexRangeNoData);
+    assert_eq!(v.range_of(&range("1,1")).unwrap_err(), StatusCode::BadIndexRangeNoData);
+    assert_eq!(Variant::from(1i32).range_of(&range("0")).unwrap_err(), StatusCode::BadIndexRangeNoData);
+
+    // Strings and byte strings are ranges of characters and bytes
+    assert_eq!(Variant::from("Hello").range_of(&range("1:3")).unwrap(), Variant::from("ell"));
+    assert_eq!(Variant::from(ByteString::from(&[1u8, 2, 3])).range_of(&range("2")).unwrap(), Variant::from(ByteString::from(&[3u8])));
+
+    let v = Variant::from_matrix(vec![vec![1i32, 2, 3], vec![4, 5, 6]]).unwrap();
+    assert_eq!(v.range_of(&range("0:1,1:2")).unwrap(), Variant::from_matrix(vec![vec![2i32, 3], vec![5, 6]]).unwrap());
+    assert_eq!(v.range_of(&range("1,0")).unwrap(), Variant::from_matrix(vec![vec![4i32]]).unwrap());
+    assert_eq!(v.range_of(&range("1")).unwrap_err(), StatusCode::BadIndexRangeNoData);
+
+    assert!(range("1:3").is_valid_for_array(&Variant::from(vec![1i32, 2, 3, 4])));
+    assert!(!range("1:4").is_valid_for_array(&Variant::from(vec![1i32, 2, 3, 4])));
+    assert!(range("1,2").is_valid_for_array(&v));
+    assert!(!range("2,0").is_valid_for_array(&v));
+}
+
+#[test]
+fn variant_set_range_of() {
+    use std::str::FromStr;
+    use crate::{numeric_range::NumericRange, status_codes::StatusCode};
+
+    let range = |s: &str| NumericRange::from_str(s).unwrap();
+
+    let mut v = Variant::from(vec![1i32, 2, 3, 4, 5]);
+    v.set_range_of(&range("1:2"), &Variant::from(vec![20i32, 30])).unwrap();
+    assert_eq!(v, Variant::from(vec![1i32, 20, 30, 4, 5]));
+    v.set_range_of(&range("4"), &Variant::from(vec![50i32])).unwrap();
+    assert_eq!(v, Variant::from(vec![1i32, 20, 30, 4, 50]));
+
+    // The range must lie within the array and the value must fill it with the same type
+    assert_eq!(v.set_range_of(&range("4:5"), &Variant::from(vec![1i32, 2])).unwrap_err(), StatusCode::BadIndexRangeNoData);
+    assert_eq!(v.set_range_of(&range("0:2"), &Variant::from(vec![1i32, 2])).unwrap_err(), StatusCode::BadIndexRangeInvalid);
+    assert_eq!(v.set_range_of(&range("0:1"), &Variant::from(vec![1u8, 2])).unwrap_err(), StatusCode::BadTypeMismatch);
+    assert_eq!(v.set_range_of(&range("0"), &Variant::from(1i32)).unwrap_err(), StatusCode::BadTypeMismatch);
+    assert_eq!(v, Variant::from(vec![1i32, 20, 30, 4, 50]));
+
+    let mut v = Variant::from("Hello");
+    v.set_range_of(&range("0:1"), &Variant::from("Je")).unwrap();
+    assert_eq!(v, Variant::from("Jello"));
+
+    let mut v = Variant::from_matrix(vec![vec![1i32, 2, 3], vec![4, 5, 6]]).unwrap();
+    v.set_range_of(&range("0:1,2"), &Variant::from_matrix(vec![vec![30i32], vec![60]]).unwrap()).unwrap();
+    assert_eq!(v, Variant::from_matrix(vec![vec![1i32, 2, 30], vec![4, 5, 60]]).unwrap());
+}
+
+#[test]
+fn from_variant_numeric() {
+    use crate::{status_codes::StatusCode, variant_conversion::FromVariant};
+
+    // Integers convert to any integer type they fit in
+    assert_eq!(i32::from_variant(&Variant::from(-5i16)), Ok(-5i32));
+    assert_eq!(u8::from_variant(&Variant::from(255i64)), Ok(255u8));
+    assert_eq!(u64::from_variant(&Variant::from(7u32)), Ok(7u64));
+    assert_eq!(u8::from_variant(&Variant::from(256i64)), Err(StatusCode::BadOutOfRange));
+    assert_eq!(u32::from_variant(&Variant::from(-1i32)), Err(StatusCode::BadOutOfRange));
+    assert_eq!(i64::from_variant(&Variant::from(u64::max_value())), Err(StatusCode::BadOutOfRange));
+
+    // Integers and floats convert to floats, floats never convert to integers
+    assert_eq!(f64::from_variant(&Variant::from(42i32)), Ok(42f64));
+    assert_eq!(f64::from_variant(&Variant::from(2.5f32)), Ok(2.5f64));
+    assert_eq!(f32::from_variant(&Variant::from(42.5f64)), Ok(42.5f32));
+    assert_eq!(f32::from_variant(&Variant::from(1e300f64)), Err(StatusCode::BadOutOfRange));
+    assert_eq!(i32::from_variant(&Variant::from(42f64)), Err(StatusCode::BadTypeMismatch));
+
+    // Other types do not convert to numbers
+    assert_eq!(f64::from_variant(&Variant::from(true)), Err(StatusCode::BadTypeMismatch));
+    assert_eq!(i32::from_variant(&Variant::from("42")), Err(StatusCode::BadTypeMismatch));
+    assert_eq!(f64::from_variant(&Variant::Empty), Err(StatusCode::BadTypeMismatch));
+}
+
+#[test]
+fn from_variant_other() {
+    use crate::{status_codes::StatusCode, variant_conversion::FromVariant};
+
+    assert_eq!(bool::from_variant(&Variant::from(true)), Ok(true));
+    assert_eq!(bool::from_variant(&Variant::from(1u8)), Err(StatusCode::BadTypeMismatch));
+    assert_eq!(String::from_variant(&Variant::from("hello")), Ok("hello".to_string()));
+    assert_eq!(UAString::from_variant(&Variant::from("hello")), Ok(UAString::from("hello")));
+    assert_eq!(String::from_variant(&Variant::from(1i32)), Err(StatusCode::BadTypeMismatch));
+
+    // Options convert from empty variants
+    assert_eq!(Option::<f64>::from_variant(&Variant::Empty), Ok(None));
+    assert_eq!(Option::<f64>::from_variant(&Variant::from(3i8)), Ok(Some(3f64)));
+
+    // Vecs convert from arrays whose values all convert
+    let array = Variant::from(vec![1i16, 2i16, 3i16]);
+    assert_eq!(Vec::<f64>::from_variant(&array), Ok(vec![1f64, 2f64, 3f64]));
+    assert_eq!(Vec::<u8>::from_variant(&Variant::from(vec![1i32, -1i32])), Err(StatusCode::BadOutOfRange));
+    assert_eq!(Vec::<u8>::from_variant(&Variant::from(1i32)), Err(StatusCode::BadTypeMismatch));
+}
@@ -0,0 +1,128 @@
+//! Contains the `TypeRegistry` which decodes the body of an `ExtensionObject` into the concrete type
+//! identified by its binary encoding id.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Cursor;
+use std::sync::RwLock;
+
+use crate::{
+    encoding::*,
+    extension_object::{ExtensionObject, ExtensionObjectEncoding},
+    node_id::NodeId,
+    service_types,
+    status_codes::StatusCode,
+};
+
+lazy_static! {
+    /// The type registry used by `ExtensionObject::decode_object()`, which holds the generated
+    /// types. Applications may register their own types with it.
+    pub static ref TYPE_REGISTRY: RwLock<TypeRegistry> = RwLock::new(TypeRegistry::default());
+}
+
+/// A value decoded from an extension object. The concrete type can be obtained with `is()`,
+/// `downcast_ref()` or `downcast()`.
+pub trait DecodedObject: Any + fmt::Debug + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T> DecodedObject for T where T: Any + fmt::Debug + Send + Sync {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl dyn DecodedObject {
+    /// Tests if the decoded object is of type `T`
+    pub fn is<T>(&self) -> bool where T: Any {
+        self.as_any().is::<T>()
+    }
+
+    /// Returns a reference to the decoded object if it is of type `T`
+    pub fn downcast_ref<T>(&self) -> Option<&T> where T: Any {
+        self.as_any().downcast_ref::<T>()
+    }
+
+    /// Returns the decoded object if it is of type `T`
+    pub fn downcast<T>(self: Box<Self>) -> Option<Box<T>> where T: Any {
+        self.into_any().downcast::<T>().ok()
+    }
+}
+
+type DecodeFn = fn(&[u8], &DecodingLimits) -> EncodingResult<Box<dyn DecodedObject>>;
+
+fn decode_type<T>(data: &[u8], decoding_limits: &DecodingLimits) -> EncodingResult<Box<dyn DecodedObject>>
+    where T: BinaryEncoder<T> + DecodedObject
+{
+    let mut stream = Cursor::new(data);
+    Ok(Box::new(T::decode(&mut stream, decoding_limits)?))
+}
+
+/// Maps the binary encoding ids of types to the functions which decode them
+pub struct TypeRegistry {
+    decoders: HashMap<NodeId, DecodeFn>,
+}
+
+impl Default for TypeRegistry {
+    /// Creates a registry holding the generated types that can be encoded in an extension object
+    fn default() -> Self {
+        let mut type_registry = TypeRegistry::new();
+        service_types::register_types(&mut type_registry);
+        type_registry
+    }
+}
+
+impl TypeRegistry {
+    /// Creates an empty registry
+    pub fn new() -> TypeRegistry {
+        TypeRegistry {
+            decoders: HashMap::new(),
+        }
+    }
+
+    /// Registers type `T` as the type of extension objects with the binary encoding id. A type
+    /// already registered with the id is replaced.
+    pub fn register<T, N>(&mut self, encoding_id: N) where T: BinaryEncoder<T> + DecodedObject, N: Into<NodeId> {
+        self.decoders.insert(encoding_id.into(), decode_type::<T>);
+    }
+
+    /// Tests if a type is registered with the binary encoding id
+    pub fn is_registered(&self, encoding_id: &NodeId) -> bool {
+        self.decoders.contains_key(encoding_id)
+    }
+
+    /// Decodes the body of the extension object into the type registered with its node id. An
+    /// unregistered node id is `BadDataTypeIdUnknown` and a body which is not binary encoded is
+    /// `BadDataEncodingUnsupported`.
+    pub fn decode(&self, extension_object: &ExtensionObject, decoding_limits: &DecodingLimits) -> EncodingResult<Box<dyn DecodedObject>> {
+        if let Some(decode) = self.decoders.get(&extension_object.node_id) {
+            match extension_object.body {
+                ExtensionObjectEncoding::ByteString(ref byte_string) => {
+                    if let Some(ref value) = byte_string.value {
+                        decode(value.as_slice(), decoding_limits)
+                    } else {
+                        Err(StatusCode::BadDecodingError)
+                    }
+                }
+                ExtensionObjectEncoding::None => {
+                    error!("Extension object {:?} has no body to decode", extension_object.node_id);
+                    Err(StatusCode::BadDecodingError)
+                }
+                ExtensionObjectEncoding::XmlElement(_) => {
+                    error!("Extension object {:?} has an XML body which cannot be decoded", extension_object.node_id);
+                    Err(StatusCode::BadDataEncodingUnsupported)
+                }
+            }
+        } else {
+            debug!("No type is registered for extension object {:?}", extension_object.node_id);
+            Err(StatusCode::BadDataTypeIdUnknown)
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Contains `UaStructure`, the trait of structures that applications define in Rust, which is
+//! implemented with `#[derive(UaStructure)]` from the `opcua-types-derive` crate.
+
+use crate::{
+    encoding::*,
+    extension_object::ExtensionObject,
+    node_id::NodeId,
+    status_codes::StatusCode,
+    type_registry::{DecodedObject, TypeRegistry},
+};
+
+/// A structure defined by an application, which is encoded in an `ExtensionObject` whose node id is
+/// its binary encoding id. The data type of the structure is a subtype of `Structure`.
+pub trait UaStructure: BinaryEncoder<Self> + Sized {
+    /// Returns the name of the structure, which is the browse name of its data type
+    fn type_name() -> &'static str;
+
+    /// Returns the node id of the data type of the structure
+    fn data_type_id() -> NodeId;
+
+    /// Returns the node id of the `Default Binary` encoding of the structure
+    fn binary_encoding_id() -> NodeId;
+
+    /// Encodes the structure in an extension object
+    fn to_extension_object(&self) -> ExtensionObject {
+        ExtensionObject::from_encodable(Self::binary_encoding_id(), self)
+    }
+
+    /// Decodes the structure from an extension object, which must have the binary encoding id of
+    /// the structure or the result is `BadDataTypeIdUnknown`.
+    fn from_extension_object(extension_object: &ExtensionObject, decoding_limits: &DecodingLimits) -> EncodingResult<Self> {
+        if extension_object.node_id != Self::binary_encoding_id() {
+            error!("Extension object {:?} does not hold a {}", extension_object.node_id, Self::type_name());
+            Err(StatusCode::BadDataTypeIdUnknown)
+        } else {
+            extension_object.decode_inner(decoding_limits)
+        }
+    }
+
+    /// Registers the structure with a type registry so that `ExtensionObject::decode_object()`
+    /// decodes extension objects holding it.
+    fn register(type_registry: &mut TypeRegistry) where Self: DecodedObject {
+        type_registry.register::<Self, _>(Self::binary_encoding_id());
+    }
+}
@@ -7,6 +7,10 @@ use ::url::Url;
 use crate::constants::DEFAULT_OPC_UA_SERVER_PORT;
 
 pub const OPC_TCP_SCHEME: &str = "opc.tcp";
+/// Scheme of the experimental transport which wraps the binary protocol inside TLS
+pub const OPC_TLS_SCHEME: &str = "opc.tls";
+/// Scheme of the experimental transport which sends binary encoded requests as HTTPS POSTs
+pub const OPC_HTTPS_SCHEME: &str = "opc.https";
 
 fn opc_url_from_str(s: &str) -> Result<Url, ()> {
     let url = Url::parse(s);
@@ -92,6 +96,24 @@ pub fn is_opc_ua_binary_url(url: &str) -> bool {
     }
 }
 
+/// Test if the url is for the experimental opc.tls transport
+pub fn is_opc_ua_tls_url(url: &str) -> bool {
+    if let Ok(url) = opc_url_from_str(url) {
+        url.scheme() == OPC_TLS_SCHEME
+    } else {
+        false
+    }
+}
+
+/// Test if the url is for the experimental opc.https transport
+pub fn is_opc_ua_https_url(url: &str) -> bool {
+    if let Ok(url) = opc_url_from_str(url) {
+        url.scheme() == OPC_HTTPS_SCHEME
+    } else {
+        false
+    }
+}
+
 pub fn hostname_from_url(url: &str) -> Result<String, ()> {
     // Validate and split out the endpoint we have
     if let Ok(url) = Url::parse(url) {
@@ -105,6 +127,16 @@ pub fn hostname_from_url(url: &str) -> Result<String, ()> {
     }
 }
 
+/// Returns the hostname and port of the url, which is the default OPC UA port if the url has none
+pub fn hostname_port_from_url(url: &str) -> Result<(String, u16), ()> {
+    let url = opc_url_from_str(url)?;
+    if let (Some(host), Some(port)) = (url.host_str(), url.port()) {
+        Ok((host.to_string(), port))
+    } else {
+        Err(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -113,6 +145,18 @@ mod tests {
     fn url_scheme() {
         assert!(is_opc_ua_binary_url("opc.tcp://foo/xyz"));
         assert!(!is_opc_ua_binary_url("http://foo/xyz"));
+        assert!(!is_opc_ua_binary_url("opc.tls://foo/xyz"));
+        assert!(is_opc_ua_tls_url("opc.tls://foo/xyz"));
+        assert!(!is_opc_ua_tls_url("opc.tcp://foo/xyz"));
+        assert!(is_opc_ua_https_url("opc.https://foo/xyz"));
+        assert!(!is_opc_ua_https_url("https://foo/xyz"));
+    }
+
+    #[test]
+    fn url_hostname_port() {
+        assert_eq!(hostname_port_from_url("opc.tcp://foo:1234/xyz").unwrap(), ("foo".to_string(), 1234));
+        assert_eq!(hostname_port_from_url("opc.tcp://foo/xyz").unwrap(), ("foo".to_string(), DEFAULT_OPC_UA_SERVER_PORT));
+        assert!(hostname_port_from_url("xyz").is_err());
     }
 
     #[test]
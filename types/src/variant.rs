@@ -1,5 +1,6 @@
 //! Contains the implementation of `Variant`.
 
+use std::cmp;
 use std::io::{Read, Write};
 use std::convert::TryFrom;
 
@@ -7,13 +8,13 @@ use crate::{
     basic_types::*,
     extension_object::ExtensionObject,
     byte_string::ByteString,
-    constants,
     data_value::DataValue,
     date_time::DateTime,
     encoding::*,
     guid::Guid,
     node_id::{ExpandedNodeId, NodeId},
     node_ids::DataTypeId,
+    numeric_range::NumericRange,
     status_codes::StatusCode,
     string::{UAString, XmlElement},
 };
@@ -368,6 +369,65 @@ try_from_variant_to_array_impl!(u32, UInt32);
 try_from_variant_to_array_impl!(f32, Float);
 try_from_variant_to_array_impl!(f64, Double);
 
+/// This macro tries to return a scalar value from a `Variant` that holds exactly that type, e.g.
+/// a `u32` from a `Variant::UInt32`. No numeric conversion is performed.
+macro_rules! try_from_variant_to_scalar_impl {
+    ($rtype: ident, $vtype: ident) => {
+        impl TryFrom<&Variant> for $rtype {
+            type Error = ();
+
+            fn try_from(value: &Variant) -> Result<Self, Self::Error> {
+                if let Variant::$vtype(ref v) = value {
+                    Ok(v.clone())
+                } else {
+                    Err(())
+                }
+            }
+        }
+    }
+}
+
+try_from_variant_to_scalar_impl!(bool, Boolean);
+try_from_variant_to_scalar_impl!(i8, SByte);
+try_from_variant_to_scalar_impl!(u8, Byte);
+try_from_variant_to_scalar_impl!(i16, Int16);
+try_from_variant_to_scalar_impl!(u16, UInt16);
+try_from_variant_to_scalar_impl!(i32, Int32);
+try_from_variant_to_scalar_impl!(u32, UInt32);
+try_from_variant_to_scalar_impl!(i64, Int64);
+try_from_variant_to_scalar_impl!(u64, UInt64);
+try_from_variant_to_scalar_impl!(f32, Float);
+try_from_variant_to_scalar_impl!(f64, Double);
+try_from_variant_to_scalar_impl!(UAString, String);
+try_from_variant_to_scalar_impl!(ByteString, ByteString);
+try_from_variant_to_scalar_impl!(StatusCode, StatusCode);
+
+/// This macro tries to return a scalar value from a `Variant` that holds the type in a box, e.g.
+/// a `LocalizedText` from a `Variant::LocalizedText`.
+macro_rules! try_from_variant_to_boxed_scalar_impl {
+    ($rtype: ident, $vtype: ident) => {
+        impl TryFrom<&Variant> for $rtype {
+            type Error = ();
+
+            fn try_from(value: &Variant) -> Result<Self, Self::Error> {
+                if let Variant::$vtype(ref v) = value {
+                    Ok(v.as_ref().clone())
+                } else {
+                    Err(())
+                }
+            }
+        }
+    }
+}
+
+try_from_variant_to_boxed_scalar_impl!(DateTime, DateTime);
+try_from_variant_to_boxed_scalar_impl!(Guid, Guid);
+try_from_variant_to_boxed_scalar_impl!(QualifiedName, QualifiedName);
+try_from_variant_to_boxed_scalar_impl!(LocalizedText, LocalizedText);
+try_from_variant_to_boxed_scalar_impl!(NodeId, NodeId);
+try_from_variant_to_boxed_scalar_impl!(ExpandedNodeId, ExpandedNodeId);
+try_from_variant_to_boxed_scalar_impl!(ExtensionObject, ExtensionObject);
+
 /// Tests that the variants in the slice all have the same variant type
 fn array_is_valid(values: &[Variant]) -> bool {
     if values.is_empty() {
@@ -444,17 +504,38 @@ impl MultiDimensionArray {
     }
 
     fn is_valid_dimensions(&self) -> bool {
-        // Check that the array dimensions match the length of the array
+        // Check that the array dimensions match the length of the array. A dimension of 0 is only
+        // valid for an empty array.
+        if self.dimensions.is_empty() {
+            return false;
+        }
         let mut length: usize = 1;
         for d in &self.dimensions {
             // Check for invalid dimensions
-            if *d <= 0 {
+            if *d < 0 {
                 return false;
             }
-            length *= *d as usize;
+            length = length.saturating_mul(*d as usize);
         }
         length == self.values.len()
     }
+
+    /// Returns the value at the index of each dimension. Values are held with the index of the
+    /// last dimension varying fastest, as they are encoded by OPC UA Part 6.
+    pub fn get(&self, indices: &[usize]) -> Option<&Variant> {
+        if indices.len() != self.dimensions.len() || !self.is_valid_dimensions() {
+            return None;
+        }
+        let mut offset = 0;
+        for (index, dimension) in indices.iter().zip(self.dimensions.iter()) {
+            let dimension = *dimension as usize;
+            if *index >= dimension {
+                return None;
+            }
+            offset = offset * dimension + *index;
+        }
+        self.values.get(offset)
+    }
 }
 
 impl BinaryEncoder<Variant> for Variant {
@@ -573,26 +654,22 @@ impl BinaryEncoder<Variant> for Variant {
         let encoding_mask = u8::decode(stream, decoding_limits)?;
         let element_encoding_mask = encoding_mask & !(ARRAY_DIMENSIONS_BIT | ARRAY_VALUES_BIT);
 
-        // Read array length
-        let array_length = if encoding_mask & ARRAY_VALUES_BIT != 0 {
+        if encoding_mask & ARRAY_VALUES_BIT != 0 {
+            // Read array length. A null array (-1) is treated the same as an empty array
             let array_length = i32::decode(stream, decoding_limits)?;
-            if array_length <= 0 {
+            if array_length < -1 {
                 error!("Invalid array_length {}", array_length);
                 return Err(StatusCode::BadDecodingError);
             }
-            array_length
-        } else {
-            -1
-        };
+            let array_length = cmp::max(array_length, 0) as usize;
 
-        // Read the value(s). If array length was specified, we assume a single or multi dimension array
-        if array_length > 0 {
             // Array length in total cannot exceed max array length
-            if array_length > constants::MAX_ARRAY_LENGTH as i32 {
+            if array_length > decoding_limits.max_array_length {
+                error!("Array length {} exceeds decoding limit {}", array_length, decoding_limits.max_array_length);
                 return Err(StatusCode::BadEncodingLimitsExceeded);
             }
 
-            let mut result: Vec<Variant> = Vec::with_capacity(array_length as usize);
+            let mut result: Vec<Variant> = Vec::with_capacity(array_length);
             for _ in 0..array_length {
                 result.push(Variant::decode_variant_value(stream, element_encoding_mask, decoding_limits)?);
             }
@@ -603,16 +680,18 @@ impl BinaryEncoder<Variant> for Variant {
                     return Err(StatusCode::BadDecodingError);
                 }
                 let dimensions = dimensions.unwrap();
-                let mut array_dimensions_length = 1;
+                // The product of the dimensions must be the array length. Checked in 64-bit so a
+                // malicious set of dimensions cannot overflow
+                let mut array_dimensions_length: i64 = 1;
                 for d in &dimensions {
-                    if *d <= 0 {
+                    if *d < 0 {
                         error!("Invalid array dimension {}", *d);
                         return Err(StatusCode::BadDecodingError);
                     }
-                    array_dimensions_length *= *d;
+                    array_dimensions_length = cmp::min(array_dimensions_length * *d as i64, i64::from(i32::max_value()) + 1);
                 }
-                if array_dimensions_length != array_length {
-                    error!("Array dimensions does not match array length {}", array_length);
+                if dimensions.is_empty() || array_dimensions_length != array_length as i64 {
+                    error!("Array dimensions {:?} do not match array length {}", dimensions, array_length);
                     Err(StatusCode::BadDecodingError)
                 } else {
                     Ok(Variant::new_multi_dimension_array(result, dimensions))
@@ -826,6 +905,39 @@ impl Variant {
         Variant::from(MultiDimensionArray::new(values, dimensions))
     }
 
+    /// Creates a two dimensional array from a vector of rows. Every row must be the same length or
+    /// the matrix is `BadInvalidArgument`.
+    pub fn from_matrix<T>(rows: Vec<Vec<T>>) -> Result<Variant, StatusCode> where T: Into<Variant> {
+        let columns = rows.first().map(|row| row.len()).unwrap_or(0);
+        if rows.iter().any(|row| row.len() != columns) {
+            error!("Rows of a matrix must all be the same length");
+            return Err(StatusCode::BadInvalidArgument);
+        }
+        let dimensions = vec![rows.len() as i32, columns as i32];
+        let values = rows.into_iter().flat_map(|row| row.into_iter().map(|v| v.into())).collect::<Vec<Variant>>();
+        Ok(Variant::new_multi_dimension_array(values, dimensions))
+    }
+
+    /// Returns the value rank of the variant, i.e. -1 for a scalar, 1 for an array or the number
+    /// of dimensions of a multi dimensional array.
+    pub fn value_rank(&self) -> i32 {
+        match *self {
+            Variant::Array(_) => 1,
+            Variant::MultiDimensionArray(ref mda) => mda.dimensions.len() as i32,
+            _ => -1
+        }
+    }
+
+    /// Returns the length of each dimension of an array as they would appear in the `ArrayDimensions`
+    /// attribute of a variable, or `None` for a scalar.
+    pub fn array_dimensions(&self) -> Option<Vec<u32>> {
+        match *self {
+            Variant::Array(ref values) => Some(vec![values.len() as u32]),
+            Variant::MultiDimensionArray(ref mda) => Some(mda.dimensions.iter().map(|d| *d as u32).collect()),
+            _ => None
+        }
+    }
+
     /// Tests and returns true if the variant holds a numeric type
     pub fn is_numeric(&self) -> bool {
         match *self {
@@ -949,6 +1061,122 @@ impl Variant {
         }
     }
 
+    /// Returns the elements of an array, or the characters of a string or bytes of a byte string,
+    /// which are selected by the numeric range. A range which runs past the end of a dimension is
+    /// trimmed to it. A range that selects nothing, or has a different number of dimensions to the
+    /// value, is `BadIndexRangeNoData`, as is any range of a scalar.
+    pub fn range_of(&self, range: &NumericRange) -> Result<Variant, StatusCode> {
+        let dimensions = self.range_dimensions(range)?;
+        let bounds = range_bounds(range)?.into_iter().zip(dimensions.iter())
+            .map(|((min, max), dimension)| {
+                if min >= *dimension { Err(StatusCode::BadIndexRangeNoData) } else { Ok((min, cmp::min(max, dimension - 1))) }
+            })
+            .collect::<Result<Vec<(usize, usize)>, StatusCode>>()?;
+        let offsets = range_offsets(&dimensions, &bounds);
+        let result = match *self {
+            Variant::String(ref value) => {
+                let chars = value.as_ref().chars().collect::<Vec<char>>();
+                Variant::String(UAString::from(offsets.into_iter().map(|offset| chars[offset]).collect::<String>()))
+            }
+            Variant::ByteString(ref value) => {
+                let bytes = value.value.as_ref().unwrap();
+                Variant::ByteString(ByteString::from(offsets.into_iter().map(|offset| bytes[offset]).collect::<Vec<u8>>()))
+            }
+            Variant::Array(ref values) => {
+                Variant::Array(offsets.into_iter().map(|offset| values[offset].clone()).collect())
+            }
+            Variant::MultiDimensionArray(ref mda) => {
+                let values = offsets.into_iter().map(|offset| mda.values[offset].clone()).collect();
+                let dimensions = bounds.iter().map(|(min, max)| (max - min + 1) as i32).collect();
+                Variant::new_multi_dimension_array(values, dimensions)
+            }
+            _ => panic!("Value has range dimensions but is not an array or string")
+        };
+        Ok(result)
+    }
+
+    /// Replaces the elements of an array, or the characters of a string or bytes of a byte string,
+    /// which are selected by the numeric range with those of the value. The range must lie within
+    /// this value or it is `BadIndexRangeNoData`. The value must be of the same kind and be the
+    /// size of the range, otherwise it is `BadTypeMismatch` or `BadIndexRangeInvalid` respectively.
+    pub fn set_range_of(&mut self, range: &NumericRange, value: &Variant) -> Result<(), StatusCode> {
+        let dimensions = self.range_dimensions(range)?;
+        let bounds = range_bounds(range)?;
+        if !bounds_lie_within(&bounds, &dimensions) {
+            return Err(StatusCode::BadIndexRangeNoData);
+        }
+        if self.type_id() != value.type_id() {
+            return Err(StatusCode::BadTypeMismatch);
+        }
+        let sizes = bounds.iter().map(|(min, max)| max - min + 1).collect::<Vec<usize>>();
+        if value.element_dimensions() != Some(sizes) {
+            return Err(StatusCode::BadIndexRangeInvalid);
+        }
+        let offsets = range_offsets(&dimensions, &bounds);
+        match (self, value) {
+            (Variant::String(target), Variant::String(value)) => {
+                let mut chars = target.as_ref().chars().collect::<Vec<char>>();
+                offsets.into_iter().zip(value.as_ref().chars()).for_each(|(offset, c)| chars[offset] = c);
+                *target = UAString::from(chars.into_iter().collect::<String>());
+            }
+            (Variant::ByteString(target), Variant::ByteString(value)) => {
+                let bytes = target.value.as_mut().unwrap();
+                offsets.into_iter().zip(value.value.as_ref().unwrap().iter()).for_each(|(offset, b)| bytes[offset] = *b);
+            }
+            (Variant::Array(target), Variant::Array(values)) => {
+                if !array_elements_match(target, values) {
+                    return Err(StatusCode::BadTypeMismatch);
+                }
+                offsets.into_iter().zip(values.iter()).for_each(|(offset, v)| target[offset] = v.clone());
+            }
+            (Variant::MultiDimensionArray(target), Variant::MultiDimensionArray(value)) => {
+                if !array_elements_match(&target.values, &value.values) {
+                    return Err(StatusCode::BadTypeMismatch);
+                }
+                offsets.into_iter().zip(value.values.iter()).for_each(|(offset, v)| target.values[offset] = v.clone());
+            }
+            _ => return Err(StatusCode::BadTypeMismatch)
+        }
+        Ok(())
+    }
+
+    /// Tests if every index of the numeric range lies within the value
+    pub(crate) fn range_lies_within(&self, range: &NumericRange) -> bool {
+        match (self.range_dimensions(range), range_bounds(range)) {
+            (Ok(dimensions), Ok(bounds)) => bounds_lie_within(&bounds, &dimensions),
+            _ => false
+        }
+    }
+
+    /// Returns the length of each dimension of the value that a numeric range can select from,
+    /// which must be as many as the range has
+    fn range_dimensions(&self, range: &NumericRange) -> Result<Vec<usize>, StatusCode> {
+        let dimensions = self.element_dimensions().ok_or(StatusCode::BadIndexRangeNoData)?;
+        let range_dimensions = match *range {
+            NumericRange::MultipleRanges(ref ranges) => ranges.len(),
+            _ => 1
+        };
+        if dimensions.len() == range_dimensions {
+            Ok(dimensions)
+        } else {
+            Err(StatusCode::BadIndexRangeNoData)
+        }
+    }
+
+    /// Returns the length of each dimension of an array, or the length of a string or byte string,
+    /// or `None` for anything else
+    fn element_dimensions(&self) -> Option<Vec<usize>> {
+        match *self {
+            Variant::String(ref value) => Some(vec![value.as_ref().chars().count()]),
+            Variant::ByteString(ref value) => Some(vec![value.value.as_ref().map(|v| v.len()).unwrap_or(0)]),
+            Variant::Array(ref values) => Some(vec![values.len()]),
+            Variant::MultiDimensionArray(ref mda) if mda.is_valid_dimensions() => {
+                Some(mda.dimensions.iter().map(|d| *d as usize).collect())
+            }
+            _ => None
+        }
+    }
+
     // Gets the encoding mask to write the variant to disk
     fn get_encoding_mask(&self) -> u8 {
         match *self {
@@ -997,3 +1225,50 @@ impl Variant {
         }
     }
 }
+
+/// Returns the first and last index of each dimension of a numeric range
+fn range_bounds(range: &NumericRange) -> Result<Vec<(usize, usize)>, StatusCode> {
+    match *range {
+        NumericRange::Index(index) => Ok(vec![(index as usize, index as usize)]),
+        NumericRange::Range(min, max) if min < max => Ok(vec![(min as usize, max as usize)]),
+        NumericRange::Range(_, _) => Err(StatusCode::BadIndexRangeInvalid),
+        NumericRange::MultipleRanges(ref ranges) => {
+            let mut result = Vec::with_capacity(ranges.len());
+            for range in ranges {
+                match *range {
+                    NumericRange::MultipleRanges(_) => return Err(StatusCode::BadIndexRangeInvalid),
+                    ref range => result.extend(range_bounds(range)?),
+                }
+            }
+            Ok(result)
+        }
+    }
+}
+
+fn bounds_lie_within(bounds: &[(usize, usize)], dimensions: &[usize]) -> bool {
+    bounds.iter().zip(dimensions.iter()).all(|((_, max), dimension)| max < dimension)
+}
+
+/// Returns the offsets of the elements within the bounds of each dimension of an array whose last
+/// dimension varies fastest, in order
+fn range_offsets(dimensions: &[usize], bounds: &[(usize, usize)]) -> Vec<usize> {
+    let mut offsets = vec![0];
+    for (dimension, (min, max)) in dimensions.iter().zip(bounds.iter()) {
+        let (dimension, min, max) = (*dimension, *min, *max);
+        offsets = offsets.into_iter()
+            .flat_map(|offset| (min..=max).map(move |index| offset * dimension + index))
+            .collect();
+    }
+    offsets
+}
+
+/// Tests if the replacement elements are of the same type as the elements of the array
+fn array_elements_match(array: &[Variant], replacement: &[Variant]) -> bool {
+    match array.first() {
+        Some(first) => {
+            let type_id = first.type_id();
+            replacement.iter().all(|v| v.type_id() == type_id)
+        }
+        None => true
+    }
+}
@@ -0,0 +1,152 @@
+//! Contains `FromVariant`, which converts the value of a `Variant` to a Rust type, e.g. to read the
+//! value of a variable without matching on the variant.
+
+use std::convert::TryFrom;
+
+use crate::{
+    basic_types::{LocalizedText, QualifiedName},
+    byte_string::ByteString,
+    date_time::DateTime,
+    extension_object::ExtensionObject,
+    guid::Guid,
+    node_id::{ExpandedNodeId, NodeId},
+    status_codes::StatusCode,
+    string::UAString,
+    variant::Variant,
+};
+
+/// A type that the value of a `Variant` can be converted to.
+///
+/// Numbers convert between numeric types as long as the value fits, so an `Int16` or a `Byte`
+/// converts to an `i32`, and an `Int64` converts to a `u8` if it is between 0 and 255. Integers
+/// convert to `f32` and `f64`, but floating point values never convert to integers. Other types
+/// must be held exactly, e.g. a `String` only converts from a `Variant::String`.
+///
+/// An `Option<T>` converts from `Variant::Empty` to `None`, and a `Vec<T>` converts from an array
+/// whose values each convert to `T`.
+pub trait FromVariant: Sized {
+    /// Converts the value of the variant, returning `BadTypeMismatch` if it is not of a type that
+    /// converts to this one, or `BadOutOfRange` if it is a number that does not fit.
+    fn from_variant(value: &Variant) -> Result<Self, StatusCode>;
+}
+
+/// Returns the value of an integer variant
+fn integer_value(value: &Variant) -> Option<i128> {
+    match *value {
+        Variant::SByte(v) => Some(i128::from(v)),
+        Variant::Byte(v) => Some(i128::from(v)),
+        Variant::Int16(v) => Some(i128::from(v)),
+        Variant::UInt16(v) => Some(i128::from(v)),
+        Variant::Int32(v) => Some(i128::from(v)),
+        Variant::UInt32(v) => Some(i128::from(v)),
+        Variant::Int64(v) => Some(i128::from(v)),
+        Variant::UInt64(v) => Some(i128::from(v)),
+        _ => None
+    }
+}
+
+/// Returns the value of an integer or floating point variant
+fn numeric_value(value: &Variant) -> Option<f64> {
+    match *value {
+        Variant::Float(v) => Some(f64::from(v)),
+        Variant::Double(v) => Some(v),
+        _ => integer_value(value).map(|v| v as f64)
+    }
+}
+
+macro_rules! from_variant_integer_impl {
+    ($rtype: ident) => {
+        impl FromVariant for $rtype {
+            fn from_variant(value: &Variant) -> Result<Self, StatusCode> {
+                let v = integer_value(value).ok_or(StatusCode::BadTypeMismatch)?;
+                $rtype::try_from(v).map_err(|_| StatusCode::BadOutOfRange)
+            }
+        }
+    }
+}
+
+from_variant_integer_impl!(i8);
+from_variant_integer_impl!(u8);
+from_variant_integer_impl!(i16);
+from_variant_integer_impl!(u16);
+from_variant_integer_impl!(i32);
+from_variant_integer_impl!(u32);
+from_variant_integer_impl!(i64);
+from_variant_integer_impl!(u64);
+
+impl FromVariant for f64 {
+    fn from_variant(value: &Variant) -> Result<Self, StatusCode> {
+        numeric_value(value).ok_or(StatusCode::BadTypeMismatch)
+    }
+}
+
+impl FromVariant for f32 {
+    fn from_variant(value: &Variant) -> Result<Self, StatusCode> {
+        let v = numeric_value(value).ok_or(StatusCode::BadTypeMismatch)?;
+        // Infinity and NaN are kept, finite values must fit
+        if v.is_finite() && v.abs() > f64::from(std::f32::MAX) {
+            Err(StatusCode::BadOutOfRange)
+        } else {
+            Ok(v as f32)
+        }
+    }
+}
+
+impl FromVariant for String {
+    fn from_variant(value: &Variant) -> Result<Self, StatusCode> {
+        if let Variant::String(ref v) = value {
+            Ok(v.as_ref().to_string())
+        } else {
+            Err(StatusCode::BadTypeMismatch)
+        }
+    }
+}
+
+/// Converts a variant that holds exactly the type through its `TryFrom` implementation
+macro_rules! from_variant_exact_impl {
+    ($rtype: ident) => {
+        impl FromVariant for $rtype {
+            fn from_variant(value: &Variant) -> Result<Self, StatusCode> {
+                $rtype::try_from(value).map_err(|_| StatusCode::BadTypeMismatch)
+            }
+        }
+    }
+}
+
+from_variant_exact_impl!(bool);
+from_variant_exact_impl!(UAString);
+from_variant_exact_impl!(ByteString);
+from_variant_exact_impl!(StatusCode);
+from_variant_exact_impl!(DateTime);
+from_variant_exact_impl!(Guid);
+from_variant_exact_impl!(QualifiedName);
+from_variant_exact_impl!(LocalizedText);
+from_variant_exact_impl!(NodeId);
+from_variant_exact_impl!(ExpandedNodeId);
+from_variant_exact_impl!(ExtensionObject);
+
+impl FromVariant for Variant {
+    fn from_variant(value: &Variant) -> Result<Self, StatusCode> {
+        Ok(value.clone())
+    }
+}
+
+impl<T> FromVariant for Option<T> where T: FromVariant {
+    fn from_variant(value: &Variant) -> Result<Self, StatusCode> {
+        if let Variant::Empty = value {
+            Ok(None)
+        } else {
+            T::from_variant(value).map(Some)
+        }
+    }
+}
+
+impl<T> FromVariant for Vec<T> where T: FromVariant {
+    fn from_variant(value: &Variant) -> Result<Self, StatusCode> {
+        if let Variant::Array(ref values) = value {
+            values.iter().map(T::from_variant).collect()
+        } else {
+            Err(StatusCode::BadTypeMismatch)
+        }
+    }
+}